@@ -0,0 +1,72 @@
+#ifndef BANYAN_H
+#define BANYAN_H
+
+/* Generated by cbindgen from banyan-ffi/src/lib.rs; do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Result of a call through the C ABI
+typedef enum BanyanStatus {
+  // The call succeeded
+  BANYAN_STATUS_OK = 0,
+  // A required pointer argument was null
+  BANYAN_STATUS_NULL_ARGUMENT = 1,
+  // A string argument was not valid UTF-8
+  BANYAN_STATUS_INVALID_UTF8 = 2,
+  // The policies could not be parsed
+  BANYAN_STATUS_POLICY_PARSE_ERROR = 3,
+  // The schema could not be parsed
+  BANYAN_STATUS_SCHEMA_PARSE_ERROR = 4,
+  // The entities could not be parsed
+  BANYAN_STATUS_ENTITIES_PARSE_ERROR = 5,
+  // The authorization request could not be parsed
+  BANYAN_STATUS_REQUEST_PARSE_ERROR = 6,
+  // The result could not be serialized, or the library panicked
+  BANYAN_STATUS_INTERNAL_ERROR = 7,
+} BanyanStatus;
+
+// A parsed set of entities
+typedef struct BanyanEntities BanyanEntities;
+
+// A parsed policy set
+typedef struct BanyanPolicySet BanyanPolicySet;
+
+// Parse policies written in the Cedar syntax into a policy set.
+enum BanyanStatus banyan_policy_set_parse(const char *policies,
+                                          struct BanyanPolicySet **out,
+                                          char **error);
+
+// Release a policy set returned by `banyan_policy_set_parse`. Passing null
+// is a no-op.
+void banyan_policy_set_free(struct BanyanPolicySet *pset);
+
+// Parse entities from their JSON representation. If `schema` is non-null it
+// is a JSON schema which informs the parsing (allowing `__entity` and
+// `__extn` escapes to be implicit, and checking attribute types).
+enum BanyanStatus banyan_entities_parse(const char *entities,
+                                        const char *schema,
+                                        struct BanyanEntities **out,
+                                        char **error);
+
+// Release entities returned by `banyan_entities_parse`. Passing null is a
+// no-op.
+void banyan_entities_free(struct BanyanEntities *entities);
+
+// Answer an authorization request, given as JSON of the form
+// `{ "principal", "action", "resource", "context" }`. On success, `response`
+// is set to JSON of the form
+// `{ "decision": "Allow" | "Deny", "diagnostics": { "reason", "errors" } }`,
+// which must be released with `banyan_string_free`.
+enum BanyanStatus banyan_is_authorized(const struct BanyanPolicySet *pset,
+                                       const struct BanyanEntities *entities,
+                                       const char *request,
+                                       char **response,
+                                       char **error);
+
+// Release a string returned by this library. Passing null is a no-op.
+void banyan_string_free(char *s);
+
+#endif /* BANYAN_H */
@@ -0,0 +1,426 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! A stable C ABI for loading policy sets and entities and answering
+//! authorization requests, for embedding the authorizer in non-Rust hosts.
+//!
+//! Every function returns a [`BanyanStatus`]. On failure, if the caller passed
+//! a non-null `error` pointer, it is set to a newly allocated, NUL-terminated
+//! message which must be released with [`banyan_string_free`]. Handles
+//! returned through `out` pointers must be released with the matching
+//! `*_free` function. All string arguments are NUL-terminated UTF-8.
+//!
+//! The C header `include/banyan.h` is generated from this file with
+//! `cbindgen --config cbindgen.toml --output include/banyan.h`.
+
+#![warn(missing_docs)]
+
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::str::FromStr;
+
+use cedar_policy::frontend::is_authorized::InterfaceResponse;
+use cedar_policy::{Authorizer, Context, Entities, EntityUid, PolicySet, Request, Schema};
+use serde::Deserialize;
+
+/// Result of a call through the C ABI
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BanyanStatus {
+    /// The call succeeded
+    Ok = 0,
+    /// A required pointer argument was null
+    NullArgument = 1,
+    /// A string argument was not valid UTF-8
+    InvalidUtf8 = 2,
+    /// The policies could not be parsed
+    PolicyParseError = 3,
+    /// The schema could not be parsed
+    SchemaParseError = 4,
+    /// The entities could not be parsed
+    EntitiesParseError = 5,
+    /// The authorization request could not be parsed
+    RequestParseError = 6,
+    /// The result could not be serialized, or the library panicked
+    InternalError = 7,
+}
+
+/// A parsed policy set
+pub struct BanyanPolicySet(PolicySet);
+
+/// A parsed set of entities
+pub struct BanyanEntities(Entities);
+
+/// A failed call: the status to return and the message to report
+struct Error {
+    status: BanyanStatus,
+    message: String,
+}
+
+impl Error {
+    fn new(status: BanyanStatus, message: impl ToString) -> Self {
+        Self {
+            status,
+            message: message.to_string(),
+        }
+    }
+}
+
+/// Authorization request, in the same format as the `cedar_policy::frontend`
+/// JSON interface. Entity uids may be given either as strings
+/// (`"User::\"alice\""`) or in the `__entity` form.
+#[derive(Debug, Deserialize)]
+struct RequestJson {
+    principal: Option<serde_json::Value>,
+    action: Option<serde_json::Value>,
+    resource: Option<serde_json::Value>,
+    #[serde(default = "empty_context")]
+    context: serde_json::Value,
+}
+
+fn empty_context() -> serde_json::Value {
+    serde_json::Value::Object(serde_json::Map::new())
+}
+
+impl RequestJson {
+    fn into_request(self) -> Result<Request, Error> {
+        fn uid(
+            component: &str,
+            json: Option<serde_json::Value>,
+        ) -> Result<Option<EntityUid>, Error> {
+            json.map(|json| {
+                EntityUid::from_json(json).map_err(|e| {
+                    Error::new(
+                        BanyanStatus::RequestParseError,
+                        format!("failed to parse {component}: {e}"),
+                    )
+                })
+            })
+            .transpose()
+        }
+        let principal = uid("principal", self.principal)?;
+        let action = uid("action", self.action)?;
+        let resource = uid("resource", self.resource)?;
+        let context = Context::from_json_value(self.context, None)
+            .map_err(|e| Error::new(BanyanStatus::RequestParseError, e))?;
+        Ok(Request::new(principal, action, resource, context))
+    }
+}
+
+/// Run `f`, reporting any error (or panic) through `error`
+fn ffi_call(error: *mut *mut c_char, f: impl FnOnce() -> Result<(), Error>) -> BanyanStatus {
+    let result = catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|_| {
+        Err(Error::new(
+            BanyanStatus::InternalError,
+            "panic in banyan_ffi",
+        ))
+    });
+    match result {
+        Ok(()) => BanyanStatus::Ok,
+        Err(e) => {
+            if !error.is_null() {
+                // SAFETY: `error` is non-null, and the caller guarantees that
+                // it is valid for writes
+                unsafe { *error = into_c_string(e.message) };
+            }
+            e.status
+        }
+    }
+}
+
+/// Borrow a string argument
+///
+/// # Safety
+///
+/// `s` must be null or point to a NUL-terminated string which outlives `'a`
+unsafe fn read_str<'a>(s: *const c_char, name: &str) -> Result<&'a str, Error> {
+    if s.is_null() {
+        return Err(Error::new(
+            BanyanStatus::NullArgument,
+            format!("`{name}` must not be null"),
+        ));
+    }
+    CStr::from_ptr(s).to_str().map_err(|e| {
+        Error::new(
+            BanyanStatus::InvalidUtf8,
+            format!("`{name}` is not valid UTF-8: {e}"),
+        )
+    })
+}
+
+/// Check that an out pointer is non-null
+fn check_out<T>(out: *mut T, name: &str) -> Result<(), Error> {
+    if out.is_null() {
+        Err(Error::new(
+            BanyanStatus::NullArgument,
+            format!("`{name}` must not be null"),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Convert a Rust string into an owned C string. Interior NULs (which can't
+/// occur in our JSON output) are replaced so that the conversion can't fail.
+fn into_c_string(s: String) -> *mut c_char {
+    CString::new(s.replace('\0', "\u{FFFD}"))
+        .unwrap_or_default()
+        .into_raw()
+}
+
+/// Parse policies written in the Cedar syntax into a policy set.
+///
+/// # Safety
+///
+/// `policies` must be a NUL-terminated string. `out` must be valid for writes.
+/// `error` must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn banyan_policy_set_parse(
+    policies: *const c_char,
+    out: *mut *mut BanyanPolicySet,
+    error: *mut *mut c_char,
+) -> BanyanStatus {
+    ffi_call(error, || {
+        check_out(out, "out")?;
+        let src = read_str(policies, "policies")?;
+        let pset = PolicySet::from_str(src).map_err(|errs| {
+            Error::new(
+                BanyanStatus::PolicyParseError,
+                errs.iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            )
+        })?;
+        *out = Box::into_raw(Box::new(BanyanPolicySet(pset)));
+        Ok(())
+    })
+}
+
+/// Release a policy set returned by [`banyan_policy_set_parse`]. Passing null
+/// is a no-op.
+///
+/// # Safety
+///
+/// `pset` must be null or a handle which has not already been released.
+#[no_mangle]
+pub unsafe extern "C" fn banyan_policy_set_free(pset: *mut BanyanPolicySet) {
+    if !pset.is_null() {
+        drop(Box::from_raw(pset));
+    }
+}
+
+/// Parse entities from their JSON representation. If `schema` is non-null it
+/// is a JSON schema which informs the parsing (allowing `__entity` and
+/// `__extn` escapes to be implicit, and checking attribute types).
+///
+/// # Safety
+///
+/// `entities` must be a NUL-terminated string, and `schema` must be null or a
+/// NUL-terminated string. `out` must be valid for writes. `error` must be null
+/// or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn banyan_entities_parse(
+    entities: *const c_char,
+    schema: *const c_char,
+    out: *mut *mut BanyanEntities,
+    error: *mut *mut c_char,
+) -> BanyanStatus {
+    ffi_call(error, || {
+        check_out(out, "out")?;
+        let json = read_str(entities, "entities")?;
+        let schema = if schema.is_null() {
+            None
+        } else {
+            Some(
+                Schema::from_str(read_str(schema, "schema")?)
+                    .map_err(|e| Error::new(BanyanStatus::SchemaParseError, e))?,
+            )
+        };
+        let entities = Entities::from_json_str(json, schema.as_ref())
+            .map_err(|e| Error::new(BanyanStatus::EntitiesParseError, e))?;
+        *out = Box::into_raw(Box::new(BanyanEntities(entities)));
+        Ok(())
+    })
+}
+
+/// Release entities returned by [`banyan_entities_parse`]. Passing null is a
+/// no-op.
+///
+/// # Safety
+///
+/// `entities` must be null or a handle which has not already been released.
+#[no_mangle]
+pub unsafe extern "C" fn banyan_entities_free(entities: *mut BanyanEntities) {
+    if !entities.is_null() {
+        drop(Box::from_raw(entities));
+    }
+}
+
+/// Answer an authorization request, given as JSON of the form
+/// `{ "principal", "action", "resource", "context" }`. On success, `response`
+/// is set to JSON of the form
+/// `{ "decision": "Allow" | "Deny", "diagnostics": { "reason", "errors" } }`,
+/// which must be released with [`banyan_string_free`].
+///
+/// # Safety
+///
+/// `pset` and `entities` must be live handles, `request` must be a
+/// NUL-terminated string, `response` must be valid for writes, and `error`
+/// must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn banyan_is_authorized(
+    pset: *const BanyanPolicySet,
+    entities: *const BanyanEntities,
+    request: *const c_char,
+    response: *mut *mut c_char,
+    error: *mut *mut c_char,
+) -> BanyanStatus {
+    ffi_call(error, || {
+        check_out(response, "response")?;
+        let pset = pset
+            .as_ref()
+            .ok_or_else(|| Error::new(BanyanStatus::NullArgument, "`pset` must not be null"))?;
+        let entities = entities
+            .as_ref()
+            .ok_or_else(|| Error::new(BanyanStatus::NullArgument, "`entities` must not be null"))?;
+        let request = serde_json::from_str::<RequestJson>(read_str(request, "request")?)
+            .map_err(|e| Error::new(BanyanStatus::RequestParseError, e))?
+            .into_request()?;
+        let answer: InterfaceResponse = Authorizer::new()
+            .is_authorized(&request, &pset.0, &entities.0)
+            .into();
+        let json = serde_json::to_string(&answer)
+            .map_err(|e| Error::new(BanyanStatus::InternalError, e))?;
+        *response = into_c_string(json);
+        Ok(())
+    })
+}
+
+/// Release a string returned by this library. Passing null is a no-op.
+///
+/// # Safety
+///
+/// `s` must be null or a string returned by this library which has not
+/// already been released.
+#[no_mangle]
+pub unsafe extern "C" fn banyan_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::ptr;
+
+    fn c(s: &str) -> CString {
+        CString::new(s).expect("no interior NUL")
+    }
+
+    /// Take ownership of a string returned by the library
+    unsafe fn take(s: *mut c_char) -> String {
+        let owned = CStr::from_ptr(s).to_string_lossy().into_owned();
+        banyan_string_free(s);
+        owned
+    }
+
+    unsafe fn load(policies: &str, entities: &str) -> (*mut BanyanPolicySet, *mut BanyanEntities) {
+        let mut pset = ptr::null_mut();
+        let mut ents = ptr::null_mut();
+        assert_eq!(
+            banyan_policy_set_parse(c(policies).as_ptr(), &mut pset, ptr::null_mut()),
+            BanyanStatus::Ok
+        );
+        assert_eq!(
+            banyan_entities_parse(
+                c(entities).as_ptr(),
+                ptr::null(),
+                &mut ents,
+                ptr::null_mut()
+            ),
+            BanyanStatus::Ok
+        );
+        (pset, ents)
+    }
+
+    #[test]
+    fn authorize() {
+        unsafe {
+            let (pset, ents) = load(
+                r#"permit(principal == User::"alice", action == Action::"transfer", resource);"#,
+                "[]",
+            );
+            let mut response = ptr::null_mut();
+            let status = banyan_is_authorized(
+                pset,
+                ents,
+                c(r#"{ "principal": "User::\"alice\"", "action": "Action::\"transfer\"", "resource": "Token::\"usdc\"" }"#).as_ptr(),
+                &mut response,
+                ptr::null_mut(),
+            );
+            assert_eq!(status, BanyanStatus::Ok);
+            let answer: serde_json::Value =
+                serde_json::from_str(&take(response)).expect("response should be JSON");
+            assert_eq!(answer["decision"], "Allow");
+            banyan_policy_set_free(pset);
+            banyan_entities_free(ents);
+        }
+    }
+
+    #[test]
+    fn error_codes() {
+        unsafe {
+            let mut pset = ptr::null_mut();
+            let mut error = ptr::null_mut();
+            let status = banyan_policy_set_parse(
+                c("permit(principal, action, resource").as_ptr(),
+                &mut pset,
+                &mut error,
+            );
+            assert_eq!(status, BanyanStatus::PolicyParseError);
+            assert!(pset.is_null());
+            assert!(!take(error).is_empty());
+
+            let status = banyan_policy_set_parse(ptr::null(), &mut pset, ptr::null_mut());
+            assert_eq!(status, BanyanStatus::NullArgument);
+
+            let mut ents = ptr::null_mut();
+            let status = banyan_entities_parse(
+                c("{ not json").as_ptr(),
+                ptr::null(),
+                &mut ents,
+                ptr::null_mut(),
+            );
+            assert_eq!(status, BanyanStatus::EntitiesParseError);
+
+            let (pset, ents) = load("", "[]");
+            let mut response = ptr::null_mut();
+            let status = banyan_is_authorized(
+                pset,
+                ents,
+                c(r#"{ "principal": "not a uid" }"#).as_ptr(),
+                &mut response,
+                ptr::null_mut(),
+            );
+            assert_eq!(status, BanyanStatus::RequestParseError);
+            assert!(response.is_null());
+            banyan_policy_set_free(pset);
+            banyan_entities_free(ents);
+        }
+    }
+}
@@ -0,0 +1,696 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Editor features computed from the text of a policy file and an optional
+//! schema, independent of the protocol. Locations are byte offsets.
+
+use std::ops::Range;
+use std::str::FromStr;
+
+use cedar_policy::{PolicySet, Schema, SchemaError, ValidationMode, Validator};
+use cedar_policy_core::ast::{CallStyle, ExtensionFunction};
+use cedar_policy_core::entities::SchemaType;
+use cedar_policy_core::extensions::Extensions;
+use serde_json::Value;
+
+/// A schema, along with its source text so that definitions can be located
+#[derive(Debug)]
+pub struct SchemaDocument {
+    text: String,
+    json: Value,
+    validator: Validator,
+}
+
+impl SchemaDocument {
+    /// Parse a schema in the JSON schema format
+    pub fn parse(text: impl Into<String>) -> Result<Self, SchemaError> {
+        let text = text.into();
+        let json: Value = serde_json::from_str(&text)?;
+        let schema = Schema::from_json_value(json.clone())?;
+        Ok(Self {
+            text,
+            json,
+            validator: Validator::new(schema),
+        })
+    }
+
+    /// The source text of the schema
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The declaration of the entity type named `path`
+    fn entity_type(&self, path: &str) -> Option<&Value> {
+        let (namespace, name) = split_path(path);
+        self.json.get(namespace)?.get("entityTypes")?.get(name)
+    }
+
+    /// The declaration of the action named `id`, where `path` is the type of
+    /// the action, e.g. `Action` or `Token::Action`
+    fn action(&self, path: &str, id: &str) -> Option<&Value> {
+        let (namespace, _) = split_path(path);
+        self.json.get(namespace)?.get("actions")?.get(id)
+    }
+
+    /// Every declaration of the attribute `attr`, on an entity type or in the
+    /// context of an action, as (declared on, type) pairs
+    fn attribute(&self, attr: &str) -> Vec<(String, String)> {
+        let mut found = Vec::new();
+        let Some(namespaces) = self.json.as_object() else {
+            return found;
+        };
+        for (namespace, def) in namespaces {
+            let qualify = |name: &str| {
+                if namespace.is_empty() {
+                    name.to_string()
+                } else {
+                    format!("{namespace}::{name}")
+                }
+            };
+            let entity_types = def.get("entityTypes").and_then(Value::as_object);
+            for (name, ety) in entity_types.into_iter().flatten() {
+                if let Some(ty) = ety.pointer("/shape/attributes").and_then(|a| a.get(attr)) {
+                    found.push((format!("`{}`", qualify(name)), render_type(ty)));
+                }
+            }
+            let actions = def.get("actions").and_then(Value::as_object);
+            for (name, action) in actions.into_iter().flatten() {
+                let context = action.pointer("/appliesTo/context/attributes");
+                if let Some(ty) = context.and_then(|a| a.get(attr)) {
+                    found.push((
+                        format!("context of `{}::\"{name}\"`", qualify("Action")),
+                        render_type(ty),
+                    ));
+                }
+            }
+        }
+        found
+    }
+}
+
+/// An error found in a policy file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// Location of the problem in the policy file
+    pub range: Range<usize>,
+    /// Description of the problem
+    pub message: String,
+}
+
+/// Parse errors in `src`, or, if it parses and there is a schema, validation
+/// errors against the schema
+pub fn diagnostics(src: &str, schema: Option<&SchemaDocument>) -> Vec<Diagnostic> {
+    let pset = match PolicySet::from_str(src) {
+        Ok(pset) => pset,
+        Err(errs) => {
+            return errs
+                .iter()
+                .map(|err| Diagnostic {
+                    range: err
+                        .primary_source_span()
+                        .map_or(0..0, |span| span.offset()..span.offset() + span.len()),
+                    message: err.to_string(),
+                })
+                .collect()
+        }
+    };
+    let Some(schema) = schema else {
+        return Vec::new();
+    };
+    schema
+        .validator
+        .validate(&pset, ValidationMode::default())
+        .validation_errors()
+        .map(|err| Diagnostic {
+            range: match (err.location().range_start(), err.location().range_end()) {
+                (Some(start), Some(end)) => start..end,
+                _ => 0..0,
+            },
+            message: format!(
+                "policy `{}`: {}",
+                err.location().policy_id(),
+                err.error_kind()
+            ),
+        })
+        .collect()
+}
+
+/// Hover information for a location in a policy file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hover {
+    /// The range of the item the information is about
+    pub range: Range<usize>,
+    /// The information, as Markdown
+    pub contents: String,
+}
+
+/// Describe the item at `offset` in `src`: the signature of an extension
+/// function, or the declaration in the schema of an entity type, action, or
+/// attribute
+pub fn hover(src: &str, offset: usize, schema: Option<&SchemaDocument>) -> Option<Hover> {
+    let (range, token) = token_at(src, offset)?;
+    let contents = match token {
+        Token::Function(name) => {
+            let extensions = Extensions::all_available();
+            let func = extensions.func(&name.parse().ok()?).ok()?;
+            format!("```cedar\n{}\n```", signature(func))
+        }
+        Token::Attribute(attr) => {
+            let decls = schema?.attribute(&attr);
+            if decls.is_empty() {
+                return None;
+            }
+            let lines: Vec<_> = decls
+                .into_iter()
+                .map(|(on, ty)| format!("- `{attr}: {ty}` on {on}"))
+                .collect();
+            format!("attribute `{attr}`\n\n{}", lines.join("\n"))
+        }
+        Token::EntityUid { ty, id } if is_action_type(&ty) => {
+            let action = schema?.action(&ty, &id)?;
+            let applies_to = action.get("appliesTo");
+            let types = |key: &str| {
+                let names: Vec<_> = applies_to
+                    .and_then(|a| a.get(key))
+                    .and_then(Value::as_array)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(Value::as_str)
+                    .map(|name| format!("`{name}`"))
+                    .collect();
+                if names.is_empty() {
+                    "none".to_string()
+                } else {
+                    names.join(", ")
+                }
+            };
+            let context = applies_to
+                .and_then(|a| a.get("context"))
+                .map_or_else(|| "{}".to_string(), render_type);
+            format!(
+                "action `{ty}::\"{id}\"`\n\nprincipals: {}\n\nresources: {}\n\ncontext: `{context}`",
+                types("principalTypes"),
+                types("resourceTypes"),
+            )
+        }
+        Token::EntityUid { ty, .. } | Token::Path(ty) => {
+            let ety = schema?.entity_type(&ty)?;
+            let mut contents = format!("entity type `{ty}`");
+            let parents: Vec<_> = ety
+                .get("memberOfTypes")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+                .map(|name| format!("`{name}`"))
+                .collect();
+            if !parents.is_empty() {
+                contents.push_str(&format!("\n\nmember of: {}", parents.join(", ")));
+            }
+            if let Some(shape) = ety.get("shape") {
+                contents.push_str(&format!("\n\nattributes: `{}`", render_type(shape)));
+            }
+            contents
+        }
+    };
+    Some(Hover { range, contents })
+}
+
+/// The range in the schema text of the declaration of the entity type or
+/// action at `offset` in `src`
+pub fn definition(src: &str, offset: usize, schema: &SchemaDocument) -> Option<Range<usize>> {
+    let (_, token) = token_at(src, offset)?;
+    match token {
+        Token::EntityUid { ty, id } if is_action_type(&ty) => {
+            let (namespace, _) = split_path(&ty);
+            find_key(&schema.text, &[namespace, "actions", &id])
+        }
+        Token::EntityUid { ty, .. } | Token::Path(ty) => {
+            let (namespace, name) = split_path(&ty);
+            find_key(&schema.text, &[namespace, "entityTypes", name])
+        }
+        Token::Function(_) | Token::Attribute(_) => None,
+    }
+}
+
+/// A completion candidate
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    /// Text to insert
+    pub label: String,
+    /// Signature of the function
+    pub detail: String,
+}
+
+/// Extension functions which can be called at `offset` in `src`: methods
+/// after a `.`, and other functions elsewhere
+pub fn completions(src: &str, offset: usize) -> Vec<Completion> {
+    let before = src.get(..offset).unwrap_or(src);
+    let partial = before.trim_end_matches(|c: char| c.is_alphanumeric() || c == '_');
+    let after_dot = partial.ends_with('.');
+    let extensions = Extensions::all_available();
+    let mut completions: Vec<_> = extensions
+        .all_funcs()
+        .filter(|func| (func.style() == CallStyle::MethodStyle) == after_dot)
+        .map(|func| Completion {
+            label: func.name().to_string(),
+            detail: signature(func),
+        })
+        .collect();
+    completions.sort_by(|a, b| a.label.cmp(&b.label));
+    completions
+}
+
+/// The signature of an extension function, e.g. `u256(String) -> u256` or
+/// `u256.u256LessThan(u256) -> Boolean`
+fn signature(func: &ExtensionFunction) -> String {
+    let arg = |ty: &Option<SchemaType>| ty.as_ref().map_or_else(|| "_".into(), render_schema_type);
+    let ret = func
+        .return_type()
+        .map_or_else(|| "!".into(), render_schema_type);
+    let args: Vec<_> = func.arg_types().iter().map(arg).collect();
+    match (func.style(), args.split_first()) {
+        (CallStyle::MethodStyle, Some((receiver, rest))) => {
+            format!("{receiver}.{}({}) -> {ret}", func.name(), rest.join(", "))
+        }
+        _ => format!("{}({}) -> {ret}", func.name(), args.join(", ")),
+    }
+}
+
+fn render_schema_type(ty: &SchemaType) -> String {
+    match ty {
+        SchemaType::Bool => "Boolean".into(),
+        SchemaType::Long => "Long".into(),
+        SchemaType::String => "String".into(),
+        SchemaType::Set { element_ty } => format!("Set<{}>", render_schema_type(element_ty)),
+        SchemaType::EmptySet => "Set".into(),
+        SchemaType::Record { .. } => "Record".into(),
+        SchemaType::Entity { ty } => ty.to_string(),
+        SchemaType::Extension { name } => name.to_string(),
+    }
+}
+
+/// Render a type in the JSON schema format, e.g. `Set<u256>` or
+/// `{ amount: u256, memo?: String }`
+fn render_type(ty: &Value) -> String {
+    let name = |key: &str| {
+        ty.get(key)
+            .and_then(Value::as_str)
+            .unwrap_or("_")
+            .to_string()
+    };
+    match ty.get("type").and_then(Value::as_str) {
+        Some("Set") => format!(
+            "Set<{}>",
+            ty.get("element").map_or_else(|| "_".into(), render_type)
+        ),
+        Some("Record") => {
+            let attrs: Vec<_> = ty
+                .get("attributes")
+                .and_then(Value::as_object)
+                .into_iter()
+                .flatten()
+                .map(|(attr, attr_ty)| {
+                    let optional = attr_ty.get("required") == Some(&Value::Bool(false));
+                    let mark = if optional { "?" } else { "" };
+                    format!("{attr}{mark}: {}", render_type(attr_ty))
+                })
+                .collect();
+            if attrs.is_empty() {
+                "{}".into()
+            } else {
+                format!("{{ {} }}", attrs.join(", "))
+            }
+        }
+        Some("Entity" | "Extension") => name("name"),
+        // primitive types and references to common types
+        Some(other) => other.to_string(),
+        None => "_".into(),
+    }
+}
+
+/// Split `Token::Account` into `("Token", "Account")`
+fn split_path(path: &str) -> (&str, &str) {
+    path.rsplit_once("::").unwrap_or(("", path))
+}
+
+fn is_action_type(path: &str) -> bool {
+    split_path(path).1 == "Action"
+}
+
+/// An item in a policy which editor features apply to
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    /// A name which isn't called, e.g. an entity type
+    Path(String),
+    /// A called name, e.g. `ip` in `ip("10.0.0.1")` or `u256LessThan` in
+    /// `a.u256LessThan(b)`
+    Function(String),
+    /// An attribute access, e.g. `amount` in `context.amount`
+    Attribute(String),
+    /// An entity literal, e.g. `Action::"transfer"`
+    EntityUid { ty: String, id: String },
+}
+
+/// The token containing `offset`, if it's one editor features apply to
+fn token_at(src: &str, offset: usize) -> Option<(Range<usize>, Token)> {
+    let tokens = lex(src);
+    let index = tokens
+        .iter()
+        .position(|(range, _)| range.start <= offset && offset < range.end)?;
+    let (range, lexeme) = &tokens[index];
+    let prev = index.checked_sub(1).map(|i| &tokens[i].1);
+    let next = tokens.get(index + 1).map(|(_, lexeme)| lexeme);
+    let token = match lexeme {
+        Lexeme::Path(path) if next == Some(&Lexeme::Punct('(')) => Token::Function(path.clone()),
+        Lexeme::Path(path) if prev == Some(&Lexeme::Punct('.')) => Token::Attribute(path.clone()),
+        Lexeme::Path(path) => Token::Path(path.clone()),
+        Lexeme::EntityUid { ty, id } => Token::EntityUid {
+            ty: ty.clone(),
+            id: id.clone(),
+        },
+        Lexeme::String | Lexeme::Punct(_) => return None,
+    };
+    Some((range.clone(), token))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Lexeme {
+    Path(String),
+    EntityUid { ty: String, id: String },
+    String,
+    Punct(char),
+}
+
+/// Split `src` into lexemes, skipping whitespace and comments. This is much
+/// looser than the real grammar, which is fine for locating names in text
+/// which may not parse.
+fn lex(src: &str) -> Vec<(Range<usize>, Lexeme)> {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    let mut lexemes = Vec::new();
+    let mut i = 0;
+    while let Some(c) = src[i..].chars().next() {
+        let start = i;
+        if c.is_whitespace() {
+            i += c.len_utf8();
+        } else if src[i..].starts_with("//") {
+            i = src[i..].find('\n').map_or(src.len(), |n| i + n);
+        } else if c == '"' {
+            let (end, _) = lex_string(src, i);
+            i = end;
+            lexemes.push((start..i, Lexeme::String));
+        } else if is_ident(c) {
+            i = end_of(src, i, is_ident);
+            // continue through `::` separators, stopping at an entity id
+            loop {
+                let rest = &src[i..];
+                if rest.starts_with("::\"") {
+                    let ty = src[start..i].to_string();
+                    let (end, id) = lex_string(src, i + 2);
+                    i = end;
+                    lexemes.push((start..i, Lexeme::EntityUid { ty, id }));
+                    break;
+                } else if rest.starts_with("::") && rest[2..].starts_with(is_ident) {
+                    i = end_of(src, i + 2, is_ident);
+                } else {
+                    lexemes.push((start..i, Lexeme::Path(src[start..i].to_string())));
+                    break;
+                }
+            }
+        } else {
+            i += c.len_utf8();
+            lexemes.push((start..i, Lexeme::Punct(c)));
+        }
+    }
+    lexemes
+}
+
+fn end_of(src: &str, start: usize, pred: impl Fn(char) -> bool) -> usize {
+    src[start..]
+        .find(|c| !pred(c))
+        .map_or(src.len(), |n| start + n)
+}
+
+/// Lex the string literal starting at `start`, returning the offset after
+/// it and its contents. Escapes are kept as written.
+fn lex_string(src: &str, start: usize) -> (usize, String) {
+    let mut escaped = false;
+    for (n, c) in src[start + 1..].char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => return (start + 1 + n + 1, src[start + 1..start + 1 + n].to_string()),
+            _ => (),
+        }
+    }
+    (src.len(), src[start + 1..].to_string())
+}
+
+/// Find the range of the key at `path` in the JSON document `text`, e.g.
+/// `["", "entityTypes", "User"]`. Only object keys are followed.
+fn find_key(text: &str, path: &[&str]) -> Option<Range<usize>> {
+    let mut scanner = JsonScanner { text, pos: 0 };
+    scanner.find(path)
+}
+
+/// A minimal JSON scanner which tracks where keys are; `serde_json` doesn't
+/// report locations
+struct JsonScanner<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl JsonScanner<'_> {
+    fn skip_whitespace(&mut self) {
+        self.pos = end_of(self.text, self.pos, char::is_whitespace);
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.text[self.pos..].chars().next()
+    }
+
+    /// Look for `path` in the value starting here, consuming the value if
+    /// the path isn't found in it
+    fn find(&mut self, path: &[&str]) -> Option<Range<usize>> {
+        let (first, rest) = path.split_first()?;
+        if self.peek()? != '{' {
+            self.skip_value();
+            return None;
+        }
+        self.pos += 1;
+        loop {
+            match self.peek()? {
+                '}' => {
+                    self.pos += 1;
+                    return None;
+                }
+                ',' => self.pos += 1,
+                '"' => {
+                    let start = self.pos;
+                    let (end, key) = lex_string(self.text, start);
+                    self.pos = end;
+                    if self.peek()? != ':' {
+                        return None;
+                    }
+                    self.pos += 1;
+                    if key == *first {
+                        if rest.is_empty() {
+                            return Some(start..end);
+                        }
+                        return self.find(rest);
+                    }
+                    self.skip_value();
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    fn skip_value(&mut self) {
+        let mut depth = 0usize;
+        while let Some(c) = self.peek() {
+            match c {
+                '"' => {
+                    self.pos = lex_string(self.text, self.pos).0;
+                    if depth == 0 {
+                        return;
+                    }
+                    continue;
+                }
+                '{' | '[' => depth += 1,
+                '}' | ']' | ',' if depth == 0 => return,
+                '}' | ']' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        self.pos += 1;
+                        return;
+                    }
+                }
+                _ => (),
+            }
+            self.pos += c.len_utf8();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SCHEMA: &str = r#"{
+    "Token": {
+        "entityTypes": {
+            "Account": {
+                "memberOfTypes": ["Group"],
+                "shape": {
+                    "type": "Record",
+                    "attributes": {
+                        "limit": { "type": "Extension", "name": "u256" },
+                        "tags": { "type": "Set", "element": { "type": "String" }, "required": false }
+                    }
+                }
+            },
+            "Group": {},
+            "Contract": {}
+        },
+        "actions": {
+            "transfer": {
+                "appliesTo": {
+                    "principalTypes": ["Account"],
+                    "resourceTypes": ["Contract"],
+                    "context": {
+                        "type": "Record",
+                        "attributes": { "amount": { "type": "Extension", "name": "u256" } }
+                    }
+                }
+            }
+        }
+    }
+}"#;
+
+    fn schema() -> SchemaDocument {
+        SchemaDocument::parse(SCHEMA).expect("valid schema")
+    }
+
+    /// The offset of the first occurrence of `needle` in `src`, plus `delta`
+    fn at(src: &str, needle: &str, delta: usize) -> usize {
+        src.find(needle).expect("needle is in the source") + delta
+    }
+
+    const POLICY: &str = r#"// transfers under the limit
+permit(
+    principal in Token::Group::"signers",
+    action == Token::Action::"transfer",
+    resource
+) when {
+    context.amount.u256LessThan(principal.limit) && u256("0").u256LessThan(context.amount)
+};"#;
+
+    #[test]
+    #[cfg(feature = "u256")]
+    fn reports_diagnostics() {
+        let schema = schema();
+        assert!(diagnostics(POLICY, Some(&schema)).is_empty());
+
+        let errors = diagnostics("permit(principal, action, resource) when { 1 + };", None);
+        assert!(!errors.is_empty());
+        assert!(errors.iter().all(|e| e.range.start > 0));
+
+        let src = r#"permit(principal, action == Token::Action::"mint", resource);"#;
+        let errors = diagnostics(src, Some(&schema));
+        assert!(errors
+            .first()
+            .is_some_and(|e| e.message.contains("unrecognized action")));
+        assert!(errors
+            .iter()
+            .all(|e| e.message.starts_with("policy `policy0`")));
+        assert!(diagnostics(src, None).is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "u256")]
+    fn hovers() {
+        let schema = schema();
+        let hover_at = |needle, delta| {
+            hover(POLICY, at(POLICY, needle, delta), Some(&schema)).map(|h| h.contents)
+        };
+        assert_eq!(
+            hover_at("u256(", 1).as_deref(),
+            Some("```cedar\nu256(String) -> u256\n```")
+        );
+        assert_eq!(
+            hover_at("u256LessThan", 3).as_deref(),
+            Some("```cedar\nu256.u256LessThan(u256) -> Boolean\n```")
+        );
+        assert_eq!(
+            hover_at("amount", 0).as_deref(),
+            Some("attribute `amount`\n\n- `amount: u256` on context of `Token::Action::\"transfer\"`")
+        );
+        let action = hover_at("Action", 0).expect("action hover");
+        assert!(action.contains("principals: `Account`"));
+        assert!(action.contains("context: `{ amount: u256 }`"));
+        assert!(hover_at("signers", 0)
+            .expect("entity type hover")
+            .starts_with("entity type `Token::Group`"));
+        assert!(hover_at("when", 0).is_none());
+
+        let src = r#"permit(principal == Token::Account::"alice", action, resource);"#;
+        let account = hover(src, at(src, "Account", 0), Some(&schema))
+            .expect("entity type hover")
+            .contents;
+        assert_eq!(
+            account,
+            "entity type `Token::Account`\n\nmember of: `Group`\n\nattributes: `{ limit: u256, tags?: Set<String> }`"
+        );
+        assert!(hover(src, at(src, "permit", 0), Some(&schema)).is_none());
+        assert!(hover(src, at(src, "Account", 0), None).is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "u256")]
+    fn definitions() {
+        let schema = schema();
+        let action = definition(POLICY, at(POLICY, "Action", 0), &schema).expect("action");
+        assert_eq!(&SCHEMA[action], r#""transfer""#);
+        let group = definition(POLICY, at(POLICY, "Group", 2), &schema).expect("entity type");
+        assert_eq!(group.start, at(SCHEMA, r#""Group": {}"#, 0));
+        assert!(definition(POLICY, at(POLICY, "u256(", 0), &schema).is_none());
+        assert!(definition(
+            "permit(principal == Foo::\"a\", action, resource);",
+            21,
+            &schema
+        )
+        .is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "u256")]
+    fn completes_extension_functions() {
+        let labels = |src: &str| -> Vec<String> {
+            completions(src, src.len())
+                .into_iter()
+                .map(|c| c.label)
+                .collect()
+        };
+        let functions = labels("permit(principal, action, resource) when { ");
+        assert!(functions.contains(&"u256".to_string()));
+        assert!(!functions.contains(&"u256LessThan".to_string()));
+        let methods = labels("permit(principal, action, resource) when { u256(\"1\").u2");
+        assert!(methods.contains(&"u256LessThan".to_string()));
+        assert!(!methods.contains(&"u256".to_string()));
+    }
+}
@@ -0,0 +1,57 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+#![forbid(unsafe_code)]
+
+use std::path::PathBuf;
+
+use banyan_lsp::analysis::SchemaDocument;
+use banyan_lsp::server::{Backend, LoadedSchema};
+use clap::Parser;
+use tower_lsp::lsp_types::Url;
+use tower_lsp::{LspService, Server};
+
+/// Serve the Language Server Protocol over stdin and stdout
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Schema file, in JSON format. If given, policies are validated against
+    /// it, and entity types and actions are resolved in it
+    #[arg(long, value_name = "FILE")]
+    schema: Option<PathBuf>,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+
+    let schema = match &args.schema {
+        Some(path) => {
+            let path = std::fs::canonicalize(path)?;
+            let url = Url::from_file_path(&path)
+                .map_err(|()| format!("invalid schema path {}", path.display()))?;
+            let document = SchemaDocument::parse(std::fs::read_to_string(&path)?)?;
+            Some(LoadedSchema { url, document })
+        }
+        None => None,
+    };
+
+    let (service, socket) = LspService::new(|client| Backend::new(client, schema));
+    Server::new(tokio::io::stdin(), tokio::io::stdout(), socket)
+        .serve(service)
+        .await;
+    Ok(())
+}
@@ -0,0 +1,262 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! The language server: keeps the text of open policy files and answers
+//! requests using [`crate::analysis`].
+
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::RwLock;
+
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, CompletionResponse,
+    Diagnostic, DiagnosticSeverity, DidChangeTextDocumentParams, DidCloseTextDocumentParams,
+    DidOpenTextDocumentParams, GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverContents,
+    HoverParams, HoverProviderCapability, InitializeParams, InitializeResult, InitializedParams,
+    Location, MarkupContent, MarkupKind, MessageType, OneOf, Position, ServerCapabilities,
+    ServerInfo, TextDocumentSyncCapability, TextDocumentSyncKind, Url,
+};
+use tower_lsp::{Client, LanguageServer};
+
+use crate::analysis::{self, SchemaDocument};
+
+/// A schema and the location it was loaded from
+#[derive(Debug)]
+pub struct LoadedSchema {
+    /// Location of the schema file, for go-to-definition
+    pub url: Url,
+    /// The schema
+    pub document: SchemaDocument,
+}
+
+/// The state of the server
+#[derive(Debug)]
+pub struct Backend {
+    client: Client,
+    schema: Option<LoadedSchema>,
+    documents: RwLock<HashMap<Url, String>>,
+}
+
+impl Backend {
+    /// Create a server which reports to `client`. Without a schema, the
+    /// server reports only parse errors and doesn't resolve entity types or
+    /// actions.
+    pub fn new(client: Client, schema: Option<LoadedSchema>) -> Self {
+        Self {
+            client,
+            schema,
+            documents: RwLock::new(HashMap::new()),
+        }
+    }
+
+    fn schema_document(&self) -> Option<&SchemaDocument> {
+        self.schema.as_ref().map(|s| &s.document)
+    }
+
+    /// Run `f` on the text of the open document `url`
+    fn with_document<T>(&self, url: &Url, f: impl FnOnce(&str) -> Option<T>) -> Option<T> {
+        let documents = self.documents.read().ok()?;
+        f(documents.get(url)?)
+    }
+
+    async fn update(&self, url: Url, text: String, version: Option<i32>) {
+        let diagnostics = analysis::diagnostics(&text, self.schema_document())
+            .into_iter()
+            .map(|d| Diagnostic {
+                range: to_lsp_range(&text, d.range),
+                severity: Some(DiagnosticSeverity::ERROR),
+                source: Some("banyan".into()),
+                message: d.message,
+                ..Diagnostic::default()
+            })
+            .collect();
+        if let Ok(mut documents) = self.documents.write() {
+            documents.insert(url.clone(), text);
+        }
+        self.client
+            .publish_diagnostics(url, diagnostics, version)
+            .await;
+    }
+}
+
+#[tower_lsp::async_trait]
+impl LanguageServer for Backend {
+    async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+        Ok(InitializeResult {
+            capabilities: ServerCapabilities {
+                text_document_sync: Some(TextDocumentSyncCapability::Kind(
+                    TextDocumentSyncKind::FULL,
+                )),
+                hover_provider: Some(HoverProviderCapability::Simple(true)),
+                definition_provider: Some(OneOf::Left(true)),
+                completion_provider: Some(CompletionOptions {
+                    trigger_characters: Some(vec![".".into()]),
+                    ..CompletionOptions::default()
+                }),
+                ..ServerCapabilities::default()
+            },
+            server_info: Some(ServerInfo {
+                name: "banyan-lsp".into(),
+                version: Some(env!("CARGO_PKG_VERSION").into()),
+            }),
+        })
+    }
+
+    async fn initialized(&self, _: InitializedParams) {
+        if self.schema.is_none() {
+            self.client
+                .log_message(
+                    MessageType::INFO,
+                    "no schema given; only parse errors will be reported",
+                )
+                .await;
+        }
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn did_open(&self, params: DidOpenTextDocumentParams) {
+        let doc = params.text_document;
+        self.update(doc.uri, doc.text, Some(doc.version)).await;
+    }
+
+    async fn did_change(&self, params: DidChangeTextDocumentParams) {
+        // with full sync, the last change holds the whole text
+        if let Some(change) = params.content_changes.into_iter().last() {
+            let doc = params.text_document;
+            self.update(doc.uri, change.text, Some(doc.version)).await;
+        }
+    }
+
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        let url = params.text_document.uri;
+        if let Ok(mut documents) = self.documents.write() {
+            documents.remove(&url);
+        }
+        self.client.publish_diagnostics(url, Vec::new(), None).await;
+    }
+
+    async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+        let position = params.text_document_position_params;
+        Ok(self.with_document(&position.text_document.uri, |text| {
+            let offset = to_offset(text, position.position);
+            let hover = analysis::hover(text, offset, self.schema_document())?;
+            Some(Hover {
+                contents: HoverContents::Markup(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: hover.contents,
+                }),
+                range: Some(to_lsp_range(text, hover.range)),
+            })
+        }))
+    }
+
+    async fn goto_definition(
+        &self,
+        params: GotoDefinitionParams,
+    ) -> Result<Option<GotoDefinitionResponse>> {
+        let position = params.text_document_position_params;
+        let Some(schema) = &self.schema else {
+            return Ok(None);
+        };
+        Ok(self.with_document(&position.text_document.uri, |text| {
+            let offset = to_offset(text, position.position);
+            let range = analysis::definition(text, offset, &schema.document)?;
+            Some(GotoDefinitionResponse::Scalar(Location {
+                uri: schema.url.clone(),
+                range: to_lsp_range(schema.document.text(), range),
+            }))
+        }))
+    }
+
+    async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
+        let position = params.text_document_position;
+        Ok(self.with_document(&position.text_document.uri, |text| {
+            let offset = to_offset(text, position.position);
+            let items = analysis::completions(text, offset)
+                .into_iter()
+                .map(|c| CompletionItem {
+                    label: c.label,
+                    kind: Some(CompletionItemKind::FUNCTION),
+                    detail: Some(c.detail),
+                    ..CompletionItem::default()
+                })
+                .collect();
+            Some(CompletionResponse::Array(items))
+        }))
+    }
+}
+
+/// Convert an LSP position, whose character is counted in UTF-16 code
+/// units, to a byte offset in `text`. Positions past the end of a line are
+/// clamped to the end of the line.
+fn to_offset(text: &str, position: Position) -> usize {
+    let mut line_start = 0;
+    for _ in 0..position.line {
+        match text[line_start..].find('\n') {
+            Some(n) => line_start += n + 1,
+            None => return text.len(),
+        }
+    }
+    let line = text[line_start..].split('\n').next().unwrap_or_default();
+    let mut units = 0;
+    for (n, c) in line.char_indices() {
+        if units >= position.character as usize {
+            return line_start + n;
+        }
+        units += c.len_utf16();
+    }
+    line_start + line.len()
+}
+
+/// Convert a byte offset in `text` to an LSP position
+fn to_position(text: &str, offset: usize) -> Position {
+    let before = text.get(..offset).unwrap_or(text);
+    let line_start = before.rfind('\n').map_or(0, |n| n + 1);
+    let character: usize = before[line_start..].chars().map(char::len_utf16).sum();
+    Position::new(before.matches('\n').count() as u32, character as u32)
+}
+
+fn to_lsp_range(text: &str, range: Range<usize>) -> tower_lsp::lsp_types::Range {
+    tower_lsp::lsp_types::Range::new(to_position(text, range.start), to_position(text, range.end))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn positions() {
+        let text = "permit(\n  principal == User::\"é𝄞\",\n  action, resource);";
+        for (offset, position) in [
+            (0, Position::new(0, 0)),
+            (8, Position::new(1, 0)),
+            (text.find('é').expect("in text"), Position::new(1, 22)),
+            (text.find('𝄞').expect("in text"), Position::new(1, 23)),
+            (text.find("\",").expect("in text"), Position::new(1, 25)),
+            (text.len(), Position::new(2, 20)),
+        ] {
+            assert_eq!(to_position(text, offset), position);
+            assert_eq!(to_offset(text, position), offset);
+        }
+        // past the end of a line or the text
+        assert_eq!(to_offset(text, Position::new(0, 100)), 7);
+        assert_eq!(to_offset(text, Position::new(9, 0)), text.len());
+    }
+}
@@ -0,0 +1,100 @@
+"""Parse, validate, and evaluate Cedar policies."""
+
+from typing import Dict, List, Mapping, Optional, Sequence, Union
+
+class PolicySet:
+    """A set of policies and templates."""
+
+    def __init__(self, src: str) -> None:
+        """Parse policies written in the Cedar syntax.
+
+        Raises ValueError if the policies don't parse."""
+    def policy_ids(self) -> List[str]:
+        """The ids of the policies, in order."""
+    def __len__(self) -> int: ...
+
+class Schema:
+    """A schema, in the JSON schema format."""
+
+    def __init__(self, json: str) -> None:
+        """Parse a schema from a JSON string.
+
+        Raises ValueError if the schema is malformed."""
+    def validate(self, policies: PolicySet) -> List[str]:
+        """Validate policies against this schema, returning a message for each
+        error. An empty list means the policies are valid."""
+
+class Entities:
+    """A set of entities."""
+
+    def __init__(self, json: str, schema: Optional[Schema] = None) -> None:
+        """Parse entities from a JSON string in the entities file format.
+
+        If a schema is given, attributes are parsed according to it, and the
+        action entities it declares are added."""
+    def __len__(self) -> int: ...
+
+class ExtensionValue:
+    """A value of an extension type, for use in a context."""
+
+    @property
+    def func(self) -> str:
+        """Name of the constructor, e.g. "u256"."""
+    @property
+    def arg(self) -> str:
+        """Argument to the constructor."""
+
+def ip(arg: str) -> ExtensionValue:
+    """Construct an IP address or range, e.g. ip("10.0.0.0/8")."""
+
+def decimal(arg: str) -> ExtensionValue:
+    """Construct a decimal, e.g. decimal("1.25")."""
+
+def u256(arg: str) -> ExtensionValue:
+    """Construct an unsigned 256-bit integer, e.g. u256("1000000000000000000")."""
+
+CedarValue = Union[
+    bool,
+    int,
+    str,
+    ExtensionValue,
+    Sequence["CedarValue"],
+    Mapping[str, "CedarValue"],
+]
+
+class Response:
+    """The answer to an authorization request."""
+
+    @property
+    def decision(self) -> str:
+        """"Allow" or "Deny"."""
+    @property
+    def allowed(self) -> bool:
+        """Whether the request is allowed."""
+    @property
+    def reasons(self) -> List[str]:
+        """Ids of the policies which determined the decision."""
+    @property
+    def errors(self) -> List[str]:
+        """Errors raised while evaluating policies."""
+
+class Authorizer:
+    """Answers authorization requests."""
+
+    def __init__(self) -> None: ...
+    def is_authorized(
+        self,
+        principal: Optional[str],
+        action: Optional[str],
+        resource: Optional[str],
+        policies: PolicySet,
+        entities: Entities,
+        context: Optional[Dict[str, CedarValue]] = None,
+        schema: Optional[Schema] = None,
+    ) -> Response:
+        """Answer a request.
+
+        The principal, action, and resource are entity uids such as
+        'User::"alice"'; None leaves them unknown. If a schema is given, the
+        context is parsed according to it. Raises ValueError if the request is
+        malformed."""
@@ -0,0 +1,435 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Python bindings for parsing, validating, and evaluating Cedar policies.
+//!
+//! The module is named `banyan`; its typed interface is described in
+//! `banyan.pyi`. Contexts are passed as Python dictionaries, with extension
+//! values built by the `ip`, `decimal`, and `u256` constructors.
+
+#![forbid(unsafe_code)]
+#![warn(missing_docs)]
+
+use std::str::FromStr;
+
+use cedar_policy::{
+    eval_expression, Authorizer, Context, Decision, Entities, EntityUid, Expression, PolicySet,
+    Request, Schema, ValidationMode, Validator,
+};
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyDict, PyList, PyLong, PyString, PyTuple};
+use serde_json::{json, Value};
+
+fn value_error(err: impl ToString) -> PyErr {
+    PyValueError::new_err(err.to_string())
+}
+
+/// A set of policies and templates
+#[pyclass(name = "PolicySet", module = "banyan")]
+#[derive(Debug, Clone)]
+pub struct PyPolicySet(PolicySet);
+
+#[pymethods]
+impl PyPolicySet {
+    /// Parse policies written in the Cedar syntax
+    #[new]
+    fn new(src: &str) -> PyResult<Self> {
+        PolicySet::from_str(src).map(Self).map_err(value_error)
+    }
+
+    /// The ids of the policies, in order
+    fn policy_ids(&self) -> Vec<String> {
+        self.0.policies().map(|p| p.id().to_string()).collect()
+    }
+
+    fn __len__(&self) -> usize {
+        self.0.policies().count()
+    }
+
+    fn __str__(&self) -> String {
+        self.0.to_string()
+    }
+}
+
+/// A schema, in the JSON schema format
+#[pyclass(name = "Schema", module = "banyan")]
+#[derive(Debug, Clone)]
+pub struct PySchema(Schema);
+
+#[pymethods]
+impl PySchema {
+    /// Parse a schema from a JSON string
+    #[new]
+    fn new(json: &str) -> PyResult<Self> {
+        Schema::from_str(json).map(Self).map_err(value_error)
+    }
+
+    /// Validate `policies` against this schema, returning a message for each
+    /// error. An empty list means the policies are valid.
+    fn validate(&self, policies: &PyPolicySet) -> Vec<String> {
+        Validator::new(self.0.clone())
+            .validate(&policies.0, ValidationMode::default())
+            .validation_errors()
+            .map(ToString::to_string)
+            .collect()
+    }
+}
+
+/// A set of entities
+#[pyclass(name = "Entities", module = "banyan")]
+#[derive(Debug, Clone)]
+pub struct PyEntities(Entities);
+
+#[pymethods]
+impl PyEntities {
+    /// Parse entities from a JSON string in the entities file format. If a
+    /// schema is given, attributes are parsed according to it, and the
+    /// action entities it declares are added.
+    #[new]
+    #[pyo3(signature = (json, schema = None))]
+    fn new(json: &str, schema: Option<&PySchema>) -> PyResult<Self> {
+        parse_entities(json, schema.map(|s| &s.0))
+            .map(Self)
+            .map_err(value_error)
+    }
+
+    fn __len__(&self) -> usize {
+        self.0.iter().count()
+    }
+}
+
+fn parse_entities(json: &str, schema: Option<&Schema>) -> Result<Entities, String> {
+    let entities = Entities::from_json_str(json, schema).map_err(|e| e.to_string())?;
+    match schema {
+        Some(schema) => {
+            let actions = schema.action_entities().map_err(|e| e.to_string())?;
+            Entities::from_entities(entities.iter().chain(actions.iter()).cloned())
+                .map_err(|e| e.to_string())
+        }
+        None => Ok(entities),
+    }
+}
+
+/// A value of an extension type, e.g. `u256("1000")`, for use in a context
+#[pyclass(name = "ExtensionValue", module = "banyan")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PyExtensionValue {
+    /// Name of the constructor
+    #[pyo3(get)]
+    func: String,
+    /// Argument to the constructor
+    #[pyo3(get)]
+    arg: String,
+}
+
+#[pymethods]
+impl PyExtensionValue {
+    fn __repr__(&self) -> String {
+        format!("{}({:?})", self.func, self.arg)
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+impl PyExtensionValue {
+    /// Call the constructor `func` on `arg`, failing if `arg` is malformed
+    fn new(func: &str, arg: &str) -> Result<Self, String> {
+        let value = Self {
+            func: func.into(),
+            arg: arg.into(),
+        };
+        // construct the value by evaluating it, to report errors now rather
+        // than during authorization
+        let context = Context::from_json_value(json!({ "value": value.to_json() }), None)
+            .map_err(|e| e.to_string())?;
+        let request = Request::new(None, None, None, context);
+        let expr = Expression::from_str("context.value").map_err(|e| e.to_string())?;
+        eval_expression(&request, &Entities::empty(), &expr).map_err(|e| e.to_string())?;
+        Ok(value)
+    }
+
+    fn to_json(&self) -> Value {
+        json!({ "__extn": { "fn": self.func, "arg": self.arg } })
+    }
+}
+
+/// Construct an IP address or range, e.g. `ip("10.0.0.0/8")`
+#[cfg(feature = "ipaddr")]
+#[pyfunction]
+fn ip(arg: &str) -> PyResult<PyExtensionValue> {
+    PyExtensionValue::new("ip", arg).map_err(value_error)
+}
+
+/// Construct a decimal, e.g. `decimal("1.25")`
+#[cfg(feature = "decimal")]
+#[pyfunction]
+fn decimal(arg: &str) -> PyResult<PyExtensionValue> {
+    PyExtensionValue::new("decimal", arg).map_err(value_error)
+}
+
+/// Construct an unsigned 256-bit integer, e.g. `u256("1000000000000000000")`
+#[cfg(feature = "u256")]
+#[pyfunction]
+fn u256(arg: &str) -> PyResult<PyExtensionValue> {
+    PyExtensionValue::new("u256", arg).map_err(value_error)
+}
+
+/// Convert a Python value to the JSON representation of a Cedar value
+fn to_json(obj: &PyAny) -> PyResult<Value> {
+    if let Ok(value) = obj.extract::<PyExtensionValue>() {
+        Ok(value.to_json())
+    } else if let Ok(b) = obj.downcast::<PyBool>() {
+        // checked before integers, since `bool` is a subclass of `int`
+        Ok(Value::Bool(b.is_true()))
+    } else if obj.is_instance_of::<PyLong>() {
+        Ok(Value::from(obj.extract::<i64>()?))
+    } else if let Ok(s) = obj.downcast::<PyString>() {
+        Ok(Value::String(s.to_str()?.to_string()))
+    } else if let Ok(dict) = obj.downcast::<PyDict>() {
+        dict.iter()
+            .map(|(k, v)| Ok((k.extract::<String>()?, to_json(v)?)))
+            .collect::<PyResult<serde_json::Map<_, _>>>()
+            .map(Value::Object)
+    } else if let Ok(list) = obj.downcast::<PyList>() {
+        list.iter()
+            .map(to_json)
+            .collect::<PyResult<_>>()
+            .map(Value::Array)
+    } else if let Ok(tuple) = obj.downcast::<PyTuple>() {
+        tuple
+            .iter()
+            .map(to_json)
+            .collect::<PyResult<_>>()
+            .map(Value::Array)
+    } else {
+        Err(PyTypeError::new_err(format!(
+            "cannot convert {} to a Cedar value",
+            obj.get_type().name()?
+        )))
+    }
+}
+
+/// The answer to an authorization request
+#[pyclass(name = "Response", module = "banyan")]
+#[derive(Debug, Clone)]
+pub struct PyResponse {
+    /// `"Allow"` or `"Deny"`
+    #[pyo3(get)]
+    decision: String,
+    /// Ids of the policies which determined the decision
+    #[pyo3(get)]
+    reasons: Vec<String>,
+    /// Errors raised while evaluating policies
+    #[pyo3(get)]
+    errors: Vec<String>,
+}
+
+#[pymethods]
+impl PyResponse {
+    /// Whether the request is allowed
+    #[getter]
+    fn allowed(&self) -> bool {
+        self.decision == "Allow"
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Response(decision={:?}, reasons={:?}, errors={:?})",
+            self.decision, self.reasons, self.errors
+        )
+    }
+}
+
+/// Answers authorization requests
+#[pyclass(name = "Authorizer", module = "banyan")]
+#[derive(Debug)]
+pub struct PyAuthorizer(Authorizer);
+
+#[pymethods]
+impl PyAuthorizer {
+    #[new]
+    fn new() -> Self {
+        Self(Authorizer::new())
+    }
+
+    /// Answer a request. The principal, action, and resource are entity uids
+    /// such as `'User::"alice"'`; `None` leaves them unknown. If a schema is
+    /// given, the context is parsed according to it.
+    #[pyo3(signature = (principal, action, resource, policies, entities, context = None, schema = None))]
+    #[allow(clippy::too_many_arguments)]
+    fn is_authorized(
+        &self,
+        principal: Option<&str>,
+        action: Option<&str>,
+        resource: Option<&str>,
+        policies: &PyPolicySet,
+        entities: &PyEntities,
+        context: Option<&PyDict>,
+        schema: Option<&PySchema>,
+    ) -> PyResult<PyResponse> {
+        let context = match context {
+            Some(context) => to_json(context)?,
+            None => json!({}),
+        };
+        self.authorize(
+            [principal, action, resource],
+            context,
+            &policies.0,
+            &entities.0,
+            schema.map(|s| &s.0),
+        )
+        .map_err(value_error)
+    }
+}
+
+impl PyAuthorizer {
+    fn authorize(
+        &self,
+        uids: [Option<&str>; 3],
+        context: Value,
+        policies: &PolicySet,
+        entities: &Entities,
+        schema: Option<&Schema>,
+    ) -> Result<PyResponse, String> {
+        let [principal, action, resource] = uids.map(|uid| {
+            uid.map(|uid| {
+                EntityUid::from_str(uid).map_err(|e| format!("invalid entity uid {uid}: {e}"))
+            })
+            .transpose()
+        });
+        let (principal, action, resource) = (principal?, action?, resource?);
+        let context = Context::from_json_value(context, schema.zip(action.as_ref()))
+            .map_err(|e| e.to_string())?;
+        let request = Request::new(principal, action, resource, context);
+        let response = self.0.is_authorized(&request, policies, entities);
+        Ok(PyResponse {
+            decision: match response.decision() {
+                Decision::Allow => "Allow",
+                Decision::Deny => "Deny",
+            }
+            .into(),
+            reasons: response
+                .diagnostics()
+                .reason()
+                .map(ToString::to_string)
+                .collect(),
+            errors: response
+                .diagnostics()
+                .errors()
+                .map(ToString::to_string)
+                .collect(),
+        })
+    }
+}
+
+/// Parse, validate, and evaluate Cedar policies
+#[pymodule]
+fn banyan(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
+    m.add_class::<PyPolicySet>()?;
+    m.add_class::<PySchema>()?;
+    m.add_class::<PyEntities>()?;
+    m.add_class::<PyExtensionValue>()?;
+    m.add_class::<PyResponse>()?;
+    m.add_class::<PyAuthorizer>()?;
+    #[cfg(feature = "ipaddr")]
+    m.add_function(wrap_pyfunction!(ip, m)?)?;
+    #[cfg(feature = "decimal")]
+    m.add_function(wrap_pyfunction!(decimal, m)?)?;
+    #[cfg(feature = "u256")]
+    m.add_function(wrap_pyfunction!(u256, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "u256")]
+    fn authorize() {
+        let authorizer = PyAuthorizer(Authorizer::new());
+        let policies = PolicySet::from_str(
+            r#"permit(principal == User::"alice", action == Action::"transfer", resource)
+               when { context.amount.u256LessThan(u256("1000")) };"#,
+        )
+        .expect("policies should parse");
+        let uids = [
+            Some(r#"User::"alice""#),
+            Some(r#"Action::"transfer""#),
+            Some(r#"Wallet::"w""#),
+        ];
+        let amount = |arg| json!({ "amount": PyExtensionValue::new("u256", arg).expect("valid u256").to_json() });
+        let entities = parse_entities("[]", None).expect("valid entities");
+
+        let response = authorizer
+            .authorize(uids, amount("5"), &policies, &entities, None)
+            .expect("well-formed request");
+        assert!(response.allowed());
+        assert_eq!(response.reasons, ["policy0"]);
+
+        let response = authorizer
+            .authorize(uids, amount("5000"), &policies, &entities, None)
+            .expect("well-formed request");
+        assert_eq!(response.decision, "Deny");
+
+        // a context of the wrong type is an evaluation error, not a failure
+        let response = authorizer
+            .authorize(uids, json!({ "amount": 5 }), &policies, &entities, None)
+            .expect("well-formed request");
+        assert_eq!(response.decision, "Deny");
+        assert_eq!(response.errors.len(), 1);
+
+        assert!(authorizer
+            .authorize(
+                [Some("alice"), None, None],
+                json!({}),
+                &policies,
+                &entities,
+                None
+            )
+            .is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "u256")]
+    fn extension_values() {
+        let value = PyExtensionValue::new("u256", "1000").expect("valid u256");
+        assert_eq!(value.__repr__(), r#"u256("1000")"#);
+        assert!(PyExtensionValue::new("u256", "-1").is_err());
+        assert!(PyExtensionValue::new("nope", "1").is_err());
+    }
+
+    #[test]
+    fn entities_with_schema() {
+        let schema = Schema::from_str(
+            r#"{ "": {
+                "entityTypes": { "User": {}, "Wallet": {} },
+                "actions": { "transfer": { "appliesTo": { "principalTypes": ["User"], "resourceTypes": ["Wallet"] } } }
+            } }"#,
+        )
+        .expect("valid schema");
+        let entities = parse_entities(
+            r#"[{ "uid": { "type": "User", "id": "alice" }, "attrs": {}, "parents": [] }]"#,
+            Some(&schema),
+        )
+        .expect("valid entities");
+        // the user, plus the action from the schema
+        assert_eq!(entities.iter().count(), 2);
+    }
+}
@@ -0,0 +1,42 @@
+import pytest
+
+import banyan
+
+POLICIES = """
+permit(principal == User::"alice", action == Action::"transfer", resource)
+when { context.amount.u256LessThan(u256("1000")) };
+"""
+
+
+def test_is_authorized():
+    policies = banyan.PolicySet(POLICIES)
+    assert policies.policy_ids() == ["policy0"]
+    entities = banyan.Entities("[]")
+    authorizer = banyan.Authorizer()
+
+    def check(amount):
+        return authorizer.is_authorized(
+            'User::"alice"',
+            'Action::"transfer"',
+            'Wallet::"w"',
+            policies,
+            entities,
+            context={"amount": amount},
+        )
+
+    allowed = check(banyan.u256("5"))
+    assert allowed.allowed
+    assert allowed.reasons == ["policy0"]
+    assert check(banyan.u256("5000")).decision == "Deny"
+    assert len(check(5).errors) == 1
+
+
+def test_errors():
+    with pytest.raises(ValueError):
+        banyan.PolicySet("permit(")
+    with pytest.raises(ValueError):
+        banyan.u256("-1")
+    with pytest.raises(TypeError):
+        banyan.Authorizer().is_authorized(
+            None, None, None, banyan.PolicySet(""), banyan.Entities("[]"), {"x": 1.5}
+        )
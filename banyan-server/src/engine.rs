@@ -0,0 +1,419 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! The transport-independent core of the server: answering authorization
+//! requests and validating and storing policies.
+
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Instant;
+
+use cedar_policy::provenance::{self, SignatureMode, TrustedAuthors};
+use cedar_policy::{
+    metrics, AuthorizationError, Authorizer, Context, Entities, EntityUid, Policy, PolicySet,
+    Request, Response, Schema, ValidationMode, Validator,
+};
+use thiserror::Error;
+
+use crate::store::{PolicyStore, StoreError};
+
+/// Errors answering a call
+#[derive(Debug, Error)]
+pub enum EngineError {
+    /// The call was malformed, e.g. its context or entities failed to parse
+    #[error("invalid request: {0}")]
+    InvalidRequest(String),
+    /// Policies failed to parse or validate
+    #[error("invalid policy: {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))]
+    InvalidPolicy(Vec<PolicyDiagnostic>),
+    /// The policy store failed
+    #[error(transparent)]
+    Store(#[from] StoreError),
+}
+
+/// A problem attributed to a policy, if one is known
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolicyDiagnostic {
+    /// Id of the policy, or `None` for problems which aren't specific to one
+    /// policy (e.g. a syntax error which prevents splitting the input)
+    pub policy_id: Option<String>,
+    /// Description of the problem
+    pub message: String,
+}
+
+impl std::fmt::Display for PolicyDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.policy_id {
+            Some(id) => write!(f, "policy `{id}`: {}", self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl From<&AuthorizationError> for PolicyDiagnostic {
+    fn from(err: &AuthorizationError) -> Self {
+        match err {
+            AuthorizationError::PolicyEvaluationError { id, error } => Self {
+                policy_id: Some(id.to_string()),
+                message: error.to_string(),
+            },
+            AuthorizationError::AttributeEvaluationError(_)
+            | AuthorizationError::Replayed(_)
+            | AuthorizationError::InvalidChain(_)
+            | AuthorizationError::Halted(_)
+            | AuthorizationError::RateLimited(_)
+            | AuthorizationError::ScreeningFailed(_)
+            | AuthorizationError::InvalidHierarchy(_) => Self {
+                policy_id: None,
+                message: err.to_string(),
+            },
+        }
+    }
+}
+
+/// An authorization request, before its context and entities are parsed
+#[derive(Debug, Clone)]
+pub struct AuthorizeCall {
+    /// Principal, if known
+    pub principal: Option<EntityUid>,
+    /// Action, if known
+    pub action: Option<EntityUid>,
+    /// Resource, if known
+    pub resource: Option<EntityUid>,
+    /// Context, as a JSON object
+    pub context: serde_json::Value,
+    /// Entities to add to the server's entities for this request only, as a
+    /// JSON array in the entities file format
+    pub entities: Option<serde_json::Value>,
+}
+
+/// Answers calls against a policy store, with an optional schema and a fixed
+/// set of entities
+pub struct Engine {
+    store: Arc<dyn PolicyStore>,
+    schema: Option<Schema>,
+    validator: Option<Validator>,
+    signatures: SignatureMode,
+    trusted: TrustedAuthors,
+    entities: Entities,
+    authorizer: Authorizer,
+}
+
+impl std::fmt::Debug for Engine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Engine")
+            .field("schema", &self.schema)
+            .field("entities", &self.entities)
+            .field("signatures", &self.signatures)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Engine {
+    /// Create an engine. If `schema` is given, it informs the parsing of
+    /// contexts and entities, and policies are validated against it before
+    /// they are stored.
+    pub fn new(store: Arc<dyn PolicyStore>, schema: Option<Schema>, entities: Entities) -> Self {
+        Self {
+            store,
+            validator: schema.clone().map(Validator::new),
+            schema,
+            signatures: SignatureMode::default(),
+            trusted: TrustedAuthors::default(),
+            entities,
+            authorizer: Authorizer::new(),
+        }
+    }
+
+    /// Check policy signatures before storing policies according to `mode`,
+    /// accepting only signatures by `trusted`. By default, unsigned policies
+    /// are accepted but no signed policies are.
+    #[must_use]
+    pub fn with_signature_mode(mut self, mode: SignatureMode, trusted: TrustedAuthors) -> Self {
+        self.signatures = mode;
+        self.trusted = trusted;
+        self
+    }
+
+    /// Answer an authorization request against the current policies
+    pub fn authorize(&self, call: AuthorizeCall) -> Result<Response, EngineError> {
+        let (_, pset) = self.store.snapshot()?;
+        self.authorize_with(&pset, call)
+    }
+
+    /// Answer several authorization requests against the same snapshot of
+    /// the policies. Fails if any request is malformed.
+    pub fn batch_authorize(
+        &self,
+        calls: impl IntoIterator<Item = AuthorizeCall>,
+    ) -> Result<Vec<Response>, EngineError> {
+        let (_, pset) = self.store.snapshot()?;
+        calls
+            .into_iter()
+            .map(|call| self.authorize_with(&pset, call))
+            .collect()
+    }
+
+    /// Parse `policies` and validate them against the schema, if there is
+    /// one. Returns every problem found; an empty result means the policies
+    /// are valid.
+    pub fn validate(&self, policies: &str) -> Vec<PolicyDiagnostic> {
+        match PolicySet::from_str(policies) {
+            Ok(pset) => self.validation_errors(&pset),
+            Err(errs) => errs
+                .iter()
+                .map(|e| PolicyDiagnostic {
+                    policy_id: None,
+                    message: e.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Parse and validate a single policy, and store it under `id`,
+    /// replacing any policy with the same id. Returns the new store version.
+    pub fn put_policy(&self, id: &str, policy: &str) -> Result<u64, EngineError> {
+        let policy = Policy::parse(Some(id.to_string()), policy).map_err(|errs| {
+            EngineError::InvalidPolicy(
+                errs.iter()
+                    .map(|e| PolicyDiagnostic {
+                        policy_id: Some(id.to_string()),
+                        message: e.to_string(),
+                    })
+                    .collect(),
+            )
+        })?;
+        let pset = PolicySet::from_policies([policy.clone()]).map_err(StoreError::from)?;
+        let errors = self.validation_errors(&pset);
+        if !errors.is_empty() {
+            return Err(EngineError::InvalidPolicy(errors));
+        }
+        Ok(self.store.put_policy(policy)?)
+    }
+
+    fn validation_errors(&self, pset: &PolicySet) -> Vec<PolicyDiagnostic> {
+        let mut errors: Vec<_> = match &self.validator {
+            Some(validator) => validator
+                .validate(pset, ValidationMode::default())
+                .validation_errors()
+                .map(|e| PolicyDiagnostic {
+                    policy_id: Some(e.location().policy_id().to_string()),
+                    message: e.error_kind().to_string(),
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        if let Err(provenance_errors) =
+            provenance::verify_policy_set(pset, self.signatures, &self.trusted)
+        {
+            errors.extend(provenance_errors.iter().map(|e| PolicyDiagnostic {
+                policy_id: e.policy_id().map(ToString::to_string),
+                message: e.to_string(),
+            }));
+        }
+        errors
+    }
+
+    fn authorize_with(
+        &self,
+        pset: &PolicySet,
+        call: AuthorizeCall,
+    ) -> Result<Response, EngineError> {
+        let context =
+            Context::from_json_value(call.context, self.schema.as_ref().zip(call.action.as_ref()))
+                .map_err(|e| EngineError::InvalidRequest(e.to_string()))?;
+        let request = Request::new(call.principal, call.action, call.resource, context);
+        match call.entities {
+            Some(json) => {
+                let start = Instant::now();
+                let entities = self
+                    .entities
+                    .clone()
+                    .add_entities_from_json_value(json, self.schema.as_ref())
+                    .map_err(|e| EngineError::InvalidRequest(e.to_string()))?;
+                metrics::record_entity_fetch(start.elapsed());
+                Ok(self.authorizer.is_authorized(&request, pset, &entities))
+            }
+            None => Ok(self
+                .authorizer
+                .is_authorized(&request, pset, &self.entities)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::store::MemoryPolicyStore;
+    use cedar_policy::Decision;
+    use serde_json::json;
+
+    fn uid(ty: &str, id: &str) -> EntityUid {
+        EntityUid::from_json(json!({ "type": ty, "id": id })).expect("valid uid")
+    }
+
+    fn schema() -> Schema {
+        Schema::from_json_value(json!(
+        { "": {
+            "entityTypes": {
+                "Wallet": { "memberOfTypes": ["Group"] },
+                "Group": {},
+                "Token": {}
+            },
+            "actions": {
+                "transfer": {
+                    "appliesTo": {
+                        "principalTypes": ["Wallet"],
+                        "resourceTypes": ["Token"],
+                        "context": {
+                            "type": "Record",
+                            "attributes": { "amount": { "type": "Long" } }
+                        }
+                    }
+                }
+            }
+        }}))
+        .expect("valid schema")
+    }
+
+    fn engine(policies: &str) -> Engine {
+        let pset = PolicySet::from_str(policies).expect("policies should parse");
+        Engine::new(
+            Arc::new(MemoryPolicyStore::new(&pset).expect("valid policy set")),
+            Some(schema()),
+            Entities::empty(),
+        )
+    }
+
+    fn transfer(amount: i64, entities: Option<serde_json::Value>) -> AuthorizeCall {
+        AuthorizeCall {
+            principal: Some(uid("Wallet", "alice")),
+            action: Some(uid("Action", "transfer")),
+            resource: Some(uid("Token", "usdc")),
+            context: json!({ "amount": amount }),
+            entities,
+        }
+    }
+
+    #[test]
+    fn authorize() {
+        let engine = engine(
+            r#"permit(principal in Group::"signers", action == Action::"transfer", resource)
+               when { context.amount < 100 };"#,
+        );
+        let signer = json!([
+            { "uid": { "type": "Wallet", "id": "alice" }, "attrs": {}, "parents": [{ "type": "Group", "id": "signers" }] },
+            { "uid": { "type": "Group", "id": "signers" }, "attrs": {}, "parents": [] }
+        ]);
+        let responses = engine
+            .batch_authorize([
+                transfer(10, Some(signer.clone())),
+                transfer(1000, Some(signer)),
+                transfer(10, None),
+            ])
+            .expect("requests are well-formed");
+        let decisions: Vec<_> = responses.iter().map(Response::decision).collect();
+        assert_eq!(decisions, [Decision::Allow, Decision::Deny, Decision::Deny]);
+
+        let mut call = transfer(10, None);
+        call.context = json!({ "amount": "ten" });
+        assert!(matches!(
+            engine.authorize(call),
+            Err(EngineError::InvalidRequest(_))
+        ));
+    }
+
+    #[test]
+    fn validate_and_put() {
+        let engine = engine("");
+        assert!(engine
+            .validate(r#"permit(principal, action == Action::"transfer", resource);"#)
+            .is_empty());
+        let errors = engine.validate(r#"permit(principal, action == Action::"mint", resource);"#);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].policy_id.as_deref(), Some("policy0"));
+        assert!(engine
+            .validate("permit(")
+            .iter()
+            .all(|e| e.policy_id.is_none()));
+
+        assert!(matches!(
+            engine.put_policy(
+                "bad",
+                r#"permit(principal, action == Action::"mint", resource);"#
+            ),
+            Err(EngineError::InvalidPolicy(_))
+        ));
+        assert_eq!(
+            engine
+                .put_policy(
+                    "small",
+                    r#"permit(principal, action, resource) when { context.amount < 100 };"#
+                )
+                .expect("valid policy"),
+            1
+        );
+        let response = engine.authorize(transfer(10, None)).expect("well-formed");
+        assert_eq!(response.decision(), Decision::Allow);
+    }
+
+    #[test]
+    fn require_signed() {
+        let key = k256::ecdsa::SigningKey::from_slice(&[7; 32]).expect("valid key");
+        let trusted =
+            TrustedAuthors::new([provenance::address(key.verifying_key())]).expect("valid address");
+        let engine = engine("").with_signature_mode(SignatureMode::RequireSigned, trusted);
+        let errors = engine.validate("permit(principal, action, resource);");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].policy_id.as_deref(), Some("policy0"));
+        assert!(matches!(
+            engine.put_policy("unsigned", "permit(principal, action, resource);"),
+            Err(EngineError::InvalidPolicy(_))
+        ));
+
+        let policy = Policy::parse(
+            Some("signed".into()),
+            format!(
+                r#"@author("{}") permit(principal, action, resource);"#,
+                provenance::address(key.verifying_key())
+            ),
+        )
+        .expect("policy should parse");
+        let signed = provenance::sign_policy(&policy, &key).expect("policy should sign");
+        assert_eq!(
+            engine
+                .put_policy("signed", &signed.to_string())
+                .expect("signed policy"),
+            1
+        );
+
+        // a policy its author signed themselves isn't accepted
+        let attacker = k256::ecdsa::SigningKey::from_slice(&[9; 32]).expect("valid key");
+        let policy = Policy::parse(
+            Some("backdoor".into()),
+            format!(
+                r#"@author("{}") permit(principal, action, resource);"#,
+                provenance::address(attacker.verifying_key())
+            ),
+        )
+        .expect("policy should parse");
+        let signed = provenance::sign_policy(&policy, &attacker).expect("policy should sign");
+        assert!(matches!(
+            engine.put_policy("backdoor", &signed.to_string()),
+            Err(EngineError::InvalidPolicy(_))
+        ));
+    }
+}
@@ -0,0 +1,477 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! An HTTP/JSON API mirroring the gRPC service, for clients without protobuf
+//! tooling. The OpenAPI document describing it is served at `/openapi.json`.
+
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post, put};
+use axum::{Json, Router};
+use cedar_policy::{Decision, EntityUid};
+use serde::{Deserialize, Serialize};
+use utoipa::{OpenApi, ToSchema};
+
+use crate::engine::{AuthorizeCall, Engine, EngineError, PolicyDiagnostic};
+
+/// Build the router serving the HTTP API
+pub fn router(engine: Arc<Engine>) -> Router {
+    Router::new()
+        .route("/v1/authorize", post(authorize))
+        .route("/v1/batch-authorize", post(batch_authorize))
+        .route("/v1/validate-policy", post(validate_policy))
+        .route("/v1/policies/:policy_id", put(put_policy))
+        .route("/openapi.json", get(openapi))
+        .with_state(engine)
+}
+
+/// The OpenAPI document for the HTTP API
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Banyan authorization API"),
+    paths(authorize, batch_authorize, validate_policy, put_policy),
+    components(schemas(
+        EntityUidBody,
+        AuthorizeBody,
+        AuthorizeAnswer,
+        DecisionBody,
+        DiagnosticsBody,
+        PolicyErrorBody,
+        BatchAuthorizeBody,
+        BatchAuthorizeAnswer,
+        ValidatePolicyBody,
+        ValidatePolicyAnswer,
+        PutPolicyBody,
+        PutPolicyAnswer,
+        ErrorBody,
+    ))
+)]
+pub struct ApiDoc;
+
+/// An entity uid, e.g. `{ "type": "Wallet", "id": "0xabc..." }`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct EntityUidBody {
+    #[serde(rename = "type")]
+    ty: String,
+    id: String,
+}
+
+/// An authorization request
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AuthorizeBody {
+    /// Omitted components are unknown
+    principal: Option<EntityUidBody>,
+    action: Option<EntityUidBody>,
+    resource: Option<EntityUidBody>,
+    /// Context as a JSON object; defaults to `{}`
+    #[schema(value_type = Option<Object>)]
+    context: Option<serde_json::Value>,
+    /// Entities added to the server's entities for this request only, in the
+    /// entities file format
+    #[schema(value_type = Option<Vec<Object>>)]
+    entities: Option<serde_json::Value>,
+}
+
+/// The answer to an authorization request
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AuthorizeAnswer {
+    decision: DecisionBody,
+    diagnostics: DiagnosticsBody,
+}
+
+/// An authorization decision
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum DecisionBody {
+    /// The request is permitted
+    Allow,
+    /// The request is not permitted
+    Deny,
+}
+
+/// How a decision was reached
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DiagnosticsBody {
+    /// Ids of the policies that determined the decision
+    reasons: Vec<String>,
+    /// Errors which occurred while evaluating policies
+    errors: Vec<PolicyErrorBody>,
+}
+
+/// A problem attributed to a policy, if one is known
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PolicyErrorBody {
+    #[serde(rename = "policyId", skip_serializing_if = "Option::is_none")]
+    policy_id: Option<String>,
+    message: String,
+}
+
+/// Several authorization requests, answered against the same policy-set
+/// snapshot
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BatchAuthorizeBody {
+    requests: Vec<AuthorizeBody>,
+}
+
+/// Answers to a batch of authorization requests, in request order
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BatchAuthorizeAnswer {
+    responses: Vec<AuthorizeAnswer>,
+}
+
+/// Policies to validate
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ValidatePolicyBody {
+    /// Policies in the Cedar syntax
+    policies: String,
+}
+
+/// The result of validating policies
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ValidatePolicyAnswer {
+    valid: bool,
+    errors: Vec<PolicyErrorBody>,
+}
+
+/// A policy to store
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PutPolicyBody {
+    /// A single static policy in the Cedar syntax
+    policy: String,
+}
+
+/// The result of storing a policy
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PutPolicyAnswer {
+    /// Version of the policy store after the update
+    version: u64,
+}
+
+/// A failed call
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ErrorBody {
+    error: String,
+    /// Per-policy details, for invalid policies
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<PolicyErrorBody>,
+}
+
+/// An [`EngineError`] as an HTTP response. Malformed requests are a 400,
+/// invalid policies a 422, and store failures a 500.
+struct ApiError(EngineError);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let (status, errors) = match &self.0 {
+            EngineError::InvalidRequest(_) => (StatusCode::BAD_REQUEST, Vec::new()),
+            EngineError::InvalidPolicy(diagnostics) => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                diagnostics.iter().cloned().map(Into::into).collect(),
+            ),
+            EngineError::Store(_) => (StatusCode::INTERNAL_SERVER_ERROR, Vec::new()),
+        };
+        let body = ErrorBody {
+            error: self.0.to_string(),
+            errors,
+        };
+        (status, Json(body)).into_response()
+    }
+}
+
+impl From<EngineError> for ApiError {
+    fn from(err: EngineError) -> Self {
+        Self(err)
+    }
+}
+
+impl From<PolicyDiagnostic> for PolicyErrorBody {
+    fn from(diagnostic: PolicyDiagnostic) -> Self {
+        Self {
+            policy_id: diagnostic.policy_id,
+            message: diagnostic.message,
+        }
+    }
+}
+
+impl From<cedar_policy::Response> for AuthorizeAnswer {
+    fn from(response: cedar_policy::Response) -> Self {
+        let decision = match response.decision() {
+            Decision::Allow => DecisionBody::Allow,
+            Decision::Deny => DecisionBody::Deny,
+        };
+        let mut reasons: Vec<_> = response
+            .diagnostics()
+            .reason()
+            .map(ToString::to_string)
+            .collect();
+        reasons.sort();
+        Self {
+            decision,
+            diagnostics: DiagnosticsBody {
+                reasons,
+                errors: response
+                    .diagnostics()
+                    .errors()
+                    .map(|e| PolicyDiagnostic::from(e).into())
+                    .collect(),
+            },
+        }
+    }
+}
+
+impl TryFrom<AuthorizeBody> for AuthorizeCall {
+    type Error = EngineError;
+
+    fn try_from(body: AuthorizeBody) -> Result<Self, Self::Error> {
+        fn uid(body: Option<EntityUidBody>) -> Result<Option<EntityUid>, EngineError> {
+            body.map(|uid| {
+                EntityUid::from_json(serde_json::json!({ "type": uid.ty, "id": uid.id }))
+                    .map_err(|e| EngineError::InvalidRequest(format!("invalid entity uid: {e}")))
+            })
+            .transpose()
+        }
+        Ok(Self {
+            principal: uid(body.principal)?,
+            action: uid(body.action)?,
+            resource: uid(body.resource)?,
+            context: body.context.unwrap_or_else(|| serde_json::json!({})),
+            entities: body.entities,
+        })
+    }
+}
+
+/// Answer an authorization request against the current policy set
+#[utoipa::path(
+    post,
+    path = "/v1/authorize",
+    request_body = AuthorizeBody,
+    responses(
+        (status = 200, body = AuthorizeAnswer),
+        (status = 400, description = "Malformed request", body = ErrorBody),
+    )
+)]
+async fn authorize(
+    State(engine): State<Arc<Engine>>,
+    Json(body): Json<AuthorizeBody>,
+) -> Result<Json<AuthorizeAnswer>, ApiError> {
+    let response = engine.authorize(body.try_into()?)?;
+    Ok(Json(response.into()))
+}
+
+/// Answer several authorization requests against the same policy-set
+/// snapshot
+#[utoipa::path(
+    post,
+    path = "/v1/batch-authorize",
+    request_body = BatchAuthorizeBody,
+    responses(
+        (status = 200, body = BatchAuthorizeAnswer),
+        (status = 400, description = "A request is malformed", body = ErrorBody),
+    )
+)]
+async fn batch_authorize(
+    State(engine): State<Arc<Engine>>,
+    Json(body): Json<BatchAuthorizeBody>,
+) -> Result<Json<BatchAuthorizeAnswer>, ApiError> {
+    let calls = body
+        .requests
+        .into_iter()
+        .map(AuthorizeCall::try_from)
+        .collect::<Result<Vec<_>, _>>()?;
+    let responses = engine.batch_authorize(calls)?;
+    Ok(Json(BatchAuthorizeAnswer {
+        responses: responses.into_iter().map(Into::into).collect(),
+    }))
+}
+
+/// Parse and validate policies against the server's schema, without storing
+/// them
+#[utoipa::path(
+    post,
+    path = "/v1/validate-policy",
+    request_body = ValidatePolicyBody,
+    responses((status = 200, body = ValidatePolicyAnswer))
+)]
+async fn validate_policy(
+    State(engine): State<Arc<Engine>>,
+    Json(body): Json<ValidatePolicyBody>,
+) -> Json<ValidatePolicyAnswer> {
+    let errors = engine.validate(&body.policies);
+    Json(ValidatePolicyAnswer {
+        valid: errors.is_empty(),
+        errors: errors.into_iter().map(Into::into).collect(),
+    })
+}
+
+/// Parse, validate, and store a policy, replacing any policy with the same id
+#[utoipa::path(
+    put,
+    path = "/v1/policies/{policy_id}",
+    params(("policy_id" = String, Path, description = "Id to store the policy under")),
+    request_body = PutPolicyBody,
+    responses(
+        (status = 200, body = PutPolicyAnswer),
+        (status = 422, description = "The policy is invalid", body = ErrorBody),
+    )
+)]
+async fn put_policy(
+    State(engine): State<Arc<Engine>>,
+    Path(policy_id): Path<String>,
+    Json(body): Json<PutPolicyBody>,
+) -> Result<Json<PutPolicyAnswer>, ApiError> {
+    let version = engine.put_policy(&policy_id, &body.policy)?;
+    Ok(Json(PutPolicyAnswer { version }))
+}
+
+async fn openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::store::MemoryPolicyStore;
+    use axum::body::Body;
+    use axum::http::Request;
+    use cedar_policy::{Entities, PolicySet};
+    use serde_json::{json, Value};
+    use std::str::FromStr;
+    use tower::ServiceExt;
+
+    fn app() -> Router {
+        let pset = PolicySet::from_str(
+            r#"permit(principal, action, resource) when { context.amount < 100 };"#,
+        )
+        .expect("policies should parse");
+        let store = MemoryPolicyStore::new(&pset).expect("valid policy set");
+        router(Arc::new(Engine::new(
+            Arc::new(store),
+            None,
+            Entities::empty(),
+        )))
+    }
+
+    async fn call(app: &Router, method: &str, uri: &str, body: Value) -> (StatusCode, Value) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .expect("valid request");
+        let response = app.clone().oneshot(request).await.expect("infallible");
+        let status = response.status();
+        let bytes = hyper::body::to_bytes(response.into_body())
+            .await
+            .expect("body should be readable");
+        (
+            status,
+            serde_json::from_slice(&bytes).expect("body should be JSON"),
+        )
+    }
+
+    fn transfer(amount: i64) -> Value {
+        json!({
+            "principal": { "type": "Wallet", "id": "alice" },
+            "action": { "type": "Action", "id": "transfer" },
+            "resource": { "type": "Token", "id": "usdc" },
+            "context": { "amount": amount }
+        })
+    }
+
+    #[tokio::test]
+    async fn authorize() {
+        let app = app();
+        let (status, body) = call(&app, "POST", "/v1/authorize", transfer(10)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["decision"], "Allow");
+        assert_eq!(body["diagnostics"]["reasons"], json!(["policy0"]));
+
+        let (status, body) = call(
+            &app,
+            "POST",
+            "/v1/batch-authorize",
+            json!({ "requests": [transfer(10), transfer(1000)] }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["responses"][0]["decision"], "Allow");
+        assert_eq!(body["responses"][1]["decision"], "Deny");
+
+        let (status, _) = call(
+            &app,
+            "POST",
+            "/v1/authorize",
+            json!({ "principal": { "type": "Wallet!", "id": "alice" } }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn policies() {
+        let app = app();
+        let (status, body) = call(
+            &app,
+            "POST",
+            "/v1/validate-policy",
+            json!({ "policies": "permit(" }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["valid"], false);
+
+        let (status, body) = call(
+            &app,
+            "PUT",
+            "/v1/policies/policy0",
+            json!({ "policy": "permit(principal, action, resource);" }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["version"], 1);
+        let (_, body) = call(&app, "POST", "/v1/authorize", transfer(1000)).await;
+        assert_eq!(body["decision"], "Allow");
+
+        let (status, body) = call(
+            &app,
+            "PUT",
+            "/v1/policies/broken",
+            json!({ "policy": "permit(" }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["errors"][0]["policyId"], "broken");
+    }
+
+    #[tokio::test]
+    async fn openapi_document() {
+        let app = app();
+        let request = Request::builder()
+            .uri("/openapi.json")
+            .body(Body::empty())
+            .expect("valid request");
+        let response = app.oneshot(request).await.expect("infallible");
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = hyper::body::to_bytes(response.into_body())
+            .await
+            .expect("body should be readable");
+        let doc: Value = serde_json::from_slice(&bytes).expect("body should be JSON");
+        assert!(doc["paths"]["/v1/authorize"]["post"].is_object());
+        assert!(doc["components"]["schemas"]["AuthorizeBody"].is_object());
+    }
+}
@@ -0,0 +1,33 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! An authorization service answering requests against a policy store over
+//! gRPC and HTTP/JSON, for deployments which can't link the `cedar-policy`
+//! crate directly.
+
+#![forbid(unsafe_code)]
+#![warn(missing_docs)]
+
+pub mod engine;
+pub mod http;
+pub mod service;
+pub mod store;
+
+/// Types and service stubs generated from `proto/banyan.proto`
+#[allow(missing_docs, clippy::all)]
+pub mod proto {
+    tonic::include_proto!("banyan.v1");
+}
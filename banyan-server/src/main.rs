@@ -0,0 +1,141 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+#![forbid(unsafe_code)]
+
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use banyan_server::engine::Engine;
+use banyan_server::http;
+use banyan_server::service::AuthorizerService;
+use banyan_server::store::MemoryPolicyStore;
+use cedar_policy::provenance::{self, SignatureMode, TrustedAuthors};
+use cedar_policy::{Entities, PolicySet, Schema};
+use clap::Parser;
+use tonic::transport::{Certificate, Identity, Server, ServerTlsConfig};
+
+/// Serve authorization requests over gRPC and, optionally, HTTP/JSON
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Address to listen on
+    #[arg(long, env = "BANYAN_LISTEN", default_value = "127.0.0.1:50051")]
+    listen: SocketAddr,
+    /// Address to serve the HTTP/JSON API on. Not served if omitted
+    #[arg(long, env = "BANYAN_HTTP_LISTEN")]
+    http_listen: Option<SocketAddr>,
+    /// File containing the initial policies
+    #[arg(long, value_name = "FILE")]
+    policies: Option<PathBuf>,
+    /// Schema file, in JSON format. If given, contexts and entities are
+    /// parsed according to it, and policies are validated against it
+    #[arg(long, value_name = "FILE")]
+    schema: Option<PathBuf>,
+    /// Reject policies and templates without a valid signature, both in
+    /// `--policies` and when stored. Otherwise only signed policies are
+    /// checked
+    #[arg(long)]
+    require_signed: bool,
+    /// Address of an author whose signatures are accepted. May be given more
+    /// than once; signed policies by anyone else are rejected
+    #[arg(long = "trusted-author", value_name = "ADDRESS")]
+    trusted_authors: Vec<String>,
+    /// File containing the entities used for every request, in JSON format
+    #[arg(long, value_name = "FILE")]
+    entities: Option<PathBuf>,
+    /// PEM-encoded TLS certificate chain. Serves plaintext if omitted
+    #[arg(long, value_name = "FILE", requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+    /// PEM-encoded TLS private key
+    #[arg(long, value_name = "FILE", requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+    /// PEM-encoded CA certificate. If given, clients must present a
+    /// certificate signed by it
+    #[arg(long, value_name = "FILE", requires = "tls_cert")]
+    tls_client_ca: Option<PathBuf>,
+    /// Address to serve Prometheus metrics on. Not served if omitted
+    #[cfg(feature = "metrics")]
+    #[arg(long, env = "BANYAN_METRICS_LISTEN")]
+    metrics_listen: Option<SocketAddr>,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+
+    #[cfg(feature = "metrics")]
+    if let Some(addr) = args.metrics_listen {
+        metrics_exporter_prometheus::PrometheusBuilder::new()
+            .with_http_listener(addr)
+            .install()?;
+    }
+
+    let schema = match &args.schema {
+        Some(path) => Some(Schema::from_file(std::fs::File::open(path)?)?),
+        None => None,
+    };
+    let pset = match &args.policies {
+        Some(path) => PolicySet::from_str(&std::fs::read_to_string(path)?)?,
+        None => PolicySet::new(),
+    };
+    let entities = match &args.entities {
+        Some(path) => Entities::from_json_file(std::fs::File::open(path)?, schema.as_ref())?,
+        None => Entities::empty(),
+    };
+    let signatures = if args.require_signed {
+        SignatureMode::RequireSigned
+    } else {
+        SignatureMode::VerifyIfSigned
+    };
+    let trusted = TrustedAuthors::new(&args.trusted_authors)?;
+    if let Err(errors) = provenance::verify_policy_set(&pset, signatures, &trusted) {
+        let errors: Vec<_> = errors.iter().map(ToString::to_string).collect();
+        return Err(errors.join("; ").into());
+    }
+    let store = MemoryPolicyStore::new(&pset)?;
+    let engine = Arc::new(
+        Engine::new(Arc::new(store), schema, entities).with_signature_mode(signatures, trusted),
+    );
+
+    let mut server = Server::builder();
+    if let (Some(cert), Some(key)) = (&args.tls_cert, &args.tls_key) {
+        let mut tls = ServerTlsConfig::new().identity(Identity::from_pem(
+            std::fs::read(cert)?,
+            std::fs::read(key)?,
+        ));
+        if let Some(ca) = &args.tls_client_ca {
+            tls = tls.client_ca_root(Certificate::from_pem(std::fs::read(ca)?));
+        }
+        server = server.tls_config(tls)?;
+    }
+    let grpc = server
+        .add_service(AuthorizerService::new(Arc::clone(&engine)).into_server())
+        .serve(args.listen);
+    match args.http_listen {
+        Some(addr) => {
+            let http = axum::Server::bind(&addr).serve(http::router(engine).into_make_service());
+            tokio::try_join!(
+                async { grpc.await.map_err(Box::<dyn std::error::Error>::from) },
+                async { http.await.map_err(Box::<dyn std::error::Error>::from) },
+            )?;
+        }
+        None => grpc.await?,
+    }
+    Ok(())
+}
@@ -0,0 +1,276 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! The gRPC `Authorizer` service.
+
+use std::sync::Arc;
+
+use cedar_policy::{Decision, EntityUid, Response};
+use tonic::{Request, Status};
+
+use crate::engine::{AuthorizeCall, Engine, EngineError, PolicyDiagnostic};
+use crate::proto;
+use crate::proto::authorizer_server::Authorizer;
+
+/// Implementation of the gRPC `Authorizer` service, backed by an [`Engine`]
+#[derive(Debug, Clone)]
+pub struct AuthorizerService {
+    engine: Arc<Engine>,
+}
+
+impl AuthorizerService {
+    /// Create a service answering calls with `engine`
+    pub fn new(engine: Arc<Engine>) -> Self {
+        Self { engine }
+    }
+
+    /// Wrap this service for use with a `tonic` server
+    pub fn into_server(self) -> proto::authorizer_server::AuthorizerServer<Self> {
+        proto::authorizer_server::AuthorizerServer::new(self)
+    }
+}
+
+#[tonic::async_trait]
+impl Authorizer for AuthorizerService {
+    async fn authorize(
+        &self,
+        request: Request<proto::AuthorizeRequest>,
+    ) -> Result<tonic::Response<proto::AuthorizeResponse>, Status> {
+        let call = authorize_call(request.into_inner())?;
+        let response = self.engine.authorize(call).map_err(status)?;
+        Ok(tonic::Response::new(response.into()))
+    }
+
+    async fn batch_authorize(
+        &self,
+        request: Request<proto::BatchAuthorizeRequest>,
+    ) -> Result<tonic::Response<proto::BatchAuthorizeResponse>, Status> {
+        let calls = request
+            .into_inner()
+            .requests
+            .into_iter()
+            .map(authorize_call)
+            .collect::<Result<Vec<_>, _>>()?;
+        let responses = self.engine.batch_authorize(calls).map_err(status)?;
+        Ok(tonic::Response::new(proto::BatchAuthorizeResponse {
+            responses: responses.into_iter().map(Into::into).collect(),
+        }))
+    }
+
+    async fn validate_policy(
+        &self,
+        request: Request<proto::ValidatePolicyRequest>,
+    ) -> Result<tonic::Response<proto::ValidatePolicyResponse>, Status> {
+        let errors = self.engine.validate(&request.into_inner().policies);
+        Ok(tonic::Response::new(proto::ValidatePolicyResponse {
+            valid: errors.is_empty(),
+            errors: errors.into_iter().map(Into::into).collect(),
+        }))
+    }
+
+    async fn put_policy(
+        &self,
+        request: Request<proto::PutPolicyRequest>,
+    ) -> Result<tonic::Response<proto::PutPolicyResponse>, Status> {
+        let request = request.into_inner();
+        let version = self
+            .engine
+            .put_policy(&request.policy_id, &request.policy)
+            .map_err(status)?;
+        Ok(tonic::Response::new(proto::PutPolicyResponse { version }))
+    }
+}
+
+/// Convert a request message into an [`AuthorizeCall`]
+fn authorize_call(request: proto::AuthorizeRequest) -> Result<AuthorizeCall, Status> {
+    let context = if request.context_json.is_empty() {
+        serde_json::json!({})
+    } else {
+        serde_json::from_str(&request.context_json)
+            .map_err(|e| Status::invalid_argument(format!("invalid context JSON: {e}")))?
+    };
+    let entities = if request.entities_json.is_empty() {
+        None
+    } else {
+        Some(
+            serde_json::from_str(&request.entities_json)
+                .map_err(|e| Status::invalid_argument(format!("invalid entities JSON: {e}")))?,
+        )
+    };
+    Ok(AuthorizeCall {
+        principal: request.principal.map(entity_uid).transpose()?,
+        action: request.action.map(entity_uid).transpose()?,
+        resource: request.resource.map(entity_uid).transpose()?,
+        context,
+        entities,
+    })
+}
+
+fn entity_uid(uid: proto::EntityUid) -> Result<EntityUid, Status> {
+    EntityUid::from_json(serde_json::json!({ "type": uid.r#type, "id": uid.id }))
+        .map_err(|e| Status::invalid_argument(format!("invalid entity uid: {e}")))
+}
+
+/// Map an [`EngineError`] to a gRPC status. Malformed requests and invalid
+/// policies are the caller's fault; store failures are ours.
+fn status(err: EngineError) -> Status {
+    match err {
+        EngineError::InvalidRequest(_) => Status::invalid_argument(err.to_string()),
+        EngineError::InvalidPolicy(_) => Status::failed_precondition(err.to_string()),
+        EngineError::Store(_) => Status::internal(err.to_string()),
+    }
+}
+
+impl From<Response> for proto::AuthorizeResponse {
+    fn from(response: Response) -> Self {
+        let decision = match response.decision() {
+            Decision::Allow => proto::Decision::Allow,
+            Decision::Deny => proto::Decision::Deny,
+        };
+        let mut reasons: Vec<_> = response
+            .diagnostics()
+            .reason()
+            .map(ToString::to_string)
+            .collect();
+        reasons.sort();
+        Self {
+            decision: decision.into(),
+            diagnostics: Some(proto::Diagnostics {
+                reasons,
+                errors: response
+                    .diagnostics()
+                    .errors()
+                    .map(|e| PolicyDiagnostic::from(e).into())
+                    .collect(),
+            }),
+        }
+    }
+}
+
+impl From<PolicyDiagnostic> for proto::PolicyError {
+    fn from(diagnostic: PolicyDiagnostic) -> Self {
+        Self {
+            policy_id: diagnostic.policy_id.unwrap_or_default(),
+            message: diagnostic.message,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::store::MemoryPolicyStore;
+    use cedar_policy::{Entities, PolicySet};
+    use std::str::FromStr;
+
+    fn service() -> AuthorizerService {
+        let pset = PolicySet::from_str(
+            r#"permit(principal, action, resource) when { context.amount < 100 };"#,
+        )
+        .expect("policies should parse");
+        let store = MemoryPolicyStore::new(&pset).expect("valid policy set");
+        AuthorizerService::new(Arc::new(Engine::new(
+            Arc::new(store),
+            None,
+            Entities::empty(),
+        )))
+    }
+
+    fn request(context: &str) -> proto::AuthorizeRequest {
+        let uid = |ty: &str, id: &str| proto::EntityUid {
+            r#type: ty.into(),
+            id: id.into(),
+        };
+        proto::AuthorizeRequest {
+            principal: Some(uid("Wallet", "alice")),
+            action: Some(uid("Action", "transfer")),
+            resource: Some(uid("Token", "usdc")),
+            context_json: context.into(),
+            entities_json: String::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn authorize() {
+        let service = service();
+        let response = service
+            .batch_authorize(Request::new(proto::BatchAuthorizeRequest {
+                requests: vec![
+                    request(r#"{ "amount": 10 }"#),
+                    request(r#"{ "amount": 1000 }"#),
+                    request(r#"{}"#),
+                ],
+            }))
+            .await
+            .expect("requests are well-formed")
+            .into_inner();
+        let decisions: Vec<_> = response.responses.iter().map(|r| r.decision()).collect();
+        assert_eq!(
+            decisions,
+            [
+                proto::Decision::Allow,
+                proto::Decision::Deny,
+                proto::Decision::Deny
+            ]
+        );
+        let diagnostics = response.responses[0]
+            .diagnostics
+            .as_ref()
+            .expect("diagnostics are always set");
+        assert_eq!(diagnostics.reasons, ["policy0"]);
+        // the missing attribute is reported against the policy
+        let diagnostics = response.responses[2]
+            .diagnostics
+            .as_ref()
+            .expect("diagnostics are always set");
+        assert_eq!(diagnostics.errors.len(), 1);
+        assert_eq!(diagnostics.errors[0].policy_id, "policy0");
+
+        let err = service
+            .authorize(Request::new(request("not json")))
+            .await
+            .expect_err("context is malformed");
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn put_policy() {
+        let service = service();
+        let err = service
+            .put_policy(Request::new(proto::PutPolicyRequest {
+                policy_id: "broken".into(),
+                policy: "permit(".into(),
+            }))
+            .await
+            .expect_err("policy does not parse");
+        assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+        let response = service
+            .put_policy(Request::new(proto::PutPolicyRequest {
+                policy_id: "policy0".into(),
+                policy: "permit(principal, action, resource);".into(),
+            }))
+            .await
+            .expect("valid policy")
+            .into_inner();
+        assert_eq!(response.version, 1);
+        let response = service
+            .authorize(Request::new(request("{}")))
+            .await
+            .expect("well-formed")
+            .into_inner();
+        assert_eq!(response.decision(), proto::Decision::Allow);
+    }
+}
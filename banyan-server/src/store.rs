@@ -0,0 +1,136 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Storage for the policies served by the server.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+
+use cedar_policy::{Policy, PolicySet, PolicySetError};
+use thiserror::Error;
+
+/// Errors from a [`PolicyStore`]
+#[derive(Debug, Error)]
+pub enum StoreError {
+    /// The stored policies do not form a valid policy set
+    #[error("invalid policy set: {0}")]
+    PolicySet(#[from] PolicySetError),
+    /// The backing storage failed
+    #[error("policy store backend error: {0}")]
+    Backend(String),
+}
+
+/// A source of policies for the server. Implementations must be safe to share
+/// between request handlers; reads should be cheap, since every request takes
+/// a snapshot.
+pub trait PolicyStore: Send + Sync {
+    /// The current policy set, and the store version it corresponds to
+    fn snapshot(&self) -> Result<(u64, Arc<PolicySet>), StoreError>;
+
+    /// Add `policy`, replacing any policy with the same id, and return the new
+    /// store version
+    fn put_policy(&self, policy: Policy) -> Result<u64, StoreError>;
+}
+
+/// A [`PolicyStore`] which keeps policies in memory
+#[derive(Debug, Default)]
+pub struct MemoryPolicyStore {
+    state: RwLock<MemoryState>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryState {
+    version: u64,
+    policies: BTreeMap<String, Policy>,
+    snapshot: Arc<PolicySet>,
+}
+
+impl MemoryPolicyStore {
+    /// Create a store holding the policies of `pset`. Template-linked policies
+    /// are not supported, and are reported as a `PolicySet` error.
+    pub fn new(pset: &PolicySet) -> Result<Self, StoreError> {
+        let policies: BTreeMap<_, _> = pset
+            .policies()
+            .map(|p| (p.id().to_string(), p.clone()))
+            .collect();
+        let snapshot = Arc::new(PolicySet::from_policies(policies.values().cloned())?);
+        Ok(Self {
+            state: RwLock::new(MemoryState {
+                version: 0,
+                policies,
+                snapshot,
+            }),
+        })
+    }
+}
+
+impl PolicyStore for MemoryPolicyStore {
+    fn snapshot(&self) -> Result<(u64, Arc<PolicySet>), StoreError> {
+        let state = self
+            .state
+            .read()
+            .map_err(|_| StoreError::Backend("policy store lock poisoned".into()))?;
+        Ok((state.version, Arc::clone(&state.snapshot)))
+    }
+
+    fn put_policy(&self, policy: Policy) -> Result<u64, StoreError> {
+        let mut state = self
+            .state
+            .write()
+            .map_err(|_| StoreError::Backend("policy store lock poisoned".into()))?;
+        let mut policies = state.policies.clone();
+        policies.insert(policy.id().to_string(), policy);
+        // build the new snapshot before committing, so that a failure leaves
+        // the store unchanged
+        let snapshot = Arc::new(PolicySet::from_policies(policies.values().cloned())?);
+        state.policies = policies;
+        state.snapshot = snapshot;
+        state.version += 1;
+        Ok(state.version)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn put_replaces_policy() {
+        let pset = PolicySet::from_str("permit(principal, action, resource);")
+            .expect("policy should parse");
+        let store = MemoryPolicyStore::new(&pset).expect("valid policy set");
+        let (version, snapshot) = store.snapshot().expect("snapshot");
+        assert_eq!(version, 0);
+        assert_eq!(snapshot.policies().count(), 1);
+
+        let replacement = Policy::parse(
+            Some("policy0".into()),
+            "forbid(principal, action, resource);",
+        )
+        .expect("policy should parse");
+        assert_eq!(store.put_policy(replacement).expect("put"), 1);
+        let added = Policy::parse(Some("other".into()), "permit(principal, action, resource);")
+            .expect("policy should parse");
+        assert_eq!(store.put_policy(added).expect("put"), 2);
+
+        let (version, new_snapshot) = store.snapshot().expect("snapshot");
+        assert_eq!(version, 2);
+        assert_eq!(new_snapshot.policies().count(), 2);
+        // earlier snapshots are unaffected
+        assert_eq!(snapshot.policies().count(), 1);
+    }
+}
@@ -0,0 +1,396 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::str::FromStr;
+
+use cedar_policy::revocation::Revocation;
+use cedar_policy::{
+    Authorizer, Context, Decision, Entities, EntityId, EntityTypeName, EntityUid, Policy, PolicyId,
+    PolicySet, Request, RestrictedExpression, Template,
+};
+
+use crate::{Link, PolicyStore, StoreError};
+
+/// The entity type of the stored items meta-policies govern
+pub const POLICY_TYPE: &str = "Policy";
+
+/// The action of saving an item whose id isn't stored yet
+pub const CREATE_ACTION: &str = "createPolicy";
+/// The action of saving an item which replaces a stored one
+pub const UPDATE_ACTION: &str = "updatePolicy";
+/// The action of removing a stored item
+pub const DELETE_ACTION: &str = "deletePolicy";
+/// The action of revoking a template link
+pub const REVOKE_ACTION: &str = "revokePolicy";
+/// The action of removing the revocation of a template link
+pub const REINSTATE_ACTION: &str = "reinstatePolicy";
+
+/// A [`PolicyStore`] whose contents may only be changed as its meta-policies
+/// allow.
+///
+/// Meta-policies are Cedar policies over who may change the store. Each
+/// change is a request whose principal is the administrator making it, whose
+/// resource is the `Policy` entity with the item's id, and whose action is
+/// one of [`CREATE_ACTION`], [`UPDATE_ACTION`], [`DELETE_ACTION`],
+/// [`REVOKE_ACTION`], and [`REINSTATE_ACTION`], e.g.
+/// ```text
+/// permit(principal in Role::"policy-admins", action, resource);
+/// permit(
+///   principal in Role::"signers-admins",
+///   action in [Action::"createPolicy", Action::"deletePolicy"],
+///   resource
+/// ) when { context.kind == "link" && context.templateId == "signer" };
+/// ```
+/// The context holds
+/// - `kind`: `policy`, `template`, or `link`, except for revocations
+/// - `effect`: `permit` or `forbid`, when saving a static policy or template
+/// - `templateId`: the template, when saving a link
+/// - `reason`: why, when revoking a link
+///
+/// The store is reached through [`GovernedStore::admin()`], which answers
+/// each change with [`StoreError::Forbidden`] unless the meta-policies allow
+/// it. Reading is not governed. The meta-policies themselves aren't in the
+/// store, so they can't be changed through it.
+#[derive(Debug)]
+pub struct GovernedStore<S: PolicyStore> {
+    store: S,
+    policies: PolicySet,
+    entities: Entities,
+    authorizer: Authorizer,
+}
+
+impl<S: PolicyStore> GovernedStore<S> {
+    /// `store`, governed by the meta-policies `policies`, with the
+    /// administrators and their roles in `entities`
+    pub fn new(store: S, policies: PolicySet, entities: Entities) -> Self {
+        Self {
+            store,
+            policies,
+            entities,
+            authorizer: Authorizer::new(),
+        }
+    }
+
+    /// Evaluate the meta-policies with `authorizer`, e.g. to apply the same
+    /// extensions, limits, or audit sink as other requests
+    #[must_use]
+    pub fn with_authorizer(mut self, authorizer: Authorizer) -> Self {
+        self.authorizer = authorizer;
+        self
+    }
+
+    /// The store, without governance, e.g. to load it
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// The store, as changed by `principal`
+    pub fn admin(&self, principal: EntityUid) -> AdminStore<'_, S> {
+        AdminStore {
+            governed: self,
+            principal,
+        }
+    }
+}
+
+/// A [`GovernedStore`] as changed by one administrator; see
+/// [`GovernedStore::admin()`]
+#[derive(Debug)]
+pub struct AdminStore<'a, S: PolicyStore> {
+    governed: &'a GovernedStore<S>,
+    principal: EntityUid,
+}
+
+impl<S: PolicyStore> AdminStore<'_, S> {
+    /// Check that the meta-policies allow the administrator to `action` the
+    /// item `id`
+    fn authorize(
+        &self,
+        action: &str,
+        id: &PolicyId,
+        context: Vec<(&str, RestrictedExpression)>,
+    ) -> Result<(), StoreError> {
+        let context = Context::from_pairs(
+            context
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value)),
+        );
+        let request = Request::new(
+            Some(self.principal.clone()),
+            Some(uid("Action", action)),
+            Some(uid(POLICY_TYPE, id.as_ref())),
+            context,
+        );
+        let response = self.governed.authorizer.is_authorized(
+            &request,
+            &self.governed.policies,
+            &self.governed.entities,
+        );
+        if response.decision() == Decision::Allow {
+            Ok(())
+        } else {
+            Err(StoreError::Forbidden {
+                principal: self.principal.clone(),
+                action: action.to_string(),
+                id: id.clone(),
+            })
+        }
+    }
+
+    /// The action of saving an item of the kind listed by `stored` with id
+    /// `id`
+    fn save_action<T>(
+        id: &PolicyId,
+        stored: Result<Vec<T>, StoreError>,
+        id_of: impl Fn(&T) -> &PolicyId,
+    ) -> Result<&'static str, StoreError> {
+        Ok(if stored?.iter().any(|item| id_of(item) == id) {
+            UPDATE_ACTION
+        } else {
+            CREATE_ACTION
+        })
+    }
+
+    /// The kind of the stored item `id`, if there is one
+    fn kind(&self, id: &PolicyId) -> Result<Option<&'static str>, StoreError> {
+        let store = &self.governed.store;
+        Ok(if store.static_policies()?.iter().any(|p| p.id() == id) {
+            Some("policy")
+        } else if store.templates()?.iter().any(|t| t.id() == id) {
+            Some("template")
+        } else if store.links()?.iter().any(|l| &l.id == id) {
+            Some("link")
+        } else {
+            None
+        })
+    }
+}
+
+impl<S: PolicyStore> PolicyStore for AdminStore<'_, S> {
+    fn static_policies(&self) -> Result<Vec<Policy>, StoreError> {
+        self.governed.store.static_policies()
+    }
+
+    fn templates(&self) -> Result<Vec<Template>, StoreError> {
+        self.governed.store.templates()
+    }
+
+    fn links(&self) -> Result<Vec<Link>, StoreError> {
+        self.governed.store.links()
+    }
+
+    fn save_static_policy(&self, policy: &Policy) -> Result<(), StoreError> {
+        let action = Self::save_action(policy.id(), self.static_policies(), Policy::id)?;
+        self.authorize(
+            action,
+            policy.id(),
+            vec![
+                ("kind", RestrictedExpression::new_string("policy".into())),
+                (
+                    "effect",
+                    RestrictedExpression::new_string(policy.effect().to_string()),
+                ),
+            ],
+        )?;
+        self.governed.store.save_static_policy(policy)
+    }
+
+    fn save_template(&self, template: &Template) -> Result<(), StoreError> {
+        let action = Self::save_action(template.id(), self.templates(), Template::id)?;
+        self.authorize(
+            action,
+            template.id(),
+            vec![
+                ("kind", RestrictedExpression::new_string("template".into())),
+                (
+                    "effect",
+                    RestrictedExpression::new_string(template.effect().to_string()),
+                ),
+            ],
+        )?;
+        self.governed.store.save_template(template)
+    }
+
+    fn save_link(&self, link: &Link) -> Result<(), StoreError> {
+        let action = Self::save_action(&link.id, self.links(), |l| &l.id)?;
+        self.authorize(
+            action,
+            &link.id,
+            vec![
+                ("kind", RestrictedExpression::new_string("link".into())),
+                (
+                    "templateId",
+                    RestrictedExpression::new_string(link.template_id.to_string()),
+                ),
+            ],
+        )?;
+        self.governed.store.save_link(link)
+    }
+
+    fn revocations(&self) -> Result<Vec<Revocation>, StoreError> {
+        self.governed.store.revocations()
+    }
+
+    fn save_revocation(&self, revocation: &Revocation) -> Result<(), StoreError> {
+        self.authorize(
+            REVOKE_ACTION,
+            &revocation.id,
+            vec![(
+                "reason",
+                RestrictedExpression::new_string(revocation.reason.clone()),
+            )],
+        )?;
+        self.governed.store.save_revocation(revocation)
+    }
+
+    /// Remove the revocation of `id`. Removing a revocation which doesn't
+    /// exist changes nothing, so it isn't governed.
+    fn remove_revocation(&self, id: &PolicyId) -> Result<bool, StoreError> {
+        if !self.revocations()?.iter().any(|r| &r.id == id) {
+            return Ok(false);
+        }
+        self.authorize(REINSTATE_ACTION, id, vec![])?;
+        self.governed.store.remove_revocation(id)
+    }
+
+    /// Remove the item `id`. Removing an item which doesn't exist changes
+    /// nothing, so it isn't governed.
+    fn remove(&self, id: &PolicyId) -> Result<bool, StoreError> {
+        let Some(kind) = self.kind(id)? else {
+            return Ok(false);
+        };
+        self.authorize(
+            DELETE_ACTION,
+            id,
+            vec![("kind", RestrictedExpression::new_string(kind.into()))],
+        )?;
+        self.governed.store.remove(id)
+    }
+}
+
+/// The entity `type_name::"id"`
+fn uid(type_name: &str, id: &str) -> EntityUid {
+    // PANIC SAFETY: `type_name` is one of our constants, which are valid type
+    // names, and parsing an `EntityId` never fails
+    #[allow(clippy::unwrap_used)]
+    EntityUid::from_type_name_and_id(
+        EntityTypeName::from_str(type_name).unwrap(),
+        EntityId::from_str(id).unwrap(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::FsPolicyStore;
+    use cedar_policy::Entity;
+    use std::collections::{HashMap, HashSet};
+
+    fn user(name: &str) -> EntityUid {
+        EntityUid::from_str(&format!(r#"User::"{name}""#)).expect("valid uid")
+    }
+
+    fn governed(dir: &std::path::Path) -> GovernedStore<FsPolicyStore> {
+        let policies = PolicySet::from_str(
+            r#"permit(principal in Role::"admins", action, resource);
+               permit(
+                 principal == User::"bob",
+                 action == Action::"createPolicy",
+                 resource
+               ) when { context.kind == "policy" && context.effect == "forbid" };"#,
+        )
+        .expect("meta-policies should parse");
+        let alice = Entity::new(
+            user("alice"),
+            HashMap::new(),
+            HashSet::from([EntityUid::from_str(r#"Role::"admins""#).expect("valid uid")]),
+        );
+        let entities = Entities::from_entities([alice]).expect("valid entities");
+        GovernedStore::new(
+            FsPolicyStore::open(dir).expect("store should open"),
+            policies,
+            entities,
+        )
+    }
+
+    fn policy(id: &str, text: &str) -> Policy {
+        Policy::parse(Some(id.into()), text).expect("policy should parse")
+    }
+
+    #[test]
+    fn changes_are_governed() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let governed = governed(dir.path());
+        let alice = governed.admin(user("alice"));
+        let bob = governed.admin(user("bob"));
+        let mallory = governed.admin(user("mallory"));
+
+        let deny_all = policy("deny-all", "forbid(principal, action, resource);");
+        let allow_all = policy("allow-all", "permit(principal, action, resource);");
+        // bob may add forbid policies, but not permit policies
+        bob.save_static_policy(&deny_all)
+            .expect("bob may add forbids");
+        assert!(matches!(
+            bob.save_static_policy(&allow_all),
+            Err(StoreError::Forbidden { action, .. }) if action == CREATE_ACTION
+        ));
+        // nor change or remove them
+        assert!(matches!(
+            bob.save_static_policy(&deny_all),
+            Err(StoreError::Forbidden { action, .. }) if action == UPDATE_ACTION
+        ));
+        assert!(matches!(
+            bob.remove(deny_all.id()),
+            Err(StoreError::Forbidden { action, .. }) if action == DELETE_ACTION
+        ));
+        assert!(matches!(
+            mallory.save_static_policy(&deny_all),
+            Err(StoreError::Forbidden { principal, .. }) if principal == user("mallory")
+        ));
+        let revocation = Revocation::new(deny_all.id().clone(), "compromised");
+        assert!(mallory.save_revocation(&revocation).is_err());
+        assert_eq!(
+            mallory
+                .remove(&PolicyId::from_str("missing").expect("valid id"))
+                .ok(),
+            Some(false)
+        );
+
+        let stored = |id: &str| {
+            governed
+                .store()
+                .load()
+                .expect("store should load")
+                .policies
+                .policy(&PolicyId::from_str(id).expect("valid id"))
+                .is_some()
+        };
+        assert!(stored("deny-all"));
+        assert!(!stored("allow-all"));
+
+        // admins may do anything
+        alice
+            .save_static_policy(&allow_all)
+            .expect("alice may add permits");
+        alice
+            .save_revocation(&revocation)
+            .expect("alice may revoke");
+        assert_eq!(alice.remove_revocation(deny_all.id()).ok(), Some(true));
+        assert_eq!(alice.remove(deny_all.id()).ok(), Some(true));
+        assert!(stored("allow-all"));
+        assert!(!stored("deny-all"));
+    }
+}
@@ -0,0 +1,518 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Bundles: the whole contents of a store in one JSON document, for moving it
+//! between environments.
+//!
+//! With the `encryption` feature, a bundle can be kept encrypted, e.g. in
+//! object storage, as an [`Envelope`] of [`BUNDLE_CONTENT_TYPE`].
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "encryption")]
+use cedar_policy::envelope::{Envelope, KeyWrapper};
+use cedar_policy::revocation::Revocation;
+use cedar_policy::{Policy, Schema, Template};
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
+
+use crate::{
+    parse_static_policy, parse_template, static_policy_text, Link, LinkRecord, StoreError,
+};
+
+/// Version of the bundle format written by [`Bundle`]
+pub const BUNDLE_VERSION: u32 = 1;
+
+/// The content type of envelopes holding bundles
+#[cfg(feature = "encryption")]
+pub const BUNDLE_CONTENT_TYPE: &str = "banyan/policy-bundle";
+
+/// Where a bundle came from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleMetadata {
+    /// Name of the exported store, e.g. `treasury-staging`
+    pub name: String,
+    /// When the bundle was exported, in seconds since the Unix epoch
+    pub exported_at: u64,
+    /// What the bundle is for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+impl BundleMetadata {
+    /// Metadata for a bundle of the store `name` exported now
+    pub fn new(name: impl Into<String>, description: Option<String>) -> Self {
+        let exported_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or(Duration::ZERO)
+            .as_secs();
+        Self {
+            name: name.into(),
+            exported_at,
+            description,
+        }
+    }
+}
+
+/// The static policies, templates, links, and revocations of a store, with an
+/// optional schema, as written by
+/// [`PolicyStore::export_bundle()`](crate::PolicyStore::export_bundle) and
+/// read by [`PolicyStore::import_bundle()`](crate::PolicyStore::import_bundle).
+///
+/// A bundle serializes as a single JSON document. Each item carries the
+/// Keccak-256 hash of its contents, and the bundle carries a hash of its
+/// metadata and all the item hashes, so an edited, added, or dropped item is
+/// detected on import.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Bundle {
+    version: u32,
+    metadata: BundleMetadata,
+    policies: Vec<TextEntry>,
+    templates: Vec<TextEntry>,
+    links: Vec<JsonEntry<LinkRecord>>,
+    revocations: Vec<JsonEntry<Revocation>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    schema: Option<SchemaEntry>,
+    hash: String,
+}
+
+/// A policy or template as Cedar text
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TextEntry {
+    id: String,
+    text: String,
+    hash: String,
+}
+
+impl TextEntry {
+    fn new(id: &str, text: String) -> Self {
+        Self {
+            id: id.to_string(),
+            hash: keccak_hex(text.as_bytes()),
+            text,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct JsonEntry<T> {
+    #[serde(flatten)]
+    item: T,
+    hash: String,
+}
+
+impl<T: Serialize> JsonEntry<T> {
+    fn new(item: T) -> Result<Self, StoreError> {
+        let hash = keccak_hex(&serde_json::to_vec(&item)?);
+        Ok(Self { item, hash })
+    }
+
+    fn verify(&self, what: impl FnOnce() -> String) -> Result<&T, StoreError> {
+        if keccak_hex(&serde_json::to_vec(&self.item)?) == self.hash {
+            Ok(&self.item)
+        } else {
+            Err(StoreError::HashMismatch(what()))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SchemaEntry {
+    json: serde_json::Value,
+    hash: String,
+}
+
+/// The parsed contents of a verified [`Bundle`]
+pub(crate) struct BundleContents {
+    pub(crate) policies: Vec<Policy>,
+    pub(crate) templates: Vec<Template>,
+    pub(crate) links: Vec<Link>,
+    pub(crate) revocations: Vec<Revocation>,
+}
+
+impl Bundle {
+    /// Bundle the given items. Each list is sorted by id, so bundling the same
+    /// items gives the same bundle.
+    pub(crate) fn new(
+        metadata: BundleMetadata,
+        mut policies: Vec<Policy>,
+        mut templates: Vec<Template>,
+        mut links: Vec<Link>,
+        mut revocations: Vec<Revocation>,
+        schema: Option<serde_json::Value>,
+    ) -> Result<Self, StoreError> {
+        policies.sort_by(|a, b| a.id().as_ref().cmp(b.id().as_ref()));
+        templates.sort_by(|a, b| a.id().as_ref().cmp(b.id().as_ref()));
+        links.sort_by(|a, b| a.id.as_ref().cmp(b.id.as_ref()));
+        revocations.sort_by(|a, b| a.id.as_ref().cmp(b.id.as_ref()));
+        if let Some(json) = &schema {
+            Schema::from_json_value(json.clone())?;
+        }
+        let mut bundle = Self {
+            version: BUNDLE_VERSION,
+            metadata,
+            policies: policies
+                .iter()
+                .map(|policy| {
+                    Ok(TextEntry::new(
+                        policy.id().as_ref(),
+                        static_policy_text(policy)?,
+                    ))
+                })
+                .collect::<Result<_, StoreError>>()?,
+            templates: templates
+                .iter()
+                .map(|template| TextEntry::new(template.id().as_ref(), template.to_string()))
+                .collect(),
+            links: links
+                .iter()
+                .map(|link| JsonEntry::new(LinkRecord::from(link)))
+                .collect::<Result<_, _>>()?,
+            revocations: revocations
+                .into_iter()
+                .map(JsonEntry::new)
+                .collect::<Result<_, _>>()?,
+            schema: schema
+                .map(|json| {
+                    Ok::<_, StoreError>(SchemaEntry {
+                        hash: keccak_hex(&serde_json::to_vec(&json)?),
+                        json,
+                    })
+                })
+                .transpose()?,
+            hash: String::new(),
+        };
+        bundle.hash = bundle.bundle_hash()?;
+        Ok(bundle)
+    }
+
+    /// Version of the bundle format
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Where the bundle came from
+    pub fn metadata(&self) -> &BundleMetadata {
+        &self.metadata
+    }
+
+    /// The schema in the bundle, if any, as JSON
+    pub fn schema_json(&self) -> Option<&serde_json::Value> {
+        self.schema.as_ref().map(|entry| &entry.json)
+    }
+
+    /// The bundle's JSON, encrypted under a data key wrapped by `wrapper`
+    #[cfg(feature = "encryption")]
+    pub fn encrypt(&self, wrapper: &dyn KeyWrapper) -> Result<Envelope, StoreError> {
+        Ok(Envelope::seal(
+            BUNDLE_CONTENT_TYPE,
+            &serde_json::to_vec(self)?,
+            wrapper,
+        )?)
+    }
+
+    /// Read a bundle from an envelope, decrypting it with the data key
+    /// unwrapped by `wrapper`. Its hashes are checked when it's imported.
+    #[cfg(feature = "encryption")]
+    pub fn decrypt(envelope: &Envelope, wrapper: &dyn KeyWrapper) -> Result<Self, StoreError> {
+        Ok(serde_json::from_slice(
+            &envelope.open(BUNDLE_CONTENT_TYPE, wrapper)?,
+        )?)
+    }
+
+    /// Hash of the version, the metadata, and each list of item hashes
+    fn bundle_hash(&self) -> Result<String, StoreError> {
+        let mut hasher = Keccak256::new();
+        hasher.update(self.version.to_be_bytes());
+        hasher.update(serde_json::to_vec(&self.metadata)?);
+        let sections: [Vec<&String>; 5] = [
+            self.policies.iter().map(|entry| &entry.hash).collect(),
+            self.templates.iter().map(|entry| &entry.hash).collect(),
+            self.links.iter().map(|entry| &entry.hash).collect(),
+            self.revocations.iter().map(|entry| &entry.hash).collect(),
+            self.schema.iter().map(|entry| &entry.hash).collect(),
+        ];
+        for hashes in sections {
+            // the length keeps an item from moving between lists unnoticed
+            hasher.update((hashes.len() as u64).to_be_bytes());
+            for hash in hashes {
+                hasher.update(hash.as_bytes());
+            }
+        }
+        Ok(to_hex(&hasher.finalize()))
+    }
+
+    /// Check the version and every hash, and parse the items
+    pub(crate) fn contents(&self) -> Result<BundleContents, StoreError> {
+        if self.version != BUNDLE_VERSION {
+            return Err(StoreError::UnsupportedBundleVersion(self.version));
+        }
+        if self.bundle_hash()? != self.hash {
+            return Err(StoreError::HashMismatch("bundle".into()));
+        }
+        let verify_text = |kind: &str, entry: &TextEntry| {
+            if keccak_hex(entry.text.as_bytes()) == entry.hash {
+                Ok(())
+            } else {
+                Err(StoreError::HashMismatch(format!("{kind} `{}`", entry.id)))
+            }
+        };
+        let policies = self
+            .policies
+            .iter()
+            .map(|entry| {
+                verify_text("policy", entry)?;
+                parse_static_policy(&entry.id, &entry.text)
+            })
+            .collect::<Result<_, _>>()?;
+        let templates = self
+            .templates
+            .iter()
+            .map(|entry| {
+                verify_text("template", entry)?;
+                parse_template(&entry.id, &entry.text)
+            })
+            .collect::<Result<_, _>>()?;
+        let links = self
+            .links
+            .iter()
+            .map(|entry| {
+                let record = entry.verify(|| format!("link `{}`", entry.item.id))?;
+                Link::try_from(record.clone())
+            })
+            .collect::<Result<_, _>>()?;
+        let revocations = self
+            .revocations
+            .iter()
+            .map(|entry| {
+                entry
+                    .verify(|| format!("revocation of `{}`", entry.item.id))
+                    .cloned()
+            })
+            .collect::<Result<_, _>>()?;
+        if let Some(entry) = &self.schema {
+            if keccak_hex(&serde_json::to_vec(&entry.json)?) != entry.hash {
+                return Err(StoreError::HashMismatch("schema".into()));
+            }
+            Schema::from_json_value(entry.json.clone())?;
+        }
+        Ok(BundleContents {
+            policies,
+            templates,
+            links,
+            revocations,
+        })
+    }
+}
+
+fn keccak_hex(bytes: &[u8]) -> String {
+    to_hex(&Keccak256::digest(bytes))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    use std::fmt::Write as _;
+    bytes.iter().fold(String::from("0x"), |mut hex, b| {
+        // writing to a `String` can't fail
+        let _ = write!(hex, "{b:02x}");
+        hex
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{FsPolicyStore, LinkMetadata, PolicyStore};
+    use cedar_policy::{EntityUid, PolicyId, SlotId};
+    use serde_json::json;
+    use std::str::FromStr;
+
+    fn populated_store(dir: &std::path::Path) -> FsPolicyStore {
+        let store = FsPolicyStore::open(dir).expect("store should open");
+        store
+            .save_static_policy(
+                &Policy::parse(
+                    Some("limit".into()),
+                    "forbid(principal, action, resource) when { context.amount > 100 };",
+                )
+                .expect("policy should parse"),
+            )
+            .expect("save policy");
+        let template = Template::parse(
+            Some("signer".into()),
+            "permit(principal == ?principal, action, resource);",
+        )
+        .expect("template should parse");
+        store.save_template(&template).expect("save template");
+        let id = PolicyId::from_str("alice signs").expect("valid id");
+        store
+            .save_link(&Link {
+                id: id.clone(),
+                template_id: template.id().clone(),
+                values: [(
+                    SlotId::principal(),
+                    EntityUid::from_str(r#"User::"alice""#).expect("uid"),
+                )]
+                .into_iter()
+                .collect(),
+                metadata: LinkMetadata {
+                    created_by: "0xabc".into(),
+                    created_at: 1_700_000_000,
+                    proposal_id: None,
+                },
+            })
+            .expect("save link");
+        store
+            .save_revocation(&Revocation {
+                id,
+                reason: "key compromised".into(),
+                revoked_at: 1_700_000_100,
+            })
+            .expect("save revocation");
+        store
+    }
+
+    fn schema() -> serde_json::Value {
+        json!({ "": { "entityTypes": { "User": {} }, "actions": {} } })
+    }
+
+    fn exported(dir: &std::path::Path) -> serde_json::Value {
+        let bundle = populated_store(&dir.join("source"))
+            .export_bundle(
+                BundleMetadata::new("staging", Some("release 12".into())),
+                Some(schema()),
+            )
+            .expect("export");
+        serde_json::to_value(&bundle).expect("bundle serializes")
+    }
+
+    #[test]
+    fn export_and_import() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let json = exported(dir.path());
+        let bundle: Bundle = serde_json::from_value(json).expect("bundle deserializes");
+        assert_eq!(bundle.version(), BUNDLE_VERSION);
+        assert_eq!(bundle.metadata().name, "staging");
+        assert_eq!(bundle.schema_json(), Some(&schema()));
+
+        let target = FsPolicyStore::open(dir.path().join("target")).expect("store should open");
+        target.import_bundle(&bundle).expect("import");
+        let source = populated_store(&dir.path().join("source"))
+            .load()
+            .expect("load");
+        let imported = target.load().expect("load");
+        assert_eq!(imported.links, source.links);
+        assert_eq!(
+            imported.policies.policies().count(),
+            source.policies.policies().count()
+        );
+        assert!(imported
+            .revocations
+            .get(&PolicyId::from_str("alice signs").expect("valid id"))
+            .is_some());
+
+        // exporting the same contents gives the same bundle
+        let again = target
+            .export_bundle(bundle.metadata().clone(), Some(schema()))
+            .expect("export");
+        assert_eq!(again.hash, bundle.hash);
+    }
+
+    #[test]
+    fn tampering() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let target = FsPolicyStore::open(dir.path().join("target")).expect("store should open");
+        let json = exported(dir.path());
+        let import = |json: &serde_json::Value| {
+            let bundle: Bundle = serde_json::from_value(json.clone()).expect("bundle deserializes");
+            target.import_bundle(&bundle)
+        };
+
+        let mut edited = json.clone();
+        *edited.pointer_mut("/policies/0/text").expect("policy") =
+            json!("permit(principal, action, resource);");
+        assert!(matches!(
+            import(&edited),
+            Err(StoreError::HashMismatch(what)) if what == "policy `limit`"
+        ));
+
+        let mut edited = json.clone();
+        *edited
+            .pointer_mut("/links/0/values/?principal")
+            .expect("link") = json!(r#"User::"mallory""#);
+        assert!(matches!(
+            import(&edited),
+            Err(StoreError::HashMismatch(what)) if what == "link `alice signs`"
+        ));
+
+        let mut edited = json.clone();
+        *edited.pointer_mut("/revocations").expect("revocations") = json!([]);
+        assert!(matches!(
+            import(&edited),
+            Err(StoreError::HashMismatch(what)) if what == "bundle"
+        ));
+
+        let mut edited = json;
+        *edited.pointer_mut("/version").expect("version") = json!(2);
+        assert!(matches!(
+            import(&edited),
+            Err(StoreError::UnsupportedBundleVersion(2))
+        ));
+
+        // nothing was imported
+        assert!(target.load().expect("load").policies.is_empty());
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn encrypted_bundles() {
+        use cedar_policy::envelope::{EnvelopeError, LocalKeyWrapper};
+
+        let dir = tempfile::tempdir().expect("temp dir");
+        let bundle = populated_store(&dir.path().join("source"))
+            .export_bundle(BundleMetadata::new("staging", None), None)
+            .expect("export");
+        let wrapper = LocalKeyWrapper::new([3; 32], "kek");
+        let envelope = bundle.encrypt(&wrapper).expect("encrypt");
+        let json = String::from_utf8(envelope.to_json().expect("serialize")).expect("utf-8");
+        assert!(!json.contains("context.amount > 100"));
+
+        let decrypted = Bundle::decrypt(&envelope, &wrapper).expect("decrypt");
+        let target = FsPolicyStore::open(dir.path().join("target")).expect("store should open");
+        target.import_bundle(&decrypted).expect("import");
+        assert_eq!(target.load().expect("load").policies.policies().count(), 2);
+
+        let other = LocalKeyWrapper::new([3; 32], "other-kek");
+        assert!(matches!(
+            Bundle::decrypt(&envelope, &other),
+            Err(StoreError::Envelope(EnvelopeError::UnknownKey(_)))
+        ));
+    }
+
+    #[test]
+    fn invalid_schema() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let store = populated_store(dir.path());
+        assert!(matches!(
+            store.export_bundle(
+                BundleMetadata::new("staging", None),
+                Some(json!({ "": { "entityTypes": 3 } }))
+            ),
+            Err(StoreError::Schema(_))
+        ));
+    }
+}
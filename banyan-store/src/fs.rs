@@ -0,0 +1,221 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! A [`PolicyStore`] keeping one file per item in a directory.
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use cedar_policy::revocation::Revocation;
+use cedar_policy::{Policy, PolicyId, Template};
+
+use crate::{
+    parse_static_policy, parse_template, static_policy_text, Link, LinkRecord, PolicyStore,
+    StoreError,
+};
+
+const POLICIES: &str = "policies";
+const TEMPLATES: &str = "templates";
+const LINKS: &str = "links";
+const REVOCATIONS: &str = "revocations";
+
+/// A [`PolicyStore`] in a directory, with static policies and templates in
+/// `policies/<id>.cedar` and `templates/<id>.cedar`, and links in
+/// `links/<id>.json`. Revocations are in `revocations/<id>.json`. Characters of ids other than ASCII letters, digits,
+/// `-`, and `_` are percent-encoded in file names.
+///
+/// Each file is replaced atomically when saved.
+#[derive(Debug, Clone)]
+pub struct FsPolicyStore {
+    root: PathBuf,
+}
+
+impl FsPolicyStore {
+    /// Open the store in `root`, creating the directory and its
+    /// subdirectories if needed
+    pub fn open(root: impl Into<PathBuf>) -> Result<Self, StoreError> {
+        let root = root.into();
+        for dir in [POLICIES, TEMPLATES, LINKS, REVOCATIONS] {
+            fs::create_dir_all(root.join(dir))?;
+        }
+        Ok(Self { root })
+    }
+
+    fn path(&self, dir: &str, id: &str, extension: &str) -> PathBuf {
+        self.root
+            .join(dir)
+            .join(format!("{}.{extension}", encode_id(id)))
+    }
+
+    /// Ids and contents of the files in `dir` with `extension`, sorted by id
+    fn read_dir(&self, dir: &str, extension: &str) -> Result<Vec<(String, String)>, StoreError> {
+        let mut items = Vec::new();
+        for entry in fs::read_dir(self.root.join(dir))? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some(extension) {
+                continue;
+            }
+            let Some(id) = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(decode_id)
+            else {
+                continue;
+            };
+            items.push((id, fs::read_to_string(&path)?));
+        }
+        items.sort();
+        Ok(items)
+    }
+}
+
+/// Write `contents` to `path` by writing a temporary file and renaming it, so
+/// readers never see a partly written file
+fn write_atomic(path: &Path, contents: &str) -> Result<(), StoreError> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, contents)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+fn remove_if_exists(path: &Path) -> Result<bool, StoreError> {
+    match fs::remove_file(path) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn encode_id(id: &str) -> String {
+    let mut encoded = String::with_capacity(id.len());
+    for b in id.bytes() {
+        if b.is_ascii_alphanumeric() || b == b'-' || b == b'_' {
+            encoded.push(char::from(b));
+        } else {
+            encoded.push_str(&format!("%{b:02X}"));
+        }
+    }
+    encoded
+}
+
+fn decode_id(encoded: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut rest = encoded.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        if b == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = tail.get(2..)?;
+        } else {
+            bytes.push(b);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+impl PolicyStore for FsPolicyStore {
+    fn static_policies(&self) -> Result<Vec<Policy>, StoreError> {
+        self.read_dir(POLICIES, "cedar")?
+            .iter()
+            .map(|(id, text)| parse_static_policy(id, text))
+            .collect()
+    }
+
+    fn templates(&self) -> Result<Vec<Template>, StoreError> {
+        self.read_dir(TEMPLATES, "cedar")?
+            .iter()
+            .map(|(id, text)| parse_template(id, text))
+            .collect()
+    }
+
+    fn links(&self) -> Result<Vec<Link>, StoreError> {
+        self.read_dir(LINKS, "json")?
+            .iter()
+            .map(|(_, json)| Link::try_from(serde_json::from_str::<LinkRecord>(json)?))
+            .collect()
+    }
+
+    fn save_static_policy(&self, policy: &Policy) -> Result<(), StoreError> {
+        let text = static_policy_text(policy)?;
+        write_atomic(&self.path(POLICIES, policy.id().as_ref(), "cedar"), &text)
+    }
+
+    fn save_template(&self, template: &Template) -> Result<(), StoreError> {
+        write_atomic(
+            &self.path(TEMPLATES, template.id().as_ref(), "cedar"),
+            &template.to_string(),
+        )
+    }
+
+    fn save_link(&self, link: &Link) -> Result<(), StoreError> {
+        let json = serde_json::to_string_pretty(&LinkRecord::from(link))?;
+        write_atomic(&self.path(LINKS, link.id.as_ref(), "json"), &json)
+    }
+
+    fn revocations(&self) -> Result<Vec<Revocation>, StoreError> {
+        self.read_dir(REVOCATIONS, "json")?
+            .iter()
+            .map(|(_, json)| Ok(serde_json::from_str(json)?))
+            .collect()
+    }
+
+    fn save_revocation(&self, revocation: &Revocation) -> Result<(), StoreError> {
+        let json = serde_json::to_string_pretty(revocation)?;
+        write_atomic(
+            &self.path(REVOCATIONS, revocation.id.as_ref(), "json"),
+            &json,
+        )
+    }
+
+    fn remove_revocation(&self, id: &PolicyId) -> Result<bool, StoreError> {
+        remove_if_exists(&self.path(REVOCATIONS, id.as_ref(), "json"))
+    }
+
+    fn remove(&self, id: &PolicyId) -> Result<bool, StoreError> {
+        let id = id.as_ref();
+        let mut removed = false;
+        for (dir, extension) in [(POLICIES, "cedar"), (TEMPLATES, "cedar"), (LINKS, "json")] {
+            removed |= remove_if_exists(&self.path(dir, id, extension))?;
+        }
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let store = FsPolicyStore::open(dir.path().join("store")).expect("store should open");
+        crate::test::round_trip(&store);
+    }
+
+    #[test]
+    fn ids_in_file_names() {
+        for id in ["plain", "with space", "a/b", "100%", "ünï"] {
+            let encoded = encode_id(id);
+            assert!(encoded
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b"-_%".contains(&b)));
+            assert_eq!(decode_id(&encoded).as_deref(), Some(id));
+        }
+        assert_eq!(decode_id("%4"), None);
+    }
+}
@@ -0,0 +1,480 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Persistence for policy sets: static policies, templates, and template
+//! links, with the provenance of each link.
+//!
+//! A [`PolicyStore`] saves each item individually and loads them back as a
+//! [`StoredPolicySet`]. [`FsPolicyStore`] keeps one file per item in a
+//! directory; with the `sqlite` feature, `SqlitePolicyStore` keeps them in a
+//! SQLite database. A store's contents can be exported as a [`Bundle`] and
+//! imported into another store. A [`GovernedStore`] only lets administrators
+//! change the store as its meta-policies allow. [`TenantStores`] gives each
+//! tenant of a hosted service its own store.
+
+#![forbid(unsafe_code)]
+#![warn(missing_docs)]
+
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use cedar_policy::revocation::{Revocation, RevocationList};
+use cedar_policy::{
+    EntityUid, ParseErrors, Policy, PolicyId, PolicySet, PolicySetError, SchemaError, SlotId,
+    Template,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Meta-policies over who may change a store
+pub mod admin;
+pub use admin::{AdminStore, GovernedStore};
+mod bundle;
+#[cfg(feature = "encryption")]
+pub use bundle::BUNDLE_CONTENT_TYPE;
+pub use bundle::{Bundle, BundleMetadata, BUNDLE_VERSION};
+mod fs;
+pub use fs::FsPolicyStore;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqlitePolicyStore;
+mod tenant;
+pub use tenant::TenantStores;
+
+/// Who created a template link, when, and why
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LinkMetadata {
+    /// Who instantiated the template, e.g. an account address
+    pub created_by: String,
+    /// When the link was created, in seconds since the Unix epoch
+    pub created_at: u64,
+    /// The governance proposal which approved the link, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proposal_id: Option<String>,
+}
+
+impl LinkMetadata {
+    /// Metadata for a link created now by `created_by`
+    pub fn new(created_by: impl Into<String>, proposal_id: Option<String>) -> Self {
+        let created_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or(Duration::ZERO)
+            .as_secs();
+        Self {
+            created_by: created_by.into(),
+            created_at,
+            proposal_id,
+        }
+    }
+}
+
+/// A template link and its provenance
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Link {
+    /// Id of the linked policy
+    pub id: PolicyId,
+    /// Id of the template it links
+    pub template_id: PolicyId,
+    /// Values of the template's slots
+    pub values: HashMap<SlotId, EntityUid>,
+    /// Provenance of the link
+    pub metadata: LinkMetadata,
+}
+
+/// The serialized form of a [`Link`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LinkRecord {
+    id: String,
+    template_id: String,
+    /// Slot (e.g. `?principal`) to entity UID
+    values: BTreeMap<String, String>,
+    #[serde(flatten)]
+    metadata: LinkMetadata,
+}
+
+impl From<&Link> for LinkRecord {
+    fn from(link: &Link) -> Self {
+        Self {
+            id: link.id.as_ref().to_string(),
+            template_id: link.template_id.as_ref().to_string(),
+            values: link
+                .values
+                .iter()
+                .map(|(slot, uid)| (slot.to_string(), uid.to_string()))
+                .collect(),
+            metadata: link.metadata.clone(),
+        }
+    }
+}
+
+impl TryFrom<LinkRecord> for Link {
+    type Error = StoreError;
+
+    fn try_from(record: LinkRecord) -> Result<Self, StoreError> {
+        let values = record
+            .values
+            .iter()
+            .map(|(slot, uid)| {
+                let slot = match slot.as_str() {
+                    "?principal" => SlotId::principal(),
+                    "?resource" => SlotId::resource(),
+                    _ => {
+                        return Err(StoreError::Corrupt {
+                            id: record.id.clone(),
+                            reason: format!("unknown slot `{slot}`"),
+                        })
+                    }
+                };
+                let uid = EntityUid::from_str(uid).map_err(|e| StoreError::Corrupt {
+                    id: record.id.clone(),
+                    reason: format!("invalid entity UID `{uid}`: {e}"),
+                })?;
+                Ok((slot, uid))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            id: parse_id(&record.id)?,
+            template_id: parse_id(&record.template_id)?,
+            values,
+            metadata: record.metadata,
+        })
+    }
+}
+
+fn parse_id(id: &str) -> Result<PolicyId, StoreError> {
+    PolicyId::from_str(id).map_err(|_| StoreError::Corrupt {
+        id: id.to_string(),
+        reason: "invalid policy id".into(),
+    })
+}
+
+/// Errors from a [`PolicyStore`]
+#[derive(Debug, Error)]
+pub enum StoreError {
+    /// Reading or writing the filesystem failed
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    /// The SQLite database failed
+    #[cfg(feature = "sqlite")]
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+    /// A stored link couldn't be serialized or deserialized
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    /// A stored policy or template doesn't parse
+    #[error("stored policy `{id}` doesn't parse: {source}")]
+    Parse {
+        /// Id of the policy or template
+        id: String,
+        /// The parse errors
+        source: ParseErrors,
+    },
+    /// A stored item is malformed
+    #[error("stored item `{id}` is corrupt: {reason}")]
+    Corrupt {
+        /// Id of the item
+        id: String,
+        /// What's wrong with it
+        reason: String,
+    },
+    /// A template-linked policy was saved as a static policy. Save links with
+    /// [`PolicyStore::save_link()`].
+    #[error("`{0}` is a template-linked policy, not a static policy")]
+    NotStatic(PolicyId),
+    /// The stored items don't form a valid policy set, e.g. a link refers to
+    /// a missing template
+    #[error(transparent)]
+    PolicySet(#[from] PolicySetError),
+    /// A bundle was written in a format version this crate doesn't read
+    #[error("bundle version {0} is not supported")]
+    UnsupportedBundleVersion(u32),
+    /// An item of a bundle, or the bundle as a whole, doesn't match its hash
+    #[error("{0} doesn't match its hash in the bundle")]
+    HashMismatch(String),
+    /// The schema of a bundle is invalid
+    #[error("invalid schema in bundle: {0}")]
+    Schema(#[from] SchemaError),
+    /// An encrypted bundle couldn't be encrypted or decrypted
+    #[cfg(feature = "encryption")]
+    #[error(transparent)]
+    Envelope(#[from] cedar_policy::envelope::EnvelopeError),
+    /// The meta-policies of a [`GovernedStore`] don't allow a change
+    #[error("`{principal}` may not {action} `{id}`")]
+    Forbidden {
+        /// Who tried to make the change
+        principal: EntityUid,
+        /// The action of the change, e.g. [`admin::CREATE_ACTION`]
+        action: String,
+        /// The item changed
+        id: PolicyId,
+    },
+}
+
+/// Persistent storage for static policies, templates, and template links.
+///
+/// Saving an item replaces any item of the same kind with the same id.
+/// Items are stored individually, so a store may hold items which don't form
+/// a valid policy set, e.g. a link to a template which was removed; this is
+/// reported by [`PolicyStore::load()`].
+pub trait PolicyStore {
+    /// All stored static policies
+    fn static_policies(&self) -> Result<Vec<Policy>, StoreError>;
+
+    /// All stored templates
+    fn templates(&self) -> Result<Vec<Template>, StoreError>;
+
+    /// All stored template links
+    fn links(&self) -> Result<Vec<Link>, StoreError>;
+
+    /// Save a static policy
+    fn save_static_policy(&self, policy: &Policy) -> Result<(), StoreError>;
+
+    /// Save a template
+    fn save_template(&self, template: &Template) -> Result<(), StoreError>;
+
+    /// Save a template link
+    fn save_link(&self, link: &Link) -> Result<(), StoreError>;
+
+    /// All stored revocations
+    fn revocations(&self) -> Result<Vec<Revocation>, StoreError>;
+
+    /// Save a revocation, replacing any earlier revocation of the same link
+    fn save_revocation(&self, revocation: &Revocation) -> Result<(), StoreError>;
+
+    /// Remove the revocation of `id`, returning whether there was one
+    fn remove_revocation(&self, id: &PolicyId) -> Result<bool, StoreError>;
+
+    /// Remove the static policy, template, or link with id `id`, returning
+    /// whether there was one. Revocations of `id` are kept.
+    fn remove(&self, id: &PolicyId) -> Result<bool, StoreError>;
+
+    /// Load everything into a policy set
+    fn load(&self) -> Result<StoredPolicySet, StoreError> {
+        let mut policies = PolicySet::new();
+        for template in self.templates()? {
+            policies.add_template(template)?;
+        }
+        for policy in self.static_policies()? {
+            policies.add(policy)?;
+        }
+        let mut links = HashMap::new();
+        for link in self.links()? {
+            policies.link(
+                link.template_id.clone(),
+                link.id.clone(),
+                link.values.clone(),
+            )?;
+            links.insert(link.id.clone(), link);
+        }
+        Ok(StoredPolicySet {
+            policies,
+            links,
+            revocations: Arc::new(self.revocations()?.into_iter().collect()),
+        })
+    }
+
+    /// Export everything, together with `schema` if given, as a [`Bundle`]
+    fn export_bundle(
+        &self,
+        metadata: BundleMetadata,
+        schema: Option<serde_json::Value>,
+    ) -> Result<Bundle, StoreError> {
+        Bundle::new(
+            metadata,
+            self.static_policies()?,
+            self.templates()?,
+            self.links()?,
+            self.revocations()?,
+            schema,
+        )
+    }
+
+    /// Save everything in `bundle`, replacing items with the same ids. The
+    /// bundle's hashes are checked and its items parsed before anything is
+    /// saved. The bundle's schema isn't stored; see [`Bundle::schema_json()`].
+    fn import_bundle(&self, bundle: &Bundle) -> Result<(), StoreError> {
+        let contents = bundle.contents()?;
+        for template in &contents.templates {
+            self.save_template(template)?;
+        }
+        for policy in &contents.policies {
+            self.save_static_policy(policy)?;
+        }
+        for link in &contents.links {
+            self.save_link(link)?;
+        }
+        for revocation in &contents.revocations {
+            self.save_revocation(revocation)?;
+        }
+        Ok(())
+    }
+}
+
+/// The contents of a [`PolicyStore`]
+#[derive(Debug, Clone)]
+pub struct StoredPolicySet {
+    /// The policy set
+    pub policies: PolicySet,
+    /// The template links in the set, by id
+    pub links: HashMap<PolicyId, Link>,
+    /// The revoked links, for
+    /// [`Authorizer::with_revocations()`](cedar_policy::Authorizer::with_revocations).
+    /// Revoked links are still in `policies`.
+    pub revocations: Arc<RevocationList>,
+}
+
+impl StoredPolicySet {
+    /// The provenance of the template-linked policy `id`
+    pub fn link_metadata(&self, id: &PolicyId) -> Option<&LinkMetadata> {
+        self.links.get(id).map(|link| &link.metadata)
+    }
+}
+
+/// Text of a static policy, suitable for parsing with [`Policy::parse()`]
+fn static_policy_text(policy: &Policy) -> Result<String, StoreError> {
+    if policy.is_static() {
+        Ok(policy.to_string())
+    } else {
+        Err(StoreError::NotStatic(policy.id().clone()))
+    }
+}
+
+fn parse_static_policy(id: &str, text: &str) -> Result<Policy, StoreError> {
+    Policy::parse(Some(id.to_string()), text).map_err(|source| StoreError::Parse {
+        id: id.to_string(),
+        source,
+    })
+}
+
+fn parse_template(id: &str, text: &str) -> Result<Template, StoreError> {
+    Template::parse(Some(id.to_string()), text).map_err(|source| StoreError::Parse {
+        id: id.to_string(),
+        source,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Exercise a store, which must start empty
+    pub(crate) fn round_trip(store: &impl PolicyStore) {
+        let load = || store.load().expect("store should load");
+        assert!(load().policies.is_empty());
+
+        let policy = Policy::parse(
+            Some("static/1".into()),
+            r#"@advice("ok") permit(principal, action, resource) when { context.amount < 100 };"#,
+        )
+        .expect("policy should parse");
+        store.save_static_policy(&policy).expect("save policy");
+        let template = Template::parse(
+            Some("signer".into()),
+            r#"permit(principal == ?principal, action == Action::"sign", resource in ?resource);"#,
+        )
+        .expect("template should parse");
+        store.save_template(&template).expect("save template");
+        let link = Link {
+            id: PolicyId::from_str("alice signs").expect("valid id"),
+            template_id: template.id().clone(),
+            values: [
+                (
+                    SlotId::principal(),
+                    EntityUid::from_str(r#"User::"alice""#).expect("uid"),
+                ),
+                (
+                    SlotId::resource(),
+                    EntityUid::from_str(r#"Wallet::"w""#).expect("uid"),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+            metadata: LinkMetadata {
+                created_by: "0xabc".into(),
+                created_at: 1_700_000_000,
+                proposal_id: Some("prop-7".into()),
+            },
+        };
+        store.save_link(&link).expect("save link");
+
+        let loaded = load();
+        assert_eq!(loaded.policies.policies().count(), 2);
+        assert_eq!(loaded.policies.templates().count(), 1);
+        let loaded_policy = loaded.policies.policy(policy.id()).expect("policy loaded");
+        // source locations differ, so compare the text
+        assert_eq!(loaded_policy.to_string(), policy.to_string());
+        assert_eq!(loaded_policy.annotation("advice"), Some("ok"));
+        assert_eq!(
+            loaded
+                .policies
+                .policy(&link.id)
+                .expect("link loaded")
+                .template_id(),
+            Some(template.id())
+        );
+        assert_eq!(loaded.link_metadata(&link.id), Some(&link.metadata));
+        assert_eq!(loaded.links.get(&link.id), Some(&link));
+
+        // saving replaces
+        let replacement = Policy::parse(
+            Some("static/1".into()),
+            "forbid(principal, action, resource);",
+        )
+        .expect("policy should parse");
+        store.save_static_policy(&replacement).expect("save policy");
+        assert_eq!(store.static_policies().expect("list").len(), 1);
+        assert_eq!(
+            load()
+                .policies
+                .policy(policy.id())
+                .expect("policy loaded")
+                .to_string(),
+            replacement.to_string()
+        );
+
+        // links can't be saved as static policies
+        let linked = loaded.policies.policy(&link.id).expect("link loaded");
+        assert!(matches!(
+            store.save_static_policy(linked),
+            Err(StoreError::NotStatic(_))
+        ));
+
+        // a link to a missing template fails to load
+        assert!(store.remove(template.id()).expect("remove"));
+        assert!(!store.remove(template.id()).expect("remove"));
+        assert!(matches!(store.load(), Err(StoreError::PolicySet(_))));
+        assert!(store.remove(&link.id).expect("remove"));
+        assert_eq!(load().policies.policies().count(), 1);
+
+        // revocations
+        let revocation = Revocation {
+            id: link.id.clone(),
+            reason: "key compromised".into(),
+            revoked_at: 1_700_000_100,
+        };
+        store.save_revocation(&revocation).expect("save revocation");
+        assert_eq!(store.revocations().expect("list"), vec![revocation.clone()]);
+        assert_eq!(load().revocations.get(&link.id), Some(revocation));
+        assert!(store.remove_revocation(&link.id).expect("remove"));
+        assert!(!store.remove_revocation(&link.id).expect("remove"));
+        assert!(load().revocations.is_empty());
+    }
+}
@@ -0,0 +1,272 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! A [`PolicyStore`] in a SQLite database.
+
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use cedar_policy::revocation::Revocation;
+use cedar_policy::{Policy, PolicyId, Template};
+use rusqlite::{params, Connection};
+
+use crate::{
+    parse_id, parse_static_policy, parse_template, static_policy_text, Link, LinkMetadata,
+    LinkRecord, PolicyStore, StoreError,
+};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS policies (
+        id TEXT PRIMARY KEY NOT NULL,
+        text TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS templates (
+        id TEXT PRIMARY KEY NOT NULL,
+        text TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS links (
+        id TEXT PRIMARY KEY NOT NULL,
+        template_id TEXT NOT NULL,
+        slot_values TEXT NOT NULL,
+        created_by TEXT NOT NULL,
+        created_at INTEGER NOT NULL,
+        proposal_id TEXT
+    );
+    CREATE TABLE IF NOT EXISTS revocations (
+        id TEXT PRIMARY KEY NOT NULL,
+        reason TEXT NOT NULL,
+        revoked_at INTEGER NOT NULL
+    );
+";
+
+/// A [`PolicyStore`] in a SQLite database, with tables `policies`,
+/// `templates`, `links`, and `revocations`. Link slot values are stored as a JSON object
+/// from slot to entity UID.
+#[derive(Debug)]
+pub struct SqlitePolicyStore {
+    conn: Mutex<Connection>,
+}
+
+impl SqlitePolicyStore {
+    /// Open the database at `path`, creating it and its tables if needed
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StoreError> {
+        Self::new(Connection::open(path)?)
+    }
+
+    /// A store in a new in-memory database
+    pub fn in_memory() -> Result<Self, StoreError> {
+        Self::new(Connection::open_in_memory()?)
+    }
+
+    fn new(conn: Connection) -> Result<Self, StoreError> {
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    fn conn(&self) -> MutexGuard<'_, Connection> {
+        // a panic while holding the lock can't leave the connection in a
+        // state that's unsafe to reuse; SQLite rolls back incomplete writes
+        self.conn.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Ids and texts of the rows of `table`, sorted by id
+    fn texts(&self, table: &str) -> Result<Vec<(String, String)>, StoreError> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(&format!("SELECT id, text FROM {table} ORDER BY id"))?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+}
+
+impl PolicyStore for SqlitePolicyStore {
+    fn static_policies(&self) -> Result<Vec<Policy>, StoreError> {
+        self.texts("policies")?
+            .iter()
+            .map(|(id, text)| parse_static_policy(id, text))
+            .collect()
+    }
+
+    fn templates(&self) -> Result<Vec<Template>, StoreError> {
+        self.texts("templates")?
+            .iter()
+            .map(|(id, text)| parse_template(id, text))
+            .collect()
+    }
+
+    fn links(&self) -> Result<Vec<Link>, StoreError> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT id, template_id, slot_values, created_by, created_at, proposal_id
+             FROM links ORDER BY id",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, i64>(4)?,
+                row.get::<_, Option<String>>(5)?,
+            ))
+        })?;
+        let mut links = Vec::new();
+        for row in rows {
+            let (id, template_id, values, created_by, created_at, proposal_id) = row?;
+            let created_at = u64::try_from(created_at).map_err(|_| StoreError::Corrupt {
+                id: id.clone(),
+                reason: format!("negative creation time {created_at}"),
+            })?;
+            links.push(Link::try_from(LinkRecord {
+                id,
+                template_id,
+                values: serde_json::from_str(&values)?,
+                metadata: LinkMetadata {
+                    created_by,
+                    created_at,
+                    proposal_id,
+                },
+            })?);
+        }
+        Ok(links)
+    }
+
+    fn save_static_policy(&self, policy: &Policy) -> Result<(), StoreError> {
+        let text = static_policy_text(policy)?;
+        self.conn().execute(
+            "INSERT OR REPLACE INTO policies (id, text) VALUES (?1, ?2)",
+            params![policy.id().as_ref(), text],
+        )?;
+        Ok(())
+    }
+
+    fn save_template(&self, template: &Template) -> Result<(), StoreError> {
+        self.conn().execute(
+            "INSERT OR REPLACE INTO templates (id, text) VALUES (?1, ?2)",
+            params![template.id().as_ref(), template.to_string()],
+        )?;
+        Ok(())
+    }
+
+    fn save_link(&self, link: &Link) -> Result<(), StoreError> {
+        let record = LinkRecord::from(link);
+        let created_at =
+            i64::try_from(record.metadata.created_at).map_err(|_| StoreError::Corrupt {
+                id: record.id.clone(),
+                reason: "creation time out of range".into(),
+            })?;
+        self.conn().execute(
+            "INSERT OR REPLACE INTO links
+                (id, template_id, slot_values, created_by, created_at, proposal_id)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                record.id,
+                record.template_id,
+                serde_json::to_string(&record.values)?,
+                record.metadata.created_by,
+                created_at,
+                record.metadata.proposal_id,
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn revocations(&self) -> Result<Vec<Revocation>, StoreError> {
+        let conn = self.conn();
+        let mut stmt =
+            conn.prepare("SELECT id, reason, revoked_at FROM revocations ORDER BY id")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+            ))
+        })?;
+        let mut revocations = Vec::new();
+        for row in rows {
+            let (id, reason, revoked_at) = row?;
+            revocations.push(Revocation {
+                id: parse_id(&id)?,
+                reason,
+                revoked_at: u64::try_from(revoked_at).map_err(|_| StoreError::Corrupt {
+                    id: id.clone(),
+                    reason: format!("negative revocation time {revoked_at}"),
+                })?,
+            });
+        }
+        Ok(revocations)
+    }
+
+    fn save_revocation(&self, revocation: &Revocation) -> Result<(), StoreError> {
+        let revoked_at = i64::try_from(revocation.revoked_at).map_err(|_| StoreError::Corrupt {
+            id: revocation.id.as_ref().to_string(),
+            reason: "revocation time out of range".into(),
+        })?;
+        self.conn().execute(
+            "INSERT OR REPLACE INTO revocations (id, reason, revoked_at) VALUES (?1, ?2, ?3)",
+            params![revocation.id.as_ref(), revocation.reason, revoked_at],
+        )?;
+        Ok(())
+    }
+
+    fn remove_revocation(&self, id: &PolicyId) -> Result<bool, StoreError> {
+        let removed = self.conn().execute(
+            "DELETE FROM revocations WHERE id = ?1",
+            params![id.as_ref()],
+        )?;
+        Ok(removed > 0)
+    }
+
+    fn remove(&self, id: &PolicyId) -> Result<bool, StoreError> {
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        let mut removed = 0;
+        for table in ["policies", "templates", "links"] {
+            removed += tx.execute(
+                &format!("DELETE FROM {table} WHERE id = ?1"),
+                params![id.as_ref()],
+            )?;
+        }
+        tx.commit()?;
+        Ok(removed > 0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let store = SqlitePolicyStore::in_memory().expect("store should open");
+        crate::test::round_trip(&store);
+    }
+
+    #[test]
+    fn persists() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("policies.db");
+        let policy = Policy::parse(Some("p".into()), "permit(principal, action, resource);")
+            .expect("policy should parse");
+        SqlitePolicyStore::open(&path)
+            .expect("store should open")
+            .save_static_policy(&policy)
+            .expect("save policy");
+        let reopened = SqlitePolicyStore::open(&path).expect("store should reopen");
+        assert_eq!(reopened.static_policies().expect("list").len(), 1);
+    }
+}
@@ -0,0 +1,119 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use cedar_policy::tenant::TenantId;
+
+use crate::{FsPolicyStore, PolicyStore, StoreError};
+
+type Opener<S> = Box<dyn Fn(&TenantId) -> Result<S, StoreError> + Send + Sync>;
+
+/// A separate [`PolicyStore`] for each tenant, opened when first needed.
+///
+/// Tenants never share a store, so one tenant's policies can't be loaded
+/// into, or overwritten by, another's.
+pub struct TenantStores<S: PolicyStore> {
+    open: Opener<S>,
+    stores: Mutex<HashMap<TenantId, Arc<S>>>,
+}
+
+impl<S: PolicyStore> std::fmt::Debug for TenantStores<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let stores = self.stores.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut tenants: Vec<_> = stores.keys().collect();
+        tenants.sort();
+        f.debug_struct("TenantStores")
+            .field("open", &tenants)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S: PolicyStore> TenantStores<S> {
+    /// Stores opened by `open`, which must give each tenant its own store
+    pub fn new(open: impl Fn(&TenantId) -> Result<S, StoreError> + Send + Sync + 'static) -> Self {
+        Self {
+            open: Box::new(open),
+            stores: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The store of `tenant`, opening it if it isn't open yet
+    pub fn store(&self, tenant: &TenantId) -> Result<Arc<S>, StoreError> {
+        let mut stores = self.stores.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(store) = stores.get(tenant) {
+            return Ok(store.clone());
+        }
+        let store = Arc::new((self.open)(tenant)?);
+        stores.insert(tenant.clone(), store.clone());
+        drop(stores);
+        Ok(store)
+    }
+}
+
+impl TenantStores<FsPolicyStore> {
+    /// An [`FsPolicyStore`] for each tenant, in `root/<tenant id>`
+    pub fn fs(root: impl Into<PathBuf>) -> Self {
+        let root = root.into();
+        Self::new(move |tenant| FsPolicyStore::open(root.join(tenant.as_str())))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cedar_policy::Policy;
+    use std::str::FromStr;
+
+    #[test]
+    fn stores_are_per_tenant() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let stores = TenantStores::fs(dir.path());
+        let acme = TenantId::from_str("acme").expect("valid tenant id");
+        let globex = TenantId::from_str("globex").expect("valid tenant id");
+
+        let policy = Policy::parse(
+            Some("shared-id".into()),
+            "permit(principal, action, resource);",
+        )
+        .expect("policy should parse");
+        stores
+            .store(&acme)
+            .expect("store should open")
+            .save_static_policy(&policy)
+            .expect("policy should save");
+
+        let load = |tenant| {
+            stores
+                .store(tenant)
+                .expect("store should open")
+                .load()
+                .expect("store should load")
+                .policies
+        };
+        assert_eq!(load(&acme).policies().count(), 1);
+        assert!(load(&globex).is_empty());
+        assert!(dir.path().join("acme").join("policies").is_dir());
+
+        // the same store is given out again
+        assert!(Arc::ptr_eq(
+            &stores.store(&acme).expect("store should open"),
+            &stores.store(&acme).expect("store should open")
+        ));
+    }
+}
@@ -0,0 +1,146 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! WebAssembly bindings for parsing, validating, and evaluating Cedar
+//! policies from JavaScript.
+//!
+//! Every function takes a JSON string and returns a JSON-encoded
+//! [`InterfaceResult`], using the same formats as the
+//! [`cedar_policy::frontend`] JSON interface.
+
+#![forbid(unsafe_code)]
+#![warn(missing_docs)]
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use cedar_policy::frontend::is_authorized::json_is_authorized;
+use cedar_policy::frontend::utils::InterfaceResult;
+use cedar_policy::frontend::validate::json_validate;
+use cedar_policy::PolicySet;
+use wasm_bindgen::prelude::wasm_bindgen;
+
+/// Parse a set of policies written in the Cedar syntax. On success, the
+/// result is a map from policy id to the JSON representation of the policy.
+#[wasm_bindgen(js_name = parsePolicySet)]
+pub fn parse_policy_set(policies: &str) -> String {
+    let result = match PolicySet::from_str(policies) {
+        Ok(pset) => pset
+            .policies()
+            .map(|p| p.to_json().map(|json| (p.id().to_string(), json)))
+            .collect::<Result<HashMap<_, _>, _>>()
+            .map_or_else(
+                |e| InterfaceResult::fail_internally(format!("error converting policy: {e}")),
+                InterfaceResult::succeed,
+            ),
+        Err(errs) => {
+            InterfaceResult::fail_bad_request(errs.iter().map(ToString::to_string).collect())
+        }
+    };
+    to_json(&result)
+}
+
+/// Validate a set of policies against a schema. The input is the same as for
+/// [`json_validate`].
+#[wasm_bindgen(js_name = validate)]
+pub fn validate(input: &str) -> String {
+    to_json(&json_validate(input))
+}
+
+/// Answer an authorization request. The input is the same as for
+/// [`json_is_authorized`].
+#[wasm_bindgen(js_name = isAuthorized)]
+pub fn is_authorized(input: &str) -> String {
+    to_json(&json_is_authorized(input))
+}
+
+/// Serialize an `InterfaceResult` to return to JavaScript
+fn to_json(result: &InterfaceResult) -> String {
+    serde_json::to_string(result).unwrap_or_else(|e| {
+        serde_json::json!({
+            "success": "false",
+            "isInternal": true,
+            "errors": [format!("error serializing result: {e}")],
+        })
+        .to_string()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn parse(result: &str) -> Value {
+        serde_json::from_str(result).expect("result should be JSON")
+    }
+
+    #[test]
+    fn parse_policies() {
+        let result = parse(&parse_policy_set(
+            r#"permit(principal, action == Action::"transfer", resource);"#,
+        ));
+        assert_eq!(result["success"], "true");
+        let policies = parse(
+            result["result"]
+                .as_str()
+                .expect("result should be a string"),
+        );
+        assert_eq!(policies["policy0"]["effect"], "permit");
+    }
+
+    #[test]
+    fn parse_errors() {
+        let result = parse(&parse_policy_set("permit(principal, action, resource"));
+        assert_eq!(result["success"], "false");
+        assert_eq!(result["isInternal"], false);
+        assert!(!result["errors"]
+            .as_array()
+            .expect("errors should be an array")
+            .is_empty());
+    }
+
+    #[test]
+    fn authorize() {
+        let call = json!({
+            "principal": "User::\"alice\"",
+            "action": "Action::\"transfer\"",
+            "resource": "Token::\"usdc\"",
+            "context": {},
+            "slice": {
+                "policies": {
+                    "ID1": "permit(principal == User::\"alice\", action, resource);"
+                },
+                "entities": []
+            }
+        });
+        let result = parse(&is_authorized(&call.to_string()));
+        assert_eq!(result["success"], "true");
+        let answer = parse(
+            result["result"]
+                .as_str()
+                .expect("result should be a string"),
+        );
+        assert_eq!(answer["response"]["decision"], "Allow");
+    }
+
+    #[test]
+    fn malformed_call() {
+        let result = parse(&validate("not json"));
+        assert_eq!(result["success"], "false");
+        assert_eq!(result["isInternal"], true);
+    }
+}
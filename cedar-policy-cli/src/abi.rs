@@ -0,0 +1,244 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Generation of a Cedar schema from an Ethereum contract ABI: one action per
+//! state-changing function, with the function's inputs as the context.
+
+use std::collections::{BTreeMap, HashMap};
+
+use clap::Args;
+use miette::{miette, IntoDiagnostic, Result, WrapErr};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::{read_from_file, CedarExitCode};
+
+#[derive(Args, Debug)]
+pub struct AbiToSchemaArgs {
+    /// File containing the contract ABI, either as a JSON array or as a
+    /// compiler artifact with an "abi" field
+    #[arg(value_name = "FILE")]
+    pub abi_file: String,
+    /// Namespace for the generated entity types and actions
+    #[arg(long, default_value = "")]
+    pub namespace: String,
+    /// Entity type of the principals calling the contract
+    #[arg(long, default_value = "Account")]
+    pub principal_type: String,
+    /// Entity type of the contract
+    #[arg(long, default_value = "Contract")]
+    pub resource_type: String,
+    /// Also generate actions for `view` and `pure` functions
+    #[arg(long)]
+    pub include_view: bool,
+}
+
+/// An entry in a contract ABI. Only functions are used.
+#[derive(Debug, Deserialize)]
+struct AbiItem {
+    #[serde(rename = "type", default = "function_item")]
+    ty: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    inputs: Vec<AbiParam>,
+    #[serde(rename = "stateMutability", default)]
+    state_mutability: Option<String>,
+}
+
+fn function_item() -> String {
+    "function".into()
+}
+
+/// A function input in a contract ABI
+#[derive(Debug, Deserialize)]
+struct AbiParam {
+    #[serde(default)]
+    name: String,
+    #[serde(rename = "type")]
+    ty: String,
+    #[serde(default)]
+    components: Vec<AbiParam>,
+}
+
+impl AbiItem {
+    fn is_view(&self) -> bool {
+        matches!(self.state_mutability.as_deref(), Some("view" | "pure"))
+    }
+
+    fn is_payable(&self) -> bool {
+        self.state_mutability.as_deref() == Some("payable")
+    }
+
+    /// The canonical signature of the function, e.g. `transfer(address,uint256)`
+    fn signature(&self) -> String {
+        let params: Vec<_> = self.inputs.iter().map(AbiParam::canonical_type).collect();
+        format!("{}({})", self.name, params.join(","))
+    }
+}
+
+impl AbiParam {
+    fn canonical_type(&self) -> String {
+        match self.ty.strip_prefix("tuple") {
+            Some(dims) => {
+                let components: Vec<_> = self.components.iter().map(Self::canonical_type).collect();
+                format!("({}){dims}", components.join(","))
+            }
+            None => self.ty.clone(),
+        }
+    }
+}
+
+/// Generate a schema from a contract ABI. Each state-changing function (and
+/// each `view` or `pure` function, if requested) becomes an action whose
+/// context holds the function's inputs. Overloaded functions are named by
+/// their full signature.
+pub fn schema_from_abi(abi: &Value, args: &AbiToSchemaArgs) -> Result<Value> {
+    let items = abi.get("abi").unwrap_or(abi).clone();
+    let items: Vec<AbiItem> = serde_json::from_value(items)
+        .into_diagnostic()
+        .wrap_err("failed to parse contract ABI")?;
+    let functions: Vec<_> = items
+        .iter()
+        .filter(|item| item.ty == "function" && (args.include_view || !item.is_view()))
+        .collect();
+
+    let mut overloads: HashMap<&str, usize> = HashMap::new();
+    for function in &functions {
+        *overloads.entry(function.name.as_str()).or_default() += 1;
+    }
+
+    let mut actions = BTreeMap::new();
+    for function in functions {
+        let action = if overloads[function.name.as_str()] > 1 {
+            function.signature()
+        } else {
+            function.name.clone()
+        };
+        let mut attributes = record_attributes(&function.inputs).wrap_err_with(|| {
+            format!("failed to translate inputs of `{}`", function.signature())
+        })?;
+        if function.is_payable() {
+            attributes.insert("value".into(), u256_type());
+        }
+        actions.insert(
+            action,
+            json!({
+                "appliesTo": {
+                    "principalTypes": [args.principal_type],
+                    "resourceTypes": [args.resource_type],
+                    "context": { "type": "Record", "attributes": attributes }
+                }
+            }),
+        );
+    }
+
+    Ok(json!({
+        args.namespace.clone(): {
+            "entityTypes": {
+                args.principal_type.clone(): {},
+                args.resource_type.clone(): {}
+            },
+            "actions": actions
+        }
+    }))
+}
+
+/// Attributes of a record holding `params`. Unnamed parameters are named by
+/// their position, e.g. `arg0`.
+fn record_attributes(params: &[AbiParam]) -> Result<BTreeMap<String, Value>> {
+    params
+        .iter()
+        .enumerate()
+        .map(|(i, param)| {
+            let name = if param.name.is_empty() {
+                format!("arg{i}")
+            } else {
+                param.name.clone()
+            };
+            Ok((name, cedar_type(&param.ty, &param.components)?))
+        })
+        .collect()
+}
+
+/// Translate a Solidity type to a schema type. Unsigned integers which don't
+/// fit in a `Long` are `u256` values; addresses and byte strings are
+/// hex strings.
+fn cedar_type(ty: &str, components: &[AbiParam]) -> Result<Value> {
+    if let Some(element) = ty.strip_suffix(']') {
+        let (element, _) = element
+            .rsplit_once('[')
+            .ok_or_else(|| miette!("malformed array type `{ty}`"))?;
+        return Ok(json!({ "type": "Set", "element": cedar_type(element, components)? }));
+    }
+    let bits = |prefix: &str| -> Result<u32> {
+        match &ty[prefix.len()..] {
+            "" => Ok(256),
+            n => n
+                .parse()
+                .map_err(|_| miette!("malformed integer type `{ty}`")),
+        }
+    };
+    match ty {
+        "bool" => Ok(json!({ "type": "Boolean" })),
+        "address" | "string" | "function" => Ok(json!({ "type": "String" })),
+        "tuple" => Ok(json!({ "type": "Record", "attributes": record_attributes(components)? })),
+        _ if ty.starts_with("bytes") => Ok(json!({ "type": "String" })),
+        _ if ty.starts_with("uint") => {
+            if bits("uint")? < 64 {
+                Ok(json!({ "type": "Long" }))
+            } else {
+                Ok(u256_type())
+            }
+        }
+        _ if ty.starts_with("int") => {
+            if bits("int")? <= 64 {
+                Ok(json!({ "type": "Long" }))
+            } else {
+                // there's no signed 256-bit type, so wide signed integers are
+                // passed as decimal strings
+                Ok(json!({ "type": "String" }))
+            }
+        }
+        _ => Err(miette!("unsupported ABI type `{ty}`")),
+    }
+}
+
+fn u256_type() -> Value {
+    json!({ "type": "Extension", "name": "u256" })
+}
+
+fn abi_to_schema_inner(args: &AbiToSchemaArgs) -> Result<()> {
+    let abi_src = read_from_file(&args.abi_file, "contract ABI")?;
+    let abi: Value = serde_json::from_str(&abi_src)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("failed to parse contract ABI from {}", args.abi_file))?;
+    let schema = schema_from_abi(&abi, args)?;
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&schema).into_diagnostic()?
+    );
+    Ok(())
+}
+
+pub fn abi_to_schema(args: &AbiToSchemaArgs) -> CedarExitCode {
+    if let Err(err) = abi_to_schema_inner(args) {
+        println!("Error: {err:?}");
+        CedarExitCode::Failure
+    } else {
+        CedarExitCode::Success
+    }
+}
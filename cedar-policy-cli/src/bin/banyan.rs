@@ -0,0 +1,43 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+#![forbid(unsafe_code)]
+
+use clap::Parser;
+
+use cedar_policy_cli::{
+    abi_to_schema, authorize, cost, diff, format_policies, invariants, lint, repl, seal, validate,
+    BanyanCli, BanyanCommands, CedarExitCode,
+};
+
+fn main() -> CedarExitCode {
+    let cli = BanyanCli::parse();
+
+    cli.err_fmt.install_hook();
+
+    match cli.command {
+        BanyanCommands::Validate(args) => validate(&args),
+        BanyanCommands::Check(args) => authorize(&args),
+        BanyanCommands::Fmt(args) => format_policies(&args),
+        BanyanCommands::Lint(args) => lint(&args),
+        BanyanCommands::Diff(args) => diff(&args),
+        BanyanCommands::AbiToSchema(args) => abi_to_schema(&args),
+        BanyanCommands::Repl(args) => repl(&args),
+        BanyanCommands::Seal(args) => seal(&args),
+        BanyanCommands::Invariants(args) => invariants(&args),
+        BanyanCommands::Cost(args) => cost(&args),
+    }
+}
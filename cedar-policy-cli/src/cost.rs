@@ -0,0 +1,73 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Estimating the cost of each policy, and checking it against budgets.
+
+use cedar_policy::cost::{estimate_all, PolicyCost};
+use clap::Args;
+use miette::{IntoDiagnostic, Result};
+
+use crate::{read_policy_set, CedarExitCode};
+
+#[derive(Args, Debug)]
+pub struct CostArgs {
+    /// File containing the policy set. If none is provided, read input from
+    /// stdin.
+    #[arg(short, long = "policies", value_name = "FILE")]
+    pub policies_file: Option<String>,
+    /// Report policies whose on-chain check is estimated to cost more gas
+    #[arg(long, value_name = "GAS")]
+    pub max_gas: Option<u64>,
+    /// Report policies estimated to take more evaluation steps
+    #[arg(long, value_name = "STEPS")]
+    pub max_evaluation: Option<u64>,
+}
+
+impl CostArgs {
+    /// Whether `cost` is within the budgets
+    fn within_budget(&self, cost: &PolicyCost) -> bool {
+        self.max_gas.is_none_or(|max| cost.gas <= max)
+            && self.max_evaluation.is_none_or(|max| cost.evaluation <= max)
+    }
+}
+
+fn cost_inner(args: &CostArgs) -> Result<Vec<PolicyCost>> {
+    let policies = read_policy_set(args.policies_file.as_ref())?;
+    Ok(estimate_all(&policies))
+}
+
+pub fn cost(args: &CostArgs) -> CedarExitCode {
+    match cost_inner(args) {
+        Ok(costs) => {
+            let mut exit_code = CedarExitCode::Success;
+            for cost in costs {
+                match serde_json::to_string(&cost).into_diagnostic() {
+                    Ok(json) => println!("{json}"),
+                    Err(err) => println!("Error: {err:?}"),
+                }
+                if !args.within_budget(&cost) {
+                    println!("policy `{}` is over budget", cost.policy);
+                    exit_code = CedarExitCode::ValidationFailure;
+                }
+            }
+            exit_code
+        }
+        Err(err) => {
+            println!("Error: {err:?}");
+            CedarExitCode::Failure
+        }
+    }
+}
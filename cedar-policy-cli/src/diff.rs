@@ -0,0 +1,133 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Comparison of two versions of a policy set, policy by policy.
+
+use std::collections::BTreeMap;
+
+use cedar_policy::PolicySet;
+use clap::Args;
+use miette::Result;
+
+use crate::{read_policy_set, CedarExitCode};
+
+#[derive(Args, Debug)]
+pub struct DiffArgs {
+    /// File containing the old policy set
+    #[arg(value_name = "OLD")]
+    pub old_policies_file: String,
+    /// File containing the new policy set
+    #[arg(value_name = "NEW")]
+    pub new_policies_file: String,
+}
+
+/// A difference between two policy sets
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PolicyChange {
+    /// A policy only in the new set
+    Added {
+        /// Id of the policy
+        id: String,
+        /// Text of the policy
+        text: String,
+    },
+    /// A policy only in the old set
+    Removed {
+        /// Id of the policy
+        id: String,
+        /// Text of the policy
+        text: String,
+    },
+    /// A policy in both sets, with different text
+    Changed {
+        /// Id of the policy
+        id: String,
+        /// Text of the old policy
+        old: String,
+        /// Text of the new policy
+        new: String,
+    },
+}
+
+/// Compare the policies of two policy sets by id. Policies are compared by
+/// their normalized text, so changes in whitespace or comments are ignored.
+/// Templates are compared through their linked policies.
+pub fn diff_policy_sets(old: &PolicySet, new: &PolicySet) -> Vec<PolicyChange> {
+    let texts = |pset: &PolicySet| -> BTreeMap<String, String> {
+        pset.policies()
+            .map(|p| (p.id().to_string(), p.to_string()))
+            .collect()
+    };
+    let old = texts(old);
+    let mut new = texts(new);
+    let mut changes = Vec::new();
+    for (id, old_text) in old {
+        match new.remove(&id) {
+            None => changes.push(PolicyChange::Removed { id, text: old_text }),
+            Some(new_text) if new_text != old_text => changes.push(PolicyChange::Changed {
+                id,
+                old: old_text,
+                new: new_text,
+            }),
+            Some(_) => (),
+        }
+    }
+    changes.extend(
+        new.into_iter()
+            .map(|(id, text)| PolicyChange::Added { id, text }),
+    );
+    changes
+}
+
+fn prefixed(text: &str, prefix: &str) -> String {
+    text.lines()
+        .map(|line| format!("{prefix} {line}"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn diff_inner(args: &DiffArgs) -> Result<Vec<PolicyChange>> {
+    let old = read_policy_set(Some(&args.old_policies_file))?;
+    let new = read_policy_set(Some(&args.new_policies_file))?;
+    Ok(diff_policy_sets(&old, &new))
+}
+
+pub fn diff(args: &DiffArgs) -> CedarExitCode {
+    match diff_inner(args) {
+        Ok(changes) => {
+            for change in changes {
+                match change {
+                    PolicyChange::Added { id, text } => {
+                        println!("added policy `{id}`:\n{}", prefixed(&text, "+"))
+                    }
+                    PolicyChange::Removed { id, text } => {
+                        println!("removed policy `{id}`:\n{}", prefixed(&text, "-"))
+                    }
+                    PolicyChange::Changed { id, old, new } => println!(
+                        "changed policy `{id}`:\n{}\n{}",
+                        prefixed(&old, "-"),
+                        prefixed(&new, "+")
+                    ),
+                }
+            }
+            CedarExitCode::Success
+        }
+        Err(err) => {
+            println!("Error: {err:?}");
+            CedarExitCode::Failure
+        }
+    }
+}
@@ -0,0 +1,79 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Checking a policy set against the invariants declared in a file.
+
+use cedar_policy::invariants::{check, parse_invariants, Violation};
+use cedar_policy::repair::{repair_violations, Repair};
+use clap::Args;
+use miette::{IntoDiagnostic, Result};
+
+use crate::{read_from_file, read_policy_set, read_schema_file, CedarExitCode};
+
+#[derive(Args, Debug)]
+pub struct InvariantsArgs {
+    /// File containing the schema
+    #[arg(short, long = "schema", value_name = "FILE")]
+    pub schema_file: String,
+    /// File containing the policy set
+    #[arg(short, long = "policies", value_name = "FILE")]
+    pub policies_file: String,
+    /// File containing the invariants
+    #[arg(short, long = "invariants", value_name = "FILE")]
+    pub invariants_file: String,
+    /// Also print a suggested repair of each violating policy, as JSON
+    #[arg(long)]
+    pub repair: bool,
+}
+
+fn invariants_inner(args: &InvariantsArgs) -> Result<(Vec<Violation>, Vec<Repair>)> {
+    let policies = read_policy_set(Some(&args.policies_file))?;
+    let schema = read_schema_file(&args.schema_file)?;
+    let invariants = parse_invariants(&read_from_file(&args.invariants_file, "invariants")?)
+        .into_diagnostic()?;
+    let violations = check(&invariants, &policies, &schema);
+    let repairs = if args.repair {
+        repair_violations(&violations, &invariants, &policies)
+    } else {
+        Vec::new()
+    };
+    Ok((violations, repairs))
+}
+
+pub fn invariants(args: &InvariantsArgs) -> CedarExitCode {
+    match invariants_inner(args) {
+        Ok((violations, _)) if violations.is_empty() => {
+            println!("All invariants hold");
+            CedarExitCode::Success
+        }
+        Ok((violations, repairs)) => {
+            for violation in violations {
+                println!("{violation}");
+            }
+            for repair in repairs {
+                match serde_json::to_string(&repair) {
+                    Ok(json) => println!("{json}"),
+                    Err(err) => println!("Error: {err}"),
+                }
+            }
+            CedarExitCode::ValidationFailure
+        }
+        Err(err) => {
+            println!("Error: {err:?}");
+            CedarExitCode::Failure
+        }
+    }
+}
@@ -19,10 +19,25 @@
 // omitted.
 #![allow(clippy::needless_return)]
 
+mod abi;
+mod cost;
+mod diff;
 mod err;
+mod invariants;
+mod lint;
+mod repl;
+mod seal;
+
+pub use abi::{abi_to_schema, schema_from_abi, AbiToSchemaArgs};
+pub use cost::{cost, CostArgs};
+pub use diff::{diff, diff_policy_sets, DiffArgs, PolicyChange};
+pub use invariants::{invariants, InvariantsArgs};
+pub use lint::{lint, lint_policy_set, lint_requester_controlled, LintArgs, LintFinding};
+pub use repl::{repl, Repl, ReplArgs};
+pub use seal::{seal, SealArgs};
 
 use clap::{Args, Parser, Subcommand, ValueEnum};
-use miette::{miette, IntoDiagnostic, NamedSource, Report, Result, WrapErr};
+use miette::{miette, ErrorHook, IntoDiagnostic, NamedSource, Report, Result, WrapErr};
 use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
@@ -55,6 +70,51 @@ pub struct Cli {
     pub err_fmt: ErrorFormat,
 }
 
+/// Banyan CLI for checking, formatting, and testing policies
+#[derive(Parser)]
+#[command(name = "banyan", author, version, about, long_about = None)]
+pub struct BanyanCli {
+    #[command(subcommand)]
+    pub command: BanyanCommands,
+    /// The output format to use for error reporting.
+    #[arg(
+        global = true,
+        short = 'f',
+        long = "error-format",
+        env = "CEDAR_ERROR_FORMAT",
+        default_value_t,
+        value_enum
+    )]
+    pub err_fmt: ErrorFormat,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum BanyanCommands {
+    /// Validate a policy set against a schema
+    Validate(ValidateArgs),
+    /// Authorize a request given as JSON files
+    Check(AuthorizeArgs),
+    /// Format a policy set
+    Fmt(FormatArgs),
+    /// Check a policy set for likely mistakes
+    Lint(LintArgs),
+    /// Show the policies added, removed, or changed between two policy sets
+    Diff(DiffArgs),
+    /// Generate a schema from an Ethereum contract ABI
+    AbiToSchema(AbiToSchemaArgs),
+    /// Evaluate expressions interactively against a schema and entities
+    Repl(ReplArgs),
+    /// Validate a policy set and write it out sealed, so that it loads
+    /// without validation, printing the hash of the sealed set
+    Seal(SealArgs),
+    /// Check that a policy set keeps the invariants declared in a file,
+    /// reporting the policies which may break them
+    Invariants(InvariantsArgs),
+    /// Estimate the evaluation cost and on-chain gas of each policy,
+    /// reporting the policies over budget
+    Cost(CostArgs),
+}
+
 #[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
 pub enum ErrorFormat {
     /// Human-readable error messages with terminal graphics and inline code
@@ -68,6 +128,25 @@ pub enum ErrorFormat {
     Json,
 }
 
+impl ErrorFormat {
+    /// Install the `miette` error-reporting hook for this format. Must be
+    /// called at most once, before any errors are reported.
+    pub fn install_hook(self) {
+        let err_hook: Option<ErrorHook> = match self {
+            ErrorFormat::Human => None, // This is the default.
+            ErrorFormat::Plain => Some(Box::new(|_| {
+                Box::new(miette::NarratableReportHandler::new())
+            })),
+            ErrorFormat::Json => Some(Box::new(|_| Box::new(miette::JSONReportHandler::new()))),
+        };
+        if let Some(err_hook) = err_hook {
+            // PANIC SAFETY: `set_hook` returns an error if a hook has already been installed. Callers install the hook once, on entering `main`.
+            #[allow(clippy::expect_used)]
+            miette::set_hook(err_hook).expect("failed to install error-reporting hook");
+        }
+    }
+}
+
 impl Display for ErrorFormat {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
@@ -108,6 +187,17 @@ pub struct ValidateArgs {
     /// File containing the policy set
     #[arg(short, long = "policies", value_name = "FILE")]
     pub policies_file: String,
+    /// Fail validation for policies without this annotation. May be given
+    /// more than once.
+    #[arg(long = "require-annotation", value_name = "KEY")]
+    pub required_annotations: Vec<String>,
+    /// Fail validation for `@chain` annotations listing a chain id other
+    /// than these. May be given more than once.
+    #[arg(long = "chain", value_name = "ID")]
+    pub known_chains: Vec<u64>,
+    /// Allow contexts to have attributes which the schema doesn't declare
+    #[arg(long = "open-context")]
+    pub open_context: bool,
 }
 
 #[derive(Args, Debug)]
@@ -407,6 +497,7 @@ pub fn validate(args: &ValidateArgs) -> CedarExitCode {
     };
 
     let schema = match read_schema_file(&args.schema_file) {
+        Ok(schema) if args.open_context => schema.with_context_mode(ContextMode::Open),
         Ok(schema) => schema,
         Err(e) => {
             println!("Error: {e:?}");
@@ -414,7 +505,11 @@ pub fn validate(args: &ValidateArgs) -> CedarExitCode {
         }
     };
 
-    let validator = Validator::new(schema);
+    let mut validator =
+        Validator::new(schema).with_required_annotations(args.required_annotations.iter().cloned());
+    if !args.known_chains.is_empty() {
+        validator = validator.with_known_chains(args.known_chains.iter().copied());
+    }
     let result = validator.validate(&pset, ValidationMode::default());
     if result.validation_passed() {
         println!("Validation Passed");
@@ -911,7 +1006,11 @@ fn read_policy_set(
 ) -> miette::Result<PolicySet> {
     let context = "policy set";
     let ps_str = read_from_file_or_stdin(filename, context)?;
-    let ps = PolicySet::from_str(&ps_str)
+    // imports are relative to the directory of the policy file
+    let import_dir = filename
+        .and_then(|n| n.as_ref().parent().map(Path::to_path_buf))
+        .unwrap_or_default();
+    let ps = PolicySet::from_str_with_imports(&ps_str, &ImportDir::new(import_dir))
         .map_err(|err| {
             let name = filename.map_or_else(
                 || "<stdin>".to_owned(),
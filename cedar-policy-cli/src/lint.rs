@@ -0,0 +1,170 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Checks for policies which are legal but likely to be mistakes.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+
+use cedar_policy::*;
+use clap::Args;
+use miette::{IntoDiagnostic, Result, WrapErr};
+
+use crate::{read_policy_set, read_schema_file, CedarExitCode};
+
+#[derive(Args, Debug)]
+pub struct LintArgs {
+    /// File containing the policy set. If none is provided, read input from
+    /// stdin.
+    #[arg(short, long = "policies", value_name = "FILE")]
+    pub policies_file: Option<String>,
+    /// File containing the schema. If one is provided, also report permits
+    /// which only depend on requester controlled context.
+    #[arg(short, long = "schema", value_name = "FILE")]
+    pub schema_file: Option<String>,
+}
+
+/// A potential problem found by the linter
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintFinding {
+    /// Short identifier of the check which produced this finding
+    pub code: &'static str,
+    /// Policy the finding is about
+    pub policy_id: String,
+    /// Description of the problem
+    pub message: String,
+}
+
+impl Display for LintFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "warning[{}]: policy `{}`: {}",
+            self.code, self.policy_id, self.message
+        )
+    }
+}
+
+/// Run every check on `pset`. Findings are sorted by policy id.
+pub fn lint_policy_set(pset: &PolicySet) -> Result<Vec<LintFinding>> {
+    let mut findings = Vec::new();
+    let mut seen: HashMap<String, &PolicyId> = HashMap::new();
+    for policy in pset.policies() {
+        let unconstrained = matches!(policy.principal_constraint(), PrincipalConstraint::Any)
+            && matches!(policy.action_constraint(), ActionConstraint::Any)
+            && matches!(policy.resource_constraint(), ResourceConstraint::Any);
+        let est = policy
+            .to_json()
+            .into_diagnostic()
+            .wrap_err_with(|| format!("failed to convert policy `{}`", policy.id()))?;
+        let conditions = est.get("conditions").cloned().unwrap_or_default();
+        let unconditional = conditions.as_array().map_or(true, Vec::is_empty);
+        if unconstrained && unconditional {
+            let message = match policy.effect() {
+                Effect::Permit => "permits every request",
+                Effect::Forbid => "forbids every request",
+            };
+            findings.push(LintFinding {
+                code: "unconstrained-policy",
+                policy_id: policy.id().to_string(),
+                message: message.into(),
+            });
+        }
+
+        // two policies with the same effect, scope, and conditions (ignoring
+        // annotations) are redundant
+        let mut key = est;
+        if let Some(obj) = key.as_object_mut() {
+            obj.remove("annotations");
+        }
+        match seen.get(&key.to_string()) {
+            Some(original) => findings.push(LintFinding {
+                code: "duplicate-policy",
+                policy_id: policy.id().to_string(),
+                message: format!("is equivalent to policy `{original}`"),
+            }),
+            None => {
+                seen.insert(key.to_string(), policy.id());
+            }
+        }
+    }
+
+    // the confusable-text check works on templates, so check static policies
+    // by re-parsing them as templates without slots
+    let static_templates = pset
+        .policies()
+        .filter(|p| p.is_static())
+        .map(|p| Template::parse(Some(p.id().to_string()), p.to_string()))
+        .collect::<Result<Vec<_>, _>>()
+        .into_diagnostic()?;
+    for warning in confusable_string_checker(pset.templates().chain(static_templates.iter())) {
+        findings.push(LintFinding {
+            code: "confusable-text",
+            policy_id: warning.location().policy_id().to_string(),
+            message: warning.warning_kind().to_string(),
+        });
+    }
+
+    findings.sort_by(|a, b| a.policy_id.cmp(&b.policy_id));
+    Ok(findings)
+}
+
+/// Report permits in `pset` which, for some action in `schema`, only depend
+/// on context attributes the requester controls
+pub fn lint_requester_controlled(pset: &PolicySet, schema: &Schema) -> Vec<LintFinding> {
+    taint::check(pset, schema)
+        .into_iter()
+        .map(|tainted| LintFinding {
+            code: "requester-controlled",
+            policy_id: tainted.policy().to_string(),
+            message: format!(
+                "permits `{}` on requester controlled context alone: {}",
+                tainted.action(),
+                tainted.attributes().collect::<Vec<_>>().join(", ")
+            ),
+        })
+        .collect()
+}
+
+fn lint_inner(args: &LintArgs) -> Result<Vec<LintFinding>> {
+    let pset = read_policy_set(args.policies_file.as_ref())?;
+    let mut findings = lint_policy_set(&pset)?;
+    if let Some(schema_file) = &args.schema_file {
+        let schema = read_schema_file(schema_file)?;
+        findings.extend(lint_requester_controlled(&pset, &schema));
+        findings.sort_by(|a, b| a.policy_id.cmp(&b.policy_id));
+    }
+    Ok(findings)
+}
+
+pub fn lint(args: &LintArgs) -> CedarExitCode {
+    match lint_inner(args) {
+        Ok(findings) if findings.is_empty() => {
+            println!("No problems found");
+            CedarExitCode::Success
+        }
+        Ok(findings) => {
+            for finding in findings {
+                println!("{finding}");
+            }
+            CedarExitCode::ValidationFailure
+        }
+        Err(err) => {
+            println!("Error: {err:?}");
+            CedarExitCode::Failure
+        }
+    }
+}
@@ -17,28 +17,16 @@
 #![forbid(unsafe_code)]
 
 use clap::Parser;
-use miette::ErrorHook;
 
 use cedar_policy_cli::{
     authorize, check_parse, evaluate, format_policies, link, new, validate, CedarExitCode, Cli,
-    Commands, ErrorFormat,
+    Commands,
 };
 
 fn main() -> CedarExitCode {
     let cli = Cli::parse();
 
-    let err_hook: Option<ErrorHook> = match cli.err_fmt {
-        ErrorFormat::Human => None, // This is the default.
-        ErrorFormat::Plain => Some(Box::new(|_| {
-            Box::new(miette::NarratableReportHandler::new())
-        })),
-        ErrorFormat::Json => Some(Box::new(|_| Box::new(miette::JSONReportHandler::new()))),
-    };
-    if let Some(err_hook) = err_hook {
-        // PANIC SAFETY: `set_hook` returns an error if a hook has already been installed. We have just entered `main`, so we know a hook has not been installed.
-        #[allow(clippy::expect_used)]
-        miette::set_hook(err_hook).expect("failed to install error-reporting hook");
-    }
+    cli.err_fmt.install_hook();
 
     match cli.command {
         Commands::Authorize(args) => authorize(&args),
@@ -0,0 +1,229 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! An interactive loop for evaluating expressions against a schema, entities,
+//! and a request which can be changed between expressions.
+
+use std::io::{BufRead, Write};
+use std::str::FromStr;
+
+use cedar_policy::*;
+use clap::Args;
+use miette::{miette, IntoDiagnostic, Result, WrapErr};
+
+use crate::{load_actions_from_schema, load_entities, read_schema_file, CedarExitCode};
+
+/// Values whose single-line form is longer than this are printed over
+/// several lines
+const LINE_WIDTH: usize = 80;
+
+const HELP: &str = "\
+Enter an expression to evaluate it, or one of these commands:
+  :principal <uid>    set the principal, e.g. :principal Account::\"alice\"
+  :action <uid>       set the action
+  :resource <uid>     set the resource
+  :context <json>     set the context to a JSON object
+  :request            show the current request
+  :help               show this message
+  :quit               exit";
+
+#[derive(Args, Debug)]
+pub struct ReplArgs {
+    /// File containing schema information, used to populate the store with
+    /// action entities and for schema-based parsing of entities and context
+    #[arg(short, long = "schema", value_name = "FILE")]
+    pub schema_file: Option<String>,
+    /// File containing JSON representation of the Cedar entity hierarchy
+    #[arg(short, long = "entities", value_name = "FILE")]
+    pub entities_file: Option<String>,
+}
+
+/// The state of a REPL session: the loaded schema and entities, and the
+/// request expressions are evaluated in
+#[derive(Debug)]
+pub struct Repl {
+    schema: Option<Schema>,
+    entities: Entities,
+    principal: Option<EntityUid>,
+    action: Option<EntityUid>,
+    resource: Option<EntityUid>,
+    context: serde_json::Value,
+}
+
+impl Repl {
+    /// Start a session with an unknown principal, action, and resource, and
+    /// an empty context
+    pub fn new(schema: Option<Schema>, entities: Entities) -> Self {
+        Self {
+            schema,
+            entities,
+            principal: None,
+            action: None,
+            resource: None,
+            context: serde_json::json!({}),
+        }
+    }
+
+    /// Start a session from the files named in `args`
+    pub fn from_args(args: &ReplArgs) -> Result<Self> {
+        let schema = args
+            .schema_file
+            .as_ref()
+            .map(read_schema_file)
+            .transpose()?;
+        let entities = match &args.entities_file {
+            Some(file) => load_entities(file, schema.as_ref())?,
+            None => Entities::empty(),
+        };
+        let entities = load_actions_from_schema(entities, &schema)?;
+        Ok(Self::new(schema, entities))
+    }
+
+    /// Evaluate one line of input, which is either a command or an
+    /// expression, and return the text to show. The result of an expression
+    /// is shown as `value : type`.
+    pub fn eval_line(&mut self, line: &str) -> Result<String> {
+        let line = line.trim();
+        let Some(command) = line.strip_prefix(':') else {
+            return self.eval(line);
+        };
+        let (command, arg) = command
+            .split_once(char::is_whitespace)
+            .unwrap_or((command, ""));
+        let arg = arg.trim();
+        match command {
+            "principal" => self.principal = Some(parse_uid(arg, "principal")?),
+            "action" => self.action = Some(parse_uid(arg, "action")?),
+            "resource" => self.resource = Some(parse_uid(arg, "resource")?),
+            "context" => {
+                let context: serde_json::Value = serde_json::from_str(arg)
+                    .into_diagnostic()
+                    .wrap_err("failed to parse context as JSON")?;
+                if !context.is_object() {
+                    return Err(miette!("context must be a JSON object"));
+                }
+                self.context = context;
+            }
+            "request" => (),
+            "help" => return Ok(HELP.into()),
+            _ => return Err(miette!("unknown command `:{command}`; try `:help`")),
+        }
+        Ok(self.describe_request())
+    }
+
+    fn eval(&self, src: &str) -> Result<String> {
+        let expr = Expression::from_str(src).wrap_err("failed to parse the expression")?;
+        let (value, ty) = eval_expression_with_type(&self.request()?, &self.entities, &expr)
+            .into_diagnostic()
+            .wrap_err("failed to evaluate the expression")?;
+        Ok(format!("{} : {ty}", pretty(&value, 0)))
+    }
+
+    fn request(&self) -> Result<Request> {
+        let context = Context::from_json_value(
+            self.context.clone(),
+            self.schema.as_ref().zip(self.action.as_ref()),
+        )
+        .into_diagnostic()
+        .wrap_err("failed to create a context")?;
+        Ok(Request::new(
+            self.principal.clone(),
+            self.action.clone(),
+            self.resource.clone(),
+            context,
+        ))
+    }
+
+    fn describe_request(&self) -> String {
+        let show = |uid: &Option<EntityUid>| {
+            uid.as_ref()
+                .map_or_else(|| "unknown".to_string(), ToString::to_string)
+        };
+        format!(
+            "principal: {}\naction: {}\nresource: {}\ncontext: {}",
+            show(&self.principal),
+            show(&self.action),
+            show(&self.resource),
+            self.context
+        )
+    }
+}
+
+fn parse_uid(src: &str, role: &str) -> Result<EntityUid> {
+    EntityUid::from_str(src).wrap_err_with(|| format!("failed to parse {role} {src} as entity Uid"))
+}
+
+/// Print `value` on one line if it fits, and otherwise with one set element
+/// or record attribute per line, indented by `depth`
+fn pretty(value: &EvalResult, depth: usize) -> String {
+    let flat = value.to_string();
+    if flat.len() + 2 * depth <= LINE_WIDTH {
+        return flat;
+    }
+    let pad = "  ".repeat(depth + 1);
+    let end = "  ".repeat(depth);
+    match value {
+        EvalResult::Set(set) => {
+            let elements: Vec<_> = set
+                .iter()
+                .map(|v| format!("{pad}{}", pretty(v, depth + 1)))
+                .collect();
+            format!("[\n{}\n{end}]", elements.join(",\n"))
+        }
+        EvalResult::Record(record) => {
+            let attrs: Vec<_> = record
+                .iter()
+                .map(|(k, v)| format!("{pad}\"{}\": {}", k.escape_debug(), pretty(v, depth + 1)))
+                .collect();
+            format!("{{\n{}\n{end}}}", attrs.join(",\n"))
+        }
+        _ => flat,
+    }
+}
+
+fn repl_inner(args: &ReplArgs) -> Result<()> {
+    let mut repl = Repl::from_args(args)?;
+    let stdin = std::io::stdin();
+    let mut stdout = std::io::stdout();
+    println!("Type `:help` for help, `:quit` to exit");
+    loop {
+        print!("banyan> ");
+        stdout.flush().into_diagnostic()?;
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).into_diagnostic()? == 0 {
+            // end of input
+            println!();
+            return Ok(());
+        }
+        match line.trim() {
+            "" => continue,
+            ":quit" | ":q" => return Ok(()),
+            line => match repl.eval_line(line) {
+                Ok(output) => println!("{output}"),
+                Err(err) => println!("Error: {err:?}"),
+            },
+        }
+    }
+}
+
+pub fn repl(args: &ReplArgs) -> CedarExitCode {
+    if let Err(err) = repl_inner(args) {
+        println!("Error: {err:?}");
+        CedarExitCode::Failure
+    } else {
+        CedarExitCode::Success
+    }
+}
@@ -0,0 +1,71 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Sealing a policy set at build time, so that it loads without validation.
+
+use cedar_policy::sealed::SealedPolicySet;
+use cedar_policy::{ValidationMode, Validator};
+use clap::Args;
+use miette::{IntoDiagnostic, Result, WrapErr};
+
+use crate::{read_policy_set, read_schema_file, CedarExitCode};
+
+#[derive(Args, Debug)]
+pub struct SealArgs {
+    /// File containing the schema
+    #[arg(short, long = "schema", value_name = "FILE")]
+    pub schema_file: String,
+    /// File containing the policy set
+    #[arg(short, long = "policies", value_name = "FILE")]
+    pub policies_file: String,
+    /// Version of the schema, e.g. a release tag, recorded in the sealed set
+    #[arg(long, value_name = "VERSION")]
+    pub schema_version: String,
+    /// File to write the sealed policy set to
+    #[arg(short, long = "output", value_name = "FILE")]
+    pub output_file: String,
+}
+
+/// Seal the policy set, returning the hash of the sealed set
+fn seal_inner(args: &SealArgs) -> Result<[u8; 32]> {
+    let policies = read_policy_set(Some(&args.policies_file))?;
+    let schema = read_schema_file(&args.schema_file)?;
+    let sealed = SealedPolicySet::seal(
+        &policies,
+        &Validator::new(schema),
+        ValidationMode::default(),
+        args.schema_version.as_str(),
+    )
+    .into_diagnostic()?;
+    std::fs::write(&args.output_file, sealed.to_json().into_diagnostic()?)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("failed to write sealed policy set to {}", args.output_file))?;
+    Ok(sealed.hash())
+}
+
+pub fn seal(args: &SealArgs) -> CedarExitCode {
+    match seal_inner(args) {
+        Ok(hash) => {
+            let hex: String = hash.iter().map(|b| format!("{b:02x}")).collect();
+            println!("0x{hex}");
+            CedarExitCode::Success
+        }
+        Err(err) => {
+            println!("Error: {err:?}");
+            CedarExitCode::Failure
+        }
+    }
+}
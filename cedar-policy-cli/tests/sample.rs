@@ -15,13 +15,19 @@
  */
 
 use std::collections::HashMap;
+use std::str::FromStr;
 
 use cedar_policy::EvalResult;
+use cedar_policy::PolicyId;
 use cedar_policy::SlotId;
+use cedar_policy::{PolicySet, Schema};
 use cedar_policy_cli::check_parse;
 use cedar_policy_cli::{
-    authorize, evaluate, link, validate, Arguments, AuthorizeArgs, CedarExitCode, CheckParseArgs,
-    EvaluateArgs, LinkArgs, RequestArgs, ValidateArgs,
+    abi_to_schema, authorize, cost, diff, diff_policy_sets, evaluate, invariants, link, lint,
+    lint_policy_set, lint_requester_controlled, schema_from_abi, seal, validate, AbiToSchemaArgs,
+    Arguments, AuthorizeArgs, CedarExitCode, CheckParseArgs, CostArgs, DiffArgs, EvaluateArgs,
+    InvariantsArgs, LinkArgs, LintArgs, PolicyChange, Repl, ReplArgs, RequestArgs, SealArgs,
+    ValidateArgs,
 };
 
 fn run_check_parse_test(policies_file: impl Into<String>, expected_exit_code: CedarExitCode) {
@@ -418,9 +424,21 @@ fn test_authorize_samples() {
 }
 
 fn run_validate_test(policies_file: &str, schema_file: &str, exit_code: CedarExitCode) {
+    run_validate_test_with_annotations(policies_file, schema_file, &[], exit_code);
+}
+
+fn run_validate_test_with_annotations(
+    policies_file: &str,
+    schema_file: &str,
+    required_annotations: &[&str],
+    exit_code: CedarExitCode,
+) {
     let cmd = ValidateArgs {
         schema_file: schema_file.into(),
         policies_file: policies_file.into(),
+        required_annotations: required_annotations.iter().map(|&key| key.into()).collect(),
+        known_chains: Vec::new(),
+        open_context: false,
     };
     let output = validate(&cmd);
     assert_eq!(exit_code, output, "{:#?}", cmd);
@@ -443,6 +461,12 @@ fn test_validate_samples() {
         "sample-data/sandbox_a/schema.cedarschema.json",
         CedarExitCode::Success,
     );
+    run_validate_test_with_annotations(
+        "sample-data/sandbox_a/policies_1.cedar",
+        "sample-data/sandbox_a/schema.cedarschema.json",
+        &["owner"],
+        CedarExitCode::ValidationFailure,
+    );
     // Contains misspelled entity type.
     run_validate_test(
         "sample-data/sandbox_a/policies_1_bad.cedar",
@@ -829,3 +853,248 @@ fn test_format_samples() {
     let ps_files = glob("sample-data/**/polic*.cedar").unwrap();
     ps_files.for_each(|ps_file| run_format_test(ps_file.unwrap().to_str().unwrap()));
 }
+
+#[test]
+fn test_lint_samples() {
+    let findings = lint_policy_set(
+        &PolicySet::from_str(
+            &std::fs::read_to_string("sample-data/banyan/lint.cedar").expect("file exists"),
+        )
+        .expect("policies should parse"),
+    )
+    .expect("policies should convert");
+    let codes: Vec<_> = findings.iter().map(|f| f.code).collect();
+    assert_eq!(codes, ["unconstrained-policy", "duplicate-policy"]);
+
+    let cmd = LintArgs {
+        policies_file: Some("sample-data/banyan/lint.cedar".into()),
+        schema_file: None,
+    };
+    assert_eq!(lint(&cmd), CedarExitCode::ValidationFailure);
+    let cmd = LintArgs {
+        policies_file: Some("sample-data/sandbox_a/policies_1.cedar".into()),
+        schema_file: None,
+    };
+    assert_eq!(lint(&cmd), CedarExitCode::Success);
+}
+
+#[test]
+fn test_lint_requester_controlled() {
+    let schema = Schema::from_str(
+        &std::fs::read_to_string("sample-data/banyan/lint.cedarschema.json").expect("file exists"),
+    )
+    .expect("schema should parse");
+    let pset = PolicySet::from_str(
+        &std::fs::read_to_string("sample-data/banyan/lint.cedar").expect("file exists"),
+    )
+    .expect("policies should parse");
+    let findings: Vec<_> = lint_requester_controlled(&pset, &schema)
+        .into_iter()
+        .map(|f| f.policy_id)
+        .collect();
+    // `policy0` has no conditions, and `policy1` and `policy2` only check the
+    // amount the requester claims to transfer
+    assert_eq!(findings, ["policy1", "policy2"]);
+
+    let cmd = LintArgs {
+        policies_file: Some("sample-data/sandbox_a/policies_1.cedar".into()),
+        schema_file: Some("sample-data/banyan/lint.cedarschema.json".into()),
+    };
+    assert_eq!(lint(&cmd), CedarExitCode::Success);
+}
+
+#[test]
+fn test_diff_samples() {
+    let cmd = DiffArgs {
+        old_policies_file: "sample-data/banyan/old.cedar".into(),
+        new_policies_file: "sample-data/banyan/new.cedar".into(),
+    };
+    assert_eq!(diff(&cmd), CedarExitCode::Success);
+
+    let old = PolicySet::from_str(
+        r#"permit(principal, action == Action::"transfer", resource) when { context.amount < 100 };
+           forbid(principal, action == Action::"approve", resource);"#,
+    )
+    .expect("policies should parse");
+    let new = PolicySet::from_str(
+        r#"permit(principal, action == Action::"transfer", resource)
+             when { context.amount < 100 }; // reformatted
+           forbid(principal, action == Action::"approve", resource) unless { false };
+           permit(principal, action == Action::"deposit", resource);"#,
+    )
+    .expect("policies should parse");
+    let changes: Vec<_> = diff_policy_sets(&old, &new)
+        .into_iter()
+        .map(|change| match change {
+            PolicyChange::Added { id, .. } => format!("+{id}"),
+            PolicyChange::Removed { id, .. } => format!("-{id}"),
+            PolicyChange::Changed { id, .. } => format!("~{id}"),
+        })
+        .collect();
+    assert_eq!(changes, ["~policy1", "+policy2"]);
+}
+
+#[test]
+fn test_abi_to_schema() {
+    let args = AbiToSchemaArgs {
+        abi_file: "sample-data/banyan/erc20.abi.json".into(),
+        namespace: "Token".into(),
+        principal_type: "Account".into(),
+        resource_type: "Contract".into(),
+        include_view: false,
+    };
+    assert_eq!(abi_to_schema(&args), CedarExitCode::Success);
+
+    let abi: serde_json::Value = serde_json::from_str(
+        &std::fs::read_to_string("sample-data/banyan/erc20.abi.json").expect("file exists"),
+    )
+    .expect("ABI is JSON");
+    let schema_json = schema_from_abi(&abi, &args).expect("ABI should translate");
+    let actions = &schema_json["Token"]["actions"];
+    assert_eq!(
+        actions["transfer"]["appliesTo"]["context"]["attributes"]["amount"],
+        serde_json::json!({ "type": "Extension", "name": "u256" })
+    );
+    assert_eq!(
+        actions["deposit"]["appliesTo"]["context"]["attributes"]["value"]["name"],
+        "u256"
+    );
+    assert_eq!(
+        actions["multicall"]["appliesTo"]["context"]["attributes"]["calls"]["element"]["type"],
+        "Record"
+    );
+    assert!(actions.get("balanceOf").is_none());
+    Schema::from_json_value(schema_json).expect("generated schema should be valid");
+
+    // artifacts wrap the ABI, and view functions can be included
+    let args = AbiToSchemaArgs {
+        include_view: true,
+        ..args
+    };
+    let schema_json =
+        schema_from_abi(&serde_json::json!({ "abi": abi }), &args).expect("ABI should translate");
+    assert!(schema_json["Token"]["actions"].get("balanceOf").is_some());
+}
+
+#[test]
+fn test_seal() {
+    let dir = tempfile::tempdir().expect("failed to create temp dir");
+    let output = dir.path().join("sealed.json");
+    let args = SealArgs {
+        schema_file: "sample-data/sandbox_a/schema.cedarschema.json".into(),
+        policies_file: "sample-data/sandbox_a/policies_1.cedar".into(),
+        schema_version: "1".into(),
+        output_file: output.to_string_lossy().into_owned(),
+    };
+    assert_eq!(seal(&args), CedarExitCode::Success);
+    let sealed = cedar_policy::sealed::SealedPolicySet::from_json(
+        &std::fs::read(&output).expect("sealed set was written"),
+    )
+    .expect("sealed set should load");
+    assert_eq!(sealed.schema_version(), "1");
+    let policies = sealed.into_policy_set().expect("digest matches");
+    assert_eq!(policies.policies().count(), 2);
+    assert!(policies
+        .policy(&PolicyId::from_str("disallow tim policy").expect("valid id"))
+        .is_some());
+
+    // policies which don't validate aren't sealed
+    std::fs::remove_file(&output).expect("sealed set was written");
+    let args = SealArgs {
+        policies_file: "sample-data/sandbox_a/policies_1_bad.cedar".into(),
+        ..args
+    };
+    assert_eq!(seal(&args), CedarExitCode::Failure);
+    assert!(!output.exists());
+}
+
+#[test]
+fn test_invariants() {
+    let dir = tempfile::tempdir().expect("failed to create temp dir");
+    let file = dir.path().join("invariants");
+    let run = |src: &str| {
+        std::fs::write(&file, src).expect("failed to write invariants");
+        invariants(&InvariantsArgs {
+            schema_file: "sample-data/sandbox_a/schema.cedarschema.json".into(),
+            policies_file: "sample-data/sandbox_a/policies_1.cedar".into(),
+            invariants_file: file.to_string_lossy().into_owned(),
+            repair: true,
+        })
+    };
+    assert_eq!(
+        run(r#"invariant friends: permit Action::"view" on Photo
+               only if principal in UserGroup::"jane_friends";"#),
+        CedarExitCode::Success
+    );
+    assert_eq!(
+        run(r#"invariant alice: permit Action::"view" only if principal == User::"alice";"#),
+        CedarExitCode::ValidationFailure
+    );
+    assert_eq!(
+        run(r#"invariant alice: view only if true;"#),
+        CedarExitCode::Failure
+    );
+}
+
+#[test]
+fn test_cost() {
+    let run = |max_gas, max_evaluation| {
+        cost(&CostArgs {
+            policies_file: Some("sample-data/sandbox_a/policies_1.cedar".into()),
+            max_gas,
+            max_evaluation,
+        })
+    };
+    assert_eq!(run(None, None), CedarExitCode::Success);
+    assert_eq!(run(Some(1_000_000), Some(1_000)), CedarExitCode::Success);
+    assert_eq!(run(Some(10), None), CedarExitCode::ValidationFailure);
+    assert_eq!(run(None, Some(1)), CedarExitCode::ValidationFailure);
+}
+
+#[test]
+fn test_repl() {
+    let mut repl = Repl::from_args(&ReplArgs {
+        schema_file: None,
+        entities_file: Some("sample-data/sandbox_a/entities.json".into()),
+    })
+    .expect("sample data should load");
+    assert_eq!(
+        repl.eval_line(r#"User::"alice" in UserGroup::"jane_friends""#)
+            .expect("expression should evaluate"),
+        "true : Boolean"
+    );
+    assert_eq!(
+        repl.eval_line("[1, 2]")
+            .expect("expression should evaluate"),
+        "[1, 2] : Set<Long>"
+    );
+
+    repl.eval_line(r#":context { "amount": "5" }"#)
+        .expect("context should be set");
+    assert_eq!(
+        repl.eval_line(r#"u256(context.amount).u256LessThan(u256("10"))"#)
+            .expect("expression should evaluate"),
+        "true : Boolean"
+    );
+    assert!(repl
+        .eval_line(r#":principal User::"alice""#)
+        .expect("principal should be set")
+        .contains(r#"principal: User::"alice""#));
+    assert_eq!(
+        repl.eval_line("principal")
+            .expect("expression should evaluate"),
+        r#"User::"alice" : User"#
+    );
+
+    // long values are split over several lines
+    let strings: Vec<_> = (0..9).map(|i| format!(r#""abcdefghi{i}""#)).collect();
+    let long = format!("[{}]", strings.join(", "));
+    assert!(repl
+        .eval_line(&format!("{{ a: {long} }}"))
+        .expect("expression should evaluate")
+        .starts_with("{\n  \"a\": "));
+
+    assert!(repl.eval_line(":bogus").is_err());
+    assert!(repl.eval_line(":context [1]").is_err());
+    assert!(repl.eval_line("1 +").is_err());
+}
@@ -222,6 +222,14 @@ pub struct Entity {
     /// Set of ancestors of this `Entity` (i.e., all direct and transitive
     /// parents), as UIDs
     ancestors: HashSet<EntityUID>,
+
+    /// Internal HashMap of tags: key-value metadata which, unlike attributes,
+    /// isn't declared key-by-key in the schema.
+    ///
+    /// In the serialized form of `Entity`, tag values appear as
+    /// `RestrictedExpr`s.
+    #[serde(default)]
+    tags: HashMap<SmolStr, RestrictedExpr>,
 }
 
 impl Entity {
@@ -230,11 +238,22 @@ impl Entity {
         uid: EntityUID,
         attrs: HashMap<SmolStr, RestrictedExpr>,
         ancestors: HashSet<EntityUID>,
+    ) -> Self {
+        Self::new_with_tags(uid, attrs, ancestors, HashMap::new())
+    }
+
+    /// Create a new `Entity` with this UID, attributes, ancestors, and tags
+    pub fn new_with_tags(
+        uid: EntityUID,
+        attrs: HashMap<SmolStr, RestrictedExpr>,
+        ancestors: HashSet<EntityUID>,
+        tags: HashMap<SmolStr, RestrictedExpr>,
     ) -> Self {
         Entity {
             uid,
             attrs,
             ancestors,
+            tags,
         }
     }
 
@@ -248,6 +267,11 @@ impl Entity {
         self.attrs.get(attr)
     }
 
+    /// Get the value for the given tag, or `None` if not present
+    pub fn get_tag(&self, tag: &str) -> Option<&RestrictedExpr> {
+        self.tags.get(tag)
+    }
+
     /// Is this `Entity` a descendant of `e` in the entity hierarchy?
     pub fn is_descendant_of(&self, e: &EntityUID) -> bool {
         self.ancestors.contains(e)
@@ -265,12 +289,19 @@ impl Entity {
             .map(|(k, v)| (k.as_str(), v.as_borrowed()))
     }
 
-    /// Create an `Entity` with the given UID, no attributes, and no parents.
+    /// Iterate over this entity's tags
+    pub fn tags(&self) -> impl Iterator<Item = (&str, BorrowedRestrictedExpr<'_>)> {
+        self.tags.iter().map(|(k, v)| (k.as_str(), v.as_borrowed()))
+    }
+
+    /// Create an `Entity` with the given UID, no attributes, no parents, and
+    /// no tags.
     pub fn with_uid(uid: EntityUID) -> Self {
         Self {
             uid,
             attrs: HashMap::new(),
             ancestors: HashSet::new(),
+            tags: HashMap::new(),
         }
     }
 
@@ -293,6 +324,13 @@ impl Entity {
         self.attrs.insert(attr, val);
     }
 
+    /// Set the given tag to the given value.
+    // Only used for convenience in some tests and when fuzzing
+    #[cfg(any(test, fuzzing))]
+    pub fn set_tag(&mut self, tag: SmolStr, val: RestrictedExpr) {
+        self.tags.insert(tag, val);
+    }
+
     /// Mark the given `UID` as an ancestor of this `Entity`.
     // When fuzzing, `add_ancestor()` is fully `pub`.
     #[cfg(not(fuzzing))]
@@ -349,7 +387,18 @@ impl std::fmt::Display for Entity {
                 .map(|(k, v)| format!("{}: {}", k, v))
                 .join("; "),
             self.ancestors.iter().join(", ")
-        )
+        )?;
+        if !self.tags.is_empty() {
+            write!(
+                f,
+                "\n  tags:{}",
+                self.tags
+                    .iter()
+                    .map(|(k, v)| format!("{}: {}", k, v))
+                    .join("; ")
+            )?;
+        }
+        Ok(())
     }
 }
 
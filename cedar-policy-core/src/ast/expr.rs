@@ -147,6 +147,14 @@ pub enum ExprKind<T = ()> {
         /// Be careful the backslash in `\*` must not be another escape sequence. For instance, `\\*` matches a backslash plus an arbitrary string.
         pattern: Pattern,
     },
+    /// Entity type test. Does `expr` evaluate to an entity of type
+    /// `entity_type`?
+    Is {
+        /// Expression to test. Must evaluate to an Entity
+        expr: Arc<Expr<T>>,
+        /// The entity type to test for
+        entity_type: Name,
+    },
     /// Set (whose elements may be arbitrary expressions)
     //
     // This is backed by `Vec` (and not e.g. `HashSet`), because two `Expr`s
@@ -415,6 +423,18 @@ impl Expr {
         ExprBuilder::new().contains_any(e1, e2)
     }
 
+    /// Create a `getTag` expression. `expr` must evaluate to Entity type,
+    /// `tag` to String type
+    pub fn get_tag(expr: Expr, tag: Expr) -> Self {
+        ExprBuilder::new().get_tag(expr, tag)
+    }
+
+    /// Create a `hasTag` expression. `expr` must evaluate to Entity type,
+    /// `tag` to String type
+    pub fn has_tag(expr: Expr, tag: Expr) -> Self {
+        ExprBuilder::new().has_tag(expr, tag)
+    }
+
     /// Create an `Expr` which evaluates to a Set of the given `Expr`s
     pub fn set(exprs: impl IntoIterator<Item = Expr>) -> Self {
         ExprBuilder::new().set(exprs)
@@ -466,6 +486,13 @@ impl Expr {
         ExprBuilder::new().like(expr, pattern)
     }
 
+    /// Create an `is` expression.
+    ///
+    /// `expr` must evaluate to an Entity type
+    pub fn is_entity_type(expr: Expr, entity_type: Name) -> Self {
+        ExprBuilder::new().is_entity_type(expr, entity_type)
+    }
+
     /// Check if an expression contains any symbolic unknowns
     pub fn is_unknown(&self) -> bool {
         self.subexpressions()
@@ -552,6 +579,10 @@ impl Expr {
                 expr.substitute(definitions)?,
                 pattern.iter().cloned(),
             )),
+            ExprKind::Is { expr, entity_type } => Ok(Expr::is_entity_type(
+                expr.substitute(definitions)?,
+                entity_type.clone(),
+            )),
             ExprKind::Set(members) => {
                 let members = members
                     .iter()
@@ -667,6 +698,12 @@ impl std::fmt::Display for Expr {
                 BinaryOp::ContainsAny => {
                     write!(f, "{}.containsAny({})", maybe_with_parens(arg1), &arg2)
                 }
+                BinaryOp::GetTag => {
+                    write!(f, "{}.getTag({})", maybe_with_parens(arg1), &arg2)
+                }
+                BinaryOp::HasTag => {
+                    write!(f, "{}.hasTag({})", maybe_with_parens(arg1), &arg2)
+                }
             },
             ExprKind::MulByConst { arg, constant } => {
                 write!(f, "{} * {}", maybe_with_parens(arg), constant)
@@ -717,7 +754,15 @@ impl std::fmt::Display for Expr {
             ExprKind::Like { expr, pattern } => {
                 // during parsing we convert \* in the pattern into \u{0000},
                 // so when printing we need to convert back
-                write!(f, "{} like \"{}\"", maybe_with_parens(expr), pattern,)
+                let op = if pattern.has_classes() {
+                    "glob"
+                } else {
+                    "like"
+                };
+                write!(f, "{} {op} \"{}\"", maybe_with_parens(expr), pattern,)
+            }
+            ExprKind::Is { expr, entity_type } => {
+                write!(f, "{} is {}", maybe_with_parens(expr), entity_type)
             }
             ExprKind::Set(v) => write!(f, "[{}]", v.iter().join(", ")),
             ExprKind::Record { pairs } => write!(
@@ -761,6 +806,7 @@ fn maybe_with_parens(expr: &Expr) -> String {
         ExprKind::GetAttr { .. } => format!("({})", expr),
         ExprKind::HasAttr { .. } => format!("({})", expr),
         ExprKind::Like { .. } => format!("({})", expr),
+        ExprKind::Is { .. } => format!("({})", expr),
         ExprKind::Set { .. } => expr.to_string(),
         ExprKind::Record { .. } => expr.to_string(),
     }
@@ -1049,6 +1095,26 @@ impl<T> ExprBuilder<T> {
         })
     }
 
+    /// Create a `getTag` expression. `expr` must evaluate to Entity type,
+    /// `tag` to String type
+    pub fn get_tag(self, expr: Expr<T>, tag: Expr<T>) -> Expr<T> {
+        self.with_expr_kind(ExprKind::BinaryApp {
+            op: BinaryOp::GetTag,
+            arg1: Arc::new(expr),
+            arg2: Arc::new(tag),
+        })
+    }
+
+    /// Create a `hasTag` expression. `expr` must evaluate to Entity type,
+    /// `tag` to String type
+    pub fn has_tag(self, expr: Expr<T>, tag: Expr<T>) -> Expr<T> {
+        self.with_expr_kind(ExprKind::BinaryApp {
+            op: BinaryOp::HasTag,
+            arg1: Arc::new(expr),
+            arg2: Arc::new(tag),
+        })
+    }
+
     /// Create an `Expr` which evaluates to a Set of the given `Expr`s
     pub fn set(self, exprs: impl IntoIterator<Item = Expr<T>>) -> Expr<T> {
         self.with_expr_kind(ExprKind::Set(Arc::new(exprs.into_iter().collect())))
@@ -1120,6 +1186,16 @@ impl<T> ExprBuilder<T> {
             pattern: Pattern::new(pattern),
         })
     }
+
+    /// Create an `is` expression.
+    ///
+    /// `expr` must evaluate to an Entity type
+    pub fn is_entity_type(self, expr: Expr<T>, entity_type: Name) -> Expr<T> {
+        self.with_expr_kind(ExprKind::Is {
+            expr: Arc::new(expr),
+            entity_type,
+        })
+    }
 }
 
 impl<T: Clone> ExprBuilder<T> {
@@ -1280,6 +1356,13 @@ impl<T> Expr<T> {
                     pattern: pattern1,
                 },
             ) => pattern == pattern1 && expr.eq_shape(expr1),
+            (
+                Is { expr, entity_type },
+                Is {
+                    expr: expr1,
+                    entity_type: entity_type1,
+                },
+            ) => entity_type == entity_type1 && expr.eq_shape(expr1),
             (Set(elems), Set(elems1)) => elems
                 .iter()
                 .zip(elems1.iter())
@@ -1360,6 +1443,10 @@ impl<T> Expr<T> {
                 expr.hash_shape(state);
                 pattern.hash(state);
             }
+            ExprKind::Is { expr, entity_type } => {
+                expr.hash_shape(state);
+                entity_type.hash(state);
+            }
             ExprKind::Set(elems) => {
                 state.write_usize(elems.len());
                 elems.iter().for_each(|e| {
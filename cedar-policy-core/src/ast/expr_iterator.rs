@@ -88,6 +88,12 @@ impl<'a, T> Iterator for ExprIterator<'a, T> {
             ExprKind::Like { expr, pattern: _ } => {
                 self.expression_stack.push(expr);
             }
+            ExprKind::Is {
+                expr,
+                entity_type: _,
+            } => {
+                self.expression_stack.push(expr);
+            }
             ExprKind::Set(elems) => {
                 for expr in elems.as_ref() {
                     self.expression_stack.push(expr);
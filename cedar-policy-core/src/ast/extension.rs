@@ -272,6 +272,42 @@ impl ExtensionFunction {
         )
     }
 
+    /// Create a new `ExtensionFunction` taking four arguments
+    pub fn quaternary(
+        name: Name,
+        style: CallStyle,
+        func: Box<
+            dyn Fn(Value, Value, Value, Value) -> evaluator::Result<ExtensionOutputValue>
+                + Sync
+                + Send
+                + 'static,
+        >,
+        return_type: SchemaType,
+        arg_types: (
+            Option<SchemaType>,
+            Option<SchemaType>,
+            Option<SchemaType>,
+            Option<SchemaType>,
+        ),
+    ) -> Self {
+        Self::new(
+            name.clone(),
+            style,
+            Box::new(move |args: &[Value]| match &args {
+                &[first, second, third, fourth] => {
+                    func(first.clone(), second.clone(), third.clone(), fourth.clone())
+                }
+                _ => Err(evaluator::EvaluationError::wrong_num_arguments(
+                    name.clone(),
+                    4,
+                    args.len(),
+                )),
+            }),
+            Some(return_type),
+            vec![arg_types.0, arg_types.1, arg_types.2, arg_types.3],
+        )
+    }
+
     /// Get the `Name` of the `ExtensionFunction`
     pub fn name(&self) -> &Name {
         &self.name
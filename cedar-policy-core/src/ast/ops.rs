@@ -84,6 +84,18 @@ pub enum BinaryOp {
     ///
     /// Arguments must have Set type
     ContainsAny,
+
+    /// Get a tag of an entity.
+    ///
+    /// First argument must have Entity type.
+    /// Second argument must have String type.
+    GetTag,
+
+    /// Does the given entity have the given tag?
+    ///
+    /// First argument must have Entity type.
+    /// Second argument must have String type.
+    HasTag,
 }
 
 impl std::fmt::Display for UnaryOp {
@@ -107,6 +119,8 @@ impl std::fmt::Display for BinaryOp {
             BinaryOp::Contains => write!(f, "contains"),
             BinaryOp::ContainsAll => write!(f, "containsAll"),
             BinaryOp::ContainsAny => write!(f, "containsAny"),
+            BinaryOp::GetTag => write!(f, "getTag"),
+            BinaryOp::HasTag => write!(f, "hasTag"),
         }
     }
 }
@@ -18,8 +18,73 @@ use std::sync::Arc;
 
 use serde::{Deserialize, Serialize};
 
+/// A character class in the pattern of a `glob` expression, written `[a-f0-9]` or
+/// `[^0-9]`
+#[derive(Serialize, Deserialize, Hash, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct CharClass {
+    /// Inclusive character ranges; a single character `c` is the range `(c, c)`
+    ranges: Arc<Vec<(char, char)>>,
+    /// Whether the class matches characters outside of `ranges` instead
+    negated: bool,
+}
+
+impl CharClass {
+    /// Create a character class out of inclusive character ranges
+    pub fn new(ranges: impl IntoIterator<Item = (char, char)>, negated: bool) -> Self {
+        Self {
+            ranges: Arc::new(ranges.into_iter().collect()),
+            negated,
+        }
+    }
+
+    /// Getter to the inclusive character ranges
+    pub fn ranges(&self) -> &[(char, char)] {
+        &self.ranges
+    }
+
+    /// Whether the class is negated
+    pub fn is_negated(&self) -> bool {
+        self.negated
+    }
+
+    /// Does the class match the character `c`
+    pub fn contains(&self, c: char) -> bool {
+        self.ranges.iter().any(|(lo, hi)| (*lo..=*hi).contains(&c)) != self.negated
+    }
+
+    /// A class is well-formed if it is nonempty and none of its ranges are
+    /// reversed. The parser only produces well-formed classes.
+    pub fn is_well_formed(&self) -> bool {
+        !self.ranges.is_empty() && self.ranges.iter().all(|(lo, hi)| lo <= hi)
+    }
+}
+
+impl std::fmt::Display for CharClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn write_class_char(f: &mut std::fmt::Formatter<'_>, c: char) -> std::fmt::Result {
+            match c {
+                ']' | '-' | '^' => write!(f, "\\{c}"),
+                _ => write!(f, "{}", c.escape_debug()),
+            }
+        }
+        write!(f, "[")?;
+        if self.negated {
+            write!(f, "^")?;
+        }
+        for (lo, hi) in self.ranges.iter() {
+            write_class_char(f, *lo)?;
+            if lo != hi {
+                write!(f, "-")?;
+                write_class_char(f, *hi)?;
+            }
+        }
+        write!(f, "]")
+    }
+}
+
 /// Represent an element in a pattern literal (the RHS of the like operation)
-#[derive(Deserialize, Hash, Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Deserialize, Hash, Debug, Clone, PartialEq, Eq)]
 // We need special serialization implementation for CedarDRT because Rust's
 // unicode escape sequences (e.g., `\u{1234}`) can appear in serialized strings
 // and it's difficult to parse them into Dafny characters.
@@ -32,6 +97,8 @@ pub enum PatternElem {
     Char(char),
     /// The wildcard `*`
     Wildcard,
+    /// A character class such as `[0-9a-f]`, matching a single character
+    Class(CharClass),
 }
 
 #[cfg(feature = "arbitrary")]
@@ -45,10 +112,23 @@ impl Serialize for PatternElem {
         enum PatternElemForDafny {
             Char(u32),
             Wildcard,
+            Class {
+                ranges: Vec<(u32, u32)>,
+                negated: bool,
+            },
         }
         match self {
             Self::Char(c) => PatternElemForDafny::Char(*c as u32).serialize(serializer),
             Self::Wildcard => PatternElemForDafny::Wildcard.serialize(serializer),
+            Self::Class(class) => PatternElemForDafny::Class {
+                ranges: class
+                    .ranges()
+                    .iter()
+                    .map(|(lo, hi)| (*lo as u32, *hi as u32))
+                    .collect(),
+                negated: class.is_negated(),
+            }
+            .serialize(serializer),
         }
     }
 }
@@ -79,15 +159,39 @@ impl Pattern {
     pub fn iter(&self) -> impl Iterator<Item = &PatternElem> {
         self.elems.iter()
     }
+
+    /// Does the pattern have a character class, which only `glob` patterns
+    /// can have
+    pub fn has_classes(&self) -> bool {
+        self.elems
+            .iter()
+            .any(|elem| matches!(elem, PatternElem::Class(_)))
+    }
+
+    /// Is every character class in the pattern well-formed
+    pub fn is_well_formed(&self) -> bool {
+        self.elems.iter().all(|elem| match elem {
+            PatternElem::Class(class) => class.is_well_formed(),
+            PatternElem::Char(_) | PatternElem::Wildcard => true,
+        })
+    }
 }
 
 impl std::fmt::Display for Pattern {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // a pattern with classes is printed as the pattern of a `glob`, where
+        // these characters are escaped as they would otherwise start or be
+        // part of a class. A `like` pattern is printed as it always was.
+        let glob = self.has_classes();
         for pc in self.elems.as_ref() {
             match pc {
                 PatternElem::Char(c) if c == &'*' => write!(f, r#"\*"#)?,
+                PatternElem::Char(c) if glob && matches!(c, '[' | ']' | '-' | '^') => {
+                    write!(f, "\\{c}")?
+                }
                 PatternElem::Char(c) => write!(f, "{}", c.escape_debug())?,
                 PatternElem::Wildcard => write!(f, r#"*"#)?,
+                PatternElem::Class(class) => write!(f, "{class}")?,
             }
         }
         Ok(())
@@ -95,10 +199,11 @@ impl std::fmt::Display for Pattern {
 }
 
 impl PatternElem {
-    fn match_char(&self, text_char: &char) -> bool {
+    fn match_char(&self, text_char: char) -> bool {
         match self {
-            PatternElem::Char(c) => text_char == c,
+            PatternElem::Char(c) => text_char == *c,
             PatternElem::Wildcard => true,
+            PatternElem::Class(class) => class.contains(text_char),
         }
     }
     fn is_wildcard(&self) -> bool {
@@ -109,58 +214,95 @@ impl PatternElem {
 impl Pattern {
     /// Find if the argument text matches the pattern
     pub fn wildcard_match(&self, text: &str) -> bool {
-        let pattern = self.get_elems();
-        if pattern.is_empty() {
-            return text.is_empty();
+        // ASCII text (e.g., hex-encoded addresses and hashes) is matched
+        // byte-by-byte without copying it. Otherwise, copying the string into a
+        // vector requires extra space, but makes accessing elements efficient
+        // and provides an unambiguous length: in general for a string s,
+        // s.len() is not the same as s.chars().count().
+        if text.is_ascii() {
+            match_elems(self.get_elems(), text.as_bytes())
+        } else {
+            let text: Vec<char> = text.chars().collect();
+            match_elems(self.get_elems(), &text)
         }
+    }
+}
 
-        // Copying the strings into vectors requires extra space, but has two benefits:
-        // 1. It makes accessing elements more efficient. The alternative (i.e.,
-        //    chars().nth()) needs to re-scan the string for each invocation. Note
-        //    that a simple iterator will not work here since we move both forward
-        //    and backward through the string.
-        // 2. It provides an unambiguous length. In general for a string s,
-        //    s.len() is not the same as s.chars().count(). The length of these
-        //    created vectors will match .chars().count()
-        let text: Vec<char> = text.chars().collect();
-
-        let mut i: usize = 0; // index into text
-        let mut j: usize = 0; // index into pattern
-        let mut star_idx: usize = 0; // index in pattern (j) of the most recent *
-        let mut tmp_idx: usize = 0; // index in text (i) of the most recent *
-        let mut contains_star: bool = false; // does the pattern contain *?
-
-        let text_len = text.len();
-        let pattern_len = pattern.len();
-
-        while i < text_len && (!contains_star || star_idx != pattern_len - 1) {
-            // PANIC SAFETY `j` is checked to be less than length
-            #[allow(clippy::indexing_slicing)]
-            if j < pattern_len && pattern[j].is_wildcard() {
-                contains_star = true;
-                star_idx = j;
-                tmp_idx = i;
-                j += 1;
-            } else if j < pattern_len && pattern[j].match_char(&text[i]) {
-                i += 1;
-                j += 1;
-            } else if contains_star {
-                j = star_idx + 1;
-                i = tmp_idx + 1;
-                tmp_idx = i;
-            } else {
-                return false;
-            }
-        }
+/// Match `text` against `pattern`. The literal prefix and suffix of the
+/// pattern (everything before the first and after the last `*`) are matched
+/// directly, so the backtracking search only runs over the part of the text
+/// between them.
+fn match_elems<C: Copy + Into<char>>(pattern: &[PatternElem], text: &[C]) -> bool {
+    let matches_exactly = |pattern: &[PatternElem], text: &[C]| {
+        pattern.len() == text.len()
+            && pattern
+                .iter()
+                .zip(text)
+                .all(|(elem, c)| elem.match_char((*c).into()))
+    };
+
+    let prefix_len = pattern
+        .iter()
+        .take_while(|elem| !elem.is_wildcard())
+        .count();
+    if prefix_len == pattern.len() {
+        return matches_exactly(pattern, text);
+    }
+    let suffix_len = pattern
+        .iter()
+        .rev()
+        .take_while(|elem| !elem.is_wildcard())
+        .count();
+    if prefix_len + suffix_len > text.len() {
+        return false;
+    }
+    let (pattern_prefix, pattern) = pattern.split_at(prefix_len);
+    let (pattern, pattern_suffix) = pattern.split_at(pattern.len() - suffix_len);
+    let (text_prefix, text) = text.split_at(prefix_len);
+    let (text, text_suffix) = text.split_at(text.len() - suffix_len);
+    if !matches_exactly(pattern_prefix, text_prefix)
+        || !matches_exactly(pattern_suffix, text_suffix)
+    {
+        return false;
+    }
+
+    // `pattern` now starts and ends with a `*`
+    let mut i: usize = 0; // index into text
+    let mut j: usize = 0; // index into pattern
+    let mut star_idx: usize = 0; // index in pattern (j) of the most recent *
+    let mut tmp_idx: usize = 0; // index in text (i) of the most recent *
+    let mut contains_star: bool = false; // does the pattern contain *?
+
+    let text_len = text.len();
+    let pattern_len = pattern.len();
 
+    while i < text_len && (!contains_star || star_idx != pattern_len - 1) {
         // PANIC SAFETY `j` is checked to be less than length
         #[allow(clippy::indexing_slicing)]
-        while j < pattern_len && pattern[j].is_wildcard() {
+        if j < pattern_len && pattern[j].is_wildcard() {
+            contains_star = true;
+            star_idx = j;
+            tmp_idx = i;
             j += 1;
+        } else if j < pattern_len && pattern[j].match_char(text[i].into()) {
+            i += 1;
+            j += 1;
+        } else if contains_star {
+            j = star_idx + 1;
+            i = tmp_idx + 1;
+            tmp_idx = i;
+        } else {
+            return false;
         }
+    }
 
-        j == pattern_len
+    // PANIC SAFETY `j` is checked to be less than length
+    #[allow(clippy::indexing_slicing)]
+    while j < pattern_len && pattern[j].is_wildcard() {
+        j += 1;
     }
+
+    j == pattern_len
 }
 
 #[cfg(test)]
@@ -240,4 +382,66 @@ pub mod test {
         // Patterns that do not match "ḛ̶͑͝x̶͔͛a̵̰̯͛m̴͉̋́p̷̠͂l̵͇̍̔ȩ̶̣͝"
         assert!(!(string_map("y") + star()).wildcard_match("ḛ̶͑͝x̶͔͛a̵̰̯͛m̴͉̋́p̷̠͂l̵͇̍̔ȩ̶̣͝"));
     }
+
+    // Create a pattern literal with a single character class
+    fn class(ranges: &[(char, char)], negated: bool) -> Pattern {
+        Pattern::new(vec![PatternElem::Class(CharClass::new(
+            ranges.iter().copied(),
+            negated,
+        ))])
+    }
+
+    #[test]
+    fn test_wildcard_match_class() {
+        let hex = class(&[('0', '9'), ('a', 'f')], false);
+        assert!((string_map("0x") + hex.clone() + star()).wildcard_match("0xab"));
+        assert!((star() + hex.clone()).wildcard_match("0xab"));
+        assert!(!(string_map("0x") + hex.clone() + star()).wildcard_match("0xgb"));
+        assert!(!hex.wildcard_match(""));
+        assert!(!hex.wildcard_match("ab"));
+
+        let not_zero = class(&[('0', '0')], true);
+        assert!((string_map("0x") + not_zero.clone() + star()).wildcard_match("0x1234"));
+        assert!(!(string_map("0x") + not_zero + star()).wildcard_match("0x0123"));
+
+        // classes match a single unicode character
+        assert!(class(&[('α', 'ω')], false).wildcard_match("λ"));
+        assert!((star() + class(&[('α', 'ω')], false)).wildcard_match("fooλ"));
+    }
+
+    #[test]
+    fn test_wildcard_match_long_hex() {
+        let address = format!("0x0000{}", "ab".repeat(18));
+        let vanity = string_map("0x0000") + star();
+        assert!(vanity.wildcard_match(&address));
+        assert!(!vanity.wildcard_match(&format!("0x0001{}", "ab".repeat(18))));
+
+        // the address `0x00` followed by a digit 0-7 then anything
+        let range = string_map("0x00") + class(&[('0', '7')], false) + star();
+        assert!(range.wildcard_match(&address));
+        assert!(!range.wildcard_match(&format!("0x008{}", "ab".repeat(18))));
+
+        // prefix and suffix must not overlap
+        assert!(!(string_map("0xab") + star() + string_map("bab")).wildcard_match("0xabab"));
+        assert!((string_map("0xab") + star() + string_map("ab")).wildcard_match("0xabab"));
+
+        let long = "a".repeat(10_000);
+        assert!((star() + string_map("a") + star() + string_map("a")).wildcard_match(&long));
+        assert!(!(star() + string_map("b") + star() + string_map("a")).wildcard_match(&long));
+    }
+
+    #[test]
+    fn test_display_class() {
+        let pattern =
+            string_map("[x") + class(&[('0', '9'), ('-', '-'), (']', ']')], false) + star();
+        assert_eq!(pattern.to_string(), r"\[x[0-9\-\]]*");
+        assert_eq!(class(&[('a', 'a')], true).to_string(), "[^a]");
+    }
+
+    #[test]
+    fn test_well_formed() {
+        assert!((string_map("0x") + class(&[('0', '9')], false)).is_well_formed());
+        assert!(!class(&[], false).is_well_formed());
+        assert!(!class(&[('9', '0')], false).is_well_formed());
+    }
 }
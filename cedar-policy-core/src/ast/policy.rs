@@ -616,9 +616,11 @@ impl LiteralPolicy {
     }
 }
 
+/// Sorted, so that the text of a linked policy is deterministic
 fn display_slot_env(env: &SlotEnv) -> String {
     env.iter()
         .map(|(slot, value)| format!("{slot} -> {value}"))
+        .sorted()
         .join(",")
 }
 
@@ -1024,6 +1026,12 @@ impl PrincipalConstraint {
             PrincipalOrResourceConstraint::In(EntityReference::Slot) => Self {
                 constraint: PrincipalOrResourceConstraint::In(EntityReference::EUID(euid)),
             },
+            PrincipalOrResourceConstraint::IsIn(entity_type, EntityReference::Slot) => Self {
+                constraint: PrincipalOrResourceConstraint::IsIn(
+                    entity_type,
+                    EntityReference::EUID(euid),
+                ),
+            },
             _ => self,
         }
     }
@@ -1110,6 +1118,12 @@ impl ResourceConstraint {
             PrincipalOrResourceConstraint::In(EntityReference::Slot) => Self {
                 constraint: PrincipalOrResourceConstraint::In(EntityReference::EUID(euid)),
             },
+            PrincipalOrResourceConstraint::IsIn(entity_type, EntityReference::Slot) => Self {
+                constraint: PrincipalOrResourceConstraint::IsIn(
+                    entity_type,
+                    EntityReference::EUID(euid),
+                ),
+            },
             _ => self,
         }
     }
@@ -1210,7 +1224,8 @@ impl TryFrom<Var> for PrincipalOrResource {
 }
 
 /// Represents the constraints for principals and resources.
-/// Can either not constrain, or constrain via `==` or `in` for a single entity literal.
+/// Can either not constrain, constrain via `==` or `in` for a single entity literal,
+/// or constrain the entity type with `is` (optionally combined with `in`).
 #[derive(Serialize, Deserialize, Clone, Hash, Eq, PartialEq, Debug)]
 pub enum PrincipalOrResourceConstraint {
     /// Unconstrained
@@ -1219,6 +1234,10 @@ pub enum PrincipalOrResourceConstraint {
     In(EntityReference),
     /// Equality constraint
     Eq(EntityReference),
+    /// Entity type constraint
+    Is(Name),
+    /// Entity type and hierarchical constraint
+    IsIn(Name, EntityReference),
 }
 
 impl PrincipalOrResourceConstraint {
@@ -1247,6 +1266,16 @@ impl PrincipalOrResourceConstraint {
         PrincipalOrResourceConstraint::In(EntityReference::euid(euid))
     }
 
+    /// Constrained to have a specific entity type.
+    pub fn is_entity_type(entity_type: Name) -> Self {
+        PrincipalOrResourceConstraint::Is(entity_type)
+    }
+
+    /// Constrained to have a specific entity type and be in a specific euid.
+    pub fn is_entity_type_in(entity_type: Name, euid: EntityUID) -> Self {
+        PrincipalOrResourceConstraint::IsIn(entity_type, EntityReference::euid(euid))
+    }
+
     /// Turn the constraint into an expr
     /// # arguments
     /// * `v` - The variable name to be used in the expression.
@@ -1259,6 +1288,13 @@ impl PrincipalOrResourceConstraint {
             PrincipalOrResourceConstraint::In(euid) => {
                 Expr::is_in(Expr::var(v.into()), euid.into_expr(v.into()))
             }
+            PrincipalOrResourceConstraint::Is(entity_type) => {
+                Expr::is_entity_type(Expr::var(v.into()), entity_type.clone())
+            }
+            PrincipalOrResourceConstraint::IsIn(entity_type, euid) => Expr::and(
+                Expr::is_entity_type(Expr::var(v.into()), entity_type.clone()),
+                Expr::is_in(Expr::var(v.into()), euid.into_expr(v.into())),
+            ),
         }
     }
 
@@ -1273,6 +1309,12 @@ impl PrincipalOrResourceConstraint {
             PrincipalOrResourceConstraint::Eq(euid) => {
                 format!("{} == {}", v, euid.into_expr(v.into()))
             }
+            PrincipalOrResourceConstraint::Is(entity_type) => {
+                format!("{} is {}", v, entity_type)
+            }
+            PrincipalOrResourceConstraint::IsIn(entity_type, euid) => {
+                format!("{} is {} in {}", v, entity_type, euid.into_expr(v.into()))
+            }
             PrincipalOrResourceConstraint::Any => format!("{}", v),
         }
     }
@@ -1289,6 +1331,11 @@ impl PrincipalOrResourceConstraint {
                 EntityIterator::One(euid)
             }
             PrincipalOrResourceConstraint::Eq(EntityReference::Slot) => EntityIterator::None,
+            PrincipalOrResourceConstraint::Is(_) => EntityIterator::None,
+            PrincipalOrResourceConstraint::IsIn(_, EntityReference::EUID(euid)) => {
+                EntityIterator::One(euid)
+            }
+            PrincipalOrResourceConstraint::IsIn(_, EntityReference::Slot) => EntityIterator::None,
         }
     }
 }
@@ -1383,7 +1430,7 @@ impl std::fmt::Display for StaticPolicy {
 }
 
 /// A unique identifier for a policy statement
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Hash)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash)]
 pub struct PolicyID(SmolStr);
 
 impl PolicyID {
@@ -1404,6 +1451,12 @@ impl std::fmt::Display for PolicyID {
     }
 }
 
+impl AsRef<str> for PolicyID {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
 #[cfg(feature = "arbitrary")]
 impl<'u> arbitrary::Arbitrary<'u> for PolicyID {
     fn arbitrary(u: &mut arbitrary::Unstructured<'_>) -> arbitrary::Result<PolicyID> {
@@ -20,11 +20,16 @@ use super::{
 };
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
-use std::collections::{hash_map::Entry, HashMap};
+use smol_str::SmolStr;
+use std::collections::{hash_map::Entry, BTreeSet, HashMap};
 use std::{borrow::Borrow, sync::Arc};
 use thiserror::Error;
 
 /// Represents a set of `Policy`s
+///
+/// The maps are shared between clones and copied on write, so cloning a
+/// `PolicySet` is cheap and never copies policy ASTs; modifying a clone
+/// copies only the map being modified.
 #[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(try_from = "LiteralPolicySet")]
 #[serde(into = "LiteralPolicySet")]
@@ -33,12 +38,12 @@ pub struct PolicySet {
     /// A body is either:
     ///    A Body of a `Template`, which has slots that need to be filled in
     ///    A Body of an `StaticPolicy`, which has been converted into a `Template` that has zero slots
-    templates: HashMap<PolicyID, Arc<Template>>,
+    templates: Arc<HashMap<PolicyID, Arc<Template>>>,
     /// `links` contains all of the executable policies in the `PolicySet`
     /// A `StaticPolicy` must have exactly one `Policy` in `links`
     ///   (this is managed by `PolicySet::add)
     /// A `Template` may have zero or many links
-    links: HashMap<PolicyID, Policy>,
+    links: Arc<HashMap<PolicyID, Policy>>,
 }
 
 /// Converts a LiteralPolicySet into a PolicySet, ensuring the invariants are met
@@ -51,13 +56,16 @@ impl TryFrom<LiteralPolicySet> for PolicySet {
             .templates
             .into_iter()
             .map(|(id, template)| (id, Arc::new(template)))
-            .collect();
+            .collect::<HashMap<_, _>>();
         let links = pset
             .links
             .into_iter()
             .map(|(id, literal)| literal.reify(&templates).map(|linked| (id, linked)))
             .collect::<Result<HashMap<PolicyID, Policy>, ReificationError>>()?;
-        Ok(Self { templates, links })
+        Ok(Self {
+            templates: Arc::new(templates),
+            links: Arc::new(links),
+        })
     }
 }
 
@@ -72,13 +80,13 @@ impl From<PolicySet> for LiteralPolicySet {
     fn from(pset: PolicySet) -> Self {
         let templates = pset
             .templates
-            .into_iter()
-            .map(|(id, template)| (id, template.as_ref().clone()))
+            .iter()
+            .map(|(id, template)| (id.clone(), template.as_ref().clone()))
             .collect();
         let links = pset
             .links
-            .into_iter()
-            .map(|(id, p)| (id, p.into()))
+            .iter()
+            .map(|(id, p)| (id.clone(), p.clone().into()))
             .collect();
         Self { templates, links }
     }
@@ -102,8 +110,8 @@ impl PolicySet {
     /// Create a fresh empty `PolicySet`
     pub fn new() -> Self {
         Self {
-            templates: HashMap::new(),
-            links: HashMap::new(),
+            templates: Arc::new(HashMap::new()),
+            links: Arc::new(HashMap::new()),
         }
     }
 
@@ -115,7 +123,9 @@ impl PolicySet {
         // modifications to `self`.
         // So we just collect the `ventry` here, and we only do the insertion
         // once we know there will be no error
-        let template_ventry = match self.templates.entry(t.id().clone()) {
+        let templates = Arc::make_mut(&mut self.templates);
+        let links = Arc::make_mut(&mut self.links);
+        let template_ventry = match templates.entry(t.id().clone()) {
             Entry::Vacant(ventry) => Some(ventry),
             Entry::Occupied(oentry) => {
                 if oentry.get() != &t {
@@ -127,7 +137,7 @@ impl PolicySet {
             }
         };
 
-        let link_ventry = match self.links.entry(policy.id().clone()) {
+        let link_ventry = match links.entry(policy.id().clone()) {
             Entry::Vacant(ventry) => Some(ventry),
             Entry::Occupied(oentry) => {
                 return Err(PolicySetError::Occupied {
@@ -155,8 +165,8 @@ impl PolicySet {
         // TODO: Use `try_insert` when stabilized.
         // https://doc.rust-lang.org/std/collections/struct.HashMap.html#method.try_insert
         match (
-            self.templates.entry(t.id().clone()),
-            self.links.entry(t.id().clone()),
+            Arc::make_mut(&mut self.templates).entry(t.id().clone()),
+            Arc::make_mut(&mut self.links).entry(t.id().clone()),
         ) {
             (Entry::Vacant(templates_entry), Entry::Vacant(links_entry)) => {
                 templates_entry.insert(t);
@@ -176,7 +186,7 @@ impl PolicySet {
     pub fn add_template(&mut self, t: Template) -> Result<(), PolicySetError> {
         // TODO: Use `try_insert` when stabilized.
         // https://doc.rust-lang.org/std/collections/struct.HashMap.html#method.try_insert
-        match self.templates.entry(t.id().clone()) {
+        match Arc::make_mut(&mut self.templates).entry(t.id().clone()) {
             Entry::Occupied(oentry) => Err(PolicySetError::Occupied {
                 id: oentry.key().clone(),
             }),
@@ -209,8 +219,8 @@ impl PolicySet {
 
         // Both maps must not contain the `new_id`
         match (
-            self.links.entry(new_id.clone()),
-            self.templates.entry(new_id),
+            Arc::make_mut(&mut self.links).entry(new_id.clone()),
+            Arc::make_mut(&mut self.templates).entry(new_id),
         ) {
             (Entry::Vacant(links_entry), Entry::Vacant(_)) => Ok(links_entry.insert(r)),
             (Entry::Occupied(oentry), _) => Err(LinkingError::PolicyIdConflict {
@@ -222,6 +232,29 @@ impl PolicySet {
         }
     }
 
+    /// Remove the template-linked policy with id `id`, returning it. Returns
+    /// `None`, leaving the set unchanged, if there is no such policy or it is
+    /// a static policy.
+    pub fn unlink(&mut self, id: &PolicyID) -> Option<Policy> {
+        match self.links.get(id) {
+            Some(policy) if !policy.is_static() => Arc::make_mut(&mut self.links).remove(id),
+            _ => None,
+        }
+    }
+
+    /// Remove the static policy with id `id`, returning it. Returns `None`,
+    /// leaving the set unchanged, if there is no such policy or it is a
+    /// template-linked policy.
+    pub fn remove_static(&mut self, id: &PolicyID) -> Option<Policy> {
+        match self.links.get(id) {
+            Some(policy) if policy.is_static() => {
+                Arc::make_mut(&mut self.templates).remove(id);
+                Arc::make_mut(&mut self.links).remove(id)
+            }
+            _ => None,
+        }
+    }
+
     /// Iterate over all policies
     pub fn policies(&self) -> impl Iterator<Item = &Policy> {
         self.links.values()
@@ -248,6 +281,20 @@ impl PolicySet {
         self.templates.is_empty() && self.links.is_empty()
     }
 
+    /// Get the names of all unknowns occurring in the conditions of the
+    /// policies in this set (e.g., in residual policies produced by partial
+    /// evaluation)
+    pub fn unknowns(&self) -> BTreeSet<SmolStr> {
+        self.policies()
+            .flat_map(|p| {
+                p.condition()
+                    .unknowns()
+                    .map(SmolStr::from)
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
     /// Lookup a template by policy id
     pub fn get_template(&self, id: &PolicyID) -> Option<Arc<Template>> {
         self.templates.get(id).map(Arc::clone)
@@ -297,6 +344,31 @@ mod test {
 
     use super::*;
 
+    #[test]
+    fn clones_share_until_modified() {
+        let mut pset = PolicySet::new();
+        let p1 = parser::parse_policy(Some("p1".into()), "permit(principal,action,resource);")
+            .expect("Failed to parse");
+        pset.add_static(p1).expect("Failed to add!");
+
+        let mut snapshot = pset.clone();
+        assert!(Arc::ptr_eq(&pset.links, &snapshot.links));
+        assert!(Arc::ptr_eq(&pset.templates, &snapshot.templates));
+
+        let p2 = parser::parse_policy(Some("p2".into()), "forbid(principal,action,resource);")
+            .expect("Failed to parse");
+        snapshot.add_static(p2).expect("Failed to add!");
+        assert!(!Arc::ptr_eq(&pset.links, &snapshot.links));
+        assert_eq!(pset.policies().count(), 1);
+        assert_eq!(snapshot.policies().count(), 2);
+        // the policies themselves are still shared
+        let id = PolicyID::from_string("p1");
+        assert!(Arc::ptr_eq(
+            &pset.get_template(&id).expect("p1 is in the set"),
+            &snapshot.get_template(&id).expect("p1 is in the set")
+        ));
+    }
+
     #[test]
     fn link_conflicts() {
         let mut pset = PolicySet::new();
@@ -328,6 +400,50 @@ mod test {
         };
     }
 
+    #[test]
+    fn unlink() {
+        let mut pset = PolicySet::new();
+        let p1 = parser::parse_policy(Some("static".into()), "permit(principal,action,resource);")
+            .expect("Failed to parse");
+        pset.add_static(p1).expect("Failed to add!");
+        let template = parser::parse_policy_template(
+            Some("t".into()),
+            "permit(principal == ?principal, action, resource);",
+        )
+        .expect("Failed to parse");
+        pset.add_template(template).expect("Add failed");
+        let env: HashMap<SlotId, EntityUID> = [(
+            SlotId::principal(),
+            r#"Test::"test""#.parse().expect("Failed to parse"),
+        )]
+        .into_iter()
+        .collect();
+        pset.link(
+            PolicyID::from_string("t"),
+            PolicyID::from_string("link"),
+            env,
+        )
+        .expect("Failed to link");
+
+        assert!(pset.unlink(&PolicyID::from_string("static")).is_none());
+        assert!(pset.unlink(&PolicyID::from_string("t")).is_none());
+        let removed = pset
+            .unlink(&PolicyID::from_string("link"))
+            .expect("link is in the set");
+        assert_eq!(removed.id(), &PolicyID::from_string("link"));
+        assert!(pset.get(&PolicyID::from_string("link")).is_none());
+        assert_eq!(pset.policies().count(), 1);
+        assert_eq!(pset.templates().count(), 1);
+
+        assert!(pset.remove_static(&PolicyID::from_string("t")).is_none());
+        let removed = pset
+            .remove_static(&PolicyID::from_string("static"))
+            .expect("static policy is in the set");
+        assert_eq!(removed.id(), &PolicyID::from_string("static"));
+        assert_eq!(pset.policies().count(), 0);
+        assert_eq!(pset.all_templates().count(), 1);
+    }
+
     /// This test focuses on `PolicySet::add()`, while other tests mostly use
     /// `PolicySet::add_static()` and `PolicySet::link()`.
     #[test]
@@ -15,7 +15,10 @@
  */
 
 use crate::ast::{BorrowedRestrictedExpr, EntityUID, ExprKind, RestrictedExpr};
-use crate::entities::{ContextJsonParser, JsonDeserializationError, NullContextSchema};
+use crate::entities::{
+    ContextJsonParser, JSONValue, JsonDeserializationError, JsonSerializationError,
+    NullContextSchema,
+};
 use crate::extensions::Extensions;
 use serde::{Deserialize, Serialize};
 use smol_str::SmolStr;
@@ -210,6 +213,14 @@ impl Context {
             .from_json_file(json)
     }
 
+    /// Convert this `Context` into a JSON value suitable for parsing in via
+    /// `from_json_value()`. Extension values are written with the `__extn`
+    /// escape, so the JSON is parse-able even with no schema.
+    pub fn to_json_value(&self) -> Result<serde_json::Value, JsonSerializationError> {
+        let jvalue = JSONValue::from_expr(self.context.as_borrowed())?;
+        Ok(serde_json::to_value(jvalue)?)
+    }
+
     /// Iterate over the (key, value) pairs in the `Context`
     pub fn iter(&self) -> impl Iterator<Item = (&str, BorrowedRestrictedExpr<'_>)> {
         match self.context.as_ref().expr_kind() {
@@ -22,16 +22,23 @@
 
 use crate::ast::*;
 use crate::entities::Entities;
-use crate::evaluator::{EvaluationError, Evaluator};
+use crate::evaluator::{yield_now, EvaluationError, Evaluator, Interrupt, Interrupted};
 use crate::extensions::Extensions;
+use crate::metrics;
 use itertools::Either;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use smol_str::SmolStr;
+use std::collections::{BTreeSet, HashSet};
 use std::iter::once;
+use std::time::Instant;
 
 mod err;
 pub use err::AuthorizationError;
 
+/// Number of policies [`Authorizer::is_authorized_async()`] evaluates between
+/// yields to the async executor
+pub const POLICIES_PER_YIELD: usize = 16;
+
 /// Authorizer
 pub struct Authorizer {
     /// Cedar `Extension`s which will be used during requests to this `Authorizer`
@@ -94,7 +101,72 @@ impl Authorizer {
     /// The language spec and Dafny model give a precise definition of how this is
     /// computed.
     pub fn is_authorized(&self, q: &Request, pset: &PolicySet, entities: &Entities) -> Response {
-        match self.is_authorized_core(q, pset, entities) {
+        let start = metrics::enabled().then(Instant::now);
+        let response = self.concretize(pset, self.is_authorized_core(q, pset, entities));
+        Self::record_metrics(&response, start);
+        response
+    }
+
+    /// Like [`Self::is_authorized()`], but stops when `interrupt` fires, and
+    /// yields to the async executor every [`POLICIES_PER_YIELD`] policies so
+    /// that evaluating a large policy set doesn't starve other tasks.
+    ///
+    /// If evaluation is interrupted, the result is an error rather than a
+    /// response: the policies evaluated so far may not determine the
+    /// decision.
+    pub async fn is_authorized_async(
+        &self,
+        q: &Request,
+        pset: &PolicySet,
+        entities: &Entities,
+        interrupt: &Interrupt,
+    ) -> Result<Response, Interrupted> {
+        let start = metrics::enabled().then(Instant::now);
+        interrupt.check()?;
+        let eval = match Evaluator::new(q, entities, &self.extensions) {
+            Ok(eval) => eval.with_interrupt(interrupt.clone()),
+            Err(e) => {
+                return Ok(Response::new(
+                    Decision::Deny,
+                    HashSet::new(),
+                    vec![AuthorizationError::AttributeEvaluationError(e)],
+                ))
+            }
+        };
+
+        let mut results = EvaluationResults::default();
+        for (n, p) in pset.policies().enumerate() {
+            if n > 0 && n % POLICIES_PER_YIELD == 0 {
+                yield_now().await;
+            }
+            interrupt.check()?;
+            self.evaluate_policy(p, &eval, &mut results);
+        }
+        // an interrupt during the last policy is reported as an evaluation
+        // error of that policy, so check once more
+        interrupt.check()?;
+
+        let response = self.concretize(pset, self.response_from_results(results));
+        Self::record_metrics(&response, start);
+        Ok(response)
+    }
+
+    /// Record `response` to a request whose authorization started at `start`,
+    /// which is only taken if metrics are being reported
+    fn record_metrics(response: &Response, start: Option<Instant>) {
+        metrics::authorization(match response.decision {
+            Decision::Allow => "allow",
+            Decision::Deny => "deny",
+        });
+        if let Some(start) = start {
+            metrics::authorization_duration(start.elapsed());
+        }
+    }
+
+    /// Turn a response which may have residuals into a concrete response,
+    /// treating every residual policy as an error
+    fn concretize(&self, pset: &PolicySet, response: ResponseKind) -> Response {
+        match response {
             ResponseKind::FullyEvaluated(response) => response,
             ResponseKind::Partial(partial) => {
                 // If we get a residual, we have to treat every residual policy as an error, and obey the error semantics.
@@ -177,7 +249,11 @@ impl Authorizer {
         };
 
         let results = self.evaluate_policies(pset, eval);
+        self.response_from_results(results)
+    }
 
+    /// Combine the results of evaluating each policy into a response
+    fn response_from_results(&self, results: EvaluationResults<'_>) -> ResponseKind {
         let errors = results
             .errors
             .into_iter()
@@ -288,55 +364,51 @@ impl Authorizer {
         eval: Evaluator<'_>,
     ) -> EvaluationResults<'a> {
         let mut results = EvaluationResults::default();
-        let mut satisfied_policies = vec![];
-
         for p in pset.policies() {
-            match eval.partial_evaluate(p) {
-                Ok(Either::Left(response)) => {
-                    if response {
-                        satisfied_policies.push(p)
-                    }
+            self.evaluate_policy(p, &eval, &mut results);
+        }
+        results
+    }
+
+    /// Evaluate one policy, adding the outcome to `results`
+    fn evaluate_policy<'a>(
+        &self,
+        p: &'a Policy,
+        eval: &Evaluator<'_>,
+        results: &mut EvaluationResults<'a>,
+    ) {
+        let result = eval.partial_evaluate(p);
+        let satisfied = match result {
+            Ok(Either::Left(satisfied)) => satisfied,
+            Ok(Either::Right(residual)) => {
+                let residual = Policy::from_when_clause(p.effect(), residual, p.id().clone());
+                match p.effect() {
+                    Effect::Permit => results.permit_residuals.push(residual),
+                    Effect::Forbid => results.forbid_residuals.push(residual),
                 }
-                Ok(Either::Right(residual)) => match p.effect() {
-                    Effect::Permit => results.permit_residuals.push(Policy::from_when_clause(
-                        p.effect(),
-                        residual,
-                        p.id().clone(),
-                    )),
-                    Effect::Forbid => results.forbid_residuals.push(Policy::from_when_clause(
-                        p.effect(),
-                        residual,
-                        p.id().clone(),
-                    )),
-                },
-                Err(e) => {
-                    results.errors.push((p.id().clone(), e));
-                    let satisfied = match self.error_handling {
-                        ErrorHandling::Deny => {
-                            results.global_deny_policies.insert(p.id().clone());
-                            true
-                        }
-                        ErrorHandling::Forbid => match p.effect() {
-                            Effect::Permit => false,
-                            Effect::Forbid => true,
-                        },
-                        ErrorHandling::Skip => false,
-                    };
-                    if satisfied {
-                        satisfied_policies.push(p);
+                false
+            }
+            Err(e) => {
+                results.errors.push((p.id().clone(), e));
+                match self.error_handling {
+                    ErrorHandling::Deny => {
+                        results.global_deny_policies.insert(p.id().clone());
+                        true
                     }
+                    ErrorHandling::Forbid => match p.effect() {
+                        Effect::Permit => false,
+                        Effect::Forbid => true,
+                    },
+                    ErrorHandling::Skip => false,
                 }
-            };
+            }
+        };
+        if satisfied {
+            match p.effect() {
+                Effect::Permit => results.satisfied_permits.push(p),
+                Effect::Forbid => results.satisfied_forbids.push(p),
+            }
         }
-
-        let (satisfied_permits, satisfied_forbids) = satisfied_policies
-            .iter()
-            .partition(|p| p.effect() == Effect::Permit);
-
-        results.satisfied_forbids = satisfied_forbids;
-        results.satisfied_permits = satisfied_permits;
-
-        results
     }
 
     /// Private helper function which determines if policy `p1` overrides policy
@@ -397,6 +469,7 @@ impl std::fmt::Debug for Authorizer {
 mod test {
     use std::collections::BTreeMap;
 
+    use crate::evaluator::CancellationToken;
     use crate::parser;
 
     use super::*;
@@ -418,6 +491,78 @@ mod test {
         assert_eq!(ans.decision, Decision::Deny);
     }
 
+    /// Poll `f` to completion, returning its output and how many times it
+    /// was polled
+    fn block_on<F: std::future::Future>(f: F) -> (F::Output, usize) {
+        use std::task::{Context as TaskContext, Poll, Wake, Waker};
+
+        struct NoopWaker;
+        impl Wake for NoopWaker {
+            fn wake(self: std::sync::Arc<Self>) {}
+        }
+        let waker = Waker::from(std::sync::Arc::new(NoopWaker));
+        let mut cx = TaskContext::from_waker(&waker);
+        let mut f = std::pin::pin!(f);
+        let mut polls = 0;
+        loop {
+            polls += 1;
+            if let Poll::Ready(output) = f.as_mut().poll(&mut cx) {
+                return (output, polls);
+            }
+        }
+    }
+
+    #[test]
+    fn async_matches_sync() {
+        let a = Authorizer::new();
+        let q = Request::new(
+            EntityUID::with_eid("p"),
+            EntityUID::with_eid("a"),
+            EntityUID::with_eid("r"),
+            Context::empty(),
+        );
+        let mut pset = PolicySet::new();
+        for n in 0..40 {
+            let src =
+                format!("permit(principal, action, resource) when {{ [1, 2].contains({n}) }};");
+            pset.add_static(parser::parse_policy(Some(n.to_string()), &src).unwrap())
+                .unwrap();
+        }
+        let entities = Entities::new();
+
+        let (ans, polls) = block_on(a.is_authorized_async(&q, &pset, &entities, &Interrupt::new()));
+        assert_eq!(ans.unwrap(), a.is_authorized(&q, &pset, &entities));
+        // yields after 16 and 32 policies
+        assert_eq!(polls, 3);
+    }
+
+    #[test]
+    fn async_interrupted() {
+        let a = Authorizer::new();
+        let q = Request::new(
+            EntityUID::with_eid("p"),
+            EntityUID::with_eid("a"),
+            EntityUID::with_eid("r"),
+            Context::empty(),
+        );
+        let mut pset = PolicySet::new();
+        pset.add_static(
+            parser::parse_policy(Some("0".into()), "permit(principal, action, resource);").unwrap(),
+        )
+        .unwrap();
+        let entities = Entities::new();
+
+        let token = CancellationToken::new();
+        token.cancel();
+        let cancelled = Interrupt::new().with_cancellation(token);
+        let (ans, _) = block_on(a.is_authorized_async(&q, &pset, &entities, &cancelled));
+        assert_eq!(ans, Err(Interrupted::Cancelled));
+
+        let expired = Interrupt::new().with_deadline(std::time::Instant::now());
+        let (ans, _) = block_on(a.is_authorized_async(&q, &pset, &entities, &expired));
+        assert_eq!(ans, Err(Interrupted::DeadlineExceeded));
+    }
+
     /// Simple tests of skip-on-error semantics
     #[test]
     fn skip_on_error_tests() {
@@ -793,6 +938,40 @@ mod test {
         let r = a.is_authorized_core(&q, &pset, &es);
         assert_eq!(r.decision(), Some(Decision::Deny));
     }
+
+    #[test]
+    fn partial_response_missing() {
+        let context = Context::from_expr(RestrictedExpr::record([(
+            "test".into(),
+            RestrictedExpr::new(Expr::unknown("context.test")).unwrap(),
+        )]));
+        let q = Request::new(
+            EntityUID::with_eid("p"),
+            EntityUID::with_eid("a"),
+            EntityUID::with_eid("r"),
+            context,
+        );
+        let a = Authorizer::new();
+        let es = Entities::new();
+        let mut pset = PolicySet::new();
+        pset.add_static(context_pol("1", Effect::Permit))
+            .expect("Policy ID overlap");
+        let src = r#"
+        forbid(principal, action, resource) when { unknown("sanctioned") };
+        "#;
+        pset.add_static(parser::parse_policy(Some("2".into()), src).unwrap())
+            .unwrap();
+
+        match a.is_authorized_core(&q, &pset, &es) {
+            ResponseKind::FullyEvaluated(_) => {
+                panic!("Reached response, should have gotten residual.")
+            }
+            ResponseKind::Partial(p) => assert_eq!(
+                p.missing(),
+                BTreeSet::from(["context.test".into(), "sanctioned".into()])
+            ),
+        }
+    }
 }
 // by default, Coverlay does not track coverage for lines after a line
 // containing #[cfg(test)].
@@ -830,6 +1009,12 @@ impl PartialResponse {
             diagnostics: Diagnostics { reason, errors },
         }
     }
+
+    /// Names of the unknowns which the residual policies depend on. Supplying
+    /// values for all of these is sufficient to reach a decision.
+    pub fn missing(&self) -> BTreeSet<SmolStr> {
+        self.residuals.unknowns()
+    }
 }
 
 /// Diagnostics providing more information on how a `Decision` was reached
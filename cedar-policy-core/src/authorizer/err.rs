@@ -33,4 +33,34 @@ pub enum AuthorizationError {
         /// Specific evaluation error
         error: EvaluationError,
     },
+
+    /// The request reused a nonce or request id, so it was denied regardless
+    /// of the policies.
+    #[error("request was denied as a replay: {0}")]
+    Replayed(String),
+
+    /// The request's chain couldn't be determined, so it was denied
+    /// regardless of the policies.
+    #[error("request was denied because its chain is invalid: {0}")]
+    InvalidChain(String),
+
+    /// The request matched a rule of an active kill switch, so it was denied
+    /// regardless of the policies.
+    #[error("request was denied by the kill switch: {0}")]
+    Halted(String),
+
+    /// The request was allowed more often than its rate limit allows, so it
+    /// was denied regardless of the policies.
+    #[error("request was denied by a rate limit: {0}")]
+    RateLimited(String),
+
+    /// An address in the request couldn't be screened, so it was denied
+    /// regardless of the policies.
+    #[error("request was denied because screening failed: {0}")]
+    ScreeningFailed(String),
+
+    /// Adding an organization chart to the request's entities made their
+    /// hierarchy invalid, so it was denied regardless of the policies.
+    #[error("request was denied because the entity hierarchy is invalid: {0}")]
+    InvalidHierarchy(String),
 }
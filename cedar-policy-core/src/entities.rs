@@ -23,6 +23,7 @@ use crate::transitive_closure::{compute_tc, enforce_tc_and_dag};
 use std::borrow::Cow;
 use std::collections::{hash_map, HashMap};
 use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
@@ -63,6 +64,9 @@ pub struct Entities {
     #[serde(skip_deserializing)]
     #[serde(skip_serializing)]
     mode: Mode,
+
+    #[serde(skip)]
+    snapshot: SnapshotId,
 }
 
 impl Entities {
@@ -72,6 +76,7 @@ impl Entities {
             entities: HashMap::new(),
             mode: Mode::default(),
             evaluated_entities: None,
+            snapshot: SnapshotId::default(),
         }
     }
 
@@ -84,6 +89,7 @@ impl Entities {
             entities: self.entities,
             mode: Mode::Partial,
             evaluated_entities: self.evaluated_entities,
+            snapshot: SnapshotId::default(),
         }
     }
 
@@ -99,6 +105,14 @@ impl Entities {
         }
     }
 
+    /// An id of the contents of this `Entities`, for caching results computed
+    /// from them. Every `Entities` gets a new id when it is created, and
+    /// clones share it. Two `Entities` with the same id hold the same
+    /// entities, although equal ones may have different ids.
+    pub fn snapshot_id(&self) -> u64 {
+        self.snapshot.0
+    }
+
     /// Iterate over the `Entity`s in the `Entities`
     pub fn iter(&self) -> impl Iterator<Item = &Entity> {
         self.entities.values()
@@ -131,6 +145,7 @@ impl Entities {
             TCComputation::ComputeNow => compute_tc(&mut self.entities, true).map_err(Box::new)?,
         };
         self.evaluated_entities = None;
+        self.snapshot = SnapshotId::default();
         Ok(self)
     }
 
@@ -156,6 +171,7 @@ impl Entities {
             entities: entity_map,
             mode: Mode::default(),
             evaluated_entities: None,
+            snapshot: SnapshotId::default(),
         })
     }
 
@@ -269,6 +285,7 @@ impl Entities {
                 entities: self.entities,
                 evaluated_entities: Some(r),
                 mode: self.mode,
+                snapshot: self.snapshot,
             })
         }
     }
@@ -281,6 +298,7 @@ impl Entities {
     /// If the entity values have already been computed via [`Self::evaluate`], then that will be re-used.
     /// Otherwise, the attributes will be evaluated.
     pub fn get_attr_values(&self) -> std::result::Result<EntityAttrValues<'_>, EvaluationError> {
+        crate::metrics::entity_cache_lookup(self.evaluated_entities.is_some());
         let map = match &self.evaluated_entities {
             Some(cached) => Cow::Borrowed(cached),
             None => Cow::Owned(self.compute_entities_values()?),
@@ -427,6 +445,27 @@ where
     }
 }
 
+/// The id of an [`Entities`], see [`Entities::snapshot_id()`]. It is not
+/// part of the contents, so it is ignored by equality.
+#[derive(Debug, Clone, Copy)]
+struct SnapshotId(u64);
+
+impl Default for SnapshotId {
+    /// A new id, different from every other
+    fn default() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl PartialEq for SnapshotId {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for SnapshotId {}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Mode {
     Concrete,
@@ -1194,6 +1233,52 @@ mod json_parsing_tests {
         ));
     }
 
+    /// Test that `u256` values written with the `__extn` escape are written back
+    /// in the same form, and evaluate to the same value after roundtripping
+    #[test]
+    #[cfg(feature = "u256")]
+    fn u256_json_roundtripping() {
+        let max = "115792089237316195423570985008687907853269984665640564039457584007913129639935";
+        let json = serde_json::json!(
+            [
+            {
+                "uid": { "type": "Wallet", "id": "alice" },
+                "attrs": {
+                    "balance": { "__extn": { "fn": "u256", "arg": max } },
+                    "history": [{ "__extn": { "fn": "u256", "arg": "0" } }]
+                },
+                "parents": []
+            }
+            ]
+        );
+        let eparser: EntityJsonParser<'_> =
+            EntityJsonParser::new(None, Extensions::all_available(), TCComputation::ComputeNow);
+        let entities = eparser
+            .from_json_value(json.clone())
+            .expect("should parse without errors");
+        assert_eq!(
+            entities
+                .to_json_value()
+                .expect("should serialize without errors"),
+            json
+        );
+        let roundtripped = roundtrip(&entities).expect("should roundtrip without errors");
+        assert_eq!(entities, roundtripped);
+
+        let balance = |entities: &Entities| {
+            let attr = entities
+                .iter()
+                .next()
+                .and_then(|alice| alice.get("balance"))
+                .expect("alice should have a balance");
+            RestrictedEvaluator::new(&Extensions::all_available())
+                .interpret(attr.as_borrowed())
+                .expect("balance should evaluate")
+        };
+        assert_eq!(balance(&roundtripped), balance(&entities));
+        assert_eq!(balance(&roundtripped).to_string(), max);
+    }
+
     /// test that an Action having a non-Action parent is an error
     #[test]
     fn bad_action_parent() {
@@ -1221,6 +1306,49 @@ mod json_parsing_tests {
             err
         );
     }
+
+    /// test that tags are parsed and survive a JSON roundtrip
+    #[test]
+    fn tags_roundtrip() {
+        let json = serde_json::json!(
+            [
+                {
+                    "uid": { "type": "User", "id": "alice" },
+                    "attrs": {},
+                    "parents": [],
+                    "tags": {
+                        "tier": "gold",
+                        "limit": 100
+                    }
+                },
+                {
+                    "uid": { "type": "User", "id": "bob" },
+                    "attrs": {},
+                    "parents": []
+                }
+            ]
+        );
+        let eparser: EntityJsonParser<'_> =
+            EntityJsonParser::new(None, Extensions::all_available(), TCComputation::ComputeNow);
+        let es = eparser.from_json_value(json).expect("should parse");
+        let alice = es
+            .entity(&r#"User::"alice""#.parse().unwrap())
+            .expect("alice should exist");
+        assert_eq!(alice.get_tag("tier"), Some(&RestrictedExpr::val("gold")));
+        assert_eq!(alice.get_tag("limit"), Some(&RestrictedExpr::val(100)));
+        assert_eq!(alice.get_tag("other"), None);
+        let bob = es
+            .entity(&r#"User::"bob""#.parse().unwrap())
+            .expect("bob should exist");
+        assert_eq!(bob.tags().count(), 0);
+
+        let roundtripped = roundtrip(&es).expect("should roundtrip without errors");
+        let alice = roundtripped
+            .entity(&r#"User::"alice""#.parse().unwrap())
+            .expect("alice should exist");
+        assert_eq!(alice.get_tag("tier"), Some(&RestrictedExpr::val("gold")));
+        assert_eq!(alice.tags().count(), 2);
+    }
 }
 
 #[cfg(test)]
@@ -1293,6 +1421,25 @@ mod entities_tests {
         Entities::from_entities(vec![e1, e2, e3], TCComputation::EnforceAlreadyComputed)
             .expect("Should have succeeded");
     }
+
+    #[test]
+    fn snapshot_ids() {
+        let (e0, e1, _, _) = test_entities();
+        let es = Entities::from_entities(vec![e0.clone()], TCComputation::ComputeNow)
+            .expect("Failed to construct entities");
+        assert_eq!(es.clone().snapshot_id(), es.snapshot_id());
+        // equal entities may have different ids, which equality ignores
+        let rebuilt = Entities::from_entities(vec![e0], TCComputation::ComputeNow)
+            .expect("Failed to construct entities");
+        assert_ne!(rebuilt.snapshot_id(), es.snapshot_id());
+        assert_eq!(rebuilt, es);
+        // adding entities gives a new id
+        let id = es.snapshot_id();
+        let added = es
+            .add_entities(vec![e1], TCComputation::ComputeNow)
+            .expect("Failed to add entities");
+        assert_ne!(added.snapshot_id(), id);
+    }
 }
 
 #[cfg(test)]
@@ -1430,6 +1577,10 @@ mod schema_based_parsing_tests {
         fn allowed_parent_types(&self) -> Arc<HashSet<EntityType>> {
             Arc::new(HashSet::new())
         }
+
+        fn tag_type(&self) -> Option<SchemaType> {
+            Some(SchemaType::String)
+        }
     }
 
     #[cfg(feature = "ipaddr")]
@@ -2180,6 +2331,84 @@ mod schema_based_parsing_tests {
         );
     }
 
+    #[cfg(feature = "ipaddr")]
+    /// Test that tag values are checked against the schema's tag type
+    #[test]
+    fn tags_wrong_type() {
+        let entitiesjson = json!(
+            [
+                {
+                    "uid": { "type": "Employee", "id": "12UA45" },
+                    "attrs": {
+                        "isFullTime": true,
+                        "numDirectReports": 3,
+                        "department": "Sales",
+                        "manager": { "type": "Employee", "id": "34FB87" },
+                        "hr_contacts": [],
+                        "json_blob": {
+                            "inner1": false,
+                            "inner2": "-*/",
+                            "inner3": { "innerinner": { "type": "Employee", "id": "09AE76" }},
+                        },
+                        "home_ip": "222.222.222.101",
+                        "work_ip": { "fn": "ip", "arg": "2.2.2.0/24" },
+                        "trust_score": "5.7"
+                    },
+                    "parents": [],
+                    "tags": {
+                        "team": "payments",
+                        "level": 3
+                    }
+                }
+            ]
+        );
+        let eparser = EntityJsonParser::new(
+            Some(MockSchema),
+            Extensions::all_available(),
+            TCComputation::ComputeNow,
+        );
+        let err = eparser
+            .from_json_value(entitiesjson)
+            .expect_err("should fail due to the type of the `level` tag");
+        assert!(
+            err.to_string()
+                .contains(r#"in tag `level` on `Employee::"12UA45"`"#),
+            "actual error message was {}",
+            err
+        );
+    }
+
+    /// Test that tags on an action are rejected, since the schema never
+    /// declares them
+    #[test]
+    fn tags_on_action() {
+        let entitiesjson = json!(
+            [
+                {
+                    "uid": { "type": "Action", "id": "readOnly" },
+                    "attrs": {},
+                    "parents": [],
+                    "tags": { "team": "payments" }
+                }
+            ]
+        );
+        let eparser = EntityJsonParser::new(
+            Some(MockSchema),
+            Extensions::all_available(),
+            TCComputation::ComputeNow,
+        );
+        let err = eparser
+            .from_json_value(entitiesjson)
+            .expect_err("should fail due to tags on an action");
+        assert!(
+            err.to_string().contains(
+                r#"definition of action `Action::"readOnly"` does not match its schema declaration"#
+            ),
+            "actual error message was {}",
+            err
+        );
+    }
+
     /// Test that involves an entity type not declared in the schema
     #[test]
     fn undeclared_entity_type() {
@@ -15,7 +15,7 @@
  */
 
 use super::{JsonDeserializationError, JsonDeserializationErrorContext, SchemaType, ValueParser};
-use crate::ast::{Context, ExprKind};
+use crate::ast::{Context, ExprKind, RestrictedExpr};
 use crate::extensions::Extensions;
 use std::collections::HashMap;
 
@@ -23,6 +23,12 @@ use std::collections::HashMap;
 pub trait ContextSchema {
     /// `SchemaType` (expected to be a `Record`) for the context.
     fn context_type(&self) -> SchemaType;
+
+    /// Whether the context may have attributes which `context_type()` doesn't
+    /// declare. Those attributes are parsed as if there was no schema.
+    fn open_attributes(&self) -> bool {
+        false
+    }
 }
 
 /// Simple type that implements `ContextSchema` by expecting an empty context
@@ -77,10 +83,39 @@ impl<'e, 's, S: ContextSchema> ContextJsonParser<'e, 's, S> {
     ) -> Result<Context, JsonDeserializationError> {
         let vparser = ValueParser::new(self.extensions.clone());
         let expected_ty = self.schema.map(|s| s.context_type());
+        let (json, undeclared) = match (&expected_ty, json) {
+            (Some(SchemaType::Record { attrs }), serde_json::Value::Object(record)) if matches!(self.schema, Some(s) if s.open_attributes()) =>
+            {
+                let (declared, undeclared): (Vec<_>, Vec<_>) = record
+                    .into_iter()
+                    .partition(|(k, _)| attrs.contains_key(k.as_str()));
+                (
+                    serde_json::Value::Object(declared.into_iter().collect()),
+                    undeclared,
+                )
+            }
+            (_, json) => (json, Vec::new()),
+        };
         let rexpr = vparser.val_into_rexpr(json, expected_ty.as_ref(), || {
             JsonDeserializationErrorContext::Context
         })?;
         match rexpr.expr_kind() {
+            ExprKind::Record { pairs } if !undeclared.is_empty() => {
+                let undeclared = undeclared
+                    .into_iter()
+                    .map(|(k, v)| {
+                        let v = vparser
+                            .val_into_rexpr(v, None, || JsonDeserializationErrorContext::Context)?;
+                        Ok((k.into(), v))
+                    })
+                    .collect::<Result<Vec<_>, JsonDeserializationError>>()?;
+                Ok(Context::from_expr(RestrictedExpr::record(
+                    pairs
+                        .iter()
+                        .map(|(k, v)| (k.clone(), RestrictedExpr::new_unchecked(v.clone())))
+                        .chain(undeclared),
+                )))
+            }
             ExprKind::Record { .. } => Ok(Context::from_expr(rexpr)),
             _ => Err(JsonDeserializationError::ExpectedContextToBeRecord {
                 got: Box::new(rexpr),
@@ -24,7 +24,7 @@ use crate::entities::{Entities, EntitiesError, TCComputation};
 use crate::extensions::Extensions;
 use serde::{Deserialize, Serialize};
 use smol_str::SmolStr;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
 /// Serde JSON format for a single entity
@@ -39,6 +39,11 @@ pub struct EntityJSON {
     attrs: HashMap<SmolStr, serde_json::Value>,
     /// Parents of the entity, specified in any form accepted by `EntityUidJSON`
     parents: Vec<EntityUidJSON>,
+    /// Tags of the entity, whose values are parsed the same way as `attrs`.
+    /// Optional; omitted when the entity has no tags.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    tags: HashMap<SmolStr, serde_json::Value>,
 }
 
 /// Struct used to parse entities from JSON.
@@ -326,6 +331,51 @@ impl<'e, S: Schema> EntityJsonParser<'e, S> {
                 }
             })
             .collect::<Result<_, JsonDeserializationError>>()?;
+        let tags: HashMap<SmolStr, RestrictedExpr> = ejson
+            .tags
+            .into_iter()
+            .map(|(k, v)| {
+                let ctx = || JsonDeserializationErrorContext::EntityTag {
+                    uid: uid.clone(),
+                    tag: k.clone(),
+                };
+                match &entity_schema_info {
+                    EntitySchemaInfo::NoSchema => {
+                        Ok((k.clone(), vparser.val_into_rexpr(v, None, ctx)?))
+                    }
+                    EntitySchemaInfo::NonAction(desc) => {
+                        // `None` indicates that entities of this type have no
+                        // tags -- see docs on the `tag_type()` trait method
+                        let expected_ty = match desc.tag_type() {
+                            None => {
+                                return Err(JsonDeserializationError::UnexpectedEntityTag {
+                                    uid: uid.clone(),
+                                    tag: k,
+                                })
+                            }
+                            Some(expected_ty) => expected_ty,
+                        };
+                        let rexpr = vparser.val_into_rexpr(v, Some(&expected_ty), ctx)?;
+                        let actual_ty = vparser.type_of_rexpr(rexpr.as_borrowed(), ctx)?;
+                        if actual_ty.is_consistent_with(&expected_ty) {
+                            Ok((k, rexpr))
+                        } else {
+                            Err(JsonDeserializationError::TypeMismatch {
+                                ctx: Box::new(ctx()),
+                                expected: Box::new(expected_ty),
+                                actual: Box::new(actual_ty),
+                            })
+                        }
+                    }
+                    // the schema never declares tags on actions
+                    EntitySchemaInfo::Action(_) => {
+                        Err(JsonDeserializationError::ActionDeclarationMismatch {
+                            uid: uid.clone(),
+                        })
+                    }
+                }
+            })
+            .collect::<Result<_, JsonDeserializationError>>()?;
         let is_parent_allowed = |parent_euid: &EntityUID| {
             match &entity_schema_info {
                 EntitySchemaInfo::NoSchema => {
@@ -368,7 +418,7 @@ impl<'e, S: Schema> EntityJsonParser<'e, S> {
                 }
             }
         };
-        let parents = ejson
+        let mut parents: HashSet<EntityUID> = ejson
             .parents
             .into_iter()
             .map(|parent| {
@@ -384,8 +434,16 @@ impl<'e, S: Schema> EntityJsonParser<'e, S> {
             })
             .collect::<Result<_, JsonDeserializationError>>()?;
         match &entity_schema_info {
-            EntitySchemaInfo::NoSchema => {}     // no checks to do
-            EntitySchemaInfo::NonAction(_) => {} // no checks to do
+            EntitySchemaInfo::NoSchema => {} // no checks to do
+            EntitySchemaInfo::NonAction(desc) => {
+                // materialize the parents the schema derives from attributes
+                parents.extend(
+                    desc.derived_parents()
+                        .iter()
+                        .filter(|derived| derived.applies_to(&attrs))
+                        .map(|derived| derived.parent.clone()),
+                );
+            }
             EntitySchemaInfo::Action(action) => {
                 // check that the json entity and the schema declaration
                 // fully agree on parents
@@ -394,7 +452,7 @@ impl<'e, S: Schema> EntityJsonParser<'e, S> {
                 }
             }
         }
-        Ok(Entity::new(uid, attrs, parents))
+        Ok(Entity::new_with_tags(uid, attrs, parents, tags))
     }
 }
 
@@ -414,6 +472,10 @@ impl EntityJSON {
                 .ancestors()
                 .map(|euid| EntityUidJSON::ImplicitEntityEscape(TypeAndId::from(euid.clone())))
                 .collect(),
+            tags: entity
+                .tags()
+                .map(|(k, expr)| Ok((k.into(), serde_json::to_value(JSONValue::from_expr(expr)?)?)))
+                .collect::<Result<_, JsonSerializationError>>()?,
         })
     }
 }
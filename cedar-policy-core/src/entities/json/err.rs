@@ -152,6 +152,15 @@ pub enum JsonDeserializationError {
         /// Name of the attribute that was unexpected
         attr: SmolStr,
     },
+    /// During schema-based parsing, encountered this tag on this entity, but
+    /// entities of this type shouldn't have tags
+    #[error("tag `{tag}` on `{uid}` should not exist according to the schema")]
+    UnexpectedEntityTag {
+        /// Entity that had the unexpected tag
+        uid: EntityUID,
+        /// Name of the tag that was unexpected
+        tag: SmolStr,
+    },
     /// During schema-based parsing, encountered this attribute on a record, but
     /// that attribute shouldn't exist on that record
     #[error("{ctx}, record attribute `{record_attr}` should not exist according to the schema")]
@@ -264,6 +273,13 @@ pub enum JsonDeserializationErrorContext {
         /// Attribute where the error occurred
         attr: SmolStr,
     },
+    /// The error occurred while deserializing the tag `tag` of an entity.
+    EntityTag {
+        /// Entity where the error occurred
+        uid: EntityUID,
+        /// Tag where the error occurred
+        tag: SmolStr,
+    },
     /// The error occurred while deserializing the `parents` field of an entity.
     EntityParents {
         /// Entity where the error occurred
@@ -279,6 +295,7 @@ impl std::fmt::Display for JsonDeserializationErrorContext {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::EntityAttribute { uid, attr } => write!(f, "in attribute `{attr}` on `{uid}`"),
+            Self::EntityTag { uid, tag } => write!(f, "in tag `{tag}` on `{uid}`"),
             Self::EntityParents { uid } => write!(f, "in parents field of `{uid}`"),
             Self::EntityUid => write!(f, "in uid field of <unknown entity>"),
             Self::Context => write!(f, "while parsing context"),
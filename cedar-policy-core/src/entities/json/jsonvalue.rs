@@ -19,7 +19,7 @@ use super::{
     JsonSerializationError, SchemaType,
 };
 use crate::ast::{
-    BorrowedRestrictedExpr, Eid, EntityUID, Expr, ExprKind, Literal, Name, RestrictedExpr,
+    BorrowedRestrictedExpr, Eid, EntityUID, Expr, ExprKind, Literal, Name, RestrictedExpr, Value,
 };
 use crate::entities::EscapeKind;
 use crate::extensions::{ExtensionFunctionLookupError, Extensions};
@@ -336,7 +336,10 @@ impl<'e> ValueParser<'e> {
             // this means is that we parse the contents as `ExtnValueJSON`, and then
             // convert that into an extension-function-call `RestrictedExpr`
             Some(SchemaType::Extension { ref name, .. }) => {
-                let extjson: ExtnValueJSON = serde_json::from_value(val)?;
+                let extjson: ExtnValueJSON = match val {
+                    serde_json::Value::Number(n) => self.number_into_extn_value_json(n, name)?,
+                    val => serde_json::from_value(val)?,
+                };
                 self.extn_value_json_into_rexpr(extjson, name.clone(), ctx)
             }
             // The expected type is a set type. No special parsing rules apply, but
@@ -414,6 +417,33 @@ impl<'e> ValueParser<'e> {
         }
     }
 
+    /// internal function that interprets a JSON number where a value of the
+    /// extension type `expected_typename` is expected.
+    ///
+    /// Numbers which the type's `String` constructor accepts in their decimal
+    /// form are passed to that constructor. This lets integers be given for
+    /// `u256` values, including ones which don't fit in a `Long`. Any other
+    /// number is parsed as usual.
+    fn number_into_extn_value_json(
+        &self,
+        n: serde_json::Number,
+        expected_typename: &Name,
+    ) -> Result<ExtnValueJSON, JsonDeserializationError> {
+        let string_ctor = self.extensions.lookup_single_arg_constructor(
+            &SchemaType::Extension {
+                name: expected_typename.clone(),
+            },
+            &SchemaType::String,
+        )?;
+        let arg = n.to_string();
+        match string_ctor {
+            Some(func) if func.call(&[Value::from(arg.as_str())]).is_ok() => Ok(
+                ExtnValueJSON::ImplicitConstructor(JSONValue::String(arg.into())),
+            ),
+            _ => Ok(serde_json::from_value(serde_json::Value::Number(n))?),
+        }
+    }
+
     /// internal function that converts an `ExtnValueJSON` into a
     /// `RestrictedExpr`, which will be an extension constructor call.
     ///
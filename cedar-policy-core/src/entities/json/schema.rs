@@ -1,6 +1,11 @@
 use super::SchemaType;
-use crate::ast::{Entity, EntityType, EntityUID, Id, Name};
+use crate::ast::{
+    BorrowedRestrictedExpr, Entity, EntityType, EntityUID, ExprKind, Id, Literal, Name,
+    RestrictedExpr,
+};
+use serde::{Deserialize, Serialize};
 use smol_str::SmolStr;
+use std::cmp::Ordering;
 use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
@@ -90,6 +95,118 @@ pub trait EntityTypeDescription {
 
     /// Get the entity types which are allowed to be parents of this entity type.
     fn allowed_parent_types(&self) -> Arc<HashSet<EntityType>>;
+
+    /// Do entities of this type have tags, and if so, what type are the tag
+    /// values?
+    ///
+    /// Returning `None` indicates that no tags should exist.
+    fn tag_type(&self) -> Option<SchemaType> {
+        None
+    }
+
+    /// Parents which entities of this type have whenever their attributes
+    /// satisfy a predicate. These are added to the entity's parents when it
+    /// is parsed, in addition to any parents listed explicitly.
+    fn derived_parents(&self) -> &[DerivedParent] {
+        &[]
+    }
+}
+
+/// A parent which an entity has whenever one of its attributes satisfies a
+/// comparison against a literal, e.g., every `Wallet` with `kycLevel >= 2` is
+/// in `Group::"kyc2"`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DerivedParent {
+    /// The parent entity
+    pub parent: EntityUID,
+    /// The attribute which is compared
+    pub attr: SmolStr,
+    /// How the attribute is compared against `value`
+    pub op: DerivedParentOp,
+    /// The literal the attribute is compared against
+    pub value: Literal,
+}
+
+impl DerivedParent {
+    /// Does an entity whose attributes are `attrs` have this parent?
+    ///
+    /// Only literal attribute values are compared. The orderings only hold
+    /// between two `Long`s, so a missing attribute, or one with a value of some
+    /// other type, never satisfies them.
+    pub fn applies_to(&self, attrs: &HashMap<SmolStr, RestrictedExpr>) -> bool {
+        match attrs.get(&self.attr) {
+            Some(attr_value) => self.is_satisfied_by(attr_value.as_borrowed()),
+            None => false,
+        }
+    }
+
+    fn is_satisfied_by(&self, attr_value: BorrowedRestrictedExpr<'_>) -> bool {
+        let ExprKind::Lit(lit) = attr_value.expr_kind() else {
+            return false;
+        };
+        match (self.op, lit, &self.value) {
+            (DerivedParentOp::Eq, _, _) => lit == &self.value,
+            (DerivedParentOp::NotEq, _, _) => lit != &self.value,
+            (op, Literal::Long(actual), Literal::Long(expected)) => {
+                op.holds_for(actual.cmp(expected))
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Comparison used by a `DerivedParent`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DerivedParentOp {
+    /// `==`
+    #[serde(rename = "==")]
+    Eq,
+    /// `!=`
+    #[serde(rename = "!=")]
+    NotEq,
+    /// `<`
+    #[serde(rename = "<")]
+    Less,
+    /// `<=`
+    #[serde(rename = "<=")]
+    LessEq,
+    /// `>`
+    #[serde(rename = ">")]
+    Greater,
+    /// `>=`
+    #[serde(rename = ">=")]
+    GreaterEq,
+}
+
+impl DerivedParentOp {
+    /// Is this one of the orderings, which only apply to `Long`s?
+    pub fn is_ordering(self) -> bool {
+        !matches!(self, Self::Eq | Self::NotEq)
+    }
+
+    fn holds_for(self, ordering: Ordering) -> bool {
+        match self {
+            Self::Eq => ordering == Ordering::Equal,
+            Self::NotEq => ordering != Ordering::Equal,
+            Self::Less => ordering == Ordering::Less,
+            Self::LessEq => ordering != Ordering::Greater,
+            Self::Greater => ordering == Ordering::Greater,
+            Self::GreaterEq => ordering != Ordering::Less,
+        }
+    }
+}
+
+impl std::fmt::Display for DerivedParentOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Eq => write!(f, "=="),
+            Self::NotEq => write!(f, "!="),
+            Self::Less => write!(f, "<"),
+            Self::LessEq => write!(f, "<="),
+            Self::Greater => write!(f, ">"),
+            Self::GreaterEq => write!(f, ">="),
+        }
+    }
 }
 
 /// Simple type that implements `EntityTypeDescription` by expecting no
@@ -112,16 +112,24 @@ impl TryFrom<cst::Policy> for Policy {
         if let (Some(effect), Some(principal), Some(action), Some(resource), true) =
             (effect, principal, action, resource, errs.is_empty())
         {
-            let conditions = policy
-                .conds
+            let scope_conditions = policy
+                .extract_scope_conditions()
                 .into_iter()
-                .map(|node| {
+                .map(|node| match node.as_inner() {
+                    Some(e) => e.clone().try_into().map(Clause::When),
+                    None => Err(ParseError::ToAST(ToASTError::MissingNodeData).into()),
+                })
+                .collect::<Result<Vec<_>, ParseErrors>>()?;
+            let conditions = scope_conditions
+                .into_iter()
+                .map(Ok)
+                .chain(policy.conds.into_iter().map(|node| {
                     let (cond, _) = node.into_inner();
                     let cond = cond.ok_or_else(|| {
                         ParseErrors(vec![ParseError::ToAST(ToASTError::EmptyClause(None))])
                     })?;
                     cond.try_into()
-                })
+                }))
                 .collect::<Result<Vec<_>, ParseErrors>>()?;
             let annotations = policy
                 .annotations
@@ -1473,6 +1481,89 @@ mod test {
         assert_eq!(circular_roundtrip(est.clone()), est);
     }
 
+    #[test]
+    fn is_scope_and_expr() {
+        let policy = r#"
+            permit(
+                principal is Wallet when principal.kycLevel <= 2 && context.source is Device,
+                action,
+                resource is Vault in Org::"acme"
+            );
+        "#;
+        let cst = parser::text_to_cst::parse_policy(policy)
+            .unwrap()
+            .node
+            .unwrap();
+        let est: Policy = cst.try_into().unwrap();
+        let expected_json = json!(
+            {
+                "effect": "permit",
+                "principal": {
+                    "op": "is",
+                    "entity_type": "Wallet",
+                },
+                "action": {
+                    "op": "All",
+                },
+                "resource": {
+                    "op": "is",
+                    "entity_type": "Vault",
+                    "in": { "entity": { "type": "Org", "id": "acme" } },
+                },
+                "conditions": [
+                    {
+                        "kind": "when",
+                        "body": {
+                            "&&": {
+                                "left": {
+                                    "<=": {
+                                        "left": {
+                                            ".": {
+                                                "left": {
+                                                    "Var": "principal"
+                                                },
+                                                "attr": "kycLevel"
+                                            }
+                                        },
+                                        "right": {
+                                            "Value": 2
+                                        }
+                                    }
+                                },
+                                "right": {
+                                    "is": {
+                                        "left": {
+                                            ".": {
+                                                "left": {
+                                                    "Var": "context"
+                                                },
+                                                "attr": "source"
+                                            }
+                                        },
+                                        "entity_type": "Device"
+                                    }
+                                }
+                            }
+                        }
+                    }
+                ]
+            }
+        );
+        assert_eq!(
+            serde_json::to_value(&est).unwrap(),
+            expected_json,
+            "\nExpected:\n{}\n\nActual:\n{}\n\n",
+            serde_json::to_string_pretty(&expected_json).unwrap(),
+            serde_json::to_string_pretty(&est).unwrap()
+        );
+        let old_est = est.clone();
+        let est = est_roundtrip(est);
+        assert_eq!(&old_est, &est);
+
+        assert_eq!(ast_roundtrip(est.clone()), est);
+        assert_eq!(circular_roundtrip(est.clone()), est);
+    }
+
     #[test]
     fn nested_records() {
         let policy = r#"
@@ -2025,6 +2116,64 @@ mod test {
         assert_eq!(circular_roundtrip(est.clone()), est);
     }
 
+    #[test]
+    fn glob() {
+        let policy = r#"
+            permit(principal, action, resource)
+            when { resource.address glob "0x[0-9a-f]*" };
+        "#;
+        let cst = parser::text_to_cst::parse_policy(policy)
+            .unwrap()
+            .node
+            .unwrap();
+        let est: Policy = cst.try_into().unwrap();
+        let expected_json = json!(
+            {
+                "effect": "permit",
+                "principal": {
+                    "op": "All",
+                },
+                "action": {
+                    "op": "All",
+                },
+                "resource": {
+                    "op": "All",
+                },
+                "conditions": [
+                    {
+                        "kind": "when",
+                        "body": {
+                            "glob": {
+                                "left": {
+                                    ".": {
+                                        "left": {
+                                            "Var": "resource"
+                                        },
+                                        "attr": "address"
+                                    }
+                                },
+                                "pattern": "0x[0-9a-f]*"
+                            }
+                        }
+                    }
+                ]
+            }
+        );
+        assert_eq!(
+            serde_json::to_value(&est).unwrap(),
+            expected_json,
+            "\nExpected:\n{}\n\nActual:\n{}\n\n",
+            serde_json::to_string_pretty(&expected_json).unwrap(),
+            serde_json::to_string_pretty(&est).unwrap()
+        );
+        let old_est = est.clone();
+        let est = est_roundtrip(est);
+        assert_eq!(&old_est, &est);
+
+        assert_eq!(ast_roundtrip(est.clone()), est);
+        assert_eq!(circular_roundtrip(est.clone()), est);
+    }
+
     #[test]
     fn decimal() {
         let policy = r#"
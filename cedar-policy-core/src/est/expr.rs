@@ -190,6 +190,22 @@ pub enum ExprNoExt {
         /// Right-hand argument (inside the `()`)
         right: Arc<Expr>,
     },
+    /// `getTag()`
+    #[serde(rename = "getTag")]
+    GetTag {
+        /// Left-hand argument (receiver)
+        left: Arc<Expr>,
+        /// Right-hand argument (inside the `()`)
+        right: Arc<Expr>,
+    },
+    /// `hasTag()`
+    #[serde(rename = "hasTag")]
+    HasTag {
+        /// Left-hand argument (receiver)
+        left: Arc<Expr>,
+        /// Right-hand argument (inside the `()`)
+        right: Arc<Expr>,
+    },
     /// Get-attribute
     #[serde(rename = ".")]
     GetAttr {
@@ -214,6 +230,22 @@ pub enum ExprNoExt {
         /// Pattern
         pattern: SmolStr,
     },
+    /// `glob`
+    #[serde(rename = "glob")]
+    Glob {
+        /// Left-hand argument
+        left: Arc<Expr>,
+        /// Pattern, which may have character classes
+        pattern: SmolStr,
+    },
+    /// `is`
+    #[serde(rename = "is")]
+    Is {
+        /// Left-hand argument
+        left: Arc<Expr>,
+        /// Entity type name
+        entity_type: SmolStr,
+    },
     /// Ternary
     #[serde(rename = "if-then-else")]
     If {
@@ -403,6 +435,22 @@ impl Expr {
         })
     }
 
+    /// `left.getTag(right)`
+    pub fn get_tag(left: Arc<Expr>, right: Expr) -> Self {
+        Expr::ExprNoExt(ExprNoExt::GetTag {
+            left,
+            right: Arc::new(right),
+        })
+    }
+
+    /// `left.hasTag(right)`
+    pub fn has_tag(left: Arc<Expr>, right: Expr) -> Self {
+        Expr::ExprNoExt(ExprNoExt::HasTag {
+            left,
+            right: Arc::new(right),
+        })
+    }
+
     /// `left.attr`
     pub fn get_attr(left: Expr, attr: SmolStr) -> Self {
         Expr::ExprNoExt(ExprNoExt::GetAttr {
@@ -427,6 +475,22 @@ impl Expr {
         })
     }
 
+    /// `left glob pattern`
+    pub fn glob(left: Expr, pattern: SmolStr) -> Self {
+        Expr::ExprNoExt(ExprNoExt::Glob {
+            left: Arc::new(left),
+            pattern,
+        })
+    }
+
+    /// `left is entity_type`
+    pub fn is_entity_type(left: Expr, entity_type: SmolStr) -> Self {
+        Expr::ExprNoExt(ExprNoExt::Is {
+            left: Arc::new(left),
+            entity_type,
+        })
+    }
+
     /// `if cond_expr then then_expr else else_expr`
     pub fn ite(cond_expr: Expr, then_expr: Expr, else_expr: Expr) -> Self {
         Expr::ExprNoExt(ExprNoExt::If {
@@ -436,6 +500,16 @@ impl Expr {
         })
     }
 
+    /// `getOr(left, attr, default)`, which is sugar for
+    /// `if left has attr then left.attr else default`
+    pub fn get_or(left: Expr, attr: SmolStr, default: Expr) -> Self {
+        Expr::ite(
+            Expr::has_attr(left.clone(), attr.clone()),
+            Expr::get_attr(left, attr),
+            default,
+        )
+    }
+
     /// e.g. [1+2, !(context has department)]
     pub fn set(elements: Vec<Expr>) -> Self {
         Expr::ExprNoExt(ExprNoExt::Set(elements))
@@ -554,6 +628,14 @@ impl TryFrom<Expr> for ast::Expr {
                 (*left).clone().try_into()?,
                 (*right).clone().try_into()?,
             )),
+            Expr::ExprNoExt(ExprNoExt::GetTag { left, right }) => Ok(ast::Expr::get_tag(
+                (*left).clone().try_into()?,
+                (*right).clone().try_into()?,
+            )),
+            Expr::ExprNoExt(ExprNoExt::HasTag { left, right }) => Ok(ast::Expr::has_tag(
+                (*left).clone().try_into()?,
+                (*right).clone().try_into()?,
+            )),
             Expr::ExprNoExt(ExprNoExt::GetAttr { left, attr }) => {
                 Ok(ast::Expr::get_attr((*left).clone().try_into()?, attr))
             }
@@ -566,6 +648,23 @@ impl TryFrom<Expr> for ast::Expr {
                     Err(errs) => Err(Self::Error::UnescapeError(errs)),
                 }
             }
+            Expr::ExprNoExt(ExprNoExt::Glob { left, pattern }) => {
+                match unescape::to_glob_pattern(&pattern) {
+                    Ok(pattern) => Ok(ast::Expr::like((*left).clone().try_into()?, pattern)),
+                    Err(errs) => Err(Self::Error::UnescapeError(errs)),
+                }
+            }
+            Expr::ExprNoExt(ExprNoExt::Is { left, entity_type }) => {
+                let name =
+                    entity_type
+                        .parse()
+                        .map_err(|errs| JsonDeserializationError::ParseEscape {
+                            kind: EscapeKind::Entity,
+                            value: entity_type.to_string(),
+                            errs,
+                        })?;
+                Ok(ast::Expr::is_entity_type((*left).clone().try_into()?, name))
+            }
             Expr::ExprNoExt(ExprNoExt::If {
                 cond_expr,
                 then_expr,
@@ -661,6 +760,8 @@ impl From<ast::Expr> for Expr {
                     ast::BinaryOp::Contains => Expr::contains(Arc::new(arg1), arg2),
                     ast::BinaryOp::ContainsAll => Expr::contains_all(Arc::new(arg1), arg2),
                     ast::BinaryOp::ContainsAny => Expr::contains_any(Arc::new(arg1), arg2),
+                    ast::BinaryOp::GetTag => Expr::get_tag(Arc::new(arg1), arg2),
+                    ast::BinaryOp::HasTag => Expr::has_tag(Arc::new(arg1), arg2),
                 }
             }
             ast::ExprKind::MulByConst { arg, constant } => Expr::mul(
@@ -677,9 +778,15 @@ impl From<ast::Expr> for Expr {
             ast::ExprKind::HasAttr { expr, attr } => {
                 Expr::has_attr(unwrap_or_clone(expr).into(), attr)
             }
+            ast::ExprKind::Like { expr, pattern } if pattern.has_classes() => {
+                Expr::glob(unwrap_or_clone(expr).into(), pattern.to_string().into())
+            }
             ast::ExprKind::Like { expr, pattern } => {
                 Expr::like(unwrap_or_clone(expr).into(), pattern.to_string().into())
             }
+            ast::ExprKind::Is { expr, entity_type } => {
+                Expr::is_entity_type(unwrap_or_clone(expr).into(), entity_type.to_string().into())
+            }
             ast::ExprKind::Set(set) => {
                 Expr::set(unwrap_or_clone(set).into_iter().map(Into::into).collect())
             }
@@ -776,6 +883,34 @@ impl TryFrom<cst::And> for Expr {
     }
 }
 
+/// The target and pattern of a `like` or `glob` expression
+fn pattern_operands(
+    target: ASTNode<Option<cst::Add>>,
+    pattern: ASTNode<Option<cst::Add>>,
+) -> Result<(Expr, SmolStr), ParseErrors> {
+    match (target, pattern) {
+        (
+            ASTNode {
+                node: Some(target), ..
+            },
+            ASTNode {
+                node: Some(pattern),
+                ..
+            },
+        ) => {
+            let target_expr = target.try_into()?;
+            let pat_expr: Expr = pattern.try_into()?;
+            let pat_str = pat_expr.into_string_literal().map_err(|e| {
+                ParseError::ToAST(ToASTError::InvalidPattern(
+                    serde_json::to_string(&e).unwrap_or_else(|_| "<malformed est>".to_string()),
+                ))
+            })?;
+            Ok((target_expr, pat_str))
+        }
+        (_, _) => Err(ParseError::ToAST(ToASTError::MissingNodeData).into()),
+    }
+}
+
 impl TryFrom<cst::Relation> for Expr {
     type Error = ParseErrors;
     fn try_from(r: cst::Relation) -> Result<Expr, ParseErrors> {
@@ -841,27 +976,31 @@ impl TryFrom<cst::Relation> for Expr {
                 }
                 (_, _) => Err(ParseError::ToAST(ToASTError::MissingNodeData).into()),
             },
-            cst::Relation::Like { target, pattern } => match (target, pattern) {
-                (
-                    ASTNode {
-                        node: Some(target), ..
-                    },
-                    ASTNode {
-                        node: Some(pattern),
-                        ..
-                    },
-                ) => {
+            cst::Relation::Like { target, pattern } => {
+                let (target, pattern) = pattern_operands(target, pattern)?;
+                Ok(Expr::like(target, pattern))
+            }
+            cst::Relation::Glob { target, pattern } => {
+                let (target, pattern) = pattern_operands(target, pattern)?;
+                Ok(Expr::glob(target, pattern))
+            }
+            cst::Relation::Is {
+                target,
+                entity_type,
+            } => match target {
+                ASTNode {
+                    node: Some(target), ..
+                } => {
                     let target_expr = target.try_into()?;
-                    let pat_expr: Expr = pattern.try_into()?;
-                    let pat_str = pat_expr.into_string_literal().map_err(|e| {
-                        ParseError::ToAST(ToASTError::InvalidPattern(
-                            serde_json::to_string(&e)
-                                .unwrap_or_else(|_| "<malformed est>".to_string()),
-                        ))
-                    })?;
-                    Ok(Expr::like(target_expr, pat_str))
+                    let mut errs = ParseErrors::new();
+                    match entity_type.to_name(&mut errs) {
+                        Some(name) => {
+                            Ok(Expr::is_entity_type(target_expr, name.to_string().into()))
+                        }
+                        None => Err(errs),
+                    }
                 }
-                (_, _) => Err(ParseError::ToAST(ToASTError::MissingNodeData).into()),
+                _ => Err(ParseError::ToAST(ToASTError::MissingNodeData).into()),
             },
         }
     }
@@ -1201,6 +1340,29 @@ impl TryFrom<cst::Member> for Expr {
                     //      method call instead.
                     //   - any other expression: it's an illegal call as the target is a higher order expression
                     item = match item {
+                        Either::Left(name) if name.to_string() == "getOr" => {
+                            let args = args
+                                .into_iter()
+                                .map(|node| match node.node {
+                                    Some(arg) => arg.try_into(),
+                                    None => {
+                                        Err(ParseError::ToAST(ToASTError::MissingNodeData).into())
+                                    }
+                                })
+                                .collect::<Result<Vec<Expr>, ParseErrors>>()?;
+                            let [left, attr, default]: [Expr; 3] =
+                                args.try_into().map_err(|args: Vec<Expr>| {
+                                    ParseError::ToAST(ToASTError::wrong_arity(
+                                        "getOr",
+                                        3,
+                                        args.len(),
+                                    ))
+                                })?;
+                            let attr = attr
+                                .into_string_literal()
+                                .map_err(|_| ParseError::ToAST(ToASTError::NonStringGetOrAttr))?;
+                            Either::Right(Expr::get_or(left, attr, default))
+                        }
                         Either::Left(name) => Either::Right(Expr::ext_call(
                             name.to_string().into(),
                             args.into_iter()
@@ -1236,6 +1398,14 @@ impl TryFrom<cst::Member> for Expr {
                                     left,
                                     extract_single_argument(args, "containsAny()")?,
                                 )),
+                                "getTag" => Either::Right(Expr::get_tag(
+                                    left,
+                                    extract_single_argument(args, "getTag()")?,
+                                )),
+                                "hasTag" => Either::Right(Expr::has_tag(
+                                    left,
+                                    extract_single_argument(args, "hasTag()")?,
+                                )),
                                 _ => {
                                     // have to add the "receiver" argument as
                                     // first in the list for the method call
@@ -1353,6 +1523,7 @@ fn ident_to_str_len(i: &Ident) -> usize {
         Ident::In => 2,
         Ident::Has => 3,
         Ident::Like => 4,
+        Ident::Is => 2,
         Ident::If => 2,
         Ident::Then => 4,
         Ident::Else => 4,
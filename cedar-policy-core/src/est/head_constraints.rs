@@ -16,8 +16,11 @@
 
 use super::{FromJsonError, InstantiationError};
 use crate::ast;
-use crate::entities::{EntityUidJSON, JsonDeserializationErrorContext};
+use crate::entities::{
+    EntityUidJSON, EscapeKind, JsonDeserializationError, JsonDeserializationErrorContext,
+};
 use serde::{Deserialize, Serialize};
+use smol_str::SmolStr;
 use std::collections::HashMap;
 use std::sync::Arc;
 
@@ -34,6 +37,9 @@ pub enum PrincipalConstraint {
     /// `in` constraint
     #[serde(rename = "in")]
     In(PrincipalOrResourceInConstraint),
+    /// `is` constraint, optionally combined with `in`
+    #[serde(rename = "is")]
+    Is(PrincipalOrResourceIsConstraint),
 }
 
 /// Serde JSON structure for an action head constraint in the EST format
@@ -64,6 +70,9 @@ pub enum ResourceConstraint {
     /// `in` constraint
     #[serde(rename = "in")]
     In(PrincipalOrResourceInConstraint),
+    /// `is` constraint, optionally combined with `in`
+    #[serde(rename = "is")]
+    Is(PrincipalOrResourceIsConstraint),
 }
 
 /// Serde JSON structure for a `==` head constraint in the EST format
@@ -99,6 +108,92 @@ pub enum PrincipalOrResourceInConstraint {
     },
 }
 
+/// Serde JSON structure for an `is` head constraint for principal/resource in
+/// the EST format
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PrincipalOrResourceIsConstraint {
+    /// Entity type it must have
+    pub entity_type: SmolStr,
+    /// Entity (or template slot) it must additionally be `in`, if any
+    #[serde(rename = "in")]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub in_entity: Option<PrincipalOrResourceInConstraint>,
+}
+
+impl PrincipalOrResourceIsConstraint {
+    fn instantiate(
+        self,
+        vals: &HashMap<ast::SlotId, EntityUidJSON>,
+    ) -> Result<Self, InstantiationError> {
+        let in_entity = match self.in_entity {
+            Some(PrincipalOrResourceInConstraint::Slot { slot }) => match vals.get(&slot) {
+                Some(val) => Some(PrincipalOrResourceInConstraint::Entity {
+                    entity: val.clone(),
+                }),
+                None => return Err(InstantiationError::MissedSlot { slot }),
+            },
+            in_entity => in_entity,
+        };
+        Ok(Self {
+            entity_type: self.entity_type,
+            in_entity,
+        })
+    }
+
+    fn from_ast(
+        entity_type: &ast::Name,
+        eref: Option<ast::EntityReference>,
+        slot: ast::SlotId,
+    ) -> Self {
+        Self {
+            entity_type: entity_type.to_string().into(),
+            in_entity: eref.map(|eref| match eref {
+                ast::EntityReference::EUID(e) => PrincipalOrResourceInConstraint::Entity {
+                    entity: EntityUidJSON::ImplicitEntityEscape((&*e).into()),
+                },
+                ast::EntityReference::Slot => PrincipalOrResourceInConstraint::Slot { slot },
+            }),
+        }
+    }
+
+    fn try_into_ast(
+        self,
+        expected_slot: ast::SlotId,
+    ) -> Result<ast::PrincipalOrResourceConstraint, FromJsonError> {
+        let entity_type: ast::Name =
+            self.entity_type
+                .parse()
+                .map_err(|errs| JsonDeserializationError::ParseEscape {
+                    kind: EscapeKind::Entity,
+                    value: self.entity_type.to_string(),
+                    errs,
+                })?;
+        match self.in_entity {
+            None => Ok(ast::PrincipalOrResourceConstraint::Is(entity_type)),
+            Some(PrincipalOrResourceInConstraint::Entity { entity }) => {
+                Ok(ast::PrincipalOrResourceConstraint::IsIn(
+                    entity_type,
+                    ast::EntityReference::EUID(Arc::new(
+                        entity.into_euid(|| JsonDeserializationErrorContext::EntityUid)?,
+                    )),
+                ))
+            }
+            Some(PrincipalOrResourceInConstraint::Slot { slot }) => {
+                if slot == expected_slot {
+                    Ok(ast::PrincipalOrResourceConstraint::IsIn(
+                        entity_type,
+                        ast::EntityReference::Slot,
+                    ))
+                } else {
+                    Err(FromJsonError::InvalidSlotName)
+                }
+            }
+        }
+    }
+}
+
 /// Serde JSON structure for an `in` head constraint for action in the EST
 /// format
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -148,6 +243,7 @@ impl PrincipalConstraint {
                     None => Err(InstantiationError::MissedSlot { slot }),
                 }
             }
+            PrincipalConstraint::Is(is) => Ok(PrincipalConstraint::Is(is.instantiate(vals)?)),
         }
     }
 }
@@ -184,6 +280,7 @@ impl ResourceConstraint {
                     None => Err(InstantiationError::MissedSlot { slot }),
                 }
             }
+            ResourceConstraint::Is(is) => Ok(ResourceConstraint::Is(is.instantiate(vals)?)),
         }
     }
 }
@@ -251,6 +348,20 @@ impl From<ast::PrincipalOrResourceConstraint> for PrincipalConstraint {
                     slot: ast::SlotId::principal(),
                 })
             }
+            ast::PrincipalOrResourceConstraint::Is(entity_type) => {
+                PrincipalConstraint::Is(PrincipalOrResourceIsConstraint::from_ast(
+                    &entity_type,
+                    None,
+                    ast::SlotId::principal(),
+                ))
+            }
+            ast::PrincipalOrResourceConstraint::IsIn(entity_type, eref) => {
+                PrincipalConstraint::Is(PrincipalOrResourceIsConstraint::from_ast(
+                    &entity_type,
+                    Some(eref),
+                    ast::SlotId::principal(),
+                ))
+            }
         }
     }
 }
@@ -279,6 +390,20 @@ impl From<ast::PrincipalOrResourceConstraint> for ResourceConstraint {
                     slot: ast::SlotId::resource(),
                 })
             }
+            ast::PrincipalOrResourceConstraint::Is(entity_type) => {
+                ResourceConstraint::Is(PrincipalOrResourceIsConstraint::from_ast(
+                    &entity_type,
+                    None,
+                    ast::SlotId::resource(),
+                ))
+            }
+            ast::PrincipalOrResourceConstraint::IsIn(entity_type, eref) => {
+                ResourceConstraint::Is(PrincipalOrResourceIsConstraint::from_ast(
+                    &entity_type,
+                    Some(eref),
+                    ast::SlotId::resource(),
+                ))
+            }
         }
     }
 }
@@ -318,6 +443,7 @@ impl TryFrom<PrincipalConstraint> for ast::PrincipalOrResourceConstraint {
                     Err(Self::Error::InvalidSlotName)
                 }
             }
+            PrincipalConstraint::Is(is) => is.try_into_ast(ast::SlotId::principal()),
         }
     }
 }
@@ -357,6 +483,7 @@ impl TryFrom<ResourceConstraint> for ast::PrincipalOrResourceConstraint {
                     Err(Self::Error::InvalidSlotName)
                 }
             }
+            ResourceConstraint::Is(is) => is.try_into_ast(ast::SlotId::resource()),
         }
     }
 }
@@ -19,6 +19,7 @@
 use crate::ast::*;
 use crate::entities::{Dereference, Entities, EntityAttrValues};
 use crate::extensions::Extensions;
+use crate::metrics;
 #[cfg(test)]
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -26,6 +27,9 @@ use std::sync::Arc;
 mod err;
 pub(crate) use err::*;
 pub use err::{EvaluationError, EvaluationErrorKind};
+mod interrupt;
+pub(crate) use interrupt::yield_now;
+pub use interrupt::{CancellationToken, Interrupt, Interrupted};
 use itertools::Either;
 use smol_str::SmolStr;
 
@@ -66,6 +70,8 @@ pub struct Evaluator<'e> {
     ///
     /// We evaluate entity attribute expressions upon the creation of an evaluator.
     entity_attr_values: EntityAttrValues<'e>,
+    /// When to stop evaluating early, if ever
+    interrupt: Option<Interrupt>,
 }
 
 /// Evaluator for "restricted" expressions. See notes on `RestrictedExpr`.
@@ -130,6 +136,7 @@ impl<'e> RestrictedEvaluator<'e> {
                     Either::Left(values) => {
                         let values : Vec<_> = values.collect();
                         let efunc = self.extensions.func(fn_name)?;
+                        metrics::extension_call(fn_name);
                         efunc.call(&values)
                     },
                     Either::Right(residuals) => Ok(Expr::call_extension_fn(fn_name.clone(), residuals.collect()).into()),
@@ -178,9 +185,25 @@ impl<'q, 'e> Evaluator<'e> {
             entities,
             extensions,
             entity_attr_values,
+            interrupt: None,
         })
     }
 
+    /// Stop evaluating, with an [`EvaluationErrorKind::Interrupted`] error,
+    /// when `interrupt` fires
+    #[must_use]
+    pub fn with_interrupt(mut self, interrupt: Interrupt) -> Self {
+        self.interrupt = Some(interrupt);
+        self
+    }
+
+    fn check_interrupt(&self) -> Result<()> {
+        match &self.interrupt {
+            Some(interrupt) => Ok(interrupt.check()?),
+            None => Ok(()),
+        }
+    }
+
     /// Evaluate the given `Policy`, returning either a bool or an error.
     /// The bool indicates whether the policy applies, ie, "is satisfied" for the
     /// current `request`.
@@ -395,6 +418,7 @@ impl<'q, 'e> Evaluator<'e> {
                     }
                     // hierarchy membership operator; see note on `BinaryOp::In`
                     BinaryOp::In => {
+                        self.check_interrupt()?;
                         let uid1 = arg1.get_as_entity().map_err(|mut e|
                             {
                                 // If arg1 is not an entity and arg2 is a set, then possibly
@@ -430,6 +454,7 @@ impl<'q, 'e> Evaluator<'e> {
                     },
                     // ContainsAll and ContainsAny, which work on Sets
                     BinaryOp::ContainsAll | BinaryOp::ContainsAny => {
+                        self.check_interrupt()?;
                         let arg1_set = arg1.get_as_set()?;
                         let arg2_set = arg2.get_as_set()?;
                         match (&arg1_set.fast, &arg2_set.fast) {
@@ -477,6 +502,31 @@ impl<'q, 'e> Evaluator<'e> {
                             }
                         }
                     }
+                    // GetTag and HasTag, which work on entities
+                    BinaryOp::GetTag | BinaryOp::HasTag => {
+                        let uid = arg1.get_as_entity()?;
+                        let tag = arg2.get_as_string()?;
+                        match self.entities.entity(uid) {
+                            Dereference::Residual(r) => Ok(PartialValue::Residual(
+                                Expr::binary_app(*op, r, arg2.clone().into()),
+                            )),
+                            Dereference::NoSuchEntity => match op {
+                                BinaryOp::HasTag => Ok(false.into()),
+                                _ => Err(EvaluationError::entity_does_not_exist(Arc::new(
+                                    uid.clone(),
+                                ))),
+                            },
+                            Dereference::Data(entity) => match (op, entity.get_tag(tag)) {
+                                (BinaryOp::HasTag, tag_value) => Ok(tag_value.is_some().into()),
+                                (_, Some(tag_value)) => RestrictedEvaluator::new(self.extensions)
+                                    .partial_interpret(tag_value.as_borrowed()),
+                                (_, None) => Err(EvaluationError::entity_tag_does_not_exist(
+                                    Arc::new(uid.clone()),
+                                    tag.clone(),
+                                )),
+                            },
+                        }
+                    }
                 }
             }
             ExprKind::MulByConst { arg, constant } => match self.partial_interpret(arg, slots)? {
@@ -502,6 +552,7 @@ impl<'q, 'e> Evaluator<'e> {
                     Either::Left(vals) => {
                         let vals: Vec<_> = vals.collect();
                         let efunc = self.extensions.func(fn_name)?;
+                        metrics::extension_call(fn_name);
                         efunc.call(&vals)
                     }
                     Either::Right(residuals) => Ok(PartialValue::Residual(
@@ -539,7 +590,27 @@ impl<'q, 'e> Evaluator<'e> {
                     PartialValue::Residual(r) => Ok(Expr::like(r, pattern.iter().cloned()).into()),
                 }
             }
+            ExprKind::Is { expr, entity_type } => {
+                let v = self.partial_interpret(expr, slots)?;
+                match v {
+                    // `is` tests the type of an entity or of an extension
+                    // value; any other value has neither type
+                    PartialValue::Value(v) => Ok(match v {
+                        Value::Lit(Literal::EntityUID(uid)) => match uid.entity_type() {
+                            EntityType::Concrete(ty) => ty == entity_type,
+                            EntityType::Unspecified => false,
+                        },
+                        Value::ExtensionValue(ev) => &ev.typename() == entity_type,
+                        _ => false,
+                    }
+                    .into()),
+                    PartialValue::Residual(r) => {
+                        Ok(Expr::is_entity_type(r, entity_type.clone()).into())
+                    }
+                }
+            }
             ExprKind::Set(items) => {
+                self.check_interrupt()?;
                 let vals = items
                     .iter()
                     .map(|item| self.partial_interpret(item, slots))
@@ -3214,6 +3285,130 @@ pub mod test {
         assert_eq!(eval.interpret_inline_policy(&parse_expr(r#""string\\*with\\*backslashes\\*and\\*stars" like "string\\*with\\*backslashes\\*and\\*stars""#).expect("parsing error")), Ok(Value::Lit(Literal::Bool(true))));
     }
 
+    #[test]
+    fn interpret_tags() {
+        let mut wallet = Entity::with_uid(EntityUID::with_eid("wallet"));
+        wallet.set_tag("tier".into(), RestrictedExpr::val("gold"));
+        wallet.set_tag("limit".into(), RestrictedExpr::val(100));
+        let entities = Entities::from_entities(
+            vec![wallet, Entity::with_uid(EntityUID::with_eid("untagged"))],
+            TCComputation::ComputeNow,
+        )
+        .expect("failed to create entities");
+        let request = basic_request();
+        let exts = Extensions::none();
+        let eval = Evaluator::new(&request, &entities, &exts).expect("failed to create evaluator");
+        let wallet = || Expr::val(EntityUID::with_eid("wallet"));
+
+        assert_eq!(
+            eval.interpret_inline_policy(&Expr::has_tag(wallet(), Expr::val("tier"))),
+            Ok(Value::from(true))
+        );
+        assert_eq!(
+            eval.interpret_inline_policy(&Expr::has_tag(wallet(), Expr::val("name"))),
+            Ok(Value::from(false))
+        );
+        assert_eq!(
+            eval.interpret_inline_policy(&Expr::get_tag(wallet(), Expr::val("tier"))),
+            Ok(Value::from("gold"))
+        );
+        assert_eq!(
+            eval.interpret_inline_policy(&Expr::greater(
+                Expr::get_tag(wallet(), Expr::val("limit")),
+                Expr::val(10)
+            )),
+            Ok(Value::from(true))
+        );
+        // tags and attributes are separate
+        assert_eq!(
+            eval.interpret_inline_policy(&Expr::has_attr(wallet(), "tier".into())),
+            Ok(Value::from(false))
+        );
+        assert_eq!(
+            eval.interpret_inline_policy(&Expr::get_tag(
+                Expr::val(EntityUID::with_eid("untagged")),
+                Expr::val("tier")
+            )),
+            Err(EvaluationError::entity_tag_does_not_exist(
+                Arc::new(EntityUID::with_eid("untagged")),
+                "tier".into()
+            ))
+        );
+        // missing entities have no tags
+        assert_eq!(
+            eval.interpret_inline_policy(&Expr::has_tag(
+                Expr::val(EntityUID::with_eid("missing")),
+                Expr::val("tier")
+            )),
+            Ok(Value::from(false))
+        );
+        assert_eq!(
+            eval.interpret_inline_policy(&Expr::get_tag(
+                Expr::val(EntityUID::with_eid("missing")),
+                Expr::val("tier")
+            )),
+            Err(EvaluationError::entity_does_not_exist(Arc::new(
+                EntityUID::with_eid("missing")
+            )))
+        );
+        assert_eq!(
+            eval.interpret_inline_policy(&Expr::has_tag(Expr::val(1), Expr::val("tier"))),
+            Err(EvaluationError::type_error(
+                vec![Type::entity_type(names::ANY_ENTITY_TYPE.clone())],
+                Type::Long
+            ))
+        );
+    }
+
+    #[test]
+    fn interpret_is() {
+        let request = basic_request();
+        let entities = basic_entities();
+        let exts = Extensions::none();
+        let eval = Evaluator::new(&request, &entities, &exts).expect("failed to create evaluator");
+        assert_eq!(
+            eval.interpret_inline_policy(
+                &parse_expr(r#"User::"alice" is User"#).expect("parsing error")
+            ),
+            Ok(Value::from(true))
+        );
+        assert_eq!(
+            eval.interpret_inline_policy(
+                &parse_expr(r#"User::"alice" is Group"#).expect("parsing error")
+            ),
+            Ok(Value::from(false))
+        );
+        assert_eq!(
+            eval.interpret_inline_policy(
+                &parse_expr(r#"NS::User::"alice" is User"#).expect("parsing error")
+            ),
+            Ok(Value::from(false))
+        );
+        assert_eq!(
+            eval.interpret_inline_policy(
+                &parse_expr(r#"NS::User::"alice" is NS::User"#).expect("parsing error")
+            ),
+            Ok(Value::from(true))
+        );
+        // `is` binds tighter than `&&`
+        assert_eq!(
+            eval.interpret_inline_policy(
+                &parse_expr(r#"User::"alice" is User && 1 < 2"#).expect("parsing error")
+            ),
+            Ok(Value::from(true))
+        );
+        // values which are neither entities nor extension values have no
+        // type to match
+        assert_eq!(
+            eval.interpret_inline_policy(&parse_expr(r#"1 is User"#).expect("parsing error")),
+            Ok(Value::from(false))
+        );
+        assert_eq!(
+            eval.interpret_inline_policy(&parse_expr(r#""1" is u256"#).expect("parsing error")),
+            Ok(Value::from(false))
+        );
+    }
+
     #[test]
     fn interpret_contains_all_and_contains_any() -> Result<()> {
         let request = basic_request();
@@ -4689,6 +4884,8 @@ pub mod test {
             BinaryOp::Less,
             BinaryOp::LessEq,
             BinaryOp::Sub,
+            BinaryOp::GetTag,
+            BinaryOp::HasTag,
         ];
 
         for binop in binops {
@@ -65,6 +65,14 @@ impl EvaluationError {
         }
     }
 
+    /// Construct a [`EntityTagDoesNotExist`] error
+    pub(crate) fn entity_tag_does_not_exist(entity: Arc<EntityUID>, tag: SmolStr) -> Self {
+        Self {
+            error_kind: EvaluationErrorKind::EntityTagDoesNotExist { entity, tag },
+            advice: None,
+        }
+    }
+
     /// Construct a [`UnspecifiedEntityAccess`] error
     pub(crate) fn unspecified_entity_access(attr: SmolStr) -> Self {
         Self {
@@ -149,6 +157,15 @@ impl EvaluationError {
     }
 }
 
+impl From<super::Interrupted> for EvaluationError {
+    fn from(err: super::Interrupted) -> Self {
+        Self {
+            error_kind: err.into(),
+            advice: None,
+        }
+    }
+}
+
 impl From<crate::extensions::ExtensionFunctionLookupError> for EvaluationError {
     fn from(err: crate::extensions::ExtensionFunctionLookupError) -> Self {
         Self {
@@ -198,6 +215,15 @@ pub enum EvaluationErrorKind {
     #[error("cannot access attribute `{0}` of unspecified entity")]
     UnspecifiedEntityAccess(SmolStr),
 
+    /// Tried to get this tag, but the specified entity didn't have that tag
+    #[error("`{}` does not have the tag `{}`", &.entity, &.tag)]
+    EntityTagDoesNotExist {
+        /// Entity that didn't have the tag
+        entity: Arc<EntityUID>,
+        /// Name of the tag it didn't have
+        tag: SmolStr,
+    },
+
     /// Tried to get an attribute of a (non-entity) record, but that record
     /// didn't have that attribute
     #[error("record does not have the attribute `{0}`. Available attributes: {1:?}")]
@@ -260,6 +286,10 @@ pub enum EvaluationErrorKind {
     /// Maximum recursion limit reached for expression evaluation
     #[error("recursion limit reached")]
     RecursionLimit,
+
+    /// Evaluation was cancelled or its deadline passed
+    #[error(transparent)]
+    Interrupted(#[from] super::Interrupted),
 }
 
 /// helper function for pretty-printing type errors
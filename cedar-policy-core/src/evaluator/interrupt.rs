@@ -0,0 +1,155 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Deadlines and cooperative cancellation for evaluation.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// Cancels the evaluations holding a clone of it. Cloning is cheap.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Create a token which hasn't been cancelled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel every evaluation using this token, or a clone of it
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether [`Self::cancel()`] has been called
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// When to stop an evaluation early. Evaluation checks this at safe points:
+/// before each policy, and before building a set or evaluating
+/// `containsAll`, `containsAny`, or `in`.
+///
+/// Deadlines use [`Instant`], which isn't available on
+/// `wasm32-unknown-unknown`; use only cancellation there.
+#[derive(Debug, Clone, Default)]
+pub struct Interrupt {
+    deadline: Option<Instant>,
+    cancellation: Option<CancellationToken>,
+}
+
+impl Interrupt {
+    /// An interrupt which never fires
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stop evaluating at `deadline`
+    #[must_use]
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Stop evaluating `timeout` from now
+    #[must_use]
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        self.with_deadline(Instant::now() + timeout)
+    }
+
+    /// Stop evaluating when `token` is cancelled
+    #[must_use]
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Return an error if evaluation should stop
+    pub fn check(&self) -> Result<(), Interrupted> {
+        if self
+            .cancellation
+            .as_ref()
+            .map_or(false, CancellationToken::is_cancelled)
+        {
+            return Err(Interrupted::Cancelled);
+        }
+        match self.deadline {
+            Some(deadline) if Instant::now() >= deadline => Err(Interrupted::DeadlineExceeded),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Why an evaluation stopped early
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum Interrupted {
+    /// The evaluation's [`CancellationToken`] was cancelled
+    #[error("evaluation was cancelled")]
+    Cancelled,
+    /// The evaluation's deadline passed
+    #[error("evaluation deadline exceeded")]
+    DeadlineExceeded,
+}
+
+/// Yield to the async executor once, so that other tasks can run
+pub(crate) fn yield_now() -> impl Future<Output = ()> {
+    YieldNow { yielded: false }
+}
+
+struct YieldNow {
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.yielded {
+            Poll::Ready(())
+        } else {
+            self.yielded = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn interrupts() {
+        assert_eq!(Interrupt::new().check(), Ok(()));
+
+        let token = CancellationToken::new();
+        let interrupt = Interrupt::new().with_cancellation(token.clone());
+        assert_eq!(interrupt.check(), Ok(()));
+        token.cancel();
+        assert_eq!(interrupt.check(), Err(Interrupted::Cancelled));
+
+        let past = Interrupt::new().with_deadline(Instant::now());
+        assert_eq!(past.check(), Err(Interrupted::DeadlineExceeded));
+        let future = Interrupt::new().with_timeout(Duration::from_secs(3600));
+        assert_eq!(future.check(), Ok(()));
+    }
+}
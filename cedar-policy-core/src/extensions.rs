@@ -19,6 +19,7 @@
 #[cfg(feature = "ipaddr")]
 pub mod ipaddr;
 
+pub mod config;
 #[cfg(feature = "decimal")]
 pub mod decimal;
 pub mod partial_evaluation;
@@ -26,6 +27,54 @@ pub mod partial_evaluation;
 #[cfg(feature = "u256")]
 pub mod u256;
 
+#[cfg(feature = "price-feed")]
+pub mod price_feed;
+
+#[cfg(feature = "log-match")]
+pub mod log_match;
+
+#[cfg(feature = "gas")]
+pub mod gas;
+
+#[cfg(feature = "hash")]
+pub mod hash;
+
+#[cfg(feature = "commitment")]
+pub mod commitment;
+
+#[cfg(feature = "zk")]
+pub mod zk;
+
+#[cfg(feature = "webauthn")]
+pub mod webauthn;
+
+#[cfg(feature = "totp")]
+pub mod totp;
+
+#[cfg(feature = "set-ops")]
+pub mod set_ops;
+
+#[cfg(feature = "record-ops")]
+pub mod record_ops;
+
+#[cfg(feature = "entity-ops")]
+pub mod entity_ops;
+
+#[cfg(feature = "travel-rule")]
+pub mod travel_rule;
+
+#[cfg(feature = "vesting")]
+pub mod vesting;
+
+#[cfg(feature = "health-factor")]
+pub mod health_factor;
+
+#[cfg(feature = "concentration")]
+pub mod concentration;
+
+#[cfg(feature = "exposure")]
+pub mod exposure;
+
 use crate::ast::{Extension, ExtensionFunction, Name};
 use crate::entities::SchemaType;
 use thiserror::Error;
@@ -37,8 +86,41 @@ lazy_static::lazy_static! {
         #[cfg(feature = "decimal")]
         decimal::extension(),
         partial_evaluation::extension(),
+        config::extension(),
         #[cfg(feature = "u256")]
         u256::extension(),
+        #[cfg(feature = "price-feed")]
+        price_feed::extension(),
+        #[cfg(feature = "log-match")]
+        log_match::extension(),
+        #[cfg(feature = "gas")]
+        gas::extension(),
+        #[cfg(feature = "hash")]
+        hash::extension(),
+        #[cfg(feature = "commitment")]
+        commitment::extension(),
+        #[cfg(feature = "zk")]
+        zk::extension(),
+        #[cfg(feature = "webauthn")]
+        webauthn::extension(),
+        #[cfg(feature = "totp")]
+        totp::extension(),
+        #[cfg(feature = "set-ops")]
+        set_ops::extension(),
+        #[cfg(feature = "record-ops")]
+        record_ops::extension(),
+        #[cfg(feature = "entity-ops")]
+        entity_ops::extension(),
+        #[cfg(feature = "travel-rule")]
+        travel_rule::extension(),
+        #[cfg(feature = "vesting")]
+        vesting::extension(),
+        #[cfg(feature = "health-factor")]
+        health_factor::extension(),
+        #[cfg(feature = "concentration")]
+        concentration::extension(),
+        #[cfg(feature = "exposure")]
+        exposure::extension(),
     ];
 }
 
@@ -76,6 +158,19 @@ impl<'a> Extensions<'a> {
         self.extensions.iter().map(|ext| ext.name())
     }
 
+    /// Get the names of the types constructed by these extensions, e.g.
+    /// `u256` or `decimal`.
+    ///
+    /// No guarantee that this list won't have duplicates.
+    pub fn ext_types(&self) -> impl Iterator<Item = &'a Name> {
+        self.all_funcs()
+            .filter(|f| f.is_constructor())
+            .filter_map(|f| match f.return_type() {
+                Some(SchemaType::Extension { name }) => Some(name),
+                _ => None,
+            })
+    }
+
     /// Get the extension function with the given name, from these extensions.
     ///
     /// Returns an error if the function is not defined by any extension, or if
@@ -102,7 +197,7 @@ impl<'a> Extensions<'a> {
     /// Iterate over all extension functions defined by all of these extensions.
     ///
     /// No guarantee that this list won't have duplicates or repeated names.
-    pub(crate) fn all_funcs(&self) -> impl Iterator<Item = &'a ExtensionFunction> {
+    pub fn all_funcs(&self) -> impl Iterator<Item = &'a ExtensionFunction> {
         self.extensions.iter().flat_map(|ext| ext.funcs())
     }
 
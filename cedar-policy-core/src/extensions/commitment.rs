@@ -0,0 +1,194 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+//! This module contains the Cedar 'commitment' extension.
+//!
+//! Its functions support Semaphore-style anonymous allowlists, where a
+//! member proves membership of a group, in zero knowledge, without revealing
+//! which member it is. The proof is verified before authorization, and its
+//! public signals are passed in the context: the nullifier, which is unique
+//! to the member and the scope of the proof, and the set of nullifiers
+//! already spent in that scope. A policy then only needs
+//! `nullifierUnspent(context.nullifier, context.spentNullifiers)` to allow
+//! each member to act once, e.g. to vote once in a DAO proposal.
+//!
+//! `commitmentOpens(commitment, value, blinding)` checks the opening of a
+//! Poseidon commitment, `poseidonHash(value, blinding)` (see the
+//! [`hash`](super::hash) extension), e.g. the reveal of a commit-reveal vote.
+//! All values are `u256`s which are BN254 field elements.
+
+use crate::ast::{CallStyle, Extension, ExtensionFunction, ExtensionOutputValue, Value};
+use crate::entities::SchemaType;
+use crate::evaluator;
+use ethers::types::U256;
+use thiserror::Error;
+
+use super::hash::poseidon;
+use super::u256::as_u256;
+
+// PANIC SAFETY All `Name`s in here are valid `Name`s
+#[allow(clippy::expect_used)]
+mod names {
+    use crate::ast::Name;
+    // PANIC SAFETY all of the names here are valid names
+    lazy_static::lazy_static! {
+        pub static ref COMMITMENT : Name = Name::parse_unqualified_name("commitment").expect("should be a valid identifier");
+        pub static ref COMMITMENT_OPENS : Name = Name::parse_unqualified_name("commitmentOpens").expect("should be a valid identifier");
+        pub static ref NULLIFIER_UNSPENT : Name = Name::parse_unqualified_name("nullifierUnspent").expect("should be a valid identifier");
+        pub static ref U256 : Name = Name::parse_unqualified_name("u256").expect("should be a valid identifier");
+    }
+}
+
+/// Potential errors when working with commitments. Note that these are
+/// converted to evaluator::Err::ExtensionErr (which takes a string argument)
+/// before being reported to users.
+#[derive(Debug, Error)]
+enum Error {
+    /// A value is not a field element
+    #[error("{0} is not an element of the BN254 scalar field")]
+    NotAFieldElement(U256),
+}
+
+fn extension_err(msg: impl Into<String>) -> evaluator::EvaluationError {
+    evaluator::EvaluationError::failed_extension_function_application(
+        names::COMMITMENT.clone(),
+        msg.into(),
+    )
+}
+
+/// The field element in `v`, which must be a `u256`
+fn field_element(v: &Value) -> evaluator::Result<U256> {
+    let value = as_u256(v)?;
+    if value < poseidon::MODULUS {
+        Ok(value)
+    } else {
+        Err(extension_err(Error::NotAFieldElement(value).to_string()))
+    }
+}
+
+/// Cedar function that tests whether `value` and `blinding` open
+/// `commitment`, returning a Cedar bool
+fn commitment_opens(
+    commitment: Value,
+    value: Value,
+    blinding: Value,
+) -> evaluator::Result<ExtensionOutputValue> {
+    let commitment = field_element(&commitment)?;
+    let inputs = [field_element(&value)?, field_element(&blinding)?];
+    Ok(Value::from(poseidon::POSEIDON_2.hash(&inputs) == commitment).into())
+}
+
+/// Cedar function that tests whether `nullifier` is not in the set `spent`,
+/// returning a Cedar bool
+fn nullifier_unspent(nullifier: Value, spent: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let nullifier = field_element(&nullifier)?;
+    for spent in spent.get_as_set()?.iter() {
+        if as_u256(spent)? == nullifier {
+            return Ok(Value::from(false).into());
+        }
+    }
+    Ok(Value::from(true).into())
+}
+
+/// Construct the extension
+pub fn extension() -> Extension {
+    let u256_type = SchemaType::Extension {
+        name: names::U256.clone(),
+    };
+    Extension::new(
+        names::COMMITMENT.clone(),
+        vec![
+            ExtensionFunction::ternary(
+                names::COMMITMENT_OPENS.clone(),
+                CallStyle::FunctionStyle,
+                Box::new(commitment_opens),
+                SchemaType::Bool,
+                (
+                    Some(u256_type.clone()),
+                    Some(u256_type.clone()),
+                    Some(u256_type.clone()),
+                ),
+            ),
+            ExtensionFunction::binary(
+                names::NULLIFIER_UNSPENT.clone(),
+                CallStyle::FunctionStyle,
+                Box::new(nullifier_unspent),
+                SchemaType::Bool,
+                (
+                    Some(u256_type.clone()),
+                    Some(SchemaType::Set {
+                        element_ty: Box::new(u256_type),
+                    }),
+                ),
+            ),
+        ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::test::{basic_entities, basic_request};
+    use crate::evaluator::Evaluator;
+    use crate::extensions::Extensions;
+    use crate::parser::parse_expr;
+
+    fn eval(expr: &str) -> evaluator::Result<Value> {
+        let ext_array = [
+            extension(),
+            super::super::hash::extension(),
+            super::super::u256::extension(),
+        ];
+        let exts = Extensions::specific_extensions(&ext_array);
+        let request = basic_request();
+        let entities = basic_entities();
+        let eval = Evaluator::new(&request, &entities, &exts).unwrap();
+        eval.interpret_inline_policy(&parse_expr(expr).expect("parsing error"))
+    }
+
+    #[test]
+    fn openings() {
+        assert_eq!(
+            eval(r#"commitmentOpens(poseidonHash(u256("7"), u256("42")), u256("7"), u256("42"))"#),
+            Ok(Value::from(true))
+        );
+        assert_eq!(
+            eval(r#"commitmentOpens(poseidonHash(u256("7"), u256("42")), u256("8"), u256("42"))"#),
+            Ok(Value::from(false))
+        );
+        let modulus = poseidon::MODULUS.to_string();
+        assert!(eval(&format!(
+            r#"commitmentOpens(u256("1"), u256("{modulus}"), u256("42"))"#
+        ))
+        .is_err());
+    }
+
+    #[test]
+    fn nullifiers() {
+        assert_eq!(
+            eval(r#"nullifierUnspent(u256("5"), [u256("1"), u256("2")])"#),
+            Ok(Value::from(true))
+        );
+        assert_eq!(
+            eval(r#"nullifierUnspent(u256("2"), [u256("1"), u256("2")])"#),
+            Ok(Value::from(false))
+        );
+        assert_eq!(
+            eval(r#"nullifierUnspent(u256("2"), [])"#),
+            Ok(Value::from(true))
+        );
+        assert!(eval(r#"nullifierUnspent(u256("2"), [2])"#).is_err());
+    }
+}
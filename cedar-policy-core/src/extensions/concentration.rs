@@ -0,0 +1,306 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+//! This module contains the Cedar 'concentration' extension.
+//!
+//! A portfolio is a set of records of the form
+//! `{ token: String, usdValue: decimal }`, with any number of records per
+//! token, though as in any set, identical records count once.
+//! `concentration(holdings, token)` is the percentage of the
+//! portfolio's value held in `token`, and `maxConcentration(holdings)` is the
+//! largest percentage held in any one token, both as `decimal`s rounded down,
+//! e.g. `maxConcentration(context.holdingsAfter).lessThanOrEqual(decimal("40.0"))`.
+//! A portfolio without value has no concentration.
+
+use crate::ast::{
+    CallStyle, Extension, ExtensionFunction, ExtensionOutputValue, Name, StaticallyTyped, Type,
+    Value,
+};
+use crate::entities::{AttributeType, SchemaType};
+use crate::evaluator;
+use smol_str::SmolStr;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use thiserror::Error;
+
+// PANIC SAFETY All `Name`s in here are valid `Name`s
+#[allow(clippy::expect_used)]
+mod names {
+    use super::{Name, EXTENSION_NAME};
+    // PANIC SAFETY all of the names here are valid names
+    lazy_static::lazy_static! {
+        pub static ref CONCENTRATION : Name = Name::parse_unqualified_name(EXTENSION_NAME).expect("should be a valid identifier");
+        pub static ref MAX_CONCENTRATION : Name = Name::parse_unqualified_name("maxConcentration").expect("should be a valid identifier");
+        pub static ref DECIMAL : Name = Name::parse_unqualified_name("decimal").expect("should be a valid identifier");
+    }
+}
+
+/// Potential errors when computing concentrations. Note that these are
+/// converted to evaluator::Err::ExtensionErr (which takes a string argument)
+/// before being reported to users.
+#[derive(Debug, Error)]
+enum Error {
+    /// A holding has a negative value
+    #[error("holding of `{0}` has a negative value")]
+    NegativeValue(SmolStr),
+}
+
+const EXTENSION_NAME: &str = "concentration";
+
+const TOKEN: &str = "token";
+const USD_VALUE: &str = "usdValue";
+
+/// One hundred percent, scaled by `10^4` like a `decimal`
+const HUNDRED_PERCENT: i128 = 1_000_000;
+
+fn extension_err(msg: impl Into<String>) -> evaluator::EvaluationError {
+    evaluator::EvaluationError::failed_extension_function_application(
+        names::CONCENTRATION.clone(),
+        msg.into(),
+    )
+}
+
+/// The values of a portfolio's holdings, per token, scaled by `10^4` like
+/// `decimal`s
+#[derive(Debug, Default)]
+struct Portfolio {
+    values: BTreeMap<SmolStr, i128>,
+    total: i128,
+}
+
+impl Portfolio {
+    /// The portfolio of a Cedar set of holdings
+    fn from_value(v: &Value) -> evaluator::Result<Self> {
+        let mut portfolio = Self::default();
+        for holding in v.get_as_set()?.iter() {
+            let record = as_record(holding)?;
+            let attr = |name: &str| {
+                record.get(name).ok_or_else(|| {
+                    evaluator::EvaluationError::record_attr_does_not_exist(
+                        name.into(),
+                        record.keys().cloned().collect(),
+                    )
+                })
+            };
+            let token = attr(TOKEN)?.get_as_string()?;
+            let value = super::decimal::decimal_scaled(attr(USD_VALUE)?)?;
+            if value < 0 {
+                return Err(extension_err(
+                    Error::NegativeValue(token.clone()).to_string(),
+                ));
+            }
+            *portfolio.values.entry(token.clone()).or_default() += i128::from(value);
+            portfolio.total += i128::from(value);
+        }
+        Ok(portfolio)
+    }
+
+    /// The percentage of the portfolio's value held in `value`, scaled by
+    /// `10^4` and rounded down
+    fn percentage(&self, value: i128) -> i64 {
+        if self.total == 0 {
+            return 0;
+        }
+        // `value` is at most `total`, so this is at most one hundred percent
+        i64::try_from(value * HUNDRED_PERCENT / self.total).unwrap_or(i64::MAX)
+    }
+}
+
+fn as_record(v: &Value) -> Result<&Arc<BTreeMap<SmolStr, Value>>, evaluator::EvaluationError> {
+    match v {
+        Value::Record(record) => Ok(record),
+        _ => Err(evaluator::EvaluationError::type_error(
+            vec![Type::Record],
+            v.type_of(),
+        )),
+    }
+}
+
+/// Cedar function that computes the percentage of a portfolio's value held in
+/// a token, returning a Cedar `decimal`
+fn concentration(holdings: Value, token: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let portfolio = Portfolio::from_value(&holdings)?;
+    let value = portfolio
+        .values
+        .get(token.get_as_string()?)
+        .copied()
+        .unwrap_or_default();
+    Ok(super::decimal::decimal_value(portfolio.percentage(value)).into())
+}
+
+/// Cedar function that computes the largest percentage of a portfolio's value
+/// held in any one token, returning a Cedar `decimal`
+fn max_concentration(holdings: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let portfolio = Portfolio::from_value(&holdings)?;
+    let value = portfolio.values.values().max().copied().unwrap_or_default();
+    Ok(super::decimal::decimal_value(portfolio.percentage(value)).into())
+}
+
+/// Construct the extension
+pub fn extension() -> Extension {
+    let decimal_type = SchemaType::Extension {
+        name: names::DECIMAL.clone(),
+    };
+    let holdings_type = SchemaType::Set {
+        element_ty: Box::new(SchemaType::Record {
+            attrs: HashMap::from([
+                (TOKEN.into(), AttributeType::required(SchemaType::String)),
+                (
+                    USD_VALUE.into(),
+                    AttributeType::required(decimal_type.clone()),
+                ),
+            ]),
+        }),
+    };
+    Extension::new(
+        names::CONCENTRATION.clone(),
+        vec![
+            ExtensionFunction::binary(
+                names::CONCENTRATION.clone(),
+                CallStyle::FunctionStyle,
+                Box::new(concentration),
+                decimal_type.clone(),
+                (Some(holdings_type.clone()), Some(SchemaType::String)),
+            ),
+            ExtensionFunction::unary(
+                names::MAX_CONCENTRATION.clone(),
+                CallStyle::FunctionStyle,
+                Box::new(max_concentration),
+                decimal_type,
+                Some(holdings_type),
+            ),
+        ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::test::{basic_entities, basic_request};
+    use crate::evaluator::{EvaluationErrorKind, Evaluator};
+    use crate::extensions::Extensions;
+    use crate::parser::parse_expr;
+
+    /// A Cedar set of holdings
+    fn holdings(holdings: &[(&str, &str)]) -> String {
+        let records: Vec<_> = holdings
+            .iter()
+            .map(|(token, value)| {
+                format!(r#"{{ token: "{token}", usdValue: decimal("{value}") }}"#)
+            })
+            .collect();
+        format!("[{}]", records.join(", "))
+    }
+
+    #[test]
+    fn concentrations() {
+        let ext_array = [extension(), super::super::decimal::extension()];
+        let exts = Extensions::specific_extensions(&ext_array);
+        let request = basic_request();
+        let entities = basic_entities();
+        let eval = Evaluator::new(&request, &entities, &exts).unwrap();
+
+        let is = |expr: &str, expected: &str| {
+            eval.interpret_inline_policy(
+                &parse_expr(&format!("{expr} == decimal(\"{expected}\")")).expect("parsing error"),
+            )
+        };
+        let portfolio = holdings(&[
+            ("ETH", "40000.0"),
+            ("USDC", "30000.0"),
+            ("WBTC", "20000.0"),
+            ("USDC", "10000.0"),
+        ]);
+        assert_eq!(
+            is(&format!(r#"concentration({portfolio}, "ETH")"#), "40.0"),
+            Ok(Value::from(true))
+        );
+        // holdings of the same token add up
+        assert_eq!(
+            is(&format!(r#"concentration({portfolio}, "USDC")"#), "40.0"),
+            Ok(Value::from(true))
+        );
+        assert_eq!(
+            is(&format!(r#"concentration({portfolio}, "DAI")"#), "0.0"),
+            Ok(Value::from(true))
+        );
+        assert_eq!(
+            is(&format!("maxConcentration({portfolio})"), "40.0"),
+            Ok(Value::from(true))
+        );
+        // rounded down
+        assert_eq!(
+            is(
+                &format!(
+                    "maxConcentration({})",
+                    holdings(&[("ETH", "1.0"), ("USDC", "1.0"), ("WBTC", "1.0")])
+                ),
+                "33.3333"
+            ),
+            Ok(Value::from(true))
+        );
+        // without value
+        assert_eq!(is("maxConcentration([])", "0.0"), Ok(Value::from(true)));
+        assert_eq!(
+            is(
+                &format!(r#"concentration({}, "ETH")"#, holdings(&[("ETH", "0.0")])),
+                "0.0"
+            ),
+            Ok(Value::from(true))
+        );
+
+        // a single condition
+        assert_eq!(
+            eval.interpret_inline_policy(
+                &parse_expr(&format!(
+                    r#"maxConcentration({}).lessThanOrEqual(decimal("40.0"))"#,
+                    holdings(&[("ETH", "40000.0001"), ("USDC", "59999.9999")])
+                ))
+                .expect("parsing error")
+            ),
+            Ok(Value::from(false))
+        );
+    }
+
+    #[test]
+    fn invalid_holdings() {
+        let ext_array = [extension(), super::super::decimal::extension()];
+        let exts = Extensions::specific_extensions(&ext_array);
+        let request = basic_request();
+        let entities = basic_entities();
+        let eval = Evaluator::new(&request, &entities, &exts).unwrap();
+
+        let eval_err = |src: &str| {
+            eval.interpret_inline_policy(&parse_expr(src).expect("parsing error"))
+                .map_err(|e| e.error_kind().clone())
+        };
+        assert!(matches!(
+            eval_err(&format!(
+                "maxConcentration({})",
+                holdings(&[("ETH", "1.0"), ("USDC", "-1.0")])
+            )),
+            Err(EvaluationErrorKind::FailedExtensionFunctionApplication { msg, .. })
+                if msg.contains("`USDC`")
+        ));
+        assert!(matches!(
+            eval_err(r#"maxConcentration([{ token: "ETH" }])"#),
+            Err(EvaluationErrorKind::RecordAttrDoesNotExist(attr, _)) if attr == "usdValue"
+        ));
+        assert!(matches!(
+            eval_err(r#"maxConcentration([{ token: "ETH", usdValue: 5 }])"#),
+            Err(EvaluationErrorKind::TypeError { .. })
+        ));
+    }
+}
@@ -0,0 +1,51 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! This module contains the extension for references to configuration
+//! values, `config("key")`, which are replaced with the values before
+//! policies are evaluated. Evaluating a reference which wasn't replaced is an
+//! error.
+use crate::{
+    ast::{CallStyle, Extension, ExtensionFunction, ExtensionOutputValue, Value},
+    entities::SchemaType,
+    evaluator::{self, EvaluationError},
+};
+
+fn unresolved(v: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let key = v.get_as_string()?;
+    // PANIC SAFETY: This name is fully static, and is a valid extension name
+    #[allow(clippy::unwrap_used)]
+    let err = EvaluationError::failed_extension_function_application(
+        "config".parse().unwrap(),
+        format!("configuration value `{key}` was not resolved"),
+    );
+    Err(err)
+}
+
+/// Construct the extension
+// PANIC SAFETY: all uses of `unwrap` here on parsing extension names are correct names
+#[allow(clippy::unwrap_used)]
+pub fn extension() -> Extension {
+    Extension::new(
+        "config".parse().unwrap(),
+        vec![ExtensionFunction::unary_never(
+            "config".parse().unwrap(),
+            CallStyle::FunctionStyle,
+            Box::new(unresolved),
+            Some(SchemaType::String),
+        )],
+    )
+}
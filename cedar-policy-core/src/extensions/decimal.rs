@@ -181,6 +181,34 @@ fn decimal_from_str(arg: Value) -> evaluator::Result<ExtensionOutputValue> {
     Ok(Value::ExtensionValue(Arc::new(e)).into())
 }
 
+/// Construct a `decimal` Cedar value representing `value / 10^NUM_DIGITS`,
+/// for extensions whose functions return decimals
+#[cfg(any(feature = "u256", feature = "concentration"))]
+pub(crate) fn decimal_value(value: i64) -> Value {
+    let sign = if value < 0 { "-" } else { "" };
+    let scale = 10_u64.pow(NUM_DIGITS);
+    let magnitude = value.unsigned_abs();
+    let str = format!(
+        "{sign}{}.{:0width$}",
+        magnitude / scale,
+        magnitude % scale,
+        width = NUM_DIGITS as usize
+    );
+    let e = ExtensionValueWithArgs::new(
+        Arc::new(Decimal { value }),
+        vec![Value::from(str).into()],
+        names::DECIMAL_FROM_STR_NAME.clone(),
+    );
+    Value::ExtensionValue(Arc::new(e))
+}
+
+/// The value of a `decimal` Cedar value, scaled by `10^NUM_DIGITS`, for
+/// extensions whose functions take decimals
+#[cfg(feature = "concentration")]
+pub(crate) fn decimal_scaled(v: &Value) -> Result<i64, evaluator::EvaluationError> {
+    as_decimal(v).map(|d| d.value)
+}
+
 /// Check that `v` is a decimal type and, if it is, return the wrapped value
 fn as_decimal(v: &Value) -> Result<&Decimal, evaluator::EvaluationError> {
     match v {
@@ -0,0 +1,149 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+//! This module contains the Cedar 'entityOps' extension.
+//!
+//! `entityFromString("Wallet", s)` is the entity reference `Wallet::"<s>"`,
+//! constructed at evaluation time. It lets policies compare addresses decoded
+//! into the context with entities and entity hierarchies, e.g.
+//! `entityFromString("Wallet", context.to) in principal.allowlist`, without
+//! the application resolving them to entity references first.
+
+use crate::ast::{
+    CallStyle, Eid, EntityType, EntityUID, Extension, ExtensionFunction, ExtensionOutputValue,
+    Name, Value,
+};
+use crate::entities::SchemaType;
+use crate::evaluator;
+use crate::FromNormalizedStr;
+
+// PANIC SAFETY All `Name`s in here are valid `Name`s
+#[allow(clippy::expect_used)]
+mod names {
+    use crate::ast::Name;
+    // PANIC SAFETY all of the names here are valid names
+    lazy_static::lazy_static! {
+        pub static ref ENTITY_OPS : Name = Name::parse_unqualified_name("entityOps").expect("should be a valid identifier");
+        pub static ref ENTITY_FROM_STRING : Name = Name::parse_unqualified_name("entityFromString").expect("should be a valid identifier");
+    }
+}
+
+/// Cedar function constructing the entity reference with the given entity
+/// type and id
+fn entity_from_string(ty: Value, id: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let ty = ty.get_as_string()?;
+    let id = id.get_as_string()?;
+    let name = Name::from_normalized_str(ty).map_err(|_| {
+        evaluator::EvaluationError::failed_extension_function_application(
+            names::ENTITY_OPS.clone(),
+            format!("`{ty}` is not a valid entity type name"),
+        )
+    })?;
+    Ok(Value::from(EntityUID::from_components(name, Eid::new(id.clone()))).into())
+}
+
+/// Construct the extension
+pub fn extension() -> Extension {
+    Extension::new(
+        names::ENTITY_OPS.clone(),
+        vec![ExtensionFunction::binary(
+            names::ENTITY_FROM_STRING.clone(),
+            CallStyle::FunctionStyle,
+            Box::new(entity_from_string),
+            // The entity type depends on the first argument
+            SchemaType::Entity {
+                ty: EntityType::Unspecified,
+            },
+            (Some(SchemaType::String), Some(SchemaType::String)),
+        )],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Type;
+    use crate::evaluator::test::{basic_entities, basic_request};
+    use crate::evaluator::{EvaluationErrorKind, Evaluator};
+    use crate::extensions::Extensions;
+    use crate::parser::parse_expr;
+
+    fn eval(expr: &str) -> evaluator::Result<Value> {
+        let ext_array = [extension()];
+        let exts = Extensions::specific_extensions(&ext_array);
+        let request = basic_request();
+        let entities = basic_entities();
+        let eval = Evaluator::new(&request, &entities, &exts).unwrap();
+        eval.interpret_inline_policy(&parse_expr(expr).expect("parsing error"))
+    }
+
+    #[test]
+    fn constructs_entities() {
+        assert_eq!(
+            eval(r#"entityFromString("Wallet", "0xab")"#).unwrap(),
+            eval(r#"Wallet::"0xab""#).unwrap()
+        );
+        assert_eq!(
+            eval(r#"entityFromString("Banyan::Wallet", "0xab") == Banyan::Wallet::"0xab""#)
+                .unwrap(),
+            Value::from(true)
+        );
+        assert_eq!(
+            eval(r#"entityFromString("test_entity_type", "foo") in test_entity_type::"foo""#)
+                .unwrap(),
+            Value::from(true)
+        );
+        // the id is used verbatim
+        assert_eq!(
+            eval(r#"entityFromString("Wallet", "a \"quoted\" id") == Wallet::"a \"quoted\" id""#)
+                .unwrap(),
+            Value::from(true)
+        );
+    }
+
+    #[test]
+    fn invalid_entity_type() {
+        for ty in ["", "Wallet::", "not a type", " Wallet"] {
+            let expr = format!(r#"entityFromString("{ty}", "0xab")"#);
+            match eval(&expr) {
+                Err(e) => match e.error_kind() {
+                    EvaluationErrorKind::FailedExtensionFunctionApplication { msg, .. } => {
+                        assert_eq!(msg, &format!("`{ty}` is not a valid entity type name"));
+                    }
+                    _ => panic!("Expected an extension error for {expr}, got {e:?}"),
+                },
+                Ok(v) => panic!("Expected an extension error for {expr}, got {v:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn not_strings() {
+        for expr in [
+            r#"entityFromString(1, "0xab")"#,
+            r#"entityFromString("Wallet", Wallet::"0xab")"#,
+        ] {
+            match eval(expr) {
+                Err(e) => match e.error_kind() {
+                    EvaluationErrorKind::TypeError { expected, .. } => {
+                        assert_eq!(expected, &vec![Type::String]);
+                    }
+                    _ => panic!("Expected a type error for {expr}, got {e:?}"),
+                },
+                Ok(v) => panic!("Expected a type error for {expr}, got {v:?}"),
+            }
+        }
+    }
+}
@@ -0,0 +1,325 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! This module contains the Cedar 'exposure' extension.
+//!
+//! An `exposure` value holds how much of one token has been sent to each
+//! counterparty, e.g. `exposure("0xabc...=1000,0xdef...=250")`, so that
+//! policies can cap it, e.g.
+//! `context.exposure.to(principal.dest).u256LessThanOrEqual(u256("5000"))`.
+
+use crate::ast::{
+    CallStyle, Extension, ExtensionFunction, ExtensionOutputValue, ExtensionValue,
+    ExtensionValueWithArgs, Name, StaticallyTyped, Type, Value,
+};
+use crate::entities::SchemaType;
+use crate::evaluator;
+use ethers::types::U256;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use thiserror::Error;
+
+/// The amounts of one token sent to each counterparty, keyed by their
+/// lowercased address
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Default)]
+pub struct Exposure {
+    amounts: BTreeMap<String, U256>,
+}
+
+// PANIC SAFETY All `Name`s in here are valid `Name`s
+#[allow(clippy::expect_used)]
+mod names {
+    use super::{Name, EXTENSION_NAME};
+    // PANIC SAFETY all of the names here are valid names
+    lazy_static::lazy_static! {
+        pub static ref EXPOSURE_FROM_STR_NAME : Name = Name::parse_unqualified_name(EXTENSION_NAME).expect("should be a valid identifier");
+        pub static ref TO : Name = Name::parse_unqualified_name("to").expect("should be a valid identifier");
+        pub static ref TOTAL_EXPOSURE : Name = Name::parse_unqualified_name("totalExposure").expect("should be a valid identifier");
+        pub static ref U256 : Name = Name::parse_unqualified_name("u256").expect("should be a valid identifier");
+    }
+}
+
+/// Potential errors when working with exposure values. Note that these are
+/// converted to evaluator::Err::ExtensionErr (which takes a string argument)
+/// before being reported to users.
+#[derive(Debug, Error)]
+enum Error {
+    /// Error parsing the input string as an exposure value
+    #[error("input string is not a well-formed exposure value: {0}")]
+    FailedParse(String),
+
+    /// The amounts sent to a counterparty don't fit in a `u256`
+    #[error("exposure to `{0}` overflows")]
+    Overflow(String),
+}
+
+impl Exposure {
+    /// The Cedar typename of exposure values
+    fn typename() -> Name {
+        names::EXPOSURE_FROM_STR_NAME.clone()
+    }
+
+    /// Convert a string of the form `address=amount,address=amount` into an
+    /// `Exposure` value, adding up the amounts of repeated addresses, which
+    /// are compared ignoring case. The empty string is no exposure at all.
+    /// Whitespace around each component is ignored.
+    fn from_str(str: impl AsRef<str>) -> Result<Self, Error> {
+        let str = str.as_ref();
+        let fail = || Error::FailedParse(str.to_owned());
+        let mut exposure = Self::default();
+        if str.trim().is_empty() {
+            return Ok(exposure);
+        }
+        for entry in str.split(',') {
+            let (address, amount) = entry.split_once('=').ok_or_else(fail)?;
+            let (address, amount) = (address.trim(), amount.trim());
+            if address.is_empty() || amount.is_empty() {
+                return Err(fail());
+            }
+            if !amount.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(fail());
+            }
+            let amount = U256::from_dec_str(amount).map_err(|_| fail())?;
+            exposure.add(address, amount)?;
+        }
+        Ok(exposure)
+    }
+
+    /// Add `amount` to the exposure to `address`
+    fn add(&mut self, address: &str, amount: U256) -> Result<(), Error> {
+        let address = address.to_lowercase();
+        let total = self.amounts.entry(address.clone()).or_default();
+        *total = total.checked_add(amount).ok_or(Error::Overflow(address))?;
+        Ok(())
+    }
+
+    /// The amount sent to `address`
+    pub fn to(&self, address: &str) -> U256 {
+        self.amounts
+            .get(&address.to_lowercase())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// The amount sent to all counterparties, saturating at the largest
+    /// `u256`
+    pub fn total(&self) -> U256 {
+        self.amounts
+            .values()
+            .fold(U256::zero(), |total, amount| total.saturating_add(*amount))
+    }
+}
+
+impl std::fmt::Display for Exposure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let entries: Vec<_> = self
+            .amounts
+            .iter()
+            .map(|(address, amount)| format!("{address}={amount}"))
+            .collect();
+        write!(f, "{}", entries.join(","))
+    }
+}
+
+impl ExtensionValue for Exposure {
+    fn typename(&self) -> Name {
+        Self::typename()
+    }
+}
+
+const EXTENSION_NAME: &str = "exposure";
+
+fn extension_err(msg: impl Into<String>) -> evaluator::EvaluationError {
+    evaluator::EvaluationError::failed_extension_function_application(
+        names::EXPOSURE_FROM_STR_NAME.clone(),
+        msg.into(),
+    )
+}
+
+/// Cedar function that constructs an `exposure` Cedar type from a
+/// Cedar string
+fn exposure_from_str(arg: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let str = arg.get_as_string()?;
+    let exposure = Exposure::from_str(str.as_str()).map_err(|e| extension_err(e.to_string()))?;
+    let function_name = names::EXPOSURE_FROM_STR_NAME.clone();
+    let e = ExtensionValueWithArgs::new(Arc::new(exposure), vec![arg.into()], function_name);
+    Ok(Value::ExtensionValue(Arc::new(e)).into())
+}
+
+/// Check that `v` is an exposure type and, if it is, return the wrapped value
+fn as_exposure(v: &Value) -> Result<&Exposure, evaluator::EvaluationError> {
+    match v {
+        Value::ExtensionValue(ev) if ev.typename() == Exposure::typename() => {
+            // PANIC SAFETY Conditional above performs a typecheck
+            #[allow(clippy::expect_used)]
+            let exposure = ev
+                .value()
+                .as_any()
+                .downcast_ref::<Exposure>()
+                .expect("already typechecked, so this downcast should succeed");
+            Ok(exposure)
+        }
+        _ => Err(evaluator::EvaluationError::type_error(
+            vec![Type::Extension {
+                name: Exposure::typename(),
+            }],
+            v.type_of(),
+        )),
+    }
+}
+
+/// Cedar function that returns the amount sent to a counterparty, as a Cedar
+/// `u256`
+fn to(exposure: Value, address: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let exposure = as_exposure(&exposure)?;
+    let amount = exposure.to(address.get_as_string()?);
+    Ok(super::u256::u256_value(amount).into())
+}
+
+/// Cedar function that returns the amount sent to all counterparties, as a
+/// Cedar `u256`
+fn total_exposure(exposure: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let exposure = as_exposure(&exposure)?;
+    Ok(super::u256::u256_value(exposure.total()).into())
+}
+
+/// Construct the extension
+pub fn extension() -> Extension {
+    let exposure_type = SchemaType::Extension {
+        name: Exposure::typename(),
+    };
+    let u256_type = SchemaType::Extension {
+        name: names::U256.clone(),
+    };
+    Extension::new(
+        names::EXPOSURE_FROM_STR_NAME.clone(),
+        vec![
+            ExtensionFunction::unary(
+                names::EXPOSURE_FROM_STR_NAME.clone(),
+                CallStyle::FunctionStyle,
+                Box::new(exposure_from_str),
+                exposure_type.clone(),
+                Some(SchemaType::String),
+            ),
+            ExtensionFunction::binary(
+                names::TO.clone(),
+                CallStyle::MethodStyle,
+                Box::new(to),
+                u256_type.clone(),
+                (Some(exposure_type.clone()), Some(SchemaType::String)),
+            ),
+            ExtensionFunction::unary(
+                names::TOTAL_EXPOSURE.clone(),
+                CallStyle::MethodStyle,
+                Box::new(total_exposure),
+                u256_type,
+                Some(exposure_type),
+            ),
+        ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::test::{basic_entities, basic_request};
+    use crate::evaluator::Evaluator;
+    use crate::extensions::Extensions;
+    use crate::parser::parse_expr;
+
+    /// Asserts that a `Result` is an `Err::ExtensionErr` with our extension name
+    fn assert_exposure_err<T: std::fmt::Debug>(res: evaluator::Result<T>) {
+        match res {
+            Err(e) => match e.error_kind() {
+                evaluator::EvaluationErrorKind::FailedExtensionFunctionApplication {
+                    extension_name,
+                    ..
+                } => {
+                    assert_eq!(
+                        *extension_name,
+                        Name::parse_unqualified_name("exposure")
+                            .expect("should be a valid identifier")
+                    )
+                }
+                _ => panic!("Expected an exposure ExtensionErr, got {:?}", e),
+            },
+            Ok(v) => panic!("Expected an exposure ExtensionErr, got {:?}", v),
+        }
+    }
+
+    #[test]
+    fn parsing() {
+        let exposure = Exposure::from_str("0xAbC=100, 0xdef = 5,0xabc=1").unwrap();
+        assert_eq!(exposure.to("0xabc"), U256::from(101));
+        assert_eq!(exposure.to("0xABC"), U256::from(101));
+        assert_eq!(exposure.to("0xdef"), U256::from(5));
+        assert_eq!(exposure.to("0x123"), U256::zero());
+        assert_eq!(exposure.total(), U256::from(106));
+        assert_eq!(exposure.to_string(), "0xabc=101,0xdef=5");
+
+        assert_eq!(Exposure::from_str("").unwrap(), Exposure::default());
+        assert_eq!(Exposure::from_str(" ").unwrap().total(), U256::zero());
+        for str in ["0xabc", "0xabc=", "=1", "0xabc=1,", "0xabc=-1", "0xabc=1.5"] {
+            assert!(Exposure::from_str(str).is_err(), "{str}");
+        }
+        assert!(matches!(
+            Exposure::from_str(format!("0xabc={},0xABC=1", U256::MAX)),
+            Err(Error::Overflow(address)) if address == "0xabc"
+        ));
+        // the total saturates
+        let max = Exposure::from_str(format!("0xabc={},0xdef=1", U256::MAX)).unwrap();
+        assert_eq!(max.total(), U256::MAX);
+    }
+
+    #[test]
+    fn exposure_methods() {
+        let ext_array = [extension(), super::super::u256::extension()];
+        let exts = Extensions::specific_extensions(&ext_array);
+        let request = basic_request();
+        let entities = basic_entities();
+        let eval = Evaluator::new(&request, &entities, &exts).unwrap();
+
+        let eval_str =
+            |src: &str| eval.interpret_inline_policy(&parse_expr(src).expect("parsing error"));
+        assert_eq!(
+            eval_str(r#"exposure("0xabc=100,0xdef=5").to("0xABC") == u256("100")"#),
+            Ok(Value::from(true))
+        );
+        assert_eq!(
+            eval_str(r#"exposure("0xabc=100").to("0xdef") == u256("0")"#),
+            Ok(Value::from(true))
+        );
+        assert_eq!(
+            eval_str(r#"exposure("").totalExposure() == u256("0")"#),
+            Ok(Value::from(true))
+        );
+        assert_eq!(
+            eval_str(r#"exposure("0xabc=100,0xdef=5").totalExposure() == u256("105")"#),
+            Ok(Value::from(true))
+        );
+        assert_eq!(
+            eval_str(r#"exposure("0xABC=1,0xabc=1") == exposure(" 0xabc = 2 ")"#),
+            Ok(Value::from(true))
+        );
+        assert_eq!(
+            eval_str(r#"exposure("0xabc=100").to("0xabc").u256LessThanOrEqual(u256("99"))"#),
+            Ok(Value::from(false))
+        );
+
+        assert_exposure_err(eval_str(r#"exposure("0xabc")"#));
+        assert!(eval_str(r#"exposure("0xabc=1").to(1)"#).is_err());
+    }
+}
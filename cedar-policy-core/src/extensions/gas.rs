@@ -0,0 +1,341 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+//! This module contains the Cedar 'gas' extension.
+//!
+//! Its functions take a record describing a transaction's gas, as `u256`
+//! values in wei or gas units:
+//! - `gasLimit`
+//! - `maxFeePerGas` and `maxPriorityFeePerGas`, for EIP-1559 transactions
+//! - `gasPrice`, for legacy transactions
+//! - `baseFeePerGas`, the base fee of the block the transaction is expected
+//!   to be included in
+//!
+//! and compute what the transaction may pay, so that e.g. "never spend more
+//! than 0.05 ETH on gas" is a single condition:
+//! `context.gas.maxTxCostWei().u256LessThanOrEqual(u256("50000000000000000"))`.
+
+use crate::ast::{
+    CallStyle, Extension, ExtensionFunction, ExtensionOutputValue, Name, StaticallyTyped, Type,
+    Value,
+};
+use crate::entities::SchemaType;
+use crate::evaluator;
+use ethers::types::U256;
+use smol_str::SmolStr;
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+use super::u256::{as_u256, u256_value};
+
+// PANIC SAFETY All `Name`s in here are valid `Name`s
+#[allow(clippy::expect_used)]
+mod names {
+    use super::{Name, EXTENSION_NAME};
+    // PANIC SAFETY all of the names here are valid names
+    lazy_static::lazy_static! {
+        pub static ref GAS : Name = Name::parse_unqualified_name(EXTENSION_NAME).expect("should be a valid identifier");
+        pub static ref MAX_TX_COST_WEI : Name = Name::parse_unqualified_name("maxTxCostWei").expect("should be a valid identifier");
+        pub static ref EFFECTIVE_GAS_PRICE : Name = Name::parse_unqualified_name("effectiveGasPrice").expect("should be a valid identifier");
+        pub static ref EFFECTIVE_PRIORITY_FEE : Name = Name::parse_unqualified_name("effectivePriorityFee").expect("should be a valid identifier");
+        pub static ref U256 : Name = Name::parse_unqualified_name("u256").expect("should be a valid identifier");
+    }
+}
+
+/// Potential errors when working with gas records. Note that these are
+/// converted to evaluator::Err::ExtensionErr (which takes a string argument)
+/// before being reported to users.
+#[derive(Debug, Error)]
+enum Error {
+    /// A required attribute is missing
+    #[error("gas record has no `{0}` attribute")]
+    MissingAttribute(&'static str),
+
+    /// The record has neither `maxFeePerGas` nor `gasPrice`
+    #[error("gas record has neither `maxFeePerGas` nor `gasPrice`")]
+    NoFee,
+
+    /// The cost doesn't fit in a u256
+    #[error("overflow when computing the transaction cost")]
+    Overflow,
+}
+
+const EXTENSION_NAME: &str = "gas";
+
+fn extension_err(msg: impl Into<String>) -> evaluator::EvaluationError {
+    evaluator::EvaluationError::failed_extension_function_application(
+        names::GAS.clone(),
+        msg.into(),
+    )
+}
+
+/// The fees a transaction may pay
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Fees {
+    /// An EIP-1559 transaction
+    Eip1559 {
+        max_fee_per_gas: U256,
+        max_priority_fee_per_gas: U256,
+    },
+    /// A legacy transaction, which pays `gas_price` regardless of the base fee
+    Legacy { gas_price: U256 },
+}
+
+/// A gas record
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Gas {
+    gas_limit: Option<U256>,
+    fees: Fees,
+    base_fee_per_gas: Option<U256>,
+}
+
+impl Gas {
+    fn from_value(v: &Value) -> evaluator::Result<Self> {
+        let record = match v {
+            Value::Record(record) => record,
+            _ => {
+                return Err(evaluator::EvaluationError::type_error(
+                    vec![Type::Record],
+                    v.type_of(),
+                ))
+            }
+        };
+        let max_fee_per_gas = attr(record, "maxFeePerGas")?;
+        let fees = match (max_fee_per_gas, attr(record, "gasPrice")?) {
+            (Some(max_fee_per_gas), _) => Fees::Eip1559 {
+                max_fee_per_gas,
+                max_priority_fee_per_gas: attr(record, "maxPriorityFeePerGas")?
+                    .unwrap_or(max_fee_per_gas),
+            },
+            (None, Some(gas_price)) => Fees::Legacy { gas_price },
+            (None, None) => return Err(extension_err(Error::NoFee.to_string())),
+        };
+        Ok(Self {
+            gas_limit: attr(record, "gasLimit")?,
+            fees,
+            base_fee_per_gas: attr(record, "baseFeePerGas")?,
+        })
+    }
+
+    /// The most the transaction may pay per gas
+    fn max_price(&self) -> U256 {
+        match self.fees {
+            Fees::Eip1559 {
+                max_fee_per_gas, ..
+            } => max_fee_per_gas,
+            Fees::Legacy { gas_price } => gas_price,
+        }
+    }
+
+    /// The price per gas the transaction pays at `base_fee_per_gas`
+    fn effective_gas_price(&self) -> Result<U256, Error> {
+        match self.fees {
+            Fees::Eip1559 {
+                max_fee_per_gas,
+                max_priority_fee_per_gas,
+            } => {
+                let base_fee = self
+                    .base_fee_per_gas
+                    .ok_or(Error::MissingAttribute("baseFeePerGas"))?;
+                Ok(base_fee
+                    .saturating_add(max_priority_fee_per_gas)
+                    .min(max_fee_per_gas))
+            }
+            Fees::Legacy { gas_price } => Ok(gas_price),
+        }
+    }
+
+    /// The fee per gas the block producer receives at `base_fee_per_gas`
+    fn effective_priority_fee(&self) -> Result<U256, Error> {
+        let base_fee = self
+            .base_fee_per_gas
+            .ok_or(Error::MissingAttribute("baseFeePerGas"))?;
+        Ok(self.effective_gas_price()?.saturating_sub(base_fee))
+    }
+
+    /// The most the transaction may pay in total
+    fn max_tx_cost_wei(&self) -> Result<U256, Error> {
+        let gas_limit = self.gas_limit.ok_or(Error::MissingAttribute("gasLimit"))?;
+        gas_limit
+            .checked_mul(self.max_price())
+            .ok_or(Error::Overflow)
+    }
+}
+
+/// The `u256` value of an optional attribute of a gas record
+fn attr(record: &BTreeMap<SmolStr, Value>, name: &str) -> evaluator::Result<Option<U256>> {
+    record.get(name).map(as_u256).transpose()
+}
+
+/// Cedar function that returns the most a transaction may pay in total, in
+/// wei, as a `u256`
+fn max_tx_cost_wei(gas: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let cost = Gas::from_value(&gas)?
+        .max_tx_cost_wei()
+        .map_err(|e| extension_err(e.to_string()))?;
+    Ok(u256_value(cost).into())
+}
+
+/// Cedar function that returns the price per gas a transaction pays at the
+/// base fee, in wei, as a `u256`
+fn effective_gas_price(gas: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let price = Gas::from_value(&gas)?
+        .effective_gas_price()
+        .map_err(|e| extension_err(e.to_string()))?;
+    Ok(u256_value(price).into())
+}
+
+/// Cedar function that returns the fee per gas the block producer receives
+/// at the base fee, in wei, as a `u256`
+fn effective_priority_fee(gas: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let fee = Gas::from_value(&gas)?
+        .effective_priority_fee()
+        .map_err(|e| extension_err(e.to_string()))?;
+    Ok(u256_value(fee).into())
+}
+
+/// Construct the extension
+pub fn extension() -> Extension {
+    let u256_type = SchemaType::Extension {
+        name: names::U256.clone(),
+    };
+    Extension::new(
+        names::GAS.clone(),
+        vec![
+            ExtensionFunction::unary(
+                names::MAX_TX_COST_WEI.clone(),
+                CallStyle::MethodStyle,
+                Box::new(max_tx_cost_wei),
+                u256_type.clone(),
+                None,
+            ),
+            ExtensionFunction::unary(
+                names::EFFECTIVE_GAS_PRICE.clone(),
+                CallStyle::MethodStyle,
+                Box::new(effective_gas_price),
+                u256_type.clone(),
+                None,
+            ),
+            ExtensionFunction::unary(
+                names::EFFECTIVE_PRIORITY_FEE.clone(),
+                CallStyle::MethodStyle,
+                Box::new(effective_priority_fee),
+                u256_type,
+                None,
+            ),
+        ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::test::{basic_entities, basic_request};
+    use crate::evaluator::Evaluator;
+    use crate::extensions::Extensions;
+    use crate::parser::parse_expr;
+
+    fn eval(expr: &str) -> evaluator::Result<Value> {
+        let ext_array = [extension(), super::super::u256::extension()];
+        let exts = Extensions::specific_extensions(&ext_array);
+        let request = basic_request();
+        let entities = basic_entities();
+        let eval = Evaluator::new(&request, &entities, &exts).unwrap();
+        eval.interpret_inline_policy(&parse_expr(expr).expect("parsing error"))
+    }
+
+    fn assert_gas_err(res: evaluator::Result<Value>) {
+        match res {
+            Err(e) => match e.error_kind() {
+                evaluator::EvaluationErrorKind::FailedExtensionFunctionApplication {
+                    extension_name,
+                    ..
+                } => assert_eq!(*extension_name, names::GAS.clone()),
+                _ => panic!("Expected a gas ExtensionErr, got {:?}", e),
+            },
+            Ok(v) => panic!("Expected a gas ExtensionErr, got {:?}", v),
+        }
+    }
+
+    const EIP1559: &str = r#"{
+        gasLimit: u256("21000"),
+        maxFeePerGas: u256("30000000000"),
+        maxPriorityFeePerGas: u256("2000000000"),
+        baseFeePerGas: u256("20000000000")
+    }"#;
+
+    #[test]
+    fn eip1559() {
+        let is = |function: &str, expected: &str| {
+            eval(&format!(r#"{EIP1559}.{function}() == u256("{expected}")"#))
+        };
+        assert_eq!(is("maxTxCostWei", "630000000000000"), Ok(Value::from(true)));
+        assert_eq!(
+            is("effectiveGasPrice", "22000000000"),
+            Ok(Value::from(true))
+        );
+        assert_eq!(
+            is("effectivePriorityFee", "2000000000"),
+            Ok(Value::from(true))
+        );
+
+        // the max fee caps the price when the base fee rises
+        let congested = EIP1559.replace("20000000000", "29000000000");
+        assert_eq!(
+            eval(&format!(
+                r#"{congested}.effectivePriorityFee() == u256("1000000000")"#
+            )),
+            Ok(Value::from(true))
+        );
+        assert_eq!(
+            eval(&format!(
+                r#"{EIP1559}.maxTxCostWei().u256LessThanOrEqual(u256("50000000000000000"))"#
+            )),
+            Ok(Value::from(true))
+        );
+    }
+
+    #[test]
+    fn legacy() {
+        let legacy = r#"{ gasLimit: u256("100000"), gasPrice: u256("25000000000"), baseFeePerGas: u256("20000000000") }"#;
+        let is = |function: &str, expected: &str| {
+            eval(&format!(r#"{legacy}.{function}() == u256("{expected}")"#))
+        };
+        assert_eq!(
+            is("maxTxCostWei", "2500000000000000"),
+            Ok(Value::from(true))
+        );
+        assert_eq!(
+            is("effectiveGasPrice", "25000000000"),
+            Ok(Value::from(true))
+        );
+        assert_eq!(
+            is("effectivePriorityFee", "5000000000"),
+            Ok(Value::from(true))
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_gas_err(eval(r#"{ gasLimit: u256("1") }.maxTxCostWei()"#));
+        assert_gas_err(eval(r#"{ maxFeePerGas: u256("1") }.maxTxCostWei()"#));
+        assert_gas_err(eval(r#"{ maxFeePerGas: u256("1") }.effectiveGasPrice()"#));
+        assert_gas_err(eval(
+            r#"{ gasLimit: u256("115792089237316195423570985008687907853269984665640564039457584007913129639935"), gasPrice: u256("2") }.maxTxCostWei()"#,
+        ));
+        assert!(eval(r#"{ gasLimit: 1, gasPrice: u256("1") }.maxTxCostWei()"#).is_err());
+        assert!(eval(r#""gas".maxTxCostWei()"#).is_err());
+    }
+}
@@ -0,0 +1,412 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+//! This module contains the Cedar 'hash' extension.
+//!
+//! `keccak256(s)` and `sha256(s)` hash the UTF-8 bytes of a string, and
+//! return the digest as a `u256`, read big-endian.
+//!
+//! `poseidonHash(a, b)` is the Poseidon hash of two `u256` values which are
+//! elements of the BN254 scalar field, as computed by circomlib's
+//! `Poseidon(2)` template. Policies which must be re-checked inside a SNARK
+//! circuit can use it where Keccak would be prohibitively expensive. The
+//! round constants and MDS matrix are generated with the Grain LFSR, as in
+//! the Poseidon reference implementation, with circomlib's parameters: the
+//! S-box `x^5`, 8 full rounds, and 57 partial rounds. No trusted setup is
+//! involved.
+
+use crate::ast::{CallStyle, Extension, ExtensionFunction, ExtensionOutputValue, Value};
+use crate::entities::SchemaType;
+use crate::evaluator;
+use ethers::types::{U256, U512};
+use ethers::utils::keccak256;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use super::u256::{as_u256, u256_value};
+
+// PANIC SAFETY All `Name`s in here are valid `Name`s
+#[allow(clippy::expect_used)]
+mod names {
+    use crate::ast::Name;
+    // PANIC SAFETY all of the names here are valid names
+    lazy_static::lazy_static! {
+        pub static ref HASH : Name = Name::parse_unqualified_name("hash").expect("should be a valid identifier");
+        pub static ref KECCAK256 : Name = Name::parse_unqualified_name("keccak256").expect("should be a valid identifier");
+        pub static ref SHA256 : Name = Name::parse_unqualified_name("sha256").expect("should be a valid identifier");
+        pub static ref POSEIDON_HASH : Name = Name::parse_unqualified_name("poseidonHash").expect("should be a valid identifier");
+        pub static ref U256 : Name = Name::parse_unqualified_name("u256").expect("should be a valid identifier");
+    }
+}
+
+/// Potential errors when hashing. Note that these are converted to
+/// evaluator::Err::ExtensionErr (which takes a string argument) before being
+/// reported to users.
+#[derive(Debug, Error)]
+enum Error {
+    /// A Poseidon input is not a field element
+    #[error("{0} is not an element of the BN254 scalar field")]
+    NotAFieldElement(U256),
+}
+
+fn extension_err(msg: impl Into<String>) -> evaluator::EvaluationError {
+    evaluator::EvaluationError::failed_extension_function_application(
+        names::HASH.clone(),
+        msg.into(),
+    )
+}
+
+/// Cedar function that returns the Keccak-256 hash of a string, as a `u256`
+fn keccak256_of(s: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let digest = keccak256(s.get_as_string()?.as_bytes());
+    Ok(u256_value(U256::from_big_endian(&digest)).into())
+}
+
+/// Cedar function that returns the SHA-256 hash of a string, as a `u256`
+fn sha256_of(s: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let digest = Sha256::digest(s.get_as_string()?.as_bytes());
+    Ok(u256_value(U256::from_big_endian(&digest)).into())
+}
+
+/// Cedar function that returns the Poseidon hash of two field elements, as a
+/// `u256`
+fn poseidon_hash(a: Value, b: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let inputs = [as_u256(&a)?, as_u256(&b)?];
+    if let Some(input) = inputs.iter().find(|input| **input >= poseidon::MODULUS) {
+        return Err(extension_err(Error::NotAFieldElement(*input).to_string()));
+    }
+    Ok(u256_value(poseidon::POSEIDON_2.hash(&inputs)).into())
+}
+
+/// Poseidon over the BN254 scalar field, with circomlib's parameters
+pub(crate) mod poseidon {
+    use super::{U256, U512};
+
+    /// The order of the BN254 scalar field
+    pub const MODULUS: U256 = U256([
+        0x43e1_f593_f000_0001,
+        0x2833_e848_79b9_7091,
+        0xb850_45b6_8181_585d,
+        0x3064_4e72_e131_a029,
+    ]);
+    /// The bits in a field element
+    const FIELD_BITS: u32 = 254;
+    const FULL_ROUNDS: usize = 8;
+    /// The partial rounds for each number of inputs, from one, as in
+    /// circomlib
+    const PARTIAL_ROUNDS: [usize; 16] = [
+        56, 57, 56, 60, 60, 63, 64, 63, 60, 66, 60, 65, 70, 60, 64, 68,
+    ];
+
+    lazy_static::lazy_static! {
+        /// The Poseidon hash of two inputs
+        pub static ref POSEIDON_2: Poseidon = Poseidon::new(2);
+    }
+
+    fn add(a: U256, b: U256) -> U256 {
+        // both are below 2^254, so the sum doesn't overflow
+        let sum = a + b;
+        if sum >= MODULUS {
+            sum - MODULUS
+        } else {
+            sum
+        }
+    }
+
+    fn mul(a: U256, b: U256) -> U256 {
+        let product = a.full_mul(b) % U512::from(MODULUS);
+        // PANIC SAFETY: the remainder is less than the modulus, so it fits
+        #[allow(clippy::expect_used)]
+        U256::try_from(product).expect("a field element fits in 256 bits")
+    }
+
+    fn pow(base: U256, exponent: U256) -> U256 {
+        let mut result = U256::one();
+        for bit in (0..exponent.bits()).rev() {
+            result = mul(result, result);
+            if exponent.bit(bit) {
+                result = mul(result, base);
+            }
+        }
+        result
+    }
+
+    fn inverse(a: U256) -> U256 {
+        pow(a, MODULUS - 2)
+    }
+
+    fn sbox(a: U256) -> U256 {
+        let square = mul(a, a);
+        mul(mul(square, square), a)
+    }
+
+    /// The Grain LFSR which generates the round constants and MDS matrix,
+    /// as in the reference `generate_parameters_grain.sage`
+    struct Grain {
+        /// The 80 bits of state, the oldest in the most significant place
+        state: u128,
+    }
+
+    impl Grain {
+        const MASK: u128 = (1 << 80) - 1;
+
+        fn new(width: usize, partial_rounds: usize) -> Self {
+            let mut grain = Self { state: 0 };
+            // a prime field, the S-box x^alpha, the field size, the width,
+            // and the rounds, followed by ones
+            for (value, bits) in [
+                (1, 2),
+                (0, 4),
+                (u128::from(FIELD_BITS), 12),
+                (width as u128, 12),
+                (FULL_ROUNDS as u128, 10),
+                (partial_rounds as u128, 10),
+                ((1 << 30) - 1, 30),
+            ] {
+                grain.state = (grain.state << bits) | value;
+            }
+            for _ in 0..160 {
+                grain.clock();
+            }
+            grain
+        }
+
+        /// The bit `i` places from the oldest
+        fn tap(&self, i: u32) -> u128 {
+            (self.state >> (79 - i)) & 1
+        }
+
+        fn clock(&mut self) -> bool {
+            let bit = self.tap(62)
+                ^ self.tap(51)
+                ^ self.tap(38)
+                ^ self.tap(23)
+                ^ self.tap(13)
+                ^ self.tap(0);
+            self.state = ((self.state << 1) | bit) & Self::MASK;
+            bit == 1
+        }
+
+        /// The next output bit: of each pair of bits, the second is output
+        /// if the first is set, and otherwise discarded
+        fn bit(&mut self) -> bool {
+            loop {
+                let keep = self.clock();
+                let bit = self.clock();
+                if keep {
+                    return bit;
+                }
+            }
+        }
+
+        /// The next `FIELD_BITS` bits, most significant first
+        fn bits(&mut self) -> U256 {
+            (0..FIELD_BITS).fold(U256::zero(), |value, _| {
+                (value << 1) | U256::from(u8::from(self.bit()))
+            })
+        }
+
+        /// The next field element, sampled by rejection
+        fn element(&mut self) -> U256 {
+            loop {
+                let value = self.bits();
+                if value < MODULUS {
+                    return value;
+                }
+            }
+        }
+
+        /// A Cauchy MDS matrix of `width` rows
+        fn mds(&mut self, width: usize) -> Vec<Vec<U256>> {
+            loop {
+                let mut values: Vec<U256> = (0..2 * width)
+                    .map(|_| add(self.bits(), U256::zero()))
+                    .collect();
+                let mut sorted = values.clone();
+                sorted.sort_unstable();
+                sorted.dedup();
+                if sorted.len() != values.len() {
+                    continue;
+                }
+                let ys = values.split_off(width);
+                let xs = values;
+                if xs.iter().any(|x| ys.iter().any(|y| add(*x, *y).is_zero())) {
+                    continue;
+                }
+                return xs
+                    .iter()
+                    .map(|x| ys.iter().map(|y| inverse(add(*x, *y))).collect())
+                    .collect();
+            }
+        }
+    }
+
+    /// The Poseidon permutation for a number of inputs
+    #[derive(Debug)]
+    pub struct Poseidon {
+        partial_rounds: usize,
+        round_constants: Vec<U256>,
+        mds: Vec<Vec<U256>>,
+    }
+
+    impl Poseidon {
+        /// Generate the parameters for hashing `inputs` field elements
+        fn new(inputs: usize) -> Self {
+            let width = inputs + 1;
+            let partial_rounds = PARTIAL_ROUNDS.get(inputs - 1).copied().unwrap_or_default();
+            let mut grain = Grain::new(width, partial_rounds);
+            let round_constants = (0..(FULL_ROUNDS + partial_rounds) * width)
+                .map(|_| grain.element())
+                .collect();
+            let mds = grain.mds(width);
+            Self {
+                partial_rounds,
+                round_constants,
+                mds,
+            }
+        }
+
+        /// The hash of `inputs`, which must be field elements
+        pub fn hash(&self, inputs: &[U256]) -> U256 {
+            let mut state = vec![U256::zero()];
+            state.extend_from_slice(inputs);
+            let half = FULL_ROUNDS / 2;
+            for (round, constants) in self.round_constants.chunks(state.len()).enumerate() {
+                for (element, constant) in state.iter_mut().zip(constants) {
+                    *element = add(*element, *constant);
+                }
+                if round < half || round >= half + self.partial_rounds {
+                    for element in &mut state {
+                        *element = sbox(*element);
+                    }
+                } else if let Some(first) = state.first_mut() {
+                    *first = sbox(*first);
+                }
+                state = self
+                    .mds
+                    .iter()
+                    .map(|row| {
+                        row.iter()
+                            .zip(&state)
+                            .fold(U256::zero(), |sum, (m, element)| {
+                                add(sum, mul(*m, *element))
+                            })
+                    })
+                    .collect();
+            }
+            state.first().copied().unwrap_or_default()
+        }
+    }
+}
+
+/// Construct the extension
+pub fn extension() -> Extension {
+    let u256_type = SchemaType::Extension {
+        name: names::U256.clone(),
+    };
+    // The digests take strings, but leaving their argument types unspecified
+    // keeps them from being taken for `u256` constructors
+    Extension::new(
+        names::HASH.clone(),
+        vec![
+            ExtensionFunction::unary(
+                names::KECCAK256.clone(),
+                CallStyle::FunctionStyle,
+                Box::new(keccak256_of),
+                u256_type.clone(),
+                None,
+            ),
+            ExtensionFunction::unary(
+                names::SHA256.clone(),
+                CallStyle::FunctionStyle,
+                Box::new(sha256_of),
+                u256_type.clone(),
+                None,
+            ),
+            ExtensionFunction::binary(
+                names::POSEIDON_HASH.clone(),
+                CallStyle::FunctionStyle,
+                Box::new(poseidon_hash),
+                u256_type.clone(),
+                (Some(u256_type.clone()), Some(u256_type)),
+            ),
+        ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::test::{basic_entities, basic_request};
+    use crate::evaluator::Evaluator;
+    use crate::extensions::Extensions;
+    use crate::parser::parse_expr;
+
+    fn eval(expr: &str) -> evaluator::Result<Value> {
+        let ext_array = [extension(), super::super::u256::extension()];
+        let exts = Extensions::specific_extensions(&ext_array);
+        let request = basic_request();
+        let entities = basic_entities();
+        let eval = Evaluator::new(&request, &entities, &exts).unwrap();
+        eval.interpret_inline_policy(&parse_expr(expr).expect("parsing error"))
+    }
+
+    fn u256_of(hex: &str) -> String {
+        U256::from_str_radix(hex, 16).unwrap().to_string()
+    }
+
+    #[test]
+    fn digests() {
+        // keccak256("") and sha256("abc")
+        let keccak = u256_of("c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470");
+        let sha = u256_of("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert_eq!(
+            eval(&format!(r#"keccak256("") == u256("{keccak}")"#)),
+            Ok(Value::from(true))
+        );
+        assert_eq!(
+            eval(&format!(r#"sha256("abc") == u256("{sha}")"#)),
+            Ok(Value::from(true))
+        );
+        assert!(eval("keccak256(1)").is_err());
+    }
+
+    #[test]
+    fn poseidon_matches_circomlib() {
+        // circomlibjs: poseidon([1, 2])
+        let expected = u256_of("115cc0f5e7d690413df64c6b9662e9cf2a3617f2743245519e19607a4417189a");
+        assert_eq!(
+            eval(&format!(
+                r#"poseidonHash(u256("1"), u256("2")) == u256("{expected}")"#
+            )),
+            Ok(Value::from(true))
+        );
+    }
+
+    #[test]
+    fn poseidon_rejects_non_field_elements() {
+        let modulus = poseidon::MODULUS.to_string();
+        match eval(&format!(r#"poseidonHash(u256("1"), u256("{modulus}"))"#)) {
+            Err(e) => match e.error_kind() {
+                evaluator::EvaluationErrorKind::FailedExtensionFunctionApplication {
+                    extension_name,
+                    ..
+                } => assert_eq!(*extension_name, names::HASH.clone()),
+                _ => panic!("Expected a hash ExtensionErr, got {:?}", e),
+            },
+            Ok(v) => panic!("Expected a hash ExtensionErr, got {:?}", v),
+        }
+    }
+}
@@ -0,0 +1,407 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+//! This module contains the Cedar 'healthFactor' extension.
+//!
+//! A lending position is a set of records, one per asset, of the form
+//! `{ collateral: u256, debt: u256, liquidationThreshold: Long }`, with the
+//! collateral and debt valued in the same base currency and the liquidation
+//! threshold in basis points. As in Aave, the health factor of a position is
+//! its collateral weighted by the liquidation thresholds over its debt:
+//! `healthFactor(positions)` computes it as a `decimal`, rounded down, and
+//! `healthFactorAfter(positions, change)` computes it once the collateral of
+//! `change` is withdrawn and its debt borrowed, e.g.
+//! `healthFactorAfter(principal.positions, context.change).lessThan(decimal("1.2"))`.
+//! A position without debt can't be liquidated, and its health factor is the
+//! largest `decimal`, as is any health factor too large for one.
+
+use crate::ast::{
+    CallStyle, Extension, ExtensionFunction, ExtensionOutputValue, Name, StaticallyTyped, Type,
+    Value,
+};
+use crate::entities::{AttributeType, SchemaType};
+use crate::evaluator;
+use ethers::types::{U256, U512};
+use smol_str::SmolStr;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use thiserror::Error;
+
+// PANIC SAFETY All `Name`s in here are valid `Name`s
+#[allow(clippy::expect_used)]
+mod names {
+    use super::{Name, EXTENSION_NAME};
+    // PANIC SAFETY all of the names here are valid names
+    lazy_static::lazy_static! {
+        pub static ref HEALTH_FACTOR : Name = Name::parse_unqualified_name(EXTENSION_NAME).expect("should be a valid identifier");
+        pub static ref HEALTH_FACTOR_AFTER : Name = Name::parse_unqualified_name("healthFactorAfter").expect("should be a valid identifier");
+        pub static ref U256 : Name = Name::parse_unqualified_name("u256").expect("should be a valid identifier");
+        pub static ref DECIMAL : Name = Name::parse_unqualified_name("decimal").expect("should be a valid identifier");
+    }
+}
+
+/// Potential errors when computing health factors. Note that these are
+/// converted to evaluator::Err::ExtensionErr (which takes a string argument)
+/// before being reported to users.
+#[derive(Debug, Error)]
+enum Error {
+    /// A liquidation threshold isn't between 0 and 100%
+    #[error("invalid liquidation threshold: {0} (must be between 0 and {BPS} basis points)")]
+    InvalidThreshold(i64),
+}
+
+const EXTENSION_NAME: &str = "healthFactor";
+
+/// One hundred percent, in basis points
+const BPS: i64 = 10_000;
+
+const COLLATERAL: &str = "collateral";
+const DEBT: &str = "debt";
+const LIQUIDATION_THRESHOLD: &str = "liquidationThreshold";
+
+fn extension_err(msg: impl Into<String>) -> evaluator::EvaluationError {
+    evaluator::EvaluationError::failed_extension_function_application(
+        names::HEALTH_FACTOR.clone(),
+        msg.into(),
+    )
+}
+
+/// The collateral and debt of a position in one asset
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Position {
+    collateral: U256,
+    debt: U256,
+    liquidation_threshold: U256,
+}
+
+impl Position {
+    /// Read a position from a Cedar record
+    fn from_value(v: &Value) -> evaluator::Result<Self> {
+        let record = as_record(v)?;
+        let attr = |name: &str| {
+            record.get(name).ok_or_else(|| {
+                evaluator::EvaluationError::record_attr_does_not_exist(
+                    name.into(),
+                    record.keys().cloned().collect(),
+                )
+            })
+        };
+        let collateral = super::u256::as_u256(attr(COLLATERAL)?)?;
+        let debt = super::u256::as_u256(attr(DEBT)?)?;
+        let threshold = attr(LIQUIDATION_THRESHOLD)?.get_as_long()?;
+        let liquidation_threshold = u64::try_from(threshold)
+            .ok()
+            .filter(|t| *t <= BPS.unsigned_abs())
+            .ok_or_else(|| extension_err(Error::InvalidThreshold(threshold).to_string()))?;
+        Ok(Self {
+            collateral,
+            debt,
+            liquidation_threshold: liquidation_threshold.into(),
+        })
+    }
+
+    /// The collateral weighted by the liquidation threshold, in basis points
+    fn weighted_collateral(&self) -> U512 {
+        self.collateral.full_mul(self.liquidation_threshold)
+    }
+}
+
+/// The weighted collateral and debt of a set of positions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Totals {
+    weighted_collateral: U512,
+    debt: U512,
+}
+
+impl Totals {
+    /// The totals of a Cedar set of positions
+    fn from_value(v: &Value) -> evaluator::Result<Self> {
+        let mut totals = Self::default();
+        for position in v.get_as_set()?.iter() {
+            let position = Position::from_value(position)?;
+            totals.weighted_collateral = totals
+                .weighted_collateral
+                .saturating_add(position.weighted_collateral());
+            totals.debt = totals.debt.saturating_add(position.debt.into());
+        }
+        Ok(totals)
+    }
+
+    /// The totals once `change`'s collateral is withdrawn and its debt
+    /// borrowed. Withdrawing more collateral than there is leaves none.
+    fn after(self, change: &Position) -> Self {
+        Self {
+            weighted_collateral: self
+                .weighted_collateral
+                .saturating_sub(change.weighted_collateral()),
+            debt: self.debt.saturating_add(change.debt.into()),
+        }
+    }
+
+    /// The health factor, scaled by `10^4` like a `decimal`, rounded down and
+    /// saturating. The weighted collateral is already scaled by `10^4`, since
+    /// thresholds are in basis points.
+    fn health_factor(&self) -> i64 {
+        if self.debt.is_zero() {
+            return i64::MAX;
+        }
+        i64::try_from(self.weighted_collateral / self.debt).unwrap_or(i64::MAX)
+    }
+}
+
+fn as_record(v: &Value) -> Result<&Arc<BTreeMap<SmolStr, Value>>, evaluator::EvaluationError> {
+    match v {
+        Value::Record(record) => Ok(record),
+        _ => Err(evaluator::EvaluationError::type_error(
+            vec![Type::Record],
+            v.type_of(),
+        )),
+    }
+}
+
+/// Cedar function that computes the health factor of a set of positions,
+/// returning a Cedar `decimal`
+fn health_factor(positions: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let totals = Totals::from_value(&positions)?;
+    Ok(super::decimal::decimal_value(totals.health_factor()).into())
+}
+
+/// Cedar function that computes the health factor of a set of positions once
+/// the collateral of a change is withdrawn and its debt borrowed, returning a
+/// Cedar `decimal`
+fn health_factor_after(positions: Value, change: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let totals = Totals::from_value(&positions)?.after(&Position::from_value(&change)?);
+    Ok(super::decimal::decimal_value(totals.health_factor()).into())
+}
+
+/// Construct the extension
+pub fn extension() -> Extension {
+    let u256_type = SchemaType::Extension {
+        name: names::U256.clone(),
+    };
+    let position_type = SchemaType::Record {
+        attrs: HashMap::from([
+            (
+                COLLATERAL.into(),
+                AttributeType::required(u256_type.clone()),
+            ),
+            (DEBT.into(), AttributeType::required(u256_type)),
+            (
+                LIQUIDATION_THRESHOLD.into(),
+                AttributeType::required(SchemaType::Long),
+            ),
+        ]),
+    };
+    let decimal_type = SchemaType::Extension {
+        name: names::DECIMAL.clone(),
+    };
+    Extension::new(
+        names::HEALTH_FACTOR.clone(),
+        vec![
+            ExtensionFunction::unary(
+                names::HEALTH_FACTOR.clone(),
+                CallStyle::FunctionStyle,
+                Box::new(health_factor),
+                decimal_type.clone(),
+                Some(SchemaType::Set {
+                    element_ty: Box::new(position_type.clone()),
+                }),
+            ),
+            ExtensionFunction::binary(
+                names::HEALTH_FACTOR_AFTER.clone(),
+                CallStyle::FunctionStyle,
+                Box::new(health_factor_after),
+                decimal_type,
+                (
+                    Some(SchemaType::Set {
+                        element_ty: Box::new(position_type.clone()),
+                    }),
+                    Some(position_type),
+                ),
+            ),
+        ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::test::{basic_entities, basic_request};
+    use crate::evaluator::{EvaluationErrorKind, Evaluator};
+    use crate::extensions::Extensions;
+    use crate::parser::parse_expr;
+
+    /// Asserts that a `Result` is an `Err::ExtensionErr` with our extension name
+    fn assert_health_factor_err<T: std::fmt::Debug>(res: evaluator::Result<T>) {
+        match res {
+            Err(e) => match e.error_kind() {
+                EvaluationErrorKind::FailedExtensionFunctionApplication {
+                    extension_name, ..
+                } => {
+                    assert_eq!(
+                        *extension_name,
+                        Name::parse_unqualified_name("healthFactor")
+                            .expect("should be a valid identifier")
+                    )
+                }
+                _ => panic!("Expected a healthFactor ExtensionErr, got {:?}", e),
+            },
+            Ok(v) => panic!("Expected a healthFactor ExtensionErr, got {:?}", v),
+        }
+    }
+
+    /// A Cedar position record
+    fn position(collateral: &str, debt: &str, threshold: i64) -> String {
+        format!(
+            r#"{{ collateral: u256("{collateral}"), debt: u256("{debt}"), liquidationThreshold: {threshold} }}"#
+        )
+    }
+
+    #[test]
+    fn health_factors() {
+        let ext_array = [
+            extension(),
+            super::super::u256::extension(),
+            super::super::decimal::extension(),
+        ];
+        let exts = Extensions::specific_extensions(&ext_array);
+        let request = basic_request();
+        let entities = basic_entities();
+        let eval = Evaluator::new(&request, &entities, &exts).unwrap();
+
+        let is = |expr: &str, expected: &str| {
+            eval.interpret_inline_policy(
+                &parse_expr(&format!("{expr} == decimal(\"{expected}\")")).expect("parsing error"),
+            )
+        };
+        // $10,000 of ETH at 82.5% and $5,000 of USDC at 78%, against $6,000 of
+        // debt, with 8 decimals
+        let eth = position("1000000000000", "0", 8250);
+        let usdc = position("500000000000", "600000000000", 7800);
+        let positions = format!("[{eth}, {usdc}]");
+        assert_eq!(
+            is(&format!("healthFactor({positions})"), "2.0250"),
+            Ok(Value::from(true))
+        );
+        // withdrawing $6,000 of ETH
+        assert_eq!(
+            is(
+                &format!(
+                    "healthFactorAfter({positions}, {})",
+                    position("600000000000", "0", 8250)
+                ),
+                "1.2000"
+            ),
+            Ok(Value::from(true))
+        );
+        // borrowing another $1,000
+        assert_eq!(
+            is(
+                &format!(
+                    "healthFactorAfter({positions}, {})",
+                    position("0", "100000000000", 0)
+                ),
+                "1.7357"
+            ),
+            Ok(Value::from(true))
+        );
+        // withdrawing everything
+        assert_eq!(
+            is(
+                &format!(
+                    "healthFactorAfter({positions}, {})",
+                    position("10000000000000", "0", 10_000)
+                ),
+                "0.0"
+            ),
+            Ok(Value::from(true))
+        );
+        // without debt, or with little of it
+        assert_eq!(
+            is(&format!("healthFactor([{eth}])"), "922337203685477.5807"),
+            Ok(Value::from(true))
+        );
+        assert_eq!(
+            is("healthFactor([])", "922337203685477.5807"),
+            Ok(Value::from(true))
+        );
+        assert_eq!(
+            is(
+                &format!(
+                    "healthFactor([{}])",
+                    position(
+                        "115792089237316195423570985008687907853269984665640564039457584007913129639935",
+                        "1",
+                        10_000
+                    )
+                ),
+                "922337203685477.5807"
+            ),
+            Ok(Value::from(true))
+        );
+
+        // a single condition
+        assert_eq!(
+            eval.interpret_inline_policy(
+                &parse_expr(&format!(
+                    "healthFactorAfter({positions}, {}).lessThan(decimal(\"1.2\"))",
+                    position("600000000001", "0", 8250)
+                ))
+                .expect("parsing error")
+            ),
+            Ok(Value::from(true))
+        );
+    }
+
+    #[test]
+    fn invalid_positions() {
+        let ext_array = [
+            extension(),
+            super::super::u256::extension(),
+            super::super::decimal::extension(),
+        ];
+        let exts = Extensions::specific_extensions(&ext_array);
+        let request = basic_request();
+        let entities = basic_entities();
+        let eval = Evaluator::new(&request, &entities, &exts).unwrap();
+
+        let eval_str =
+            |src: &str| eval.interpret_inline_policy(&parse_expr(src).expect("parsing error"));
+        assert_health_factor_err(eval_str(&format!(
+            "healthFactor([{}])",
+            position("1", "1", 10_001)
+        )));
+        assert_health_factor_err(eval_str(&format!(
+            "healthFactor([{}])",
+            position("1", "1", -1)
+        )));
+        assert!(matches!(
+            eval_str(r#"healthFactor([{ collateral: u256("1"), liquidationThreshold: 8000 }])"#)
+                .map_err(|e| e.error_kind().clone()),
+            Err(EvaluationErrorKind::RecordAttrDoesNotExist(attr, _)) if attr == "debt"
+        ));
+        assert!(matches!(
+            eval_str(
+                r#"healthFactor([{ collateral: 1, debt: u256("1"), liquidationThreshold: 8000 }])"#
+            )
+            .map_err(|e| e.error_kind().clone()),
+            Err(EvaluationErrorKind::TypeError { .. })
+        ));
+        assert!(matches!(
+            eval_str(r#"healthFactor([1])"#).map_err(|e| e.error_kind().clone()),
+            Err(EvaluationErrorKind::TypeError { .. })
+        ));
+    }
+}
@@ -0,0 +1,503 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+//! This module contains the Cedar 'logMatch' extension.
+//!
+//! `logMatch(logs, signature, constraints)` is true if a set of logs holds an
+//! event with the given signature, e.g.
+//! `"Transfer(address indexed from, address indexed to, uint256 value)"`,
+//! whose parameters equal the values in the `constraints` record. Each log is
+//! a record with a `topic0` through `topic3` for each of its topics, and
+//! `address` and `data` attributes, all `0x`-prefixed hex strings.
+//!
+//! Parameters are decoded from the topics and data: addresses to lowercase
+//! hex strings, `uint`s to `u256` values, `bool`s to booleans, and anything
+//! else to the hex string of its word. Parameters without names are named
+//! `arg0`, `arg1`, and so on, and the `log.address` constraint matches the
+//! contract which emitted the log. If the signature doesn't mark any
+//! parameter `indexed`, the leading parameters are taken to be indexed, one
+//! for each topic after the first.
+
+use crate::ast::{
+    CallStyle, Extension, ExtensionFunction, ExtensionOutputValue, Literal, Name, StaticallyTyped,
+    Type, Value,
+};
+use crate::entities::SchemaType;
+use crate::evaluator;
+use ethers::types::U256;
+use ethers::utils::{hex, keccak256};
+use smol_str::SmolStr;
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+// PANIC SAFETY All `Name`s in here are valid `Name`s
+#[allow(clippy::expect_used)]
+mod names {
+    use super::{Name, EXTENSION_NAME};
+    // PANIC SAFETY all of the names here are valid names
+    lazy_static::lazy_static! {
+        pub static ref LOG_MATCH : Name = Name::parse_unqualified_name(EXTENSION_NAME).expect("should be a valid identifier");
+    }
+}
+
+/// Potential errors when matching logs. Note that these are converted to
+/// evaluator::Err::ExtensionErr (which takes a string argument) before being
+/// reported to users.
+#[derive(Debug, Error)]
+enum Error {
+    /// Error parsing an event signature
+    #[error("`{0}` is not a well-formed event signature")]
+    FailedParse(String),
+
+    /// A constraint on a parameter the event doesn't have
+    #[error("event `{event}` has no parameter `{param}`")]
+    UnknownParameter {
+        /// The event's canonical signature
+        event: String,
+        /// The constraint's key
+        param: String,
+    },
+
+    /// A constraint on a parameter which can't be decoded
+    #[error("cannot match parameter `{0}`: only indexed or static parameters are supported")]
+    UnsupportedParameter(String),
+}
+
+const EXTENSION_NAME: &str = "logMatch";
+
+/// The constraint on the address of the contract which emitted a log
+const ADDRESS_CONSTRAINT: &str = "log.address";
+
+/// The most topics a log can have
+const MAX_TOPICS: usize = 4;
+
+fn extension_err(msg: impl Into<String>) -> evaluator::EvaluationError {
+    evaluator::EvaluationError::failed_extension_function_application(
+        names::LOG_MATCH.clone(),
+        msg.into(),
+    )
+}
+
+/// A parameter of an event
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Param {
+    ty: String,
+    name: String,
+    indexed: bool,
+}
+
+/// A parsed event signature
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Event {
+    /// The canonical signature, e.g. `Transfer(address,address,uint256)`
+    canonical: String,
+    /// The hash of the canonical signature, which is the first topic of the
+    /// event's logs
+    topic0: [u8; 32],
+    params: Vec<Param>,
+    /// Whether the signature marks which parameters are indexed
+    marks_indexed: bool,
+}
+
+impl Event {
+    /// Parse a signature such as `Transfer(address,address,uint256)` or
+    /// `Transfer(address indexed from, address indexed to, uint256 value)`
+    fn parse(signature: &str) -> Result<Self, Error> {
+        let fail = || Error::FailedParse(signature.to_owned());
+        let (name, rest) = signature.trim().split_once('(').ok_or_else(fail)?;
+        let inner = rest.strip_suffix(')').ok_or_else(fail)?;
+        let name = name.trim();
+        if name.is_empty()
+            || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            || inner.contains(['(', ')'])
+        {
+            return Err(fail());
+        }
+        let params = if inner.trim().is_empty() {
+            Vec::new()
+        } else {
+            inner
+                .split(',')
+                .enumerate()
+                .map(|(i, param)| {
+                    let mut words = param.split_whitespace();
+                    let ty = canonical_type(words.next().ok_or_else(fail)?);
+                    let (indexed, name) = match (words.next(), words.next(), words.next()) {
+                        (None, _, _) => (false, None),
+                        (Some("indexed"), name, None) => (true, name),
+                        (Some(name), None, _) => (false, Some(name)),
+                        _ => return Err(fail()),
+                    };
+                    Ok(Param {
+                        ty,
+                        name: name.map_or_else(|| format!("arg{i}"), str::to_owned),
+                        indexed,
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        let canonical = format!(
+            "{name}({})",
+            params
+                .iter()
+                .map(|p| p.ty.as_str())
+                .collect::<Vec<_>>()
+                .join(",")
+        );
+        Ok(Self {
+            topic0: keccak256(canonical.as_bytes()),
+            canonical,
+            marks_indexed: params.iter().any(|p| p.indexed),
+            params,
+        })
+    }
+
+    /// The value of each parameter of `log`, or `None` if `log` isn't an
+    /// instance of this event. Parameters which can't be decoded are `None`.
+    fn decode(&self, log: &BTreeMap<SmolStr, Value>) -> Option<BTreeMap<&str, Option<Value>>> {
+        let topics: Vec<Vec<u8>> = (0..MAX_TOPICS)
+            .map_while(|i| string_attr(log, &format!("topic{i}")))
+            .map(|topic| unhex(topic).filter(|topic| topic.len() == 32))
+            .collect::<Option<_>>()?;
+        let (topic0, topics) = topics.split_first()?;
+        if topic0.as_slice() != self.topic0 {
+            return None;
+        }
+        let indexed: Vec<bool> = if self.marks_indexed {
+            self.params.iter().map(|p| p.indexed).collect()
+        } else {
+            (0..self.params.len()).map(|i| i < topics.len()).collect()
+        };
+        if indexed.iter().filter(|i| **i).count() != topics.len() {
+            return None;
+        }
+        let data = unhex(string_attr(log, "data").unwrap_or("0x"))?;
+        let mut topics = topics.iter();
+        let mut words = Some(data.chunks(32));
+        let mut values = BTreeMap::new();
+        for (param, indexed) in self.params.iter().zip(indexed) {
+            let value = if indexed {
+                let topic = topics.next()?;
+                Some(if is_dynamic(&param.ty) {
+                    hex_value(topic)
+                } else {
+                    decode_word(&param.ty, topic)
+                })
+            } else if param.ty.contains('[') && !is_dynamic(&param.ty) {
+                // a fixed size array takes more than one word, so the
+                // position of any parameter after it isn't known
+                words = None;
+                None
+            } else if let Some(words) = words.as_mut() {
+                let word = words.next().filter(|w| w.len() == 32)?;
+                (!is_dynamic(&param.ty)).then(|| decode_word(&param.ty, word))
+            } else {
+                None
+            };
+            values.insert(param.name.as_str(), value);
+        }
+        Some(values)
+    }
+}
+
+/// The canonical form of an event signature, e.g.
+/// `Transfer(address,address,uint256)` for
+/// `Transfer(address indexed from, address indexed to, uint value)`, or
+/// `None` if it isn't well-formed
+pub fn canonical_signature(signature: &str) -> Option<String> {
+    Event::parse(signature).ok().map(|event| event.canonical)
+}
+
+/// The canonical form of an ABI type
+fn canonical_type(ty: &str) -> String {
+    match ty {
+        "uint" => "uint256".to_owned(),
+        "int" => "int256".to_owned(),
+        ty => ty.to_owned(),
+    }
+}
+
+/// Whether an ABI type is dynamically sized
+fn is_dynamic(ty: &str) -> bool {
+    ty == "string" || ty == "bytes" || ty.ends_with("[]")
+}
+
+fn unhex(s: &str) -> Option<Vec<u8>> {
+    hex::decode(s.strip_prefix("0x")?).ok()
+}
+
+fn hex_value(bytes: &[u8]) -> Value {
+    Value::from(format!("0x{}", hex::encode(bytes)))
+}
+
+/// Decode a word holding a value of a static ABI type
+fn decode_word(ty: &str, word: &[u8]) -> Value {
+    match ty {
+        "address" => hex_value(word.get(12..).unwrap_or(word)),
+        "bool" => Value::from(word.iter().any(|b| *b != 0)),
+        ty if ty.starts_with("uint") => super::u256::u256_value(U256::from_big_endian(word)),
+        _ => hex_value(word),
+    }
+}
+
+fn string_attr<'a>(record: &'a BTreeMap<SmolStr, Value>, attr: &str) -> Option<&'a str> {
+    match record.get(attr) {
+        Some(Value::Lit(Literal::String(s))) => Some(s.as_str()),
+        _ => None,
+    }
+}
+
+/// Lowercase strings, so that hex strings compare regardless of case
+fn normalize(value: &Value) -> Value {
+    match value {
+        Value::Lit(Literal::String(s)) => Value::from(s.to_ascii_lowercase()),
+        value => value.clone(),
+    }
+}
+
+fn as_record(v: &Value) -> Result<&BTreeMap<SmolStr, Value>, evaluator::EvaluationError> {
+    match v {
+        Value::Record(record) => Ok(record),
+        _ => Err(evaluator::EvaluationError::type_error(
+            vec![Type::Record],
+            v.type_of(),
+        )),
+    }
+}
+
+/// Whether `log` is an instance of `event` matching `constraints`
+fn log_matches(
+    event: &Event,
+    log: &BTreeMap<SmolStr, Value>,
+    constraints: &BTreeMap<SmolStr, Value>,
+) -> Result<bool, Error> {
+    let Some(values) = event.decode(log) else {
+        return Ok(false);
+    };
+    for (key, expected) in constraints {
+        let actual = if key == ADDRESS_CONSTRAINT {
+            string_attr(log, "address").map(Value::from)
+        } else {
+            let value = values.get(key.as_str()).cloned().flatten();
+            Some(value.ok_or_else(|| Error::UnsupportedParameter(key.to_string()))?)
+        };
+        if actual.as_ref().map(normalize) != Some(normalize(expected)) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Cedar function that tests whether a set of logs holds an event with the
+/// given signature matching the given constraints, returning a Cedar bool
+fn log_match(
+    logs: Value,
+    signature: Value,
+    constraints: Value,
+) -> evaluator::Result<ExtensionOutputValue> {
+    let logs = logs.get_as_set()?;
+    let event =
+        Event::parse(signature.get_as_string()?).map_err(|e| extension_err(e.to_string()))?;
+    let constraints = as_record(&constraints)?;
+    if let Some(key) = constraints.keys().find(|key| {
+        key.as_str() != ADDRESS_CONSTRAINT && !event.params.iter().any(|p| p.name == key.as_str())
+    }) {
+        return Err(extension_err(
+            Error::UnknownParameter {
+                event: event.canonical.clone(),
+                param: key.to_string(),
+            }
+            .to_string(),
+        ));
+    }
+    // when the signature marks which parameters are indexed, a dynamic
+    // parameter in the data can't be decoded from any log
+    if let Some(param) = event.params.iter().find(|p| {
+        event.marks_indexed
+            && !p.indexed
+            && is_dynamic(&p.ty)
+            && constraints.contains_key(p.name.as_str())
+    }) {
+        return Err(extension_err(
+            Error::UnsupportedParameter(param.name.clone()).to_string(),
+        ));
+    }
+    for log in logs.authoritative.iter() {
+        if log_matches(&event, as_record(log)?, constraints)
+            .map_err(|e| extension_err(e.to_string()))?
+        {
+            return Ok(Value::Lit(true.into()).into());
+        }
+    }
+    Ok(Value::Lit(false.into()).into())
+}
+
+/// Construct the extension
+pub fn extension() -> Extension {
+    Extension::new(
+        names::LOG_MATCH.clone(),
+        vec![ExtensionFunction::ternary(
+            names::LOG_MATCH.clone(),
+            CallStyle::FunctionStyle,
+            Box::new(log_match),
+            SchemaType::Bool,
+            (None, Some(SchemaType::String), None),
+        )],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::test::{basic_entities, basic_request};
+    use crate::evaluator::Evaluator;
+    use crate::extensions::Extensions;
+    use crate::parser::parse_expr;
+
+    const TRANSFER_TOPIC: &str =
+        "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef";
+    const USDC: &str = "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48";
+    const SAFE: &str = "0x1111111111111111111111111111111111111111";
+    const TREASURY: &str = "0x2222222222222222222222222222222222222222";
+
+    fn topic(address: &str) -> String {
+        format!("0x{:0>64}", &address[2..])
+    }
+
+    /// An ERC-20 transfer log, as a Cedar record
+    fn transfer(from: &str, to: &str, amount: u64) -> String {
+        format!(
+            r#"{{address: "{USDC}", topic0: "{TRANSFER_TOPIC}", topic1: "{}", topic2: "{}", data: "0x{amount:064x}"}}"#,
+            topic(from),
+            topic(to)
+        )
+    }
+
+    fn eval(expr: &str) -> evaluator::Result<Value> {
+        let ext_array = [extension(), super::super::u256::extension()];
+        let exts = Extensions::specific_extensions(&ext_array);
+        let request = basic_request();
+        let entities = basic_entities();
+        let eval = Evaluator::new(&request, &entities, &exts).unwrap();
+        eval.interpret_inline_policy(&parse_expr(expr).expect("parsing error"))
+    }
+
+    fn assert_log_match_err(res: evaluator::Result<Value>) {
+        match res {
+            Err(e) => match e.error_kind() {
+                evaluator::EvaluationErrorKind::FailedExtensionFunctionApplication {
+                    extension_name,
+                    ..
+                } => assert_eq!(*extension_name, names::LOG_MATCH.clone()),
+                _ => panic!("Expected a logMatch ExtensionErr, got {:?}", e),
+            },
+            Ok(v) => panic!("Expected a logMatch ExtensionErr, got {:?}", v),
+        }
+    }
+
+    #[test]
+    fn signatures() {
+        let event =
+            Event::parse("Transfer(address indexed from, address indexed to, uint value)").unwrap();
+        assert_eq!(event.canonical, "Transfer(address,address,uint256)");
+        assert_eq!(format!("0x{}", hex::encode(event.topic0)), TRANSFER_TOPIC);
+        assert!(event.marks_indexed);
+        assert_eq!(event.params[2].name, "value");
+
+        let event = Event::parse(" Approval(address,address,uint256) ").unwrap();
+        assert!(!event.marks_indexed);
+        assert_eq!(event.params[1].name, "arg1");
+        assert_eq!(Event::parse("Paused()").unwrap().params, vec![]);
+
+        for bad in [
+            "Transfer",
+            "Transfer(address",
+            "(address)",
+            "Swap((uint,uint))",
+            "A(uint a b)",
+        ] {
+            assert!(Event::parse(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn matching() {
+        let logs = format!(
+            "[{}, {}]",
+            transfer(SAFE, USDC, 5),
+            transfer(SAFE, TREASURY, 1000)
+        );
+        let named = "Transfer(address indexed from, address indexed to, uint256 value)";
+        let check = |signature: &str, constraints: &str| {
+            eval(&format!(
+                r#"logMatch({logs}, "{signature}", {constraints})"#
+            ))
+        };
+
+        assert_eq!(check(named, "{}"), Ok(Value::from(true)));
+        assert_eq!(
+            check(named, &format!(r#"{{to: "{TREASURY}"}}"#)),
+            Ok(Value::from(true))
+        );
+        assert_eq!(
+            check(
+                named,
+                &format!(r#"{{to: "{TREASURY}", value: u256("1000")}}"#)
+            ),
+            Ok(Value::from(true))
+        );
+        assert_eq!(
+            check(named, &format!(r#"{{to: "{TREASURY}", value: u256("5")}}"#)),
+            Ok(Value::from(false))
+        );
+        assert_eq!(
+            check(
+                "Transfer(address,address,uint256)",
+                &format!(r#"{{arg1: "{TREASURY}", "log.address": "{USDC}"}}"#)
+            ),
+            Ok(Value::from(true))
+        );
+        assert_eq!(
+            check(named, &format!(r#"{{"log.address": "{SAFE}"}}"#)),
+            Ok(Value::from(false))
+        );
+        assert_eq!(
+            check(
+                named,
+                &format!(r#"{{"log.address": "0x{}"}}"#, USDC[2..].to_uppercase())
+            ),
+            Ok(Value::from(true))
+        );
+        // ERC-721 transfers index the token id, so they have another topic
+        assert_eq!(
+            check(
+                "Transfer(address indexed, address indexed, uint256 indexed id)",
+                "{}"
+            ),
+            Ok(Value::from(false))
+        );
+        assert_eq!(
+            check("Approval(address,address,uint256)", "{}"),
+            Ok(Value::from(false))
+        );
+
+        assert_log_match_err(check(named, r#"{amount: u256("1")}"#));
+        assert_log_match_err(check("Transfer(address", "{}"));
+        assert_log_match_err(check(
+            "Transfer(address indexed from, address indexed to, string memo)",
+            r#"{memo: "hi"}"#,
+        ));
+    }
+}
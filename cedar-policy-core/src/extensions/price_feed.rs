@@ -0,0 +1,585 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+//! This module contains the Cedar 'priceFeed' extension.
+
+use crate::ast::{
+    CallStyle, Extension, ExtensionFunction, ExtensionOutputValue, ExtensionValue,
+    ExtensionValueWithArgs, Literal, Name, StaticallyTyped, Type, Value,
+};
+use crate::entities::SchemaType;
+use crate::evaluator;
+use std::sync::Arc;
+use thiserror::Error;
+
+#[cfg(all(feature = "u256", feature = "decimal"))]
+use ethers::types::{U256, U512};
+
+/// A price reported by an oracle, in the shape of a Chainlink
+/// `latestRoundData()` response: the answer, scaled by `10^decimals`, and the
+/// unix time at which it was last updated.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub struct PriceFeed {
+    answer: i128,
+    updated_at: i64,
+    decimals: u8,
+}
+
+// PANIC SAFETY All `Name`s in here are valid `Name`s
+#[allow(clippy::expect_used)]
+mod names {
+    use super::{Name, EXTENSION_NAME};
+    // PANIC SAFETY all of the names here are valid names
+    lazy_static::lazy_static! {
+        pub static ref PRICE_FEED_FROM_STR_NAME : Name = Name::parse_unqualified_name(EXTENSION_NAME).expect("should be a valid identifier");
+        pub static ref ANSWER : Name = Name::parse_unqualified_name("answer").expect("should be a valid identifier");
+        pub static ref UPDATED_AT : Name = Name::parse_unqualified_name("updatedAt").expect("should be a valid identifier");
+        pub static ref DECIMALS : Name = Name::parse_unqualified_name("decimals").expect("should be a valid identifier");
+        pub static ref IS_FRESH : Name = Name::parse_unqualified_name("isFresh").expect("should be a valid identifier");
+        pub static ref DEVIATION_BPS : Name = Name::parse_unqualified_name("deviationBps").expect("should be a valid identifier");
+        #[cfg(all(feature = "u256", feature = "decimal"))]
+        pub static ref USD_VALUE : Name = Name::parse_unqualified_name("usdValue").expect("should be a valid identifier");
+        #[cfg(all(feature = "u256", feature = "decimal"))]
+        pub static ref U256 : Name = Name::parse_unqualified_name("u256").expect("should be a valid identifier");
+        #[cfg(all(feature = "u256", feature = "decimal"))]
+        pub static ref DECIMAL : Name = Name::parse_unqualified_name("decimal").expect("should be a valid identifier");
+    }
+}
+
+/// Potential errors when working with price feed values. Note that these are
+/// converted to evaluator::Err::ExtensionErr (which takes a string argument)
+/// before being reported to users.
+#[derive(Debug, Error)]
+enum Error {
+    /// Error parsing the input string as a price feed value
+    #[error("input string is not a well-formed priceFeed value: {0}")]
+    FailedParse(String),
+
+    /// The feed has more decimals than an answer can hold
+    #[error("too many decimals: {0} (at most {MAX_DECIMALS} are supported)")]
+    TooManyDecimals(u8),
+
+    /// The answer doesn't fit in a Long
+    #[error("answer {0} is out of range for a Long")]
+    AnswerOverflow(i128),
+
+    /// Deviations and values can only be computed from a positive answer
+    #[error("price feed answer must be positive, got {0}")]
+    NonPositiveAnswer(i128),
+
+    /// A negative maximum age was given to `isFresh`
+    #[error("maximum age must not be negative: {0}")]
+    NegativeMaxAge(i64),
+
+    /// A token can't have this many decimals
+    #[cfg(all(feature = "u256", feature = "decimal"))]
+    #[error("invalid token decimals: {0} (must be between 0 and {MAX_TOKEN_DECIMALS})")]
+    InvalidTokenDecimals(i64),
+
+    /// A value is too large for a decimal
+    #[cfg(all(feature = "u256", feature = "decimal"))]
+    #[error("overflow when converting to decimal")]
+    Overflow,
+}
+
+/// The most decimals a feed may have. `10^38` doesn't fit in an `i128`.
+const MAX_DECIMALS: u8 = 36;
+
+/// The most decimals a token may have. `10^78` doesn't fit in a `u256`.
+#[cfg(all(feature = "u256", feature = "decimal"))]
+const MAX_TOKEN_DECIMALS: i64 = 77;
+
+/// Number of digits after the decimal point of a `decimal` value
+#[cfg(all(feature = "u256", feature = "decimal"))]
+const DECIMAL_DIGITS: usize = 4;
+
+/// One hundred percent, in basis points
+const BPS: u128 = 10_000;
+
+impl PriceFeed {
+    /// The Cedar typename of price feed values
+    fn typename() -> Name {
+        names::PRICE_FEED_FROM_STR_NAME.clone()
+    }
+
+    /// Convert a string of the form `answer,updatedAt,decimals` into a
+    /// `PriceFeed` value. Whitespace around each component is ignored.
+    fn from_str(str: impl AsRef<str>) -> Result<Self, Error> {
+        let str = str.as_ref();
+        let fail = || Error::FailedParse(str.to_owned());
+        let mut parts = str.split(',').map(str::trim);
+        let (Some(answer), Some(updated_at), Some(decimals), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(fail());
+        };
+        let answer = answer.parse().map_err(|_| fail())?;
+        let updated_at = updated_at.parse().map_err(|_| fail())?;
+        let decimals = decimals.parse().map_err(|_| fail())?;
+        if decimals > MAX_DECIMALS {
+            return Err(Error::TooManyDecimals(decimals));
+        }
+        Ok(Self {
+            answer,
+            updated_at,
+            decimals,
+        })
+    }
+
+    /// The answer, scaled by `10^decimals`
+    pub fn answer(&self) -> i128 {
+        self.answer
+    }
+
+    /// When the answer was last updated, in seconds since the unix epoch
+    pub fn updated_at(&self) -> i64 {
+        self.updated_at
+    }
+
+    /// How many decimal places the answer has
+    pub fn decimals(&self) -> u8 {
+        self.decimals
+    }
+
+    /// Whether the answer was updated at most `max_age` seconds before `now`.
+    /// An answer updated after `now`, as happens when the caller's clock is
+    /// behind the chain's, is fresh.
+    fn is_fresh(&self, now: i64, max_age: i64) -> Result<bool, Error> {
+        if max_age < 0 {
+            return Err(Error::NegativeMaxAge(max_age));
+        }
+        Ok(now.saturating_sub(self.updated_at) <= max_age)
+    }
+
+    /// How far `quoted`, in the same units as the answer, is from the answer,
+    /// in basis points of the answer, rounded down
+    fn deviation_bps(&self, quoted: i64) -> Result<i64, Error> {
+        if self.answer <= 0 {
+            return Err(Error::NonPositiveAnswer(self.answer));
+        }
+        let difference = self.answer.abs_diff(i128::from(quoted));
+        let bps = difference
+            .checked_mul(BPS)
+            .map_or(u128::MAX, |scaled| scaled / self.answer.unsigned_abs());
+        Ok(i64::try_from(bps).unwrap_or(i64::MAX))
+    }
+
+    /// The value of `amount` base units of a token with `token_decimals`
+    /// decimals at this price, scaled by `10^DECIMAL_DIGITS` and rounded down
+    #[cfg(all(feature = "u256", feature = "decimal"))]
+    fn value_of(&self, amount: U256, token_decimals: i64) -> Result<i64, Error> {
+        if self.answer <= 0 {
+            return Err(Error::NonPositiveAnswer(self.answer));
+        }
+        if !(0..=MAX_TOKEN_DECIMALS).contains(&token_decimals) {
+            return Err(Error::InvalidTokenDecimals(token_decimals));
+        }
+        let product = amount.full_mul(U256::from(self.answer.unsigned_abs()));
+        // `token_decimals` was checked to be in range above
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let scale = token_decimals as usize + usize::from(self.decimals);
+        let value = if scale >= DECIMAL_DIGITS {
+            product / U512::exp10(scale - DECIMAL_DIGITS)
+        } else {
+            product
+                .checked_mul(U512::exp10(DECIMAL_DIGITS - scale))
+                .ok_or(Error::Overflow)?
+        };
+        i64::try_from(value).map_err(|_| Error::Overflow)
+    }
+}
+
+impl std::fmt::Display for PriceFeed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{},{},{}", self.answer, self.updated_at, self.decimals)
+    }
+}
+
+impl ExtensionValue for PriceFeed {
+    fn typename(&self) -> Name {
+        Self::typename()
+    }
+}
+
+const EXTENSION_NAME: &str = "priceFeed";
+
+fn extension_err(msg: impl Into<String>) -> evaluator::EvaluationError {
+    evaluator::EvaluationError::failed_extension_function_application(
+        names::PRICE_FEED_FROM_STR_NAME.clone(),
+        msg.into(),
+    )
+}
+
+/// Cedar function that constructs a `priceFeed` Cedar type from a
+/// Cedar string
+fn price_feed_from_str(arg: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let str = arg.get_as_string()?;
+    let feed = PriceFeed::from_str(str.as_str()).map_err(|e| extension_err(e.to_string()))?;
+    let function_name = names::PRICE_FEED_FROM_STR_NAME.clone();
+    let e = ExtensionValueWithArgs::new(Arc::new(feed), vec![arg.into()], function_name);
+    Ok(Value::ExtensionValue(Arc::new(e)).into())
+}
+
+/// Check that `v` is a price feed type and, if it is, return the wrapped value
+pub(crate) fn as_price_feed(v: &Value) -> Result<&PriceFeed, evaluator::EvaluationError> {
+    match v {
+        Value::ExtensionValue(ev) if ev.typename() == PriceFeed::typename() => {
+            // PANIC SAFETY Conditional above performs a typecheck
+            #[allow(clippy::expect_used)]
+            let feed = ev
+                .value()
+                .as_any()
+                .downcast_ref::<PriceFeed>()
+                .expect("already typechecked, so this downcast should succeed");
+            Ok(feed)
+        }
+        _ => Err(evaluator::EvaluationError::type_error(
+            vec![Type::Extension {
+                name: PriceFeed::typename(),
+            }],
+            v.type_of(),
+        )),
+    }
+}
+
+/// Cedar function that returns the answer of a `priceFeed` as a Long
+fn answer(feed: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let feed = as_price_feed(&feed)?;
+    let answer = i64::try_from(feed.answer)
+        .map_err(|_| extension_err(Error::AnswerOverflow(feed.answer).to_string()))?;
+    Ok(Value::Lit(Literal::Long(answer)).into())
+}
+
+/// Cedar function that returns when a `priceFeed` was last updated
+fn updated_at(feed: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let feed = as_price_feed(&feed)?;
+    Ok(Value::Lit(Literal::Long(feed.updated_at)).into())
+}
+
+/// Cedar function that returns the number of decimals of a `priceFeed`
+fn decimals(feed: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let feed = as_price_feed(&feed)?;
+    Ok(Value::Lit(Literal::Long(feed.decimals.into())).into())
+}
+
+/// Cedar function that tests whether a `priceFeed` was updated at most
+/// `maxAge` seconds before `now`, returning a Cedar bool
+fn is_fresh(feed: Value, now: Value, max_age: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let feed = as_price_feed(&feed)?;
+    let fresh = feed
+        .is_fresh(now.get_as_long()?, max_age.get_as_long()?)
+        .map_err(|e| extension_err(e.to_string()))?;
+    Ok(Value::Lit(fresh.into()).into())
+}
+
+/// Cedar function that returns how far a quoted price is from a `priceFeed`'s
+/// answer, in basis points, as a Cedar Long
+fn deviation_bps(feed: Value, quoted: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let feed = as_price_feed(&feed)?;
+    let bps = feed
+        .deviation_bps(quoted.get_as_long()?)
+        .map_err(|e| extension_err(e.to_string()))?;
+    Ok(Value::Lit(Literal::Long(bps)).into())
+}
+
+/// Cedar function that converts `amount` base units of a token with
+/// `tokenDecimals` decimals to its value at a `priceFeed`'s answer, returning
+/// a Cedar `decimal`
+#[cfg(all(feature = "u256", feature = "decimal"))]
+fn usd_value(
+    amount: Value,
+    token_decimals: Value,
+    feed: Value,
+) -> evaluator::Result<ExtensionOutputValue> {
+    let amount = super::u256::as_u256(&amount)?;
+    let token_decimals = token_decimals.get_as_long()?;
+    let feed = as_price_feed(&feed)?;
+    let value = feed
+        .value_of(amount, token_decimals)
+        .map_err(|e| extension_err(e.to_string()))?;
+    Ok(super::decimal::decimal_value(value).into())
+}
+
+/// Construct the extension
+pub fn extension() -> Extension {
+    let price_feed_type = SchemaType::Extension {
+        name: PriceFeed::typename(),
+    };
+    Extension::new(
+        names::PRICE_FEED_FROM_STR_NAME.clone(),
+        vec![
+            ExtensionFunction::unary(
+                names::PRICE_FEED_FROM_STR_NAME.clone(),
+                CallStyle::FunctionStyle,
+                Box::new(price_feed_from_str),
+                price_feed_type.clone(),
+                Some(SchemaType::String),
+            ),
+            ExtensionFunction::unary(
+                names::ANSWER.clone(),
+                CallStyle::MethodStyle,
+                Box::new(answer),
+                SchemaType::Long,
+                Some(price_feed_type.clone()),
+            ),
+            ExtensionFunction::unary(
+                names::UPDATED_AT.clone(),
+                CallStyle::MethodStyle,
+                Box::new(updated_at),
+                SchemaType::Long,
+                Some(price_feed_type.clone()),
+            ),
+            ExtensionFunction::unary(
+                names::DECIMALS.clone(),
+                CallStyle::MethodStyle,
+                Box::new(decimals),
+                SchemaType::Long,
+                Some(price_feed_type.clone()),
+            ),
+            ExtensionFunction::ternary(
+                names::IS_FRESH.clone(),
+                CallStyle::MethodStyle,
+                Box::new(is_fresh),
+                SchemaType::Bool,
+                (
+                    Some(price_feed_type.clone()),
+                    Some(SchemaType::Long),
+                    Some(SchemaType::Long),
+                ),
+            ),
+            ExtensionFunction::binary(
+                names::DEVIATION_BPS.clone(),
+                CallStyle::MethodStyle,
+                Box::new(deviation_bps),
+                SchemaType::Long,
+                (Some(price_feed_type.clone()), Some(SchemaType::Long)),
+            ),
+            #[cfg(all(feature = "u256", feature = "decimal"))]
+            ExtensionFunction::ternary(
+                names::USD_VALUE.clone(),
+                CallStyle::FunctionStyle,
+                Box::new(usd_value),
+                SchemaType::Extension {
+                    name: names::DECIMAL.clone(),
+                },
+                (
+                    Some(SchemaType::Extension {
+                        name: names::U256.clone(),
+                    }),
+                    Some(SchemaType::Long),
+                    Some(price_feed_type),
+                ),
+            ),
+        ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::test::{basic_entities, basic_request};
+    use crate::evaluator::Evaluator;
+    use crate::extensions::Extensions;
+    use crate::parser::parse_expr;
+
+    /// Asserts that a `Result` is an `Err::ExtensionErr` with our extension name
+    fn assert_price_feed_err<T: std::fmt::Debug>(res: evaluator::Result<T>) {
+        match res {
+            Err(e) => match e.error_kind() {
+                evaluator::EvaluationErrorKind::FailedExtensionFunctionApplication {
+                    extension_name,
+                    ..
+                } => {
+                    assert_eq!(
+                        *extension_name,
+                        Name::parse_unqualified_name("priceFeed")
+                            .expect("should be a valid identifier")
+                    )
+                }
+                _ => panic!("Expected a priceFeed ExtensionErr, got {:?}", e),
+            },
+            Ok(v) => panic!("Expected a priceFeed ExtensionErr, got {:?}", v),
+        }
+    }
+
+    #[test]
+    fn constructors() {
+        let ext = extension();
+        assert!(ext
+            .get_func(
+                &Name::parse_unqualified_name("priceFeed").expect("should be a valid identifier")
+            )
+            .expect("function should exist")
+            .is_constructor());
+        for name in ["answer", "updatedAt", "decimals", "isFresh", "deviationBps"] {
+            assert!(!ext
+                .get_func(
+                    &Name::parse_unqualified_name(name).expect("should be a valid identifier")
+                )
+                .expect("function should exist")
+                .is_constructor());
+        }
+    }
+
+    #[test]
+    fn price_feed_creation() {
+        let ext_array = [extension()];
+        let exts = Extensions::specific_extensions(&ext_array);
+        let request = basic_request();
+        let entities = basic_entities();
+        let eval = Evaluator::new(&request, &entities, &exts).unwrap();
+
+        let eval_str =
+            |src: &str| eval.interpret_inline_policy(&parse_expr(src).expect("parsing error"));
+        assert_eq!(
+            eval_str(r#"priceFeed("185023000000, 1700000000, 8").answer()"#),
+            Ok(Value::from(185_023_000_000))
+        );
+        assert_eq!(
+            eval_str(r#"priceFeed("185023000000,1700000000,8").updatedAt()"#),
+            Ok(Value::from(1_700_000_000))
+        );
+        assert_eq!(
+            eval_str(r#"priceFeed("185023000000,1700000000,8").decimals()"#),
+            Ok(Value::from(8))
+        );
+        assert_eq!(
+            eval_str(r#"priceFeed("1,2,8") == priceFeed(" 1 , 2 , 8 ")"#),
+            Ok(Value::from(true))
+        );
+
+        assert_price_feed_err(eval_str(r#"priceFeed("1,2")"#));
+        assert_price_feed_err(eval_str(r#"priceFeed("1,2,8,4")"#));
+        assert_price_feed_err(eval_str(r#"priceFeed("1.5,2,8")"#));
+        assert_price_feed_err(eval_str(r#"priceFeed("1,2,37")"#));
+        assert_price_feed_err(eval_str(
+            r#"priceFeed("100000000000000000000000,2,18").answer()"#,
+        ));
+    }
+
+    #[test]
+    fn freshness() {
+        let ext_array = [extension()];
+        let exts = Extensions::specific_extensions(&ext_array);
+        let request = basic_request();
+        let entities = basic_entities();
+        let eval = Evaluator::new(&request, &entities, &exts).unwrap();
+
+        let fresh = |now: i64, max_age: i64| {
+            eval.interpret_inline_policy(
+                &parse_expr(&format!(
+                    r#"priceFeed("185023000000,1700000000,8").isFresh({now}, {max_age})"#
+                ))
+                .expect("parsing error"),
+            )
+        };
+        assert_eq!(fresh(1_700_003_600, 3600), Ok(Value::from(true)));
+        assert_eq!(fresh(1_700_003_601, 3600), Ok(Value::from(false)));
+        assert_eq!(fresh(1_699_999_000, 0), Ok(Value::from(true)));
+        assert_price_feed_err(fresh(1_700_000_000, -1));
+    }
+
+    #[test]
+    fn deviation() {
+        let ext_array = [extension()];
+        let exts = Extensions::specific_extensions(&ext_array);
+        let request = basic_request();
+        let entities = basic_entities();
+        let eval = Evaluator::new(&request, &entities, &exts).unwrap();
+
+        let deviation = |feed: &str, quoted: i64| {
+            eval.interpret_inline_policy(
+                &parse_expr(&format!(r#"priceFeed("{feed}").deviationBps({quoted})"#))
+                    .expect("parsing error"),
+            )
+        };
+        let feed = "200000000000,1700000000,8";
+        assert_eq!(deviation(feed, 200_000_000_000), Ok(Value::from(0)));
+        assert_eq!(deviation(feed, 204_000_000_000), Ok(Value::from(200)));
+        assert_eq!(deviation(feed, 195_000_000_000), Ok(Value::from(250)));
+        assert_eq!(deviation(feed, 204_000_000_001), Ok(Value::from(200)));
+        assert_eq!(deviation(feed, 0), Ok(Value::from(10_000)));
+        assert_price_feed_err(deviation("0,1700000000,8", 1));
+        assert_price_feed_err(deviation("-5,1700000000,8", 1));
+    }
+
+    #[test]
+    #[cfg(all(feature = "u256", feature = "decimal"))]
+    fn usd_value_conversion() {
+        let ext_array = [
+            extension(),
+            super::super::u256::extension(),
+            super::super::decimal::extension(),
+        ];
+        let exts = Extensions::specific_extensions(&ext_array);
+        let request = basic_request();
+        let entities = basic_entities();
+        let eval = Evaluator::new(&request, &entities, &exts).unwrap();
+
+        let usd_value = |amount: &str, token_decimals: i64, feed: &str| {
+            eval.interpret_inline_policy(
+                &parse_expr(&format!(
+                    r#"usdValue(u256("{amount}"), {token_decimals}, priceFeed("{feed}"))"#
+                ))
+                .expect("parsing error"),
+            )
+        };
+        let is = |amount: &str, token_decimals: i64, feed: &str, expected: &str| {
+            eval.interpret_inline_policy(
+                &parse_expr(&format!(
+                    r#"usdValue(u256("{amount}"), {token_decimals}, priceFeed("{feed}")) == decimal("{expected}")"#
+                ))
+                .expect("parsing error"),
+            )
+        };
+        let eth = "200012345678,1700000000,8";
+        // 1.5 ETH at $2000.12345678
+        assert_eq!(
+            is("1500000000000000000", 18, eth, "3000.1851"),
+            Ok(Value::from(true))
+        );
+        // 250 USDC at $0.99990000
+        assert_eq!(
+            is("250000000", 6, "99990000,1700000000,8", "249.9750"),
+            Ok(Value::from(true))
+        );
+        // a token without decimals, priced with two
+        assert_eq!(
+            is("3", 0, "150,1700000000,2", "4.5000"),
+            Ok(Value::from(true))
+        );
+        assert_eq!(is("0", 18, eth, "0.0"), Ok(Value::from(true)));
+
+        // the result is a decimal
+        assert_eq!(
+            eval.interpret_inline_policy(
+                &parse_expr(&format!(
+                    r#"usdValue(u256("1500000000000000000"), 18, priceFeed("{eth}")).lessThan(decimal("10000.0"))"#
+                ))
+                .expect("parsing error"),
+            ),
+            Ok(Value::from(true))
+        );
+
+        assert_price_feed_err(usd_value("1", 78, eth));
+        assert_price_feed_err(usd_value("1", -1, eth));
+        assert_price_feed_err(usd_value("1", 18, "0,1700000000,8"));
+        assert_price_feed_err(usd_value(
+            "115792089237316195423570985008687907853269984665640564039457584007913129639935",
+            18,
+            eth,
+        ));
+    }
+}
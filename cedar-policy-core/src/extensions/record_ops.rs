@@ -0,0 +1,193 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+//! This module contains the Cedar 'recordOps' extension.
+//!
+//! `r.keys()` is the set of a record's attribute names, `r.hasAll(keys)` is
+//! true if the record has every attribute named in a set of strings, and
+//! `merge(r, s)` is a record with the attributes of both, taking those of
+//! `s` where both have an attribute. They let policies over decoded calldata
+//! check its shape defensively, e.g.
+//! `context.calldata.hasAll(["to", "amount"])`.
+
+use crate::ast::{
+    CallStyle, Extension, ExtensionFunction, ExtensionOutputValue, StaticallyTyped, Type, Value,
+};
+use crate::entities::SchemaType;
+use crate::evaluator;
+use smol_str::SmolStr;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+// PANIC SAFETY All `Name`s in here are valid `Name`s
+#[allow(clippy::expect_used)]
+mod names {
+    use crate::ast::Name;
+    // PANIC SAFETY all of the names here are valid names
+    lazy_static::lazy_static! {
+        pub static ref RECORD_OPS : Name = Name::parse_unqualified_name("recordOps").expect("should be a valid identifier");
+        pub static ref KEYS : Name = Name::parse_unqualified_name("keys").expect("should be a valid identifier");
+        pub static ref HAS_ALL : Name = Name::parse_unqualified_name("hasAll").expect("should be a valid identifier");
+        pub static ref MERGE : Name = Name::parse_unqualified_name("merge").expect("should be a valid identifier");
+    }
+}
+
+fn as_record(v: &Value) -> Result<&Arc<BTreeMap<SmolStr, Value>>, evaluator::EvaluationError> {
+    match v {
+        Value::Record(record) => Ok(record),
+        _ => Err(evaluator::EvaluationError::type_error(
+            vec![Type::Record],
+            v.type_of(),
+        )),
+    }
+}
+
+/// Cedar function returning the names of a record's attributes, as a set of
+/// strings
+fn keys(record: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let record = as_record(&record)?;
+    Ok(Value::set(record.keys().map(|k| Value::from(k.clone()))).into())
+}
+
+/// Cedar function testing whether a record has every attribute named in a
+/// set of strings, returning a Cedar bool
+fn has_all(record: Value, keys: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let record = as_record(&record)?;
+    for key in keys.get_as_set()?.authoritative.iter() {
+        if !record.contains_key(key.get_as_string()?) {
+            return Ok(Value::from(false).into());
+        }
+    }
+    Ok(Value::from(true).into())
+}
+
+/// Cedar function returning a record with the attributes of both records,
+/// taking those of the second where both have an attribute
+fn merge(first: Value, second: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let mut merged = BTreeMap::clone(as_record(&first)?);
+    merged.extend(
+        as_record(&second)?
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone())),
+    );
+    Ok(Value::Record(Arc::new(merged)).into())
+}
+
+/// Construct the extension
+pub fn extension() -> Extension {
+    Extension::new(
+        names::RECORD_OPS.clone(),
+        vec![
+            ExtensionFunction::unary(
+                names::KEYS.clone(),
+                CallStyle::MethodStyle,
+                Box::new(keys),
+                SchemaType::Set {
+                    element_ty: Box::new(SchemaType::String),
+                },
+                None,
+            ),
+            ExtensionFunction::binary(
+                names::HAS_ALL.clone(),
+                CallStyle::MethodStyle,
+                Box::new(has_all),
+                SchemaType::Bool,
+                (None, None),
+            ),
+            // A record with no declared attributes is compatible with every
+            // record type
+            ExtensionFunction::binary(
+                names::MERGE.clone(),
+                CallStyle::FunctionStyle,
+                Box::new(merge),
+                SchemaType::Record {
+                    attrs: HashMap::new(),
+                },
+                (None, None),
+            ),
+        ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::test::{basic_entities, basic_request};
+    use crate::evaluator::{EvaluationErrorKind, Evaluator};
+    use crate::extensions::Extensions;
+    use crate::parser::parse_expr;
+
+    fn eval(expr: &str) -> evaluator::Result<Value> {
+        let ext_array = [extension()];
+        let exts = Extensions::specific_extensions(&ext_array);
+        let request = basic_request();
+        let entities = basic_entities();
+        let eval = Evaluator::new(&request, &entities, &exts).unwrap();
+        eval.interpret_inline_policy(&parse_expr(expr).expect("parsing error"))
+    }
+
+    #[test]
+    fn record_keys() {
+        assert_eq!(
+            eval(r#"{to: "0x1", amount: 5}.keys()"#).unwrap(),
+            eval(r#"["amount", "to"]"#).unwrap()
+        );
+        assert_eq!(eval("{}.keys() == []").unwrap(), Value::from(true));
+    }
+
+    #[test]
+    fn record_has_all() {
+        assert_eq!(
+            eval(r#"{to: "0x1", amount: 5}.hasAll(["to", "amount"])"#).unwrap(),
+            Value::from(true)
+        );
+        assert_eq!(
+            eval(r#"{to: "0x1"}.hasAll(["to", "amount"])"#).unwrap(),
+            Value::from(false)
+        );
+        assert_eq!(eval("{}.hasAll([])").unwrap(), Value::from(true));
+    }
+
+    #[test]
+    fn record_merge() {
+        assert_eq!(
+            eval(r#"merge({to: "0x1", amount: 5}, {amount: 7, data: "0x"})"#).unwrap(),
+            eval(r#"{to: "0x1", amount: 7, data: "0x"}"#).unwrap()
+        );
+        assert_eq!(eval("merge({a: 1}, {}).a").unwrap(), Value::from(1));
+    }
+
+    #[test]
+    fn type_errors() {
+        for (expr, expected) in [
+            ("[1].keys()", Type::Record),
+            ("{a: 1}.hasAll(\"a\")", Type::Set),
+            ("{a: 1}.hasAll([1])", Type::String),
+            ("merge({a: 1}, 2)", Type::Record),
+        ] {
+            match eval(expr) {
+                Err(e) => match e.error_kind() {
+                    EvaluationErrorKind::TypeError {
+                        expected: types, ..
+                    } => {
+                        assert_eq!(types, &vec![expected]);
+                    }
+                    _ => panic!("Expected a type error for {expr}, got {e:?}"),
+                },
+                Ok(v) => panic!("Expected a type error for {expr}, got {v:?}"),
+            }
+        }
+    }
+}
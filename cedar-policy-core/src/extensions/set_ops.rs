@@ -0,0 +1,181 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+//! This module contains the Cedar 'setOps' extension.
+//!
+//! `setIntersect(a, b)` is the set of elements in both `a` and `b`,
+//! `setUnionSize(a, b)` is the number of elements in either, and
+//! `a.isSubsetOf(b)` is true if every element of `a` is in `b`. They spare
+//! policies over role or signer sets from composing `containsAll` and
+//! `containsAny`, e.g. `setUnionSize(context.signers, principal.owners)`
+//! or `context.signers.isSubsetOf(principal.owners)`.
+
+use crate::ast::{CallStyle, Extension, ExtensionFunction, ExtensionOutputValue, Value};
+use crate::entities::SchemaType;
+use crate::evaluator;
+
+// PANIC SAFETY All `Name`s in here are valid `Name`s
+#[allow(clippy::expect_used)]
+mod names {
+    use crate::ast::Name;
+    // PANIC SAFETY all of the names here are valid names
+    lazy_static::lazy_static! {
+        pub static ref SET_OPS : Name = Name::parse_unqualified_name("setOps").expect("should be a valid identifier");
+        pub static ref SET_INTERSECT : Name = Name::parse_unqualified_name("setIntersect").expect("should be a valid identifier");
+        pub static ref SET_UNION_SIZE : Name = Name::parse_unqualified_name("setUnionSize").expect("should be a valid identifier");
+        pub static ref IS_SUBSET_OF : Name = Name::parse_unqualified_name("isSubsetOf").expect("should be a valid identifier");
+    }
+}
+
+/// Cedar function returning the set of elements in both sets
+fn set_intersect(a: Value, b: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let (a, b) = (a.get_as_set()?, b.get_as_set()?);
+    Ok(Value::set(a.authoritative.intersection(&b.authoritative).cloned()).into())
+}
+
+/// Cedar function returning the number of elements in either set, as a
+/// Cedar Long
+fn set_union_size(a: Value, b: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let (a, b) = (a.get_as_set()?, b.get_as_set()?);
+    let size = a.authoritative.union(&b.authoritative).count();
+    // A set can't have more elements than fit in memory, so this fits in an i64
+    Ok(Value::from(i64::try_from(size).unwrap_or(i64::MAX)).into())
+}
+
+/// Cedar function testing whether every element of the first set is in the
+/// second, returning a Cedar bool
+fn is_subset_of(a: Value, b: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let (a, b) = (a.get_as_set()?, b.get_as_set()?);
+    Ok(Value::from(a.authoritative.is_subset(&b.authoritative)).into())
+}
+
+/// Construct the extension
+pub fn extension() -> Extension {
+    // `setIntersect` returns a set of the same type as its arguments, which
+    // `EmptySet` is compatible with
+    Extension::new(
+        names::SET_OPS.clone(),
+        vec![
+            ExtensionFunction::binary(
+                names::SET_INTERSECT.clone(),
+                CallStyle::FunctionStyle,
+                Box::new(set_intersect),
+                SchemaType::EmptySet,
+                (None, None),
+            ),
+            ExtensionFunction::binary(
+                names::SET_UNION_SIZE.clone(),
+                CallStyle::FunctionStyle,
+                Box::new(set_union_size),
+                SchemaType::Long,
+                (None, None),
+            ),
+            ExtensionFunction::binary(
+                names::IS_SUBSET_OF.clone(),
+                CallStyle::MethodStyle,
+                Box::new(is_subset_of),
+                SchemaType::Bool,
+                (None, None),
+            ),
+        ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Type;
+    use crate::evaluator::test::{basic_entities, basic_request};
+    use crate::evaluator::{EvaluationErrorKind, Evaluator};
+    use crate::extensions::Extensions;
+    use crate::parser::parse_expr;
+
+    fn eval(expr: &str) -> evaluator::Result<Value> {
+        let ext_array = [extension()];
+        let exts = Extensions::specific_extensions(&ext_array);
+        let request = basic_request();
+        let entities = basic_entities();
+        let eval = Evaluator::new(&request, &entities, &exts).unwrap();
+        eval.interpret_inline_policy(&parse_expr(expr).expect("parsing error"))
+    }
+
+    #[test]
+    fn intersection() {
+        assert_eq!(
+            eval("setIntersect([1, 2, 3], [3, 2, 5])").unwrap(),
+            eval("[2, 3]").unwrap()
+        );
+        assert_eq!(
+            eval(r#"setIntersect(["alice"], ["bob"]) == []"#).unwrap(),
+            Value::from(true)
+        );
+        assert_eq!(
+            eval(
+                r#"setIntersect([User::"alice", User::"bob"], [User::"bob"]).contains(User::"bob")"#
+            )
+            .unwrap(),
+            Value::from(true)
+        );
+    }
+
+    #[test]
+    fn union_size() {
+        assert_eq!(
+            eval("setUnionSize([1, 2], [2, 3])").unwrap(),
+            Value::from(3)
+        );
+        assert_eq!(eval("setUnionSize([], [])").unwrap(), Value::from(0));
+        assert_eq!(
+            eval(r#"setUnionSize(["a", "b"], ["b", "a"])"#).unwrap(),
+            Value::from(2)
+        );
+    }
+
+    #[test]
+    fn subset() {
+        assert_eq!(
+            eval("[1, 2].isSubsetOf([1, 2, 3])").unwrap(),
+            Value::from(true)
+        );
+        assert_eq!(eval("[].isSubsetOf([1])").unwrap(), Value::from(true));
+        assert_eq!(
+            eval("[1, 4].isSubsetOf([1, 2])").unwrap(),
+            Value::from(false)
+        );
+        assert_eq!(
+            eval("[1, 2].isSubsetOf([1, 2])").unwrap(),
+            Value::from(true)
+        );
+    }
+
+    #[test]
+    fn not_sets() {
+        for expr in [
+            "setIntersect(1, [1])",
+            r#"setUnionSize([1], "1")"#,
+            "[1].isSubsetOf({a: 1})",
+        ] {
+            match eval(expr) {
+                Err(e) => match e.error_kind() {
+                    EvaluationErrorKind::TypeError { expected, .. } => {
+                        assert_eq!(expected, &vec![Type::Set]);
+                    }
+                    _ => panic!("Expected a type error for {expr}, got {e:?}"),
+                },
+                Ok(v) => panic!("Expected a type error for {expr}, got {v:?}"),
+            }
+        }
+    }
+}
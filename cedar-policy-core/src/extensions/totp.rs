@@ -0,0 +1,209 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+//! This module contains the Cedar 'totp' extension.
+//!
+//! `totpVerify(secret, code, now)` checks a time-based one-time password
+//! (RFC 6238) for step-up authentication, e.g. to require a fresh code for
+//! transfers above some amount. The secret is the base32 string of an
+//! authenticator app's `otpauth://` URI, typically stored in an attribute of
+//! the principal, the code is the 6 digit string the user entered, and `now`
+//! is the unix time of the request. Codes are HMAC-SHA1 over 30 second
+//! steps, and a code from one step either side of `now` is accepted, to
+//! allow for clock skew and the time it takes to enter it.
+//!
+//! Secrets shorter than 80 bits, including empty ones, are errors rather
+//! than keys.
+//!
+//! The extension doesn't remember which codes were used, so a code can be
+//! replayed within its window unless the caller rejects reuse.
+
+use crate::ast::{CallStyle, Extension, ExtensionFunction, ExtensionOutputValue, Value};
+use crate::entities::SchemaType;
+use crate::evaluator;
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use thiserror::Error;
+
+// PANIC SAFETY All `Name`s in here are valid `Name`s
+#[allow(clippy::expect_used)]
+mod names {
+    use crate::ast::Name;
+    // PANIC SAFETY all of the names here are valid names
+    lazy_static::lazy_static! {
+        pub static ref TOTP : Name = Name::parse_unqualified_name("totp").expect("should be a valid identifier");
+        pub static ref TOTP_VERIFY : Name = Name::parse_unqualified_name("totpVerify").expect("should be a valid identifier");
+    }
+}
+
+/// Potential errors when verifying codes. Note that these are converted to
+/// evaluator::Err::ExtensionErr (which takes a string argument) before being
+/// reported to users.
+#[derive(Debug, Error)]
+enum Error {
+    /// The secret isn't a base32 string of at least [`MIN_SECRET_BYTES`]
+    #[error("the secret is not a base32 string of at least {MIN_SECRET_BYTES} bytes")]
+    BadSecret,
+    /// The time is before the unix epoch
+    #[error("{0} is not a unix time")]
+    BadTime(i64),
+}
+
+fn extension_err(msg: impl Into<String>) -> evaluator::EvaluationError {
+    evaluator::EvaluationError::failed_extension_function_application(
+        names::TOTP.clone(),
+        msg.into(),
+    )
+}
+
+/// The seconds in each time step
+const STEP: u64 = 30;
+/// The steps either side of the current one whose codes are accepted
+const SKEW_STEPS: u64 = 1;
+/// The digits in a code
+const DIGITS: usize = 6;
+/// The shortest secret accepted, 80 bits. RFC 4226 requires 128 bits, but
+/// many authenticator apps still issue 80 bit secrets. Shorter keys, and the
+/// empty key of an unset or blank secret, make the codes guessable.
+const MIN_SECRET_BYTES: usize = 10;
+
+/// The secret in the base32 string `s`, which may be unpadded, lowercase or
+/// grouped with spaces as authenticator apps display it, or `None` if it is
+/// shorter than [`MIN_SECRET_BYTES`]
+fn decode_secret(s: &str) -> Option<Vec<u8>> {
+    let normalized: String = s
+        .chars()
+        .filter(|c| *c != ' ' && *c != '=')
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    data_encoding::BASE32_NOPAD
+        .decode(normalized.as_bytes())
+        .ok()
+        .filter(|key| key.len() >= MIN_SECRET_BYTES)
+}
+
+/// The code for `key` in time step `counter`
+fn code_at(key: &[u8], counter: u64) -> Option<u32> {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).ok()?;
+    mac.update(&counter.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+    // dynamic truncation: the low nibble of the last byte picks four bytes
+    let offset = usize::from(digest.last()? & 0x0f);
+    let bytes: [u8; 4] = digest.get(offset..offset + 4)?.try_into().ok()?;
+    Some((u32::from_be_bytes(bytes) & 0x7fff_ffff) % 10_u32.pow(DIGITS as u32))
+}
+
+/// Cedar function that checks `code` against `secret` at unix time `now`,
+/// returning a Cedar bool
+fn totp_verify(secret: Value, code: Value, now: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let key = decode_secret(secret.get_as_string()?)
+        .ok_or_else(|| extension_err(Error::BadSecret.to_string()))?;
+    let now = now.get_as_long()?;
+    let step =
+        u64::try_from(now).map_err(|_| extension_err(Error::BadTime(now).to_string()))? / STEP;
+    let code = code.get_as_string()?;
+    // a malformed code is a wrong code
+    let Some(code) = code
+        .parse::<u32>()
+        .ok()
+        .filter(|_| code.len() == DIGITS && code.bytes().all(|b| b.is_ascii_digit()))
+    else {
+        return Ok(Value::from(false).into());
+    };
+    let valid = (step.saturating_sub(SKEW_STEPS)..=step.saturating_add(SKEW_STEPS))
+        .any(|counter| code_at(&key, counter) == Some(code));
+    Ok(Value::from(valid).into())
+}
+
+/// Construct the extension
+pub fn extension() -> Extension {
+    Extension::new(
+        names::TOTP.clone(),
+        vec![ExtensionFunction::ternary(
+            names::TOTP_VERIFY.clone(),
+            CallStyle::FunctionStyle,
+            Box::new(totp_verify),
+            SchemaType::Bool,
+            (
+                Some(SchemaType::String),
+                Some(SchemaType::String),
+                Some(SchemaType::Long),
+            ),
+        )],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::test::{basic_entities, basic_request};
+    use crate::evaluator::Evaluator;
+    use crate::extensions::Extensions;
+    use crate::parser::parse_expr;
+
+    /// The secret of the RFC 6238 test vectors, `12345678901234567890`
+    const SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    fn eval(expr: &str) -> evaluator::Result<Value> {
+        let ext_array = [extension()];
+        let exts = Extensions::specific_extensions(&ext_array);
+        let request = basic_request();
+        let entities = basic_entities();
+        let eval = Evaluator::new(&request, &entities, &exts).unwrap();
+        eval.interpret_inline_policy(&parse_expr(expr).expect("parsing error"))
+    }
+
+    fn verify(secret: &str, code: &str, now: i64) -> evaluator::Result<Value> {
+        eval(&format!(r#"totpVerify("{secret}", "{code}", {now})"#))
+    }
+
+    #[test]
+    fn rfc_6238_codes() {
+        assert_eq!(verify(SECRET, "287082", 59), Ok(Value::from(true)));
+        assert_eq!(verify(SECRET, "081804", 1111111109), Ok(Value::from(true)));
+        assert_eq!(
+            verify(
+                "gezd gnbv gy3t qojq gezd gnbv gy3t qojq",
+                "005924",
+                1234567890
+            ),
+            Ok(Value::from(true))
+        );
+        assert_eq!(verify(SECRET, "005925", 1234567890), Ok(Value::from(false)));
+        assert_eq!(verify(SECRET, "5924", 1234567890), Ok(Value::from(false)));
+    }
+
+    #[test]
+    fn skew_window() {
+        // the codes of the previous and next steps
+        assert_eq!(verify(SECRET, "980357", 1234567890), Ok(Value::from(true)));
+        assert_eq!(verify(SECRET, "590587", 1234567890), Ok(Value::from(true)));
+        // two steps ahead
+        assert_eq!(verify(SECRET, "240500", 1234567890), Ok(Value::from(false)));
+    }
+
+    #[test]
+    fn malformed_arguments() {
+        assert!(verify("not base32!", "287082", 59).is_err());
+        // an unset or blank secret would be an empty key anyone can use
+        for secret in ["", "   ", "====", " = = "] {
+            assert!(verify(secret, "328482", 59).is_err(), "{secret:?}");
+        }
+        // 8 bytes is too short, 10 is enough
+        assert!(verify("GEZDGNBVGY3TQ", "287082", 59).is_err());
+        assert!(verify("GEZDGNBVGY3TQOJQ", "000000", 59).is_ok());
+        assert!(verify(SECRET, "287082", -1).is_err());
+    }
+}
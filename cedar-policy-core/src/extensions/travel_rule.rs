@@ -0,0 +1,393 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+//! This module contains the Cedar 'travelRule' extension.
+//!
+//! `r.isIvms101Originator()` and `r.isIvms101Beneficiary()` are true if a
+//! record is a well-formed IVMS101 `Originator` or `Beneficiary`, with the
+//! JSON field names of the standard, e.g.
+//! `context.travelRule.originator.isIvms101Originator()`. They let policies
+//! on VASP transfers require compliant travel-rule data.
+//!
+//! A well-formed originator has a nonempty set of `originatorPersons`, and a
+//! beneficiary a nonempty set of `beneficiaryPersons`. Each person has
+//! exactly one of:
+//! - `naturalPerson`, whose `name.nameIdentifier` is a nonempty set of
+//!   records with a `primaryIdentifier` and a `nameIdentifierType` of `LEGL`,
+//!   `ALIA`, `BIRT`, `MAID`, or `MISC`, and optionally a
+//!   `secondaryIdentifier`
+//! - `legalPerson`, whose `name.nameIdentifier` is a nonempty set of records
+//!   with a `legalPersonName` and a `legalPersonNameIdentifierType` of
+//!   `LEGL`, `SHRT`, or `TRAD`, and optionally a `countryOfRegistration`
+//!
+//! Names are 1 to 100 characters. A person's optional `geographicAddress` is
+//! a set of addresses, each with an `addressType` of `HOME`, `BIZZ`, or
+//! `GEOG`, a `townName`, a `country` which is an ISO 3166-1 alpha-2 code, and
+//! either an `addressLine` of 1 to 7 lines of up to 70 characters or a
+//! `streetName` with a `buildingName` or `buildingNumber`. A natural person
+//! who is an originator must also have at least one of a
+//! `geographicAddress`, `customerIdentification`, `nationalIdentification`,
+//! or `dateAndPlaceOfBirth`. An optional `accountNumber` is a set of strings.
+
+use crate::ast::{
+    CallStyle, Extension, ExtensionFunction, ExtensionOutputValue, Literal, StaticallyTyped, Type,
+    Value,
+};
+use crate::entities::SchemaType;
+use crate::evaluator;
+use smol_str::SmolStr;
+use std::collections::{BTreeMap, BTreeSet};
+
+// PANIC SAFETY All `Name`s in here are valid `Name`s
+#[allow(clippy::expect_used)]
+mod names {
+    use crate::ast::Name;
+    // PANIC SAFETY all of the names here are valid names
+    lazy_static::lazy_static! {
+        pub static ref TRAVEL_RULE : Name = Name::parse_unqualified_name("travelRule").expect("should be a valid identifier");
+        pub static ref IS_ORIGINATOR : Name = Name::parse_unqualified_name("isIvms101Originator").expect("should be a valid identifier");
+        pub static ref IS_BENEFICIARY : Name = Name::parse_unqualified_name("isIvms101Beneficiary").expect("should be a valid identifier");
+    }
+}
+
+/// The longest name IVMS101 allows
+const MAX_NAME_LENGTH: usize = 100;
+
+/// The longest address line IVMS101 allows
+const MAX_ADDRESS_LINE_LENGTH: usize = 70;
+
+/// The most address lines IVMS101 allows
+const MAX_ADDRESS_LINES: usize = 7;
+
+type Record = BTreeMap<SmolStr, Value>;
+
+fn as_record(v: &Value) -> Result<&Record, evaluator::EvaluationError> {
+    match v {
+        Value::Record(record) => Ok(record),
+        _ => Err(evaluator::EvaluationError::type_error(
+            vec![Type::Record],
+            v.type_of(),
+        )),
+    }
+}
+
+/// The record attribute `key` of `record`, if it has one
+fn record<'a>(record: &'a Record, key: &str) -> Option<&'a Record> {
+    match record.get(key)? {
+        Value::Record(r) => Some(r),
+        _ => None,
+    }
+}
+
+/// The string attribute `key` of `record`, if it has one
+fn string<'a>(record: &'a Record, key: &str) -> Option<&'a str> {
+    match record.get(key)? {
+        Value::Lit(Literal::String(s)) => Some(s),
+        _ => None,
+    }
+}
+
+/// The set attribute `key` of `record`, if it has one
+fn set<'a>(record: &'a Record, key: &str) -> Option<&'a BTreeSet<Value>> {
+    match record.get(key)? {
+        Value::Set(s) => Some(&s.authoritative),
+        _ => None,
+    }
+}
+
+/// Whether the attribute `key` of `record` is absent or passes `valid`
+fn optional(record: &Record, key: &str, valid: impl FnOnce(&Record, &str) -> bool) -> bool {
+    !record.contains_key(key) || valid(record, key)
+}
+
+/// Whether the attribute `key` of `record` is a nonempty set of records
+/// which all pass `valid`
+fn nonempty_records(record: &Record, key: &str, valid: impl Fn(&Record) -> bool) -> bool {
+    set(record, key).is_some_and(|elements| {
+        !elements.is_empty()
+            && elements
+                .iter()
+                .all(|element| matches!(element, Value::Record(r) if valid(r)))
+    })
+}
+
+fn is_name(s: &str) -> bool {
+    !s.trim().is_empty() && s.chars().count() <= MAX_NAME_LENGTH
+}
+
+fn is_country_code(s: &str) -> bool {
+    s.len() == 2 && s.bytes().all(|b| b.is_ascii_uppercase())
+}
+
+fn is_one_of(s: Option<&str>, codes: &[&str]) -> bool {
+    s.is_some_and(|s| codes.contains(&s))
+}
+
+fn is_string_set(record: &Record, key: &str) -> bool {
+    set(record, key).is_some_and(|elements| {
+        elements
+            .iter()
+            .all(|e| matches!(e, Value::Lit(Literal::String(_))))
+    })
+}
+
+fn is_address(address: &Record) -> bool {
+    let has_lines = set(address, "addressLine").is_some_and(|lines| {
+        (1..=MAX_ADDRESS_LINES).contains(&lines.len())
+            && lines.iter().all(|line| {
+                matches!(line, Value::Lit(Literal::String(s))
+                    if !s.trim().is_empty() && s.chars().count() <= MAX_ADDRESS_LINE_LENGTH)
+            })
+    });
+    let has_street = string(address, "streetName").is_some_and(is_name)
+        && (string(address, "buildingName").is_some_and(is_name)
+            || string(address, "buildingNumber").is_some_and(is_name));
+    is_one_of(string(address, "addressType"), &["HOME", "BIZZ", "GEOG"])
+        && string(address, "townName").is_some_and(is_name)
+        && string(address, "country").is_some_and(is_country_code)
+        && (has_lines || has_street)
+}
+
+fn is_natural_person(person: &Record, originator: bool) -> bool {
+    let named = record(person, "name").is_some_and(|name| {
+        nonempty_records(name, "nameIdentifier", |id| {
+            string(id, "primaryIdentifier").is_some_and(is_name)
+                && optional(id, "secondaryIdentifier", |id, key| {
+                    string(id, key).is_some_and(is_name)
+                })
+                && is_one_of(
+                    string(id, "nameIdentifierType"),
+                    &["LEGL", "ALIA", "BIRT", "MAID", "MISC"],
+                )
+        })
+    });
+    let identified = !originator
+        || [
+            "geographicAddress",
+            "customerIdentification",
+            "nationalIdentification",
+            "dateAndPlaceOfBirth",
+        ]
+        .iter()
+        .any(|key| person.contains_key(*key));
+    named
+        && identified
+        && optional(person, "geographicAddress", |person, key| {
+            nonempty_records(person, key, is_address)
+        })
+}
+
+fn is_legal_person(person: &Record) -> bool {
+    let named = record(person, "name").is_some_and(|name| {
+        nonempty_records(name, "nameIdentifier", |id| {
+            string(id, "legalPersonName").is_some_and(is_name)
+                && is_one_of(
+                    string(id, "legalPersonNameIdentifierType"),
+                    &["LEGL", "SHRT", "TRAD"],
+                )
+        })
+    });
+    named
+        && optional(person, "countryOfRegistration", |person, key| {
+            string(person, key).is_some_and(is_country_code)
+        })
+        && optional(person, "geographicAddress", |person, key| {
+            nonempty_records(person, key, is_address)
+        })
+}
+
+fn is_person(person: &Record, originator: bool) -> bool {
+    match (
+        record(person, "naturalPerson"),
+        record(person, "legalPerson"),
+    ) {
+        (Some(natural), None) => is_natural_person(natural, originator),
+        (None, Some(legal)) => is_legal_person(legal),
+        _ => false,
+    }
+}
+
+fn is_party(party: &Record, persons: &str, originator: bool) -> bool {
+    nonempty_records(party, persons, |person| is_person(person, originator))
+        && optional(party, "accountNumber", is_string_set)
+}
+
+/// Cedar function testing whether a record is a well-formed IVMS101
+/// `Originator`, returning a Cedar bool
+fn is_originator(originator: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let originator = as_record(&originator)?;
+    Ok(Value::from(is_party(originator, "originatorPersons", true)).into())
+}
+
+/// Cedar function testing whether a record is a well-formed IVMS101
+/// `Beneficiary`, returning a Cedar bool
+fn is_beneficiary(beneficiary: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let beneficiary = as_record(&beneficiary)?;
+    Ok(Value::from(is_party(beneficiary, "beneficiaryPersons", false)).into())
+}
+
+/// Construct the extension
+pub fn extension() -> Extension {
+    Extension::new(
+        names::TRAVEL_RULE.clone(),
+        vec![
+            ExtensionFunction::unary(
+                names::IS_ORIGINATOR.clone(),
+                CallStyle::MethodStyle,
+                Box::new(is_originator),
+                SchemaType::Bool,
+                None,
+            ),
+            ExtensionFunction::unary(
+                names::IS_BENEFICIARY.clone(),
+                CallStyle::MethodStyle,
+                Box::new(is_beneficiary),
+                SchemaType::Bool,
+                None,
+            ),
+        ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::test::{basic_entities, basic_request};
+    use crate::evaluator::{EvaluationErrorKind, Evaluator};
+    use crate::extensions::Extensions;
+    use crate::parser::parse_expr;
+
+    const ALICE: &str = r#"{
+        name: {nameIdentifier: [{primaryIdentifier: "Liddell", secondaryIdentifier: "Alice", nameIdentifierType: "LEGL"}]},
+        geographicAddress: [{addressType: "HOME", streetName: "Rabbit Hole", buildingNumber: "1", townName: "Oxford", country: "GB"}]
+    }"#;
+
+    const ACME: &str = r#"{
+        name: {nameIdentifier: [{legalPersonName: "Acme Ltd", legalPersonNameIdentifierType: "LEGL"}]},
+        countryOfRegistration: "US",
+        geographicAddress: [{addressType: "BIZZ", addressLine: ["1 Main St"], townName: "Springfield", country: "US"}]
+    }"#;
+
+    fn natural(person: &str) -> String {
+        format!("{{naturalPerson: {person}}}")
+    }
+
+    fn legal(person: &str) -> String {
+        format!("{{legalPerson: {person}}}")
+    }
+
+    fn eval(expr: &str) -> evaluator::Result<Value> {
+        let ext_array = [extension()];
+        let exts = Extensions::specific_extensions(&ext_array);
+        let request = basic_request();
+        let entities = basic_entities();
+        let eval = Evaluator::new(&request, &entities, &exts).unwrap();
+        eval.interpret_inline_policy(&parse_expr(expr).expect("parsing error"))
+    }
+
+    fn originator(persons: &str) -> bool {
+        let expr = format!(
+            "{{originatorPersons: [{persons}], accountNumber: [\"0x1\"]}}.isIvms101Originator()"
+        );
+        eval(&expr).unwrap() == Value::from(true)
+    }
+
+    #[test]
+    fn originators() {
+        assert!(originator(&natural(ALICE)));
+        assert!(originator(&legal(ACME)));
+        assert!(originator(&format!("{}, {}", natural(ALICE), legal(ACME))));
+        assert!(!originator(""));
+        // a natural person originator needs more than a name
+        let name = r#"name: {nameIdentifier: [{primaryIdentifier: "Liddell", nameIdentifierType: "LEGL"}]}"#;
+        assert!(!originator(&natural(&format!("{{{name}}}"))));
+        assert!(originator(&natural(&format!(
+            "{{{name}, customerIdentification: \"c-1\"}}"
+        ))));
+        // not both kinds of person
+        assert!(!originator(&format!(
+            "{{naturalPerson: {ALICE}, legalPerson: {ACME}}}"
+        )));
+        assert_eq!(
+            eval("{originatorPersons: [], accountNumber: [1]}.isIvms101Originator()").unwrap(),
+            Value::from(false)
+        );
+    }
+
+    #[test]
+    fn beneficiaries() {
+        let beneficiary = |persons: &str, key: &str| {
+            eval(&format!("{{{key}: [{persons}]}}.isIvms101Beneficiary()")).unwrap()
+                == Value::from(true)
+        };
+        let hatter = r#"{name: {nameIdentifier: [{primaryIdentifier: "Hatter", nameIdentifierType: "MISC"}]}}"#;
+        assert!(beneficiary(&natural(hatter), "beneficiaryPersons"));
+        assert!(beneficiary(&natural(ALICE), "beneficiaryPersons"));
+        assert!(!beneficiary(&natural(ALICE), "originatorPersons"));
+    }
+
+    #[test]
+    fn formats() {
+        for (from, to) in [
+            (
+                r#"nameIdentifierType: "LEGL""#,
+                r#"nameIdentifierType: "NICK""#,
+            ),
+            (
+                r#"primaryIdentifier: "Liddell""#,
+                r#"primaryIdentifier: " ""#,
+            ),
+            (r#"country: "GB""#, r#"country: "gb""#),
+            (r#"country: "GB""#, r#"country: "GBR""#),
+            (r#"addressType: "HOME""#, r#"addressType: "WORK""#),
+            (r#"buildingNumber: "1", "#, ""),
+            (r#"townName: "Oxford", "#, ""),
+        ] {
+            assert!(!originator(&natural(&ALICE.replace(from, to))), "{to}");
+        }
+        let name = "x".repeat(MAX_NAME_LENGTH);
+        assert!(originator(&natural(&ALICE.replace("Liddell", &name))));
+        let name = "x".repeat(MAX_NAME_LENGTH + 1);
+        assert!(!originator(&natural(&ALICE.replace("Liddell", &name))));
+        for (from, to) in [
+            (r#"countryOfRegistration: "US""#, "countryOfRegistration: 1"),
+            (r#"addressLine: ["1 Main St"]"#, "addressLine: []"),
+            (
+                r#"addressLine: ["1 Main St"]"#,
+                r#"addressLine: ["1", "2", "3", "4", "5", "6", "7", "8"]"#,
+            ),
+            (r#""LEGL""#, r#""BIRT""#),
+        ] {
+            assert!(!originator(&legal(&ACME.replace(from, to))), "{to}");
+        }
+    }
+
+    #[test]
+    fn type_errors() {
+        for expr in ["1.isIvms101Originator()", "[].isIvms101Beneficiary()"] {
+            match eval(expr) {
+                Err(e) => match e.error_kind() {
+                    EvaluationErrorKind::TypeError {
+                        expected: types, ..
+                    } => {
+                        assert_eq!(types, &vec![Type::Record]);
+                    }
+                    _ => panic!("Expected a type error for {expr}, got {e:?}"),
+                },
+                Ok(v) => panic!("Expected a type error for {expr}, got {v:?}"),
+            }
+        }
+    }
+}
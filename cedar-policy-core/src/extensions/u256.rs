@@ -28,6 +28,8 @@ use std::sync::Arc;
 use thiserror::Error;
 
 use ethers::prelude::U256;
+#[cfg(feature = "decimal")]
+use ethers::types::U512;
 
 /// UINT256 value, represented internally as an integer.
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
@@ -47,6 +49,10 @@ mod names {
         pub static ref LESS_THAN_OR_EQUAL : Name = Name::parse_unqualified_name("u256LessThanOrEqual").expect("should be a valid identifier");
         pub static ref GREATER_THAN : Name = Name::parse_unqualified_name("u256GreaterThan").expect("should be a valid identifier");
         pub static ref GREATER_THAN_OR_EQUAL : Name = Name::parse_unqualified_name("u256GreaterThanOrEqual").expect("should be a valid identifier");
+        #[cfg(feature = "decimal")]
+        pub static ref IMPLIED_PRICE : Name = Name::parse_unqualified_name("impliedPrice").expect("should be a valid identifier");
+        #[cfg(feature = "decimal")]
+        pub static ref DECIMAL : Name = Name::parse_unqualified_name("decimal").expect("should be a valid identifier");
     }
 }
 
@@ -62,6 +68,64 @@ enum Error {
     /// Overflow occurred when converting to a u256 value
     #[error("overflow when converting to u256")]
     Overflow,
+
+    /// A token can't have this many decimals
+    #[cfg(feature = "decimal")]
+    #[error("invalid token decimals: {0} (must be between 0 and {MAX_TOKEN_DECIMALS})")]
+    InvalidTokenDecimals(i64),
+
+    /// A price can't be implied by trading nothing
+    #[cfg(feature = "decimal")]
+    #[error("cannot imply a price from an input amount of 0")]
+    ZeroAmountIn,
+
+    /// The implied price is too large for a decimal
+    #[cfg(feature = "decimal")]
+    #[error("implied price is too large for a decimal")]
+    PriceOverflow,
+}
+
+/// The most decimals a token may have. `10^78` doesn't fit in a `u256`.
+#[cfg(feature = "decimal")]
+const MAX_TOKEN_DECIMALS: i64 = 77;
+
+/// Number of digits after the decimal point of a `decimal` value
+#[cfg(feature = "decimal")]
+const DECIMAL_DIGITS: i64 = 4;
+
+/// The price of one whole input token in whole output tokens, when
+/// `amount_in` base units of a token with `decimals_in` decimals trade for
+/// `amount_out` base units of one with `decimals_out`, scaled by
+/// `10^DECIMAL_DIGITS` and rounded down
+#[cfg(feature = "decimal")]
+fn implied_price(
+    amount_in: U256,
+    amount_out: U256,
+    decimals_in: i64,
+    decimals_out: i64,
+) -> Result<i64, Error> {
+    for decimals in [decimals_in, decimals_out] {
+        if !(0..=MAX_TOKEN_DECIMALS).contains(&decimals) {
+            return Err(Error::InvalidTokenDecimals(decimals));
+        }
+    }
+    if amount_in.is_zero() {
+        return Err(Error::ZeroAmountIn);
+    }
+    // price = (amount_out / 10^decimals_out) / (amount_in / 10^decimals_in),
+    // with the powers of ten moved to whichever side keeps them positive so
+    // the division happens last
+    let shift = decimals_in + DECIMAL_DIGITS - decimals_out;
+    let exp10 = |n: i64| U512::exp10(usize::try_from(n.unsigned_abs()).unwrap_or(usize::MAX));
+    let (numerator, denominator) = if shift >= 0 {
+        let numerator = U512::from(amount_out)
+            .checked_mul(exp10(shift))
+            .ok_or(Error::PriceOverflow)?;
+        (numerator, U512::from(amount_in))
+    } else {
+        (U512::from(amount_out), U512::from(amount_in) * exp10(shift))
+    };
+    i64::try_from(numerator / denominator).map_err(|_| Error::PriceOverflow)
 }
 
 impl UINT256 {
@@ -130,28 +194,98 @@ fn uint256_from_str(arg: Value) -> evaluator::Result<ExtensionOutputValue> {
     Ok(Value::ExtensionValue(Arc::new(e)).into())
 }
 
+/// Construct a `u256` Cedar value, for extensions whose functions return
+/// `u256`s
+#[cfg(any(
+    feature = "log-match",
+    feature = "gas",
+    feature = "hash",
+    feature = "vesting",
+    feature = "exposure"
+))]
+pub(crate) fn u256_value(value: U256) -> Value {
+    let e = ExtensionValueWithArgs::new(
+        Arc::new(UINT256 { value }),
+        vec![Value::from(value.to_string()).into()],
+        names::UINT256_FROM_STR_NAME.clone(),
+    );
+    Value::ExtensionValue(Arc::new(e))
+}
+
+/// Check that `v` is a u256 type and, if it is, return the wrapped value
+pub(crate) fn as_u256(v: &Value) -> Result<U256, evaluator::EvaluationError> {
+    use crate::ast::{StaticallyTyped, Type};
+    match v {
+        Value::ExtensionValue(ev) if ev.typename() == UINT256::typename() => {
+            // PANIC SAFETY Conditional above performs a typecheck
+            #[allow(clippy::expect_used)]
+            let u = ev
+                .value()
+                .as_any()
+                .downcast_ref::<UINT256>()
+                .expect("already typechecked, so this downcast should succeed");
+            Ok(u.value)
+        }
+        _ => Err(evaluator::EvaluationError::type_error(
+            vec![Type::Extension {
+                name: UINT256::typename(),
+            }],
+            v.type_of(),
+        )),
+    }
+}
+
 /// Cedar function that tests whether the first `u256` Cedar type is
 /// less than the second `u256` Cedar type, returning a Cedar bool
 fn uint256_lt(left: Value, right: Value) -> evaluator::Result<ExtensionOutputValue> {
-    Ok(Value::Lit((left.lt(&right)).into()).into())
+    let left = as_u256(&left)?;
+    let right = as_u256(&right)?;
+    Ok(Value::Lit((left < right).into()).into())
 }
 
 /// Cedar function that tests whether the first `u256` Cedar type is
 /// less than or equal to the second `u256` Cedar type, returning a Cedar bool
 fn uint256_le(left: Value, right: Value) -> evaluator::Result<ExtensionOutputValue> {
-    Ok(Value::Lit((left.le(&right)).into()).into())
+    let left = as_u256(&left)?;
+    let right = as_u256(&right)?;
+    Ok(Value::Lit((left <= right).into()).into())
 }
 
 /// Cedar function that tests whether the first `u256` Cedar type is
 /// greater than the second `u256` Cedar type, returning a Cedar bool
 fn uint256_gt(left: Value, right: Value) -> evaluator::Result<ExtensionOutputValue> {
-    Ok(Value::Lit((left.gt(&right)).into()).into())
+    let left = as_u256(&left)?;
+    let right = as_u256(&right)?;
+    Ok(Value::Lit((left > right).into()).into())
 }
 
 /// Cedar function that tests whether the first `u256` Cedar type is
 /// greater than or equal to the second `u256` Cedar type, returning a Cedar bool
 fn uint256_ge(left: Value, right: Value) -> evaluator::Result<ExtensionOutputValue> {
-    Ok(Value::Lit((left.ge(&right)).into()).into())
+    let left = as_u256(&left)?;
+    let right = as_u256(&right)?;
+    Ok(Value::Lit((left >= right).into()).into())
+}
+
+/// Cedar function that computes the price implied by trading `amountIn` of a
+/// token with `decimalsIn` decimals for `amountOut` of one with
+/// `decimalsOut`, in output tokens per input token, returning a Cedar
+/// `decimal`
+#[cfg(feature = "decimal")]
+fn uint256_implied_price(
+    amount_in: Value,
+    amount_out: Value,
+    decimals_in: Value,
+    decimals_out: Value,
+) -> evaluator::Result<ExtensionOutputValue> {
+    let price = implied_price(
+        as_u256(&amount_in)?,
+        as_u256(&amount_out)?,
+        decimals_in.get_as_long()?,
+        decimals_out.get_as_long()?,
+    )
+    .map_err(|e| extension_err(e.to_string()))?;
+    Ok(super::decimal::decimal_value(price).into())
 }
 
 /// Construct the extension
@@ -195,7 +329,22 @@ pub fn extension() -> Extension {
                 CallStyle::MethodStyle,
                 Box::new(uint256_ge),
                 SchemaType::Bool,
-                (Some(uint256_type.clone()), Some(uint256_type)),
+                (Some(uint256_type.clone()), Some(uint256_type.clone())),
+            ),
+            #[cfg(feature = "decimal")]
+            ExtensionFunction::quaternary(
+                names::IMPLIED_PRICE.clone(),
+                CallStyle::FunctionStyle,
+                Box::new(uint256_implied_price),
+                SchemaType::Extension {
+                    name: names::DECIMAL.clone(),
+                },
+                (
+                    Some(uint256_type.clone()),
+                    Some(uint256_type),
+                    Some(SchemaType::Long),
+                    Some(SchemaType::Long),
+                ),
             ),
         ],
     )
@@ -390,6 +539,32 @@ mod tests {
         );
     }
 
+    #[test]
+    fn uint256_is() {
+        let ext_array = [extension()];
+        let exts = Extensions::specific_extensions(&ext_array);
+        let request = basic_request();
+        let entities = basic_entities();
+        let eval = Evaluator::new(&request, &entities, &exts).unwrap();
+
+        assert_eq!(
+            eval.interpret_inline_policy(
+                &parse_expr(r#"u256("123") is u256"#).expect("parsing error")
+            ),
+            Ok(Value::from(true))
+        );
+        assert_eq!(
+            eval.interpret_inline_policy(
+                &parse_expr(r#"u256("123") is decimal"#).expect("parsing error")
+            ),
+            Ok(Value::from(false))
+        );
+        assert_eq!(
+            eval.interpret_inline_policy(&parse_expr(r#""123" is u256"#).expect("parsing error")),
+            Ok(Value::from(false))
+        );
+    }
+
     fn uint256_ops_helper(op: &str, tests: Vec<((Expr, Expr), bool)>) {
         let ext_array = [extension()];
         let exts = Extensions::specific_extensions(&ext_array);
@@ -463,6 +638,81 @@ mod tests {
         );
     }
 
+    #[test]
+    #[cfg(feature = "decimal")]
+    fn implied_prices() {
+        let ext_array = [extension(), super::super::decimal::extension()];
+        let exts = Extensions::specific_extensions(&ext_array);
+        let request = basic_request();
+        let entities = basic_entities();
+        let eval = Evaluator::new(&request, &entities, &exts).unwrap();
+
+        let price = |amount_in: &str, amount_out: &str, decimals_in: i64, decimals_out: i64| {
+            eval.interpret_inline_policy(
+                &parse_expr(&format!(
+                    r#"impliedPrice(u256("{amount_in}"), u256("{amount_out}"), {decimals_in}, {decimals_out})"#
+                ))
+                .expect("parsing error"),
+            )
+        };
+        let is = |args: (&str, &str, i64, i64), expected: &str| {
+            let (amount_in, amount_out, decimals_in, decimals_out) = args;
+            eval.interpret_inline_policy(
+                &parse_expr(&format!(
+                    r#"impliedPrice(u256("{amount_in}"), u256("{amount_out}"), {decimals_in}, {decimals_out}) == decimal("{expected}")"#
+                ))
+                .expect("parsing error"),
+            )
+        };
+        // 1.5 WETH for 3000.185 USDC
+        assert_eq!(
+            is(("1500000000000000000", "3000185000", 18, 6), "2000.1233"),
+            Ok(Value::from(true))
+        );
+        // the other way around
+        assert_eq!(
+            is(("3000185000", "1500000000000000000", 6, 18), "0.0004"),
+            Ok(Value::from(true))
+        );
+        // amounts beyond a Long
+        assert_eq!(
+            is(
+                (
+                    "100000000000000000000000000",
+                    "250000000000000000000000000",
+                    18,
+                    18
+                ),
+                "2.5"
+            ),
+            Ok(Value::from(true))
+        );
+        assert_eq!(is(("1", "0", 0, 77), "0.0"), Ok(Value::from(true)));
+
+        // bounding an execution price
+        assert_eq!(
+            eval.interpret_inline_policy(
+                &parse_expr(
+                    r#"impliedPrice(u256("1500000000000000000"), u256("3000185000"), 18, 6).greaterThanOrEqual(decimal("1990.0"))"#
+                )
+                .expect("parsing error"),
+            ),
+            Ok(Value::from(true))
+        );
+
+        assert_uint256_err(price("0", "1", 18, 6));
+        assert_uint256_err(price("1", "1", 78, 6));
+        assert_uint256_err(price("1", "1", 18, -1));
+        // a price too large for a decimal
+        assert_uint256_err(price("1", "1000000000000000", 0, 0));
+        assert_uint256_err(price(
+            "1",
+            "115792089237316195423570985008687907853269984665640564039457584007913129639935",
+            77,
+            0,
+        ));
+    }
+
     fn check_round_trip(s: &str) {
         let d = UINT256::from_str(s).expect("should be a valid u256");
         assert_eq!(s, d.to_string());
@@ -0,0 +1,442 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+//! This module contains the Cedar 'vesting' extension.
+
+use crate::ast::{
+    CallStyle, Extension, ExtensionFunction, ExtensionOutputValue, ExtensionValue,
+    ExtensionValueWithArgs, Literal, Name, StaticallyTyped, Type, Value,
+};
+use crate::entities::SchemaType;
+use crate::evaluator;
+use ethers::types::U256;
+use std::sync::Arc;
+use thiserror::Error;
+
+/// A linear vesting schedule with a cliff: nothing vests before
+/// `start + cliff`, then `total` vests linearly from `start` until
+/// `start + duration`, as with OpenZeppelin's `VestingWallet`.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub struct Vesting {
+    start: i64,
+    cliff: i64,
+    duration: i64,
+    total: U256,
+}
+
+// PANIC SAFETY All `Name`s in here are valid `Name`s
+#[allow(clippy::expect_used)]
+mod names {
+    use super::{Name, EXTENSION_NAME};
+    // PANIC SAFETY all of the names here are valid names
+    lazy_static::lazy_static! {
+        pub static ref VESTING_FROM_STR_NAME : Name = Name::parse_unqualified_name(EXTENSION_NAME).expect("should be a valid identifier");
+        pub static ref VESTED_AT : Name = Name::parse_unqualified_name("vestedAt").expect("should be a valid identifier");
+        pub static ref RELEASABLE_AT : Name = Name::parse_unqualified_name("releasableAt").expect("should be a valid identifier");
+        pub static ref TOTAL : Name = Name::parse_unqualified_name("total").expect("should be a valid identifier");
+        pub static ref CLIFF_END : Name = Name::parse_unqualified_name("cliffEnd").expect("should be a valid identifier");
+        pub static ref END : Name = Name::parse_unqualified_name("end").expect("should be a valid identifier");
+        pub static ref U256 : Name = Name::parse_unqualified_name("u256").expect("should be a valid identifier");
+    }
+}
+
+/// Potential errors when working with vesting values. Note that these are
+/// converted to evaluator::Err::ExtensionErr (which takes a string argument)
+/// before being reported to users.
+#[derive(Debug, Error)]
+enum Error {
+    /// Error parsing the input string as a vesting value
+    #[error("input string is not a well-formed vesting value: {0}")]
+    FailedParse(String),
+
+    /// The cliff or duration is negative, or the cliff is after the end
+    #[error("invalid schedule: cliff {cliff} and duration {duration} must satisfy 0 <= cliff <= duration")]
+    InvalidSchedule { cliff: i64, duration: i64 },
+
+    /// The end of the schedule doesn't fit in a Long
+    #[error("schedule starting at {start} with duration {duration} ends out of range")]
+    Overflow { start: i64, duration: i64 },
+}
+
+impl Vesting {
+    /// The Cedar typename of vesting values
+    fn typename() -> Name {
+        names::VESTING_FROM_STR_NAME.clone()
+    }
+
+    /// Convert a string of the form `start,cliff,duration,total` into a
+    /// `Vesting` value: the start, in seconds since the unix epoch, the cliff
+    /// and duration, in seconds after the start, and the total, in base
+    /// units. Whitespace around each component is ignored.
+    fn from_str(str: impl AsRef<str>) -> Result<Self, Error> {
+        let str = str.as_ref();
+        let fail = || Error::FailedParse(str.to_owned());
+        let mut parts = str.split(',').map(str::trim);
+        let (Some(start), Some(cliff), Some(duration), Some(total), None) = (
+            parts.next(),
+            parts.next(),
+            parts.next(),
+            parts.next(),
+            parts.next(),
+        ) else {
+            return Err(fail());
+        };
+        let start: i64 = start.parse().map_err(|_| fail())?;
+        let cliff: i64 = cliff.parse().map_err(|_| fail())?;
+        let duration: i64 = duration.parse().map_err(|_| fail())?;
+        if !total.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(fail());
+        }
+        let total = U256::from_dec_str(total).map_err(|_| fail())?;
+        if cliff < 0 || cliff > duration {
+            return Err(Error::InvalidSchedule { cliff, duration });
+        }
+        if start.checked_add(duration).is_none() {
+            return Err(Error::Overflow { start, duration });
+        }
+        Ok(Self {
+            start,
+            cliff,
+            duration,
+            total,
+        })
+    }
+
+    /// The amount vested at `timestamp`, rounded down
+    pub fn vested_at(&self, timestamp: i64) -> U256 {
+        // `from_str` checked that the end doesn't overflow
+        if timestamp < self.start.saturating_add(self.cliff) {
+            U256::zero()
+        } else if timestamp >= self.start.saturating_add(self.duration) {
+            self.total
+        } else {
+            // here `start <= timestamp < start + duration`, so the elapsed
+            // time and duration are positive and the quotient is at most
+            // `total`
+            let elapsed = U256::from(timestamp.abs_diff(self.start));
+            let product = self.total.full_mul(elapsed) / self.duration.unsigned_abs();
+            U256::try_from(product).unwrap_or(self.total)
+        }
+    }
+
+    /// The total amount vesting, in base units
+    pub fn total(&self) -> U256 {
+        self.total
+    }
+
+    /// When the cliff ends, in seconds since the unix epoch
+    pub fn cliff_end(&self) -> i64 {
+        self.start.saturating_add(self.cliff)
+    }
+
+    /// When the schedule ends, in seconds since the unix epoch
+    pub fn end(&self) -> i64 {
+        self.start.saturating_add(self.duration)
+    }
+}
+
+impl std::fmt::Display for Vesting {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{},{},{},{}",
+            self.start, self.cliff, self.duration, self.total
+        )
+    }
+}
+
+impl ExtensionValue for Vesting {
+    fn typename(&self) -> Name {
+        Self::typename()
+    }
+}
+
+const EXTENSION_NAME: &str = "vesting";
+
+fn extension_err(msg: impl Into<String>) -> evaluator::EvaluationError {
+    evaluator::EvaluationError::failed_extension_function_application(
+        names::VESTING_FROM_STR_NAME.clone(),
+        msg.into(),
+    )
+}
+
+/// Cedar function that constructs a `vesting` Cedar type from a
+/// Cedar string
+fn vesting_from_str(arg: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let str = arg.get_as_string()?;
+    let vesting = Vesting::from_str(str.as_str()).map_err(|e| extension_err(e.to_string()))?;
+    let function_name = names::VESTING_FROM_STR_NAME.clone();
+    let e = ExtensionValueWithArgs::new(Arc::new(vesting), vec![arg.into()], function_name);
+    Ok(Value::ExtensionValue(Arc::new(e)).into())
+}
+
+/// Check that `v` is a vesting type and, if it is, return the wrapped value
+fn as_vesting(v: &Value) -> Result<&Vesting, evaluator::EvaluationError> {
+    match v {
+        Value::ExtensionValue(ev) if ev.typename() == Vesting::typename() => {
+            // PANIC SAFETY Conditional above performs a typecheck
+            #[allow(clippy::expect_used)]
+            let vesting = ev
+                .value()
+                .as_any()
+                .downcast_ref::<Vesting>()
+                .expect("already typechecked, so this downcast should succeed");
+            Ok(vesting)
+        }
+        _ => Err(evaluator::EvaluationError::type_error(
+            vec![Type::Extension {
+                name: Vesting::typename(),
+            }],
+            v.type_of(),
+        )),
+    }
+}
+
+/// Cedar function that returns the amount of a `vesting` schedule vested at
+/// a timestamp, as a Cedar `u256`
+fn vested_at(vesting: Value, timestamp: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let vesting = as_vesting(&vesting)?;
+    let vested = vesting.vested_at(timestamp.get_as_long()?);
+    Ok(super::u256::u256_value(vested).into())
+}
+
+/// Cedar function that returns the amount of a `vesting` schedule vested at
+/// a timestamp and not already claimed, as a Cedar `u256`. Claims beyond the
+/// vested amount leave nothing releasable.
+fn releasable_at(
+    vesting: Value,
+    timestamp: Value,
+    claimed: Value,
+) -> evaluator::Result<ExtensionOutputValue> {
+    let vesting = as_vesting(&vesting)?;
+    let vested = vesting.vested_at(timestamp.get_as_long()?);
+    let claimed = super::u256::as_u256(&claimed)?;
+    Ok(super::u256::u256_value(vested.saturating_sub(claimed)).into())
+}
+
+/// Cedar function that returns the total of a `vesting` schedule, as a Cedar
+/// `u256`
+fn total(vesting: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let vesting = as_vesting(&vesting)?;
+    Ok(super::u256::u256_value(vesting.total()).into())
+}
+
+/// Cedar function that returns when the cliff of a `vesting` schedule ends
+fn cliff_end(vesting: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let vesting = as_vesting(&vesting)?;
+    Ok(Value::Lit(Literal::Long(vesting.cliff_end())).into())
+}
+
+/// Cedar function that returns when a `vesting` schedule ends
+fn end(vesting: Value) -> evaluator::Result<ExtensionOutputValue> {
+    let vesting = as_vesting(&vesting)?;
+    Ok(Value::Lit(Literal::Long(vesting.end())).into())
+}
+
+/// Construct the extension
+pub fn extension() -> Extension {
+    let vesting_type = SchemaType::Extension {
+        name: Vesting::typename(),
+    };
+    let u256_type = SchemaType::Extension {
+        name: names::U256.clone(),
+    };
+    Extension::new(
+        names::VESTING_FROM_STR_NAME.clone(),
+        vec![
+            ExtensionFunction::unary(
+                names::VESTING_FROM_STR_NAME.clone(),
+                CallStyle::FunctionStyle,
+                Box::new(vesting_from_str),
+                vesting_type.clone(),
+                Some(SchemaType::String),
+            ),
+            ExtensionFunction::binary(
+                names::VESTED_AT.clone(),
+                CallStyle::MethodStyle,
+                Box::new(vested_at),
+                u256_type.clone(),
+                (Some(vesting_type.clone()), Some(SchemaType::Long)),
+            ),
+            ExtensionFunction::ternary(
+                names::RELEASABLE_AT.clone(),
+                CallStyle::MethodStyle,
+                Box::new(releasable_at),
+                u256_type.clone(),
+                (
+                    Some(vesting_type.clone()),
+                    Some(SchemaType::Long),
+                    Some(u256_type.clone()),
+                ),
+            ),
+            ExtensionFunction::unary(
+                names::TOTAL.clone(),
+                CallStyle::MethodStyle,
+                Box::new(total),
+                u256_type,
+                Some(vesting_type.clone()),
+            ),
+            ExtensionFunction::unary(
+                names::CLIFF_END.clone(),
+                CallStyle::MethodStyle,
+                Box::new(cliff_end),
+                SchemaType::Long,
+                Some(vesting_type.clone()),
+            ),
+            ExtensionFunction::unary(
+                names::END.clone(),
+                CallStyle::MethodStyle,
+                Box::new(end),
+                SchemaType::Long,
+                Some(vesting_type),
+            ),
+        ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::test::{basic_entities, basic_request};
+    use crate::evaluator::Evaluator;
+    use crate::extensions::Extensions;
+    use crate::parser::parse_expr;
+
+    /// Asserts that a `Result` is an `Err::ExtensionErr` with our extension name
+    fn assert_vesting_err<T: std::fmt::Debug>(res: evaluator::Result<T>) {
+        match res {
+            Err(e) => match e.error_kind() {
+                evaluator::EvaluationErrorKind::FailedExtensionFunctionApplication {
+                    extension_name,
+                    ..
+                } => {
+                    assert_eq!(
+                        *extension_name,
+                        Name::parse_unqualified_name("vesting")
+                            .expect("should be a valid identifier")
+                    )
+                }
+                _ => panic!("Expected a vesting ExtensionErr, got {:?}", e),
+            },
+            Ok(v) => panic!("Expected a vesting ExtensionErr, got {:?}", v),
+        }
+    }
+
+    /// One year, in seconds
+    const YEAR: i64 = 31_536_000;
+
+    #[test]
+    fn schedule() {
+        // four years with a one year cliff, starting at 1_700_000_000
+        let vesting = Vesting::from_str(format!(
+            "1700000000, {YEAR}, {}, 4000000000000000000000",
+            4 * YEAR
+        ))
+        .unwrap();
+        let start = 1_700_000_000;
+        let vested = |t: i64| vesting.vested_at(t).to_string();
+        assert_eq!(vested(0), "0");
+        assert_eq!(vested(start), "0");
+        assert_eq!(vested(start + YEAR - 1), "0");
+        assert_eq!(vested(start + YEAR), "1000000000000000000000");
+        assert_eq!(
+            vested(start + 2 * YEAR + YEAR / 2),
+            "2500000000000000000000"
+        );
+        assert_eq!(vested(start + 4 * YEAR), "4000000000000000000000");
+        assert_eq!(vested(i64::MAX), "4000000000000000000000");
+        // rounded down
+        assert_eq!(
+            Vesting::from_str("0,0,3,10").unwrap().vested_at(1),
+            U256::from(3)
+        );
+        // without a duration, everything vests at the start
+        let instant = Vesting::from_str("100,0,0,10").unwrap();
+        assert_eq!(instant.vested_at(99), U256::zero());
+        assert_eq!(instant.vested_at(100), U256::from(10));
+        // the largest total doesn't overflow
+        let max = Vesting::from_str(format!("0,0,2,{}", U256::MAX)).unwrap();
+        assert_eq!(max.vested_at(1), U256::MAX / 2);
+    }
+
+    #[test]
+    fn vesting_creation() {
+        let ext_array = [extension(), super::super::u256::extension()];
+        let exts = Extensions::specific_extensions(&ext_array);
+        let request = basic_request();
+        let entities = basic_entities();
+        let eval = Evaluator::new(&request, &entities, &exts).unwrap();
+
+        let eval_str =
+            |src: &str| eval.interpret_inline_policy(&parse_expr(src).expect("parsing error"));
+        assert_eq!(
+            eval_str(r#"vesting("100, 10, 50, 1000").cliffEnd()"#),
+            Ok(Value::from(110))
+        );
+        assert_eq!(
+            eval_str(r#"vesting("100,10,50,1000").end()"#),
+            Ok(Value::from(150))
+        );
+        assert_eq!(
+            eval_str(r#"vesting("100,10,50,1000").total() == u256("1000")"#),
+            Ok(Value::from(true))
+        );
+        assert_eq!(
+            eval_str(r#"vesting("100,10,50,1000") == vesting(" 100 , 10 , 50 , 1000 ")"#),
+            Ok(Value::from(true))
+        );
+
+        assert_vesting_err(eval_str(r#"vesting("100,10,50")"#));
+        assert_vesting_err(eval_str(r#"vesting("100,10,50,1000,1")"#));
+        assert_vesting_err(eval_str(r#"vesting("100,10,50,-1000")"#));
+        assert_vesting_err(eval_str(r#"vesting("100,10,50,1.5")"#));
+        assert_vesting_err(eval_str(r#"vesting("100,60,50,1000")"#));
+        assert_vesting_err(eval_str(r#"vesting("100,-1,50,1000")"#));
+        assert_vesting_err(eval_str(&format!(r#"vesting("{},0,1,1000")"#, i64::MAX)));
+    }
+
+    #[test]
+    fn claims() {
+        let ext_array = [extension(), super::super::u256::extension()];
+        let exts = Extensions::specific_extensions(&ext_array);
+        let request = basic_request();
+        let entities = basic_entities();
+        let eval = Evaluator::new(&request, &entities, &exts).unwrap();
+
+        let eval_str =
+            |src: &str| eval.interpret_inline_policy(&parse_expr(src).expect("parsing error"));
+        let schedule = r#"vesting("100,10,100,1000")"#;
+        assert_eq!(
+            eval_str(&format!(r#"{schedule}.vestedAt(150) == u256("500")"#)),
+            Ok(Value::from(true))
+        );
+        // requested amount <= vested - claimed
+        let may_claim = |requested: &str, claimed: &str, now: i64| {
+            eval_str(&format!(
+                r#"u256("{requested}").u256LessThanOrEqual({schedule}.releasableAt({now}, u256("{claimed}")))"#
+            ))
+        };
+        assert_eq!(may_claim("300", "200", 150), Ok(Value::from(true)));
+        assert_eq!(may_claim("301", "200", 150), Ok(Value::from(false)));
+        assert_eq!(may_claim("1", "0", 105), Ok(Value::from(false)));
+        // claims beyond the vested amount leave nothing
+        assert_eq!(
+            eval_str(&format!(
+                r#"{schedule}.releasableAt(150, u256("700")) == u256("0")"#
+            )),
+            Ok(Value::from(true))
+        );
+    }
+}
@@ -0,0 +1,332 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+//! This module contains the Cedar 'webauthn' extension.
+//!
+//! `webauthnVerify(publicKey, assertion, expected)` verifies a WebAuthn
+//! (passkey) assertion, so that policies for e.g. smart account recovery or
+//! step-up authentication can require the principal's authenticator to have
+//! signed a challenge. The public key is the credential's P-256 key, as the
+//! hex string of its uncompressed SEC1 encoding (`0x04`, `x` and `y`),
+//! typically stored in an attribute of the principal. The assertion is a
+//! record of the authenticator's response:
+//!
+//! - `authenticatorData`: the authenticator data, as a hex string
+//! - `clientDataJSON`: the client data, as the JSON string itself
+//! - `signature`: the DER-encoded ECDSA signature, as a hex string
+//!
+//! and `expected` is a record of what it must have been made for:
+//!
+//! - `challenge`: the (base64url) challenge
+//! - `origin`: the origin of the relying party's web page, e.g.
+//!   `"https://example.com"`
+//! - `rpId`: the relying party ID, e.g. `"example.com"`
+//!
+//! The assertion is valid if the client data is of type `webauthn.get` with
+//! the expected challenge and origin, the authenticator data has the SHA-256
+//! hash of the expected relying party ID and the user presence flag set, and
+//! the signature over the authenticator data and the SHA-256 hash of the
+//! client data verifies. Checking the origin and relying party ID is what
+//! makes an assertion collected by a phishing site invalid.
+
+use crate::ast::{
+    CallStyle, Extension, ExtensionFunction, ExtensionOutputValue, Literal, StaticallyTyped, Type,
+    Value,
+};
+use crate::entities::SchemaType;
+use crate::evaluator;
+use p256::ecdsa::signature::Verifier;
+use p256::ecdsa::{Signature, VerifyingKey};
+use sha2::{Digest, Sha256};
+use smol_str::SmolStr;
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+// PANIC SAFETY All `Name`s in here are valid `Name`s
+#[allow(clippy::expect_used)]
+mod names {
+    use crate::ast::Name;
+    // PANIC SAFETY all of the names here are valid names
+    lazy_static::lazy_static! {
+        pub static ref WEBAUTHN : Name = Name::parse_unqualified_name("webauthn").expect("should be a valid identifier");
+        pub static ref WEBAUTHN_VERIFY : Name = Name::parse_unqualified_name("webauthnVerify").expect("should be a valid identifier");
+    }
+}
+
+/// Potential errors when verifying assertions. Note that these are converted
+/// to evaluator::Err::ExtensionErr (which takes a string argument) before
+/// being reported to users.
+#[derive(Debug, Error)]
+enum Error {
+    /// The public key isn't an uncompressed P-256 point
+    #[error("the public key is not the hex string of an uncompressed P-256 point")]
+    BadPublicKey,
+    /// An attribute of a record is missing or isn't a string
+    #[error("the {0} has no string attribute `{1}`")]
+    MissingAttribute(&'static str, &'static str),
+    /// An attribute of the assertion isn't a hex string
+    #[error("the assertion's `{0}` is not a hex string")]
+    NotHex(&'static str),
+    /// The authenticator data is shorter than its fixed fields
+    #[error("the authenticator data is too short")]
+    ShortAuthenticatorData,
+    /// The signature isn't a DER-encoded ECDSA signature
+    #[error("the signature is not a DER-encoded ECDSA signature")]
+    BadSignature,
+}
+
+fn extension_err(msg: impl Into<String>) -> evaluator::EvaluationError {
+    evaluator::EvaluationError::failed_extension_function_application(
+        names::WEBAUTHN.clone(),
+        msg.into(),
+    )
+}
+
+/// The user presence flag of the authenticator data
+const USER_PRESENT: u8 = 0x01;
+/// The length of the relying party ID hash at the start of the
+/// authenticator data, which is followed by the flags
+const RP_ID_HASH_LEN: usize = 32;
+/// The length of the authenticator data without extensions or attested
+/// credential data: the relying party ID hash, flags and signature counter
+const AUTHENTICATOR_DATA_LEN: usize = 37;
+
+fn as_record(v: &Value) -> Result<&BTreeMap<SmolStr, Value>, evaluator::EvaluationError> {
+    match v {
+        Value::Record(record) => Ok(record),
+        _ => Err(evaluator::EvaluationError::type_error(
+            vec![Type::Record],
+            v.type_of(),
+        )),
+    }
+}
+
+fn string_attr<'a>(
+    record: &'a BTreeMap<SmolStr, Value>,
+    what: &'static str,
+    attr: &'static str,
+) -> evaluator::Result<&'a str> {
+    match record.get(attr) {
+        Some(Value::Lit(Literal::String(s))) => Ok(s.as_str()),
+        _ => Err(extension_err(
+            Error::MissingAttribute(what, attr).to_string(),
+        )),
+    }
+}
+
+fn hex_attr(record: &BTreeMap<SmolStr, Value>, attr: &'static str) -> evaluator::Result<Vec<u8>> {
+    let s = string_attr(record, "assertion", attr)?;
+    hex::decode(s.strip_prefix("0x").unwrap_or(s))
+        .map_err(|_| extension_err(Error::NotHex(attr).to_string()))
+}
+
+/// The public key in the hex string `s`
+fn public_key(s: &str) -> Option<VerifyingKey> {
+    let bytes = hex::decode(s.strip_prefix("0x").unwrap_or(s)).ok()?;
+    // only the uncompressed encoding is accepted
+    if bytes.len() != 65 || bytes.first() != Some(&0x04) {
+        return None;
+    }
+    VerifyingKey::from_sec1_bytes(&bytes).ok()
+}
+
+/// Whether `client_data` is for an assertion of `challenge` on a page of
+/// `origin`
+fn client_data_matches(client_data: &str, challenge: &str, origin: &str) -> bool {
+    let Ok(serde_json::Value::Object(client_data)) = serde_json::from_str(client_data) else {
+        return false;
+    };
+    let field = |name| client_data.get(name).and_then(serde_json::Value::as_str);
+    field("type") == Some("webauthn.get")
+        && field("challenge") == Some(challenge)
+        && field("origin") == Some(origin)
+}
+
+/// Cedar function that verifies a WebAuthn assertion, by the credential with
+/// `public_key`, of the challenge for the origin and relying party in
+/// `expected`, returning a Cedar bool
+fn webauthn_verify(
+    public_key_hex: Value,
+    assertion: Value,
+    expected: Value,
+) -> evaluator::Result<ExtensionOutputValue> {
+    let key = public_key(public_key_hex.get_as_string()?)
+        .ok_or_else(|| extension_err(Error::BadPublicKey.to_string()))?;
+    let assertion = as_record(&assertion)?;
+    let authenticator_data = hex_attr(assertion, "authenticatorData")?;
+    let client_data = string_attr(assertion, "assertion", "clientDataJSON")?;
+    let signature = Signature::from_der(&hex_attr(assertion, "signature")?)
+        .map_err(|_| extension_err(Error::BadSignature.to_string()))?;
+    let expected = as_record(&expected)?;
+    let challenge = string_attr(expected, "expected assertion", "challenge")?;
+    let origin = string_attr(expected, "expected assertion", "origin")?;
+    let rp_id = string_attr(expected, "expected assertion", "rpId")?;
+    let flags = authenticator_data
+        .get(RP_ID_HASH_LEN)
+        .filter(|_| authenticator_data.len() >= AUTHENTICATOR_DATA_LEN)
+        .ok_or_else(|| extension_err(Error::ShortAuthenticatorData.to_string()))?;
+    let rp_id_hash = authenticator_data.get(..RP_ID_HASH_LEN);
+
+    if !client_data_matches(client_data, challenge, origin)
+        || rp_id_hash != Some(&Sha256::digest(rp_id.as_bytes())[..])
+        || flags & USER_PRESENT == 0
+    {
+        return Ok(Value::from(false).into());
+    }
+    let message = [
+        authenticator_data.as_slice(),
+        &Sha256::digest(client_data.as_bytes()),
+    ]
+    .concat();
+    let valid = key.verify(&message, &signature).is_ok();
+    Ok(Value::from(valid).into())
+}
+
+/// Construct the extension
+pub fn extension() -> Extension {
+    Extension::new(
+        names::WEBAUTHN.clone(),
+        vec![ExtensionFunction::ternary(
+            names::WEBAUTHN_VERIFY.clone(),
+            CallStyle::FunctionStyle,
+            Box::new(webauthn_verify),
+            SchemaType::Bool,
+            (Some(SchemaType::String), None, None),
+        )],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::test::{basic_entities, basic_request};
+    use crate::evaluator::Evaluator;
+    use crate::extensions::Extensions;
+    use crate::parser::parse_expr;
+
+    const PUBLIC_KEY: &str = concat!(
+        "0x049fad84aeae08bbef7f010014d82cef6a09de2b0cf871b5ce0c4f1d13a59a5934",
+        "07cb45769f1070e2c2470fe5b1bfe63133c0b0cdc64ea4bf3791a8ec2a07fd4f",
+    );
+    const AUTHENTICATOR_DATA: &str =
+        "a379a6f6eeafb9a55e378c118034e2751e682fab9f2d30ab13d2125586ce19470500000001";
+    const CLIENT_DATA: &str = r#"{\"type\":\"webauthn.get\",\"challenge\":\"cmVjb3Zlcnk\",\"origin\":\"https://example.com\",\"crossOrigin\":false}"#;
+    const SIGNATURE: &str = concat!(
+        "3046022100dd6b10db78678e090da65e4cdb49efadb76c9eb460ab380be34e54bbe2e35f50",
+        "022100920b1420ed848ac4c4ed22fb327ea3a11d0f83d6f94dade73c5b589ea5773e18",
+    );
+
+    fn eval(expr: &str) -> evaluator::Result<Value> {
+        let ext_array = [extension()];
+        let exts = Extensions::specific_extensions(&ext_array);
+        let request = basic_request();
+        let entities = basic_entities();
+        let eval = Evaluator::new(&request, &entities, &exts).unwrap();
+        eval.interpret_inline_policy(&parse_expr(expr).expect("parsing error"))
+    }
+
+    fn verify(
+        authenticator_data: &str,
+        signature: &str,
+        challenge: &str,
+    ) -> evaluator::Result<Value> {
+        verify_for(
+            authenticator_data,
+            signature,
+            &format!(
+                r#"{{challenge: "{challenge}", origin: "https://example.com", rpId: "example.com"}}"#
+            ),
+        )
+    }
+
+    fn verify_for(
+        authenticator_data: &str,
+        signature: &str,
+        expected: &str,
+    ) -> evaluator::Result<Value> {
+        eval(&format!(
+            r#"webauthnVerify("{PUBLIC_KEY}", {{
+                authenticatorData: "{authenticator_data}",
+                clientDataJSON: "{CLIENT_DATA}",
+                signature: "{signature}"
+            }}, {expected})"#
+        ))
+    }
+
+    #[test]
+    fn assertions() {
+        assert_eq!(
+            verify(AUTHENTICATOR_DATA, SIGNATURE, "cmVjb3Zlcnk"),
+            Ok(Value::from(true))
+        );
+        // another challenge
+        assert_eq!(
+            verify(AUTHENTICATOR_DATA, SIGNATURE, "c3RlcC11cA"),
+            Ok(Value::from(false))
+        );
+        // a different signature counter
+        let replayed = AUTHENTICATOR_DATA.replace("00000001", "00000002");
+        assert_eq!(
+            verify(&replayed, SIGNATURE, "cmVjb3Zlcnk"),
+            Ok(Value::from(false))
+        );
+        // the user wasn't present
+        let absent = AUTHENTICATOR_DATA.replace("0500000001", "0400000001");
+        assert_eq!(
+            verify(&absent, SIGNATURE, "cmVjb3Zlcnk"),
+            Ok(Value::from(false))
+        );
+    }
+
+    #[test]
+    fn origin_and_relying_party() {
+        // an assertion relayed by a phishing site is for another origin or
+        // relying party
+        assert_eq!(
+            verify_for(
+                AUTHENTICATOR_DATA,
+                SIGNATURE,
+                r#"{challenge: "cmVjb3Zlcnk", origin: "https://wallet.example", rpId: "example.com"}"#
+            ),
+            Ok(Value::from(false))
+        );
+        assert_eq!(
+            verify_for(
+                AUTHENTICATOR_DATA,
+                SIGNATURE,
+                r#"{challenge: "cmVjb3Zlcnk", origin: "https://example.com", rpId: "wallet.example"}"#
+            ),
+            Ok(Value::from(false))
+        );
+        // both are required
+        assert!(verify_for(
+            AUTHENTICATOR_DATA,
+            SIGNATURE,
+            r#"{challenge: "cmVjb3Zlcnk", rpId: "example.com"}"#
+        )
+        .is_err());
+        assert!(verify_for(AUTHENTICATOR_DATA, SIGNATURE, r#""cmVjb3Zlcnk""#).is_err());
+    }
+
+    #[test]
+    fn malformed_assertions() {
+        assert!(verify(AUTHENTICATOR_DATA, "3046", "cmVjb3Zlcnk").is_err());
+        assert!(verify("a379a6", SIGNATURE, "cmVjb3Zlcnk").is_err());
+        assert!(eval(&format!(
+            r#"webauthnVerify("0x04", {{authenticatorData: "{AUTHENTICATOR_DATA}", clientDataJSON: "", signature: "{SIGNATURE}"}}, {{challenge: "", origin: "", rpId: ""}})"#
+        ))
+        .is_err());
+    }
+}
@@ -0,0 +1,877 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+//! This module contains the Cedar 'zk' extension.
+//!
+//! `zkVerify(vk, proof, publicInputs)` verifies a Groth16 proof, and
+//! `zkVerifyPlonk(vk, proof, publicInputs)` a PLONK proof, over BN254, the
+//! curve of circom, snarkjs and the EVM's pairing precompile, so that a
+//! policy can require e.g. a proof of solvency or of age. They return
+//! `false` if the proof is well-formed but invalid, and error if the
+//! verifying key, the proof or the public inputs are malformed. Groth16
+//! proofs are checked by `ark-groth16`, and both verifiers use the curve and
+//! pairing of `ark-bn254`.
+//!
+//! `vk` and `proof` are hex strings, with or without a `0x` prefix, of
+//! 32-byte big-endian words, with points in the encoding of the pairing
+//! precompile (EIP-197): a G1 point is its `x` and `y` coordinates, a G2
+//! point is `x` and `y` with the imaginary part of each first, and `(0, 0)`
+//! is the point at infinity. The keys and proofs are laid out as in the
+//! Solidity verifiers which snarkjs exports:
+//!
+//! * A Groth16 verifying key is `alpha` (G1), `beta`, `gamma` and `delta`
+//!   (G2), then one G1 point for the constant term and one for each public
+//!   input. The proof is `A` (G1), `B` (G2) and `C` (G1).
+//! * A PLONK verifying key is the base two logarithm of the domain size, the
+//!   number of public inputs, `k1`, `k2` and the domain's root of unity `w`,
+//!   then the commitments `Qm`, `Ql`, `Qr`, `Qo`, `Qc`, `S1`, `S2` and `S3`
+//!   (G1), and `X_2` (G2). The proof is the commitments `A`, `B`, `C`, `Z`,
+//!   `T1`, `T2`, `T3`, `Wxi` and `Wxiw` (G1), then the evaluations `eval_a`,
+//!   `eval_b`, `eval_c`, `eval_s1`, `eval_s2` and `eval_zw`.
+//!
+//! The public inputs are a record of `u256` values named `input0`, `input1`,
+//! and so on, in the circuit's order.
+
+use crate::ast::{
+    CallStyle, Extension, ExtensionFunction, ExtensionOutputValue, StaticallyTyped, Type, Value,
+};
+use crate::entities::SchemaType;
+use crate::evaluator;
+use ark_bn254::{Bn254, Fq, Fq2, Fr, G1Affine, G1Projective, G2Affine};
+use ark_ec::pairing::Pairing;
+use ark_ec::{AffineRepr, CurveGroup};
+use ark_ff::{BigInt, BigInteger, Field, One, PrimeField, Zero};
+use ark_groth16::{prepare_verifying_key, Groth16, Proof, VerifyingKey};
+use ethers::types::U256;
+use ethers::utils::{hex, keccak256};
+use thiserror::Error;
+
+use super::u256::as_u256;
+
+// PANIC SAFETY All `Name`s in here are valid `Name`s
+#[allow(clippy::expect_used)]
+mod names {
+    use crate::ast::Name;
+    // PANIC SAFETY all of the names here are valid names
+    lazy_static::lazy_static! {
+        pub static ref ZK : Name = Name::parse_unqualified_name("zk").expect("should be a valid identifier");
+        pub static ref ZK_VERIFY : Name = Name::parse_unqualified_name("zkVerify").expect("should be a valid identifier");
+        pub static ref ZK_VERIFY_PLONK : Name = Name::parse_unqualified_name("zkVerifyPlonk").expect("should be a valid identifier");
+    }
+}
+
+/// Potential errors when verifying proofs. Note that these are converted to
+/// evaluator::Err::ExtensionErr (which takes a string argument) before being
+/// reported to users.
+#[derive(Debug, Error)]
+enum Error {
+    /// A key or proof isn't a hex string
+    #[error("the {0} is not a hex string")]
+    NotHex(&'static str),
+    /// A key or proof has the wrong length
+    #[error("the {0} has the wrong length")]
+    BadLength(&'static str),
+    /// A point isn't on the curve, or isn't in its prime order subgroup
+    #[error("the {0} contains a point which is not in the BN254 group")]
+    NotAPoint(&'static str),
+    /// A number in a key or proof isn't a scalar field element
+    #[error("the {0} contains a number which is not in the BN254 scalar field")]
+    NotAScalar(&'static str),
+    /// A PLONK verifying key's root of unity doesn't generate its domain
+    #[error("the verifying key's root of unity does not generate its domain")]
+    BadDomain,
+    /// The number of public inputs doesn't match the verifying key
+    #[error("the verifying key takes {expected} public inputs, but {found} were given")]
+    InputCount { expected: usize, found: usize },
+    /// The public inputs aren't named `input0` to `input{n - 1}`
+    #[error("the public inputs have no `input{0}`")]
+    MissingInput(usize),
+    /// A public input isn't a scalar field element
+    #[error("public input {0} is not an element of the BN254 scalar field")]
+    NotAFieldElement(U256),
+}
+
+fn extension_err(msg: impl Into<String>) -> evaluator::EvaluationError {
+    evaluator::EvaluationError::failed_extension_function_application(names::ZK.clone(), msg.into())
+}
+
+/// The 32-byte words of the hex string in `v`, which is a `what`
+fn words(v: &Value, what: &'static str) -> evaluator::Result<Vec<U256>> {
+    let s = v.get_as_string()?;
+    let bytes = hex::decode(s.strip_prefix("0x").unwrap_or(s))
+        .map_err(|_| extension_err(Error::NotHex(what).to_string()))?;
+    if bytes.len() % 32 != 0 {
+        return Err(extension_err(Error::BadLength(what).to_string()));
+    }
+    Ok(bytes.chunks_exact(32).map(U256::from_big_endian).collect())
+}
+
+/// The field element `word`, if it is less than the field's order
+fn field_element<F: PrimeField<BigInt = BigInt<4>>>(word: U256) -> Option<F> {
+    F::from_bigint(BigInt(word.0))
+}
+
+/// Reads numbers and points from the words of a `what`
+struct Words<'a> {
+    words: std::slice::Iter<'a, U256>,
+    what: &'static str,
+}
+
+impl<'a> Words<'a> {
+    /// The words of a `what`, which must number `len`
+    fn new(words: &'a [U256], len: usize, what: &'static str) -> evaluator::Result<Self> {
+        if words.len() != len {
+            return Err(extension_err(Error::BadLength(what).to_string()));
+        }
+        Ok(Self {
+            words: words.iter(),
+            what,
+        })
+    }
+
+    fn not_a_point(&self) -> evaluator::EvaluationError {
+        extension_err(Error::NotAPoint(self.what).to_string())
+    }
+
+    fn next_word(&mut self) -> evaluator::Result<U256> {
+        self.words
+            .next()
+            .copied()
+            .ok_or_else(|| extension_err(Error::BadLength(self.what).to_string()))
+    }
+
+    fn next_fr(&mut self) -> evaluator::Result<Fr> {
+        let word = self.next_word()?;
+        field_element(word).ok_or_else(|| extension_err(Error::NotAScalar(self.what).to_string()))
+    }
+
+    fn next_fq(&mut self) -> evaluator::Result<Fq> {
+        let word = self.next_word()?;
+        field_element(word).ok_or_else(|| self.not_a_point())
+    }
+
+    fn next_fq2(&mut self) -> evaluator::Result<Fq2> {
+        let im = self.next_fq()?;
+        let re = self.next_fq()?;
+        Ok(Fq2::new(re, im))
+    }
+
+    fn next_g1(&mut self) -> evaluator::Result<G1Affine> {
+        let (x, y) = (self.next_fq()?, self.next_fq()?);
+        if x.is_zero() && y.is_zero() {
+            return Ok(G1Affine::identity());
+        }
+        // G1 is all of the curve, so every point on it is in the subgroup
+        Some(G1Affine::new_unchecked(x, y))
+            .filter(G1Affine::is_on_curve)
+            .ok_or_else(|| self.not_a_point())
+    }
+
+    fn next_g2(&mut self) -> evaluator::Result<G2Affine> {
+        let (x, y) = (self.next_fq2()?, self.next_fq2()?);
+        if x.is_zero() && y.is_zero() {
+            return Ok(G2Affine::identity());
+        }
+        Some(G2Affine::new_unchecked(x, y))
+            .filter(|p| p.is_on_curve() && p.is_in_correct_subgroup_assuming_on_curve())
+            .ok_or_else(|| self.not_a_point())
+    }
+}
+
+/// The `expected` public inputs in the record `v`, in order
+fn public_inputs(v: &Value, expected: usize) -> evaluator::Result<Vec<Fr>> {
+    let Value::Record(record) = v else {
+        return Err(evaluator::EvaluationError::type_error(
+            vec![Type::Record],
+            v.type_of(),
+        ));
+    };
+    if record.len() != expected {
+        return Err(extension_err(
+            Error::InputCount {
+                expected,
+                found: record.len(),
+            }
+            .to_string(),
+        ));
+    }
+    (0..expected)
+        .map(|i| {
+            let input = record
+                .get(format!("input{i}").as_str())
+                .ok_or_else(|| extension_err(Error::MissingInput(i).to_string()))
+                .and_then(as_u256)?;
+            field_element(input)
+                .ok_or_else(|| extension_err(Error::NotAFieldElement(input).to_string()))
+        })
+        .collect()
+}
+
+/// Cedar function that verifies a Groth16 proof of `public_inputs` against
+/// the verifying key `vk`, returning a Cedar bool
+fn zk_verify(
+    vk: Value,
+    proof: Value,
+    public_inputs: Value,
+) -> evaluator::Result<ExtensionOutputValue> {
+    let vk_words = words(&vk, "verifying key")?;
+    // alpha, beta, gamma and delta, and at least the constant term's point
+    if vk_words.len() < 16 || vk_words.len() % 2 != 0 {
+        return Err(extension_err(Error::BadLength("verifying key").to_string()));
+    }
+    let mut key = Words::new(&vk_words, vk_words.len(), "verifying key")?;
+    let alpha_g1 = key.next_g1()?;
+    let beta_g2 = key.next_g2()?;
+    let gamma_g2 = key.next_g2()?;
+    let delta_g2 = key.next_g2()?;
+    let gamma_abc_g1 = (0..(vk_words.len() - 14) / 2)
+        .map(|_| key.next_g1())
+        .collect::<Result<Vec<_>, _>>()?;
+    let inputs = self::public_inputs(&public_inputs, gamma_abc_g1.len() - 1)?;
+    let vk = VerifyingKey::<Bn254> {
+        alpha_g1,
+        beta_g2,
+        gamma_g2,
+        delta_g2,
+        gamma_abc_g1,
+    };
+
+    let proof_words = words(&proof, "proof")?;
+    let mut proof = Words::new(&proof_words, 8, "proof")?;
+    let proof = Proof {
+        a: proof.next_g1()?,
+        b: proof.next_g2()?,
+        c: proof.next_g1()?,
+    };
+
+    // the only errors left are degenerate proofs, which are invalid
+    let valid = Groth16::<Bn254>::verify_proof(&prepare_verifying_key(&vk), &proof, &inputs)
+        .unwrap_or(false);
+    Ok(Value::from(valid).into())
+}
+
+/// A PLONK verifying key
+struct PlonkKey {
+    /// The size of the domain
+    n: u64,
+    inputs: usize,
+    k1: Fr,
+    k2: Fr,
+    /// The domain's root of unity
+    w: Fr,
+    q_m: G1Affine,
+    q_l: G1Affine,
+    q_r: G1Affine,
+    q_o: G1Affine,
+    q_c: G1Affine,
+    s1: G1Affine,
+    s2: G1Affine,
+    s3: G1Affine,
+    x_2: G2Affine,
+}
+
+impl PlonkKey {
+    fn new(words: &[U256]) -> evaluator::Result<Self> {
+        let mut key = Words::new(words, 25, "verifying key")?;
+        let power = key.next_word()?;
+        // the scalar field has roots of unity of order up to 2^28
+        if power > U256::from(28) {
+            return Err(extension_err(Error::BadDomain.to_string()));
+        }
+        let n = 1u64 << power.as_u64();
+        let inputs = key.next_word()?;
+        if inputs > U256::from(n) {
+            return Err(extension_err(Error::BadDomain.to_string()));
+        }
+        let (k1, k2, w) = (key.next_fr()?, key.next_fr()?, key.next_fr()?);
+        if !w.pow([n]).is_one() || (n > 1 && w.pow([n / 2]).is_one()) {
+            return Err(extension_err(Error::BadDomain.to_string()));
+        }
+        Ok(Self {
+            n,
+            inputs: inputs.as_usize(),
+            k1,
+            k2,
+            w,
+            q_m: key.next_g1()?,
+            q_l: key.next_g1()?,
+            q_r: key.next_g1()?,
+            q_o: key.next_g1()?,
+            q_c: key.next_g1()?,
+            s1: key.next_g1()?,
+            s2: key.next_g1()?,
+            s3: key.next_g1()?,
+            x_2: key.next_g2()?,
+        })
+    }
+}
+
+/// A PLONK proof
+struct PlonkProof {
+    a: G1Affine,
+    b: G1Affine,
+    c: G1Affine,
+    z: G1Affine,
+    t1: G1Affine,
+    t2: G1Affine,
+    t3: G1Affine,
+    w_xi: G1Affine,
+    w_xiw: G1Affine,
+    eval_a: Fr,
+    eval_b: Fr,
+    eval_c: Fr,
+    eval_s1: Fr,
+    eval_s2: Fr,
+    eval_zw: Fr,
+}
+
+impl PlonkProof {
+    fn new(words: &[U256]) -> evaluator::Result<Self> {
+        let mut proof = Words::new(words, 24, "proof")?;
+        Ok(Self {
+            a: proof.next_g1()?,
+            b: proof.next_g1()?,
+            c: proof.next_g1()?,
+            z: proof.next_g1()?,
+            t1: proof.next_g1()?,
+            t2: proof.next_g1()?,
+            t3: proof.next_g1()?,
+            w_xi: proof.next_g1()?,
+            w_xiw: proof.next_g1()?,
+            eval_a: proof.next_fr()?,
+            eval_b: proof.next_fr()?,
+            eval_c: proof.next_fr()?,
+            eval_s1: proof.next_fr()?,
+            eval_s2: proof.next_fr()?,
+            eval_zw: proof.next_fr()?,
+        })
+    }
+}
+
+/// The Keccak-256 Fiat-Shamir transcript of snarkjs's PLONK verifier
+#[derive(Default)]
+struct Transcript(Vec<u8>);
+
+impl Transcript {
+    fn scalar(&mut self, s: &Fr) {
+        self.0.extend(s.into_bigint().to_bytes_be());
+    }
+
+    fn point(&mut self, p: &G1Affine) {
+        self.0.extend(p.x.into_bigint().to_bytes_be());
+        self.0.extend(p.y.into_bigint().to_bytes_be());
+    }
+
+    /// The hash of the transcript, which is then cleared
+    fn challenge(&mut self) -> Fr {
+        let challenge = Fr::from_be_bytes_mod_order(&keccak256(&self.0));
+        self.0.clear();
+        challenge
+    }
+}
+
+/// Whether `proof` is a PLONK proof of `inputs` for `key`, following
+/// snarkjs's verifier
+fn plonk_verify(key: &PlonkKey, proof: &PlonkProof, inputs: &[Fr]) -> bool {
+    let mut transcript = Transcript::default();
+    for p in [
+        &key.q_m, &key.q_l, &key.q_r, &key.q_o, &key.q_c, &key.s1, &key.s2, &key.s3,
+    ] {
+        transcript.point(p);
+    }
+    for input in inputs {
+        transcript.scalar(input);
+    }
+    for p in [&proof.a, &proof.b, &proof.c] {
+        transcript.point(p);
+    }
+    let beta = transcript.challenge();
+    transcript.scalar(&beta);
+    let gamma = transcript.challenge();
+    transcript.scalar(&beta);
+    transcript.scalar(&gamma);
+    transcript.point(&proof.z);
+    let alpha = transcript.challenge();
+    transcript.scalar(&alpha);
+    for p in [&proof.t1, &proof.t2, &proof.t3] {
+        transcript.point(p);
+    }
+    let xi = transcript.challenge();
+    transcript.scalar(&xi);
+    for eval in [
+        &proof.eval_a,
+        &proof.eval_b,
+        &proof.eval_c,
+        &proof.eval_s1,
+        &proof.eval_s2,
+        &proof.eval_zw,
+    ] {
+        transcript.scalar(eval);
+    }
+    let v1 = transcript.challenge();
+    let (v2, v3, v4, v5) = (v1.square(), v1.pow([3]), v1.pow([4]), v1.pow([5]));
+    transcript.point(&proof.w_xi);
+    transcript.point(&proof.w_xiw);
+    let u = transcript.challenge();
+
+    let xin = xi.pow([key.n]);
+    let zh = xin - Fr::one();
+    // the Lagrange polynomials at xi of the first points of the domain, one
+    // for each public input and at least one
+    let mut lagrange = Vec::new();
+    let mut root = Fr::one();
+    for _ in 0..inputs.len().max(1) {
+        let Some(inverse) = (Fr::from(key.n) * (xi - root)).inverse() else {
+            return false;
+        };
+        lagrange.push(root * zh * inverse);
+        root *= key.w;
+    }
+    let l1 = lagrange.first().copied().unwrap_or_default();
+    let pi = -inputs
+        .iter()
+        .zip(&lagrange)
+        .map(|(input, l)| *input * l)
+        .sum::<Fr>();
+
+    let (a, b, c) = (proof.eval_a, proof.eval_b, proof.eval_c);
+    let (s1, s2, zw) = (proof.eval_s1, proof.eval_s2, proof.eval_zw);
+    let alpha2 = alpha.square();
+    let permuted = (a + beta * s1 + gamma) * (b + beta * s2 + gamma);
+    let r0 = pi - l1 * alpha2 - alpha * permuted * (c + gamma) * zw;
+    let identity = (a + beta * xi + gamma)
+        * (b + beta * key.k1 * xi + gamma)
+        * (c + beta * key.k2 * xi + gamma);
+
+    let d = key.q_m * (a * b)
+        + key.q_l * a
+        + key.q_r * b
+        + key.q_o * c
+        + key.q_c
+        + proof.z * (identity * alpha + l1 * alpha2 + u)
+        - key.s3 * (permuted * alpha * beta * zw)
+        - (proof.t1.into_group() + proof.t2 * xin + proof.t3 * xin.square()) * zh;
+    let f = d + proof.a * v1 + proof.b * v2 + proof.c * v3 + key.s1 * v4 + key.s2 * v5;
+    let e = G1Projective::from(G1Affine::generator())
+        * (-r0 + v1 * a + v2 * b + v3 * c + v4 * s1 + v5 * s2 + u * zw);
+
+    // e(-(Wxi + u Wxiw), X_2) e(xi Wxi + u xi w Wxiw + F - E, 1) = 1
+    let lhs = -(proof.w_xi + proof.w_xiw * u);
+    let rhs = proof.w_xi * xi + proof.w_xiw * (u * xi * key.w) + f - e;
+    Bn254::final_exponentiation(Bn254::multi_miller_loop(
+        [lhs.into_affine(), rhs.into_affine()],
+        [key.x_2, G2Affine::generator()],
+    ))
+    .is_some_and(|product| product.is_zero())
+}
+
+/// Cedar function that verifies a PLONK proof of `public_inputs` against
+/// the verifying key `vk`, returning a Cedar bool
+fn zk_verify_plonk(
+    vk: Value,
+    proof: Value,
+    public_inputs: Value,
+) -> evaluator::Result<ExtensionOutputValue> {
+    let key = PlonkKey::new(&words(&vk, "verifying key")?)?;
+    let proof = PlonkProof::new(&words(&proof, "proof")?)?;
+    let inputs = self::public_inputs(&public_inputs, key.inputs)?;
+    Ok(Value::from(plonk_verify(&key, &proof, &inputs)).into())
+}
+
+/// Construct the extension
+pub fn extension() -> Extension {
+    Extension::new(
+        names::ZK.clone(),
+        vec![
+            ExtensionFunction::ternary(
+                names::ZK_VERIFY.clone(),
+                CallStyle::FunctionStyle,
+                Box::new(zk_verify),
+                SchemaType::Bool,
+                (Some(SchemaType::String), Some(SchemaType::String), None),
+            ),
+            ExtensionFunction::ternary(
+                names::ZK_VERIFY_PLONK.clone(),
+                CallStyle::FunctionStyle,
+                Box::new(zk_verify_plonk),
+                SchemaType::Bool,
+                (Some(SchemaType::String), Some(SchemaType::String), None),
+            ),
+        ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::test::{basic_entities, basic_request};
+    use crate::evaluator::Evaluator;
+    use crate::extensions::Extensions;
+    use crate::parser::parse_expr;
+    use ark_poly::univariate::DensePolynomial;
+    use ark_poly::{
+        DenseUVPolynomial, EvaluationDomain, Evaluations, Polynomial, Radix2EvaluationDomain,
+    };
+
+    /// A verifying key for two public inputs, and a proof for the inputs 5
+    /// and 42, made with known toxic waste
+    const VK: &str = concat!(
+        "2a14705537b009189da8808651eecdb82482477fe92ac12ca8b71f80fc3d49ef",
+        "2df7ee7f243ea8b38e1ddf14029258877a618c779fd4717db6177e19ea67ec38",
+        "009edaf0698a8c56f51139588acc094cee3c37d427bb6d2eab830aae529097d1",
+        "23ad66f3a7cca9dc75049635faebd124316244b91de5fb2764cd151572a905f7",
+        "2700e8a29b7bb45f3022a18a07bdc66d0254559e17cce64e3b4ad21578fcf410",
+        "1ad4f87d3b4375a39988ac099b042b1e7c0c715678e4c2bea8905f607cf950f8",
+        "227071bba5ff3b47ed8b504bb5b215bc701d7a3259b933bff1a4164eae499c2c",
+        "0c51a367b61d3119677b29739ddccbb78002b5558d8f49ff16e299c1b41f8098",
+        "08bb188b2a6187bb1e87834c85a6a917763d65b98febf2c45ea339dd77fac415",
+        "18fd2fd13be8494c39e8a91325d1ef3ba7d1a205d10788e38bc9e09d9be87769",
+        "25407be35f18c6594174374841311466c0e66ff003762448c06bca4fa5e9c54e",
+        "15cbba9ab73bc73d0ba4ad132a15cb0c73107a9c19b040c4c73d89f6bf75404d",
+        "1edef86c1a42fa85ab6ae8d268a7e9b46890b2130dd83b91c86c504cf1f93fbf",
+        "2c750c045112e4ab07f18b12475309cebdcb726bda1ca9948bacd498a28cf411",
+        "1e28260f0ee971dec1e84cf81ff2776ad314d2cfb9ef81d4c970620c29b811f1",
+        "28fc8a72d4ff12654c3c39dab54eaef9638d28de738959779fcd3e7ac918b396",
+        "1605ffc1ea2e1aef15d774d3207176420c5cc454b19b55558562b0c7ddf00a7d",
+        "0cf605873faa8028df38ec2d0800d5ddc67f1776338d675491fe87f6bb7354b3",
+        "14b4fa251277a6f4cbbfe379a152a976641f58a4a2bffd3b677ea093bdad853c",
+        "28ce094a6d16280abcf8d84efa062c85511819dd87d8da255885ce0580ebee36",
+    );
+
+    const PROOF: &str = concat!(
+        "24f253a56d4badbe5f105ae102f14cf23ecb3a3892640ed1edb49c9d9e45d063",
+        "1392ab50e020ade3c6069f16bf09d1ac4ebe686a3063ce392a0ea2b7ec03f6b1",
+        "112481cb92f08c33f3b41dd04d01cccc74ea26dd31f17f3da66b624dd5ccc074",
+        "14550237c376595b16cb8a6107729731c341634464ba1a15c239e2047f0cc083",
+        "2d6a1707745972d09054b5098b7abe2433d727e336a1f9933f9aa14748ad594e",
+        "255420d4a7200beb753a7ea90439f5790e06d7082c75c77ea40771acdc81643e",
+        "1d068b5107061b790f9527e9b21c4be8bb7277a65669285bb6379eef3bad7e85",
+        "1eda2d236fcde982afbec463ddc274fcd4b57972d0d90540c2f3a786ab3ee249",
+    );
+
+    fn eval(expr: &str) -> evaluator::Result<Value> {
+        let ext_array = [extension(), super::super::u256::extension()];
+        let exts = Extensions::specific_extensions(&ext_array);
+        let request = basic_request();
+        let entities = basic_entities();
+        let eval = Evaluator::new(&request, &entities, &exts).unwrap();
+        eval.interpret_inline_policy(&parse_expr(expr).expect("parsing error"))
+    }
+
+    fn verify(vk: &str, proof: &str, inputs: &str) -> evaluator::Result<Value> {
+        eval(&format!(r#"zkVerify("{vk}", "0x{proof}", {{{inputs}}})"#))
+    }
+
+    fn verify_plonk(vk: &str, proof: &str, inputs: &str) -> evaluator::Result<Value> {
+        eval(&format!(
+            r#"zkVerifyPlonk("{vk}", "0x{proof}", {{{inputs}}})"#
+        ))
+    }
+
+    #[test]
+    fn proofs() {
+        assert_eq!(
+            verify(VK, PROOF, r#"input0: u256("5"), input1: u256("42")"#),
+            Ok(Value::from(true))
+        );
+        assert_eq!(
+            verify(VK, PROOF, r#"input0: u256("6"), input1: u256("42")"#),
+            Ok(Value::from(false))
+        );
+        // the inputs are taken in order
+        assert_eq!(
+            verify(VK, PROOF, r#"input0: u256("42"), input1: u256("5")"#),
+            Ok(Value::from(false))
+        );
+        // A and C swapped
+        let swapped = format!("{}{}{}", &PROOF[384..], &PROOF[128..384], &PROOF[..128]);
+        assert_eq!(
+            verify(VK, &swapped, r#"input0: u256("5"), input1: u256("42")"#),
+            Ok(Value::from(false))
+        );
+    }
+
+    #[test]
+    fn malformed_inputs() {
+        // too few public inputs
+        assert!(verify(VK, PROOF, r#"input0: u256("5")"#).is_err());
+        // misnamed public inputs
+        assert!(verify(VK, PROOF, r#"input0: u256("5"), input2: u256("42")"#).is_err());
+        // not hex
+        assert!(verify(VK, "xyz", r#"input0: u256("5"), input1: u256("42")"#).is_err());
+        // a point off the curve
+        let off_curve = format!("{}{}", &PROOF[..127], "0");
+        assert!(verify(
+            VK,
+            &format!("{off_curve}{}", &PROOF[128..]),
+            r#"input0: u256("5"), input1: u256("42")"#
+        )
+        .is_err());
+        // a public input outside the scalar field
+        let order = Fr::MODULUS.to_string();
+        assert!(verify(
+            VK,
+            PROOF,
+            &format!(r#"input0: u256("5"), input1: u256("{order}")"#)
+        )
+        .is_err());
+    }
+
+    fn word(f: impl PrimeField) -> String {
+        hex::encode(f.into_bigint().to_bytes_be())
+    }
+
+    fn g1(p: G1Affine) -> String {
+        word(p.x) + &word(p.y)
+    }
+
+    fn g2(p: G2Affine) -> String {
+        [p.x.c1, p.x.c0, p.y.c1, p.y.c0].map(word).concat()
+    }
+
+    fn poly(evals: Vec<Fr>, domain: Radix2EvaluationDomain<Fr>) -> DensePolynomial<Fr> {
+        Evaluations::from_vec_and_domain(evals, domain).interpolate()
+    }
+
+    fn constant(c: Fr) -> DensePolynomial<Fr> {
+        DensePolynomial::from_coefficients_vec(vec![c])
+    }
+
+    fn sum<const N: usize>(ps: [DensePolynomial<Fr>; N]) -> DensePolynomial<Fr> {
+        ps.iter().fold(DensePolynomial::zero(), |sum, p| &sum + p)
+    }
+
+    fn product<const N: usize>(ps: [DensePolynomial<Fr>; N]) -> DensePolynomial<Fr> {
+        ps.iter()
+            .fold(constant(Fr::one()), |product, p| &product * p)
+    }
+
+    /// `(p - p(x)) / (X - x)`
+    fn open(p: &DensePolynomial<Fr>, x: Fr) -> DensePolynomial<Fr> {
+        let mut quotient = vec![Fr::zero(); p.coeffs.len().saturating_sub(1)];
+        let mut carry = Fr::zero();
+        for (i, coeff) in p.coeffs.iter().enumerate().skip(1).rev() {
+            carry = carry * x + coeff;
+            quotient[i - 1] = carry;
+        }
+        DensePolynomial::from_coefficients_vec(quotient)
+    }
+
+    /// A PLONK verifying key, and a proof made with known toxic waste of an
+    /// `x` with `x * x = y` and `x + y = z` for the public inputs `y` and `z`
+    fn plonk_proof(x: u64) -> (String, String) {
+        let tau = Fr::from(0x5eed_u64);
+        let commit =
+            |p: &DensePolynomial<Fr>| (G1Affine::generator() * p.evaluate(&tau)).into_affine();
+        let domain = Radix2EvaluationDomain::<Fr>::new(4).unwrap();
+        let w = domain.element(1);
+        let (k1, k2) = (Fr::from(2), Fr::from(3));
+        let (zero, one) = (Fr::zero(), Fr::one());
+        let (x, y) = (Fr::from(x), Fr::from(x * x));
+        let z = x + y;
+
+        // rows for the inputs y and z, then x * x = y and x + y = z
+        let wires = [[y, z, x, x], [zero, zero, x, y], [zero, zero, y, z]];
+        let q_m = poly(vec![zero, zero, one, zero], domain);
+        let q_l = poly(vec![one, one, zero, one], domain);
+        let q_r = poly(vec![zero, zero, zero, one], domain);
+        let q_o = poly(vec![zero, zero, -one, -one], domain);
+        let q_c = poly(vec![zero; 4], domain);
+        let pi = poly(vec![-y, -z, zero, zero], domain);
+        let l1 = poly(vec![one, zero, zero, zero], domain);
+        // the copies of y, z and x, by wire, where wire `4j + i` is column
+        // `j` of row `i`
+        let sigma: [usize; 12] = [10, 11, 6, 2, 4, 5, 3, 0, 8, 9, 7, 1];
+        let label = |wire: usize| [one, k1, k2][wire / 4] * w.pow([(wire % 4) as u64]);
+        let s: Vec<_> = (0..3)
+            .map(|j| poly((0..4).map(|i| label(sigma[4 * j + i])).collect(), domain))
+            .collect();
+        let [a, b, c] = wires.map(|column| poly(column.to_vec(), domain));
+
+        let mut transcript = Transcript::default();
+        for p in [&q_m, &q_l, &q_r, &q_o, &q_c, &s[0], &s[1], &s[2]] {
+            transcript.point(&commit(p));
+        }
+        transcript.scalar(&y);
+        transcript.scalar(&z);
+        for p in [&a, &b, &c] {
+            transcript.point(&commit(p));
+        }
+        let beta = transcript.challenge();
+        transcript.scalar(&beta);
+        let gamma = transcript.challenge();
+
+        let mut accumulator = vec![one];
+        for i in 0..3 {
+            let ratio = (0..3)
+                .map(|j| {
+                    (wires[j][i] + beta * label(4 * j + i) + gamma)
+                        / (wires[j][i] + beta * label(sigma[4 * j + i]) + gamma)
+                })
+                .product::<Fr>();
+            accumulator.push(accumulator[i] * ratio);
+        }
+        let perm = poly(accumulator, domain);
+        transcript.scalar(&beta);
+        transcript.scalar(&gamma);
+        transcript.point(&commit(&perm));
+        let alpha = transcript.challenge();
+
+        let id = DensePolynomial::from_coefficients_vec(vec![zero, beta]);
+        let shifted = DensePolynomial::from_coefficients_vec(
+            perm.coeffs
+                .iter()
+                .enumerate()
+                .map(|(i, coeff)| *coeff * w.pow([i as u64]))
+                .collect(),
+        );
+        let wired =
+            |p: &DensePolynomial<Fr>, q: DensePolynomial<Fr>| sum([p.clone(), q, constant(gamma)]);
+        let numerator = sum([
+            &q_m * &(&a * &b),
+            &q_l * &a,
+            &q_r * &b,
+            &q_o * &c,
+            q_c.clone(),
+            pi,
+            &(&product([
+                wired(&a, id.clone()),
+                wired(&b, &id * k1),
+                wired(&c, &id * k2),
+                perm.clone(),
+            ]) - &product([
+                wired(&a, &s[0] * beta),
+                wired(&b, &s[1] * beta),
+                wired(&c, &s[2] * beta),
+                shifted,
+            ])) * alpha,
+            &(&(&perm - &constant(one)) * &l1) * alpha.square(),
+        ]);
+        let (t, remainder) = numerator.divide_by_vanishing_poly(domain).unwrap();
+        assert!(remainder.is_zero());
+        let [t1, t2, t3] = [0, 4, 8].map(|i| {
+            DensePolynomial::from_coefficients_slice(
+                t.coeffs
+                    .get(i..(i + 4).min(t.coeffs.len()))
+                    .unwrap_or_default(),
+            )
+        });
+        transcript.scalar(&alpha);
+        for p in [&t1, &t2, &t3] {
+            transcript.point(&commit(p));
+        }
+        let xi = transcript.challenge();
+
+        let evals = [&a, &b, &c, &s[0], &s[1]].map(|p| p.evaluate(&xi));
+        let eval_zw = perm.evaluate(&(xi * w));
+        let [eval_a, eval_b, eval_c, eval_s1, eval_s2] = evals;
+        transcript.scalar(&xi);
+        for eval in evals.iter().chain([&eval_zw]) {
+            transcript.scalar(eval);
+        }
+        let v = transcript.challenge();
+
+        let xin = xi.pow([4]);
+        let identity = (eval_a + beta * xi + gamma)
+            * (eval_b + beta * k1 * xi + gamma)
+            * (eval_c + beta * k2 * xi + gamma);
+        let permuted = (eval_a + beta * eval_s1 + gamma) * (eval_b + beta * eval_s2 + gamma);
+        let linearization = sum([
+            &q_m * (eval_a * eval_b),
+            &q_l * eval_a,
+            &q_r * eval_b,
+            &q_o * eval_c,
+            q_c.clone(),
+            &perm * (identity * alpha + l1.evaluate(&xi) * alpha.square()),
+            &s[2] * -(permuted * alpha * beta * eval_zw),
+            &sum([t1.clone(), &t2 * xin, &t3 * xin.square()]) * (one - xin),
+        ]);
+        let opened = [&a, &b, &c, &s[0], &s[1]]
+            .into_iter()
+            .zip(1..)
+            .fold(linearization, |sum, (p, i)| &sum + &(p * v.pow([i])));
+        let w_xi = open(&opened, xi);
+        let w_xiw = open(&perm, xi * w);
+
+        let vk = [
+            format!("{:064x}{:064x}", 2, 2),
+            [k1, k2, w].map(word).concat(),
+            [&q_m, &q_l, &q_r, &q_o, &q_c, &s[0], &s[1], &s[2]]
+                .map(|p| g1(commit(p)))
+                .concat(),
+            g2((G2Affine::generator() * tau).into_affine()),
+        ]
+        .concat();
+        let proof = [
+            [&a, &b, &c, &perm, &t1, &t2, &t3, &w_xi, &w_xiw]
+                .map(|p| g1(commit(p)))
+                .concat(),
+            [eval_a, eval_b, eval_c, eval_s1, eval_s2, eval_zw]
+                .map(word)
+                .concat(),
+        ]
+        .concat();
+        (vk, proof)
+    }
+
+    #[test]
+    fn plonk_proofs() {
+        let (vk, proof) = plonk_proof(3);
+        assert_eq!(
+            verify_plonk(&vk, &proof, r#"input0: u256("9"), input1: u256("12")"#),
+            Ok(Value::from(true))
+        );
+        assert_eq!(
+            verify_plonk(&vk, &proof, r#"input0: u256("9"), input1: u256("13")"#),
+            Ok(Value::from(false))
+        );
+        assert_eq!(
+            verify_plonk(&vk, &proof, r#"input0: u256("12"), input1: u256("9")"#),
+            Ok(Value::from(false))
+        );
+        // a proof for other inputs
+        let (_, other) = plonk_proof(4);
+        assert_eq!(
+            verify_plonk(&vk, &other, r#"input0: u256("9"), input1: u256("12")"#),
+            Ok(Value::from(false))
+        );
+        assert_eq!(
+            verify_plonk(&vk, &other, r#"input0: u256("16"), input1: u256("20")"#),
+            Ok(Value::from(true))
+        );
+    }
+
+    #[test]
+    fn malformed_plonk_inputs() {
+        let (vk, proof) = plonk_proof(3);
+        assert!(verify_plonk(&vk, &proof, r#"input0: u256("9")"#).is_err());
+        assert!(verify_plonk(
+            &vk,
+            &proof[..64],
+            r#"input0: u256("9"), input1: u256("12")"#
+        )
+        .is_err());
+        // a root of unity of the wrong order
+        let bad_root = format!("{}{}{}", &vk[..256], word(Fr::one()), &vk[320..]);
+        assert!(verify_plonk(
+            &bad_root,
+            &proof,
+            r#"input0: u256("9"), input1: u256("12")"#
+        )
+        .is_err());
+        // a Groth16 key
+        assert!(verify_plonk(VK, &proof, r#"input0: u256("9"), input1: u256("12")"#).is_err());
+    }
+}
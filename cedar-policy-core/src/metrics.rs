@@ -0,0 +1,89 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Metrics emitted by the evaluator and authorizer.
+//!
+//! With the `metrics` feature, these are reported through the
+//! [`metrics`](https://docs.rs/metrics) facade, so they go to whichever
+//! recorder the application installs (e.g. `metrics-exporter-prometheus`).
+//! Without the feature, every function here does nothing.
+
+use std::time::Duration;
+
+/// Counter of authorization requests, labelled by `decision`
+pub const AUTHORIZATIONS: &str = "cedar_authorizations_total";
+/// Histogram of the time taken to authorize a request, in seconds. It is
+/// recorded once per request rather than per policy, so that it costs as
+/// little as possible and has no label per policy.
+pub const AUTHORIZATION_SECONDS: &str = "cedar_authorization_seconds";
+/// Counter of extension function calls, labelled by `function`
+pub const EXTENSION_CALLS: &str = "cedar_extension_calls_total";
+/// Counter of lookups of evaluated entity attributes, labelled by `result`,
+/// which is `hit` if the attributes were evaluated ahead of time and `miss`
+/// otherwise
+pub const ENTITY_CACHE_LOOKUPS: &str = "cedar_entity_cache_lookups_total";
+/// Histogram of the time taken to fetch entities for a request, in seconds.
+/// This is recorded by whatever supplies the entities, using
+/// [`record_entity_fetch()`].
+pub const ENTITY_FETCH_SECONDS: &str = "cedar_entity_fetch_seconds";
+
+/// Record an authorization request with the given decision
+pub(crate) fn authorization(decision: &'static str) {
+    #[cfg(feature = "metrics")]
+    ::metrics::increment_counter!(AUTHORIZATIONS, "decision" => decision);
+    #[cfg(not(feature = "metrics"))]
+    let _ = decision;
+}
+
+/// Record the time taken to authorize one request
+pub(crate) fn authorization_duration(duration: Duration) {
+    #[cfg(feature = "metrics")]
+    ::metrics::histogram!(AUTHORIZATION_SECONDS, duration.as_secs_f64());
+    #[cfg(not(feature = "metrics"))]
+    let _ = duration;
+}
+
+/// Record a call to an extension function
+pub(crate) fn extension_call(function: &impl ToString) {
+    #[cfg(feature = "metrics")]
+    ::metrics::increment_counter!(EXTENSION_CALLS, "function" => function.to_string());
+    #[cfg(not(feature = "metrics"))]
+    let _ = function;
+}
+
+/// Record a lookup of evaluated entity attributes
+pub(crate) fn entity_cache_lookup(hit: bool) {
+    #[cfg(feature = "metrics")]
+    ::metrics::increment_counter!(
+        ENTITY_CACHE_LOOKUPS,
+        "result" => if hit { "hit" } else { "miss" }
+    );
+    #[cfg(not(feature = "metrics"))]
+    let _ = hit;
+}
+
+/// Record the time taken to fetch the entities for a request
+pub fn record_entity_fetch(duration: Duration) {
+    #[cfg(feature = "metrics")]
+    ::metrics::histogram!(ENTITY_FETCH_SECONDS, duration.as_secs_f64());
+    #[cfg(not(feature = "metrics"))]
+    let _ = duration;
+}
+
+/// Whether metrics are being reported
+pub(crate) const fn enabled() -> bool {
+    cfg!(feature = "metrics")
+}
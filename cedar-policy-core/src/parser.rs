@@ -16,6 +16,9 @@
 
 //! This module contains the parser for the Cedar language.
 
+/// Imports and declarations at the start of a policy file
+mod decls;
+pub use decls::{ImportSource, Prelude};
 /// Concrete Syntax Tree def used as parser first pass
 pub mod cst;
 /// Step two: convert CST to package AST
@@ -33,6 +36,7 @@ pub mod text_to_cst;
 pub(crate) mod unescape;
 
 use smol_str::SmolStr;
+use std::borrow::Cow;
 use std::collections::HashMap;
 
 use crate::ast;
@@ -59,12 +63,33 @@ pub fn parse_policyset(text: &str) -> Result<ast::PolicySet, err::ParseErrors> {
 /// INVARIANT: The `PolicyId` of every `Policy` and `Template` returned by the
 /// `policies()` and `templates()` methods on the returned `Policy` _must_
 /// appear as a key in the returned map.
+/// A policy that refers to constants or macros declared in `text` gets the
+/// text of the policy with the references replaced by their values instead,
+/// so that it can be parsed on its own.
 pub fn parse_policyset_and_also_return_policy_text(
     text: &str,
-) -> Result<(HashMap<ast::PolicyID, &str>, ast::PolicySet), err::ParseErrors> {
+) -> Result<(HashMap<ast::PolicyID, Cow<'_, str>>, ast::PolicySet), err::ParseErrors> {
+    parse_policyset_and_also_return_policy_text_with_prelude(
+        text,
+        &Prelude::default(),
+        &decls::NoImports,
+    )
+}
+
+/// Like `parse_policyset_and_also_return_policy_text()`, but `text` can also
+/// use the declarations in `prelude`, and the files it imports are loaded
+/// from `imports`.
+pub fn parse_policyset_and_also_return_policy_text_with_prelude<'a>(
+    text: &'a str,
+    prelude: &Prelude,
+    imports: &dyn ImportSource,
+) -> Result<(HashMap<ast::PolicyID, Cow<'a, str>>, ast::PolicySet), err::ParseErrors> {
     let mut errs = err::ParseErrors::new();
     let cst = text_to_cst::parse_policies(text)?;
-    let Some(pset) = cst.to_policyset(&mut errs) else {
+    let Some(substituted) = cst.substitute_decls(prelude, imports, &mut errs) else {
+        return Err(errs);
+    };
+    let Some(pset) = substituted.to_policyset(&mut errs) else {
         return Err(errs);
     };
     if errs.is_empty() {
@@ -80,8 +105,19 @@ pub fn parse_policyset_and_also_return_policy_text(
         let texts = cst
             .with_generated_policyids()
             .expect("shouldn't be None since parse_policies() and to_policyset() didn't return Err")
-            .map(|(id, policy)| (id, &text[policy.info.0.clone()]))
-            .collect::<HashMap<ast::PolicyID, &str>>();
+            .zip(
+                substituted
+                    .with_generated_policyids()
+                    .expect("shouldn't be None since substitute_decls() didn't return None"),
+            )
+            .map(|((id, policy), (_, substituted))| {
+                let text = match substituted.as_inner() {
+                    Some(p) if substituted != policy => Cow::Owned(p.to_string()),
+                    _ => Cow::Borrowed(&text[policy.info.0.clone()]),
+                };
+                (id, text)
+            })
+            .collect::<HashMap<ast::PolicyID, Cow<'a, str>>>();
         Ok((texts, pset))
     } else {
         Err(errs)
@@ -96,6 +132,9 @@ pub fn parse_policyset_to_ests_and_pset(
 ) -> Result<(HashMap<ast::PolicyID, est::Policy>, ast::PolicySet), err::ParseErrors> {
     let mut errs = err::ParseErrors::new();
     let cst = text_to_cst::parse_policies(text)?;
+    let Some(cst) = cst.substitute_decls(&Prelude::default(), &decls::NoImports, &mut errs) else {
+        return Err(errs);
+    };
     let Some(pset) = cst.to_policyset(&mut errs) else {
         return Err(errs);
     };
@@ -597,7 +636,9 @@ mod eval_tests {
 
 #[cfg(test)]
 mod parse_tests {
+    use super::err::ToASTError;
     use super::*;
+    use crate::extensions::Extensions;
 
     #[test]
     fn parse_exists() {
@@ -629,21 +670,368 @@ mod parse_tests {
         assert_eq!(pset.static_policies().count(), 2);
         assert_eq!(texts.len(), 2);
         assert_eq!(
-            texts.get(&PolicyID::from_string("policy0")),
+            texts
+                .get(&PolicyID::from_string("policy0"))
+                .map(AsRef::as_ref),
             Some(
-                &r#"permit(principal, action, resource)
+                r#"permit(principal, action, resource)
             when { principal == resource.owner };"#
             )
         );
         assert_eq!(
-            texts.get(&PolicyID::from_string("policy1")),
+            texts
+                .get(&PolicyID::from_string("policy1"))
+                .map(AsRef::as_ref),
             Some(
-                &r#"forbid(principal, action == Action::"modify", resource) // a comment
+                r#"forbid(principal, action == Action::"modify", resource) // a comment
             when { resource . highSecurity };"#
             )
         );
     }
 
+    #[test]
+    fn test_parse_policyset_with_consts() {
+        use crate::ast::PolicyID;
+        let src = r#"
+            const MAX_TRANSFER = u256("5000000000000000000");
+            const LIMITS = { transfer: MAX_TRANSFER, calls: 10 };
+
+            forbid(principal, action, resource)
+            when { context.amount.u256GreaterThan(MAX_TRANSFER) };
+
+            // MAX_TRANSFER is an attribute name here
+            permit(principal, action, resource)
+            when { context has MAX_TRANSFER && context.limits == LIMITS };
+
+            permit(principal, action, resource);
+        "#;
+        let (texts, pset) = parse_policyset_and_also_return_policy_text(src).expect("Should parse");
+        assert_eq!(pset.policies().count(), 3);
+        let expected = parse_policyset(
+            r#"
+            forbid(principal, action, resource)
+            when { context.amount.u256GreaterThan((u256("5000000000000000000"))) };
+
+            permit(principal, action, resource)
+            when { context has MAX_TRANSFER &&
+                   context.limits == ({ transfer: (u256("5000000000000000000")), calls: 10 }) };
+
+            permit(principal, action, resource);
+        "#,
+        )
+        .expect("Should parse");
+        for policy in expected.policies() {
+            let parsed = pset.get(policy.id()).expect("should have the same ids");
+            assert!(
+                parsed
+                    .non_head_constraints()
+                    .eq_shape(policy.non_head_constraints()),
+                "{parsed} and {policy} should have the same shape"
+            );
+        }
+
+        // the text of a policy using a constant stands on its own
+        let text = texts.get(&PolicyID::from_string("policy0")).expect("text");
+        assert!(matches!(text, Cow::Owned(_)));
+        parse_policy(None, text).expect("text should parse on its own");
+        assert_eq!(
+            texts
+                .get(&PolicyID::from_string("policy2"))
+                .map(AsRef::as_ref),
+            Some("permit(principal, action, resource);")
+        );
+
+        let (ests, _) = parse_policyset_to_ests_and_pset(src).expect("Should parse");
+        assert_eq!(ests.len(), 3);
+    }
+
+    #[test]
+    fn test_parse_policyset_with_invalid_consts() {
+        for (src, err) in [
+            (
+                "const A = 1; const A = 2; permit(principal, action, resource);",
+                ToASTError::DuplicateDeclaration("A".into()),
+            ),
+            (
+                "const principal = 1; permit(principal, action, resource);",
+                ToASTError::ReservedIdentifier(cst::Ident::Principal),
+            ),
+            (
+                r#"const MAX = u256("-1"); permit(principal, action, resource);"#,
+                ToASTError::InvalidConstValue {
+                    name: "MAX".into(),
+                    reason: String::new(),
+                },
+            ),
+            (
+                "const LIMIT = context.limit; permit(principal, action, resource);",
+                ToASTError::InvalidConstValue {
+                    name: "LIMIT".into(),
+                    reason: String::new(),
+                },
+            ),
+        ] {
+            let errs = parse_policyset(src).expect_err("should fail");
+            assert!(
+                errs.iter().any(|e| match (e, &err) {
+                    (
+                        err::ParseError::ToAST(ToASTError::InvalidConstValue { name, .. }),
+                        ToASTError::InvalidConstValue { name: expected, .. },
+                    ) => name == expected,
+                    (err::ParseError::ToAST(e), err) => e == err,
+                    _ => false,
+                }),
+                "{src}: {errs:?}"
+            );
+        }
+
+        // a constant can only use the constants declared before it
+        assert!(parse_policyset(
+            "const A = B; const B = 1; permit(principal, action, resource) when { A == 1 };"
+        )
+        .is_err());
+        // an undeclared name is still an error
+        assert!(
+            parse_policyset("permit(principal, action, resource) when { LIMIT == 1 };").is_err()
+        );
+        // `const` is still an ordinary identifier elsewhere
+        parse_policyset("permit(principal, action, resource) when { context.const == 1 };")
+            .expect("Should parse");
+    }
+
+    #[test]
+    fn test_parse_policyset_with_macros() {
+        use crate::ast::PolicyID;
+        let src = r#"
+            const STABLECOINS = Group::"stablecoins";
+            macro isStablecoin(token) = token in STABLECOINS;
+            macro canMove(token, amount) =
+                isStablecoin(token) && amount.u256LessThan(u256("1000"));
+
+            permit(principal, action, resource)
+            when { canMove(resource, context.amount) && principal.isStablecoin };
+        "#;
+        let (texts, pset) = parse_policyset_and_also_return_policy_text(src).expect("Should parse");
+        let expected = parse_policy(
+            None,
+            r#"permit(principal, action, resource)
+            when { ((resource) in (Group::"stablecoins")
+                   && (context.amount).u256LessThan(u256("1000")))
+                   && principal.isStablecoin };"#,
+        )
+        .expect("Should parse");
+        let policy = pset
+            .get(&PolicyID::from_string("policy0"))
+            .expect("should have a policy");
+        assert!(
+            policy
+                .non_head_constraints()
+                .eq_shape(expected.non_head_constraints()),
+            "{policy} and {expected} should have the same shape"
+        );
+        let text = texts.get(&PolicyID::from_string("policy0")).expect("text");
+        parse_policy(None, text).expect("text should parse on its own");
+
+        for (src, err) in [
+            (
+                "macro f(x) = x; permit(principal, action, resource) when { f(1, 2) };",
+                ToASTError::MacroArity {
+                    name: "f".into(),
+                    expected: 1,
+                    got: 2,
+                },
+            ),
+            (
+                "const x = 1; macro f(x) = x; permit(principal, action, resource);",
+                ToASTError::DuplicateDeclaration("x".into()),
+            ),
+            (
+                "macro f(x, x) = x; permit(principal, action, resource);",
+                ToASTError::DuplicateDeclaration("x".into()),
+            ),
+        ] {
+            let errs = parse_policyset(src).expect_err("should fail");
+            assert!(errs.contains(&err.into()), "{src}: {errs:?}");
+        }
+    }
+
+    #[test]
+    fn test_parse_policyset_with_imports() {
+        let files: HashMap<String, String> = [
+            (
+                "tokens.cedar",
+                r#"import "groups.cedar"; macro isStablecoin(t) = t in STABLECOINS;"#,
+            ),
+            (
+                "limits.cedar",
+                r#"import "groups.cedar"; const MAX = u256("1000");"#,
+            ),
+            (
+                "groups.cedar",
+                r#"const STABLECOINS = Group::"stablecoins";"#,
+            ),
+            ("a.cedar", r#"import "b.cedar";"#),
+            ("b.cedar", r#"import "a.cedar";"#),
+            ("policies.cedar", "permit(principal, action, resource);"),
+            ("broken.cedar", "const X = ;"),
+        ]
+        .into_iter()
+        .map(|(path, text)| (path.to_string(), text.to_string()))
+        .collect();
+        let parse = |src| {
+            parse_policyset_and_also_return_policy_text_with_prelude(
+                src,
+                &Prelude::default(),
+                &files,
+            )
+        };
+
+        // `groups.cedar` is imported twice, but only read once
+        let (_, pset) = parse(
+            r#"
+            import "tokens.cedar";
+            import "limits.cedar";
+            permit(principal, action, resource)
+            when { isStablecoin(resource) && context.amount.u256LessThan(MAX) };
+            "#,
+        )
+        .expect("Should parse");
+        assert_eq!(pset.policies().count(), 1);
+
+        let errs = parse(r#"import "a.cedar"; permit(principal, action, resource);"#)
+            .expect_err("should fail");
+        assert!(
+            errs.contains(
+                &ToASTError::ImportCycle(vec![
+                    "a.cedar".into(),
+                    "b.cedar".into(),
+                    "a.cedar".into()
+                ])
+                .into()
+            ),
+            "{errs:?}"
+        );
+        for (path, reason) in [
+            (
+                "policies.cedar",
+                "can only contain imports and declarations",
+            ),
+            ("missing.cedar", "no such file"),
+            ("broken.cedar", "unexpected token"),
+        ] {
+            let src = format!(r#"import "{path}"; permit(principal, action, resource);"#);
+            let errs = parse_policyset_and_also_return_policy_text_with_prelude(
+                &src,
+                &Prelude::default(),
+                &files,
+            )
+            .expect_err("should fail");
+            assert!(
+                errs.iter().any(|e| matches!(e,
+                    err::ParseError::ToAST(ToASTError::InvalidImport { path: p, reason: r })
+                        if p == path && r.contains(reason)
+                )),
+                "{path}: {errs:?}"
+            );
+        }
+
+        // without an import source, imports are an error
+        assert!(
+            parse_policyset(r#"import "groups.cedar"; permit(principal, action, resource);"#)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_parse_policyset_with_prelude() {
+        let files: HashMap<String, String> = [(
+            "limits.cedar".to_string(),
+            r#"const DAILY_CAP = u256("1000");"#.to_string(),
+        )]
+        .into_iter()
+        .collect();
+        let prelude = Prelude::parse(
+            r#"
+            import "limits.cedar";
+            macro withinRange(amount, floor, cap) =
+                amount.u256GreaterThanOrEqual(floor) && amount.u256LessThanOrEqual(cap);
+            "#,
+            &files,
+        )
+        .expect("Should parse");
+        let no_imports = HashMap::<String, String>::new();
+
+        let (texts, pset) = parse_policyset_and_also_return_policy_text_with_prelude(
+            r#"
+            permit(principal, action, resource)
+            when { withinRange(context.amount, context.floor, DAILY_CAP) };
+            permit(principal, action, resource)
+            when { withinRange(context.amount, u256("0"), DAILY_CAP) };
+            "#,
+            &prelude,
+            &no_imports,
+        )
+        .expect("Should parse");
+        assert_eq!(pset.policies().count(), 2);
+        // the text of each policy has the function inlined
+        for text in texts.values() {
+            assert!(!text.contains("withinRange"), "{text}");
+            parse_policy(None, text).expect("Should parse");
+        }
+
+        // files can't redeclare the prelude's names
+        let errs = parse_policyset_and_also_return_policy_text_with_prelude(
+            r#"macro withinRange(a) = a; permit(principal, action, resource);"#,
+            &prelude,
+            &no_imports,
+        )
+        .expect_err("should fail");
+        assert!(
+            errs.contains(&ToASTError::DuplicateDeclaration("withinRange".into()).into()),
+            "{errs:?}"
+        );
+
+        for (src, err) in [
+            (
+                "permit(principal, action, resource);",
+                ToASTError::PolicyInPrelude,
+            ),
+            // macros can't use themselves
+            ("macro f(x) = f(x);", ToASTError::RecursiveMacro("f".into())),
+        ] {
+            let errs = Prelude::parse(src, &no_imports).expect_err("should fail");
+            assert!(errs.contains(&err.clone().into()), "{src}: {errs:?}");
+        }
+
+        // constants are checked with the prelude's extensions, in the prelude
+        // and in the files parsed with it
+        let none = Prelude::parse_with_extensions("", &no_imports, Extensions::none())
+            .expect("Should parse");
+        for errs in [
+            Prelude::parse_with_extensions(
+                r#"const DAILY_CAP = u256("1000");"#,
+                &no_imports,
+                Extensions::none(),
+            )
+            .expect_err("should fail"),
+            parse_policyset_and_also_return_policy_text_with_prelude(
+                r#"const DAILY_CAP = u256("1000"); permit(principal, action, resource);"#,
+                &none,
+                &no_imports,
+            )
+            .expect_err("should fail"),
+        ] {
+            assert!(
+                errs.iter().any(|e| matches!(
+                    e,
+                    err::ParseError::ToAST(ToASTError::InvalidConstValue { name, .. })
+                        if name == "DAILY_CAP"
+                )),
+                "{errs:?}"
+            );
+        }
+    }
+
     #[test]
     fn test_parse_string() {
         // test idempotence
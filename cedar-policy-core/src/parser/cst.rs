@@ -23,7 +23,51 @@ type Node<N> = ASTNode<Option<N>>;
 
 /// The set of policy statements that forms an authorization policy
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub struct Policies(pub Vec<Node<Policy>>);
+pub struct Policies {
+    /// Files whose declarations this file uses
+    pub imports: Vec<Node<Import>>,
+    /// Constants and macros declared at the start of the file
+    pub decls: Vec<Node<Decl>>,
+    /// Policies, which may refer to the declarations
+    pub policies: Vec<Node<Policy>>,
+}
+
+/// Import of the declarations in another file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Import {
+    /// path of the file
+    pub path: Node<Str>,
+}
+
+/// Declaration of a name shared by the policies in a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decl {
+    /// constant
+    Const(Const),
+    /// macro
+    Macro(Macro),
+}
+
+/// Constant declaration, naming a value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Const {
+    /// name of the constant
+    pub name: Node<Ident>,
+    /// value of the constant
+    pub value: Node<Expr>,
+}
+
+/// Macro declaration, naming an expression with parameters
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Macro {
+    /// name of the macro
+    pub name: Node<Ident>,
+    /// parameters
+    pub params: Vec<Node<Ident>>,
+    /// expression each use expands to, with the arguments in place of the
+    /// parameters
+    pub body: Node<Expr>,
+}
 
 /// Annotations: application-defined data, as a key-value pair
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -68,8 +112,12 @@ pub struct VariableDef {
     pub variable: Node<Ident>,
     /// type of entity
     pub name: Option<Node<Name>>,
+    /// entity type the variable is tested against with `is`
+    pub entity_type: Option<Node<Name>>,
     /// hierarchy of entity
     pub ineq: Option<(RelOp, Node<Expr>)>,
+    /// condition attached to the variable with `when`
+    pub when: Option<Node<Expr>>,
 }
 
 /// Any identifier, including special ones
@@ -105,6 +153,8 @@ pub enum Ident {
     Has,
     /// like
     Like,
+    /// is
+    Is,
     /// if
     If,
     /// then
@@ -187,6 +237,20 @@ pub enum Relation {
         /// pattern to match on
         pattern: Node<Add>,
     },
+    /// Built-in 'glob' operation: 'like' with character classes
+    Glob {
+        /// element to test
+        target: Node<Add>,
+        /// pattern to match on
+        pattern: Node<Add>,
+    },
+    /// Built-in 'is' operation
+    Is {
+        /// element to test
+        target: Node<Add>,
+        /// entity type to check for
+        entity_type: Node<Name>,
+    },
 }
 
 /// The operation involved in a comparision
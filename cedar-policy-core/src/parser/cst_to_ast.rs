@@ -35,9 +35,10 @@
 // cases where there is a secondary conversion. This prevents any further
 // cloning.
 
+use super::decls::{NoImports, Prelude};
 use super::err::{ParseError, ParseErrors, Ref, RefCreationError, ToASTError};
 use super::node::{ASTNode, SourceInfo};
-use super::unescape::{to_pattern, to_unescaped_string};
+use super::unescape::{to_glob_pattern, to_pattern, to_unescaped_string};
 use super::{cst, err};
 use crate::ast::{
     self, ActionConstraint, CallStyle, EntityReference, EntityType, EntityUID, PatternElem,
@@ -84,7 +85,7 @@ impl ASTNode<Option<cst::Policies>> {
 
         Some(
             policies
-                .0
+                .policies
                 .iter()
                 .enumerate()
                 .map(|(count, node)| (ast::PolicyID::from_string(format!("policy{count}")), node)),
@@ -95,10 +96,11 @@ impl ASTNode<Option<cst::Policies>> {
     pub fn to_policyset(&self, errs: &mut ParseErrors) -> Option<ast::PolicySet> {
         let mut pset = ast::PolicySet::new();
         let mut complete_set = true;
+        let substituted = self.substitute_decls(&Prelude::default(), &NoImports, errs)?;
         // Caution: `parser::parse_policyset_and_also_return_policy_text()`
         // depends on this function returning a policy set with `PolicyID`s as
         // generated by `with_generated_policyids()` to maintain an invariant.
-        for (policy_id, policy) in self.with_generated_policyids()? {
+        for (policy_id, policy) in substituted.with_generated_policyids()? {
             // policy may have convert error
             match policy.to_policy_or_template(policy_id, errs) {
                 Some(Either::Right(template)) => {
@@ -215,12 +217,14 @@ impl ASTNode<Option<cst::Policy>> {
         // convert head
         let (maybe_principal, maybe_action, maybe_resource) = policy.extract_head(errs);
 
-        // convert conditions
-        let conds: Vec<_> = policy
-            .conds
-            .iter()
-            .filter_map(|c| c.to_expr(errs))
-            .collect();
+        // convert conditions, starting with any `when` sugar in the scope
+        let scope_conds = policy.extract_scope_conditions();
+        let mut conds: Vec<_> = scope_conds.iter().filter_map(|e| e.to_expr(errs)).collect();
+        if conds.len() != scope_conds.len() {
+            failure = true
+        }
+        let num_scope_conds = conds.len();
+        conds.extend(policy.conds.iter().filter_map(|c| c.to_expr(errs)));
 
         for e in conds.iter() {
             for _slot in e.slots() {
@@ -228,7 +232,7 @@ impl ASTNode<Option<cst::Policy>> {
             }
         }
 
-        if conds.len() != policy.conds.len() {
+        if conds.len() != num_scope_conds + policy.conds.len() {
             failure = true
         }
 
@@ -300,6 +304,17 @@ impl cst::Policy {
         }
         (principal, action, resource)
     }
+
+    /// get the `when` conditions attached to the `principal` and `resource`
+    /// scope constraints, which are desugared into ordinary `when` clauses
+    pub fn extract_scope_conditions(&self) -> Vec<&ASTNode<Option<cst::Expr>>> {
+        self.variables
+            .iter()
+            .filter_map(|v| v.as_inner())
+            .filter(|v| !matches!(v.variable.as_inner(), Some(cst::Ident::Action)))
+            .filter_map(|v| v.when.as_ref())
+            .collect()
+    }
 }
 
 impl ASTNode<Option<cst::Annotation>> {
@@ -346,7 +361,8 @@ impl ASTNode<Option<cst::Ident>> {
             | cst::Ident::Else
             | cst::Ident::In
             | cst::Ident::Has
-            | cst::Ident::Like => {
+            | cst::Ident::Like
+            | cst::Ident::Is => {
                 errs.push(err::ParseError::ToAST(ToASTError::ReservedIdentifier(
                     ident.clone(),
                 )));
@@ -433,6 +449,14 @@ impl ast::Id {
                 let arg = mem::replace(a, ast::Expr::val(false));
                 Some(construct_method_contains_any(e, arg, l))
             }
+            ("getTag", Some(a), None) => {
+                let arg = mem::replace(a, ast::Expr::val(false));
+                Some(construct_method_get_tag(e, arg, l))
+            }
+            ("hasTag", Some(a), None) => {
+                let arg = mem::replace(a, ast::Expr::val(false));
+                Some(construct_method_has_tag(e, arg, l))
+            }
             (name, _, _) => {
                 if EXTENSION_STYLES.methods.contains(&name) {
                     args.insert(0, e);
@@ -510,16 +534,30 @@ impl ASTNode<Option<cst::VariableDef>> {
             typename.to_type_constraint(errs)?;
         }
 
+        let entity_type = match vardef.entity_type.as_ref() {
+            Some(entity_type) => Some(entity_type.to_name(errs)?),
+            None => None,
+        };
+
         let c = if let Some((op, rel_expr)) = &vardef.ineq {
             let eref = rel_expr.to_ref_or_slot(errs, var)?;
-            match op {
-                cst::RelOp::Eq => Some(PrincipalOrResourceConstraint::Eq(eref)),
-                cst::RelOp::In => Some(PrincipalOrResourceConstraint::In(eref)),
-                op => {
+            match (op, entity_type) {
+                (cst::RelOp::Eq, None) => Some(PrincipalOrResourceConstraint::Eq(eref)),
+                (cst::RelOp::Eq, Some(_)) => {
+                    errs.push(ToASTError::IsWithEqConstraint.into());
+                    None
+                }
+                (cst::RelOp::In, None) => Some(PrincipalOrResourceConstraint::In(eref)),
+                (cst::RelOp::In, Some(entity_type)) => {
+                    Some(PrincipalOrResourceConstraint::IsIn(entity_type, eref))
+                }
+                (op, _) => {
                     errs.push(ToASTError::InvalidConstraintOperator(*op).into());
                     None
                 }
             }
+        } else if let Some(entity_type) = entity_type {
+            Some(PrincipalOrResourceConstraint::Is(entity_type))
         } else {
             Some(PrincipalOrResourceConstraint::Any)
         }?;
@@ -558,6 +596,15 @@ impl ASTNode<Option<cst::VariableDef>> {
             typename.to_type_constraint(errs)?;
         }
 
+        if vardef.entity_type.is_some() {
+            errs.push(ToASTError::UnsupportedActionScope("is").into());
+            return None;
+        }
+        if vardef.when.is_some() {
+            errs.push(ToASTError::UnsupportedActionScope("when").into());
+            return None;
+        }
+
         let action_constraint = if let Some((op, rel_expr)) = &vardef.ineq {
             let refs = rel_expr.to_refs(errs, ast::Var::Action)?;
             match (op, refs) {
@@ -750,9 +797,15 @@ impl ExprOrSpecial<'_> {
         }
     }
 
-    fn into_pattern(self, errs: &mut ParseErrors) -> Option<Vec<PatternElem>> {
+    /// to the pattern of a `like` expression or, if `glob`, of a `glob`
+    /// expression
+    fn into_pattern(self, glob: bool, errs: &mut ParseErrors) -> Option<Vec<PatternElem>> {
         match self {
-            Self::StrLit(s, _) => match to_pattern(s) {
+            Self::StrLit(s, _) => match if glob {
+                to_glob_pattern(s)
+            } else {
+                to_pattern(s)
+            } {
                 Ok(pat) => Some(pat),
                 Err(escape_errs) => {
                     errs.extend(
@@ -1029,6 +1082,14 @@ impl ASTNode<Option<cst::Relation>> {
                 errs.push(ToASTError::wrong_node(T::err_str(), "like").into());
                 None
             }
+            cst::Relation::Glob { .. } => {
+                errs.push(ToASTError::wrong_node(T::err_str(), "glob").into());
+                None
+            }
+            cst::Relation::Is { .. } => {
+                errs.push(ToASTError::wrong_node(T::err_str(), "is").into());
+                None
+            }
         }
     }
 
@@ -1079,7 +1140,7 @@ impl ASTNode<Option<cst::Relation>> {
             cst::Relation::Like { target, pattern } => {
                 match (
                     target.to_expr(errs),
-                    pattern.to_expr_or_special(errs)?.into_pattern(errs),
+                    pattern.to_expr_or_special(errs)?.into_pattern(false, errs),
                 ) {
                     (Some(t), Some(s)) => {
                         Some(ExprOrSpecial::Expr(construct_expr_like(t, s, src.clone())))
@@ -1087,6 +1148,26 @@ impl ASTNode<Option<cst::Relation>> {
                     _ => None,
                 }
             }
+            cst::Relation::Glob { target, pattern } => {
+                match (
+                    target.to_expr(errs),
+                    pattern.to_expr_or_special(errs)?.into_pattern(true, errs),
+                ) {
+                    (Some(t), Some(s)) => {
+                        Some(ExprOrSpecial::Expr(construct_expr_like(t, s, src.clone())))
+                    }
+                    _ => None,
+                }
+            }
+            cst::Relation::Is {
+                target,
+                entity_type,
+            } => match (target.to_expr(errs), entity_type.to_name(errs)) {
+                (Some(t), Some(n)) => {
+                    Some(ExprOrSpecial::Expr(construct_expr_is(t, n, src.clone())))
+                }
+                _ => None,
+            },
         }
     }
 }
@@ -1775,10 +1856,11 @@ impl ast::Name {
         if self.path.is_empty() {
             let id = self.id.as_ref();
             match id {
-                "contains" | "containsAll" | "containsAny" => {
+                "contains" | "containsAll" | "containsAny" | "getTag" | "hasTag" => {
                     errs.push(ToASTError::FunctionCallOnMethod(self.id).into());
                     return None;
                 }
+                "getOr" => return construct_expr_get_or(args, errs, l),
                 _ => {}
             }
         }
@@ -2032,9 +2114,39 @@ fn construct_expr_has(t: ast::Expr, s: SmolStr, l: SourceInfo) -> ast::Expr {
 fn construct_expr_attr(e: ast::Expr, s: SmolStr, l: SourceInfo) -> ast::Expr {
     ast::ExprBuilder::new().with_source_info(l).get_attr(e, s)
 }
+/// `getOr(e, "attr", default)` is sugar for
+/// `if e has attr then e.attr else default`
+fn construct_expr_get_or(
+    args: Vec<ast::Expr>,
+    errs: &mut ParseErrors,
+    l: SourceInfo,
+) -> Option<ast::Expr> {
+    let [e, attr, default]: [ast::Expr; 3] = match args.try_into() {
+        Ok(args) => args,
+        Err(args) => {
+            errs.push(ToASTError::wrong_arity("getOr", 3, args.len()).into());
+            return None;
+        }
+    };
+    let ast::ExprKind::Lit(ast::Literal::String(attr)) = attr.expr_kind() else {
+        errs.push(ToASTError::NonStringGetOrAttr.into());
+        return None;
+    };
+    Some(construct_expr_if(
+        construct_expr_has(e.clone(), attr.clone(), l.clone()),
+        construct_expr_attr(e, attr.clone(), l.clone()),
+        default,
+        l,
+    ))
+}
 fn construct_expr_like(e: ast::Expr, s: Vec<PatternElem>, l: SourceInfo) -> ast::Expr {
     ast::ExprBuilder::new().with_source_info(l).like(e, s)
 }
+fn construct_expr_is(e: ast::Expr, n: ast::Name, l: SourceInfo) -> ast::Expr {
+    ast::ExprBuilder::new()
+        .with_source_info(l)
+        .is_entity_type(e, n)
+}
 fn construct_ext_func(name: ast::Name, args: Vec<ast::Expr>, l: SourceInfo) -> ast::Expr {
     // INVARIANT (MethodStyleArgs): CallStyle is not MethodStyle, so any args vector is fine
     ast::ExprBuilder::new()
@@ -2055,6 +2167,12 @@ fn construct_method_contains_any(e0: ast::Expr, e1: ast::Expr, l: SourceInfo) ->
         .with_source_info(l)
         .contains_any(e0, e1)
 }
+fn construct_method_get_tag(e0: ast::Expr, e1: ast::Expr, l: SourceInfo) -> ast::Expr {
+    ast::ExprBuilder::new().with_source_info(l).get_tag(e0, e1)
+}
+fn construct_method_has_tag(e0: ast::Expr, e1: ast::Expr, l: SourceInfo) -> ast::Expr {
+    ast::ExprBuilder::new().with_source_info(l).has_tag(e0, e1)
+}
 
 // INVARIANT (MethodStyleArgs), args must be non-empty
 fn construct_ext_meth(n: String, args: Vec<ast::Expr>, l: SourceInfo) -> ast::Expr {
@@ -2905,6 +3023,74 @@ mod tests {
         assert_eq!(s1, s2);
     }
 
+    #[test]
+    fn construct_glob() {
+        let mut errs = ParseErrors::new();
+        let class = ast::PatternElem::Class(ast::CharClass::new([('0', '9')], false));
+        // `[` starts a character class in `glob` patterns, and is literal in
+        // `like` patterns
+        for (src, elems) in [
+            (
+                r#""a1" glob "a[0-9]""#,
+                vec![PatternElem::Char('a'), class.clone()],
+            ),
+            (
+                r#""a1" like "a[0-9]""#,
+                "a[0-9]".chars().map(PatternElem::Char).collect(),
+            ),
+            (
+                r#""a[" glob "a\[""#,
+                vec![PatternElem::Char('a'), PatternElem::Char('[')],
+            ),
+        ] {
+            let expr = text_to_cst::parse_expr(src)
+                .expect("failed parser")
+                .to_expr(&mut errs)
+                .expect("failed convert");
+            match expr.expr_kind() {
+                ast::ExprKind::Like { pattern, .. } => assert_eq!(pattern.get_elems(), elems),
+                _ => panic!("should be a like expr"),
+            }
+        }
+
+        // a pattern with a class prints as `glob`, with `[`, `]`, `-` and `^`
+        // escaped, and one without as `like`, exactly as before classes
+        let glob = ast::Expr::like(
+            ast::Expr::val("a1"),
+            "[]-^"
+                .chars()
+                .map(PatternElem::Char)
+                .chain([class])
+                .collect::<Vec<_>>(),
+        );
+        assert_eq!(glob.to_string(), r#""a1" glob "\[\]\-\^[0-9]""#);
+        let like = ast::Expr::like(
+            ast::Expr::val("a["),
+            "[a-z]^".chars().map(PatternElem::Char).collect::<Vec<_>>(),
+        );
+        assert_eq!(like.to_string(), r#""a[" like "[a-z]^""#);
+        for e in [glob, like] {
+            let reparsed = text_to_cst::parse_expr(&e.to_string())
+                .expect("failed parser")
+                .to_expr(&mut errs)
+                .expect("failed convert");
+            assert!(reparsed.eq_shape(&e));
+        }
+
+        // malformed classes are errors
+        assert!(text_to_cst::parse_expr(r#""a" glob "[9-0]""#)
+            .expect("failed parser")
+            .to_expr(&mut errs)
+            .is_none());
+
+        // `glob` is still an identifier elsewhere
+        let expr = text_to_cst::parse_expr(r#"context.glob glob "*""#)
+            .expect("failed parser")
+            .to_expr(&mut errs)
+            .expect("failed convert");
+        assert_eq!(expr.to_string(), r#"(context["glob"]) like "*""#);
+    }
+
     #[test]
     fn issue_wf_5046() {
         let policy = parse_policy(
@@ -3480,6 +3666,178 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_get_or() {
+        let mut errs = ParseErrors::new();
+        let e = text_to_cst::parse_expr(r#"getOr(context.calldata, "fee", 0) < 5"#)
+            .expect("should construct a CST")
+            .to_expr(&mut errs)
+            .expect("should convert to AST");
+        let calldata = Expr::get_attr(Expr::var(ast::Var::Context), "calldata".into());
+        let expr = Expr::less(
+            Expr::ite(
+                Expr::has_attr(calldata.clone(), "fee".into()),
+                Expr::get_attr(calldata, "fee".into()),
+                Expr::val(0),
+            ),
+            Expr::val(5),
+        );
+        assert!(
+            e.eq_shape(&expr),
+            "{:?} and {:?} should have the same shape.",
+            e,
+            expr
+        );
+
+        for (es, err) in [
+            (
+                r#"getOr(context, "fee")"#,
+                ToASTError::wrong_arity("getOr", 3, 2),
+            ),
+            (
+                r#"getOr(context, context.attr, 0)"#,
+                ToASTError::NonStringGetOrAttr,
+            ),
+        ] {
+            let mut errs = ParseErrors::new();
+            let e = text_to_cst::parse_expr(es)
+                .expect("should construct a CST")
+                .to_expr(&mut errs);
+            assert!(e.is_none());
+            assert!(
+                errs.contains(&err.into()),
+                "expected {es} to fail with the right error, got {errs:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_tags() {
+        let mut errs = ParseErrors::new();
+        let e = text_to_cst::parse_expr(
+            r#"principal.hasTag("tier") && principal.getTag("tier") == "gold""#,
+        )
+        .expect("should construct a CST")
+        .to_expr(&mut errs)
+        .expect("should convert to AST");
+        let expr = Expr::and(
+            Expr::has_tag(Expr::var(ast::Var::Principal), Expr::val("tier")),
+            Expr::is_eq(
+                Expr::get_tag(Expr::var(ast::Var::Principal), Expr::val("tier")),
+                Expr::val("gold"),
+            ),
+        );
+        assert!(
+            e.eq_shape(&expr),
+            "{:?} and {:?} should have the same shape.",
+            e,
+            expr
+        );
+        assert_eq!(
+            e.to_string(),
+            r#"(principal.hasTag("tier")) && ((principal.getTag("tier")) == "gold")"#
+        );
+
+        let mut errs = ParseErrors::new();
+        let e = text_to_cst::parse_expr(r#"getTag(principal, "tier")"#)
+            .expect("should construct a CST")
+            .to_expr(&mut errs);
+        assert!(e.is_none());
+        assert!(errs
+            .contains(&ToASTError::FunctionCallOnMethod(ast::Id::new_unchecked("getTag")).into()));
+    }
+
+    #[test]
+    fn test_is() {
+        let mut errs = ParseErrors::new();
+        let e = text_to_cst::parse_expr(r#"principal is NS::Wallet && true"#)
+            .expect("should construct a CST")
+            .to_expr(&mut errs)
+            .expect("should convert to AST");
+        let expr = Expr::and(
+            Expr::is_entity_type(
+                Expr::var(ast::Var::Principal),
+                "NS::Wallet".parse().expect("valid name"),
+            ),
+            Expr::val(true),
+        );
+        assert!(
+            e.eq_shape(&expr),
+            "{:?} and {:?} should have the same shape.",
+            e,
+            expr
+        );
+    }
+
+    #[test]
+    fn scope_is_when() {
+        let mut errs = ParseErrors::new();
+        let policy = text_to_cst::parse_policy(
+            r#"
+            permit(
+                principal is Wallet when principal.kycLevel >= 2,
+                action,
+                resource is Vault in Org::"acme"
+            ) when { context.mfa };
+        "#,
+        )
+        .expect("failed parse")
+        .to_policy(ast::PolicyID::from_string("id"), &mut errs)
+        .expect("failed convert");
+        assert_eq!(
+            policy.principal_constraint().as_inner(),
+            &ast::PrincipalOrResourceConstraint::is_entity_type(
+                "Wallet".parse().expect("valid name")
+            )
+        );
+        assert_eq!(
+            policy.resource_constraint().as_inner(),
+            &ast::PrincipalOrResourceConstraint::is_entity_type_in(
+                "Vault".parse().expect("valid name"),
+                r#"Org::"acme""#.parse().expect("valid euid"),
+            )
+        );
+        // the scope condition comes before the policy's own conditions
+        let conds = Expr::and(
+            Expr::greatereq(
+                Expr::get_attr(Expr::var(ast::Var::Principal), "kycLevel".into()),
+                Expr::val(2),
+            ),
+            Expr::get_attr(Expr::var(ast::Var::Context), "mfa".into()),
+        );
+        assert!(
+            policy.non_head_constraints().eq_shape(&conds),
+            "{:?} and {:?} should have the same shape.",
+            policy.non_head_constraints(),
+            conds
+        );
+    }
+
+    #[test]
+    fn scope_is_errors() {
+        for (src, err) in [
+            (
+                r#"permit(principal is User == User::"alice", action, resource);"#,
+                ToASTError::IsWithEqConstraint,
+            ),
+            (
+                r#"permit(principal, action is Action, resource);"#,
+                ToASTError::UnsupportedActionScope("is"),
+            ),
+            (
+                r#"permit(principal, action when true, resource);"#,
+                ToASTError::UnsupportedActionScope("when"),
+            ),
+        ] {
+            let mut errs = ParseErrors::new();
+            let policy = text_to_cst::parse_policy(src)
+                .expect("failed parse")
+                .to_policy(ast::PolicyID::from_string("id"), &mut errs);
+            assert!(policy.is_none());
+            assert!(errs.contains(&err.into()), "{src}: {errs:?}");
+        }
+    }
+
     #[test]
     fn test_neg() {
         for (es, expr) in [
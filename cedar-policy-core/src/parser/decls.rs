@@ -0,0 +1,622 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Imports and declarations at the start of a policy file
+//!
+//! A file may begin by importing other files, and then declare constants and
+//! macros:
+//!
+//! ```text
+//! import "tokens.cedar";
+//!
+//! const MAX_TRANSFER = u256("5000000000000000000");
+//! macro isStablecoin(token) = token in Group::"stablecoins";
+//! ```
+//!
+//! Its policies may then use `MAX_TRANSFER` wherever they could use the value
+//! itself, and `isStablecoin(resource)` wherever they could use the macro's
+//! body with `resource` in place of `token`. A constant's value must be a
+//! literal, or an extension function applied to literals, which is evaluated
+//! when the file is parsed so that a malformed value (say, a `u256` that
+//! doesn't fit) is reported at its declaration. It is evaluated with the
+//! extensions of the [`Prelude`] the file is parsed with, all the available
+//! ones unless it was parsed with [`Prelude::parse_with_extensions()`]. A declaration may use the
+//! names declared before it, including those the file imports.
+//!
+//! An imported file adds its declarations, and those of the files it imports,
+//! but can't contain policies. Its path is given to an [`ImportSource`], which
+//! loads it. A file imported more than once is only read once, and a file that
+//! imports itself, directly or not, is an error.
+//!
+//! Declarations that many files share can also be given to the parser as a
+//! [`Prelude`], which every file it parses can use without importing it:
+//!
+//! ```text
+//! macro withinRange(amount, floor, cap) =
+//!   amount.u256GreaterThanOrEqual(floor) && amount.u256LessThanOrEqual(cap);
+//! ```
+//!
+//! Since a name must be declared before it's used, a macro can't be recursive.
+//!
+//! References are replaced with the (parenthesized) value in the CST, before
+//! it's converted to an AST or EST, so the rest of the parser, and everything
+//! downstream of it, never sees them. This is why macros are declared in
+//! policy files and preludes, rather than in the schema and inlined when
+//! policies are validated: policies parsed without a schema are evaluated
+//! with the same expansions as validated ones.
+
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::hash::BuildHasher;
+
+use smol_str::SmolStr;
+
+use super::cst;
+use super::err::{ParseError, ParseErrors, ToASTError};
+use super::node::ASTNode;
+use super::text_to_cst;
+use super::unescape::to_unescaped_string;
+use crate::ast::BorrowedRestrictedExpr;
+use crate::evaluator::RestrictedEvaluator;
+use crate::extensions::Extensions;
+
+type Node<N> = ASTNode<Option<N>>;
+
+/// Where the files that policy files import come from
+pub trait ImportSource {
+    /// The text of the file at `path`, or why it can't be read
+    fn load(&self, path: &str) -> Result<String, String>;
+}
+
+/// Files by path
+impl<S: BuildHasher> ImportSource for HashMap<String, String, S> {
+    fn load(&self, path: &str) -> Result<String, String> {
+        self.get(path)
+            .cloned()
+            .ok_or_else(|| "no such file".to_string())
+    }
+}
+
+/// Source for parsers that can't resolve imports
+#[derive(Debug)]
+pub(crate) struct NoImports;
+
+impl ImportSource for NoImports {
+    fn load(&self, _path: &str) -> Result<String, String> {
+        Err("imports can only be resolved when parsing with an import source".to_string())
+    }
+}
+
+/// Declarations shared by every file parsed with them, as if each file
+/// declared them first, and the extensions the values of constants are
+/// checked with
+#[derive(Debug, Clone)]
+pub struct Prelude {
+    decls: Decls,
+    extensions: Extensions<'static>,
+}
+
+impl Default for Prelude {
+    fn default() -> Self {
+        Self {
+            decls: Decls::default(),
+            extensions: Extensions::all_available(),
+        }
+    }
+}
+
+impl Prelude {
+    /// Parse the imports and declarations in `text`, loading the files it
+    /// imports from `imports`. It can't contain policies.
+    pub fn parse(text: &str, imports: &dyn ImportSource) -> Result<Self, ParseErrors> {
+        Self::parse_with_extensions(text, imports, Extensions::all_available())
+    }
+
+    /// Like [`Self::parse()`], but checking the values of constants, both
+    /// those of the prelude and those of the files parsed with it, with
+    /// `extensions` rather than all the available extensions
+    pub fn parse_with_extensions(
+        text: &str,
+        imports: &dyn ImportSource,
+        extensions: Extensions<'static>,
+    ) -> Result<Self, ParseErrors> {
+        let cst = text_to_cst::parse_policies(text)?;
+        let mut errs = ParseErrors::new();
+        let Some(file) = cst.as_inner() else {
+            return Err(errs);
+        };
+        if !file.policies.is_empty() {
+            errs.push(ToASTError::PolicyInPrelude.into());
+            return Err(errs);
+        }
+        let mut decls = Decls::default();
+        let mut importer = Importer {
+            source: imports,
+            extensions: &extensions,
+            stack: vec![],
+            done: HashSet::new(),
+        };
+        match importer.declare_file(&mut decls, file, &mut errs) {
+            Some(()) if errs.is_empty() => Ok(Self { decls, extensions }),
+            _ => Err(errs),
+        }
+    }
+
+    /// Whether the prelude declares nothing
+    pub fn is_empty(&self) -> bool {
+        self.decls.consts.is_empty() && self.decls.macros.is_empty()
+    }
+}
+
+impl Node<cst::Policies> {
+    /// The policies, with references to declarations (its own and those in
+    /// `prelude`) replaced by their values and macro uses expanded, and the
+    /// imports and declarations themselves removed. Borrows `self` when
+    /// neither it nor `prelude` declares anything.
+    pub fn substitute_decls(
+        &self,
+        prelude: &Prelude,
+        imports: &dyn ImportSource,
+        errs: &mut ParseErrors,
+    ) -> Option<Cow<'_, Self>> {
+        let policies = self.as_inner()?;
+        if policies.imports.is_empty() && policies.decls.is_empty() && prelude.is_empty() {
+            return Some(Cow::Borrowed(self));
+        }
+
+        let mut decls = prelude.decls.clone();
+        let mut importer = Importer {
+            source: imports,
+            extensions: &prelude.extensions,
+            stack: vec![],
+            done: HashSet::new(),
+        };
+        importer.declare_file(&mut decls, policies, errs)?;
+
+        let before = errs.len();
+        let mut substituted = policies.policies.clone();
+        for policy in &mut substituted {
+            decls.policy(policy, errs);
+        }
+        if errs.len() > before {
+            return None;
+        }
+        Some(Cow::Owned(ASTNode::from_source(
+            self.info.clone(),
+            Some(cst::Policies {
+                imports: vec![],
+                decls: vec![],
+                policies: substituted,
+            }),
+        )))
+    }
+}
+
+/// Resolves the imports of a file
+struct Importer<'a> {
+    source: &'a dyn ImportSource,
+    /// the extensions the values of constants are checked with
+    extensions: &'a Extensions<'static>,
+    /// files whose imports are being resolved, outermost first
+    stack: Vec<SmolStr>,
+    /// files whose declarations have been added
+    done: HashSet<SmolStr>,
+}
+
+impl Importer<'_> {
+    /// Add the declarations of `file`, after those of the files it imports
+    fn declare_file(
+        &mut self,
+        decls: &mut Decls,
+        file: &cst::Policies,
+        errs: &mut ParseErrors,
+    ) -> Option<()> {
+        let mut complete = true;
+        for import in &file.imports {
+            complete &= self.import(decls, import, errs).is_some();
+        }
+        for decl in &file.decls {
+            complete &= decls.declare(decl, self.extensions, errs).is_some();
+        }
+        complete.then_some(())
+    }
+
+    fn import(
+        &mut self,
+        decls: &mut Decls,
+        import: &Node<cst::Import>,
+        errs: &mut ParseErrors,
+    ) -> Option<()> {
+        let path = match to_unescaped_string(import.as_inner()?.path.as_valid_string(errs)?) {
+            Ok(path) => path,
+            Err(unescape_errs) => {
+                errs.extend(
+                    unescape_errs
+                        .into_iter()
+                        .map(|e| ToASTError::from(e).into()),
+                );
+                return None;
+            }
+        };
+        if let Some(start) = self.stack.iter().position(|p| p == &path) {
+            let mut cycle: Vec<_> = self.stack.iter().skip(start).cloned().collect();
+            cycle.push(path);
+            errs.push(ToASTError::ImportCycle(cycle).into());
+            return None;
+        }
+        if self.done.contains(&path) {
+            return Some(());
+        }
+
+        let invalid = |reason: String| -> ParseError {
+            ToASTError::InvalidImport {
+                path: path.clone(),
+                reason,
+            }
+            .into()
+        };
+        let text = match self.source.load(&path) {
+            Ok(text) => text,
+            Err(reason) => {
+                errs.push(invalid(reason));
+                return None;
+            }
+        };
+        let file = match text_to_cst::parse_policies(&text) {
+            Ok(file) => file,
+            Err(file_errs) => {
+                errs.push(invalid(file_errs.to_string()));
+                return None;
+            }
+        };
+        let file = file.as_inner()?;
+        if !file.policies.is_empty() {
+            errs.push(invalid(
+                "an imported file can only contain imports and declarations".to_string(),
+            ));
+            return None;
+        }
+
+        self.stack.push(path.clone());
+        let mut file_errs = ParseErrors::new();
+        let declared = self.declare_file(decls, file, &mut file_errs);
+        self.stack.pop();
+        // errors about the files it imports already say which file they're in
+        for e in file_errs.iter() {
+            match e {
+                ParseError::ToAST(
+                    ToASTError::ImportCycle(_) | ToASTError::InvalidImport { .. },
+                ) => errs.push(e.clone()),
+                e => errs.push(invalid(e.to_string())),
+            }
+        }
+        declared?;
+        self.done.insert(path);
+        Some(())
+    }
+}
+
+/// A declared macro
+#[derive(Debug, Clone)]
+struct Macro {
+    params: Vec<SmolStr>,
+    body: Node<cst::Expr>,
+}
+
+impl Macro {
+    /// The body, with `args` in place of the parameters
+    fn expand(&self, args: &[Node<cst::Expr>]) -> Node<cst::Expr> {
+        let params = Decls {
+            consts: self
+                .params
+                .iter()
+                .cloned()
+                .zip(args.iter().cloned())
+                .collect(),
+            macros: HashMap::new(),
+            declaring: None,
+        };
+        let mut body = self.body.clone();
+        // without any macros to expand, this can't fail
+        params.expr(&mut body, &mut ParseErrors::new());
+        body
+    }
+}
+
+/// Names declared by a file and the files it imports
+#[derive(Debug, Clone, Default)]
+struct Decls {
+    consts: HashMap<SmolStr, Node<cst::Expr>>,
+    macros: HashMap<SmolStr, Macro>,
+    /// the macro whose body is being expanded
+    declaring: Option<SmolStr>,
+}
+
+impl Decls {
+    /// Check the declaration `decl`, evaluating a constant's value with
+    /// `extensions`, and add it if it's valid
+    fn declare(
+        &mut self,
+        decl: &Node<cst::Decl>,
+        extensions: &Extensions<'_>,
+        errs: &mut ParseErrors,
+    ) -> Option<()> {
+        match decl.as_inner()? {
+            cst::Decl::Const(c) => self.declare_const(c, extensions, errs),
+            cst::Decl::Macro(m) => self.declare_macro(m, errs),
+        }
+    }
+
+    fn declare_const(
+        &mut self,
+        decl: &cst::Const,
+        extensions: &Extensions<'_>,
+        errs: &mut ParseErrors,
+    ) -> Option<()> {
+        let name = self.new_name(&decl.name, errs)?;
+        let before = errs.len();
+        let mut value = decl.value.clone();
+        self.expr(&mut value, errs);
+        if errs.len() > before {
+            return None;
+        }
+
+        let expr = value.to_expr(errs)?;
+        let checked = BorrowedRestrictedExpr::new(&expr)
+            .map_err(|e| e.to_string())
+            .and_then(|expr| {
+                RestrictedEvaluator::new(extensions)
+                    .interpret(expr)
+                    .map_err(|e| e.to_string())
+            });
+        if let Err(reason) = checked {
+            errs.push(ToASTError::InvalidConstValue { name, reason }.into());
+            return None;
+        }
+
+        self.consts.insert(name, value);
+        Some(())
+    }
+
+    fn declare_macro(&mut self, decl: &cst::Macro, errs: &mut ParseErrors) -> Option<()> {
+        let name = self.new_name(&decl.name, errs)?;
+        let mut params = Vec::with_capacity(decl.params.len());
+        for param in &decl.params {
+            let param = self.new_name(param, errs)?;
+            if params.contains(&param) {
+                errs.push(ToASTError::DuplicateDeclaration(param).into());
+                return None;
+            }
+            params.push(param);
+        }
+        let before = errs.len();
+        let mut body = decl.body.clone();
+        self.declaring = Some(name.clone());
+        self.expr(&mut body, errs);
+        self.declaring = None;
+        if errs.len() > before {
+            return None;
+        }
+
+        self.macros.insert(name, Macro { params, body });
+        Some(())
+    }
+
+    /// The identifier `name`, if it's one that hasn't been declared yet
+    fn new_name(&self, name: &Node<cst::Ident>, errs: &mut ParseErrors) -> Option<SmolStr> {
+        let name = match name.as_inner()? {
+            cst::Ident::Ident(name) => name.clone(),
+            ident => {
+                errs.push(ToASTError::ReservedIdentifier(ident.clone()).into());
+                return None;
+            }
+        };
+        if self.consts.contains_key(&name) || self.macros.contains_key(&name) {
+            errs.push(ToASTError::DuplicateDeclaration(name).into());
+            return None;
+        }
+        Some(name)
+    }
+
+    /// The unqualified name in `primary`, if it's a name
+    fn name(primary: &Node<cst::Primary>) -> Option<&SmolStr> {
+        match primary.as_inner()? {
+            cst::Primary::Name(name) => match name.as_inner()? {
+                cst::Name { path, name } if path.is_empty() => match name.as_inner()? {
+                    cst::Ident::Ident(name) => Some(name),
+                    _ => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn policy(&self, policy: &mut Node<cst::Policy>, errs: &mut ParseErrors) {
+        let Some(policy) = &mut policy.node else {
+            return;
+        };
+        for var in policy.variables.iter_mut().filter_map(|v| v.node.as_mut()) {
+            if let Some((_, e)) = &mut var.ineq {
+                self.expr(e, errs);
+            }
+            if let Some(e) = &mut var.when {
+                self.expr(e, errs);
+            }
+        }
+        for cond in policy.conds.iter_mut().filter_map(|c| c.node.as_mut()) {
+            if let Some(e) = &mut cond.expr {
+                self.expr(e, errs);
+            }
+        }
+    }
+
+    fn expr(&self, expr: &mut Node<cst::Expr>, errs: &mut ParseErrors) {
+        let Some(expr) = &mut expr.node else {
+            return;
+        };
+        match expr.expr.as_mut() {
+            cst::ExprData::Or(or) => self.or(or, errs),
+            cst::ExprData::If(cond, then, otherwise) => {
+                self.expr(cond, errs);
+                self.expr(then, errs);
+                self.expr(otherwise, errs);
+            }
+        }
+    }
+
+    fn or(&self, or: &mut Node<cst::Or>, errs: &mut ParseErrors) {
+        if let Some(or) = &mut or.node {
+            self.and(&mut or.initial, errs);
+            for and in &mut or.extended {
+                self.and(and, errs);
+            }
+        }
+    }
+
+    fn and(&self, and: &mut Node<cst::And>, errs: &mut ParseErrors) {
+        if let Some(and) = &mut and.node {
+            self.relation(&mut and.initial, errs);
+            for relation in &mut and.extended {
+                self.relation(relation, errs);
+            }
+        }
+    }
+
+    fn relation(&self, relation: &mut Node<cst::Relation>, errs: &mut ParseErrors) {
+        match &mut relation.node {
+            Some(cst::Relation::Common { initial, extended }) => {
+                self.add(initial, errs);
+                for (_, add) in extended {
+                    self.add(add, errs);
+                }
+            }
+            // the right hand side of `has` names an attribute
+            Some(cst::Relation::Has { target, .. }) | Some(cst::Relation::Is { target, .. }) => {
+                self.add(target, errs)
+            }
+            Some(
+                cst::Relation::Like { target, pattern } | cst::Relation::Glob { target, pattern },
+            ) => {
+                self.add(target, errs);
+                self.add(pattern, errs);
+            }
+            None => {}
+        }
+    }
+
+    fn add(&self, add: &mut Node<cst::Add>, errs: &mut ParseErrors) {
+        if let Some(add) = &mut add.node {
+            self.mult(&mut add.initial, errs);
+            for (_, mult) in &mut add.extended {
+                self.mult(mult, errs);
+            }
+        }
+    }
+
+    fn mult(&self, mult: &mut Node<cst::Mult>, errs: &mut ParseErrors) {
+        if let Some(mult) = &mut mult.node {
+            self.unary(&mut mult.initial, errs);
+            for (_, unary) in &mut mult.extended {
+                self.unary(unary, errs);
+            }
+        }
+    }
+
+    fn unary(&self, unary: &mut Node<cst::Unary>, errs: &mut ParseErrors) {
+        if let Some(unary) = &mut unary.node {
+            self.member(&mut unary.item, errs);
+        }
+    }
+
+    fn member(&self, member: &mut Node<cst::Member>, errs: &mut ParseErrors) {
+        let Some(member) = &mut member.node else {
+            return;
+        };
+        for access in member.access.iter_mut().filter_map(|a| a.node.as_mut()) {
+            match access {
+                cst::MemAccess::Field(_) => {}
+                cst::MemAccess::Call(args) => {
+                    for arg in args {
+                        self.expr(arg, errs);
+                    }
+                }
+                cst::MemAccess::Index(e) => self.expr(e, errs),
+            }
+        }
+
+        // in `f(..)`, `f` names a function or macro rather than a value
+        let expansion = match member.access.first().and_then(|a| a.as_inner()) {
+            Some(cst::MemAccess::Call(args)) => {
+                let name = Self::name(&member.item);
+                if let Some(name) = name.filter(|n| self.declaring.as_ref() == Some(*n)) {
+                    errs.push(ToASTError::RecursiveMacro(name.clone()).into());
+                    return;
+                }
+                let Some((name, m)) = name.and_then(|n| self.macros.get_key_value(n)) else {
+                    return;
+                };
+                if m.params.len() != args.len() {
+                    errs.push(
+                        ToASTError::MacroArity {
+                            name: name.clone(),
+                            expected: m.params.len(),
+                            got: args.len(),
+                        }
+                        .into(),
+                    );
+                    return;
+                }
+                m.expand(args)
+            }
+            _ => {
+                self.primary(&mut member.item, errs);
+                return;
+            }
+        };
+        member.item = ASTNode::from_source(
+            member.item.info.clone(),
+            Some(cst::Primary::Expr(expansion)),
+        );
+        member.access.remove(0);
+    }
+
+    fn primary(&self, primary: &mut Node<cst::Primary>, errs: &mut ParseErrors) {
+        if let Some(value) = Self::name(primary).and_then(|n| self.consts.get(n)) {
+            primary.node = Some(cst::Primary::Expr(value.clone()));
+            return;
+        }
+        match &mut primary.node {
+            Some(cst::Primary::Expr(e)) => self.expr(e, errs),
+            Some(cst::Primary::EList(es)) => {
+                for e in es {
+                    self.expr(e, errs);
+                }
+            }
+            // record keys name attributes, so only the values are replaced
+            Some(cst::Primary::RInits(inits)) => {
+                for init in inits.iter_mut().filter_map(|i| i.node.as_mut()) {
+                    self.expr(&mut init.1, errs);
+                }
+            }
+            Some(cst::Primary::Name(_))
+            | Some(cst::Primary::Literal(_))
+            | Some(cst::Primary::Ref(_))
+            | Some(cst::Primary::Slot(_))
+            | None => {}
+        }
+    }
+}
@@ -246,6 +246,56 @@ pub enum ToASTError {
     /// Returned when the contents of an indexing expression is not a string literal
     #[error("the contents of an index expression must be a string literal")]
     NonStringIndex,
+    /// Returned when the attribute passed to `getOr` is not a string literal
+    #[error("the second argument of `getOr` must be a string literal naming an attribute")]
+    NonStringGetOrAttr,
+    /// Returned when `is` is combined with `==` in a policy scope constraint
+    #[error(
+        "`is` cannot be combined with `==` in the policy scope; use `is` with `in`, or `==` alone"
+    )]
+    IsWithEqConstraint,
+    /// Returned when the `action` scope constraint uses `is` or `when`
+    #[error("`{0}` is not supported in the `action` scope constraint")]
+    UnsupportedActionScope(&'static str),
+    /// Returned when a policy file, or the files it imports, declare the same
+    /// name twice
+    #[error("`{0}` is declared more than once")]
+    DuplicateDeclaration(SmolStr),
+    /// Returned when the value of a constant isn't a valid literal value
+    #[error("invalid value for constant `{name}`: {reason}")]
+    InvalidConstValue {
+        /// Name of the constant
+        name: SmolStr,
+        /// Why the value is invalid
+        reason: String,
+    },
+    /// Returned when a macro is used with the wrong number of arguments
+    #[error("macro `{name}` takes {expected} argument{}, but got {got}", if .expected == &1 { "" } else { "s" })]
+    MacroArity {
+        /// Name of the macro
+        name: SmolStr,
+        /// The number of parameters the macro declares
+        expected: usize,
+        /// The number of arguments present in source
+        got: usize,
+    },
+    /// Returned when a macro's body uses the macro itself
+    #[error("macro `{0}` can't use itself")]
+    RecursiveMacro(SmolStr),
+    /// Returned when a file imports itself, directly or through other files
+    #[error("import cycle: {}", .0.join(" -> "))]
+    ImportCycle(Vec<SmolStr>),
+    /// Returned when an imported file can't be loaded or has errors
+    #[error("invalid import `{path}`: {reason}")]
+    InvalidImport {
+        /// Path of the imported file
+        path: SmolStr,
+        /// Why the import failed
+        reason: String,
+    },
+    /// Returned when a prelude contains policies
+    #[error("a prelude can only contain imports and declarations")]
+    PolicyInPrelude,
     /// Returned when a user attempts to use type-constraint syntax. This is not currently supported
     #[error("type constraints are not currently supported")]
     TypeConstraints,
@@ -458,8 +508,12 @@ lazy_static! {
         ("IN", "`in`"),
         ("HAS", "`has`"),
         ("LIKE", "`like`"),
+        ("IS", "`is`"),
         ("THEN", "`then`"),
         ("ELSE", "`else`"),
+        ("CONST", "`const`"),
+        ("MACRO", "`macro`"),
+        ("IMPORT", "`import`"),
         ("PRINCIPAL", "`principal`"),
         ("ACTION", "`action`"),
         ("RESOURCE", "`resource`"),
@@ -37,26 +37,59 @@ impl<'a, T: fmt::Display> fmt::Display for View<'a, T> {
 
 impl fmt::Display for Policies {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let decls = self
+            .imports
+            .iter()
+            .map(|i| View(i).to_string())
+            .chain(self.decls.iter().map(|d| View(d).to_string()));
         if f.alternate() {
-            let mut ps = self.0.iter();
-            if let Some(p) = ps.next() {
-                write!(f, "{:#}", View(p))?;
+            let policies = self.policies.iter().map(|p| format!("{:#}", View(p)));
+            let mut items = decls.chain(policies);
+            if let Some(item) = items.next() {
+                write!(f, "{item}")?;
             }
-            for p in ps {
-                write!(f, "\n\n{:#}", View(p))?;
+            for item in items {
+                write!(f, "\n\n{item}")?;
             }
         } else {
-            let mut ps = self.0.iter();
-            if let Some(p) = ps.next() {
-                write!(f, "{}", View(p))?;
+            let policies = self.policies.iter().map(|p| View(p).to_string());
+            let mut items = decls.chain(policies);
+            if let Some(item) = items.next() {
+                write!(f, "{item}")?;
             }
-            for p in ps {
-                write!(f, " {}", View(p))?;
+            for item in items {
+                write!(f, " {item}")?;
             }
         }
         Ok(())
     }
 }
+impl fmt::Display for Import {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "import {};", View(&self.path))
+    }
+}
+impl fmt::Display for Decl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Decl::Const(c) => write!(f, "const {} = {};", View(&c.name), View(&c.value)),
+            Decl::Macro(m) => write!(f, "macro {m}"),
+        }
+    }
+}
+impl fmt::Display for Macro {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}(", View(&self.name))?;
+        let mut params = self.params.iter();
+        if let Some(p) = params.next() {
+            write!(f, "{}", View(p))?;
+        }
+        for p in params {
+            write!(f, ", {}", View(p))?;
+        }
+        write!(f, ") = {};", View(&self.body))
+    }
+}
 impl fmt::Display for Policy {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         // start with annotations
@@ -126,9 +159,15 @@ impl fmt::Display for VariableDef {
         if let Some(name) = &self.name {
             write!(f, ": {}", View(name))?;
         }
+        if let Some(entity_type) = &self.entity_type {
+            write!(f, " is {}", View(entity_type))?;
+        }
         if let Some((op, expr)) = &self.ineq {
             write!(f, " {} {}", op, View(expr))?;
         }
+        if let Some(when) = &self.when {
+            write!(f, " when {}", View(when))?;
+        }
         Ok(())
     }
 }
@@ -190,6 +229,15 @@ impl fmt::Display for Relation {
             Relation::Like { target, pattern } => {
                 write!(f, "{} like {}", View(target), View(pattern))?;
             }
+            Relation::Glob { target, pattern } => {
+                write!(f, "{} glob {}", View(target), View(pattern))?;
+            }
+            Relation::Is {
+                target,
+                entity_type,
+            } => {
+                write!(f, "{} is {}", View(target), View(entity_type))?;
+            }
         }
         Ok(())
     }
@@ -390,6 +438,7 @@ impl fmt::Display for Ident {
             Ident::In => write!(f, "in"),
             Ident::Has => write!(f, "has"),
             Ident::Like => write!(f, "like"),
+            Ident::Is => write!(f, "is"),
             Ident::If => write!(f, "if"),
             Ident::Then => write!(f, "then"),
             Ident::Else => write!(f, "else"),
@@ -821,7 +821,7 @@ mod tests {
         .expect("parse fail")
         .node
         .expect("no data")
-        .0
+        .policies
         .iter()
         .all(|p| p.node.is_some()));
     }
@@ -841,7 +841,7 @@ mod tests {
         .expect("parse fail")
         .node
         .expect("no data")
-        .0
+        .policies
         .iter()
         .all(|p| p.node.is_some()));
     }
@@ -864,7 +864,7 @@ mod tests {
         .expect("parse fail")
         .node
         .expect("no data");
-        assert!(result.0.iter().all(|p| p.node.is_some()));
+        assert!(result.policies.iter().all(|p| p.node.is_some()));
     }
 
     #[test]
@@ -884,7 +884,7 @@ mod tests {
         .expect("parse fail")
         .node
         .expect("no data")
-        .0
+        .policies
         .into_iter()
         .all(|p| p.node.is_some()));
     }
@@ -906,7 +906,7 @@ mod tests {
             .node
             .expect("no data");
         let success = policies
-            .0
+            .policies
             .into_iter()
             .filter_map(|p| p.node)
             .collect::<Vec<_>>();
@@ -927,7 +927,7 @@ mod tests {
         .node
         .expect("no data");
         let success = policies
-            .0
+            .policies
             .into_iter()
             .filter_map(|p| p.node)
             .collect::<Vec<_>>();
@@ -14,7 +14,7 @@
  * limitations under the License.
  */
 
-use crate::ast::PatternElem;
+use crate::ast::{CharClass, PatternElem};
 use rustc_lexer::unescape::{unescape_str, EscapeError};
 use smol_str::SmolStr;
 use std::ops::Range;
@@ -25,11 +25,7 @@ pub(crate) fn to_unescaped_string(s: &str) -> Result<SmolStr, Vec<UnescapeError>
     let mut errs = Vec::new();
     let mut callback = |range, r| match r {
         Ok(c) => unescaped_str.push(c),
-        Err(err) => errs.push(UnescapeError {
-            err,
-            input: s.to_owned(),
-            range,
-        }),
+        Err(err) => errs.push(UnescapeError::escape(err, s, range)),
     };
     unescape_str(s, &mut callback);
     if errs.is_empty() {
@@ -39,37 +35,133 @@ pub(crate) fn to_unescaped_string(s: &str) -> Result<SmolStr, Vec<UnescapeError>
     }
 }
 
+/// A character of a pattern literal after unescaping
+struct PatternChar {
+    c: char,
+    /// Was the character written with a backslash (e.g., `\*`), making it a
+    /// literal character rather than pattern syntax
+    escaped: bool,
+    /// Range of the input string the character was unescaped from
+    range: Range<usize>,
+}
+
+impl PatternChar {
+    fn is_syntax(&self, c: char) -> bool {
+        !self.escaped && self.c == c
+    }
+}
+
+/// Unescape the pattern of a `like` expression, in which `*` is the only
+/// pattern syntax and `[` is a literal character
 pub(crate) fn to_pattern(s: &str) -> Result<Vec<PatternElem>, Vec<UnescapeError>> {
-    let mut unescaped_str = Vec::new();
+    pattern(s, false)
+}
+
+/// Unescape the pattern of a `glob` expression, in which `[` also starts a
+/// character class
+pub(crate) fn to_glob_pattern(s: &str) -> Result<Vec<PatternElem>, Vec<UnescapeError>> {
+    pattern(s, true)
+}
+
+fn pattern(s: &str, classes: bool) -> Result<Vec<PatternElem>, Vec<UnescapeError>> {
+    let mut chars = Vec::new();
     let mut errs = Vec::new();
     let bytes = s.as_bytes(); // to inspect string element in O(1) time
     let mut callback = |range: Range<usize>, r| match r {
-        Ok(c) => unescaped_str.push(if c == '*' { PatternElem::Wildcard }else { PatternElem::Char(c) }),
+        Ok(c) => chars.push(PatternChar { c, escaped: false, range }),
         // PANIC SAFETY By invariant, all passed in ranges must be in range
         #[allow(clippy::indexing_slicing)]
         Err(EscapeError::InvalidEscape)
         // note that the range argument refers to the *byte* offset into the string.
-        // so we can compare the byte slice against the bytes of the ``star'' escape sequence.
-        if &bytes[range.start..range.end] == r"\*".as_bytes()
+        // so we can compare the byte slice against the bytes of the pattern
+        // escape sequences `\*`, `\[`, `\]`, `\-`, and `\^`.
+        if matches!(&bytes[range.start..range.end], br"\*" | br"\[" | br"\]" | br"\-" | br"\^")
             =>
         {
-            unescaped_str.push(PatternElem::Char('*'))
+            let c = char::from(bytes[range.end - 1]);
+            chars.push(PatternChar { c, escaped: true, range })
         }
-        Err(err) => errs.push(UnescapeError { err, input: s.to_owned(), range }),
+        Err(err) => errs.push(UnescapeError::escape(err, s, range)),
     };
     unescape_str(s, &mut callback);
+
+    let mut elems = Vec::new();
+    let mut i = 0;
+    while let Some(pc) = chars.get(i) {
+        if pc.is_syntax('*') {
+            elems.push(PatternElem::Wildcard);
+            i += 1;
+        } else if classes && pc.is_syntax('[') {
+            match to_char_class(&chars, i + 1, s.len()) {
+                Ok((class, next)) => {
+                    elems.push(PatternElem::Class(class));
+                    i = next;
+                }
+                Err((reason, end)) => {
+                    errs.push(UnescapeError::malformed_class(
+                        reason,
+                        s,
+                        pc.range.start..end,
+                    ));
+                    break;
+                }
+            }
+        } else {
+            elems.push(PatternElem::Char(pc.c));
+            i += 1;
+        }
+    }
     if errs.is_empty() {
-        Ok(unescaped_str)
+        Ok(elems)
     } else {
         Err(errs)
     }
 }
 
+/// Parse the character class starting at `chars[i]`, just after its opening
+/// `[`. Returns the class and the index just past its closing `]`, or the
+/// reason the class is malformed and the byte offset where it ends.
+fn to_char_class(
+    chars: &[PatternChar],
+    mut i: usize,
+    input_len: usize,
+) -> Result<(CharClass, usize), (&'static str, usize)> {
+    let negated = matches!(chars.get(i), Some(pc) if pc.is_syntax('^'));
+    if negated {
+        i += 1;
+    }
+    let mut ranges = Vec::new();
+    loop {
+        let Some(lo) = chars.get(i) else {
+            return Err(("missing closing `]`", input_len));
+        };
+        if lo.is_syntax(']') {
+            if ranges.is_empty() {
+                return Err(("it is empty", lo.range.end));
+            }
+            return Ok((CharClass::new(ranges, negated), i + 1));
+        }
+        match (chars.get(i + 1), chars.get(i + 2)) {
+            (Some(dash), Some(hi)) if dash.is_syntax('-') && !hi.is_syntax(']') => {
+                if lo.c > hi.c {
+                    return Err(("range is reversed", hi.range.end));
+                }
+                ranges.push((lo.c, hi.c));
+                i += 3;
+            }
+            _ => {
+                ranges.push((lo.c, lo.c));
+                i += 1;
+            }
+        }
+    }
+}
+
 /// Errors generated when processing escapes
 #[derive(Debug, Error, PartialEq, Eq)]
 pub struct UnescapeError {
-    /// underlying EscapeError
-    err: EscapeError,
+    /// underlying error
+    err: UnescapeErrorKind,
     /// copy of the input string which had the error
     input: String,
     /// Range of the input string where the error occurred
@@ -77,10 +169,42 @@ pub struct UnescapeError {
     range: Range<usize>,
 }
 
+/// The kinds of errors generated when processing escapes
+#[derive(Debug, PartialEq, Eq)]
+enum UnescapeErrorKind {
+    /// An invalid escape sequence
+    Escape(EscapeError),
+    /// A malformed character class in a pattern literal
+    MalformedClass(&'static str),
+}
+
+impl UnescapeError {
+    fn escape(err: EscapeError, input: &str, range: Range<usize>) -> Self {
+        Self {
+            err: UnescapeErrorKind::Escape(err),
+            input: input.to_owned(),
+            range,
+        }
+    }
+
+    fn malformed_class(reason: &'static str, input: &str, range: Range<usize>) -> Self {
+        Self {
+            err: UnescapeErrorKind::MalformedClass(reason),
+            input: input.to_owned(),
+            range,
+        }
+    }
+}
+
 impl Clone for UnescapeError {
     fn clone(&self) -> Self {
         Self {
-            err: clone_escape_error(&self.err),
+            err: match &self.err {
+                UnescapeErrorKind::Escape(e) => UnescapeErrorKind::Escape(clone_escape_error(e)),
+                UnescapeErrorKind::MalformedClass(reason) => {
+                    UnescapeErrorKind::MalformedClass(reason)
+                }
+            },
             input: self.input.clone(),
             range: self.range.clone(),
         }
@@ -118,14 +242,20 @@ impl std::fmt::Display for UnescapeError {
     // PANIC SAFETY By invariant, the range will always be within the bounds of `input`
     #[allow(clippy::indexing_slicing)]
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}: `{}`", self.err, &self.input[self.range.clone()])
+        let input = &self.input[self.range.clone()];
+        match &self.err {
+            UnescapeErrorKind::Escape(err) => write!(f, "{err:?}: `{input}`"),
+            UnescapeErrorKind::MalformedClass(reason) => {
+                write!(f, "malformed character class, {reason}: `{input}`")
+            }
+        }
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::to_unescaped_string;
-    use crate::ast;
+    use super::{to_glob_pattern, to_pattern, to_unescaped_string};
+    use crate::ast::{self, CharClass, Pattern, PatternElem};
     use crate::parser::{
         err::{ParseError, ParseErrors},
         text_to_cst,
@@ -162,6 +292,103 @@ mod test {
         assert_eq!(errs.len(), 2);
     }
 
+    #[test]
+    fn test_pattern_class() {
+        let pattern = to_glob_pattern(r"0x[0-9a-f][^0]*").expect("valid pattern");
+        assert_eq!(
+            pattern,
+            vec![
+                PatternElem::Char('0'),
+                PatternElem::Char('x'),
+                PatternElem::Class(CharClass::new([('0', '9'), ('a', 'f')], false)),
+                PatternElem::Class(CharClass::new([('0', '0')], true)),
+                PatternElem::Wildcard,
+            ]
+        );
+
+        // `-` at either end of a class, and `*` inside a class, are literal
+        assert_eq!(
+            to_glob_pattern(r"[-a*-]").expect("valid pattern"),
+            vec![PatternElem::Class(CharClass::new(
+                [('-', '-'), ('a', 'a'), ('*', '*'), ('-', '-')],
+                false
+            ))]
+        );
+
+        // escaped class syntax is literal
+        assert_eq!(
+            to_glob_pattern(r"\[a\][\]\-\^]").expect("valid pattern"),
+            vec![
+                PatternElem::Char('['),
+                PatternElem::Char('a'),
+                PatternElem::Char(']'),
+                PatternElem::Class(CharClass::new([(']', ']'), ('-', '-'), ('^', '^')], false)),
+            ]
+        );
+
+        // a bare `]` outside of a class is literal
+        assert_eq!(
+            to_glob_pattern("a]").expect("valid pattern"),
+            vec![PatternElem::Char('a'), PatternElem::Char(']')]
+        );
+
+        // display round trips
+        for s in [r"0x[0-9a-f][^0]*", r"\[a][\]\-\^]", r"[a-z]\*[*]"] {
+            let pattern = Pattern::new(to_glob_pattern(s).expect("valid pattern"));
+            assert_eq!(
+                to_glob_pattern(&pattern.to_string()).expect("valid pattern"),
+                pattern.get_elems()
+            );
+        }
+    }
+
+    #[test]
+    fn test_like_pattern_brackets() {
+        // `like` patterns have no classes, so `[` and `]` are literal, escaped
+        // or not
+        let brackets = vec![
+            PatternElem::Char('['),
+            PatternElem::Char('a'),
+            PatternElem::Char('-'),
+            PatternElem::Char('f'),
+            PatternElem::Char(']'),
+            PatternElem::Wildcard,
+        ];
+        assert_eq!(to_pattern("[a-f]*").expect("valid pattern"), brackets);
+        assert_eq!(to_pattern(r"\[a-f\]*").expect("valid pattern"), brackets);
+        assert_eq!(
+            to_pattern("[").expect("valid pattern"),
+            vec![PatternElem::Char('[')]
+        );
+
+        // and display round trips
+        let pattern = Pattern::new(brackets);
+        assert_eq!(
+            to_pattern(&pattern.to_string()).expect("valid pattern"),
+            pattern.get_elems()
+        );
+    }
+
+    #[test]
+    fn test_pattern_class_malformed() {
+        for (s, msg) in [
+            (
+                "0x[0-9",
+                "malformed character class, missing closing `]`: `[0-9`",
+            ),
+            ("0x[]*", "malformed character class, it is empty: `[]`"),
+            ("0x[^]", "malformed character class, it is empty: `[^]`"),
+            (
+                "[a-f9-0]",
+                "malformed character class, range is reversed: `[a-f9-0`",
+            ),
+        ] {
+            let errs = to_glob_pattern(s).expect_err("should be a malformed class");
+            assert_eq!(errs.len(), 1);
+            assert_eq!(errs[0].to_string(), msg);
+        }
+    }
+
     #[test]
     fn test_pattern_escape() {
         // valid ASCII escapes
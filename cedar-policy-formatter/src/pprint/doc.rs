@@ -41,6 +41,56 @@ impl Doc for ASTNode<Option<VariableDef>> {
         let end_comment = get_comment_at_end(self.info.0.end, &mut context.tokens)?;
         let var_doc = vd.variable.as_inner()?.to_doc(context)?;
 
+        if vd.entity_type.is_some() || vd.when.is_some() {
+            // `is` and `when` sugar: lay out each keyword and its operand in turn
+            let mut doc = var_doc.append(get_trailing_comment_doc_from_str(
+                &start_comment.trailing_comment,
+            ));
+            let mut prev_end = vd.variable.info.0.end;
+            if let Some(entity_type) = &vd.entity_type {
+                doc = doc
+                    .append(RcDoc::line())
+                    .append(add_comment(
+                        RcDoc::text("is"),
+                        get_comment_after_end(prev_end, &mut context.tokens)?,
+                        RcDoc::nil(),
+                    ))
+                    .append(RcDoc::line())
+                    .append(entity_type.to_doc(context)?);
+                prev_end = entity_type.info.0.end;
+            }
+            if let Some((op, rhs)) = &vd.ineq {
+                doc = doc
+                    .append(RcDoc::line())
+                    .append(add_comment(
+                        RcDoc::as_string(op),
+                        get_comment_after_end(prev_end, &mut context.tokens)?,
+                        RcDoc::nil(),
+                    ))
+                    .append(RcDoc::line())
+                    .append(rhs.to_doc(context)?);
+                prev_end = rhs.info.0.end;
+            }
+            if let Some(when) = &vd.when {
+                doc = doc
+                    .append(RcDoc::line())
+                    .append(add_comment(
+                        RcDoc::text("when"),
+                        get_comment_after_end(prev_end, &mut context.tokens)?,
+                        RcDoc::nil(),
+                    ))
+                    .append(RcDoc::line())
+                    .append(when.to_doc(context)?.nest(context.config.indent_width));
+            }
+            return Some(
+                get_leading_comment_doc_from_str(&start_comment.leading_comment)
+                    .append(doc.group())
+                    .append(get_trailing_comment_doc_from_str(
+                        &end_comment.trailing_comment,
+                    )),
+            );
+        }
+
         Some(match &vd.ineq {
             Some((op, rhs)) => get_leading_comment_doc_from_str(&start_comment.leading_comment)
                 .append(
@@ -247,12 +297,16 @@ impl Doc for ASTNode<Option<Relation>> {
                     .append(field.to_doc(context)?.nest(context.config.indent_width))
                     .group(),
             ),
-            Relation::Like { target, pattern } => Some(
+            Relation::Like { target, pattern } | Relation::Glob { target, pattern } => Some(
                 target
                     .to_doc(context)?
                     .append(RcDoc::line())
                     .append(add_comment(
-                        RcDoc::text("like"),
+                        RcDoc::text(if matches!(e, Relation::Glob { .. }) {
+                            "glob"
+                        } else {
+                            "like"
+                        }),
                         get_comment_after_end(target.info.0.end, &mut context.tokens)?,
                         RcDoc::nil(),
                     ))
@@ -260,6 +314,26 @@ impl Doc for ASTNode<Option<Relation>> {
                     .append(pattern.to_doc(context)?.nest(context.config.indent_width))
                     .group(),
             ),
+            Relation::Is {
+                target,
+                entity_type,
+            } => Some(
+                target
+                    .to_doc(context)?
+                    .append(RcDoc::line())
+                    .append(add_comment(
+                        RcDoc::text("is"),
+                        get_comment_after_end(target.info.0.end, &mut context.tokens)?,
+                        RcDoc::nil(),
+                    ))
+                    .append(RcDoc::line())
+                    .append(
+                        entity_type
+                            .to_doc(context)?
+                            .nest(context.config.indent_width),
+                    )
+                    .group(),
+            ),
         }
     }
 }
@@ -697,6 +771,92 @@ impl Doc for ASTNode<Option<Ident>> {
     }
 }
 
+impl Doc for ASTNode<Option<Import>> {
+    fn to_doc(&self, context: &mut Context<'_>) -> Option<RcDoc<'_>> {
+        let import = self.as_inner()?;
+        let import_doc = add_comment(
+            RcDoc::text("import"),
+            get_comment_at_start(self.info.0.start, &mut context.tokens)?,
+            RcDoc::space(),
+        );
+        let path_doc = import.path.to_doc(context)?;
+        let semi_doc = add_comment(
+            RcDoc::text(";"),
+            get_comment_at_end(self.info.0.end, &mut context.tokens)?,
+            RcDoc::nil(),
+        );
+        Some(import_doc.append(path_doc).append(semi_doc))
+    }
+}
+
+impl Doc for ASTNode<Option<Decl>> {
+    fn to_doc(&self, context: &mut Context<'_>) -> Option<RcDoc<'_>> {
+        let (keyword, name, params, value) = match self.as_inner()? {
+            Decl::Const(c) => ("const", &c.name, None, &c.value),
+            Decl::Macro(m) => ("macro", &m.name, Some(&m.params), &m.body),
+        };
+        let keyword_doc = add_comment(
+            RcDoc::text(keyword),
+            get_comment_at_start(self.info.0.start, &mut context.tokens)?,
+            RcDoc::space(),
+        );
+        let mut head_doc = keyword_doc.append(name.to_doc(context)?);
+        let mut head_end = name.info.0.end;
+        if let Some(params) = params {
+            // the end of the token starting at or after `pos`
+            let token_end = |pos: usize, context: &Context<'_>| {
+                context
+                    .tokens
+                    .iter()
+                    .find(|t| t.span.start >= pos)
+                    .map(|t| t.span.end)
+            };
+            let lp_end = token_end(head_end, context)?;
+            head_doc = head_doc.append(add_comment(
+                RcDoc::text("("),
+                get_comment_after_end(head_end, &mut context.tokens)?,
+                RcDoc::nil(),
+            ));
+            let mut params_end = lp_end;
+            for (i, param) in params.iter().enumerate() {
+                if i > 0 {
+                    head_doc = head_doc.append(add_comment(
+                        RcDoc::text(","),
+                        get_comment_after_end(params_end, &mut context.tokens)?,
+                        RcDoc::space(),
+                    ));
+                }
+                head_doc = head_doc.append(param.to_doc(context)?);
+                params_end = param.info.0.end;
+            }
+            head_end = token_end(params_end, context)?;
+            head_doc = head_doc.append(add_comment(
+                RcDoc::text(")"),
+                get_comment_after_end(params_end, &mut context.tokens)?,
+                RcDoc::nil(),
+            ));
+        }
+        let eq_doc = add_comment(
+            RcDoc::text("="),
+            get_comment_after_end(head_end, &mut context.tokens)?,
+            RcDoc::line(),
+        );
+        let value_doc = value.to_doc(context)?;
+        let semi_doc = add_comment(
+            RcDoc::text(";"),
+            get_comment_at_end(self.info.0.end, &mut context.tokens)?,
+            RcDoc::nil(),
+        );
+        Some(
+            head_doc
+                .append(RcDoc::space())
+                .append(eq_doc.append(value_doc).nest(context.config.indent_width))
+                .group()
+                .append(semi_doc),
+        )
+    }
+}
+
 impl Doc for ASTNode<Option<Policy>> {
     fn to_doc(&self, context: &mut Context<'_>) -> Option<RcDoc<'_>> {
         let policy = self.as_inner()?;
@@ -714,7 +874,7 @@ impl Doc for ASTNode<Option<Policy>> {
         let resource_doc = vars.get(2)?.to_doc(context)?;
         let vars_doc = if vars.get(0..3)?.iter().all(|v| {
             if let Some(v) = v.as_inner() {
-                v.ineq.is_none()
+                v.ineq.is_none() && v.entity_type.is_none() && v.when.is_none()
             } else {
                 false
             }
@@ -77,11 +77,19 @@ fn soundness_check(ps: &str, ast: &PolicySet) -> Result<()> {
 
 pub fn policies_str_to_pretty(ps: &str, config: &Config) -> Result<String> {
     let cst = parse_policies(ps).wrap_err("cannot parse input policies to CSTs")?;
-    let mut errs = ParseErrors::new();
-    let ast = cst
-        .to_policyset(&mut errs)
-        .ok_or(errs)
-        .wrap_err("cannot parse input policies to ASTs")?;
+    // imported macros aren't available here, so a file with imports can only
+    // be checked as far as the CST
+    let has_imports = cst.as_inner().is_some_and(|ps| !ps.imports.is_empty());
+    let ast = if has_imports {
+        None
+    } else {
+        let mut errs = ParseErrors::new();
+        Some(
+            cst.to_policyset(&mut errs)
+                .ok_or(errs)
+                .wrap_err("cannot parse input policies to ASTs")?,
+        )
+    };
     let tokens = get_token_stream(ps).ok_or(miette!("cannot get token stream"))?;
     let end_comment_str = ps
         .get(
@@ -93,14 +101,21 @@ pub fn policies_str_to_pretty(ps: &str, config: &Config) -> Result<String> {
         )
         .ok_or(miette!("cannot get ending comment string"))?;
     let mut context = config::Context { config, tokens };
-    let mut formatted_policies = cst
+    let policies = cst
         .as_inner()
-        .ok_or(miette!("fail to get input policy CST"))?
-        .0
+        .ok_or(miette!("fail to get input policy CST"))?;
+    let mut formatted = policies
+        .imports
         .iter()
-        .map(|p| Ok(remove_empty_lines(tree_to_pretty(p, &mut context)?.trim())))
-        .collect::<Result<Vec<String>>>()?
-        .join("\n\n");
+        .map(|i| Ok(remove_empty_lines(tree_to_pretty(i, &mut context)?.trim())))
+        .collect::<Result<Vec<String>>>()?;
+    for d in &policies.decls {
+        formatted.push(remove_empty_lines(tree_to_pretty(d, &mut context)?.trim()));
+    }
+    for p in &policies.policies {
+        formatted.push(remove_empty_lines(tree_to_pretty(p, &mut context)?.trim()));
+    }
+    let mut formatted_policies = formatted.join("\n\n");
     // handle comment at the end of a policyset
     let (trailing_comment, end_comment) = match end_comment_str.split_once('\n') {
         Some((f, r)) => (get_comment(f), get_comment(r)),
@@ -123,7 +138,12 @@ pub fn policies_str_to_pretty(ps: &str, config: &Config) -> Result<String> {
         }
     };
     // add soundness check to make sure formatting doesn't alter policy ASTs
-    soundness_check(&formatted_policies, &ast)?;
+    match ast {
+        Some(ast) => soundness_check(&formatted_policies, &ast)?,
+        None => {
+            parse_policies(&formatted_policies).wrap_err("formatter produces invalid policies")?;
+        }
+    }
     Ok(formatted_policies)
 }
 
@@ -166,6 +186,47 @@ mod tests {
         );
     }
 
+    #[test]
+    fn consts() {
+        let policy = r#"const   MAX_TRANSFER=u256("5000000000000000000"); // one transfer
+const LIMITS = {transfer: MAX_TRANSFER};
+forbid (principal, action, resource) when { context.amount.u256GreaterThan(MAX_TRANSFER) };"#;
+        let expected = r#"const MAX_TRANSFER = u256("5000000000000000000"); // one transfer
+
+const LIMITS = {transfer:MAX_TRANSFER};
+
+forbid (principal, action, resource)
+when { context.amount.u256GreaterThan(MAX_TRANSFER) };"#;
+        let config = Config {
+            line_width: 80,
+            indent_width: 2,
+        };
+        assert_eq!(policies_str_to_pretty(policy, &config).unwrap(), expected);
+    }
+
+    #[test]
+    fn imports_and_macros() {
+        let policy = r#"import   "lib/tokens.cedar" ;
+macro isStablecoin( token,amount ) = token in Group::"stablecoins" && amount > 0; // stables
+macro isLarge(amount)=amount>1000;
+permit (principal, action, resource) when { isStablecoin(resource, context.amount) };"#;
+        let expected = r#"import "lib/tokens.cedar";
+
+macro isStablecoin(token, amount) =
+  token in Group::"stablecoins" &&
+  amount > 0; // stables
+
+macro isLarge(amount) = amount > 1000;
+
+permit (principal, action, resource)
+when { isStablecoin(resource, context.amount) };"#;
+        let config = Config {
+            line_width: 80,
+            indent_width: 2,
+        };
+        assert_eq!(policies_str_to_pretty(policy, &config).unwrap(), expected);
+    }
+
     #[test]
     fn test_format_files() {
         use std::fs::read_to_string;
@@ -104,6 +104,9 @@ pub enum Token {
     #[token("like")]
     Like,
 
+    #[token("is")]
+    Is,
+
     #[token("then")]
     Then,
 
@@ -176,6 +179,9 @@ pub enum Token {
     #[token("==")]
     Equal,
 
+    #[token("=")]
+    Assign,
+
     #[token("!=")]
     NotEqual,
 
@@ -222,6 +228,7 @@ impl fmt::Display for Token {
             Self::Action => write!(f, "action"),
             Self::Add => write!(f, "+"),
             Self::And => write!(f, "&&"),
+            Self::Assign => write!(f, "="),
             Self::At => write!(f, "@"),
             Self::Colon => write!(f, ":"),
             Self::Comma => write!(f, ","),
@@ -248,6 +255,7 @@ impl fmt::Display for Token {
             Self::LParen => write!(f, "("),
             Self::Le => write!(f, "<="),
             Self::Like => write!(f, "like"),
+            Self::Is => write!(f, "is"),
             Self::Lt => write!(f, "<"),
             Self::Modulo => write!(f, "%"),
             Self::Mul => write!(f, "*"),
@@ -97,6 +97,23 @@ pub enum SchemaError {
     /// This error variant should only be used when `PermitAttributes` is enabled.
     #[error("action `{0}` has an attribute with unsupported JSON representation: {1}")]
     UnsupportedActionAttribute(EntityUID, String),
+    /// The `contextNormalization` of an action names an attribute which is
+    /// not declared in the context of that action.
+    #[error("context normalization for action `{0}` refers to undeclared context attribute `{1}`")]
+    UndeclaredNormalizedContextAttr(EntityUID, String),
+    /// The `requesterControlled` list of an action names an attribute which
+    /// is not declared in the context of that action.
+    #[error("requester controlled context attribute `{1}` of action `{0}` is not declared")]
+    UndeclaredRequesterControlledContextAttr(EntityUID, String),
+    /// The default value declared for a context attribute is not a valid
+    /// value of the attribute's declared type.
+    #[error("invalid default for context attribute `{1}` of action `{0}`: {2}")]
+    InvalidContextDefault(EntityUID, String, String),
+    /// A `derivedParents` entry of an entity type is malformed, or compares
+    /// an attribute which is undeclared or has a different type than the
+    /// value it is compared against.
+    #[error("invalid derived parent for entity type `{0}`: {1}")]
+    InvalidDerivedParent(Name, String),
 }
 
 impl From<transitive_closure::TcError<EntityUID>> for SchemaError {
@@ -15,7 +15,8 @@
  */
 
 use cedar_policy_core::ast::{
-    EntityType, EntityUID, Expr, ExprKind, Literal, Name, PatternElem, Template,
+    EntityType, EntityUID, Expr, ExprKind, Literal, Name, PatternElem,
+    PrincipalOrResourceConstraint, Template,
 };
 
 /// Returns an iterator over all literal entity uids in the expression.
@@ -39,6 +40,27 @@ pub(super) fn policy_entity_uids(template: &Template) -> impl Iterator<Item = &E
         .chain(expr_entity_uids(template.non_head_constraints()))
 }
 
+/// Returns an iterator over all entity type names tested with `is` in a
+/// policy, both in the policy head and in the body.
+pub(super) fn policy_is_entity_types(template: &Template) -> impl Iterator<Item = &Name> {
+    fn head_type(c: &PrincipalOrResourceConstraint) -> Option<&Name> {
+        match c {
+            PrincipalOrResourceConstraint::Is(entity_type)
+            | PrincipalOrResourceConstraint::IsIn(entity_type, _) => Some(entity_type),
+            _ => None,
+        }
+    }
+    head_type(template.principal_constraint().as_inner())
+        .into_iter()
+        .chain(head_type(template.resource_constraint().as_inner()))
+        .chain(template.non_head_constraints().subexpressions().filter_map(
+            |e| match e.expr_kind() {
+                ExprKind::Is { entity_type, .. } => Some(entity_type),
+                _ => None,
+            },
+        ))
+}
+
 /// The 3 different "classes" of text in an expression.
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub enum TextKind<'a> {
@@ -63,6 +85,7 @@ fn text_in_expr(e: &'_ Expr) -> impl IntoIterator<Item = TextKind<'_>> {
         ExprKind::GetAttr { attr, .. } => vec![TextKind::Identifier(attr)],
         ExprKind::HasAttr { attr, .. } => vec![TextKind::Identifier(attr)],
         ExprKind::Like { pattern, .. } => vec![TextKind::Pattern(pattern.get_elems())],
+        ExprKind::Is { entity_type, .. } => text_in_name(entity_type).collect(),
         ExprKind::Record { pairs } => pairs
             .iter()
             .map(|(attr, _)| TextKind::Identifier(attr))
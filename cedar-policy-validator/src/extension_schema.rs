@@ -15,6 +15,7 @@
  */
 
 use crate::types::Type;
+use crate::{ValidationMode, ValidatorSchema};
 use cedar_policy_core::ast::{Expr, Name};
 use std::collections::HashMap;
 
@@ -61,7 +62,17 @@ impl ExtensionSchema {
 /// extension function application. An `ArgumentCheckFn` is passed a slice
 /// containing the arguments to the extension function call and returns `Err` if
 /// it can statically determine that the arguments are invalid.
-pub(crate) type ArgumentCheckFn = Box<dyn Fn(&[Expr]) -> Result<(), String>>;
+pub(crate) type ArgumentCheckFn = Box<dyn Fn(&[Expr]) -> Result<(), String> + Send + Sync>;
+
+/// The type of a function computing the return type of an extension function
+/// application from its arguments and their types, for functions whose return
+/// type depends on them, e.g. on the element type of a set argument. It
+/// returns `Err` if the arguments are incompatible with each other.
+pub(crate) type ReturnTypeFn = Box<
+    dyn Fn(&ValidatorSchema, ValidationMode, &[Expr], &[Type]) -> Result<Type, String>
+        + Send
+        + Sync,
+>;
 
 /// Type information for a single extension function.
 pub struct ExtensionFunctionType {
@@ -73,6 +84,8 @@ pub struct ExtensionFunctionType {
     return_type: Type,
     /// Custom argument validation (optional)
     check_arguments: Option<ArgumentCheckFn>,
+    /// Return type depending on the argument types (optional)
+    return_type_fn: Option<ReturnTypeFn>,
 }
 
 impl ExtensionFunctionType {
@@ -88,9 +101,18 @@ impl ExtensionFunctionType {
             argument_types,
             return_type,
             check_arguments,
+            return_type_fn: None,
         }
     }
 
+    /// Compute the return type of applications from their arguments and the
+    /// types of their arguments. The result should be a subtype of the declared return type.
+    #[cfg(any(feature = "set-ops", feature = "record-ops", feature = "entity-ops"))]
+    pub(crate) fn with_return_type_fn(mut self, return_type_fn: ReturnTypeFn) -> Self {
+        self.return_type_fn = Some(return_type_fn);
+        self
+    }
+
     /// Get the name of the extension function
     pub fn name(&self) -> &Name {
         &self.name
@@ -106,6 +128,21 @@ impl ExtensionFunctionType {
         &self.return_type
     }
 
+    /// The return type of an application with the given arguments, which
+    /// have the given types
+    pub(crate) fn return_type_for(
+        &self,
+        schema: &ValidatorSchema,
+        mode: ValidationMode,
+        args: &[Expr],
+        arg_types: &[Type],
+    ) -> Result<Type, String> {
+        match &self.return_type_fn {
+            Some(f) => f(schema, mode, args, arg_types),
+            None => Ok(self.return_type.clone()),
+        }
+    }
+
     /// Call the `check_arguments` function with the given args
     pub fn check_arguments(&self, args: &[Expr]) -> Result<(), String> {
         if let Some(f) = &self.check_arguments {
@@ -17,6 +17,9 @@
 //! This module contains type information for all of the standard Cedar extensions.
 
 use crate::extension_schema::ExtensionSchema;
+use cedar_policy_core::ast::Name;
+use cedar_policy_core::extensions::Extensions;
+use std::collections::HashSet;
 
 #[cfg(feature = "ipaddr")]
 pub mod ipaddr;
@@ -24,12 +27,60 @@ pub mod ipaddr;
 #[cfg(feature = "decimal")]
 pub mod decimal;
 
-
+pub mod config;
 pub mod partial_evaluation;
 
 #[cfg(feature = "u256")]
 pub mod u256;
 
+#[cfg(feature = "price-feed")]
+pub mod price_feed;
+
+#[cfg(feature = "log-match")]
+pub mod log_match;
+
+#[cfg(feature = "gas")]
+pub mod gas;
+
+#[cfg(feature = "hash")]
+pub mod hash;
+
+#[cfg(feature = "commitment")]
+pub mod commitment;
+
+#[cfg(feature = "zk")]
+pub mod zk;
+
+#[cfg(feature = "webauthn")]
+pub mod webauthn;
+
+#[cfg(feature = "totp")]
+pub mod totp;
+
+#[cfg(feature = "set-ops")]
+pub mod set_ops;
+
+#[cfg(feature = "record-ops")]
+pub mod record_ops;
+
+#[cfg(feature = "entity-ops")]
+pub mod entity_ops;
+
+#[cfg(feature = "travel-rule")]
+pub mod travel_rule;
+
+#[cfg(feature = "vesting")]
+pub mod vesting;
+
+#[cfg(feature = "health-factor")]
+pub mod health_factor;
+
+#[cfg(feature = "concentration")]
+pub mod concentration;
+
+#[cfg(feature = "exposure")]
+pub mod exposure;
+
 /// Get schemas for all the available extensions.
 pub fn all_available_extension_schemas() -> Vec<ExtensionSchema> {
     vec![
@@ -38,7 +89,56 @@ pub fn all_available_extension_schemas() -> Vec<ExtensionSchema> {
         #[cfg(feature = "decimal")]
         decimal::extension_schema(),
         partial_evaluation::extension_schema(),
+        config::extension_schema(),
         #[cfg(feature = "u256")]
         u256::extension_schema(),
+        #[cfg(feature = "price-feed")]
+        price_feed::extension_schema(),
+        #[cfg(feature = "log-match")]
+        log_match::extension_schema(),
+        #[cfg(feature = "gas")]
+        gas::extension_schema(),
+        #[cfg(feature = "hash")]
+        hash::extension_schema(),
+        #[cfg(feature = "commitment")]
+        commitment::extension_schema(),
+        #[cfg(feature = "zk")]
+        zk::extension_schema(),
+        #[cfg(feature = "webauthn")]
+        webauthn::extension_schema(),
+        #[cfg(feature = "totp")]
+        totp::extension_schema(),
+        #[cfg(feature = "set-ops")]
+        set_ops::extension_schema(),
+        #[cfg(feature = "record-ops")]
+        record_ops::extension_schema(),
+        #[cfg(feature = "entity-ops")]
+        entity_ops::extension_schema(),
+        #[cfg(feature = "travel-rule")]
+        travel_rule::extension_schema(),
+        #[cfg(feature = "vesting")]
+        vesting::extension_schema(),
+        #[cfg(feature = "health-factor")]
+        health_factor::extension_schema(),
+        #[cfg(feature = "concentration")]
+        concentration::extension_schema(),
+        #[cfg(feature = "exposure")]
+        exposure::extension_schema(),
     ]
 }
+
+/// The names of the types constructed by the available extensions, e.g.
+/// `u256`.
+pub(crate) fn extension_types() -> impl Iterator<Item = Name> {
+    Extensions::all_available()
+        .ext_types()
+        .cloned()
+        .collect::<HashSet<_>>()
+        .into_iter()
+}
+
+/// Whether `name` is the name of a type constructed by one of the available
+/// extensions.
+pub(crate) fn is_extension_type(name: &Name) -> bool {
+    Extensions::all_available().ext_types().any(|ty| ty == name)
+}
@@ -0,0 +1,63 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::extension_schema::{ExtensionFunctionType, ExtensionSchema};
+use crate::types::{self, Type};
+use cedar_policy_core::extensions::config;
+
+// If any of the panics in this file are triggered, that means that this file has become
+// out-of-date with the config extension definition in CedarCore.
+
+fn get_argument_types(fname: &str) -> Vec<types::Type> {
+    match fname {
+        "config" => vec![Type::primitive_string()],
+        _ => panic!("unexpected config extension function name: {fname}"),
+    }
+}
+
+fn get_return_type(fname: &str) -> Type {
+    match fname {
+        // a reference is replaced with its value, of whatever type, before
+        // the policy is evaluated
+        "config" => Type::Never,
+        _ => panic!("unexpected config extension function name: {fname}"),
+    }
+}
+
+/// Construct the extension schema
+pub fn extension_schema() -> ExtensionSchema {
+    let config_ext = config::extension();
+
+    let fun_tys: Vec<ExtensionFunctionType> = config_ext
+        .funcs()
+        .map(|f| {
+            let fname = f.name();
+            let fstring = fname.to_string();
+            let return_type = get_return_type(&fstring);
+            debug_assert!(f
+                .return_type()
+                .map(|ty| return_type.is_consistent_with(ty))
+                .unwrap_or_else(|| return_type == Type::Never));
+            ExtensionFunctionType::new(
+                fname.clone(),
+                get_argument_types(&fstring),
+                return_type,
+                None,
+            )
+        })
+        .collect();
+    ExtensionSchema::new(config_ext.name().clone(), fun_tys)
+}
@@ -0,0 +1,96 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+use crate::extension_schema::{ExtensionFunctionType, ExtensionSchema, ReturnTypeFn};
+use crate::types::{self, Type};
+use crate::{ValidationMode, ValidatorSchema};
+use cedar_policy_core::ast::{Expr, ExprKind, Literal, Name};
+use cedar_policy_core::extensions::entity_ops;
+use cedar_policy_core::FromNormalizedStr;
+
+/// If any of the panics in this file are triggered, that means that this file has become
+/// out-of-date with the entityOps extension definition in CedarCore.
+
+fn get_argument_types(fname: &str) -> Vec<types::Type> {
+    match fname {
+        "entityFromString" => vec![Type::primitive_string(), Type::primitive_string()],
+        _ => panic!("unexpected entityOps extension function name: {fname}"),
+    }
+}
+
+fn get_return_type(fname: &str) -> Type {
+    match fname {
+        "entityFromString" => Type::any_entity_reference(),
+        _ => panic!("unexpected entityOps extension function name: {fname}"),
+    }
+}
+
+fn get_return_type_fn(fname: &str) -> ReturnTypeFn {
+    match fname {
+        "entityFromString" => Box::new(named_entity_type),
+        _ => panic!("unexpected entityOps extension function name: {fname}"),
+    }
+}
+
+/// The type of `entityFromString(ty, id)` is the entity type named by `ty`,
+/// which must be a string literal naming an entity type in the schema
+fn named_entity_type(
+    schema: &ValidatorSchema,
+    _mode: ValidationMode,
+    args: &[Expr],
+    _arg_types: &[Type],
+) -> Result<Type, String> {
+    match args.first().map(Expr::expr_kind) {
+        Some(ExprKind::Lit(Literal::String(ty))) => {
+            let name = Name::from_normalized_str(ty)
+                .map_err(|_| format!("`{ty}` is not a valid entity type name"))?;
+            if schema.is_known_entity_type(&name) {
+                Ok(Type::named_entity_reference(name))
+            } else {
+                Err(format!("`{ty}` is not an entity type declared in the schema"))
+            }
+        }
+        _ => Err(
+            "the first argument of `entityFromString` must be a string literal naming an entity type"
+                .to_string(),
+        ),
+    }
+}
+
+/// Construct the extension schema
+pub fn extension_schema() -> ExtensionSchema {
+    let entity_ops_ext = entity_ops::extension();
+
+    let fun_tys: Vec<ExtensionFunctionType> = entity_ops_ext
+        .funcs()
+        .map(|f| {
+            let fname = f.name();
+            let fstring = fname.to_string();
+            let return_type = get_return_type(&fstring);
+            debug_assert!(f
+                .return_type()
+                .map(|ty| return_type.is_consistent_with(ty))
+                .unwrap_or_else(|| return_type == Type::Never));
+            ExtensionFunctionType::new(
+                fname.clone(),
+                get_argument_types(&fstring),
+                return_type,
+                None,
+            )
+            .with_return_type_fn(get_return_type_fn(&fstring))
+        })
+        .collect();
+    ExtensionSchema::new(entity_ops_ext.name().clone(), fun_tys)
+}
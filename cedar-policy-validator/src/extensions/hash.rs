@@ -0,0 +1,69 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+//! This module contains type information for the Cedar 'hash' extension.
+
+use crate::extension_schema::{ExtensionFunctionType, ExtensionSchema};
+use crate::types::{self, Type};
+use cedar_policy_core::ast::Name;
+use cedar_policy_core::extensions::hash;
+
+// If any of the panics in this file are triggered, that means that this file has become
+// out-of-date with the hash extension definition in CedarCore.
+
+fn get_argument_types(fname: &str, u256_ty: &Type) -> Vec<types::Type> {
+    match fname {
+        "keccak256" | "sha256" => vec![Type::primitive_string()],
+        "poseidonHash" => vec![u256_ty.clone(), u256_ty.clone()],
+        _ => panic!("unexpected hash extension function name: {fname}"),
+    }
+}
+
+fn get_return_type(fname: &str, u256_ty: &Type) -> Type {
+    match fname {
+        "keccak256" | "sha256" | "poseidonHash" => u256_ty.clone(),
+        _ => panic!("unexpected hash extension function name: {fname}"),
+    }
+}
+
+/// Construct the extension schema
+pub fn extension_schema() -> ExtensionSchema {
+    let hash_ext = hash::extension();
+    // PANIC SAFETY: `u256` is a valid identifier
+    #[allow(clippy::expect_used)]
+    let u256_ty = Type::extension(
+        Name::parse_unqualified_name("u256").expect("should be a valid identifier"),
+    );
+
+    let fun_tys: Vec<ExtensionFunctionType> = hash_ext
+        .funcs()
+        .map(|f| {
+            let fname = f.name();
+            let fstring = fname.to_string();
+            let return_type = get_return_type(&fstring, &u256_ty);
+            debug_assert!(f
+                .return_type()
+                .map(|ty| return_type.is_consistent_with(ty))
+                .unwrap_or_else(|| return_type == Type::Never));
+            ExtensionFunctionType::new(
+                fname.clone(),
+                get_argument_types(&fstring, &u256_ty),
+                return_type,
+                None,
+            )
+        })
+        .collect();
+    ExtensionSchema::new(hash_ext.name().clone(), fun_tys)
+}
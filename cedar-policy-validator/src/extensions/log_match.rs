@@ -0,0 +1,90 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+//! This module contains type information for the Cedar 'logMatch' extension.
+
+use crate::extension_schema::{ArgumentCheckFn, ExtensionFunctionType, ExtensionSchema};
+use crate::types::{self, Type};
+use cedar_policy_core::ast::{Expr, ExprKind, Literal};
+use cedar_policy_core::extensions::log_match;
+
+/// If any of the panics in this file are triggered, that means that this file has become
+/// out-of-date with the logMatch extension definition in CedarCore.
+
+fn get_argument_types(fname: &str) -> Vec<types::Type> {
+    match fname {
+        "logMatch" => vec![
+            Type::any_set(),
+            Type::primitive_string(),
+            Type::any_record(),
+        ],
+        _ => panic!("unexpected logMatch extension function name: {fname}"),
+    }
+}
+
+fn get_return_type(fname: &str) -> Type {
+    match fname {
+        "logMatch" => Type::primitive_boolean(),
+        _ => panic!("unexpected logMatch extension function name: {fname}"),
+    }
+}
+
+fn get_argument_check(fname: &str) -> Option<ArgumentCheckFn> {
+    match fname {
+        "logMatch" => Some(Box::new(validate_signature)),
+        _ => panic!("unexpected logMatch extension function name: {fname}"),
+    }
+}
+
+/// Construct the extension schema
+pub fn extension_schema() -> ExtensionSchema {
+    let log_match_ext = log_match::extension();
+
+    let fun_tys: Vec<ExtensionFunctionType> = log_match_ext
+        .funcs()
+        .map(|f| {
+            let fname = f.name();
+            let fstring = fname.to_string();
+            let return_type = get_return_type(&fstring);
+            debug_assert!(f
+                .return_type()
+                .map(|ty| return_type.is_consistent_with(ty))
+                .unwrap_or_else(|| return_type == Type::Never));
+            ExtensionFunctionType::new(
+                fname.clone(),
+                get_argument_types(&fstring),
+                return_type,
+                get_argument_check(&fstring),
+            )
+        })
+        .collect();
+    ExtensionSchema::new(log_match_ext.name().clone(), fun_tys)
+}
+
+/// Extra validation step for the `logMatch` function, checking that a
+/// literal event signature is well-formed.
+/// Note that `exprs` will have already been checked to contain the correct number of arguments.
+fn validate_signature(exprs: &[Expr]) -> Result<(), String> {
+    match exprs.get(1).map(Expr::expr_kind) {
+        Some(ExprKind::Lit(Literal::String(signature)))
+            if log_match::canonical_signature(signature).is_none() =>
+        {
+            Err(format!(
+                "Failed to parse as an event signature: `{signature}`"
+            ))
+        }
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,116 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+//! This module contains type information for the Cedar 'priceFeed' extension.
+
+use crate::extension_schema::{ArgumentCheckFn, ExtensionFunctionType, ExtensionSchema};
+use crate::types::{self, Type};
+use cedar_policy_core::ast::{Expr, ExprKind, Literal, Name, RestrictedExpr};
+use cedar_policy_core::evaluator::RestrictedEvaluator;
+use cedar_policy_core::extensions::{price_feed, Extensions};
+use std::str::FromStr;
+
+/// If any of the panics in this file are triggered, that means that this file has become
+/// out-of-date with the priceFeed extension definition in CedarCore.
+
+fn get_argument_types(fname: &str, price_feed_ty: &Type) -> Vec<types::Type> {
+    match fname {
+        "priceFeed" => vec![Type::primitive_string()],
+        "answer" | "updatedAt" | "decimals" => vec![price_feed_ty.clone()],
+        "isFresh" => vec![
+            price_feed_ty.clone(),
+            Type::primitive_long(),
+            Type::primitive_long(),
+        ],
+        "deviationBps" => vec![price_feed_ty.clone(), Type::primitive_long()],
+        "usdValue" => vec![
+            extension_type("u256"),
+            Type::primitive_long(),
+            price_feed_ty.clone(),
+        ],
+        _ => panic!("unexpected priceFeed extension function name: {fname}"),
+    }
+}
+
+fn get_return_type(fname: &str, price_feed_ty: &Type) -> Type {
+    match fname {
+        "priceFeed" => price_feed_ty.clone(),
+        "answer" | "updatedAt" | "decimals" | "deviationBps" => Type::primitive_long(),
+        "isFresh" => Type::primitive_boolean(),
+        "usdValue" => extension_type("decimal"),
+        _ => panic!("unexpected priceFeed extension function name: {fname}"),
+    }
+}
+
+fn get_argument_check(fname: &str) -> Option<ArgumentCheckFn> {
+    match fname {
+        "priceFeed" => Some(Box::new(validate_price_feed_string)),
+        "answer" | "updatedAt" | "decimals" | "isFresh" | "deviationBps" | "usdValue" => None,
+        _ => panic!("unexpected priceFeed extension function name: {fname}"),
+    }
+}
+
+/// The type of another extension's values, e.g. `u256`
+fn extension_type(name: &str) -> Type {
+    // PANIC SAFETY: only called with valid extension names
+    #[allow(clippy::expect_used)]
+    let name = Name::parse_unqualified_name(name).expect("should be a valid identifier");
+    Type::extension(name)
+}
+
+/// Construct the extension schema
+pub fn extension_schema() -> ExtensionSchema {
+    let price_feed_ext = price_feed::extension();
+    let price_feed_ty = Type::extension(price_feed_ext.name().clone());
+
+    let fun_tys: Vec<ExtensionFunctionType> = price_feed_ext
+        .funcs()
+        .map(|f| {
+            let fname = f.name();
+            let fstring = fname.to_string();
+            let return_type = get_return_type(&fstring, &price_feed_ty);
+            debug_assert!(f
+                .return_type()
+                .map(|ty| return_type.is_consistent_with(ty))
+                .unwrap_or_else(|| return_type == Type::Never));
+            ExtensionFunctionType::new(
+                fname.clone(),
+                get_argument_types(&fstring, &price_feed_ty),
+                return_type,
+                get_argument_check(&fstring),
+            )
+        })
+        .collect();
+    ExtensionSchema::new(price_feed_ext.name().clone(), fun_tys)
+}
+
+/// Extra validation step for the `priceFeed` function.
+/// Note that `exprs` will have already been checked to contain the correct number of arguments.
+fn validate_price_feed_string(exprs: &[Expr]) -> Result<(), String> {
+    match exprs.get(0) {
+        Some(arg) if matches!(arg.expr_kind(), ExprKind::Lit(Literal::String(_))) => {
+            let exts = Extensions::all_available();
+            let evaluator = RestrictedEvaluator::new(&exts);
+            match RestrictedExpr::from_str(&format!("priceFeed({arg})")) {
+                Ok(expr) => match evaluator.interpret(expr.as_borrowed()) {
+                    Ok(_) => Ok(()),
+                    Err(_) => Err(format!("Failed to parse as a priceFeed value: `{arg}`")),
+                },
+                Err(_) => Err(format!("Failed to parse as a priceFeed value: `{arg}`")),
+            }
+        }
+        _ => Ok(()),
+    }
+}
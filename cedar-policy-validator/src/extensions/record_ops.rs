@@ -0,0 +1,131 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::extension_schema::{ExtensionFunctionType, ExtensionSchema, ReturnTypeFn};
+use crate::types::{self, AttributeType, EntityRecordKind, OpenTag, Type};
+use crate::{ValidationMode, ValidatorSchema};
+use cedar_policy_core::ast::Expr;
+use cedar_policy_core::extensions::record_ops;
+use std::collections::BTreeMap;
+
+/// If any of the panics in this file are triggered, that means that this file has become
+/// out-of-date with the recordOps extension definition in CedarCore.
+
+fn get_argument_types(fname: &str) -> Vec<types::Type> {
+    match fname {
+        "keys" => vec![Type::any_record()],
+        "hasAll" => vec![Type::any_record(), Type::set(Type::primitive_string())],
+        "merge" => vec![Type::any_record(), Type::any_record()],
+        _ => panic!("unexpected recordOps extension function name: {fname}"),
+    }
+}
+
+fn get_return_type(fname: &str) -> Type {
+    match fname {
+        "keys" => Type::set(Type::primitive_string()),
+        "hasAll" => Type::primitive_boolean(),
+        "merge" => Type::any_record(),
+        _ => panic!("unexpected recordOps extension function name: {fname}"),
+    }
+}
+
+fn get_return_type_fn(fname: &str) -> Option<ReturnTypeFn> {
+    match fname {
+        "keys" | "hasAll" => None,
+        "merge" => Some(Box::new(merged_record)),
+        _ => panic!("unexpected recordOps extension function name: {fname}"),
+    }
+}
+
+/// The type of `merge(first, second)` when the shapes of both records are
+/// known. Attributes of `second` override those of `first`, so an optional
+/// attribute of `second` which `first` also has may have the type of either.
+/// If `second` is open, it may override any attribute of `first`, so only
+/// the attributes declared by `second` are known.
+fn merged_record(
+    schema: &ValidatorSchema,
+    mode: ValidationMode,
+    _args: &[Expr],
+    arg_types: &[Type],
+) -> Result<Type, String> {
+    let [Type::EntityOrRecord(EntityRecordKind::Record {
+        attrs: first,
+        open_attributes: first_open,
+    }), Type::EntityOrRecord(EntityRecordKind::Record {
+        attrs: second,
+        open_attributes: second_open,
+    })] = arg_types
+    else {
+        return Ok(Type::any_record());
+    };
+    let mut merged: BTreeMap<_, _> = if second_open.is_open() {
+        BTreeMap::new()
+    } else {
+        first.attrs.clone()
+    };
+    for (attr, ty) in second.iter() {
+        let ty = match first.attrs.get(attr) {
+            Some(prior) if !ty.is_required => AttributeType::new(
+                Type::least_upper_bound(schema, &prior.attr_type, &ty.attr_type, mode).ok_or_else(
+                    || {
+                        format!(
+                            "`merge` may give attribute `{attr}` either type `{}` or `{}`",
+                            prior.attr_type, ty.attr_type
+                        )
+                    },
+                )?,
+                prior.is_required,
+            ),
+            _ => ty.clone(),
+        };
+        merged.insert(attr.clone(), ty);
+    }
+    let open_attributes = if first_open.is_open() || second_open.is_open() {
+        OpenTag::OpenAttributes
+    } else {
+        OpenTag::ClosedAttributes
+    };
+    Ok(Type::record_with_attributes(merged, open_attributes))
+}
+
+/// Construct the extension schema
+pub fn extension_schema() -> ExtensionSchema {
+    let record_ops_ext = record_ops::extension();
+
+    let fun_tys: Vec<ExtensionFunctionType> = record_ops_ext
+        .funcs()
+        .map(|f| {
+            let fname = f.name();
+            let fstring = fname.to_string();
+            let return_type = get_return_type(&fstring);
+            debug_assert!(f
+                .return_type()
+                .map(|ty| return_type.is_consistent_with(ty))
+                .unwrap_or_else(|| return_type == Type::Never));
+            let fun_ty = ExtensionFunctionType::new(
+                fname.clone(),
+                get_argument_types(&fstring),
+                return_type,
+                None,
+            );
+            match get_return_type_fn(&fstring) {
+                Some(return_type_fn) => fun_ty.with_return_type_fn(return_type_fn),
+                None => fun_ty,
+            }
+        })
+        .collect();
+    ExtensionSchema::new(record_ops_ext.name().clone(), fun_tys)
+}
@@ -0,0 +1,100 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::extension_schema::{ExtensionFunctionType, ExtensionSchema, ReturnTypeFn};
+use crate::types::{self, Type};
+use crate::{ValidationMode, ValidatorSchema};
+use cedar_policy_core::extensions::set_ops;
+
+/// If any of the panics in this file are triggered, that means that this file has become
+/// out-of-date with the setOps extension definition in CedarCore.
+
+fn get_argument_types(fname: &str) -> Vec<types::Type> {
+    match fname {
+        "setIntersect" | "setUnionSize" | "isSubsetOf" => vec![Type::any_set(), Type::any_set()],
+        _ => panic!("unexpected setOps extension function name: {fname}"),
+    }
+}
+
+fn get_return_type(fname: &str) -> Type {
+    match fname {
+        "setIntersect" => Type::any_set(),
+        "setUnionSize" => Type::primitive_long(),
+        "isSubsetOf" => Type::primitive_boolean(),
+        _ => panic!("unexpected setOps extension function name: {fname}"),
+    }
+}
+
+fn get_return_type_fn(fname: &str) -> ReturnTypeFn {
+    match fname {
+        // The intersection is a subset of both sets, so it has the type of
+        // both
+        "setIntersect" => Box::new(|schema, mode, _, arg_types| {
+            homogeneous_sets("setIntersect", schema, mode, arg_types)
+        }),
+        "setUnionSize" => Box::new(|schema, mode, _, arg_types| {
+            homogeneous_sets("setUnionSize", schema, mode, arg_types)
+                .map(|_| Type::primitive_long())
+        }),
+        "isSubsetOf" => Box::new(|schema, mode, _, arg_types| {
+            homogeneous_sets("isSubsetOf", schema, mode, arg_types)
+                .map(|_| Type::primitive_boolean())
+        }),
+        _ => panic!("unexpected setOps extension function name: {fname}"),
+    }
+}
+
+/// The least upper bound of the set types, or an error if the sets' elements
+/// don't have a common type, e.g. a set of strings and a set of entities
+fn homogeneous_sets(
+    fname: &str,
+    schema: &ValidatorSchema,
+    mode: ValidationMode,
+    arg_types: &[Type],
+) -> Result<Type, String> {
+    match arg_types {
+        [first, second] => Type::least_upper_bound(schema, first, second, mode).ok_or_else(|| {
+            format!("`{fname}` expects sets with elements of the same type, but got `{first}` and `{second}`")
+        }),
+        _ => Ok(Type::any_set()),
+    }
+}
+
+/// Construct the extension schema
+pub fn extension_schema() -> ExtensionSchema {
+    let set_ops_ext = set_ops::extension();
+
+    let fun_tys: Vec<ExtensionFunctionType> = set_ops_ext
+        .funcs()
+        .map(|f| {
+            let fname = f.name();
+            let fstring = fname.to_string();
+            let return_type = get_return_type(&fstring);
+            debug_assert!(f
+                .return_type()
+                .map(|ty| return_type.is_consistent_with(ty))
+                .unwrap_or_else(|| return_type == Type::Never));
+            ExtensionFunctionType::new(
+                fname.clone(),
+                get_argument_types(&fstring),
+                return_type,
+                None,
+            )
+            .with_return_type_fn(get_return_type_fn(&fstring))
+        })
+        .collect();
+    ExtensionSchema::new(set_ops_ext.name().clone(), fun_tys)
+}
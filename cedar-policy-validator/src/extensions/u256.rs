@@ -16,7 +16,7 @@
 
 use crate::extension_schema::{ArgumentCheckFn, ExtensionFunctionType, ExtensionSchema};
 use crate::types::{self, Type};
-use cedar_policy_core::ast::{Expr, ExprKind, Literal, RestrictedExpr};
+use cedar_policy_core::ast::{Expr, ExprKind, Literal, Name, RestrictedExpr};
 use cedar_policy_core::evaluator::RestrictedEvaluator;
 use cedar_policy_core::extensions::{u256, Extensions};
 use std::str::FromStr;
@@ -30,6 +30,12 @@ fn get_argument_types(fname: &str, u256_ty: &Type) -> Vec<types::Type> {
         "u256LessThan" | "u256LessThanOrEqual" | "u256GreaterThan" | "u256GreaterThanOrEqual" => {
             vec![u256_ty.clone(), u256_ty.clone()]
         }
+        "impliedPrice" => vec![
+            u256_ty.clone(),
+            u256_ty.clone(),
+            Type::primitive_long(),
+            Type::primitive_long(),
+        ],
         _ => panic!("unexpected u256 extension function name: {fname}"),
     }
 }
@@ -40,6 +46,7 @@ fn get_return_type(fname: &str, u256_ty: &Type) -> Type {
         "u256LessThan" | "u256LessThanOrEqual" | "u256GreaterThan" | "u256GreaterThanOrEqual" => {
             Type::primitive_boolean()
         }
+        "impliedPrice" => extension_type("decimal"),
         _ => panic!("unexpected u256 extension function name: {fname}"),
     }
 }
@@ -47,11 +54,23 @@ fn get_return_type(fname: &str, u256_ty: &Type) -> Type {
 fn get_argument_check(fname: &str) -> Option<ArgumentCheckFn> {
     match fname {
         "u256" => Some(Box::new(validate_u256_string)),
-        "u256LessThan" | "u256LessThanOrEqual" | "u256GreaterThan" | "u256GreaterThanOrEqual" => None,
+        "u256LessThan"
+        | "u256LessThanOrEqual"
+        | "u256GreaterThan"
+        | "u256GreaterThanOrEqual"
+        | "impliedPrice" => None,
         _ => panic!("unexpected u256 extension function name: {fname}"),
     }
 }
 
+/// The type of another extension's values, e.g. `decimal`
+fn extension_type(name: &str) -> Type {
+    // PANIC SAFETY: only called with valid extension names
+    #[allow(clippy::expect_used)]
+    let name = Name::parse_unqualified_name(name).expect("should be a valid identifier");
+    Type::extension(name)
+}
+
 /// Construct the extension schema
 pub fn extension_schema() -> ExtensionSchema {
     let u256_ext = u256::extension();
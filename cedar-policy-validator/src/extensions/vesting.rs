@@ -0,0 +1,105 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::extension_schema::{ArgumentCheckFn, ExtensionFunctionType, ExtensionSchema};
+use crate::types::{self, Type};
+use cedar_policy_core::ast::{Expr, ExprKind, Literal, Name, RestrictedExpr};
+use cedar_policy_core::evaluator::RestrictedEvaluator;
+use cedar_policy_core::extensions::{vesting, Extensions};
+use std::str::FromStr;
+
+// If any of the panics in this file are triggered, that means that this file has become
+// out-of-date with the vesting extension definition in CedarCore.
+
+fn get_argument_types(fname: &str, vesting_ty: &Type) -> Vec<types::Type> {
+    match fname {
+        "vesting" => vec![Type::primitive_string()],
+        "total" | "cliffEnd" | "end" => vec![vesting_ty.clone()],
+        "vestedAt" => vec![vesting_ty.clone(), Type::primitive_long()],
+        "releasableAt" => vec![vesting_ty.clone(), Type::primitive_long(), u256_type()],
+        _ => panic!("unexpected vesting extension function name: {fname}"),
+    }
+}
+
+fn get_return_type(fname: &str, vesting_ty: &Type) -> Type {
+    match fname {
+        "vesting" => vesting_ty.clone(),
+        "vestedAt" | "releasableAt" | "total" => u256_type(),
+        "cliffEnd" | "end" => Type::primitive_long(),
+        _ => panic!("unexpected vesting extension function name: {fname}"),
+    }
+}
+
+fn get_argument_check(fname: &str) -> Option<ArgumentCheckFn> {
+    match fname {
+        "vesting" => Some(Box::new(validate_vesting_string)),
+        "vestedAt" | "releasableAt" | "total" | "cliffEnd" | "end" => None,
+        _ => panic!("unexpected vesting extension function name: {fname}"),
+    }
+}
+
+/// The type of `u256` values
+fn u256_type() -> Type {
+    // PANIC SAFETY: `u256` is a valid identifier
+    #[allow(clippy::expect_used)]
+    let name = Name::parse_unqualified_name("u256").expect("should be a valid identifier");
+    Type::extension(name)
+}
+
+/// Construct the extension schema
+pub fn extension_schema() -> ExtensionSchema {
+    let vesting_ext = vesting::extension();
+    let vesting_ty = Type::extension(vesting_ext.name().clone());
+
+    let fun_tys: Vec<ExtensionFunctionType> = vesting_ext
+        .funcs()
+        .map(|f| {
+            let fname = f.name();
+            let fstring = fname.to_string();
+            let return_type = get_return_type(&fstring, &vesting_ty);
+            debug_assert!(f
+                .return_type()
+                .map(|ty| return_type.is_consistent_with(ty))
+                .unwrap_or_else(|| return_type == Type::Never));
+            ExtensionFunctionType::new(
+                fname.clone(),
+                get_argument_types(&fstring, &vesting_ty),
+                return_type,
+                get_argument_check(&fstring),
+            )
+        })
+        .collect();
+    ExtensionSchema::new(vesting_ext.name().clone(), fun_tys)
+}
+
+/// Extra validation step for the `vesting` function.
+/// Note that `exprs` will have already been checked to contain the correct number of arguments.
+fn validate_vesting_string(exprs: &[Expr]) -> Result<(), String> {
+    match exprs.first() {
+        Some(arg) if matches!(arg.expr_kind(), ExprKind::Lit(Literal::String(_))) => {
+            let exts = Extensions::all_available();
+            let evaluator = RestrictedEvaluator::new(&exts);
+            match RestrictedExpr::from_str(&format!("vesting({arg})")) {
+                Ok(expr) => match evaluator.interpret(expr.as_borrowed()) {
+                    Ok(_) => Ok(()),
+                    Err(_) => Err(format!("Failed to parse as a vesting value: `{arg}`")),
+                },
+                Err(_) => Err(format!("Failed to parse as a vesting value: `{arg}`")),
+            }
+        }
+        _ => Ok(()),
+    }
+}
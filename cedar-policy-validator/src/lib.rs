@@ -16,10 +16,18 @@
 
 //! Validator for Cedar policies
 #![forbid(unsafe_code)]
+// the `trojan_source` test has BIDI control characters in its literals.
+// rustc checks for them before `cfg(test)` is applied, and only accepts
+// allowing them for a whole crate, ignoring (with a warning) the test's own
+// `allow`.
+#![allow(text_direction_codepoint_in_literal)]
+#![cfg_attr(test, allow(unused_attributes))]
 
-use std::collections::HashSet;
+use std::collections::{BTreeSet, HashSet};
 
 use cedar_policy_core::ast::{PolicySet, Template};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 
 mod err;
 mod str_checks;
@@ -28,6 +36,7 @@ mod expr_iterator;
 mod extension_schema;
 mod extensions;
 mod fuzzy_match;
+mod normalize;
 mod validation_result;
 use serde::Serialize;
 pub use validation_result::*;
@@ -63,61 +72,160 @@ impl ValidationMode {
     }
 }
 
+/// The annotation restricting a policy to some chains, e.g.
+/// `@chain("1, 8453")`
+pub const CHAIN_ANNOTATION: &str = "chain";
+
+/// The chain ids listed in the value of a [`CHAIN_ANNOTATION`]: decimal
+/// integers separated by commas. Returns `None` if the value is malformed or
+/// lists no chains.
+pub fn parse_chain_annotation(value: &str) -> Option<BTreeSet<u64>> {
+    let chains = value
+        .split(',')
+        .map(|chain| chain.trim().parse().ok())
+        .collect::<Option<BTreeSet<u64>>>()?;
+    (!chains.is_empty()).then_some(chains)
+}
+
 /// Structure containing the context needed for policy validation. This is
 /// currently only the `EntityType`s and `ActionType`s from a single schema.
 #[derive(Debug)]
 pub struct Validator {
     schema: ValidatorSchema,
+    required_annotations: Vec<String>,
+    known_chains: Option<BTreeSet<u64>>,
 }
 
 impl Validator {
     /// Construct a new Validator from a schema file.
     pub fn new(schema: ValidatorSchema) -> Validator {
-        Self { schema }
+        Self {
+            schema,
+            required_annotations: Vec::new(),
+            known_chains: None,
+        }
+    }
+
+    /// Also report a [`ValidationErrorKind::MissingAnnotation`] for each
+    /// template or static policy without one of the annotations `keys`.
+    /// Template-linked policies have the annotations of their template.
+    #[must_use]
+    pub fn with_required_annotations(
+        mut self,
+        keys: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.required_annotations = keys.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Also report an [`ValidationErrorKind::UnknownChain`] for each chain
+    /// in a `@chain` annotation which isn't one of `chains`. Malformed
+    /// `@chain` annotations are always reported.
+    #[must_use]
+    pub fn with_known_chains(mut self, chains: impl IntoIterator<Item = u64>) -> Self {
+        self.known_chains = Some(chains.into_iter().collect());
+        self
     }
 
     /// Validate all templates in a policy set (which includes static policies) and
     /// return an iterator of policy notes associated with each policy id.
+    /// With the `parallel` feature, templates are validated in parallel.
     pub fn validate<'a>(
         &'a self,
         policies: &'a PolicySet,
         mode: ValidationMode,
     ) -> ValidationResult<'a> {
-        let template_errs = policies
-            .all_templates()
-            .flat_map(|p| self.validate_policy(p, mode));
+        let typechecker = Typechecker::new(&self.schema, mode);
+        let templates: Vec<&Template> = policies.all_templates().collect();
+        #[cfg(feature = "parallel")]
+        let template_errs: Vec<_> = templates
+            .into_par_iter()
+            .flat_map_iter(|p| self.validate_template(p, &typechecker))
+            .collect();
+        #[cfg(not(feature = "parallel"))]
+        let template_errs = templates
+            .into_iter()
+            .flat_map(|p| self.validate_template(p, &typechecker));
         let instantiation_errs = policies.policies().flat_map(|p| {
             self.validate_slots(p.env())
                 .map(move |note| ValidationError::with_policy_id(p.id(), None, note))
         });
-        ValidationResult::new(template_errs.chain(instantiation_errs))
+        ValidationResult::new(template_errs.into_iter().chain(instantiation_errs))
     }
 
     /// Run all validations against a single policy, gathering all validation
     /// notes from together in the returned iterator.
+    #[cfg(test)]
     fn validate_policy<'a>(
         &'a self,
         p: &'a Template,
         mode: ValidationMode,
-    ) -> impl Iterator<Item = ValidationError> + 'a {
+    ) -> impl Iterator<Item = ValidationError<'a>> + 'a {
+        self.validate_template(p, &Typechecker::new(&self.schema, mode))
+    }
+
+    /// Run all validations against a single template, typechecking it with
+    /// `typechecker`.
+    fn validate_template<'a>(
+        &'a self,
+        p: &'a Template,
+        typechecker: &Typechecker<'_>,
+    ) -> impl Iterator<Item = ValidationError<'a>> + 'a {
         self.validate_entity_types(p)
             .chain(self.validate_action_ids(p))
             .chain(self.validate_action_application(p))
+            .chain(self.validate_required_annotations(p))
+            .chain(self.validate_chain_annotation(p))
             .map(move |note| ValidationError::with_policy_id(p.id(), None, note))
-            .chain(self.typecheck_policy(p, mode))
+            .chain(Self::typecheck_policy(p, typechecker))
     }
 
-    /// Construct a Typechecker instance and use it to detect any type errors in
-    /// the argument policy in the context of the schema for this validator. Any
-    /// detected type errors are wrapped and returned as `ValidationErrorKind`s.
-    fn typecheck_policy<'a>(
+    /// Generate a `MissingAnnotation` note for each required annotation the
+    /// policy doesn't have.
+    fn validate_required_annotations<'a>(
         &'a self,
+        p: &'a Template,
+    ) -> impl Iterator<Item = ValidationErrorKind> + 'a {
+        self.required_annotations
+            .iter()
+            .filter(move |key| {
+                !p.annotations()
+                    .any(|(present, _)| AsRef::<str>::as_ref(present) == key.as_str())
+            })
+            .map(|key| ValidationErrorKind::missing_annotation(key.clone()))
+    }
+
+    /// Generate an `InvalidChainAnnotation` note if the policy's `@chain`
+    /// annotation is malformed, or an `UnknownChain` note for each chain it
+    /// lists which isn't known.
+    fn validate_chain_annotation(&self, p: &Template) -> Vec<ValidationErrorKind> {
+        let Some((_, value)) = p
+            .annotations()
+            .find(|(key, _)| AsRef::<str>::as_ref(*key) == CHAIN_ANNOTATION)
+        else {
+            return Vec::new();
+        };
+        match (parse_chain_annotation(value), &self.known_chains) {
+            (None, _) => vec![ValidationErrorKind::invalid_chain_annotation(
+                value.to_string(),
+            )],
+            (Some(chains), Some(known)) => chains
+                .difference(known)
+                .map(|chain| ValidationErrorKind::unknown_chain(*chain))
+                .collect(),
+            (Some(_), None) => Vec::new(),
+        }
+    }
+
+    /// Use `typechecker` to detect any type errors in the argument policy in
+    /// the context of the schema for this validator. Any detected type errors
+    /// are wrapped and returned as `ValidationErrorKind`s.
+    fn typecheck_policy<'a>(
         t: &'a Template,
-        mode: ValidationMode,
-    ) -> impl Iterator<Item = ValidationError> + 'a {
-        let typecheck = Typechecker::new(&self.schema, mode);
+        typechecker: &Typechecker<'_>,
+    ) -> impl Iterator<Item = ValidationError<'a>> + 'a {
         let mut type_errors = HashSet::new();
-        typecheck.typecheck_policy(t, &mut type_errors);
+        typechecker.typecheck_policy(t, &mut type_errors);
         type_errors.into_iter().map(|type_error| {
             let (kind, location) = type_error.kind_and_location();
             ValidationError::with_policy_id(t.id(), location, ValidationErrorKind::type_error(kind))
@@ -144,6 +252,8 @@ mod test {
                     foo_type.into(),
                     EntityType {
                         member_of_types: vec![],
+                        tags: None,
+                        derived_parents: vec![],
                         shape: AttributesOrContext::default(),
                     },
                 ),
@@ -151,6 +261,8 @@ mod test {
                     bar_type.into(),
                     EntityType {
                         member_of_types: vec![],
+                        tags: None,
+                        derived_parents: vec![],
                         shape: AttributesOrContext::default(),
                     },
                 ),
@@ -162,6 +274,8 @@ mod test {
                         resource_types: None,
                         principal_types: None,
                         context: AttributesOrContext::default(),
+                        context_normalization: None,
+                        requester_controlled: Vec::new(),
                     }),
                     member_of: None,
                     attributes: None,
@@ -329,4 +443,273 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn required_annotations() {
+        let schema: ValidatorSchema = serde_json::from_str::<SchemaFragment>(
+            r#"
+            {
+                "": {
+                    "entityTypes": { "User": {} },
+                    "actions": {
+                        "view": {
+                            "appliesTo": { "principalTypes": ["User"], "resourceTypes": ["User"] }
+                        }
+                    }
+                }
+            }
+        "#,
+        )
+        .expect("Schema parse error.")
+        .try_into()
+        .expect("Expected valid schema.");
+        let validator = Validator::new(schema).with_required_annotations(["owner", "review"]);
+
+        let mut set = PolicySet::new();
+        let annotated = parser::parse_policy(
+            Some("annotated".to_string()),
+            r#"@owner("alice") @review("2023-06-01") permit(principal, action, resource);"#,
+        )
+        .expect("Test Policy Should Parse");
+        set.add_static(annotated)
+            .expect("Policy already present in PolicySet");
+        assert!(validator
+            .validate(&set, ValidationMode::default())
+            .validation_passed());
+
+        let partial = parser::parse_policy(
+            Some("partial".to_string()),
+            r#"@owner("alice") permit(principal, action, resource);"#,
+        )
+        .expect("Test Policy Should Parse");
+        set.add_static(partial)
+            .expect("Policy already present in PolicySet");
+        let result = validator.validate(&set, ValidationMode::default());
+        let pid = ast::PolicyID::from_string("partial");
+        assert_eq!(
+            result.into_validation_errors().collect::<Vec<_>>(),
+            vec![ValidationError::with_policy_id(
+                &pid,
+                None,
+                ValidationErrorKind::missing_annotation("review".to_string())
+            )]
+        );
+    }
+
+    #[test]
+    fn chain_annotations() {
+        assert_eq!(
+            parse_chain_annotation(" 1, 8453,10"),
+            Some(BTreeSet::from([1, 10, 8453]))
+        );
+        assert_eq!(parse_chain_annotation(""), None);
+        assert_eq!(parse_chain_annotation("1,,10"), None);
+        assert_eq!(parse_chain_annotation("mainnet"), None);
+
+        let schema: ValidatorSchema = serde_json::from_str::<SchemaFragment>(
+            r#"
+            {
+                "": {
+                    "entityTypes": { "User": {} },
+                    "actions": {
+                        "view": {
+                            "appliesTo": { "principalTypes": ["User"], "resourceTypes": ["User"] }
+                        }
+                    }
+                }
+            }
+        "#,
+        )
+        .expect("Schema parse error.")
+        .try_into()
+        .expect("Expected valid schema.");
+        let mut set = PolicySet::new();
+        for (id, src) in [
+            ("any", "permit(principal, action, resource);"),
+            (
+                "base",
+                r#"@chain("8453") permit(principal, action, resource);"#,
+            ),
+            (
+                "multi",
+                r#"@chain("1, 10, 42161") permit(principal, action, resource);"#,
+            ),
+            (
+                "named",
+                r#"@chain("mainnet") permit(principal, action, resource);"#,
+            ),
+        ] {
+            set.add_static(
+                parser::parse_policy(Some(id.to_string()), src).expect("Test Policy Should Parse"),
+            )
+            .expect("Policy already present in PolicySet");
+        }
+        let errors = |validator: &Validator| {
+            let mut errors = validator
+                .validate(&set, ValidationMode::default())
+                .into_validation_errors()
+                .map(|e| {
+                    (
+                        e.location().policy_id().to_string(),
+                        e.error_kind().to_string(),
+                    )
+                })
+                .collect::<Vec<_>>();
+            errors.sort();
+            errors
+        };
+
+        let validator = Validator::new(schema);
+        assert_eq!(
+            errors(&validator),
+            vec![(
+                "named".to_string(),
+                ValidationErrorKind::invalid_chain_annotation("mainnet".to_string()).to_string()
+            )]
+        );
+        let validator = validator.with_known_chains([1, 10, 8453]);
+        assert_eq!(
+            errors(&validator),
+            vec![
+                (
+                    "multi".to_string(),
+                    ValidationErrorKind::unknown_chain(42161).to_string()
+                ),
+                (
+                    "named".to_string(),
+                    ValidationErrorKind::invalid_chain_annotation("mainnet".to_string())
+                        .to_string()
+                ),
+            ]
+        );
+    }
+    #[test]
+    fn open_context() {
+        use cedar_policy_core::entities::ContextJsonParser;
+        use cedar_policy_core::extensions::Extensions;
+
+        let schema: ValidatorSchema = serde_json::from_str::<SchemaFragment>(
+            r#"
+            {
+                "": {
+                    "entityTypes": { "User": {} },
+                    "actions": {
+                        "swap": {
+                            "appliesTo": {
+                                "principalTypes": ["User"],
+                                "resourceTypes": ["User"],
+                                "context": {
+                                    "type": "Record",
+                                    "attributes": { "amount": { "type": "Long" } }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        "#,
+        )
+        .expect("Schema parse error.")
+        .try_into()
+        .expect("Expected valid schema.");
+        let mut set = PolicySet::new();
+        set.add_static(
+            parser::parse_policy(
+                Some("slippage".to_string()),
+                r#"forbid(principal, action, resource) when { context.amount > 100 && context has slippage };"#,
+            )
+            .expect("Test Policy Should Parse"),
+        )
+        .expect("Policy already present in PolicySet");
+        let context = serde_json::json!({ "amount": 200, "slippage": 50 });
+        let swap: ast::EntityUID = r#"Action::"swap""#.parse().expect("valid action");
+
+        assert!(!Validator::new(schema.clone())
+            .validate(&set, ValidationMode::default())
+            .validation_passed());
+        let context_schema = schema.get_context_schema(&swap).expect("action in schema");
+        assert!(
+            ContextJsonParser::new(Some(&context_schema), Extensions::all_available())
+                .from_json_value(context.clone())
+                .is_err()
+        );
+
+        let schema = schema.with_context_mode(ContextMode::Open);
+        assert!(Validator::new(schema.clone())
+            .validate(&set, ValidationMode::default())
+            .validation_passed());
+        let context_schema = schema.get_context_schema(&swap).expect("action in schema");
+        let context = ContextJsonParser::new(Some(&context_schema), Extensions::all_available())
+            .from_json_value(context)
+            .expect("undeclared attributes are allowed");
+        assert_eq!(
+            context
+                .iter()
+                .map(|(k, _)| k.to_string())
+                .collect::<BTreeSet<_>>(),
+            BTreeSet::from(["amount".to_string(), "slippage".to_string()])
+        );
+    }
+
+    #[test]
+    fn templates_of_every_shape_are_validated_together() {
+        let schema: ValidatorSchema = serde_json::from_str::<SchemaFragment>(
+            r#"
+            {
+                "": {
+                    "entityTypes": {
+                        "User": { "memberOfTypes": ["Group"] },
+                        "Group": {},
+                        "Vault": { "shape": { "type": "Record", "attributes": {
+                            "limit": { "type": "Long" }
+                        } } }
+                    },
+                    "actions": {
+                        "withdraw": {
+                            "appliesTo": { "principalTypes": ["User"], "resourceTypes": ["Vault"] }
+                        }
+                    }
+                }
+            }
+        "#,
+        )
+        .expect("Schema parse error.")
+        .try_into()
+        .expect("Expected valid schema.");
+        let validator = Validator::new(schema);
+        let texts = [
+            r#"permit(principal == ?principal, action, resource) when { resource.limit > 10 };"#,
+            r#"permit(principal in ?principal, action, resource == ?resource);"#,
+            r#"permit(principal, action, resource in ?resource) when { resource.cap > 10 };"#,
+            r#"permit(principal in ?principal, action, resource) when { principal.limit > 1 };"#,
+            r#"permit(principal, action, resource) when { resource.limit > 10 };"#,
+            r#"forbid(principal, action, resource) when { resource.limit };"#,
+        ];
+        let mut set = PolicySet::new();
+        for round in 0..20 {
+            for (i, text) in texts.iter().enumerate() {
+                let id = format!("policy{round}_{i}");
+                let template = parser::parse_policy_template(Some(id), text)
+                    .expect("Test Policy Should Parse");
+                set.add_template(template)
+                    .expect("Policy already present in PolicySet");
+            }
+        }
+
+        let result = validator.validate(&set, ValidationMode::default());
+        let mut failed = 0;
+        for template in set.all_templates() {
+            let alone: Vec<_> = validator
+                .validate_policy(template, ValidationMode::default())
+                .collect();
+            let together: Vec<_> = result
+                .validation_errors()
+                .filter(|err| err.location().policy_id() == template.id())
+                .collect();
+            assert_eq!(together.len(), alone.len(), "{}", template.id());
+            assert!(together.iter().all(|err| alone.contains(err)));
+            failed += usize::from(!alone.is_empty());
+        }
+        assert_eq!(failed, 60);
+    }
 }
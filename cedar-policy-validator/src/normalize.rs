@@ -0,0 +1,267 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Request normalization: applies the defaults and coercions declared for an
+//! action's context (`contextNormalization` in the schema file) to context
+//! JSON, before it is parsed according to the declared context type.
+
+use cedar_policy_core::entities::{JsonDeserializationErrorContext, SchemaType, ValueParser};
+use cedar_policy_core::evaluator::RestrictedEvaluator;
+use cedar_policy_core::extensions::Extensions;
+use serde_json::Value;
+use smol_str::SmolStr;
+
+use crate::{Result, SchemaError, ValidatorActionId};
+
+impl ValidatorActionId {
+    /// Apply the defaults and coercions declared for this action's context to
+    /// context JSON. Omitted attributes which have a default are filled in,
+    /// and then attributes which are declared as coercible are converted to
+    /// their declared type where possible.
+    ///
+    /// Values which can't be converted are left unchanged, as is `context` if
+    /// it is not a JSON object, so that parsing the result reports the error.
+    pub fn normalize_context(&self, context: Value) -> Value {
+        match context {
+            Value::Object(mut attrs) => {
+                for (attr, default) in &self.context_normalization.defaults {
+                    attrs
+                        .entry(attr.to_string())
+                        .or_insert_with(|| default.clone());
+                }
+                for (attr, value) in attrs.iter_mut() {
+                    *value = self.normalize_context_attr(attr, value.take());
+                }
+                Value::Object(attrs)
+            }
+            context => context,
+        }
+    }
+
+    /// Apply the coercion declared for a single context attribute, if any, to
+    /// the JSON value of that attribute
+    pub fn normalize_context_attr(&self, attr: &str, value: Value) -> Value {
+        if self
+            .context_normalization
+            .coerce
+            .iter()
+            .any(|coerced| coerced == attr)
+        {
+            match self.context_attr_schema_type(attr) {
+                Some(ty) => coerce(value, &ty),
+                None => value,
+            }
+        } else {
+            value
+        }
+    }
+
+    /// An iterator over the default values declared for this action's context
+    /// attributes
+    pub fn context_defaults(&self) -> impl Iterator<Item = (&SmolStr, &Value)> {
+        self.context_normalization.defaults.iter()
+    }
+
+    /// Check that the defaults and coercions only mention declared context
+    /// attributes, and that each default (after coercion) is a valid value of
+    /// the declared type.
+    pub(crate) fn check_context_normalization(&self) -> Result<()> {
+        let normalization = &self.context_normalization;
+        if let Some(attr) = normalization
+            .coerce
+            .iter()
+            .chain(normalization.defaults.keys())
+            .find(|attr| self.context_attr(attr).is_none())
+        {
+            return Err(SchemaError::UndeclaredNormalizedContextAttr(
+                self.name.clone(),
+                attr.to_string(),
+            ));
+        }
+        let extensions = Extensions::all_available();
+        let parser = ValueParser::new(extensions.clone());
+        let evaluator = RestrictedEvaluator::new(&extensions);
+        for (attr, default) in &normalization.defaults {
+            let invalid = |reason: String| {
+                SchemaError::InvalidContextDefault(self.name.clone(), attr.to_string(), reason)
+            };
+            let ty = self
+                .context_attr_schema_type(attr)
+                .ok_or_else(|| invalid("attribute is not declared".into()))?;
+            let value = self.normalize_context_attr(attr, default.clone());
+            let rexpr = parser
+                .val_into_rexpr(value, Some(&ty), || {
+                    JsonDeserializationErrorContext::Context
+                })
+                .map_err(|e| invalid(e.to_string()))?;
+            let actual_ty = parser
+                .type_of_rexpr(rexpr.as_borrowed(), || {
+                    JsonDeserializationErrorContext::Context
+                })
+                .map_err(|e| invalid(e.to_string()))?;
+            if !actual_ty.is_consistent_with(&ty) {
+                return Err(invalid(format!("expected {ty}, got {actual_ty}")));
+            }
+            evaluator
+                .interpret(rexpr.as_borrowed())
+                .map_err(|e| invalid(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Get the declared type of a context attribute as a Core `SchemaType`
+    fn context_attr_schema_type(&self, attr: &str) -> Option<SchemaType> {
+        self.context_attr(attr).map(|attr_ty| {
+            // PANIC SAFETY: the context of a `ValidatorActionId` is constructed
+            // from a schema, so its attribute types are representable as
+            // `SchemaType`s
+            #[allow(clippy::expect_used)]
+            attr_ty
+                .attr_type
+                .clone()
+                .try_into()
+                .expect("failed to convert validator type into Core SchemaType")
+        })
+    }
+}
+
+/// Convert `value` into the JSON representation expected for `ty`, where there
+/// is an unambiguous conversion. Anything else is returned unchanged.
+fn coerce(value: Value, ty: &SchemaType) -> Value {
+    match (ty, value) {
+        (SchemaType::Long, Value::String(s)) => match s.parse::<i64>() {
+            Ok(n) => Value::from(n),
+            Err(_) => Value::String(s),
+        },
+        (SchemaType::Bool, Value::String(s)) => match s.as_str() {
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            _ => Value::String(s),
+        },
+        (SchemaType::String, Value::Number(n)) => Value::String(n.to_string()),
+        (SchemaType::String, Value::Bool(b)) => Value::String(b.to_string()),
+        // Extension values are written as the string argument to their
+        // constructor (e.g., `u256` or `decimal`), so a JSON number is
+        // converted to the string with the same digits
+        (SchemaType::Extension { .. }, Value::Number(n)) => Value::String(n.to_string()),
+        (SchemaType::Set { element_ty }, Value::Array(elements)) => Value::Array(
+            elements
+                .into_iter()
+                .map(|element| coerce(element, element_ty))
+                .collect(),
+        ),
+        (SchemaType::Record { attrs }, Value::Object(mut record)) => {
+            for (k, v) in record.iter_mut() {
+                if let Some(attr_ty) = attrs.get(k.as_str()) {
+                    *v = coerce(v.take(), attr_ty.schema_type());
+                }
+            }
+            Value::Object(record)
+        }
+        (_, value) => value,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cedar_policy_core::ast::EntityUID;
+    use serde_json::json;
+
+    use crate::{SchemaError, ValidatorSchema};
+
+    fn schema(normalization: serde_json::Value) -> Result<ValidatorSchema, SchemaError> {
+        ValidatorSchema::from_json_value(json!(
+        {"": {
+            "entityTypes": {},
+            "actions": {
+                "transfer": {
+                    "appliesTo": {
+                        "context": {
+                            "type": "Record",
+                            "attributes": {
+                                "chainId": { "type": "Long" },
+                                "amount": { "type": "Extension", "name": "u256" },
+                                "memo": { "type": "String", "required": false }
+                            }
+                        },
+                        "contextNormalization": normalization
+                    }
+                }
+            }
+        }}))
+    }
+
+    fn transfer() -> EntityUID {
+        EntityUID::with_eid_and_type("Action", "transfer").expect("valid uid")
+    }
+
+    #[test]
+    fn defaults_and_coercions() {
+        let schema = schema(json!({
+            "defaults": { "chainId": 1 },
+            "coerce": ["chainId", "amount", "memo"]
+        }))
+        .expect("valid schema");
+        let action = schema.get_action_id(&transfer()).expect("declared action");
+        assert_eq!(
+            action.normalize_context(json!({ "amount": 1000, "memo": 7 })),
+            json!({ "chainId": 1, "amount": "1000", "memo": "7" })
+        );
+        assert_eq!(
+            action.normalize_context(json!({ "chainId": "10", "amount": "5" })),
+            json!({ "chainId": 10, "amount": "5" })
+        );
+        // values which can't be coerced are left for parsing to reject
+        assert_eq!(
+            action.normalize_context(json!({ "chainId": "mainnet", "amount": "5" })),
+            json!({ "chainId": "mainnet", "amount": "5" })
+        );
+    }
+
+    #[test]
+    fn no_coercion_unless_declared() {
+        let schema = schema(json!({ "defaults": { "chainId": 1 } })).expect("valid schema");
+        let action = schema.get_action_id(&transfer()).expect("declared action");
+        assert_eq!(
+            action.normalize_context(json!({ "chainId": "10", "amount": 5 })),
+            json!({ "chainId": "10", "amount": 5 })
+        );
+    }
+
+    #[test]
+    fn undeclared_attr() {
+        match schema(json!({ "coerce": ["fee"] })) {
+            Err(SchemaError::UndeclaredNormalizedContextAttr(_, attr)) => assert_eq!(attr, "fee"),
+            r => panic!("expected an undeclared attribute error, got {r:?}"),
+        }
+        match schema(json!({ "defaults": { "fee": 1 } })) {
+            Err(SchemaError::UndeclaredNormalizedContextAttr(_, attr)) => assert_eq!(attr, "fee"),
+            r => panic!("expected an undeclared attribute error, got {r:?}"),
+        }
+    }
+
+    #[test]
+    fn invalid_default() {
+        match schema(json!({ "defaults": { "chainId": "one" } })) {
+            Err(SchemaError::InvalidContextDefault(_, attr, _)) => assert_eq!(attr, "chainId"),
+            r => panic!("expected an invalid default error, got {r:?}"),
+        }
+        match schema(json!({ "defaults": { "amount": "-1" } })) {
+            Err(SchemaError::InvalidContextDefault(_, attr, _)) => assert_eq!(attr, "amount"),
+            r => panic!("expected an invalid default error, got {r:?}"),
+        }
+    }
+}
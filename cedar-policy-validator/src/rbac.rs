@@ -44,7 +44,8 @@ impl<'a> From<&'a PrincipalOrResourceConstraint> for HeadConstraint<'a> {
     }
 }
 
-use crate::expr_iterator::policy_entity_uids;
+use crate::expr_iterator::{policy_entity_uids, policy_is_entity_types};
+use crate::extensions::is_extension_type;
 
 use super::{
     fuzzy_match::fuzzy_search, schema::*, validation_result::ValidationErrorKind, Validator,
@@ -65,30 +66,51 @@ impl Validator {
             .map(ToString::to_string)
             .collect::<Vec<_>>();
 
-        policy_entity_uids(template).filter_map(move |euid| {
-            let entity_type = euid.entity_type();
-            match entity_type {
-                cedar_policy_core::ast::EntityType::Unspecified => Some(
-                    ValidationErrorKind::unspecified_entity(euid.eid().to_string()),
-                ),
-                cedar_policy_core::ast::EntityType::Concrete(name) => {
-                    let is_action_entity_type = is_action_entity_type(name);
-                    let is_known_entity_type = self.schema.is_known_entity_type(name);
-
-                    if !is_action_entity_type && !is_known_entity_type {
-                        let actual_entity_type = entity_type.to_string();
-                        let suggested_entity_type =
-                            fuzzy_search(&actual_entity_type, known_entity_types.as_slice());
-                        Some(ValidationErrorKind::unrecognized_entity_type(
-                            actual_entity_type,
-                            suggested_entity_type,
-                        ))
-                    } else {
-                        None
-                    }
+        // Entity types tested with `is` must also be declared in the schema,
+        // unless they name an extension type.
+        let unrecognized_is_types = policy_is_entity_types(template).filter_map({
+            let known_entity_types = known_entity_types.clone();
+            move |name| {
+                if self.schema.is_known_entity_type(name) || is_extension_type(name) {
+                    None
+                } else {
+                    let actual_entity_type = name.to_string();
+                    let suggested_entity_type =
+                        fuzzy_search(&actual_entity_type, known_entity_types.as_slice());
+                    Some(ValidationErrorKind::unrecognized_entity_type(
+                        actual_entity_type,
+                        suggested_entity_type,
+                    ))
                 }
             }
-        })
+        });
+
+        policy_entity_uids(template)
+            .filter_map(move |euid| {
+                let entity_type = euid.entity_type();
+                match entity_type {
+                    cedar_policy_core::ast::EntityType::Unspecified => Some(
+                        ValidationErrorKind::unspecified_entity(euid.eid().to_string()),
+                    ),
+                    cedar_policy_core::ast::EntityType::Concrete(name) => {
+                        let is_action_entity_type = is_action_entity_type(name);
+                        let is_known_entity_type = self.schema.is_known_entity_type(name);
+
+                        if !is_action_entity_type && !is_known_entity_type {
+                            let actual_entity_type = entity_type.to_string();
+                            let suggested_entity_type =
+                                fuzzy_search(&actual_entity_type, known_entity_types.as_slice());
+                            Some(ValidationErrorKind::unrecognized_entity_type(
+                                actual_entity_type,
+                                suggested_entity_type,
+                            ))
+                        } else {
+                            None
+                        }
+                    }
+                }
+            })
+            .chain(unrecognized_is_types)
     }
 
     /// Generate UnrecognizedActionId notes for every entity id with an action
@@ -381,7 +403,7 @@ impl Validator {
     ) -> Box<dyn Iterator<Item = K> + 'a>
     where
         H: 'a + HeadVar<K>,
-        K: 'a + Clone,
+        K: 'a + Clone + PartialEq,
     {
         match head_var_condition {
             HeadConstraint::Action(ActionConstraint::Any)
@@ -411,6 +433,29 @@ impl Validator {
             HeadConstraint::PrincipalOrResource(PrincipalOrResourceConstraint::In(
                 EntityReference::Slot,
             )) => Box::new(var.get_known_vars(&self.schema).map(Clone::clone)),
+            HeadConstraint::PrincipalOrResource(PrincipalOrResourceConstraint::Is(entity_type))
+            | HeadConstraint::PrincipalOrResource(PrincipalOrResourceConstraint::IsIn(
+                entity_type,
+                EntityReference::Slot,
+            )) => {
+                // <var> is <entity type>
+                Box::new(
+                    var.get_entity_type_component_if_present(&self.schema, entity_type)
+                        .into_iter(),
+                )
+            }
+            HeadConstraint::PrincipalOrResource(PrincipalOrResourceConstraint::IsIn(
+                entity_type,
+                EntityReference::EUID(euid),
+            )) => {
+                // <var> is <entity type> in <literal euid>
+                let component = var.get_entity_type_component_if_present(&self.schema, entity_type);
+                Box::new(
+                    self.schema
+                        .get_entities_in(var, euid.as_ref().clone())
+                        .filter(move |k| Some(k) == component.as_ref()),
+                )
+            }
             HeadConstraint::Action(ActionConstraint::In(euids)) => {
                 // <var> in [<literal euid>...]
                 Box::new(
@@ -486,6 +531,8 @@ mod test {
                     user_type.into(),
                     EntityType {
                         member_of_types: vec![group_type.into()],
+                        tags: None,
+                        derived_parents: vec![],
                         shape: AttributesOrContext::default(),
                     },
                 ),
@@ -493,6 +540,8 @@ mod test {
                     group_type.into(),
                     EntityType {
                         member_of_types: vec![],
+                        tags: None,
+                        derived_parents: vec![],
                         shape: AttributesOrContext::default(),
                     },
                 ),
@@ -500,6 +549,8 @@ mod test {
                     widget_type.into(),
                     EntityType {
                         member_of_types: vec![bin_type.into()],
+                        tags: None,
+                        derived_parents: vec![],
                         shape: AttributesOrContext::default(),
                     },
                 ),
@@ -507,6 +558,8 @@ mod test {
                     bin_type.into(),
                     EntityType {
                         member_of_types: vec![],
+                        tags: None,
+                        derived_parents: vec![],
                         shape: AttributesOrContext::default(),
                     },
                 ),
@@ -518,6 +571,8 @@ mod test {
                         resource_types: Some(vec![widget_type.into()]),
                         principal_types: Some(vec![user_type.into()]),
                         context: AttributesOrContext::default(),
+                        context_normalization: None,
+                        requester_controlled: Vec::new(),
                     }),
                     member_of: None,
                     attributes: None,
@@ -563,6 +618,8 @@ mod test {
                 foo_type.into(),
                 EntityType {
                     member_of_types: vec![],
+                    tags: None,
+                    derived_parents: vec![],
                     shape: AttributesOrContext::default(),
                 },
             )],
@@ -598,6 +655,8 @@ mod test {
                 "foo_type".into(),
                 EntityType {
                     member_of_types: vec![],
+                    tags: None,
+                    derived_parents: vec![],
                     shape: AttributesOrContext::default(),
                 },
             )],
@@ -640,6 +699,51 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn validate_is_entity_type_not_in_singleton_schema() -> Result<()> {
+        let schema_file = NamespaceDefinition::new(
+            [(
+                "foo_type".into(),
+                EntityType {
+                    member_of_types: vec![],
+                    tags: None,
+                    derived_parents: vec![],
+                    shape: AttributesOrContext::default(),
+                },
+            )],
+            [],
+        );
+        let singleton_schema = schema_file.try_into().unwrap();
+        let policy = parse_policy(
+            Some("policy0".to_string()),
+            r#"permit(principal is foo_typ, action, resource) when { resource is foo_type };"#,
+        )
+        .expect("Policy should parse.");
+        let (policy, _) = Template::link_static_policy(policy);
+
+        let validate = Validator::new(singleton_schema);
+        let notes: Vec<ValidationErrorKind> = validate.validate_entity_types(&policy).collect();
+
+        assert_eq!(1, notes.len());
+        match notes.get(0) {
+            Some(ValidationErrorKind::UnrecognizedEntityType(UnrecognizedEntityType {
+                actual_entity_type,
+                suggested_entity_type,
+            })) => {
+                assert_eq!("foo_typ", actual_entity_type);
+                assert_eq!(
+                    "foo_type",
+                    suggested_entity_type
+                        .as_ref()
+                        .expect("Expected a suggested entity type")
+                );
+            }
+            _ => panic!("Unexpected variant of ValidationErrorKind."),
+        };
+
+        Ok(())
+    }
+
     #[test]
     fn validate_action_id_empty_schema() -> Result<()> {
         let entity = EntityUID::with_eid_and_type("Action", "foo_name")
@@ -719,6 +823,8 @@ mod test {
                 p_name.into(),
                 EntityType {
                     member_of_types: vec![],
+                    tags: None,
+                    derived_parents: vec![],
                     shape: AttributesOrContext::default(),
                 },
             )],
@@ -751,6 +857,8 @@ mod test {
                 p_name.into(),
                 EntityType {
                     member_of_types: vec![],
+                    tags: None,
+                    derived_parents: vec![],
                     shape: AttributesOrContext::default(),
                 },
             )],
@@ -783,6 +891,8 @@ mod test {
                 p_name.into(),
                 EntityType {
                     member_of_types: vec![],
+                    tags: None,
+                    derived_parents: vec![],
                     shape: AttributesOrContext::default(),
                 },
             )],
@@ -1134,6 +1244,8 @@ mod test {
                 foo_type.into(),
                 EntityType {
                     member_of_types: vec![],
+                    tags: None,
+                    derived_parents: vec![],
                     shape: AttributesOrContext::default(),
                 },
             )],
@@ -1169,6 +1281,8 @@ mod test {
                     principal_type.into(),
                     EntityType {
                         member_of_types: vec![],
+                        tags: None,
+                        derived_parents: vec![],
                         shape: AttributesOrContext::default(),
                     },
                 ),
@@ -1176,6 +1290,8 @@ mod test {
                     resource_type.into(),
                     EntityType {
                         member_of_types: vec![],
+                        tags: None,
+                        derived_parents: vec![],
                         shape: AttributesOrContext::default(),
                     },
                 ),
@@ -1187,6 +1303,8 @@ mod test {
                         resource_types: Some(vec![resource_type.into()]),
                         principal_types: Some(vec![principal_type.into()]),
                         context: AttributesOrContext::default(),
+                        context_normalization: None,
+                        requester_controlled: Vec::new(),
                     }),
                     member_of: Some(vec![]),
                     attributes: None,
@@ -1377,6 +1495,8 @@ mod test {
                     principal_type.into(),
                     EntityType {
                         member_of_types: vec![],
+                        tags: None,
+                        derived_parents: vec![],
                         shape: AttributesOrContext::default(),
                     },
                 ),
@@ -1384,6 +1504,8 @@ mod test {
                     resource_type.into(),
                     EntityType {
                         member_of_types: vec![resource_parent_type.into()],
+                        tags: None,
+                        derived_parents: vec![],
                         shape: AttributesOrContext::default(),
                     },
                 ),
@@ -1391,6 +1513,8 @@ mod test {
                     resource_parent_type.into(),
                     EntityType {
                         member_of_types: vec![resource_grandparent_type.into()],
+                        tags: None,
+                        derived_parents: vec![],
                         shape: AttributesOrContext::default(),
                     },
                 ),
@@ -1398,6 +1522,8 @@ mod test {
                     resource_grandparent_type.into(),
                     EntityType {
                         member_of_types: vec![],
+                        tags: None,
+                        derived_parents: vec![],
                         shape: AttributesOrContext::default(),
                     },
                 ),
@@ -1410,6 +1536,8 @@ mod test {
                             resource_types: Some(vec![resource_type.into()]),
                             principal_types: Some(vec![principal_type.into()]),
                             context: AttributesOrContext::default(),
+                            context_normalization: None,
+                            requester_controlled: Vec::new(),
                         }),
                         member_of: Some(vec![ActionEntityUID {
                             ty: None,
@@ -24,8 +24,8 @@ use std::collections::{hash_map::Entry, HashMap, HashSet};
 use std::sync::Arc;
 
 use cedar_policy_core::{
-    ast::{Eid, Entity, EntityType, EntityUID, Id, Name, RestrictedExpr},
-    entities::{Entities, JSONValue, TCComputation},
+    ast::{Eid, Entity, EntityType, EntityUID, Id, Literal, Name, RestrictedExpr},
+    entities::{DerivedParent, Entities, JSONValue, TCComputation},
     parser::err::ParseErrors,
     transitive_closure::{compute_tc, TCNode},
     FromNormalizedStr,
@@ -38,8 +38,8 @@ use crate::types::OpenTag;
 use crate::{
     schema_file_format,
     types::{AttributeType, Attributes, EntityRecordKind, Type},
-    ActionEntityUID, ActionType, SchemaFragment, SchemaType, SchemaTypeVariant, TypeOfAttribute,
-    SCHEMA_TYPE_VARIANT_TAGS,
+    ActionEntityUID, ActionType, ContextNormalization, SchemaFragment, SchemaType,
+    SchemaTypeVariant, TypeOfAttribute, SCHEMA_TYPE_VARIANT_TAGS,
 };
 
 use super::err::*;
@@ -75,6 +75,23 @@ pub enum ActionBehavior {
     PermitAttributes,
 }
 
+/// Whether a request context may have attributes its action's context type
+/// doesn't declare.
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Default, Serialize)]
+pub enum ContextMode {
+    /// Contexts have only the declared attributes. Requests with undeclared
+    /// attributes are rejected, and `context has a` is always false for an
+    /// undeclared `a`.
+    #[default]
+    Closed,
+    /// Contexts may have attributes beyond the declared ones, e.g. when an
+    /// intent decoder adds fields the schema doesn't know about yet. Context
+    /// records are typed as open records, so policies which validate against
+    /// the declared attributes still validate, and undeclared attributes of
+    /// a request are parsed without the schema.
+    Open,
+}
+
 /// A single namespace definition from the schema json processed into a form
 /// which is closer to that used by the validator. The processing includes
 /// detection of some errors, for example, parse errors in entity type names or
@@ -127,6 +144,13 @@ pub struct EntityTypeFragment {
     /// namespace, so we will check if they are declared in any fragment when
     /// constructing a `ValidatorSchema`.
     parents: HashSet<Name>,
+    /// The type of this entity type's tag values, if it has tags. Wrapped in a
+    /// `WithUnresolvedTypeDefs` for the same reason as `attributes`.
+    tags: Option<WithUnresolvedTypeDefs<Type>>,
+    /// Parents which entities of this type have whenever an attribute
+    /// satisfies a comparison. The types of these parents are also included
+    /// in `parents`.
+    derived_parents: Vec<DerivedParent>,
 }
 
 /// Action declarations held in a `ValidatorNamespaceDef`. Entity types
@@ -142,6 +166,11 @@ pub struct ActionFragment {
     /// a `WithUnresolvedTypeDefs` because it may refer to common types which
     /// are not defined in this fragment.
     context: WithUnresolvedTypeDefs<Type>,
+    /// Defaults and coercions for the context, applied by request
+    /// normalization.
+    context_normalization: ContextNormalization,
+    /// The context attributes which the requester supplies unverified.
+    requester_controlled: HashSet<SmolStr>,
     /// The principals and resources that an action can be applied to.
     applies_to: ValidatorApplySpec,
     /// The direct parent action entities for this action.
@@ -308,7 +337,15 @@ impl ValidatorNamespaceDef {
                     )
                     .map_err(SchemaError::ParseEntityType)?;
 
-                    let parents = entity_type
+                    let derived_parents = entity_type
+                        .derived_parents
+                        .into_iter()
+                        .map(|derived| {
+                            Self::convert_derived_parent(&name, derived, schema_namespace)
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+
+                    let mut parents = entity_type
                         .member_of_types
                         .iter()
                         .map(|parent| -> Result<_> {
@@ -319,17 +356,32 @@ impl ValidatorNamespaceDef {
                             .map_err(SchemaError::ParseEntityType)
                         })
                         .collect::<Result<HashSet<_>>>()?;
+                    parents.extend(derived_parents.iter().filter_map(|derived| {
+                        match derived.parent.entity_type() {
+                            EntityType::Concrete(parent_type) => Some(parent_type.clone()),
+                            EntityType::Unspecified => None,
+                        }
+                    }));
 
                     let attributes = Self::try_schema_type_into_validator_type(
                         schema_namespace,
                         entity_type.shape.into_inner(),
                     )?;
 
+                    let tags = entity_type
+                        .tags
+                        .map(|tags| {
+                            Self::try_schema_type_into_validator_type(schema_namespace, tags)
+                        })
+                        .transpose()?;
+
                     Ok((
                         name,
                         EntityTypeFragment {
                             attributes,
                             parents,
+                            tags,
+                            derived_parents,
                         },
                     ))
                 })
@@ -337,6 +389,49 @@ impl ValidatorNamespaceDef {
         })
     }
 
+    /// Convert a `derivedParents` entry from the schema file format. The
+    /// parent's entity type is resolved in the same way as a `memberOfTypes`
+    /// entry. Whether the attribute is declared with the type of the value is
+    /// checked later, once common types have been resolved.
+    fn convert_derived_parent(
+        entity_type: &Name,
+        derived: schema_file_format::DerivedParent,
+        schema_namespace: Option<&Name>,
+    ) -> Result<DerivedParent> {
+        let invalid =
+            |reason: String| SchemaError::InvalidDerivedParent(entity_type.clone(), reason);
+        let parent_type = Self::parse_possibly_qualified_name_with_default_namespace(
+            &derived.parent.ty,
+            schema_namespace,
+        )
+        .map_err(SchemaError::ParseEntityType)?;
+        let value = match derived.value {
+            serde_json::Value::Bool(b) => Literal::Bool(b),
+            serde_json::Value::Number(n) => Literal::Long(
+                n.as_i64()
+                    .ok_or_else(|| invalid(format!("`{n}` is not a 64-bit integer")))?,
+            ),
+            serde_json::Value::String(s) => Literal::String(s.into()),
+            v => {
+                return Err(invalid(format!(
+                    "`{v}` is not a boolean, integer, or string"
+                )))
+            }
+        };
+        if derived.op.is_ordering() && !matches!(value, Literal::Long(_)) {
+            return Err(invalid(format!(
+                "`{}` can only compare against an integer",
+                derived.op
+            )));
+        }
+        Ok(DerivedParent {
+            parent: EntityUID::from_components(parent_type, Eid::new(derived.parent.id)),
+            attr: derived.attribute,
+            op: derived.op,
+            value,
+        })
+    }
+
     // Helper to get types from JSONValues. Currently doesn't support all
     // JSONValue types. Note: If this function is extended to cover move
     // `JSONValue`s, we must update `convert_attr_jsonval_map_to_attributes` to
@@ -443,13 +538,21 @@ impl ValidatorNamespaceDef {
                         schema_namespace,
                     )?;
 
-                    let (principal_types, resource_types, context) = action_type
+                    let (
+                        principal_types,
+                        resource_types,
+                        context,
+                        context_normalization,
+                        requester_controlled,
+                    ) = action_type
                         .applies_to
                         .map(|applies_to| {
                             (
                                 applies_to.principal_types,
                                 applies_to.resource_types,
                                 applies_to.context,
+                                applies_to.context_normalization,
+                                applies_to.requester_controlled,
                             )
                         })
                         .unwrap_or_default();
@@ -486,6 +589,8 @@ impl ValidatorNamespaceDef {
                         action_id,
                         ActionFragment {
                             context,
+                            context_normalization: context_normalization.unwrap_or_default(),
+                            requester_controlled: requester_controlled.into_iter().collect(),
                             applies_to,
                             parents,
                             attribute_types,
@@ -785,6 +890,10 @@ pub struct ValidatorSchema {
     #[serde(rename = "actionIds")]
     #[serde_as(as = "Vec<(_, _)>")]
     action_ids: HashMap<EntityUID, ValidatorActionId>,
+
+    /// Whether contexts may have undeclared attributes
+    #[serde(rename = "contextMode")]
+    context_mode: ContextMode,
 }
 
 impl std::str::FromStr for ValidatorSchema {
@@ -819,9 +928,23 @@ impl ValidatorSchema {
         Self {
             entity_types: HashMap::new(),
             action_ids: HashMap::new(),
+            context_mode: ContextMode::default(),
         }
     }
 
+    /// Set whether contexts may have attributes their action's context type
+    /// doesn't declare.
+    pub fn with_context_mode(mut self, context_mode: ContextMode) -> Self {
+        self.context_mode = context_mode;
+        self
+    }
+
+    /// Whether contexts may have attributes their action's context type
+    /// doesn't declare.
+    pub fn context_mode(&self) -> ContextMode {
+        self.context_mode
+    }
+
     /// Construct a `ValidatorSchema` from a JSON value (which should be an
     /// object matching the `SchemaFileFormat` shape).
     pub fn from_json_value(json: serde_json::Value) -> Result<Self> {
@@ -924,6 +1047,11 @@ impl ValidatorSchema {
                         .ok_or(SchemaError::ContextOrShapeNotRecord(
                             ContextOrShape::EntityTypeShape(name),
                         ))?,
+                        tags: entity_type
+                            .tags
+                            .map(|tags| tags.resolve_type_defs(&type_defs))
+                            .transpose()?,
+                        derived_parents: entity_type.derived_parents,
                     },
                 ))
             })
@@ -955,6 +1083,8 @@ impl ValidatorSchema {
                         .ok_or(SchemaError::ContextOrShapeNotRecord(
                             ContextOrShape::ActionContext(name),
                         ))?,
+                        context_normalization: action.context_normalization,
+                        requester_controlled: action.requester_controlled,
                         attribute_types: action.attribute_types,
                         attributes: action.attributes,
                     },
@@ -962,6 +1092,18 @@ impl ValidatorSchema {
             })
             .collect::<Result<HashMap<_, _>>>()?;
 
+        // Context defaults and coercions, and requester controlled attributes,
+        // can only be checked once the context type has been resolved.
+        for action in action_ids.values() {
+            action.check_context_normalization()?;
+            action.check_requester_controlled()?;
+        }
+        // Likewise, derived parents can only be checked against attribute
+        // types once the entity type shapes have been resolved.
+        for entity_type in entity_types.values() {
+            entity_type.check_derived_parents()?;
+        }
+
         // We constructed entity types and actions with child maps, but we need
         // transitively closed descendants.
         compute_tc(&mut entity_types, false)?;
@@ -985,6 +1127,7 @@ impl ValidatorSchema {
         Ok(ValidatorSchema {
             entity_types,
             action_ids,
+            context_mode: ContextMode::default(),
         })
     }
 
@@ -1019,6 +1162,9 @@ impl ValidatorSchema {
                     &mut undeclared_e,
                 );
             }
+            if let Some(tag_type) = entity_type.tag_type() {
+                Self::check_undeclared_in_type(tag_type, entity_types, &mut undeclared_e);
+            }
         }
 
         // Undeclared actions in a `memberOf` list.
@@ -1142,6 +1288,11 @@ impl ValidatorSchema {
         self.entity_types.keys()
     }
 
+    /// An iterator over the actions in the schema
+    pub fn action_ids(&self) -> impl Iterator<Item = &ValidatorActionId> {
+        self.action_ids.values()
+    }
+
     /// An iterator matching the entity Types to their Validator Types
     pub fn entity_types(&self) -> impl Iterator<Item = (&Name, &ValidatorEntityType)> {
         self.entity_types.iter()
@@ -1206,13 +1357,16 @@ impl ValidatorSchema {
             // representable. The values are representable because they are
             // taken from the context of a `ValidatorActionId` which was
             // constructed directly from a schema.
-            ContextSchema(crate::types::Type::record_with_attributes(
-                action_id
-                    .context
-                    .iter()
-                    .map(|(k, v)| (k.clone(), v.clone())),
-                OpenTag::ClosedAttributes,
-            ))
+            ContextSchema(
+                crate::types::Type::record_with_attributes(
+                    action_id
+                        .context
+                        .iter()
+                        .map(|(k, v)| (k.clone(), v.clone())),
+                    OpenTag::ClosedAttributes,
+                ),
+                self.context_mode,
+            )
         })
     }
 
@@ -1374,12 +1528,30 @@ impl cedar_policy_core::entities::EntityTypeDescription for EntityTypeDescriptio
     fn allowed_parent_types(&self) -> Arc<HashSet<cedar_policy_core::ast::EntityType>> {
         Arc::clone(&self.allowed_parent_types)
     }
+
+    fn derived_parents(&self) -> &[DerivedParent] {
+        self.validator_type.derived_parents()
+    }
+
+    fn tag_type(&self) -> Option<cedar_policy_core::entities::SchemaType> {
+        let tag_type = self.validator_type.tag_type()?;
+        // See `attr_type` above: the tag type is also taken from a
+        // `ValidatorEntityType` which was constructed from a schema.
+        // PANIC SAFETY: see above
+        #[allow(clippy::expect_used)]
+        let core_schema_type: cedar_policy_core::entities::SchemaType = tag_type
+            .clone()
+            .try_into()
+            .expect("failed to convert validator type into Core SchemaType");
+        debug_assert!(tag_type.is_consistent_with(&core_schema_type));
+        Some(core_schema_type)
+    }
 }
 
 /// Struct which carries enough information that it can impl Core's
 /// `ContextSchema` INVARIANT: The `Type` stored in this struct must be
 /// representable as a `SchemaType` to avoid panicking in `context_type`.
-struct ContextSchema(crate::types::Type);
+struct ContextSchema(crate::types::Type, ContextMode);
 
 /// A `Type` contains all the information we need for a Core `ContextSchema`.
 impl cedar_policy_core::entities::ContextSchema for ContextSchema {
@@ -1391,6 +1563,10 @@ impl cedar_policy_core::entities::ContextSchema for ContextSchema {
             .try_into()
             .expect("failed to convert validator type into Core SchemaType")
     }
+
+    fn open_attributes(&self) -> bool {
+        self.1 == ContextMode::Open
+    }
 }
 
 /// Contains entity type information for use by the validator. The contents of
@@ -1410,6 +1586,15 @@ pub struct ValidatorEntityType {
     /// The attributes associated with this entity. Keys are the attribute
     /// identifiers while the values are the type of the attribute.
     pub(crate) attributes: Attributes,
+
+    /// The type of the tag values on entities of this type, or `None` if
+    /// entities of this type may not have tags.
+    pub(crate) tags: Option<Type>,
+
+    /// Parents which entities of this type have whenever an attribute
+    /// satisfies a comparison.
+    #[serde(skip)]
+    pub(crate) derived_parents: Vec<DerivedParent>,
 }
 
 impl ValidatorEntityType {
@@ -1423,6 +1608,44 @@ impl ValidatorEntityType {
         self.attributes.iter()
     }
 
+    /// Get the type of this entity's tag values, if it may have tags
+    pub fn tag_type(&self) -> Option<&Type> {
+        self.tags.as_ref()
+    }
+
+    /// Get the parents which entities of this type have whenever an attribute
+    /// satisfies a comparison
+    pub fn derived_parents(&self) -> &[DerivedParent] {
+        &self.derived_parents
+    }
+
+    /// Check that each derived parent compares a declared attribute against a
+    /// value of that attribute's type.
+    fn check_derived_parents(&self) -> Result<()> {
+        for derived in &self.derived_parents {
+            let invalid =
+                |reason: String| SchemaError::InvalidDerivedParent(self.name.clone(), reason);
+            let attr_ty = self
+                .attr(&derived.attr)
+                .ok_or_else(|| invalid(format!("attribute `{}` is not declared", derived.attr)))?;
+            let value_ty = match &derived.value {
+                Literal::Bool(_) => Type::primitive_boolean(),
+                Literal::Long(_) => Type::primitive_long(),
+                Literal::String(_) => Type::primitive_string(),
+                Literal::EntityUID(uid) => {
+                    Type::possibly_unspecified_entity_reference(uid.entity_type().clone())
+                }
+            };
+            if attr_ty.attr_type != value_ty {
+                return Err(invalid(format!(
+                    "`{}` does not have the declared type of attribute `{}`",
+                    derived.value, derived.attr
+                )));
+            }
+        }
+        Ok(())
+    }
+
     /// Return `true` if this entity type has an `EntityType` declared as a
     /// possible descendant in the schema. This takes an `EntityType` rather
     /// than a `Name`, It's not possible to declare the unspecified entity type
@@ -1476,6 +1699,16 @@ pub struct ValidatorActionId {
     /// attribute identifiers while the values are the type of the attribute.
     pub(crate) context: Attributes,
 
+    /// Defaults and coercions for the context attributes of this action,
+    /// applied by request normalization.
+    #[serde(rename = "contextNormalization")]
+    pub(crate) context_normalization: ContextNormalization,
+
+    /// The context attributes of this action whose values are supplied by the
+    /// requester and not verified by the provider.
+    #[serde(rename = "requesterControlled")]
+    pub(crate) requester_controlled: HashSet<SmolStr>,
+
     /// The attribute types for this action, used for typechecking.
     pub(crate) attribute_types: Attributes,
 
@@ -1486,10 +1719,56 @@ pub struct ValidatorActionId {
 }
 
 impl ValidatorActionId {
+    /// The action entity
+    pub fn name(&self) -> &EntityUID {
+        &self.name
+    }
+
     /// An iterator over the attributes of this action's required context
     pub fn context(&self) -> impl Iterator<Item = (&SmolStr, &AttributeType)> {
         self.context.iter()
     }
+
+    /// Get the type of the context attribute with the given name, if this
+    /// action's context declares it
+    pub fn context_attr(&self, attr: &str) -> Option<&AttributeType> {
+        self.context.get_attr(attr)
+    }
+
+    /// Whether the context attribute with the given name is supplied by the
+    /// requester and not verified by the provider
+    pub fn is_requester_controlled(&self, attr: &str) -> bool {
+        self.requester_controlled.contains(attr)
+    }
+
+    /// Check that only declared context attributes are requester controlled
+    pub(crate) fn check_requester_controlled(&self) -> Result<()> {
+        match self
+            .requester_controlled
+            .iter()
+            .find(|attr| self.context_attr(attr).is_none())
+        {
+            Some(attr) => Err(SchemaError::UndeclaredRequesterControlledContextAttr(
+                self.name.clone(),
+                attr.to_string(),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// An iterator over the principal entity types this action applies to.
+    /// Contains the unspecified entity type when `principalTypes` is omitted
+    /// in the schema.
+    pub fn applies_to_principals(&self) -> impl Iterator<Item = &EntityType> {
+        self.applies_to.applicable_principal_types()
+    }
+
+    /// An iterator over the resource entity types this action applies to.
+    /// Contains the unspecified entity type when `resourceTypes` is omitted
+    /// in the schema.
+    pub fn applies_to_resources(&self) -> impl Iterator<Item = &EntityType> {
+        self.applies_to.applicable_resource_types()
+    }
 }
 
 impl TCNode<EntityUID> for ValidatorActionId {
@@ -1579,6 +1858,15 @@ pub(crate) trait HeadVar<K>: Copy {
         schema: &'a ValidatorSchema,
         euid: EntityUID,
     ) -> Option<Box<dyn Iterator<Item = &'a K> + 'a>>;
+
+    /// Extract the relevant component of an entity type if the entity type is
+    /// in the schema. Otherwise return None. Actions are never constrained by
+    /// an entity type, so this is always None for actions.
+    fn get_entity_type_component_if_present(
+        &self,
+        schema: &ValidatorSchema,
+        entity_type: &Name,
+    ) -> Option<K>;
 }
 
 /// Used to have `get_entities_satisfying_constraint` return the
@@ -1629,6 +1917,18 @@ impl HeadVar<Name> for PrincipalOrResourceHeadVar {
             None => None,
         }
     }
+
+    fn get_entity_type_component_if_present(
+        &self,
+        schema: &ValidatorSchema,
+        entity_type: &Name,
+    ) -> Option<Name> {
+        if schema.is_known_entity_type(entity_type) {
+            Some(entity_type.clone())
+        } else {
+            None
+        }
+    }
 }
 
 /// Used to have `get_entities_satisfying_constraint` return the
@@ -1674,6 +1974,14 @@ impl HeadVar<EntityUID> for ActionHeadVar {
             None => None,
         }
     }
+
+    fn get_entity_type_component_if_present(
+        &self,
+        _schema: &ValidatorSchema,
+        _entity_type: &Name,
+    ) -> Option<EntityUID> {
+        None
+    }
 }
 
 /// Used to write a schema implicitly overriding the default handling of action
@@ -3000,4 +3308,133 @@ mod test {
             "ExampleCo::Personnel::Action"
         );
     }
+
+    #[test]
+    fn test_derived_parents() {
+        let src = json!({
+            "": {
+                "entityTypes": {
+                    "Wallet": {
+                        "shape": {
+                            "type": "Record",
+                            "attributes": {
+                                "kycLevel": { "type": "Long" }
+                            }
+                        },
+                        "derivedParents": [
+                            {
+                                "parent": { "type": "Group", "id": "kyc2" },
+                                "attribute": "kycLevel",
+                                "op": ">=",
+                                "value": 2
+                            }
+                        ]
+                    },
+                    "Group": {}
+                },
+                "actions": {}
+            }
+        });
+        let schema_fragment =
+            serde_json::from_value::<SchemaFragment>(src).expect("Failed to parse schema");
+        let schema: ValidatorSchema = schema_fragment.try_into().unwrap();
+        // the parent's type is implicitly a `memberOfTypes` entry
+        let group = schema
+            .get_entity_type(&"Group".parse().unwrap())
+            .expect("Group should be declared");
+        assert!(group.descendants.contains(&"Wallet".parse().unwrap()));
+        let wallet = schema
+            .get_entity_type(&"Wallet".parse().unwrap())
+            .expect("Wallet should be declared");
+        assert_eq!(wallet.derived_parents().len(), 1);
+    }
+
+    #[test]
+    fn test_invalid_derived_parents() {
+        let schema_with = |attribute: &str, op: &str, value: serde_json::Value| {
+            let src = json!({
+                "": {
+                    "entityTypes": {
+                        "Wallet": {
+                            "shape": {
+                                "type": "Record",
+                                "attributes": {
+                                    "kycLevel": { "type": "Long" },
+                                    "region": { "type": "String" }
+                                }
+                            },
+                            "derivedParents": [
+                                {
+                                    "parent": { "type": "Group", "id": "g" },
+                                    "attribute": attribute,
+                                    "op": op,
+                                    "value": value
+                                }
+                            ]
+                        },
+                        "Group": {}
+                    },
+                    "actions": {}
+                }
+            });
+            let schema_fragment =
+                serde_json::from_value::<SchemaFragment>(src).expect("Failed to parse schema");
+            ValidatorSchema::try_from(schema_fragment)
+        };
+        let reason = |attribute: &str, op: &str, value: serde_json::Value| match schema_with(
+            attribute, op, value,
+        ) {
+            Err(SchemaError::InvalidDerivedParent(name, reason)) => {
+                assert_eq!(name.to_string(), "Wallet");
+                reason
+            }
+            other => panic!("expected an invalid derived parent error, got {other:?}"),
+        };
+        assert!(schema_with("region", "==", json!("eu")).is_ok());
+        assert_eq!(
+            reason("tier", ">=", json!(2)),
+            "attribute `tier` is not declared"
+        );
+        assert_eq!(
+            reason("kycLevel", "==", json!("2")),
+            r#"`"2"` does not have the declared type of attribute `kycLevel`"#
+        );
+        assert_eq!(
+            reason("region", "<", json!("eu")),
+            "`<` can only compare against an integer"
+        );
+        assert_eq!(
+            reason("region", "==", json!(["eu"])),
+            r#"`["eu"]` is not a boolean, integer, or string"#
+        );
+    }
+
+    #[test]
+    fn test_requester_controlled() {
+        let schema_with = |requester_controlled: serde_json::Value| {
+            let src = json!({"": {
+                "entityTypes": {},
+                "actions": {"withdraw": {"appliesTo": {
+                    "context": {"type": "Record", "attributes": {
+                        "claimedBalance": {"type": "Long"},
+                        "amount": {"type": "Long"}
+                    }},
+                    "requesterControlled": requester_controlled
+                }}}
+            }});
+            ValidatorSchema::from_json_value(src)
+        };
+        let schema = schema_with(json!(["claimedBalance"])).expect("valid schema");
+        let withdraw = schema
+            .get_action_id(&EntityUID::with_eid_and_type("Action", "withdraw").expect("valid uid"))
+            .expect("declared action");
+        assert!(withdraw.is_requester_controlled("claimedBalance"));
+        assert!(!withdraw.is_requester_controlled("amount"));
+        match schema_with(json!(["fee"])) {
+            Err(SchemaError::UndeclaredRequesterControlledContextAttr(_, attr)) => {
+                assert_eq!(attr, "fee")
+            }
+            other => panic!("expected an undeclared attribute error, got {other:?}"),
+        }
+    }
 }
@@ -14,7 +14,7 @@
  * limitations under the License.
  */
 
-use cedar_policy_core::entities::JSONValue;
+use cedar_policy_core::entities::{DerivedParentOp, JSONValue};
 use serde::{
     de::{MapAccess, Visitor},
     Deserialize, Serialize,
@@ -91,6 +91,41 @@ pub struct EntityType {
     pub member_of_types: Vec<SmolStr>,
     #[serde(default)]
     pub shape: AttributesOrContext,
+    /// Type of the values of this entity type's tags. When absent, entities of
+    /// this type have no tags.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<SchemaType>,
+    #[serde(default)]
+    #[serde(rename = "derivedParents")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub derived_parents: Vec<DerivedParent>,
+}
+
+/// A parent which entities of some type are in whenever one of their
+/// attributes satisfies a comparison, e.g., every `Wallet` with
+/// `kycLevel >= 2` is in `Group::"kyc2"`. The entity store adds these parents
+/// when it parses entities, so the group never drifts from the attribute.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DerivedParent {
+    /// The parent entity. Its type is resolved like a `memberOfTypes` entry
+    /// and it is implicitly added to `memberOfTypes`.
+    pub parent: DerivedParentUID,
+    /// The attribute which is compared. It must be declared in the shape.
+    pub attribute: SmolStr,
+    pub op: DerivedParentOp,
+    /// A boolean, integer, or string to compare the attribute against.
+    /// Orderings (`<`, `<=`, `>`, `>=`) require an integer.
+    pub value: serde_json::Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DerivedParentUID {
+    #[serde(rename = "type")]
+    pub ty: SmolStr,
+    pub id: SmolStr,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -153,6 +188,37 @@ pub struct ApplySpec {
     pub principal_types: Option<Vec<SmolStr>>,
     #[serde(default)]
     pub context: AttributesOrContext,
+    #[serde(default)]
+    #[serde(rename = "contextNormalization")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context_normalization: Option<ContextNormalization>,
+    /// Context attributes whose values are supplied by the requester and not
+    /// verified by the provider, e.g., a balance the requester claims to hold.
+    /// These may only name attributes declared in the action's context.
+    #[serde(default)]
+    #[serde(rename = "requesterControlled")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub requester_controlled: Vec<SmolStr>,
+}
+
+/// Rules applied to the context of a request for an action by request
+/// normalization, before the context is parsed according to its declared type.
+/// Both `defaults` and `coerce` may only name attributes which are declared in
+/// the action's context.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ContextNormalization {
+    /// Values for context attributes which are omitted from a request. These
+    /// are written in the same JSON format as a context for schema-based
+    /// parsing, so `__entity` and `__extn` escapes may be implicit.
+    #[serde(default)]
+    pub defaults: HashMap<SmolStr, serde_json::Value>,
+    /// Context attributes whose values are converted to their declared type
+    /// when they are written as a different JSON type, e.g., a string
+    /// containing a decimal integer for a `Long` attribute, or a JSON number
+    /// for an extension type such as `u256` whose constructor takes a string.
+    #[serde(default)]
+    pub coerce: Vec<SmolStr>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -659,6 +725,8 @@ mod test {
             resource_types: Some(vec!["Album".into()]),
             principal_types: Some(vec!["User".into()]),
             context: AttributesOrContext::default(),
+            context_normalization: None,
+            requester_controlled: Vec::new(),
         };
         assert_eq!(at.applies_to, Some(spec));
         assert_eq!(
@@ -85,7 +85,7 @@ pub fn confusable_string_checks<'a>(
                 TextKind::String(s) => permissable_str(s),
                 TextKind::Identifier(i) => permissable_ident(i),
                 TextKind::Pattern(p) => {
-                    let pat = Pattern::new(p.iter().copied());
+                    let pat = Pattern::new(p.iter().cloned());
                     let as_str = format!("{pat}");
                     permissable_str(&as_str)
                 }
@@ -195,6 +195,30 @@ impl TypeError {
             kind: TypeErrorKind::HierarchyNotRespected(HierarchyNotRespected { in_lhs, in_rhs }),
         }
     }
+
+    pub(crate) fn malformed_pattern<T>(on_expr: Expr<T>, pattern: String) -> Self {
+        Self {
+            on_expr: None,
+            source_location: on_expr.into_source_info(),
+            kind: TypeErrorKind::MalformedPattern(MalformedPattern { pattern }),
+        }
+    }
+
+    pub(crate) fn unsafe_tag_access<T>(on_expr: Expr<T>, tag: String) -> Self {
+        Self {
+            on_expr: None,
+            source_location: on_expr.into_source_info(),
+            kind: TypeErrorKind::UnsafeTagAccess(UnsafeTagAccess { tag }),
+        }
+    }
+
+    pub(crate) fn no_tags_allowed<T>(on_expr: Expr<T>, entity_ty: Type) -> Self {
+        Self {
+            on_expr: None,
+            source_location: on_expr.into_source_info(),
+            kind: TypeErrorKind::NoTagsAllowed(NoTagsAllowed { entity_ty }),
+        }
+    }
 }
 
 impl Display for TypeError {
@@ -277,6 +301,18 @@ pub enum TypeErrorKind {
             _ => "".to_string(),
         })]
     HierarchyNotRespected(HierarchyNotRespected),
+    /// A `glob` pattern contains a character class that is empty or has a
+    /// reversed range, so it can't be written in policy syntax.
+    #[error("malformed pattern `{}`: character classes must be nonempty and have ordered ranges", .0.pattern)]
+    MalformedPattern(MalformedPattern),
+    /// The typechecker could not conclude that an access to a tag was safe,
+    /// because it is not guarded by a `hasTag` check for the same tag.
+    #[error("unable to guarantee safety of access to tag {}. Guard the access with `hasTag`", .0.tag)]
+    UnsafeTagAccess(UnsafeTagAccess),
+    /// `getTag` was used on an entity type for which the schema does not
+    /// declare tags.
+    #[error("entities of type {} do not have tags according to the schema", .0.entity_ty)]
+    NoTagsAllowed(NoTagsAllowed),
 }
 
 /// Structure containing details about an unexpected type error.
@@ -347,6 +383,24 @@ pub struct HierarchyNotRespected {
     in_rhs: Option<Name>,
 }
 
+/// Structure containing details about a malformed pattern error
+#[derive(Debug, Hash, Eq, PartialEq)]
+pub struct MalformedPattern {
+    pattern: String,
+}
+
+/// Structure containing details about an unsafe tag access error
+#[derive(Debug, Hash, Eq, PartialEq)]
+pub struct UnsafeTagAccess {
+    tag: String,
+}
+
+/// Structure containing details about a no tags allowed error
+#[derive(Debug, Hash, Eq, PartialEq)]
+pub struct NoTagsAllowed {
+    entity_ty: Type,
+}
+
 /// Contains more detailed information about an attribute access when it occurs
 /// on an entity type expression or on the `context` variable. Track a `Vec` of
 /// attributes rather than a single attribute so that on `principal.foo.bar` can
@@ -20,10 +20,12 @@
 
 mod test_expr;
 mod test_extensions;
+mod test_is;
 mod test_namespace;
 mod test_optional_attributes;
 mod test_policy;
 mod test_strict;
+mod test_tags;
 mod test_type_annotation;
 mod test_unspecified_entity;
 mod test_utils;
@@ -32,14 +34,16 @@ use std::{
     borrow::Cow,
     collections::{HashMap, HashSet},
     iter::zip,
+    sync::OnceLock,
 };
 
 use crate::{
     extension_schema::{ExtensionFunctionType, ExtensionSchema},
-    extensions::all_available_extension_schemas,
+    extensions::{all_available_extension_schemas, extension_types, is_extension_type},
     fuzzy_match::fuzzy_search,
     schema::{
-        is_action_entity_type, ActionHeadVar, HeadVar, PrincipalOrResourceHeadVar, ValidatorSchema,
+        is_action_entity_type, ActionHeadVar, ContextMode, HeadVar, PrincipalOrResourceHeadVar,
+        ValidatorSchema,
     },
     types::{AttributeType, Effect, EffectSet, EntityRecordKind, OpenTag, RequestEnv, Type},
     AttributeAccess, ValidationMode,
@@ -243,6 +247,33 @@ pub struct Typechecker<'a> {
     schema: &'a ValidatorSchema,
     extensions: HashMap<Name, ExtensionSchema>,
     mode: ValidationMode,
+    /// The request environments of templates, by the [`head_shape()`] of the
+    /// template. They depend only on the shape, so they are computed once
+    /// for all templates with the same shape.
+    request_envs: [OnceLock<Vec<RequestEnv<'a>>>; HEAD_SHAPES],
+}
+
+/// The number of distinct values of [`head_shape()`]
+const HEAD_SHAPES: usize = 16;
+
+/// Which request environments a template is typechecked in depends only on
+/// whether its principal and resource constraints use a slot, and how. This
+/// is an index below [`HEAD_SHAPES`] identifying those.
+fn head_shape(t: &Template) -> usize {
+    let slot_shape = |slot: SlotId, constraint: &PrincipalOrResourceConstraint| {
+        if !t.slots().contains(&slot) {
+            0
+        } else {
+            match constraint {
+                PrincipalOrResourceConstraint::Eq(_) => 1,
+                PrincipalOrResourceConstraint::In(_)
+                | PrincipalOrResourceConstraint::IsIn(_, _) => 2,
+                PrincipalOrResourceConstraint::Any | PrincipalOrResourceConstraint::Is(_) => 3,
+            }
+        }
+    };
+    slot_shape(SlotId::principal(), t.principal_constraint().as_inner()) * 4
+        + slot_shape(SlotId::resource(), t.resource_constraint().as_inner())
 }
 
 impl<'a> Typechecker<'a> {
@@ -257,6 +288,7 @@ impl<'a> Typechecker<'a> {
             schema,
             extensions,
             mode,
+            request_envs: Default::default(),
         }
     }
 
@@ -342,16 +374,29 @@ impl<'a> Typechecker<'a> {
         // explicit that `expect_type` will be called for every element of
         // request_env without short circuiting.
         let policy_condition = &t.condition();
-        for requeste in self
-            .unlinked_request_envs()
-            .flat_map(|env| self.link_request_env(env, t))
-        {
-            let check = typecheck_fn(&requeste, policy_condition);
-            result_checks.push((requeste, check))
+        for requeste in self.request_envs(t) {
+            let check = typecheck_fn(requeste, policy_condition);
+            result_checks.push((requeste.clone(), check))
         }
         result_checks
     }
 
+    /// The request environments to typecheck `t` in: every schema-defined
+    /// request environment, with its slots instantiated by every entity type
+    /// which could fill them
+    fn request_envs(&self, t: &Template) -> &[RequestEnv<'a>] {
+        let envs = match self.request_envs.get(head_shape(t)) {
+            Some(envs) => envs,
+            // `head_shape()` is always below `HEAD_SHAPES`
+            None => return &[],
+        };
+        envs.get_or_init(|| {
+            self.unlinked_request_envs()
+                .flat_map(|env| self.link_request_env(env, t))
+                .collect()
+        })
+    }
+
     /// Additional entry point for typechecking requests. This method takes a slice
     /// over policies and typechecks each under every schema-defined request environment.
     ///
@@ -391,12 +436,12 @@ impl<'a> Typechecker<'a> {
         env_checks
     }
 
-    fn unlinked_request_envs(&self) -> impl Iterator<Item = RequestEnv> + '_ {
+    fn unlinked_request_envs(&self) -> impl Iterator<Item = RequestEnv<'a>> + 'a {
         // Gather all of the actions declared in the schema.
-        let all_actions = self
-            .schema
+        let schema = self.schema;
+        let all_actions = schema
             .known_action_ids()
-            .filter_map(|a| self.schema.get_action_id(a));
+            .filter_map(|a| schema.get_action_id(a));
 
         // For every action compute the cross product of the principal and
         // resource applies_to sets.
@@ -424,9 +469,9 @@ impl<'a> Typechecker<'a> {
     /// formed by instantiating template slots with possible entity types.
     fn link_request_env<'b>(
         &'b self,
-        env: RequestEnv<'b>,
+        env: RequestEnv<'a>,
         t: &'b Template,
-    ) -> impl Iterator<Item = RequestEnv> + 'b {
+    ) -> impl Iterator<Item = RequestEnv<'a>> + 'b {
         self.possible_slot_instantiations(
             t,
             SlotId::principal(),
@@ -472,7 +517,8 @@ impl<'a> Typechecker<'a> {
                 }
                 // The condition is `var in ?slot`, so the policy can only apply
                 // if the var is some descendant of the slot.
-                PrincipalOrResourceConstraint::In(_) => Box::new(
+                PrincipalOrResourceConstraint::In(_)
+                | PrincipalOrResourceConstraint::IsIn(_, _) => Box::new(
                     all_entity_types
                         .filter(|(_, ety)| ety.has_descendant_entity_type(var))
                         .map(|(name, _)| Some(EntityType::Concrete(name.clone())))
@@ -483,9 +529,11 @@ impl<'a> Typechecker<'a> {
                 // appear in head constraints, but if we ever see this, then the
                 // only correct way to proceed is by returning all entity types
                 // as possible instantiations.
-                PrincipalOrResourceConstraint::Any => Box::new(
-                    all_entity_types.map(|(name, _)| Some(EntityType::Concrete(name.clone()))),
-                ),
+                PrincipalOrResourceConstraint::Any | PrincipalOrResourceConstraint::Is(_) => {
+                    Box::new(
+                        all_entity_types.map(|(name, _)| Some(EntityType::Concrete(name.clone()))),
+                    )
+                }
             }
         } else {
             // If the template does not contain this slot, then we don't need to
@@ -560,7 +608,10 @@ impl<'a> Typechecker<'a> {
             ExprKind::Var(Var::Context) => TypecheckAnswer::success(
                 ExprBuilder::with_data(Some(Type::record_with_attributes(
                     request_env.context.clone(),
-                    OpenTag::ClosedAttributes,
+                    match self.schema.context_mode() {
+                        ContextMode::Closed => OpenTag::ClosedAttributes,
+                        ContextMode::Open => OpenTag::OpenAttributes,
+                    },
                 )))
                 .with_same_source_info(e)
                 .var(Var::Context),
@@ -1084,6 +1135,12 @@ impl<'a> Typechecker<'a> {
             }
 
             ExprKind::Like { expr, pattern } => {
+                // The parser only produces well-formed patterns, but a policy
+                // constructed some other way might contain a malformed one.
+                let well_formed = pattern.is_well_formed();
+                if !well_formed {
+                    type_errors.push(TypeError::malformed_pattern(e.clone(), pattern.to_string()));
+                }
                 // `like` applies to a string
                 let actual = self.expect_type(
                     request_env,
@@ -1092,7 +1149,7 @@ impl<'a> Typechecker<'a> {
                     Type::primitive_string(),
                     type_errors,
                 );
-                actual.then_typecheck(|actual_expr_ty, _| {
+                let ans = actual.then_typecheck(|actual_expr_ty, _| {
                     TypecheckAnswer::success(
                         ExprBuilder::with_data(Some(Type::primitive_boolean()))
                             .with_same_source_info(e)
@@ -1101,6 +1158,54 @@ impl<'a> Typechecker<'a> {
                             // pattern vec. Need a different constructor.
                             .like(actual_expr_ty, pattern.iter().cloned()),
                     )
+                });
+                if well_formed {
+                    ans
+                } else {
+                    ans.into_fail()
+                }
+            }
+
+            ExprKind::Is { expr, entity_type } => {
+                // `is` applies to an entity, or also to an extension value
+                // when it names an extension type rather than an entity type
+                let expected = if !self.schema.is_known_entity_type(entity_type)
+                    && is_extension_type(entity_type)
+                {
+                    std::iter::once(Type::any_entity_reference())
+                        .chain(extension_types().map(Type::extension))
+                        .collect()
+                } else {
+                    vec![Type::any_entity_reference()]
+                };
+                let actual =
+                    self.expect_one_of_types(request_env, prior_eff, expr, &expected, type_errors);
+                actual.then_typecheck(|actual_expr_ty, _| {
+                    // When the entity types the operand may have are known, the
+                    // result may be known to be `true` or `false`.
+                    let ty = match actual_expr_ty.data() {
+                        Some(Type::EntityOrRecord(EntityRecordKind::Entity(lub))) => {
+                            if lub.get_single_entity() == Some(entity_type) {
+                                Type::singleton_boolean(true)
+                            } else if lub.iter().any(|name| name == entity_type) {
+                                Type::primitive_boolean()
+                            } else {
+                                Type::singleton_boolean(false)
+                            }
+                        }
+                        Some(Type::EntityOrRecord(EntityRecordKind::ActionEntity {
+                            name, ..
+                        })) => Type::singleton_boolean(name == entity_type),
+                        Some(Type::ExtensionType { name }) => {
+                            Type::singleton_boolean(name == entity_type)
+                        }
+                        _ => Type::primitive_boolean(),
+                    };
+                    TypecheckAnswer::success(
+                        ExprBuilder::with_data(Some(ty))
+                            .with_same_source_info(e)
+                            .is_entity_type(actual_expr_ty, entity_type.clone()),
+                    )
                 })
             }
 
@@ -1383,6 +1488,106 @@ impl<'a> Typechecker<'a> {
                             })
                     })
             }
+
+            // `hasTag` applies to an entity and a string naming the tag. Like
+            // `has`, it generates an effect so that a guarded `getTag` on the
+            // same entity and tag expression is known to be safe.
+            BinaryOp::HasTag => self
+                .expect_type(
+                    request_env,
+                    prior_eff,
+                    arg1,
+                    Type::any_entity_reference(),
+                    type_errors,
+                )
+                .then_typecheck(|expr_ty_arg1, _| {
+                    self.expect_type(
+                        request_env,
+                        prior_eff,
+                        arg2,
+                        Type::primitive_string(),
+                        type_errors,
+                    )
+                    .then_typecheck(|expr_ty_arg2, _| {
+                        let effect = Effect::new_tag(arg1, arg2);
+                        let may_have_tags = expr_ty_arg1
+                            .data()
+                            .as_ref()
+                            .map_or(true, |ty| Type::may_have_tags(self.schema, ty));
+                        if may_have_tags {
+                            let type_of_has = if prior_eff.contains(&effect) {
+                                Type::singleton_boolean(true)
+                            } else {
+                                Type::primitive_boolean()
+                            };
+                            TypecheckAnswer::success_with_effect(
+                                ExprBuilder::with_data(Some(type_of_has))
+                                    .with_same_source_info(bin_expr)
+                                    .binary_app(*op, expr_ty_arg1, expr_ty_arg2),
+                                EffectSet::singleton(effect),
+                            )
+                        } else {
+                            // None of the entity types can have tags, so the
+                            // tag definitely does not exist.
+                            TypecheckAnswer::success(
+                                ExprBuilder::with_data(Some(Type::singleton_boolean(false)))
+                                    .with_same_source_info(bin_expr)
+                                    .binary_app(*op, expr_ty_arg1, expr_ty_arg2),
+                            )
+                        }
+                    })
+                }),
+
+            // `getTag` applies to an entity and a string naming the tag. The
+            // result is the tag type declared in the schema, and the access is
+            // only safe when guarded by a matching `hasTag`.
+            BinaryOp::GetTag => self
+                .expect_type(
+                    request_env,
+                    prior_eff,
+                    arg1,
+                    Type::any_entity_reference(),
+                    type_errors,
+                )
+                .then_typecheck(|expr_ty_arg1, _| {
+                    self.expect_type(
+                        request_env,
+                        prior_eff,
+                        arg2,
+                        Type::primitive_string(),
+                        type_errors,
+                    )
+                    .then_typecheck(|expr_ty_arg2, _| {
+                        let Some(entity_ty) = expr_ty_arg1.data().clone() else {
+                            return TypecheckAnswer::fail(
+                                ExprBuilder::new()
+                                    .with_same_source_info(bin_expr)
+                                    .binary_app(*op, expr_ty_arg1, expr_ty_arg2),
+                            );
+                        };
+                        let tag_ty = Type::lookup_tag_type(self.schema, &entity_ty, self.mode);
+                        let annot_expr = ExprBuilder::with_data(tag_ty.clone())
+                            .with_same_source_info(bin_expr)
+                            .binary_app(*op, expr_ty_arg1, expr_ty_arg2);
+                        match tag_ty {
+                            Some(_) if prior_eff.contains(&Effect::new_tag(arg1, arg2)) => {
+                                TypecheckAnswer::success(annot_expr)
+                            }
+                            Some(_) => {
+                                type_errors.push(TypeError::unsafe_tag_access(
+                                    bin_expr.clone(),
+                                    arg2.to_string(),
+                                ));
+                                TypecheckAnswer::fail(annot_expr)
+                            }
+                            None => {
+                                type_errors
+                                    .push(TypeError::no_tags_allowed(bin_expr.clone(), entity_ty));
+                                TypecheckAnswer::fail(annot_expr)
+                            }
+                        }
+                    })
+                }),
         }
     }
 
@@ -2196,18 +2401,41 @@ impl<'a> Typechecker<'a> {
                     let typechecked_args = zip(args.as_ref(), arg_tys).map(|(arg, ty)| {
                         self.expect_type(request_env, prior_eff, arg, ty.clone(), type_errors)
                     });
-                    TypecheckAnswer::sequence_all_then_typecheck(
+                    let mut return_type_err = None;
+                    let answer = TypecheckAnswer::sequence_all_then_typecheck(
                         typechecked_args,
                         |arg_exprs_effects| {
                             let (typed_arg_exprs, _): (Vec<Expr<Option<Type>>>, Vec<_>) =
                                 arg_exprs_effects.into_iter().unzip();
+                            let arg_types = typed_arg_exprs
+                                .iter()
+                                .map(|e| e.data().clone())
+                                .collect::<Option<Vec<_>>>();
+                            let ret_ty = match arg_types.map(|tys| {
+                                efunc.return_type_for(self.schema, self.mode, args, &tys)
+                            }) {
+                                Some(Ok(ty)) => ty,
+                                Some(Err(msg)) => {
+                                    return_type_err = Some(msg);
+                                    return TypecheckAnswer::fail(
+                                        ExprBuilder::with_data(Some(ret_ty.clone()))
+                                            .with_same_source_info(ext_expr)
+                                            .call_extension_fn(fn_name.clone(), typed_arg_exprs),
+                                    );
+                                }
+                                None => ret_ty.clone(),
+                            };
                             TypecheckAnswer::success(
-                                ExprBuilder::with_data(Some(ret_ty.clone()))
+                                ExprBuilder::with_data(Some(ret_ty))
                                     .with_same_source_info(ext_expr)
                                     .call_extension_fn(fn_name.clone(), typed_arg_exprs),
                             )
                         },
-                    )
+                    );
+                    if let Some(msg) = return_type_err {
+                        type_errors.push(TypeError::arg_validation_error(ext_expr.clone(), msg));
+                    }
+                    answer
                 }
             }
             Err(typ_err) => {
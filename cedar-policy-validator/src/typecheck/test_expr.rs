@@ -21,7 +21,7 @@
 
 use std::str::FromStr;
 
-use cedar_policy_core::ast::{BinaryOp, EntityUID, Expr, PatternElem, SlotId, Var};
+use cedar_policy_core::ast::{BinaryOp, CharClass, EntityUID, Expr, PatternElem, SlotId, Var};
 use serde_json::json;
 use smol_str::SmolStr;
 
@@ -57,6 +57,8 @@ fn slot_typechecks() {
 fn slot_in_typechecks() {
     let etype = EntityType {
         member_of_types: vec![],
+        tags: None,
+        derived_parents: vec![],
         shape: AttributesOrContext::default(),
     };
     let schema = NamespaceDefinition::new([("typename".into(), etype)], []);
@@ -86,6 +88,8 @@ fn slot_in_typechecks() {
 fn slot_equals_typechecks() {
     let etype = EntityType {
         member_of_types: vec![],
+        tags: None,
+        derived_parents: vec![],
         shape: AttributesOrContext::default(),
     };
     // These don't typecheck in strict mode because the test_util expression
@@ -919,6 +923,35 @@ fn like_typecheck_fails() {
     );
 }
 
+#[test]
+fn like_char_class_typechecks() {
+    assert_typechecks_empty_schema(
+        Expr::like(
+            Expr::val("0xab"),
+            vec![
+                PatternElem::Char('0'),
+                PatternElem::Char('x'),
+                PatternElem::Class(CharClass::new([('0', '9'), ('a', 'f')], false)),
+                PatternElem::Wildcard,
+            ],
+        ),
+        Type::primitive_boolean(),
+    );
+}
+
+#[test]
+fn like_malformed_char_class_fails() {
+    let expr = Expr::like(
+        Expr::val("0xab"),
+        vec![PatternElem::Class(CharClass::new([('f', 'a')], false))],
+    );
+    assert_typecheck_fails_empty_schema(
+        expr.clone(),
+        Type::primitive_boolean(),
+        vec![TypeError::malformed_pattern(expr, "[f-a]".to_string())],
+    );
+}
+
 #[test]
 fn less_than_typechecks() {
     assert_typechecks_empty_schema(
@@ -140,3 +140,496 @@ fn decimal_extension_typecheck_fails() {
         )],
     );
 }
+
+#[test]
+#[cfg(feature = "hash")]
+fn hash_extension_typechecks() {
+    let u256_name = Name::parse_unqualified_name("u256").expect("should be a valid identifier");
+    let expr = Expr::from_str("poseidonHash(keccak256(\"a\"), u256(\"1\"))")
+        .expect("parsing should succeed");
+    assert_typechecks_empty_schema(expr, Type::extension(u256_name.clone()));
+    let expr = Expr::from_str("poseidonHash(sha256(\"a\"), 1)").expect("parsing should succeed");
+    assert_typecheck_fails_empty_schema(
+        expr,
+        Type::extension(u256_name.clone()),
+        vec![TypeError::expected_type(
+            Expr::val(1),
+            Type::extension(u256_name),
+            Type::primitive_long(),
+        )],
+    );
+}
+
+#[test]
+#[cfg(feature = "commitment")]
+fn commitment_extension_typechecks() {
+    let u256_name = Name::parse_unqualified_name("u256").expect("should be a valid identifier");
+    let expr = Expr::from_str(
+        "commitmentOpens(u256(\"1\"), u256(\"2\"), u256(\"3\")) && nullifierUnspent(u256(\"4\"), [u256(\"5\")])",
+    )
+    .expect("parsing should succeed");
+    assert_typechecks_empty_schema(expr, Type::primitive_boolean());
+    let expr =
+        Expr::from_str("nullifierUnspent(u256(\"4\"), [4])").expect("parsing should succeed");
+    assert_typecheck_fails_empty_schema(
+        expr,
+        Type::primitive_boolean(),
+        vec![TypeError::expected_type(
+            Expr::set([Expr::val(4)]),
+            Type::set(Type::extension(u256_name)),
+            Type::set(Type::primitive_long()),
+        )],
+    );
+}
+
+#[test]
+#[cfg(feature = "zk")]
+fn zk_extension_typechecks() {
+    for fname in ["zkVerify", "zkVerifyPlonk"] {
+        let expr = Expr::from_str(&format!(
+            "{fname}(\"0x00\", \"0x00\", {{input0: u256(\"1\")}})"
+        ))
+        .expect("parsing should succeed");
+        assert_typechecks_empty_schema(expr, Type::primitive_boolean());
+    }
+    let expr = Expr::from_str("zkVerify(\"0x00\", \"0x00\", [u256(\"1\")])")
+        .expect("parsing should succeed");
+    let u256_name = Name::parse_unqualified_name("u256").expect("should be a valid identifier");
+    assert_typecheck_fails_empty_schema(
+        expr,
+        Type::primitive_boolean(),
+        vec![TypeError::expected_type(
+            Expr::set([Expr::call_extension_fn(
+                u256_name.clone(),
+                vec![Expr::val("1")],
+            )]),
+            Type::any_record(),
+            Type::set(Type::extension(u256_name)),
+        )],
+    );
+}
+
+#[test]
+#[cfg(feature = "webauthn")]
+fn webauthn_extension_typechecks() {
+    let expr = Expr::from_str(
+        "webauthnVerify(\"0x04\", {authenticatorData: \"\", clientDataJSON: \"\", signature: \"\"}, {challenge: \"\", origin: \"\", rpId: \"\"})",
+    )
+    .expect("parsing should succeed");
+    assert_typechecks_empty_schema(expr, Type::primitive_boolean());
+    let expr = Expr::from_str("webauthnVerify(\"0x04\", {}, \"challenge\")")
+        .expect("parsing should succeed");
+    assert_typecheck_fails_empty_schema(
+        expr,
+        Type::primitive_boolean(),
+        vec![TypeError::expected_type(
+            Expr::val("challenge"),
+            Type::any_record(),
+            Type::primitive_string(),
+        )],
+    );
+}
+
+#[test]
+#[cfg(feature = "totp")]
+fn totp_extension_typechecks() {
+    let expr =
+        Expr::from_str("totpVerify(\"GEZDGNBV\", \"287082\", 59)").expect("parsing should succeed");
+    assert_typechecks_empty_schema(expr, Type::primitive_boolean());
+    let expr =
+        Expr::from_str("totpVerify(\"GEZDGNBV\", 287082, 59)").expect("parsing should succeed");
+    assert_typecheck_fails_empty_schema(
+        expr,
+        Type::primitive_boolean(),
+        vec![TypeError::expected_type(
+            Expr::val(287082),
+            Type::primitive_string(),
+            Type::primitive_long(),
+        )],
+    );
+}
+
+#[test]
+#[cfg(feature = "set-ops")]
+fn set_ops_extension_typechecks() {
+    let expr = Expr::from_str("setIntersect([1, 2], [2, 3])").expect("parsing should succeed");
+    assert_typechecks_empty_schema(expr, Type::set(Type::primitive_long()));
+    let expr = Expr::from_str("setIntersect([\"a\"], [\"b\"]).contains(\"a\")")
+        .expect("parsing should succeed");
+    assert_typechecks_empty_schema(expr, Type::primitive_boolean());
+    let expr = Expr::from_str("setUnionSize([1, 2], [2, 3]) > 2").expect("parsing should succeed");
+    assert_typechecks_empty_schema(expr, Type::primitive_boolean());
+    let expr = Expr::from_str("[1].isSubsetOf([1, 2])").expect("parsing should succeed");
+    assert_typechecks_empty_schema(expr, Type::primitive_boolean());
+}
+
+#[test]
+#[cfg(feature = "set-ops")]
+fn set_ops_extension_typecheck_fails() {
+    let expr = Expr::from_str("setUnionSize([1], [\"a\"])").expect("parsing should succeed");
+    assert_typecheck_fails_empty_schema(
+        expr.clone(),
+        Type::primitive_long(),
+        vec![TypeError::arg_validation_error(
+            expr,
+            format!(
+                "`setUnionSize` expects sets with elements of the same type, but got `{}` and `{}`",
+                Type::set(Type::primitive_long()),
+                Type::set(Type::primitive_string())
+            ),
+        )],
+    );
+    let expr = Expr::from_str("[1].isSubsetOf(1)").expect("parsing should succeed");
+    assert_typecheck_fails_empty_schema(
+        expr,
+        Type::primitive_boolean(),
+        vec![TypeError::expected_type(
+            Expr::val(1),
+            Type::any_set(),
+            Type::primitive_long(),
+        )],
+    );
+}
+
+#[test]
+#[cfg(feature = "record-ops")]
+fn record_ops_extension_typechecks() {
+    let expr = Expr::from_str("{a: 1, b: \"x\"}.keys()").expect("parsing should succeed");
+    assert_typechecks_empty_schema(expr, Type::set(Type::primitive_string()));
+    let expr =
+        Expr::from_str("{a: 1, b: \"x\"}.hasAll([\"a\", \"c\"])").expect("parsing should succeed");
+    assert_typechecks_empty_schema(expr, Type::primitive_boolean());
+    let expr = Expr::from_str("merge({a: 1, b: \"x\"}, {b: 2})").expect("parsing should succeed");
+    assert_typechecks_empty_schema(
+        expr,
+        Type::closed_record_with_required_attributes([
+            ("a".into(), Type::primitive_long()),
+            ("b".into(), Type::primitive_long()),
+        ]),
+    );
+    let expr = Expr::from_str("merge({a: 1}, {b: 2}).a > merge({a: 1}, {b: 2}).b")
+        .expect("parsing should succeed");
+    assert_typechecks_empty_schema(expr, Type::primitive_boolean());
+}
+
+#[test]
+#[cfg(feature = "record-ops")]
+fn record_ops_extension_typecheck_fails() {
+    let expr = Expr::from_str("{a: 1}.hasAll([1])").expect("parsing should succeed");
+    assert_typecheck_fails_empty_schema(
+        expr,
+        Type::primitive_boolean(),
+        vec![TypeError::expected_type(
+            Expr::set([Expr::val(1)]),
+            Type::set(Type::primitive_string()),
+            Type::set(Type::primitive_long()),
+        )],
+    );
+    let expr = Expr::from_str("[1].keys()").expect("parsing should succeed");
+    assert_typecheck_fails_empty_schema(
+        expr,
+        Type::set(Type::primitive_string()),
+        vec![TypeError::expected_type(
+            Expr::set([Expr::val(1)]),
+            Type::any_record(),
+            Type::set(Type::primitive_long()),
+        )],
+    );
+}
+
+#[cfg(feature = "entity-ops")]
+fn wallet_schema() -> crate::NamespaceDefinition {
+    serde_json::from_str(r#"{ "entityTypes": { "Wallet": {} }, "actions": {} }"#)
+        .expect("expected valid schema")
+}
+
+#[test]
+#[cfg(feature = "entity-ops")]
+fn entity_ops_extension_typechecks() {
+    use super::test_utils::assert_typechecks;
+
+    let expr =
+        Expr::from_str(r#"entityFromString("Wallet", "0xab")"#).expect("parsing should succeed");
+    assert_typechecks(
+        wallet_schema(),
+        expr,
+        Type::named_entity_reference(Name::from_str("Wallet").expect("should be a valid name")),
+    );
+    let expr = Expr::from_str(r#"entityFromString("Wallet", "0xab") in Wallet::"0xcd""#)
+        .expect("parsing should succeed");
+    assert_typechecks(wallet_schema(), expr, Type::primitive_boolean());
+}
+
+#[test]
+#[cfg(feature = "entity-ops")]
+fn entity_ops_extension_typecheck_fails() {
+    use super::test_utils::assert_typecheck_fails;
+
+    for (expr, msg) in [
+        (
+            r#"entityFromString("Vault", "0xab")"#,
+            "`Vault` is not an entity type declared in the schema",
+        ),
+        (
+            r#"entityFromString("not a type", "0xab")"#,
+            "`not a type` is not a valid entity type name",
+        ),
+        (
+            r#"entityFromString(if true then "Wallet" else "Wallet", "0xab")"#,
+            "the first argument of `entityFromString` must be a string literal naming an entity type",
+        ),
+    ] {
+        let expr = Expr::from_str(expr).expect("parsing should succeed");
+        assert_typecheck_fails(
+            wallet_schema(),
+            expr.clone(),
+            Some(Type::any_entity_reference()),
+            vec![TypeError::arg_validation_error(expr, msg.to_string())],
+        );
+    }
+}
+
+#[test]
+#[cfg(feature = "travel-rule")]
+fn travel_rule_extension_typechecks() {
+    let expr = Expr::from_str(r#"{originatorPersons: [{legalPerson: {}}]}.isIvms101Originator()"#)
+        .expect("parsing should succeed");
+    assert_typechecks_empty_schema(expr, Type::primitive_boolean());
+    let expr =
+        Expr::from_str(r#"{beneficiaryPersons: [{legalPerson: {}}]}.isIvms101Beneficiary()"#)
+            .expect("parsing should succeed");
+    assert_typechecks_empty_schema(expr, Type::primitive_boolean());
+}
+
+#[test]
+#[cfg(feature = "travel-rule")]
+fn travel_rule_extension_typecheck_fails() {
+    let expr = Expr::from_str("\"alice\".isIvms101Originator()").expect("parsing should succeed");
+    assert_typecheck_fails_empty_schema(
+        expr,
+        Type::primitive_boolean(),
+        vec![TypeError::expected_type(
+            Expr::val("alice"),
+            Type::any_record(),
+            Type::primitive_string(),
+        )],
+    );
+}
+
+#[test]
+#[cfg(feature = "vesting")]
+fn vesting_extension_typechecks() {
+    let u256_name = Name::parse_unqualified_name("u256").expect("should be a valid identifier");
+    let expr = Expr::from_str(r#"vesting("100,10,50,1000").releasableAt(120, u256("0"))"#)
+        .expect("parsing should succeed");
+    assert_typechecks_empty_schema(expr, Type::extension(u256_name));
+    let expr =
+        Expr::from_str(r#"vesting("100,10,50,1000").end()"#).expect("parsing should succeed");
+    assert_typechecks_empty_schema(expr, Type::primitive_long());
+}
+
+#[test]
+#[cfg(feature = "vesting")]
+fn vesting_extension_typecheck_fails() {
+    let vesting_name =
+        Name::parse_unqualified_name("vesting").expect("should be a valid identifier");
+    let expr = Expr::from_str(r#"vesting("100,10,50,1000").vestedAt("now")"#)
+        .expect("parsing should succeed");
+    let u256_name = Name::parse_unqualified_name("u256").expect("should be a valid identifier");
+    assert_typecheck_fails_empty_schema(
+        expr,
+        Type::extension(u256_name),
+        vec![TypeError::expected_type(
+            Expr::val("now"),
+            Type::primitive_long(),
+            Type::primitive_string(),
+        )],
+    );
+    let expr = Expr::from_str(r#"vesting("100,60,50,1000")"#).expect("parsing should succeed");
+    assert_typecheck_fails_empty_schema(
+        expr.clone(),
+        Type::extension(vesting_name),
+        vec![TypeError::arg_validation_error(
+            expr,
+            r#"Failed to parse as a vesting value: `"100,60,50,1000"`"#.into(),
+        )],
+    );
+}
+
+#[test]
+#[cfg(all(feature = "u256", feature = "decimal"))]
+fn implied_price_typechecks() {
+    let decimal_name =
+        Name::parse_unqualified_name("decimal").expect("should be a valid identifier");
+    let expr = Expr::from_str(r#"impliedPrice(u256("1000000"), u256("500000000000000"), 6, 18)"#)
+        .expect("parsing should succeed");
+    assert_typechecks_empty_schema(expr, Type::extension(decimal_name));
+    let expr = Expr::from_str(
+        r#"impliedPrice(u256("1000000"), u256("500000000000000"), 6, 18).lessThan(decimal("0.0006"))"#,
+    )
+    .expect("parsing should succeed");
+    assert_typechecks_empty_schema(expr, Type::primitive_boolean());
+}
+
+#[test]
+#[cfg(all(feature = "u256", feature = "decimal"))]
+fn implied_price_typecheck_fails() {
+    let decimal_name =
+        Name::parse_unqualified_name("decimal").expect("should be a valid identifier");
+    let expr = Expr::from_str(r#"impliedPrice(u256("1000000"), 500, 6, 18)"#)
+        .expect("parsing should succeed");
+    assert_typecheck_fails_empty_schema(
+        expr,
+        Type::extension(decimal_name),
+        vec![TypeError::expected_type(
+            Expr::val(500),
+            Type::extension(
+                Name::parse_unqualified_name("u256").expect("should be a valid identifier"),
+            ),
+            Type::primitive_long(),
+        )],
+    );
+}
+
+#[test]
+#[cfg(feature = "health-factor")]
+fn health_factor_extension_typechecks() {
+    let decimal_name =
+        Name::parse_unqualified_name("decimal").expect("should be a valid identifier");
+    let expr = Expr::from_str(
+        r#"healthFactor([{ asset: "ETH", collateral: u256("100"), debt: u256("0"), liquidationThreshold: 8250 }])"#,
+    )
+    .expect("parsing should succeed");
+    assert_typechecks_empty_schema(expr, Type::extension(decimal_name));
+    let expr = Expr::from_str(
+        r#"healthFactorAfter(
+            [{ collateral: u256("100"), debt: u256("50"), liquidationThreshold: 8250 }],
+            { collateral: u256("10"), debt: u256("0"), liquidationThreshold: 8250 }
+        ).lessThan(decimal("1.2"))"#,
+    )
+    .expect("parsing should succeed");
+    assert_typechecks_empty_schema(expr, Type::primitive_boolean());
+}
+
+#[test]
+#[cfg(feature = "health-factor")]
+fn health_factor_extension_typecheck_fails() {
+    use crate::types::OpenTag;
+
+    let decimal_name =
+        Name::parse_unqualified_name("decimal").expect("should be a valid identifier");
+    let u256_name = Name::parse_unqualified_name("u256").expect("should be a valid identifier");
+    let position = Type::record_with_required_attributes(
+        [
+            ("collateral".into(), Type::extension(u256_name.clone())),
+            ("debt".into(), Type::extension(u256_name)),
+            ("liquidationThreshold".into(), Type::primitive_long()),
+        ],
+        OpenTag::OpenAttributes,
+    );
+    let expr = Expr::from_str("healthFactor([1])").expect("parsing should succeed");
+    assert_typecheck_fails_empty_schema(
+        expr,
+        Type::extension(decimal_name),
+        vec![TypeError::expected_type(
+            Expr::set([Expr::val(1)]),
+            Type::set(position),
+            Type::set(Type::primitive_long()),
+        )],
+    );
+}
+
+#[test]
+#[cfg(feature = "concentration")]
+fn concentration_extension_typechecks() {
+    let expr = Expr::from_str(
+        r#"maxConcentration([
+            { token: "ETH", usdValue: decimal("40000.0"), amount: 16 },
+            { token: "USDC", usdValue: decimal("60000.0"), amount: 60000 }
+        ]).lessThanOrEqual(decimal("40.0"))"#,
+    )
+    .expect("parsing should succeed");
+    assert_typechecks_empty_schema(expr, Type::primitive_boolean());
+}
+
+#[test]
+#[cfg(feature = "concentration")]
+fn concentration_extension_typecheck_fails() {
+    use crate::types::OpenTag;
+
+    let decimal_name =
+        Name::parse_unqualified_name("decimal").expect("should be a valid identifier");
+    let holding = Type::record_with_required_attributes(
+        [
+            ("token".into(), Type::primitive_string()),
+            ("usdValue".into(), Type::extension(decimal_name.clone())),
+        ],
+        OpenTag::OpenAttributes,
+    );
+    let expr = Expr::from_str(r#"concentration(["ETH"], "ETH")"#).expect("parsing should succeed");
+    assert_typecheck_fails_empty_schema(
+        expr,
+        Type::extension(decimal_name),
+        vec![TypeError::expected_type(
+            Expr::set([Expr::val("ETH")]),
+            Type::set(holding),
+            Type::set(Type::primitive_string()),
+        )],
+    );
+}
+
+#[test]
+#[cfg(feature = "exposure")]
+fn exposure_extension_typechecks() {
+    let expr = Expr::from_str(
+        r#"exposure("0xabc=100,0xdef=5").to("0xabc").u256LessThanOrEqual(u256("1000"))"#,
+    )
+    .expect("parsing should succeed");
+    assert_typechecks_empty_schema(expr, Type::primitive_boolean());
+    let u256_name = Name::parse_unqualified_name("u256").expect("should be a valid identifier");
+    let expr = Expr::from_str(r#"exposure("").totalExposure()"#).expect("parsing should succeed");
+    assert_typechecks_empty_schema(expr, Type::extension(u256_name));
+}
+
+#[test]
+#[cfg(feature = "exposure")]
+fn exposure_extension_typecheck_fails() {
+    let exposure_name =
+        Name::parse_unqualified_name("exposure").expect("should be a valid identifier");
+    let u256_name = Name::parse_unqualified_name("u256").expect("should be a valid identifier");
+    let expr = Expr::from_str(r#"exposure("0xabc=100").to(1)"#).expect("parsing should succeed");
+    assert_typecheck_fails_empty_schema(
+        expr,
+        Type::extension(u256_name),
+        vec![TypeError::expected_type(
+            Expr::val(1),
+            Type::primitive_string(),
+            Type::primitive_long(),
+        )],
+    );
+    let expr = Expr::from_str(r#"exposure("0xabc=-1")"#).expect("parsing should succeed");
+    assert_typecheck_fails_empty_schema(
+        expr.clone(),
+        Type::extension(exposure_name),
+        vec![TypeError::arg_validation_error(
+            expr,
+            r#"Failed to parse as an exposure value: `"0xabc=-1"`"#.into(),
+        )],
+    );
+}
+
+#[test]
+fn config_extension_typechecks() {
+    let expr = Expr::from_str(r#"config("limit")"#).expect("parsing should succeed");
+    assert_typechecks_empty_schema(expr, Type::Never);
+    let expr = Expr::from_str("config(1)").expect("parsing should succeed");
+    assert_typecheck_fails_empty_schema(
+        expr,
+        Type::Never,
+        vec![TypeError::expected_type(
+            Expr::val(1),
+            Type::primitive_string(),
+            Type::primitive_long(),
+        )],
+    );
+}
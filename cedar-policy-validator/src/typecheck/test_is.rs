@@ -0,0 +1,145 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Contains tests for typechecking the `is` operator, both in policy
+//! conditions and in the `is` sugar of the policy scope.
+#![cfg(test)]
+// GRCOV_STOP_COVERAGE
+
+use cedar_policy_core::{
+    ast::{EntityUID, Expr},
+    parser::parse_policy,
+};
+
+use crate::{type_error::TypeError, types::Type, NamespaceDefinition};
+
+use super::test_utils::{assert_policy_typechecks, assert_typecheck_fails, assert_typechecks};
+
+fn schema() -> NamespaceDefinition {
+    serde_json::from_str::<NamespaceDefinition>(
+        r#"
+{
+    "entityTypes": {
+        "User": {
+            "shape": {
+                "type": "Record",
+                "attributes": {
+                    "kycLevel": { "type": "Long" }
+                }
+            }
+        },
+        "Wallet": {
+            "shape": {
+                "type": "Record",
+                "attributes": {
+                    "kycLevel": { "type": "Long" }
+                }
+            }
+        },
+        "Vault": {}
+    },
+    "actions": {
+        "sign": {
+            "appliesTo": {
+                "principalTypes": ["User", "Wallet"],
+                "resourceTypes": ["Vault"]
+            }
+        }
+    }
+}
+    "#,
+    )
+    .expect("Expected valid schema.")
+}
+
+#[test]
+fn is_on_known_entity_type() {
+    let wallet: EntityUID = r#"Wallet::"w""#.parse().expect("valid uid");
+    assert_typechecks(
+        schema(),
+        Expr::is_entity_type(Expr::val(wallet.clone()), "Wallet".parse().unwrap()),
+        Type::singleton_boolean(true),
+    );
+    assert_typechecks(
+        schema(),
+        Expr::is_entity_type(Expr::val(wallet), "User".parse().unwrap()),
+        Type::singleton_boolean(false),
+    );
+}
+
+#[test]
+fn is_on_non_entity_fails() {
+    assert_typecheck_fails(
+        schema(),
+        Expr::is_entity_type(Expr::val(1), "User".parse().unwrap()),
+        Some(Type::primitive_boolean()),
+        vec![TypeError::expected_type(
+            Expr::val(1),
+            Type::any_entity_reference(),
+            Type::primitive_long(),
+        )],
+    );
+}
+
+#[test]
+fn scope_is_when_typechecks() {
+    let policy = parse_policy(
+        Some("0".to_string()),
+        r#"permit(principal is Wallet when principal.kycLevel >= 2, action == Action::"sign", resource is Vault);"#,
+    )
+    .expect("Policy should parse.");
+    assert_policy_typechecks(schema(), policy);
+}
+
+#[test]
+#[cfg(feature = "u256")]
+fn is_on_extension_value() {
+    use crate::extensions::extension_types;
+    use cedar_policy_core::ast::Name;
+    use std::str::FromStr;
+
+    let u256: Name = "u256".parse().unwrap();
+    let amount = Expr::from_str(r#"u256("5")"#).expect("parsing should succeed");
+    assert_typechecks(
+        schema(),
+        Expr::is_entity_type(amount.clone(), u256.clone()),
+        Type::singleton_boolean(true),
+    );
+    assert_typechecks(
+        schema(),
+        Expr::is_entity_type(amount, "decimal".parse().unwrap()),
+        Type::singleton_boolean(false),
+    );
+    let wallet: EntityUID = r#"Wallet::"w""#.parse().expect("valid uid");
+    assert_typechecks(
+        schema(),
+        Expr::is_entity_type(Expr::val(wallet), u256.clone()),
+        Type::singleton_boolean(false),
+    );
+    let expected: Vec<_> = std::iter::once(Type::any_entity_reference())
+        .chain(extension_types().map(Type::extension))
+        .collect();
+    assert_typecheck_fails(
+        schema(),
+        Expr::is_entity_type(Expr::val("5"), u256),
+        Some(Type::primitive_boolean()),
+        vec![TypeError::expected_one_of_types(
+            Expr::val("5"),
+            expected,
+            Type::primitive_string(),
+        )],
+    );
+}
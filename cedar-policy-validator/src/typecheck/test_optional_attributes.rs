@@ -815,3 +815,13 @@ fn action_attrs_failing() {
     .expect("Policy should parse.");
     assert_policy_typecheck_fails(schema, failing_policy, vec![]); //fails because OtherNamespace::Action::"view" doesn't have defined attributes
 }
+
+#[test]
+fn get_or_optional_attribute() {
+    let policy = parse_policy(
+        Some("0".to_string()),
+        r#"permit(principal, action, resource) when { getOr(principal, "name", "anon") == "foo" && getOr(resource, "age", 0) > 1 };"#,
+    )
+    .expect("Policy should parse.");
+    assert_policy_typechecks_optional_schema(policy);
+}
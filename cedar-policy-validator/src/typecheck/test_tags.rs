@@ -0,0 +1,157 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Contains tests for typechecking `hasTag` and `getTag` against the tag
+//! types declared in the schema.
+#![cfg(test)]
+// GRCOV_STOP_COVERAGE
+
+use cedar_policy_core::{
+    ast::{EntityUID, Expr, Var},
+    parser::parse_policy,
+};
+
+use crate::{
+    type_error::TypeError, types::Type, NamespaceDefinition, SchemaError, ValidatorSchema,
+};
+
+use super::test_utils::{
+    assert_policy_typecheck_fails, assert_policy_typechecks, assert_typechecks,
+};
+
+fn schema_with_tags() -> NamespaceDefinition {
+    serde_json::from_str::<NamespaceDefinition>(
+        r#"
+{
+    "entityTypes": {
+        "User": {
+            "tags": { "type": "String" }
+        },
+        "Photo": {}
+    },
+    "actions": {
+        "view_photo": {
+            "appliesTo": {
+                "principalTypes": ["User"],
+                "resourceTypes": ["Photo"]
+            }
+        }
+    }
+}
+    "#,
+    )
+    .expect("Expected valid schema.")
+}
+
+#[test]
+fn guarded_get_tag_typechecks() {
+    let policy = parse_policy(
+        Some("0".to_string()),
+        r#"permit(principal, action, resource) when { principal.hasTag("tier") && principal.getTag("tier") == "gold" };"#,
+    )
+    .expect("Policy should parse.");
+    assert_policy_typechecks(schema_with_tags(), policy);
+}
+
+#[test]
+fn unguarded_get_tag_fails() {
+    let policy = parse_policy(
+        Some("0".to_string()),
+        r#"permit(principal, action, resource) when { principal.getTag("tier") == "gold" };"#,
+    )
+    .expect("Policy should parse.");
+    assert_policy_typecheck_fails(
+        schema_with_tags(),
+        policy,
+        vec![TypeError::unsafe_tag_access(
+            Expr::get_tag(Expr::var(Var::Principal), Expr::val("tier")),
+            r#""tier""#.to_string(),
+        )],
+    );
+}
+
+#[test]
+fn guard_on_different_tag_fails() {
+    let policy = parse_policy(
+        Some("0".to_string()),
+        r#"permit(principal, action, resource) when { principal.hasTag("team") && principal.getTag("tier") == "gold" };"#,
+    )
+    .expect("Policy should parse.");
+    assert_policy_typecheck_fails(
+        schema_with_tags(),
+        policy,
+        vec![TypeError::unsafe_tag_access(
+            Expr::get_tag(Expr::var(Var::Principal), Expr::val("tier")),
+            r#""tier""#.to_string(),
+        )],
+    );
+}
+
+#[test]
+fn get_tag_without_declared_tags_fails() {
+    let policy = parse_policy(
+        Some("0".to_string()),
+        r#"permit(principal, action, resource) when { resource.hasTag("tier") || resource.getTag("tier") == "gold" };"#,
+    )
+    .expect("Policy should parse.");
+    assert_policy_typecheck_fails(
+        schema_with_tags(),
+        policy,
+        vec![TypeError::no_tags_allowed(
+            Expr::get_tag(Expr::var(Var::Resource), Expr::val("tier")),
+            Type::named_entity_reference_from_str("Photo"),
+        )],
+    );
+}
+
+#[test]
+fn has_tag_without_declared_tags_is_false() {
+    let photo: EntityUID = r#"Photo::"vacation""#.parse().expect("valid uid");
+    assert_typechecks(
+        schema_with_tags(),
+        Expr::has_tag(Expr::val(photo), Expr::val("tier")),
+        Type::singleton_boolean(false),
+    );
+    let user: EntityUID = r#"User::"alice""#.parse().expect("valid uid");
+    assert_typechecks(
+        schema_with_tags(),
+        Expr::has_tag(Expr::val(user), Expr::val("tier")),
+        Type::primitive_boolean(),
+    );
+}
+
+#[test]
+fn undeclared_entity_in_tag_type() {
+    let schema = serde_json::from_str::<NamespaceDefinition>(
+        r#"
+{
+    "entityTypes": {
+        "User": {
+            "tags": { "type": "Entity", "name": "Team" }
+        }
+    },
+    "actions": {}
+}
+    "#,
+    )
+    .expect("Expected valid schema.");
+    match ValidatorSchema::try_from(schema) {
+        Err(SchemaError::UndeclaredEntityTypes(tys)) => {
+            assert_eq!(tys, ["Team".to_string()].into_iter().collect())
+        }
+        other => panic!("expected undeclared entity type error, got {:?}", other),
+    }
+}
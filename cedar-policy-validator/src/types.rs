@@ -403,6 +403,49 @@ impl Type {
         }
     }
 
+    /// Get the type of the tag values for an entity type. For an entity least
+    /// upper bound this is the least upper bound of the tag types of its
+    /// members. Returns `None` if the type is not a named entity type, if any
+    /// member entity type has no tags declared, or if the tag types of the
+    /// members have no least upper bound.
+    pub(crate) fn lookup_tag_type(
+        schema: &ValidatorSchema,
+        ty: &Type,
+        mode: ValidationMode,
+    ) -> Option<Type> {
+        match ty {
+            Type::EntityOrRecord(EntityRecordKind::Entity(entity_lub)) => {
+                let mut tag_types = entity_lub
+                    .iter()
+                    .map(|entity| schema.get_entity_type(entity)?.tag_type());
+                let first = tag_types.next()??.clone();
+                tag_types.try_fold(first, |lub, tag_ty| {
+                    Type::least_upper_bound(schema, &lub, tag_ty?, mode)
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Return true if the Type `ty` could possibly have tags. Only entities
+    /// have tags, so for a named entity type we check whether any of the
+    /// constituent entity types declare tags in the schema.
+    pub(crate) fn may_have_tags(schema: &ValidatorSchema, ty: &Type) -> bool {
+        match ty {
+            Type::Never => true,
+            Type::EntityOrRecord(EntityRecordKind::AnyEntity) => true,
+            Type::EntityOrRecord(EntityRecordKind::Entity(entity_lub)) => {
+                entity_lub.iter().any(|entity| {
+                    schema
+                        .get_entity_type(entity)
+                        .map_or(false, |entity_type| entity_type.tag_type().is_some())
+                })
+            }
+            // The schema never declares tags on actions
+            _ => false,
+        }
+    }
+
     /// Return true if we know that any value in this type must be a specified
     /// entity. An unspecified entity has type `AnyEntity`, so `AnyEntity` might
     /// not be specified. Other entity types must be specified.
@@ -1355,19 +1398,35 @@ impl<'a> EffectSet<'a> {
     }
 }
 
-/// Represent a single effect, which is an expression and some attribute that is
-/// known to exist for that expression.
+/// Represent a single effect, which is an expression and some attribute or tag
+/// that is known to exist for that expression.
 #[derive(Hash, Eq, PartialEq, Debug, Clone)]
 pub struct Effect<'a> {
     on_expr: ExprShapeOnly<'a>,
-    attribute: &'a str,
+    key: EffectKey<'a>,
+}
+
+/// What an `Effect` knows to exist: either an attribute, or a tag whose name
+/// is given by an expression.
+#[derive(Hash, Eq, PartialEq, Debug, Clone)]
+enum EffectKey<'a> {
+    Attribute(&'a str),
+    Tag(ExprShapeOnly<'a>),
 }
 
 impl<'a> Effect<'a> {
     pub fn new(on_expr: &'a Expr, attribute: &'a str) -> Self {
         Self {
             on_expr: ExprShapeOnly::new(on_expr),
-            attribute,
+            key: EffectKey::Attribute(attribute),
+        }
+    }
+
+    /// An effect recording that the tag named by `tag` exists on `on_expr`.
+    pub fn new_tag(on_expr: &'a Expr, tag: &'a Expr) -> Self {
+        Self {
+            on_expr: ExprShapeOnly::new(on_expr),
+            key: EffectKey::Tag(ExprShapeOnly::new(tag)),
         }
     }
 }
@@ -164,6 +164,18 @@ pub enum ValidationErrorKind {
         .0.entity_id,
     )]
     UnspecifiedEntity(UnspecifiedEntity),
+    /// A policy lacks an annotation which the validator was configured to
+    /// require.
+    #[error("missing required annotation `@{}`", .0.key)]
+    MissingAnnotation(MissingAnnotation),
+    /// A policy's `@chain` annotation isn't a comma-separated list of chain
+    /// ids.
+    #[error("invalid `@chain` annotation `{}`: expected comma-separated chain ids", .0.value)]
+    InvalidChainAnnotation(InvalidChainAnnotation),
+    /// A policy's `@chain` annotation lists a chain which the validator
+    /// wasn't configured to know.
+    #[error("unknown chain `{}` in `@chain` annotation", .0.chain)]
+    UnknownChain(UnknownChain),
 }
 
 impl ValidationErrorKind {
@@ -204,6 +216,18 @@ impl ValidationErrorKind {
     pub(crate) fn unspecified_entity(entity_id: String) -> ValidationErrorKind {
         Self::UnspecifiedEntity(UnspecifiedEntity { entity_id })
     }
+
+    pub(crate) fn missing_annotation(key: String) -> ValidationErrorKind {
+        Self::MissingAnnotation(MissingAnnotation { key })
+    }
+
+    pub(crate) fn invalid_chain_annotation(value: String) -> ValidationErrorKind {
+        Self::InvalidChainAnnotation(InvalidChainAnnotation { value })
+    }
+
+    pub(crate) fn unknown_chain(chain: u64) -> ValidationErrorKind {
+        Self::UnknownChain(UnknownChain { chain })
+    }
 }
 
 /// Structure containing details about an unrecognized entity type error.
@@ -217,6 +241,19 @@ pub struct UnrecognizedEntityType {
     pub(crate) suggested_entity_type: Option<String>,
 }
 
+impl UnrecognizedEntityType {
+    /// The entity type seen in the policy.
+    pub fn actual_entity_type(&self) -> &str {
+        &self.actual_entity_type
+    }
+
+    /// An entity type from the schema that the user might reasonably have
+    /// intended to write.
+    pub fn suggested_entity_type(&self) -> Option<&str> {
+        self.suggested_entity_type.as_deref()
+    }
+}
+
 /// Structure containing details about an unrecognized action id error.
 #[derive(Debug)]
 #[cfg_attr(test, derive(Eq, PartialEq))]
@@ -228,6 +265,19 @@ pub struct UnrecognizedActionId {
     pub(crate) suggested_action_id: Option<String>,
 }
 
+impl UnrecognizedActionId {
+    /// Action Id seen in the policy.
+    pub fn actual_action_id(&self) -> &str {
+        &self.actual_action_id
+    }
+
+    /// An action id from the schema that the user might reasonably have
+    /// intended to write.
+    pub fn suggested_action_id(&self) -> Option<&str> {
+        self.suggested_action_id.as_deref()
+    }
+}
+
 /// Structure containing details about an invalid action application error.
 #[derive(Debug)]
 #[cfg_attr(test, derive(Eq, PartialEq))]
@@ -236,6 +286,20 @@ pub struct InvalidActionApplication {
     pub(crate) would_in_fix_resource: bool,
 }
 
+impl InvalidActionApplication {
+    /// Whether replacing `==` with `in` in the principal clause would fix
+    /// the policy.
+    pub fn would_in_fix_principal(&self) -> bool {
+        self.would_in_fix_principal
+    }
+
+    /// Whether replacing `==` with `in` in the resource clause would fix the
+    /// policy.
+    pub fn would_in_fix_resource(&self) -> bool {
+        self.would_in_fix_resource
+    }
+}
+
 /// Structure containing details about an unspecified entity error.
 #[derive(Debug)]
 #[cfg_attr(test, derive(Eq, PartialEq))]
@@ -243,3 +307,49 @@ pub struct UnspecifiedEntity {
     /// EID of the unspecified entity.
     pub(crate) entity_id: String,
 }
+
+/// Structure containing details about a missing required annotation.
+#[derive(Debug)]
+#[cfg_attr(test, derive(Eq, PartialEq))]
+pub struct MissingAnnotation {
+    /// Key of the missing annotation.
+    pub(crate) key: String,
+}
+
+impl MissingAnnotation {
+    /// Key of the missing annotation.
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+}
+
+/// Structure containing details about a malformed `@chain` annotation.
+#[derive(Debug)]
+#[cfg_attr(test, derive(Eq, PartialEq))]
+pub struct InvalidChainAnnotation {
+    /// Value of the annotation.
+    pub(crate) value: String,
+}
+
+impl InvalidChainAnnotation {
+    /// Value of the annotation.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+}
+
+/// Structure containing details about an unknown chain in a `@chain`
+/// annotation.
+#[derive(Debug)]
+#[cfg_attr(test, derive(Eq, PartialEq))]
+pub struct UnknownChain {
+    /// The unknown chain id.
+    pub(crate) chain: u64,
+}
+
+impl UnknownChain {
+    /// The unknown chain id.
+    pub fn chain(&self) -> u64 {
+        self.chain
+    }
+}
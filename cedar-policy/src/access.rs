@@ -0,0 +1,373 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Access review: who can do what.
+//!
+//! These analyses narrow the search using the head constraints of the permit
+//! policies (`principal in Group::"signers"`, `action == Action::"sign"`,
+//! and so on) and the entity hierarchy, then confirm each candidate by
+//! answering a request for it, so conditions and `forbid` policies are
+//! taken into account.
+
+use std::collections::BTreeSet;
+
+use cedar_policy_core::ast::{ExprKind, Literal};
+
+use crate::{
+    ActionConstraint, Authorizer, Context, Decision, Effect, Entities, EntityTypeName, EntityUid,
+    Policy, PolicyId, PolicySet, PrincipalConstraint, Request, ResourceConstraint,
+};
+
+/// The principals which can perform an action on a resource; see
+/// [`Authorizer::principals_with_access()`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PrincipalAccess {
+    /// The principals which are allowed, sorted
+    pub allowed: Vec<EntityUid>,
+    /// Whether a permit policy for the action and resource doesn't constrain
+    /// the principal in its head. Principals which aren't in the entities may
+    /// then be allowed too.
+    pub unbounded: bool,
+}
+
+/// An action a principal could be permitted to perform on the resources
+/// matching a pattern; see [`Authorizer::permissions_for()`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Permission {
+    /// The permit policy granting this permission
+    pub policy: PolicyId,
+    /// The actions permitted, as written in the policy head
+    pub action: ActionConstraint,
+    /// The resources the actions are permitted on, as written in the policy
+    /// head
+    pub resource: ResourceConstraint,
+    /// Whether the policy has `when` or `unless` conditions, so the
+    /// permission depends on the request
+    pub conditional: bool,
+}
+
+impl Authorizer {
+    /// Which principals of type `principal_type` are allowed to perform
+    /// `action` on `resource`, with `context`.
+    ///
+    /// The candidates are the principals named by the heads of the permit
+    /// policies for `action` and `resource`, and the members of the groups
+    /// they name, including principals named in template links which aren't
+    /// in `entities`. If a permit policy doesn't constrain the principal,
+    /// every entity of the type is a candidate. Each candidate is then
+    /// checked with a request.
+    /// ```
+    /// # use cedar_policy::{Authorizer, Context, Entities, EntityTypeName, EntityUid, PolicySet};
+    /// # use std::str::FromStr;
+    /// let policies = PolicySet::from_str(
+    ///     r#"permit(principal == Wallet::"a", action == Action::"sign", resource);"#,
+    /// ).unwrap();
+    /// let access = Authorizer::new().principals_with_access(
+    ///     &EntityTypeName::from_str("Wallet").unwrap(),
+    ///     &EntityUid::from_str(r#"Action::"sign""#).unwrap(),
+    ///     &EntityUid::from_str(r#"Vault::"v""#).unwrap(),
+    ///     &Context::empty(),
+    ///     &policies,
+    ///     &Entities::empty(),
+    /// );
+    /// assert_eq!(access.allowed, [EntityUid::from_str(r#"Wallet::"a""#).unwrap()]);
+    /// assert!(!access.unbounded);
+    /// ```
+    pub fn principals_with_access(
+        &self,
+        principal_type: &EntityTypeName,
+        action: &EntityUid,
+        resource: &EntityUid,
+        context: &Context,
+        policies: &PolicySet,
+        entities: &Entities,
+    ) -> PrincipalAccess {
+        let of_type = || {
+            entities
+                .iter()
+                .map(crate::Entity::uid)
+                .filter(|uid| uid.type_name() == principal_type)
+        };
+        let mut candidates = BTreeSet::new();
+        let mut unbounded = false;
+        for policy in permits(policies).filter(|p| {
+            action_matches(p, action, entities) && resource_matches(p, resource, entities)
+        }) {
+            match policy.principal_constraint() {
+                PrincipalConstraint::Any => {
+                    unbounded = true;
+                    candidates.extend(of_type());
+                }
+                PrincipalConstraint::Eq(uid) => {
+                    if uid.type_name() == principal_type {
+                        candidates.insert(uid);
+                    }
+                }
+                PrincipalConstraint::In(group) => {
+                    if group.type_name() == principal_type {
+                        candidates.insert(group.clone());
+                    }
+                    candidates.extend(of_type().filter(|uid| entities.is_ancestor_of(&group, uid)));
+                }
+                PrincipalConstraint::Is(entity_type) => {
+                    if &entity_type == principal_type {
+                        unbounded = true;
+                        candidates.extend(of_type());
+                    }
+                }
+                PrincipalConstraint::IsIn(entity_type, group) => {
+                    if &entity_type == principal_type {
+                        if group.type_name() == principal_type {
+                            candidates.insert(group.clone());
+                        }
+                        candidates
+                            .extend(of_type().filter(|uid| entities.is_ancestor_of(&group, uid)));
+                    }
+                }
+            }
+        }
+        let allowed = candidates
+            .into_iter()
+            .filter(|principal| {
+                let request = Request::new(
+                    Some(principal.clone()),
+                    Some(action.clone()),
+                    Some(resource.clone()),
+                    context.clone(),
+                );
+                self.decide(&request, policies, entities).decision() == Decision::Allow
+            })
+            .collect();
+        PrincipalAccess { allowed, unbounded }
+    }
+
+    /// The permissions `principal` could be granted: one for each permit
+    /// policy whose principal head matches `principal`, directly or through
+    /// its ancestors in `entities`, sorted by policy id. Revoked links grant
+    /// nothing.
+    ///
+    /// `forbid` policies aren't considered; use
+    /// [`Self::principals_with_access()`] or [`Self::is_authorized()`] to
+    /// check a particular action and resource.
+    /// ```
+    /// # use cedar_policy::{Authorizer, Entities, EntityUid, PolicySet, ResourceConstraint};
+    /// # use std::str::FromStr;
+    /// let policies = PolicySet::from_str(
+    ///     r#"permit(principal == Wallet::"a", action == Action::"sign", resource == Vault::"v");"#,
+    /// ).unwrap();
+    /// let permissions = Authorizer::new().permissions_for(
+    ///     &EntityUid::from_str(r#"Wallet::"a""#).unwrap(),
+    ///     &policies,
+    ///     &Entities::empty(),
+    /// );
+    /// assert_eq!(
+    ///     permissions[0].resource,
+    ///     ResourceConstraint::Eq(EntityUid::from_str(r#"Vault::"v""#).unwrap()),
+    /// );
+    /// ```
+    pub fn permissions_for(
+        &self,
+        principal: &EntityUid,
+        policies: &PolicySet,
+        entities: &Entities,
+    ) -> Vec<Permission> {
+        let effective = self.effective(policies);
+        let mut permissions: Vec<_> = permits(effective.as_ref())
+            .filter(|policy| match policy.principal_constraint() {
+                PrincipalConstraint::Any => true,
+                PrincipalConstraint::Eq(uid) => &uid == principal,
+                PrincipalConstraint::In(group) => is_in(principal, &group, entities),
+                PrincipalConstraint::Is(entity_type) => principal.type_name() == &entity_type,
+                PrincipalConstraint::IsIn(entity_type, group) => {
+                    principal.type_name() == &entity_type && is_in(principal, &group, entities)
+                }
+            })
+            .map(|policy| Permission {
+                policy: policy.id().clone(),
+                action: policy.action_constraint(),
+                resource: policy.resource_constraint(),
+                conditional: is_conditional(policy),
+            })
+            .collect();
+        permissions.sort_by(|a, b| a.policy.as_ref().cmp(b.policy.as_ref()));
+        permissions
+    }
+}
+
+/// The permit policies in `policies`
+fn permits(policies: &PolicySet) -> impl Iterator<Item = &Policy> {
+    policies.policies().filter(|p| p.effect() == Effect::Permit)
+}
+
+/// Whether the action head of `policy` admits `action`
+fn action_matches(policy: &Policy, action: &EntityUid, entities: &Entities) -> bool {
+    match policy.action_constraint() {
+        ActionConstraint::Any => true,
+        ActionConstraint::Eq(uid) => &uid == action,
+        ActionConstraint::In(groups) => groups.iter().any(|group| is_in(action, group, entities)),
+    }
+}
+
+/// Whether the resource head of `policy` admits `resource`
+fn resource_matches(policy: &Policy, resource: &EntityUid, entities: &Entities) -> bool {
+    match policy.resource_constraint() {
+        ResourceConstraint::Any => true,
+        ResourceConstraint::Eq(uid) => &uid == resource,
+        ResourceConstraint::In(group) => is_in(resource, &group, entities),
+        ResourceConstraint::Is(entity_type) => resource.type_name() == &entity_type,
+        ResourceConstraint::IsIn(entity_type, group) => {
+            resource.type_name() == &entity_type && is_in(resource, &group, entities)
+        }
+    }
+}
+
+/// Whether `policy` has conditions besides its head. The parser marks their
+/// absence with a literal `true`.
+fn is_conditional(policy: &Policy) -> bool {
+    !matches!(
+        policy.ast.non_head_constraints().expr_kind(),
+        ExprKind::Lit(Literal::Bool(true))
+    )
+}
+
+/// Whether `child in parent` holds
+fn is_in(child: &EntityUid, parent: &EntityUid, entities: &Entities) -> bool {
+    child == parent || entities.is_ancestor_of(parent, child)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::SlotId;
+    use std::collections::HashMap;
+    use std::str::FromStr;
+
+    fn uid(ty: &str, id: &str) -> EntityUid {
+        EntityUid::from_strs(ty, id)
+    }
+
+    fn entities() -> Entities {
+        Entities::from_json_str(
+            r#"[
+                { "uid": { "type": "Wallet", "id": "b" }, "attrs": {},
+                  "parents": [{ "type": "Group", "id": "signers" }] },
+                { "uid": { "type": "Wallet", "id": "blocked" }, "attrs": {},
+                  "parents": [{ "type": "Group", "id": "signers" }] },
+                { "uid": { "type": "Wallet", "id": "outsider" }, "attrs": {}, "parents": [] },
+                { "uid": { "type": "Group", "id": "signers" }, "attrs": {}, "parents": [] },
+                { "uid": { "type": "Token", "id": "usdc" }, "attrs": {},
+                  "parents": [{ "type": "Vault", "id": "treasury" }] }
+            ]"#,
+            None,
+        )
+        .unwrap()
+    }
+
+    fn policies() -> PolicySet {
+        let mut policies = PolicySet::from_str(
+            r#"permit(principal == Wallet::"a", action == Action::"transfer", resource);
+               permit(principal in Group::"signers", action == Action::"transfer", resource in Vault::"treasury")
+               when { context.amount < 100 };
+               forbid(principal == Wallet::"blocked", action, resource);
+               permit(principal, action == Action::"view", resource);
+               permit(principal == ?principal, action in [Action::"transfer"], resource == ?resource);"#,
+        )
+        .unwrap();
+        policies
+            .link(
+                PolicyId::from_str("policy4").unwrap(),
+                PolicyId::from_str("session").unwrap(),
+                HashMap::from([
+                    (SlotId::principal(), uid("Wallet", "session")),
+                    (SlotId::resource(), uid("Token", "usdc")),
+                ]),
+            )
+            .unwrap();
+        policies
+    }
+
+    fn access(action: &str, amount: i64) -> PrincipalAccess {
+        Authorizer::new().principals_with_access(
+            &EntityTypeName::from_str("Wallet").unwrap(),
+            &uid("Action", action),
+            &uid("Token", "usdc"),
+            &Context::from_json_value(serde_json::json!({ "amount": amount }), None).unwrap(),
+            &policies(),
+            &entities(),
+        )
+    }
+
+    #[test]
+    fn principals_with_access() {
+        let small = access("transfer", 10);
+        assert_eq!(
+            small.allowed,
+            [
+                uid("Wallet", "a"),
+                uid("Wallet", "b"),
+                uid("Wallet", "session")
+            ]
+        );
+        assert!(!small.unbounded);
+
+        let large = access("transfer", 1000);
+        assert_eq!(
+            large.allowed,
+            [uid("Wallet", "a"), uid("Wallet", "session")]
+        );
+
+        let view = access("view", 0);
+        assert_eq!(
+            view.allowed,
+            [uid("Wallet", "b"), uid("Wallet", "outsider")]
+        );
+        assert!(view.unbounded);
+    }
+
+    #[test]
+    fn permissions_for() {
+        let authorizer = Authorizer::new();
+        let summarize = |principal: &EntityUid| {
+            authorizer
+                .permissions_for(principal, &policies(), &entities())
+                .into_iter()
+                .map(|p| (p.policy.to_string(), p.conditional))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            summarize(&uid("Wallet", "b")),
+            [
+                ("policy1".to_string(), true),
+                ("policy3".to_string(), false)
+            ]
+        );
+        assert_eq!(
+            summarize(&uid("Wallet", "session")),
+            [
+                ("policy3".to_string(), false),
+                ("session".to_string(), false)
+            ]
+        );
+
+        let permissions = authorizer.permissions_for(&uid("Wallet", "a"), &policies(), &entities());
+        assert_eq!(permissions.len(), 2);
+        assert_eq!(
+            permissions[0].action,
+            ActionConstraint::Eq(uid("Action", "transfer"))
+        );
+        assert_eq!(permissions[0].resource, ResourceConstraint::Any);
+    }
+}
@@ -0,0 +1,559 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! An address book: labels, categories, and risk scores for addresses,
+//! exposed to policies as entity attributes.
+//!
+//! Each address is an entity of type `Address` (see
+//! [`AddressBook::with_entity_type()`]) with the attributes:
+//! - `known`: whether the address is in the book
+//! - `label`: a human-readable name, or `""`
+//! - `category`: one of the [`Category`] names, or `"unknown"`
+//! - `riskScore`: from 0 to 100; see [`Category::default_risk_score()`]
+//! - `tags`: a set of strings
+//!
+//! Every address gets every attribute, so policies like
+//! ```text
+//! forbid(principal, action == Action::"transfer", resource)
+//! when { resource.category == "mixer" || resource.riskScore > 80 };
+//! ```
+//! work for addresses which aren't in the book too.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io::BufRead;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::audit::normalize_address;
+use crate::{
+    Entities, EntitiesError, Entity, EntityId, EntityTypeName, EntityUid, RestrictedExpression,
+};
+
+/// The default entity type of addresses
+pub const ADDRESS_TYPE: &str = "Address";
+
+/// Errors loading an address book
+#[derive(Debug, Error)]
+pub enum AddressBookError {
+    /// Reading the file failed
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    /// The JSON is malformed
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    /// A line of CSV is malformed
+    #[error("line {line} of the CSV is malformed: {message}")]
+    Csv {
+        /// Line number, starting at 1
+        line: usize,
+        /// What's wrong with it
+        message: String,
+    },
+    /// An address isn't 20 bytes of `0x`-prefixed hex
+    #[error("`{0}` isn't an address")]
+    InvalidAddress(String),
+    /// A risk score isn't between 0 and 100
+    #[error("risk score {score} of `{address}` isn't between 0 and 100")]
+    InvalidRiskScore {
+        /// The address
+        address: String,
+        /// The score
+        score: i64,
+    },
+}
+
+/// What kind of party an address belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Category {
+    /// A centralized exchange
+    Exchange,
+    /// A cross-chain bridge
+    Bridge,
+    /// A mixer, such as Tornado Cash
+    Mixer,
+    /// A decentralized finance protocol
+    Defi,
+    /// A phishing, scam, or exploit address
+    Scam,
+    /// A sanctioned address
+    Sanctioned,
+    /// Anything else
+    Other,
+}
+
+impl Category {
+    /// The name of the category, as in the `category` attribute
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Exchange => "exchange",
+            Self::Bridge => "bridge",
+            Self::Mixer => "mixer",
+            Self::Defi => "defi",
+            Self::Scam => "scam",
+            Self::Sanctioned => "sanctioned",
+            Self::Other => "other",
+        }
+    }
+
+    /// The risk score of addresses in this category which don't have one of
+    /// their own
+    pub fn default_risk_score(self) -> i64 {
+        match self {
+            Self::Scam | Self::Sanctioned => 100,
+            Self::Mixer => 90,
+            Self::Bridge => 30,
+            Self::Exchange => 20,
+            Self::Defi => 10,
+            Self::Other => 0,
+        }
+    }
+
+    /// Guess the category from a label in a public dataset, e.g.
+    /// `tornado-cash` or `binance`
+    fn from_label(label: &str) -> Option<Self> {
+        let label = label.to_ascii_lowercase();
+        let any = |words: &[&str]| words.iter().any(|word| label.contains(word));
+        if any(&["ofac", "sanction"]) {
+            Some(Self::Sanctioned)
+        } else if any(&["phish", "scam", "hack", "exploit", "heist"]) {
+            Some(Self::Scam)
+        } else if any(&["tornado", "mixer", "blender", "sinbad"]) {
+            Some(Self::Mixer)
+        } else if any(&["bridge", "wormhole", "layerzero"]) {
+            Some(Self::Bridge)
+        } else if any(&[
+            "exchange", "binance", "coinbase", "kraken", "okx", "bitfinex",
+        ]) {
+            Some(Self::Exchange)
+        } else if any(&["uniswap", "aave", "compound", "curve", "lido", "defi"]) {
+            Some(Self::Defi)
+        } else {
+            None
+        }
+    }
+}
+
+impl FromStr for Category {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            Self::Exchange,
+            Self::Bridge,
+            Self::Mixer,
+            Self::Defi,
+            Self::Scam,
+            Self::Sanctioned,
+            Self::Other,
+        ]
+        .into_iter()
+        .find(|category| category.as_str().eq_ignore_ascii_case(s))
+        .ok_or_else(|| format!("unknown category `{s}`"))
+    }
+}
+
+/// What the address book knows about an address
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AddressEntry {
+    /// The address, as `0x`-prefixed hex
+    pub address: String,
+    /// A human-readable name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// What kind of party the address belongs to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<Category>,
+    /// From 0 to 100
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub risk_score: Option<i64>,
+    /// Free-form tags
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub tags: BTreeSet<String>,
+}
+
+impl AddressEntry {
+    /// The risk score, or the category's default if there isn't one
+    pub fn effective_risk_score(&self) -> i64 {
+        self.risk_score
+            .or_else(|| self.category.map(Category::default_risk_score))
+            .unwrap_or(0)
+    }
+
+    /// Fill in from `other`: its label, category, and risk score replace
+    /// these if it has them, and its tags are added
+    fn merge(&mut self, other: Self) {
+        self.label = other.label.or(self.label.take());
+        self.category = other.category.or(self.category);
+        self.risk_score = other.risk_score.or(self.risk_score);
+        self.tags.extend(other.tags);
+    }
+}
+
+/// An entry of a public label dataset, in the format of the `eth-labels`
+/// dataset
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LabelledAddress {
+    address: String,
+    label: String,
+    #[serde(default)]
+    name_tag: Option<String>,
+}
+
+/// Addresses and what's known about them
+#[derive(Debug, Clone)]
+pub struct AddressBook {
+    entries: HashMap<String, AddressEntry>,
+    entity_type: EntityTypeName,
+}
+
+impl Default for AddressBook {
+    fn default() -> Self {
+        // PANIC SAFETY: `Address` is a valid entity type name
+        #[allow(clippy::expect_used)]
+        let entity_type =
+            EntityTypeName::from_str(ADDRESS_TYPE).expect("`Address` is a valid entity type name");
+        Self {
+            entries: HashMap::new(),
+            entity_type,
+        }
+    }
+}
+
+impl AddressBook {
+    /// An empty address book
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make addresses entities of type `entity_type`, instead of `Address`
+    #[must_use]
+    pub fn with_entity_type(mut self, entity_type: EntityTypeName) -> Self {
+        self.entity_type = entity_type;
+        self
+    }
+
+    /// Add `entry`. If the address is already in the book, the entries are
+    /// merged: the label, category, and risk score of `entry` replace the
+    /// existing ones if it has them, and its tags are added.
+    pub fn insert(&mut self, mut entry: AddressEntry) -> Result<(), AddressBookError> {
+        entry.address = normalize_address(&entry.address)
+            .ok_or_else(|| AddressBookError::InvalidAddress(entry.address.clone()))?;
+        if let Some(score) = entry.risk_score.filter(|score| !(0..=100).contains(score)) {
+            return Err(AddressBookError::InvalidRiskScore {
+                address: entry.address,
+                score,
+            });
+        }
+        match self.entries.get_mut(&entry.address) {
+            Some(existing) => existing.merge(entry),
+            None => {
+                self.entries.insert(entry.address.clone(), entry);
+            }
+        }
+        Ok(())
+    }
+
+    /// Add the entries of a JSON array of [`AddressEntry`]s
+    pub fn load_json(&mut self, json: &str) -> Result<(), AddressBookError> {
+        let entries: Vec<AddressEntry> = serde_json::from_str(json)?;
+        entries.into_iter().try_for_each(|entry| self.insert(entry))
+    }
+
+    /// Add the entries of a CSV file. The first line is a header naming the
+    /// columns: `address`, and optionally `label`, `category`, `risk_score`,
+    /// and `tags`, whose tags are separated by `;`.
+    pub fn load_csv(&mut self, csv: impl BufRead) -> Result<(), AddressBookError> {
+        let mut lines = csv.lines().enumerate();
+        let Some((_, header)) = lines.next() else {
+            return Ok(());
+        };
+        let header = split_csv_line(&header?)
+            .map_err(|message| AddressBookError::Csv { line: 1, message })?;
+        let column = |name: &str| header.iter().position(|h| h.trim() == name);
+        let address_column = column("address").ok_or_else(|| AddressBookError::Csv {
+            line: 1,
+            message: "no `address` column".to_string(),
+        })?;
+        let (label, category, risk_score, tags) = (
+            column("label"),
+            column("category"),
+            column("risk_score"),
+            column("tags"),
+        );
+        for (i, line) in lines {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let csv_error = |message| AddressBookError::Csv {
+                line: i + 1,
+                message,
+            };
+            let fields = split_csv_line(&line).map_err(csv_error)?;
+            let field = |column: Option<usize>| {
+                column
+                    .and_then(|c| fields.get(c))
+                    .map(|f| f.trim())
+                    .filter(|f| !f.is_empty())
+            };
+            let entry = AddressEntry {
+                address: field(Some(address_column))
+                    .ok_or_else(|| csv_error("no address".to_string()))?
+                    .to_string(),
+                label: field(label).map(str::to_string),
+                category: field(category)
+                    .map(Category::from_str)
+                    .transpose()
+                    .map_err(csv_error)?,
+                risk_score: field(risk_score)
+                    .map(|s| {
+                        s.parse()
+                            .map_err(|_| csv_error(format!("`{s}` isn't a risk score")))
+                    })
+                    .transpose()?,
+                tags: field(tags)
+                    .map(|tags| {
+                        tags.split(';')
+                            .map(str::trim)
+                            .filter(|t| !t.is_empty())
+                            .map(str::to_string)
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+            };
+            self.insert(entry)?;
+        }
+        Ok(())
+    }
+
+    /// Add the entries of a public label dataset: a JSON array of objects
+    /// with an `address`, a `label` such as `tornado-cash`, and optionally a
+    /// `nameTag` such as `Tornado.Cash: Router`. Each label becomes a tag,
+    /// the name tag becomes the label, and the category is guessed from the
+    /// labels.
+    pub fn load_label_dataset(&mut self, json: &str) -> Result<(), AddressBookError> {
+        let labelled: Vec<LabelledAddress> = serde_json::from_str(json)?;
+        labelled.into_iter().try_for_each(|entry| {
+            self.insert(AddressEntry {
+                address: entry.address,
+                category: Category::from_label(&entry.label)
+                    .or_else(|| entry.name_tag.as_deref().and_then(Category::from_label)),
+                label: entry.name_tag,
+                risk_score: None,
+                tags: BTreeSet::from([entry.label]),
+            })
+        })
+    }
+
+    /// What the book knows about `address`
+    pub fn get(&self, address: &str) -> Option<&AddressEntry> {
+        self.entries.get(&normalize_address(address)?)
+    }
+
+    /// Iterate over the entries
+    pub fn iter(&self) -> impl Iterator<Item = &AddressEntry> {
+        self.entries.values()
+    }
+
+    /// The entity for `address`, which has default attributes if it isn't in
+    /// the book, or `None` if it isn't an address
+    pub fn entity(&self, address: &str) -> Option<Entity> {
+        let address = normalize_address(address)?;
+        let entry = self.entries.get(&address);
+        let attrs = HashMap::from([
+            (
+                "known".to_string(),
+                RestrictedExpression::new_bool(entry.is_some()),
+            ),
+            (
+                "label".to_string(),
+                RestrictedExpression::new_string(
+                    entry.and_then(|e| e.label.clone()).unwrap_or_default(),
+                ),
+            ),
+            (
+                "category".to_string(),
+                RestrictedExpression::new_string(
+                    entry
+                        .and_then(|e| e.category)
+                        .map_or("unknown", Category::as_str)
+                        .to_string(),
+                ),
+            ),
+            (
+                "riskScore".to_string(),
+                RestrictedExpression::new_long(entry.map_or(0, AddressEntry::effective_risk_score)),
+            ),
+            (
+                "tags".to_string(),
+                RestrictedExpression::new_set(
+                    entry
+                        .into_iter()
+                        .flat_map(|e| e.tags.iter())
+                        .map(|tag| RestrictedExpression::new_string(tag.clone())),
+                ),
+            ),
+        ]);
+        Some(Entity::new(self.uid(&address), attrs, HashSet::new()))
+    }
+
+    /// The entities for `addresses`, including the ones which aren't in the
+    /// book. Values which aren't addresses are skipped.
+    pub fn entities_for<'a>(
+        &self,
+        addresses: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Entities, EntitiesError> {
+        Entities::from_entities(addresses.into_iter().filter_map(|a| self.entity(a)))
+    }
+
+    /// The entities for every address in the book
+    pub fn to_entities(&self) -> Result<Entities, EntitiesError> {
+        self.entities_for(self.entries.keys().map(String::as_str))
+    }
+
+    /// The entity uid of `address`, which must be normalized
+    fn uid(&self, address: &str) -> EntityUid {
+        // PANIC SAFETY: `EntityId::from_str` never fails
+        #[allow(clippy::unwrap_used)]
+        let id = EntityId::from_str(address).unwrap();
+        EntityUid::from_type_name_and_id(self.entity_type.clone(), id)
+    }
+}
+
+/// Split a line of CSV into fields. Fields may be quoted, with `""` for a
+/// quote inside a quoted field.
+fn split_csv_line(line: &str) -> Result<Vec<String>, String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut chars = line.chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            ('"', true) => quoted = false,
+            ('"', false) if field.is_empty() => quoted = true,
+            (',', false) => fields.push(std::mem::take(&mut field)),
+            (c, _) => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("unterminated quoted field".to_string());
+    }
+    fields.push(field);
+    Ok(fields)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Authorizer, Context, Decision, PolicySet, Request};
+
+    const TORNADO: &str = "0xD90e2f925DA726b50C4Ed8D0Fb90Ad053324F31b";
+    const BINANCE: &str = "0x28c6c06298d514db089934071355e5743bf21d60";
+    const STRANGER: &str = "0x00000000000000000000000000000000000000ff";
+
+    fn book() -> AddressBook {
+        let mut book = AddressBook::new();
+        book.load_csv(
+            format!(
+                "address,label,category,risk_score,tags\n\
+                 {BINANCE},\"Binance 14, hot wallet\",exchange,,cex;hot\n"
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+        book.load_label_dataset(&format!(
+            r#"[{{ "address": "{TORNADO}", "label": "tornado-cash", "nameTag": "Tornado.Cash: Router" }}]"#
+        ))
+        .unwrap();
+        book
+    }
+
+    #[test]
+    fn load() {
+        let book = book();
+        let binance = book.get(BINANCE).unwrap();
+        assert_eq!(binance.label.as_deref(), Some("Binance 14, hot wallet"));
+        assert_eq!(binance.category, Some(Category::Exchange));
+        assert_eq!(binance.effective_risk_score(), 20);
+        assert_eq!(
+            binance.tags,
+            BTreeSet::from(["cex".to_string(), "hot".to_string()])
+        );
+
+        let tornado = book.get(&TORNADO.to_ascii_lowercase()).unwrap();
+        assert_eq!(tornado.category, Some(Category::Mixer));
+        assert_eq!(tornado.label.as_deref(), Some("Tornado.Cash: Router"));
+
+        let mut book = book;
+        book.load_json(&format!(
+            r#"[{{ "address": "{TORNADO}", "riskScore": 95, "tags": ["ofac"] }}]"#
+        ))
+        .unwrap();
+        let tornado = book.get(TORNADO).unwrap();
+        assert_eq!(tornado.effective_risk_score(), 95);
+        assert_eq!(tornado.category, Some(Category::Mixer));
+        assert_eq!(tornado.tags.len(), 2);
+
+        assert!(matches!(
+            book.load_json(r#"[{ "address": "0x1234" }]"#),
+            Err(AddressBookError::InvalidAddress(_))
+        ));
+        assert!(matches!(
+            book.load_csv(
+                "address,risk_score\n0x00000000000000000000000000000000000000aa,high\n".as_bytes()
+            ),
+            Err(AddressBookError::Csv { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn deny_mixers() {
+        let book = book();
+        let policies = PolicySet::from_str(
+            r#"permit(principal, action == Action::"transfer", resource);
+               forbid(principal, action == Action::"transfer", resource)
+               when { resource.category == "mixer" || resource.riskScore > 80 };"#,
+        )
+        .unwrap();
+        let entities = book.entities_for([TORNADO, BINANCE, STRANGER]).unwrap();
+        let decide = |address: &str| {
+            let request = Request::new(
+                None,
+                Some(EntityUid::from_strs("Action", "transfer")),
+                Some(EntityUid::from_strs(
+                    ADDRESS_TYPE,
+                    &address.to_ascii_lowercase(),
+                )),
+                Context::empty(),
+            );
+            Authorizer::new()
+                .is_authorized(&request, &policies, &entities)
+                .decision()
+        };
+        assert_eq!(decide(TORNADO), Decision::Deny);
+        assert_eq!(decide(BINANCE), Decision::Allow);
+        assert_eq!(decide(STRANGER), Decision::Allow);
+    }
+}
@@ -0,0 +1,1594 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Verification of policies with an SMT solver.
+//!
+//! Tests show that some requests are decided as intended; verification shows
+//! that no request is decided otherwise. A [`Verifier`] translates a policy
+//! set, the schema it validates against, and a query, e.g. "can a request for
+//! `Action::"withdraw"` be allowed where `context.amount > context.cap`?",
+//! into an SMT-LIB script, and runs an SMT solver on it. The answer is either
+//! that no such request exists, or a [`Counterexample`]: a request which is
+//! allowed and satisfies the query, with the entities it is allowed with.
+//!
+//! Longs are 64-bit bitvectors, whose overflow is an error as in evaluation,
+//! and `u256` values are 256-bit bitvectors. Entity attributes and
+//! memberships are only constrained by the schema, and memberships aren't
+//! assumed to be transitive, so a counterexample may rely on an entity
+//! hierarchy which isn't transitively closed. Sets, `like`, tags, and
+//! extension functions other than those of `u256` aren't supported: a policy
+//! using them is rejected with [`AnalysisError::Unsupported`].
+//!
+//! The solver runs as a separate process reading the script on its standard
+//! input, `z3 -in` by default. Any solver supporting SMT-LIB 2.6 strings and
+//! bitvectors can be used with [`Verifier::with_solver()`].
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+
+use cedar_policy_core::ast::{self, BinaryOp, ExprKind, Literal, SlotEnv, UnaryOp, Var};
+use cedar_policy_core::entities::Dereference;
+use cedar_policy_validator::types::{EntityRecordKind, Primitive, Type};
+use ethers::types::U256;
+use ref_cast::RefCast;
+use smol_str::SmolStr;
+use thiserror::Error;
+
+use crate::{
+    Context, Entities, Entity, EntityId, EntityTypeName, EntityUid, Expression, PolicyId,
+    PolicySet, Request, RestrictedExpression, Schema,
+};
+
+/// Errors in verifying policies
+#[derive(Debug, Error)]
+pub enum AnalysisError {
+    /// The schema doesn't declare the action
+    #[error("the schema doesn't declare the action `{0}`")]
+    UnknownAction(String),
+    /// A policy, or the query if `policy` is `None`, uses something which
+    /// can't be translated
+    #[error("{} uses {reason}, which isn't supported", policy.as_ref().map_or_else(|| "the query".to_string(), |id| format!("policy `{id}`")))]
+    Unsupported {
+        /// The policy
+        policy: Option<PolicyId>,
+        /// What isn't supported
+        reason: String,
+    },
+    /// The solver couldn't be run, or its output couldn't be understood
+    #[error("solver error: {0}")]
+    Solver(String),
+    /// Running the solver failed
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// The answer to a query
+#[derive(Debug)]
+pub enum Verdict {
+    /// No request satisfying the query is allowed
+    Unreachable,
+    /// A request satisfying the query is allowed
+    Counterexample(Box<Counterexample>),
+    /// The solver couldn't decide, e.g. because it timed out
+    Unknown,
+}
+
+/// A request which is allowed and satisfies a query
+#[derive(Debug)]
+pub struct Counterexample {
+    request: Request,
+    entities: Entities,
+}
+
+impl Counterexample {
+    /// The request
+    pub fn request(&self) -> &Request {
+        &self.request
+    }
+
+    /// The entities it is allowed with: the principal, the resource, the
+    /// actions of the schema, and the attributes and parents of entities
+    /// which the policies depend on
+    pub fn entities(&self) -> &Entities {
+        &self.entities
+    }
+}
+
+/// Answers queries about policy sets with an SMT solver
+#[derive(Debug, Clone)]
+pub struct Verifier<'s> {
+    schema: &'s Schema,
+    program: String,
+    args: Vec<String>,
+}
+
+impl<'s> Verifier<'s> {
+    /// A verifier for policies which validate against `schema`, running
+    /// `z3 -in`
+    pub fn new(schema: &'s Schema) -> Self {
+        Self {
+            schema,
+            program: "z3".to_string(),
+            args: vec!["-in".to_string()],
+        }
+    }
+
+    /// Run `program` with `args` as the solver. It must read an SMT-LIB
+    /// script on its standard input.
+    #[must_use]
+    pub fn with_solver(
+        mut self,
+        program: impl Into<String>,
+        args: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.program = program.into();
+        self.args = args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// The schema policies are checked against
+    pub fn schema(&self) -> &'s Schema {
+        self.schema
+    }
+
+    /// The SMT-LIB script asking whether a request for `action` which
+    /// satisfies `query` is allowed by `policies`
+    pub fn script(
+        &self,
+        policies: &PolicySet,
+        action: &EntityUid,
+        query: &Expression,
+    ) -> Result<String, AnalysisError> {
+        Ok(self.encode(policies, action, query)?.script())
+    }
+
+    /// Whether a request for `action` which satisfies `query`, e.g.
+    /// `context.amount > context.cap`, is allowed by `policies`
+    pub fn can_allow(
+        &self,
+        policies: &PolicySet,
+        action: &EntityUid,
+        query: &Expression,
+    ) -> Result<Verdict, AnalysisError> {
+        let encoding = self.encode(policies, action, query)?;
+        let mut solver = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        if let Some(mut stdin) = solver.stdin.take() {
+            stdin.write_all(encoding.script().as_bytes())?;
+        }
+        let output = solver.wait_with_output()?;
+        encoding.interpret(&String::from_utf8_lossy(&output.stdout))
+    }
+
+    fn encode(
+        &self,
+        policies: &PolicySet,
+        action: &EntityUid,
+        query: &Expression,
+    ) -> Result<Encoding, AnalysisError> {
+        let schema = &self.schema.0;
+        let action_id = schema
+            .get_action_id(&action.0)
+            .ok_or_else(|| AnalysisError::UnknownAction(action.to_string()))?;
+        let action_entities = schema
+            .action_entities()
+            .map_err(|err| AnalysisError::Solver(err.to_string()))?;
+        let mut encoder = Encoder {
+            schema,
+            action: action.0.clone(),
+            action_entities,
+            script: String::new(),
+            next: 0,
+            functions: HashMap::new(),
+            observations: Vec::new(),
+        };
+
+        let unsupported =
+            |policy: Option<PolicyId>| move |reason| AnalysisError::Unsupported { policy, reason };
+        let principal = encoder
+            .request_entity("principal", action_id.applies_to_principals())
+            .map_err(unsupported(None))?;
+        let resource = encoder
+            .request_entity("resource", action_id.applies_to_resources())
+            .map_err(unsupported(None))?;
+        let context_kind = Kind::Record(
+            action_id
+                .context()
+                .map(|(name, attr)| (name.clone(), (kind_of(&attr.attr_type), attr.is_required)))
+                .collect(),
+        );
+        let context = encoder.declare_value(&context_kind, "context");
+        let request = RequestValues {
+            principal,
+            resource,
+            context,
+        };
+
+        let mut permits = Vec::new();
+        let mut forbids = Vec::new();
+        for policy in policies.ast.policies() {
+            let term = encoder
+                .expr(&policy.condition(), policy.env(), &request)
+                .map_err(unsupported(Some(PolicyId::ref_cast(policy.id()).clone())))?;
+            let name = encoder.fresh();
+            let (value, err) = term.as_bool();
+            let _ = writeln!(
+                encoder.script,
+                "; {}\n(define-fun {name} () Bool {})",
+                policy.id(),
+                and(&not(&err), &value)
+            );
+            match policy.effect() {
+                ast::Effect::Permit => permits.push(name),
+                ast::Effect::Forbid => forbids.push(name),
+            }
+        }
+        let query = encoder
+            .expr(&query.0, &SlotEnv::new(), &request)
+            .map_err(unsupported(None))?;
+        let (value, err) = query.as_bool();
+        let _ = writeln!(
+            encoder.script,
+            "(assert {})\n(assert (not {}))\n(assert {})",
+            any(&permits),
+            any(&forbids),
+            and(&not(&err), &value)
+        );
+        Ok(Encoding {
+            script: encoder.script,
+            request,
+            observations: encoder.observations,
+            action: action.clone(),
+            action_entities: encoder.action_entities,
+        })
+    }
+}
+
+/// The sorts of SMT values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Sort {
+    Bool,
+    Long,
+    U256,
+    String,
+}
+
+impl Sort {
+    fn smt(self) -> &'static str {
+        match self {
+            Self::Bool => "Bool",
+            Self::Long => "(_ BitVec 64)",
+            Self::U256 => "(_ BitVec 256)",
+            Self::String => "String",
+        }
+    }
+}
+
+/// The translatable types of attributes
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Kind {
+    Prim(Sort),
+    Entity(ast::Name),
+    /// Attributes, with whether they're required
+    Record(BTreeMap<SmolStr, (Self, bool)>),
+    /// A type which can't be translated, with what it is
+    Opaque(String),
+}
+
+fn kind_of(ty: &Type) -> Kind {
+    match ty {
+        Type::True
+        | Type::False
+        | Type::Primitive {
+            primitive_type: Primitive::Bool,
+        } => Kind::Prim(Sort::Bool),
+        Type::Primitive {
+            primitive_type: Primitive::Long,
+        } => Kind::Prim(Sort::Long),
+        Type::Primitive {
+            primitive_type: Primitive::String,
+        } => Kind::Prim(Sort::String),
+        Type::ExtensionType { name } if name.to_string() == "u256" => Kind::Prim(Sort::U256),
+        Type::EntityOrRecord(EntityRecordKind::Entity(lub)) => lub.get_single_entity().map_or_else(
+            || Kind::Opaque("attributes of several entity types".to_string()),
+            |name| Kind::Entity(name.clone()),
+        ),
+        Type::EntityOrRecord(EntityRecordKind::Record { attrs, .. }) => Kind::Record(
+            attrs
+                .iter()
+                .map(|(name, attr)| (name.clone(), (kind_of(&attr.attr_type), attr.is_required)))
+                .collect(),
+        ),
+        _ => Kind::Opaque(format!("attributes of type {ty}")),
+    }
+}
+
+/// A translated value
+#[derive(Debug, Clone)]
+enum Value {
+    Prim(Sort, String),
+    /// An entity, with SMT terms for its type name and id, and the entity
+    /// types it may have
+    Entity {
+        ty: String,
+        id: String,
+        types: Vec<ast::Name>,
+    },
+    Record(BTreeMap<SmolStr, Field>),
+    /// A value which can't be translated, with what it is
+    Opaque(String),
+}
+
+/// A record attribute, with an SMT term for whether it is present
+#[derive(Debug, Clone)]
+struct Field {
+    value: Value,
+    present: String,
+}
+
+/// A translated expression: its value, if evaluating it doesn't error, and
+/// an SMT term for whether it errors
+#[derive(Debug, Clone)]
+struct Term {
+    value: Value,
+    err: String,
+}
+
+impl Term {
+    fn ok(value: Value) -> Self {
+        Self {
+            value,
+            err: "false".to_string(),
+        }
+    }
+
+    /// A term which always errors, e.g. on a type error
+    fn error() -> Self {
+        Self {
+            value: Value::Prim(Sort::Bool, "false".to_string()),
+            err: "true".to_string(),
+        }
+    }
+
+    /// The value and error of a term expected to be a boolean; any other
+    /// value is a type error
+    fn as_bool(&self) -> (String, String) {
+        match &self.value {
+            Value::Prim(Sort::Bool, value) => (value.clone(), self.err.clone()),
+            _ => ("false".to_string(), "true".to_string()),
+        }
+    }
+
+    /// The value and error of a term expected to be of `sort`
+    fn as_sort(&self, sort: Sort) -> Option<(&str, &str)> {
+        match &self.value {
+            Value::Prim(s, value) if *s == sort => Some((value, &self.err)),
+            _ => None,
+        }
+    }
+}
+
+/// The values of the request variables
+#[derive(Debug, Clone)]
+struct RequestValues {
+    principal: Value,
+    resource: Value,
+    context: Value,
+}
+
+/// Terms whose values in a model make up a counterexample's entities
+#[derive(Debug, Clone)]
+enum Observation {
+    /// The entity `(ty, id)` has the attribute if it is of type `owner` and
+    /// `present` holds
+    Attr {
+        ty: String,
+        id: String,
+        owner: ast::Name,
+        attr: SmolStr,
+        value: Value,
+        present: String,
+    },
+    /// The entity `(ty, id)` is a member of `(parent_ty, parent_id)` if
+    /// `holds` holds
+    Member {
+        ty: String,
+        id: String,
+        parent_ty: String,
+        parent_id: String,
+        holds: String,
+    },
+}
+
+struct Encoder<'a> {
+    schema: &'a cedar_policy_validator::ValidatorSchema,
+    action: ast::EntityUID,
+    action_entities: cedar_policy_core::entities::Entities,
+    script: String,
+    next: usize,
+    /// Declared functions by a description of what they are
+    functions: HashMap<String, String>,
+    observations: Vec<Observation>,
+}
+
+type Translation<T> = std::result::Result<T, String>;
+
+impl Encoder<'_> {
+    fn fresh(&mut self) -> String {
+        let name = format!("v{}", self.next);
+        self.next += 1;
+        name
+    }
+
+    fn declare_const(&mut self, sort: Sort, label: &str) -> String {
+        let name = self.fresh();
+        let _ = writeln!(
+            self.script,
+            "; {label}\n(declare-const {name} {})",
+            sort.smt()
+        );
+        name
+    }
+
+    /// The function described by `label`, declared the first time
+    fn function(&mut self, label: String, args: &str, sort: Sort) -> String {
+        if let Some(name) = self.functions.get(&label) {
+            return name.clone();
+        }
+        let name = self.fresh();
+        let _ = writeln!(
+            self.script,
+            "; {label}\n(declare-fun {name} ({args}) {})",
+            sort.smt()
+        );
+        self.functions.insert(label, name.clone());
+        name
+    }
+
+    fn request_entity<'t>(
+        &mut self,
+        label: &str,
+        types: impl Iterator<Item = &'t ast::EntityType>,
+    ) -> Translation<Value> {
+        let types = types
+            .map(|ty| match ty {
+                ast::EntityType::Concrete(name) => Ok(name.clone()),
+                ast::EntityType::Unspecified => Err(format!("an unspecified {label} type")),
+            })
+            .collect::<Translation<Vec<_>>>()?;
+        let ty = self.declare_const(Sort::String, &format!("{label} type"));
+        let id = self.declare_const(Sort::String, &format!("{label} id"));
+        let domain: Vec<_> = types
+            .iter()
+            .map(|name| format!("(= {ty} {})", quote(&name.to_string())))
+            .collect();
+        let _ = writeln!(self.script, "(assert {})", any(&domain));
+        Ok(Value::Entity { ty, id, types })
+    }
+
+    fn declare_value(&mut self, kind: &Kind, label: &str) -> Value {
+        match kind {
+            Kind::Prim(sort) => Value::Prim(*sort, self.declare_const(*sort, label)),
+            Kind::Entity(name) => Value::Entity {
+                ty: quote(&name.to_string()),
+                id: self.declare_const(Sort::String, &format!("{label} id")),
+                types: vec![name.clone()],
+            },
+            Kind::Record(attrs) => Value::Record(
+                attrs
+                    .iter()
+                    .map(|(name, (kind, required))| {
+                        let label = format!("{label}.{name}");
+                        let value = self.declare_value(kind, &label);
+                        let present = if *required {
+                            "true".to_string()
+                        } else {
+                            self.declare_const(Sort::Bool, &format!("{label} is present"))
+                        };
+                        (name.clone(), Field { value, present })
+                    })
+                    .collect(),
+            ),
+            Kind::Opaque(what) => Value::Opaque(what.clone()),
+        }
+    }
+
+    fn entity_literal(uid: &ast::EntityUID) -> Translation<Value> {
+        match uid.entity_type() {
+            ast::EntityType::Concrete(name) => Ok(Value::Entity {
+                ty: quote(&name.to_string()),
+                id: quote(uid.eid().as_ref()),
+                types: vec![name.clone()],
+            }),
+            ast::EntityType::Unspecified => Err("an entity of unspecified type".to_string()),
+        }
+    }
+
+    #[allow(clippy::too_many_lines)]
+    fn expr(&mut self, e: &ast::Expr, env: &SlotEnv, request: &RequestValues) -> Translation<Term> {
+        match e.expr_kind() {
+            ExprKind::Lit(Literal::Bool(b)) => Ok(Term::ok(Value::Prim(Sort::Bool, b.to_string()))),
+            ExprKind::Lit(Literal::Long(n)) => Ok(Term::ok(Value::Prim(Sort::Long, bv64(*n)))),
+            ExprKind::Lit(Literal::String(s)) => Ok(Term::ok(Value::Prim(Sort::String, quote(s)))),
+            ExprKind::Lit(Literal::EntityUID(uid)) => Ok(Term::ok(Self::entity_literal(uid)?)),
+            ExprKind::Var(Var::Principal) => Ok(Term::ok(request.principal.clone())),
+            ExprKind::Var(Var::Resource) => Ok(Term::ok(request.resource.clone())),
+            ExprKind::Var(Var::Context) => Ok(Term::ok(request.context.clone())),
+            ExprKind::Var(Var::Action) => Ok(Term::ok(Self::entity_literal(&self.action)?)),
+            ExprKind::Slot(slot) => match env.get(slot) {
+                Some(uid) => Ok(Term::ok(Self::entity_literal(uid)?)),
+                None => Err("an unlinked slot".to_string()),
+            },
+            ExprKind::Unknown { .. } => Err("unknowns".to_string()),
+            ExprKind::If {
+                test_expr,
+                then_expr,
+                else_expr,
+            } => {
+                let (test, test_err) = self.expr(test_expr, env, request)?.as_bool();
+                let then_term = self.expr(then_expr, env, request)?;
+                let else_term = self.expr(else_expr, env, request)?;
+                let value = ite_value(&test, &then_term.value, &else_term.value)?;
+                let err = or(&test_err, &ite(&test, &then_term.err, &else_term.err));
+                Ok(Term { value, err })
+            }
+            ExprKind::And { left, right } => {
+                let (l, l_err) = self.expr(left, env, request)?.as_bool();
+                let (r, r_err) = self.expr(right, env, request)?.as_bool();
+                Ok(Term {
+                    value: Value::Prim(Sort::Bool, and(&l, &r)),
+                    err: or(&l_err, &and(&l, &r_err)),
+                })
+            }
+            ExprKind::Or { left, right } => {
+                let (l, l_err) = self.expr(left, env, request)?.as_bool();
+                let (r, r_err) = self.expr(right, env, request)?.as_bool();
+                Ok(Term {
+                    value: Value::Prim(Sort::Bool, or(&l, &r)),
+                    err: or(&l_err, &and(&not(&l), &r_err)),
+                })
+            }
+            ExprKind::UnaryApp { op, arg } => {
+                let arg = self.expr(arg, env, request)?;
+                Ok(match op {
+                    UnaryOp::Not => {
+                        let (value, err) = arg.as_bool();
+                        Term {
+                            value: Value::Prim(Sort::Bool, not(&value)),
+                            err,
+                        }
+                    }
+                    UnaryOp::Neg => match arg.as_sort(Sort::Long) {
+                        Some((value, err)) => Term {
+                            value: Value::Prim(Sort::Long, format!("(bvneg {value})")),
+                            err: or(err, &format!("(= {value} {})", bv64(i64::MIN))),
+                        },
+                        None => Term::error(),
+                    },
+                })
+            }
+            ExprKind::BinaryApp { op, arg1, arg2 } => {
+                if *op == BinaryOp::In && matches!(arg1.expr_kind(), ExprKind::Var(Var::Action)) {
+                    return self.action_in(arg2, env);
+                }
+                let left = self.expr(arg1, env, request)?;
+                let right = self.expr(arg2, env, request)?;
+                match op {
+                    BinaryOp::Eq => Ok(Term {
+                        value: Value::Prim(Sort::Bool, eq(&left.value, &right.value)?),
+                        err: or(&left.err, &right.err),
+                    }),
+                    BinaryOp::Less | BinaryOp::LessEq => {
+                        let function = if *op == BinaryOp::Less {
+                            "bvslt"
+                        } else {
+                            "bvsle"
+                        };
+                        Ok(compare(&left, &right, Sort::Long, function))
+                    }
+                    BinaryOp::Add | BinaryOp::Sub => Ok(add_or_sub(&left, &right, *op)),
+                    BinaryOp::In => self.member(&left, &right, arg2),
+                    BinaryOp::Contains | BinaryOp::ContainsAll | BinaryOp::ContainsAny => {
+                        Err("sets".to_string())
+                    }
+                    BinaryOp::GetTag | BinaryOp::HasTag => Err("tags".to_string()),
+                }
+            }
+            ExprKind::MulByConst { arg, constant } => {
+                let arg = self.expr(arg, env, request)?;
+                Ok(match arg.as_sort(Sort::Long) {
+                    Some((value, err)) => {
+                        let product = format!("(bvmul {value} {})", bv64(*constant));
+                        let wide = format!(
+                            "(bvmul ((_ sign_extend 64) {value}) ((_ sign_extend 64) {}))",
+                            bv64(*constant)
+                        );
+                        Term {
+                            err: or(
+                                err,
+                                &format!("(not (= {wide} ((_ sign_extend 64) {product})))"),
+                            ),
+                            value: Value::Prim(Sort::Long, product),
+                        }
+                    }
+                    None => Term::error(),
+                })
+            }
+            ExprKind::ExtensionFunctionApp { fn_name, args } => {
+                let name = fn_name.to_string();
+                let args = args
+                    .iter()
+                    .map(|arg| Ok((arg, self.expr(arg, env, request)?)))
+                    .collect::<Translation<Vec<_>>>()?;
+                match (name.as_str(), args.as_slice()) {
+                    ("u256", [(arg, _)]) => match arg.expr_kind() {
+                        ExprKind::Lit(Literal::String(s)) => Ok(u256_literal(s)),
+                        _ => Err("`u256()` of a non-literal string".to_string()),
+                    },
+                    ("u256LessThan", [(_, l), (_, r)]) => Ok(compare(l, r, Sort::U256, "bvult")),
+                    ("u256LessThanOrEqual", [(_, l), (_, r)]) => {
+                        Ok(compare(l, r, Sort::U256, "bvule"))
+                    }
+                    ("u256GreaterThan", [(_, l), (_, r)]) => Ok(compare(l, r, Sort::U256, "bvugt")),
+                    ("u256GreaterThanOrEqual", [(_, l), (_, r)]) => {
+                        Ok(compare(l, r, Sort::U256, "bvuge"))
+                    }
+                    _ => Err(format!("the extension function `{name}`")),
+                }
+            }
+            ExprKind::GetAttr { expr, attr } => {
+                let base = self.expr(expr, env, request)?;
+                self.get_attr(&base, attr, false)
+            }
+            ExprKind::HasAttr { expr, attr } => {
+                let base = self.expr(expr, env, request)?;
+                self.get_attr(&base, attr, true)
+            }
+            ExprKind::Like { .. } => Err("`like`".to_string()),
+            ExprKind::Is { expr, entity_type } => {
+                let term = self.expr(expr, env, request)?;
+                Ok(match &term.value {
+                    Value::Entity { ty, .. } => Term {
+                        value: Value::Prim(
+                            Sort::Bool,
+                            format!("(= {ty} {})", quote(&entity_type.to_string())),
+                        ),
+                        err: term.err,
+                    },
+                    _ => Term::error(),
+                })
+            }
+            ExprKind::Set(_) => Err("sets".to_string()),
+            ExprKind::Record { pairs } => {
+                let mut err = "false".to_string();
+                let mut fields = BTreeMap::new();
+                for (name, value) in pairs.iter() {
+                    let term = self.expr(value, env, request)?;
+                    err = or(&err, &term.err);
+                    fields.insert(
+                        name.clone(),
+                        Field {
+                            value: term.value,
+                            present: "true".to_string(),
+                        },
+                    );
+                }
+                Ok(Term {
+                    value: Value::Record(fields),
+                    err,
+                })
+            }
+        }
+    }
+
+    /// `action in rhs`, decided with the schema's action hierarchy
+    fn action_in(&self, rhs: &ast::Expr, env: &SlotEnv) -> Translation<Term> {
+        let literal = |e: &ast::Expr| match e.expr_kind() {
+            ExprKind::Lit(Literal::EntityUID(uid)) => Ok((**uid).clone()),
+            ExprKind::Slot(slot) => env
+                .get(slot)
+                .cloned()
+                .ok_or_else(|| "an unlinked slot".to_string()),
+            _ => Err("`action in` an expression other than a literal".to_string()),
+        };
+        let groups = match rhs.expr_kind() {
+            ExprKind::Set(elements) => elements
+                .iter()
+                .map(literal)
+                .collect::<Translation<Vec<_>>>()?,
+            _ => vec![literal(rhs)?],
+        };
+        let within = groups.iter().any(|group| {
+            *group == self.action
+                || matches!(
+                    self.action_entities.entity(&self.action),
+                    Dereference::Data(action) if action.is_descendant_of(group)
+                )
+        });
+        Ok(Term::ok(Value::Prim(Sort::Bool, within.to_string())))
+    }
+
+    /// `left in right`, where `right` is an entity or a set literal of them
+    fn member(&mut self, left: &Term, right: &Term, rhs: &ast::Expr) -> Translation<Term> {
+        let Value::Entity { ty, id, types } = &left.value else {
+            return Ok(Term::error());
+        };
+        if let ExprKind::Set(_) = rhs.expr_kind() {
+            return Err("`in` a set".to_string());
+        }
+        let Value::Entity {
+            ty: parent_ty,
+            id: parent_id,
+            types: parent_types,
+        } = &right.value
+        else {
+            return Ok(Term::error());
+        };
+        let member = self.function(
+            "membership".to_string(),
+            "String String String String",
+            Sort::Bool,
+        );
+        let holds = format!("({member} {ty} {id} {parent_ty} {parent_id})");
+        self.observations.push(Observation::Member {
+            ty: ty.clone(),
+            id: id.clone(),
+            parent_ty: parent_ty.clone(),
+            parent_id: parent_id.clone(),
+            holds: holds.clone(),
+        });
+        // only the types the schema declares as members of the parent's
+        // types can be members
+        let possible: Vec<_> = types
+            .iter()
+            .filter(|child| {
+                parent_types.iter().any(|parent| {
+                    self.schema
+                        .get_entity_type(parent)
+                        .is_some_and(|parent| parent.descendants.contains(*child))
+                })
+            })
+            .map(|child| format!("(= {ty} {})", quote(&child.to_string())))
+            .collect();
+        let value = or(
+            &format!("(and (= {ty} {parent_ty}) (= {id} {parent_id}))"),
+            &and(&any(&possible), &holds),
+        );
+        Ok(Term {
+            value: Value::Prim(Sort::Bool, value),
+            err: or(&left.err, &right.err),
+        })
+    }
+
+    /// `base.attr`, or `base has attr` if `has`
+    fn get_attr(&mut self, base: &Term, attr: &SmolStr, has: bool) -> Translation<Term> {
+        let (value, present) = match &base.value {
+            Value::Record(fields) => fields.get(attr).map_or_else(
+                || {
+                    (
+                        Value::Prim(Sort::Bool, "false".to_string()),
+                        "false".to_string(),
+                    )
+                },
+                |field| (field.value.clone(), field.present.clone()),
+            ),
+            Value::Entity { ty, id, types } => self.entity_attr(ty, id, types, attr)?,
+            Value::Prim(..) => return Ok(Term::error()),
+            Value::Opaque(what) => return Err(what.clone()),
+        };
+        if has {
+            Ok(Term {
+                value: Value::Prim(Sort::Bool, present),
+                err: base.err.clone(),
+            })
+        } else {
+            if let Value::Opaque(what) = value {
+                return Err(what);
+            }
+            Ok(Term {
+                value,
+                err: or(&base.err, &not(&present)),
+            })
+        }
+    }
+
+    /// The value of the attribute `attr` of the entity `(ty, id)`, which has
+    /// one of `types`, and whether it is present
+    fn entity_attr(
+        &mut self,
+        ty: &str,
+        id: &str,
+        types: &[ast::Name],
+        attr: &SmolStr,
+    ) -> Translation<(Value, String)> {
+        let mut cases = Vec::new();
+        for owner in types {
+            let Some(attr_type) = self
+                .schema
+                .get_entity_type(owner)
+                .and_then(|entity_type| entity_type.attr(attr))
+            else {
+                continue;
+            };
+            let kind = kind_of(&attr_type.attr_type);
+            let label = format!("{owner}.{attr}");
+            let value = match &kind {
+                Kind::Prim(sort) => {
+                    let f = self.function(label.clone(), "String", *sort);
+                    Value::Prim(*sort, format!("({f} {id})"))
+                }
+                Kind::Entity(name) => {
+                    let f = self.function(label.clone(), "String", Sort::String);
+                    Value::Entity {
+                        ty: quote(&name.to_string()),
+                        id: format!("({f} {id})"),
+                        types: vec![name.clone()],
+                    }
+                }
+                Kind::Record(_) => Value::Opaque("record attributes of entities".to_string()),
+                Kind::Opaque(what) => Value::Opaque(what.clone()),
+            };
+            let present = if attr_type.is_required {
+                "true".to_string()
+            } else {
+                let f = self.function(format!("{label} is present"), "String", Sort::Bool);
+                format!("({f} {id})")
+            };
+            self.observations.push(Observation::Attr {
+                ty: ty.to_string(),
+                id: id.to_string(),
+                owner: owner.clone(),
+                attr: attr.clone(),
+                value: value.clone(),
+                present: present.clone(),
+            });
+            let is_owner = format!("(= {ty} {})", quote(&owner.to_string()));
+            cases.push((is_owner, value, present));
+        }
+        let present = cases
+            .iter()
+            .map(|(is_owner, _, present)| and(is_owner, present))
+            .collect::<Vec<_>>();
+        let mut cases = cases.into_iter().rev();
+        let Some((_, mut value, _)) = cases.next() else {
+            return Ok((
+                Value::Prim(Sort::Bool, "false".to_string()),
+                "false".to_string(),
+            ));
+        };
+        for (is_owner, case_value, _) in cases {
+            value = ite_value(&is_owner, &case_value, &value)?;
+        }
+        let present = any(&present);
+        Ok((value, present))
+    }
+}
+
+/// A script and how to read a counterexample from a model of it
+struct Encoding {
+    script: String,
+    request: RequestValues,
+    observations: Vec<Observation>,
+    action: EntityUid,
+    action_entities: cedar_policy_core::entities::Entities,
+}
+
+impl Encoding {
+    /// The terms whose values make up a counterexample, in order
+    fn terms(&self) -> Vec<String> {
+        fn value_terms(value: &Value, terms: &mut Vec<String>) {
+            match value {
+                Value::Prim(_, term) => terms.push(term.clone()),
+                Value::Entity { ty, id, .. } => {
+                    terms.push(ty.clone());
+                    terms.push(id.clone());
+                }
+                Value::Record(fields) => {
+                    for field in fields.values() {
+                        terms.push(field.present.clone());
+                        value_terms(&field.value, terms);
+                    }
+                }
+                Value::Opaque(_) => (),
+            }
+        }
+        let mut terms = Vec::new();
+        value_terms(&self.request.principal, &mut terms);
+        value_terms(&self.request.resource, &mut terms);
+        value_terms(&self.request.context, &mut terms);
+        for observation in &self.observations {
+            match observation {
+                Observation::Attr {
+                    ty,
+                    id,
+                    value,
+                    present,
+                    ..
+                } => {
+                    terms.extend([ty.clone(), id.clone(), present.clone()]);
+                    value_terms(value, &mut terms);
+                }
+                Observation::Member {
+                    ty,
+                    id,
+                    parent_ty,
+                    parent_id,
+                    holds,
+                } => terms.extend([
+                    ty.clone(),
+                    id.clone(),
+                    parent_ty.clone(),
+                    parent_id.clone(),
+                    holds.clone(),
+                ]),
+            }
+        }
+        terms.dedup();
+        terms
+    }
+
+    fn script(&self) -> String {
+        format!(
+            "(set-option :produce-models true)\n(set-logic ALL)\n{}(check-sat)\n(get-value ({}))\n",
+            self.script,
+            self.terms().join(" ")
+        )
+    }
+
+    /// The verdict in the solver's `output`
+    fn interpret(&self, output: &str) -> Result<Verdict, AnalysisError> {
+        let output = output.trim_start();
+        let (answer, rest) = output.split_once('\n').unwrap_or((output, ""));
+        match answer.trim() {
+            "unsat" => return Ok(Verdict::Unreachable),
+            "unknown" => return Ok(Verdict::Unknown),
+            "sat" => (),
+            other => {
+                return Err(AnalysisError::Solver(format!(
+                    "unexpected answer `{other}`"
+                )))
+            }
+        }
+        let values = match parse_sexpr(rest) {
+            Some(SExpr::List(pairs)) => pairs
+                .into_iter()
+                .map(|pair| match pair {
+                    SExpr::List(mut pair) if pair.len() == 2 => pair.pop(),
+                    _ => None,
+                })
+                .collect::<Option<Vec<_>>>(),
+            _ => None,
+        }
+        .ok_or_else(|| AnalysisError::Solver("malformed model".to_string()))?;
+        let model: HashMap<String, SExpr> = self.terms().into_iter().zip(values).collect();
+        let model = Model(model);
+        self.counterexample(&model)
+            .map(|counterexample| Verdict::Counterexample(Box::new(counterexample)))
+            .ok_or_else(|| AnalysisError::Solver("model doesn't give every value".to_string()))
+    }
+
+    fn counterexample(&self, model: &Model) -> Option<Counterexample> {
+        let principal = model.entity(&self.request.principal)?;
+        let resource = model.entity(&self.request.resource)?;
+        let context = match model.restricted(&self.request.context)? {
+            Restricted::Record(fields) => fields,
+            Restricted::Expr(_) => Vec::new(),
+        };
+        let request = Request::new(
+            Some(principal.clone()),
+            Some(self.action.clone()),
+            Some(resource.clone()),
+            Context::from_pairs(context.into_iter().map(|(k, v)| (k, v.into_restricted()))),
+        );
+
+        let mut entities = EntityBuilder::default();
+        entities.entry(&principal);
+        entities.entry(&resource);
+        for observation in &self.observations {
+            match observation {
+                Observation::Attr {
+                    ty,
+                    id,
+                    owner,
+                    attr,
+                    value,
+                    present,
+                } => {
+                    if !matches!(value, Value::Opaque(_))
+                        && model.string(ty)? == owner.to_string()
+                        && model.bool(present)?
+                    {
+                        let value = model.restricted(value)?.into_restricted();
+                        entities
+                            .entry(&model.uid(ty, id)?)
+                            .0
+                            .insert(attr.to_string(), value);
+                    }
+                }
+                Observation::Member {
+                    ty,
+                    id,
+                    parent_ty,
+                    parent_id,
+                    holds,
+                } => {
+                    if model.bool(holds)? {
+                        let parent = model.uid(parent_ty, parent_id)?;
+                        entities.entry(&parent);
+                        entities.entry(&model.uid(ty, id)?).1.insert(parent);
+                    }
+                }
+            }
+        }
+        let actions = self.action_entities.iter().cloned().map(Entity);
+        let entities = entities
+            .0
+            .into_iter()
+            .map(|(uid, (attrs, parents))| Entity::new(uid, attrs, parents))
+            .chain(actions);
+        Some(Counterexample {
+            request,
+            entities: Entities::from_entities(entities).ok()?,
+        })
+    }
+}
+
+/// The attributes and parents of the entities in a counterexample
+#[derive(Default)]
+struct EntityBuilder(
+    HashMap<EntityUid, (HashMap<String, RestrictedExpression>, HashSet<EntityUid>)>,
+);
+
+impl EntityBuilder {
+    fn entry(
+        &mut self,
+        uid: &EntityUid,
+    ) -> &mut (HashMap<String, RestrictedExpression>, HashSet<EntityUid>) {
+        self.0.entry(uid.clone()).or_default()
+    }
+}
+
+/// A value in a model, as an expression
+enum Restricted {
+    Expr(RestrictedExpression),
+    Record(Vec<(String, Self)>),
+}
+
+impl Restricted {
+    fn into_restricted(self) -> RestrictedExpression {
+        match self {
+            Self::Expr(restricted) => restricted,
+            Self::Record(fields) => RestrictedExpression::new_record(
+                fields.into_iter().map(|(k, v)| (k, v.into_restricted())),
+            ),
+        }
+    }
+}
+
+/// The values of terms in a model
+struct Model(HashMap<String, SExpr>);
+
+impl Model {
+    fn get(&self, term: &str) -> Option<&SExpr> {
+        self.0.get(term)
+    }
+
+    fn bool(&self, term: &str) -> Option<bool> {
+        match self.get(term)? {
+            SExpr::Atom(atom) if atom == "true" => Some(true),
+            SExpr::Atom(atom) if atom == "false" => Some(false),
+            _ => None,
+        }
+    }
+
+    fn string(&self, term: &str) -> Option<String> {
+        match self.get(term)? {
+            SExpr::String(s) => Some(unescape(s)),
+            _ => None,
+        }
+    }
+
+    fn bits(&self, term: &str) -> Option<U256> {
+        match self.get(term)? {
+            SExpr::Atom(atom) => {
+                if let Some(hex) = atom.strip_prefix("#x") {
+                    U256::from_str_radix(hex, 16).ok()
+                } else {
+                    U256::from_str_radix(atom.strip_prefix("#b")?, 2).ok()
+                }
+            }
+            // `(_ bvN width)`
+            SExpr::List(items) => match items.as_slice() {
+                [SExpr::Atom(underscore), SExpr::Atom(value), _] if underscore == "_" => {
+                    U256::from_dec_str(value.strip_prefix("bv")?).ok()
+                }
+                _ => None,
+            },
+            SExpr::String(_) => None,
+        }
+    }
+
+    fn uid(&self, ty: &str, id: &str) -> Option<EntityUid> {
+        Some(EntityUid::from_type_name_and_id(
+            EntityTypeName::from_str(&self.string(ty)?).ok()?,
+            EntityId::from_str(&self.string(id)?).ok()?,
+        ))
+    }
+
+    fn entity(&self, value: &Value) -> Option<EntityUid> {
+        match value {
+            Value::Entity { ty, id, .. } => self.uid(ty, id),
+            _ => None,
+        }
+    }
+
+    /// The value of `value` as an expression. Opaque values, and the record
+    /// attributes holding them, are left out.
+    fn restricted(&self, value: &Value) -> Option<Restricted> {
+        let expr = |restricted| Some(Restricted::Expr(restricted));
+        match value {
+            Value::Prim(Sort::Bool, term) => expr(RestrictedExpression::new_bool(self.bool(term)?)),
+            Value::Prim(Sort::Long, term) => {
+                let bits = self.bits(term)?.low_u64();
+                expr(RestrictedExpression::new_long(i64::from_ne_bytes(
+                    bits.to_ne_bytes(),
+                )))
+            }
+            Value::Prim(Sort::U256, term) => expr(
+                RestrictedExpression::from_str(&format!("u256(\"{}\")", self.bits(term)?)).ok()?,
+            ),
+            Value::Prim(Sort::String, term) => {
+                expr(RestrictedExpression::new_string(self.string(term)?))
+            }
+            Value::Entity { .. } => {
+                expr(RestrictedExpression::from_str(&self.entity(value)?.to_string()).ok()?)
+            }
+            Value::Record(fields) => {
+                let mut present = Vec::new();
+                for (name, field) in fields {
+                    if !matches!(field.value, Value::Opaque(_)) && self.bool(&field.present)? {
+                        present.push((name.to_string(), self.restricted(&field.value)?));
+                    }
+                }
+                Some(Restricted::Record(present))
+            }
+            Value::Opaque(_) => None,
+        }
+    }
+}
+
+/// An s-expression in solver output
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum SExpr {
+    Atom(String),
+    /// A string literal, still escaped
+    String(String),
+    List(Vec<Self>),
+}
+
+/// The first s-expression in `input`
+fn parse_sexpr(input: &str) -> Option<SExpr> {
+    fn parse(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) -> Option<SExpr> {
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+        match chars.next()? {
+            '(' => {
+                let mut items = Vec::new();
+                loop {
+                    while chars.peek().is_some_and(|c| c.is_whitespace()) {
+                        chars.next();
+                    }
+                    if chars.peek() == Some(&')') {
+                        chars.next();
+                        return Some(SExpr::List(items));
+                    }
+                    items.push(parse(chars)?);
+                }
+            }
+            '"' => {
+                let mut s = String::new();
+                loop {
+                    match chars.next()? {
+                        '"' if chars.peek() == Some(&'"') => {
+                            chars.next();
+                            s.push('"');
+                        }
+                        '"' => return Some(SExpr::String(s)),
+                        c => s.push(c),
+                    }
+                }
+            }
+            '|' => {
+                let mut s = String::new();
+                loop {
+                    match chars.next()? {
+                        '|' => return Some(SExpr::Atom(s)),
+                        c => s.push(c),
+                    }
+                }
+            }
+            ')' => None,
+            c => {
+                let mut s = c.to_string();
+                while let Some(c) = chars.peek() {
+                    if c.is_whitespace() || *c == '(' || *c == ')' {
+                        break;
+                    }
+                    s.push(*c);
+                    chars.next();
+                }
+                Some(SExpr::Atom(s))
+            }
+        }
+    }
+    parse(&mut input.chars().peekable())
+}
+
+/// An SMT-LIB string literal for `s`: `"` is doubled, and characters other
+/// than printable ASCII, and `\`, are written as `\u{...}`
+fn quote(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\"\""),
+            ' '..='~' if c != '\\' => quoted.push(c),
+            _ => {
+                let _ = write!(quoted, "\\u{{{:x}}}", u32::from(c));
+            }
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// The string an SMT-LIB string literal's contents denote
+fn unescape(s: &str) -> String {
+    let mut unescaped = String::new();
+    let mut rest = s;
+    while let Some(i) = rest.find("\\u") {
+        let (before, escape) = rest.split_at(i);
+        unescaped.push_str(before);
+        let escape = escape.get(2..).unwrap_or_default();
+        let (hex, after) = escape.strip_prefix('{').map_or_else(
+            || {
+                (
+                    escape.get(..4).unwrap_or_default(),
+                    escape.get(4..).unwrap_or_default(),
+                )
+            },
+            |braced| braced.split_once('}').unwrap_or(("", escape)),
+        );
+        if let Some(c) = u32::from_str_radix(hex, 16).ok().and_then(char::from_u32) {
+            unescaped.push(c);
+            rest = after;
+        } else {
+            unescaped.push_str("\\u");
+            rest = escape;
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+fn bv64(n: i64) -> String {
+    format!("(_ bv{} 64)", u64::from_ne_bytes(n.to_ne_bytes()))
+}
+
+fn u256_literal(s: &str) -> Term {
+    let digits = !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    match U256::from_dec_str(s) {
+        Ok(value) if digits => Term::ok(Value::Prim(Sort::U256, format!("(_ bv{value} 256)"))),
+        _ => Term::error(),
+    }
+}
+
+fn not(a: &str) -> String {
+    match a {
+        "true" => "false".to_string(),
+        "false" => "true".to_string(),
+        _ => format!("(not {a})"),
+    }
+}
+
+fn and(a: &str, b: &str) -> String {
+    match (a, b) {
+        ("false", _) | (_, "false") => "false".to_string(),
+        ("true", other) | (other, "true") => other.to_string(),
+        _ => format!("(and {a} {b})"),
+    }
+}
+
+fn or(a: &str, b: &str) -> String {
+    match (a, b) {
+        ("true", _) | (_, "true") => "true".to_string(),
+        ("false", other) | (other, "false") => other.to_string(),
+        _ => format!("(or {a} {b})"),
+    }
+}
+
+/// The disjunction of `terms`
+fn any(terms: &[String]) -> String {
+    terms
+        .iter()
+        .fold("false".to_string(), |acc, term| or(&acc, term))
+}
+
+fn ite(test: &str, then: &str, otherwise: &str) -> String {
+    match test {
+        "true" => then.to_string(),
+        "false" => otherwise.to_string(),
+        _ if then == otherwise => then.to_string(),
+        _ => format!("(ite {test} {then} {otherwise})"),
+    }
+}
+
+fn ite_value(test: &str, then: &Value, otherwise: &Value) -> Translation<Value> {
+    match (then, otherwise) {
+        (Value::Prim(s1, a), Value::Prim(s2, b)) if s1 == s2 => {
+            Ok(Value::Prim(*s1, ite(test, a, b)))
+        }
+        (
+            Value::Entity {
+                ty: t1,
+                id: i1,
+                types: types1,
+            },
+            Value::Entity {
+                ty: t2,
+                id: i2,
+                types: types2,
+            },
+        ) => {
+            let mut types = types1.clone();
+            types.extend(types2.iter().filter(|ty| !types1.contains(ty)).cloned());
+            Ok(Value::Entity {
+                ty: ite(test, t1, t2),
+                id: ite(test, i1, i2),
+                types,
+            })
+        }
+        _ => Err("conditionals whose branches have different types".to_string()),
+    }
+}
+
+fn eq(left: &Value, right: &Value) -> Translation<String> {
+    match (left, right) {
+        (Value::Prim(s1, a), Value::Prim(s2, b)) if s1 == s2 => Ok(format!("(= {a} {b})")),
+        (Value::Entity { ty: t1, id: i1, .. }, Value::Entity { ty: t2, id: i2, .. }) => {
+            Ok(format!("(and (= {t1} {t2}) (= {i1} {i2}))"))
+        }
+        (Value::Record(_), _) | (_, Value::Record(_)) => Err("record equality".to_string()),
+        (Value::Opaque(what), _) | (_, Value::Opaque(what)) => Err(what.clone()),
+        _ => Ok("false".to_string()),
+    }
+}
+
+fn compare(left: &Term, right: &Term, sort: Sort, function: &str) -> Term {
+    match (left.as_sort(sort), right.as_sort(sort)) {
+        (Some((l, l_err)), Some((r, r_err))) => Term {
+            value: Value::Prim(Sort::Bool, format!("({function} {l} {r})")),
+            err: or(l_err, r_err),
+        },
+        _ => Term::error(),
+    }
+}
+
+fn add_or_sub(left: &Term, right: &Term, op: BinaryOp) -> Term {
+    let (Some((l, l_err)), Some((r, r_err))) =
+        (left.as_sort(Sort::Long), right.as_sort(Sort::Long))
+    else {
+        return Term::error();
+    };
+    let sign = |term: &str| format!("((_ extract 63 63) {term})");
+    let (value, overflow) = if op == BinaryOp::Add {
+        let sum = format!("(bvadd {l} {r})");
+        // overflow iff the operands have the same sign, which the sum doesn't
+        let overflow = format!(
+            "(and (= {} {}) (not (= {} {})))",
+            sign(l),
+            sign(r),
+            sign(&sum),
+            sign(l)
+        );
+        (sum, overflow)
+    } else {
+        let difference = format!("(bvsub {l} {r})");
+        // overflow iff the operands have different signs, and the difference
+        // has the sign of the subtrahend
+        let overflow = format!(
+            "(and (not (= {} {})) (not (= {} {})))",
+            sign(l),
+            sign(r),
+            sign(&difference),
+            sign(l)
+        );
+        (difference, overflow)
+    };
+    Term {
+        value: Value::Prim(Sort::Long, value),
+        err: or(&or(l_err, r_err), &overflow),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Authorizer, Decision};
+
+    fn schema() -> Schema {
+        Schema::from_str(
+            r#"{"": {
+                "entityTypes": {
+                    "User": {
+                        "memberOfTypes": ["Team"],
+                        "shape": {"type": "Record", "attributes": {
+                            "cap": {"type": "Long"}
+                        }}
+                    },
+                    "Team": {},
+                    "Vault": {"shape": {"type": "Record", "attributes": {
+                        "frozen": {"type": "Boolean"},
+                        "balance": {"type": "Extension", "name": "u256"}
+                    }}}
+                },
+                "actions": {"withdraw": {"appliesTo": {
+                    "principalTypes": ["User"],
+                    "resourceTypes": ["Vault"],
+                    "context": {"type": "Record", "attributes": {
+                        "amount": {"type": "Long"},
+                        "memo": {"type": "String", "required": false}
+                    }}
+                }}}
+            }}"#,
+        )
+        .unwrap()
+    }
+
+    fn policies() -> PolicySet {
+        PolicySet::from_str(
+            r#"permit(principal in Team::"treasury", action == Action::"withdraw", resource)
+               when { context.amount <= principal.cap };
+               forbid(principal, action, resource)
+               when { resource.frozen || resource.balance.u256LessThan(u256("100")) };"#,
+        )
+        .unwrap()
+    }
+
+    fn withdraw() -> EntityUid {
+        EntityUid::from_strs("Action", "withdraw")
+    }
+
+    #[test]
+    fn scripts() {
+        let schema = schema();
+        let verifier = Verifier::new(&schema);
+        let query = Expression::from_str("context.amount > 1000").unwrap();
+        let script = verifier.script(&policies(), &withdraw(), &query).unwrap();
+        assert!(script.starts_with("(set-option :produce-models true)"));
+        assert!(script.contains("; User.cap\n(declare-fun"));
+        assert!(script.contains("(bvult"));
+        assert!(script.contains("(_ bv100 256)"));
+        assert!(script.contains("(check-sat)\n(get-value ("));
+
+        assert!(matches!(
+            verifier.script(
+                &policies(),
+                &EntityUid::from_strs("Action", "deposit"),
+                &query
+            ),
+            Err(AnalysisError::UnknownAction(_))
+        ));
+        let sets = PolicySet::from_str(
+            "permit(principal, action, resource) when { [1, 2].contains(context.amount) };",
+        )
+        .unwrap();
+        assert!(matches!(
+            verifier.script(&sets, &withdraw(), &query),
+            Err(AnalysisError::Unsupported { policy: Some(id), .. }) if id.to_string() == "policy0"
+        ));
+    }
+
+    #[test]
+    fn strings_are_quoted() {
+        for s in ["plain", "a \"quote\"", "back\\slash", "ünïcode ✓", ""] {
+            let quoted = quote(s);
+            let Some(SExpr::String(contents)) = parse_sexpr(&quoted) else {
+                panic!("{quoted}");
+            };
+            assert_eq!(unescape(&contents), s);
+        }
+    }
+
+    #[test]
+    fn counterexamples_are_read_from_models() {
+        let schema = schema();
+        let policies = PolicySet::from_str(
+            r#"permit(principal, action == Action::"withdraw", resource)
+               when { context.amount <= principal.cap };"#,
+        )
+        .unwrap();
+        let query = Expression::from_str("context.amount > 1000").unwrap();
+        let encoding = Verifier::new(&schema)
+            .encode(&policies, &withdraw(), &query)
+            .unwrap();
+        assert!(matches!(
+            encoding.interpret("unsat\n(error \"model is not available\")"),
+            Ok(Verdict::Unreachable)
+        ));
+
+        // the request, the context's attributes, and alice's cap
+        let model = r#"sat
+            ((v0 "User") (v1 "alice") (v2 "Vault") (v3 "main")
+             (true true) (v4 #x00000000000007d0) (v6 false) (v5 "")
+             (v0 "User") (v1 "alice") (true true) ((v7 v1) (_ bv5000 64)))"#;
+        assert_eq!(
+            encoding.terms(),
+            ["v0", "v1", "v2", "v3", "true", "v4", "v6", "v5", "v0", "v1", "true", "(v7 v1)"]
+        );
+        let Verdict::Counterexample(counterexample) = encoding.interpret(model).unwrap() else {
+            panic!("expected a counterexample");
+        };
+        let response = Authorizer::new().is_authorized(
+            counterexample.request(),
+            &policies,
+            counterexample.entities(),
+        );
+        assert_eq!(response.decision(), Decision::Allow);
+        let alice = counterexample
+            .entities()
+            .get(&EntityUid::from_strs("User", "alice"))
+            .unwrap();
+        assert_eq!(
+            alice.attr("cap").unwrap().unwrap(),
+            crate::EvalResult::Long(5000)
+        );
+    }
+
+    #[test]
+    fn solver() {
+        if Command::new("z3").arg("--version").output().is_err() {
+            // no solver installed
+            return;
+        }
+        let schema = schema();
+        let verifier = Verifier::new(&schema);
+        let over_cap = Expression::from_str("context.amount > principal.cap").unwrap();
+        assert!(matches!(
+            verifier.can_allow(&policies(), &withdraw(), &over_cap),
+            Ok(Verdict::Unreachable)
+        ));
+        let large = Expression::from_str("context.amount > 1000").unwrap();
+        let Ok(Verdict::Counterexample(counterexample)) =
+            verifier.can_allow(&policies(), &withdraw(), &large)
+        else {
+            panic!("expected a counterexample");
+        };
+        let response = Authorizer::new().is_authorized(
+            counterexample.request(),
+            &policies(),
+            counterexample.entities(),
+        );
+        assert_eq!(response.decision(), Decision::Allow);
+    }
+}
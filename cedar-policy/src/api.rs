@@ -20,6 +20,7 @@
     clippy::missing_errors_doc,
     clippy::similar_names
 )]
+use arc_swap::ArcSwap;
 pub use ast::Effect;
 pub use authorizer::Decision;
 use cedar_policy_core::ast;
@@ -30,24 +31,50 @@ use cedar_policy_core::entities;
 use cedar_policy_core::entities::JsonDeserializationErrorContext;
 use cedar_policy_core::entities::{ContextSchema, Dereference, JsonDeserializationError};
 use cedar_policy_core::est;
-pub use cedar_policy_core::evaluator::{EvaluationError, EvaluationErrorKind};
+pub use cedar_policy_core::evaluator::{
+    CancellationToken, EvaluationError, EvaluationErrorKind, Interrupt, Interrupted,
+};
 use cedar_policy_core::evaluator::{Evaluator, RestrictedEvaluator};
 pub use cedar_policy_core::extensions;
 use cedar_policy_core::extensions::Extensions;
+pub use cedar_policy_core::metrics;
 use cedar_policy_core::parser;
 pub use cedar_policy_core::parser::err::ParseErrors;
+pub use cedar_policy_core::parser::ImportSource;
 use cedar_policy_core::parser::SourceInfo;
 use cedar_policy_core::FromNormalizedStr;
 pub use cedar_policy_validator::{
-    TypeErrorKind, UnsupportedFeature, ValidationErrorKind, ValidationWarningKind,
+    ContextMode, InvalidActionApplication, InvalidChainAnnotation, MissingAnnotation,
+    TypeErrorKind, UnknownChain, UnrecognizedActionId, UnrecognizedEntityType, UnsupportedFeature,
+    ValidationErrorKind, ValidationWarningKind,
 };
 use ref_cast::RefCast;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
 use smol_str::SmolStr;
+use std::borrow::Cow;
 use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
 use std::str::FromStr;
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::time::Instant;
 use thiserror::Error;
 
+use crate::audit::{AuditDetail, AuditRecord, AuditSink};
+use crate::cache::DecisionCache;
+use crate::chain::{ChainError, ChainScope};
+use crate::environment::policies_for_environment;
+use crate::kill_switch::KillSwitch;
+use crate::nonce::NonceTracker;
+use crate::organization::OrgChart;
+use crate::rate_limit::{RateLimitError, RateLimiter};
+use crate::revocation::RevocationList;
+use crate::risk::{RiskScorer, MAX_RISK_SCORE, RISK_SCORE_ATTRIBUTE};
+use crate::rollout::Rollout;
+use crate::screening::{Screener, ScreeningError};
+use crate::shadow::{enforced_policies, has_shadow_policies, ShadowOutcome};
+
 /// Identifier for a Template slot
 #[repr(transparent)]
 #[derive(Debug, Clone, Eq, PartialEq, PartialOrd, Ord, Hash, RefCast)]
@@ -86,7 +113,7 @@ impl From<SlotId> for ast::SlotId {
 /// Entity datatype
 #[repr(transparent)]
 #[derive(Debug, Clone, PartialEq, Eq, RefCast)]
-pub struct Entity(ast::Entity);
+pub struct Entity(pub(crate) ast::Entity);
 
 impl Entity {
     /// Create a new `Entity` with this Uid, attributes, and parents.
@@ -127,6 +154,41 @@ impl Entity {
         ))
     }
 
+    /// Create a new `Entity` with this Uid, attributes, parents, and tags.
+    ///
+    /// Tag values are specified as "restricted expressions", the same as
+    /// attribute values.
+    /// ```
+    /// # use cedar_policy::{Entity, EntityUid, EvalResult, RestrictedExpression};
+    /// # use std::collections::{HashMap, HashSet};
+    /// # use std::str::FromStr;
+    /// let euid = EntityUid::from_str(r#"User::"alice""#).unwrap();
+    /// let tags = HashMap::from([
+    ///     ("tier".to_string(), RestrictedExpression::from_str("\"gold\"").unwrap()),
+    /// ]);
+    /// let entity = Entity::new_with_tags(euid, HashMap::new(), HashSet::new(), tags);
+    /// assert_eq!(entity.tag("tier").unwrap(), Ok(EvalResult::String("gold".to_string())));
+    /// assert!(entity.tag("team").is_none());
+    ///```
+    pub fn new_with_tags(
+        uid: EntityUid,
+        attrs: HashMap<String, RestrictedExpression>,
+        parents: HashSet<EntityUid>,
+        tags: HashMap<String, RestrictedExpression>,
+    ) -> Self {
+        Self(ast::Entity::new_with_tags(
+            uid.0,
+            attrs
+                .into_iter()
+                .map(|(k, v)| (SmolStr::from(k), v.0))
+                .collect(),
+            parents.into_iter().map(|uid| uid.0).collect(),
+            tags.into_iter()
+                .map(|(k, v)| (SmolStr::from(k), v.0))
+                .collect(),
+        ))
+    }
+
     /// Create a new `Entity` with this Uid, no attributes, and no parents.
     /// ```
     /// use cedar_policy::{Entity, EntityId, EntityTypeName, EntityUid};
@@ -184,6 +246,20 @@ impl Entity {
                 .map(EvalResult::from),
         )
     }
+
+    /// Get the value for the given tag, or `None` if not present.
+    ///
+    /// This can also return Some(Err) if the tag had an illegal value.
+    pub fn tag(&self, tag: &str) -> Option<Result<EvalResult, EvaluationError>> {
+        let expr = self.0.get_tag(tag)?;
+        let all_ext = Extensions::all_available();
+        let evaluator = RestrictedEvaluator::new(&all_ext);
+        Some(
+            evaluator
+                .interpret(expr.as_borrowed())
+                .map(EvalResult::from),
+        )
+    }
 }
 
 impl std::fmt::Display for Entity {
@@ -483,10 +559,29 @@ impl Entities {
     }
 }
 
+/// The entities and request a request is evaluated with, if they differ from
+/// its own
+type Admitted = (Option<Entities>, Option<Request>);
+
 /// Authorizer object, which provides responses to authorization queries
-#[repr(transparent)]
-#[derive(Debug, RefCast)]
-pub struct Authorizer(authorizer::Authorizer);
+#[derive(Debug)]
+pub struct Authorizer {
+    authorizer: authorizer::Authorizer,
+    audit_sink: Option<Arc<dyn AuditSink>>,
+    audit_detail: AuditDetail,
+    revocations: Option<Arc<RevocationList>>,
+    nonces: Option<Arc<NonceTracker>>,
+    chains: Option<ChainScope>,
+    environment: Option<String>,
+    rollout: Option<Rollout>,
+    shadow_policies: Option<PolicySet>,
+    cache: Option<Arc<DecisionCache>>,
+    kill_switch: Option<Arc<KillSwitch>>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    risk_scorer: Option<Arc<dyn RiskScorer>>,
+    screener: Option<Arc<Screener>>,
+    org_chart: Option<Arc<OrgChart>>,
+}
 
 impl Default for Authorizer {
     fn default() -> Self {
@@ -547,7 +642,453 @@ impl Authorizer {
     /// let r = authorizer.is_authorized(&request, &policy, &entities);
     /// ```
     pub fn new() -> Self {
-        Self(authorizer::Authorizer::new())
+        Self {
+            authorizer: authorizer::Authorizer::new(),
+            audit_sink: None,
+            audit_detail: AuditDetail::default(),
+            revocations: None,
+            nonces: None,
+            chains: None,
+            environment: None,
+            rollout: None,
+            shadow_policies: None,
+            cache: None,
+            kill_switch: None,
+            rate_limiter: None,
+            risk_scorer: None,
+            screener: None,
+            org_chart: None,
+        }
+    }
+
+    /// Record every decision made by `is_authorized()` to `sink`. Partial
+    /// evaluation is not recorded.
+    #[must_use]
+    pub fn with_audit_sink(mut self, sink: Arc<dyn AuditSink>) -> Self {
+        self.audit_sink = Some(sink);
+        self
+    }
+
+    /// Capture `detail` in each audit record, e.g. the request itself so
+    /// that the decision can be replayed. By default, records hold only
+    /// digests.
+    #[must_use]
+    pub fn with_audit_detail(mut self, detail: AuditDetail) -> Self {
+        self.audit_detail = detail;
+        self
+    }
+
+    /// Ignore the template-linked policies revoked in `revocations` when
+    /// answering requests. The list is consulted on every request, so
+    /// revocations take effect immediately.
+    #[must_use]
+    pub fn with_revocations(mut self, revocations: Arc<RevocationList>) -> Self {
+        self.revocations = Some(revocations);
+        self
+    }
+
+    /// Deny requests which `tracker` finds to be replays: those reusing a
+    /// nonce or request id. Only requests the policies allow are checked, so
+    /// denied requests don't use up their nonces.
+    #[must_use]
+    pub fn with_nonce_tracker(mut self, tracker: Arc<NonceTracker>) -> Self {
+        self.nonces = Some(tracker);
+        self
+    }
+
+    /// The tracker nonces and request ids are checked by, if there is one
+    pub(crate) fn nonce_tracker(&self) -> Option<&Arc<NonceTracker>> {
+        self.nonces.as_ref()
+    }
+
+    /// Consider only the policies which apply to each request's chain, as
+    /// determined by `scope`. Requests whose chain can't be determined are
+    /// denied.
+    #[must_use]
+    pub fn with_chain_scope(mut self, scope: ChainScope) -> Self {
+        self.chains = Some(scope);
+        self
+    }
+
+    /// Consider only the policies which apply in the deployment environment
+    /// `env`: those without an `@env` annotation, and those whose annotation
+    /// lists `env`. See [`policies_for_environment()`].
+    #[must_use]
+    pub fn with_environment(mut self, env: impl Into<String>) -> Self {
+        self.environment = Some(env.into());
+        self
+    }
+
+    /// Consider only the policies rolled out to each request's principal, as
+    /// determined by `rollout`. See [`Rollout::apply()`].
+    #[must_use]
+    pub fn with_rollout(mut self, rollout: Rollout) -> Self {
+        self.rollout = Some(rollout);
+        self
+    }
+
+    /// Also answer each request with `policies` instead of the policies it
+    /// is authorized against, and report that decision in
+    /// [`Diagnostics::shadow()`] and audit records without enforcing it
+    #[must_use]
+    pub fn with_shadow_policies(mut self, policies: PolicySet) -> Self {
+        self.shadow_policies = Some(policies);
+        self
+    }
+
+    /// Answer requests answered recently from `cache`. See [`crate::cache`]
+    /// for when cached decisions are reused. Partial evaluation isn't
+    /// cached.
+    #[must_use]
+    pub fn with_decision_cache(mut self, cache: Arc<DecisionCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// The cache requests are answered from, if there is one
+    pub(crate) fn decision_cache(&self) -> Option<&Arc<DecisionCache>> {
+        self.cache.as_ref()
+    }
+
+    /// Deny the requests matching the rules of `kill_switch` while it is
+    /// active, before consulting the policies or the decision cache. See
+    /// [`crate::kill_switch`].
+    #[must_use]
+    pub fn with_kill_switch(mut self, kill_switch: Arc<KillSwitch>) -> Self {
+        self.kill_switch = Some(kill_switch);
+        self
+    }
+
+    /// Limit how often requests are allowed with `limiter`. See
+    /// [`crate::rate_limit`].
+    #[must_use]
+    pub fn with_rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.rate_limiter = Some(limiter);
+        self
+    }
+
+    /// The rate limiter requests are counted by, if there is one
+    pub(crate) fn rate_limiter(&self) -> Option<&Arc<RateLimiter>> {
+        self.rate_limiter.as_ref()
+    }
+
+    /// Score each request with `scorer` before evaluating it, adding the
+    /// score to its context. See [`crate::risk`].
+    #[must_use]
+    pub fn with_risk_scorer(mut self, scorer: Arc<dyn RiskScorer>) -> Self {
+        self.risk_scorer = Some(scorer);
+        self
+    }
+
+    /// Screen the addresses of each request with `screener` before
+    /// evaluating it, adding the results to their entities. See
+    /// [`crate::screening`].
+    #[must_use]
+    pub fn with_screener(mut self, screener: Arc<Screener>) -> Self {
+        self.screener = Some(screener);
+        self
+    }
+
+    /// Add the ancestors of the members of `chart` to the entities of each
+    /// request, so that policies scoped to an organization or team apply to
+    /// its descendants. See [`crate::organization`].
+    #[must_use]
+    pub fn with_org_chart(mut self, chart: Arc<OrgChart>) -> Self {
+        self.org_chart = Some(chart);
+        self
+    }
+
+    /// The policies to answer requests with in shadow mode, instead of `p`:
+    /// the shadow policy set if there is one, else `p` itself if it has
+    /// policies in shadow mode
+    fn shadow_set<'a>(&'a self, p: &'a PolicySet) -> Option<Cow<'a, PolicySet>> {
+        if let Some(shadow) = &self.shadow_policies {
+            return Some(self.effective(shadow));
+        }
+        has_shadow_policies(p).then_some(Cow::Borrowed(p))
+    }
+
+    /// The policies in `p` which apply to the chain and principal of `r`
+    fn request_scoped<'a>(
+        &self,
+        r: &Request,
+        p: &'a PolicySet,
+    ) -> Result<Cow<'a, PolicySet>, ChainError> {
+        let scoped = match &self.chains {
+            Some(scope) => scope.apply(r, p)?,
+            None => Cow::Borrowed(p),
+        };
+        Ok(match (&self.rollout, scoped) {
+            (None, scoped) => scoped,
+            (Some(rollout), Cow::Borrowed(p)) => rollout.apply(r, p),
+            (Some(rollout), Cow::Owned(p)) => Cow::Owned(rollout.apply(r, &p).into_owned()),
+        })
+    }
+
+    /// Answer `r` with the policies in `p` which apply to its chain and
+    /// principal
+    fn evaluate_scoped(&self, r: &Request, p: &PolicySet, e: &Entities) -> Response {
+        match self.request_scoped(r, p) {
+            Ok(scoped) => self
+                .authorizer
+                .is_authorized(&r.0, &scoped.ast, &e.0)
+                .into(),
+            Err(err) => invalid_chain(&err),
+        }
+    }
+
+    /// Like [`Self::evaluate_scoped()`], but stopping when `interrupt` fires
+    async fn evaluate_scoped_async(
+        &self,
+        r: &Request,
+        p: &PolicySet,
+        e: &Entities,
+        interrupt: &Interrupt,
+    ) -> Result<Response, Interrupted> {
+        Ok(match self.request_scoped(r, p) {
+            Ok(scoped) => self
+                .authorizer
+                .is_authorized_async(&r.0, &scoped.ast, &e.0, interrupt)
+                .await?
+                .into(),
+            Err(err) => invalid_chain(&err),
+        })
+    }
+
+    /// Answer `r` with the enforced policies in `p`, reporting what the
+    /// shadow policies would have decided
+    fn evaluate(&self, r: &Request, p: &PolicySet, e: &Entities) -> Response {
+        let response = self.evaluate_scoped(r, &enforced_policies(p), e);
+        match self.shadow_set(p) {
+            Some(shadow) => response.with_shadow(self.evaluate_scoped(r, &shadow, e)),
+            None => response,
+        }
+    }
+
+    /// Like [`Self::evaluate()`], but stopping when `interrupt` fires
+    async fn evaluate_async(
+        &self,
+        r: &Request,
+        p: &PolicySet,
+        e: &Entities,
+        interrupt: &Interrupt,
+    ) -> Result<Response, Interrupted> {
+        let response = self
+            .evaluate_scoped_async(r, &enforced_policies(p), e, interrupt)
+            .await?;
+        Ok(match self.shadow_set(p) {
+            Some(shadow) => {
+                response.with_shadow(self.evaluate_scoped_async(r, &shadow, e, interrupt).await?)
+            }
+            None => response,
+        })
+    }
+
+    /// Like [`Self::evaluate()`], but answering from the decision cache if
+    /// possible
+    fn evaluate_cached(&self, r: &Request, p: &PolicySet, e: &Entities) -> Response {
+        let Some(cache) = &self.cache else {
+            return self.evaluate(r, p, e);
+        };
+        let key = match cache.get(r, p, e) {
+            Ok(response) => return response,
+            Err(key) => key,
+        };
+        let response = self.evaluate(r, p, e);
+        cache.insert(key, response.clone());
+        response
+    }
+
+    /// Like [`Self::evaluate_async()`], but answering from the decision cache
+    /// if possible
+    async fn evaluate_cached_async(
+        &self,
+        r: &Request,
+        p: &PolicySet,
+        e: &Entities,
+        interrupt: &Interrupt,
+    ) -> Result<Response, Interrupted> {
+        let Some(cache) = &self.cache else {
+            return self.evaluate_async(r, p, e, interrupt).await;
+        };
+        let key = match cache.get(r, p, e) {
+            Ok(response) => return Ok(response),
+            Err(key) => key,
+        };
+        let response = self.evaluate_async(r, p, e, interrupt).await?;
+        cache.insert(key, response.clone());
+        Ok(response)
+    }
+
+    /// The denial of `r`, if the kill switch is active and one of its rules
+    /// matches it or can't be evaluated for it
+    fn halted(&self, r: &Request, e: &Entities) -> Option<Response> {
+        let kill_switch = self.kill_switch.as_ref()?;
+        if !kill_switch.is_active() {
+            return None;
+        }
+        let rules = kill_switch.rules();
+        let response: Response = self.authorizer.is_authorized(&r.0, &rules.ast, &e.0).into();
+        let (_, reason, errors) = response.into_parts();
+        // a rule which errors might have matched, so it halts the request too
+        let mut causes: Vec<_> = reason
+            .iter()
+            .map(|id| format!("matched {id}"))
+            .chain(errors.iter().map(ToString::to_string))
+            .collect();
+        if causes.is_empty() {
+            return None;
+        }
+        causes.sort();
+        Some(Response::new(
+            Decision::Deny,
+            reason,
+            vec![AuthorizationError::Halted(causes.join(", "))],
+        ))
+    }
+
+    /// `e` with the hierarchy of the organization chart, if there is one, or
+    /// else the denial of the request if that makes the hierarchy invalid
+    fn organize(&self, e: &Entities) -> Result<Option<Entities>, Response> {
+        self.org_chart.as_ref().map_or(Ok(None), |chart| {
+            chart
+                .apply(e)
+                .map(Some)
+                .map_err(|err| invalid_hierarchy(&err))
+        })
+    }
+
+    /// `e` with the screening results of the addresses of `r`, if there is
+    /// a screener and `r` has addresses, or else the denial of `r` if
+    /// screening fails
+    fn screen(&self, r: &Request, e: &Entities) -> Result<Option<Entities>, Response> {
+        self.screener.as_ref().map_or(Ok(None), |screener| {
+            screener
+                .screened(r, e)
+                .map_err(|err| screening_failed(&err))
+        })
+    }
+
+    /// `r` with the rate limit of its principal and action and its risk
+    /// score added to its context, if the rate limiter counts them and there
+    /// is a risk scorer, or else the denial of `r` if the rate limiter fails
+    fn prepare(&self, r: &Request, e: &Entities) -> Result<Option<Request>, Response> {
+        let counted = self.rate_limiter.as_ref().map_or(Ok(None), |limiter| {
+            limiter.count(r).map_err(|err| rate_limited(&err))
+        })?;
+        let Some(scorer) = &self.risk_scorer else {
+            return Ok(counted);
+        };
+        let r = counted.as_ref().unwrap_or(r);
+        let score = scorer.score(r, e).min(MAX_RISK_SCORE);
+        Ok(Some(r.with_context_attribute(
+            RISK_SCORE_ATTRIBUTE,
+            RestrictedExpression::new_long(score.into()),
+        )))
+    }
+
+    /// The entities and request to evaluate `r` with: `e` with the
+    /// organization chart and the screening results of its addresses, and
+    /// `r` with its rate limit and risk score added to its context, if they
+    /// change them. Or else the denial of `r`, if the kill switch halts it or
+    /// any of these fail.
+    fn admit(&self, r: &Request, e: &Entities) -> Result<Admitted, Response> {
+        let organized = self.organize(e)?;
+        let e = organized.as_ref().unwrap_or(e);
+        if let Some(response) = self.halted(r, e) {
+            return Err(response);
+        }
+        let screened = self.screen(r, e)?;
+        let prepared = self.prepare(r, screened.as_ref().unwrap_or(e))?;
+        Ok((screened.or(organized), prepared))
+    }
+
+    /// Answer `r` with the kill switch, then the cache, then the policies in
+    /// `p`, after adding the organization chart to `e`, screening its
+    /// addresses, and adding its rate limit and risk score to its context,
+    /// denying requests over their rate limit and replays
+    fn respond(&self, r: &Request, p: &PolicySet, e: &Entities) -> Response {
+        let (admitted, prepared) = match self.admit(r, e) {
+            Ok(admitted) => admitted,
+            Err(response) => return response,
+        };
+        let e = admitted.as_ref().unwrap_or(e);
+        let response = self.evaluate_cached(prepared.as_ref().unwrap_or(r), p, e);
+        self.guard(r, p, response)
+    }
+
+    /// Like [`Self::respond()`], but stopping when `interrupt` fires
+    async fn respond_async(
+        &self,
+        r: &Request,
+        p: &PolicySet,
+        e: &Entities,
+        interrupt: &Interrupt,
+    ) -> Result<Response, Interrupted> {
+        let (admitted, prepared) = match self.admit(r, e) {
+            Ok(admitted) => admitted,
+            Err(response) => return Ok(response),
+        };
+        let e = admitted.as_ref().unwrap_or(e);
+        let response = self
+            .evaluate_cached_async(prepared.as_ref().unwrap_or(r), p, e, interrupt)
+            .await?;
+        Ok(self.guard(r, p, response))
+    }
+
+    /// `response` to `r`, unless it allows `r` more often than its rate limit
+    /// or `r` is a replay
+    fn guard(&self, r: &Request, p: &PolicySet, response: Response) -> Response {
+        let response = self.guard_rate_limit(r, p, response);
+        self.guard_replay(r, response)
+    }
+
+    /// `response` to `r`, unless it allows `r` more often than the
+    /// `@rateLimit` policies in `p` which determined it do
+    fn guard_rate_limit(&self, r: &Request, p: &PolicySet, response: Response) -> Response {
+        match &self.rate_limiter {
+            Some(limiter) => match limiter.check(r, p, &response) {
+                Ok(()) => response,
+                Err(err) => rate_limited(&err),
+            },
+            None => response,
+        }
+    }
+
+    /// `response` to `r`, unless it allows a replayed request
+    fn guard_replay(&self, r: &Request, response: Response) -> Response {
+        match &self.nonces {
+            Some(tracker) if response.decision() == Decision::Allow => match tracker.check(r) {
+                Ok(()) => response,
+                Err(e) => Response::new(
+                    Decision::Deny,
+                    HashSet::new(),
+                    vec![AuthorizationError::Replayed(e.to_string())],
+                ),
+            },
+            _ => response,
+        }
+    }
+
+    /// Answer `r` like `is_authorized()`, but without recording it to the
+    /// audit sink
+    pub(crate) fn decide(&self, r: &Request, p: &PolicySet, e: &Entities) -> Response {
+        self.evaluate(r, &self.effective(p), e)
+    }
+
+    /// `p` without any revoked links or policies of other environments
+    pub(crate) fn effective<'a>(&self, p: &'a PolicySet) -> Cow<'a, PolicySet> {
+        let unrevoked = self
+            .revocations
+            .as_ref()
+            .map_or(Cow::Borrowed(p), |revocations| revocations.apply(p));
+        match (&self.environment, unrevoked) {
+            (None, unrevoked) => unrevoked,
+            (Some(env), Cow::Borrowed(p)) => policies_for_environment(p, env),
+            (Some(env), Cow::Owned(p)) => {
+                Cow::Owned(policies_for_environment(&p, env).into_owned())
+            }
+        }
     }
 
     /// Returns an authorization response for `r` with respect to the given
@@ -606,13 +1147,72 @@ impl Authorizer {
     /// println!("{:?}", r);
     /// ```
     pub fn is_authorized(&self, r: &Request, p: &PolicySet, e: &Entities) -> Response {
-        self.0.is_authorized(&r.0, &p.ast, &e.0).into()
+        let effective = self.effective(p);
+        let p = effective.as_ref();
+        let Some(sink) = &self.audit_sink else {
+            return self.respond(r, p, e);
+        };
+        let start = Instant::now();
+        let response = self.respond(r, p, e);
+        sink.record(
+            &AuditRecord::new(r, p, &response, start.elapsed()).with_detail(
+                self.audit_detail,
+                r,
+                e,
+            ),
+        );
+        response
+    }
+
+    /// Like [`Authorizer::is_authorized()`], but stops early with an error
+    /// when `interrupt` fires: at its deadline, or when its
+    /// [`CancellationToken`] is cancelled. Evaluation yields to the async
+    /// executor periodically, so a large policy set doesn't hold up other
+    /// tasks on the same thread.
+    /// ```
+    /// # use cedar_policy::{Authorizer, Context, Entities, Interrupt, PolicySet, Request};
+    /// # use std::str::FromStr;
+    /// # use std::time::Duration;
+    /// # async fn example() {
+    /// let policies = PolicySet::from_str("permit(principal, action, resource);").unwrap();
+    /// let request = Request::new(None, None, None, Context::empty());
+    /// let interrupt = Interrupt::new().with_timeout(Duration::from_millis(50));
+    /// let response = Authorizer::new()
+    ///     .is_authorized_async(&request, &policies, &Entities::empty(), &interrupt)
+    ///     .await;
+    /// # }
+    /// ```
+    pub async fn is_authorized_async(
+        &self,
+        r: &Request,
+        p: &PolicySet,
+        e: &Entities,
+        interrupt: &Interrupt,
+    ) -> Result<Response, Interrupted> {
+        let start = Instant::now();
+        let effective = self.effective(p);
+        let p = effective.as_ref();
+        let response = self.respond_async(r, p, e, interrupt).await?;
+        if let Some(sink) = &self.audit_sink {
+            sink.record(
+                &AuditRecord::new(r, p, &response, start.elapsed()).with_detail(
+                    self.audit_detail,
+                    r,
+                    e,
+                ),
+            );
+        }
+        Ok(response)
     }
 
     /// A partially evaluated authorization request.
     /// The Authorizer will attempt to make as much progress as possible in the presence of unknowns.
     /// If the Authorizer can reach a response, it will return that response.
     /// Otherwise, it will return a list of residual policies that still need to be evaluated.
+    ///
+    /// Requests go through the kill switch, organization chart, screener,
+    /// and rate limiter as they do for [`Authorizer::is_authorized()`], and
+    /// concrete responses are checked for replays, but nothing is cached.
     #[cfg(feature = "partial-eval")]
     pub fn is_authorized_partial(
         &self,
@@ -620,16 +1220,67 @@ impl Authorizer {
         policy_set: &PolicySet,
         entities: &Entities,
     ) -> PartialResponse {
+        let effective = self.effective(policy_set);
+        let (admitted, prepared) = match self.admit(query, entities) {
+            Ok(admitted) => admitted,
+            Err(response) => return PartialResponse::Concrete(response),
+        };
+        let entities = admitted.as_ref().unwrap_or(entities);
+        let prepared = prepared.as_ref().unwrap_or(query);
+        let enforced = enforced_policies(&effective);
+        let scoped = match self.request_scoped(prepared, &enforced) {
+            Ok(scoped) => scoped,
+            Err(err) => return PartialResponse::Concrete(invalid_chain(&err)),
+        };
         let response = self
-            .0
-            .is_authorized_core(&query.0, &policy_set.ast, &entities.0);
+            .authorizer
+            .is_authorized_core(&prepared.0, &scoped.ast, &entities.0);
         match response {
-            authorizer::ResponseKind::FullyEvaluated(a) => PartialResponse::Concrete(a.into()),
+            authorizer::ResponseKind::FullyEvaluated(a) => {
+                PartialResponse::Concrete(self.guard(query, &effective, a.into()))
+            }
             authorizer::ResponseKind::Partial(p) => PartialResponse::Residual(p.into()),
         }
     }
 }
 
+/// The response to a request whose chain couldn't be determined
+fn invalid_chain(err: &ChainError) -> Response {
+    Response::new(
+        Decision::Deny,
+        HashSet::new(),
+        vec![AuthorizationError::InvalidChain(err.to_string())],
+    )
+}
+
+/// The response to a request refused by the rate limiter
+fn rate_limited(err: &RateLimitError) -> Response {
+    Response::new(
+        Decision::Deny,
+        HashSet::new(),
+        vec![AuthorizationError::RateLimited(err.to_string())],
+    )
+}
+
+/// The response to a request whose entities' hierarchy the organization
+/// chart made invalid
+fn invalid_hierarchy(err: &EntitiesError) -> Response {
+    Response::new(
+        Decision::Deny,
+        HashSet::new(),
+        vec![AuthorizationError::InvalidHierarchy(err.to_string())],
+    )
+}
+
+/// The response to a request whose addresses couldn't be screened
+fn screening_failed(err: &ScreeningError) -> Response {
+    Response::new(
+        Decision::Deny,
+        HashSet::new(),
+        vec![AuthorizationError::ScreeningFailed(err.to_string())],
+    )
+}
+
 /// Authorization response returned from the `Authorizer`
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Response {
@@ -669,6 +1320,8 @@ pub struct Diagnostics {
     /// Errors that occurred during authorization. The errors should be
     /// treated as unordered, since policies may be evaluated in any order.
     errors: Vec<AuthorizationError>,
+    /// What the shadow policies would have decided, if any were evaluated
+    shadow: Option<Box<ShadowOutcome>>,
 }
 
 impl From<authorizer::Diagnostics> for Diagnostics {
@@ -676,6 +1329,7 @@ impl From<authorizer::Diagnostics> for Diagnostics {
         Self {
             reason: diagnostics.reason.into_iter().map(PolicyId).collect(),
             errors: diagnostics.errors,
+            shadow: None,
         }
     }
 }
@@ -797,6 +1451,12 @@ impl Diagnostics {
     pub fn errors(&self) -> impl Iterator<Item = &AuthorizationError> + '_ {
         self.errors.iter()
     }
+
+    /// What the decision would have been with the shadow policies enforced,
+    /// if there were any. See [`crate::shadow`].
+    pub fn shadow(&self) -> Option<&ShadowOutcome> {
+        self.shadow.as_deref()
+    }
 }
 
 impl Response {
@@ -808,10 +1468,30 @@ impl Response {
     ) -> Self {
         Self {
             decision,
-            diagnostics: Diagnostics { reason, errors },
+            diagnostics: Diagnostics {
+                reason,
+                errors,
+                shadow: None,
+            },
         }
     }
 
+    /// Report `shadow` as the response with the shadow policies enforced
+    #[must_use]
+    pub(crate) fn with_shadow(mut self, shadow: Self) -> Self {
+        self.diagnostics.shadow = Some(Box::new(shadow.into()));
+        self
+    }
+
+    /// The decision, reason, and errors
+    pub(crate) fn into_parts(self) -> (Decision, HashSet<PolicyId>, Vec<AuthorizationError>) {
+        (
+            self.decision,
+            self.diagnostics.reason,
+            self.diagnostics.errors,
+        )
+    }
+
     /// Get the authorization decision
     pub fn decision(&self) -> Decision {
         self.decision
@@ -842,7 +1522,11 @@ impl ResidualResponse {
     ) -> Self {
         Self {
             residuals,
-            diagnostics: Diagnostics { reason, errors },
+            diagnostics: Diagnostics {
+                reason,
+                errors,
+                shadow: None,
+            },
         }
     }
 
@@ -855,6 +1539,47 @@ impl ResidualResponse {
     pub fn diagnostics(&self) -> &Diagnostics {
         &self.diagnostics
     }
+
+    /// Get the names of the unknowns that the residual policies depend on, in
+    /// sorted order. Supplying values for all of these is sufficient to reach
+    /// a decision.
+    pub fn missing(&self) -> impl Iterator<Item = String> {
+        self.residuals.ast.unknowns().into_iter().map(String::from)
+    }
+}
+
+#[cfg(feature = "partial-eval")]
+impl PartialResponse {
+    /// Get the three-valued decision for this response: `Allow` or `Deny` if
+    /// a decision was reached regardless of the unknowns, or `Indeterminate`
+    /// listing the unknowns that must be supplied to reach one.
+    pub fn decision(&self) -> PartialDecision {
+        match self {
+            Self::Concrete(response) => match response.decision() {
+                Decision::Allow => PartialDecision::Allow,
+                Decision::Deny => PartialDecision::Deny,
+            },
+            Self::Residual(residual) => PartialDecision::Indeterminate {
+                missing: residual.missing().collect(),
+            },
+        }
+    }
+}
+
+/// Three-valued authorization decision, for requests which may contain
+/// unknowns. See [`PartialResponse::decision`].
+#[cfg(feature = "partial-eval")]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum PartialDecision {
+    /// The request is allowed, whatever values the unknowns take
+    Allow,
+    /// The request is denied, whatever values the unknowns take
+    Deny,
+    /// No decision can be reached without values for these unknowns
+    Indeterminate {
+        /// Names of the unknowns which need values, in sorted order
+        missing: Vec<String>,
+    },
 }
 
 #[cfg(feature = "partial-eval")]
@@ -900,6 +1625,25 @@ impl Validator {
         Self(cedar_policy_validator::Validator::new(schema.0))
     }
 
+    /// Also report a validation error for each template or static policy
+    /// without one of the annotations `keys`, e.g. `["owner", "review"]`.
+    /// Template-linked policies have the annotations of their template.
+    #[must_use]
+    pub fn with_required_annotations(
+        self,
+        keys: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        Self(self.0.with_required_annotations(keys))
+    }
+
+    /// Also report a validation error for each chain in a `@chain`
+    /// annotation which isn't one of `chains`. Malformed `@chain`
+    /// annotations are always reported.
+    #[must_use]
+    pub fn with_known_chains(self, chains: impl IntoIterator<Item = u64>) -> Self {
+        Self(self.0.with_known_chains(chains))
+    }
+
     /// Validate all policies in a policy set, collecting all validation errors
     /// found into the returned `ValidationResult`. Each error is returned together with the
     /// policy id of the policy where the error was found. If a policy id
@@ -1026,11 +1770,40 @@ impl Schema {
         )?))
     }
 
+    /// Set whether request contexts may have attributes their action's
+    /// context type doesn't declare. With [`ContextMode::Open`], contexts are
+    /// typed as open records when validating, and undeclared attributes are
+    /// parsed without the schema when building a [`Context`], so adding
+    /// fields to an intent decoder doesn't break existing policies.
+    #[must_use]
+    pub fn with_context_mode(self, context_mode: ContextMode) -> Self {
+        Self(self.0.with_context_mode(context_mode))
+    }
+
     /// Extract from the schema an `Entities` containing the action entities
     /// declared in the schema.
     pub fn action_entities(&self) -> Result<Entities, entities::EntitiesError> {
         Ok(Entities(self.0.action_entities()?))
     }
+
+    /// Normalize context JSON for a request for the given action, by applying
+    /// the defaults and coercions declared in the schema for that action's
+    /// context. The result can then be parsed with [`Context::from_json_value`].
+    ///
+    /// Values which can't be coerced to their declared type are left as they
+    /// are, so that parsing reports the type error.
+    pub fn normalize_context(
+        &self,
+        action: &EntityUid,
+        context: serde_json::Value,
+    ) -> Result<serde_json::Value, ContextJsonError> {
+        match self.0.get_action_id(&action.0) {
+            Some(action_id) => Ok(action_id.normalize_context(context)),
+            None => Err(ContextJsonError::MissingAction {
+                action: action.clone(),
+            }),
+        }
+    }
 }
 
 /// Errors encountered during construction of a Validation Schema
@@ -1107,6 +1880,23 @@ pub enum SchemaError {
     /// This error variant should only be used when `PermitAttributes` is enabled.
     #[error("action `{0}` has an attribute with unsupported JSON representation: {1}")]
     UnsupportedActionAttribute(EntityUid, String),
+    /// The `contextNormalization` of an action names an attribute which is
+    /// not declared in the context of that action.
+    #[error("context normalization for action `{0}` refers to undeclared context attribute `{1}`")]
+    UndeclaredNormalizedContextAttr(EntityUid, String),
+    /// The `requesterControlled` list of an action names an attribute which
+    /// is not declared in the context of that action.
+    #[error("requester controlled context attribute `{1}` of action `{0}` is not declared")]
+    UndeclaredRequesterControlledContextAttr(EntityUid, String),
+    /// The default value declared for a context attribute is not a valid
+    /// value of the attribute's declared type.
+    #[error("invalid default for context attribute `{1}` of action `{0}`: {2}")]
+    InvalidContextDefault(EntityUid, String, String),
+    /// A `derivedParents` entry of an entity type is malformed, or compares
+    /// an attribute which is undeclared or has a different type than the
+    /// value it is compared against.
+    #[error("invalid derived parent for entity type `{0}`: {1}")]
+    InvalidDerivedParent(EntityTypeName, String),
 }
 
 /// Describes in what action context or entity type shape a schema parsing error
@@ -1194,6 +1984,19 @@ impl From<cedar_policy_validator::SchemaError> for SchemaError {
             cedar_policy_validator::SchemaError::UnsupportedActionAttribute(uid, escape_type) => {
                 Self::UnsupportedActionAttribute(EntityUid(uid), escape_type)
             }
+            cedar_policy_validator::SchemaError::UndeclaredNormalizedContextAttr(uid, attr) => {
+                Self::UndeclaredNormalizedContextAttr(EntityUid(uid), attr)
+            }
+            cedar_policy_validator::SchemaError::UndeclaredRequesterControlledContextAttr(
+                uid,
+                attr,
+            ) => Self::UndeclaredRequesterControlledContextAttr(EntityUid(uid), attr),
+            cedar_policy_validator::SchemaError::InvalidContextDefault(uid, attr, reason) => {
+                Self::InvalidContextDefault(EntityUid(uid), attr, reason)
+            }
+            cedar_policy_validator::SchemaError::InvalidDerivedParent(name, reason) => {
+                Self::InvalidDerivedParent(EntityTypeName(name), reason)
+            }
         }
     }
 }
@@ -1387,7 +2190,7 @@ impl std::fmt::Display for EntityId {
 /// Represents a concatenation of Namespaces and `TypeName`
 #[repr(transparent)]
 #[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord, RefCast)]
-pub struct EntityTypeName(ast::Name);
+pub struct EntityTypeName(pub(crate) ast::Name);
 
 impl EntityTypeName {
     /// Get the basename of the `EntityTypeName` (ie, with namespaces stripped).
@@ -1467,7 +2270,7 @@ impl std::fmt::Display for EntityNamespace {
 /// Unique Id for an entity, such as `User::"alice"`
 #[repr(transparent)]
 #[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord, RefCast)]
-pub struct EntityUid(ast::EntityUID);
+pub struct EntityUid(pub(crate) ast::EntityUID);
 
 impl EntityUid {
     /// Returns the portion of the Euid that represents namespace and entity type
@@ -1528,8 +2331,12 @@ impl EntityUid {
         ))
     }
 
-    /// Testing utility for creating `EntityUids` a bit easier
-    #[cfg(test)]
+    /// Utility for creating `EntityUids` a bit easier. `typename` must be a
+    /// valid entity type name; callers only pass literal type names. Used by
+    /// tests and the `domain` modules behind the `u256` feature.
+    // PANIC SAFETY: `typename` is a valid type name, and `EntityId::from_str` never fails
+    #[allow(clippy::unwrap_used)]
+    #[cfg(any(test, all(feature = "domain", feature = "u256")))]
     pub(crate) fn from_strs(typename: &str, id: &str) -> Self {
         Self::from_type_name_and_id(
             EntityTypeName::from_str(typename).unwrap(),
@@ -1612,15 +2419,22 @@ impl From<ast::UnexpectedSlotError> for PolicySetError {
 }
 
 /// Represents a set of `Policy`s
+///
+/// Cloning a `PolicySet` is cheap: clones share their policies, and a clone
+/// copies the maps of policies only when it is modified. To share a policy
+/// set between threads while it is being updated, see [`SharedPolicySet`].
 #[derive(Debug, Clone, Default)]
 pub struct PolicySet {
     /// AST representation. Technically partially redundant with the other fields.
     /// Internally, we ensure that the duplicated information remains consistent.
     pub(crate) ast: ast::PolicySet,
     /// Policies in the set (this includes both static policies and template linked-policies)
-    policies: HashMap<PolicyId, Policy>,
+    policies: Arc<HashMap<PolicyId, Policy>>,
     /// Templates in the set
-    templates: HashMap<PolicyId, Template>,
+    templates: Arc<HashMap<PolicyId, Template>>,
+    /// The [`fingerprint()`](Self::fingerprint) of the set, once computed.
+    /// Cleared whenever the set is modified.
+    fingerprint: OnceLock<[u8; 32]>,
 }
 
 impl PartialEq for PolicySet {
@@ -1639,43 +2453,131 @@ impl FromStr for PolicySet {
     /// Policy ids will default to "policy*" with numbers from 0.
     /// If you load more policies, do not use the default id, or there will be conflicts.
     ///
+    /// The statements may be preceded by constant declarations, like
+    /// `const MAX_TRANSFER = u256("5000000000000000000");`, which they can then
+    /// refer to by name. A constant's value must be a literal, possibly built
+    /// with extension functions, and is checked when the policies are parsed.
+    /// Macros, like `macro isAdmin(p) = p in Group::"admins";`, name conditions
+    /// that the statements can then use like functions. The text of a policy
+    /// that uses a constant or macro has its value in place of the name.
+    ///
     /// See [`Policy`] for more.
     fn from_str(policies: &str) -> Result<Self, Self::Err> {
         let (texts, pset) = parser::parse_policyset_and_also_return_policy_text(policies)?;
-        // PANIC SAFETY: By the invariant on `parse_policyset_and_also_return_policy_text(policies)`, every `PolicyId` in `pset.policies()` occurs as a key in `text`.
-        #[allow(clippy::expect_used)]
-        let policies = pset.policies().map(|p|
-            (
-                PolicyId(p.id().clone()),
-                Policy { lossless: LosslessPolicy::policy_or_template_text(*texts.get(p.id()).expect("internal invariant violation: policy id exists in asts but not texts")), ast: p.clone() }
-            )
-        ).collect();
-        // PANIC SAFETY: By the same invariant, every `PolicyId` in `pset.templates()` also occurs as a key in `text`.
-        #[allow(clippy::expect_used)]
-        let templates = pset.templates().map(|t|
-            (
-                PolicyId(t.id().clone()),
-                Template { lossless: LosslessPolicy::policy_or_template_text(*texts.get(t.id()).expect("internal invariant violation: template id exists in asts but not ests")), ast: t.clone() }
-            )
-        ).collect();
-        Ok(Self {
-            ast: pset,
-            policies,
-            templates,
-        })
+        Ok(Self::from_texts(&texts, pset))
     }
 }
 
-impl PolicySet {
-    /// Create a fresh empty `PolicySet`
-    pub fn new() -> Self {
-        Self {
-            ast: ast::PolicySet::new(),
-            policies: HashMap::new(),
-            templates: HashMap::new(),
-        }
-    }
-
+/// Files imported by policy files, read from a directory
+///
+/// An import's path is relative to the directory, and can't leave it.
+#[derive(Debug, Clone)]
+pub struct ImportDir(PathBuf);
+
+impl ImportDir {
+    /// Imports from the directory `root`
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self(root.into())
+    }
+}
+
+impl ImportSource for ImportDir {
+    fn load(&self, path: &str) -> Result<String, String> {
+        let path = Path::new(path);
+        if !path
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            return Err("the path must be relative, and stay within the import directory".into());
+        }
+        std::fs::read_to_string(self.0.join(path)).map_err(|e| e.to_string())
+    }
+}
+
+/// Constants and macros that every policy set parsed with the prelude can
+/// use, as if it declared them itself.
+///
+/// Macros, like `macro withinRange(amount, floor, cap) = ...;`, are inlined
+/// where they're used, so validation and evaluation only see the expanded
+/// policies.
+#[derive(Debug, Clone, Default)]
+pub struct Prelude(parser::Prelude);
+
+impl Prelude {
+    /// Parse the imports and `const` and `macro` declarations in `text`,
+    /// loading the files it imports from `imports`
+    pub fn parse(text: &str, imports: &dyn ImportSource) -> Result<Self, ParseErrors> {
+        Ok(Self(parser::Prelude::parse(text, imports)?))
+    }
+}
+
+impl PolicySet {
+    /// Create a policy set from multiple statements, like
+    /// [`PolicySet::from_str()`], loading the files they import from `imports`.
+    ///
+    /// A policy file may start with `import "path";` statements. Each imported
+    /// file can declare constants and macros, like `macro isStablecoin(token) =
+    /// token in Group::"stablecoins";`, for the policies to use, but can't
+    /// contain policies itself.
+    pub fn from_str_with_imports(
+        policies: &str,
+        imports: &dyn ImportSource,
+    ) -> Result<Self, ParseErrors> {
+        Self::from_str_with_prelude(policies, &Prelude::default(), imports)
+    }
+
+    /// Create a policy set from multiple statements, like
+    /// [`PolicySet::from_str_with_imports()`], whose policies can also use the
+    /// declarations in `prelude`.
+    pub fn from_str_with_prelude(
+        policies: &str,
+        prelude: &Prelude,
+        imports: &dyn ImportSource,
+    ) -> Result<Self, ParseErrors> {
+        let (texts, pset) = parser::parse_policyset_and_also_return_policy_text_with_prelude(
+            policies, &prelude.0, imports,
+        )?;
+        Ok(Self::from_texts(&texts, pset))
+    }
+
+    /// Policy set with the policies in `pset`, which have the text in `texts`
+    fn from_texts(texts: &HashMap<ast::PolicyID, Cow<'_, str>>, pset: ast::PolicySet) -> Self {
+        // PANIC SAFETY: By the invariant on `parse_policyset_and_also_return_policy_text(policies)`, every `PolicyId` in `pset.policies()` occurs as a key in `text`.
+        #[allow(clippy::expect_used)]
+        let policies = pset.policies().map(|p|
+            (
+                PolicyId(p.id().clone()),
+                Policy { lossless: LosslessPolicy::policy_or_template_text(texts.get(p.id()).expect("internal invariant violation: policy id exists in asts but not texts").clone()), ast: p.clone() }
+            )
+        ).collect::<HashMap<_, _>>();
+        // PANIC SAFETY: By the same invariant, every `PolicyId` in `pset.templates()` also occurs as a key in `text`.
+        #[allow(clippy::expect_used)]
+        let templates = pset.templates().map(|t|
+            (
+                PolicyId(t.id().clone()),
+                Template { lossless: LosslessPolicy::policy_or_template_text(texts.get(t.id()).expect("internal invariant violation: template id exists in asts but not ests").clone()), ast: t.clone() }
+            )
+        ).collect::<HashMap<_, _>>();
+        Self {
+            ast: pset,
+            policies: Arc::new(policies),
+            templates: Arc::new(templates),
+            fingerprint: OnceLock::new(),
+        }
+    }
+}
+
+impl PolicySet {
+    /// Create a fresh empty `PolicySet`
+    pub fn new() -> Self {
+        Self {
+            ast: ast::PolicySet::new(),
+            policies: Arc::default(),
+            templates: Arc::default(),
+            fingerprint: OnceLock::new(),
+        }
+    }
+
     /// Create a `PolicySet` from the given policies
     pub fn from_policies(
         policies: impl IntoIterator<Item = Policy>,
@@ -1692,9 +2594,10 @@ impl PolicySet {
     /// the `PolicySet`) if a template-linked policy is passed in.
     pub fn add(&mut self, policy: Policy) -> Result<(), PolicySetError> {
         if policy.is_static() {
+            self.fingerprint.take();
             let id = PolicyId(policy.ast.id().clone());
             self.ast.add(policy.ast.clone())?;
-            self.policies.insert(id, policy);
+            Arc::make_mut(&mut self.policies).insert(id, policy);
             Ok(())
         } else {
             Err(PolicySetError::ExpectedStatic)
@@ -1703,9 +2606,10 @@ impl PolicySet {
 
     /// Add a `Template` to the `PolicySet`
     pub fn add_template(&mut self, template: Template) -> Result<(), PolicySetError> {
+        self.fingerprint.take();
         let id = PolicyId(template.ast.id().clone());
         self.ast.add_template(template.ast.clone())?;
-        self.templates.insert(id, template);
+        Arc::make_mut(&mut self.templates).insert(id, template);
         Ok(())
     }
 
@@ -1739,6 +2643,27 @@ impl PolicySet {
             .map(smol_str::SmolStr::as_str)
     }
 
+    /// Iterate over the annotations of every `Policy` in the `PolicySet`, as
+    /// (policy id, key, value) triples. Template-linked policies have the
+    /// annotations of their template.
+    pub fn annotations(&self) -> impl Iterator<Item = (&PolicyId, &str, &str)> {
+        self.policies().flat_map(|policy| {
+            policy
+                .annotations()
+                .map(move |(key, value)| (policy.id(), key, value))
+        })
+    }
+
+    /// Iterate over the `Policy`s in the `PolicySet` with the annotation
+    /// `key`, together with its value
+    pub fn annotated<'a>(
+        &'a self,
+        key: &'a str,
+    ) -> impl Iterator<Item = (&'a Policy, &'a str)> + 'a {
+        self.policies()
+            .filter_map(move |policy| Some((policy, policy.annotation(key)?)))
+    }
+
     /// Extract annotation data from a `Template` by its `PolicyId` and annotation key.
     pub fn template_annotation(&self, id: &PolicyId, key: impl AsRef<str>) -> Option<String> {
         self.ast
@@ -1771,6 +2696,7 @@ impl PolicySet {
         new_id: PolicyId,
         vals: HashMap<SlotId, EntityUid>,
     ) -> Result<(), PolicySetError> {
+        self.fingerprint.take();
         let unwrapped_vals: HashMap<ast::SlotId, ast::EntityUID> = vals
             .into_iter()
             .map(|(key, value)| (key.into(), value.0))
@@ -1802,7 +2728,7 @@ impl PolicySet {
             // will have already errored if there are any unfilled slots in the
             // template.
             .expect("ast.link() didn't fail above, so this shouldn't fail");
-        self.policies.insert(
+        Arc::make_mut(&mut self.policies).insert(
             new_id,
             Policy {
                 ast: linked_ast.clone(),
@@ -1812,27 +2738,154 @@ impl PolicySet {
         Ok(())
     }
 
+    /// Remove the template-linked policy with id `policy_id`, returning it.
+    /// Returns `None`, leaving the set unchanged, if there is no such policy
+    /// or it is a static policy.
+    pub fn unlink(&mut self, policy_id: &PolicyId) -> Option<Policy> {
+        self.fingerprint.take();
+        self.ast.unlink(&policy_id.0)?;
+        Arc::make_mut(&mut self.policies).remove(policy_id)
+    }
+
+    /// Remove the static policy with id `policy_id`, returning it. Returns
+    /// `None`, leaving the set unchanged, if there is no such policy or it is
+    /// a template-linked policy.
+    pub fn remove_static(&mut self, policy_id: &PolicyId) -> Option<Policy> {
+        self.fingerprint.take();
+        self.ast.remove_static(&policy_id.0)?;
+        Arc::make_mut(&mut self.policies).remove(policy_id)
+    }
+
+    /// The policies of this set for which `keep` returns `true`, with all of
+    /// its templates. Borrows this set if `keep` returns `true` for every
+    /// policy.
+    pub fn retained(&self, mut keep: impl FnMut(&Policy) -> bool) -> Cow<'_, Self> {
+        let mut kept = Cow::Borrowed(self);
+        for policy in self.policies() {
+            if !keep(policy) {
+                if policy.is_static() {
+                    kept.to_mut().remove_static(policy.id());
+                } else {
+                    kept.to_mut().unlink(policy.id());
+                }
+            }
+        }
+        kept
+    }
+
+    /// A digest of the policies and templates in this set, which differs
+    /// for sets which may decide a request differently. It is computed once,
+    /// and again only after the set is modified.
+    pub(crate) fn fingerprint(&self) -> [u8; 32] {
+        *self.fingerprint.get_or_init(|| {
+            let mut texts: Vec<_> = self
+                .ast
+                .policies()
+                .map(|p| ("policy", p.id().to_string(), p.to_string()))
+                .chain(
+                    self.ast
+                        .templates()
+                        .map(|t| ("template", t.id().to_string(), t.to_string())),
+                )
+                .collect();
+            texts.sort();
+            let mut hasher = Keccak256::new();
+            for (kind, id, text) in texts {
+                // prefixed by their lengths, so that no two sets are hashed
+                // from the same bytes
+                for part in [kind, &id, &text] {
+                    hasher.update((part.len() as u64).to_be_bytes());
+                    hasher.update(part);
+                }
+            }
+            hasher.finalize().into()
+        })
+    }
+
     /// Create a `PolicySet` from its AST representation only. The EST will
     /// reflect the AST structure. When possible, don't use this method and
     /// create the ESTs from the policy text or CST instead, as the conversion
     /// to AST is lossy. ESTs generated by this method will reflect the AST and
     /// not the original policy syntax.
-    #[cfg_attr(not(feature = "partial-eval"), allow(unused))]
-    fn from_ast(ast: ast::PolicySet) -> Self {
+    pub(crate) fn from_ast(ast: ast::PolicySet) -> Self {
         let policies = ast
             .policies()
             .map(|p| (PolicyId(p.id().clone()), Policy::from_ast(p.clone())))
-            .collect();
+            .collect::<HashMap<_, _>>();
         let templates = ast
             .templates()
             .map(|t| (PolicyId(t.id().clone()), Template::from_ast(t.clone())))
-            .collect();
+            .collect::<HashMap<_, _>>();
         Self {
             ast,
-            policies,
-            templates,
+            policies: Arc::new(policies),
+            templates: Arc::new(templates),
+            fingerprint: OnceLock::new(),
+        }
+    }
+}
+
+/// A [`PolicySet`] shared between threads which evaluate requests and
+/// threads which update it.
+///
+/// Readers take a [`snapshot()`](SharedPolicySet::snapshot) without locking
+/// and keep evaluating against it, unaffected by later updates. Updates are
+/// applied to a copy-on-write clone of the current set and published
+/// atomically, so an update never copies policy ASTs and readers never wait
+/// for it.
+#[derive(Debug)]
+pub struct SharedPolicySet {
+    current: ArcSwap<PolicySet>,
+    /// Serializes updates, so that concurrent updates don't overwrite each
+    /// other. Readers never take it.
+    update_lock: Mutex<()>,
+}
+
+impl Default for SharedPolicySet {
+    fn default() -> Self {
+        Self::new(PolicySet::new())
+    }
+}
+
+impl SharedPolicySet {
+    /// Share `policies`
+    pub fn new(policies: PolicySet) -> Self {
+        Self {
+            current: ArcSwap::from_pointee(policies),
+            update_lock: Mutex::new(()),
         }
     }
+
+    /// The current policy set
+    pub fn snapshot(&self) -> Arc<PolicySet> {
+        self.current.load_full()
+    }
+
+    /// Apply `update` to a clone of the current policy set, and publish the
+    /// result if `update` succeeds. If it fails, the current set is
+    /// unchanged.
+    pub fn update<T, E>(
+        &self,
+        update: impl FnOnce(&mut PolicySet) -> Result<T, E>,
+    ) -> Result<T, E> {
+        let _guard = self
+            .update_lock
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let mut policies = PolicySet::clone(&self.current.load());
+        let result = update(&mut policies)?;
+        self.current.store(Arc::new(policies));
+        Ok(result)
+    }
+
+    /// Replace the policy set, returning the previous one
+    pub fn replace(&self, policies: PolicySet) -> Arc<PolicySet> {
+        let _guard = self
+            .update_lock
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        self.current.swap(Arc::new(policies))
+    }
 }
 
 impl std::fmt::Display for PolicySet {
@@ -1846,7 +2899,7 @@ impl std::fmt::Display for PolicySet {
 pub struct Template {
     /// AST representation of the template, used for most operations.
     /// In particular, the `ast` contains the authoritative `PolicyId` for the template.
-    ast: ast::Template,
+    pub(crate) ast: ast::Template,
 
     /// Some "lossless" representation of the template, whichever is most
     /// convenient to provide (and can be provided with the least overhead).
@@ -1908,6 +2961,17 @@ impl Template {
             .map(smol_str::SmolStr::as_str)
     }
 
+    /// Get an annotation value of this `Template`, converted to `T`. See
+    /// [`Policy::annotation_as()`].
+    pub fn annotation_as<T: DeserializeOwned>(
+        &self,
+        key: impl AsRef<str>,
+    ) -> Option<Result<T, AnnotationError>> {
+        let key = key.as_ref();
+        self.annotation(key)
+            .map(|value| parse_annotation(key, value))
+    }
+
     /// Iterate through annotation data of this `Template` as key-value pairs
     pub fn annotations(&self) -> impl Iterator<Item = (&str, &str)> {
         self.ast
@@ -1936,6 +3000,18 @@ impl Template {
                     ast::EntityReference::Slot => None,
                 })
             }
+            ast::PrincipalOrResourceConstraint::Is(entity_type) => {
+                TemplatePrincipalConstraint::Is(EntityTypeName(entity_type.clone()))
+            }
+            ast::PrincipalOrResourceConstraint::IsIn(entity_type, eref) => {
+                TemplatePrincipalConstraint::IsIn(
+                    EntityTypeName(entity_type.clone()),
+                    match eref {
+                        ast::EntityReference::EUID(e) => Some(EntityUid(e.as_ref().clone())),
+                        ast::EntityReference::Slot => None,
+                    },
+                )
+            }
         }
     }
 
@@ -1969,6 +3045,18 @@ impl Template {
                     ast::EntityReference::Slot => None,
                 })
             }
+            ast::PrincipalOrResourceConstraint::Is(entity_type) => {
+                TemplateResourceConstraint::Is(EntityTypeName(entity_type.clone()))
+            }
+            ast::PrincipalOrResourceConstraint::IsIn(entity_type, eref) => {
+                TemplateResourceConstraint::IsIn(
+                    EntityTypeName(entity_type.clone()),
+                    match eref {
+                        ast::EntityReference::EUID(e) => Some(EntityUid(e.as_ref().clone())),
+                        ast::EntityReference::Slot => None,
+                    },
+                )
+            }
         }
     }
 
@@ -2002,7 +3090,6 @@ impl Template {
     /// create the EST from the policy text or CST instead, as the conversion
     /// to AST is lossy. ESTs generated by this method will reflect the AST and
     /// not the original policy syntax.
-    #[cfg_attr(not(feature = "partial-eval"), allow(unused))]
     fn from_ast(ast: ast::Template) -> Self {
         let text = ast.to_string(); // assume that pretty-printing is faster than `est::Policy::from(ast.clone())`; is that true?
         Self {
@@ -2012,6 +3099,12 @@ impl Template {
     }
 }
 
+impl std::fmt::Display for Template {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.ast.fmt(f)
+    }
+}
+
 impl FromStr for Template {
     type Err = ParseErrors;
 
@@ -2029,6 +3122,10 @@ pub enum PrincipalConstraint {
     In(EntityUid),
     /// Must be equal to the given EntityUid
     Eq(EntityUid),
+    /// Must be of the given EntityTypeName
+    Is(EntityTypeName),
+    /// Must be of the given EntityTypeName and In the given EntityUid
+    IsIn(EntityTypeName, EntityUid),
 }
 
 /// Head constraint on policy principals for templates.
@@ -2042,14 +3139,19 @@ pub enum TemplatePrincipalConstraint {
     /// Must be equal to the given EntityUid.
     /// If [`None`], then it is a template slot.
     Eq(Option<EntityUid>),
+    /// Must be of the given EntityTypeName
+    Is(EntityTypeName),
+    /// Must be of the given EntityTypeName and In the given EntityUid.
+    /// If [`None`], then it is a template slot.
+    IsIn(EntityTypeName, Option<EntityUid>),
 }
 
 impl TemplatePrincipalConstraint {
     /// Does this constraint contain a slot?
     pub fn has_slot(&self) -> bool {
         match self {
-            Self::Any => false,
-            Self::In(o) | Self::Eq(o) => o.is_none(),
+            Self::Any | Self::Is(_) => false,
+            Self::In(o) | Self::Eq(o) | Self::IsIn(_, o) => o.is_none(),
         }
     }
 }
@@ -2074,6 +3176,10 @@ pub enum ResourceConstraint {
     In(EntityUid),
     /// Must be equal to the given EntityUid
     Eq(EntityUid),
+    /// Must be of the given EntityTypeName
+    Is(EntityTypeName),
+    /// Must be of the given EntityTypeName and In the given EntityUid
+    IsIn(EntityTypeName, EntityUid),
 }
 
 /// Head constraint on policy resources for templates.
@@ -2087,21 +3193,59 @@ pub enum TemplateResourceConstraint {
     /// Must be equal to the given EntityUid.
     /// If [`None`], then it is a template slot.
     Eq(Option<EntityUid>),
+    /// Must be of the given EntityTypeName
+    Is(EntityTypeName),
+    /// Must be of the given EntityTypeName and In the given EntityUid.
+    /// If [`None`], then it is a template slot.
+    IsIn(EntityTypeName, Option<EntityUid>),
 }
 
 impl TemplateResourceConstraint {
     /// Does this constraint contain a slot?
     pub fn has_slot(&self) -> bool {
         match self {
-            Self::Any => false,
-            Self::In(o) | Self::Eq(o) => o.is_none(),
+            Self::Any | Self::Is(_) => false,
+            Self::In(o) | Self::Eq(o) | Self::IsIn(_, o) => o.is_none(),
         }
     }
 }
 
+/// Error converting an annotation value with [`Policy::annotation_as()`] or
+/// [`Template::annotation_as()`]
+#[derive(Debug, Error)]
+#[error("annotation `{key}` has value `{value}`, which can't be converted: {source}")]
+pub struct AnnotationError {
+    key: String,
+    value: String,
+    source: serde_json::Error,
+}
+
+impl AnnotationError {
+    /// The annotation key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// The annotation value which couldn't be converted
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+}
+
+/// Read an annotation value as JSON, or failing that, as a JSON string
+fn parse_annotation<T: DeserializeOwned>(key: &str, value: &str) -> Result<T, AnnotationError> {
+    serde_json::from_str(value)
+        .or_else(|_| serde_json::from_value(serde_json::Value::String(value.to_string())))
+        .map_err(|source| AnnotationError {
+            key: key.to_string(),
+            value: value.to_string(),
+            source,
+        })
+}
+
 /// Unique Ids assigned to policies and templates
 #[repr(transparent)]
-#[derive(Debug, PartialEq, Eq, Clone, Hash, Serialize, Deserialize, RefCast)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash, Serialize, Deserialize, RefCast)]
 pub struct PolicyId(ast::PolicyID);
 
 impl FromStr for PolicyId {
@@ -2119,12 +3263,19 @@ impl std::fmt::Display for PolicyId {
     }
 }
 
+/// The id exactly as given, without the escaping done by `Display`
+impl AsRef<str> for PolicyId {
+    fn as_ref(&self) -> &str {
+        self.0.as_ref()
+    }
+}
+
 /// Structure for a `Policy`. Includes both static policies and template-linked policies.
 #[derive(Debug, Clone)]
 pub struct Policy {
     /// AST representation of the policy, used for most operations.
     /// In particular, the `ast` contains the authoritative `PolicyId` for the policy.
-    ast: ast::Policy,
+    pub(crate) ast: ast::Policy,
     /// Some "lossless" representation of the policy, whichever is most
     /// convenient to provide (and can be provided with the least overhead).
     /// This is used just for `to_json()`.
@@ -2166,6 +3317,33 @@ impl Policy {
             .map(smol_str::SmolStr::as_str)
     }
 
+    /// Get an annotation value of this template-linked or static policy,
+    /// converted to `T`.
+    ///
+    /// The value is read as JSON if it is valid JSON, and as a string
+    /// otherwise, so `@limit("100")` can be read as a number and
+    /// `@owner("alice")` as a `String`.
+    ///
+    /// ```
+    /// # use cedar_policy::Policy;
+    /// let policy = Policy::parse(
+    ///     None,
+    ///     r#"@limit("100") @owner("alice") permit(principal, action, resource);"#,
+    /// ).unwrap();
+    /// assert_eq!(policy.annotation_as::<u64>("limit").unwrap().unwrap(), 100);
+    /// assert_eq!(policy.annotation_as::<String>("owner").unwrap().unwrap(), "alice");
+    /// assert!(policy.annotation_as::<u64>("owner").unwrap().is_err());
+    /// assert!(policy.annotation_as::<u64>("review").is_none());
+    /// ```
+    pub fn annotation_as<T: DeserializeOwned>(
+        &self,
+        key: impl AsRef<str>,
+    ) -> Option<Result<T, AnnotationError>> {
+        let key = key.as_ref();
+        self.annotation(key)
+            .map(|value| parse_annotation(key, value))
+    }
+
     /// Iterate through annotation data of this template-linked or static policy
     pub fn annotations(&self) -> impl Iterator<Item = (&str, &str)> {
         self.ast
@@ -2203,6 +3381,15 @@ impl Policy {
             ast::PrincipalOrResourceConstraint::Eq(eref) => {
                 PrincipalConstraint::Eq(self.convert_entity_reference(eref, slot_id).clone())
             }
+            ast::PrincipalOrResourceConstraint::Is(entity_type) => {
+                PrincipalConstraint::Is(EntityTypeName(entity_type.clone()))
+            }
+            ast::PrincipalOrResourceConstraint::IsIn(entity_type, eref) => {
+                PrincipalConstraint::IsIn(
+                    EntityTypeName(entity_type.clone()),
+                    self.convert_entity_reference(eref, slot_id).clone(),
+                )
+            }
         }
     }
 
@@ -2232,6 +3419,15 @@ impl Policy {
             ast::PrincipalOrResourceConstraint::Eq(eref) => {
                 ResourceConstraint::Eq(self.convert_entity_reference(eref, slot_id).clone())
             }
+            ast::PrincipalOrResourceConstraint::Is(entity_type) => {
+                ResourceConstraint::Is(EntityTypeName(entity_type.clone()))
+            }
+            ast::PrincipalOrResourceConstraint::IsIn(entity_type, eref) => {
+                ResourceConstraint::IsIn(
+                    EntityTypeName(entity_type.clone()),
+                    self.convert_entity_reference(eref, slot_id).clone(),
+                )
+            }
         }
     }
 
@@ -2378,7 +3574,6 @@ impl Policy {
     /// create the `Policy` from the policy text, CST, or EST instead, as the
     /// conversion to AST is lossy. ESTs for policies generated by this method
     /// will reflect the AST and not the original policy syntax.
-    #[cfg_attr(not(feature = "partial-eval"), allow(unused))]
     fn from_ast(ast: ast::Policy) -> Self {
         let text = ast.to_string(); // assume that pretty-printing is faster than `est::Policy::from(ast.clone())`; is that true?
         Self {
@@ -2490,7 +3685,7 @@ pub enum PolicyToJsonError {
 /// Expressions to be evaluated
 #[repr(transparent)]
 #[derive(Debug, Clone, RefCast)]
-pub struct Expression(ast::Expr);
+pub struct Expression(pub(crate) ast::Expr);
 
 impl Expression {
     /// Create an expression representing a literal string.
@@ -2576,6 +3771,17 @@ impl RestrictedExpression {
     pub fn new_set(values: impl IntoIterator<Item = Self>) -> Self {
         Self(ast::RestrictedExpr::set(values.into_iter().map(|v| v.0)))
     }
+
+    /// Create an unknown with the given name, for use in a context or entity
+    /// attribute whose value isn't available when the request is made.
+    /// Authorizing with [`Authorizer::is_authorized_partial`] reports the
+    /// names of any unknowns needed to reach a decision.
+    #[cfg(feature = "partial-eval")]
+    pub fn new_unknown(name: impl AsRef<str>) -> Self {
+        Self(ast::RestrictedExpr::new_unchecked(ast::Expr::unknown(
+            name.as_ref(),
+        )))
+    }
 }
 
 impl FromStr for RestrictedExpression {
@@ -2674,6 +3880,319 @@ impl RequestBuilder {
     }
 }
 
+/// Builder for a [`Request`] which checks each component against a
+/// [`Schema`] as it is supplied, rather than leaving type errors to surface
+/// during evaluation.
+///
+/// The action is fixed when the builder is created, since it determines which
+/// principal and resource types are allowed and what type the context has.
+/// Each context attribute is parsed according to its declared type, so, e.g.,
+/// a JSON string supplied for an attribute declared as an extension type
+/// (such as `u256`) is converted into a value of that type here, and a
+/// malformed value is reported as an error on that attribute.
+#[derive(Debug)]
+pub struct SchemaRequestBuilder<'s> {
+    schema: &'s Schema,
+    action: EntityUid,
+    action_id: &'s cedar_policy_validator::ValidatorActionId,
+    principal: Option<EntityUid>,
+    resource: Option<EntityUid>,
+    context: BTreeMap<SmolStr, ast::RestrictedExpr>,
+}
+
+impl<'s> SchemaRequestBuilder<'s> {
+    /// Create a builder for a request for the given `action`, which must be
+    /// declared in the `schema`
+    pub fn new(schema: &'s Schema, action: EntityUid) -> Result<Self, RequestValidationError> {
+        match schema.0.get_action_id(&action.0) {
+            Some(action_id) => Ok(Self {
+                schema,
+                action,
+                action_id,
+                principal: None,
+                resource: None,
+                context: BTreeMap::new(),
+            }),
+            None => Err(RequestValidationError::UndeclaredAction { action }),
+        }
+    }
+
+    /// Set the principal. Its type must be declared in the schema, and the
+    /// action must apply to principals of that type.
+    pub fn principal(self, principal: EntityUid) -> Result<Self, RequestValidationError> {
+        self.check_entity_type(RequestComponent::Principal, &principal)?;
+        Ok(Self {
+            principal: Some(principal),
+            ..self
+        })
+    }
+
+    /// Set the resource. Its type must be declared in the schema, and the
+    /// action must apply to resources of that type.
+    pub fn resource(self, resource: EntityUid) -> Result<Self, RequestValidationError> {
+        self.check_entity_type(RequestComponent::Resource, &resource)?;
+        Ok(Self {
+            resource: Some(resource),
+            ..self
+        })
+    }
+
+    /// Set a single context attribute from its JSON representation.
+    ///
+    /// The attribute must be declared in the context of the action, unless
+    /// the schema's context mode is [`ContextMode::Open`], in which case
+    /// undeclared attributes are parsed as if there was no schema. The value
+    /// of a declared attribute is parsed according to the declared type:
+    /// `__entity` and `__extn` escapes may be omitted, and extension values
+    /// are constructed (and checked) immediately. If the schema declares the
+    /// attribute as coercible, the value is coerced to the declared type
+    /// first.
+    pub fn context_attr(
+        mut self,
+        attr: &str,
+        value: serde_json::Value,
+    ) -> Result<Self, RequestValidationError> {
+        let expected_ty: Option<entities::SchemaType> = match self.action_id.context_attr(attr) {
+            // PANIC SAFETY: the type is taken from a `ValidatorActionId` which was
+            // constructed from a schema, so it is representable as a `SchemaType`
+            #[allow(clippy::expect_used)]
+            Some(attr_ty) => Some(
+                attr_ty
+                    .attr_type
+                    .clone()
+                    .try_into()
+                    .expect("failed to convert validator type into Core SchemaType"),
+            ),
+            None if self.schema.0.context_mode() == ContextMode::Open => None,
+            None => {
+                return Err(RequestValidationError::UndeclaredContextAttr {
+                    attr: attr.into(),
+                    action: self.action.clone(),
+                })
+            }
+        };
+        let value = self.action_id.normalize_context_attr(attr, value);
+        let extensions = Extensions::all_available();
+        let vparser = entities::ValueParser::new(extensions.clone());
+        let invalid = |err| RequestValidationError::InvalidContextAttr {
+            attr: attr.into(),
+            err,
+        };
+        let rexpr = vparser
+            .val_into_rexpr(value, expected_ty.as_ref(), || {
+                JsonDeserializationErrorContext::Context
+            })
+            .map_err(invalid)?;
+        // the parser only uses the expected type as a hint, so check that the
+        // value actually has it
+        if let Some(expected_ty) = expected_ty {
+            let actual_ty = vparser
+                .type_of_rexpr(rexpr.as_borrowed(), || {
+                    JsonDeserializationErrorContext::Context
+                })
+                .map_err(invalid)?;
+            if !actual_ty.is_consistent_with(&expected_ty) {
+                return Err(invalid(entities::JsonDeserializationError::TypeMismatch {
+                    ctx: Box::new(JsonDeserializationErrorContext::Context),
+                    expected: Box::new(expected_ty),
+                    actual: Box::new(actual_ty),
+                }));
+            }
+        }
+        // Evaluating here is what catches, e.g., a string which isn't a valid
+        // argument to the implied extension constructor
+        RestrictedEvaluator::new(&extensions)
+            .interpret(rexpr.as_borrowed())
+            .map_err(|err| RequestValidationError::ContextAttrEvaluation {
+                attr: attr.into(),
+                err,
+            })?;
+        self.context.insert(attr.into(), rexpr);
+        Ok(self)
+    }
+
+    /// Create the [`Request`]. Context attributes which were not supplied but
+    /// have a default declared in the schema are set to that default.
+    ///
+    /// Fails if a required context attribute was not supplied, or if the
+    /// principal or resource was not supplied and the action applies to
+    /// specific principal or resource types.
+    pub fn build(mut self) -> Result<Request, RequestValidationError> {
+        let action_id = self.action_id;
+        for (attr, default) in action_id.context_defaults() {
+            if !self.context.contains_key(attr) {
+                self = self.context_attr(attr, default.clone())?;
+            }
+        }
+        if let Some((attr, _)) = self
+            .action_id
+            .context()
+            .find(|(attr, ty)| ty.is_required && !self.context.contains_key(*attr))
+        {
+            return Err(RequestValidationError::MissingContextAttr {
+                attr: attr.to_string(),
+                action: self.action,
+            });
+        }
+        let principal = Self::head_uid(
+            RequestComponent::Principal,
+            self.principal,
+            self.action_id.applies_to_principals(),
+        )?;
+        let resource = Self::head_uid(
+            RequestComponent::Resource,
+            self.resource,
+            self.action_id.applies_to_resources(),
+        )?;
+        Ok(Request(ast::Request::new(
+            principal,
+            self.action.0,
+            resource,
+            ast::Context::from_pairs(self.context),
+        )))
+    }
+
+    /// Check that `uid` has an entity type which is declared in the schema and
+    /// to which the action applies, in the position given by `component`
+    fn check_entity_type(
+        &self,
+        component: RequestComponent,
+        uid: &EntityUid,
+    ) -> Result<(), RequestValidationError> {
+        let entity_type = uid.type_name();
+        if self.schema.0.get_entity_type(&entity_type.0).is_none() {
+            return Err(RequestValidationError::UndeclaredEntityType {
+                component,
+                entity_type: entity_type.clone(),
+            });
+        }
+        let ety = ast::EntityType::Concrete(entity_type.0.clone());
+        let applies = match component {
+            RequestComponent::Principal => {
+                self.action_id.applies_to_principals().any(|t| t == &ety)
+            }
+            RequestComponent::Resource => self.action_id.applies_to_resources().any(|t| t == &ety),
+        };
+        if applies {
+            Ok(())
+        } else {
+            Err(RequestValidationError::InapplicableEntityType {
+                component,
+                entity_type: entity_type.clone(),
+                action: self.action.clone(),
+            })
+        }
+    }
+
+    /// Get the UID to use for the principal or resource. An omitted UID is
+    /// only allowed if the action applies to the unspecified entity type in
+    /// that position.
+    fn head_uid<'a>(
+        component: RequestComponent,
+        uid: Option<EntityUid>,
+        mut applies_to: impl Iterator<Item = &'a ast::EntityType>,
+    ) -> Result<ast::EntityUID, RequestValidationError> {
+        match uid {
+            Some(uid) => Ok(uid.0),
+            None if applies_to.any(|t| matches!(t, ast::EntityType::Unspecified)) => Ok(
+                ast::EntityUID::unspecified_from_eid(ast::Eid::new(component.to_string())),
+            ),
+            None => Err(RequestValidationError::MissingComponent { component }),
+        }
+    }
+}
+
+/// Component of a request which is checked against the schema by a
+/// [`SchemaRequestBuilder`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestComponent {
+    /// The principal
+    Principal,
+    /// The resource
+    Resource,
+}
+
+impl std::fmt::Display for RequestComponent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Principal => write!(f, "principal"),
+            Self::Resource => write!(f, "resource"),
+        }
+    }
+}
+
+/// Errors encountered while building a [`Request`] with a
+/// [`SchemaRequestBuilder`]
+#[derive(Debug, Error)]
+pub enum RequestValidationError {
+    /// The action is not declared in the schema
+    #[error("action `{action}` is not declared in the schema")]
+    UndeclaredAction {
+        /// Action which is not declared
+        action: EntityUid,
+    },
+    /// The principal or resource has an entity type which is not declared in
+    /// the schema
+    #[error("{component} has type `{entity_type}` which is not declared in the schema")]
+    UndeclaredEntityType {
+        /// Whether this was the principal or resource
+        component: RequestComponent,
+        /// Entity type which is not declared
+        entity_type: EntityTypeName,
+    },
+    /// The action does not apply to the entity type of the principal or resource
+    #[error("action `{action}` does not apply to a {component} of type `{entity_type}`")]
+    InapplicableEntityType {
+        /// Whether this was the principal or resource
+        component: RequestComponent,
+        /// Entity type the action does not apply to
+        entity_type: EntityTypeName,
+        /// The action
+        action: EntityUid,
+    },
+    /// The principal or resource was not supplied, but the action requires one
+    #[error("missing {component}, which is required by the action")]
+    MissingComponent {
+        /// Whether this was the principal or resource
+        component: RequestComponent,
+    },
+    /// A context attribute was supplied which is not declared for the action
+    #[error("context attribute `{attr}` is not declared for action `{action}`")]
+    UndeclaredContextAttr {
+        /// Name of the attribute
+        attr: String,
+        /// The action
+        action: EntityUid,
+    },
+    /// A context attribute value could not be parsed as its declared type
+    #[error("invalid value for context attribute `{attr}`: {err}")]
+    InvalidContextAttr {
+        /// Name of the attribute
+        attr: String,
+        /// Underlying parse error
+        #[source]
+        err: JsonDeserializationError,
+    },
+    /// A context attribute value parsed, but could not be evaluated (e.g.,
+    /// a malformed argument to an extension constructor)
+    #[error("invalid value for context attribute `{attr}`: {err}")]
+    ContextAttrEvaluation {
+        /// Name of the attribute
+        attr: String,
+        /// Underlying evaluation error
+        #[source]
+        err: EvaluationError,
+    },
+    /// A context attribute required by the action was not supplied
+    #[error("missing context attribute `{attr}`, which is required by action `{action}`")]
+    MissingContextAttr {
+        /// Name of the attribute
+        attr: String,
+        /// The action
+        action: EntityUid,
+    },
+}
+
 /// Represents the request tuple <P, A, R, C> (see the Cedar design doc).
 #[repr(transparent)]
 #[derive(Debug, RefCast)]
@@ -2754,6 +4273,137 @@ impl Request {
             ast::EntityUIDEntry::Unknown => None,
         }
     }
+
+    /// The chain id in the [`chain`](crate::chain::CHAIN_ATTRIBUTE)
+    /// attribute of the context, if it has one
+    pub fn chain(&self) -> Option<u64> {
+        ChainScope::new().chain_of(self).ok().flatten()
+    }
+
+    /// keccak256 digest of this request, for receipts, audit logs, and
+    /// anything else which needs to refer to a request by a digest. The
+    /// digest covers the principal, action, resource, and context, extension
+    /// values included, and is the digest of [`Request::canonical_bytes()`].
+    pub fn canonical_hash(&self) -> [u8; 32] {
+        Keccak256::digest(self.canonical_bytes()).into()
+    }
+
+    /// The stable serialization of this request which
+    /// [`Request::canonical_hash()`] digests: compact JSON with the
+    /// `principal`, `action`, `resource`, and `context` of the request.
+    ///
+    /// Values are written in the `__entity` and `__extn` JSON format, with
+    /// record keys in sorted order, and set elements in sorted order without
+    /// duplicates, so that equal requests have equal serializations. A record
+    /// with a key which is one of those escapes is wrapped in a `__record`
+    /// escape, so that it can't be mistaken for an entity or extension value.
+    /// Unspecified and unknown components are `null`.
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        let entry = |entry: &ast::EntityUIDEntry| match entry {
+            ast::EntityUIDEntry::Concrete(uid) => match uid.entity_type() {
+                ast::EntityType::Concrete(_) => canonical_uid(uid),
+                ast::EntityType::Unspecified => serde_json::Value::Null,
+            },
+            ast::EntityUIDEntry::Unknown => serde_json::Value::Null,
+        };
+        let canonical = serde_json::json!({
+            "action": entry(self.0.action()),
+            "context": self
+                .0
+                .context()
+                .map_or(serde_json::Value::Null, |c| canonical_value(c.as_ref())),
+            "principal": entry(self.0.principal()),
+            "resource": entry(self.0.resource()),
+        });
+        canonical.to_string().into_bytes()
+    }
+
+    /// This request, with the context attribute `key` set to `value`
+    pub(crate) fn with_context_attribute(&self, key: &str, value: RestrictedExpression) -> Self {
+        let context = self.0.context().map(|context| {
+            let pairs = context
+                .iter()
+                .filter(|(k, _)| *k != key)
+                .map(|(k, v)| {
+                    (
+                        SmolStr::from(k),
+                        ast::RestrictedExpr::new_unchecked(ast::Expr::clone(&v)),
+                    )
+                })
+                .chain(std::iter::once((SmolStr::from(key), value.0)));
+            ast::Context::from_pairs(pairs)
+        });
+        Self(ast::Request::new_with_unknowns(
+            self.0.principal().clone(),
+            self.0.action().clone(),
+            self.0.resource().clone(),
+            context,
+        ))
+    }
+
+    /// This request, made by `principal` instead
+    pub(crate) fn with_principal(&self, principal: &EntityUid) -> Self {
+        Self(ast::Request::new_with_unknowns(
+            ast::EntityUIDEntry::concrete(principal.0.clone()),
+            self.0.action().clone(),
+            self.0.resource().clone(),
+            self.0.context().cloned(),
+        ))
+    }
+}
+
+/// The JSON form of `uid` used by [`Request::canonical_bytes()`]
+fn canonical_uid(uid: &ast::EntityUID) -> serde_json::Value {
+    serde_json::json!({
+        "__entity": { "id": uid.eid().as_ref() as &str, "type": uid.entity_type().to_string() }
+    })
+}
+
+/// The JSON form of `expr` used by [`Request::canonical_bytes()`]
+fn canonical_value(expr: &ast::Expr) -> serde_json::Value {
+    use serde_json::Value;
+    match expr.expr_kind() {
+        ast::ExprKind::Lit(ast::Literal::Bool(b)) => Value::from(*b),
+        ast::ExprKind::Lit(ast::Literal::Long(i)) => Value::from(*i),
+        ast::ExprKind::Lit(ast::Literal::String(s)) => Value::from(s.as_str()),
+        ast::ExprKind::Lit(ast::Literal::EntityUID(uid)) => canonical_uid(uid),
+        ast::ExprKind::ExtensionFunctionApp { fn_name, args } => {
+            let args: Vec<_> = args.iter().map(canonical_value).collect();
+            match args.as_slice() {
+                [arg] => serde_json::json!({ "__extn": { "arg": arg, "fn": fn_name.to_string() } }),
+                _ => serde_json::json!({ "__extn": { "args": args, "fn": fn_name.to_string() } }),
+            }
+        }
+        ast::ExprKind::Set(elements) => {
+            // sort by the serialized element, which is the same for equal elements
+            let elements: BTreeMap<String, Value> = elements
+                .iter()
+                .map(|e| {
+                    let value = canonical_value(e);
+                    (value.to_string(), value)
+                })
+                .collect();
+            Value::Array(elements.into_values().collect())
+        }
+        ast::ExprKind::Record { pairs } => {
+            let record: serde_json::Map<String, Value> = pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), canonical_value(v)))
+                .collect::<BTreeMap<_, _>>()
+                .into_iter()
+                .collect();
+            if record
+                .keys()
+                .any(|k| ["__entity", "__extn", "__expr", "__record"].contains(&k.as_str()))
+            {
+                serde_json::json!({ "__record": record })
+            } else {
+                Value::Object(record)
+            }
+        }
+        // restricted expressions have no other kinds of expression
+        _ => serde_json::json!({ "__expr": expr.to_string() }),
+    }
 }
 
 /// the Context object for an authorization request
@@ -2973,6 +4623,20 @@ impl Context {
         Ok(Self(context))
     }
 
+    /// Convert this `Context` into a JSON value suitable for parsing in via
+    /// `from_json_value()`. Entity references and extension values are written
+    /// with the `__entity` and `__extn` escapes, so the JSON is parse-able even
+    /// with no `Schema`.
+    /// ```
+    /// # use cedar_policy::Context;
+    /// let json = serde_json::json!({ "amount": { "__extn": { "fn": "u256", "arg": "1000" } } });
+    /// let context = Context::from_json_value(json.clone(), None).unwrap();
+    /// assert_eq!(context.to_json_value().unwrap(), json);
+    /// ```
+    pub fn to_json_value(&self) -> Result<serde_json::Value, impl std::error::Error> {
+        self.0.to_json_value()
+    }
+
     /// Internal helper function to convert `(&Schema, &EntityUid)` to `impl ContextSchema`
     fn get_context_schema(
         schema: &Schema,
@@ -3158,12 +4822,66 @@ pub fn eval_expression(
     ))
 }
 
+/// Evaluates an expression, like [`eval_expression`], and also describes the
+/// type of the result using the type names of the schema format, e.g. `Long`,
+/// `Set<u256>`, or `{ amount: u256, to: String }`.
+pub fn eval_expression_with_type(
+    request: &Request,
+    entities: &Entities,
+    expr: &Expression,
+) -> Result<(EvalResult, String), EvaluationError> {
+    let all_ext = Extensions::all_available();
+    let eval = Evaluator::new(&request.0, &entities.0, &all_ext)?;
+    // Evaluate under the empty slot map, as an expression should not have slots
+    let value = eval.interpret(&expr.0, &ast::SlotEnv::new())?;
+    let ty = describe_type(&value);
+    Ok((EvalResult::from(value), ty))
+}
+
+/// Describe the type of a value. The element type of a set is the union of
+/// the types of its elements, which is a single type for any set the
+/// validator accepts.
+fn describe_type(value: &ast::Value) -> String {
+    match value {
+        ast::Value::Lit(ast::Literal::Bool(_)) => "Boolean".into(),
+        ast::Value::Lit(ast::Literal::Long(_)) => "Long".into(),
+        ast::Value::Lit(ast::Literal::String(_)) => "String".into(),
+        ast::Value::Lit(ast::Literal::EntityUID(e)) => e.entity_type().to_string(),
+        ast::Value::Set(s) => {
+            let elements: BTreeSet<String> = s.authoritative.iter().map(describe_type).collect();
+            if elements.is_empty() {
+                "Set".into()
+            } else {
+                format!(
+                    "Set<{}>",
+                    elements.into_iter().collect::<Vec<_>>().join(" | ")
+                )
+            }
+        }
+        ast::Value::Record(r) => {
+            if r.is_empty() {
+                "{}".into()
+            } else {
+                let attrs: Vec<_> = r
+                    .iter()
+                    .map(|(k, v)| format!("{k}: {}", describe_type(v)))
+                    .collect();
+                format!("{{ {} }}", attrs.join(", "))
+            }
+        }
+        ast::Value::ExtensionValue(ev) => ev.typename().to_string(),
+    }
+}
+
 #[cfg(test)]
 #[cfg(feature = "partial-eval")]
 mod partial_eval_test {
     use std::collections::HashSet;
 
-    use crate::{AuthorizationError, PolicyId, PolicySet, ResidualResponse};
+    use crate::{
+        AuthorizationError, Authorizer, Context, Entities, EntityUid, PartialDecision, PolicyId,
+        PolicySet, Request, ResidualResponse, RestrictedExpression,
+    };
 
     #[test]
     fn test_pe_response_constructor() {
@@ -3175,11 +4893,72 @@ mod partial_eval_test {
         assert_eq!(a.diagnostics().reason, reason);
         assert_eq!(a.residuals(), &p);
     }
-}
-
-#[cfg(test)]
-mod entity_uid_tests {
-    use super::*;
+
+    #[test]
+    fn three_valued_decisions() {
+        let policies: PolicySet = "
+            permit(principal, action, resource) when { context.kycPassed };
+            forbid(principal, action, resource) when { context.sanctioned };
+        "
+        .parse()
+        .unwrap();
+        let request = |kyc: RestrictedExpression, sanctioned: RestrictedExpression| {
+            Request::new(
+                Some(EntityUid::from_strs("User", "alice")),
+                Some(EntityUid::from_strs("Action", "sign")),
+                Some(EntityUid::from_strs("Wallet", "w")),
+                Context::from_pairs([
+                    ("kycPassed".to_string(), kyc),
+                    ("sanctioned".to_string(), sanctioned),
+                ]),
+            )
+        };
+        let authorizer = Authorizer::new();
+        let entities = Entities::empty();
+
+        let r = request(
+            RestrictedExpression::new_unknown("context.kycPassed"),
+            RestrictedExpression::new_unknown("context.sanctioned"),
+        );
+        assert_eq!(
+            authorizer
+                .is_authorized_partial(&r, &policies, &entities)
+                .decision(),
+            PartialDecision::Indeterminate {
+                missing: vec![
+                    "context.kycPassed".to_string(),
+                    "context.sanctioned".to_string()
+                ]
+            }
+        );
+
+        let r = request(
+            RestrictedExpression::new_unknown("context.kycPassed"),
+            RestrictedExpression::new_bool(true),
+        );
+        assert_eq!(
+            authorizer
+                .is_authorized_partial(&r, &policies, &entities)
+                .decision(),
+            PartialDecision::Deny
+        );
+
+        let r = request(
+            RestrictedExpression::new_bool(true),
+            RestrictedExpression::new_bool(false),
+        );
+        assert_eq!(
+            authorizer
+                .is_authorized_partial(&r, &policies, &entities)
+                .decision(),
+            PartialDecision::Allow
+        );
+    }
+}
+
+#[cfg(test)]
+mod entity_uid_tests {
+    use super::*;
 
     /// building an `EntityUid` from components
     #[test]
@@ -3427,6 +5206,25 @@ mod head_constraints_tests {
         assert_eq!(p.principal_constraint(), PrincipalConstraint::In(euid));
     }
 
+    #[test]
+    fn is_constraint_inline() {
+        let ty = EntityTypeName::from_str("T").expect("Failed to parse EntityTypeName");
+        let p = Policy::from_str("permit(principal is T,action,resource);").unwrap();
+        assert_eq!(
+            p.principal_constraint(),
+            PrincipalConstraint::Is(ty.clone())
+        );
+        let p = Policy::from_str(
+            "permit(principal,action,resource is T in T::\"a\" when resource.public);",
+        )
+        .unwrap();
+        assert_eq!(
+            p.resource_constraint(),
+            ResourceConstraint::IsIn(ty, EntityUid::from_strs("T", "a"))
+        );
+        assert!(p.to_string().contains(r#"resource["public"]"#));
+    }
+
     #[test]
     fn action_constraint_inline() {
         let p = Policy::from_str("permit(principal,action,resource);").unwrap();
@@ -3779,6 +5577,220 @@ mod policy_set_tests {
             ))
         );
     }
+
+    #[test]
+    fn retained() {
+        let mut pset = PolicySet::from_str(
+            "permit(principal, action, resource);
+             forbid(principal, action, resource);",
+        )
+        .unwrap();
+        pset.add_template(
+            Template::parse(
+                Some("template".into()),
+                "permit(principal == ?principal, action, resource);",
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        pset.link(
+            PolicyId::from_str("template").unwrap(),
+            PolicyId::from_str("linked").unwrap(),
+            std::iter::once((SlotId::principal(), EntityUid::from_strs("Test", "test"))).collect(),
+        )
+        .unwrap();
+
+        assert!(matches!(pset.retained(|_| true), Cow::Borrowed(_)));
+        let forbids = pset.retained(|policy| policy.effect() == Effect::Forbid);
+        assert_eq!(
+            forbids.policies().map(Policy::id).collect::<Vec<_>>(),
+            [&PolicyId::from_str("policy1").unwrap()]
+        );
+        // templates are kept
+        assert_eq!(forbids.templates().count(), 1);
+    }
+
+    #[test]
+    fn shared_policy_set() {
+        let shared = SharedPolicySet::new(
+            PolicySet::from_str("permit(principal, action, resource);").unwrap(),
+        );
+        let before = shared.snapshot();
+
+        let forbid = Policy::parse(
+            Some("forbid".into()),
+            "forbid(principal, action, resource);",
+        )
+        .unwrap();
+        shared.update(|pset| pset.add(forbid)).unwrap();
+        // a failed update leaves the set unchanged
+        let duplicate = Policy::parse(
+            Some("forbid".into()),
+            "forbid(principal, action, resource);",
+        )
+        .unwrap();
+        assert!(shared.update(|pset| pset.add(duplicate)).is_err());
+
+        let after = shared.snapshot();
+        assert_eq!(before.policies().count(), 1);
+        assert_eq!(after.policies().count(), 2);
+
+        let request = Request::new(None, None, None, Context::empty());
+        let authorizer = Authorizer::new();
+        let decide = |pset: &PolicySet| {
+            authorizer
+                .is_authorized(&request, pset, &Entities::empty())
+                .decision()
+        };
+        assert_eq!(decide(&before), Decision::Allow);
+        assert_eq!(decide(&after), Decision::Deny);
+
+        let replaced = shared.replace(PolicySet::new());
+        assert_eq!(replaced.policies().count(), 2);
+        assert!(shared.snapshot().is_empty());
+    }
+
+    #[test]
+    fn shared_policy_set_concurrent_updates() {
+        let shared = SharedPolicySet::default();
+        std::thread::scope(|scope| {
+            for n in 0..8 {
+                let shared = &shared;
+                scope.spawn(move || {
+                    let src = format!(r#"permit(principal == User::"{n}", action, resource);"#);
+                    let policy = Policy::parse(Some(n.to_string()), src).unwrap();
+                    shared.update(|pset| pset.add(policy)).unwrap();
+                    assert!(shared.snapshot().policies().count() > 0);
+                });
+            }
+        });
+        assert_eq!(shared.snapshot().policies().count(), 8);
+    }
+
+    #[test]
+    fn typed_annotations() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Review {
+            by: String,
+            ticket: u32,
+        }
+
+        let mut pset = PolicySet::new();
+        let policy = Policy::parse(
+            Some("p".into()),
+            r#"@owner("alice") @review("{\"by\": \"bob\", \"ticket\": 7}") @tags("[\"a\", \"b\"]")
+            permit(principal, action, resource);"#,
+        )
+        .unwrap();
+        assert_eq!(
+            policy.annotation_as::<Review>("review").unwrap().unwrap(),
+            Review {
+                by: "bob".into(),
+                ticket: 7
+            }
+        );
+        assert_eq!(
+            policy
+                .annotation_as::<Vec<String>>("tags")
+                .unwrap()
+                .unwrap(),
+            vec!["a", "b"]
+        );
+        let err = policy
+            .annotation_as::<Review>("owner")
+            .unwrap()
+            .unwrap_err();
+        assert_eq!((err.key(), err.value()), ("owner", "alice"));
+        pset.add(policy).unwrap();
+
+        let template = Template::parse(
+            Some("t".into()),
+            r#"@owner("carol") permit(principal == ?principal, action, resource);"#,
+        )
+        .unwrap();
+        assert_eq!(
+            template.annotation_as::<String>("owner").unwrap().unwrap(),
+            "carol"
+        );
+        pset.add_template(template).unwrap();
+        pset.link(
+            PolicyId::from_str("t").unwrap(),
+            PolicyId::from_str("link").unwrap(),
+            HashMap::from([(SlotId::principal(), EntityUid::from_strs("User", "dan"))]),
+        )
+        .unwrap();
+        pset.add(Policy::parse(Some("q".into()), "forbid(principal, action, resource);").unwrap())
+            .unwrap();
+
+        let mut owners: Vec<_> = pset
+            .annotated("owner")
+            .map(|(policy, owner)| (policy.id().to_string(), owner))
+            .collect();
+        owners.sort_unstable();
+        assert_eq!(
+            owners,
+            vec![("link".to_string(), "carol"), ("p".to_string(), "alice")]
+        );
+        assert_eq!(pset.annotations().count(), 4);
+    }
+
+    #[test]
+    fn imports() {
+        let dir = std::env::temp_dir().join(format!("cedar-imports-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        std::fs::write(
+            dir.join("lib").join("tokens.cedar"),
+            r#"macro isStablecoin(token) = token in Group::"stablecoins";"#,
+        )
+        .unwrap();
+        let imports = ImportDir::new(&dir);
+
+        let pset = PolicySet::from_str_with_imports(
+            r#"import "lib/tokens.cedar";
+            permit(principal, action, resource) when { isStablecoin(resource) };"#,
+            &imports,
+        )
+        .unwrap();
+        let policy = pset
+            .policy(&PolicyId::from_str("policy0").unwrap())
+            .unwrap();
+        // the policy stands on its own, without the import
+        assert!(policy.to_string().contains(r#"Group::"stablecoins""#));
+        policy.to_json().unwrap();
+
+        assert!(imports.load("../tokens.cedar").is_err());
+        assert!(imports.load("/etc/hosts").is_err());
+        assert!(PolicySet::from_str(
+            r#"import "lib/tokens.cedar"; permit(principal, action, resource);"#
+        )
+        .is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn prelude() {
+        let imports = HashMap::<String, String>::new();
+        let prelude = Prelude::parse(
+            "macro withinRange(amount, floor, cap) =
+                amount.u256GreaterThanOrEqual(floor) && amount.u256LessThanOrEqual(cap);",
+            &imports,
+        )
+        .unwrap();
+        let pset = PolicySet::from_str_with_prelude(
+            r#"permit(principal, action, resource)
+            when { withinRange(context.amount, u256("1"), u256("1000")) };"#,
+            &prelude,
+            &imports,
+        )
+        .unwrap();
+        assert_eq!(pset.policies().count(), 1);
+        assert!(PolicySet::from_str(
+            r#"permit(principal, action, resource)
+            when { withinRange(context.amount, u256("1"), u256("1000")) };"#
+        )
+        .is_err());
+        assert!(Prelude::parse("macro f(x) = f(x);", &imports).is_err());
+    }
 }
 
 #[cfg(test)]
@@ -4594,6 +6606,183 @@ mod schema_based_parsing_tests {
         assert_eq!(parsed.iter().count(), 1);
     }
 
+    /// `u256` attributes may be given as strings, integers, or `__extn`
+    /// escapes, and policies can compare them without reconstructing them
+    #[test]
+    #[cfg(feature = "u256")]
+    #[allow(clippy::too_many_lines)]
+    fn u256_attrs() {
+        let schema = Schema::from_json_value(json!(
+        {"": {
+            "entityTypes": {
+                "Wallet": {
+                    "shape": {
+                        "type": "Record",
+                        "attributes": {
+                            "balance": { "type": "Extension", "name": "u256" },
+                            "limit": { "type": "Extension", "name": "u256" },
+                            "nonce": { "type": "Extension", "name": "u256" }
+                        }
+                    }
+                }
+            },
+            "actions": {
+                "spend": {
+                    "appliesTo": {
+                        "principalTypes": ["Wallet"],
+                        "resourceTypes": ["Wallet"]
+                    }
+                }
+            }
+        }}
+        ))
+        .expect("should be a valid schema");
+
+        let entitiesjson = json!(
+            [
+                {
+                    "uid": { "type": "Wallet", "id": "alice" },
+                    "attrs": {
+                        "balance": "1000000000000000000000",
+                        "limit": u64::MAX,
+                        "nonce": { "__extn": { "fn": "u256", "arg": "7" } }
+                    },
+                    "parents": []
+                }
+            ]
+        );
+        let parsed = Entities::from_json_value(entitiesjson, Some(&schema))
+            .expect("Should parse without error");
+        let alice = EntityUid::from_strs("Wallet", "alice");
+        let entity = parsed.get(&alice).expect("alice should exist");
+        assert_eq!(
+            entity.attr("balance"),
+            Some(Ok(EvalResult::ExtensionValue(
+                "1000000000000000000000".into()
+            )))
+        );
+        assert_eq!(
+            entity.attr("limit"),
+            Some(Ok(EvalResult::ExtensionValue(u64::MAX.to_string())))
+        );
+        assert_eq!(
+            entity.attr("nonce"),
+            Some(Ok(EvalResult::ExtensionValue("7".into())))
+        );
+
+        let policies = PolicySet::from_str(
+            "permit(principal, action, resource) when { resource.limit.u256LessThan(resource.balance) };",
+        )
+        .expect("should be a valid policy");
+        let request = Request::new(
+            Some(alice.clone()),
+            Some(EntityUid::from_strs("Action", "spend")),
+            Some(alice),
+            Context::empty(),
+        );
+        let response = Authorizer::new().is_authorized(&request, &policies, &parsed);
+        assert_eq!(response.decision(), Decision::Allow);
+
+        // an integer that isn't a valid `u256`
+        let entitiesjson = json!(
+            [
+                {
+                    "uid": { "type": "Wallet", "id": "alice" },
+                    "attrs": { "balance": -1, "limit": 1, "nonce": 0 },
+                    "parents": []
+                }
+            ]
+        );
+        let err = Entities::from_json_value(entitiesjson, Some(&schema))
+            .expect_err("should fail due to a negative balance");
+        assert!(
+            err.to_string()
+                .contains("missing extension constructor for long -> u256"),
+            "actual error message was {err}"
+        );
+
+        // an extension value of the wrong type
+        let entitiesjson = json!(
+            [
+                {
+                    "uid": { "type": "Wallet", "id": "alice" },
+                    "attrs": {
+                        "balance": { "__extn": { "fn": "decimal", "arg": "1.5" } },
+                        "limit": 1,
+                        "nonce": 0
+                    },
+                    "parents": []
+                }
+            ]
+        );
+        let err = Entities::from_json_value(entitiesjson, Some(&schema))
+            .expect_err("should fail due to type mismatch on balance");
+        assert!(
+            err.to_string().contains(r#"in attribute `balance` on `Wallet::"alice"`, type mismatch: attribute was expected to have type u256, but actually has type decimal"#),
+            "actual error message was {err}"
+        );
+    }
+
+    /// A `u256` context attribute parsed with a schema is written back with
+    /// the `__extn` escape, and so parses to the same value without one
+    #[test]
+    #[cfg(feature = "u256")]
+    fn u256_context_roundtrip() {
+        let schema = Schema::from_json_value(json!(
+        {"": {
+            "entityTypes": {},
+            "actions": {
+                "transfer": {
+                    "appliesTo": {
+                        "context": {
+                            "type": "Record",
+                            "attributes": {
+                                "amount": { "type": "Extension", "name": "u256" },
+                                "fees": { "type": "Set", "element": { "type": "Extension", "name": "u256" } }
+                            }
+                        }
+                    }
+                }
+            }
+        }}
+        ))
+        .expect("should be a valid schema");
+        let action = EntityUid::from_strs("Action", "transfer");
+        let context = Context::from_json_value(
+            json!({ "amount": "1000000000000000000000", "fees": [21000] }),
+            Some((&schema, &action)),
+        )
+        .expect("should parse without error");
+        let json = context
+            .to_json_value()
+            .expect("should serialize without error");
+        assert_eq!(
+            json,
+            json!({
+                "amount": { "__extn": { "fn": "u256", "arg": "1000000000000000000000" } },
+                "fees": [{ "__extn": { "fn": "u256", "arg": "21000" } }]
+            })
+        );
+        let reparsed =
+            Context::from_json_value(json.clone(), None).expect("should parse without error");
+        assert_eq!(
+            reparsed
+                .to_json_value()
+                .expect("should serialize without error"),
+            json
+        );
+        let request = Request::new(None, Some(action), None, reparsed);
+        assert_eq!(
+            eval_expression(
+                &request,
+                &Entities::empty(),
+                &Expression::from_str("context.amount").unwrap()
+            )
+            .unwrap(),
+            EvalResult::ExtensionValue("1000000000000000000000".into())
+        );
+    }
+
     #[test]
     fn schema_sanity_check() {
         let src = "{ , .. }";
@@ -4862,4 +7051,357 @@ mod schema_based_parsing_tests {
             .unwrap()
         );
     }
+
+    /// Test that the parents an entity type derives from attribute predicates
+    /// in the schema are added when entities are parsed
+    #[test]
+    fn derived_parents() {
+        let schema = Schema::from_json_value(json!(
+        {"": {
+            "entityTypes": {
+                "Wallet": {
+                    "shape": {
+                        "type": "Record",
+                        "attributes": {
+                            "kycLevel": { "type": "Long" }
+                        }
+                    },
+                    "derivedParents": [
+                        {
+                            "parent": { "type": "Group", "id": "kyc2" },
+                            "attribute": "kycLevel",
+                            "op": ">=",
+                            "value": 2
+                        }
+                    ]
+                },
+                "Group": {}
+            },
+            "actions": {
+                "view": { }
+            }
+        }}
+        ))
+        .expect("should be a valid schema");
+        let entitiesjson = json!(
+            [
+                {
+                    "uid": { "type": "Wallet", "id": "alice" },
+                    "attrs": { "kycLevel": 3 },
+                    "parents": []
+                },
+                {
+                    "uid": { "type": "Wallet", "id": "bob" },
+                    "attrs": { "kycLevel": 1 },
+                    "parents": []
+                }
+            ]
+        );
+        let entities = Entities::from_json_value(entitiesjson, Some(&schema))
+            .expect("Should parse without error");
+        let kyc2 = EntityUid::from_strs("Group", "kyc2");
+        let alice_ancestors = entities
+            .ancestors(&EntityUid::from_strs("Wallet", "alice"))
+            .expect("alice should exist")
+            .collect::<HashSet<_>>();
+        assert_eq!(alice_ancestors, HashSet::from([&kyc2]));
+        let bob_ancestors = entities
+            .ancestors(&EntityUid::from_strs("Wallet", "bob"))
+            .expect("bob should exist")
+            .count();
+        assert_eq!(bob_ancestors, 0);
+    }
+}
+
+#[cfg(test)]
+mod schema_request_builder_tests {
+    use super::*;
+    use cool_asserts::assert_matches;
+    use serde_json::json;
+
+    fn schema() -> Schema {
+        Schema::from_json_value(json!(
+        {"": {
+            "entityTypes": {
+                "Account": {},
+                "Token": {},
+                "Vault": {}
+            },
+            "actions": {
+                "transfer": {
+                    "appliesTo": {
+                        "principalTypes": ["Account"],
+                        "resourceTypes": ["Token"],
+                        "context": {
+                            "type": "Record",
+                            "attributes": {
+                                "amount": { "type": "Extension", "name": "u256" },
+                                "memo": { "type": "String", "required": false },
+                                "recipient": { "type": "Entity", "name": "Account" }
+                            }
+                        }
+                    }
+                }
+            }
+        }}
+        ))
+        .expect("should be a valid schema")
+    }
+
+    fn transfer() -> EntityUid {
+        EntityUid::from_strs("Action", "transfer")
+    }
+
+    #[test]
+    fn builds_with_converted_context() {
+        let schema = schema();
+        let request = SchemaRequestBuilder::new(&schema, transfer())
+            .and_then(|b| b.principal(EntityUid::from_strs("Account", "alice")))
+            .and_then(|b| b.resource(EntityUid::from_strs("Token", "usdc")))
+            .and_then(|b| b.context_attr("amount", json!("1000000")))
+            .and_then(|b| b.context_attr("recipient", json!({ "type": "Account", "id": "bob" })))
+            .and_then(SchemaRequestBuilder::build)
+            .expect("request should be valid");
+        let amount = eval_expression(
+            &request,
+            &Entities::empty(),
+            &Expression::from_str("context.amount").unwrap(),
+        );
+        assert_matches!(amount, Ok(EvalResult::ExtensionValue(_)));
+        let recipient = eval_expression(
+            &request,
+            &Entities::empty(),
+            &Expression::from_str("context.recipient").unwrap(),
+        );
+        assert_eq!(
+            recipient.unwrap(),
+            EvalResult::EntityUid(EntityUid::from_strs("Account", "bob"))
+        );
+    }
+
+    #[test]
+    fn undeclared_action() {
+        let schema = schema();
+        assert_matches!(
+            SchemaRequestBuilder::new(&schema, EntityUid::from_strs("Action", "burn")),
+            Err(RequestValidationError::UndeclaredAction { .. })
+        );
+    }
+
+    #[test]
+    fn wrong_head_types() {
+        let schema = schema();
+        let builder = SchemaRequestBuilder::new(&schema, transfer()).unwrap();
+        assert_matches!(
+            builder.principal(EntityUid::from_strs("Ghost", "alice")),
+            Err(RequestValidationError::UndeclaredEntityType {
+                component: RequestComponent::Principal,
+                ..
+            })
+        );
+        let builder = SchemaRequestBuilder::new(&schema, transfer()).unwrap();
+        assert_matches!(
+            builder.resource(EntityUid::from_strs("Vault", "v")),
+            Err(RequestValidationError::InapplicableEntityType {
+                component: RequestComponent::Resource,
+                ..
+            })
+        );
+    }
+
+    #[test]
+    fn bad_context_attrs() {
+        let schema = schema();
+        let builder = SchemaRequestBuilder::new(&schema, transfer()).unwrap();
+        assert_matches!(
+            builder.context_attr("fee", json!(1)),
+            Err(RequestValidationError::UndeclaredContextAttr { attr, .. }) if attr == "fee"
+        );
+        let builder = SchemaRequestBuilder::new(&schema, transfer()).unwrap();
+        assert_matches!(
+            builder.context_attr("memo", json!(7)),
+            Err(RequestValidationError::InvalidContextAttr { attr, .. }) if attr == "memo"
+        );
+        let builder = SchemaRequestBuilder::new(&schema, transfer()).unwrap();
+        assert_matches!(
+            builder.context_attr("amount", json!("lots")),
+            Err(RequestValidationError::ContextAttrEvaluation { attr, .. }) if attr == "amount"
+        );
+    }
+
+    #[test]
+    fn open_context() {
+        let schema = schema().with_context_mode(ContextMode::Open);
+        let builder = SchemaRequestBuilder::new(&schema, transfer())
+            .and_then(|b| b.context_attr("fee", json!(1)))
+            .unwrap();
+        assert_matches!(
+            builder.context_attr("memo", json!(7)),
+            Err(RequestValidationError::InvalidContextAttr { attr, .. }) if attr == "memo"
+        );
+    }
+
+    #[test]
+    fn missing_components() {
+        let schema = schema();
+        let result = SchemaRequestBuilder::new(&schema, transfer())
+            .and_then(|b| b.principal(EntityUid::from_strs("Account", "alice")))
+            .and_then(|b| b.resource(EntityUid::from_strs("Token", "usdc")))
+            .and_then(|b| b.context_attr("amount", json!("1")))
+            .and_then(SchemaRequestBuilder::build);
+        assert_matches!(
+            result,
+            Err(RequestValidationError::MissingContextAttr { attr, .. }) if attr == "recipient"
+        );
+        let result = SchemaRequestBuilder::new(&schema, transfer())
+            .and_then(|b| b.resource(EntityUid::from_strs("Token", "usdc")))
+            .and_then(|b| b.context_attr("amount", json!("1")))
+            .and_then(|b| b.context_attr("recipient", json!({ "type": "Account", "id": "bob" })))
+            .and_then(SchemaRequestBuilder::build);
+        assert_matches!(
+            result,
+            Err(RequestValidationError::MissingComponent {
+                component: RequestComponent::Principal
+            })
+        );
+    }
+
+    #[test]
+    fn context_normalization() {
+        let schema = Schema::from_json_value(json!(
+        {"": {
+            "entityTypes": {},
+            "actions": {
+                "transfer": {
+                    "appliesTo": {
+                        "context": {
+                            "type": "Record",
+                            "attributes": {
+                                "chainId": { "type": "Long" },
+                                "amount": { "type": "Extension", "name": "u256" }
+                            }
+                        },
+                        "contextNormalization": {
+                            "defaults": { "chainId": 1 },
+                            "coerce": ["amount"]
+                        }
+                    }
+                }
+            }
+        }}
+        ))
+        .expect("should be a valid schema");
+        assert_eq!(
+            schema
+                .normalize_context(&transfer(), json!({ "amount": 5 }))
+                .unwrap(),
+            json!({ "chainId": 1, "amount": "5" })
+        );
+        assert_matches!(
+            schema.normalize_context(&EntityUid::from_strs("Action", "burn"), json!({})),
+            Err(ContextJsonError::MissingAction { .. })
+        );
+        let request = SchemaRequestBuilder::new(&schema, transfer())
+            .and_then(|b| b.context_attr("amount", json!(5)))
+            .and_then(SchemaRequestBuilder::build)
+            .expect("request should be valid");
+        let chain_id = eval_expression(
+            &request,
+            &Entities::empty(),
+            &Expression::from_str("context.chainId").unwrap(),
+        );
+        assert_eq!(chain_id.unwrap(), EvalResult::Long(1));
+    }
+}
+
+#[cfg(test)]
+mod canonical_hash_tests {
+    use super::*;
+
+    fn request(context: &str) -> Request {
+        Request::new(
+            Some(EntityUid::from_strs("Wallet", "alice")),
+            Some(EntityUid::from_strs("Action", "transfer")),
+            None,
+            Context::from_pairs([(
+                "args".to_string(),
+                RestrictedExpression::from_str(context).unwrap(),
+            )]),
+        )
+    }
+
+    #[test]
+    fn canonical_bytes() {
+        let request =
+            request(r#"{ to: Wallet::"bob", amount: u256("1000"), tags: ["b", "a", "b"] }"#);
+        assert_eq!(
+            String::from_utf8(request.canonical_bytes()).unwrap(),
+            concat!(
+                r#"{"action":{"__entity":{"id":"transfer","type":"Action"}},"#,
+                r#""context":{"args":{"amount":{"__extn":{"arg":"1000","fn":"u256"}},"#,
+                r#""tags":["a","b"],"to":{"__entity":{"id":"bob","type":"Wallet"}}}},"#,
+                r#""principal":{"__entity":{"id":"alice","type":"Wallet"}},"#,
+                r#""resource":null}"#,
+            )
+        );
+        assert_eq!(request.canonical_hash().len(), 32);
+    }
+
+    #[test]
+    fn equal_requests_have_equal_hashes() {
+        assert_eq!(
+            request("{ a: 1, b: [1, 2, 2] }").canonical_hash(),
+            request("{ b: [2, 1], a: 1 }").canonical_hash()
+        );
+        assert_ne!(
+            request(r#"u256("1")"#).canonical_hash(),
+            request(r#"u256("2")"#).canonical_hash()
+        );
+        assert_ne!(
+            request(r#"u256("1")"#).canonical_hash(),
+            request(r#""1""#).canonical_hash()
+        );
+        // a record which looks like an escape isn't mistaken for one
+        assert_ne!(
+            request(r#"Wallet::"bob""#).canonical_hash(),
+            request(r#"{ __entity: { id: "bob", type: "Wallet" } }"#).canonical_hash()
+        );
+    }
+}
+
+#[cfg(test)]
+mod eval_expression_tests {
+    use super::*;
+
+    fn eval_typed(src: &str) -> (EvalResult, String) {
+        let request = Request::new(None, None, None, Context::empty());
+        eval_expression_with_type(
+            &request,
+            &Entities::empty(),
+            &Expression::from_str(src).expect("expression should parse"),
+        )
+        .expect("expression should evaluate")
+    }
+
+    #[test]
+    fn describes_types() {
+        assert_eq!(eval_typed("1 + 2"), (EvalResult::Long(3), "Long".into()));
+        assert_eq!(eval_typed(r#"User::"alice""#).1, "User");
+        assert_eq!(eval_typed("[1, 2]").1, "Set<Long>");
+        assert_eq!(eval_typed("[]").1, "Set");
+        assert_eq!(
+            eval_typed(r#"{ b: [true], a: "x" }"#).1,
+            "{ a: String, b: Set<Boolean> }"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "u256")]
+    fn describes_extension_types() {
+        assert_eq!(eval_typed(r#"u256("1000")"#).1, "u256");
+        assert_eq!(
+            eval_typed(r#"u256("1").u256LessThan(u256("2"))"#),
+            (EvalResult::Bool(true), "Boolean".into())
+        );
+    }
 }
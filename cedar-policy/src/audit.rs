@@ -0,0 +1,568 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Audit records of authorization decisions.
+//!
+//! An [`Authorizer`](crate::Authorizer) configured with
+//! [`Authorizer::with_audit_sink()`](crate::Authorizer::with_audit_sink)
+//! passes an [`AuditRecord`] to the sink after every call to
+//! `is_authorized()`. Records identify the request and the policy set by
+//! digests, so a record can later be checked against the request and
+//! policies it claims to describe.
+//!
+//! With [`Authorizer::with_audit_detail()`](crate::Authorizer::with_audit_detail),
+//! records can also capture the request itself and the entities it was
+//! answered with, so that decisions can be replayed later with
+//! [`crate::replay`].
+
+use std::fmt::Debug;
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use cedar_policy_core::ast;
+use ref_cast::RefCast;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::shadow::{ShadowOutcome, ShadowSummary};
+use crate::{Context, Decision, Entities, EntityUid, PolicyId, PolicySet, Request, Response};
+
+/// Everything recorded about one authorization decision
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditRecord {
+    /// Hex-encoded digest of the request, as computed by [`request_digest()`]
+    pub request_hash: String,
+    /// The decision
+    pub decision: Decision,
+    /// Ids of the policies which determined the decision, sorted
+    pub determining_policies: Vec<String>,
+    /// Hex-encoded digest of the policy set, as computed by
+    /// [`policy_set_digest()`]
+    pub policy_set_hash: String,
+    /// Time taken to reach the decision
+    #[serde(
+        rename = "durationMicros",
+        serialize_with = "serialize_micros",
+        deserialize_with = "deserialize_micros"
+    )]
+    pub duration: Duration,
+    /// Extension values in the request context and the determining policies,
+    /// e.g. `u256("1000")`, sorted and without duplicates
+    pub extension_values: Vec<String>,
+    /// The request, if recorded with [`AuditDetail::Requests`] or
+    /// [`AuditDetail::RequestsAndEntities`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request: Option<RecordedRequest>,
+    /// The entities, in the entities JSON format, if recorded with
+    /// [`AuditDetail::RequestsAndEntities`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entities: Option<serde_json::Value>,
+    /// What the shadow policies would have decided, if any were evaluated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shadow: Option<ShadowSummary>,
+    /// The tenant the decision was made for, if recorded through a
+    /// [`TenantAuditSink`](crate::tenant::TenantAuditSink)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+}
+
+// `Duration` doesn't implement `Serialize` in a format that's useful in logs
+fn serialize_micros<S: serde::Serializer>(d: &Duration, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_u128(d.as_micros())
+}
+
+fn deserialize_micros<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Duration, D::Error> {
+    u64::deserialize(d).map(Duration::from_micros)
+}
+
+/// How much an [`AuditRecord`] captures besides digests
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AuditDetail {
+    /// Only digests of the request and policy set
+    #[default]
+    Digests,
+    /// Also the request
+    Requests,
+    /// Also the request and the entities. Entity sets can be large, so this
+    /// is best kept for low-volume or high-value decisions.
+    RequestsAndEntities,
+}
+
+/// A request as captured in an [`AuditRecord`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    /// The principal, e.g. `User::"alice"`, or `None` if it was unknown
+    pub principal: Option<String>,
+    /// The action, or `None` if it was unknown
+    pub action: Option<String>,
+    /// The resource, or `None` if it was unknown
+    pub resource: Option<String>,
+    /// The context, as a JSON object
+    pub context: serde_json::Value,
+}
+
+impl RecordedRequest {
+    /// Capture `request`. Returns `None` if its context can't be expressed
+    /// in JSON, e.g. because it contains unknowns.
+    pub fn new(request: &Request) -> Option<Self> {
+        let entry = |entry: &ast::EntityUIDEntry| match entry {
+            ast::EntityUIDEntry::Concrete(uid) => Some(uid.to_string()),
+            ast::EntityUIDEntry::Unknown => None,
+        };
+        let context = match request.0.context() {
+            Some(context) => {
+                let expr: &ast::RestrictedExpr = context.as_ref();
+                let json =
+                    cedar_policy_core::entities::JSONValue::from_expr(expr.as_borrowed()).ok()?;
+                serde_json::to_value(json).ok()?
+            }
+            None => serde_json::Value::Object(serde_json::Map::new()),
+        };
+        Some(Self {
+            principal: entry(request.0.principal()),
+            action: entry(request.0.action()),
+            resource: entry(request.0.resource()),
+            context,
+        })
+    }
+
+    /// Rebuild the request
+    pub fn to_request(&self) -> Result<Request, String> {
+        let uid = |uid: &Option<String>| {
+            uid.as_deref()
+                .map(|uid| EntityUid::from_str(uid).map_err(|e| format!("`{uid}`: {e}")))
+                .transpose()
+        };
+        let context = Context::from_json_value(self.context.clone(), None)
+            .map_err(|e| format!("context: {e}"))?;
+        Ok(Request::new(
+            uid(&self.principal)?,
+            uid(&self.action)?,
+            uid(&self.resource)?,
+            context,
+        ))
+    }
+}
+
+impl AuditRecord {
+    /// Build the record of answering `request` against `policies` with
+    /// `response`, which took `duration`
+    pub fn new(
+        request: &Request,
+        policies: &PolicySet,
+        response: &Response,
+        duration: Duration,
+    ) -> Self {
+        let reason = response.diagnostics().reason().collect::<Vec<_>>();
+        let mut determining_policies: Vec<_> = reason.iter().map(ToString::to_string).collect();
+        determining_policies.sort();
+
+        let context_exprs = request.0.context().map(|context| {
+            let expr: &ast::RestrictedExpr = context.as_ref();
+            AsRef::<ast::Expr>::as_ref(expr).clone()
+        });
+        let policy_exprs = policies
+            .ast
+            .policies()
+            .filter(|p| reason.contains(&PolicyId::ref_cast(p.id())))
+            .map(ast::Policy::condition);
+        let mut extension_values: Vec<_> = context_exprs
+            .into_iter()
+            .chain(policy_exprs)
+            .flat_map(|expr| extension_values(&expr))
+            .collect();
+        extension_values.sort();
+        extension_values.dedup();
+
+        Self {
+            request_hash: request_digest(request),
+            decision: response.decision(),
+            determining_policies,
+            policy_set_hash: policy_set_digest(policies),
+            duration,
+            extension_values,
+            request: None,
+            entities: None,
+            shadow: response.diagnostics().shadow().map(ShadowOutcome::summary),
+            tenant: None,
+        }
+    }
+
+    /// Also capture `request`, if it can be expressed in JSON
+    #[must_use]
+    pub fn with_request(mut self, request: &Request) -> Self {
+        self.request = RecordedRequest::new(request);
+        self
+    }
+
+    /// Also capture `entities`, if they can be expressed in JSON
+    #[must_use]
+    pub fn with_entities(mut self, entities: &Entities) -> Self {
+        self.entities = entities.0.to_json_value().ok();
+        self
+    }
+
+    /// Capture as much as `detail` asks for
+    pub(crate) fn with_detail(
+        self,
+        detail: AuditDetail,
+        request: &Request,
+        entities: &Entities,
+    ) -> Self {
+        match detail {
+            AuditDetail::Digests => self,
+            AuditDetail::Requests => self.with_request(request),
+            AuditDetail::RequestsAndEntities => self.with_request(request).with_entities(entities),
+        }
+    }
+}
+
+/// Extension values constructed from literals in `expr`
+fn extension_values(expr: &ast::Expr) -> Vec<String> {
+    expr.subexpressions()
+        .filter(|e| match e.expr_kind() {
+            ast::ExprKind::ExtensionFunctionApp { args, .. } => args
+                .iter()
+                .all(|arg| matches!(arg.expr_kind(), ast::ExprKind::Lit(_))),
+            _ => false,
+        })
+        .map(ToString::to_string)
+        .collect()
+}
+
+/// Hex-encoded digest of a request, as computed by
+/// [`Request::canonical_hash()`].
+///
+/// The digest covers the principal, action, resource, and context, and
+/// doesn't depend on the order of the context's attributes.
+pub fn request_digest(request: &Request) -> String {
+    to_hex(&request.canonical_hash())
+}
+
+/// Hex-encoded SHA-256 digest of a policy set. The digest covers the id and
+/// normalized text of every policy, so it doesn't change if policies are
+/// reformatted or added in a different order.
+pub fn policy_set_digest(policies: &PolicySet) -> String {
+    let mut texts: Vec<_> = policies
+        .policies()
+        .map(|p| (p.id().to_string(), p.to_string()))
+        .collect();
+    texts.sort();
+    let mut hasher = Sha256::new();
+    for (id, text) in texts {
+        // NUL can't appear in either, so it separates them unambiguously
+        hasher.update(id.as_bytes());
+        hasher.update([0]);
+        hasher.update(text.as_bytes());
+        hasher.update([0]);
+    }
+    to_hex(&hasher.finalize())
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    use std::fmt::Write as _;
+    bytes.iter().fold(String::new(), |mut hex, b| {
+        // writing to a `String` can't fail
+        let _ = write!(hex, "{b:02x}");
+        hex
+    })
+}
+
+#[cfg(any(feature = "signing", feature = "eth-rpc", feature = "encryption"))]
+pub(crate) fn unhex(s: &str) -> Option<Vec<u8>> {
+    if s.len() % 2 != 0 {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// `address` in lowercase, if it is a 20-byte hex address with a `0x` prefix
+pub(crate) fn normalize_address(address: &str) -> Option<String> {
+    let hex = address.strip_prefix("0x")?;
+    (hex.len() == 40 && hex.bytes().all(|b| b.is_ascii_hexdigit()))
+        .then(|| format!("0x{}", hex.to_ascii_lowercase()))
+}
+
+pub(crate) fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_secs()
+}
+
+/// Receives a record of every authorization decision made by an
+/// [`Authorizer`](crate::Authorizer). Sinks are called synchronously, on the
+/// thread that made the decision, and must handle their own errors.
+pub trait AuditSink: Debug + Send + Sync {
+    /// Record one decision
+    fn record(&self, record: &AuditRecord);
+}
+
+/// Writes each record as one line of JSON
+#[derive(Debug)]
+pub struct JsonLinesAuditSink<W: Write + Send + Debug> {
+    inner: Mutex<JsonLinesState<W>>,
+}
+
+#[derive(Debug)]
+struct JsonLinesState<W> {
+    writer: W,
+    error: Option<std::io::Error>,
+}
+
+impl<W: Write + Send + Debug> JsonLinesAuditSink<W> {
+    /// Write records to `writer`, e.g. a file opened for appending
+    pub fn new(writer: W) -> Self {
+        Self {
+            inner: Mutex::new(JsonLinesState {
+                writer,
+                error: None,
+            }),
+        }
+    }
+
+    /// Take the first error encountered writing records since the last call,
+    /// if any. Records are still attempted after an error.
+    pub fn take_error(&self) -> Option<std::io::Error> {
+        self.inner.lock().ok()?.error.take()
+    }
+
+    /// Stop recording and return the writer
+    pub fn into_inner(self) -> W {
+        match self.inner.into_inner() {
+            Ok(state) => state.writer,
+            Err(poisoned) => poisoned.into_inner().writer,
+        }
+    }
+}
+
+impl<W: Write + Send + Debug> AuditSink for JsonLinesAuditSink<W> {
+    fn record(&self, record: &AuditRecord) {
+        let Ok(mut state) = self.inner.lock() else {
+            return;
+        };
+        let result = serde_json::to_writer(&mut state.writer, record)
+            .map_err(std::io::Error::from)
+            .and_then(|()| state.writer.write_all(b"\n"))
+            .and_then(|()| state.writer.flush());
+        if let Err(err) = result {
+            state.error.get_or_insert(err);
+        }
+    }
+}
+
+/// Emits each record as an OpenTelemetry span, using the global tracer
+/// provider. The span ends when the record is made and starts the decision's
+/// duration earlier.
+#[cfg(feature = "opentelemetry")]
+#[derive(Debug, Clone)]
+pub struct OpenTelemetryAuditSink {
+    tracer_name: std::borrow::Cow<'static, str>,
+}
+
+#[cfg(feature = "opentelemetry")]
+impl OpenTelemetryAuditSink {
+    /// Emit spans from the tracer named `tracer_name`
+    pub fn new(tracer_name: impl Into<std::borrow::Cow<'static, str>>) -> Self {
+        Self {
+            tracer_name: tracer_name.into(),
+        }
+    }
+}
+
+#[cfg(feature = "opentelemetry")]
+impl Default for OpenTelemetryAuditSink {
+    fn default() -> Self {
+        Self::new("cedar-policy")
+    }
+}
+
+#[cfg(feature = "opentelemetry")]
+impl AuditSink for OpenTelemetryAuditSink {
+    fn record(&self, record: &AuditRecord) {
+        use opentelemetry::trace::{Span, Tracer};
+        use opentelemetry::{Array, KeyValue, StringValue, Value};
+
+        let strings = |values: &[String]| {
+            Value::Array(Array::String(
+                values.iter().cloned().map(StringValue::from).collect(),
+            ))
+        };
+        let end = std::time::SystemTime::now();
+        let tracer = opentelemetry::global::tracer(self.tracer_name.clone());
+        let mut attributes = vec![
+            KeyValue::new("cedar.request_hash", record.request_hash.clone()),
+            KeyValue::new(
+                "cedar.decision",
+                match record.decision {
+                    Decision::Allow => "Allow",
+                    Decision::Deny => "Deny",
+                },
+            ),
+            KeyValue::new(
+                "cedar.determining_policies",
+                strings(&record.determining_policies),
+            ),
+            KeyValue::new("cedar.policy_set_hash", record.policy_set_hash.clone()),
+            KeyValue::new("cedar.extension_values", strings(&record.extension_values)),
+        ];
+        if let Some(tenant) = &record.tenant {
+            attributes.push(KeyValue::new("cedar.tenant", tenant.clone()));
+        }
+        let mut span = tracer
+            .span_builder("cedar.authorize")
+            .with_start_time(end - record.duration)
+            .with_attributes(attributes)
+            .start(&tracer);
+        span.end_with_timestamp(end);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Authorizer, Context, Entities, EntityUid, RestrictedExpression};
+    use std::str::FromStr;
+    use std::sync::Arc;
+
+    /// Keeps records in memory
+    #[derive(Debug, Default)]
+    struct MemorySink(Mutex<Vec<AuditRecord>>);
+
+    impl AuditSink for MemorySink {
+        fn record(&self, record: &AuditRecord) {
+            self.0.lock().unwrap().push(record.clone());
+        }
+    }
+
+    fn request(amount: &str) -> Request {
+        Request::new(
+            Some(EntityUid::from_strs("User", "alice")),
+            Some(EntityUid::from_strs("Action", "transfer")),
+            Some(EntityUid::from_strs("Wallet", "w")),
+            Context::from_pairs([
+                (
+                    "amount".to_string(),
+                    RestrictedExpression::from_str(&format!(r#"decimal("{amount}")"#)).unwrap(),
+                ),
+                (
+                    "memo".to_string(),
+                    RestrictedExpression::from_str(r#""rent""#).unwrap(),
+                ),
+            ]),
+        )
+    }
+
+    #[test]
+    #[cfg(feature = "decimal")]
+    fn records_decisions() {
+        let policies = PolicySet::from_str(
+            r#"permit(principal, action, resource)
+               when { context.amount.lessThan(decimal("10.0")) };
+               permit(principal == User::"bob", action, resource);"#,
+        )
+        .unwrap();
+        let sink = Arc::new(MemorySink::default());
+        let authorizer = Authorizer::new().with_audit_sink(sink.clone());
+
+        let response = authorizer.is_authorized(&request("1.5"), &policies, &Entities::empty());
+        assert_eq!(response.decision(), Decision::Allow);
+        authorizer.is_authorized(&request("20.0"), &policies, &Entities::empty());
+
+        let records = sink.0.lock().unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].decision, Decision::Allow);
+        assert_eq!(records[0].determining_policies, ["policy0"]);
+        assert_eq!(
+            records[0].extension_values,
+            [r#"decimal("1.5")"#, r#"decimal("10.0")"#]
+        );
+        assert_eq!(records[1].decision, Decision::Deny);
+        assert!(records[1].determining_policies.is_empty());
+        assert_eq!(records[1].extension_values, [r#"decimal("20.0")"#]);
+        assert_ne!(records[0].request_hash, records[1].request_hash);
+        assert_eq!(records[0].policy_set_hash, records[1].policy_set_hash);
+        assert_eq!(records[0].policy_set_hash, policy_set_digest(&policies));
+    }
+
+    #[test]
+    fn digests_are_canonical() {
+        let request = |pairs: Vec<(&str, &str)>| {
+            Request::new(
+                Some(EntityUid::from_strs("User", "alice")),
+                None,
+                None,
+                Context::from_pairs(
+                    pairs
+                        .into_iter()
+                        .map(|(k, v)| (k.to_string(), RestrictedExpression::from_str(v).unwrap())),
+                ),
+            )
+        };
+        assert_eq!(
+            request_digest(&request(vec![("a", "1"), ("b", "true")])),
+            request_digest(&request(vec![("b", "true"), ("a", "1")]))
+        );
+        assert_ne!(
+            request_digest(&request(vec![("a", "1")])),
+            request_digest(&request(vec![("a", "2")]))
+        );
+        assert_eq!(request_digest(&request(vec![])).len(), 64);
+
+        let reformatted = PolicySet::from_str(
+            "permit(principal, action, resource)\n  when { 1 < 2 };\nforbid(principal, action, resource);",
+        )
+        .unwrap();
+        let original = PolicySet::from_str(
+            "permit(principal,action,resource) when {1<2}; forbid(principal, action, resource);",
+        )
+        .unwrap();
+        assert_eq!(
+            policy_set_digest(&reformatted),
+            policy_set_digest(&original)
+        );
+        let changed =
+            PolicySet::from_str("permit(principal, action, resource) when { 1 < 3 }; forbid(principal, action, resource);")
+                .unwrap();
+        assert_ne!(policy_set_digest(&changed), policy_set_digest(&original));
+    }
+
+    #[test]
+    fn writes_json_lines() {
+        let sink = JsonLinesAuditSink::new(Vec::new());
+        let policies = PolicySet::from_str("permit(principal, action, resource);").unwrap();
+        let request = Request::new(None, None, None, Context::empty());
+        let response = Authorizer::new().is_authorized(&request, &policies, &Entities::empty());
+        let record = AuditRecord::new(&request, &policies, &response, Duration::from_micros(42));
+        sink.record(&record);
+        sink.record(&record);
+        assert!(sink.take_error().is_none());
+
+        let output = String::from_utf8(sink.into_inner()).unwrap();
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(lines.len(), 2);
+        let json: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(json["decision"], "Allow");
+        assert_eq!(json["determiningPolicies"], serde_json::json!(["policy0"]));
+        assert_eq!(json["durationMicros"], 42);
+        assert_eq!(json["requestHash"], record.request_hash);
+    }
+}
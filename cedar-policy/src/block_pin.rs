@@ -0,0 +1,209 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Pinning of on-chain reads to one block.
+//!
+//! Providers which read from a chain node read the latest block by default,
+//! so the reads made for one authorization can see different states if a
+//! block is mined, or a reorg replaces one, between them. A [`BlockPin`]
+//! names a block by its number and hash. Given one, a provider reads every
+//! value at that block number, then checks that the block with that number
+//! still has that hash, failing with [`BlockPinError::Reorged`] if a reorg
+//! replaced it.
+//!
+//! [`config::ConfigResolver::with_block_pin()`] takes pins, as do
+//! `domain::governance::GovernanceProvider::entities_at()` and
+//! `domain::role_sync::RoleMirror::sync_to()` with the `domain` feature.
+//!
+//! [`config::ConfigResolver::with_block_pin()`]: crate::config::ConfigResolver::with_block_pin
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Errors reading pinned blocks
+#[derive(Debug, Error)]
+pub enum BlockPinError {
+    /// The block with the pinned number no longer has the pinned hash
+    #[error("block {number} is now {found}, not the pinned {expected}: it was reorged")]
+    Reorged {
+        /// The number of the block
+        number: u64,
+        /// The pinned hash
+        expected: String,
+        /// The hash of the block with that number now
+        found: String,
+    },
+    /// The node doesn't have a block with the pinned number
+    #[error("unknown block {0}")]
+    UnknownBlock(u64),
+    /// The node failed
+    #[error("failed to read block {number}: {message}")]
+    Node {
+        /// The number of the block
+        number: u64,
+        /// What went wrong
+        message: String,
+    },
+}
+
+/// A block, by its number and hash, which on-chain reads are pinned to
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct BlockPin {
+    /// The number of the block
+    pub number: u64,
+    /// The hash of the block, as `0x`-prefixed hex
+    pub hash: String,
+}
+
+impl BlockPin {
+    /// The block numbered `number` with hash `hash`
+    pub fn new(number: u64, hash: impl Into<String>) -> Self {
+        Self {
+            number,
+            hash: hash.into(),
+        }
+    }
+
+    /// The block number as a JSON-RPC block parameter
+    pub fn block_tag(&self) -> String {
+        format!("{:#x}", self.number)
+    }
+
+    /// Check that `hash`, the hash of the block with the pinned number now,
+    /// is the pinned hash
+    pub fn check(&self, hash: &str) -> Result<(), BlockPinError> {
+        if hash.eq_ignore_ascii_case(&self.hash) {
+            Ok(())
+        } else {
+            Err(BlockPinError::Reorged {
+                number: self.number,
+                expected: self.hash.clone(),
+                found: hash.to_string(),
+            })
+        }
+    }
+}
+
+#[cfg(any(feature = "eth-rpc", feature = "governor"))]
+pub(crate) mod rpc {
+    use super::{BlockPin, BlockPinError};
+    use serde::Deserialize;
+    use serde_json::json;
+
+    /// A JSON-RPC response
+    #[derive(Debug, Deserialize)]
+    struct RpcResponse {
+        result: Option<Block>,
+        error: Option<RpcError>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct RpcError {
+        message: String,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Block {
+        hash: String,
+    }
+
+    /// The hash of block `number`, read from the JSON-RPC node at `rpc_url`
+    pub fn block_hash(
+        client: &reqwest::blocking::Client,
+        rpc_url: &str,
+        number: u64,
+    ) -> Result<String, BlockPinError> {
+        let body = client
+            .post(rpc_url)
+            .json(&json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "eth_getBlockByNumber",
+                "params": [format!("{number:#x}"), false],
+            }))
+            .send()
+            .and_then(reqwest::blocking::Response::error_for_status)
+            .and_then(reqwest::blocking::Response::text)
+            .map_err(|err| BlockPinError::Node {
+                number,
+                message: err.to_string(),
+            })?;
+        parse_block_hash(number, &body)
+    }
+
+    /// Check `pin` against the JSON-RPC node at `rpc_url`
+    pub fn check(
+        client: &reqwest::blocking::Client,
+        rpc_url: &str,
+        pin: &BlockPin,
+    ) -> Result<(), BlockPinError> {
+        pin.check(&block_hash(client, rpc_url, pin.number)?)
+    }
+
+    /// The hash in an `eth_getBlockByNumber` response `body` for block
+    /// `number`
+    pub fn parse_block_hash(number: u64, body: &str) -> Result<String, BlockPinError> {
+        let node = |message: String| BlockPinError::Node { number, message };
+        let response: RpcResponse =
+            serde_json::from_str(body).map_err(|err| node(err.to_string()))?;
+        if let Some(error) = response.error {
+            return Err(node(error.message));
+        }
+        response
+            .result
+            .map(|block| block.hash)
+            .ok_or(BlockPinError::UnknownBlock(number))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const HASH: &str = "0x88e96d4537bea4d9c05d12549907b32561d3bf31f45aae734cdc119f13406cb6";
+
+    #[test]
+    fn check() {
+        let pin = BlockPin::new(1, HASH);
+        assert_eq!(pin.block_tag(), "0x1");
+        pin.check(&HASH.to_ascii_uppercase().replace("0X", "0x"))
+            .unwrap();
+        assert!(matches!(
+            pin.check("0xb495a1d7e6663152ae92708da4843337b958146015a2802f4193a410044698c9"),
+            Err(BlockPinError::Reorged { number: 1, .. })
+        ));
+    }
+
+    #[cfg(any(feature = "eth-rpc", feature = "governor"))]
+    #[test]
+    fn rpc_responses() {
+        let body = format!(
+            r#"{{"jsonrpc": "2.0", "id": 1, "result": {{"number": "0x1", "hash": "{HASH}"}}}}"#
+        );
+        assert_eq!(rpc::parse_block_hash(1, &body).unwrap(), HASH);
+        assert!(matches!(
+            rpc::parse_block_hash(2, r#"{"jsonrpc": "2.0", "id": 1, "result": null}"#),
+            Err(BlockPinError::UnknownBlock(2))
+        ));
+        assert!(matches!(
+            rpc::parse_block_hash(
+                1,
+                r#"{"jsonrpc": "2.0", "id": 1, "error": {"code": -32000, "message": "header not found"}}"#
+            ),
+            Err(BlockPinError::Node { number: 1, .. })
+        ));
+    }
+}
@@ -0,0 +1,284 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Caching of authorization decisions.
+//!
+//! The same request is often checked repeatedly, e.g. one approval checked
+//! by several services. An [`Authorizer`](crate::Authorizer) given a
+//! [`DecisionCache`] with
+//! [`with_decision_cache()`](crate::Authorizer::with_decision_cache) answers
+//! a request it has answered recently from the cache, without evaluating
+//! any policies.
+//!
+//! Entries are keyed by the [canonical hash](crate::Request::canonical_hash)
+//! of the request, by a digest of the policy set it was answered with, and
+//! by the snapshot id of the entities it was answered with, so a request is
+//! only ever answered from the cache with the policies and entities it was
+//! evaluated with. The digest of a policy set is computed when it is first
+//! needed and kept until the set is modified. Entities can't be modified,
+//! and each [`Entities`](crate::Entities) gets a snapshot id when it is
+//! created, which its clones share: requests are answered from the cache
+//! when they are given the same entities, or a clone of them, but not
+//! entities rebuilt from the same data. Replay protection is applied to
+//! every request, including those answered from the cache.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::{Entities, PolicySet, Request, Response};
+
+/// The default maximum number of entries in a [`DecisionCache`]
+pub const DEFAULT_CAPACITY: usize = 10_000;
+
+/// The key of a request's entry, with the policies and entities it is
+/// answered with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct CacheKey {
+    request: [u8; 32],
+    policies: [u8; 32],
+    entities: u64,
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    response: Response,
+    expires: Instant,
+}
+
+/// Recent authorization decisions, which expire after a time to live
+#[derive(Debug)]
+pub struct DecisionCache {
+    ttl: Duration,
+    capacity: usize,
+    entries: Mutex<HashMap<CacheKey, CacheEntry>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl DecisionCache {
+    /// An empty cache whose entries live for `ttl`, holding at most
+    /// [`DEFAULT_CAPACITY`] entries
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            capacity: DEFAULT_CAPACITY,
+            entries: Mutex::new(HashMap::new()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Hold at most `capacity` entries. When the cache is full, expired
+    /// entries are dropped, and then the entry closest to expiry.
+    #[must_use]
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Drop the entries for `request`, whichever policies and entities they
+    /// were answered with, returning whether there were any
+    pub fn invalidate(&self, request: &Request) -> bool {
+        let hash = request.canonical_hash();
+        let mut entries = self.entries();
+        let len = entries.len();
+        entries.retain(|key, _| key.request != hash);
+        len != entries.len()
+    }
+
+    /// Drop every entry
+    pub fn clear(&self) {
+        self.entries().clear();
+    }
+
+    /// The number of entries, including expired ones not yet dropped
+    pub fn len(&self) -> usize {
+        self.entries().len()
+    }
+
+    /// Whether there are no entries
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of requests answered from the cache, and the number not
+    pub fn stats(&self) -> (u64, u64) {
+        (
+            self.hits.load(Ordering::Relaxed),
+            self.misses.load(Ordering::Relaxed),
+        )
+    }
+
+    fn entries(&self) -> std::sync::MutexGuard<'_, HashMap<CacheKey, CacheEntry>> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The cached response to `request` with `policies` and `entities`, if it
+    /// hasn't expired, or else the key to [`insert()`](Self::insert) the
+    /// response under once it has been evaluated
+    pub(crate) fn get(
+        &self,
+        request: &Request,
+        policies: &PolicySet,
+        entities: &Entities,
+    ) -> Result<Response, CacheKey> {
+        let key = CacheKey {
+            request: request.canonical_hash(),
+            policies: policies.fingerprint(),
+            entities: entities.0.snapshot_id(),
+        };
+        let mut entries = self.entries();
+        let response = match entries.get(&key) {
+            Some(entry) if entry.expires > Instant::now() => Some(entry.response.clone()),
+            Some(_) => {
+                entries.remove(&key);
+                None
+            }
+            None => None,
+        };
+        drop(entries);
+        let counter = if response.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        response.ok_or(key)
+    }
+
+    /// Cache `response` under `key`, from [`get()`](Self::get)
+    pub(crate) fn insert(&self, key: CacheKey, response: Response) {
+        if self.capacity == 0 {
+            return;
+        }
+        let now = Instant::now();
+        let mut entries = self.entries();
+        if entries.len() >= self.capacity && !entries.contains_key(&key) {
+            entries.retain(|_, entry| entry.expires > now);
+            if entries.len() >= self.capacity {
+                let oldest = entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.expires)
+                    .map(|(key, _)| *key);
+                if let Some(oldest) = oldest {
+                    entries.remove(&oldest);
+                }
+            }
+        }
+        entries.insert(
+            key,
+            CacheEntry {
+                response,
+                expires: now + self.ttl,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Authorizer, Context, Decision, EntityUid, Policy, PolicyId};
+    use std::str::FromStr;
+    use std::sync::Arc;
+
+    fn request(user: &str) -> Request {
+        Request::new(
+            Some(EntityUid::from_strs("User", user)),
+            Some(EntityUid::from_strs("Action", "approve")),
+            Some(EntityUid::from_strs("Proposal", "7")),
+            Context::empty(),
+        )
+    }
+
+    #[test]
+    fn decisions_are_cached_per_policy_set_and_entities() {
+        let allow = PolicySet::from_str(r#"permit(principal == User::"alice", action, resource);"#)
+            .unwrap();
+        let deny = PolicySet::new();
+        let entities = Entities::empty();
+        let cache = Arc::new(DecisionCache::new(Duration::from_secs(30)));
+        let authorizer = Authorizer::new().with_decision_cache(Arc::clone(&cache));
+        let decide = |policies: &PolicySet, entities: &Entities| {
+            authorizer
+                .is_authorized(&request("alice"), policies, entities)
+                .decision()
+        };
+
+        assert_eq!(decide(&allow, &entities), Decision::Allow);
+        // clones share the digest and the snapshot id
+        let (copied, snapshot) = (allow.clone(), entities.clone());
+        assert_eq!(decide(&copied, &snapshot), Decision::Allow);
+        assert_eq!(cache.stats(), (1, 1));
+        // other policies, or other entities, miss
+        assert_eq!(decide(&deny, &entities), Decision::Deny);
+        assert_eq!(decide(&allow, &Entities::empty()), Decision::Allow);
+        assert_eq!(cache.stats(), (1, 3));
+        assert_eq!(cache.len(), 3);
+
+        // as do the same policies once they are modified
+        let mut modified = allow;
+        modified
+            .add(
+                Policy::parse(Some("deny".into()), "forbid(principal, action, resource);").unwrap(),
+            )
+            .unwrap();
+        assert_eq!(decide(&modified, &entities), Decision::Deny);
+        assert!(modified
+            .remove_static(&PolicyId::from_str("deny").unwrap())
+            .is_some());
+        assert_eq!(decide(&modified, &entities), Decision::Allow);
+        assert_eq!(cache.stats(), (2, 4));
+
+        assert!(cache.invalidate(&request("alice")));
+        assert!(!cache.invalidate(&request("alice")));
+        assert!(cache.is_empty());
+        assert_eq!(decide(&deny, &entities), Decision::Deny);
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn entries_expire_and_are_evicted() {
+        let policies = PolicySet::from_str("permit(principal, action, resource);").unwrap();
+        let entities = Entities::empty();
+        let expired = DecisionCache::new(Duration::ZERO);
+        let authorizer = Authorizer::new();
+        let response = authorizer.is_authorized(&request("alice"), &policies, &entities);
+        let key = expired
+            .get(&request("alice"), &policies, &entities)
+            .unwrap_err();
+        expired.insert(key, response.clone());
+        assert!(expired
+            .get(&request("alice"), &policies, &entities)
+            .is_err());
+        assert!(expired.is_empty());
+
+        let small = DecisionCache::new(Duration::from_secs(30)).with_capacity(2);
+        for user in ["alice", "bob", "carol"] {
+            let key = small.get(&request(user), &policies, &entities).unwrap_err();
+            small.insert(key, response.clone());
+        }
+        assert_eq!(small.len(), 2);
+        assert!(small.get(&request("alice"), &policies, &entities).is_err());
+        assert_eq!(
+            small.get(&request("carol"), &policies, &entities),
+            Ok(response)
+        );
+    }
+}
@@ -0,0 +1,332 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Scoping policies and entities to chains.
+//!
+//! One policy set often governs activity on several networks, and a policy
+//! written for one network must not grant anything on another. A policy is
+//! restricted to some chains with a `@chain` annotation listing their ids,
+//! e.g. `@chain("1, 8453")`; a policy without one applies on every chain.
+//! Each request carries its chain id in the [`CHAIN_ATTRIBUTE`] of its
+//! context. A [`ChainScope`] removes the policies which don't apply to a
+//! request's chain, and an [`Authorizer`](crate::Authorizer) given one with
+//! [`with_chain_scope()`](crate::Authorizer::with_chain_scope) considers only
+//! the policies which apply to each request's chain.
+//!
+//! Entities, such as token contracts and their attributes, differ between
+//! chains too; [`ChainEntities`] keeps an entity store for each chain.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use cedar_policy_core::ast::{ExprKind, Literal};
+pub use cedar_policy_validator::{parse_chain_annotation, CHAIN_ANNOTATION};
+use thiserror::Error;
+
+use crate::{Effect, Entities, PolicySet, Request};
+
+/// The default context attribute holding a request's chain id, a `Long`
+pub const CHAIN_ATTRIBUTE: &str = "chain";
+
+/// Why a request's chain couldn't be determined
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ChainError {
+    /// The request has no chain, but one is required
+    #[error("request has no chain")]
+    Missing,
+    /// The chain attribute isn't a non-negative `Long`
+    #[error("context attribute `{0}` is not a chain id")]
+    WrongType(String),
+    /// There are no entities for the request's chain
+    #[error("no entities for chain {0}")]
+    UnknownChain(u64),
+}
+
+/// Determines the chain of each request, and so which policies apply to it
+#[derive(Debug, Clone)]
+pub struct ChainScope {
+    attribute: String,
+    required: bool,
+}
+
+impl Default for ChainScope {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ChainScope {
+    /// A scope reading each request's chain from the [`CHAIN_ATTRIBUTE`] of
+    /// its context. Requests without one are subject only to the policies
+    /// without a `@chain` annotation.
+    pub fn new() -> Self {
+        Self {
+            attribute: CHAIN_ATTRIBUTE.to_string(),
+            required: false,
+        }
+    }
+
+    /// Read chains from the context attribute `attribute`
+    #[must_use]
+    pub fn with_attribute(mut self, attribute: impl Into<String>) -> Self {
+        self.attribute = attribute.into();
+        self
+    }
+
+    /// Refuse requests without a chain
+    #[must_use]
+    pub fn require_chain(mut self) -> Self {
+        self.required = true;
+        self
+    }
+
+    /// The chain of `request`, if it has one
+    pub fn chain_of(&self, request: &Request) -> Result<Option<u64>, ChainError> {
+        let chain = request
+            .0
+            .context()
+            .into_iter()
+            .flat_map(|c| c.iter())
+            .find(|(key, _)| *key == self.attribute);
+        match chain {
+            Some((key, value)) => match value.expr_kind() {
+                ExprKind::Lit(Literal::Long(n)) => u64::try_from(*n)
+                    .map(Some)
+                    .map_err(|_| ChainError::WrongType(key.to_string())),
+                _ => Err(ChainError::WrongType(key.to_string())),
+            },
+            None if self.required => Err(ChainError::Missing),
+            None => Ok(None),
+        }
+    }
+
+    /// The policies in `policies` which apply to `request`, as in
+    /// [`policies_for_chain()`]
+    pub fn apply<'a>(
+        &self,
+        request: &Request,
+        policies: &'a PolicySet,
+    ) -> Result<Cow<'a, PolicySet>, ChainError> {
+        Ok(policies_for_chain(policies, self.chain_of(request)?))
+    }
+}
+
+/// The policies in `policies` which apply on `chain`: those without a
+/// `@chain` annotation, and those whose annotation lists `chain`. Without a
+/// chain, only the policies without an annotation apply. A malformed
+/// annotation makes a `permit` policy apply on no chain and a `forbid`
+/// policy apply on every chain, so that a typo never allows more. Borrows
+/// `policies` if they all apply.
+pub fn policies_for_chain(policies: &PolicySet, chain: Option<u64>) -> Cow<'_, PolicySet> {
+    policies.retained(|policy| {
+        match policy
+            .annotation(CHAIN_ANNOTATION)
+            .map(parse_chain_annotation)
+        {
+            None => true,
+            Some(Some(chains)) => chain.is_some_and(|chain| chains.contains(&chain)),
+            Some(None) => policy.effect() == Effect::Forbid,
+        }
+    })
+}
+
+/// An entity store for each chain
+#[derive(Debug, Clone, Default)]
+pub struct ChainEntities {
+    chains: HashMap<u64, Entities>,
+}
+
+impl ChainEntities {
+    /// No entities for any chain
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use `entities` for `chain`, returning the entities it replaces
+    pub fn insert(&mut self, chain: u64, entities: Entities) -> Option<Entities> {
+        self.chains.insert(chain, entities)
+    }
+
+    /// The entities of `chain`
+    pub fn get(&self, chain: u64) -> Option<&Entities> {
+        self.chains.get(&chain)
+    }
+
+    /// The chains with entities
+    pub fn chains(&self) -> impl Iterator<Item = u64> + '_ {
+        self.chains.keys().copied()
+    }
+
+    /// The entities of `request`'s chain, as determined by `scope`
+    pub fn for_request(
+        &self,
+        scope: &ChainScope,
+        request: &Request,
+    ) -> Result<&Entities, ChainError> {
+        let chain = scope.chain_of(request)?.ok_or(ChainError::Missing)?;
+        self.get(chain).ok_or(ChainError::UnknownChain(chain))
+    }
+}
+
+impl FromIterator<(u64, Entities)> for ChainEntities {
+    fn from_iter<T: IntoIterator<Item = (u64, Entities)>>(iter: T) -> Self {
+        Self {
+            chains: iter.into_iter().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        AuthorizationError, Authorizer, Context, Decision, EntityUid, PolicyId,
+        RestrictedExpression, SlotId,
+    };
+    use std::str::FromStr;
+
+    fn request(chain: Option<RestrictedExpression>) -> Request {
+        Request::new(
+            Some(EntityUid::from_strs("User", "alice")),
+            Some(EntityUid::from_strs("Action", "transfer")),
+            Some(EntityUid::from_strs("Token", "usdc")),
+            Context::from_pairs(chain.map(|chain| ("chain".to_string(), chain))),
+        )
+    }
+
+    fn on(chain: i64) -> Request {
+        request(Some(RestrictedExpression::new_long(chain)))
+    }
+
+    fn policies() -> PolicySet {
+        let mut policies = PolicySet::from_str(
+            r#"permit(principal, action == Action::"view", resource);
+               @chain("1") permit(principal, action == Action::"transfer", resource);
+               @chain("base") permit(principal, action, resource);
+               @chain("1,") forbid(principal, action, resource) when { context has frozen };
+               @chain("1, 8453") permit(principal == ?principal, action, resource == Token::"weth");"#,
+        )
+        .unwrap();
+        policies
+            .link(
+                PolicyId::from_str("policy4").unwrap(),
+                PolicyId::from_str("alice").unwrap(),
+                HashMap::from([(SlotId::principal(), EntityUid::from_strs("User", "alice"))]),
+            )
+            .unwrap();
+        policies
+    }
+
+    fn ids(policies: &PolicySet) -> Vec<String> {
+        let mut ids: Vec<_> = policies.policies().map(|p| p.id().to_string()).collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn policies_are_scoped_to_their_chains() {
+        let policies = policies();
+        assert_eq!(
+            ids(&policies_for_chain(&policies, Some(1))),
+            ["alice", "policy0", "policy1", "policy3"]
+        );
+        assert_eq!(
+            ids(&policies_for_chain(&policies, Some(8453))),
+            ["alice", "policy0", "policy3"]
+        );
+        assert_eq!(
+            ids(&policies_for_chain(&policies, None)),
+            ["policy0", "policy3"]
+        );
+        assert!(matches!(
+            policies_for_chain(&PolicySet::new(), Some(1)),
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[test]
+    fn chains_of_requests() {
+        let scope = ChainScope::new();
+        assert_eq!(scope.chain_of(&on(10)), Ok(Some(10)));
+        assert_eq!(scope.chain_of(&request(None)), Ok(None));
+        assert_eq!(
+            scope.chain_of(&on(-1)),
+            Err(ChainError::WrongType("chain".to_string()))
+        );
+        assert_eq!(
+            scope.chain_of(&request(Some(RestrictedExpression::new_string(
+                "1".to_string()
+            )))),
+            Err(ChainError::WrongType("chain".to_string()))
+        );
+        assert_eq!(on(10).chain(), Some(10));
+
+        let scope = ChainScope::new().with_attribute("chainId").require_chain();
+        assert_eq!(scope.chain_of(&on(10)), Err(ChainError::Missing));
+    }
+
+    #[test]
+    fn authorizer_applies_policies_of_the_request_chain() {
+        let policies = policies();
+        let authorizer = Authorizer::new().with_chain_scope(ChainScope::new().require_chain());
+        let decide =
+            |request: &Request| authorizer.is_authorized(request, &policies, &Entities::empty());
+        assert_eq!(decide(&on(1)).decision(), Decision::Allow);
+        assert_eq!(decide(&on(8453)).decision(), Decision::Deny);
+        let unscoped = decide(&request(None));
+        assert_eq!(unscoped.decision(), Decision::Deny);
+        assert!(matches!(
+            unscoped.diagnostics().errors().next(),
+            Some(AuthorizationError::InvalidChain(_))
+        ));
+        // the malformed forbid still applies
+        let frozen = Request::new(
+            Some(EntityUid::from_strs("User", "alice")),
+            Some(EntityUid::from_strs("Action", "transfer")),
+            Some(EntityUid::from_strs("Token", "usdc")),
+            Context::from_pairs([
+                ("chain".to_string(), RestrictedExpression::new_long(1)),
+                ("frozen".to_string(), RestrictedExpression::new_bool(true)),
+            ]),
+        );
+        assert_eq!(decide(&frozen).decision(), Decision::Deny);
+    }
+
+    #[test]
+    fn entities_per_chain() {
+        let mainnet = Entities::from_json_str(
+            r#"[{"uid": {"type": "Token", "id": "usdc"}, "attrs": {"decimals": 6}, "parents": []}]"#,
+            None,
+        )
+        .unwrap();
+        let chains: ChainEntities = [(1, mainnet.clone()), (56, Entities::empty())]
+            .into_iter()
+            .collect();
+        let scope = ChainScope::new();
+        assert_eq!(chains.for_request(&scope, &on(1)), Ok(&mainnet));
+        assert_eq!(
+            chains.for_request(&scope, &on(10)),
+            Err(ChainError::UnknownChain(10))
+        );
+        assert_eq!(
+            chains.for_request(&scope, &request(None)),
+            Err(ChainError::Missing)
+        );
+        let mut sorted: Vec<_> = chains.chains().collect();
+        sorted.sort_unstable();
+        assert_eq!(sorted, [1, 56]);
+    }
+}
@@ -0,0 +1,822 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Named configuration values in policies, resolved when they're loaded.
+//!
+//! A policy can refer to a value by name with `config("key")` instead of
+//! writing it out, e.g.
+//! ```text
+//! forbid(principal, action == Action::"transfer", resource)
+//! when { context.amount.u256GreaterThan(config("maxTransfer")) };
+//! ```
+//! so a limit can be changed without editing, and re-approving, the policy.
+//! [`ConfigResolver::resolve()`] replaces each `config("key")` with the
+//! value of `key`, typed as declared with [`ConfigResolver::with_key()`],
+//! from the first of its [`ConfigSource`]s which has one. A policy set is
+//! resolved once, when it's loaded, so a request is never answered with a
+//! mix of old and new values.
+//!
+//! Evaluating a `config("key")` which wasn't resolved is an error, so the
+//! policy is skipped: always resolve a policy set before using it. The
+//! validator accepts `config("key")` anywhere, so validate the resolved set
+//! to check that the values have the types the policies expect.
+//!
+//! [`MemoryConfigSource`] holds values in memory, [`EnvConfigSource`] reads
+//! them from environment variables, and [`FileConfigSource`] from a JSON
+//! file. With the `eth-rpc` feature, `RegistryConfigSource` reads them from
+//! a registry contract, as of the block pinned with
+//! [`ConfigResolver::with_block_pin()`], if any.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Debug;
+use std::path::Path;
+use std::sync::Arc;
+
+use cedar_policy_core::ast;
+use cedar_policy_core::evaluator::RestrictedEvaluator;
+use cedar_policy_core::extensions::Extensions;
+use ref_cast::RefCast;
+use thiserror::Error;
+
+use crate::block_pin::{BlockPin, BlockPinError};
+use crate::{PolicyId, PolicySet};
+
+/// The function policies refer to configuration values with
+pub const CONFIG_FUNCTION: &str = "config";
+
+/// Errors resolving configuration values
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    /// A policy calls `config` with something other than a string literal
+    #[error("policy `{0}` calls `config` with something other than a string literal")]
+    MalformedReference(PolicyId),
+    /// A policy refers to a key which wasn't declared
+    #[error("policy `{policy}` refers to undeclared configuration key `{key}`")]
+    UndeclaredKey {
+        /// The policy
+        policy: PolicyId,
+        /// The key
+        key: String,
+    },
+    /// No source has a value for a key
+    #[error("no value for configuration key `{0}`")]
+    Missing(String),
+    /// The value of a key isn't of its declared type
+    #[error("value `{value}` of configuration key `{key}` isn't a {expected}")]
+    InvalidValue {
+        /// The key
+        key: String,
+        /// The value
+        value: String,
+        /// The declared type
+        expected: ConfigType,
+    },
+    /// A source failed
+    #[error("configuration source failed: {0}")]
+    Source(String),
+    /// The pinned block was reorged, or couldn't be read
+    #[error(transparent)]
+    Pin(#[from] BlockPinError),
+    /// The resolved policies don't form a policy set
+    #[error("the resolved policies don't form a policy set: {0}")]
+    PolicySet(String),
+}
+
+/// The type of a configuration value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigType {
+    /// `true` or `false`
+    Bool,
+    /// A decimal integer
+    Long,
+    /// Any string
+    String,
+    /// A value of an extension type, given by the string its constructor
+    /// takes, e.g. `Extension("u256".into())` for `u256("1000")`
+    Extension(String),
+}
+
+impl std::fmt::Display for ConfigType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Bool => write!(f, "Bool"),
+            Self::Long => write!(f, "Long"),
+            Self::String => write!(f, "String"),
+            Self::Extension(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+impl ConfigType {
+    /// `value` as a Cedar expression of this type
+    fn expr(&self, key: &str, value: &str) -> Result<ast::Expr, ConfigError> {
+        let invalid = || ConfigError::InvalidValue {
+            key: key.to_string(),
+            value: value.to_string(),
+            expected: self.clone(),
+        };
+        match self {
+            Self::Bool => value
+                .parse::<bool>()
+                .map(ast::Expr::val)
+                .map_err(|_| invalid()),
+            Self::Long => value
+                .parse::<i64>()
+                .map(ast::Expr::val)
+                .map_err(|_| invalid()),
+            Self::String => Ok(ast::Expr::val(value)),
+            Self::Extension(name) => {
+                let name = ast::Name::parse_unqualified_name(name).map_err(|_| invalid())?;
+                let expr = ast::Expr::call_extension_fn(name, vec![ast::Expr::val(value)]);
+                // construct the value now, so a malformed one is reported here
+                // rather than on every request
+                let extensions = Extensions::all_available();
+                RestrictedEvaluator::new(&extensions)
+                    .interpret(ast::BorrowedRestrictedExpr::new_unchecked(&expr))
+                    .map_err(|_| invalid())?;
+                Ok(expr)
+            }
+        }
+    }
+}
+
+/// A source of configuration values
+pub trait ConfigSource: Debug + Send + Sync {
+    /// The value of `key`, if the source has one
+    fn get(&self, key: &str) -> Result<Option<String>, ConfigError>;
+
+    /// The value of `key` as of the block `pin`, if the source has one.
+    /// Sources which don't read a chain ignore the pin.
+    fn get_at(&self, key: &str, _pin: &BlockPin) -> Result<Option<String>, ConfigError> {
+        self.get(key)
+    }
+}
+
+/// Configuration values held in memory
+#[derive(Debug, Clone, Default)]
+pub struct MemoryConfigSource {
+    values: HashMap<String, String>,
+}
+
+impl MemoryConfigSource {
+    /// No values
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// With the value `value` for `key`
+    #[must_use]
+    pub fn with(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.values.insert(key.into(), value.into());
+        self
+    }
+}
+
+impl ConfigSource for MemoryConfigSource {
+    fn get(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        Ok(self.values.get(key).cloned())
+    }
+}
+
+/// Configuration values in environment variables.
+///
+/// The variable of a key is the prefix followed by the key in upper snake
+/// case, e.g. `BANYAN_MAX_TRANSFER` for `maxTransfer` with the prefix
+/// `BANYAN_`.
+#[derive(Debug, Clone)]
+pub struct EnvConfigSource {
+    prefix: String,
+}
+
+impl EnvConfigSource {
+    /// Values in the variables starting with `prefix`
+    pub fn new(prefix: impl Into<String>) -> Self {
+        Self {
+            prefix: prefix.into(),
+        }
+    }
+
+    /// The variable holding the value of `key`
+    pub fn variable(&self, key: &str) -> String {
+        let mut variable = self.prefix.clone();
+        let mut previous = None;
+        for c in key.chars() {
+            if c.is_ascii_uppercase() && previous.is_some_and(|p: char| p.is_ascii_lowercase()) {
+                variable.push('_');
+            }
+            variable.push(if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            });
+            previous = Some(c);
+        }
+        variable
+    }
+}
+
+impl ConfigSource for EnvConfigSource {
+    fn get(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        Ok(std::env::var(self.variable(key)).ok())
+    }
+}
+
+/// Configuration values in a JSON object of keys and values, which may be
+/// strings, numbers, or booleans
+#[derive(Debug, Clone)]
+pub struct FileConfigSource {
+    values: HashMap<String, String>,
+}
+
+impl FileConfigSource {
+    /// Read the values in the file at `path`
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let json = std::fs::read_to_string(path.as_ref()).map_err(|e| {
+            ConfigError::Source(format!("reading {}: {e}", path.as_ref().display()))
+        })?;
+        Self::from_json_str(&json)
+    }
+
+    /// The values in the JSON object `json`
+    pub fn from_json_str(json: &str) -> Result<Self, ConfigError> {
+        let object: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_str(json).map_err(|e| ConfigError::Source(e.to_string()))?;
+        let values = object
+            .into_iter()
+            .map(|(key, value)| {
+                let value = match value {
+                    serde_json::Value::String(s) => s,
+                    serde_json::Value::Number(n) => n.to_string(),
+                    serde_json::Value::Bool(b) => b.to_string(),
+                    _ => {
+                        return Err(ConfigError::Source(format!(
+                            "the value of `{key}` isn't a string, number, or boolean"
+                        )))
+                    }
+                };
+                Ok((key, value))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { values })
+    }
+}
+
+impl ConfigSource for FileConfigSource {
+    fn get(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        Ok(self.values.get(key).cloned())
+    }
+}
+
+/// Resolves the configuration values policies refer to
+#[derive(Debug, Clone, Default)]
+pub struct ConfigResolver {
+    keys: HashMap<String, ConfigType>,
+    sources: Vec<Arc<dyn ConfigSource>>,
+    pin: Option<BlockPin>,
+}
+
+impl ConfigResolver {
+    /// No keys and no sources
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Declare `key`, whose values are of type `ty`
+    #[must_use]
+    pub fn with_key(mut self, key: impl Into<String>, ty: ConfigType) -> Self {
+        self.keys.insert(key.into(), ty);
+        self
+    }
+
+    /// Look values up in `source`, after the sources already added
+    #[must_use]
+    pub fn with_source(mut self, source: Arc<dyn ConfigSource>) -> Self {
+        self.sources.push(source);
+        self
+    }
+
+    /// Read values from sources which read a chain as of the block `pin`,
+    /// instead of the latest block, so they're all read from the same block
+    #[must_use]
+    pub fn with_block_pin(mut self, pin: BlockPin) -> Self {
+        self.pin = Some(pin);
+        self
+    }
+
+    /// The keys `policies` refer to
+    pub fn references(&self, policies: &PolicySet) -> Result<BTreeSet<String>, ConfigError> {
+        let mut keys = BTreeSet::new();
+        for template in policies.ast.all_templates() {
+            for key in references(template)? {
+                if !self.keys.contains_key(&key) {
+                    return Err(ConfigError::UndeclaredKey {
+                        policy: PolicyId::ref_cast(template.id()).clone(),
+                        key,
+                    });
+                }
+                keys.insert(key);
+            }
+        }
+        Ok(keys)
+    }
+
+    /// The current values of the keys `policies` refer to
+    pub fn values(&self, policies: &PolicySet) -> Result<BTreeMap<String, String>, ConfigError> {
+        self.references(policies)?
+            .into_iter()
+            .map(|key| {
+                let value = self.value(&key)?;
+                Ok((key, value))
+            })
+            .collect()
+    }
+
+    /// `policies`, with each `config("key")` replaced by the current value of
+    /// `key`
+    pub fn resolve(&self, policies: &PolicySet) -> Result<PolicySet, ConfigError> {
+        let mut values = HashMap::new();
+        for (key, value) in self.values(policies)? {
+            // every key referred to is declared
+            let Some(ty) = self.keys.get(&key) else {
+                continue;
+            };
+            values.insert(key.clone(), ty.expr(&key, &value)?);
+        }
+        let error = |e: &dyn std::fmt::Display| ConfigError::PolicySet(e.to_string());
+        let mut resolved = ast::PolicySet::new();
+        for policy in policies.ast.static_policies() {
+            let template = substitute_template(policy.template(), &values);
+            let policy = ast::StaticPolicy::try_from(template).map_err(|e| error(&e))?;
+            resolved.add_static(policy).map_err(|e| error(&e))?;
+        }
+        for template in policies.ast.templates() {
+            resolved
+                .add_template(substitute_template(template, &values))
+                .map_err(|e| error(&e))?;
+        }
+        for policy in policies.ast.policies().filter(|policy| !policy.is_static()) {
+            resolved
+                .link(
+                    policy.template().id().clone(),
+                    policy.id().clone(),
+                    policy.env().clone(),
+                )
+                .map_err(|e| error(&e))?;
+        }
+        Ok(PolicySet::from_ast(resolved))
+    }
+
+    /// The value of `key` in the first source which has one
+    fn value(&self, key: &str) -> Result<String, ConfigError> {
+        for source in &self.sources {
+            let value = match &self.pin {
+                Some(pin) => source.get_at(key, pin)?,
+                None => source.get(key)?,
+            };
+            if let Some(value) = value {
+                return Ok(value);
+            }
+        }
+        Err(ConfigError::Missing(key.to_string()))
+    }
+}
+
+/// Whether `expr` calls `config`
+fn is_config_call(expr: &ast::Expr) -> Option<&[ast::Expr]> {
+    match expr.expr_kind() {
+        ast::ExprKind::ExtensionFunctionApp { fn_name, args }
+            if fn_name.to_string() == CONFIG_FUNCTION =>
+        {
+            Some(args)
+        }
+        _ => None,
+    }
+}
+
+/// The key a call to `config` refers to, if it's a single string literal
+fn key_of(args: &[ast::Expr]) -> Option<&str> {
+    match args {
+        [arg] => match arg.expr_kind() {
+            ast::ExprKind::Lit(ast::Literal::String(key)) => Some(key.as_str()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The keys `template` refers to
+fn references(template: &ast::Template) -> Result<Vec<String>, ConfigError> {
+    template
+        .non_head_constraints()
+        .subexpressions()
+        .filter_map(is_config_call)
+        .map(|args| {
+            key_of(args).map(ToString::to_string).ok_or_else(|| {
+                ConfigError::MalformedReference(PolicyId::ref_cast(template.id()).clone())
+            })
+        })
+        .collect()
+}
+
+/// `template`, with its calls to `config` replaced by `values`
+fn substitute_template(
+    template: &ast::Template,
+    values: &HashMap<String, ast::Expr>,
+) -> ast::Template {
+    ast::Template::new(
+        template.id().clone(),
+        template
+            .annotations()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect(),
+        template.effect(),
+        template.principal_constraint().clone(),
+        template.action_constraint().clone(),
+        template.resource_constraint().clone(),
+        substitute(template.non_head_constraints(), values),
+    )
+}
+
+/// `expr`, with its calls to `config` replaced by `values`
+fn substitute(expr: &ast::Expr, values: &HashMap<String, ast::Expr>) -> ast::Expr {
+    use ast::{Expr, ExprKind};
+
+    if let Some(value) = is_config_call(expr)
+        .and_then(key_of)
+        .and_then(|key| values.get(key))
+    {
+        return value.clone();
+    }
+    let sub = |e: &Expr| substitute(e, values);
+    match expr.expr_kind() {
+        ExprKind::Lit(_) | ExprKind::Var(_) | ExprKind::Slot(_) | ExprKind::Unknown { .. } => {
+            expr.clone()
+        }
+        ExprKind::If {
+            test_expr,
+            then_expr,
+            else_expr,
+        } => Expr::ite(sub(test_expr), sub(then_expr), sub(else_expr)),
+        ExprKind::And { left, right } => Expr::and(sub(left), sub(right)),
+        ExprKind::Or { left, right } => Expr::or(sub(left), sub(right)),
+        ExprKind::UnaryApp { op, arg } => Expr::unary_app(*op, sub(arg)),
+        ExprKind::BinaryApp { op, arg1, arg2 } => Expr::binary_app(*op, sub(arg1), sub(arg2)),
+        ExprKind::ExtensionFunctionApp { fn_name, args } => {
+            Expr::call_extension_fn(fn_name.clone(), args.iter().map(sub).collect())
+        }
+        ExprKind::GetAttr { expr, attr } => Expr::get_attr(sub(expr), attr.clone()),
+        ExprKind::HasAttr { expr, attr } => Expr::has_attr(sub(expr), attr.clone()),
+        ExprKind::Like { expr, pattern } => Expr::like(sub(expr), pattern.iter().cloned()),
+        ExprKind::Is { expr, entity_type } => Expr::is_entity_type(sub(expr), entity_type.clone()),
+        ExprKind::Set(members) => Expr::set(members.iter().map(sub)),
+        ExprKind::Record { pairs } => {
+            Expr::record(pairs.iter().map(|(name, e)| (name.clone(), sub(e))))
+        }
+        ExprKind::MulByConst { arg, constant } => Expr::mul(sub(arg), *constant),
+    }
+}
+
+#[cfg(feature = "eth-rpc")]
+pub use registry::RegistryConfigSource;
+
+#[cfg(feature = "eth-rpc")]
+mod registry {
+    use super::{ConfigError, ConfigSource};
+    use crate::audit::{to_hex, unhex};
+    use crate::block_pin::{self, BlockPin};
+    use serde::Deserialize;
+    use serde_json::json;
+    use sha3::{Digest, Keccak256};
+
+    /// A JSON-RPC response
+    #[derive(Debug, Deserialize)]
+    struct RpcResponse {
+        result: Option<String>,
+        error: Option<RpcError>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct RpcError {
+        message: String,
+    }
+
+    /// Reads configuration values from a registry contract with
+    /// `getString(bytes32 key) returns (string)`, where `key` is the
+    /// Keccak-256 hash of the configuration key. An empty string is no value.
+    /// Values are read as of the latest block, or of the pinned block with
+    /// [`ConfigSource::get_at()`].
+    ///
+    /// Its requests are blocking, see the `domain` module's docs.
+    #[derive(Debug)]
+    pub struct RegistryConfigSource {
+        client: reqwest::blocking::Client,
+        rpc_url: String,
+        registry: String,
+    }
+
+    impl RegistryConfigSource {
+        /// A source reading the registry at `registry` through the JSON-RPC
+        /// node at `rpc_url`
+        pub fn new(rpc_url: impl Into<String>, registry: impl Into<String>) -> Self {
+            Self {
+                client: reqwest::blocking::Client::new(),
+                rpc_url: rpc_url.into(),
+                registry: registry.into(),
+            }
+        }
+    }
+
+    impl RegistryConfigSource {
+        /// The value of `key` at the block `block`
+        fn read(&self, key: &str, block: &str) -> Result<Option<String>, ConfigError> {
+            let body = self
+                .client
+                .post(&self.rpc_url)
+                .json(&json!({
+                    "jsonrpc": "2.0",
+                    "id": 1,
+                    "method": "eth_call",
+                    "params": [
+                        { "to": self.registry, "data": format!("0x{}", call_data(key)) },
+                        block
+                    ],
+                }))
+                .send()
+                .and_then(reqwest::blocking::Response::error_for_status)
+                .and_then(reqwest::blocking::Response::text)
+                .map_err(|err| ConfigError::Source(err.to_string()))?;
+            let value = parse_response(&body).map_err(ConfigError::Source)?;
+            Ok(Some(value).filter(|value| !value.is_empty()))
+        }
+    }
+
+    impl ConfigSource for RegistryConfigSource {
+        fn get(&self, key: &str) -> Result<Option<String>, ConfigError> {
+            self.read(key, "latest")
+        }
+
+        fn get_at(&self, key: &str, pin: &BlockPin) -> Result<Option<String>, ConfigError> {
+            let value = self.read(key, &pin.block_tag())?;
+            block_pin::rpc::check(&self.client, &self.rpc_url, pin)?;
+            Ok(value)
+        }
+    }
+
+    /// The call data of `getString(keccak256(key))`, hex-encoded
+    pub(super) fn call_data(key: &str) -> String {
+        let selector = Keccak256::digest(b"getString(bytes32)");
+        let mut data = selector.get(..4).unwrap_or_default().to_vec();
+        data.extend(Keccak256::digest(key.as_bytes()));
+        to_hex(&data)
+    }
+
+    /// The string returned in a JSON-RPC response `body`
+    pub(super) fn parse_response(body: &str) -> Result<String, String> {
+        let response: RpcResponse = serde_json::from_str(body).map_err(|e| e.to_string())?;
+        if let Some(error) = response.error {
+            return Err(error.message);
+        }
+        let result = response.result.ok_or_else(|| "no result".to_string())?;
+        let bytes = unhex(result.strip_prefix("0x").unwrap_or(&result))
+            .ok_or_else(|| format!("invalid result `{result}`"))?;
+        decode_string(&bytes).ok_or_else(|| format!("`{result}` isn't an ABI-encoded string"))
+    }
+
+    /// An ABI-encoded `string` return value
+    fn decode_string(bytes: &[u8]) -> Option<String> {
+        let word = |i: usize| {
+            let word = bytes.get(i..i.checked_add(32)?)?;
+            // offsets and lengths fit in 8 bytes
+            if word.get(..24)?.iter().any(|b| *b != 0) {
+                return None;
+            }
+            usize::try_from(u64::from_be_bytes(word.get(24..)?.try_into().ok()?)).ok()
+        };
+        let offset = word(0)?;
+        let len = word(offset)?;
+        let start = offset.checked_add(32)?;
+        let string = bytes.get(start..start.checked_add(len)?)?;
+        String::from_utf8(string.to_vec()).ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Authorizer, Context, Decision, Entities, EntityUid, Request};
+    use std::str::FromStr;
+
+    fn policies() -> PolicySet {
+        PolicySet::from_str(
+            r#"permit(principal, action, resource);
+               forbid(principal, action == Action::"transfer", resource)
+               when { context.amount > config("maxTransfer") };
+               forbid(principal, action, resource)
+               when { config("frozen") || principal == config("blocked") };"#,
+        )
+        .unwrap()
+    }
+
+    fn resolver(source: MemoryConfigSource) -> ConfigResolver {
+        ConfigResolver::new()
+            .with_key("maxTransfer", ConfigType::Long)
+            .with_key("frozen", ConfigType::Bool)
+            .with_key("blocked", ConfigType::String)
+            .with_source(Arc::new(source))
+    }
+
+    fn transfer(policies: &PolicySet, amount: i64) -> Decision {
+        let request = Request::new(
+            Some(EntityUid::from_strs("User", "alice")),
+            Some(EntityUid::from_strs("Action", "transfer")),
+            Some(EntityUid::from_strs("Wallet", "w")),
+            Context::from_pairs([(
+                "amount".to_string(),
+                crate::RestrictedExpression::new_long(amount),
+            )]),
+        );
+        Authorizer::new()
+            .is_authorized(&request, policies, &Entities::empty())
+            .decision()
+    }
+
+    #[test]
+    fn resolving() {
+        let source = MemoryConfigSource::new()
+            .with("maxTransfer", "1000")
+            .with("frozen", "false")
+            .with("blocked", "mallory");
+        let config = resolver(source);
+        assert_eq!(
+            config.references(&policies()).unwrap(),
+            BTreeSet::from(["blocked".into(), "frozen".into(), "maxTransfer".into()])
+        );
+        let resolved = config.resolve(&policies()).unwrap();
+        assert_eq!(resolved.policies().count(), 3);
+        assert!(!resolved.to_string().contains("config"));
+        assert_eq!(transfer(&resolved, 1000), Decision::Allow);
+        assert_eq!(transfer(&resolved, 1001), Decision::Deny);
+
+        // rotating the limit doesn't touch the policies
+        let rotated = resolver(
+            MemoryConfigSource::new()
+                .with("maxTransfer", "5000")
+                .with("frozen", "false")
+                .with("blocked", "mallory"),
+        );
+        let resolved = rotated.resolve(&policies()).unwrap();
+        assert_eq!(transfer(&resolved, 1001), Decision::Allow);
+    }
+
+    #[test]
+    fn sources_in_order() {
+        let file = FileConfigSource::from_json_str(
+            r#"{ "maxTransfer": 1000, "frozen": false, "blocked": "mallory" }"#,
+        )
+        .unwrap();
+        let config = resolver(MemoryConfigSource::new().with("maxTransfer", "10"))
+            .with_source(Arc::new(file));
+        let values = config.values(&policies()).unwrap();
+        assert_eq!(values["maxTransfer"], "10");
+        assert_eq!(values["frozen"], "false");
+        assert!(FileConfigSource::from_json_str(r#"{ "limits": [1] }"#).is_err());
+
+        let env = EnvConfigSource::new("BANYAN_");
+        assert_eq!(env.variable("maxTransfer"), "BANYAN_MAX_TRANSFER");
+        assert_eq!(env.variable("limits.daily"), "BANYAN_LIMITS_DAILY");
+    }
+
+    /// A registry whose values are those of block 10, which a reorg may
+    /// have replaced
+    #[derive(Debug)]
+    struct ChainSource {
+        hash: &'static str,
+    }
+
+    impl ConfigSource for ChainSource {
+        fn get(&self, _key: &str) -> Result<Option<String>, ConfigError> {
+            Ok(Some("1".to_string()))
+        }
+
+        fn get_at(&self, key: &str, pin: &BlockPin) -> Result<Option<String>, ConfigError> {
+            assert_eq!(pin.number, 10);
+            pin.check(self.hash)?;
+            Ok(Some(format!("{}0", self.get(key)?.unwrap_or_default())))
+        }
+    }
+
+    #[test]
+    fn pinned_block() {
+        let config = |hash| {
+            ConfigResolver::new()
+                .with_key("maxTransfer", ConfigType::Long)
+                .with_source(Arc::new(ChainSource { hash }))
+        };
+        let policies = PolicySet::from_str(
+            r#"forbid(principal, action, resource) when { context.amount > config("maxTransfer") };"#,
+        )
+        .unwrap();
+        let pin = BlockPin::new(10, "0xa");
+        assert_eq!(config("0xa").values(&policies).unwrap()["maxTransfer"], "1");
+        assert_eq!(
+            config("0xa")
+                .with_block_pin(pin.clone())
+                .values(&policies)
+                .unwrap()["maxTransfer"],
+            "10"
+        );
+        assert!(matches!(
+            config("0xb").with_block_pin(pin).resolve(&policies),
+            Err(ConfigError::Pin(BlockPinError::Reorged { number: 10, .. }))
+        ));
+    }
+
+    #[test]
+    fn errors() {
+        let complete = || {
+            MemoryConfigSource::new()
+                .with("frozen", "false")
+                .with("blocked", "mallory")
+        };
+        assert!(matches!(
+            resolver(complete()).resolve(&policies()),
+            Err(ConfigError::Missing(key)) if key == "maxTransfer"
+        ));
+        assert!(matches!(
+            resolver(complete().with("maxTransfer", "1e3")).resolve(&policies()),
+            Err(ConfigError::InvalidValue { key, .. }) if key == "maxTransfer"
+        ));
+
+        let undeclared =
+            PolicySet::from_str(r#"forbid(principal, action, resource) when { config("other") };"#)
+                .unwrap();
+        assert!(matches!(
+            resolver(complete()).resolve(&undeclared),
+            Err(ConfigError::UndeclaredKey { key, .. }) if key == "other"
+        ));
+        let malformed = PolicySet::from_str(
+            "forbid(principal, action, resource) when { config(context.key) };",
+        )
+        .unwrap();
+        assert!(matches!(
+            resolver(complete()).resolve(&malformed),
+            Err(ConfigError::MalformedReference(_))
+        ));
+    }
+
+    #[cfg(feature = "decimal")]
+    #[test]
+    fn extension_values() {
+        let policies = PolicySet::from_str(
+            r#"forbid(principal, action, resource)
+               when { decimal("0.5").lessThan(config("maxShare")) };"#,
+        )
+        .unwrap();
+        let with_share = |share: &str| {
+            ConfigResolver::new()
+                .with_key("maxShare", ConfigType::Extension("decimal".into()))
+                .with_source(Arc::new(MemoryConfigSource::new().with("maxShare", share)))
+        };
+        let resolved = with_share("0.25").resolve(&policies).unwrap();
+        assert!(resolved.to_string().contains(r#"decimal("0.25")"#));
+        assert!(matches!(
+            with_share("a quarter").resolve(&policies),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+    }
+
+    #[cfg(feature = "eth-rpc")]
+    #[test]
+    fn registry_responses() {
+        use super::registry::{call_data, parse_response};
+        use sha3::{Digest, Keccak256};
+
+        let selector = Keccak256::digest(b"getString(bytes32)");
+        assert!(
+            call_data("maxTransfer").starts_with(&crate::audit::to_hex(selector.get(..4).unwrap()))
+        );
+        let result = format!(
+            "0x{:064x}{:064x}{}{}",
+            32,
+            4,
+            crate::audit::to_hex(b"1000"),
+            "0".repeat(56)
+        );
+        let body = serde_json::json!({ "jsonrpc": "2.0", "id": 1, "result": result }).to_string();
+        assert_eq!(parse_response(&body).unwrap(), "1000");
+        assert!(parse_response(r#"{"error":{"message":"reverted"}}"#).is_err());
+    }
+}
@@ -0,0 +1,196 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Estimated cost of evaluating policies, off-chain and on-chain.
+//!
+//! [`estimate()`] scores a policy's scope and conditions in two units:
+//! abstract evaluation steps for the authorizer, and gas for checking the
+//! policy in an EVM contract. Both are summed over the expression, with
+//! extra weight for the operations which dominate each: reading entity
+//! attributes and tags (a storage load on-chain), walking the entity
+//! hierarchy for `in`, extension function calls, and `like` patterns, which
+//! are matched character by character.
+//!
+//! With the `domain` feature, spending limits compile to Allowance module
+//! configuration (see `domain::allowance`), so their gas is instead what the
+//! module spends checking an allowance transfer against them.
+//!
+//! The estimates are for comparing policies and setting budgets: a policy
+//! whose estimate is over budget is too expensive to enforce on-chain.
+
+use cedar_policy_core::ast::{self, BinaryOp, ExprKind, Var};
+use serde::{Deserialize, Serialize};
+
+use crate::{Policy, PolicySet};
+
+/// Gas the Allowance module spends checking and recording a transfer
+/// against an allowance: the signature check, and reading and updating the
+/// allowance in storage. It excludes the transfer itself.
+#[cfg(all(feature = "domain", feature = "u256"))]
+pub const ALLOWANCE_CHECK_GAS: u64 = 30_000;
+
+/// Gas to read a word from storage the transaction hasn't read yet
+const SLOAD_GAS: u64 = 2_100;
+
+/// The estimated cost of a policy
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PolicyCost {
+    /// The policy id
+    pub policy: String,
+    /// Evaluation steps for the authorizer
+    pub evaluation: u64,
+    /// Gas to check the policy on-chain
+    pub gas: u64,
+}
+
+/// The estimated cost of `policy`
+pub fn estimate(policy: &Policy) -> PolicyCost {
+    let condition = policy.ast.condition();
+    let (evaluation, gas) = condition
+        .subexpressions()
+        .map(weight)
+        .fold((0_u64, 0_u64), |(evaluation, gas), (e, g)| {
+            (evaluation.saturating_add(e), gas.saturating_add(g))
+        });
+    #[cfg(all(feature = "domain", feature = "u256"))]
+    let gas = if crate::domain::allowance::SpendingLimit::from_policy(policy).is_ok() {
+        ALLOWANCE_CHECK_GAS
+    } else {
+        gas
+    };
+    PolicyCost {
+        policy: policy.id().as_ref().to_string(),
+        evaluation,
+        gas,
+    }
+}
+
+/// The estimated cost of each policy in `policies`, sorted by id
+pub fn estimate_all(policies: &PolicySet) -> Vec<PolicyCost> {
+    let mut costs: Vec<_> = policies.policies().map(estimate).collect();
+    costs.sort_by(|a, b| a.policy.cmp(&b.policy));
+    costs
+}
+
+/// The evaluation steps and gas of a single expression node, not counting
+/// its subexpressions
+fn weight(expr: &ast::Expr) -> (u64, u64) {
+    match expr.expr_kind() {
+        // values are pushed or loaded from calldata, and compared or added
+        // with single opcodes
+        ExprKind::Lit(_)
+        | ExprKind::Var(_)
+        | ExprKind::Slot(_)
+        | ExprKind::Unknown { .. }
+        | ExprKind::BinaryApp {
+            op: BinaryOp::Less | BinaryOp::LessEq | BinaryOp::Add | BinaryOp::Sub,
+            ..
+        } => (1, 3),
+        // conditional jumps, and building sets and records in memory
+        ExprKind::If { .. }
+        | ExprKind::And { .. }
+        | ExprKind::Or { .. }
+        | ExprKind::Set(_)
+        | ExprKind::Record { .. } => (1, 10),
+        ExprKind::UnaryApp { .. } | ExprKind::MulByConst { .. } | ExprKind::Is { .. } => (1, 5),
+        // fields of the context are decoded from calldata, while entity
+        // attributes are read from storage
+        ExprKind::GetAttr { expr, .. } | ExprKind::HasAttr { expr, .. } => {
+            if in_context(expr) {
+                (2, 50)
+            } else {
+                (10, SLOAD_GAS)
+            }
+        }
+        // hashing for string comparison dominates equality
+        ExprKind::BinaryApp {
+            op: BinaryOp::Eq, ..
+        } => (1, 42),
+        // a membership check reads the ancestors of the entity
+        ExprKind::BinaryApp {
+            op: BinaryOp::In, ..
+        } => (20, 2 * SLOAD_GAS),
+        // tags are read from storage, like entity attributes
+        ExprKind::BinaryApp {
+            op: BinaryOp::GetTag | BinaryOp::HasTag,
+            ..
+        } => (10, SLOAD_GAS),
+        ExprKind::BinaryApp {
+            op: BinaryOp::Contains | BinaryOp::ContainsAll | BinaryOp::ContainsAny,
+            ..
+        } => (5, 200),
+        ExprKind::ExtensionFunctionApp { .. } => (10, 50),
+        ExprKind::Like { pattern, .. } => {
+            let len = pattern.iter().count() as u64;
+            (len + 1, 100 + 50 * len)
+        }
+    }
+}
+
+/// Whether `expr` is the context or a record within it
+fn in_context(expr: &ast::Expr) -> bool {
+    match expr.expr_kind() {
+        ExprKind::Var(Var::Context) => true,
+        ExprKind::GetAttr { expr, .. } => in_context(expr),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+
+    fn costs(src: &str) -> Vec<PolicyCost> {
+        estimate_all(&PolicySet::from_str(src).unwrap())
+    }
+
+    #[test]
+    fn entity_reads_cost_more_than_context() {
+        let costs = costs(
+            r#"permit(principal, action, resource) when { context.amount < 100 };
+               permit(principal, action, resource) when { principal.balance < 100 };
+               permit(principal in Group::"signers", action, resource)
+               when { principal.balance < 100 && principal.name like "*-admin-*" };"#,
+        );
+        let [context, entity, complex] = costs.as_slice() else {
+            panic!("expected three estimates");
+        };
+        assert_eq!(context.policy, "policy0");
+        assert!(context.evaluation < entity.evaluation);
+        assert!(context.gas < entity.gas);
+        assert!(entity.gas + SLOAD_GAS < complex.gas);
+        assert!(entity.evaluation < complex.evaluation);
+    }
+
+    #[cfg(all(feature = "domain", feature = "u256"))]
+    #[test]
+    fn spending_limits_cost_the_allowance_check() {
+        let costs = costs(
+            r#"@spendingLimit("1440")
+               permit(
+                   principal == Wallet::"0x1111111111111111111111111111111111111111",
+                   action == Action::"transfer",
+                   resource == Token::"0x2222222222222222222222222222222222222222"
+               ) when { context.amount.u256LessThanOrEqual(u256("1000")) };"#,
+        );
+        assert_eq!(
+            costs.first().map(|cost| cost.gas),
+            Some(ALLOWANCE_CHECK_GAS)
+        );
+    }
+}
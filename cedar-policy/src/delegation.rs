@@ -0,0 +1,505 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Delegation chains.
+//!
+//! A delegation lets one principal (the delegator) act for another (the
+//! delegate), for a scope of actions, until an expiry time. A delegate may
+//! pass the delegation on, forming a chain; a chain is valid when every link
+//! in it is unexpired and covers the requested action, so the scope of a
+//! chain is the intersection of the scopes of its links.
+//!
+//! Delegations can be loaded from entities of a chosen type, with the
+//! attributes:
+//! - `delegator`: the entity delegating
+//! - `delegate`: the entity delegated to
+//! - `scope` (optional): a set of actions or action groups. Without it, every
+//!   action is delegated.
+//! - `expiresAt` (optional): the expiry time, in seconds since the Unix epoch
+//!
+//! [`Authorizer::is_authorized_on_behalf_of()`] resolves `actsFor(principal,
+//! onBehalfOf)` for a request, then answers it as though `onBehalfOf` had made
+//! it.
+
+use std::collections::{BTreeSet, HashSet, VecDeque};
+use std::time::SystemTime;
+
+use thiserror::Error;
+
+use crate::audit::unix_seconds;
+use crate::{
+    Authorizer, Entities, EntityTypeName, EntityUid, EvalResult, PolicySet, Request, Response,
+};
+
+/// The default limit on the number of links in a chain
+pub const DEFAULT_MAX_CHAIN_LENGTH: usize = 3;
+
+/// Errors loading or resolving delegations
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum DelegationError {
+    /// A delegation entity has a missing or invalid attribute
+    #[error("delegation `{uid}` has a missing or invalid `{attribute}` attribute")]
+    Malformed {
+        /// The delegation entity
+        uid: EntityUid,
+        /// The attribute
+        attribute: &'static str,
+    },
+    /// The request's principal or action isn't specified
+    #[error("the request must specify a principal and an action")]
+    Unspecified,
+    /// No valid chain lets the principal act for the other principal
+    #[error("`{delegate}` can't act for `{delegator}` to perform `{action}`")]
+    NotDelegated {
+        /// The principal of the request
+        delegate: EntityUid,
+        /// The principal it tried to act for
+        delegator: EntityUid,
+        /// The action of the request
+        action: EntityUid,
+    },
+}
+
+/// The actions a delegation covers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Scope {
+    /// Every action
+    All,
+    /// These actions, and the actions in these action groups
+    Actions(BTreeSet<EntityUid>),
+}
+
+impl Scope {
+    /// Whether `action` is in this scope. Action groups are resolved using
+    /// `entities`.
+    pub fn covers(&self, action: &EntityUid, entities: &Entities) -> bool {
+        match self {
+            Self::All => true,
+            Self::Actions(actions) => actions
+                .iter()
+                .any(|group| group == action || entities.is_ancestor_of(group, action)),
+        }
+    }
+
+    /// The actions in both scopes. An action or group in one scope is kept
+    /// if it's covered by the other.
+    #[must_use]
+    pub fn intersect(&self, other: &Self, entities: &Entities) -> Self {
+        match (self, other) {
+            (Self::All, scope) | (scope, Self::All) => scope.clone(),
+            (Self::Actions(a), Self::Actions(b)) => Self::Actions(
+                a.iter()
+                    .filter(|action| other.covers(action, entities))
+                    .chain(b.iter().filter(|action| self.covers(action, entities)))
+                    .cloned()
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// `delegator` lets `delegate` act for it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delegation {
+    /// The principal delegating
+    pub delegator: EntityUid,
+    /// The principal delegated to
+    pub delegate: EntityUid,
+    /// The actions delegated
+    pub scope: Scope,
+    /// When the delegation expires, in seconds since the Unix epoch
+    pub expires_at: Option<u64>,
+}
+
+impl Delegation {
+    /// Delegate every action, without expiry
+    pub fn new(delegator: EntityUid, delegate: EntityUid) -> Self {
+        Self {
+            delegator,
+            delegate,
+            scope: Scope::All,
+            expires_at: None,
+        }
+    }
+
+    /// Delegate only `actions`
+    #[must_use]
+    pub fn with_scope(mut self, actions: impl IntoIterator<Item = EntityUid>) -> Self {
+        self.scope = Scope::Actions(actions.into_iter().collect());
+        self
+    }
+
+    /// Expire at `expires_at`, in seconds since the Unix epoch
+    #[must_use]
+    pub fn with_expiry(mut self, expires_at: u64) -> Self {
+        self.expires_at = Some(expires_at);
+        self
+    }
+
+    /// Whether the delegation has expired at `now`
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at
+            .map_or(false, |expires_at| now >= expires_at)
+    }
+}
+
+/// A chain of delegations, from the principal acted for to the principal
+/// acting
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DelegationChain {
+    /// The links, in order
+    pub links: Vec<Delegation>,
+}
+
+impl DelegationChain {
+    /// The actions delegated by every link
+    pub fn scope(&self, entities: &Entities) -> Scope {
+        self.links.iter().fold(Scope::All, |scope, link| {
+            scope.intersect(&link.scope, entities)
+        })
+    }
+
+    /// When the first link in the chain expires
+    pub fn expires_at(&self) -> Option<u64> {
+        self.links.iter().filter_map(|link| link.expires_at).min()
+    }
+}
+
+/// A set of delegations
+#[derive(Debug, Clone)]
+pub struct Delegations {
+    delegations: Vec<Delegation>,
+    max_chain_length: usize,
+}
+
+impl Default for Delegations {
+    fn default() -> Self {
+        Self {
+            delegations: Vec::new(),
+            max_chain_length: DEFAULT_MAX_CHAIN_LENGTH,
+        }
+    }
+}
+
+impl Delegations {
+    /// An empty set of delegations
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load the delegations from the entities of type `delegation_type`
+    pub fn from_entities(
+        entities: &Entities,
+        delegation_type: &EntityTypeName,
+    ) -> Result<Self, DelegationError> {
+        entities
+            .iter()
+            .filter(|entity| entity.uid().type_name() == delegation_type)
+            .map(|entity| {
+                let uid = entity.uid();
+                let malformed = |attribute| DelegationError::Malformed {
+                    uid: uid.clone(),
+                    attribute,
+                };
+                let entity_attr = |attribute| match entity.attr(attribute) {
+                    Some(Ok(EvalResult::EntityUid(uid))) => Ok(uid),
+                    _ => Err(malformed(attribute)),
+                };
+                let scope = match entity.attr("scope") {
+                    None => Scope::All,
+                    Some(Ok(EvalResult::Set(actions))) => Scope::Actions(
+                        actions
+                            .iter()
+                            .map(|action| match action {
+                                EvalResult::EntityUid(uid) => Ok(uid.clone()),
+                                _ => Err(malformed("scope")),
+                            })
+                            .collect::<Result<_, _>>()?,
+                    ),
+                    Some(_) => return Err(malformed("scope")),
+                };
+                let expires_at = match entity.attr("expiresAt") {
+                    None => None,
+                    Some(Ok(EvalResult::Long(seconds))) => {
+                        Some(u64::try_from(seconds).map_err(|_| malformed("expiresAt"))?)
+                    }
+                    Some(_) => return Err(malformed("expiresAt")),
+                };
+                Ok(Delegation {
+                    delegator: entity_attr("delegator")?,
+                    delegate: entity_attr("delegate")?,
+                    scope,
+                    expires_at,
+                })
+            })
+            .collect()
+    }
+
+    /// Limit chains to `max_chain_length` links. The default is
+    /// [`DEFAULT_MAX_CHAIN_LENGTH`].
+    #[must_use]
+    pub fn with_max_chain_length(mut self, max_chain_length: usize) -> Self {
+        self.max_chain_length = max_chain_length;
+        self
+    }
+
+    /// Add a delegation
+    pub fn add(&mut self, delegation: Delegation) {
+        self.delegations.push(delegation);
+    }
+
+    /// Iterate over the delegations
+    pub fn iter(&self) -> impl Iterator<Item = &Delegation> {
+        self.delegations.iter()
+    }
+
+    /// The shortest chain letting `delegate` act for `delegator` to perform
+    /// `action` at `now`, in seconds since the Unix epoch, or `None` if there
+    /// is none within the chain length limit
+    pub fn resolve(
+        &self,
+        delegate: &EntityUid,
+        delegator: &EntityUid,
+        action: &EntityUid,
+        now: u64,
+        entities: &Entities,
+    ) -> Option<DelegationChain> {
+        let usable: Vec<_> = self
+            .delegations
+            .iter()
+            .filter(|d| !d.is_expired(now) && d.scope.covers(action, entities))
+            .collect();
+        let mut visited = HashSet::from([delegator]);
+        let mut queue = VecDeque::from([(delegator, Vec::new())]);
+        while let Some((current, links)) = queue.pop_front() {
+            if links.len() >= self.max_chain_length {
+                continue;
+            }
+            for link in usable.iter().filter(|d| &d.delegator == current) {
+                let mut links = links.clone();
+                links.push((*link).clone());
+                if &link.delegate == delegate {
+                    return Some(DelegationChain { links });
+                }
+                if visited.insert(&link.delegate) {
+                    queue.push_back((&link.delegate, links));
+                }
+            }
+        }
+        None
+    }
+}
+
+impl FromIterator<Delegation> for Delegations {
+    fn from_iter<T: IntoIterator<Item = Delegation>>(iter: T) -> Self {
+        Self {
+            delegations: iter.into_iter().collect(),
+            ..Self::default()
+        }
+    }
+}
+
+impl Authorizer {
+    /// Answer `r` with its principal acting for `on_behalf_of`: resolve
+    /// `actsFor(principal, on_behalf_of)` using `delegations`, then answer the
+    /// request as though `on_behalf_of` had made it.
+    ///
+    /// A principal always acts for itself.
+    pub fn is_authorized_on_behalf_of(
+        &self,
+        r: &Request,
+        on_behalf_of: &EntityUid,
+        delegations: &Delegations,
+        p: &PolicySet,
+        e: &Entities,
+    ) -> Result<Response, DelegationError> {
+        let (Some(delegate), Some(action)) = (r.principal(), r.action()) else {
+            return Err(DelegationError::Unspecified);
+        };
+        if delegate != on_behalf_of
+            && delegations
+                .resolve(
+                    delegate,
+                    on_behalf_of,
+                    action,
+                    unix_seconds(SystemTime::now()),
+                    e,
+                )
+                .is_none()
+        {
+            return Err(DelegationError::NotDelegated {
+                delegate: delegate.clone(),
+                delegator: on_behalf_of.clone(),
+                action: action.clone(),
+            });
+        }
+        let request = r.with_principal(on_behalf_of);
+        Ok(self.is_authorized(&request, p, e))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Context, Decision};
+    use std::str::FromStr;
+
+    fn uid(ty: &str, id: &str) -> EntityUid {
+        EntityUid::from_strs(ty, id)
+    }
+
+    fn entities() -> Entities {
+        Entities::from_json_str(
+            r#"[
+                { "uid": { "type": "Action", "id": "transfer" }, "attrs": {},
+                  "parents": [{ "type": "Action", "id": "spend" }] },
+                { "uid": { "type": "Delegation", "id": "owner-to-session" },
+                  "attrs": {
+                    "delegator": { "__entity": { "type": "Wallet", "id": "owner" } },
+                    "delegate": { "__entity": { "type": "Wallet", "id": "session" } },
+                    "scope": [{ "__entity": { "type": "Action", "id": "spend" } }],
+                    "expiresAt": 4000000000
+                  },
+                  "parents": [] },
+                { "uid": { "type": "Delegation", "id": "session-to-bot" },
+                  "attrs": {
+                    "delegator": { "__entity": { "type": "Wallet", "id": "session" } },
+                    "delegate": { "__entity": { "type": "Wallet", "id": "bot" } }
+                  },
+                  "parents": [] }
+            ]"#,
+            None,
+        )
+        .unwrap()
+    }
+
+    fn delegations() -> Delegations {
+        Delegations::from_entities(
+            &entities(),
+            &EntityTypeName::from_str("Delegation").unwrap(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn resolve() {
+        let entities = entities();
+        let delegations = delegations();
+        let (owner, bot) = (uid("Wallet", "owner"), uid("Wallet", "bot"));
+        let transfer = uid("Action", "transfer");
+
+        let chain = delegations
+            .resolve(&bot, &owner, &transfer, 0, &entities)
+            .unwrap();
+        assert_eq!(chain.links.len(), 2);
+        assert_eq!(chain.expires_at(), Some(4_000_000_000));
+        assert_eq!(
+            chain.scope(&entities),
+            Scope::Actions(BTreeSet::from([uid("Action", "spend")]))
+        );
+
+        // outside the scope of the first link
+        let vote = uid("Action", "vote");
+        assert!(delegations
+            .resolve(&bot, &owner, &vote, 0, &entities)
+            .is_none());
+        assert!(delegations
+            .resolve(&bot, &uid("Wallet", "session"), &vote, 0, &entities)
+            .is_some());
+        // expired
+        assert!(delegations
+            .resolve(&bot, &owner, &transfer, 4_000_000_000, &entities)
+            .is_none());
+        // too long
+        assert!(delegations
+            .clone()
+            .with_max_chain_length(1)
+            .resolve(&bot, &owner, &transfer, 0, &entities)
+            .is_none());
+        // not in reverse
+        assert!(delegations
+            .resolve(&owner, &bot, &transfer, 0, &entities)
+            .is_none());
+    }
+
+    #[test]
+    fn malformed() {
+        let entities = Entities::from_json_str(
+            r#"[{ "uid": { "type": "Delegation", "id": "d" },
+                  "attrs": { "delegator": "owner", "delegate": { "__entity": { "type": "Wallet", "id": "bot" } } },
+                  "parents": [] }]"#,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            Delegations::from_entities(&entities, &EntityTypeName::from_str("Delegation").unwrap())
+                .unwrap_err(),
+            DelegationError::Malformed {
+                uid: uid("Delegation", "d"),
+                attribute: "delegator",
+            }
+        );
+    }
+
+    #[test]
+    fn on_behalf_of() {
+        let policies =
+            PolicySet::from_str(r#"permit(principal == Wallet::"owner", action, resource);"#)
+                .unwrap();
+        let request = |action| {
+            Request::new(
+                Some(uid("Wallet", "bot")),
+                Some(uid("Action", action)),
+                Some(uid("Vault", "treasury")),
+                Context::empty(),
+            )
+        };
+        let authorizer = Authorizer::new();
+        let (owner, entities, delegations) = (uid("Wallet", "owner"), entities(), delegations());
+
+        let response = authorizer
+            .is_authorized_on_behalf_of(
+                &request("transfer"),
+                &owner,
+                &delegations,
+                &policies,
+                &entities,
+            )
+            .unwrap();
+        assert_eq!(response.decision(), Decision::Allow);
+        assert!(matches!(
+            authorizer.is_authorized_on_behalf_of(
+                &request("vote"),
+                &owner,
+                &delegations,
+                &policies,
+                &entities
+            ),
+            Err(DelegationError::NotDelegated { .. })
+        ));
+        // acting for itself
+        let response = authorizer
+            .is_authorized_on_behalf_of(
+                &request("transfer"),
+                &uid("Wallet", "bot"),
+                &delegations,
+                &policies,
+                &entities,
+            )
+            .unwrap();
+        assert_eq!(response.decision(), Decision::Deny);
+    }
+}
@@ -0,0 +1,504 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Spending limits, compiled to Safe Allowance module configuration.
+//!
+//! A spending limit is a policy annotated `@spendingLimit("<minutes>")`, of
+//! the form:
+//! ```text
+//! @spendingLimit("1440")
+//! permit(
+//!     principal == Wallet::"0x…delegate",
+//!     action == Action::"transfer",
+//!     resource == Token::"0x…token"
+//! ) when { context.amount.u256LessThanOrEqual(u256("1000")) };
+//! ```
+//! It lets the delegate transfer up to the amount of the token, and the
+//! annotation is the period, in minutes, after which the Allowance module
+//! resets the amount spent; `0` means it never resets.
+//!
+//! [`compile()`] produces the Safe transactions configuring the Allowance
+//! module to match, and [`verify()`] checks a module configuration against
+//! the policies, so the two can't drift apart.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
+use thiserror::Error;
+
+use crate::audit::{to_hex, unhex};
+use crate::domain::session::{conjuncts, u256_cap};
+use crate::{
+    ActionConstraint, Effect, EntityUid, Policy, PolicyId, PolicySet, PrincipalConstraint,
+    ResourceConstraint,
+};
+
+/// The annotation marking spending limits, whose value is the reset period
+/// in minutes
+pub const SPENDING_LIMIT_ANNOTATION: &str = "spendingLimit";
+/// The entity type of delegates
+pub const DELEGATE_TYPE: &str = "Wallet";
+/// The entity type of tokens
+pub const TOKEN_TYPE: &str = "Token";
+/// The id of the transfer action
+pub const TRANSFER_ACTION: &str = "transfer";
+
+const ADD_DELEGATE: &str = "addDelegate(address)";
+const SET_ALLOWANCE: &str = "setAllowance(address,address,uint96,uint16,uint32)";
+
+/// Errors compiling spending limits or reading module configuration
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum AllowanceError {
+    /// A policy annotated as a spending limit doesn't have the form of one
+    #[error("`{id}` isn't in the form of a spending limit: {reason}")]
+    Unrecognized {
+        /// The policy
+        id: PolicyId,
+        /// What's wrong with it
+        reason: &'static str,
+    },
+    /// A limit doesn't fit in the module's 96-bit amounts
+    #[error("the limit of `{id}` is larger than the Allowance module supports")]
+    AmountOutOfRange {
+        /// The policy
+        id: PolicyId,
+    },
+    /// Two spending limits apply to the same delegate and token
+    #[error("`{first}` and `{second}` both limit the same delegate and token")]
+    Conflict {
+        /// One policy
+        first: PolicyId,
+        /// The other
+        second: PolicyId,
+    },
+    /// An address isn't 20 bytes of hex
+    #[error("`{0}` isn't an address")]
+    InvalidAddress(String),
+    /// A transaction to the module isn't a call this module understands
+    #[error("malformed Allowance module transaction: {0}")]
+    MalformedTransaction(&'static str),
+}
+
+/// A delegate's allowance of a token
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpendingLimit {
+    /// The address allowed to spend
+    pub delegate: String,
+    /// The token address. The zero address is Ether.
+    pub token: String,
+    /// The amount it may spend in each period
+    pub amount: u128,
+    /// The period, in minutes; `0` means the allowance never resets
+    pub reset_time_min: u16,
+}
+
+/// A transaction for a Safe to execute
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SafeTransaction {
+    /// The contract called
+    pub to: String,
+    /// The value sent, in wei
+    pub value: String,
+    /// The calldata, as `0x`-prefixed hex
+    pub data: String,
+}
+
+/// A difference between the spending limits and the module configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Drift {
+    /// A spending limit isn't configured in the module
+    Missing {
+        /// The policy
+        policy: PolicyId,
+        /// Its limit
+        limit: SpendingLimit,
+    },
+    /// The module configures an allowance no policy grants
+    Unexpected(SpendingLimit),
+    /// The module configures a different allowance from the policy
+    Mismatch {
+        /// The policy
+        policy: PolicyId,
+        /// Its limit
+        expected: SpendingLimit,
+        /// The module's
+        actual: SpendingLimit,
+    },
+}
+
+impl SpendingLimit {
+    /// Read the limit from `policy`, which must have the form of a spending
+    /// limit; see the [module documentation](self)
+    pub fn from_policy(policy: &Policy) -> Result<Self, AllowanceError> {
+        let unrecognized = |reason| AllowanceError::Unrecognized {
+            id: policy.id().clone(),
+            reason,
+        };
+        let reset_time_min = policy
+            .annotation(SPENDING_LIMIT_ANNOTATION)
+            .ok_or_else(|| unrecognized("no `@spendingLimit` annotation"))?
+            .parse()
+            .map_err(|_| unrecognized("the reset period isn't a number of minutes"))?;
+        if policy.effect() != Effect::Permit {
+            return Err(unrecognized("not a permit policy"));
+        }
+        let PrincipalConstraint::Eq(delegate) = policy.principal_constraint() else {
+            return Err(unrecognized(
+                "expected `principal == Wallet::\"<address>\"`",
+            ));
+        };
+        let ResourceConstraint::Eq(token) = policy.resource_constraint() else {
+            return Err(unrecognized("expected `resource == Token::\"<address>\"`"));
+        };
+        match policy.action_constraint() {
+            ActionConstraint::Eq(action) if action.id().as_ref() == TRANSFER_ACTION => {}
+            _ => return Err(unrecognized("expected `action == Action::\"transfer\"`")),
+        }
+        let mut caps = conjuncts(policy.ast.non_head_constraints())
+            .into_iter()
+            .map(|condition| u256_cap(condition, "amount"));
+        let (Some(Some(cap)), None) = (caps.next(), caps.next()) else {
+            return Err(unrecognized(
+                "expected only `context.amount.u256LessThanOrEqual(u256(\"<amount>\"))`",
+            ));
+        };
+        let amount = cap
+            .parse::<u128>()
+            .ok()
+            .filter(|amount| amount >> 96 == 0)
+            .ok_or_else(|| AllowanceError::AmountOutOfRange {
+                id: policy.id().clone(),
+            })?;
+        Ok(Self {
+            delegate: entity_address(&delegate, DELEGATE_TYPE)
+                .ok_or_else(|| unrecognized("the principal isn't a Wallet address"))?,
+            token: entity_address(&token, TOKEN_TYPE)
+                .ok_or_else(|| unrecognized("the resource isn't a Token address"))?,
+            amount,
+            reset_time_min,
+        })
+    }
+
+    /// The `setAllowance` call configuring this limit on the module at
+    /// `module`, with the first period starting `reset_base_min` minutes
+    /// after the Unix epoch
+    pub fn set_allowance(
+        &self,
+        module: &str,
+        reset_base_min: u32,
+    ) -> Result<SafeTransaction, AllowanceError> {
+        call(
+            module,
+            SET_ALLOWANCE,
+            &[
+                address_word(&self.delegate)?,
+                address_word(&self.token)?,
+                uint_word(self.amount),
+                uint_word(self.reset_time_min.into()),
+                uint_word(reset_base_min.into()),
+            ],
+        )
+    }
+
+    /// Read the limit configured by `transaction`, if it's a `setAllowance`
+    /// call. Returns `None` for `addDelegate` calls.
+    pub fn from_transaction(transaction: &SafeTransaction) -> Result<Option<Self>, AllowanceError> {
+        let data = transaction
+            .data
+            .strip_prefix("0x")
+            .and_then(unhex)
+            .ok_or(AllowanceError::MalformedTransaction("the data isn't hex"))?;
+        let (selector_bytes, words) = data.split_at(data.len().min(4));
+        if selector_bytes == selector(ADD_DELEGATE) {
+            return Ok(None);
+        }
+        if selector_bytes != selector(SET_ALLOWANCE) {
+            return Err(AllowanceError::MalformedTransaction("unknown function"));
+        }
+        let words: Vec<&[u8]> = words.chunks(32).collect();
+        let [delegate, token, amount, reset_time_min, _reset_base_min] = words.as_slice() else {
+            return Err(AllowanceError::MalformedTransaction(
+                "wrong number of arguments",
+            ));
+        };
+        let malformed = || AllowanceError::MalformedTransaction("malformed arguments");
+        Ok(Some(Self {
+            delegate: word_address(delegate).ok_or_else(malformed)?,
+            token: word_address(token).ok_or_else(malformed)?,
+            amount: word_uint(amount)
+                .filter(|amount| amount >> 96 == 0)
+                .ok_or_else(malformed)?,
+            reset_time_min: word_uint(reset_time_min)
+                .and_then(|min| u16::try_from(min).ok())
+                .ok_or_else(malformed)?,
+        }))
+    }
+}
+
+/// The spending limits in `policies`, sorted by policy id
+pub fn spending_limits(
+    policies: &PolicySet,
+) -> Result<Vec<(PolicyId, SpendingLimit)>, AllowanceError> {
+    let mut limits = Vec::new();
+    let mut by_target: BTreeMap<(String, String), PolicyId> = BTreeMap::new();
+    for policy in policies
+        .policies()
+        .filter(|p| p.annotation(SPENDING_LIMIT_ANNOTATION).is_some())
+    {
+        let limit = SpendingLimit::from_policy(policy)?;
+        let target = (limit.delegate.clone(), limit.token.clone());
+        if let Some(first) = by_target.insert(target, policy.id().clone()) {
+            return Err(AllowanceError::Conflict {
+                first,
+                second: policy.id().clone(),
+            });
+        }
+        limits.push((policy.id().clone(), limit));
+    }
+    limits.sort_by(|(a, _), (b, _)| a.as_ref().cmp(b.as_ref()));
+    Ok(limits)
+}
+
+/// The transactions configuring the Allowance module at `module` with the
+/// spending limits in `policies`: an `addDelegate` call for each delegate,
+/// then a `setAllowance` call for each limit. Periods start
+/// `reset_base_min` minutes after the Unix epoch.
+pub fn compile(
+    policies: &PolicySet,
+    module: &str,
+    reset_base_min: u32,
+) -> Result<Vec<SafeTransaction>, AllowanceError> {
+    let limits = spending_limits(policies)?;
+    let mut delegates: Vec<_> = limits.iter().map(|(_, l)| l.delegate.as_str()).collect();
+    delegates.sort_unstable();
+    delegates.dedup();
+    let mut transactions = delegates
+        .into_iter()
+        .map(|delegate| call(module, ADD_DELEGATE, &[address_word(delegate)?]))
+        .collect::<Result<Vec<_>, _>>()?;
+    for (_, limit) in &limits {
+        transactions.push(limit.set_allowance(module, reset_base_min)?);
+    }
+    Ok(transactions)
+}
+
+/// Compare the spending limits in `policies` with the allowances configured
+/// by `transactions`, which are taken in order, so a later `setAllowance`
+/// overrides an earlier one. Transactions to contracts other than `module`
+/// are ignored. Returns the differences, which are empty if the two match.
+pub fn verify(
+    policies: &PolicySet,
+    module: &str,
+    transactions: &[SafeTransaction],
+) -> Result<Vec<Drift>, AllowanceError> {
+    let module = address(module)?;
+    let mut configured = BTreeMap::new();
+    for transaction in transactions {
+        if address(&transaction.to)? != module {
+            continue;
+        }
+        if let Some(limit) = SpendingLimit::from_transaction(transaction)? {
+            configured.insert((limit.delegate.clone(), limit.token.clone()), limit);
+        }
+    }
+    let mut drift = Vec::new();
+    for (policy, expected) in spending_limits(policies)? {
+        match configured.remove(&(expected.delegate.clone(), expected.token.clone())) {
+            None => drift.push(Drift::Missing {
+                policy,
+                limit: expected,
+            }),
+            Some(actual) if actual != expected => drift.push(Drift::Mismatch {
+                policy,
+                expected,
+                actual,
+            }),
+            Some(_) => {}
+        }
+    }
+    drift.extend(configured.into_values().map(Drift::Unexpected));
+    Ok(drift)
+}
+
+/// A call of `signature` on `to` with the ABI-encoded `words`
+fn call(to: &str, signature: &str, words: &[[u8; 32]]) -> Result<SafeTransaction, AllowanceError> {
+    let mut data = selector(signature).to_vec();
+    for word in words {
+        data.extend_from_slice(word);
+    }
+    Ok(SafeTransaction {
+        to: address(to)?,
+        value: "0".to_string(),
+        data: format!("0x{}", to_hex(&data)),
+    })
+}
+
+/// The function selector of `signature`
+fn selector(signature: &str) -> [u8; 4] {
+    let hash: [u8; 32] = Keccak256::digest(signature.as_bytes()).into();
+    let [a, b, c, d, ..] = hash;
+    [a, b, c, d]
+}
+
+/// `s` in canonical form, if it's an address
+fn address(s: &str) -> Result<String, AllowanceError> {
+    crate::domain::session::address(s).map_err(|_| AllowanceError::InvalidAddress(s.to_string()))
+}
+
+/// The address `uid` names, if it's of type `ty`
+fn entity_address(uid: &EntityUid, ty: &str) -> Option<String> {
+    if uid.type_name().to_string() != ty {
+        return None;
+    }
+    address(uid.id().as_ref()).ok()
+}
+
+fn address_word(s: &str) -> Result<[u8; 32], AllowanceError> {
+    let invalid = || AllowanceError::InvalidAddress(s.to_string());
+    let address = address(s)?;
+    let bytes = unhex(address.strip_prefix("0x").ok_or_else(invalid)?).ok_or_else(invalid)?;
+    Ok(right_aligned(&bytes))
+}
+
+fn uint_word(n: u128) -> [u8; 32] {
+    right_aligned(&n.to_be_bytes())
+}
+
+/// A word holding `bytes` at its end, padded with leading zeros
+fn right_aligned(bytes: &[u8]) -> [u8; 32] {
+    let mut word = [0; 32];
+    for (w, b) in word.iter_mut().rev().zip(bytes.iter().rev()) {
+        *w = *b;
+    }
+    word
+}
+
+fn word_address(word: &[u8]) -> Option<String> {
+    if word.len() != 32 {
+        return None;
+    }
+    let (padding, address) = word.split_at(12);
+    padding
+        .iter()
+        .all(|b| *b == 0)
+        .then(|| format!("0x{}", to_hex(address)))
+}
+
+fn word_uint(word: &[u8]) -> Option<u128> {
+    if word.len() != 32 {
+        return None;
+    }
+    let (padding, n) = word.split_at(16);
+    padding
+        .iter()
+        .all(|b| *b == 0)
+        .then(|| n.iter().fold(0, |n, b| (n << 8) | u128::from(*b)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+
+    const MODULE: &str = "0xCFbFaC74C26F8647cBDb8c5caf80BB5b32E43134";
+    const DELEGATE: &str = "0x00000000000000000000000000000000000000bb";
+    const USDC: &str = "0x00000000000000000000000000000000000000cc";
+
+    fn limit_policy(id: &str, amount: &str) -> Policy {
+        Policy::parse(
+            Some(id.to_string()),
+            format!(
+                r#"@spendingLimit("1440")
+                permit(principal == Wallet::"{DELEGATE}", action == Action::"transfer", resource == Token::"{USDC}")
+                when {{ context.amount.u256LessThanOrEqual(u256("{amount}")) }};"#
+            ),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn selectors() {
+        assert_eq!(to_hex(&selector(ADD_DELEGATE)), "e71bdf41");
+        assert_eq!(to_hex(&selector(SET_ALLOWANCE)), "beaeb388");
+    }
+
+    #[test]
+    fn compile_and_verify() {
+        let policies = PolicySet::from_policies([limit_policy("usdc", "1000")]).unwrap();
+        let transactions = compile(&policies, MODULE, 28_000_000).unwrap();
+        assert_eq!(transactions.len(), 2);
+        assert_eq!(
+            SpendingLimit::from_transaction(&transactions[1]).unwrap(),
+            Some(SpendingLimit {
+                delegate: DELEGATE.to_string(),
+                token: USDC.to_string(),
+                amount: 1000,
+                reset_time_min: 1440,
+            })
+        );
+        assert_eq!(verify(&policies, MODULE, &transactions).unwrap(), []);
+
+        let raised = PolicySet::from_policies([limit_policy("usdc", "2000")]).unwrap();
+        assert!(matches!(
+            verify(&raised, MODULE, &transactions).unwrap().as_slice(),
+            [Drift::Mismatch { expected, actual, .. }] if expected.amount == 2000 && actual.amount == 1000
+        ));
+        assert!(matches!(
+            verify(&policies, MODULE, &[]).unwrap().as_slice(),
+            [Drift::Missing { .. }]
+        ));
+        assert!(matches!(
+            verify(&PolicySet::new(), MODULE, &transactions)
+                .unwrap()
+                .as_slice(),
+            [Drift::Unexpected(_)]
+        ));
+    }
+
+    #[test]
+    fn unrecognized() {
+        let too_large =
+            PolicySet::from_policies([limit_policy("usdc", &(1u128 << 96).to_string())]).unwrap();
+        assert_eq!(
+            compile(&too_large, MODULE, 0),
+            Err(AllowanceError::AmountOutOfRange {
+                id: PolicyId::from_str("usdc").unwrap()
+            })
+        );
+        let conditional = Policy::parse(
+            Some("conditional".to_string()),
+            format!(
+                r#"@spendingLimit("0")
+                permit(principal == Wallet::"{DELEGATE}", action == Action::"transfer", resource == Token::"{USDC}")
+                when {{ context.amount.u256LessThanOrEqual(u256("1")) && context.weekday }};"#
+            ),
+        )
+        .unwrap();
+        assert!(matches!(
+            SpendingLimit::from_policy(&conditional),
+            Err(AllowanceError::Unrecognized { .. })
+        ));
+        let conflict =
+            PolicySet::from_policies([limit_policy("a", "1"), limit_policy("b", "2")]).unwrap();
+        assert!(matches!(
+            spending_limits(&conflict),
+            Err(AllowanceError::Conflict { .. })
+        ));
+    }
+}
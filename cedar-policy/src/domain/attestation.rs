@@ -0,0 +1,656 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Ethereum Attestation Service attestations, as entity attributes and
+//! parents.
+//!
+//! An [`AttestationProvider`] fetches the attestations made to a wallet from
+//! an [`AttestationSource`], decodes the data of those whose schema it knows
+//! (see [`AttestationSchema`]), and makes the wallet an `Address` entity
+//! with:
+//! - a parent `Attestation::"<schema name>"` for each schema it holds a
+//!   valid attestation of
+//! - an attribute `attestations`, a record with a record for each of those
+//!   schemas, mapping each attester's address to its latest valid
+//!   attestation: a record with the `uid`, `time`, and `expirationTime` of
+//!   the attestation, and its decoded `data`
+//!
+//! so a policy can require an attestation of a type from an attester, e.g.
+//! ```text
+//! permit(principal in Attestation::"kyc", action == Action::"withdraw", resource)
+//! when {
+//!   principal.attestations.kyc has "0x4200000000000000000000000000000000000021" &&
+//!   principal.attestations.kyc["0x4200000000000000000000000000000000000021"].data.verified
+//! };
+//! ```
+//!
+//! An attestation is valid if it isn't revoked and hasn't expired.
+//! Addresses are lowercase. Data fields which are `uint`s become `u256`
+//! values, `int`s become `Long`s, `address`es and `bytes` become hex
+//! strings, and arrays become sets.
+//!
+//! [`MemoryAttestationSource`] holds attestations in memory. With the `eas`
+//! feature, `EasGraphqlSource` fetches them from an EAS GraphQL indexer.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Debug;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::SystemTime;
+
+use cedar_policy_core::ast;
+use ethers::abi::{self, param_type::Reader, ParamType, Token};
+use ethers::types::{I256, U256};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::address_book::ADDRESS_TYPE;
+use crate::audit::{normalize_address, to_hex, unhex, unix_seconds};
+use crate::{Entities, EntitiesError, Entity, EntityTypeName, EntityUid, RestrictedExpression};
+
+/// The entity attribute holding a wallet's attestations
+pub const ATTESTATIONS_ATTRIBUTE: &str = "attestations";
+
+/// The entity type of the parents for the schemas of a wallet's
+/// attestations
+pub const ATTESTATION_TYPE: &str = "Attestation";
+
+/// Errors resolving attestations
+#[derive(Debug, Error)]
+pub enum AttestationError {
+    /// A schema definition isn't a list of Solidity types and names
+    #[error("invalid schema `{0}`")]
+    InvalidSchema(String),
+    /// A value isn't an address
+    #[error("`{0}` is not an address")]
+    InvalidAddress(String),
+    /// An attestation's data doesn't match its schema
+    #[error("attestation {uid} doesn't match its schema: {message}")]
+    InvalidData {
+        /// The attestation
+        uid: String,
+        /// What went wrong
+        message: String,
+    },
+    /// The source failed
+    #[error("failed to fetch attestations: {0}")]
+    Source(String),
+    /// The entities couldn't be built
+    #[error(transparent)]
+    Entities(#[from] EntitiesError),
+}
+
+/// An attestation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Attestation {
+    /// Its uid, as `0x`-prefixed hex
+    pub uid: String,
+    /// The uid of its schema, as `0x`-prefixed hex
+    pub schema: String,
+    /// The address which made it
+    pub attester: String,
+    /// The address it was made to
+    pub recipient: String,
+    /// When it was made, in seconds since the Unix epoch
+    pub time: u64,
+    /// When it expires, in seconds since the Unix epoch, or 0 if it doesn't
+    #[serde(default)]
+    pub expiration_time: u64,
+    /// When it was revoked, in seconds since the Unix epoch, or 0 if it
+    /// wasn't
+    #[serde(default)]
+    pub revocation_time: u64,
+    /// Its ABI-encoded data, as `0x`-prefixed hex
+    pub data: String,
+}
+
+impl Attestation {
+    /// Whether it is neither revoked nor expired at `now`, in seconds since
+    /// the Unix epoch
+    pub fn is_valid(&self, now: u64) -> bool {
+        self.revocation_time == 0 && (self.expiration_time == 0 || self.expiration_time > now)
+    }
+}
+
+/// A schema of attestations: its uid, the name policies know it by, and the
+/// fields of its data
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttestationSchema {
+    uid: String,
+    name: String,
+    fields: Vec<(String, ParamType)>,
+}
+
+impl AttestationSchema {
+    /// The schema `uid` named `name`, with the fields of `definition`, which
+    /// is in the format of the EAS schema registry, e.g.
+    /// `"bool verified, uint8 level, string country"`
+    pub fn new(
+        uid: &str,
+        name: impl Into<String>,
+        definition: &str,
+    ) -> Result<Self, AttestationError> {
+        let invalid = || AttestationError::InvalidSchema(definition.to_string());
+        let fields = definition
+            .split(',')
+            .map(str::trim)
+            .filter(|field| !field.is_empty())
+            .map(|field| {
+                let (ty, name) = field.rsplit_once(' ').ok_or_else(invalid)?;
+                let ty = ty.trim();
+                if !is_elementary(ty.split('[').next().unwrap_or(ty)) {
+                    return Err(invalid());
+                }
+                let ty = Reader::read(ty).map_err(|_| invalid())?;
+                Ok((name.to_string(), ty))
+            })
+            .collect::<Result<Vec<_>, AttestationError>>()?;
+        Ok(Self {
+            uid: uid.to_ascii_lowercase(),
+            name: name.into(),
+            fields,
+        })
+    }
+
+    /// The uid of the schema
+    pub fn uid(&self) -> &str {
+        &self.uid
+    }
+
+    /// The name of the schema
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The data of `attestation`, as a record
+    fn decode(&self, attestation: &Attestation) -> Result<RestrictedExpression, AttestationError> {
+        let invalid = |message: String| AttestationError::InvalidData {
+            uid: attestation.uid.clone(),
+            message,
+        };
+        let data = attestation
+            .data
+            .strip_prefix("0x")
+            .unwrap_or(&attestation.data);
+        let bytes = unhex(data).ok_or_else(|| invalid("data isn't hex".to_string()))?;
+        let types: Vec<_> = self.fields.iter().map(|(_, ty)| ty.clone()).collect();
+        let tokens = abi::decode(&types, &bytes).map_err(|err| invalid(err.to_string()))?;
+        let fields = self
+            .fields
+            .iter()
+            .zip(tokens)
+            .map(|((name, _), token)| Ok((name.clone(), token_expr(token).map_err(&invalid)?)))
+            .collect::<Result<Vec<_>, AttestationError>>()?;
+        Ok(RestrictedExpression::new_record(fields))
+    }
+}
+
+/// Whether `ty` is the name of an elementary Solidity type, e.g. `uint8`
+fn is_elementary(ty: &str) -> bool {
+    let sized = |prefix: &str| {
+        ty.strip_prefix(prefix)
+            .is_some_and(|size| size.is_empty() || size.bytes().all(|b| b.is_ascii_digit()))
+    };
+    matches!(ty, "address" | "bool" | "string") || sized("bytes") || sized("uint") || sized("int")
+}
+
+/// A decoded ABI value, as a Cedar value
+fn token_expr(token: Token) -> Result<RestrictedExpression, String> {
+    Ok(match token {
+        Token::Bool(b) => RestrictedExpression::new_bool(b),
+        Token::String(s) => RestrictedExpression::new_string(s),
+        Token::Address(a) => {
+            RestrictedExpression::new_string(format!("0x{}", to_hex(a.as_bytes())))
+        }
+        Token::Bytes(b) | Token::FixedBytes(b) => {
+            RestrictedExpression::new_string(format!("0x{}", to_hex(&b)))
+        }
+        Token::Uint(n) => u256_expr(n),
+        Token::Int(n) => RestrictedExpression::new_long(
+            i64::try_from(I256::from_raw(n))
+                .map_err(|_| format!("{} is too large", I256::from_raw(n)))?,
+        ),
+        Token::Array(tokens) | Token::FixedArray(tokens) => RestrictedExpression::new_set(
+            tokens
+                .into_iter()
+                .map(token_expr)
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+        Token::Tuple(_) => return Err("tuples are not supported".to_string()),
+    })
+}
+
+fn u256_expr(n: U256) -> RestrictedExpression {
+    // PANIC SAFETY: a decimal integer is a valid argument to `u256`
+    #[allow(clippy::expect_used)]
+    RestrictedExpression::from_str(&format!("u256(\"{n}\")")).expect("valid u256 expression")
+}
+
+/// A source of the attestations made to wallets
+pub trait AttestationSource: Debug + Send + Sync {
+    /// The attestations made to `recipient`, a lowercase `0x`-prefixed hex
+    /// address, including revoked and expired ones
+    fn attestations(&self, recipient: &str) -> Result<Vec<Attestation>, AttestationError>;
+}
+
+/// Attestations held in memory
+#[derive(Debug, Default)]
+pub struct MemoryAttestationSource {
+    attestations: Mutex<HashMap<String, Vec<Attestation>>>,
+}
+
+impl MemoryAttestationSource {
+    /// A source with no attestations
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `attestation`
+    pub fn insert(&self, attestation: Attestation) -> Result<(), AttestationError> {
+        let recipient = normalize_address(&attestation.recipient)
+            .ok_or_else(|| AttestationError::InvalidAddress(attestation.recipient.clone()))?;
+        self.attestations
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(recipient)
+            .or_default()
+            .push(attestation);
+        Ok(())
+    }
+}
+
+impl AttestationSource for MemoryAttestationSource {
+    fn attestations(&self, recipient: &str) -> Result<Vec<Attestation>, AttestationError> {
+        Ok(self
+            .attestations
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(recipient)
+            .cloned()
+            .unwrap_or_default())
+    }
+}
+
+/// Resolves the attestations made to wallets into entities
+#[derive(Debug)]
+pub struct AttestationProvider {
+    source: Arc<dyn AttestationSource>,
+    schemas: HashMap<String, AttestationSchema>,
+    entity_type: EntityTypeName,
+}
+
+impl AttestationProvider {
+    /// A provider of `Address` entities with the attestations from `source`
+    pub fn new(source: Arc<dyn AttestationSource>) -> Self {
+        Self {
+            source,
+            schemas: HashMap::new(),
+            entity_type: address_type(),
+        }
+    }
+
+    /// Resolve attestations of `schema`. Attestations of schemas which
+    /// aren't added are ignored.
+    #[must_use]
+    pub fn with_schema(mut self, schema: AttestationSchema) -> Self {
+        self.schemas.insert(schema.uid.clone(), schema);
+        self
+    }
+
+    /// Make wallets entities of type `entity_type`, instead of `Address`
+    #[must_use]
+    pub fn with_entity_type(mut self, entity_type: EntityTypeName) -> Self {
+        self.entity_type = entity_type;
+        self
+    }
+
+    /// The entity for `address` with its valid attestations at `now`
+    pub fn entity(&self, address: &str, now: SystemTime) -> Result<Entity, AttestationError> {
+        let address = normalize_address(address)
+            .ok_or_else(|| AttestationError::InvalidAddress(address.to_string()))?;
+        let now = unix_seconds(now);
+        // the latest valid attestation of each schema by each attester
+        let mut latest: BTreeMap<(&str, String), (&AttestationSchema, Attestation)> =
+            BTreeMap::new();
+        for attestation in self.source.attestations(&address)? {
+            let Some(schema) = self.schemas.get(&attestation.schema.to_ascii_lowercase()) else {
+                continue;
+            };
+            if !attestation.is_valid(now) {
+                continue;
+            }
+            let attester = normalize_address(&attestation.attester)
+                .ok_or_else(|| AttestationError::InvalidAddress(attestation.attester.clone()))?;
+            match latest.get(&(schema.name(), attester.clone())) {
+                Some((_, prior)) if prior.time >= attestation.time => {}
+                _ => {
+                    latest.insert((schema.name(), attester), (schema, attestation));
+                }
+            }
+        }
+        let mut by_schema: BTreeMap<&str, Vec<(String, RestrictedExpression)>> = BTreeMap::new();
+        for ((name, attester), (schema, attestation)) in latest {
+            let record = RestrictedExpression::new_record([
+                (
+                    "uid".to_string(),
+                    RestrictedExpression::new_string(attestation.uid.to_ascii_lowercase()),
+                ),
+                (
+                    "time".to_string(),
+                    RestrictedExpression::new_long(attestation.time.try_into().unwrap_or(i64::MAX)),
+                ),
+                (
+                    "expirationTime".to_string(),
+                    RestrictedExpression::new_long(
+                        attestation.expiration_time.try_into().unwrap_or(i64::MAX),
+                    ),
+                ),
+                ("data".to_string(), schema.decode(&attestation)?),
+            ]);
+            by_schema.entry(name).or_default().push((attester, record));
+        }
+        let parents: HashSet<EntityUid> =
+            by_schema.keys().map(|name| attestation_uid(name)).collect();
+        let attestations =
+            RestrictedExpression::new_record(by_schema.into_iter().map(|(name, attesters)| {
+                (
+                    name.to_string(),
+                    RestrictedExpression::new_record(attesters),
+                )
+            }));
+        Ok(Entity::new(
+            EntityUid(ast::EntityUID::from_components(
+                self.entity_type.0.clone(),
+                ast::Eid::new(address),
+            )),
+            HashMap::from([(ATTESTATIONS_ATTRIBUTE.to_string(), attestations)]),
+            parents,
+        ))
+    }
+
+    /// The entities for `addresses` with their valid attestations at `now`
+    pub fn entities_for<'a>(
+        &self,
+        addresses: impl IntoIterator<Item = &'a str>,
+        now: SystemTime,
+    ) -> Result<Entities, AttestationError> {
+        let entities = addresses
+            .into_iter()
+            .map(|address| self.entity(address, now))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Entities::from_entities(entities)?)
+    }
+}
+
+/// The `Address` entity type
+fn address_type() -> EntityTypeName {
+    // PANIC SAFETY: `Address` is a valid entity type name
+    #[allow(clippy::expect_used)]
+    EntityTypeName::from_str(ADDRESS_TYPE).expect("`Address` is a valid entity type name")
+}
+
+/// The parent for attestations of the schema `name`
+fn attestation_uid(name: &str) -> EntityUid {
+    EntityUid::from_strs(ATTESTATION_TYPE, name)
+}
+
+#[cfg(feature = "eas")]
+pub use eas::{EasGraphqlSource, EAS_MAINNET_GRAPHQL};
+
+#[cfg(feature = "eas")]
+mod eas {
+    use super::{Attestation, AttestationError, AttestationSource};
+    use serde::Deserialize;
+    use serde_json::json;
+
+    /// The EAS GraphQL indexer for Ethereum mainnet
+    pub const EAS_MAINNET_GRAPHQL: &str = "https://easscan.org/graphql";
+
+    const QUERY: &str = "query Attestations($recipient: String!) { \
+        attestations(where: { recipient: { equals: $recipient } }) { \
+        id schemaId attester recipient time expirationTime revocationTime data } }";
+
+    #[derive(Debug, Deserialize)]
+    struct Response {
+        data: Data,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Data {
+        attestations: Vec<Indexed>,
+    }
+
+    /// An attestation as the indexer returns it
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Indexed {
+        id: String,
+        schema_id: String,
+        attester: String,
+        recipient: String,
+        time: u64,
+        expiration_time: u64,
+        revocation_time: u64,
+        data: String,
+    }
+
+    /// Fetches attestations from an EAS GraphQL indexer, such as
+    /// [easscan](https://easscan.org)
+    ///
+    /// Its requests are [blocking](crate::domain#network-requests).
+    #[derive(Debug)]
+    pub struct EasGraphqlSource {
+        client: reqwest::blocking::Client,
+        endpoint: String,
+    }
+
+    impl EasGraphqlSource {
+        /// A source using the indexer at `endpoint`, e.g. [`EAS_MAINNET_GRAPHQL`]
+        pub fn new(endpoint: impl Into<String>) -> Self {
+            Self {
+                client: reqwest::blocking::Client::new(),
+                endpoint: endpoint.into(),
+            }
+        }
+    }
+
+    impl AttestationSource for EasGraphqlSource {
+        fn attestations(&self, recipient: &str) -> Result<Vec<Attestation>, AttestationError> {
+            // the indexer stores checksummed addresses
+            let recipient = ethers::utils::to_checksum(
+                &recipient
+                    .parse()
+                    .map_err(|_| AttestationError::InvalidAddress(recipient.to_string()))?,
+                None,
+            );
+            let body = self
+                .client
+                .post(&self.endpoint)
+                .json(&json!({ "query": QUERY, "variables": { "recipient": recipient } }))
+                .send()
+                .and_then(reqwest::blocking::Response::error_for_status)
+                .and_then(reqwest::blocking::Response::text)
+                .map_err(|err| AttestationError::Source(err.to_string()))?;
+            parse_response(&body)
+        }
+    }
+
+    /// The attestations in the indexer's response `body`
+    pub(super) fn parse_response(body: &str) -> Result<Vec<Attestation>, AttestationError> {
+        let response: Response =
+            serde_json::from_str(body).map_err(|err| AttestationError::Source(err.to_string()))?;
+        Ok(response
+            .data
+            .attestations
+            .into_iter()
+            .map(|a| Attestation {
+                uid: a.id,
+                schema: a.schema_id,
+                attester: a.attester,
+                recipient: a.recipient,
+                time: a.time,
+                expiration_time: a.expiration_time,
+                revocation_time: a.revocation_time,
+                data: a.data,
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Authorizer, Context, Decision, PolicySet, Request};
+    use std::time::Duration;
+
+    const KYC: &str = "0xf8b05c79f090979bf4a80270aba232dff11a10d9ca55c4f88de95317970f0de9";
+    const OTHER: &str = "0x0000000000000000000000000000000000000000000000000000000000000001";
+    const WALLET: &str = "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266";
+    const ISSUER: &str = "0x4200000000000000000000000000000000000021";
+    const STRANGER: &str = "0x70997970c51812dc3a010c7d01b50e0d17dc79c8";
+
+    fn kyc_data(verified: bool, level: u8, country: &str) -> String {
+        format!(
+            "0x{}",
+            to_hex(&abi::encode(&[
+                Token::Bool(verified),
+                Token::Uint(level.into()),
+                Token::String(country.to_string()),
+            ]))
+        )
+    }
+
+    fn attestation(uid: u8, attester: &str, time: u64, data: String) -> Attestation {
+        Attestation {
+            uid: format!("0x{uid:064x}"),
+            schema: KYC.to_string(),
+            attester: attester.to_string(),
+            recipient: WALLET.to_string(),
+            time,
+            expiration_time: 0,
+            revocation_time: 0,
+            data,
+        }
+    }
+
+    fn kyc_provider(source: MemoryAttestationSource) -> AttestationProvider {
+        AttestationProvider::new(Arc::new(source)).with_schema(
+            AttestationSchema::new(KYC, "kyc", "bool verified, uint8 level, string country")
+                .unwrap(),
+        )
+    }
+
+    fn at(seconds: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    #[test]
+    fn schemas() {
+        let schema =
+            AttestationSchema::new(KYC, "kyc", "address[] signers, bytes32 id,int8 x").unwrap();
+        assert_eq!(schema.fields.len(), 3);
+        assert!(AttestationSchema::new(KYC, "kyc", "bool").is_err());
+        assert!(AttestationSchema::new(KYC, "kyc", "boolean verified").is_err());
+        assert!(AttestationSchema::new(KYC, "kyc", "(bool,uint8) pair").is_err());
+    }
+
+    #[test]
+    fn resolves_valid_attestations() {
+        let source = MemoryAttestationSource::new();
+        source
+            .insert(attestation(1, ISSUER, 100, kyc_data(true, 1, "US")))
+            .unwrap();
+        source
+            .insert(attestation(2, ISSUER, 200, kyc_data(true, 2, "US")))
+            .unwrap();
+        // revoked, expired, and unknown attestations are ignored
+        let mut revoked = attestation(3, STRANGER, 300, kyc_data(true, 3, "US"));
+        revoked.revocation_time = 400;
+        source.insert(revoked).unwrap();
+        let mut expired = attestation(4, STRANGER, 300, kyc_data(true, 3, "US"));
+        expired.expiration_time = 500;
+        source.insert(expired).unwrap();
+        let mut unknown = attestation(5, STRANGER, 300, "0x".to_string());
+        unknown.schema = OTHER.to_string();
+        source.insert(unknown).unwrap();
+        let provider = kyc_provider(source);
+
+        let policies = PolicySet::from_str(&format!(
+            r#"permit(principal in Attestation::"kyc", action, resource)
+               when {{
+                 principal.attestations.kyc has "{ISSUER}" &&
+                 principal.attestations.kyc["{ISSUER}"].data.verified &&
+                 principal.attestations.kyc["{ISSUER}"].data.level.u256GreaterThan(u256("1"))
+               }};
+               forbid(principal, action, resource)
+               when {{ principal.attestations.kyc has "{STRANGER}" }};"#
+        ))
+        .unwrap();
+        let request = Request::new(
+            Some(EntityUid::from_strs(
+                "Address",
+                &WALLET.to_ascii_lowercase(),
+            )),
+            Some(EntityUid::from_strs("Action", "withdraw")),
+            Some(EntityUid::from_strs("Vault", "v")),
+            Context::empty(),
+        );
+        let decide = |entities: &Entities| {
+            Authorizer::new()
+                .is_authorized(&request, &policies, entities)
+                .decision()
+        };
+
+        // only the latest attestation of each attester counts
+        let entities = provider.entities_for([WALLET], at(1_000)).unwrap();
+        assert_eq!(decide(&entities), Decision::Allow);
+
+        let empty = kyc_provider(MemoryAttestationSource::new())
+            .entities_for([WALLET], at(1_000))
+            .unwrap();
+        assert_eq!(decide(&empty), Decision::Deny);
+    }
+
+    #[test]
+    fn malformed_data() {
+        let source = MemoryAttestationSource::new();
+        source
+            .insert(attestation(1, ISSUER, 100, "0x1234".to_string()))
+            .unwrap();
+        assert!(matches!(
+            kyc_provider(source).entity(WALLET, at(1_000)),
+            Err(AttestationError::InvalidData { .. })
+        ));
+        assert!(matches!(
+            kyc_provider(MemoryAttestationSource::new()).entity("alice", at(1_000)),
+            Err(AttestationError::InvalidAddress(_))
+        ));
+    }
+
+    #[cfg(feature = "eas")]
+    #[test]
+    fn graphql_responses() {
+        let attestations = eas::parse_response(&format!(
+            r#"{{"data":{{"attestations":[{{"id":"0x01","schemaId":"{KYC}","attester":"{ISSUER}",
+               "recipient":"{WALLET}","time":100,"expirationTime":0,"revocationTime":0,"data":"0x"}}]}}}}"#
+        ))
+        .unwrap();
+        assert_eq!(attestations.len(), 1);
+        assert_eq!(attestations.first().map(|a| a.time), Some(100));
+        assert!(eas::parse_response("{}").is_err());
+    }
+}
@@ -0,0 +1,519 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Cross-chain bridge intents.
+//!
+//! The classifiers here decode the calls of native rollup bridges,
+//! `LayerZero`, and Axelar into [`Intent`]s with the same action, `bridge`,
+//! and the same context, so that a policy such as
+//! ```text
+//! forbid(principal, action == Action::"bridge", resource)
+//! unless { context has destinationChain && [10, 8453].contains(context.destinationChain) };
+//! ```
+//! applies to every bridge without decoding each one. The context holds
+//! - `destination`: the bridge's own name for the destination chain, e.g. a
+//!   `LayerZero` endpoint id or an Axelar chain name, as a `String`
+//! - `destinationChain`: the id of the destination chain, if the bridge's
+//!   name for it is known
+//! - `sourceChain`: the id of the chain the call is made on, if known
+//! - `recipient`: who receives the tokens on the destination chain
+//! - `token`: the token bridged: its address on the source chain, the zero
+//!   address for Ether, or, for Axelar, its symbol
+//! - `amount`: the amount bridged, as a `u256` value
+//!
+//! [`schema_fragment()`] declares the `bridge` action with this context.
+
+use std::collections::HashMap;
+
+use ethers::types::U256;
+use serde_json::json;
+
+use crate::audit::to_hex;
+use crate::domain::intent::{selector, u256_value, Call, Intent, IntentClassifier};
+use crate::{SchemaError, SchemaFragment};
+
+/// The address used as the token when bridging Ether
+const ETHER: &str = "0x0000000000000000000000000000000000000000";
+
+/// `LayerZero` V2 endpoint ids and the chains they stand for
+const LAYERZERO_ENDPOINTS: &[(u64, u64)] = &[
+    (30101, 1),
+    (30102, 56),
+    (30106, 43114),
+    (30109, 137),
+    (30110, 42161),
+    (30111, 10),
+    (30184, 8453),
+];
+
+/// Axelar chain names and the chains they stand for
+const AXELAR_CHAINS: &[(&str, u64)] = &[
+    ("arbitrum", 42161),
+    ("avalanche", 43114),
+    ("base", 8453),
+    ("binance", 56),
+    ("ethereum", 1),
+    ("optimism", 10),
+    ("polygon", 137),
+];
+
+/// A bridge intent
+fn bridge(
+    protocol: &str,
+    source_chain: Option<u64>,
+    destination: String,
+    destination_chain: Option<u64>,
+    recipient: String,
+    token: String,
+    amount: U256,
+) -> Intent {
+    let mut intent = Intent::new(protocol, "bridge")
+        .with("destination", destination.into())
+        .with("recipient", recipient.into())
+        .with("token", token.into())
+        .with("amount", u256_value(amount));
+    if let Some(chain) = destination_chain {
+        intent = intent.with("destinationChain", chain.into());
+    }
+    if let Some(chain) = source_chain {
+        intent = intent.with("sourceChain", chain.into());
+    }
+    intent
+}
+
+/// A native rollup bridge contract
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct NativeBridge {
+    protocol: String,
+    source_chain: u64,
+    destination_chain: u64,
+}
+
+/// Deposits through the canonical bridges of rollups: `depositETHTo` and
+/// `depositERC20To` on OP Stack standard bridges, and `outboundTransfer` on
+/// the Arbitrum gateway router. Each bridge contract moves tokens between a
+/// fixed pair of chains, so only calls of known bridge contracts are
+/// classified.
+#[derive(Debug, Clone)]
+pub struct NativeBridges {
+    bridges: HashMap<String, NativeBridge>,
+}
+
+impl Default for NativeBridges {
+    fn default() -> Self {
+        Self {
+            bridges: HashMap::new(),
+        }
+        .with_bridge(
+            "0x99c9fc46f92e8a1c0dec1b1747d010903e884be1",
+            "optimism",
+            1,
+            10,
+        )
+        .with_bridge(
+            "0x3154cf16ccdb4c6d922629664174b904d80f2c35",
+            "base",
+            1,
+            8453,
+        )
+        .with_bridge(
+            "0x72ce9c846789fdb6fc1f34ac4ad25dd9ef7031ef",
+            "arbitrum",
+            1,
+            42161,
+        )
+    }
+}
+
+impl NativeBridges {
+    /// The Ethereum mainnet bridges of Optimism, Base, and Arbitrum
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also classify calls of the bridge contract at `address`, which moves
+    /// tokens from `source_chain` to `destination_chain`, as intents of
+    /// `protocol`
+    #[must_use]
+    pub fn with_bridge(
+        mut self,
+        address: &str,
+        protocol: impl Into<String>,
+        source_chain: u64,
+        destination_chain: u64,
+    ) -> Self {
+        self.bridges.insert(
+            address.to_ascii_lowercase(),
+            NativeBridge {
+                protocol: protocol.into(),
+                source_chain,
+                destination_chain,
+            },
+        );
+        self
+    }
+}
+
+impl IntentClassifier for NativeBridges {
+    fn classify(&self, call: &Call) -> Option<Intent> {
+        let native = self.bridges.get(&call.to.to_ascii_lowercase())?;
+        let data = call.calldata()?;
+        let selector = data.selector();
+        let (recipient, token, amount) =
+            if selector == self::selector("depositETHTo(address,uint32,bytes)") {
+                (
+                    data.address(0)?,
+                    ETHER.to_string(),
+                    U256::from_dec_str(&call.value).ok()?,
+                )
+            } else if selector
+                == self::selector("depositERC20To(address,address,address,uint256,uint32,bytes)")
+            {
+                (data.address(2)?, data.address(0)?, data.uint(3)?)
+            } else if selector
+                == self::selector("outboundTransfer(address,address,uint256,uint256,uint256,bytes)")
+            {
+                (data.address(1)?, data.address(0)?, data.uint(2)?)
+            } else {
+                return None;
+            };
+        Some(bridge(
+            &native.protocol,
+            Some(native.source_chain),
+            native.destination_chain.to_string(),
+            Some(native.destination_chain),
+            recipient,
+            token,
+            amount,
+        ))
+    }
+}
+
+/// `LayerZero` V2 OFT `send` calls. The token is the OFT contract called, and
+/// the destination is the endpoint id of the destination chain.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LayerZero;
+
+impl IntentClassifier for LayerZero {
+    fn classify(&self, call: &Call) -> Option<Intent> {
+        let data = call.calldata()?;
+        if data.selector()
+            != selector("send((uint32,bytes32,uint256,uint256,bytes,bytes,bytes),(uint256,uint256),address)")
+        {
+            return None;
+        }
+        let params = data.offset(0)?;
+        let endpoint = u64::try_from(data.uint(params)?).ok()?;
+        // recipients are `bytes32`, to allow for chains with longer addresses
+        let to = data.word(params + 1)?;
+        let recipient = data
+            .address(params + 1)
+            .unwrap_or_else(|| format!("0x{}", to_hex(to)));
+        let destination_chain = LAYERZERO_ENDPOINTS
+            .iter()
+            .find(|(id, _)| *id == endpoint)
+            .map(|(_, chain)| *chain);
+        Some(bridge(
+            "layerzero",
+            call.chain_id,
+            endpoint.to_string(),
+            destination_chain,
+            recipient,
+            crate::domain::session::address(&call.to).ok()?,
+            data.uint(params + 2)?,
+        ))
+    }
+}
+
+/// Axelar gateway `sendToken` and `callContractWithToken` calls. The token is
+/// the symbol of the Axelar asset, and the destination is the Axelar name of
+/// the destination chain. For `callContractWithToken`, the recipient is the
+/// destination contract.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Axelar;
+
+impl IntentClassifier for Axelar {
+    fn classify(&self, call: &Call) -> Option<Intent> {
+        let data = call.calldata()?;
+        let selector = data.selector();
+        let (symbol, amount) =
+            if selector == self::selector("sendToken(string,string,string,uint256)") {
+                (data.string(2)?, data.uint(3)?)
+            } else if selector
+                == self::selector("callContractWithToken(string,string,bytes,string,uint256)")
+            {
+                (data.string(3)?, data.uint(4)?)
+            } else {
+                return None;
+            };
+        let destination = data.string(0)?;
+        let recipient = data.string(1)?;
+        let recipient = crate::audit::normalize_address(&recipient).unwrap_or(recipient);
+        let destination_chain = AXELAR_CHAINS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(&destination))
+            .map(|(_, chain)| *chain);
+        Some(bridge(
+            "axelar",
+            call.chain_id,
+            destination,
+            destination_chain,
+            recipient,
+            symbol,
+            amount,
+        ))
+    }
+}
+
+/// A schema fragment declaring the `bridge` action, which applies to
+/// principals of `principal_types` and resources of `resource_types`, with
+/// the context of bridge intents. The entity types must be declared by
+/// another fragment.
+pub fn schema_fragment(
+    principal_types: &[&str],
+    resource_types: &[&str],
+) -> Result<SchemaFragment, SchemaError> {
+    let optional_long = json!({ "type": "Long", "required": false });
+    let string = json!({ "type": "String" });
+    SchemaFragment::from_json_value(json!({
+        "": {
+            "entityTypes": {},
+            "actions": {
+                "bridge": {
+                    "appliesTo": {
+                        "principalTypes": principal_types,
+                        "resourceTypes": resource_types,
+                        "context": {
+                            "type": "Record",
+                            "attributes": {
+                                "protocol": string,
+                                "destination": string,
+                                "destinationChain": optional_long,
+                                "sourceChain": optional_long,
+                                "recipient": string,
+                                "token": string,
+                                "amount": { "type": "Extension", "name": "u256" },
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::domain::intent::IntentRegistry;
+    use crate::{
+        Authorizer, Decision, Entities, EntityUid, PolicySet, Request, Schema, ValidationMode,
+        Validator,
+    };
+    use std::str::FromStr;
+
+    const OP_BRIDGE: &str = "0x99C9fc46f92E8a1c0deC1b1747d010903E884bE1";
+    const OFT: &str = "0x6985884c4392d348587b19cb9eaaf157f13271cd";
+    const USDC: &str = "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48";
+    const ALICE: &str = "0x00000000000000000000000000000000000a11ce";
+
+    fn word(n: impl Into<U256>) -> String {
+        let mut bytes = [0; 32];
+        n.into().to_big_endian(&mut bytes);
+        to_hex(&bytes)
+    }
+
+    fn address_word(address: &str) -> String {
+        format!("{:0>64}", &address[2..])
+    }
+
+    /// The length and padded contents of a dynamic `string`
+    fn string_words(s: &str) -> String {
+        let padded = (s.len() + 31) / 32 * 32;
+        format!(
+            "{}{:0<width$}",
+            word(s.len()),
+            to_hex(s.as_bytes()),
+            width = 2 * padded
+        )
+    }
+
+    fn encode(signature: &str, words: &[String]) -> String {
+        format!("0x{}{}", to_hex(&selector(signature)), words.concat())
+    }
+
+    fn native_deposit() -> Call {
+        Call::new(
+            OP_BRIDGE,
+            encode(
+                "depositERC20To(address,address,address,uint256,uint32,bytes)",
+                &[
+                    address_word(USDC),
+                    address_word("0x0b2c639c533813f4aa9d7837caf62653d097ff85"),
+                    address_word(ALICE),
+                    word(5_000_000),
+                    word(200_000),
+                    word(6 * 32),
+                    word(0),
+                ],
+            ),
+        )
+    }
+
+    #[test]
+    fn native_bridges() {
+        let intent = IntentRegistry::new().classify(&native_deposit()).unwrap();
+        assert_eq!(
+            (intent.protocol.as_str(), intent.action.as_str()),
+            ("optimism", "bridge")
+        );
+        assert_eq!(intent.context["sourceChain"], 1);
+        assert_eq!(intent.context["destinationChain"], 10);
+        assert_eq!(intent.context["recipient"], ALICE);
+        assert_eq!(intent.context["token"], USDC);
+        assert_eq!(intent.context["amount"], u256_value(5_000_000.into()));
+
+        let mut ether = Call::new(
+            OP_BRIDGE,
+            encode(
+                "depositETHTo(address,uint32,bytes)",
+                &[address_word(ALICE), word(200_000), word(3 * 32), word(0)],
+            ),
+        );
+        ether.value = "1000000000000000000".to_string();
+        let intent = NativeBridges::new().classify(&ether).unwrap();
+        assert_eq!(intent.context["token"], ETHER);
+        assert_eq!(intent.context["amount"], u256_value(U256::exp10(18)));
+
+        // the same call of an unknown contract isn't a bridge
+        let mut elsewhere = native_deposit();
+        elsewhere.to = USDC.to_string();
+        assert_eq!(NativeBridges::new().classify(&elsewhere), None);
+        let custom = NativeBridges::new().with_bridge(USDC, "zora", 1, 7_777_777);
+        assert_eq!(
+            custom.classify(&elsewhere).unwrap().context["destinationChain"],
+            7_777_777
+        );
+    }
+
+    #[test]
+    fn layerzero() {
+        let send = |endpoint: u64| {
+            let mut call = Call::new(
+                OFT,
+                encode(
+                    "send((uint32,bytes32,uint256,uint256,bytes,bytes,bytes),(uint256,uint256),address)",
+                    &[
+                        word(4 * 32),
+                        word(1000),
+                        word(0),
+                        address_word(ALICE),
+                        word(endpoint),
+                        address_word(ALICE),
+                        word(300),
+                        word(290),
+                        word(7 * 32),
+                        word(8 * 32),
+                        word(9 * 32),
+                        word(0),
+                        word(0),
+                        word(0),
+                    ],
+                ),
+            );
+            call.chain_id = Some(1);
+            IntentRegistry::new().classify(&call).unwrap()
+        };
+        let intent = send(30184);
+        assert_eq!(intent.protocol, "layerzero");
+        assert_eq!(intent.context["destination"], "30184");
+        assert_eq!(intent.context["destinationChain"], 8453);
+        assert_eq!(intent.context["sourceChain"], 1);
+        assert_eq!(intent.context["recipient"], ALICE);
+        assert_eq!(intent.context["token"], OFT);
+        assert_eq!(intent.context["amount"], u256_value(300.into()));
+
+        let intent = send(30168);
+        assert_eq!(intent.context["destination"], "30168");
+        assert!(!intent.context.contains_key("destinationChain"));
+    }
+
+    #[test]
+    fn axelar() {
+        let call = Call::new(
+            "0x4f4495243837681061c4743b74b3eedf548d56a5",
+            encode(
+                "sendToken(string,string,string,uint256)",
+                &[
+                    word(4 * 32),
+                    word(6 * 32),
+                    word(9 * 32),
+                    word(1_000_000),
+                    string_words("Polygon"),
+                    string_words("0x00000000000000000000000000000000000A11CE"),
+                    string_words("axlUSDC"),
+                ],
+            ),
+        );
+        let intent = IntentRegistry::new().classify(&call).unwrap();
+        assert_eq!(intent.protocol, "axelar");
+        assert_eq!(intent.context["destination"], "Polygon");
+        assert_eq!(intent.context["destinationChain"], 137);
+        assert_eq!(intent.context["recipient"], ALICE);
+        assert_eq!(intent.context["token"], "axlUSDC");
+        assert!(!intent.context.contains_key("sourceChain"));
+    }
+
+    #[test]
+    fn unsupported_destinations_are_forbidden() {
+        let policies = PolicySet::from_str(
+            r#"permit(principal, action, resource);
+               forbid(principal, action == Action::"bridge", resource)
+               unless { context has destinationChain && [10, 8453].contains(context.destinationChain) };"#,
+        )
+        .unwrap();
+        let schema = Schema::from_schema_fragments([
+            schema_fragment(&["User"], &["Wallet"]).unwrap(),
+            SchemaFragment::from_json_value(json!({
+                "": { "entityTypes": { "User": {}, "Wallet": {} }, "actions": {} }
+            }))
+            .unwrap(),
+        ])
+        .unwrap();
+        let validator = Validator::new(schema);
+        let validation = validator.validate(&policies, ValidationMode::default());
+        assert!(validation.validation_passed());
+
+        let decide = |intent: Intent| {
+            let request = Request::new(
+                Some(EntityUid::from_strs("User", "alice")),
+                Some(intent.action_uid()),
+                Some(EntityUid::from_strs("Wallet", "hot")),
+                intent.to_context().unwrap(),
+            );
+            Authorizer::new()
+                .is_authorized(&request, &policies, &Entities::empty())
+                .decision()
+        };
+        let to_optimism = NativeBridges::new().classify(&native_deposit()).unwrap();
+        assert_eq!(decide(to_optimism), Decision::Allow);
+        let to_arbitrum = NativeBridges::new()
+            .with_bridge(OP_BRIDGE, "arbitrum", 1, 42161)
+            .classify(&native_deposit())
+            .unwrap();
+        assert_eq!(decide(to_arbitrum), Decision::Deny);
+    }
+}
@@ -0,0 +1,384 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! EIP-712 typed data for requests and decision receipts.
+//!
+//! Requests and [`DecisionReceipt`]s are encoded as the EIP-712 structs
+//! ```text
+//! CedarRequest(string principal,string action,string resource,bytes32 requestHash)
+//! DecisionReceipt(uint256 version,bytes32 requestHash,bytes32 policySetHash,bool allowed,string[] determiningPolicies,uint256 timestamp)
+//! ```
+//! where `requestHash` is the [`Request::canonical_hash()`], so wallets can
+//! sign them with `eth_signTypedData_v4` and contracts can check the
+//! signatures with `ecrecover`. The principal, action, and resource are
+//! written as entity references, e.g. `User::"alice"`, and are empty if
+//! unspecified.
+//!
+//! [`request_typed_data()`] and [`receipt_typed_data()`] give the JSON
+//! presented to wallets; [`request_hash()`] and [`receipt_hash()`] give the
+//! digest which is signed.
+
+use k256::ecdsa::SigningKey;
+use serde_json::{json, Value};
+use sha3::{Digest, Keccak256};
+use thiserror::Error;
+
+use crate::audit::{normalize_address, to_hex, unhex};
+use crate::provenance::{recover_prehash, sign_prehash};
+use crate::receipt::DecisionReceipt;
+use crate::{Decision, EntityUid, Request};
+
+/// The EIP-712 type of requests
+pub const REQUEST_TYPE: &str =
+    "CedarRequest(string principal,string action,string resource,bytes32 requestHash)";
+
+/// The EIP-712 type of decision receipts
+pub const RECEIPT_TYPE: &str = "DecisionReceipt(uint256 version,bytes32 requestHash,bytes32 policySetHash,bool allowed,string[] determiningPolicies,uint256 timestamp)";
+
+/// Errors encoding or signing typed data
+#[derive(Debug, Error)]
+pub enum Eip712Error {
+    /// The domain's verifying contract isn't a `0x`-prefixed, 20 byte hex
+    /// string
+    #[error("invalid verifying contract address: {0}")]
+    InvalidAddress(String),
+    /// A receipt's request or policy set hash isn't a hex-encoded 32 byte
+    /// digest
+    #[error("malformed digest `{0}`")]
+    MalformedDigest(String),
+    /// The signer failed
+    #[error("failed to sign typed data: {0}")]
+    Signing(String),
+}
+
+/// The EIP-712 domain which signatures are bound to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Domain {
+    /// Name of the signing domain, e.g. the name of the service
+    pub name: String,
+    /// Version of the signing domain
+    pub version: String,
+    /// The chain the signature is valid on
+    pub chain_id: u64,
+    /// The contract which verifies signatures, if any
+    pub verifying_contract: Option<String>,
+}
+
+impl Domain {
+    /// A domain with no verifying contract
+    pub fn new(name: impl Into<String>, version: impl Into<String>, chain_id: u64) -> Self {
+        Self {
+            name: name.into(),
+            version: version.into(),
+            chain_id,
+            verifying_contract: None,
+        }
+    }
+
+    /// Bind signatures to the contract at `address`
+    #[must_use]
+    pub fn with_verifying_contract(mut self, address: impl Into<String>) -> Self {
+        self.verifying_contract = Some(address.into());
+        self
+    }
+
+    /// The `EIP712Domain` type of this domain, which only has the fields the
+    /// domain sets
+    fn type_string(&self) -> &'static str {
+        match self.verifying_contract {
+            Some(_) => {
+                "EIP712Domain(string name,string version,uint256 chainId,address verifyingContract)"
+            }
+            None => "EIP712Domain(string name,string version,uint256 chainId)",
+        }
+    }
+
+    /// The domain separator, `hashStruct(domain)`
+    pub fn separator(&self) -> Result<[u8; 32], Eip712Error> {
+        let mut fields = vec![
+            keccak(self.name.as_bytes()),
+            keccak(self.version.as_bytes()),
+            uint_word(self.chain_id),
+        ];
+        if let Some(contract) = &self.verifying_contract {
+            fields.push(address_word(contract)?);
+        }
+        Ok(hash_struct(self.type_string(), &fields))
+    }
+
+    fn to_json(&self) -> Result<(Value, Value), Eip712Error> {
+        let mut types = vec![
+            json!({ "name": "name", "type": "string" }),
+            json!({ "name": "version", "type": "string" }),
+            json!({ "name": "chainId", "type": "uint256" }),
+        ];
+        let mut domain = serde_json::Map::new();
+        domain.insert("name".to_string(), Value::from(self.name.as_str()));
+        domain.insert("version".to_string(), Value::from(self.version.as_str()));
+        domain.insert("chainId".to_string(), Value::from(self.chain_id));
+        if let Some(contract) = &self.verifying_contract {
+            let contract = normalize_address(contract)
+                .ok_or_else(|| Eip712Error::InvalidAddress(contract.clone()))?;
+            types.push(json!({ "name": "verifyingContract", "type": "address" }));
+            domain.insert("verifyingContract".to_string(), Value::from(contract));
+        }
+        Ok((Value::Array(types), Value::Object(domain)))
+    }
+}
+
+/// The digest signed for `request` in `domain`
+pub fn request_hash(request: &Request, domain: &Domain) -> Result<[u8; 32], Eip712Error> {
+    let fields = [
+        keccak(component(request.principal()).as_bytes()),
+        keccak(component(request.action()).as_bytes()),
+        keccak(component(request.resource()).as_bytes()),
+        request.canonical_hash(),
+    ];
+    typed_data_hash(domain, &hash_struct(REQUEST_TYPE, &fields))
+}
+
+/// The typed data presented to a wallet to sign `request` in `domain`
+pub fn request_typed_data(request: &Request, domain: &Domain) -> Result<Value, Eip712Error> {
+    let message = json!({
+        "principal": component(request.principal()),
+        "action": component(request.action()),
+        "resource": component(request.resource()),
+        "requestHash": format!("0x{}", to_hex(&request.canonical_hash())),
+    });
+    let fields = json!([
+        { "name": "principal", "type": "string" },
+        { "name": "action", "type": "string" },
+        { "name": "resource", "type": "string" },
+        { "name": "requestHash", "type": "bytes32" },
+    ]);
+    typed_data(domain, "CedarRequest", &fields, &message)
+}
+
+/// The digest signed for `receipt` in `domain`
+pub fn receipt_hash(receipt: &DecisionReceipt, domain: &Domain) -> Result<[u8; 32], Eip712Error> {
+    let policies: Vec<u8> = receipt
+        .determining_policies
+        .iter()
+        .flat_map(|id| keccak(id.as_bytes()))
+        .collect();
+    let fields = [
+        uint_word(u64::from(receipt.version)),
+        digest_word(&receipt.request_hash)?,
+        digest_word(&receipt.policy_set_hash)?,
+        uint_word(u64::from(receipt.decision == Decision::Allow)),
+        keccak(&policies),
+        uint_word(receipt.timestamp),
+    ];
+    typed_data_hash(domain, &hash_struct(RECEIPT_TYPE, &fields))
+}
+
+/// The typed data presented to a wallet to sign `receipt` in `domain`
+pub fn receipt_typed_data(
+    receipt: &DecisionReceipt,
+    domain: &Domain,
+) -> Result<Value, Eip712Error> {
+    let message = json!({
+        "version": receipt.version,
+        "requestHash": format!("0x{}", to_hex(&digest_word(&receipt.request_hash)?)),
+        "policySetHash": format!("0x{}", to_hex(&digest_word(&receipt.policy_set_hash)?)),
+        "allowed": receipt.decision == Decision::Allow,
+        "determiningPolicies": receipt.determining_policies,
+        "timestamp": receipt.timestamp,
+    });
+    let fields = json!([
+        { "name": "version", "type": "uint256" },
+        { "name": "requestHash", "type": "bytes32" },
+        { "name": "policySetHash", "type": "bytes32" },
+        { "name": "allowed", "type": "bool" },
+        { "name": "determiningPolicies", "type": "string[]" },
+        { "name": "timestamp", "type": "uint256" },
+    ]);
+    typed_data(domain, "DecisionReceipt", &fields, &message)
+}
+
+/// Sign the typed data `digest` with `key`, returning the 65-byte
+/// `r || s || v` signature, hex-encoded with a `0x` prefix
+pub fn sign(digest: &[u8; 32], key: &SigningKey) -> Result<String, Eip712Error> {
+    sign_prehash(digest, key).map_err(|e| Eip712Error::Signing(e.to_string()))
+}
+
+/// The address which signed the typed data `digest`, lowercase with a `0x`
+/// prefix, or `None` if `signature` is malformed
+pub fn recover(digest: &[u8; 32], signature: &str) -> Option<String> {
+    recover_prehash(digest, signature)
+}
+
+fn component(uid: Option<&EntityUid>) -> String {
+    uid.map_or_else(String::new, ToString::to_string)
+}
+
+fn typed_data(
+    domain: &Domain,
+    primary_type: &str,
+    fields: &Value,
+    message: &Value,
+) -> Result<Value, Eip712Error> {
+    let (domain_type, domain) = domain.to_json()?;
+    Ok(json!({
+        "types": {
+            "EIP712Domain": domain_type,
+            primary_type: fields,
+        },
+        "primaryType": primary_type,
+        "domain": domain,
+        "message": message,
+    }))
+}
+
+/// `keccak256(0x1901 || domainSeparator || structHash)`
+fn typed_data_hash(domain: &Domain, struct_hash: &[u8; 32]) -> Result<[u8; 32], Eip712Error> {
+    let mut hasher = Keccak256::new();
+    hasher.update([0x19, 0x01]);
+    hasher.update(domain.separator()?);
+    hasher.update(struct_hash);
+    Ok(hasher.finalize().into())
+}
+
+/// `keccak256(typeHash || encodeData(fields))`
+fn hash_struct(type_string: &str, fields: &[[u8; 32]]) -> [u8; 32] {
+    let mut hasher = Keccak256::new();
+    hasher.update(keccak(type_string.as_bytes()));
+    for field in fields {
+        hasher.update(field);
+    }
+    hasher.finalize().into()
+}
+
+fn keccak(bytes: &[u8]) -> [u8; 32] {
+    Keccak256::digest(bytes).into()
+}
+
+fn uint_word(n: u64) -> [u8; 32] {
+    let mut word = [0; 32];
+    let (_, low) = word.split_at_mut(24);
+    low.copy_from_slice(&n.to_be_bytes());
+    word
+}
+
+fn address_word(address: &str) -> Result<[u8; 32], Eip712Error> {
+    let bytes = normalize_address(address)
+        .and_then(|a| unhex(a.trim_start_matches("0x")))
+        .ok_or_else(|| Eip712Error::InvalidAddress(address.to_string()))?;
+    let mut word = [0; 32];
+    let (_, low) = word.split_at_mut(12);
+    low.copy_from_slice(&bytes);
+    Ok(word)
+}
+
+fn digest_word(hex: &str) -> Result<[u8; 32], Eip712Error> {
+    unhex(hex.strip_prefix("0x").unwrap_or(hex))
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .ok_or_else(|| Eip712Error::MalformedDigest(hex.to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::provenance::address;
+    use crate::{Authorizer, Context, Entities, PolicySet};
+    use std::str::FromStr;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    const CONTRACT: &str = "0xCcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccC";
+
+    fn domain() -> Domain {
+        Domain::new("Banyan", "1", 1).with_verifying_contract(CONTRACT)
+    }
+
+    fn request() -> Request {
+        Request::new(
+            Some(EntityUid::from_strs("User", "alice")),
+            Some(EntityUid::from_strs("Action", "transfer")),
+            None,
+            Context::from_json_value(
+                json!({ "amount": { "__extn": { "fn": "u256", "arg": "1000" } } }),
+                None,
+            )
+            .unwrap(),
+        )
+    }
+
+    fn receipt() -> DecisionReceipt {
+        let policies = PolicySet::from_str("permit(principal, action, resource);").unwrap();
+        let response = Authorizer::new().is_authorized(&request(), &policies, &Entities::empty());
+        DecisionReceipt::new(
+            &request(),
+            &policies,
+            &response,
+            UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+        )
+    }
+
+    /// Our digests match those of an independent EIP-712 implementation
+    #[test]
+    #[cfg(feature = "u256")]
+    fn matches_ethers() {
+        use ethers::types::transaction::eip712::{Eip712, TypedData};
+
+        let encode = |typed_data: Value| {
+            serde_json::from_value::<TypedData>(typed_data)
+                .unwrap()
+                .encode_eip712()
+                .unwrap()
+        };
+        for domain in [domain(), Domain::new("Banyan", "2", 8453)] {
+            assert_eq!(
+                request_hash(&request(), &domain).unwrap(),
+                encode(request_typed_data(&request(), &domain).unwrap())
+            );
+            assert_eq!(
+                receipt_hash(&receipt(), &domain).unwrap(),
+                encode(receipt_typed_data(&receipt(), &domain).unwrap())
+            );
+        }
+    }
+
+    #[test]
+    fn sign_and_recover() {
+        let key = SigningKey::from_slice(&[7; 32]).unwrap();
+        let digest = receipt_hash(&receipt(), &domain()).unwrap();
+        let signature = sign(&digest, &key).unwrap();
+        assert_eq!(
+            recover(&digest, &signature),
+            Some(address(key.verifying_key()))
+        );
+        let other = receipt_hash(&receipt(), &Domain::new("Banyan", "1", 10)).unwrap();
+        assert_ne!(
+            recover(&other, &signature),
+            Some(address(key.verifying_key()))
+        );
+        assert_eq!(recover(&digest, "0x1234"), None);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(
+            request_hash(&request(), &domain().with_verifying_contract("0x12")),
+            Err(Eip712Error::InvalidAddress(_))
+        ));
+        let mut receipt = receipt();
+        receipt.policy_set_hash = "abcd".to_string();
+        assert!(matches!(
+            receipt_hash(&receipt, &domain()),
+            Err(Eip712Error::MalformedDigest(hash)) if hash == "abcd"
+        ));
+    }
+}
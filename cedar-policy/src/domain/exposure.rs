@@ -0,0 +1,384 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Counterparty exposure over rolling windows.
+//!
+//! An [`ExposureTracker`] adds up how much of each token has been sent to
+//! each destination address, across requests. Transfers are recorded with
+//! [`ExposureTracker::record()`] once they're allowed, and
+//! [`ExposureTracker::annotate()`] exposes the amounts of a token sent within
+//! the tracker's window as `context.exposure`, an `exposure` extension value,
+//! e.g.
+//! ```text
+//! forbid(principal, action == Action::"transfer", resource)
+//! when { context.exposure.to(principal.dest).u256GreaterThan(u256("50000000000")) };
+//! ```
+//! Windows roll: a transfer stops counting once it's older than the window.
+//!
+//! Transfers are kept in an [`ExposureStore`], so that they can be shared by
+//! several trackers with different windows; [`MemoryExposureStore`] keeps
+//! them in memory.
+
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use ethers::types::U256;
+use thiserror::Error;
+
+use crate::{Request, RestrictedExpression};
+
+/// The context attribute holding the exposure to each counterparty, an
+/// `exposure` extension value
+pub const EXPOSURE_ATTRIBUTE: &str = "exposure";
+/// The window of an [`ExposureTracker`] unless it's given another
+pub const DEFAULT_WINDOW: Duration = Duration::from_hours(24);
+
+/// An error from an [`ExposureStore`]
+#[derive(Debug, Error)]
+#[error("exposure store error: {0}")]
+pub struct ExposureStoreError(pub String);
+
+/// Where an [`ExposureTracker`] keeps the transfers it records. Addresses
+/// and tokens are lowercased before they reach the store.
+pub trait ExposureStore: Debug + Send + Sync {
+    /// Record that `amount` of `token` was sent to `destination` at `at`, in
+    /// milliseconds since the Unix epoch
+    fn record(
+        &self,
+        destination: &str,
+        token: &str,
+        amount: U256,
+        at: u64,
+    ) -> Result<(), ExposureStoreError>;
+
+    /// The amounts of `token` sent to each destination at or after `since`,
+    /// in milliseconds since the Unix epoch, saturating at the largest
+    /// `u256`. Destinations without transfers are left out.
+    fn totals(&self, token: &str, since: u64)
+        -> Result<BTreeMap<String, U256>, ExposureStoreError>;
+}
+
+#[derive(Debug, Clone)]
+struct Transfer {
+    destination: String,
+    token: String,
+    amount: U256,
+    at: u64,
+}
+
+/// An [`ExposureStore`] in memory
+#[derive(Debug, Default)]
+pub struct MemoryExposureStore {
+    transfers: Mutex<Vec<Transfer>>,
+}
+
+impl MemoryExposureStore {
+    /// An empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forget the transfers before `before`, in milliseconds since the Unix
+    /// epoch, which no window reaching back only that far counts
+    pub fn prune(&self, before: u64) {
+        let mut transfers = self
+            .transfers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        transfers.retain(|transfer| transfer.at >= before);
+        drop(transfers);
+    }
+}
+
+impl ExposureStore for MemoryExposureStore {
+    fn record(
+        &self,
+        destination: &str,
+        token: &str,
+        amount: U256,
+        at: u64,
+    ) -> Result<(), ExposureStoreError> {
+        let mut transfers = self
+            .transfers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        transfers.push(Transfer {
+            destination: destination.to_string(),
+            token: token.to_string(),
+            amount,
+            at,
+        });
+        drop(transfers);
+        Ok(())
+    }
+
+    fn totals(
+        &self,
+        token: &str,
+        since: u64,
+    ) -> Result<BTreeMap<String, U256>, ExposureStoreError> {
+        let transfers = self
+            .transfers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let mut totals = BTreeMap::new();
+        for transfer in transfers
+            .iter()
+            .filter(|transfer| transfer.token == token && transfer.at >= since)
+        {
+            let total: &mut U256 = totals.entry(transfer.destination.clone()).or_default();
+            *total = total.saturating_add(transfer.amount);
+        }
+        drop(transfers);
+        Ok(totals)
+    }
+}
+
+/// Tracks the amounts sent to each counterparty within a rolling window
+#[derive(Debug)]
+pub struct ExposureTracker {
+    store: Arc<dyn ExposureStore>,
+    window: Duration,
+}
+
+impl ExposureTracker {
+    /// A tracker keeping its transfers in `store`, over the
+    /// [`DEFAULT_WINDOW`]
+    pub fn new(store: Arc<dyn ExposureStore>) -> Self {
+        Self {
+            store,
+            window: DEFAULT_WINDOW,
+        }
+    }
+
+    /// Only count the transfers within `window` of each request
+    #[must_use]
+    pub fn with_window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Record that `amount` of `token` was sent to `destination` now
+    pub fn record(
+        &self,
+        destination: &str,
+        token: &str,
+        amount: U256,
+    ) -> Result<(), ExposureStoreError> {
+        self.store.record(
+            &destination.to_lowercase(),
+            &token.to_lowercase(),
+            amount,
+            now(),
+        )
+    }
+
+    /// The amounts of `token` sent to each destination within the window
+    pub fn exposure(&self, token: &str) -> Result<BTreeMap<String, U256>, ExposureStoreError> {
+        let window = u64::try_from(self.window.as_millis()).unwrap_or(u64::MAX);
+        let since = now().saturating_sub(window);
+        self.store.totals(&token.to_lowercase(), since)
+    }
+
+    /// `request` with the [`EXPOSURE_ATTRIBUTE`] holding the amounts of
+    /// `token` sent to each destination within the window added to its
+    /// context
+    pub fn annotate(&self, request: &Request, token: &str) -> Result<Request, ExposureStoreError> {
+        let exposure = exposure_expr(&self.exposure(token)?);
+        Ok(request.with_context_attribute(EXPOSURE_ATTRIBUTE, exposure))
+    }
+}
+
+/// An `exposure` extension value of `totals`. Destinations which can't be
+/// addresses, because they contain `,` or `=`, are left out.
+fn exposure_expr(totals: &BTreeMap<String, U256>) -> RestrictedExpression {
+    let mut amounts: BTreeMap<String, U256> = BTreeMap::new();
+    for (destination, amount) in totals {
+        let destination = destination.trim().to_lowercase();
+        if destination.is_empty() || destination.contains([',', '=']) {
+            continue;
+        }
+        let total = amounts.entry(destination).or_default();
+        *total = total.saturating_add(*amount);
+    }
+    let entries: Vec<_> = amounts
+        .iter()
+        .map(|(destination, amount)| format!("{destination}={amount}"))
+        .collect();
+    // PANIC SAFETY: the entries are distinct, non-empty destinations without
+    // separators, with decimal amounts, and the string is escaped
+    #[allow(clippy::expect_used)]
+    RestrictedExpression::from_str(&format!(
+        "exposure(\"{}\")",
+        entries.join(",").escape_debug()
+    ))
+    .expect("an exposure call is a valid restricted expression")
+}
+
+/// Milliseconds since the Unix epoch
+fn now() -> u64 {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_millis();
+    u64::try_from(millis).unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Authorizer, Context, Decision, Entities, EntityUid, PolicySet};
+
+    const ALICE: &str = "0x00000000000000000000000000000000000a11ce";
+    const BOB: &str = "0x0000000000000000000000000000000000000b0b";
+    const USDC: &str = "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48";
+
+    fn request(destination: &str, amount: u64) -> Request {
+        let context = Context::from_pairs([
+            (
+                "destination".to_string(),
+                RestrictedExpression::new_string(destination.to_string()),
+            ),
+            (
+                "amount".to_string(),
+                RestrictedExpression::from_str(&format!("u256(\"{amount}\")")).unwrap(),
+            ),
+        ]);
+        Request::new(
+            Some(EntityUid::from_strs("Wallet", "treasury")),
+            Some(EntityUid::from_strs("Action", "transfer")),
+            Some(EntityUid::from_strs("Token", "usdc")),
+            context,
+        )
+    }
+
+    #[test]
+    fn stores_add_up_transfers_per_destination_and_token() {
+        let store = MemoryExposureStore::new();
+        store.record("a", "usdc", U256::from(100), 1_000).unwrap();
+        store.record("a", "usdc", U256::from(50), 2_000).unwrap();
+        store.record("b", "usdc", U256::from(7), 3_000).unwrap();
+        store.record("a", "weth", U256::from(1), 3_000).unwrap();
+        store.record("b", "usdc", U256::MAX, 3_000).unwrap();
+
+        let totals = store.totals("usdc", 0).unwrap();
+        assert_eq!(totals.get("a"), Some(&U256::from(150)));
+        assert_eq!(totals.get("b"), Some(&U256::MAX));
+        // the window starts at `since`
+        let totals = store.totals("usdc", 2_000).unwrap();
+        assert_eq!(totals.get("a"), Some(&U256::from(50)));
+        assert_eq!(store.totals("weth", 0).unwrap().len(), 1);
+        assert!(store.totals("dai", 0).unwrap().is_empty());
+
+        store.prune(2_000);
+        assert_eq!(
+            store.totals("usdc", 0).unwrap().get("a"),
+            Some(&U256::from(50))
+        );
+    }
+
+    #[test]
+    fn windows_roll() {
+        let store = Arc::new(MemoryExposureStore::new());
+        let hour = u64::try_from(Duration::from_hours(1).as_millis()).unwrap();
+        store
+            .record(
+                ALICE,
+                &USDC.to_lowercase(),
+                U256::from(100),
+                now() - 2 * hour,
+            )
+            .unwrap();
+        let tracker = ExposureTracker::new(store.clone()).with_window(Duration::from_hours(1));
+        tracker.record(ALICE, USDC, U256::from(30)).unwrap();
+        tracker
+            .record(&BOB.to_uppercase(), USDC, U256::from(5))
+            .unwrap();
+
+        let exposure = tracker.exposure(USDC).unwrap();
+        assert_eq!(exposure.get(ALICE), Some(&U256::from(30)));
+        assert_eq!(exposure.get(BOB), Some(&U256::from(5)));
+        // the default window still counts the older transfer
+        let exposure = ExposureTracker::new(store).exposure(USDC).unwrap();
+        assert_eq!(exposure.get(ALICE), Some(&U256::from(130)));
+    }
+
+    #[test]
+    fn exposure_values() {
+        let totals = BTreeMap::from([
+            ("0xABC".to_string(), U256::MAX),
+            ("0xabc".to_string(), U256::one()),
+            ("a,b".to_string(), U256::one()),
+            ("\"".to_string(), U256::from(2)),
+        ]);
+        let request =
+            request(ALICE, 0).with_context_attribute(EXPOSURE_ATTRIBUTE, exposure_expr(&totals));
+        let policies = PolicySet::from_str(&format!(
+            r#"permit(principal, action, resource)
+               when {{ context.exposure == exposure("\"=2,0xabc={}") }};"#,
+            U256::MAX
+        ))
+        .unwrap();
+        assert_eq!(
+            Authorizer::new()
+                .is_authorized(&request, &policies, &Entities::empty())
+                .decision(),
+            Decision::Allow
+        );
+    }
+
+    #[test]
+    fn policies_cap_exposure() {
+        let tracker = ExposureTracker::new(Arc::new(MemoryExposureStore::new()));
+        let policies = PolicySet::from_str(
+            r#"permit(principal, action, resource);
+               forbid(principal, action == Action::"transfer", resource)
+               when {
+                 context.exposure.to(context.destination).u256GreaterThan(u256("100")) ||
+                 context.exposure.totalExposure().u256GreaterThan(u256("150"))
+               };"#,
+        )
+        .unwrap();
+        let authorizer = Authorizer::new();
+        let decide = |destination: &str| {
+            let request = tracker.annotate(&request(destination, 10), USDC).unwrap();
+            authorizer
+                .is_authorized(&request, &policies, &Entities::empty())
+                .decision()
+        };
+
+        assert_eq!(decide(ALICE), Decision::Allow);
+        tracker.record(ALICE, USDC, U256::from(100)).unwrap();
+        assert_eq!(decide(ALICE), Decision::Allow);
+        tracker.record(ALICE, USDC, U256::from(1)).unwrap();
+        assert_eq!(decide(&ALICE.to_uppercase()), Decision::Deny);
+        // other counterparties are tracked separately
+        assert_eq!(decide(BOB), Decision::Allow);
+        tracker.record(BOB, USDC, U256::from(50)).unwrap();
+        assert_eq!(decide(BOB), Decision::Deny);
+        // as are other tokens
+        let weth = tracker.annotate(&request(BOB, 10), "weth").unwrap();
+        assert_eq!(
+            authorizer
+                .is_authorized(&weth, &policies, &Entities::empty())
+                .decision(),
+            Decision::Allow
+        );
+    }
+}
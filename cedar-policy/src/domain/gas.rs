@@ -0,0 +1,228 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+//! Gas parameters as request context.
+//!
+//! By convention, a request for a transaction holds its gas parameters in a
+//! `gas` context attribute: a record with `gasLimit`, and `maxFeePerGas` and
+//! `maxPriorityFeePerGas` or `gasPrice`, and optionally `baseFeePerGas`, all
+//! `u256` values. [`Gas`] builds that record, and the `gas` extension
+//! functions compute what the transaction may pay from it, e.g.
+//! ```text
+//! forbid(principal, action, resource)
+//! unless { context.gas.maxTxCostWei().u256LessThanOrEqual(u256("50000000000000000")) };
+//! ```
+
+use ethers::types::U256;
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+use crate::domain::intent::u256_value;
+use crate::domain::simulation::parse_amount;
+use crate::{Context, ContextJsonError};
+
+/// The context attribute holding the gas parameters
+pub const GAS_ATTRIBUTE: &str = "gas";
+
+/// Errors reading gas parameters from a transaction
+#[derive(Debug, Error)]
+pub enum GasError {
+    /// A field is missing
+    #[error("transaction has no `{0}`")]
+    Missing(&'static str),
+    /// A field isn't a quantity
+    #[error("`{field}` is not a valid quantity: {value}")]
+    InvalidQuantity {
+        /// The field
+        field: &'static str,
+        /// Its value
+        value: String,
+    },
+}
+
+/// The gas parameters of a transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Gas {
+    /// The most gas the transaction may use
+    pub gas_limit: U256,
+    /// The most an EIP-1559 transaction pays per gas
+    pub max_fee_per_gas: Option<U256>,
+    /// The most an EIP-1559 transaction pays the block producer per gas
+    pub max_priority_fee_per_gas: Option<U256>,
+    /// The price per gas of a legacy transaction
+    pub gas_price: Option<U256>,
+    /// The base fee of the block the transaction is expected to be included in
+    pub base_fee_per_gas: Option<U256>,
+}
+
+impl Gas {
+    /// The gas parameters of an EIP-1559 transaction
+    pub fn eip1559(gas_limit: U256, max_fee_per_gas: U256, max_priority_fee_per_gas: U256) -> Self {
+        Self {
+            gas_limit,
+            max_fee_per_gas: Some(max_fee_per_gas),
+            max_priority_fee_per_gas: Some(max_priority_fee_per_gas),
+            gas_price: None,
+            base_fee_per_gas: None,
+        }
+    }
+
+    /// The gas parameters of a legacy transaction
+    pub fn legacy(gas_limit: U256, gas_price: U256) -> Self {
+        Self {
+            gas_limit,
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            gas_price: Some(gas_price),
+            base_fee_per_gas: None,
+        }
+    }
+
+    /// Set the base fee of the block the transaction is expected to be
+    /// included in, which `effectiveGasPrice()` and `effectivePriorityFee()`
+    /// need
+    #[must_use]
+    pub fn with_base_fee(mut self, base_fee_per_gas: U256) -> Self {
+        self.base_fee_per_gas = Some(base_fee_per_gas);
+        self
+    }
+
+    /// The gas parameters of a transaction in the JSON-RPC format, as passed
+    /// to `eth_sendTransaction`, with quantities as hex or decimal strings
+    pub fn from_transaction(transaction: &Value) -> Result<Self, GasError> {
+        let quantity = |field: &'static str| -> Result<Option<U256>, GasError> {
+            transaction
+                .get(field)
+                .map(|value| {
+                    value
+                        .as_str()
+                        .and_then(|s| parse_amount(s).ok())
+                        .ok_or_else(|| GasError::InvalidQuantity {
+                            field,
+                            value: value.to_string(),
+                        })
+                })
+                .transpose()
+        };
+        let gas_limit = quantity("gas")?.ok_or(GasError::Missing("gas"))?;
+        let max_fee_per_gas = quantity("maxFeePerGas")?;
+        let gas_price = quantity("gasPrice")?;
+        if max_fee_per_gas.is_none() && gas_price.is_none() {
+            return Err(GasError::Missing("maxFeePerGas"));
+        }
+        Ok(Self {
+            gas_limit,
+            max_fee_per_gas,
+            max_priority_fee_per_gas: quantity("maxPriorityFeePerGas")?,
+            gas_price: if max_fee_per_gas.is_some() {
+                None
+            } else {
+                gas_price
+            },
+            base_fee_per_gas: None,
+        })
+    }
+
+    /// The gas parameters as a record in the JSON format of a Cedar context
+    pub fn to_json(&self) -> Value {
+        let mut record = Map::new();
+        let mut insert = |attr: &str, value: Option<U256>| {
+            if let Some(value) = value {
+                record.insert(attr.to_string(), u256_value(value));
+            }
+        };
+        insert("gasLimit", Some(self.gas_limit));
+        insert("maxFeePerGas", self.max_fee_per_gas);
+        insert("maxPriorityFeePerGas", self.max_priority_fee_per_gas);
+        insert("gasPrice", self.gas_price);
+        insert("baseFeePerGas", self.base_fee_per_gas);
+        Value::Object(record)
+    }
+
+    /// A context holding the gas parameters as [`GAS_ATTRIBUTE`]
+    pub fn to_context(&self) -> Result<Context, ContextJsonError> {
+        let mut context = Map::new();
+        context.insert(GAS_ATTRIBUTE.to_string(), self.to_json());
+        Context::from_json_value(Value::Object(context), None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Authorizer, Decision, Entities, PolicySet, Request};
+    use serde_json::json;
+    use std::str::FromStr;
+
+    #[test]
+    fn from_transaction() {
+        let gas = Gas::from_transaction(&json!({
+            "to": "0x2222222222222222222222222222222222222222",
+            "gas": "0x5208",
+            "maxFeePerGas": "0x6fc23ac00",
+            "maxPriorityFeePerGas": "0x77359400",
+        }))
+        .unwrap();
+        assert_eq!(
+            gas,
+            Gas::eip1559(
+                21_000.into(),
+                30_000_000_000_u64.into(),
+                2_000_000_000.into()
+            )
+        );
+
+        let gas =
+            Gas::from_transaction(&json!({ "gas": "100000", "gasPrice": "25000000000" })).unwrap();
+        assert_eq!(gas, Gas::legacy(100_000.into(), 25_000_000_000_u64.into()));
+
+        assert!(matches!(
+            Gas::from_transaction(&json!({ "gasPrice": "1" })),
+            Err(GasError::Missing("gas"))
+        ));
+        assert!(matches!(
+            Gas::from_transaction(&json!({ "gas": "1" })),
+            Err(GasError::Missing("maxFeePerGas"))
+        ));
+        assert!(matches!(
+            Gas::from_transaction(&json!({ "gas": 21000, "gasPrice": "1" })),
+            Err(GasError::InvalidQuantity { field: "gas", .. })
+        ));
+    }
+
+    #[test]
+    fn gas_cap() {
+        let policies = PolicySet::from_str(
+            r#"permit(principal, action, resource)
+               unless { context.gas.maxTxCostWei().u256GreaterThan(u256("50000000000000000")) };"#,
+        )
+        .unwrap();
+        let decide = |gas: Gas| {
+            let request = Request::new(None, None, None, gas.to_context().unwrap());
+            Authorizer::new()
+                .is_authorized(&request, &policies, &Entities::empty())
+                .decision()
+        };
+        let gwei = U256::exp10(9);
+        assert_eq!(
+            decide(Gas::eip1559(21_000.into(), gwei * 30, gwei * 2)),
+            Decision::Allow
+        );
+        assert_eq!(
+            decide(Gas::eip1559(2_000_000.into(), gwei * 30, gwei * 2)),
+            Decision::Deny
+        );
+    }
+}
@@ -0,0 +1,1181 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Outcomes of governance proposals as entity attributes.
+//!
+//! A [`GovernanceProvider`] fetches the outcome of a proposal from a
+//! [`ProposalSource`] and makes the proposal a `Proposal` entity with the
+//! attributes:
+//! - `state`: the state of the proposal, one of `pending`, `active`,
+//!   `canceled`, `defeated`, `succeeded`, `queued`, `expired` and `executed`
+//! - `succeeded`: whether the proposal passed, i.e. is `succeeded`, `queued`
+//!   or `executed`
+//! - `quorumReached`: whether the votes counting towards the quorum reached it
+//! - `forBps`: the share of the votes cast which are for the proposal, in
+//!   basis points
+//! - `participationBps`: the share of the voting supply which voted, in basis
+//!   points, if the voting supply is known
+//!
+//! so execution policies can require a linked proposal to have passed, e.g.
+//! ```text
+//! permit(principal, action == Action::"execute", resource)
+//! when {
+//!   context.proposal.succeeded &&
+//!   context.proposal has participationBps &&
+//!   context.proposal.participationBps >= 1000
+//! };
+//! ```
+//! where `context.proposal` is a `Proposal` entity.
+//!
+//! Outcomes are cached for [`DEFAULT_TTL`] (see
+//! [`GovernanceProvider::with_ttl()`]). [`MemoryProposalSource`] holds
+//! outcomes in memory. With the `snapshot` feature, `SnapshotSource` fetches
+//! them from a Snapshot hub, and with the `governor` feature,
+//! `GovernorSource` reads them from an OpenZeppelin Governor contract over
+//! JSON-RPC. [`GovernanceProvider::entities_at()`] reads the outcomes as of
+//! a [`BlockPin`], so all the proposals of one authorization are read from
+//! the same block.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use cedar_policy_core::ast;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::block_pin::{BlockPin, BlockPinError};
+use crate::{Entities, EntitiesError, Entity, EntityTypeName, EntityUid, RestrictedExpression};
+
+/// The entity type of proposals
+pub const PROPOSAL_TYPE: &str = "Proposal";
+
+/// How long a [`GovernanceProvider`] caches outcomes by default
+pub const DEFAULT_TTL: Duration = Duration::from_mins(1);
+
+/// Errors resolving the outcomes of proposals
+#[derive(Debug, Error)]
+pub enum GovernanceError {
+    /// The source failed
+    #[error("failed to fetch proposal {proposal}: {message}")]
+    Source {
+        /// The proposal
+        proposal: String,
+        /// What went wrong
+        message: String,
+    },
+    /// The pinned block was reorged, or couldn't be read
+    #[error(transparent)]
+    Pin(#[from] BlockPinError),
+    /// The entities couldn't be built
+    #[error(transparent)]
+    Entities(#[from] EntitiesError),
+}
+
+/// The state of a proposal, as a `Governor` contract reports it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ProposalState {
+    /// Voting hasn't started
+    Pending,
+    /// Voting is open
+    Active,
+    /// The proposal was canceled
+    Canceled,
+    /// The proposal was voted down or missed the quorum
+    Defeated,
+    /// The proposal passed
+    Succeeded,
+    /// The proposal passed and is queued for execution
+    Queued,
+    /// The proposal passed but wasn't executed in time
+    Expired,
+    /// The proposal passed and was executed
+    Executed,
+}
+
+impl ProposalState {
+    /// The name of the state in the `state` attribute
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Active => "active",
+            Self::Canceled => "canceled",
+            Self::Defeated => "defeated",
+            Self::Succeeded => "succeeded",
+            Self::Queued => "queued",
+            Self::Expired => "expired",
+            Self::Executed => "executed",
+        }
+    }
+
+    /// Whether a proposal in this state passed
+    pub const fn succeeded(self) -> bool {
+        matches!(self, Self::Succeeded | Self::Queued | Self::Executed)
+    }
+}
+
+impl std::fmt::Display for ProposalState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The outcome of a proposal. Votes are in units of voting power, so only
+/// their ratios matter.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProposalOutcome {
+    /// The state of the proposal
+    pub state: ProposalState,
+    /// The votes for the proposal
+    pub votes_for: u128,
+    /// The votes against the proposal
+    pub votes_against: u128,
+    /// The abstentions
+    pub votes_abstain: u128,
+    /// The voting power which could have voted, if known
+    pub voting_supply: Option<u128>,
+    /// Whether the votes counting towards the quorum reached it
+    pub quorum_reached: bool,
+}
+
+impl ProposalOutcome {
+    /// The votes cast
+    pub const fn votes_cast(&self) -> u128 {
+        self.votes_for
+            .saturating_add(self.votes_against)
+            .saturating_add(self.votes_abstain)
+    }
+
+    /// The share of the votes cast which are for the proposal, in basis
+    /// points
+    pub const fn for_bps(&self) -> u128 {
+        bps(self.votes_for, self.votes_cast())
+    }
+
+    /// The share of the voting supply which voted, in basis points, if the
+    /// voting supply is known
+    pub fn participation_bps(&self) -> Option<u128> {
+        self.voting_supply
+            .map(|supply| bps(self.votes_cast(), supply).min(10_000))
+    }
+}
+
+/// `part` of `whole` in basis points, or 0 if `whole` is 0
+const fn bps(part: u128, whole: u128) -> u128 {
+    if whole == 0 {
+        return 0;
+    }
+    // scale down instead of overflowing
+    match part.checked_mul(10_000) {
+        Some(scaled) => scaled / whole,
+        None => part / (whole / 10_000).saturating_add(1),
+    }
+}
+
+/// A source of the outcomes of proposals
+pub trait ProposalSource: Debug + Send + Sync {
+    /// The outcome of the proposal `proposal`
+    fn outcome(&self, proposal: &str) -> Result<ProposalOutcome, GovernanceError>;
+
+    /// The outcome of the proposal `proposal` as of the block `pin`. Sources
+    /// which don't read a chain ignore the pin.
+    fn outcome_at(
+        &self,
+        proposal: &str,
+        _pin: &BlockPin,
+    ) -> Result<ProposalOutcome, GovernanceError> {
+        self.outcome(proposal)
+    }
+}
+
+/// Outcomes held in memory
+#[derive(Debug, Default)]
+pub struct MemoryProposalSource {
+    outcomes: Mutex<HashMap<String, ProposalOutcome>>,
+}
+
+impl MemoryProposalSource {
+    /// A source with no proposals
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the outcome of `proposal`
+    pub fn insert(&self, proposal: impl Into<String>, outcome: ProposalOutcome) {
+        self.outcomes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(proposal.into(), outcome);
+    }
+}
+
+impl ProposalSource for MemoryProposalSource {
+    fn outcome(&self, proposal: &str) -> Result<ProposalOutcome, GovernanceError> {
+        self.outcomes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(proposal)
+            .cloned()
+            .ok_or_else(|| GovernanceError::Source {
+                proposal: proposal.to_string(),
+                message: "unknown proposal".to_string(),
+            })
+    }
+}
+
+/// Resolves the outcomes of proposals into entities, caching them
+#[derive(Debug)]
+pub struct GovernanceProvider {
+    source: Arc<dyn ProposalSource>,
+    entity_type: EntityTypeName,
+    ttl: Duration,
+    cache: Mutex<HashMap<String, (ProposalOutcome, Instant)>>,
+}
+
+impl GovernanceProvider {
+    /// A provider of `Proposal` entities with the outcomes from `source`
+    pub fn new(source: Arc<dyn ProposalSource>) -> Self {
+        Self {
+            source,
+            entity_type: proposal_type(),
+            ttl: DEFAULT_TTL,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Make proposals entities of type `entity_type`, instead of `Proposal`
+    #[must_use]
+    pub fn with_entity_type(mut self, entity_type: EntityTypeName) -> Self {
+        self.entity_type = entity_type;
+        self
+    }
+
+    /// Cache outcomes for `ttl`. A `ttl` of zero disables the cache.
+    #[must_use]
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// The outcome of `proposal`, from the cache if possible
+    pub fn outcome(&self, proposal: &str) -> Result<ProposalOutcome, GovernanceError> {
+        let now = Instant::now();
+        let cached = self
+            .cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(proposal)
+            .filter(|(_, expires)| *expires > now)
+            .map(|(outcome, _)| outcome.clone());
+        if let Some(outcome) = cached {
+            return Ok(outcome);
+        }
+        let outcome = self.source.outcome(proposal)?;
+        if !self.ttl.is_zero() {
+            self.cache
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(proposal.to_string(), (outcome.clone(), now + self.ttl));
+        }
+        Ok(outcome)
+    }
+
+    /// Forget the cached outcomes
+    pub fn clear_cache(&self) {
+        self.cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+
+    /// The entity for `proposal` with its outcome
+    pub fn entity(&self, proposal: &str) -> Result<Entity, GovernanceError> {
+        Ok(self.proposal_entity(proposal, &self.outcome(proposal)?))
+    }
+
+    /// The entity for `proposal` with its outcome as of the block `pin`.
+    /// Pinned outcomes aren't cached.
+    pub fn entity_at(&self, proposal: &str, pin: &BlockPin) -> Result<Entity, GovernanceError> {
+        Ok(self.proposal_entity(proposal, &self.source.outcome_at(proposal, pin)?))
+    }
+
+    /// The entity for `proposal` with the outcome `outcome`
+    fn proposal_entity(&self, proposal: &str, outcome: &ProposalOutcome) -> Entity {
+        let long = |n: u128| RestrictedExpression::new_long(n.try_into().unwrap_or(i64::MAX));
+        let mut attrs = HashMap::from([
+            (
+                "state".to_string(),
+                RestrictedExpression::new_string(outcome.state.to_string()),
+            ),
+            (
+                "succeeded".to_string(),
+                RestrictedExpression::new_bool(outcome.state.succeeded()),
+            ),
+            (
+                "quorumReached".to_string(),
+                RestrictedExpression::new_bool(outcome.quorum_reached),
+            ),
+            ("forBps".to_string(), long(outcome.for_bps())),
+        ]);
+        if let Some(participation) = outcome.participation_bps() {
+            attrs.insert("participationBps".to_string(), long(participation));
+        }
+        Entity::new(
+            EntityUid(ast::EntityUID::from_components(
+                self.entity_type.0.clone(),
+                ast::Eid::new(proposal),
+            )),
+            attrs,
+            HashSet::new(),
+        )
+    }
+
+    /// The entities for `proposals` with their outcomes
+    pub fn entities_for<'a>(
+        &self,
+        proposals: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Entities, GovernanceError> {
+        let entities = proposals
+            .into_iter()
+            .map(|proposal| self.entity(proposal))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Entities::from_entities(entities)?)
+    }
+
+    /// The entities for `proposals` with their outcomes as of the block
+    /// `pin`, failing if it was reorged
+    pub fn entities_at<'a>(
+        &self,
+        proposals: impl IntoIterator<Item = &'a str>,
+        pin: &BlockPin,
+    ) -> Result<Entities, GovernanceError> {
+        let entities = proposals
+            .into_iter()
+            .map(|proposal| self.entity_at(proposal, pin))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Entities::from_entities(entities)?)
+    }
+}
+
+/// The `Proposal` entity type
+fn proposal_type() -> EntityTypeName {
+    // PANIC SAFETY: `Proposal` is a valid entity type name
+    #[allow(clippy::expect_used)]
+    EntityTypeName::from_str(PROPOSAL_TYPE).expect("`Proposal` is a valid entity type name")
+}
+
+#[cfg(feature = "snapshot")]
+pub use snapshot::{SnapshotSource, SNAPSHOT_HUB_GRAPHQL};
+
+#[cfg(feature = "snapshot")]
+mod snapshot {
+    use super::{GovernanceError, ProposalOutcome, ProposalSource, ProposalState};
+    use serde::Deserialize;
+    use serde_json::json;
+
+    /// The GraphQL API of the public Snapshot hub
+    pub const SNAPSHOT_HUB_GRAPHQL: &str = "https://hub.snapshot.org/graphql";
+
+    const QUERY: &str = "query Proposal($id: String!) { \
+        proposal(id: $id) { state choices scores scores_total quorum } }";
+
+    #[derive(Debug, Deserialize)]
+    struct Response {
+        data: Data,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Data {
+        proposal: Option<Proposal>,
+    }
+
+    /// A proposal as the hub returns it
+    #[derive(Debug, Deserialize)]
+    struct Proposal {
+        state: String,
+        choices: Vec<String>,
+        scores: Vec<f64>,
+        scores_total: f64,
+        quorum: f64,
+    }
+
+    /// Fetches the outcomes of proposals from a Snapshot hub
+    ///
+    /// Snapshot proposals pass when voting closed with more votes for than
+    /// against and the votes cast reached the quorum. The votes for, against
+    /// and abstaining are those of the choices named `For`/`Yes`,
+    /// `Against`/`No` and `Abstain`. Snapshot doesn't know the voting supply,
+    /// so `participationBps` is only set with [`Self::with_voting_supply()`].
+    ///
+    /// Its requests are [blocking](crate::domain#network-requests).
+    #[derive(Debug)]
+    pub struct SnapshotSource {
+        client: reqwest::blocking::Client,
+        endpoint: String,
+        voting_supply: Option<u128>,
+    }
+
+    impl SnapshotSource {
+        /// A source using the hub at `endpoint`, e.g. [`SNAPSHOT_HUB_GRAPHQL`]
+        pub fn new(endpoint: impl Into<String>) -> Self {
+            Self {
+                client: reqwest::blocking::Client::new(),
+                endpoint: endpoint.into(),
+                voting_supply: None,
+            }
+        }
+
+        /// Measure participation against `voting_supply`, in the units of the
+        /// space's voting power
+        #[must_use]
+        pub const fn with_voting_supply(mut self, voting_supply: u128) -> Self {
+            self.voting_supply = Some(voting_supply);
+            self
+        }
+    }
+
+    impl ProposalSource for SnapshotSource {
+        fn outcome(&self, proposal: &str) -> Result<ProposalOutcome, GovernanceError> {
+            let error = |message: String| GovernanceError::Source {
+                proposal: proposal.to_string(),
+                message,
+            };
+            let body = self
+                .client
+                .post(&self.endpoint)
+                .json(&json!({ "query": QUERY, "variables": { "id": proposal } }))
+                .send()
+                .and_then(reqwest::blocking::Response::error_for_status)
+                .and_then(reqwest::blocking::Response::text)
+                .map_err(|err| error(err.to_string()))?;
+            parse_response(&body, self.voting_supply).map_err(error)
+        }
+    }
+
+    /// Whole units of voting power
+    fn whole(votes: f64) -> u128 {
+        format!("{:.0}", votes.max(0.0))
+            .parse()
+            .unwrap_or(u128::MAX)
+    }
+
+    /// The outcome in the hub's response `body`
+    pub(super) fn parse_response(
+        body: &str,
+        voting_supply: Option<u128>,
+    ) -> Result<ProposalOutcome, String> {
+        let response: Response = serde_json::from_str(body).map_err(|e| e.to_string())?;
+        let proposal = response
+            .data
+            .proposal
+            .ok_or_else(|| "unknown proposal".to_string())?;
+        let votes = |names: &[&str]| -> Option<u128> {
+            let i = proposal
+                .choices
+                .iter()
+                .position(|choice| names.iter().any(|n| choice.eq_ignore_ascii_case(n)))?;
+            Some(whole(proposal.scores.get(i).copied().unwrap_or_default()))
+        };
+        let (Some(votes_for), Some(votes_against)) =
+            (votes(&["for", "yes"]), votes(&["against", "no"]))
+        else {
+            return Err(format!(
+                "no `For` and `Against` choices in {:?}",
+                proposal.choices
+            ));
+        };
+        let votes_abstain = votes(&["abstain"]).unwrap_or(0);
+        let quorum_reached = proposal.scores_total >= proposal.quorum;
+        let state = match proposal.state.as_str() {
+            "pending" => ProposalState::Pending,
+            "active" => ProposalState::Active,
+            "closed" if quorum_reached && votes_for > votes_against => ProposalState::Succeeded,
+            "closed" => ProposalState::Defeated,
+            state => return Err(format!("unknown state `{state}`")),
+        };
+        Ok(ProposalOutcome {
+            state,
+            votes_for,
+            votes_against,
+            votes_abstain,
+            voting_supply,
+            quorum_reached,
+        })
+    }
+}
+
+#[cfg(feature = "governor")]
+pub use governor::GovernorSource;
+
+#[cfg(feature = "governor")]
+mod governor {
+    use super::{GovernanceError, ProposalOutcome, ProposalSource, ProposalState};
+    use crate::audit::{to_hex, unhex};
+    use crate::block_pin::{self, BlockPin};
+    use ethers::abi::{self, ParamType, Token};
+    use ethers::types::{Address, U256};
+    use serde::Deserialize;
+    use serde_json::json;
+
+    /// The states in the order of the Governor's `ProposalState` enum
+    const STATES: [ProposalState; 8] = [
+        ProposalState::Pending,
+        ProposalState::Active,
+        ProposalState::Canceled,
+        ProposalState::Defeated,
+        ProposalState::Succeeded,
+        ProposalState::Queued,
+        ProposalState::Expired,
+        ProposalState::Executed,
+    ];
+
+    /// A JSON-RPC response
+    #[derive(Debug, Deserialize)]
+    struct RpcResponse {
+        result: Option<String>,
+        error: Option<RpcError>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct RpcError {
+        message: String,
+    }
+
+    /// Reads the outcomes of proposals from a `Governor` contract
+    /// counting votes with `GovernorCountingSimple`, whose voting
+    /// token implements `getPastTotalSupply`
+    ///
+    /// Proposals are identified by their decimal proposal ids. The quorum is
+    /// reached by the votes for and abstaining, as the Governor counts them.
+    /// Outcomes are read as of the latest block, or of the pinned block with
+    /// [`ProposalSource::outcome_at()`].
+    ///
+    /// Its requests are [blocking](crate::domain#network-requests).
+    #[derive(Debug)]
+    pub struct GovernorSource {
+        client: reqwest::blocking::Client,
+        rpc_url: String,
+        governor: Address,
+    }
+
+    impl GovernorSource {
+        /// A source reading the Governor at `governor` through the JSON-RPC
+        /// node at `rpc_url`, or `None` if `governor` isn't an address
+        pub fn new(rpc_url: impl Into<String>, governor: &str) -> Option<Self> {
+            Some(Self {
+                client: reqwest::blocking::Client::new(),
+                rpc_url: rpc_url.into(),
+                governor: governor.parse().ok()?,
+            })
+        }
+
+        /// Call `signature` on `to` with `args` at the block `block`,
+        /// decoding the result as `outputs`
+        fn call(
+            &self,
+            block: &str,
+            to: Address,
+            signature: &str,
+            args: &[Token],
+            outputs: &[ParamType],
+        ) -> Result<Vec<Token>, String> {
+            let mut data = ethers::utils::id(signature).to_vec();
+            data.extend(abi::encode(args));
+            let body = self
+                .client
+                .post(&self.rpc_url)
+                .json(&json!({
+                    "jsonrpc": "2.0",
+                    "id": 1,
+                    "method": "eth_call",
+                    "params": [
+                        { "to": format!("{to:#x}"), "data": format!("0x{}", to_hex(&data)) },
+                        block
+                    ],
+                }))
+                .send()
+                .and_then(reqwest::blocking::Response::error_for_status)
+                .and_then(reqwest::blocking::Response::text)
+                .map_err(|err| err.to_string())?;
+            let result = parse_rpc_response(&body)?;
+            abi::decode(outputs, &result).map_err(|err| format!("{signature}: {err}"))
+        }
+
+        /// Call a function of the Governor returning a `uint256`
+        fn uint(&self, block: &str, signature: &str, arg: U256) -> Result<U256, String> {
+            match self
+                .call(
+                    block,
+                    self.governor,
+                    signature,
+                    &[Token::Uint(arg)],
+                    &[ParamType::Uint(256)],
+                )?
+                .as_slice()
+            {
+                [Token::Uint(n)] => Ok(*n),
+                _ => Err(format!("{signature}: unexpected result")),
+            }
+        }
+
+        /// The outcome of `proposal` at the block `block`
+        fn fetch(&self, proposal: &str, block: &str) -> Result<ProposalOutcome, String> {
+            let id = U256::from_dec_str(proposal)
+                .map_err(|_| format!("`{proposal}` is not a proposal id"))?;
+            let state = self.uint(block, "state(uint256)", id)?;
+            let votes = match self
+                .call(
+                    block,
+                    self.governor,
+                    "proposalVotes(uint256)",
+                    &[Token::Uint(id)],
+                    &[
+                        ParamType::Uint(256),
+                        ParamType::Uint(256),
+                        ParamType::Uint(256),
+                    ],
+                )?
+                .as_slice()
+            {
+                [Token::Uint(against), Token::Uint(for_), Token::Uint(abstain)] => {
+                    [*for_, *against, *abstain]
+                }
+                _ => return Err("proposalVotes: unexpected result".to_string()),
+            };
+            // the quorum and supply are only known once the snapshot is past
+            let (quorum, supply) = if state == U256::zero() {
+                (None, None)
+            } else {
+                let timepoint = self.uint(block, "proposalSnapshot(uint256)", id)?;
+                let quorum = self.uint(block, "quorum(uint256)", timepoint)?;
+                let token = match self
+                    .call(block, self.governor, "token()", &[], &[ParamType::Address])?
+                    .as_slice()
+                {
+                    [Token::Address(token)] => *token,
+                    _ => return Err("token: unexpected result".to_string()),
+                };
+                let supply = match self
+                    .call(
+                        block,
+                        token,
+                        "getPastTotalSupply(uint256)",
+                        &[Token::Uint(timepoint)],
+                        &[ParamType::Uint(256)],
+                    )?
+                    .as_slice()
+                {
+                    [Token::Uint(supply)] => *supply,
+                    _ => return Err("getPastTotalSupply: unexpected result".to_string()),
+                };
+                (Some(quorum), Some(supply))
+            };
+            outcome(state, votes, quorum, supply)
+        }
+    }
+
+    impl ProposalSource for GovernorSource {
+        fn outcome(&self, proposal: &str) -> Result<ProposalOutcome, GovernanceError> {
+            self.fetch(proposal, "latest")
+                .map_err(|message| GovernanceError::Source {
+                    proposal: proposal.to_string(),
+                    message,
+                })
+        }
+
+        fn outcome_at(
+            &self,
+            proposal: &str,
+            pin: &BlockPin,
+        ) -> Result<ProposalOutcome, GovernanceError> {
+            let outcome = self.fetch(proposal, &pin.block_tag()).map_err(|message| {
+                GovernanceError::Source {
+                    proposal: proposal.to_string(),
+                    message,
+                }
+            })?;
+            block_pin::rpc::check(&self.client, &self.rpc_url, pin)?;
+            Ok(outcome)
+        }
+    }
+
+    /// The result of a JSON-RPC response `body`
+    pub(super) fn parse_rpc_response(body: &str) -> Result<Vec<u8>, String> {
+        let response: RpcResponse = serde_json::from_str(body).map_err(|e| e.to_string())?;
+        if let Some(error) = response.error {
+            return Err(error.message);
+        }
+        let result = response.result.ok_or_else(|| "no result".to_string())?;
+        unhex(result.strip_prefix("0x").unwrap_or(&result))
+            .ok_or_else(|| format!("invalid result `{result}`"))
+    }
+
+    /// The outcome of a proposal with the Governor `state`, `votes` for,
+    /// against and abstaining, `quorum` and voting `supply`
+    pub(super) fn outcome(
+        state: U256,
+        [votes_for, votes_against, votes_abstain]: [U256; 3],
+        quorum: Option<U256>,
+        supply: Option<U256>,
+    ) -> Result<ProposalOutcome, String> {
+        let state = usize::try_from(state)
+            .ok()
+            .and_then(|state| STATES.get(state).copied())
+            .ok_or_else(|| format!("unknown state {state}"))?;
+        let quorum_reached =
+            quorum.is_some_and(|quorum| votes_for.saturating_add(votes_abstain) >= quorum);
+        let units = |n: U256| u128::try_from(n).unwrap_or(u128::MAX);
+        Ok(ProposalOutcome {
+            state,
+            votes_for: units(votes_for),
+            votes_against: units(votes_against),
+            votes_abstain: units(votes_abstain),
+            voting_supply: supply.map(units),
+            quorum_reached,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Authorizer, Context, Decision, PolicySet, Request};
+
+    fn outcome(state: ProposalState, votes: [u128; 3], supply: Option<u128>) -> ProposalOutcome {
+        ProposalOutcome {
+            state,
+            votes_for: votes[0],
+            votes_against: votes[1],
+            votes_abstain: votes[2],
+            voting_supply: supply,
+            quorum_reached: true,
+        }
+    }
+
+    #[test]
+    fn shares() {
+        let o = outcome(ProposalState::Succeeded, [60, 30, 10], Some(1_000));
+        assert_eq!(o.for_bps(), 6_000);
+        assert_eq!(o.participation_bps(), Some(1_000));
+        assert_eq!(outcome(ProposalState::Active, [0, 0, 0], None).for_bps(), 0);
+        let huge = outcome(
+            ProposalState::Active,
+            [u128::MAX / 2, 0, 0],
+            Some(u128::MAX),
+        );
+        assert_eq!(huge.participation_bps(), Some(4_999));
+        assert!(ProposalState::Executed.succeeded());
+        assert!(!ProposalState::Defeated.succeeded());
+    }
+
+    #[test]
+    fn outcomes_in_entities() {
+        let source = MemoryProposalSource::new();
+        source.insert(
+            "1",
+            outcome(ProposalState::Succeeded, [600, 100, 0], Some(5_000)),
+        );
+        source.insert(
+            "2",
+            outcome(ProposalState::Succeeded, [300, 100, 0], Some(5_000)),
+        );
+        source.insert(
+            "3",
+            outcome(ProposalState::Defeated, [100, 600, 0], Some(5_000)),
+        );
+        source.insert("4", outcome(ProposalState::Executed, [600, 100, 0], None));
+        let provider = GovernanceProvider::new(Arc::new(source));
+        let policies = PolicySet::from_str(
+            r#"permit(principal, action == Action::"execute", resource)
+               when {
+                 resource.succeeded &&
+                 resource has participationBps &&
+                 resource.participationBps >= 1000
+               };"#,
+        )
+        .unwrap();
+        let decide = |proposal: &str| {
+            let request = Request::new(
+                Some(EntityUid::from_strs("User", "alice")),
+                Some(EntityUid::from_strs("Action", "execute")),
+                Some(EntityUid::from_strs("Proposal", proposal)),
+                Context::empty(),
+            );
+            let entities = provider.entities_for([proposal]).unwrap();
+            Authorizer::new()
+                .is_authorized(&request, &policies, &entities)
+                .decision()
+        };
+        assert_eq!(decide("1"), Decision::Allow);
+        // participation below 10%
+        assert_eq!(decide("2"), Decision::Deny);
+        assert_eq!(decide("3"), Decision::Deny);
+        // unknown voting supply
+        assert_eq!(decide("4"), Decision::Deny);
+        assert!(provider.entity("5").is_err());
+    }
+
+    #[test]
+    fn caches_outcomes() {
+        let source = Arc::new(MemoryProposalSource::new());
+        source.insert("1", outcome(ProposalState::Active, [1, 0, 0], None));
+        let provider = GovernanceProvider::new(Arc::clone(&source) as Arc<dyn ProposalSource>);
+        assert_eq!(provider.outcome("1").unwrap().state, ProposalState::Active);
+        source.insert("1", outcome(ProposalState::Succeeded, [1, 0, 0], None));
+        assert_eq!(provider.outcome("1").unwrap().state, ProposalState::Active);
+        provider.clear_cache();
+        assert_eq!(
+            provider.outcome("1").unwrap().state,
+            ProposalState::Succeeded
+        );
+        let uncached = GovernanceProvider::new(Arc::clone(&source) as Arc<dyn ProposalSource>)
+            .with_ttl(Duration::ZERO);
+        uncached.outcome("1").unwrap();
+        source.insert("1", outcome(ProposalState::Executed, [1, 0, 0], None));
+        assert_eq!(
+            uncached.outcome("1").unwrap().state,
+            ProposalState::Executed
+        );
+    }
+
+    #[test]
+    fn state_transitions() {
+        let source = Arc::new(MemoryProposalSource::new());
+        let provider = GovernanceProvider::new(Arc::clone(&source) as Arc<dyn ProposalSource>)
+            .with_ttl(Duration::ZERO);
+        let policies = PolicySet::from_str(
+            r#"permit(principal, action == Action::"execute", resource)
+               when { resource.succeeded && resource.quorumReached };"#,
+        )
+        .unwrap();
+        let decide = || {
+            let request = Request::new(
+                Some(EntityUid::from_strs("User", "alice")),
+                Some(EntityUid::from_strs("Action", "execute")),
+                Some(EntityUid::from_strs("Proposal", "1")),
+                Context::empty(),
+            );
+            let entities = provider.entities_for(["1"]).unwrap();
+            Authorizer::new()
+                .is_authorized(&request, &policies, &entities)
+                .decision()
+        };
+        let state = || {
+            provider
+                .entity("1")
+                .unwrap()
+                .attr("state")
+                .unwrap()
+                .unwrap()
+        };
+        for (proposal_state, decision) in [
+            (ProposalState::Pending, Decision::Deny),
+            (ProposalState::Active, Decision::Deny),
+            (ProposalState::Succeeded, Decision::Allow),
+            (ProposalState::Queued, Decision::Allow),
+            (ProposalState::Executed, Decision::Allow),
+        ] {
+            source.insert("1", outcome(proposal_state, [600, 100, 0], Some(5_000)));
+            assert_eq!(
+                state(),
+                crate::EvalResult::String(proposal_state.to_string())
+            );
+            assert_eq!(decide(), decision, "{proposal_state}");
+        }
+        // a passed proposal which missed the quorum
+        source.insert(
+            "1",
+            ProposalOutcome {
+                quorum_reached: false,
+                ..outcome(ProposalState::Succeeded, [600, 100, 0], None)
+            },
+        );
+        assert_eq!(decide(), Decision::Deny);
+        source.insert("1", outcome(ProposalState::Canceled, [600, 100, 0], None));
+        assert_eq!(decide(), Decision::Deny);
+    }
+
+    #[test]
+    fn source_errors() {
+        let source = Arc::new(MemoryProposalSource::new());
+        source.insert("1", outcome(ProposalState::Active, [1, 0, 0], None));
+        let provider = GovernanceProvider::new(Arc::clone(&source) as Arc<dyn ProposalSource>)
+            .with_entity_type(EntityTypeName::from_str("Dao::Proposal").unwrap());
+        assert!(matches!(
+            provider.entities_for(["1", "2"]),
+            Err(GovernanceError::Source { proposal, .. }) if proposal == "2"
+        ));
+        // failures aren't cached
+        source.insert("2", outcome(ProposalState::Defeated, [0, 1, 0], None));
+        let entities = provider.entities_for(["1", "2"]).unwrap();
+        assert!(entities
+            .get(&EntityUid::from_strs("Dao::Proposal", "2"))
+            .is_some());
+        assert!(entities
+            .get(&EntityUid::from_strs("Proposal", "2"))
+            .is_none());
+        // unpinned reads of a chain source fail
+        let provider = GovernanceProvider::new(Arc::new(ChainSource::default()));
+        assert!(matches!(
+            provider.entities_for(["1"]),
+            Err(GovernanceError::Source { message, .. }) if message == "unpinned read"
+        ));
+    }
+
+    /// Outcomes at each block of a chain, by the block's number
+    #[derive(Debug, Default)]
+    struct ChainSource {
+        blocks: Mutex<HashMap<u64, (String, ProposalOutcome)>>,
+    }
+
+    impl ProposalSource for ChainSource {
+        fn outcome(&self, proposal: &str) -> Result<ProposalOutcome, GovernanceError> {
+            Err(GovernanceError::Source {
+                proposal: proposal.to_string(),
+                message: "unpinned read".to_string(),
+            })
+        }
+
+        fn outcome_at(
+            &self,
+            _proposal: &str,
+            pin: &BlockPin,
+        ) -> Result<ProposalOutcome, GovernanceError> {
+            let (hash, outcome) = self.blocks.lock().unwrap()[&pin.number].clone();
+            pin.check(&hash)?;
+            Ok(outcome)
+        }
+    }
+
+    #[test]
+    fn outcomes_at_pinned_blocks() {
+        let source = Arc::new(ChainSource::default());
+        let insert = |number: u64, hash: &str, state: ProposalState| {
+            source
+                .blocks
+                .lock()
+                .unwrap()
+                .insert(number, (hash.to_string(), outcome(state, [1, 0, 0], None)));
+        };
+        insert(10, "0xa", ProposalState::Active);
+        insert(11, "0xb", ProposalState::Succeeded);
+        let provider = GovernanceProvider::new(Arc::clone(&source) as Arc<dyn ProposalSource>);
+        let state = |pin: &BlockPin| {
+            provider.entities_at(["1"], pin).map(|entities| {
+                entities
+                    .get(&EntityUid::from_strs("Proposal", "1"))
+                    .unwrap()
+                    .attr("state")
+                    .unwrap()
+                    .unwrap()
+            })
+        };
+        assert_eq!(
+            state(&BlockPin::new(10, "0xa")).unwrap(),
+            crate::EvalResult::String("active".to_string())
+        );
+        assert_eq!(
+            state(&BlockPin::new(11, "0xb")).unwrap(),
+            crate::EvalResult::String("succeeded".to_string())
+        );
+        // block 10 was replaced by a reorg
+        insert(10, "0xc", ProposalState::Defeated);
+        assert!(matches!(
+            state(&BlockPin::new(10, "0xa")),
+            Err(GovernanceError::Pin(BlockPinError::Reorged {
+                number: 10,
+                ..
+            }))
+        ));
+    }
+
+    #[cfg(feature = "snapshot")]
+    #[test]
+    fn snapshot_responses() {
+        let response = |state: &str, quorum: f64| {
+            format!(
+                r#"{{"data": {{"proposal": {{"state": "{state}", "choices": ["For", "Against", "Abstain"],
+                    "scores": [1200.6, 300, 100], "scores_total": 1600.6, "quorum": {quorum}}}}}}}"#
+            )
+        };
+        let passed = snapshot::parse_response(&response("closed", 1000.0), Some(10_000)).unwrap();
+        assert_eq!(passed.state, ProposalState::Succeeded);
+        assert_eq!(
+            (passed.votes_for, passed.votes_against, passed.votes_abstain),
+            (1201, 300, 100)
+        );
+        assert_eq!(passed.participation_bps(), Some(1_601));
+        let missed = snapshot::parse_response(&response("closed", 2000.0), None).unwrap();
+        assert_eq!(missed.state, ProposalState::Defeated);
+        assert!(!missed.quorum_reached);
+        assert_eq!(
+            snapshot::parse_response(&response("active", 0.0), None)
+                .unwrap()
+                .state,
+            ProposalState::Active
+        );
+        assert!(snapshot::parse_response(r#"{"data": {"proposal": null}}"#, None).is_err());
+        assert!(snapshot::parse_response(
+            r#"{"data": {"proposal": {"state": "closed", "choices": ["Pizza", "Tacos"],
+                "scores": [1, 2], "scores_total": 3, "quorum": 0}}}"#,
+            None
+        )
+        .is_err());
+    }
+
+    #[cfg(feature = "snapshot")]
+    #[test]
+    fn snapshot_choices_and_errors() {
+        let response = |state: &str, choices: &str, scores: &str, total: f64| {
+            format!(
+                r#"{{"data": {{"proposal": {{"state": "{state}", "choices": {choices},
+                    "scores": {scores}, "scores_total": {total}, "quorum": 10}}}}}}"#
+            )
+        };
+        // `Yes`/`No` choices in any order and case, without abstentions
+        let o = snapshot::parse_response(
+            &response("closed", r#"["no", "YES"]"#, "[4, 9]", 13.0),
+            Some(100),
+        )
+        .unwrap();
+        assert_eq!(o.state, ProposalState::Succeeded);
+        assert_eq!((o.votes_for, o.votes_against, o.votes_abstain), (9, 4, 0));
+        // ties are defeats
+        let o = snapshot::parse_response(
+            &response("closed", r#"["For", "Against"]"#, "[6, 6]", 12.0),
+            None,
+        )
+        .unwrap();
+        assert_eq!(o.state, ProposalState::Defeated);
+        assert!(o.quorum_reached);
+        // missing and negative scores count as no votes
+        let o = snapshot::parse_response(
+            &response("active", r#"["For", "Against", "Abstain"]"#, "[-3]", 0.0),
+            None,
+        )
+        .unwrap();
+        assert_eq!(o.votes_cast(), 0);
+        assert!(snapshot::parse_response(
+            &response("deleted", r#"["For", "Against"]"#, "[1, 2]", 3.0),
+            None
+        )
+        .unwrap_err()
+        .contains("deleted"));
+        // GraphQL errors come without data
+        assert!(snapshot::parse_response(
+            r#"{"errors": [{"message": "Invalid proposal id"}]}"#,
+            None
+        )
+        .is_err());
+        assert!(snapshot::parse_response("<html>Bad Gateway</html>", None).is_err());
+    }
+
+    #[cfg(feature = "governor")]
+    #[test]
+    fn governor_outcomes() {
+        use ethers::types::U256;
+        let votes = [600.into(), 100.into(), 50.into()];
+        let o = governor::outcome(4.into(), votes, Some(650.into()), Some(5_000.into())).unwrap();
+        assert_eq!(o.state, ProposalState::Succeeded);
+        assert!(o.quorum_reached);
+        assert_eq!(o.participation_bps(), Some(1_500));
+        let o = governor::outcome(3.into(), votes, Some(651.into()), Some(5_000.into())).unwrap();
+        assert!(!o.quorum_reached);
+        let o = governor::outcome(U256::zero(), [U256::zero(); 3], None, None).unwrap();
+        assert_eq!(o.state, ProposalState::Pending);
+        assert_eq!(o.participation_bps(), None);
+        assert!(governor::outcome(8.into(), votes, None, None).is_err());
+
+        assert_eq!(
+            governor::parse_rpc_response(r#"{"jsonrpc": "2.0", "id": 1, "result": "0x0004"}"#)
+                .unwrap(),
+            vec![0, 4]
+        );
+        assert_eq!(
+            governor::parse_rpc_response(
+                r#"{"jsonrpc": "2.0", "id": 1, "error": {"code": 3, "message": "execution reverted"}}"#
+            )
+            .unwrap_err(),
+            "execution reverted"
+        );
+    }
+
+    #[cfg(feature = "governor")]
+    #[test]
+    fn governor_rpc_errors() {
+        assert_eq!(
+            governor::parse_rpc_response(r#"{"jsonrpc": "2.0", "id": 1, "result": "0x"}"#).unwrap(),
+            Vec::<u8>::new()
+        );
+        assert_eq!(
+            governor::parse_rpc_response(r#"{"jsonrpc": "2.0", "id": 1, "result": null}"#)
+                .unwrap_err(),
+            "no result"
+        );
+        assert!(
+            governor::parse_rpc_response(r#"{"jsonrpc": "2.0", "id": 1, "result": "0xzz"}"#)
+                .unwrap_err()
+                .contains("0xzz")
+        );
+        assert!(
+            governor::parse_rpc_response(r#"{"jsonrpc": "2.0", "id": 1, "result": "0x123"}"#)
+                .is_err()
+        );
+        assert!(governor::parse_rpc_response("upstream connect error").is_err());
+    }
+
+    #[cfg(feature = "governor")]
+    #[test]
+    fn governor_states() {
+        use ethers::types::U256;
+        let states = (0..8)
+            .map(|state| {
+                governor::outcome(state.into(), [U256::zero(); 3], None, None)
+                    .unwrap()
+                    .state
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            states,
+            [
+                ProposalState::Pending,
+                ProposalState::Active,
+                ProposalState::Canceled,
+                ProposalState::Defeated,
+                ProposalState::Succeeded,
+                ProposalState::Queued,
+                ProposalState::Expired,
+                ProposalState::Executed,
+            ]
+        );
+        assert!(governor::outcome(U256::MAX, [U256::zero(); 3], None, None).is_err());
+        // votes beyond u128 saturate
+        let o = governor::outcome(
+            1.into(),
+            [U256::MAX, U256::one(), U256::zero()],
+            Some(U256::MAX),
+            Some(U256::MAX),
+        )
+        .unwrap();
+        assert_eq!(o.votes_for, u128::MAX);
+        assert!(o.quorum_reached);
+        assert_eq!(o.voting_supply, Some(u128::MAX));
+    }
+}
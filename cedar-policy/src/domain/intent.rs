@@ -0,0 +1,614 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Classification of contract calls into protocol-level intents.
+//!
+//! An [`IntentClassifier`] decodes the calls of a protocol it knows into an
+//! [`Intent`]: a normalized action name, such as `swap` or `borrow`, and a
+//! context holding the call's parameters as Cedar values, with token amounts
+//! as `u256` values. Policies can then be written against intents, e.g.
+//! ```text
+//! permit(principal, action == Action::"swap", resource)
+//! when { context.slippageBps.u256LessThanOrEqual(u256("100")) };
+//! ```
+//! instead of against the calldata of each router.
+//!
+//! An [`IntentRegistry`] holds the classifiers, starting with the built-in
+//! ones for Uniswap, Aave, Lido, ERC-721 marketplaces, the bridges in
+//! [`bridge`](crate::domain::bridge), and the payment streams in
+//! [`streaming`](crate::domain::streaming).
+
+use std::fmt::Debug;
+
+use ethers::types::U256;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use sha3::{Digest, Keccak256};
+
+use crate::audit::{to_hex, unhex};
+use crate::domain::bridge::{Axelar, LayerZero, NativeBridges};
+use crate::domain::streaming::{Sablier, Superfluid};
+use crate::domain::swap_protection::with_slippage;
+use crate::{Context, ContextJsonError, EntityUid};
+
+/// A contract call
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Call {
+    /// The contract called
+    pub to: String,
+    /// The value sent, in wei, as a decimal string
+    #[serde(default = "zero")]
+    pub value: String,
+    /// The calldata, as `0x`-prefixed hex
+    pub data: String,
+    /// The amount a swap is expected to produce, e.g. from a quote, as a
+    /// decimal string. Swap classifiers use it to compute the slippage.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_amount_out: Option<String>,
+    /// The id of the chain the call is made on. Bridge classifiers use it as
+    /// the source chain.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chain_id: Option<u64>,
+    /// The RPC endpoint the transaction is to be sent through. Swap
+    /// protection uses it to tell whether the swap avoids the public mempool.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rpc_url: Option<String>,
+}
+
+fn zero() -> String {
+    "0".to_string()
+}
+
+impl Call {
+    /// A call of `to` with `data`, sending no value
+    pub fn new(to: impl Into<String>, data: impl Into<String>) -> Self {
+        Self {
+            to: to.into(),
+            value: zero(),
+            data: data.into(),
+            expected_amount_out: None,
+            chain_id: None,
+            rpc_url: None,
+        }
+    }
+
+    /// The decoded calldata, or `None` if it isn't hex or is shorter than a
+    /// selector
+    pub fn calldata(&self) -> Option<Calldata> {
+        let bytes = unhex(self.data.strip_prefix("0x").unwrap_or(&self.data))?;
+        (bytes.len() >= 4).then_some(Calldata { bytes })
+    }
+}
+
+/// ABI-encoded calldata
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Calldata {
+    bytes: Vec<u8>,
+}
+
+impl Calldata {
+    /// The function selector
+    pub fn selector(&self) -> [u8; 4] {
+        match self.bytes.as_slice() {
+            [a, b, c, d, ..] => [*a, *b, *c, *d],
+            // calldata is never shorter than a selector
+            _ => [0; 4],
+        }
+    }
+
+    /// The `i`th word of the arguments
+    pub fn word(&self, i: usize) -> Option<&[u8]> {
+        self.bytes.get(4 + 32 * i..4 + 32 * (i + 1))
+    }
+
+    /// The `i`th word, as an unsigned integer
+    pub fn uint(&self, i: usize) -> Option<U256> {
+        self.word(i).map(U256::from_big_endian)
+    }
+
+    /// The `i`th word, as an address
+    pub fn address(&self, i: usize) -> Option<String> {
+        let (padding, address) = self.word(i)?.split_at(12);
+        padding
+            .iter()
+            .all(|b| *b == 0)
+            .then(|| format!("0x{}", to_hex(address)))
+    }
+
+    /// The `i`th word, as a boolean
+    pub fn bool(&self, i: usize) -> Option<bool> {
+        let n = self.uint(i)?;
+        (n <= U256::one()).then(|| n == U256::one())
+    }
+
+    /// The dynamic array of addresses whose offset is the `i`th word
+    pub fn address_array(&self, i: usize) -> Option<Vec<String>> {
+        let start = self.offset(i)?;
+        let len = usize::try_from(self.uint(start)?).ok()?;
+        (1..=len).map(|j| self.address(start + j)).collect()
+    }
+
+    /// The dynamic `bytes` whose offset is the `i`th word
+    pub fn bytes(&self, i: usize) -> Option<&[u8]> {
+        let start = self.offset(i)?;
+        let len = usize::try_from(self.uint(start)?).ok()?;
+        let from = 4 + 32 * (start + 1);
+        self.bytes.get(from..from.checked_add(len)?)
+    }
+
+    /// The dynamic `string` whose offset is the `i`th word
+    pub fn string(&self, i: usize) -> Option<String> {
+        String::from_utf8(self.bytes(i)?.to_vec()).ok()
+    }
+
+    /// The index of the word at the offset held in the `i`th word
+    pub fn offset(&self, i: usize) -> Option<usize> {
+        let offset = usize::try_from(self.uint(i)?).ok()?;
+        (offset % 32 == 0).then_some(offset / 32)
+    }
+}
+
+/// The function selector of `signature`, e.g. `transfer(address,uint256)`
+pub fn selector(signature: &str) -> [u8; 4] {
+    let hash: [u8; 32] = Keccak256::digest(signature.as_bytes()).into();
+    let [a, b, c, d, ..] = hash;
+    [a, b, c, d]
+}
+
+/// What a call does, in protocol terms
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Intent {
+    /// The protocol, e.g. `uniswap`
+    pub protocol: String,
+    /// The normalized action, e.g. `swap`
+    pub action: String,
+    /// The parameters, in the JSON format of a Cedar context
+    pub context: Map<String, Value>,
+}
+
+impl Intent {
+    /// An intent with an empty context
+    pub fn new(protocol: impl Into<String>, action: impl Into<String>) -> Self {
+        Self {
+            protocol: protocol.into(),
+            action: action.into(),
+            context: Map::new(),
+        }
+    }
+
+    /// Add `value` to the context as `key`
+    #[must_use]
+    pub fn with(mut self, key: &str, value: Value) -> Self {
+        self.context.insert(key.to_string(), value);
+        self
+    }
+
+    /// The action, as an entity of type `Action`
+    pub fn action_uid(&self) -> EntityUid {
+        EntityUid::from_strs("Action", &self.action)
+    }
+
+    /// The context for a request, which also holds the protocol as
+    /// `protocol`
+    pub fn to_context(&self) -> Result<Context, ContextJsonError> {
+        let mut context = self.context.clone();
+        context.insert("protocol".to_string(), Value::from(self.protocol.as_str()));
+        Context::from_json_value(Value::Object(context), None)
+    }
+}
+
+/// A `u256` value, in the JSON format of a Cedar context
+pub fn u256_value(n: U256) -> Value {
+    json!({ "__extn": { "fn": "u256", "arg": n.to_string() } })
+}
+
+/// The number held by a `u256` value in the JSON format of a Cedar context
+pub(crate) fn u256_of(value: &Value) -> Option<U256> {
+    let arg = value.pointer("/__extn/arg")?.as_str()?;
+    U256::from_dec_str(arg).ok()
+}
+
+/// A `Long` value, if `n` fits in one
+pub(crate) fn long_value(n: U256) -> Option<Value> {
+    if n.bits() > 63 {
+        return None;
+    }
+    i64::try_from(n.low_u64()).ok().map(Value::from)
+}
+
+/// Decodes calls into intents
+pub trait IntentClassifier: Debug + Send + Sync {
+    /// The intent of `call`, or `None` if this classifier doesn't recognize
+    /// it
+    fn classify(&self, call: &Call) -> Option<Intent>;
+}
+
+/// A set of classifiers
+#[derive(Debug)]
+pub struct IntentRegistry {
+    classifiers: Vec<Box<dyn IntentClassifier>>,
+}
+
+impl Default for IntentRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.register(Sablier);
+        registry.register(Superfluid);
+        registry.register(Axelar);
+        registry.register(LayerZero);
+        registry.register(NativeBridges::new());
+        registry.register(Erc721Marketplaces);
+        registry.register(Lido);
+        registry.register(Aave);
+        registry.register(Uniswap);
+        registry
+    }
+}
+
+impl IntentRegistry {
+    /// A registry of the built-in classifiers
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A registry without any classifiers
+    pub fn empty() -> Self {
+        Self {
+            classifiers: Vec::new(),
+        }
+    }
+
+    /// Add a classifier. Classifiers are tried from the most recently
+    /// registered, so one registered later can override a built-in one.
+    pub fn register(&mut self, classifier: impl IntentClassifier + 'static) {
+        self.classifiers.push(Box::new(classifier));
+    }
+
+    /// The intent of `call`, from the first classifier which recognizes it
+    pub fn classify(&self, call: &Call) -> Option<Intent> {
+        self.classifiers
+            .iter()
+            .rev()
+            .find_map(|classifier| classifier.classify(call))
+    }
+}
+
+/// Uniswap swaps: `swapExactTokensForTokens` on the V2 router, and
+/// `exactInputSingle` on the V3 routers. The action is `swap`, with context
+/// `tokenIn`, `tokenOut`, `amountIn`, `minAmountOut`, `recipient`, and, for
+/// V3, `fee`. If the call has an expected output, the context also has
+/// `expectedAmountOut` and `slippageBps`, as added by [`with_slippage()`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Uniswap;
+
+impl IntentClassifier for Uniswap {
+    fn classify(&self, call: &Call) -> Option<Intent> {
+        let data = call.calldata()?;
+        let selector = data.selector();
+        let (token_in, token_out, amount_in, min_out, recipient, fee) = if selector
+            == self::selector("swapExactTokensForTokens(uint256,uint256,address[],address,uint256)")
+        {
+            let path = data.address_array(2)?;
+            (
+                path.first()?.clone(),
+                path.last()?.clone(),
+                data.uint(0)?,
+                data.uint(1)?,
+                data.address(3)?,
+                None,
+            )
+        } else if selector
+            == self::selector(
+                "exactInputSingle((address,address,uint24,address,uint256,uint256,uint256,uint160))",
+            )
+        {
+            (
+                data.address(0)?,
+                data.address(1)?,
+                data.uint(5)?,
+                data.uint(6)?,
+                data.address(3)?,
+                Some(data.uint(2)?),
+            )
+        } else if selector
+            == self::selector(
+                "exactInputSingle((address,address,uint24,address,uint256,uint256,uint160))",
+            )
+        {
+            (
+                data.address(0)?,
+                data.address(1)?,
+                data.uint(4)?,
+                data.uint(5)?,
+                data.address(3)?,
+                Some(data.uint(2)?),
+            )
+        } else {
+            return None;
+        };
+        let mut intent = Intent::new("uniswap", "swap")
+            .with("tokenIn", token_in.into())
+            .with("tokenOut", token_out.into())
+            .with("amountIn", u256_value(amount_in))
+            .with("minAmountOut", u256_value(min_out))
+            .with("recipient", recipient.into());
+        if let Some(fee) = fee {
+            intent = intent.with("fee", long_value(fee)?);
+        }
+        if let Some(expected) = &call.expected_amount_out {
+            intent = with_slippage(intent, U256::from_dec_str(expected).ok()?);
+        }
+        Some(intent)
+    }
+}
+
+/// Aave V3 pool `supply` and `borrow` calls. The actions are `supply` and
+/// `borrow`, with context `asset`, `amount`, and `onBehalfOf`, and, for
+/// borrows, `interestRateMode`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Aave;
+
+impl IntentClassifier for Aave {
+    fn classify(&self, call: &Call) -> Option<Intent> {
+        let data = call.calldata()?;
+        let selector = data.selector();
+        let intent = if selector == self::selector("supply(address,uint256,address,uint16)") {
+            Intent::new("aave", "supply").with("onBehalfOf", data.address(2)?.into())
+        } else if selector == self::selector("borrow(address,uint256,uint256,uint16,address)") {
+            Intent::new("aave", "borrow")
+                .with("interestRateMode", long_value(data.uint(2)?)?)
+                .with("onBehalfOf", data.address(4)?.into())
+        } else {
+            return None;
+        };
+        Some(
+            intent
+                .with("asset", data.address(0)?.into())
+                .with("amount", u256_value(data.uint(1)?)),
+        )
+    }
+}
+
+/// Lido `submit` calls, staking Ether. The action is `stake`, with context
+/// `amount`, the value sent, and `referral`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Lido;
+
+impl IntentClassifier for Lido {
+    fn classify(&self, call: &Call) -> Option<Intent> {
+        let data = call.calldata()?;
+        if data.selector() != selector("submit(address)") {
+            return None;
+        }
+        Some(
+            Intent::new("lido", "stake")
+                .with("amount", u256_value(U256::from_dec_str(&call.value).ok()?))
+                .with("referral", data.address(0)?.into()),
+        )
+    }
+}
+
+/// ERC-721 marketplace operations:
+/// - Seaport `fulfillBasicOrder`, with action `buyNft` and context
+///   `collection`, `tokenId`, `seller`, `price`, and `paymentToken` (the zero
+///   address for Ether)
+/// - `setApprovalForAll`, with action `approveOperator` and context
+///   `collection`, `operator`, and `approved`
+/// - `safeTransferFrom`, with action `transferNft` and context
+///   `collection`, `from`, `to`, and `tokenId`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Erc721Marketplaces;
+
+const FULFILL_BASIC_ORDER: &str = concat!(
+    "fulfillBasicOrder((address,uint256,uint256,address,address,address,uint256,uint256,uint8,",
+    "uint256,uint256,bytes32,uint256,bytes32,bytes32,uint256,(uint256,address)[],bytes))"
+);
+
+impl IntentClassifier for Erc721Marketplaces {
+    fn classify(&self, call: &Call) -> Option<Intent> {
+        let data = call.calldata()?;
+        let selector = data.selector();
+        let collection = crate::domain::session::address(&call.to).ok()?;
+        if selector == self::selector(FULFILL_BASIC_ORDER) {
+            let order = data.offset(0)?;
+            Some(
+                Intent::new("seaport", "buyNft")
+                    .with("collection", data.address(order + 5)?.into())
+                    .with("tokenId", u256_value(data.uint(order + 6)?))
+                    .with("seller", data.address(order + 3)?.into())
+                    .with("price", u256_value(data.uint(order + 2)?))
+                    .with("paymentToken", data.address(order)?.into()),
+            )
+        } else if selector == self::selector("setApprovalForAll(address,bool)") {
+            Some(
+                Intent::new("erc721", "approveOperator")
+                    .with("collection", collection.into())
+                    .with("operator", data.address(0)?.into())
+                    .with("approved", data.bool(1)?.into()),
+            )
+        } else if selector == self::selector("safeTransferFrom(address,address,uint256)") {
+            Some(
+                Intent::new("erc721", "transferNft")
+                    .with("collection", collection.into())
+                    .with("from", data.address(0)?.into())
+                    .with("to", data.address(1)?.into())
+                    .with("tokenId", u256_value(data.uint(2)?)),
+            )
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Authorizer, Decision, Entities, PolicySet, Request};
+    use std::str::FromStr;
+
+    const ROUTER: &str = "0xe592427a0aece92a3ee1f1d0fea70d8c7a4e0b46";
+    const WETH: &str = "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2";
+    const USDC: &str = "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48";
+
+    fn word(n: impl Into<U256>) -> String {
+        let mut bytes = [0; 32];
+        n.into().to_big_endian(&mut bytes);
+        to_hex(&bytes)
+    }
+
+    fn address_word(address: &str) -> String {
+        format!("{:0>64}", &address[2..])
+    }
+
+    fn encode(signature: &str, words: &[String]) -> String {
+        format!("0x{}{}", to_hex(&selector(signature)), words.concat())
+    }
+
+    #[test]
+    fn uniswap_v3() {
+        let mut call = Call::new(
+            ROUTER,
+            encode(
+                "exactInputSingle((address,address,uint24,address,uint256,uint256,uint256,uint160))",
+                &[
+                    address_word(WETH),
+                    address_word(USDC),
+                    word(3000),
+                    address_word(USDC),
+                    word(1_700_000_000),
+                    word(1_000_000),
+                    word(990),
+                    word(0),
+                ],
+            ),
+        );
+        call.expected_amount_out = Some("1000".to_string());
+        let intent = IntentRegistry::new().classify(&call).unwrap();
+        assert_eq!(intent.action, "swap");
+        assert_eq!(intent.context["tokenIn"], WETH);
+        assert_eq!(intent.context["fee"], 3000);
+        assert_eq!(intent.context["slippageBps"], u256_value(100.into()));
+
+        let policies = PolicySet::from_str(
+            r#"permit(principal, action == Action::"swap", resource)
+               when { context.slippageBps.u256LessThanOrEqual(u256("100")) };"#,
+        )
+        .unwrap();
+        let request = Request::new(
+            None,
+            Some(intent.action_uid()),
+            None,
+            intent.to_context().unwrap(),
+        );
+        let response = Authorizer::new().is_authorized(&request, &policies, &Entities::empty());
+        assert_eq!(response.decision(), Decision::Allow);
+    }
+
+    #[test]
+    fn uniswap_v2() {
+        let call = Call::new(
+            ROUTER,
+            encode(
+                "swapExactTokensForTokens(uint256,uint256,address[],address,uint256)",
+                &[
+                    word(1_000_000),
+                    word(0),
+                    word(5 * 32),
+                    address_word(USDC),
+                    word(1_700_000_000),
+                    word(2),
+                    address_word(USDC),
+                    address_word(WETH),
+                ],
+            ),
+        );
+        let intent = Uniswap.classify(&call).unwrap();
+        assert_eq!(intent.context["tokenIn"], USDC);
+        assert_eq!(intent.context["tokenOut"], WETH);
+        assert!(!intent.context.contains_key("slippageBps"));
+    }
+
+    #[test]
+    fn lending_and_staking() {
+        let borrow = Call::new(
+            ROUTER,
+            encode(
+                "borrow(address,uint256,uint256,uint16,address)",
+                &[
+                    address_word(USDC),
+                    word(500),
+                    word(2),
+                    word(0),
+                    address_word(WETH),
+                ],
+            ),
+        );
+        let intent = IntentRegistry::new().classify(&borrow).unwrap();
+        assert_eq!(
+            (intent.protocol.as_str(), intent.action.as_str()),
+            ("aave", "borrow")
+        );
+        assert_eq!(intent.context["interestRateMode"], 2);
+        assert_eq!(intent.context["amount"], u256_value(500.into()));
+
+        let mut stake = Call::new(ROUTER, encode("submit(address)", &[word(0)]));
+        stake.value = "1000000000000000000".to_string();
+        let intent = IntentRegistry::new().classify(&stake).unwrap();
+        assert_eq!(intent.action, "stake");
+        assert_eq!(intent.context["amount"], u256_value(U256::exp10(18)));
+    }
+
+    #[test]
+    fn marketplaces() {
+        assert_eq!(to_hex(&selector(FULFILL_BASIC_ORDER)), "fb0f3ee1");
+        let approval = Call::new(
+            WETH,
+            encode(
+                "setApprovalForAll(address,bool)",
+                &[address_word(ROUTER), word(1)],
+            ),
+        );
+        let intent = IntentRegistry::new().classify(&approval).unwrap();
+        assert_eq!(intent.action, "approveOperator");
+        assert_eq!(intent.context["approved"], true);
+        assert_eq!(intent.context["collection"], WETH);
+    }
+
+    #[derive(Debug)]
+    struct Everything;
+
+    impl IntentClassifier for Everything {
+        fn classify(&self, _: &Call) -> Option<Intent> {
+            Some(Intent::new("custom", "call"))
+        }
+    }
+
+    #[test]
+    fn registry() {
+        let unknown = Call::new(ROUTER, "0xdeadbeef");
+        assert_eq!(IntentRegistry::new().classify(&unknown), None);
+        assert_eq!(
+            IntentRegistry::empty().classify(&Call::new(ROUTER, "0x")),
+            None
+        );
+
+        let mut registry = IntentRegistry::new();
+        registry.register(Everything);
+        let stake = Call::new(ROUTER, encode("submit(address)", &[word(0)]));
+        assert_eq!(registry.classify(&stake).unwrap().protocol, "custom");
+    }
+}
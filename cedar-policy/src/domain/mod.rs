@@ -0,0 +1,97 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Providers of web3, DeFi and compliance data.
+//!
+//! These modules turn on-chain state, signed messages and third-party
+//! services into request context and entities for the authorizer: typed
+//! data and permits, governance outcomes and role mirrors, intents of
+//! contract calls, attestations, and the like. None of them are needed to
+//! evaluate policies, so they are only built with the `domain` feature.
+//! The providers which fetch data over the network also need their own
+//! features, such as `governor`, `snapshot`, `eas` and `gitcoin-passport`.
+//!
+//! # Network requests
+//!
+//! The sources which fetch data over the network, here and in the
+//! `screening` and `config` modules, send blocking requests with
+//! `reqwest::blocking`. They mustn't be used from within an async runtime,
+//! other than on a thread for blocking work, e.g. with
+//! `tokio::task::spawn_blocking`.
+
+/// EIP-712 typed data for requests and decision receipts
+pub mod eip712;
+
+/// Humanity scores of wallets, as entity attributes
+pub mod passport;
+
+/// Outcomes of governance proposals, as entity attributes
+pub mod governance;
+
+/// Mirroring of on-chain `AccessControl` roles, as entity parents
+pub mod role_sync;
+
+/// Session key permissions and ERC-7715 grants
+#[cfg(feature = "u256")]
+pub mod session;
+
+/// Spending limits compiled to Safe Allowance module configuration
+#[cfg(feature = "u256")]
+pub mod allowance;
+
+/// Classification of contract calls into protocol-level intents
+#[cfg(feature = "u256")]
+pub mod intent;
+
+/// Entity attributes verified by EIP-1186 state proofs
+#[cfg(feature = "u256")]
+pub mod state_proof;
+
+/// Cross-chain bridge intents
+#[cfg(feature = "u256")]
+pub mod bridge;
+
+/// Streaming payment intents
+#[cfg(feature = "u256")]
+pub mod streaming;
+
+/// Slippage and MEV protection for swap intents
+#[cfg(feature = "u256")]
+pub mod swap_protection;
+
+/// ERC-2612 and Permit2 permits as request context
+#[cfg(feature = "u256")]
+pub mod permit;
+
+/// Seaport orders as request context
+#[cfg(feature = "u256")]
+pub mod seaport;
+
+/// Transaction simulation results as request context
+#[cfg(feature = "u256")]
+pub mod simulation;
+
+/// Ethereum Attestation Service attestations as entity attributes
+#[cfg(feature = "u256")]
+pub mod attestation;
+
+/// Counterparty exposure over rolling windows, as request context
+#[cfg(feature = "exposure")]
+pub mod exposure;
+
+/// Gas parameters as request context
+#[cfg(feature = "gas")]
+pub mod gas;
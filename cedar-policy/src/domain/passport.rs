@@ -0,0 +1,530 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Humanity scores of wallets, such as Gitcoin Passport scores, as entity
+//! attributes.
+//!
+//! A [`PassportProvider`] fetches the score of a wallet from a
+//! [`ScoreSource`] and makes the wallet an `Address` entity with the
+//! attribute [`PASSPORT_ATTRIBUTE`], a record with:
+//! - `score`: the score, rounded down to a whole number of points
+//! - `passing`: whether the scorer considers the score passing
+//! - `updatedAt`: when the scorer last computed the score, in seconds since
+//!   the Unix epoch
+//! - `fetchedAt`: when the score was fetched from the source, in seconds
+//!   since the Unix epoch
+//!
+//! so sybil-resistance policies can require a fresh enough score, e.g.
+//! ```text
+//! permit(principal, action == Action::"claimAirdrop", resource)
+//! when {
+//!   principal.passport.score >= 20 &&
+//!   context.now - principal.passport.updatedAt < 604800
+//! };
+//! ```
+//!
+//! Scores are cached for [`DEFAULT_TTL`] (see [`PassportProvider::with_ttl()`]).
+//! [`MemoryScoreSource`] holds scores in memory. With the `gitcoin-passport`
+//! feature, `GitcoinPassportSource` fetches them from the Gitcoin Passport
+//! API.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, SystemTime};
+
+use cedar_policy_core::ast;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::address_book::ADDRESS_TYPE;
+use crate::audit::{normalize_address, unix_seconds};
+use crate::{Entities, EntitiesError, Entity, EntityTypeName, EntityUid, RestrictedExpression};
+
+/// The entity attribute holding a wallet's score
+pub const PASSPORT_ATTRIBUTE: &str = "passport";
+
+/// How long a [`PassportProvider`] caches scores by default
+pub const DEFAULT_TTL: Duration = Duration::from_mins(15);
+
+/// Errors resolving scores
+#[derive(Debug, Error)]
+pub enum PassportError {
+    /// A value isn't an address
+    #[error("`{0}` is not an address")]
+    InvalidAddress(String),
+    /// The source failed
+    #[error("failed to fetch the score of {address}: {message}")]
+    Source {
+        /// The address
+        address: String,
+        /// What went wrong
+        message: String,
+    },
+    /// The entities couldn't be built
+    #[error(transparent)]
+    Entities(#[from] EntitiesError),
+}
+
+/// The score of a wallet
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PassportScore {
+    /// The score, rounded down to a whole number of points
+    pub score: u64,
+    /// Whether the scorer considers the score passing
+    pub passing: bool,
+    /// When the scorer last computed the score, in seconds since the Unix
+    /// epoch
+    pub updated_at: u64,
+}
+
+/// A source of the scores of wallets
+pub trait ScoreSource: Debug + Send + Sync {
+    /// The score of `address`, a lowercase `0x`-prefixed hex address
+    fn score(&self, address: &str) -> Result<PassportScore, PassportError>;
+}
+
+/// Scores held in memory. Wallets without a score score 0, which isn't
+/// passing.
+#[derive(Debug, Default)]
+pub struct MemoryScoreSource {
+    scores: Mutex<HashMap<String, PassportScore>>,
+}
+
+impl MemoryScoreSource {
+    /// A source with no scores
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the score of `address`
+    pub fn insert(&self, address: &str, score: PassportScore) -> Result<(), PassportError> {
+        let address = normalize_address(address)
+            .ok_or_else(|| PassportError::InvalidAddress(address.to_string()))?;
+        self.scores
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(address, score);
+        Ok(())
+    }
+}
+
+impl ScoreSource for MemoryScoreSource {
+    fn score(&self, address: &str) -> Result<PassportScore, PassportError> {
+        Ok(self
+            .scores
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(address)
+            .cloned()
+            .unwrap_or(PassportScore {
+                score: 0,
+                passing: false,
+                updated_at: 0,
+            }))
+    }
+}
+
+/// Resolves the scores of wallets into entities, caching them
+#[derive(Debug)]
+pub struct PassportProvider {
+    source: Arc<dyn ScoreSource>,
+    entity_type: EntityTypeName,
+    ttl: Duration,
+    cache: Mutex<HashMap<String, (PassportScore, u64)>>,
+}
+
+impl PassportProvider {
+    /// A provider of `Address` entities with the scores from `source`
+    pub fn new(source: Arc<dyn ScoreSource>) -> Self {
+        Self {
+            source,
+            entity_type: address_type(),
+            ttl: DEFAULT_TTL,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Make wallets entities of type `entity_type`, instead of `Address`
+    #[must_use]
+    pub fn with_entity_type(mut self, entity_type: EntityTypeName) -> Self {
+        self.entity_type = entity_type;
+        self
+    }
+
+    /// Cache scores for `ttl`. A `ttl` of zero disables the cache.
+    #[must_use]
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// The score of `address` at `now`, from the cache if possible, and when
+    /// it was fetched, in seconds since the Unix epoch
+    pub fn score(
+        &self,
+        address: &str,
+        now: SystemTime,
+    ) -> Result<(PassportScore, u64), PassportError> {
+        let address = normalize_address(address)
+            .ok_or_else(|| PassportError::InvalidAddress(address.to_string()))?;
+        let now = unix_seconds(now);
+        let cached = self
+            .cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&address)
+            .filter(|(_, fetched_at)| now < fetched_at.saturating_add(self.ttl.as_secs()))
+            .cloned();
+        if let Some(cached) = cached {
+            return Ok(cached);
+        }
+        let score = self.source.score(&address)?;
+        if !self.ttl.is_zero() {
+            self.cache
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(address, (score.clone(), now));
+        }
+        Ok((score, now))
+    }
+
+    /// The entity for `address` with its score at `now`
+    pub fn entity(&self, address: &str, now: SystemTime) -> Result<Entity, PassportError> {
+        let address = normalize_address(address)
+            .ok_or_else(|| PassportError::InvalidAddress(address.to_string()))?;
+        let (score, fetched_at) = self.score(&address, now)?;
+        let long = |n: u64| RestrictedExpression::new_long(n.try_into().unwrap_or(i64::MAX));
+        let passport = RestrictedExpression::new_record([
+            ("score".to_string(), long(score.score)),
+            (
+                "passing".to_string(),
+                RestrictedExpression::new_bool(score.passing),
+            ),
+            ("updatedAt".to_string(), long(score.updated_at)),
+            ("fetchedAt".to_string(), long(fetched_at)),
+        ]);
+        Ok(Entity::new(
+            EntityUid(ast::EntityUID::from_components(
+                self.entity_type.0.clone(),
+                ast::Eid::new(address),
+            )),
+            HashMap::from([(PASSPORT_ATTRIBUTE.to_string(), passport)]),
+            HashSet::new(),
+        ))
+    }
+
+    /// The entities for `addresses` with their scores at `now`
+    pub fn entities_for<'a>(
+        &self,
+        addresses: impl IntoIterator<Item = &'a str>,
+        now: SystemTime,
+    ) -> Result<Entities, PassportError> {
+        let entities = addresses
+            .into_iter()
+            .map(|address| self.entity(address, now))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Entities::from_entities(entities)?)
+    }
+}
+
+/// The `Address` entity type
+fn address_type() -> EntityTypeName {
+    // PANIC SAFETY: `Address` is a valid entity type name
+    #[allow(clippy::expect_used)]
+    EntityTypeName::from_str(ADDRESS_TYPE).expect("`Address` is a valid entity type name")
+}
+
+/// The seconds since the Unix epoch of an RFC 3339 timestamp, e.g.
+/// `2024-10-01T12:00:00.000Z` or `2024-10-01T12:00:00+02:00`, or `None` if
+/// it is malformed or before the epoch
+#[cfg_attr(not(feature = "gitcoin-passport"), allow(dead_code))]
+fn parse_timestamp(timestamp: &str) -> Option<u64> {
+    let (date, time) = timestamp.split_once(['T', ' '])?;
+    let mut date = date.splitn(3, '-').map(str::parse::<i64>);
+    let (year, month, day) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+    let (clock, offset) = time
+        .find(['Z', 'z', '+', '-'])
+        .map_or((time, ""), |i| time.split_at(i));
+    let mut clock = clock.splitn(3, ':');
+    let hours: i64 = clock.next()?.parse().ok()?;
+    let minutes: i64 = clock.next()?.parse().ok()?;
+    let seconds: i64 = clock.next()?.split('.').next()?.parse().ok()?;
+    let offset = match offset.chars().next() {
+        None | Some('Z' | 'z') => 0,
+        Some(sign) => {
+            let (h, m) = offset.get(1..)?.split_once(':')?;
+            let offset = h.parse::<i64>().ok()? * 3600 + m.parse::<i64>().ok()? * 60;
+            if sign == '-' {
+                -offset
+            } else {
+                offset
+            }
+        }
+    };
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || !(0..24).contains(&hours)
+        || !(0..60).contains(&minutes)
+        || !(0..=60).contains(&seconds)
+    {
+        return None;
+    }
+    let days = days_from_civil(year, month, day);
+    u64::try_from(days * 86_400 + hours * 3600 + minutes * 60 + seconds - offset).ok()
+}
+
+/// The days since the Unix epoch of a date in the proleptic Gregorian
+/// calendar, by Howard Hinnant's `days_from_civil`
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(feature = "gitcoin-passport")]
+pub use gitcoin::{GitcoinPassportSource, GITCOIN_PASSPORT_API};
+
+#[cfg(feature = "gitcoin-passport")]
+mod gitcoin {
+    use super::{parse_timestamp, PassportError, PassportScore, ScoreSource};
+    use serde::Deserialize;
+
+    /// The base URL of the Gitcoin Passport API
+    pub const GITCOIN_PASSPORT_API: &str = "https://api.passport.xyz";
+
+    /// The response of the API for an address
+    #[derive(Debug, Deserialize)]
+    struct ScoreResponse {
+        score: Option<String>,
+        passing_score: bool,
+        last_score_timestamp: Option<String>,
+        error: Option<String>,
+    }
+
+    /// Fetches scores from the Gitcoin Passport API, with a scorer
+    ///
+    /// Its requests are [blocking](crate::domain#network-requests).
+    #[derive(Debug)]
+    pub struct GitcoinPassportSource {
+        client: reqwest::blocking::Client,
+        base_url: String,
+        scorer_id: String,
+        api_key: String,
+    }
+
+    impl GitcoinPassportSource {
+        /// A source using the scorer `scorer_id` of the public API with
+        /// `api_key`
+        pub fn new(scorer_id: impl Into<String>, api_key: impl Into<String>) -> Self {
+            Self {
+                client: reqwest::blocking::Client::new(),
+                base_url: GITCOIN_PASSPORT_API.to_string(),
+                scorer_id: scorer_id.into(),
+                api_key: api_key.into(),
+            }
+        }
+
+        /// Use the API at `base_url` instead of the public one
+        #[must_use]
+        pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+            self.base_url = base_url.into();
+            self
+        }
+    }
+
+    impl ScoreSource for GitcoinPassportSource {
+        fn score(&self, address: &str) -> Result<PassportScore, PassportError> {
+            let url = format!(
+                "{}/v2/stamps/{}/score/{address}",
+                self.base_url.trim_end_matches('/'),
+                self.scorer_id
+            );
+            let body = self
+                .client
+                .get(url)
+                .header("X-API-KEY", &self.api_key)
+                .send()
+                .and_then(reqwest::blocking::Response::error_for_status)
+                .and_then(reqwest::blocking::Response::text)
+                .map_err(|err| PassportError::Source {
+                    address: address.to_string(),
+                    message: err.to_string(),
+                })?;
+            parse_response(&body).map_err(|message| PassportError::Source {
+                address: address.to_string(),
+                message,
+            })
+        }
+    }
+
+    /// The score in the API's response `body`
+    pub(super) fn parse_response(body: &str) -> Result<PassportScore, String> {
+        let response: ScoreResponse = serde_json::from_str(body).map_err(|e| e.to_string())?;
+        if let Some(error) = response.error {
+            return Err(error);
+        }
+        let score = response.score.unwrap_or_default();
+        let whole = score.split('.').next().unwrap_or_default();
+        let score = if whole.is_empty() {
+            0
+        } else {
+            whole
+                .parse()
+                .map_err(|_| format!("invalid score `{score}`"))?
+        };
+        let updated_at = match response.last_score_timestamp {
+            Some(timestamp) => parse_timestamp(&timestamp)
+                .ok_or_else(|| format!("invalid timestamp `{timestamp}`"))?,
+            None => 0,
+        };
+        Ok(PassportScore {
+            score,
+            passing: response.passing_score,
+            updated_at,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Authorizer, Context, Decision, PolicySet, Request};
+
+    const WALLET: &str = "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266";
+
+    fn at(seconds: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(parse_timestamp("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(
+            parse_timestamp("2024-10-01T12:00:00.123456+00:00"),
+            Some(1_727_784_000)
+        );
+        assert_eq!(
+            parse_timestamp("2024-10-01T14:00:00+02:00"),
+            Some(1_727_784_000)
+        );
+        assert_eq!(parse_timestamp("2000-02-29T00:00:00Z"), Some(951_782_400));
+        assert_eq!(parse_timestamp("1969-12-31T23:59:59Z"), None);
+        assert_eq!(parse_timestamp("2024-13-01T00:00:00Z"), None);
+        assert_eq!(parse_timestamp("yesterday"), None);
+    }
+
+    #[test]
+    fn caches_scores() {
+        let source = Arc::new(MemoryScoreSource::new());
+        let score = |score| PassportScore {
+            score,
+            passing: score >= 20,
+            updated_at: 50,
+        };
+        source.insert(WALLET, score(25)).unwrap();
+        assert!(source.insert("alice", score(25)).is_err());
+        let provider = PassportProvider::new(Arc::clone(&source) as Arc<dyn ScoreSource>)
+            .with_ttl(Duration::from_secs(100));
+
+        assert_eq!(provider.score(WALLET, at(100)).unwrap(), (score(25), 100));
+        source.insert(WALLET, score(5)).unwrap();
+        assert_eq!(
+            provider
+                .score(&WALLET.to_ascii_lowercase(), at(150))
+                .unwrap(),
+            (score(25), 100)
+        );
+        assert_eq!(provider.score(WALLET, at(200)).unwrap(), (score(5), 200));
+        assert!(provider.score("alice", at(200)).is_err());
+    }
+
+    #[test]
+    fn scores_in_entities() {
+        let source = MemoryScoreSource::new();
+        source
+            .insert(
+                WALLET,
+                PassportScore {
+                    score: 25,
+                    passing: true,
+                    updated_at: 1_000,
+                },
+            )
+            .unwrap();
+        let provider = PassportProvider::new(Arc::new(source));
+        let policies = PolicySet::from_str(
+            r#"permit(principal, action == Action::"claimAirdrop", resource)
+               when {
+                 principal.passport.score >= 20 &&
+                 principal.passport.passing &&
+                 context.now - principal.passport.updatedAt < 86400
+               };"#,
+        )
+        .unwrap();
+        let decide = |address: &str, now: i64| {
+            let request = Request::new(
+                Some(EntityUid::from_strs(
+                    "Address",
+                    &address.to_ascii_lowercase(),
+                )),
+                Some(EntityUid::from_strs("Action", "claimAirdrop")),
+                Some(EntityUid::from_strs("Airdrop", "a")),
+                Context::from_pairs([("now".to_string(), RestrictedExpression::new_long(now))]),
+            );
+            let entities = provider.entities_for([address], at(2_000)).unwrap();
+            Authorizer::new()
+                .is_authorized(&request, &policies, &entities)
+                .decision()
+        };
+        assert_eq!(decide(WALLET, 2_000), Decision::Allow);
+        // the score is stale
+        assert_eq!(decide(WALLET, 100_000), Decision::Deny);
+        // wallets without a score
+        assert_eq!(
+            decide("0x70997970c51812dc3a010c7d01b50e0d17dc79c8", 2_000),
+            Decision::Deny
+        );
+    }
+
+    #[cfg(feature = "gitcoin-passport")]
+    #[test]
+    fn gitcoin_responses() {
+        let score = gitcoin::parse_response(
+            r#"{"address": "0xf39fd6e51aad88f6f4ce6ab8827279cfffb92266", "score": "23.51900",
+                "passing_score": true, "last_score_timestamp": "2024-10-01T12:00:00.000000+00:00",
+                "expiration_timestamp": null, "threshold": "20.00000", "error": null, "stamps": {}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            score,
+            PassportScore {
+                score: 23,
+                passing: true,
+                updated_at: 1_727_784_000,
+            }
+        );
+        assert!(gitcoin::parse_response(
+            r#"{"score": null, "passing_score": false, "last_score_timestamp": null, "error": "Unable to get score"}"#
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,579 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Signed token approvals as request context.
+//!
+//! An ERC-2612 or Permit2 permit grants an allowance with an off-chain
+//! signature rather than a transaction, so it never passes through calldata
+//! screening. [`Permit::from_typed_data()`] decodes the EIP-712 typed data
+//! presented for signing, e.g. to `eth_signTypedData_v4`, and
+//! [`Permit::to_context()`] exposes the approval as typed attributes, so that
+//! signing requests can be authorized like transactions, e.g.
+//! ```text
+//! forbid(principal, action == Action::"signPermit", resource)
+//! when { context.unlimited || context.deadline > context.now + 3600 };
+//! ```
+
+use ethers::types::U256;
+use serde_json::{json, Map, Value};
+use thiserror::Error;
+
+use crate::audit::normalize_address;
+use crate::domain::intent::u256_value;
+use crate::domain::simulation::parse_amount;
+use crate::{Context, ContextJsonError};
+
+/// The largest Permit2 allowance, which Permit2 treats as unlimited
+const MAX_UINT160: U256 = U256([u64::MAX, u64::MAX, 0xffff_ffff, 0]);
+
+/// Errors decoding a permit
+#[derive(Debug, Error)]
+pub enum PermitError {
+    /// A field is missing or has the wrong type
+    #[error("malformed permit: `{0}` is missing or invalid")]
+    Malformed(String),
+    /// The typed data isn't a permit this module knows
+    #[error("unsupported permit type `{0}`")]
+    Unsupported(String),
+    /// An address isn't a `0x`-prefixed, 20 byte hex string
+    #[error("invalid address: {0}")]
+    InvalidAddress(String),
+    /// An amount isn't a decimal or `0x`-prefixed hex integer below 2^256
+    #[error("invalid amount: {0}")]
+    InvalidAmount(String),
+}
+
+/// The kind of permit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PermitStandard {
+    /// An ERC-2612 `Permit`, or the DAI permit which preceded it, approving
+    /// the spender on the token itself
+    Erc2612,
+    /// A Permit2 `PermitSingle` or `PermitBatch`, setting the spender's
+    /// allowance in the Permit2 contract
+    Permit2Allowance,
+    /// A Permit2 `PermitTransferFrom` or `PermitBatchTransferFrom`, with or
+    /// without a witness, allowing a single transfer
+    Permit2Transfer,
+}
+
+impl PermitStandard {
+    /// The name of the standard in a context: `erc2612`,
+    /// `permit2Allowance`, or `permit2Transfer`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Erc2612 => "erc2612",
+            Self::Permit2Allowance => "permit2Allowance",
+            Self::Permit2Transfer => "permit2Transfer",
+        }
+    }
+}
+
+/// A token and an amount of it which a permit allows the spender to take
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Permitted {
+    /// The token contract
+    pub token: String,
+    /// The amount, in the token's base units
+    pub amount: U256,
+    /// When a Permit2 allowance expires, in seconds since the Unix epoch
+    pub expiration: Option<i64>,
+    /// The nonce of a Permit2 allowance, which each token of a `PermitBatch`
+    /// has separately
+    pub nonce: Option<U256>,
+}
+
+/// A decoded permit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Permit {
+    /// The kind of permit
+    pub standard: PermitStandard,
+    /// Who grants the approval, if the permit names them. Permit2 permits
+    /// don't; their signer is the owner.
+    pub owner: Option<String>,
+    /// Who is approved
+    pub spender: String,
+    /// The tokens and amounts approved
+    pub permitted: Vec<Permitted>,
+    /// When the signature expires, in seconds since the Unix epoch.
+    /// Deadlines too late to be a `Long`, such as the maximum `uint256`
+    /// often used for "never", are `i64::MAX`.
+    pub deadline: i64,
+    /// The nonce of the permit, except for a Permit2 `PermitBatch`, whose
+    /// tokens each have their own
+    pub nonce: Option<U256>,
+    /// The chain the signature is valid on, from the EIP-712 domain
+    pub chain_id: Option<u64>,
+    /// The contract which verifies the signature, from the EIP-712 domain:
+    /// the token for ERC-2612 permits, and Permit2 otherwise
+    pub verifying_contract: Option<String>,
+}
+
+impl Permit {
+    /// Decode EIP-712 typed data, an object with `domain`, `primaryType`,
+    /// and `message` fields. Numbers may be JSON numbers, or decimal or
+    /// `0x`-prefixed hex strings.
+    pub fn from_typed_data(typed_data: &Value) -> Result<Self, PermitError> {
+        let primary_type = typed_data
+            .get("primaryType")
+            .and_then(Value::as_str)
+            .ok_or_else(|| PermitError::Malformed("primaryType".to_string()))?;
+        let domain = typed_data
+            .get("domain")
+            .ok_or_else(|| PermitError::Malformed("domain".to_string()))?;
+        let message = typed_data
+            .get("message")
+            .ok_or_else(|| PermitError::Malformed("message".to_string()))?;
+        let chain_id = match domain.get("chainId") {
+            Some(chain_id) => Some(
+                u64::try_from(number(chain_id, "domain.chainId")?)
+                    .map_err(|_| PermitError::Malformed("domain.chainId".to_string()))?,
+            ),
+            None => None,
+        };
+        let verifying_contract = match domain.get("verifyingContract") {
+            Some(_) => Some(address_field(domain, "verifyingContract", "domain.")?),
+            None => None,
+        };
+        let mut permit = match primary_type {
+            "Permit" => Self::erc2612(message, verifying_contract.as_deref())?,
+            "PermitSingle" => Self::permit2_allowance(message, false)?,
+            "PermitBatch" => Self::permit2_allowance(message, true)?,
+            "PermitTransferFrom" | "PermitWitnessTransferFrom" => {
+                Self::permit2_transfer(message, false)?
+            }
+            "PermitBatchTransferFrom" | "PermitBatchWitnessTransferFrom" => {
+                Self::permit2_transfer(message, true)?
+            }
+            _ => return Err(PermitError::Unsupported(primary_type.to_string())),
+        };
+        permit.chain_id = chain_id;
+        permit.verifying_contract = verifying_contract;
+        Ok(permit)
+    }
+
+    /// An ERC-2612 permit, or a DAI permit with `holder`, `expiry`, and
+    /// `allowed` fields, which approves the maximum amount or nothing
+    fn erc2612(message: &Value, token: Option<&str>) -> Result<Self, PermitError> {
+        let token =
+            token.ok_or_else(|| PermitError::Malformed("domain.verifyingContract".to_string()))?;
+        let dai = message.get("allowed").is_some();
+        let amount = if dai {
+            match message.get("allowed").and_then(Value::as_bool) {
+                Some(true) => U256::MAX,
+                Some(false) => U256::zero(),
+                None => return Err(PermitError::Malformed("message.allowed".to_string())),
+            }
+        } else {
+            number_field(message, "value", "message.")?
+        };
+        let (owner, deadline) = if dai {
+            ("holder", "expiry")
+        } else {
+            ("owner", "deadline")
+        };
+        Ok(Self {
+            standard: PermitStandard::Erc2612,
+            owner: Some(address_field(message, owner, "message.")?),
+            spender: address_field(message, "spender", "message.")?,
+            permitted: vec![Permitted {
+                token: token.to_string(),
+                amount,
+                expiration: None,
+                nonce: None,
+            }],
+            deadline: seconds(number_field(message, deadline, "message.")?),
+            nonce: Some(number_field(message, "nonce", "message.")?),
+            chain_id: None,
+            verifying_contract: None,
+        })
+    }
+
+    /// A Permit2 `PermitSingle` or `PermitBatch`
+    fn permit2_allowance(message: &Value, batch: bool) -> Result<Self, PermitError> {
+        let details = items(message, "details", batch)?;
+        let permitted = details
+            .iter()
+            .enumerate()
+            .map(|(i, details)| -> Result<Permitted, PermitError> {
+                let path = if batch {
+                    format!("message.details[{i}].")
+                } else {
+                    "message.details.".to_string()
+                };
+                Ok(Permitted {
+                    token: address_field(details, "token", &path)?,
+                    amount: number_field(details, "amount", &path)?,
+                    expiration: Some(seconds(number_field(details, "expiration", &path)?)),
+                    nonce: Some(number_field(details, "nonce", &path)?),
+                })
+            })
+            .collect::<Result<Vec<_>, PermitError>>()?;
+        // a single permit's nonce is that of its only token
+        let nonce = if batch {
+            None
+        } else {
+            permitted.first().and_then(|p| p.nonce)
+        };
+        Ok(Self {
+            standard: PermitStandard::Permit2Allowance,
+            owner: None,
+            spender: address_field(message, "spender", "message.")?,
+            permitted,
+            deadline: seconds(number_field(message, "sigDeadline", "message.")?),
+            nonce,
+            chain_id: None,
+            verifying_contract: None,
+        })
+    }
+
+    /// A Permit2 `PermitTransferFrom` or `PermitBatchTransferFrom`
+    fn permit2_transfer(message: &Value, batch: bool) -> Result<Self, PermitError> {
+        let permitted = items(message, "permitted", batch)?
+            .iter()
+            .enumerate()
+            .map(|(i, permitted)| -> Result<Permitted, PermitError> {
+                let path = if batch {
+                    format!("message.permitted[{i}].")
+                } else {
+                    "message.permitted.".to_string()
+                };
+                Ok(Permitted {
+                    token: address_field(permitted, "token", &path)?,
+                    amount: number_field(permitted, "amount", &path)?,
+                    expiration: None,
+                    nonce: None,
+                })
+            })
+            .collect::<Result<Vec<_>, PermitError>>()?;
+        Ok(Self {
+            standard: PermitStandard::Permit2Transfer,
+            owner: None,
+            spender: address_field(message, "spender", "message.")?,
+            permitted,
+            deadline: seconds(number_field(message, "deadline", "message.")?),
+            nonce: Some(number_field(message, "nonce", "message.")?),
+            chain_id: None,
+            verifying_contract: None,
+        })
+    }
+
+    /// Whether the permit approves an unlimited amount of any token: the
+    /// maximum `uint256`, or, for Permit2, the maximum `uint160`
+    pub fn is_unlimited(&self) -> bool {
+        let max = match self.standard {
+            PermitStandard::Erc2612 => U256::MAX,
+            PermitStandard::Permit2Allowance | PermitStandard::Permit2Transfer => MAX_UINT160,
+        };
+        self.permitted.iter().any(|p| p.amount >= max)
+    }
+
+    /// The permit as a JSON object in the format of a Cedar context, with
+    /// attributes
+    /// - `standard`: as in [`PermitStandard::as_str()`]
+    /// - `spender`, and `owner` if known
+    /// - `permitted`: a set of records with `token` and `amount`, and, for
+    ///   Permit2 allowances, `expiration` and `nonce`
+    /// - `token` and `amount`, if only one token is permitted
+    /// - `deadline`, a `Long`, and `nonce`, if the permit has one
+    /// - `unlimited`: whether [`Self::is_unlimited()`]
+    /// - `chain` and `verifyingContract`, if the domain has them
+    ///
+    /// Amounts and nonces are `u256` values.
+    pub fn to_json(&self) -> Map<String, Value> {
+        let permitted = self
+            .permitted
+            .iter()
+            .map(|p| {
+                let mut record = Map::new();
+                record.insert("token".to_string(), Value::from(p.token.as_str()));
+                record.insert("amount".to_string(), u256_value(p.amount));
+                if let Some(expiration) = p.expiration {
+                    record.insert("expiration".to_string(), Value::from(expiration));
+                }
+                if let Some(nonce) = p.nonce {
+                    record.insert("nonce".to_string(), u256_value(nonce));
+                }
+                Value::Object(record)
+            })
+            .collect();
+        let mut context = Map::new();
+        context.insert("standard".to_string(), json!(self.standard.as_str()));
+        context.insert("spender".to_string(), Value::from(self.spender.as_str()));
+        if let Some(owner) = &self.owner {
+            context.insert("owner".to_string(), Value::from(owner.as_str()));
+        }
+        context.insert("permitted".to_string(), Value::Array(permitted));
+        if let [only] = self.permitted.as_slice() {
+            context.insert("token".to_string(), Value::from(only.token.as_str()));
+            context.insert("amount".to_string(), u256_value(only.amount));
+        }
+        context.insert("deadline".to_string(), Value::from(self.deadline));
+        if let Some(nonce) = self.nonce {
+            context.insert("nonce".to_string(), u256_value(nonce));
+        }
+        context.insert("unlimited".to_string(), Value::from(self.is_unlimited()));
+        if let Some(chain_id) = self.chain_id {
+            context.insert("chain".to_string(), Value::from(chain_id));
+        }
+        if let Some(contract) = &self.verifying_contract {
+            context.insert(
+                "verifyingContract".to_string(),
+                Value::from(contract.as_str()),
+            );
+        }
+        context
+    }
+
+    /// The context for a request, holding the permit as described in
+    /// [`Self::to_json()`]
+    pub fn to_context(&self) -> Result<Context, ContextJsonError> {
+        Context::from_json_value(Value::Object(self.to_json()), None)
+    }
+}
+
+/// The object at `field`, or, for a batch, the objects in the array there
+fn items<'a>(message: &'a Value, field: &str, batch: bool) -> Result<Vec<&'a Value>, PermitError> {
+    let value = message
+        .get(field)
+        .ok_or_else(|| PermitError::Malformed(format!("message.{field}")))?;
+    if batch {
+        value
+            .as_array()
+            .map(|items| items.iter().collect())
+            .ok_or_else(|| PermitError::Malformed(format!("message.{field}")))
+    } else {
+        Ok(vec![value])
+    }
+}
+
+/// `seconds` as a `Long`, saturating
+fn seconds(seconds: U256) -> i64 {
+    i64::try_from(seconds).unwrap_or(i64::MAX)
+}
+
+/// A JSON number, or a decimal or `0x`-prefixed hex string
+fn number(value: &Value, path: &str) -> Result<U256, PermitError> {
+    match value {
+        Value::Number(n) => n
+            .as_u64()
+            .map(U256::from)
+            .ok_or_else(|| PermitError::InvalidAmount(n.to_string())),
+        Value::String(s) => parse_amount(s).map_err(|_| PermitError::InvalidAmount(s.clone())),
+        _ => Err(PermitError::Malformed(path.to_string())),
+    }
+}
+
+fn number_field(value: &Value, field: &str, path: &str) -> Result<U256, PermitError> {
+    let n = value
+        .get(field)
+        .ok_or_else(|| PermitError::Malformed(format!("{path}{field}")))?;
+    number(n, &format!("{path}{field}"))
+}
+
+fn address_field(value: &Value, field: &str, path: &str) -> Result<String, PermitError> {
+    let address = value
+        .get(field)
+        .and_then(Value::as_str)
+        .ok_or_else(|| PermitError::Malformed(format!("{path}{field}")))?;
+    normalize_address(address).ok_or_else(|| PermitError::InvalidAddress(address.to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Authorizer, Decision, Entities, EntityUid, PolicySet, Request};
+    use std::str::FromStr;
+
+    const USDC: &str = "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48";
+    const WETH: &str = "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2";
+    const PERMIT2: &str = "0x000000000022d473030f116ddee9f6b43ac78ba3";
+    const OWNER: &str = "0x1111111111111111111111111111111111111111";
+    const ROUTER: &str = "0x3fc91a3afd70395cd496c647d5a6cc9d4b2b7fad";
+
+    fn erc2612(value: &str, deadline: Value) -> Value {
+        json!({
+            "types": {},
+            "primaryType": "Permit",
+            "domain": {
+                "name": "USD Coin",
+                "version": "2",
+                "chainId": 1,
+                "verifyingContract": "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48",
+            },
+            "message": {
+                "owner": OWNER,
+                "spender": ROUTER,
+                "value": value,
+                "nonce": 3,
+                "deadline": deadline,
+            },
+        })
+    }
+
+    #[test]
+    fn erc2612_permits() {
+        let permit = Permit::from_typed_data(&erc2612("1000000", json!(1_700_000_000))).unwrap();
+        assert_eq!(permit.standard, PermitStandard::Erc2612);
+        assert_eq!(permit.owner.as_deref(), Some(OWNER));
+        assert_eq!(permit.spender, ROUTER);
+        assert_eq!(
+            permit.permitted,
+            vec![Permitted {
+                token: USDC.to_string(),
+                amount: U256::from(1_000_000),
+                expiration: None,
+                nonce: None,
+            }]
+        );
+        assert_eq!(permit.deadline, 1_700_000_000);
+        assert_eq!(permit.nonce, Some(U256::from(3)));
+        assert_eq!(permit.chain_id, Some(1));
+        assert!(!permit.is_unlimited());
+
+        let unlimited = Permit::from_typed_data(&erc2612(
+            &format!("0x{:x}", U256::MAX),
+            json!(U256::MAX.to_string()),
+        ))
+        .unwrap();
+        assert!(unlimited.is_unlimited());
+        assert_eq!(unlimited.deadline, i64::MAX);
+
+        let dai = json!({
+            "primaryType": "Permit",
+            "domain": { "verifyingContract": "0x6b175474e89094c44da98b954eedeac495271d0f" },
+            "message": {
+                "holder": OWNER,
+                "spender": ROUTER,
+                "nonce": 0,
+                "expiry": 0,
+                "allowed": true,
+            },
+        });
+        assert!(Permit::from_typed_data(&dai).unwrap().is_unlimited());
+
+        assert!(matches!(
+            Permit::from_typed_data(&erc2612("-1", json!(0))),
+            Err(PermitError::InvalidAmount(_))
+        ));
+        let mut missing = erc2612("1", json!(0));
+        missing["message"]
+            .as_object_mut()
+            .unwrap()
+            .remove("spender");
+        assert!(matches!(
+            Permit::from_typed_data(&missing),
+            Err(PermitError::Malformed(field)) if field == "message.spender"
+        ));
+        let mut mail = erc2612("1", json!(0));
+        mail["primaryType"] = json!("Mail");
+        assert!(matches!(
+            Permit::from_typed_data(&mail),
+            Err(PermitError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn permit2_permits() {
+        let single = json!({
+            "primaryType": "PermitSingle",
+            "domain": { "name": "Permit2", "chainId": "0x2105", "verifyingContract": PERMIT2 },
+            "message": {
+                "details": {
+                    "token": USDC,
+                    "amount": "1461501637330902918203684832716283019655932542975",
+                    "expiration": "1700000000",
+                    "nonce": "7",
+                },
+                "spender": ROUTER,
+                "sigDeadline": "1690000000",
+            },
+        });
+        let permit = Permit::from_typed_data(&single).unwrap();
+        assert_eq!(permit.standard, PermitStandard::Permit2Allowance);
+        assert_eq!(permit.owner, None);
+        assert_eq!(permit.deadline, 1_690_000_000);
+        assert_eq!(permit.nonce, Some(U256::from(7)));
+        assert_eq!(permit.chain_id, Some(8453));
+        assert_eq!(permit.permitted[0].expiration, Some(1_700_000_000));
+        assert!(permit.is_unlimited());
+
+        let batch = json!({
+            "primaryType": "PermitBatchWitnessTransferFrom",
+            "domain": { "verifyingContract": PERMIT2 },
+            "message": {
+                "permitted": [
+                    { "token": USDC, "amount": "100" },
+                    { "token": WETH, "amount": "5" },
+                ],
+                "spender": ROUTER,
+                "nonce": "99",
+                "deadline": 1_690_000_000,
+                "witness": {},
+            },
+        });
+        let permit = Permit::from_typed_data(&batch).unwrap();
+        assert_eq!(permit.standard, PermitStandard::Permit2Transfer);
+        assert_eq!(permit.permitted.len(), 2);
+        assert!(!permit.is_unlimited());
+        let context = permit.to_json();
+        assert!(!context.contains_key("token"));
+        assert_eq!(context["nonce"], u256_value(99.into()));
+        assert_eq!(context["verifyingContract"], PERMIT2);
+    }
+
+    #[test]
+    fn permits_as_context() {
+        let policies = PolicySet::from_str(
+            r#"permit(principal, action, resource);
+               forbid(principal, action == Action::"signPermit", resource)
+               when { context.unlimited || context.deadline > context.now + 3600 };"#,
+        )
+        .unwrap();
+        let decide = |typed_data: Value| {
+            let mut context = Permit::from_typed_data(&typed_data).unwrap().to_json();
+            context.insert("now".to_string(), json!(1_700_000_000));
+            let request = Request::new(
+                None,
+                Some(EntityUid::from_strs("Action", "signPermit")),
+                None,
+                Context::from_json_value(Value::Object(context), None).unwrap(),
+            );
+            Authorizer::new()
+                .is_authorized(&request, &policies, &Entities::empty())
+                .decision()
+        };
+        assert_eq!(
+            decide(erc2612("1000000", json!(1_700_000_600))),
+            Decision::Allow
+        );
+        assert_eq!(
+            decide(erc2612("1000000", json!(1_800_000_000))),
+            Decision::Deny
+        );
+        assert_eq!(
+            decide(erc2612(&U256::MAX.to_string(), json!(1_700_000_600))),
+            Decision::Deny
+        );
+
+        let context = Permit::from_typed_data(&erc2612("5", json!(0)))
+            .unwrap()
+            .to_context()
+            .unwrap();
+        let request = Request::new(None, None, None, context);
+        assert_eq!(request.chain(), Some(1));
+    }
+}
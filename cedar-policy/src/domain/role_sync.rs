@@ -0,0 +1,1203 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Mirroring of the roles of on-chain `AccessControl` contracts as entity
+//! parents.
+//!
+//! A [`RoleMirror`] reads the `RoleGranted` and `RoleRevoked` events of the
+//! contracts it is configured with from a [`LogSource`], and keeps the
+//! members of each role. Each member is an `Address` entity whose parents
+//! are its roles, `Role` entities such as
+//! `Role::"MINTER_ROLE@0x5fbdb2315678afecb367f032d93f642f64180aa3"`, so
+//! policies can check on-chain roles:
+//! ```text
+//! permit(
+//!   principal in Role::"MINTER_ROLE@0x5fbdb2315678afecb367f032d93f642f64180aa3",
+//!   action == Action::"mint",
+//!   resource
+//! );
+//! ```
+//! Roles are named by their `bytes32` id, or by their name if it was given
+//! with [`RoleMirror::with_role_name()`]. `DEFAULT_ADMIN_ROLE` is always
+//! named.
+//!
+//! [`RoleMirror::sync()`] backfills each contract from the block it was
+//! added with, then follows new blocks. The mirror's [`RoleMirrorState`]
+//! records the next block to read for each contract, so a mirror can be
+//! persisted and resumed with [`RoleMirror::with_state()`].
+//! [`RoleMirror::sync_to()`] follows blocks up to a [`BlockPin`] instead,
+//! keeping what it read only if the pinned block wasn't reorged.
+//! [`RoleMirror::apply_to()`] adds the roles as parents of the entities of
+//! an entity store.
+//!
+//! [`MemoryLogSource`] holds logs in memory. With the `eth-rpc` feature,
+//! `JsonRpcLogSource` reads them from an Ethereum JSON-RPC node.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt::Debug;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use cedar_policy_core::ast;
+use cedar_policy_core::entities::{self, TCComputation};
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
+use smol_str::SmolStr;
+use thiserror::Error;
+
+use crate::address_book::ADDRESS_TYPE;
+use crate::audit::{normalize_address, to_hex};
+use crate::block_pin::{BlockPin, BlockPinError};
+use crate::{Entities, EntitiesError, Entity, EntityTypeName, EntityUid, RestrictedExpression};
+
+/// The topic of `AccessControl`'s `RoleGranted(bytes32,address,address)` event
+pub const ROLE_GRANTED_TOPIC: &str =
+    "0x2f8788117e7eff1d82e926ec794901d17c78024a50270940304540a733656f0d";
+
+/// The topic of `AccessControl`'s `RoleRevoked(bytes32,address,address)` event
+pub const ROLE_REVOKED_TOPIC: &str =
+    "0xf6391f5c32d9c69d2a47ea670b442974b53935d1edc7fd64eb21e047a839171b";
+
+/// The entity type of roles
+pub const ROLE_TYPE: &str = "Role";
+
+/// The id of `AccessControl`'s `DEFAULT_ADMIN_ROLE`
+const DEFAULT_ADMIN_ROLE: &str =
+    "0x0000000000000000000000000000000000000000000000000000000000000000";
+
+/// How many blocks a [`RoleMirror`] reads logs for at once by default
+pub const DEFAULT_BATCH_SIZE: u64 = 10_000;
+
+/// Errors mirroring roles
+#[derive(Debug, Error)]
+pub enum RoleSyncError {
+    /// A value isn't an address
+    #[error("`{0}` is not an address")]
+    InvalidAddress(String),
+    /// A log isn't a `RoleGranted` or `RoleRevoked` event
+    #[error("invalid log in block {block}: {message}")]
+    InvalidLog {
+        /// The block of the log
+        block: u64,
+        /// What is wrong with it
+        message: String,
+    },
+    /// The source failed
+    #[error("failed to read logs: {0}")]
+    Source(String),
+    /// The pinned block was reorged, or couldn't be read
+    #[error(transparent)]
+    Pin(#[from] BlockPinError),
+    /// The entities couldn't be built
+    #[error(transparent)]
+    Entities(#[from] EntitiesError),
+}
+
+/// A log emitted by a contract
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoleLog {
+    /// The block the log was emitted in
+    pub block_number: u64,
+    /// The index of the log in the block
+    pub log_index: u64,
+    /// The topics of the log, as `0x`-prefixed hex
+    pub topics: Vec<String>,
+}
+
+/// A source of the logs of contracts
+pub trait LogSource: Debug + Send + Sync {
+    /// The number of the latest block
+    fn latest_block(&self) -> Result<u64, RoleSyncError>;
+
+    /// The hash of block `number`, as `0x`-prefixed hex
+    fn block_hash(&self, number: u64) -> Result<String, RoleSyncError>;
+
+    /// The logs emitted by `contract`, a lowercase `0x`-prefixed hex address,
+    /// from `from_block` to `to_block` inclusive, whose first topic is one of
+    /// `topics`
+    fn logs(
+        &self,
+        contract: &str,
+        topics: &[&str],
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<Vec<RoleLog>, RoleSyncError>;
+}
+
+/// Logs held in memory
+#[derive(Debug, Default)]
+pub struct MemoryLogSource {
+    logs: Mutex<(u64, HashMap<String, Vec<RoleLog>>)>,
+    hashes: Mutex<HashMap<u64, String>>,
+}
+
+impl MemoryLogSource {
+    /// A source with no logs
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `log`, emitted by `contract`. The latest block becomes the block
+    /// of `log` if it is later.
+    pub fn push(&self, contract: &str, log: RoleLog) -> Result<(), RoleSyncError> {
+        let contract = normalize_address(contract)
+            .ok_or_else(|| RoleSyncError::InvalidAddress(contract.to_string()))?;
+        let mut logs = self.logs.lock().unwrap_or_else(PoisonError::into_inner);
+        logs.0 = logs.0.max(log.block_number);
+        logs.1.entry(contract).or_default().push(log);
+        drop(logs);
+        Ok(())
+    }
+
+    /// Set the latest block
+    pub fn set_latest_block(&self, block: u64) {
+        self.logs.lock().unwrap_or_else(PoisonError::into_inner).0 = block;
+    }
+
+    /// Set the hash of block `number`
+    pub fn set_block_hash(&self, number: u64, hash: impl Into<String>) {
+        self.hashes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(number, hash.into());
+    }
+}
+
+impl LogSource for MemoryLogSource {
+    fn latest_block(&self) -> Result<u64, RoleSyncError> {
+        Ok(self.logs.lock().unwrap_or_else(PoisonError::into_inner).0)
+    }
+
+    fn block_hash(&self, number: u64) -> Result<String, RoleSyncError> {
+        self.hashes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&number)
+            .cloned()
+            .ok_or(RoleSyncError::Pin(BlockPinError::UnknownBlock(number)))
+    }
+
+    fn logs(
+        &self,
+        contract: &str,
+        topics: &[&str],
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<Vec<RoleLog>, RoleSyncError> {
+        Ok(self
+            .logs
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .1
+            .get(contract)
+            .into_iter()
+            .flatten()
+            .filter(|log| (from_block..=to_block).contains(&log.block_number))
+            .filter(|log| {
+                log.topics
+                    .first()
+                    .is_some_and(|topic| topics.iter().any(|t| topic.eq_ignore_ascii_case(t)))
+            })
+            .cloned()
+            .collect())
+    }
+}
+
+/// The progress of a [`RoleMirror`], which can be persisted to resume it
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoleMirrorState {
+    /// The next block to read for each contract
+    pub next_blocks: BTreeMap<String, u64>,
+    /// The members of each role, by its `bytes32` id, of each contract
+    pub members: BTreeMap<String, BTreeMap<String, BTreeSet<String>>>,
+}
+
+/// What a [`RoleMirror::sync()`] did
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SyncReport {
+    /// The roles granted
+    pub granted: usize,
+    /// The roles revoked
+    pub revoked: usize,
+    /// The last block read, if any blocks were read
+    pub synced_to: Option<u64>,
+}
+
+/// Mirrors the roles of `AccessControl` contracts from their events
+#[derive(Debug)]
+pub struct RoleMirror {
+    source: Arc<dyn LogSource>,
+    contracts: BTreeMap<String, u64>,
+    role_names: HashMap<String, String>,
+    batch_size: u64,
+    confirmations: u64,
+    member_type: EntityTypeName,
+    role_type: EntityTypeName,
+    state: RoleMirrorState,
+}
+
+impl RoleMirror {
+    /// A mirror of no contracts, reading logs from `source`
+    pub fn new(source: Arc<dyn LogSource>) -> Self {
+        Self {
+            source,
+            contracts: BTreeMap::new(),
+            role_names: HashMap::from([(
+                DEFAULT_ADMIN_ROLE.to_string(),
+                "DEFAULT_ADMIN_ROLE".to_string(),
+            )]),
+            batch_size: DEFAULT_BATCH_SIZE,
+            confirmations: 0,
+            member_type: entity_type(ADDRESS_TYPE),
+            role_type: entity_type(ROLE_TYPE),
+            state: RoleMirrorState::default(),
+        }
+    }
+
+    /// Mirror the roles of the contract at `address`, backfilling from
+    /// `from_block`, e.g. the block it was deployed in
+    pub fn with_contract(mut self, address: &str, from_block: u64) -> Result<Self, RoleSyncError> {
+        let address = normalize_address(address)
+            .ok_or_else(|| RoleSyncError::InvalidAddress(address.to_string()))?;
+        self.contracts.insert(address, from_block);
+        Ok(self)
+    }
+
+    /// Name the role whose id is the keccak256 hash of `name`, as with
+    /// `bytes32 public constant MINTER_ROLE = keccak256("MINTER_ROLE")`
+    #[must_use]
+    pub fn with_role_name(mut self, name: &str) -> Self {
+        let id = format!("0x{}", to_hex(&Keccak256::digest(name.as_bytes())));
+        self.role_names.insert(id, name.to_string());
+        self
+    }
+
+    /// Read logs for at most `batch_size` blocks at once
+    #[must_use]
+    pub fn with_batch_size(mut self, batch_size: u64) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Only read blocks with at least `confirmations` blocks after them
+    #[must_use]
+    pub const fn with_confirmations(mut self, confirmations: u64) -> Self {
+        self.confirmations = confirmations;
+        self
+    }
+
+    /// Make members entities of type `member_type`, instead of `Address`,
+    /// and roles entities of type `role_type`, instead of `Role`
+    #[must_use]
+    pub fn with_entity_types(
+        mut self,
+        member_type: EntityTypeName,
+        role_type: EntityTypeName,
+    ) -> Self {
+        self.member_type = member_type;
+        self.role_type = role_type;
+        self
+    }
+
+    /// Resume from `state`, as returned by [`Self::state()`]
+    #[must_use]
+    pub fn with_state(mut self, state: RoleMirrorState) -> Self {
+        self.state = state;
+        self
+    }
+
+    /// The progress of the mirror
+    pub const fn state(&self) -> &RoleMirrorState {
+        &self.state
+    }
+
+    /// Read the events of each contract up to the latest confirmed block.
+    /// Progress is kept for each batch of blocks read, so a failed sync can
+    /// be retried.
+    pub fn sync(&mut self) -> Result<SyncReport, RoleSyncError> {
+        let latest = self.source.latest_block()?;
+        let Some(to_block) = latest.checked_sub(self.confirmations) else {
+            return Ok(SyncReport::default());
+        };
+        let mut state = std::mem::take(&mut self.state);
+        let report = self.read(&mut state, to_block);
+        self.state = state;
+        report
+    }
+
+    /// Read the events of each contract up to the pinned block, whatever
+    /// the confirmations. Nothing read is kept unless the whole sync
+    /// succeeds and the pinned block wasn't reorged.
+    pub fn sync_to(&mut self, pin: &BlockPin) -> Result<SyncReport, RoleSyncError> {
+        let mut state = self.state.clone();
+        let report = self.read(&mut state, pin.number)?;
+        pin.check(&self.source.block_hash(pin.number)?)?;
+        self.state = state;
+        Ok(report)
+    }
+
+    /// Read the events of each contract up to `to_block` into `state`
+    fn read(
+        &self,
+        state: &mut RoleMirrorState,
+        to_block: u64,
+    ) -> Result<SyncReport, RoleSyncError> {
+        let mut report = SyncReport::default();
+        for (contract, from_block) in &self.contracts {
+            let mut next = state
+                .next_blocks
+                .get(contract)
+                .copied()
+                .unwrap_or(*from_block);
+            while next <= to_block {
+                let end = next.saturating_add(self.batch_size - 1).min(to_block);
+                let mut logs = self.source.logs(
+                    contract,
+                    &[ROLE_GRANTED_TOPIC, ROLE_REVOKED_TOPIC],
+                    next,
+                    end,
+                )?;
+                logs.sort_by_key(|log| (log.block_number, log.log_index));
+                let mut members = state.members.get(contract).cloned().unwrap_or_default();
+                for log in &logs {
+                    if apply(&mut members, log)? {
+                        report.granted += 1;
+                    } else {
+                        report.revoked += 1;
+                    }
+                }
+                state.members.insert(contract.clone(), members);
+                next = end + 1;
+                state.next_blocks.insert(contract.clone(), next);
+                report.synced_to = Some(report.synced_to.map_or(end, |synced| synced.max(end)));
+            }
+        }
+        Ok(report)
+    }
+
+    /// The members of the role with `bytes32` id `role` of `contract`
+    pub fn members(&self, contract: &str, role: &str) -> impl Iterator<Item = &str> + '_ {
+        let contract = normalize_address(contract).unwrap_or_default();
+        self.state
+            .members
+            .get(&contract)
+            .and_then(|roles| roles.get(&role.to_ascii_lowercase()))
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+    }
+
+    /// The `Role` entity uid of the role with `bytes32` id `role` of
+    /// `contract`
+    pub fn role_uid(&self, contract: &str, role: &str) -> EntityUid {
+        let role = role.to_ascii_lowercase();
+        let name = self.role_names.get(&role).unwrap_or(&role);
+        let contract = normalize_address(contract).unwrap_or_else(|| contract.to_string());
+        EntityUid(ast::EntityUID::from_components(
+            self.role_type.0.clone(),
+            ast::Eid::new(format!("{name}@{contract}")),
+        ))
+    }
+
+    /// The roles of each member
+    fn parents(&self) -> BTreeMap<&str, BTreeSet<ast::EntityUID>> {
+        let mut parents: BTreeMap<&str, BTreeSet<ast::EntityUID>> = BTreeMap::new();
+        for (contract, roles) in &self.state.members {
+            for (role, members) in roles {
+                let uid = self.role_uid(contract, role).0;
+                for member in members {
+                    parents.entry(member).or_default().insert(uid.clone());
+                }
+            }
+        }
+        parents
+    }
+
+    /// The `Role` entities, with `contract` and `role` attributes, which are
+    /// the address of their contract and their `bytes32` id
+    fn role_entities(&self) -> Vec<ast::Entity> {
+        self.state
+            .members
+            .iter()
+            .flat_map(|(contract, roles)| {
+                roles.keys().map(move |role| {
+                    Entity::new(
+                        self.role_uid(contract, role),
+                        HashMap::from([
+                            (
+                                "contract".to_string(),
+                                RestrictedExpression::new_string(contract.clone()),
+                            ),
+                            (
+                                "role".to_string(),
+                                RestrictedExpression::new_string(role.clone()),
+                            ),
+                        ]),
+                        HashSet::new(),
+                    )
+                    .0
+                })
+            })
+            .collect()
+    }
+
+    /// The members and roles, as entities
+    pub fn entities(&self) -> Result<Entities, RoleSyncError> {
+        let members = self.parents().into_iter().map(|(member, roles)| {
+            ast::Entity::new_with_tags(
+                self.member_uid(member),
+                HashMap::new(),
+                roles.into_iter().collect(),
+                HashMap::new(),
+            )
+        });
+        Ok(Entities(entities::Entities::from_entities(
+            members.chain(self.role_entities()),
+            TCComputation::ComputeNow,
+        )?))
+    }
+
+    /// `entities` with the roles added as parents of their members, and the
+    /// role entities and members which aren't in `entities` added. The
+    /// existing attributes and parents of members are kept, but roles which
+    /// were revoked are only removed from entities built by this mirror.
+    pub fn apply_to(&self, entities: &Entities) -> Result<Entities, RoleSyncError> {
+        let mut parents = self.parents();
+        let role_entities = self.role_entities();
+        let roles: HashSet<_> = role_entities.iter().map(ast::Entity::uid).collect();
+        let mut updated: Vec<ast::Entity> = entities
+            .0
+            .iter()
+            .filter(|entity| !roles.contains(&entity.uid()))
+            .map(|entity| {
+                let uid = entity.uid();
+                let is_member =
+                    uid.entity_type() == &ast::EntityType::Concrete(self.member_type.0.clone());
+                let added = if is_member {
+                    parents
+                        .remove(AsRef::<str>::as_ref(uid.eid()))
+                        .unwrap_or_default()
+                } else {
+                    BTreeSet::new()
+                };
+                if added.is_empty() {
+                    return entity.clone();
+                }
+                ast::Entity::new_with_tags(
+                    uid,
+                    entity
+                        .attrs()
+                        .map(|(key, value)| (SmolStr::from(key), restricted(&value)))
+                        .collect(),
+                    entity.ancestors().cloned().chain(added).collect(),
+                    entity
+                        .tags()
+                        .map(|(key, value)| (SmolStr::from(key), restricted(&value)))
+                        .collect(),
+                )
+            })
+            .collect();
+        updated.extend(parents.into_iter().map(|(member, roles)| {
+            ast::Entity::new_with_tags(
+                self.member_uid(member),
+                HashMap::new(),
+                roles.into_iter().collect(),
+                HashMap::new(),
+            )
+        }));
+        updated.extend(role_entities);
+        Ok(Entities(entities::Entities::from_entities(
+            updated,
+            TCComputation::ComputeNow,
+        )?))
+    }
+
+    fn member_uid(&self, member: &str) -> ast::EntityUID {
+        ast::EntityUID::from_components(self.member_type.0.clone(), ast::Eid::new(member))
+    }
+}
+
+/// Apply a `RoleGranted` or `RoleRevoked` event to `members`, returning
+/// whether it granted the role
+fn apply(
+    members: &mut BTreeMap<String, BTreeSet<String>>,
+    log: &RoleLog,
+) -> Result<bool, RoleSyncError> {
+    let invalid = |message: &str| RoleSyncError::InvalidLog {
+        block: log.block_number,
+        message: message.to_string(),
+    };
+    let [topic, role, account, ..] = log.topics.as_slice() else {
+        return Err(invalid("missing topics"));
+    };
+    let role = role.to_ascii_lowercase();
+    if role.len() != 66 || !role.starts_with("0x") {
+        return Err(invalid("the role isn't a `bytes32`"));
+    }
+    // the account is the last 20 bytes of its topic
+    let account = account
+        .get(26..)
+        .and_then(|hex| normalize_address(&format!("0x{hex}")))
+        .ok_or_else(|| invalid("the account isn't an address"))?;
+    if topic.eq_ignore_ascii_case(ROLE_GRANTED_TOPIC) {
+        members.entry(role).or_default().insert(account);
+        Ok(true)
+    } else if topic.eq_ignore_ascii_case(ROLE_REVOKED_TOPIC) {
+        if let Some(accounts) = members.get_mut(&role) {
+            accounts.remove(&account);
+            if accounts.is_empty() {
+                members.remove(&role);
+            }
+        }
+        Ok(false)
+    } else {
+        Err(invalid("not a `RoleGranted` or `RoleRevoked` event"))
+    }
+}
+
+/// The entity type named `name`
+fn entity_type(name: &str) -> EntityTypeName {
+    // PANIC SAFETY: `Address` and `Role` are valid entity type names
+    #[allow(clippy::expect_used)]
+    EntityTypeName::from_str(name).expect("`Address` and `Role` are valid entity type names")
+}
+
+/// An owned copy of `expr`
+fn restricted(expr: &ast::Expr) -> ast::RestrictedExpr {
+    ast::RestrictedExpr::new_unchecked(expr.clone())
+}
+
+#[cfg(feature = "eth-rpc")]
+pub use rpc::JsonRpcLogSource;
+
+#[cfg(feature = "eth-rpc")]
+mod rpc {
+    use super::{LogSource, RoleLog, RoleSyncError};
+    use crate::block_pin;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    /// A JSON-RPC response
+    #[derive(Debug, Deserialize)]
+    struct RpcResponse<T> {
+        result: Option<T>,
+        error: Option<RpcError>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct RpcError {
+        message: String,
+    }
+
+    /// A log as the node returns it
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct RpcLog {
+        block_number: String,
+        log_index: String,
+        topics: Vec<String>,
+        #[serde(default)]
+        removed: bool,
+    }
+
+    /// Reads logs from an Ethereum JSON-RPC node with `eth_getLogs`
+    ///
+    /// Its requests are [blocking](crate::domain#network-requests).
+    #[derive(Debug)]
+    pub struct JsonRpcLogSource {
+        client: reqwest::blocking::Client,
+        rpc_url: String,
+    }
+
+    impl JsonRpcLogSource {
+        /// A source reading from the node at `rpc_url`
+        pub fn new(rpc_url: impl Into<String>) -> Self {
+            Self {
+                client: reqwest::blocking::Client::new(),
+                rpc_url: rpc_url.into(),
+            }
+        }
+
+        fn request(
+            &self,
+            method: &str,
+            params: &serde_json::Value,
+        ) -> Result<String, RoleSyncError> {
+            self.client
+                .post(&self.rpc_url)
+                .json(&json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params }))
+                .send()
+                .and_then(reqwest::blocking::Response::error_for_status)
+                .and_then(reqwest::blocking::Response::text)
+                .map_err(|err| RoleSyncError::Source(err.to_string()))
+        }
+    }
+
+    impl LogSource for JsonRpcLogSource {
+        fn latest_block(&self) -> Result<u64, RoleSyncError> {
+            parse_block_number(&self.request("eth_blockNumber", &json!([]))?)
+        }
+
+        fn block_hash(&self, number: u64) -> Result<String, RoleSyncError> {
+            Ok(block_pin::rpc::block_hash(
+                &self.client,
+                &self.rpc_url,
+                number,
+            )?)
+        }
+
+        fn logs(
+            &self,
+            contract: &str,
+            topics: &[&str],
+            from_block: u64,
+            to_block: u64,
+        ) -> Result<Vec<RoleLog>, RoleSyncError> {
+            let params = json!([{
+                "address": contract,
+                "topics": [topics],
+                "fromBlock": format!("{from_block:#x}"),
+                "toBlock": format!("{to_block:#x}"),
+            }]);
+            parse_logs(&self.request("eth_getLogs", &params)?)
+        }
+    }
+
+    /// The result of a JSON-RPC response `body`
+    fn result<T: for<'de> Deserialize<'de>>(body: &str) -> Result<T, RoleSyncError> {
+        let response: RpcResponse<T> =
+            serde_json::from_str(body).map_err(|err| RoleSyncError::Source(err.to_string()))?;
+        if let Some(error) = response.error {
+            return Err(RoleSyncError::Source(error.message));
+        }
+        response
+            .result
+            .ok_or_else(|| RoleSyncError::Source("no result".to_string()))
+    }
+
+    /// A `0x`-prefixed hex quantity
+    fn quantity(hex: &str) -> Result<u64, RoleSyncError> {
+        u64::from_str_radix(hex.trim_start_matches("0x"), 16)
+            .map_err(|_| RoleSyncError::Source(format!("invalid quantity `{hex}`")))
+    }
+
+    /// The block number in an `eth_blockNumber` response `body`
+    pub(super) fn parse_block_number(body: &str) -> Result<u64, RoleSyncError> {
+        quantity(&result::<String>(body)?)
+    }
+
+    /// The logs in an `eth_getLogs` response `body`, without those removed by
+    /// reorgs
+    pub(super) fn parse_logs(body: &str) -> Result<Vec<RoleLog>, RoleSyncError> {
+        result::<Vec<RpcLog>>(body)?
+            .into_iter()
+            .filter(|log| !log.removed)
+            .map(|log| {
+                Ok(RoleLog {
+                    block_number: quantity(&log.block_number)?,
+                    log_index: quantity(&log.log_index)?,
+                    topics: log.topics,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Authorizer, Context, Decision, PolicySet, Request};
+
+    const TOKEN: &str = "0x5FbDB2315678afecb367f032d93F642f64180aa3";
+    const ALICE: &str = "0xf39fd6e51aad88f6f4ce6ab8827279cfffb92266";
+    const BOB: &str = "0x70997970c51812dc3a010c7d01b50e0d17dc79c8";
+
+    fn minter_role() -> String {
+        format!("0x{}", to_hex(&Keccak256::digest(b"MINTER_ROLE")))
+    }
+
+    fn log(block: u64, topic: &str, role: &str, account: &str) -> RoleLog {
+        RoleLog {
+            block_number: block,
+            log_index: 0,
+            topics: vec![
+                topic.to_string(),
+                role.to_string(),
+                format!("0x000000000000000000000000{}", &account[2..]),
+                format!("0x000000000000000000000000{}", &ALICE[2..]),
+            ],
+        }
+    }
+
+    fn mirror(source: &Arc<MemoryLogSource>) -> RoleMirror {
+        RoleMirror::new(Arc::clone(source) as Arc<dyn LogSource>)
+            .with_contract(TOKEN, 10)
+            .unwrap()
+            .with_role_name("MINTER_ROLE")
+            .with_batch_size(100)
+    }
+
+    #[test]
+    fn topics() {
+        for (topic, signature) in [
+            (ROLE_GRANTED_TOPIC, "RoleGranted(bytes32,address,address)"),
+            (ROLE_REVOKED_TOPIC, "RoleRevoked(bytes32,address,address)"),
+        ] {
+            assert_eq!(
+                topic,
+                format!("0x{}", to_hex(&Keccak256::digest(signature.as_bytes())))
+            );
+        }
+    }
+
+    #[test]
+    fn backfills_and_resumes() {
+        let source = Arc::new(MemoryLogSource::new());
+        let minter = minter_role();
+        // before the contract's start block
+        source
+            .push(TOKEN, log(5, ROLE_GRANTED_TOPIC, &minter, BOB))
+            .unwrap();
+        source
+            .push(
+                TOKEN,
+                log(10, ROLE_GRANTED_TOPIC, DEFAULT_ADMIN_ROLE, ALICE),
+            )
+            .unwrap();
+        source
+            .push(TOKEN, log(150, ROLE_GRANTED_TOPIC, &minter, ALICE))
+            .unwrap();
+        source
+            .push(TOKEN, log(250, ROLE_GRANTED_TOPIC, &minter, BOB))
+            .unwrap();
+        source.set_latest_block(300);
+
+        let mut synced = mirror(&source).with_confirmations(10);
+        assert_eq!(
+            synced.sync().unwrap(),
+            SyncReport {
+                granted: 3,
+                revoked: 0,
+                synced_to: Some(290),
+            }
+        );
+        assert_eq!(
+            synced.members(TOKEN, &minter).collect::<Vec<_>>(),
+            [BOB, ALICE]
+        );
+        assert_eq!(
+            synced.state().next_blocks.get(&TOKEN.to_ascii_lowercase()),
+            Some(&291)
+        );
+
+        // resume from the persisted state
+        source
+            .push(TOKEN, log(320, ROLE_REVOKED_TOPIC, &minter, BOB))
+            .unwrap();
+        let state: RoleMirrorState =
+            serde_json::from_str(&serde_json::to_string(synced.state()).unwrap()).unwrap();
+        let mut resumed = mirror(&source).with_state(state);
+        assert_eq!(
+            resumed.sync().unwrap(),
+            SyncReport {
+                granted: 0,
+                revoked: 1,
+                synced_to: Some(320),
+            }
+        );
+        assert_eq!(resumed.members(TOKEN, &minter).collect::<Vec<_>>(), [ALICE]);
+        assert_eq!(resumed.sync().unwrap(), SyncReport::default());
+
+        source
+            .push(TOKEN, log(400, ROLE_GRANTED_TOPIC, "0x01", BOB))
+            .unwrap();
+        assert!(matches!(
+            resumed.sync(),
+            Err(RoleSyncError::InvalidLog { block: 400, .. })
+        ));
+    }
+
+    #[test]
+    fn decodes_events() {
+        let minter = minter_role();
+        let mut members = BTreeMap::new();
+        let with_index = |index: u64, log: RoleLog| RoleLog {
+            log_index: index,
+            ..log
+        };
+        // topics are matched in any case, and addresses normalized
+        assert!(apply(
+            &mut members,
+            &log(
+                1,
+                &ROLE_GRANTED_TOPIC.to_ascii_uppercase().replace("0X", "0x"),
+                &minter.to_ascii_uppercase().replace("0X", "0x"),
+                &ALICE.to_ascii_uppercase().replace("0X", "0x"),
+            )
+        )
+        .unwrap());
+        assert_eq!(
+            members.get(&minter),
+            Some(&BTreeSet::from([ALICE.to_string()]))
+        );
+        // granting twice and revoking from non-members change nothing
+        assert!(apply(&mut members, &log(2, ROLE_GRANTED_TOPIC, &minter, ALICE)).unwrap());
+        assert!(!apply(&mut members, &log(2, ROLE_REVOKED_TOPIC, &minter, BOB)).unwrap());
+        assert!(!apply(
+            &mut members,
+            &log(2, ROLE_REVOKED_TOPIC, DEFAULT_ADMIN_ROLE, BOB)
+        )
+        .unwrap());
+        assert_eq!(members.len(), 1);
+        // revoking the last member forgets the role
+        assert!(!apply(&mut members, &log(3, ROLE_REVOKED_TOPIC, &minter, ALICE)).unwrap());
+        assert!(members.is_empty());
+
+        let invalid = |log: RoleLog| {
+            let Err(RoleSyncError::InvalidLog { block, message }) =
+                apply(&mut BTreeMap::new(), &log)
+            else {
+                panic!("{log:?} is valid");
+            };
+            assert_eq!(block, log.block_number);
+            message
+        };
+        let mut short = log(4, ROLE_GRANTED_TOPIC, &minter, ALICE);
+        short.topics.truncate(2);
+        assert_eq!(invalid(short), "missing topics");
+        assert_eq!(
+            invalid(log(5, ROLE_GRANTED_TOPIC, &minter[..64], ALICE)),
+            "the role isn't a `bytes32`"
+        );
+        let mut account = log(6, ROLE_GRANTED_TOPIC, &minter, ALICE);
+        account.topics[2] = "0x1234".to_string();
+        assert_eq!(invalid(account), "the account isn't an address");
+        assert_eq!(
+            invalid(log(7, &minter, &minter, ALICE)),
+            "not a `RoleGranted` or `RoleRevoked` event"
+        );
+
+        // events of one block apply in the order of their log indices
+        let source = Arc::new(MemoryLogSource::new());
+        source
+            .push(
+                TOKEN,
+                with_index(1, log(20, ROLE_GRANTED_TOPIC, &minter, BOB)),
+            )
+            .unwrap();
+        source
+            .push(
+                TOKEN,
+                with_index(0, log(20, ROLE_REVOKED_TOPIC, &minter, BOB)),
+            )
+            .unwrap();
+        let mut mirror = mirror(&source);
+        mirror.sync().unwrap();
+        assert_eq!(mirror.members(TOKEN, &minter).collect::<Vec<_>>(), [BOB]);
+    }
+
+    /// A source whose logs can't be read from a block on
+    #[derive(Debug)]
+    struct FailingSource {
+        logs: Arc<MemoryLogSource>,
+        fail_from: Mutex<Option<u64>>,
+    }
+
+    impl LogSource for FailingSource {
+        fn latest_block(&self) -> Result<u64, RoleSyncError> {
+            self.logs.latest_block()
+        }
+
+        fn block_hash(&self, number: u64) -> Result<String, RoleSyncError> {
+            self.logs.block_hash(number)
+        }
+
+        fn logs(
+            &self,
+            contract: &str,
+            topics: &[&str],
+            from_block: u64,
+            to_block: u64,
+        ) -> Result<Vec<RoleLog>, RoleSyncError> {
+            if self
+                .fail_from
+                .lock()
+                .unwrap()
+                .is_some_and(|fail_from| to_block >= fail_from)
+            {
+                return Err(RoleSyncError::Source("rate limited".to_string()));
+            }
+            self.logs.logs(contract, topics, from_block, to_block)
+        }
+    }
+
+    #[test]
+    fn grants_and_revocations_over_syncs() {
+        let logs = Arc::new(MemoryLogSource::new());
+        let source = Arc::new(FailingSource {
+            logs: Arc::clone(&logs),
+            fail_from: Mutex::new(Some(150)),
+        });
+        let minter = minter_role();
+        logs.push(TOKEN, log(20, ROLE_GRANTED_TOPIC, &minter, ALICE))
+            .unwrap();
+        logs.push(TOKEN, log(120, ROLE_GRANTED_TOPIC, &minter, BOB))
+            .unwrap();
+        logs.push(TOKEN, log(180, ROLE_REVOKED_TOPIC, &minter, ALICE))
+            .unwrap();
+        let mut mirror = RoleMirror::new(Arc::clone(&source) as Arc<dyn LogSource>)
+            .with_contract(TOKEN, 10)
+            .unwrap()
+            .with_role_name("MINTER_ROLE")
+            .with_batch_size(100);
+        let is_minter = |mirror: &RoleMirror, account: &str| {
+            mirror.entities().unwrap().is_ancestor_of(
+                &EntityUid::from_strs(
+                    "Role",
+                    &format!("MINTER_ROLE@{}", TOKEN.to_ascii_lowercase()),
+                ),
+                &EntityUid::from_strs("Address", account),
+            )
+        };
+
+        // the first batch is kept when the second fails
+        assert!(matches!(mirror.sync(), Err(RoleSyncError::Source(_))));
+        assert_eq!(
+            mirror.state().next_blocks.get(&TOKEN.to_ascii_lowercase()),
+            Some(&110)
+        );
+        assert!(is_minter(&mirror, ALICE));
+        assert!(!is_minter(&mirror, BOB));
+
+        // the retry resumes after it
+        *source.fail_from.lock().unwrap() = None;
+        assert_eq!(
+            mirror.sync().unwrap(),
+            SyncReport {
+                granted: 1,
+                revoked: 1,
+                synced_to: Some(180),
+            }
+        );
+        assert!(!is_minter(&mirror, ALICE));
+        assert!(is_minter(&mirror, BOB));
+
+        // regranted after the revocation
+        logs.push(TOKEN, log(200, ROLE_GRANTED_TOPIC, &minter, ALICE))
+            .unwrap();
+        logs.push(TOKEN, log(200, ROLE_REVOKED_TOPIC, &minter, BOB))
+            .unwrap();
+        mirror.sync().unwrap();
+        assert!(is_minter(&mirror, ALICE));
+        assert!(!is_minter(&mirror, BOB));
+
+        // nothing is read until blocks are confirmed
+        logs.push(TOKEN, log(205, ROLE_REVOKED_TOPIC, &minter, ALICE))
+            .unwrap();
+        let mut mirror = mirror.with_confirmations(10);
+        assert_eq!(mirror.sync().unwrap(), SyncReport::default());
+        assert!(is_minter(&mirror, ALICE));
+        logs.set_latest_block(215);
+        assert_eq!(mirror.sync().unwrap().revoked, 1);
+        assert!(!is_minter(&mirror, ALICE));
+    }
+
+    #[test]
+    fn syncs_to_pinned_blocks() {
+        let source = Arc::new(MemoryLogSource::new());
+        let minter = minter_role();
+        source
+            .push(TOKEN, log(50, ROLE_GRANTED_TOPIC, &minter, ALICE))
+            .unwrap();
+        source
+            .push(TOKEN, log(150, ROLE_GRANTED_TOPIC, &minter, BOB))
+            .unwrap();
+        source.set_block_hash(100, "0xa");
+        source.set_block_hash(200, "0xb");
+        let mut mirror = mirror(&source);
+        assert_eq!(
+            mirror.sync_to(&BlockPin::new(100, "0xa")).unwrap(),
+            SyncReport {
+                granted: 1,
+                revoked: 0,
+                synced_to: Some(100),
+            }
+        );
+        assert_eq!(mirror.members(TOKEN, &minter).collect::<Vec<_>>(), [ALICE]);
+
+        // block 200 was replaced by a reorg, so nothing read is kept
+        let state = mirror.state().clone();
+        assert!(matches!(
+            mirror.sync_to(&BlockPin::new(200, "0xc")),
+            Err(RoleSyncError::Pin(BlockPinError::Reorged {
+                number: 200,
+                ..
+            }))
+        ));
+        assert_eq!(mirror.state(), &state);
+        assert!(matches!(
+            mirror.sync_to(&BlockPin::new(300, "0xd")),
+            Err(RoleSyncError::Pin(BlockPinError::UnknownBlock(300)))
+        ));
+        assert_eq!(mirror.state(), &state);
+        mirror.sync_to(&BlockPin::new(200, "0xb")).unwrap();
+        assert_eq!(
+            mirror.members(TOKEN, &minter).collect::<Vec<_>>(),
+            [BOB, ALICE]
+        );
+    }
+
+    #[test]
+    fn roles_as_parents() {
+        let source = Arc::new(MemoryLogSource::new());
+        let minter = minter_role();
+        source
+            .push(TOKEN, log(20, ROLE_GRANTED_TOPIC, &minter, ALICE))
+            .unwrap();
+        source
+            .push(TOKEN, log(20, ROLE_GRANTED_TOPIC, DEFAULT_ADMIN_ROLE, BOB))
+            .unwrap();
+        let mut mirror = mirror(&source);
+        mirror.sync().unwrap();
+
+        let token = TOKEN.to_ascii_lowercase();
+        let policies = PolicySet::from_str(&format!(
+            r#"permit(
+                 principal in Role::"MINTER_ROLE@{token}",
+                 action == Action::"mint",
+                 resource
+               ) when {{ principal.kyc }};"#
+        ))
+        .unwrap();
+        let existing = Entities::from_json_value(
+            serde_json::json!([
+                { "uid": { "type": "Address", "id": ALICE }, "attrs": { "kyc": true }, "parents": [] }
+            ]),
+            None,
+        )
+        .unwrap();
+        let entities = mirror.apply_to(&existing).unwrap();
+        let decide = |account: &str| {
+            let request = Request::new(
+                Some(EntityUid::from_strs("Address", account)),
+                Some(EntityUid::from_strs("Action", "mint")),
+                Some(EntityUid::from_strs("Token", "t")),
+                Context::empty(),
+            );
+            Authorizer::new()
+                .is_authorized(&request, &policies, &entities)
+                .decision()
+        };
+        assert_eq!(decide(ALICE), Decision::Allow);
+        assert_eq!(decide(BOB), Decision::Deny);
+        assert!(entities.is_ancestor_of(
+            &EntityUid::from_strs("Role", &format!("DEFAULT_ADMIN_ROLE@{token}")),
+            &EntityUid::from_strs("Address", BOB)
+        ));
+
+        let mirrored = mirror.entities().unwrap();
+        assert_eq!(mirrored.iter().count(), 4);
+        let role = mirrored.get(&mirror.role_uid(TOKEN, &minter)).unwrap();
+        assert_eq!(
+            role.attr("role").unwrap().unwrap(),
+            crate::EvalResult::String(minter)
+        );
+    }
+
+    #[cfg(feature = "eth-rpc")]
+    #[test]
+    fn rpc_responses() {
+        assert_eq!(
+            rpc::parse_block_number(r#"{"jsonrpc": "2.0", "id": 1, "result": "0x12c"}"#).unwrap(),
+            300
+        );
+        let logs = rpc::parse_logs(&format!(
+            r#"{{"jsonrpc": "2.0", "id": 1, "result": [
+                {{"blockNumber": "0x14", "logIndex": "0x2", "topics": ["{ROLE_GRANTED_TOPIC}"], "removed": false}},
+                {{"blockNumber": "0x15", "logIndex": "0x0", "topics": ["{ROLE_GRANTED_TOPIC}"], "removed": true}}
+            ]}}"#
+        ))
+        .unwrap();
+        assert_eq!(
+            logs,
+            [RoleLog {
+                block_number: 20,
+                log_index: 2,
+                topics: vec![ROLE_GRANTED_TOPIC.to_string()],
+            }]
+        );
+        assert!(rpc::parse_logs(
+            r#"{"jsonrpc": "2.0", "id": 1, "error": {"code": -32005, "message": "query returned more than 10000 results"}}"#
+        )
+        .is_err());
+    }
+
+    #[cfg(feature = "eth-rpc")]
+    #[test]
+    fn rpc_errors() {
+        fn message<T: Debug>(result: Result<T, RoleSyncError>) -> String {
+            match result {
+                Err(RoleSyncError::Source(message)) => message,
+                result => panic!("{result:?} isn't a source error"),
+            }
+        }
+        assert_eq!(
+            message(rpc::parse_block_number(
+                r#"{"jsonrpc": "2.0", "id": 1, "error": {"code": -32603, "message": "internal error"}}"#
+            )),
+            "internal error"
+        );
+        assert_eq!(
+            message(rpc::parse_block_number(
+                r#"{"jsonrpc": "2.0", "id": 1, "result": null}"#
+            )),
+            "no result"
+        );
+        assert_eq!(
+            message(rpc::parse_block_number(
+                r#"{"jsonrpc": "2.0", "id": 1, "result": "0xpending"}"#
+            )),
+            "invalid quantity `0xpending`"
+        );
+        assert!(matches!(
+            rpc::parse_block_number("502 Bad Gateway"),
+            Err(RoleSyncError::Source(_))
+        ));
+        assert_eq!(
+            message(rpc::parse_logs(
+                r#"{"jsonrpc": "2.0", "id": 1, "result": [
+                    {"blockNumber": "0x14", "logIndex": "", "topics": []}
+                ]}"#
+            )),
+            "invalid quantity ``"
+        );
+        // pending logs have no block number
+        assert!(matches!(
+            rpc::parse_logs(
+                r#"{"jsonrpc": "2.0", "id": 1, "result": [
+                    {"blockNumber": null, "logIndex": null, "topics": []}
+                ]}"#
+            ),
+            Err(RoleSyncError::Source(_))
+        ));
+        assert_eq!(
+            rpc::parse_logs(r#"{"jsonrpc": "2.0", "id": 1, "result": []}"#).unwrap(),
+            []
+        );
+    }
+}
@@ -0,0 +1,498 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Seaport orders as request context.
+//!
+//! A Seaport order trades its `offer` items, given by the offerer, for its
+//! `consideration` items, each paid to a recipient. Listing an NFT, or
+//! bidding on one, means signing an order, so an [`Order`] read from the
+//! signed typed data ([`Order::from_typed_data()`]) or from the order
+//! parameters returned by marketplace APIs ([`Order::from_parameters()`]) can
+//! be authorized like a transaction. [`Order::to_intent()`] maps it to an
+//! [`Intent`] whose context holds the items as sets of records, with tokens
+//! as addresses and amounts as `u256` values, e.g.
+//! ```text
+//! forbid(principal, action == Action::"listNft", resource)
+//! when { context.proceeds.u256LessThan(context.floorPrice) };
+//! ```
+//! where the caller adds `floorPrice` to the intent's context.
+
+use ethers::types::U256;
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+use crate::audit::normalize_address;
+use crate::domain::intent::{u256_value, Intent};
+use crate::domain::simulation::parse_amount;
+
+/// Errors reading a Seaport order
+#[derive(Debug, Error)]
+pub enum SeaportError {
+    /// A field is missing or has the wrong type
+    #[error("malformed Seaport order: `{0}` is missing or invalid")]
+    Malformed(String),
+    /// The typed data isn't an `OrderComponents`
+    #[error("unsupported typed data `{0}`, expected `OrderComponents`")]
+    Unsupported(String),
+    /// An address isn't a `0x`-prefixed, 20 byte hex string
+    #[error("invalid address: {0}")]
+    InvalidAddress(String),
+    /// An amount isn't a decimal or `0x`-prefixed hex integer below 2^256
+    #[error("invalid amount: {0}")]
+    InvalidAmount(String),
+}
+
+/// What an item is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ItemType {
+    /// Ether, or the native currency of the chain
+    Native,
+    /// An ERC-20 token
+    Erc20,
+    /// An ERC-721 token
+    Erc721,
+    /// An ERC-1155 token
+    Erc1155,
+    /// Any ERC-721 token matching a criteria
+    Erc721WithCriteria,
+    /// Any ERC-1155 token matching a criteria
+    Erc1155WithCriteria,
+}
+
+impl ItemType {
+    /// The item type with Seaport's numbering
+    pub fn from_u8(n: u8) -> Option<Self> {
+        match n {
+            0 => Some(Self::Native),
+            1 => Some(Self::Erc20),
+            2 => Some(Self::Erc721),
+            3 => Some(Self::Erc1155),
+            4 => Some(Self::Erc721WithCriteria),
+            5 => Some(Self::Erc1155WithCriteria),
+            _ => None,
+        }
+    }
+
+    /// The name of the item type in a context, e.g. `erc721`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Native => "native",
+            Self::Erc20 => "erc20",
+            Self::Erc721 => "erc721",
+            Self::Erc1155 => "erc1155",
+            Self::Erc721WithCriteria => "erc721WithCriteria",
+            Self::Erc1155WithCriteria => "erc1155WithCriteria",
+        }
+    }
+
+    /// Whether items of this type are fungible: Ether or an ERC-20 token
+    pub fn is_fungible(self) -> bool {
+        matches!(self, Self::Native | Self::Erc20)
+    }
+}
+
+/// An offer or consideration item
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Item {
+    /// What the item is
+    pub item_type: ItemType,
+    /// The token contract, or the zero address for Ether
+    pub token: String,
+    /// The token id, or the criteria root for items with criteria
+    pub identifier: U256,
+    /// The amount when the order starts
+    pub start_amount: U256,
+    /// The amount when the order ends. Amounts change linearly in between,
+    /// e.g. in a Dutch auction.
+    pub end_amount: U256,
+    /// Who receives a consideration item. Offer items have none.
+    pub recipient: Option<String>,
+}
+
+impl Item {
+    /// The smaller of the start and end amounts
+    pub fn min_amount(&self) -> U256 {
+        self.start_amount.min(self.end_amount)
+    }
+
+    fn to_json(&self) -> Value {
+        let mut record = Map::new();
+        record.insert("itemType".to_string(), self.item_type.as_str().into());
+        record.insert("token".to_string(), self.token.as_str().into());
+        record.insert("identifier".to_string(), u256_value(self.identifier));
+        record.insert("startAmount".to_string(), u256_value(self.start_amount));
+        record.insert("endAmount".to_string(), u256_value(self.end_amount));
+        if let Some(recipient) = &self.recipient {
+            record.insert("recipient".to_string(), recipient.as_str().into());
+        }
+        Value::Object(record)
+    }
+}
+
+/// A Seaport order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Order {
+    /// Who gives the offer items
+    pub offerer: String,
+    /// The zone which may restrict the order
+    pub zone: String,
+    /// What the offerer gives
+    pub offer: Vec<Item>,
+    /// What the offerer and others, e.g. royalty recipients, receive
+    pub consideration: Vec<Item>,
+    /// Seaport's order type, e.g. 0 for a full open order
+    pub order_type: u8,
+    /// When the order becomes valid, in seconds since the Unix epoch
+    pub start_time: i64,
+    /// When the order expires, in seconds since the Unix epoch. End times
+    /// too late to be a `Long` are `i64::MAX`.
+    pub end_time: i64,
+}
+
+impl Order {
+    /// Read EIP-712 typed data whose `primaryType` is `OrderComponents`, as
+    /// presented for signing
+    pub fn from_typed_data(typed_data: &Value) -> Result<Self, SeaportError> {
+        match typed_data.get("primaryType").and_then(Value::as_str) {
+            Some("OrderComponents") => {}
+            Some(other) => return Err(SeaportError::Unsupported(other.to_string())),
+            None => return Err(SeaportError::Malformed("primaryType".to_string())),
+        }
+        let message = typed_data
+            .get("message")
+            .ok_or_else(|| SeaportError::Malformed("message".to_string()))?;
+        Self::from_parameters(message)
+    }
+
+    /// Read order parameters or components: an object with `offerer`,
+    /// `zone`, `offer`, `consideration`, `orderType`, `startTime`, and
+    /// `endTime` fields. Numbers may be JSON numbers, or decimal or
+    /// `0x`-prefixed hex strings.
+    pub fn from_parameters(parameters: &Value) -> Result<Self, SeaportError> {
+        let order_type = u8::try_from(number_field(parameters, "orderType", "")?)
+            .map_err(|_| SeaportError::Malformed("orderType".to_string()))?;
+        Ok(Self {
+            offerer: address_field(parameters, "offerer", "")?,
+            zone: address_field(parameters, "zone", "")?,
+            offer: items(parameters, "offer", false)?,
+            consideration: items(parameters, "consideration", true)?,
+            order_type,
+            start_time: seconds(number_field(parameters, "startTime", "")?),
+            end_time: seconds(number_field(parameters, "endTime", "")?),
+        })
+    }
+
+    /// The fungible token the offerer is paid in, and the least they
+    /// receive, if every consideration item paid to the offerer is of the
+    /// same fungible token
+    pub fn proceeds(&self) -> Option<(&str, U256)> {
+        single_token(
+            self.consideration
+                .iter()
+                .filter(|item| item.recipient.as_deref() == Some(self.offerer.as_str())),
+        )
+    }
+
+    /// The fungible token the offerer pays in, and the most they pay, if
+    /// every offer item is of the same fungible token
+    pub fn payment(&self) -> Option<(&str, U256)> {
+        let (token, _) = single_token(self.offer.iter())?;
+        let total = self.offer.iter().fold(U256::zero(), |total, item| {
+            total.saturating_add(item.start_amount.max(item.end_amount))
+        });
+        Some((token, total))
+    }
+
+    /// The order as an intent of the `seaport` protocol. The action is
+    /// - `listNft` if the offer is only of NFTs, and the offerer is paid in a
+    ///   single fungible token
+    /// - `bidNft` if the offer is of a single fungible token, and the
+    ///   consideration includes NFTs
+    /// - `order` otherwise
+    ///
+    /// The context holds `offerer`, `zone`, `orderType`, `startTime`,
+    /// `endTime`, and the `offer` and `consideration` items as sets of
+    /// records with `itemType`, `token`, `identifier`, `startAmount`,
+    /// `endAmount`, and, for consideration items, `recipient`. Listings also
+    /// have `paymentToken` and `proceeds`, the least the offerer receives;
+    /// bids have `paymentToken` and `payment`, the most the offerer pays.
+    pub fn to_intent(&self) -> Intent {
+        let nfts_offered =
+            !self.offer.is_empty() && self.offer.iter().all(|item| !item.item_type.is_fungible());
+        let nfts_considered = self
+            .consideration
+            .iter()
+            .any(|item| !item.item_type.is_fungible());
+        let intent = match (self.proceeds(), self.payment()) {
+            (Some((token, proceeds)), _) if nfts_offered => Intent::new("seaport", "listNft")
+                .with("paymentToken", token.into())
+                .with("proceeds", u256_value(proceeds)),
+            (_, Some((token, payment))) if nfts_considered => Intent::new("seaport", "bidNft")
+                .with("paymentToken", token.into())
+                .with("payment", u256_value(payment)),
+            _ => Intent::new("seaport", "order"),
+        };
+        intent
+            .with("offerer", self.offerer.as_str().into())
+            .with("zone", self.zone.as_str().into())
+            .with("orderType", self.order_type.into())
+            .with("startTime", self.start_time.into())
+            .with("endTime", self.end_time.into())
+            .with(
+                "offer",
+                Value::Array(self.offer.iter().map(Item::to_json).collect()),
+            )
+            .with(
+                "consideration",
+                Value::Array(self.consideration.iter().map(Item::to_json).collect()),
+            )
+    }
+}
+
+/// The token of `items` and the sum of their smaller amounts, if they're
+/// all of the same fungible token
+fn single_token<'a>(mut items: impl Iterator<Item = &'a Item>) -> Option<(&'a str, U256)> {
+    let first = items.next()?;
+    if !first.item_type.is_fungible() {
+        return None;
+    }
+    let mut total = first.min_amount();
+    for item in items {
+        if item.item_type != first.item_type || item.token != first.token {
+            return None;
+        }
+        total = total.saturating_add(item.min_amount());
+    }
+    Some((&first.token, total))
+}
+
+fn items(parameters: &Value, field: &str, consideration: bool) -> Result<Vec<Item>, SeaportError> {
+    let items = parameters
+        .get(field)
+        .and_then(Value::as_array)
+        .ok_or_else(|| SeaportError::Malformed(field.to_string()))?;
+    items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| -> Result<Item, SeaportError> {
+            let path = format!("{field}[{i}].");
+            let item_type = u8::try_from(number_field(item, "itemType", &path)?)
+                .ok()
+                .and_then(ItemType::from_u8)
+                .ok_or_else(|| SeaportError::Malformed(format!("{path}itemType")))?;
+            let recipient = if consideration {
+                Some(address_field(item, "recipient", &path)?)
+            } else {
+                None
+            };
+            Ok(Item {
+                item_type,
+                token: address_field(item, "token", &path)?,
+                identifier: number_field(item, "identifierOrCriteria", &path)?,
+                start_amount: number_field(item, "startAmount", &path)?,
+                end_amount: number_field(item, "endAmount", &path)?,
+                recipient,
+            })
+        })
+        .collect()
+}
+
+/// `seconds` as a `Long`, saturating
+fn seconds(seconds: U256) -> i64 {
+    i64::try_from(seconds).unwrap_or(i64::MAX)
+}
+
+fn number_field(value: &Value, field: &str, path: &str) -> Result<U256, SeaportError> {
+    match value.get(field) {
+        Some(Value::Number(n)) => n
+            .as_u64()
+            .map(U256::from)
+            .ok_or_else(|| SeaportError::InvalidAmount(n.to_string())),
+        Some(Value::String(s)) => {
+            parse_amount(s).map_err(|_| SeaportError::InvalidAmount(s.clone()))
+        }
+        _ => Err(SeaportError::Malformed(format!("{path}{field}"))),
+    }
+}
+
+fn address_field(value: &Value, field: &str, path: &str) -> Result<String, SeaportError> {
+    let address = value
+        .get(field)
+        .and_then(Value::as_str)
+        .ok_or_else(|| SeaportError::Malformed(format!("{path}{field}")))?;
+    normalize_address(address).ok_or_else(|| SeaportError::InvalidAddress(address.to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Authorizer, Decision, Entities, PolicySet, Request};
+    use serde_json::json;
+    use std::str::FromStr;
+
+    const SELLER: &str = "0x1111111111111111111111111111111111111111";
+    const OPENSEA_FEES: &str = "0x0000a26b00c1f0df003000390027140000faa719";
+    const ZONE: &str = "0x0000000000000000000000000000000000000000";
+    const AZUKI: &str = "0xed5af388653567af2f388e6224dc7c4b3241c544";
+    const WETH: &str = "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2";
+
+    fn listing(price: &str, fee: &str) -> Value {
+        json!({
+            "offerer": SELLER,
+            "zone": ZONE,
+            "offer": [{
+                "itemType": 2,
+                "token": "0xED5AF388653567Af2F388E6224dC7C4b3241C544",
+                "identifierOrCriteria": "4321",
+                "startAmount": "1",
+                "endAmount": "1",
+            }],
+            "consideration": [
+                {
+                    "itemType": 0,
+                    "token": ZONE,
+                    "identifierOrCriteria": "0",
+                    "startAmount": price,
+                    "endAmount": price,
+                    "recipient": SELLER,
+                },
+                {
+                    "itemType": 0,
+                    "token": ZONE,
+                    "identifierOrCriteria": "0",
+                    "startAmount": fee,
+                    "endAmount": fee,
+                    "recipient": OPENSEA_FEES,
+                },
+            ],
+            "orderType": 0,
+            "startTime": "1700000000",
+            "endTime": "0xffffffffffffffffffffffffffffffff",
+            "zoneHash": "0x0000000000000000000000000000000000000000000000000000000000000000",
+            "salt": "0x1",
+            "conduitKey": "0x0000007b02230091a7ed01230072f7006a004d60a8d4e71d599b8104250f0000",
+            "counter": "0",
+        })
+    }
+
+    #[test]
+    fn listings() {
+        let order =
+            Order::from_parameters(&listing("9750000000000000000", "250000000000000000")).unwrap();
+        assert_eq!(order.offer[0].item_type, ItemType::Erc721);
+        assert_eq!(order.offer[0].token, AZUKI);
+        assert_eq!(order.offer[0].identifier, U256::from(4321));
+        assert_eq!(order.end_time, i64::MAX);
+        assert_eq!(
+            order.proceeds(),
+            Some((ZONE, U256::from_dec_str("9750000000000000000").unwrap()))
+        );
+        assert_eq!(order.payment(), None);
+
+        let intent = order.to_intent();
+        assert_eq!(intent.action, "listNft");
+        assert_eq!(intent.context["paymentToken"], ZONE);
+        assert_eq!(
+            intent.context["consideration"][1]["recipient"],
+            OPENSEA_FEES
+        );
+        assert_eq!(intent.context["offer"][0]["itemType"], "erc721");
+
+        let typed_data = json!({
+            "primaryType": "OrderComponents",
+            "domain": { "name": "Seaport", "version": "1.5", "chainId": 1 },
+            "message": listing("1", "0"),
+        });
+        assert_eq!(
+            Order::from_typed_data(&typed_data)
+                .unwrap()
+                .to_intent()
+                .action,
+            "listNft"
+        );
+        assert!(matches!(
+            Order::from_typed_data(&json!({ "primaryType": "Permit", "message": {} })),
+            Err(SeaportError::Unsupported(_))
+        ));
+        let mut malformed = listing("1", "0");
+        malformed["offer"][0]["itemType"] = json!(9);
+        assert!(matches!(
+            Order::from_parameters(&malformed),
+            Err(SeaportError::Malformed(field)) if field == "offer[0].itemType"
+        ));
+    }
+
+    #[test]
+    fn bids() {
+        let bid = json!({
+            "offerer": SELLER,
+            "zone": ZONE,
+            "offer": [{
+                "itemType": 1,
+                "token": WETH,
+                "identifierOrCriteria": 0,
+                "startAmount": "5000000000000000000",
+                "endAmount": "5000000000000000000",
+            }],
+            "consideration": [{
+                "itemType": 4,
+                "token": AZUKI,
+                "identifierOrCriteria": 0,
+                "startAmount": 1,
+                "endAmount": 1,
+                "recipient": SELLER,
+            }],
+            "orderType": 0,
+            "startTime": 1_700_000_000,
+            "endTime": 1_700_086_400,
+        });
+        let intent = Order::from_parameters(&bid).unwrap().to_intent();
+        assert_eq!(intent.action, "bidNft");
+        assert_eq!(intent.context["paymentToken"], WETH);
+        assert_eq!(
+            intent.context["payment"],
+            u256_value(U256::from(5) * U256::exp10(18))
+        );
+        assert_eq!(intent.context["endTime"], 1_700_086_400);
+    }
+
+    #[test]
+    fn listings_below_the_floor_are_forbidden() {
+        let policies = PolicySet::from_str(
+            r#"permit(principal, action, resource);
+               forbid(principal, action == Action::"listNft", resource)
+               when { context.proceeds.u256LessThan(context.floorPrice) };"#,
+        )
+        .unwrap();
+        let decide = |price: &str| {
+            let intent = Order::from_parameters(&listing(price, "250000000000000000"))
+                .unwrap()
+                .to_intent()
+                .with("floorPrice", u256_value(U256::from(9) * U256::exp10(18)));
+            let request = Request::new(
+                None,
+                Some(intent.action_uid()),
+                None,
+                intent.to_context().unwrap(),
+            );
+            Authorizer::new()
+                .is_authorized(&request, &policies, &Entities::empty())
+                .decision()
+        };
+        assert_eq!(decide("9750000000000000000"), Decision::Allow);
+        assert_eq!(decide("8000000000000000000"), Decision::Deny);
+    }
+}
@@ -0,0 +1,453 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Session key permissions, kept as Cedar policies and mapped to and from
+//! ERC-7715-style permission grants.
+//!
+//! A [`SessionGrant`] lets a session key call functions (by selector) on
+//! target contracts, up to a value cap and until an expiry. In Cedar, a grant
+//! is a template annotated `@session("<account>")`:
+//! ```text
+//! @session("0x…account")
+//! permit(
+//!     principal == ?principal,
+//!     action in [Action::"0xa9059cbb"],
+//!     resource == ?resource
+//! ) when {
+//!     context.value.u256LessThanOrEqual(u256("1000")) && context.time < 1700000000
+//! };
+//! ```
+//! linked once per target, with the session key (`Wallet::"0x…"`) as the
+//! principal and the target (`Contract::"0x…"`) as the resource. Requests
+//! for session keys should use the function selector as the action, and put
+//! the call's value (a `u256`) and the current time (seconds since the Unix
+//! epoch) in the context as `value` and `time`.
+//!
+//! The policies are the canonical representation: [`SessionGrant::from_policies()`]
+//! reads a grant back from them, so wallets can show or re-issue it.
+
+use std::collections::HashMap;
+
+use cedar_policy_core::ast::{BinaryOp, Expr, ExprKind, Literal, Var};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    ActionConstraint, EntityUid, ParseErrors, PolicyId, PolicySet, PolicySetError,
+    PrincipalConstraint, ResourceConstraint, SlotId, Template, TemplatePrincipalConstraint,
+    TemplateResourceConstraint,
+};
+
+/// The annotation marking session grant templates, whose value is the
+/// granting account
+pub const SESSION_ANNOTATION: &str = "session";
+/// The entity type of session keys
+pub const SESSION_KEY_TYPE: &str = "Wallet";
+/// The entity type of target contracts
+pub const TARGET_TYPE: &str = "Contract";
+
+/// Errors converting between grants and policies
+#[derive(Debug, Error)]
+pub enum SessionError {
+    /// An address isn't 20 bytes of hex
+    #[error("`{0}` isn't an address")]
+    InvalidAddress(String),
+    /// A selector isn't 4 bytes of hex
+    #[error("`{0}` isn't a function selector")]
+    InvalidSelector(String),
+    /// The value cap isn't a decimal integer
+    #[error("`{0}` isn't a value cap")]
+    InvalidValueCap(String),
+    /// The expiry is too large to represent in Cedar
+    #[error("expiry {0} is out of range")]
+    InvalidExpiry(u64),
+    /// There's no session grant template with this id
+    #[error("`{0}` isn't a session grant template")]
+    NotFound(PolicyId),
+    /// The template or its links don't have the form of a session grant
+    #[error("`{id}` isn't in the form of a session grant: {reason}")]
+    Unrecognized {
+        /// The template or link
+        id: PolicyId,
+        /// What's wrong with it
+        reason: &'static str,
+    },
+    /// The generated template didn't parse
+    #[error(transparent)]
+    Parse(#[from] ParseErrors),
+    /// The policy set rejected the template or a link
+    #[error(transparent)]
+    PolicySet(#[from] PolicySetError),
+}
+
+/// A permission grant to a session key, in the style of ERC-7715
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionGrant {
+    /// The account granting the permission
+    pub account: String,
+    /// The session key the permission is granted to
+    pub session_key: String,
+    /// The contracts the session key may call
+    pub targets: Vec<String>,
+    /// The function selectors it may call. If empty, it may call any
+    /// function.
+    #[serde(default)]
+    pub selectors: Vec<String>,
+    /// The largest value, in wei, it may send with a call
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value_cap: Option<String>,
+    /// When the grant expires, in seconds since the Unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiry: Option<u64>,
+}
+
+impl SessionGrant {
+    /// The template for this grant, with id `id`
+    pub fn template(&self, id: &PolicyId) -> Result<Template, SessionError> {
+        let account = address(&self.account)?;
+        let action = match self.selectors.as_slice() {
+            [] => "action".to_string(),
+            selectors => {
+                let actions = selectors
+                    .iter()
+                    .map(|s| Ok(format!("Action::\"{}\"", selector(s)?)))
+                    .collect::<Result<Vec<_>, SessionError>>()?;
+                format!("action in [{}]", actions.join(", "))
+            }
+        };
+        let mut conditions = Vec::new();
+        if let Some(cap) = &self.value_cap {
+            if cap.is_empty() || !cap.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(SessionError::InvalidValueCap(cap.clone()));
+            }
+            conditions.push(format!(
+                "context.value.u256LessThanOrEqual(u256(\"{cap}\"))"
+            ));
+        }
+        if let Some(expiry) = self.expiry {
+            if i64::try_from(expiry).is_err() {
+                return Err(SessionError::InvalidExpiry(expiry));
+            }
+            conditions.push(format!("context.time < {expiry}"));
+        }
+        let when = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!(" when {{ {} }}", conditions.join(" && "))
+        };
+        let src = format!(
+            "@{SESSION_ANNOTATION}(\"{account}\")\npermit(principal == ?principal, {action}, resource == ?resource){when};"
+        );
+        Ok(Template::parse(Some(id.to_string()), src)?)
+    }
+
+    /// Add this grant to `policies`: its template, with id `id`, and a link
+    /// for each target, with ids `<id>/0`, `<id>/1`, and so on
+    pub fn add_to(&self, policies: &mut PolicySet, id: &PolicyId) -> Result<(), SessionError> {
+        let template = self.template(id)?;
+        let session_key = EntityUid::from_strs(SESSION_KEY_TYPE, &address(&self.session_key)?);
+        let targets = self
+            .targets
+            .iter()
+            .map(|target| Ok(EntityUid::from_strs(TARGET_TYPE, &address(target)?)))
+            .collect::<Result<Vec<_>, SessionError>>()?;
+        policies.add_template(template)?;
+        for (i, target) in targets.into_iter().enumerate() {
+            policies.link(
+                id.clone(),
+                format!("{id}/{i}").parse()?,
+                HashMap::from([
+                    (SlotId::principal(), session_key.clone()),
+                    (SlotId::resource(), target),
+                ]),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Read the grant with template `id` back from `policies`
+    pub fn from_policies(policies: &PolicySet, id: &PolicyId) -> Result<Self, SessionError> {
+        let template = policies
+            .template(id)
+            .ok_or_else(|| SessionError::NotFound(id.clone()))?;
+        let unrecognized = |reason| SessionError::Unrecognized {
+            id: id.clone(),
+            reason,
+        };
+        let account = template
+            .annotation(SESSION_ANNOTATION)
+            .ok_or_else(|| SessionError::NotFound(id.clone()))?
+            .to_string();
+        if template.principal_constraint() != TemplatePrincipalConstraint::Eq(None)
+            || template.resource_constraint() != TemplateResourceConstraint::Eq(None)
+        {
+            return Err(unrecognized(
+                "expected `principal == ?principal` and `resource == ?resource`",
+            ));
+        }
+        let selectors = match template.action_constraint() {
+            ActionConstraint::Any => Vec::new(),
+            ActionConstraint::Eq(action) => vec![action.id().as_ref().to_string()],
+            ActionConstraint::In(actions) => actions
+                .iter()
+                .map(|a| a.id().as_ref().to_string())
+                .collect(),
+        };
+        let mut grant = Self {
+            account,
+            session_key: String::new(),
+            targets: Vec::new(),
+            selectors,
+            value_cap: None,
+            expiry: None,
+        };
+        for condition in conjuncts(template.ast.non_head_constraints()) {
+            if let Some(cap) = u256_cap(condition, "value") {
+                grant.value_cap = Some(cap);
+            } else if let Some(expiry) = expiry(condition) {
+                grant.expiry = Some(expiry);
+            } else if !matches!(condition.expr_kind(), ExprKind::Lit(Literal::Bool(true))) {
+                return Err(unrecognized("unexpected condition"));
+            }
+        }
+        let mut links: Vec<_> = policies
+            .policies()
+            .filter(|p| p.template_id() == Some(id))
+            .collect();
+        links.sort_by(|a, b| a.id().as_ref().cmp(b.id().as_ref()));
+        for link in links {
+            let (PrincipalConstraint::Eq(key), ResourceConstraint::Eq(target)) =
+                (link.principal_constraint(), link.resource_constraint())
+            else {
+                return Err(unrecognized("expected links to a session key and a target"));
+            };
+            if grant.session_key.is_empty() {
+                grant.session_key = key.id().as_ref().to_string();
+            } else if grant.session_key != key.id().as_ref() {
+                return Err(unrecognized("links grant to more than one session key"));
+            }
+            grant.targets.push(target.id().as_ref().to_string());
+        }
+        Ok(grant)
+    }
+
+    /// Read every session grant in `policies`, with their template ids
+    pub fn all(policies: &PolicySet) -> Vec<(PolicyId, Result<Self, SessionError>)> {
+        policies
+            .templates()
+            .filter(|t| t.annotation(SESSION_ANNOTATION).is_some())
+            .map(|t| (t.id().clone(), Self::from_policies(policies, t.id())))
+            .collect()
+    }
+}
+
+/// `s` in canonical form, if it's a 20-byte hex address
+pub(crate) fn address(s: &str) -> Result<String, SessionError> {
+    hex_of_len(s, 20).ok_or_else(|| SessionError::InvalidAddress(s.to_string()))
+}
+
+/// `s` in canonical form, if it's a 4-byte hex selector
+fn selector(s: &str) -> Result<String, SessionError> {
+    hex_of_len(s, 4).ok_or_else(|| SessionError::InvalidSelector(s.to_string()))
+}
+
+/// `s` as lowercase, `0x`-prefixed hex, if it's `bytes` bytes of hex
+fn hex_of_len(s: &str, bytes: usize) -> Option<String> {
+    let digits = s.strip_prefix("0x").unwrap_or(s);
+    (digits.len() == bytes * 2 && digits.bytes().all(|b| b.is_ascii_hexdigit()))
+        .then(|| format!("0x{}", digits.to_ascii_lowercase()))
+}
+
+/// The conditions `expr` is a conjunction of
+pub(crate) fn conjuncts(expr: &Expr) -> Vec<&Expr> {
+    match expr.expr_kind() {
+        ExprKind::And { left, right } => {
+            let mut all = conjuncts(left);
+            all.extend(conjuncts(right));
+            all
+        }
+        _ => vec![expr],
+    }
+}
+
+/// Whether `expr` is `context.<attr>`
+fn is_context_attr(expr: &Expr, attr: &str) -> bool {
+    match expr.expr_kind() {
+        ExprKind::GetAttr { expr, attr: a } => {
+            a == attr && matches!(expr.expr_kind(), ExprKind::Var(Var::Context))
+        }
+        _ => false,
+    }
+}
+
+/// The cap, if `expr` is `context.<attr>.u256LessThanOrEqual(u256("<cap>"))`
+pub(crate) fn u256_cap(expr: &Expr, attr: &str) -> Option<String> {
+    let ExprKind::ExtensionFunctionApp { fn_name, args } = expr.expr_kind() else {
+        return None;
+    };
+    let [value, cap] = args.as_slice() else {
+        return None;
+    };
+    if fn_name.basename().as_ref() != "u256LessThanOrEqual" || !is_context_attr(value, attr) {
+        return None;
+    }
+    let ExprKind::ExtensionFunctionApp { fn_name, args } = cap.expr_kind() else {
+        return None;
+    };
+    match (fn_name.basename().as_ref(), args.as_slice()) {
+        ("u256", [arg]) => match arg.expr_kind() {
+            ExprKind::Lit(Literal::String(cap)) => Some(cap.to_string()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The expiry, if `expr` is `context.time < <expiry>`
+fn expiry(expr: &Expr) -> Option<u64> {
+    match expr.expr_kind() {
+        ExprKind::BinaryApp {
+            op: BinaryOp::Less,
+            arg1,
+            arg2,
+        } if is_context_attr(arg1, "time") => match arg2.expr_kind() {
+            ExprKind::Lit(Literal::Long(expiry)) => u64::try_from(*expiry).ok(),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Authorizer, Context, Decision, Entities, Request};
+    use std::str::FromStr;
+
+    const ACCOUNT: &str = "0x00000000000000000000000000000000000000AA";
+    const SESSION_KEY: &str = "0x00000000000000000000000000000000000000bb";
+    const TOKEN: &str = "0x00000000000000000000000000000000000000cc";
+    const TRANSFER: &str = "0xa9059cbb";
+
+    fn grant() -> SessionGrant {
+        SessionGrant {
+            account: ACCOUNT.to_string(),
+            session_key: SESSION_KEY.to_string(),
+            targets: vec![TOKEN.to_string()],
+            selectors: vec![TRANSFER.to_string()],
+            value_cap: Some("1000".to_string()),
+            expiry: Some(1_700_000_000),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut policies = PolicySet::new();
+        let id = PolicyId::from_str("grant").unwrap();
+        grant().add_to(&mut policies, &id).unwrap();
+        let read = SessionGrant::from_policies(&policies, &id).unwrap();
+        assert_eq!(
+            read,
+            SessionGrant {
+                account: ACCOUNT.to_ascii_lowercase(),
+                ..grant()
+            }
+        );
+        assert_eq!(SessionGrant::all(&policies).len(), 1);
+
+        let unrestricted = SessionGrant {
+            selectors: Vec::new(),
+            value_cap: None,
+            expiry: None,
+            ..grant()
+        };
+        let id = PolicyId::from_str("unrestricted").unwrap();
+        unrestricted.add_to(&mut policies, &id).unwrap();
+        assert_eq!(
+            SessionGrant::from_policies(&policies, &id)
+                .unwrap()
+                .selectors,
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn authorizes() {
+        let mut policies = PolicySet::new();
+        grant()
+            .add_to(&mut policies, &PolicyId::from_str("grant").unwrap())
+            .unwrap();
+        let decide = |value: &str, time: i64| {
+            let context = Context::from_json_value(
+                serde_json::json!({
+                    "value": { "__extn": { "fn": "u256", "arg": value } },
+                    "time": time,
+                }),
+                None,
+            )
+            .unwrap();
+            let request = Request::new(
+                Some(EntityUid::from_strs(SESSION_KEY_TYPE, SESSION_KEY)),
+                Some(EntityUid::from_strs("Action", TRANSFER)),
+                Some(EntityUid::from_strs(TARGET_TYPE, TOKEN)),
+                context,
+            );
+            Authorizer::new()
+                .is_authorized(&request, &policies, &Entities::empty())
+                .decision()
+        };
+        assert_eq!(decide("1000", 1_600_000_000), Decision::Allow);
+        assert_eq!(decide("1001", 1_600_000_000), Decision::Deny);
+        assert_eq!(decide("1000", 1_700_000_000), Decision::Deny);
+    }
+
+    #[test]
+    fn invalid() {
+        let id = PolicyId::from_str("grant").unwrap();
+        let bad_selector = SessionGrant {
+            selectors: vec!["transfer".to_string()],
+            ..grant()
+        };
+        assert!(matches!(
+            bad_selector.template(&id),
+            Err(SessionError::InvalidSelector(_))
+        ));
+        let bad_cap = SessionGrant {
+            value_cap: Some("1e18".to_string()),
+            ..grant()
+        };
+        assert!(matches!(
+            bad_cap.template(&id),
+            Err(SessionError::InvalidValueCap(_))
+        ));
+
+        let mut policies = PolicySet::new();
+        policies
+            .add_template(
+                Template::parse(
+                    Some("grant".to_string()),
+                    r#"@session("0x00") permit(principal == ?principal, action, resource == ?resource) when { context.admin };"#,
+                )
+                .unwrap(),
+            )
+            .unwrap();
+        assert!(matches!(
+            SessionGrant::from_policies(&policies, &id),
+            Err(SessionError::Unrecognized { .. })
+        ));
+    }
+}
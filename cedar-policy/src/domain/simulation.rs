@@ -0,0 +1,566 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+//! Transaction simulation results as request context.
+//!
+//! A [`Simulation`] holds the effects of a simulated transaction: the assets
+//! it transfers, the balances it changes, and the logs it emits. It can be
+//! read from a Tenderly simulation ([`Simulation::from_tenderly()`]), from a
+//! `callTracer` trace as returned by anvil or geth
+//! ([`Simulation::from_call_trace()`]), or from plain logs
+//! ([`Simulation::from_logs()`]). [`Simulation::to_context()`] exposes the
+//! effects as sets of records, with amounts as `u256` values, so policies can
+//! authorize against what a transaction does rather than its calldata, e.g.
+//! ```text
+//! forbid(principal, action, resource)
+//! unless { context.transfers.contains({
+//!     token: "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48",
+//!     from: context.safe,
+//!     to: context.payee,
+//!     amount: context.invoiceAmount,
+//! }) };
+//! ```
+
+use ethers::types::U256;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use thiserror::Error;
+
+use crate::audit::{normalize_address, to_hex, unhex};
+use crate::domain::intent::u256_value;
+use crate::{Context, ContextJsonError};
+
+/// The pseudo-address used as the token of native currency transfers and
+/// balances
+pub const NATIVE_TOKEN: &str = "0xeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeee";
+
+/// The address tokens are minted from and burned to
+const ZERO_ADDRESS: &str = "0x0000000000000000000000000000000000000000";
+
+/// The topic of ERC-20 and ERC-721 `Transfer(address,address,uint256)` events
+const TRANSFER_TOPIC: &str = "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef";
+
+/// Errors reading a simulation result
+#[derive(Debug, Error)]
+pub enum SimulationError {
+    /// A field is missing or has the wrong type
+    #[error("malformed simulation result: `{0}` is missing or invalid")]
+    Malformed(String),
+    /// An address isn't a `0x`-prefixed, 20 byte hex string
+    #[error("invalid address: {0}")]
+    InvalidAddress(String),
+    /// An amount isn't a decimal or `0x`-prefixed hex integer below 2^256
+    #[error("invalid amount: {0}")]
+    InvalidAmount(String),
+}
+
+/// A transfer of a token, or of native currency if `token` is
+/// [`NATIVE_TOKEN`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transfer {
+    /// The token contract
+    pub token: String,
+    /// The sender, or the zero address for mints
+    pub from: String,
+    /// The recipient, or the zero address for burns
+    pub to: String,
+    /// The amount, in the token's base units
+    pub amount: U256,
+}
+
+/// A change in an account's balance of a token, or of native currency if
+/// `token` is [`NATIVE_TOKEN`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BalanceChange {
+    /// The account whose balance changed
+    pub account: String,
+    /// The token contract
+    pub token: String,
+    /// The balance before the transaction
+    pub before: U256,
+    /// The balance after the transaction
+    pub after: U256,
+}
+
+/// An emitted log, in the JSON-RPC format
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Log {
+    /// The contract which emitted the log
+    pub address: String,
+    /// The topics, as `0x`-prefixed hex
+    pub topics: Vec<String>,
+    /// The data, as `0x`-prefixed hex
+    pub data: String,
+}
+
+impl Log {
+    /// The transfer this log records, if it's an ERC-20 `Transfer` event.
+    /// ERC-721 `Transfer` events, which index the token id, aren't transfers
+    /// of an amount and are left as logs.
+    pub fn transfer(&self) -> Option<Transfer> {
+        let [topic, from, to] = self.topics.as_slice() else {
+            return None;
+        };
+        if !topic.eq_ignore_ascii_case(TRANSFER_TOPIC) {
+            return None;
+        }
+        let data = unhex(self.data.strip_prefix("0x")?)?;
+        if data.len() != 32 {
+            return None;
+        }
+        Some(Transfer {
+            token: normalize_address(&self.address)?,
+            from: topic_address(from)?,
+            to: topic_address(to)?,
+            amount: U256::from_big_endian(&data),
+        })
+    }
+}
+
+/// The address held in an indexed topic
+fn topic_address(topic: &str) -> Option<String> {
+    let word = unhex(topic.strip_prefix("0x")?)?;
+    if word.len() != 32 {
+        return None;
+    }
+    let (padding, address) = word.split_at(12);
+    padding
+        .iter()
+        .all(|b| *b == 0)
+        .then(|| format!("0x{}", to_hex(address)))
+}
+
+/// The effects of a simulated transaction
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Simulation {
+    /// Assets transferred, in the order they were transferred
+    pub transfers: Vec<Transfer>,
+    /// Balances changed
+    pub balance_changes: Vec<BalanceChange>,
+    /// Logs emitted, in the order they were emitted
+    pub logs: Vec<Log>,
+}
+
+impl Simulation {
+    /// A simulation with no effects
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The effects recorded by `logs`: the logs themselves, and the ERC-20
+    /// transfers among them
+    pub fn from_logs(logs: impl IntoIterator<Item = Log>) -> Self {
+        let logs: Vec<Log> = logs.into_iter().collect();
+        Self {
+            transfers: logs.iter().filter_map(Log::transfer).collect(),
+            balance_changes: Vec::new(),
+            logs,
+        }
+    }
+
+    /// The effects recorded by a `callTracer` trace with logs, as returned
+    /// by `debug_traceCall` or `debug_traceTransaction` with
+    /// `{"tracer": "callTracer", "tracerConfig": {"withLog": true}}` on anvil
+    /// or geth. Native currency sent by each call is a transfer. Calls which
+    /// reverted, and everything they called, had no effect and are skipped.
+    pub fn from_call_trace(trace: &Value) -> Result<Self, SimulationError> {
+        let mut simulation = Self::new();
+        simulation.add_call(trace, "")?;
+        Ok(simulation)
+    }
+
+    fn add_call(&mut self, call: &Value, path: &str) -> Result<(), SimulationError> {
+        if call.get("error").is_some() {
+            return Ok(());
+        }
+        let value = call
+            .get("value")
+            .and_then(Value::as_str)
+            .map(parse_amount)
+            .transpose()?
+            .unwrap_or_default();
+        // a delegate call runs with its caller's value, which was already
+        // counted
+        let delegated = call.get("type").and_then(Value::as_str) == Some("DELEGATECALL");
+        if !value.is_zero() && !delegated {
+            self.transfers.push(Transfer {
+                token: NATIVE_TOKEN.to_string(),
+                from: address_field(call, "from", path)?,
+                to: address_field(call, "to", path)?,
+                amount: value,
+            });
+        }
+        if let Some(logs) = call.get("logs") {
+            let logs: Vec<Log> = serde_json::from_value(logs.clone())
+                .map_err(|_| SimulationError::Malformed(format!("{path}logs")))?;
+            for log in logs {
+                self.transfers.extend(log.transfer());
+                self.logs.push(log);
+            }
+        }
+        for (i, subcall) in call
+            .get("calls")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .enumerate()
+        {
+            self.add_call(subcall, &format!("{path}calls[{i}]."))?;
+        }
+        Ok(())
+    }
+
+    /// The effects recorded by a Tenderly simulation: its asset changes,
+    /// native currency balance diffs, and logs. `simulation` may be the
+    /// response of the simulate endpoint, its `transaction`, or that
+    /// transaction's `transaction_info`.
+    pub fn from_tenderly(simulation: &Value) -> Result<Self, SimulationError> {
+        let info = simulation
+            .pointer("/transaction/transaction_info")
+            .or_else(|| simulation.get("transaction_info"))
+            .unwrap_or(simulation);
+        let mut result = Self::new();
+        for (i, change) in array_field(info, "asset_changes").iter().enumerate() {
+            let path = format!("asset_changes[{i}].");
+            let token = match change
+                .pointer("/token_info/contract_address")
+                .and_then(Value::as_str)
+            {
+                Some(address) if !address.is_empty() => address_value(address)?,
+                _ => NATIVE_TOKEN.to_string(),
+            };
+            let amount = change
+                .get("raw_amount")
+                .and_then(Value::as_str)
+                .ok_or_else(|| SimulationError::Malformed(format!("{path}raw_amount")))
+                .and_then(parse_amount)?;
+            result.transfers.push(Transfer {
+                token,
+                from: optional_address_field(change, "from")?,
+                to: optional_address_field(change, "to")?,
+                amount,
+            });
+        }
+        for (i, diff) in array_field(info, "balance_diff").iter().enumerate() {
+            let path = format!("balance_diff[{i}].");
+            let amount = |field: &str| {
+                diff.get(field)
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| SimulationError::Malformed(format!("{path}{field}")))
+                    .and_then(parse_amount)
+            };
+            result.balance_changes.push(BalanceChange {
+                account: address_field(diff, "address", &path)?,
+                token: NATIVE_TOKEN.to_string(),
+                before: amount("original")?,
+                after: amount("dirty")?,
+            });
+        }
+        for (i, log) in array_field(info, "logs").iter().enumerate() {
+            let raw = log.get("raw").unwrap_or(log);
+            result.logs.push(
+                serde_json::from_value(raw.clone())
+                    .map_err(|_| SimulationError::Malformed(format!("logs[{i}].raw")))?,
+            );
+        }
+        Ok(result)
+    }
+
+    /// The effects as a JSON object in the format of a Cedar context, with
+    /// `transfers`, `balanceChanges`, and `logs` attributes. Each log has
+    /// `address` and `data` attributes, and its topics as `topic0` through
+    /// `topic3`, as the `logMatch` extension function expects.
+    pub fn to_json(&self) -> Map<String, Value> {
+        let transfers = self
+            .transfers
+            .iter()
+            .map(|t| {
+                json!({
+                    "token": t.token,
+                    "from": t.from,
+                    "to": t.to,
+                    "amount": u256_value(t.amount),
+                })
+            })
+            .collect();
+        let balance_changes = self
+            .balance_changes
+            .iter()
+            .map(|c| {
+                json!({
+                    "account": c.account,
+                    "token": c.token,
+                    "before": u256_value(c.before),
+                    "after": u256_value(c.after),
+                })
+            })
+            .collect();
+        let logs = self
+            .logs
+            .iter()
+            .map(|log| {
+                let mut record = Map::new();
+                record.insert(
+                    "address".to_string(),
+                    Value::from(log.address.to_ascii_lowercase()),
+                );
+                // topics are kept as separate attributes because a set
+                // wouldn't keep their order
+                for (i, topic) in log.topics.iter().enumerate() {
+                    record.insert(format!("topic{i}"), Value::from(topic.to_ascii_lowercase()));
+                }
+                record.insert(
+                    "data".to_string(),
+                    Value::from(log.data.to_ascii_lowercase()),
+                );
+                Value::Object(record)
+            })
+            .collect();
+        let mut context = Map::new();
+        context.insert("transfers".to_string(), Value::Array(transfers));
+        context.insert("balanceChanges".to_string(), Value::Array(balance_changes));
+        context.insert("logs".to_string(), Value::Array(logs));
+        context
+    }
+
+    /// The context for a request, holding the effects as described in
+    /// [`Self::to_json()`]
+    pub fn to_context(&self) -> Result<Context, ContextJsonError> {
+        Context::from_json_value(Value::Object(self.to_json()), None)
+    }
+}
+
+/// Parse a decimal or `0x`-prefixed hex amount
+pub(crate) fn parse_amount(s: &str) -> Result<U256, SimulationError> {
+    let amount = match s.strip_prefix("0x") {
+        Some(hex) if !hex.is_empty() => U256::from_str_radix(hex, 16).ok(),
+        None if !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()) => {
+            U256::from_dec_str(s).ok()
+        }
+        _ => None,
+    };
+    amount.ok_or_else(|| SimulationError::InvalidAmount(s.to_string()))
+}
+
+fn address_value(address: &str) -> Result<String, SimulationError> {
+    normalize_address(address).ok_or_else(|| SimulationError::InvalidAddress(address.to_string()))
+}
+
+fn address_field(value: &Value, field: &str, path: &str) -> Result<String, SimulationError> {
+    let address = value
+        .get(field)
+        .and_then(Value::as_str)
+        .ok_or_else(|| SimulationError::Malformed(format!("{path}{field}")))?;
+    address_value(address)
+}
+
+/// An address which is absent for mints and burns
+fn optional_address_field(value: &Value, field: &str) -> Result<String, SimulationError> {
+    match value.get(field).and_then(Value::as_str) {
+        Some(address) if !address.is_empty() => address_value(address),
+        _ => Ok(ZERO_ADDRESS.to_string()),
+    }
+}
+
+fn array_field<'a>(value: &'a Value, field: &str) -> &'a [Value] {
+    value
+        .get(field)
+        .and_then(Value::as_array)
+        .map_or(&[], Vec::as_slice)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Authorizer, Decision, Entities, PolicySet, Request};
+    use std::str::FromStr;
+
+    const USDC: &str = "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48";
+    const SAFE: &str = "0x1111111111111111111111111111111111111111";
+    const PAYEE: &str = "0x2222222222222222222222222222222222222222";
+
+    fn topic(address: &str) -> String {
+        format!("0x{:0>64}", &address[2..])
+    }
+
+    fn transfer_log(from: &str, to: &str, amount: u64) -> Value {
+        json!({
+            "address": "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48",
+            "topics": [TRANSFER_TOPIC, topic(from), topic(to)],
+            "data": format!("0x{amount:064x}"),
+        })
+    }
+
+    #[test]
+    fn logs() {
+        let approval = Log {
+            address: USDC.to_string(),
+            topics: vec![
+                "0x8c5be1e5ebec7d5bd14f71427d1e84f3dd0314c0f7b2291e5b200ac8c7c3b925".to_string(),
+                topic(SAFE),
+                topic(PAYEE),
+            ],
+            data: format!("0x{:064x}", 5),
+        };
+        let transfer: Log = serde_json::from_value(transfer_log(SAFE, PAYEE, 1000)).unwrap();
+        let simulation = Simulation::from_logs([approval, transfer]);
+        assert_eq!(
+            simulation.transfers,
+            vec![Transfer {
+                token: USDC.to_string(),
+                from: SAFE.to_string(),
+                to: PAYEE.to_string(),
+                amount: 1000.into(),
+            }]
+        );
+        assert_eq!(simulation.logs.len(), 2);
+    }
+
+    #[test]
+    fn call_trace() {
+        let trace = json!({
+            "type": "CALL",
+            "from": SAFE,
+            "to": USDC,
+            "value": "0xde0b6b3a7640000",
+            "calls": [
+                {
+                    "type": "DELEGATECALL",
+                    "from": USDC,
+                    "to": PAYEE,
+                    "value": "0xde0b6b3a7640000",
+                    "logs": [transfer_log(SAFE, PAYEE, 1000)],
+                },
+                {
+                    "type": "CALL",
+                    "from": USDC,
+                    "to": PAYEE,
+                    "value": "0x1",
+                    "error": "execution reverted",
+                    "logs": [transfer_log(SAFE, PAYEE, 5)],
+                },
+            ],
+        });
+        let simulation = Simulation::from_call_trace(&trace).unwrap();
+        assert_eq!(
+            simulation.transfers,
+            vec![
+                Transfer {
+                    token: NATIVE_TOKEN.to_string(),
+                    from: SAFE.to_string(),
+                    to: USDC.to_string(),
+                    amount: U256::exp10(18),
+                },
+                Transfer {
+                    token: USDC.to_string(),
+                    from: SAFE.to_string(),
+                    to: PAYEE.to_string(),
+                    amount: 1000.into(),
+                },
+            ]
+        );
+        assert_eq!(simulation.logs.len(), 1);
+
+        assert!(matches!(
+            Simulation::from_call_trace(&json!({ "calls": [{ "value": "0x1", "from": SAFE }] })),
+            Err(SimulationError::Malformed(path)) if path == "calls[0].to"
+        ));
+        assert!(matches!(
+            Simulation::from_call_trace(&json!({ "value": "12ab" })),
+            Err(SimulationError::InvalidAmount(_))
+        ));
+    }
+
+    #[test]
+    fn tenderly() {
+        let response = json!({
+            "transaction": {
+                "transaction_info": {
+                    "asset_changes": [
+                        {
+                            "token_info": { "standard": "ERC20", "contract_address": USDC },
+                            "type": "Transfer",
+                            "from": SAFE,
+                            "to": PAYEE,
+                            "raw_amount": "1000",
+                        },
+                        {
+                            "token_info": { "standard": "NativeCurrency" },
+                            "type": "Mint",
+                            "to": PAYEE,
+                            "raw_amount": "0x10",
+                        },
+                    ],
+                    "balance_diff": [
+                        { "address": SAFE, "original": "300", "dirty": "200", "is_miner": false },
+                    ],
+                    "logs": [{ "name": "Transfer", "raw": transfer_log(SAFE, PAYEE, 1000) }],
+                }
+            }
+        });
+        let simulation = Simulation::from_tenderly(&response).unwrap();
+        assert_eq!(simulation.transfers[1].token, NATIVE_TOKEN);
+        assert_eq!(simulation.transfers[1].from, ZERO_ADDRESS);
+        assert_eq!(simulation.transfers[1].amount, 16.into());
+        assert_eq!(
+            simulation.balance_changes,
+            vec![BalanceChange {
+                account: SAFE.to_string(),
+                token: NATIVE_TOKEN.to_string(),
+                before: 300.into(),
+                after: 200.into(),
+            }]
+        );
+        assert_eq!(simulation.logs.len(), 1);
+        assert_eq!(
+            Simulation::from_tenderly(&response["transaction"]).unwrap(),
+            simulation
+        );
+
+        let policies = PolicySet::from_str(&format!(
+            r#"permit(principal, action, resource)
+               when {{ context.transfers.contains({{
+                   token: "{USDC}", from: "{SAFE}", to: "{PAYEE}", amount: u256("1000")
+               }}) }};
+               forbid(principal, action, resource)
+               when {{ context.balanceChanges.contains({{
+                   account: "{SAFE}", token: "{NATIVE_TOKEN}", before: u256("300"), after: u256("0")
+               }}) }};"#
+        ))
+        .unwrap();
+        let request = Request::new(None, None, None, simulation.to_context().unwrap());
+        let response = Authorizer::new().is_authorized(&request, &policies, &Entities::empty());
+        assert_eq!(response.decision(), Decision::Allow);
+    }
+
+    #[test]
+    #[cfg(feature = "log-match")]
+    fn log_match() {
+        let logs = [serde_json::from_value(transfer_log(SAFE, PAYEE, 1000)).unwrap()];
+        let context = Simulation::from_logs(logs).to_context().unwrap();
+        let policies = PolicySet::from_str(&format!(
+            r#"permit(principal, action, resource)
+               when {{ logMatch(
+                   context.logs,
+                   "Transfer(address indexed from, address indexed to, uint256 value)",
+                   {{ to: "{PAYEE}", value: u256("1000"), "log.address": "{USDC}" }}
+               ) }};"#
+        ))
+        .unwrap();
+        let request = Request::new(None, None, None, context);
+        let response = Authorizer::new().is_authorized(&request, &policies, &Entities::empty());
+        assert_eq!(response.decision(), Decision::Allow);
+    }
+}
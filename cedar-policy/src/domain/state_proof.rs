@@ -0,0 +1,653 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Entity attributes verified by EIP-1186 state proofs.
+//!
+//! Light clients and zk provers can't trust an RPC node's word for on-chain
+//! state. Instead, the node supplies the response of `eth_getProof`: the
+//! account and its storage slots, with Merkle-Patricia proofs. Given the
+//! block header and a trusted hash of the block, [`verify_account()`] checks
+//! the proofs against the block's state root, and only then are the values
+//! exposed as `u256` attributes by [`VerifiedAccount::to_entity()`].
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::str::FromStr;
+
+use ethers::types::U256;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
+use thiserror::Error;
+
+use crate::audit::unhex;
+use crate::{Entity, EntityUid, RestrictedExpression};
+
+/// The root of an empty trie: the hash of the RLP encoding of `""`
+const EMPTY_TRIE_ROOT: [u8; 32] = [
+    0x56, 0xe8, 0x1f, 0x17, 0x1b, 0xcc, 0x55, 0xa6, 0xff, 0x83, 0x45, 0xe6, 0x92, 0xc0, 0xf8, 0x6e,
+    0x5b, 0x48, 0xe0, 0x1b, 0x99, 0x6c, 0xad, 0xc0, 0x01, 0x62, 0x2f, 0xb5, 0xe3, 0x63, 0xb4, 0x21,
+];
+
+/// Errors verifying a proof
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ProofError {
+    /// A value isn't `0x`-prefixed hex
+    #[error("`{0}` isn't hex")]
+    InvalidHex(String),
+    /// The header doesn't hash to the trusted block hash
+    #[error("the block header doesn't match the trusted block hash")]
+    UntrustedHeader,
+    /// A proof node doesn't hash to the hash referring to it, or is missing
+    #[error("the proof doesn't lead from the root to the value")]
+    InvalidProof,
+    /// RLP or a trie node is malformed
+    #[error("malformed proof: {0}")]
+    Malformed(&'static str),
+    /// The proven value differs from the claimed one
+    #[error("the proof doesn't match the claimed {0}")]
+    ValueMismatch(String),
+    /// No storage proof was given for a slot
+    #[error("no proof for storage slot {0}")]
+    NotProven(U256),
+}
+
+/// A storage proof, as in the `storageProof` of an `eth_getProof` response
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StorageProof {
+    /// The slot
+    pub key: String,
+    /// The claimed value
+    pub value: String,
+    /// The RLP-encoded trie nodes, from the storage root
+    pub proof: Vec<String>,
+}
+
+/// An `eth_getProof` response
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountProof {
+    /// The account
+    pub address: String,
+    /// The RLP-encoded trie nodes, from the state root
+    pub account_proof: Vec<String>,
+    /// The claimed balance
+    pub balance: String,
+    /// The claimed code hash
+    pub code_hash: String,
+    /// The claimed nonce
+    pub nonce: String,
+    /// The claimed storage root
+    pub storage_hash: String,
+    /// The proofs of storage slots
+    #[serde(default)]
+    pub storage_proof: Vec<StorageProof>,
+}
+
+/// An account whose state has been verified
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifiedAccount {
+    /// The account
+    pub address: String,
+    /// Its nonce
+    pub nonce: U256,
+    /// Its balance, in wei
+    pub balance: U256,
+    /// The proven storage slots and their values
+    pub storage: BTreeMap<U256, U256>,
+}
+
+impl VerifiedAccount {
+    /// The entity `uid`, with the attributes `balance` and `nonce`, and an
+    /// attribute for each of `slots`, which maps attribute names to storage
+    /// slots. Every value is a `u256`.
+    pub fn to_entity(
+        &self,
+        uid: EntityUid,
+        slots: &HashMap<String, U256>,
+    ) -> Result<Entity, ProofError> {
+        let mut attrs = HashMap::from([
+            ("balance".to_string(), u256_expr(self.balance)),
+            ("nonce".to_string(), u256_expr(self.nonce)),
+        ]);
+        for (name, slot) in slots {
+            let value = self.storage.get(slot).ok_or(ProofError::NotProven(*slot))?;
+            attrs.insert(name.clone(), u256_expr(*value));
+        }
+        Ok(Entity::new(uid, attrs, HashSet::new()))
+    }
+}
+
+fn u256_expr(n: U256) -> RestrictedExpression {
+    // PANIC SAFETY: a decimal integer is a valid argument to `u256`
+    #[allow(clippy::expect_used)]
+    RestrictedExpression::from_str(&format!("u256(\"{n}\")")).expect("valid u256 expression")
+}
+
+/// The state root of the block with RLP-encoded header `header`, if the
+/// header hashes to `block_hash`
+pub fn state_root(block_hash: &[u8; 32], header: &[u8]) -> Result<[u8; 32], ProofError> {
+    if keccak(header) != *block_hash {
+        return Err(ProofError::UntrustedHeader);
+    }
+    match Rlp::decode(header)? {
+        Rlp::List(fields) => fields
+            .get(3)
+            .and_then(|root| root.bytes()?.try_into().ok())
+            .ok_or(ProofError::Malformed("the header has no state root")),
+        Rlp::Bytes(_) => Err(ProofError::Malformed("the header isn't a list")),
+    }
+}
+
+/// Verify `proof` against `state_root`, returning the proven state. The
+/// claimed values in `proof` must match the proven ones.
+pub fn verify_account(
+    state_root: &[u8; 32],
+    proof: &AccountProof,
+) -> Result<VerifiedAccount, ProofError> {
+    let address = hex(&proof.address)?;
+    let account_nodes = proof
+        .account_proof
+        .iter()
+        .map(|node| hex(node))
+        .collect::<Result<Vec<_>, _>>()?;
+    let (nonce, balance, storage_root, code_hash) =
+        match verify_proof(state_root, &keccak(&address), &account_nodes)? {
+            None => (U256::zero(), U256::zero(), EMPTY_TRIE_ROOT, keccak(&[])),
+            Some(account) => match Rlp::decode(&account)? {
+                Rlp::List(fields) => match fields.as_slice() {
+                    [nonce, balance, storage_root, code_hash] => (
+                        uint(nonce)?,
+                        uint(balance)?,
+                        hash(storage_root)?,
+                        hash(code_hash)?,
+                    ),
+                    _ => return Err(ProofError::Malformed("an account has four fields")),
+                },
+                Rlp::Bytes(_) => return Err(ProofError::Malformed("an account is a list")),
+            },
+        };
+    check("nonce", quantity(&proof.nonce)?, nonce)?;
+    check("balance", quantity(&proof.balance)?, balance)?;
+    check(
+        "storageHash",
+        hex(&proof.storage_hash)?,
+        storage_root.to_vec(),
+    )?;
+    check("codeHash", hex(&proof.code_hash)?, code_hash.to_vec())?;
+
+    let mut storage = BTreeMap::new();
+    for slot in &proof.storage_proof {
+        let key = quantity(&slot.key)?;
+        let mut key_bytes = [0; 32];
+        key.to_big_endian(&mut key_bytes);
+        let nodes = slot
+            .proof
+            .iter()
+            .map(|node| hex(node))
+            .collect::<Result<Vec<_>, _>>()?;
+        let value = match verify_proof(&storage_root, &keccak(&key_bytes), &nodes)? {
+            None => U256::zero(),
+            Some(value) => uint(&Rlp::decode(&value)?)?,
+        };
+        check(
+            &format!("value of slot {key}"),
+            quantity(&slot.value)?,
+            value,
+        )?;
+        storage.insert(key, value);
+    }
+    Ok(VerifiedAccount {
+        address: proof.address.to_ascii_lowercase(),
+        nonce,
+        balance,
+        storage,
+    })
+}
+
+fn check<T: PartialEq>(field: &str, claimed: T, proven: T) -> Result<(), ProofError> {
+    if claimed == proven {
+        Ok(())
+    } else {
+        Err(ProofError::ValueMismatch(field.to_string()))
+    }
+}
+
+/// The value at `path` in the trie with root `root`, or `None` if `proof`
+/// shows there's no value there
+fn verify_proof(
+    root: &[u8; 32],
+    path: &[u8; 32],
+    proof: &[Vec<u8>],
+) -> Result<Option<Vec<u8>>, ProofError> {
+    let nibbles: Vec<u8> = path.iter().flat_map(|b| [b >> 4, b & 0x0f]).collect();
+    let mut nodes = proof.iter();
+    let mut next = NodeRef::Hash(*root);
+    let mut pos = 0;
+    loop {
+        let encoded = match next {
+            NodeRef::Hash(hash) => {
+                let node = nodes.next().ok_or(ProofError::InvalidProof)?;
+                if keccak(node) != hash {
+                    return Err(ProofError::InvalidProof);
+                }
+                node.clone()
+            }
+            NodeRef::Inline(node) => node,
+        };
+        let Rlp::List(items) = Rlp::decode(&encoded)? else {
+            return Err(ProofError::Malformed("a trie node is a list"));
+        };
+        match items.as_slice() {
+            [children @ .., value] if children.len() == 16 => {
+                let Some(&nibble) = nibbles.get(pos) else {
+                    return Ok(value.bytes().filter(|v| !v.is_empty()).map(<[u8]>::to_vec));
+                };
+                pos += 1;
+                let child = children
+                    .get(usize::from(nibble))
+                    .ok_or(ProofError::Malformed("a nibble is less than 16"))?;
+                match node_ref(child)? {
+                    Some(child) => next = child,
+                    None => return Ok(None),
+                }
+            }
+            [encoded_path, value] => {
+                let (path, is_leaf) = decode_path(
+                    encoded_path
+                        .bytes()
+                        .ok_or(ProofError::Malformed("a node path is a string"))?,
+                )?;
+                let rest = nibbles.get(pos..).unwrap_or_default();
+                if is_leaf {
+                    if rest != path.as_slice() {
+                        return Ok(None);
+                    }
+                    return Ok(value.bytes().map(<[u8]>::to_vec));
+                }
+                if !rest.starts_with(&path) {
+                    return Ok(None);
+                }
+                pos += path.len();
+                next = node_ref(value)?.ok_or(ProofError::Malformed("an extension is empty"))?;
+            }
+            _ => return Err(ProofError::Malformed("a trie node has 2 or 17 items")),
+        }
+    }
+}
+
+/// A reference to a trie node: its hash, or the node itself if its
+/// encoding is shorter than a hash
+enum NodeRef {
+    Hash([u8; 32]),
+    Inline(Vec<u8>),
+}
+
+/// The node `item` refers to, or `None` if it's empty
+fn node_ref(item: &Rlp<'_>) -> Result<Option<NodeRef>, ProofError> {
+    match item {
+        Rlp::Bytes(bytes) if bytes.is_empty() => Ok(None),
+        Rlp::Bytes(bytes) => {
+            Ok(Some(NodeRef::Hash((*bytes).try_into().map_err(|_| {
+                ProofError::Malformed("a node reference is a hash")
+            })?)))
+        }
+        Rlp::List(_) => Ok(Some(NodeRef::Inline(item.encode()))),
+    }
+}
+
+/// Decode a hex-prefix encoded path into nibbles, and whether it's a leaf's
+fn decode_path(encoded: &[u8]) -> Result<(Vec<u8>, bool), ProofError> {
+    let (&first, rest) = encoded
+        .split_first()
+        .ok_or(ProofError::Malformed("a node path is empty"))?;
+    let flag = first >> 4;
+    if flag > 3 {
+        return Err(ProofError::Malformed("invalid node path flag"));
+    }
+    let mut nibbles = Vec::with_capacity(rest.len() * 2 + 1);
+    if flag & 1 == 1 {
+        nibbles.push(first & 0x0f);
+    }
+    nibbles.extend(rest.iter().flat_map(|b| [b >> 4, b & 0x0f]));
+    Ok((nibbles, flag & 2 == 2))
+}
+
+/// An RLP item
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Rlp<'a> {
+    Bytes(&'a [u8]),
+    List(Vec<Rlp<'a>>),
+}
+
+impl<'a> Rlp<'a> {
+    /// Decode `data`, which must be exactly one item
+    fn decode(data: &'a [u8]) -> Result<Self, ProofError> {
+        match Self::decode_prefix(data)? {
+            (item, []) => Ok(item),
+            _ => Err(ProofError::Malformed("trailing bytes after RLP item")),
+        }
+    }
+
+    /// Decode the item at the start of `data`, returning the rest
+    fn decode_prefix(data: &'a [u8]) -> Result<(Self, &'a [u8]), ProofError> {
+        let (&prefix, rest) = data
+            .split_first()
+            .ok_or(ProofError::Malformed("truncated RLP"))?;
+        let (is_list, len, rest) = match prefix {
+            0x00..=0x7f => return Ok((Self::Bytes(data.split_at(1).0), rest)),
+            0x80..=0xb7 => (false, usize::from(prefix - 0x80), rest),
+            0xc0..=0xf7 => (true, usize::from(prefix - 0xc0), rest),
+            _ => {
+                let (is_list, len_len) = if prefix <= 0xbf {
+                    (false, usize::from(prefix - 0xb7))
+                } else {
+                    (true, usize::from(prefix - 0xf7))
+                };
+                if len_len > rest.len() {
+                    return Err(ProofError::Malformed("truncated RLP"));
+                }
+                let (len_bytes, rest) = rest.split_at(len_len);
+                if len_len > std::mem::size_of::<usize>() {
+                    return Err(ProofError::Malformed("RLP item too long"));
+                }
+                let len = len_bytes
+                    .iter()
+                    .fold(0usize, |len, b| (len << 8) | usize::from(*b));
+                (is_list, len, rest)
+            }
+        };
+        if len > rest.len() {
+            return Err(ProofError::Malformed("truncated RLP"));
+        }
+        let (payload, rest) = rest.split_at(len);
+        if !is_list {
+            return Ok((Self::Bytes(payload), rest));
+        }
+        let mut items = Vec::new();
+        let mut payload = payload;
+        while !payload.is_empty() {
+            let (item, remaining) = Self::decode_prefix(payload)?;
+            items.push(item);
+            payload = remaining;
+        }
+        Ok((Self::List(items), rest))
+    }
+
+    fn bytes(&self) -> Option<&'a [u8]> {
+        match self {
+            Self::Bytes(bytes) => Some(bytes),
+            Self::List(_) => None,
+        }
+    }
+
+    /// Encode the item
+    fn encode(&self) -> Vec<u8> {
+        match self {
+            Self::Bytes([b]) if *b < 0x80 => vec![*b],
+            Self::Bytes(bytes) => {
+                let mut out = length_prefix(0x80, bytes.len());
+                out.extend_from_slice(bytes);
+                out
+            }
+            Self::List(items) => {
+                let payload: Vec<u8> = items.iter().flat_map(Self::encode).collect();
+                let mut out = length_prefix(0xc0, payload.len());
+                out.extend(payload);
+                out
+            }
+        }
+    }
+}
+
+fn length_prefix(offset: u8, len: usize) -> Vec<u8> {
+    if len < 56 {
+        // PANIC SAFETY: `len` is less than 56
+        #[allow(clippy::cast_possible_truncation)]
+        return vec![offset + len as u8];
+    }
+    let len_bytes: Vec<u8> = len
+        .to_be_bytes()
+        .into_iter()
+        .skip_while(|b| *b == 0)
+        .collect();
+    // PANIC SAFETY: a `usize` has at most 8 bytes
+    #[allow(clippy::cast_possible_truncation)]
+    let mut out = vec![offset + 55 + len_bytes.len() as u8];
+    out.extend(len_bytes);
+    out
+}
+
+fn uint(item: &Rlp<'_>) -> Result<U256, ProofError> {
+    match item.bytes() {
+        Some(bytes) if bytes.len() <= 32 => Ok(U256::from_big_endian(bytes)),
+        _ => Err(ProofError::Malformed("expected an integer")),
+    }
+}
+
+fn hash(item: &Rlp<'_>) -> Result<[u8; 32], ProofError> {
+    item.bytes()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(ProofError::Malformed("expected a hash"))
+}
+
+fn keccak(bytes: &[u8]) -> [u8; 32] {
+    Keccak256::digest(bytes).into()
+}
+
+fn hex(s: &str) -> Result<Vec<u8>, ProofError> {
+    s.strip_prefix("0x")
+        .and_then(unhex)
+        .ok_or_else(|| ProofError::InvalidHex(s.to_string()))
+}
+
+/// A hex quantity, which may have an odd number of digits
+fn quantity(s: &str) -> Result<U256, ProofError> {
+    match s.strip_prefix("0x") {
+        Some("") => Ok(U256::zero()),
+        Some(digits) => {
+            U256::from_str_radix(digits, 16).map_err(|_| ProofError::InvalidHex(s.to_string()))
+        }
+        None => Err(ProofError::InvalidHex(s.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::audit::to_hex;
+
+    fn rlp_uint(n: U256) -> Vec<u8> {
+        let mut bytes = [0; 32];
+        n.to_big_endian(&mut bytes);
+        let start = bytes.iter().position(|b| *b != 0).unwrap_or(32);
+        Rlp::Bytes(&bytes[start..]).encode()
+    }
+
+    /// A leaf node for the nibbles `path`
+    fn leaf(path: &[u8], value: &[u8]) -> Vec<u8> {
+        let mut encoded = vec![if path.len() % 2 == 1 {
+            0x30 | path[0]
+        } else {
+            0x20
+        }];
+        let even = if path.len() % 2 == 1 {
+            &path[1..]
+        } else {
+            path
+        };
+        encoded.extend(even.chunks(2).map(|pair| (pair[0] << 4) | pair[1]));
+        Rlp::List(vec![Rlp::Bytes(&encoded), Rlp::Bytes(value)]).encode()
+    }
+
+    fn nibbles(key: &[u8; 32]) -> Vec<u8> {
+        key.iter().flat_map(|b| [b >> 4, b & 0x0f]).collect()
+    }
+
+    fn slot_path(slot: u64) -> [u8; 32] {
+        let mut key = [0; 32];
+        U256::from(slot).to_big_endian(&mut key);
+        keccak(&key)
+    }
+
+    fn hex_of(bytes: &[u8]) -> String {
+        format!("0x{}", to_hex(bytes))
+    }
+
+    struct Fixture {
+        block_hash: [u8; 32],
+        header: Vec<u8>,
+        proof: AccountProof,
+    }
+
+    /// An account holding 5 wei, whose storage has slots 0 and another slot
+    /// under a branch node
+    fn fixture() -> Fixture {
+        let (slot_a, value_a) = (0u64, U256::from(1234));
+        let slot_b = (1..)
+            .find(|slot| nibbles(&slot_path(*slot))[0] != nibbles(&slot_path(slot_a))[0])
+            .unwrap();
+        let value_b = U256::exp10(20);
+        let leaf_a = leaf(&nibbles(&slot_path(slot_a))[1..], &rlp_uint(value_a));
+        let leaf_b = leaf(&nibbles(&slot_path(slot_b))[1..], &rlp_uint(value_b));
+        let (hash_a, hash_b) = (keccak(&leaf_a), keccak(&leaf_b));
+        let mut children = vec![Rlp::Bytes(&[]); 17];
+        children[usize::from(nibbles(&slot_path(slot_a))[0])] = Rlp::Bytes(&hash_a);
+        children[usize::from(nibbles(&slot_path(slot_b))[0])] = Rlp::Bytes(&hash_b);
+        let branch = Rlp::List(children).encode();
+        let storage_root = keccak(&branch);
+
+        let address = [0x42; 20];
+        let code_hash = keccak(&[]);
+        let account = Rlp::List(vec![
+            Rlp::Bytes(&[]),
+            Rlp::Bytes(&[5]),
+            Rlp::Bytes(&storage_root),
+            Rlp::Bytes(&code_hash),
+        ])
+        .encode();
+        let account_leaf = leaf(&nibbles(&keccak(&address)), &account);
+        let state_root = keccak(&account_leaf);
+
+        let header = Rlp::List(vec![
+            Rlp::Bytes(&[0; 32]),
+            Rlp::Bytes(&[0; 32]),
+            Rlp::Bytes(&[0; 20]),
+            Rlp::Bytes(&state_root),
+        ])
+        .encode();
+        Fixture {
+            block_hash: keccak(&header),
+            header,
+            proof: AccountProof {
+                address: hex_of(&address),
+                account_proof: vec![hex_of(&account_leaf)],
+                balance: "0x5".to_string(),
+                code_hash: hex_of(&code_hash),
+                nonce: "0x0".to_string(),
+                storage_hash: hex_of(&storage_root),
+                storage_proof: vec![
+                    StorageProof {
+                        key: "0x0".to_string(),
+                        value: format!("0x{value_a:x}"),
+                        proof: vec![hex_of(&branch), hex_of(&leaf_a)],
+                    },
+                    StorageProof {
+                        key: format!("0x{slot_b:x}"),
+                        value: format!("0x{value_b:x}"),
+                        proof: vec![hex_of(&branch), hex_of(&leaf_b)],
+                    },
+                ],
+            },
+        }
+    }
+
+    #[test]
+    fn verifies() {
+        let Fixture {
+            block_hash,
+            header,
+            proof,
+        } = fixture();
+        let root = state_root(&block_hash, &header).unwrap();
+        let account = verify_account(&root, &proof).unwrap();
+        assert_eq!(account.balance, U256::from(5));
+        assert_eq!(account.storage[&U256::zero()], U256::from(1234));
+        assert_eq!(account.storage.len(), 2);
+
+        let entity = account
+            .to_entity(
+                EntityUid::from_strs("Vault", "v"),
+                &HashMap::from([("owed".to_string(), U256::zero())]),
+            )
+            .unwrap();
+        assert!(entity.attr("owed").unwrap().is_ok());
+        assert!(matches!(
+            account.to_entity(
+                EntityUid::from_strs("Vault", "v"),
+                &HashMap::from([("missing".to_string(), U256::from(99_999))]),
+            ),
+            Err(ProofError::NotProven(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_tampering() {
+        let Fixture {
+            block_hash,
+            header,
+            proof,
+        } = fixture();
+        assert_eq!(
+            state_root(&[0; 32], &header),
+            Err(ProofError::UntrustedHeader)
+        );
+        let root = state_root(&block_hash, &header).unwrap();
+
+        let mut inflated = proof.clone();
+        inflated.balance = "0x6".to_string();
+        assert_eq!(
+            verify_account(&root, &inflated),
+            Err(ProofError::ValueMismatch("balance".to_string()))
+        );
+
+        let mut forged = proof.clone();
+        forged.storage_proof[0].value = "0x1".to_string();
+        assert!(matches!(
+            verify_account(&root, &forged),
+            Err(ProofError::ValueMismatch(_))
+        ));
+
+        let mut swapped = proof;
+        swapped.storage_proof[0].proof[1] = swapped.storage_proof[1].proof[1].clone();
+        assert!(verify_account(&root, &swapped).is_err());
+        assert_eq!(
+            verify_account(&[1; 32], &fixture().proof),
+            Err(ProofError::InvalidProof)
+        );
+    }
+
+    #[test]
+    fn rlp_round_trip() {
+        let long = [7; 60];
+        let item = Rlp::List(vec![
+            Rlp::Bytes(&[]),
+            Rlp::Bytes(&[0x7f]),
+            Rlp::Bytes(&long),
+            Rlp::List(vec![Rlp::Bytes(&[0x80])]),
+        ]);
+        assert_eq!(Rlp::decode(&item.encode()).unwrap(), item);
+        assert_eq!(keccak(&Rlp::Bytes(&[]).encode()), EMPTY_TRIE_ROOT);
+    }
+}
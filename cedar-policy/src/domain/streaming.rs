@@ -0,0 +1,467 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Streaming payment intents.
+//!
+//! The classifiers here decode the calls of Superfluid and Sablier into
+//! [`Intent`]s with the same action, `stream`, and the same context, so that
+//! continuous payments can be limited by their rate, e.g.
+//! ```text
+//! forbid(principal, action == Action::"stream", resource)
+//! when {
+//!   context has totalFlowRatePerMonth &&
+//!   context.totalFlowRatePerMonth.u256GreaterThan(u256("50000000000"))
+//! };
+//! ```
+//! The context holds
+//! - `operation`: `create`, `update`, or `delete`
+//! - `token`: the token streamed
+//! - `recipient`: who receives the stream
+//! - `sender`: who pays for the stream, if the call names them
+//! - `flowRate`: the tokens streamed per second, as a `u256` value. Deleted
+//!   streams have a flow rate of 0.
+//! - `flowRatePerMonth`: the tokens streamed per 30 days, as a `u256` value
+//! - `amount`: the total streamed, as a `u256` value, for streams with an end
+//! - `duration`: how long the stream lasts, in seconds, for streams with an
+//!   end
+//! - `startTime` and `endTime`: when the stream starts and ends, in seconds
+//!   since the Unix epoch, if the call fixes them
+//! - `cancelable`: whether the sender can cancel the stream, for Sablier
+//!
+//! A classifier only sees one call, so it can't know the sender's other
+//! streams. [`with_total_outflow()`] adds `totalFlowRate` and
+//! `totalFlowRatePerMonth`, the sender's outflow once the call is made, from
+//! their current outflow. [`schema_fragment()`] declares the `stream` action
+//! with this context.
+
+use ethers::types::U256;
+use serde_json::json;
+
+use crate::domain::intent::{long_value, selector, u256_of, u256_value, Call, Intent, IntentClassifier};
+use crate::{SchemaError, SchemaFragment};
+
+/// Seconds in the 30 days of a month of flow
+const SECONDS_PER_MONTH: u64 = 30 * 24 * 60 * 60;
+
+/// The largest `int96` flow rate
+const MAX_FLOW_RATE: u128 = (1 << 95) - 1;
+
+/// The per-month amount of `flow_rate`, saturating
+fn per_month(flow_rate: U256) -> U256 {
+    flow_rate.saturating_mul(SECONDS_PER_MONTH.into())
+}
+
+/// A stream intent with the flow rate `flow_rate`
+fn stream(
+    protocol: &str,
+    operation: &str,
+    token: String,
+    recipient: String,
+    flow_rate: U256,
+) -> Intent {
+    Intent::new(protocol, "stream")
+        .with("operation", operation.into())
+        .with("token", token.into())
+        .with("recipient", recipient.into())
+        .with("flowRate", u256_value(flow_rate))
+        .with("flowRatePerMonth", u256_value(per_month(flow_rate)))
+}
+
+/// `intent` with the sender's total outflow once the call is made.
+///
+/// `totalFlowRate` and `totalFlowRatePerMonth` are computed from their current
+/// `outflow` and the flow rate of the stream the call changes, `replaced`,
+/// which is 0 for new streams. Intents other than `stream` are unchanged.
+pub fn with_total_outflow(intent: Intent, outflow: U256, replaced: U256) -> Intent {
+    let flow_rate = intent.context.get("flowRate").and_then(u256_of);
+    let Some(flow_rate) = flow_rate.filter(|_| intent.action == "stream") else {
+        return intent;
+    };
+    let total = outflow.saturating_sub(replaced).saturating_add(flow_rate);
+    intent
+        .with("totalFlowRate", u256_value(total))
+        .with("totalFlowRatePerMonth", u256_value(per_month(total)))
+}
+
+/// Superfluid constant flows through the `CFAv1Forwarder`.
+///
+/// Decodes `createFlow`, `updateFlow`, `deleteFlow`, and `setFlowrate`, which
+/// creates or updates a flow from the caller, or deletes it with a flow rate
+/// of 0. Flows have no end.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Superfluid;
+
+impl IntentClassifier for Superfluid {
+    fn classify(&self, call: &Call) -> Option<Intent> {
+        let data = call.calldata()?;
+        let selector = data.selector();
+        // flow rates are `int96`, and negative ones are invalid
+        let flow_rate = |i| {
+            data.uint(i)
+                .filter(|rate| *rate <= U256::from(MAX_FLOW_RATE))
+        };
+        let (operation, sender, recipient, flow_rate) = if selector
+            == self::selector("createFlow(address,address,address,int96,bytes)")
+        {
+            (
+                "create",
+                Some(data.address(1)?),
+                data.address(2)?,
+                flow_rate(3)?,
+            )
+        } else if selector == self::selector("updateFlow(address,address,address,int96,bytes)") {
+            (
+                "update",
+                Some(data.address(1)?),
+                data.address(2)?,
+                flow_rate(3)?,
+            )
+        } else if selector == self::selector("deleteFlow(address,address,address,bytes)") {
+            (
+                "delete",
+                Some(data.address(1)?),
+                data.address(2)?,
+                U256::zero(),
+            )
+        } else if selector == self::selector("setFlowrate(address,address,int96)") {
+            let rate = flow_rate(2)?;
+            let operation = if rate.is_zero() { "delete" } else { "update" };
+            (operation, None, data.address(1)?, rate)
+        } else {
+            return None;
+        };
+        let mut intent = stream(
+            "superfluid",
+            operation,
+            data.address(0)?,
+            recipient,
+            flow_rate,
+        );
+        if let Some(sender) = sender {
+            intent = intent.with("sender", sender.into());
+        }
+        Some(intent)
+    }
+}
+
+/// Sablier V2 linear lockup streams.
+///
+/// Decodes `createWithDurations` and `createWithTimestamps` on
+/// `SablierV2LockupLinear`. The flow rate is the amount over the duration,
+/// rounded down, ignoring the cliff and any broker fee.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sablier;
+
+impl IntentClassifier for Sablier {
+    fn classify(&self, call: &Call) -> Option<Intent> {
+        let data = call.calldata()?;
+        let selector = data.selector();
+        let (duration, times) = if selector
+            == self::selector(
+                "createWithDurations((address,address,uint128,address,bool,bool,(uint40,uint40),(address,uint256)))",
+            ) {
+            (data.uint(7)?, None)
+        } else if selector
+            == self::selector(
+                "createWithTimestamps((address,address,uint128,address,bool,bool,(uint40,uint40,uint40),(address,uint256)))",
+            )
+        {
+            let (start, end) = (data.uint(6)?, data.uint(8)?);
+            (end.checked_sub(start)?, Some((start, end)))
+        } else {
+            return None;
+        };
+        if duration.is_zero() {
+            return None;
+        }
+        let amount = data.uint(2)?;
+        let mut intent = stream(
+            "sablier",
+            "create",
+            data.address(3)?,
+            data.address(1)?,
+            amount / duration,
+        )
+        .with("sender", data.address(0)?.into())
+        .with("amount", u256_value(amount))
+        .with("duration", long_value(duration)?)
+        .with("cancelable", data.bool(4)?.into());
+        if let Some((start, end)) = times {
+            intent = intent
+                .with("startTime", long_value(start)?)
+                .with("endTime", long_value(end)?);
+        }
+        Some(intent)
+    }
+}
+
+/// A schema fragment declaring the `stream` action.
+///
+/// The action applies to principals of `principal_types` and resources of
+/// `resource_types`, with the context of stream intents. The entity types
+/// must be declared by another fragment.
+pub fn schema_fragment(
+    principal_types: &[&str],
+    resource_types: &[&str],
+) -> Result<SchemaFragment, SchemaError> {
+    let string = json!({ "type": "String" });
+    let optional_string = json!({ "type": "String", "required": false });
+    let optional_long = json!({ "type": "Long", "required": false });
+    let u256 = json!({ "type": "Extension", "name": "u256" });
+    let optional_u256 = json!({ "type": "Extension", "name": "u256", "required": false });
+    SchemaFragment::from_json_value(json!({
+        "": {
+            "entityTypes": {},
+            "actions": {
+                "stream": {
+                    "appliesTo": {
+                        "principalTypes": principal_types,
+                        "resourceTypes": resource_types,
+                        "context": {
+                            "type": "Record",
+                            "attributes": {
+                                "protocol": string,
+                                "operation": string,
+                                "token": string,
+                                "recipient": string,
+                                "sender": optional_string,
+                                "flowRate": u256,
+                                "flowRatePerMonth": u256,
+                                "amount": optional_u256,
+                                "duration": optional_long,
+                                "startTime": optional_long,
+                                "endTime": optional_long,
+                                "cancelable": { "type": "Boolean", "required": false },
+                                "totalFlowRate": optional_u256,
+                                "totalFlowRatePerMonth": optional_u256,
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::audit::to_hex;
+    use crate::domain::intent::IntentRegistry;
+    use crate::{
+        Authorizer, Decision, Entities, EntityUid, PolicySet, Request, Schema, ValidationMode,
+        Validator,
+    };
+    use std::str::FromStr;
+
+    const FORWARDER: &str = "0xcfa132e353cb4e398080b9700609bb008eceb125";
+    const USDCX: &str = "0x1ba8603da702602a8657980e825a6daa03dee93a";
+    const USDC: &str = "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48";
+    const ALICE: &str = "0x00000000000000000000000000000000000a11ce";
+    const BOB: &str = "0x0000000000000000000000000000000000000b0b";
+
+    fn word(n: impl Into<U256>) -> String {
+        let mut bytes = [0; 32];
+        n.into().to_big_endian(&mut bytes);
+        to_hex(&bytes)
+    }
+
+    fn address_word(address: &str) -> String {
+        format!("{:0>64}", &address[2..])
+    }
+
+    fn encode(signature: &str, words: &[String]) -> String {
+        format!("0x{}{}", to_hex(&selector(signature)), words.concat())
+    }
+
+    /// A Superfluid `createFlow` of `flow_rate` per second from Alice to Bob
+    fn create_flow(flow_rate: impl Into<U256>) -> Call {
+        Call::new(
+            FORWARDER,
+            encode(
+                "createFlow(address,address,address,int96,bytes)",
+                &[
+                    address_word(USDCX),
+                    address_word(ALICE),
+                    address_word(BOB),
+                    word(flow_rate),
+                    word(5 * 32),
+                    word(0),
+                ],
+            ),
+        )
+    }
+
+    #[test]
+    fn superfluid() {
+        // 1000 USDCx (18 decimals) a month
+        let rate = U256::exp10(21) / SECONDS_PER_MONTH;
+        let intent = IntentRegistry::new().classify(&create_flow(rate)).unwrap();
+        assert_eq!(
+            (intent.protocol.as_str(), intent.action.as_str()),
+            ("superfluid", "stream")
+        );
+        assert_eq!(intent.context["operation"], "create");
+        assert_eq!(intent.context["token"], USDCX);
+        assert_eq!(intent.context["sender"], ALICE);
+        assert_eq!(intent.context["recipient"], BOB);
+        assert_eq!(intent.context["flowRate"], u256_value(rate));
+        assert_eq!(
+            intent.context["flowRatePerMonth"],
+            u256_value(rate * SECONDS_PER_MONTH)
+        );
+        assert!(!intent.context.contains_key("endTime"));
+
+        let set = |rate: u64| {
+            let call = Call::new(
+                FORWARDER,
+                encode(
+                    "setFlowrate(address,address,int96)",
+                    &[address_word(USDCX), address_word(BOB), word(rate)],
+                ),
+            );
+            Superfluid.classify(&call).unwrap()
+        };
+        assert_eq!(set(5).context["operation"], "update");
+        assert!(!set(5).context.contains_key("sender"));
+        assert_eq!(set(0).context["operation"], "delete");
+
+        // negative flow rates are invalid
+        assert_eq!(Superfluid.classify(&create_flow(U256::MAX)), None);
+    }
+
+    #[test]
+    fn sablier() {
+        let call = Call::new(
+            "0x3962f6585946823440d274ad7c719b02b49de51e",
+            encode(
+                "createWithTimestamps((address,address,uint128,address,bool,bool,(uint40,uint40,uint40),(address,uint256)))",
+                &[
+                    address_word(ALICE),
+                    address_word(BOB),
+                    word(1_000_000_000),
+                    address_word(USDC),
+                    word(1),
+                    word(0),
+                    word(1_700_000_000),
+                    word(1_700_086_400),
+                    word(1_700_000_000 + 10 * 86_400),
+                    address_word("0x0000000000000000000000000000000000000000"),
+                    word(0),
+                ],
+            ),
+        );
+        let intent = IntentRegistry::new().classify(&call).unwrap();
+        assert_eq!(intent.protocol, "sablier");
+        assert_eq!(intent.context["operation"], "create");
+        assert_eq!(intent.context["token"], USDC);
+        assert_eq!(intent.context["amount"], u256_value(1_000_000_000.into()));
+        assert_eq!(intent.context["duration"], 864_000);
+        assert_eq!(intent.context["startTime"], 1_700_000_000);
+        assert_eq!(intent.context["endTime"], 1_700_864_000);
+        assert_eq!(intent.context["cancelable"], true);
+        assert_eq!(intent.context["flowRate"], u256_value(1157.into()));
+
+        let durations = Call::new(
+            "0x3962f6585946823440d274ad7c719b02b49de51e",
+            encode(
+                "createWithDurations((address,address,uint128,address,bool,bool,(uint40,uint40),(address,uint256)))",
+                &[
+                    address_word(ALICE),
+                    address_word(BOB),
+                    word(3_000),
+                    address_word(USDC),
+                    word(0),
+                    word(1),
+                    word(0),
+                    word(1_000),
+                    address_word("0x0000000000000000000000000000000000000000"),
+                    word(0),
+                ],
+            ),
+        );
+        let intent = Sablier.classify(&durations).unwrap();
+        assert_eq!(intent.context["flowRate"], u256_value(3.into()));
+        assert_eq!(intent.context["duration"], 1_000);
+        assert!(!intent.context.contains_key("endTime"));
+    }
+
+    #[test]
+    fn outflow_limits() {
+        let policies = PolicySet::from_str(
+            r#"permit(principal, action, resource);
+               forbid(principal, action == Action::"stream", resource)
+               unless {
+                 context has totalFlowRatePerMonth &&
+                 context.totalFlowRatePerMonth.u256LessThanOrEqual(u256("2000000000000000000000"))
+               };"#,
+        )
+        .unwrap();
+        let schema = Schema::from_schema_fragments([
+            schema_fragment(&["User"], &["Wallet"]).unwrap(),
+            SchemaFragment::from_json_value(json!({
+                "": { "entityTypes": { "User": {}, "Wallet": {} }, "actions": {} }
+            }))
+            .unwrap(),
+        ])
+        .unwrap();
+        let validator = Validator::new(schema);
+        assert!(validator
+            .validate(&policies, ValidationMode::default())
+            .validation_passed());
+
+        let decide = |intent: Intent| {
+            let request = Request::new(
+                Some(EntityUid::from_strs("User", "alice")),
+                Some(intent.action_uid()),
+                Some(EntityUid::from_strs("Wallet", "treasury")),
+                intent.to_context().unwrap(),
+            );
+            Authorizer::new()
+                .is_authorized(&request, &policies, &Entities::empty())
+                .decision()
+        };
+        // 1000 a month, on top of 900 a month of other streams
+        let month = |n: u64| U256::exp10(18) * n / SECONDS_PER_MONTH;
+        let stream = Superfluid.classify(&create_flow(month(1000))).unwrap();
+        assert_eq!(
+            decide(with_total_outflow(stream.clone(), month(900), U256::zero())),
+            Decision::Allow
+        );
+        // 1000 a month, on top of 1500 a month, of which 500 are replaced
+        assert_eq!(
+            decide(with_total_outflow(stream.clone(), month(1500), month(500))),
+            Decision::Allow
+        );
+        assert_eq!(
+            decide(with_total_outflow(
+                stream.clone(),
+                month(1500),
+                U256::zero()
+            )),
+            Decision::Deny
+        );
+        // without the sender's outflow
+        assert_eq!(decide(stream), Decision::Deny);
+
+        // other intents are unchanged
+        let bridge = Intent::new("optimism", "bridge");
+        assert_eq!(
+            with_total_outflow(bridge.clone(), month(1), U256::zero()),
+            bridge
+        );
+    }
+}
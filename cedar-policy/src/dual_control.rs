@@ -0,0 +1,479 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Dual control: decisions which need a second approver.
+//!
+//! Under the two-person rule, some requests are only allowed once someone
+//! other than the requester agrees. A `permit` policy annotated with
+//! `@requireSecondApprover`, naming the group the second approver must be
+//! in, e.g.
+//!
+//! ```text
+//! @requireSecondApprover("Group::\"risk\"")
+//! permit(principal, action == Action::"withdraw", resource);
+//! ```
+//!
+//! doesn't allow a request immediately: [`DualControl::authorize()`] records
+//! it and returns a [`DualControlDecision::PendingSecondApproval`]. A member
+//! of the group other than the requester then calls
+//! [`DualControl::approve()`] or [`DualControl::deny()`], which gives the
+//! final [`DecisionReceipt`].
+//!
+//! Pending requests are kept in an [`ApprovalStore`], so that they can be
+//! shared or persisted; [`MemoryApprovalStore`] keeps them in memory.
+
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::audit::{policy_set_digest, request_digest, unix_seconds};
+use crate::receipt::{DecisionReceipt, RECEIPT_VERSION};
+use crate::{
+    Authorizer, Decision, Effect, Entities, EntityUid, PolicyId, PolicySet, Request, Response,
+};
+
+/// The annotation requiring a second approver for the `Allow` decisions of a
+/// `permit` policy. Its value is the group the approver must be in, e.g.
+/// `@requireSecondApprover("Group::\"risk\"")`.
+pub const DUAL_CONTROL_ANNOTATION: &str = "requireSecondApprover";
+
+/// A request allowed by the policies, waiting for a second approver
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingApproval {
+    /// The hex-encoded digest of the request, identifying it
+    pub id: String,
+    /// The hex-encoded digest of the policy set which allowed it
+    pub policy_set_hash: String,
+    /// The request's principal, who can't approve it
+    pub requester: Option<String>,
+    /// The groups the second approver may be in, any one of which will do,
+    /// sorted
+    pub approver_groups: Vec<String>,
+    /// Ids of the policies which allowed it, sorted
+    pub determining_policies: Vec<String>,
+    /// When it was requested, in seconds since the Unix epoch
+    pub requested_at: u64,
+}
+
+/// An error from an [`ApprovalStore`]
+#[derive(Debug, Error)]
+#[error("approval store error: {0}")]
+pub struct ApprovalStoreError(pub String);
+
+/// Where a [`DualControl`] keeps the pending requests. Each method must
+/// check and update atomically, so that a request can't be approved twice.
+pub trait ApprovalStore: Debug + Send + Sync {
+    /// Record `pending` unless a request with the same id is already
+    /// pending, returning the recorded one
+    fn insert(&self, pending: PendingApproval) -> Result<PendingApproval, ApprovalStoreError>;
+
+    /// The pending request with `id`, if any
+    fn get(&self, id: &str) -> Result<Option<PendingApproval>, ApprovalStoreError>;
+
+    /// Remove the pending request with `id`, returning it
+    fn remove(&self, id: &str) -> Result<Option<PendingApproval>, ApprovalStoreError>;
+
+    /// All pending requests
+    fn pending(&self) -> Result<Vec<PendingApproval>, ApprovalStoreError>;
+}
+
+/// An [`ApprovalStore`] in memory
+#[derive(Debug, Default)]
+pub struct MemoryApprovalStore {
+    pending: Mutex<BTreeMap<String, PendingApproval>>,
+}
+
+impl MemoryApprovalStore {
+    /// An empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ApprovalStore for MemoryApprovalStore {
+    fn insert(&self, pending: PendingApproval) -> Result<PendingApproval, ApprovalStoreError> {
+        Ok(self
+            .pending
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(pending.id.clone())
+            .or_insert(pending)
+            .clone())
+    }
+
+    fn get(&self, id: &str) -> Result<Option<PendingApproval>, ApprovalStoreError> {
+        Ok(self
+            .pending
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(id)
+            .cloned())
+    }
+
+    fn remove(&self, id: &str) -> Result<Option<PendingApproval>, ApprovalStoreError> {
+        Ok(self
+            .pending
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(id))
+    }
+
+    fn pending(&self) -> Result<Vec<PendingApproval>, ApprovalStoreError> {
+        Ok(self
+            .pending
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .values()
+            .cloned()
+            .collect())
+    }
+}
+
+/// Errors authorizing, approving, or denying requests under dual control
+#[derive(Debug, Error)]
+pub enum DualControlError {
+    /// A determining policy's `@requireSecondApprover` annotation isn't an
+    /// entity UID
+    #[error("policy `{policy}` has a malformed `@requireSecondApprover` annotation `{value}`")]
+    InvalidAnnotation {
+        /// The policy's id
+        policy: String,
+        /// The annotation value
+        value: String,
+    },
+    /// No request with the id is pending
+    #[error("no request `{0}` is pending")]
+    Unknown(String),
+    /// The approver is the requester
+    #[error("`{0}` can't approve their own request")]
+    SelfApproval(String),
+    /// The approver isn't in any of the required groups
+    #[error("`{0}` isn't in a group which can approve the request")]
+    NotAnApprover(String),
+    /// The store failed
+    #[error(transparent)]
+    Store(#[from] ApprovalStoreError),
+}
+
+/// The decision for a request which may need a second approver
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DualControlDecision {
+    /// The request was decided immediately
+    Decided(Response),
+    /// The request was allowed, but only by policies requiring a second
+    /// approver, and waits for one
+    PendingSecondApproval(PendingApproval),
+}
+
+/// Holds requests allowed by policies requiring a second approver until one
+/// approves or denies them
+#[derive(Debug, Clone)]
+pub struct DualControl {
+    store: Arc<dyn ApprovalStore>,
+}
+
+impl DualControl {
+    /// Dual control keeping pending requests in `store`
+    pub fn new(store: Arc<dyn ApprovalStore>) -> Self {
+        Self { store }
+    }
+
+    /// Decide `request` with `authorizer` at `time`. An `Allow` is returned
+    /// immediately if any determining policy has no `@requireSecondApprover`
+    /// annotation; otherwise the request waits for a member of any of the
+    /// annotations' groups. Authorizing a request which is already pending
+    /// returns the pending request.
+    pub fn authorize(
+        &self,
+        authorizer: &Authorizer,
+        request: &Request,
+        policies: &PolicySet,
+        entities: &Entities,
+        time: SystemTime,
+    ) -> Result<DualControlDecision, DualControlError> {
+        let response = authorizer.is_authorized(request, policies, entities);
+        if response.decision() != Decision::Allow {
+            return Ok(DualControlDecision::Decided(response));
+        }
+        let Some(approver_groups) = approver_groups(&response, policies)? else {
+            return Ok(DualControlDecision::Decided(response));
+        };
+        let mut determining_policies: Vec<_> = response
+            .diagnostics()
+            .reason()
+            .map(ToString::to_string)
+            .collect();
+        determining_policies.sort();
+        let pending = self.store.insert(PendingApproval {
+            id: request_digest(request),
+            policy_set_hash: policy_set_digest(policies),
+            requester: request.principal().map(ToString::to_string),
+            approver_groups,
+            determining_policies,
+            requested_at: unix_seconds(time),
+        })?;
+        Ok(DualControlDecision::PendingSecondApproval(pending))
+    }
+
+    /// Approve the pending request with `id` as `approver`, whose groups are
+    /// in `entities`, at `time`, giving the final `Allow` receipt
+    pub fn approve(
+        &self,
+        id: &str,
+        approver: &EntityUid,
+        entities: &Entities,
+        time: SystemTime,
+    ) -> Result<DecisionReceipt, DualControlError> {
+        self.decide(id, approver, entities, time, Decision::Allow)
+    }
+
+    /// Deny the pending request with `id` as `approver`, whose groups are in
+    /// `entities`, at `time`, giving the final `Deny` receipt
+    pub fn deny(
+        &self,
+        id: &str,
+        approver: &EntityUid,
+        entities: &Entities,
+        time: SystemTime,
+    ) -> Result<DecisionReceipt, DualControlError> {
+        self.decide(id, approver, entities, time, Decision::Deny)
+    }
+
+    /// All pending requests
+    pub fn pending(&self) -> Result<Vec<PendingApproval>, DualControlError> {
+        Ok(self.store.pending()?)
+    }
+
+    fn decide(
+        &self,
+        id: &str,
+        approver: &EntityUid,
+        entities: &Entities,
+        time: SystemTime,
+        decision: Decision,
+    ) -> Result<DecisionReceipt, DualControlError> {
+        let pending = self
+            .store
+            .get(id)?
+            .ok_or_else(|| DualControlError::Unknown(id.to_string()))?;
+        let name = approver.to_string();
+        if pending.requester.as_ref() == Some(&name) {
+            return Err(DualControlError::SelfApproval(name));
+        }
+        let in_group = pending.approver_groups.iter().any(|group| {
+            EntityUid::from_str(group).is_ok_and(|group| entities.is_ancestor_of(&group, approver))
+        });
+        if !in_group {
+            return Err(DualControlError::NotAnApprover(name));
+        }
+        // another approver may have decided it in the meantime
+        let pending = self
+            .store
+            .remove(id)?
+            .ok_or_else(|| DualControlError::Unknown(id.to_string()))?;
+        Ok(DecisionReceipt {
+            version: RECEIPT_VERSION,
+            request_hash: pending.id,
+            policy_set_hash: pending.policy_set_hash,
+            decision,
+            determining_policies: pending.determining_policies,
+            timestamp: unix_seconds(time),
+        })
+    }
+}
+
+/// The groups which can approve an `Allow` in `response`: `None` if a
+/// determining `permit` policy doesn't require a second approver, and
+/// otherwise the groups named by their annotations
+fn approver_groups(
+    response: &Response,
+    policies: &PolicySet,
+) -> Result<Option<Vec<String>>, DualControlError> {
+    let mut groups = Vec::new();
+    for id in response.diagnostics().reason() {
+        let Some(policy) = policies.policy(id) else {
+            continue;
+        };
+        if policy.effect() != Effect::Permit {
+            continue;
+        }
+        let Some(value) = policy.annotation(DUAL_CONTROL_ANNOTATION) else {
+            return Ok(None);
+        };
+        let group = EntityUid::from_str(value).map_err(|_| invalid(id, value))?;
+        groups.push(group.to_string());
+    }
+    groups.sort();
+    groups.dedup();
+    Ok((!groups.is_empty()).then_some(groups))
+}
+
+fn invalid(id: &PolicyId, value: &str) -> DualControlError {
+    DualControlError::InvalidAnnotation {
+        policy: id.to_string(),
+        value: value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::receipt::LocalSigner;
+    use crate::Context;
+    use k256::ecdsa::SigningKey;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn request(principal: &str, action: &str) -> Request {
+        Request::new(
+            Some(EntityUid::from_strs("User", principal)),
+            Some(EntityUid::from_strs("Action", action)),
+            Some(EntityUid::from_strs("Vault", "v")),
+            Context::empty(),
+        )
+    }
+
+    fn policies() -> PolicySet {
+        PolicySet::from_str(
+            r#"permit(principal, action == Action::"deposit", resource);
+               @requireSecondApprover("Group::\"risk\"")
+               permit(principal, action == Action::"withdraw", resource);
+               @requireSecondApprover("risk")
+               permit(principal, action == Action::"pause", resource);"#,
+        )
+        .unwrap()
+    }
+
+    fn entities() -> Entities {
+        Entities::from_json_str(
+            r#"[
+                {"uid": {"type": "User", "id": "alice"}, "attrs": {},
+                 "parents": [{"type": "Group", "id": "risk"}]},
+                {"uid": {"type": "User", "id": "bob"}, "attrs": {},
+                 "parents": [{"type": "Group", "id": "risk"}]},
+                {"uid": {"type": "User", "id": "carol"}, "attrs": {}, "parents": []},
+                {"uid": {"type": "Group", "id": "risk"}, "attrs": {}, "parents": []}
+            ]"#,
+            None,
+        )
+        .unwrap()
+    }
+
+    fn at(seconds: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    fn pending(control: &DualControl, request: &Request) -> PendingApproval {
+        let decision = control
+            .authorize(
+                &Authorizer::new(),
+                request,
+                &policies(),
+                &entities(),
+                at(1000),
+            )
+            .unwrap();
+        let DualControlDecision::PendingSecondApproval(pending) = decision else {
+            panic!("expected a pending request");
+        };
+        pending
+    }
+
+    #[test]
+    fn second_approver_decides() {
+        let control = DualControl::new(Arc::new(MemoryApprovalStore::new()));
+        let decision = control
+            .authorize(
+                &Authorizer::new(),
+                &request("alice", "deposit"),
+                &policies(),
+                &entities(),
+                at(1000),
+            )
+            .unwrap();
+        assert!(
+            matches!(decision, DualControlDecision::Decided(response) if response.decision() == Decision::Allow)
+        );
+
+        let withdraw = request("alice", "withdraw");
+        let pending = pending(&control, &withdraw);
+        assert_eq!(pending.id, request_digest(&withdraw));
+        assert_eq!(pending.requester.as_deref(), Some(r#"User::"alice""#));
+        assert_eq!(pending.approver_groups, [r#"Group::"risk""#]);
+        assert_eq!(control.pending().unwrap().len(), 1);
+
+        let alice = EntityUid::from_strs("User", "alice");
+        let bob = EntityUid::from_strs("User", "bob");
+        let carol = EntityUid::from_strs("User", "carol");
+        assert!(matches!(
+            control.approve(&pending.id, &alice, &entities(), at(1100)),
+            Err(DualControlError::SelfApproval(_))
+        ));
+        assert!(matches!(
+            control.approve(&pending.id, &carol, &entities(), at(1100)),
+            Err(DualControlError::NotAnApprover(_))
+        ));
+        let receipt = control
+            .approve(&pending.id, &bob, &entities(), at(1100))
+            .unwrap();
+        assert_eq!(receipt.decision, Decision::Allow);
+        assert_eq!(receipt.request_hash, pending.id);
+        assert_eq!(receipt.policy_set_hash, policy_set_digest(&policies()));
+        assert_eq!(receipt.determining_policies, ["policy1"]);
+        assert_eq!(receipt.timestamp, 1100);
+        let signer = LocalSigner::new(SigningKey::from_slice(&[1; 32]).unwrap(), "node");
+        let signed_receipt = receipt.sign(&signer).unwrap();
+        signed_receipt.verify(&signer.verifying_key()).unwrap();
+
+        // it can't be decided twice
+        assert!(matches!(
+            control.deny(&pending.id, &bob, &entities(), at(1200)),
+            Err(DualControlError::Unknown(_))
+        ));
+    }
+
+    #[test]
+    fn deny_and_malformed() {
+        let control = DualControl::new(Arc::new(MemoryApprovalStore::new()));
+        let pending = pending(&control, &request("carol", "withdraw"));
+        let receipt = control
+            .deny(
+                &pending.id,
+                &EntityUid::from_strs("User", "alice"),
+                &entities(),
+                at(1100),
+            )
+            .unwrap();
+        assert_eq!(receipt.decision, Decision::Deny);
+        assert!(control.pending().unwrap().is_empty());
+
+        assert!(matches!(
+            control.authorize(
+                &Authorizer::new(),
+                &request("carol", "pause"),
+                &policies(),
+                &entities(),
+                at(1000)
+            ),
+            Err(DualControlError::InvalidAnnotation { policy, value }) if policy == "policy2" && value == "risk"
+        ));
+    }
+}
@@ -0,0 +1,491 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Envelope encryption of policy artifacts at rest.
+//!
+//! Policy text often encodes limits which shouldn't be stored in plaintext,
+//! e.g. in object storage. An [`Envelope`] holds an artifact, such as a
+//! [`SealedPolicySet`](crate::sealed::SealedPolicySet), encrypted with
+//! AES-256-GCM under a fresh data key, and that data key encrypted ("wrapped")
+//! by a key encryption key which never leaves a [`KeyWrapper`], typically a
+//! KMS. [`LocalKeyWrapper`] wraps data keys with a key held in memory, and
+//! with the `aws-kms` feature, `KmsKeyWrapper` has AWS KMS generate and
+//! decrypt them.
+//!
+//! Each envelope names the kind of artifact it holds, e.g.
+//! [`SEALED_CONTENT_TYPE`](crate::sealed::SEALED_CONTENT_TYPE), and the key
+//! which wrapped its data key. Both are authenticated along with the
+//! ciphertext, so an envelope can't be opened as another kind of artifact.
+
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+use crate::audit::{to_hex, unhex};
+
+/// The version of the envelope format
+pub const ENVELOPE_VERSION: u32 = 1;
+
+/// The algorithm envelopes are encrypted with
+pub const ENVELOPE_ALGORITHM: &str = "AES-256-GCM";
+
+/// The length of data keys, and of the keys of a [`LocalKeyWrapper`]
+pub const KEY_LEN: usize = 32;
+
+/// Errors encrypting or decrypting envelopes
+#[derive(Debug, Error)]
+pub enum EnvelopeError {
+    /// The envelope is in a format this version doesn't understand
+    #[error("unsupported envelope version {0}")]
+    UnsupportedVersion(u32),
+    /// The envelope is encrypted with an algorithm this version doesn't
+    /// understand
+    #[error("unsupported envelope algorithm `{0}`")]
+    UnsupportedAlgorithm(String),
+    /// The envelope holds another kind of artifact
+    #[error("expected an envelope of `{expected}`, found `{found}`")]
+    ContentType {
+        /// The kind of artifact expected
+        expected: String,
+        /// The kind of artifact in the envelope
+        found: String,
+    },
+    /// The key wrapper doesn't hold the key which wrapped the data key
+    #[error("unknown key encryption key `{0}`")]
+    UnknownKey(String),
+    /// Wrapping or unwrapping the data key failed
+    #[error("failed to wrap or unwrap the data key: {0}")]
+    KeyWrapping(String),
+    /// A field of the envelope isn't valid hex, or has the wrong length
+    #[error("malformed envelope: invalid `{0}`")]
+    Malformed(&'static str),
+    /// The ciphertext, or what it's authenticated with, was altered, or the
+    /// data key is wrong
+    #[error("the envelope doesn't decrypt")]
+    Decryption,
+    /// Encrypting the artifact failed
+    #[error("failed to encrypt")]
+    Encryption,
+    /// Generating a data key or nonce failed
+    #[error("failed to generate random bytes")]
+    Random,
+    /// The JSON is malformed
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+/// Wraps and unwraps data keys with a key encryption key, e.g. one held in a
+/// KMS
+pub trait KeyWrapper {
+    /// Identifies the key encryption key, recorded in each envelope
+    fn key_id(&self) -> String;
+
+    /// Encrypt the data key `key`
+    fn wrap_key(&self, key: &[u8]) -> Result<Vec<u8>, EnvelopeError>;
+
+    /// Decrypt the data key `wrapped`, which was wrapped by the key `key_id`
+    fn unwrap_key(&self, key_id: &str, wrapped: &[u8]) -> Result<Vec<u8>, EnvelopeError>;
+}
+
+/// Wraps data keys with an AES-256-GCM key held in memory
+#[derive(Clone)]
+pub struct LocalKeyWrapper {
+    key: Zeroizing<[u8; KEY_LEN]>,
+    key_id: String,
+}
+
+impl std::fmt::Debug for LocalKeyWrapper {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LocalKeyWrapper")
+            .field("key_id", &self.key_id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl LocalKeyWrapper {
+    /// Wrap with `key`, identified in envelopes by `key_id`
+    pub fn new(key: [u8; KEY_LEN], key_id: impl Into<String>) -> Self {
+        Self {
+            key: Zeroizing::new(key),
+            key_id: key_id.into(),
+        }
+    }
+}
+
+impl KeyWrapper for LocalKeyWrapper {
+    fn key_id(&self) -> String {
+        self.key_id.clone()
+    }
+
+    fn wrap_key(&self, key: &[u8]) -> Result<Vec<u8>, EnvelopeError> {
+        let (nonce, mut wrapped) = encrypt(self.key.as_ref(), self.key_id.as_bytes(), key)?;
+        wrapped.splice(0..0, nonce);
+        Ok(wrapped)
+    }
+
+    fn unwrap_key(&self, key_id: &str, wrapped: &[u8]) -> Result<Vec<u8>, EnvelopeError> {
+        if key_id != self.key_id {
+            return Err(EnvelopeError::UnknownKey(key_id.to_string()));
+        }
+        let (nonce, wrapped) = wrapped
+            .split_first_chunk::<NONCE_LEN>()
+            .ok_or(EnvelopeError::Malformed("wrappedKey"))?;
+        decrypt(self.key.as_ref(), nonce, key_id.as_bytes(), wrapped)
+    }
+}
+
+/// An artifact encrypted under a data key, with the data key wrapped by a
+/// [`KeyWrapper`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Envelope {
+    version: u32,
+    algorithm: String,
+    content_type: String,
+    key_id: String,
+    wrapped_key: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+impl Envelope {
+    /// Encrypt `plaintext`, an artifact of kind `content_type`, under a new
+    /// data key wrapped by `wrapper`
+    pub fn seal(
+        content_type: impl Into<String>,
+        plaintext: &[u8],
+        wrapper: &dyn KeyWrapper,
+    ) -> Result<Self, EnvelopeError> {
+        let mut key = Zeroizing::new([0; KEY_LEN]);
+        SystemRandom::new()
+            .fill(key.as_mut())
+            .map_err(|_| EnvelopeError::Random)?;
+        let wrapped_key = wrapper.wrap_key(key.as_ref())?;
+        Self::seal_with_key(
+            content_type.into(),
+            wrapper.key_id(),
+            key.as_ref(),
+            &wrapped_key,
+            plaintext,
+        )
+    }
+
+    fn seal_with_key(
+        content_type: String,
+        key_id: String,
+        key: &[u8],
+        wrapped_key: &[u8],
+        plaintext: &[u8],
+    ) -> Result<Self, EnvelopeError> {
+        let mut envelope = Self {
+            version: ENVELOPE_VERSION,
+            algorithm: ENVELOPE_ALGORITHM.to_string(),
+            content_type,
+            key_id,
+            wrapped_key: to_hex(wrapped_key),
+            nonce: String::new(),
+            ciphertext: String::new(),
+        };
+        let (nonce, ciphertext) = encrypt(key, &envelope.aad(), plaintext)?;
+        envelope.nonce = to_hex(&nonce);
+        envelope.ciphertext = to_hex(&ciphertext);
+        Ok(envelope)
+    }
+
+    /// Decrypt the artifact, which must be of kind `content_type`, with the
+    /// data key unwrapped by `wrapper`
+    pub fn open(
+        &self,
+        content_type: &str,
+        wrapper: &dyn KeyWrapper,
+    ) -> Result<Vec<u8>, EnvelopeError> {
+        self.check(content_type)?;
+        let wrapped_key = unhex(&self.wrapped_key).ok_or(EnvelopeError::Malformed("wrappedKey"))?;
+        let key = Zeroizing::new(wrapper.unwrap_key(&self.key_id, &wrapped_key)?);
+        self.open_with_key(&key)
+    }
+
+    /// Check that the envelope holds an artifact of kind `content_type`, in a
+    /// format this version understands
+    fn check(&self, content_type: &str) -> Result<(), EnvelopeError> {
+        if self.version != ENVELOPE_VERSION {
+            return Err(EnvelopeError::UnsupportedVersion(self.version));
+        }
+        if self.algorithm != ENVELOPE_ALGORITHM {
+            return Err(EnvelopeError::UnsupportedAlgorithm(self.algorithm.clone()));
+        }
+        if self.content_type != content_type {
+            return Err(EnvelopeError::ContentType {
+                expected: content_type.to_string(),
+                found: self.content_type.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    fn open_with_key(&self, key: &[u8]) -> Result<Vec<u8>, EnvelopeError> {
+        let nonce = unhex(&self.nonce)
+            .and_then(|nonce| <[u8; NONCE_LEN]>::try_from(nonce).ok())
+            .ok_or(EnvelopeError::Malformed("nonce"))?;
+        let ciphertext = unhex(&self.ciphertext).ok_or(EnvelopeError::Malformed("ciphertext"))?;
+        decrypt(key, &nonce, &self.aad(), &ciphertext)
+    }
+
+    /// What the ciphertext is authenticated with: every field but the
+    /// wrapped key, which its key encryption key authenticates, and the
+    /// nonce and ciphertext themselves
+    fn aad(&self) -> Vec<u8> {
+        [
+            self.version.to_string().as_str(),
+            &self.algorithm,
+            &self.content_type,
+            &self.key_id,
+        ]
+        .map(|field| format!("{}:{field}", field.len()))
+        .concat()
+        .into_bytes()
+    }
+
+    /// The kind of artifact in the envelope
+    pub fn content_type(&self) -> &str {
+        &self.content_type
+    }
+
+    /// The key which wrapped the data key
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    /// Serialize as JSON
+    pub fn to_json(&self) -> Result<Vec<u8>, EnvelopeError> {
+        Ok(serde_json::to_vec(self)?)
+    }
+
+    /// Deserialize from JSON
+    pub fn from_json(json: &[u8]) -> Result<Self, EnvelopeError> {
+        Ok(serde_json::from_slice(json)?)
+    }
+}
+
+/// Encrypt `plaintext` with AES-256-GCM under `key` and a random nonce,
+/// returning the nonce and the ciphertext with its tag
+fn encrypt(
+    key: &[u8],
+    aad: &[u8],
+    plaintext: &[u8],
+) -> Result<([u8; NONCE_LEN], Vec<u8>), EnvelopeError> {
+    let key = aead_key(key)?;
+    let mut nonce = [0; NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|_| EnvelopeError::Random)?;
+    let mut ciphertext = plaintext.to_vec();
+    key.seal_in_place_append_tag(
+        Nonce::assume_unique_for_key(nonce),
+        Aad::from(aad),
+        &mut ciphertext,
+    )
+    .map_err(|_| EnvelopeError::Encryption)?;
+    Ok((nonce, ciphertext))
+}
+
+/// Decrypt and authenticate `ciphertext` with AES-256-GCM under `key`
+fn decrypt(
+    key: &[u8],
+    nonce: &[u8; NONCE_LEN],
+    aad: &[u8],
+    ciphertext: &[u8],
+) -> Result<Vec<u8>, EnvelopeError> {
+    let key = aead_key(key)?;
+    let mut plaintext = ciphertext.to_vec();
+    let len = key
+        .open_in_place(
+            Nonce::assume_unique_for_key(*nonce),
+            Aad::from(aad),
+            &mut plaintext,
+        )
+        .map_err(|_| EnvelopeError::Decryption)?
+        .len();
+    plaintext.truncate(len);
+    Ok(plaintext)
+}
+
+fn aead_key(key: &[u8]) -> Result<LessSafeKey, EnvelopeError> {
+    UnboundKey::new(&AES_256_GCM, key)
+        .map(LessSafeKey::new)
+        .map_err(|_| EnvelopeError::KeyWrapping("data keys must be 32 bytes".into()))
+}
+
+/// Has AWS KMS generate and decrypt data keys under a symmetric KMS key.
+///
+/// The KMS client is asynchronous, so this doesn't implement
+/// [`KeyWrapper`]; use [`KmsKeyWrapper::seal()`] and
+/// [`KmsKeyWrapper::open()`] instead of [`Envelope::seal()`] and
+/// [`Envelope::open()`].
+#[cfg(feature = "aws-kms")]
+#[derive(Debug, Clone)]
+pub struct KmsKeyWrapper {
+    client: aws_sdk_kms::Client,
+    key_id: String,
+}
+
+#[cfg(feature = "aws-kms")]
+impl KmsKeyWrapper {
+    /// Wrap with the KMS key `key_id`, which may be a key id, alias, or ARN
+    pub fn new(client: aws_sdk_kms::Client, key_id: impl Into<String>) -> Self {
+        Self {
+            client,
+            key_id: key_id.into(),
+        }
+    }
+
+    /// Encrypt `plaintext`, an artifact of kind `content_type`, under a data
+    /// key generated by KMS
+    pub async fn seal(
+        &self,
+        content_type: impl Into<String>,
+        plaintext: &[u8],
+    ) -> Result<Envelope, EnvelopeError> {
+        use aws_sdk_kms::types::DataKeySpec;
+
+        let output = self
+            .client
+            .generate_data_key()
+            .key_id(&self.key_id)
+            .key_spec(DataKeySpec::Aes256)
+            .send()
+            .await
+            .map_err(|e| EnvelopeError::KeyWrapping(e.to_string()))?;
+        let key = output
+            .plaintext()
+            .map(|key| Zeroizing::new(key.as_ref().to_vec()))
+            .ok_or_else(|| EnvelopeError::KeyWrapping("KMS returned no data key".into()))?;
+        let wrapped_key = output
+            .ciphertext_blob()
+            .ok_or_else(|| EnvelopeError::KeyWrapping("KMS returned no wrapped key".into()))?;
+        let key_id = output.key_id().unwrap_or(&self.key_id).to_string();
+        Envelope::seal_with_key(
+            content_type.into(),
+            key_id,
+            &key,
+            wrapped_key.as_ref(),
+            plaintext,
+        )
+    }
+
+    /// Decrypt the artifact in `envelope`, which must be of kind
+    /// `content_type`, with the data key decrypted by KMS
+    pub async fn open(
+        &self,
+        envelope: &Envelope,
+        content_type: &str,
+    ) -> Result<Vec<u8>, EnvelopeError> {
+        use aws_sdk_kms::primitives::Blob;
+
+        envelope.check(content_type)?;
+        let wrapped_key =
+            unhex(&envelope.wrapped_key).ok_or(EnvelopeError::Malformed("wrappedKey"))?;
+        let output = self
+            .client
+            .decrypt()
+            .key_id(&envelope.key_id)
+            .ciphertext_blob(Blob::new(wrapped_key))
+            .send()
+            .await
+            .map_err(|e| EnvelopeError::KeyWrapping(e.to_string()))?;
+        let key = output
+            .plaintext()
+            .map(|key| Zeroizing::new(key.as_ref().to_vec()))
+            .ok_or_else(|| EnvelopeError::KeyWrapping("KMS returned no data key".into()))?;
+        envelope.open_with_key(&key)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CONTENT_TYPE: &str = "test-artifact";
+
+    fn wrapper() -> LocalKeyWrapper {
+        LocalKeyWrapper::new([7; KEY_LEN], "kek-1")
+    }
+
+    #[test]
+    fn round_trip() {
+        let plaintext = b"forbid(principal, action, resource) when { context.amount > 5000 };";
+        let envelope = Envelope::seal(CONTENT_TYPE, plaintext, &wrapper()).unwrap();
+        assert_eq!(envelope.content_type(), CONTENT_TYPE);
+        assert_eq!(envelope.key_id(), "kek-1");
+        let json = envelope.to_json().unwrap();
+        assert!(!String::from_utf8_lossy(&json).contains("5000"));
+
+        let envelope = Envelope::from_json(&json).unwrap();
+        assert_eq!(envelope.open(CONTENT_TYPE, &wrapper()).unwrap(), plaintext);
+        // each envelope has its own data key and nonce
+        let again = Envelope::seal(CONTENT_TYPE, plaintext, &wrapper()).unwrap();
+        assert_ne!(again.wrapped_key, envelope.wrapped_key);
+        assert_ne!(again.ciphertext, envelope.ciphertext);
+    }
+
+    #[test]
+    fn tampering_is_detected() {
+        let envelope = Envelope::seal(CONTENT_TYPE, b"secret limits", &wrapper()).unwrap();
+
+        assert!(matches!(
+            envelope.open("other-artifact", &wrapper()),
+            Err(EnvelopeError::ContentType { .. })
+        ));
+        let other_key = LocalKeyWrapper::new([8; KEY_LEN], "kek-2");
+        assert!(matches!(
+            envelope.open(CONTENT_TYPE, &other_key),
+            Err(EnvelopeError::UnknownKey(id)) if id == "kek-1"
+        ));
+        let same_id = LocalKeyWrapper::new([8; KEY_LEN], "kek-1");
+        assert!(matches!(
+            envelope.open(CONTENT_TYPE, &same_id),
+            Err(EnvelopeError::Decryption)
+        ));
+
+        let mut relabeled = envelope.clone();
+        relabeled.content_type = "other-artifact".into();
+        assert!(matches!(
+            relabeled.open("other-artifact", &wrapper()),
+            Err(EnvelopeError::Decryption)
+        ));
+        let mut flipped = envelope.clone();
+        let last = if flipped.ciphertext.ends_with('0') {
+            "1"
+        } else {
+            "0"
+        };
+        flipped
+            .ciphertext
+            .replace_range(flipped.ciphertext.len() - 1.., last);
+        assert!(matches!(
+            flipped.open(CONTENT_TYPE, &wrapper()),
+            Err(EnvelopeError::Decryption)
+        ));
+        let mut newer = envelope;
+        newer.version = ENVELOPE_VERSION + 1;
+        assert!(matches!(
+            newer.open(CONTENT_TYPE, &wrapper()),
+            Err(EnvelopeError::UnsupportedVersion(_))
+        ));
+    }
+}
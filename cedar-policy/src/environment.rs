@@ -0,0 +1,161 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Policies which apply only in some deployment environments.
+//!
+//! One policy set can serve every stage of a deployment: a policy restricted
+//! to some environments carries an `@env` annotation listing their names,
+//! e.g. `@env("staging, dev")`, and a policy without one applies everywhere.
+//! [`policies_for_environment()`] selects the policies of one environment when
+//! the set is loaded, and an [`Authorizer`](crate::Authorizer) given an
+//! environment with
+//! [`with_environment()`](crate::Authorizer::with_environment) considers only
+//! those policies.
+
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+
+use crate::{Effect, PolicySet};
+
+/// The annotation restricting a policy to some environments, e.g.
+/// `@env("staging")`
+pub const ENV_ANNOTATION: &str = "env";
+
+/// The environment names listed in the value of an [`ENV_ANNOTATION`]
+///
+/// Names are separated by commas and consist of ASCII letters, digits, `-`,
+/// `_`, and `.`. Returns `None` if any name is malformed or the value lists
+/// none.
+pub fn parse_env_annotation(value: &str) -> Option<BTreeSet<&str>> {
+    let names = value
+        .split(',')
+        .map(|name| {
+            let name = name.trim();
+            let valid = !name.is_empty()
+                && name
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b"-_.".contains(&b));
+            valid.then_some(name)
+        })
+        .collect::<Option<BTreeSet<_>>>()?;
+    (!names.is_empty()).then_some(names)
+}
+
+/// The policies in `policies` which apply in the environment `env`
+///
+/// These are the policies without an `@env` annotation, and those whose
+/// annotation lists `env`. A malformed annotation makes a `permit` policy
+/// apply in no environment and a `forbid` policy apply in every environment,
+/// so that a typo never allows more. Borrows `policies` if they all apply.
+pub fn policies_for_environment<'a>(policies: &'a PolicySet, env: &str) -> Cow<'a, PolicySet> {
+    policies.retained(
+        |policy| match policy.annotation(ENV_ANNOTATION).map(parse_env_annotation) {
+            None => true,
+            Some(Some(envs)) => envs.contains(env),
+            Some(None) => policy.effect() == Effect::Forbid,
+        },
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Authorizer, Context, Decision, Entities, EntityUid, PolicyId, Request, SlotId};
+    use std::collections::HashMap;
+    use std::str::FromStr;
+
+    fn policies() -> PolicySet {
+        let mut policies = PolicySet::from_str(
+            r#"permit(principal, action == Action::"view", resource);
+               @env("staging, dev") permit(principal, action == Action::"deploy", resource);
+               @env("production") forbid(principal, action == Action::"deploy", resource);
+               @env("staging;dev") permit(principal, action, resource);
+               @env("") forbid(principal, action == Action::"drop", resource);
+               @env("dev") permit(principal == ?principal, action, resource);"#,
+        )
+        .unwrap();
+        policies
+            .link(
+                PolicyId::from_str("policy5").unwrap(),
+                PolicyId::from_str("alice").unwrap(),
+                HashMap::from([(SlotId::principal(), EntityUid::from_strs("User", "alice"))]),
+            )
+            .unwrap();
+        policies
+    }
+
+    fn ids(policies: &PolicySet) -> Vec<String> {
+        let mut ids: Vec<_> = policies.policies().map(|p| p.id().to_string()).collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn annotations() {
+        assert_eq!(
+            parse_env_annotation(" staging,dev ").map(|envs| envs.into_iter().collect::<Vec<_>>()),
+            Some(vec!["dev", "staging"])
+        );
+        assert_eq!(
+            parse_env_annotation("eu-west.prod_2").map(|envs| envs.len()),
+            Some(1)
+        );
+        assert_eq!(parse_env_annotation(""), None);
+        assert_eq!(parse_env_annotation("staging,"), None);
+        assert_eq!(parse_env_annotation("staging dev"), None);
+    }
+
+    #[test]
+    fn policies_are_selected_by_environment() {
+        let policies = policies();
+        assert_eq!(
+            ids(&policies_for_environment(&policies, "staging")),
+            ["policy0", "policy1", "policy4"]
+        );
+        assert_eq!(
+            ids(&policies_for_environment(&policies, "dev")),
+            ["alice", "policy0", "policy1", "policy4"]
+        );
+        assert_eq!(
+            ids(&policies_for_environment(&policies, "production")),
+            ["policy0", "policy2", "policy4"]
+        );
+        assert!(matches!(
+            policies_for_environment(&PolicySet::new(), "production"),
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[test]
+    fn authorizer_applies_policies_of_its_environment() {
+        let policies = policies();
+        let deploy = Request::new(
+            Some(EntityUid::from_strs("User", "bob")),
+            Some(EntityUid::from_strs("Action", "deploy")),
+            Some(EntityUid::from_strs("Service", "api")),
+            Context::empty(),
+        );
+        let decide = |env: &str| {
+            Authorizer::new()
+                .with_environment(env)
+                .is_authorized(&deploy, &policies, &Entities::empty())
+                .decision()
+        };
+        assert_eq!(decide("staging"), Decision::Allow);
+        assert_eq!(decide("production"), Decision::Deny);
+        assert_eq!(decide("qa"), Decision::Deny);
+    }
+}
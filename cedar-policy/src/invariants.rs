@@ -0,0 +1,552 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Invariants of policy sets, and the policies which break them.
+//!
+//! An invariant file declares, for an action and optionally a resource type,
+//! a condition which every request it permits must satisfy:
+//!
+//! ```text
+//! // transfers out of a treasury need a signer
+//! invariant treasury_signers:
+//!     permit Action::"transfer" on Treasury only if principal in Group::"signers";
+//! ```
+//!
+//! The condition is a Cedar expression. An invariant is checked against each
+//! `permit` policy which may apply to the action and resource type, and the
+//! policies which may permit a request breaking it are reported as
+//! [`Violation`]s.
+//!
+//! [`check()`] is fast and syntactic: a policy keeps an invariant if each
+//! conjunct of the condition is also a conjunct of the policy's scope or
+//! conditions. It reports every policy it can't show keeps an invariant,
+//! even if a `forbid` policy covers it. With the `analysis` feature,
+//! [`check_with()`] instead asks a [`Verifier`](crate::analysis::Verifier)
+//! whether each such policy, with every `forbid` policy, allows a request
+//! breaking the invariant, and reports the request it finds.
+
+#[cfg(feature = "analysis")]
+use std::borrow::Cow;
+use std::str::FromStr;
+
+use cedar_policy_core::ast::{self, ActionConstraint, EntityReference, ExprKind};
+use cedar_policy_core::entities::Dereference;
+use ref_cast::RefCast;
+use thiserror::Error;
+
+#[cfg(feature = "analysis")]
+use crate::analysis::{AnalysisError, Counterexample, Verdict, Verifier};
+use crate::{Effect, EntityTypeName, EntityUid, Expression, PolicyId, PolicySet, Schema};
+
+/// Errors in parsing invariants
+#[derive(Debug, Error)]
+pub enum InvariantError {
+    /// A declaration is malformed
+    #[error("line {line}: {message}")]
+    Syntax {
+        /// The line the declaration starts on, from 1
+        line: usize,
+        /// What is wrong
+        message: String,
+    },
+    /// Two invariants have the same name
+    #[error("duplicate invariant `{0}`")]
+    Duplicate(String),
+}
+
+/// A condition which every request for an action, on a resource type, which
+/// the policies permit must satisfy
+#[derive(Debug, Clone)]
+pub struct Invariant {
+    name: String,
+    action: EntityUid,
+    resource_type: Option<EntityTypeName>,
+    condition: Expression,
+}
+
+impl Invariant {
+    /// An invariant named `name`, that requests for `action`, on resources
+    /// of `resource_type` if given, are only permitted if `condition` holds
+    pub fn new(
+        name: impl Into<String>,
+        action: EntityUid,
+        resource_type: Option<EntityTypeName>,
+        condition: Expression,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            action,
+            resource_type,
+            condition,
+        }
+    }
+
+    /// The name of the invariant
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The action it constrains
+    pub fn action(&self) -> &EntityUid {
+        &self.action
+    }
+
+    /// The resource type it constrains, or `None` for every type
+    pub fn resource_type(&self) -> Option<&EntityTypeName> {
+        self.resource_type.as_ref()
+    }
+
+    /// The condition permitted requests must satisfy
+    pub fn condition(&self) -> &Expression {
+        &self.condition
+    }
+
+    /// Whether `policy`, a `permit` policy, may apply to the requests this
+    /// constrains
+    fn may_apply(
+        &self,
+        policy: &ast::Policy,
+        actions: &cedar_policy_core::entities::Entities,
+    ) -> bool {
+        let applies_to_action = applies_to_action(policy, &self.action.0, actions);
+        let applies_to_resource = self.resource_type.as_ref().is_none_or(|ty| {
+            match policy.resource_constraint().as_inner() {
+                ast::PrincipalOrResourceConstraint::Eq(EntityReference::EUID(uid)) => {
+                    *uid.entity_type() == ast::EntityType::Concrete(ty.0.clone())
+                }
+                ast::PrincipalOrResourceConstraint::Is(name)
+                | ast::PrincipalOrResourceConstraint::IsIn(name, _) => *name == ty.0,
+                _ => true,
+            }
+        });
+        applies_to_action && applies_to_resource
+    }
+
+    /// Whether the scope or conditions of `policy` include every conjunct of
+    /// the invariant's condition
+    fn implied_by(&self, policy: &ast::Policy) -> bool {
+        let mut conjuncts = vec![
+            policy.principal_constraint().as_expr(),
+            policy.resource_constraint().as_expr(),
+        ];
+        conjuncts.extend(
+            conjuncts_of(policy.non_head_constraints())
+                .into_iter()
+                .cloned(),
+        );
+        conjuncts_of(&self.condition.0)
+            .into_iter()
+            .all(|required| conjuncts.iter().any(|c| c.eq_shape(required)))
+    }
+}
+
+/// Whether the action scope of `policy` includes `action`, in the action
+/// hierarchy given by `actions`
+pub(crate) fn applies_to_action(
+    policy: &ast::Policy,
+    action: &ast::EntityUID,
+    actions: &cedar_policy_core::entities::Entities,
+) -> bool {
+    match policy.action_constraint() {
+        ActionConstraint::Any => true,
+        ActionConstraint::Eq(uid) => **uid == *action,
+        ActionConstraint::In(groups) => groups.iter().any(|group| {
+            **group == *action
+                || matches!(
+                    actions.entity(action),
+                    Dereference::Data(entity) if entity.is_descendant_of(group)
+                )
+        }),
+    }
+}
+
+/// The conjuncts of `expr`
+pub(crate) fn conjuncts_of(expr: &ast::Expr) -> Vec<&ast::Expr> {
+    match expr.expr_kind() {
+        ExprKind::And { left, right } => {
+            let mut conjuncts = conjuncts_of(left);
+            conjuncts.extend(conjuncts_of(right));
+            conjuncts
+        }
+        _ => vec![expr],
+    }
+}
+
+/// A policy which may permit a request breaking an invariant
+#[derive(Debug)]
+pub struct Violation {
+    invariant: String,
+    policy: PolicyId,
+    #[cfg(feature = "analysis")]
+    counterexample: Option<Box<Counterexample>>,
+}
+
+impl Violation {
+    /// The name of the invariant
+    pub fn invariant(&self) -> &str {
+        &self.invariant
+    }
+
+    /// The policy
+    pub fn policy(&self) -> &PolicyId {
+        &self.policy
+    }
+
+    /// A request breaking the invariant which the policy permits. `None` if
+    /// the violation was found syntactically, or the solver couldn't decide.
+    #[cfg(feature = "analysis")]
+    pub fn counterexample(&self) -> Option<&Counterexample> {
+        self.counterexample.as_deref()
+    }
+}
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "policy `{}` may break invariant `{}`",
+            self.policy, self.invariant
+        )
+    }
+}
+
+/// Parse the invariants declared in `src`
+pub fn parse_invariants(src: &str) -> Result<Vec<Invariant>, InvariantError> {
+    let mut invariants: Vec<Invariant> = Vec::new();
+    for (line, declaration) in declarations(src) {
+        let invariant = parse_declaration(&declaration)
+            .map_err(|message| InvariantError::Syntax { line, message })?;
+        if invariants.iter().any(|other| other.name == invariant.name) {
+            return Err(InvariantError::Duplicate(invariant.name));
+        }
+        invariants.push(invariant);
+    }
+    Ok(invariants)
+}
+
+/// The `;`-terminated declarations in `src`, without comments, with the
+/// lines they start on
+fn declarations(src: &str) -> Vec<(usize, String)> {
+    let mut declarations = Vec::new();
+    let mut current = String::new();
+    let mut start = 1;
+    let mut line = 1;
+    let mut in_string = false;
+    let mut chars = src.chars().peekable();
+    while let Some(c) = chars.next() {
+        if current.trim().is_empty() {
+            start = line;
+        }
+        match c {
+            '\n' => {
+                line += 1;
+                current.push(c);
+            }
+            '"' => {
+                in_string = !in_string;
+                current.push(c);
+            }
+            '\\' if in_string => {
+                current.push(c);
+                if let Some(escaped) = chars.next() {
+                    current.push(escaped);
+                }
+            }
+            '/' if !in_string && chars.peek() == Some(&'/') => {
+                while chars.peek().is_some_and(|c| *c != '\n') {
+                    chars.next();
+                }
+            }
+            ';' if !in_string => {
+                declarations.push((start, std::mem::take(&mut current)));
+            }
+            _ => current.push(c),
+        }
+    }
+    if !current.trim().is_empty() {
+        declarations.push((start, current));
+    }
+    declarations
+}
+
+/// Parse `invariant NAME: permit ACTION [on TYPE] only if CONDITION`
+fn parse_declaration(declaration: &str) -> Result<Invariant, String> {
+    let rest = declaration
+        .trim()
+        .strip_prefix("invariant")
+        .ok_or("expected `invariant`")?;
+    let (name, rest) = rest.split_once(':').ok_or("expected `:` after the name")?;
+    let name = name.trim();
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err(format!("invalid invariant name `{name}`"));
+    }
+    let rest = rest
+        .trim_start()
+        .strip_prefix("permit")
+        .ok_or("expected `permit` after `:`")?;
+    // the action is an entity literal, which ends with its quoted id
+    let id_start = rest.find("::\"").ok_or("expected an action")? + 3;
+    let id_len = rest
+        .get(id_start..)
+        .and_then(|id| {
+            let mut escaped = false;
+            id.find(|c| {
+                let end = c == '"' && !escaped;
+                escaped = c == '\\' && !escaped;
+                end
+            })
+        })
+        .ok_or("unterminated action id")?;
+    let (action, rest) = rest.split_at(id_start + id_len + 1);
+    let action = EntityUid::from_str(action.trim()).map_err(|err| err.to_string())?;
+    let rest = rest.trim_start();
+    let (resource_type, rest) = match rest.strip_prefix("on ") {
+        Some(on) => {
+            let (ty, rest) = on
+                .trim_start()
+                .split_once(char::is_whitespace)
+                .ok_or("expected `only if` after the resource type")?;
+            (
+                Some(EntityTypeName::from_str(ty).map_err(|err| err.to_string())?),
+                rest,
+            )
+        }
+        None => (None, rest),
+    };
+    let condition = rest
+        .trim_start()
+        .strip_prefix("only")
+        .map(str::trim_start)
+        .and_then(|rest| rest.strip_prefix("if"))
+        .ok_or("expected `only if`")?;
+    let condition = Expression::from_str(condition).map_err(|err| err.to_string())?;
+    Ok(Invariant::new(name, action, resource_type, condition))
+}
+
+/// The `permit` policies in `policies` which may apply to the requests
+/// `invariant` constrains
+fn candidates<'p>(
+    invariant: &Invariant,
+    policies: &'p PolicySet,
+    actions: &cedar_policy_core::entities::Entities,
+) -> Vec<&'p ast::Policy> {
+    policies
+        .ast
+        .policies()
+        .filter(|policy| policy.effect() == Effect::Permit && invariant.may_apply(policy, actions))
+        .collect()
+}
+
+/// The policies in `policies` which may break `invariants`, found
+/// syntactically. `schema` gives the action hierarchy.
+pub fn check(invariants: &[Invariant], policies: &PolicySet, schema: &Schema) -> Vec<Violation> {
+    let actions = schema.0.action_entities().unwrap_or_default();
+    let mut violations = Vec::new();
+    for invariant in invariants {
+        for policy in candidates(invariant, policies, &actions) {
+            if !invariant.implied_by(policy) {
+                violations.push(Violation {
+                    invariant: invariant.name.clone(),
+                    policy: PolicyId::ref_cast(policy.id()).clone(),
+                    #[cfg(feature = "analysis")]
+                    counterexample: None,
+                });
+            }
+        }
+    }
+    violations
+}
+
+/// The policies in `policies` which permit a request breaking `invariants`,
+/// found with `verifier`. A policy is also reported if the solver can't
+/// decide whether it does.
+#[cfg(feature = "analysis")]
+pub fn check_with(
+    invariants: &[Invariant],
+    policies: &PolicySet,
+    verifier: &Verifier<'_>,
+) -> Result<Vec<Violation>, AnalysisError> {
+    let actions = verifier.schema().0.action_entities().unwrap_or_default();
+    let mut violations = Vec::new();
+    for invariant in invariants {
+        let broken = ast::Expr::not(invariant.condition.0.clone());
+        let query = Expression(match &invariant.resource_type {
+            Some(ty) => ast::Expr::and(
+                ast::Expr::is_entity_type(ast::Expr::var(ast::Var::Resource), ty.0.clone()),
+                broken,
+            ),
+            None => broken,
+        });
+        for policy in candidates(invariant, policies, &actions) {
+            let alone = only_permit(policies, policy.id());
+            let counterexample = match verifier.can_allow(&alone, &invariant.action, &query)? {
+                Verdict::Unreachable => continue,
+                Verdict::Counterexample(counterexample) => Some(counterexample),
+                Verdict::Unknown => None,
+            };
+            violations.push(Violation {
+                invariant: invariant.name.clone(),
+                policy: PolicyId::ref_cast(policy.id()).clone(),
+                counterexample,
+            });
+        }
+    }
+    Ok(violations)
+}
+
+/// `policies` without the `permit` policies other than `id`
+#[cfg(feature = "analysis")]
+fn only_permit<'p>(policies: &'p PolicySet, id: &ast::PolicyID) -> Cow<'p, PolicySet> {
+    policies.retained(|policy| {
+        policy.effect() != Effect::Permit || policy.id() == PolicyId::ref_cast(id)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const INVARIANTS: &str = r#"
+        // transfers out of a treasury need a signer
+        invariant treasury_signers:
+            permit Action::"transfer" on Treasury
+            only if principal in Group::"signers";
+        invariant small_withdrawals: permit Action::"withdraw" only if context.amount <= 100;
+    "#;
+
+    fn schema() -> Schema {
+        Schema::from_str(
+            r#"{"": {
+                "entityTypes": {
+                    "User": {"memberOfTypes": ["Group"]},
+                    "Group": {},
+                    "Treasury": {},
+                    "Wallet": {}
+                },
+                "actions": {
+                    "transfer": {"appliesTo": {
+                        "principalTypes": ["User"],
+                        "resourceTypes": ["Treasury", "Wallet"]
+                    }},
+                    "withdraw": {"appliesTo": {
+                        "principalTypes": ["User"],
+                        "resourceTypes": ["Wallet"],
+                        "context": {"type": "Record", "attributes": {
+                            "amount": {"type": "Long"}
+                        }}
+                    }}
+                }
+            }}"#,
+        )
+        .unwrap()
+    }
+
+    fn policies() -> PolicySet {
+        PolicySet::from_str(
+            r#"permit(principal in Group::"signers", action == Action::"transfer", resource is Treasury);
+               permit(principal, action == Action::"transfer", resource is Wallet);
+               permit(principal, action in [Action::"transfer", Action::"withdraw"], resource)
+               when { context.amount <= 100 && principal == User::"alice" };"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn invariants_are_parsed() {
+        let invariants = parse_invariants(INVARIANTS).unwrap();
+        assert_eq!(invariants.len(), 2);
+        let [signers, small] = invariants.as_slice() else {
+            panic!("expected two invariants");
+        };
+        assert_eq!(signers.name(), "treasury_signers");
+        assert_eq!(
+            signers.action(),
+            &EntityUid::from_strs("Action", "transfer")
+        );
+        assert_eq!(
+            signers.resource_type().map(ToString::to_string),
+            Some("Treasury".to_string())
+        );
+        assert_eq!(small.resource_type(), None);
+
+        for (src, line) in [
+            ("invariant x: permit Action::\"a\" when true;", 1),
+            ("\n\ninvariant x permit Action::\"a\" only if true;", 3),
+            ("invariant x: permit Action::\"a\" only if )(;", 1),
+        ] {
+            assert!(
+                matches!(parse_invariants(src), Err(InvariantError::Syntax { line: l, .. }) if l == line),
+                "{src}"
+            );
+        }
+        assert!(matches!(
+            parse_invariants(
+                "invariant x: permit Action::\"a\" only if true; invariant x: permit Action::\"b\" only if true;"
+            ),
+            Err(InvariantError::Duplicate(name)) if name == "x"
+        ));
+    }
+
+    #[test]
+    fn syntactic_violations() {
+        let invariants = parse_invariants(INVARIANTS).unwrap();
+        let violations = check(&invariants, &policies(), &schema());
+        let found: Vec<_> = violations
+            .iter()
+            .map(|v| (v.invariant(), v.policy().to_string()))
+            .collect();
+        // `policy0` keeps `treasury_signers` by its scope, `policy1` doesn't
+        // apply to treasuries, and `policy2` keeps `small_withdrawals` by its
+        // condition
+        assert_eq!(found, [("treasury_signers", "policy2".to_string())]);
+        assert_eq!(
+            violations.first().map(ToString::to_string).as_deref(),
+            Some("policy `policy2` may break invariant `treasury_signers`")
+        );
+    }
+
+    #[cfg(feature = "analysis")]
+    #[test]
+    fn violations_found_with_a_solver() {
+        if std::process::Command::new("z3")
+            .arg("--version")
+            .output()
+            .is_err()
+        {
+            // no solver installed
+            return;
+        }
+        let schema = schema();
+        let invariants = parse_invariants(INVARIANTS).unwrap();
+        let mut policies = policies();
+        policies
+            .add(
+                crate::Policy::parse(
+                    Some("not alice".to_string()),
+                    r#"forbid(principal == User::"alice", action, resource is Treasury);"#,
+                )
+                .unwrap(),
+            )
+            .unwrap();
+        // the forbid policy covers the syntactic violation
+        let violations = check_with(&invariants, &policies, &Verifier::new(&schema)).unwrap();
+        assert!(violations.is_empty(), "{violations:?}");
+    }
+}
@@ -0,0 +1,508 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! An emergency kill switch: a layer of `forbid` rules for incidents.
+//!
+//! During an incident, access may have to be cut off faster than the main
+//! policy set can be updated. A [`KillSwitch`] holds its own `forbid` rules,
+//! apart from the main policy set, which only apply while it is active. An
+//! [`Authorizer`](crate::Authorizer) given a kill switch with
+//! [`with_kill_switch()`](crate::Authorizer::with_kill_switch) checks each
+//! request against the rules before the main policy set and before its
+//! decision cache, and denies the requests they match with an
+//! [`AuthorizationError::Halted`](crate::AuthorizationError::Halted) error.
+//!
+//! The kill switch is activated with [`KillSwitch::activate()`], or by
+//! passing the logs of watched contracts to [`KillSwitch::observe_log()`],
+//! which activates it on an OpenZeppelin `Paused(address)` event. It is only
+//! deactivated with [`KillSwitch::deactivate()`]. By default, its only rule
+//! is `forbid(principal, action, resource);`, halting everything.
+//!
+//! A kill switch opened with [`KillSwitch::open()`] keeps its rules and
+//! activation in a [`KillSwitchStore`], such as a [`FileKillSwitchStore`],
+//! rather than with the main policy set, so that it can be changed when the
+//! main store can't, and an activation survives restarts. A rule which can't
+//! be evaluated for a request halts it, as if it had matched.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Debug;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::SystemTime;
+
+use arc_swap::ArcSwap;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::audit::{normalize_address, unix_seconds};
+use crate::{Effect, ParseErrors, Policy, PolicyId, PolicySet, PolicySetError};
+
+/// The topic of OpenZeppelin `Pausable`'s `Paused(address)` event
+pub const PAUSED_TOPIC: &str = "0x62e78cea01bee320cd4e420270b5ea74000d11b0c9f74754ebdbfc544b05a258";
+
+/// The rule of a kill switch which halts everything
+const HALT_ALL: &str = "forbid(principal, action, resource);";
+
+/// Errors setting the rules of a [`KillSwitch`]
+#[derive(Debug, Error)]
+pub enum KillSwitchError {
+    /// A rule is a `permit` policy, which could only allow more
+    #[error("kill switch rule `{0}` is not a `forbid` policy")]
+    NotAForbid(PolicyId),
+    /// A stored rule doesn't parse
+    #[error("invalid kill switch rule `{id}`: {err}")]
+    InvalidRule {
+        /// The id of the rule
+        id: String,
+        /// Why it doesn't parse
+        err: ParseErrors,
+    },
+    /// The stored rules don't form a policy set
+    #[error(transparent)]
+    InvalidRules(#[from] PolicySetError),
+    /// The store couldn't be read or written. A change which couldn't be
+    /// saved still takes effect.
+    #[error("kill switch store error: {0}")]
+    Store(String),
+}
+
+/// The rules and activation of a kill switch, as kept by a
+/// [`KillSwitchStore`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KillSwitchState {
+    /// The text of each rule, by id
+    pub rules: BTreeMap<String, String>,
+    /// The activation, if the kill switch is active
+    pub activation: Option<Activation>,
+}
+
+/// Storage for a kill switch, kept apart from the main policy store
+pub trait KillSwitchStore: Debug + Send + Sync {
+    /// The stored state, if any has been saved
+    fn load(&self) -> Result<Option<KillSwitchState>, KillSwitchError>;
+
+    /// Replace the stored state
+    fn save(&self, state: &KillSwitchState) -> Result<(), KillSwitchError>;
+}
+
+/// A [`KillSwitchStore`] keeping the state in a JSON file
+#[derive(Debug, Clone)]
+pub struct FileKillSwitchStore {
+    path: PathBuf,
+}
+
+impl FileKillSwitchStore {
+    /// Keep the state in the file at `path`, which is created when the state
+    /// is first saved
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl KillSwitchStore for FileKillSwitchStore {
+    fn load(&self) -> Result<Option<KillSwitchState>, KillSwitchError> {
+        let json = match std::fs::read_to_string(&self.path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(KillSwitchError::Store(e.to_string())),
+        };
+        serde_json::from_str(&json).map_err(|e| KillSwitchError::Store(e.to_string()))
+    }
+
+    fn save(&self, state: &KillSwitchState) -> Result<(), KillSwitchError> {
+        let json = serde_json::to_string_pretty(state)
+            .map_err(|e| KillSwitchError::Store(e.to_string()))?;
+        // write a new file and rename it over the old one, so that a crash
+        // can't leave the state half written
+        let temp = self.path.with_extension("tmp");
+        std::fs::write(&temp, json)
+            .and_then(|()| std::fs::rename(&temp, &self.path))
+            .map_err(|e| KillSwitchError::Store(e.to_string()))
+    }
+}
+
+/// Why and when a kill switch was activated
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Activation {
+    /// Why it was activated
+    pub reason: String,
+    /// When it was activated, in seconds since the Unix epoch
+    pub activated_at: u64,
+}
+
+/// A layer of `forbid` rules which apply only while it is active. The rules
+/// and the activation can be read and updated concurrently, and readers
+/// never block.
+#[derive(Debug)]
+pub struct KillSwitch {
+    rules: ArcSwap<PolicySet>,
+    activation: ArcSwap<Option<Activation>>,
+    watched: HashSet<String>,
+    store: Option<Arc<dyn KillSwitchStore>>,
+    /// held while changing the kill switch, so that changes are saved in the
+    /// order they're made
+    changing: Mutex<()>,
+}
+
+impl Default for KillSwitch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl KillSwitch {
+    /// An inactive kill switch which halts everything when activated
+    pub fn new() -> Self {
+        // PANIC SAFETY: the rule is a valid policy
+        #[allow(clippy::expect_used)]
+        let rules = PolicySet::from_str(HALT_ALL).expect("rule should parse");
+        Self {
+            rules: ArcSwap::from_pointee(rules),
+            activation: ArcSwap::from_pointee(None),
+            watched: HashSet::new(),
+            store: None,
+            changing: Mutex::new(()),
+        }
+    }
+
+    /// A kill switch kept in `store`, with the rules and activation stored
+    /// there, or else inactive and halting everything when activated. Every
+    /// later change is saved to `store`.
+    pub fn open(store: Arc<dyn KillSwitchStore>) -> Result<Self, KillSwitchError> {
+        let mut kill_switch = Self::new();
+        let Some(state) = store.load()? else {
+            kill_switch.store = Some(store);
+            kill_switch.save()?;
+            return Ok(kill_switch);
+        };
+        let mut rules = PolicySet::new();
+        for (id, text) in state.rules {
+            let rule = Policy::parse(Some(id.clone()), text)
+                .map_err(|err| KillSwitchError::InvalidRule { id, err })?;
+            rules.add(rule)?;
+        }
+        Self::check_rules(&rules)?;
+        kill_switch.rules.store(Arc::new(rules));
+        kill_switch.activation.store(Arc::new(state.activation));
+        kill_switch.store = Some(store);
+        Ok(kill_switch)
+    }
+
+    /// An inactive kill switch which denies the requests matching `rules`
+    /// when activated. The rules must all be `forbid` policies.
+    pub fn with_rules(rules: PolicySet) -> Result<Self, KillSwitchError> {
+        let kill_switch = Self::new();
+        kill_switch.set_rules(rules)?;
+        Ok(kill_switch)
+    }
+
+    /// Activate on `Paused(address)` events from the contract at `address`,
+    /// a `0x`-prefixed hex address
+    #[must_use]
+    pub fn with_watched_contract(mut self, address: &str) -> Self {
+        self.watched
+            .insert(normalize_address(address).unwrap_or_else(|| address.to_ascii_lowercase()));
+        self
+    }
+
+    /// Replace the rules, which must all be `forbid` policies. The new rules
+    /// take effect immediately.
+    pub fn set_rules(&self, rules: PolicySet) -> Result<(), KillSwitchError> {
+        Self::check_rules(&rules)?;
+        let _changing = self.changing.lock().unwrap_or_else(PoisonError::into_inner);
+        self.rules.store(Arc::new(rules));
+        self.save()
+    }
+
+    /// Check that `rules` are all `forbid` policies
+    fn check_rules(rules: &PolicySet) -> Result<(), KillSwitchError> {
+        rules
+            .policies()
+            .find(|policy| policy.effect() == Effect::Permit)
+            .map_or(Ok(()), |permit| {
+                Err(KillSwitchError::NotAForbid(permit.id().clone()))
+            })
+    }
+
+    /// Save the current rules and activation to the store, if there is one.
+    /// Must be called while holding `changing`.
+    fn save(&self) -> Result<(), KillSwitchError> {
+        let Some(store) = &self.store else {
+            return Ok(());
+        };
+        let rules = self.rules.load();
+        store.save(&KillSwitchState {
+            rules: rules
+                .policies()
+                .map(|rule| (rule.id().to_string(), rule.to_string()))
+                .collect(),
+            activation: Option::clone(&self.activation.load()),
+        })
+    }
+
+    /// The current rules
+    pub fn rules(&self) -> Arc<PolicySet> {
+        self.rules.load_full()
+    }
+
+    /// Activate the kill switch now for `reason`, returning the earlier
+    /// activation if it was already active. The kill switch is active even
+    /// if the activation can't be saved.
+    pub fn activate(
+        &self,
+        reason: impl Into<String>,
+    ) -> Result<Option<Activation>, KillSwitchError> {
+        let activation = Activation {
+            reason: reason.into(),
+            activated_at: unix_seconds(SystemTime::now()),
+        };
+        self.set_activation(Some(activation))
+    }
+
+    /// Deactivate the kill switch, returning its activation if it was
+    /// active
+    pub fn deactivate(&self) -> Result<Option<Activation>, KillSwitchError> {
+        self.set_activation(None)
+    }
+
+    /// Replace the activation, returning the earlier one
+    fn set_activation(
+        &self,
+        activation: Option<Activation>,
+    ) -> Result<Option<Activation>, KillSwitchError> {
+        let _changing = self.changing.lock().unwrap_or_else(PoisonError::into_inner);
+        let earlier = Option::clone(&self.activation.swap(Arc::new(activation)));
+        self.save()?;
+        Ok(earlier)
+    }
+
+    /// The current activation, if the kill switch is active
+    pub fn activation(&self) -> Option<Activation> {
+        Option::clone(&self.activation.load())
+    }
+
+    /// Whether the kill switch is active
+    pub fn is_active(&self) -> bool {
+        self.activation.load().is_some()
+    }
+
+    /// Activate the kill switch if a log emitted by the contract at
+    /// `address` with `topics` is a `Paused(address)` event and the contract
+    /// is watched, returning whether it was. An already active kill switch
+    /// keeps its earlier activation.
+    pub fn observe_log<S: AsRef<str>>(
+        &self,
+        address: &str,
+        topics: &[S],
+    ) -> Result<bool, KillSwitchError> {
+        let is_pause = topics
+            .first()
+            .is_some_and(|topic| topic.as_ref().eq_ignore_ascii_case(PAUSED_TOPIC));
+        let address = normalize_address(address).unwrap_or_else(|| address.to_ascii_lowercase());
+        if !is_pause || !self.watched.contains(&address) {
+            return Ok(false);
+        }
+        let _changing = self.changing.lock().unwrap_or_else(PoisonError::into_inner);
+        if self.is_active() {
+            return Ok(true);
+        }
+        self.activation.store(Arc::new(Some(Activation {
+            reason: format!("`Paused` event from {address}"),
+            activated_at: unix_seconds(SystemTime::now()),
+        })));
+        self.save()?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cache::DecisionCache;
+    use crate::{AuthorizationError, Authorizer, Context, Decision, Entities, EntityUid, Request};
+    use sha3::{Digest, Keccak256};
+    use std::time::Duration;
+
+    const PAUSABLE: &str = "0x5FbDB2315678afecb367f032d93F642f64180aa3";
+
+    fn request(action: &str) -> Request {
+        Request::new(
+            Some(EntityUid::from_strs("User", "alice")),
+            Some(EntityUid::from_strs("Action", action)),
+            Some(EntityUid::from_strs("Vault", "v")),
+            Context::empty(),
+        )
+    }
+
+    #[test]
+    fn paused_topic() {
+        assert_eq!(
+            PAUSED_TOPIC,
+            format!(
+                "0x{}",
+                crate::audit::to_hex(&Keccak256::digest(b"Paused(address)"))
+            )
+        );
+    }
+
+    #[test]
+    fn halts_before_policies_and_cache() {
+        let kill_switch = Arc::new(
+            KillSwitch::with_rules(
+                PolicySet::from_str(
+                    r#"forbid(principal, action == Action::"withdraw", resource);"#,
+                )
+                .unwrap(),
+            )
+            .unwrap(),
+        );
+        let authorizer = Authorizer::new()
+            .with_decision_cache(Arc::new(DecisionCache::new(Duration::from_secs(60))))
+            .with_kill_switch(Arc::clone(&kill_switch));
+        let policies = PolicySet::from_str("permit(principal, action, resource);").unwrap();
+        let entities = Entities::empty();
+
+        let decide = |action| {
+            authorizer
+                .is_authorized(&request(action), &policies, &entities)
+                .decision()
+        };
+        assert_eq!(decide("withdraw"), Decision::Allow);
+
+        assert_eq!(kill_switch.activate("incident 42").unwrap(), None);
+        let response = authorizer.is_authorized(&request("withdraw"), &policies, &entities);
+        assert_eq!(response.decision(), Decision::Deny);
+        assert_eq!(
+            response.diagnostics().reason().collect::<Vec<_>>(),
+            [&PolicyId::from_str("policy0").unwrap()]
+        );
+        assert!(matches!(
+            response.diagnostics().errors().next(),
+            Some(AuthorizationError::Halted(_))
+        ));
+        assert_eq!(decide("deposit"), Decision::Allow);
+
+        assert_eq!(
+            kill_switch
+                .deactivate()
+                .unwrap()
+                .map(|activation| activation.reason),
+            Some("incident 42".to_string())
+        );
+        assert_eq!(decide("withdraw"), Decision::Allow);
+
+        assert!(matches!(
+            kill_switch.set_rules(policies.clone()),
+            Err(KillSwitchError::NotAForbid(_))
+        ));
+    }
+
+    #[test]
+    fn activated_by_pause_events() {
+        let kill_switch = KillSwitch::new().with_watched_contract(PAUSABLE);
+        let account = "0x000000000000000000000000f39fd6e51aad88f6f4ce6ab8827279cfffb92266";
+        assert!(!kill_switch.observe_log(PAUSABLE, &[account]).unwrap());
+        assert!(!kill_switch
+            .observe_log(
+                "0x0000000000000000000000000000000000000001",
+                &[PAUSED_TOPIC, account]
+            )
+            .unwrap());
+        assert!(!kill_switch.is_active());
+
+        assert!(kill_switch
+            .observe_log(&PAUSABLE.to_ascii_lowercase(), &[PAUSED_TOPIC, account])
+            .unwrap());
+        let activation = kill_switch.activation().unwrap();
+        assert!(activation.reason.contains(&PAUSABLE.to_ascii_lowercase()));
+
+        // everything is halted
+        let authorizer = Authorizer::new().with_kill_switch(Arc::new(kill_switch));
+        let policies = PolicySet::from_str("permit(principal, action, resource);").unwrap();
+        let response = authorizer.is_authorized(&request("deposit"), &policies, &Entities::empty());
+        assert_eq!(response.decision(), Decision::Deny);
+    }
+
+    #[test]
+    fn rules_that_error_halt() {
+        let kill_switch = KillSwitch::with_rules(
+            PolicySet::from_str("forbid(principal, action, resource) when { context.paused };")
+                .unwrap(),
+        )
+        .unwrap();
+        kill_switch.activate("incident").unwrap();
+        let authorizer = Authorizer::new().with_kill_switch(Arc::new(kill_switch));
+        let policies = PolicySet::from_str("permit(principal, action, resource);").unwrap();
+        let response = authorizer.is_authorized(&request("deposit"), &policies, &Entities::empty());
+        assert_eq!(response.decision(), Decision::Deny);
+        assert!(matches!(
+            response.diagnostics().errors().next(),
+            Some(AuthorizationError::Halted(_))
+        ));
+    }
+
+    #[test]
+    fn stored_apart_from_policies() {
+        let path = std::env::temp_dir().join(format!("kill-switch-{}.json", std::process::id()));
+        let store: Arc<dyn KillSwitchStore> = Arc::new(FileKillSwitchStore::new(&path));
+        let rules =
+            PolicySet::from_str(r#"forbid(principal, action == Action::"withdraw", resource);"#)
+                .unwrap();
+
+        let kill_switch = KillSwitch::open(Arc::clone(&store)).unwrap();
+        assert!(!kill_switch.is_active());
+        kill_switch.set_rules(rules).unwrap();
+        kill_switch.activate("incident 42").unwrap();
+
+        // a restarted process finds the kill switch still active
+        let reopened = KillSwitch::open(Arc::clone(&store)).unwrap();
+        assert_eq!(
+            reopened.activation().map(|activation| activation.reason),
+            Some("incident 42".to_string())
+        );
+        let authorizer = Authorizer::new().with_kill_switch(Arc::new(reopened));
+        let policies = PolicySet::from_str("permit(principal, action, resource);").unwrap();
+        let decide = |action| {
+            authorizer
+                .is_authorized(&request(action), &policies, &Entities::empty())
+                .decision()
+        };
+        assert_eq!(decide("withdraw"), Decision::Deny);
+        assert_eq!(decide("deposit"), Decision::Allow);
+
+        kill_switch.deactivate().unwrap();
+        assert!(!KillSwitch::open(store).unwrap().is_active());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(feature = "partial-eval")]
+    #[test]
+    fn halts_partial_evaluation() {
+        let kill_switch = KillSwitch::new();
+        kill_switch.activate("incident").unwrap();
+        let authorizer = Authorizer::new().with_kill_switch(Arc::new(kill_switch));
+        let policies = PolicySet::from_str("permit(principal, action, resource);").unwrap();
+        match authorizer.is_authorized_partial(&request("deposit"), &policies, &Entities::empty()) {
+            crate::PartialResponse::Concrete(response) => {
+                assert_eq!(response.decision(), Decision::Deny);
+            }
+            crate::PartialResponse::Residual(_) => panic!("should be halted"),
+        }
+    }
+}
@@ -34,6 +34,119 @@
 mod api;
 pub use api::*;
 
+/// Versioned JSON form of authorization responses
+pub mod response;
+
+/// Audit records of authorization decisions
+pub mod audit;
+
+/// Signed receipts of authorization decisions
+#[cfg(feature = "signing")]
+pub mod receipt;
+
+/// Signed provenance for policies and templates
+#[cfg(feature = "signing")]
+pub mod provenance;
+
+/// Revocation of template-linked policies
+pub mod revocation;
+
+/// Impact analysis of policy changes
+pub mod what_if;
+
+/// Replay of recorded authorization decisions
+pub mod replay;
+
+/// Replay protection with nonces and single-use request ids
+pub mod nonce;
+
+/// Rate limiting of requests with token buckets
+pub mod rate_limit;
+
+/// Organizations, teams, and wallets as an entity hierarchy
+pub mod organization;
+
+/// Per-tenant isolation of policies, entities, caches, and audit logs
+pub mod tenant;
+
+/// Risk scores of requests, as request context
+pub mod risk;
+
+/// Sanctions screening of addresses, as entity attributes
+pub mod screening;
+
+/// Pinning of on-chain reads to one block
+pub mod block_pin;
+
+/// Scoping of policies and entities to chains
+pub mod chain;
+
+/// Policies which apply only in some deployment environments
+pub mod environment;
+
+/// Gradual rollout of policies to a share of principals or behind flags
+pub mod rollout;
+
+/// Shadow-mode policies, evaluated but not enforced
+pub mod shadow;
+
+/// Caching of authorization decisions
+pub mod cache;
+
+/// An emergency kill switch of `forbid` rules for incidents
+pub mod kill_switch;
+
+/// Sealed policy sets, validated and linked ahead of time
+pub mod sealed;
+
+/// Named configuration values in policies, resolved when they're loaded
+pub mod config;
+
+/// Envelope encryption of policy artifacts at rest
+#[cfg(feature = "encryption")]
+pub mod envelope;
+
+/// Verification of policies with an SMT solver
+#[cfg(feature = "analysis")]
+pub mod analysis;
+
+/// Invariants of policy sets, checked syntactically or with an SMT solver
+pub mod invariants;
+
+/// Suggested repairs for policies which break invariants or don't validate
+pub mod repair;
+
+/// Permits which only depend on context supplied by the requester
+pub mod taint;
+
+/// Estimated cost of evaluating policies, off-chain and on-chain
+pub mod cost;
+
+/// K-of-N agreement of independent evaluators' decisions
+#[cfg(feature = "signing")]
+pub mod threshold;
+
+/// Time-locked `Allow` decisions, queued until a delay passes
+pub mod timelock;
+
+/// Dual control: decisions which need a second approver
+#[cfg(feature = "signing")]
+pub mod dual_control;
+
+/// Access review: who can do what
+pub mod access;
+
+/// Delegation chains
+pub mod delegation;
+
+/// Labels, categories, and risk scores for addresses
+pub mod address_book;
+
+/// Providers of web3, DeFi and compliance data as request context and
+/// entities
+#[cfg(feature = "domain")]
+pub mod domain;
+
 /// Frontend utilities, see comments in the module itself
 pub mod frontend;
 
@@ -0,0 +1,416 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+//! Replay protection with nonces and single-use request ids.
+//!
+//! An off-chain approval is only safe if it can't be presented twice. A
+//! [`NonceTracker`] reads a nonce and a request id from each request's
+//! context, and refuses requests whose nonce isn't greater than the last one
+//! accepted from the same principal, or whose request id was already used.
+//! An [`Authorizer`](crate::Authorizer) given a tracker with
+//! [`with_nonce_tracker()`](crate::Authorizer::with_nonce_tracker) denies
+//! such requests even when the policies allow them.
+//!
+//! The tracker keeps its state in a [`NonceStore`], so that it can be shared
+//! by several authorizers, or persisted; [`MemoryNonceStore`] keeps it in
+//! memory.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use cedar_policy_core::ast::{ExprKind, Literal};
+use thiserror::Error;
+
+use crate::audit::to_hex;
+use crate::Request;
+
+/// The default context attribute holding a request's nonce, a `Long`
+pub const NONCE_ATTRIBUTE: &str = "nonce";
+/// The default context attribute holding a request's id, a `String`
+pub const REQUEST_ID_ATTRIBUTE: &str = "requestId";
+
+/// An error from a [`NonceStore`]
+#[derive(Debug, Error)]
+#[error("nonce store error: {0}")]
+pub struct NonceStoreError(pub String);
+
+/// Why a [`NonceStore`] refused to record a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NonceConflict {
+    /// The nonce isn't greater than the principal's latest
+    StaleNonce,
+    /// The id at this index of the ids was already used
+    UsedId(usize),
+}
+
+/// Where a [`NonceTracker`] keeps the nonces and request ids it has seen
+pub trait NonceStore: Debug + Send + Sync {
+    /// Record the request with `nonce`, the nonce of a principal, if it has
+    /// one, and the single-use `ids`. The nonce must be greater than the
+    /// latest recorded nonce of the principal, unless it is the principal's
+    /// first, and none of `ids` may have been recorded. If either check
+    /// fails, nothing is recorded and the first conflict is returned.
+    ///
+    /// The checks and the update must be atomic, so that concurrent
+    /// requests can't both be accepted, and a refused request can't use up
+    /// the request id or nonce of a later, valid one.
+    fn record(
+        &self,
+        nonce: Option<(&str, i64)>,
+        ids: &[&str],
+    ) -> Result<Option<NonceConflict>, NonceStoreError>;
+}
+
+/// A [`NonceStore`] in memory
+#[derive(Debug, Default)]
+pub struct MemoryNonceStore {
+    seen: Mutex<Seen>,
+}
+
+/// The latest nonce of each principal, and the ids used
+#[derive(Debug, Default)]
+struct Seen {
+    nonces: HashMap<String, i64>,
+    ids: HashSet<String>,
+}
+
+impl MemoryNonceStore {
+    /// An empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl NonceStore for MemoryNonceStore {
+    fn record(
+        &self,
+        nonce: Option<(&str, i64)>,
+        ids: &[&str],
+    ) -> Result<Option<NonceConflict>, NonceStoreError> {
+        let mut seen = self.seen.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some((principal, nonce)) = nonce {
+            if seen
+                .nonces
+                .get(principal)
+                .is_some_and(|latest| *latest >= nonce)
+            {
+                return Ok(Some(NonceConflict::StaleNonce));
+            }
+        }
+        if let Some(i) = ids.iter().position(|id| seen.ids.contains(*id)) {
+            return Ok(Some(NonceConflict::UsedId(i)));
+        }
+        if let Some((principal, nonce)) = nonce {
+            seen.nonces.insert(principal.to_string(), nonce);
+        }
+        seen.ids.extend(ids.iter().map(ToString::to_string));
+        drop(seen);
+        Ok(None)
+    }
+}
+
+/// Why a request was refused as a replay
+#[derive(Debug, Error)]
+pub enum ReplayError {
+    /// The nonce isn't greater than the principal's latest
+    #[error("nonce {nonce} of `{principal}` was already used or is out of order")]
+    StaleNonce {
+        /// The request's principal
+        principal: String,
+        /// The request's nonce
+        nonce: i64,
+    },
+    /// The request id was already used
+    #[error("request id `{0}` was already used")]
+    UsedRequestId(String),
+    /// An identical request was already accepted
+    #[error("request {0} was already accepted")]
+    RepeatedRequest(String),
+    /// The request has no nonce or request id, but the tracker requires one
+    #[error("request has neither a nonce nor a request id")]
+    Missing,
+    /// A context attribute has the wrong type
+    #[error("context attribute `{0}` has the wrong type")]
+    WrongType(String),
+    /// The store failed
+    #[error(transparent)]
+    Store(#[from] NonceStoreError),
+}
+
+/// Refuses requests which reuse a nonce or request id
+#[derive(Debug)]
+pub struct NonceTracker {
+    store: Arc<dyn NonceStore>,
+    nonce_attribute: String,
+    request_id_attribute: String,
+    required: bool,
+    single_use: bool,
+}
+
+impl NonceTracker {
+    /// A tracker keeping its state in `store`, reading nonces from the
+    /// [`NONCE_ATTRIBUTE`] and request ids from the [`REQUEST_ID_ATTRIBUTE`]
+    /// of each request's context. Requests with neither are accepted.
+    pub fn new(store: Arc<dyn NonceStore>) -> Self {
+        Self {
+            store,
+            nonce_attribute: NONCE_ATTRIBUTE.to_string(),
+            request_id_attribute: REQUEST_ID_ATTRIBUTE.to_string(),
+            required: false,
+            single_use: false,
+        }
+    }
+
+    /// The store the nonces and request ids are kept in
+    pub(crate) fn store(&self) -> &Arc<dyn NonceStore> {
+        &self.store
+    }
+
+    /// Read nonces from the context attribute `attribute`
+    #[must_use]
+    pub fn with_nonce_attribute(mut self, attribute: impl Into<String>) -> Self {
+        self.nonce_attribute = attribute.into();
+        self
+    }
+
+    /// Read request ids from the context attribute `attribute`
+    #[must_use]
+    pub fn with_request_id_attribute(mut self, attribute: impl Into<String>) -> Self {
+        self.request_id_attribute = attribute.into();
+        self
+    }
+
+    /// Refuse requests with neither a nonce nor a request id
+    #[must_use]
+    pub fn require_nonce(mut self) -> Self {
+        self.required = true;
+        self
+    }
+
+    /// Also refuse a request identical to one already accepted, by recording
+    /// the hex-encoded [`Request::canonical_hash()`] of each request in the
+    /// store as if it were a request id
+    #[must_use]
+    pub fn single_use_requests(mut self) -> Self {
+        self.single_use = true;
+        self
+    }
+
+    /// Check that `request` isn't a replay and record its nonce and request
+    /// id, so that it can't be replayed later. Nonces are per principal:
+    /// each must be greater than the last accepted from the same principal.
+    /// Nothing is recorded for a refused request, so a request refused for
+    /// its nonce doesn't use up its request id.
+    pub fn check(&self, request: &Request) -> Result<(), ReplayError> {
+        let mut nonce = None;
+        let mut request_id = None;
+        for (key, value) in request.0.context().into_iter().flat_map(|c| c.iter()) {
+            if key == self.nonce_attribute {
+                match value.expr_kind() {
+                    ExprKind::Lit(Literal::Long(n)) => nonce = Some(*n),
+                    _ => return Err(ReplayError::WrongType(key.to_string())),
+                }
+            } else if key == self.request_id_attribute {
+                match value.expr_kind() {
+                    ExprKind::Lit(Literal::String(id)) => request_id = Some(id.to_string()),
+                    _ => return Err(ReplayError::WrongType(key.to_string())),
+                }
+            }
+        }
+        if self.required && nonce.is_none() && request_id.is_none() {
+            return Err(ReplayError::Missing);
+        }
+        let hash = self
+            .single_use
+            .then(|| format!("0x{}", to_hex(&request.canonical_hash())));
+        let principal = request
+            .principal()
+            .map_or_else(String::new, ToString::to_string);
+        let ids: Vec<&str> = request_id.iter().chain(&hash).map(String::as_str).collect();
+        match self
+            .store
+            .record(nonce.map(|nonce| (principal.as_str(), nonce)), &ids)?
+        {
+            None => Ok(()),
+            Some(NonceConflict::StaleNonce) => Err(ReplayError::StaleNonce {
+                principal,
+                nonce: nonce.unwrap_or_default(),
+            }),
+            // the request id comes first
+            Some(NonceConflict::UsedId(0)) if request_id.is_some() => {
+                Err(ReplayError::UsedRequestId(request_id.unwrap_or_default()))
+            }
+            Some(NonceConflict::UsedId(_)) => {
+                Err(ReplayError::RepeatedRequest(hash.unwrap_or_default()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        AuthorizationError, Authorizer, Context, Decision, Entities, EntityUid, PolicySet,
+        RestrictedExpression,
+    };
+    use std::str::FromStr;
+
+    fn request(principal: &str, attrs: Vec<(&str, RestrictedExpression)>) -> Request {
+        Request::new(
+            Some(EntityUid::from_strs("User", principal)),
+            Some(EntityUid::from_strs("Action", "approve")),
+            Some(EntityUid::from_strs("Safe", "treasury")),
+            Context::from_pairs(attrs.into_iter().map(|(k, v)| (k.to_string(), v))),
+        )
+    }
+
+    fn nonce(principal: &str, n: i64) -> Request {
+        request(
+            principal,
+            vec![("nonce", RestrictedExpression::new_long(n))],
+        )
+    }
+
+    #[test]
+    fn nonces_increase_per_principal() {
+        let tracker = NonceTracker::new(Arc::new(MemoryNonceStore::new()));
+        assert!(tracker.check(&nonce("alice", 1)).is_ok());
+        assert!(tracker.check(&nonce("alice", 5)).is_ok());
+        assert!(matches!(
+            tracker.check(&nonce("alice", 5)),
+            Err(ReplayError::StaleNonce { nonce: 5, .. })
+        ));
+        assert!(tracker.check(&nonce("alice", 4)).is_err());
+        assert!(tracker.check(&nonce("bob", 1)).is_ok());
+        assert!(tracker.check(&request("alice", vec![])).is_ok());
+        assert!(matches!(
+            tracker.check(&request(
+                "alice",
+                vec![("nonce", RestrictedExpression::new_string("6".into()))]
+            )),
+            Err(ReplayError::WrongType(_))
+        ));
+    }
+
+    #[test]
+    fn request_ids_are_single_use() {
+        let tracker = NonceTracker::new(Arc::new(MemoryNonceStore::new()))
+            .with_request_id_attribute("approvalId")
+            .require_nonce();
+        let approval = |id: &str| {
+            request(
+                "alice",
+                vec![("approvalId", RestrictedExpression::new_string(id.into()))],
+            )
+        };
+        assert!(tracker.check(&approval("a")).is_ok());
+        assert!(tracker.check(&approval("b")).is_ok());
+        assert!(matches!(
+            tracker.check(&approval("a")),
+            Err(ReplayError::UsedRequestId(id)) if id == "a"
+        ));
+        assert!(matches!(
+            tracker.check(&request("alice", vec![])),
+            Err(ReplayError::Missing)
+        ));
+    }
+
+    #[test]
+    fn repeated_requests() {
+        let tracker = NonceTracker::new(Arc::new(MemoryNonceStore::new())).single_use_requests();
+        let transfer = |amount: &str| {
+            request(
+                "alice",
+                vec![(
+                    "amount",
+                    RestrictedExpression::from_str(&format!(r#"u256("{amount}")"#)).unwrap(),
+                )],
+            )
+        };
+        assert!(tracker.check(&transfer("100")).is_ok());
+        assert!(tracker.check(&transfer("200")).is_ok());
+        assert!(matches!(
+            tracker.check(&transfer("100")),
+            Err(ReplayError::RepeatedRequest(hash)) if hash.len() == 66
+        ));
+        assert!(tracker.check(&request("bob", vec![])).is_ok());
+    }
+
+    #[test]
+    fn refused_requests_record_nothing() {
+        let tracker = NonceTracker::new(Arc::new(MemoryNonceStore::new())).single_use_requests();
+        let approval = |id: &str, n: i64| {
+            request(
+                "alice",
+                vec![
+                    ("requestId", RestrictedExpression::new_string(id.into())),
+                    ("nonce", RestrictedExpression::new_long(n)),
+                ],
+            )
+        };
+        assert!(tracker.check(&approval("a", 5)).is_ok());
+        // a stale nonce doesn't use up the request id or the request
+        assert!(matches!(
+            tracker.check(&approval("b", 5)),
+            Err(ReplayError::StaleNonce { nonce: 5, .. })
+        ));
+        assert!(tracker.check(&approval("b", 6)).is_ok());
+        // and a used request id doesn't advance the nonce
+        assert!(matches!(
+            tracker.check(&approval("b", 7)),
+            Err(ReplayError::UsedRequestId(id)) if id == "b"
+        ));
+        assert!(tracker.check(&approval("c", 7)).is_ok());
+        assert!(matches!(
+            tracker.check(&approval("c", 7)),
+            Err(ReplayError::StaleNonce { .. })
+        ));
+    }
+
+    #[test]
+    fn authorizer_denies_replays() {
+        let policies = PolicySet::from_str(
+            r#"permit(principal, action, resource);
+               forbid(principal, action, resource) when { context has blocked };"#,
+        )
+        .unwrap();
+        let authorizer = Authorizer::new().with_nonce_tracker(Arc::new(NonceTracker::new(
+            Arc::new(MemoryNonceStore::new()),
+        )));
+        let decide =
+            |request: &Request| authorizer.is_authorized(request, &policies, &Entities::empty());
+        assert_eq!(decide(&nonce("alice", 1)).decision(), Decision::Allow);
+        let replayed = decide(&nonce("alice", 1));
+        assert_eq!(replayed.decision(), Decision::Deny);
+        assert!(matches!(
+            replayed.diagnostics().errors().next(),
+            Some(AuthorizationError::Replayed(_))
+        ));
+
+        // denied requests don't use up their nonce
+        let blocked = request(
+            "alice",
+            vec![
+                ("nonce", RestrictedExpression::new_long(2)),
+                ("blocked", RestrictedExpression::new_bool(true)),
+            ],
+        );
+        assert_eq!(decide(&blocked).decision(), Decision::Deny);
+        assert_eq!(decide(&blocked).diagnostics().errors().count(), 0);
+        assert_eq!(decide(&nonce("alice", 2)).decision(), Decision::Allow);
+    }
+}
@@ -0,0 +1,556 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Organizations, teams, and wallets as an entity hierarchy.
+//!
+//! An [`OrgChart`] is a tree: organizations, which may belong to other
+//! organizations, have teams, which may belong to other teams, and both
+//! have wallets. Each member only names its parent, and the chart works
+//! out the rest, so that a policy scoped to an organization applies to all
+//! of its teams and wallets, e.g.
+//! ```text
+//! permit(principal in Organization::"acme", action == Action::"transfer", resource)
+//! when { context.amount <= 1000 };
+//! ```
+//! applies to `Wallet::"0x…"` of `Team::"treasury"` of `Organization::"acme"`
+//! without its parents being listed by hand.
+//!
+//! An [`Authorizer`](crate::Authorizer) given a chart with
+//! [`with_org_chart()`](crate::Authorizer::with_org_chart) adds the
+//! ancestors of the chart's members to the entities of each request, and the
+//! members which aren't there. If that makes the hierarchy cyclic, the
+//! request is denied with an
+//! [`AuthorizationError::InvalidHierarchy`](crate::AuthorizationError::InvalidHierarchy)
+//! error. [`schema_fragment()`] declares the entity types.
+
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+use cedar_policy_core::ast;
+use cedar_policy_core::entities::TCComputation;
+use serde::Deserialize;
+use serde_json::json;
+use thiserror::Error;
+
+use crate::audit::normalize_address;
+use crate::{
+    Entities, EntitiesError, EntityId, EntityTypeName, EntityUid, SchemaError, SchemaFragment,
+};
+
+/// The entity type of organizations
+pub const ORGANIZATION_TYPE: &str = "Organization";
+/// The entity type of teams
+pub const TEAM_TYPE: &str = "Team";
+/// The entity type of wallets, whose ids are addresses
+pub const WALLET_TYPE: &str = "Wallet";
+
+/// Errors building an [`OrgChart`]
+#[derive(Debug, Error)]
+pub enum OrgChartError {
+    /// The JSON is malformed
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    /// A member is added twice
+    #[error("`{0}` is already in the chart")]
+    Duplicate(EntityUid),
+    /// A member's parent isn't in the chart
+    #[error("`{0}` isn't in the chart")]
+    UnknownParent(EntityUid),
+    /// A member's parent can't have members of its type, e.g. a team can't
+    /// have organizations
+    #[error("`{member}` can't be a member of `{parent}`")]
+    InvalidParent {
+        /// The member
+        member: EntityUid,
+        /// Its parent
+        parent: EntityUid,
+    },
+    /// A wallet isn't 20 bytes of `0x`-prefixed hex
+    #[error("`{0}` isn't an address")]
+    InvalidAddress(String),
+}
+
+/// The kinds of members of an [`OrgChart`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Organization,
+    Team,
+    Wallet,
+}
+
+impl Kind {
+    fn type_name(self) -> &'static str {
+        match self {
+            Self::Organization => ORGANIZATION_TYPE,
+            Self::Team => TEAM_TYPE,
+            Self::Wallet => WALLET_TYPE,
+        }
+    }
+
+    /// Whether members of this kind can belong to members of kind `parent`
+    fn can_belong_to(self, parent: Self) -> bool {
+        match self {
+            Self::Organization => parent == Self::Organization,
+            Self::Team | Self::Wallet => parent != Self::Wallet,
+        }
+    }
+
+    fn uid(self, id: &str) -> EntityUid {
+        // PANIC SAFETY: the names of the kinds are valid entity type names
+        #[allow(clippy::expect_used)]
+        let type_name =
+            EntityTypeName::from_str(self.type_name()).expect("should be a valid entity type name");
+        // PANIC SAFETY: `EntityId::from_str` never fails
+        #[allow(clippy::unwrap_used)]
+        let id = EntityId::from_str(id).unwrap();
+        EntityUid::from_type_name_and_id(type_name, id)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Member {
+    kind: Kind,
+    /// The member's parent, then its parent, and so on
+    ancestors: Vec<EntityUid>,
+}
+
+/// An organization in the JSON format of an [`OrgChart`]
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OrganizationJson {
+    id: String,
+    #[serde(default)]
+    organizations: Vec<Self>,
+    #[serde(default)]
+    teams: Vec<TeamJson>,
+    #[serde(default)]
+    wallets: Vec<String>,
+}
+
+/// A team in the JSON format of an [`OrgChart`]
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TeamJson {
+    id: String,
+    #[serde(default)]
+    teams: Vec<Self>,
+    #[serde(default)]
+    wallets: Vec<String>,
+}
+
+/// A tree of organizations, teams, and wallets
+#[derive(Debug, Clone, Default)]
+pub struct OrgChart {
+    members: HashMap<EntityUid, Member>,
+}
+
+impl OrgChart {
+    /// An empty chart
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A chart from a JSON array of organizations. Each organization has an
+    /// `id`, and optionally `organizations`, `teams`, and `wallets` arrays;
+    /// each team has an `id`, and optionally `teams` and `wallets` arrays.
+    /// Wallets are addresses.
+    pub fn from_json_str(json: &str) -> Result<Self, OrgChartError> {
+        let organizations: Vec<OrganizationJson> = serde_json::from_str(json)?;
+        let mut chart = Self::new();
+        for organization in &organizations {
+            chart.add_organization_json(organization, None)?;
+        }
+        Ok(chart)
+    }
+
+    fn add_organization_json(
+        &mut self,
+        organization: &OrganizationJson,
+        parent: Option<&EntityUid>,
+    ) -> Result<(), OrgChartError> {
+        let uid = self.add_organization(&organization.id, parent)?;
+        for child in &organization.organizations {
+            self.add_organization_json(child, Some(&uid))?;
+        }
+        for team in &organization.teams {
+            self.add_team_json(team, &uid)?;
+        }
+        for wallet in &organization.wallets {
+            self.add_wallet(wallet, &uid)?;
+        }
+        Ok(())
+    }
+
+    fn add_team_json(&mut self, team: &TeamJson, parent: &EntityUid) -> Result<(), OrgChartError> {
+        let uid = self.add_team(&team.id, parent)?;
+        for child in &team.teams {
+            self.add_team_json(child, &uid)?;
+        }
+        for wallet in &team.wallets {
+            self.add_wallet(wallet, &uid)?;
+        }
+        Ok(())
+    }
+
+    /// Add the organization `id`, belonging to the organization `parent` if
+    /// there is one, returning its uid
+    pub fn add_organization(
+        &mut self,
+        id: &str,
+        parent: Option<&EntityUid>,
+    ) -> Result<EntityUid, OrgChartError> {
+        self.add(Kind::Organization.uid(id), Kind::Organization, parent)
+    }
+
+    /// Add the team `id`, belonging to the organization or team `parent`,
+    /// returning its uid
+    pub fn add_team(&mut self, id: &str, parent: &EntityUid) -> Result<EntityUid, OrgChartError> {
+        self.add(Kind::Team.uid(id), Kind::Team, Some(parent))
+    }
+
+    /// Add the wallet `address`, belonging to the organization or team
+    /// `parent`, returning its uid. The address is lowercased.
+    pub fn add_wallet(
+        &mut self,
+        address: &str,
+        parent: &EntityUid,
+    ) -> Result<EntityUid, OrgChartError> {
+        let address = normalize_address(address)
+            .ok_or_else(|| OrgChartError::InvalidAddress(address.to_string()))?;
+        self.add(Kind::Wallet.uid(&address), Kind::Wallet, Some(parent))
+    }
+
+    fn add(
+        &mut self,
+        uid: EntityUid,
+        kind: Kind,
+        parent: Option<&EntityUid>,
+    ) -> Result<EntityUid, OrgChartError> {
+        if self.members.contains_key(&uid) {
+            return Err(OrgChartError::Duplicate(uid));
+        }
+        let ancestors = match parent {
+            None => Vec::new(),
+            Some(parent) => {
+                let member = self
+                    .members
+                    .get(parent)
+                    .ok_or_else(|| OrgChartError::UnknownParent(parent.clone()))?;
+                if !kind.can_belong_to(member.kind) {
+                    return Err(OrgChartError::InvalidParent {
+                        member: uid,
+                        parent: parent.clone(),
+                    });
+                }
+                std::iter::once(parent.clone())
+                    .chain(member.ancestors.iter().cloned())
+                    .collect()
+            }
+        };
+        self.members.insert(uid.clone(), Member { kind, ancestors });
+        Ok(uid)
+    }
+
+    /// Whether `uid` is in the chart
+    pub fn contains(&self, uid: &EntityUid) -> bool {
+        self.members.contains_key(uid)
+    }
+
+    /// The parent of `uid`, if it's in the chart and has one
+    pub fn parent(&self, uid: &EntityUid) -> Option<&EntityUid> {
+        self.ancestors(uid).first()
+    }
+
+    /// The ancestors of `uid`: its parent, then its parent's parent, and so
+    /// on. Empty if it isn't in the chart.
+    pub fn ancestors(&self, uid: &EntityUid) -> &[EntityUid] {
+        self.members
+            .get(uid)
+            .map_or(&[], |member| member.ancestors.as_slice())
+    }
+
+    /// Whether `uid` is `ancestor` or one of its descendants, as with `in`,
+    /// without building any entities
+    pub fn is_within(&self, uid: &EntityUid, ancestor: &EntityUid) -> bool {
+        uid == ancestor || self.ancestors(uid).contains(ancestor)
+    }
+
+    /// The chart's members as entities, without attributes, whose parents
+    /// are their ancestors
+    pub fn entities(&self) -> Result<Entities, EntitiesError> {
+        self.apply(&Entities::empty())
+    }
+
+    /// `entities` with the ancestors of the chart's members added to their
+    /// parents, and the members which aren't there added without attributes
+    pub fn apply(&self, entities: &Entities) -> Result<Entities, EntitiesError> {
+        let mut merged: Vec<ast::Entity> = Vec::with_capacity(entities.0.iter().count());
+        for entity in entities.0.iter() {
+            let ancestors = self.ancestors(&EntityUid(entity.uid()));
+            if ancestors.is_empty() {
+                merged.push(entity.clone());
+                continue;
+            }
+            merged.push(ast::Entity::new_with_tags(
+                entity.uid(),
+                entity
+                    .attrs()
+                    .map(|(key, value)| (key.into(), restricted(&value)))
+                    .collect(),
+                entity
+                    .ancestors()
+                    .cloned()
+                    .chain(ancestors.iter().map(|ancestor| ancestor.0.clone()))
+                    .collect(),
+                entity
+                    .tags()
+                    .map(|(key, value)| (key.into(), restricted(&value)))
+                    .collect(),
+            ));
+        }
+        for (uid, member) in &self.members {
+            if entities.get(uid).is_some() {
+                continue;
+            }
+            let ancestors: HashSet<_> = member
+                .ancestors
+                .iter()
+                .map(|ancestor| ancestor.0.clone())
+                .collect();
+            merged.push(ast::Entity::new(uid.0.clone(), HashMap::new(), ancestors));
+        }
+        // Existing entities may have parents of their own in the chart, or
+        // outside it, so the transitive closure is computed again
+        cedar_policy_core::entities::Entities::from_entities(merged, TCComputation::ComputeNow)
+            .map(Entities)
+    }
+}
+
+/// An owned copy of `expr`
+fn restricted(expr: &ast::Expr) -> ast::RestrictedExpr {
+    ast::RestrictedExpr::new_unchecked(expr.clone())
+}
+
+/// A schema fragment declaring the entity types of an [`OrgChart`]
+///
+/// Organizations may belong to organizations, teams to organizations and
+/// teams, and wallets to either.
+pub fn schema_fragment() -> Result<SchemaFragment, SchemaError> {
+    SchemaFragment::from_json_value(json!({
+        "": {
+            "entityTypes": {
+                ORGANIZATION_TYPE: { "memberOfTypes": [ORGANIZATION_TYPE] },
+                TEAM_TYPE: { "memberOfTypes": [ORGANIZATION_TYPE, TEAM_TYPE] },
+                WALLET_TYPE: { "memberOfTypes": [ORGANIZATION_TYPE, TEAM_TYPE] }
+            },
+            "actions": {}
+        }
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        AuthorizationError, Authorizer, Context, Decision, Entity, PolicySet, Request,
+        RestrictedExpression, Schema,
+    };
+    use std::sync::Arc;
+
+    const VAULT: &str = "0x00000000000000000000000000000000000000aa";
+    const HOT: &str = "0x00000000000000000000000000000000000000BB";
+
+    const CHART: &str = r#"[
+        {
+            "id": "acme",
+            "organizations": [{ "id": "acme-labs", "wallets": ["0x00000000000000000000000000000000000000cc"] }],
+            "teams": [
+                {
+                    "id": "treasury",
+                    "teams": [{ "id": "ops", "wallets": ["0x00000000000000000000000000000000000000BB"] }],
+                    "wallets": ["0x00000000000000000000000000000000000000aa"]
+                }
+            ]
+        },
+        { "id": "globex" }
+    ]"#;
+
+    fn uid(kind: Kind, id: &str) -> EntityUid {
+        kind.uid(id)
+    }
+
+    #[test]
+    fn charts() {
+        let chart = OrgChart::from_json_str(CHART).unwrap();
+        let acme = uid(Kind::Organization, "acme");
+        let treasury = uid(Kind::Team, "treasury");
+        let ops = uid(Kind::Team, "ops");
+        let hot = uid(Kind::Wallet, &HOT.to_lowercase());
+
+        assert_eq!(
+            chart.ancestors(&hot),
+            [ops.clone(), treasury.clone(), acme.clone()]
+        );
+        assert_eq!(chart.parent(&hot), Some(&ops));
+        assert_eq!(chart.parent(&acme), None);
+        assert!(chart.is_within(&hot, &acme));
+        assert!(chart.is_within(&treasury, &treasury));
+        assert!(!chart.is_within(&acme, &treasury));
+        assert!(!chart.is_within(&hot, &uid(Kind::Organization, "globex")));
+        assert!(chart.contains(&uid(Kind::Organization, "acme-labs")));
+        assert!(chart.ancestors(&uid(Kind::Team, "legal")).is_empty());
+
+        let entities = chart.entities().unwrap();
+        let wallet = entities.get(&hot).unwrap();
+        assert_eq!(wallet.uid(), hot);
+        let vault = uid(Kind::Wallet, VAULT);
+        assert!(entities.get(&vault).is_some());
+    }
+
+    #[test]
+    fn invalid_charts() {
+        let mut chart = OrgChart::new();
+        let acme = chart.add_organization("acme", None).unwrap();
+        let treasury = chart.add_team("treasury", &acme).unwrap();
+        let vault = chart.add_wallet(VAULT, &treasury).unwrap();
+        assert!(matches!(
+            chart.add_team("treasury", &acme),
+            Err(OrgChartError::Duplicate(uid)) if uid == treasury
+        ));
+        assert!(matches!(
+            chart.add_wallet(&VAULT.to_uppercase().replace("0X", "0x"), &acme),
+            Err(OrgChartError::Duplicate(_))
+        ));
+        assert!(matches!(
+            chart.add_team("ops", &uid(Kind::Team, "legal")),
+            Err(OrgChartError::UnknownParent(_))
+        ));
+        assert!(matches!(
+            chart.add_organization("labs", Some(&treasury)),
+            Err(OrgChartError::InvalidParent { .. })
+        ));
+        assert!(matches!(
+            chart.add_team("ops", &vault),
+            Err(OrgChartError::InvalidParent { .. })
+        ));
+        assert!(matches!(
+            chart.add_wallet("vault", &treasury),
+            Err(OrgChartError::InvalidAddress(_))
+        ));
+        assert!(matches!(
+            OrgChart::from_json_str(r#"[{ "id": "acme", "members": [] }]"#),
+            Err(OrgChartError::Json(_))
+        ));
+    }
+
+    #[test]
+    fn schemas() {
+        let fragment = schema_fragment().unwrap();
+        assert!(Schema::from_schema_fragments([fragment]).is_ok());
+    }
+
+    #[test]
+    fn policies_scoped_to_organizations_apply_to_descendants() {
+        let chart = OrgChart::from_json_str(CHART).unwrap();
+        let policies = PolicySet::from_str(
+            r#"permit(principal in Organization::"acme", action, resource)
+               when { context.amount <= 1000 };
+               forbid(principal in Team::"ops", action, resource)
+               when { context.amount > 100 };"#,
+        )
+        .unwrap();
+        // the wallets' entities have attributes but no parents
+        let entities = Entities::from_entities([Entity::new(
+            uid(Kind::Wallet, VAULT),
+            HashMap::from([(
+                "label".to_string(),
+                RestrictedExpression::new_string("vault".into()),
+            )]),
+            HashSet::new(),
+        )])
+        .unwrap();
+        let request = |wallet: &str, amount: i64| {
+            Request::new(
+                Some(uid(Kind::Wallet, &wallet.to_lowercase())),
+                Some(EntityUid::from_strs("Action", "transfer")),
+                Some(EntityUid::from_strs("Token", "usdc")),
+                Context::from_pairs([(
+                    "amount".to_string(),
+                    RestrictedExpression::new_long(amount),
+                )]),
+            )
+        };
+
+        let decide = |authorizer: &Authorizer, wallet: &str, amount: i64| {
+            authorizer
+                .is_authorized(&request(wallet, amount), &policies, &entities)
+                .decision()
+        };
+        let authorizer = Authorizer::new();
+        assert_eq!(decide(&authorizer, VAULT, 500), Decision::Deny);
+
+        let authorizer = Authorizer::new().with_org_chart(Arc::new(chart));
+        assert_eq!(decide(&authorizer, VAULT, 500), Decision::Allow);
+        assert_eq!(decide(&authorizer, VAULT, 5000), Decision::Deny);
+        assert_eq!(decide(&authorizer, HOT, 50), Decision::Allow);
+        assert_eq!(decide(&authorizer, HOT, 500), Decision::Deny);
+        assert_eq!(
+            decide(
+                &authorizer,
+                "0x00000000000000000000000000000000000000cc",
+                500
+            ),
+            Decision::Allow
+        );
+        assert_eq!(
+            decide(
+                &authorizer,
+                "0x00000000000000000000000000000000000000dd",
+                500
+            ),
+            Decision::Deny
+        );
+    }
+
+    #[test]
+    fn cyclic_hierarchies_are_denied() {
+        let mut chart = OrgChart::new();
+        let acme = chart.add_organization("acme", None).unwrap();
+        let vault = chart.add_wallet(VAULT, &acme).unwrap();
+        // the organization already belongs to its own wallet
+        let entities = Entities::from_entities([Entity::new(
+            acme,
+            HashMap::new(),
+            HashSet::from([vault.clone()]),
+        )])
+        .unwrap();
+        let policies = PolicySet::from_str("permit(principal, action, resource);").unwrap();
+        let request = Request::new(
+            Some(vault),
+            Some(EntityUid::from_strs("Action", "transfer")),
+            Some(EntityUid::from_strs("Token", "usdc")),
+            Context::empty(),
+        );
+        let response = Authorizer::new()
+            .with_org_chart(Arc::new(chart))
+            .is_authorized(&request, &policies, &entities);
+        assert_eq!(response.decision(), Decision::Deny);
+        assert!(matches!(
+            response.diagnostics().errors().next(),
+            Some(AuthorizationError::InvalidHierarchy(_))
+        ));
+    }
+}
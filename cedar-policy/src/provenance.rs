@@ -0,0 +1,534 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Signed provenance for policies and templates.
+//!
+//! Provenance is recorded in annotations: `@author` is the Ethereum address
+//! of the author, `@proposal` optionally names the governance proposal which
+//! approved the policy, and `@signature` is the author's signature over the
+//! policy's canonical form.
+//!
+//! The canonical form is the policy as printed by `Display`, without the
+//! `@signature` annotation, so it doesn't depend on the formatting of the
+//! source. It is signed as an Ethereum personal message (EIP-191), so
+//! signatures can be made with any Ethereum wallet; the signature is the
+//! 65-byte `r || s || v` form, hex-encoded with a `0x` prefix.
+//!
+//! Template-linked policies are covered by the signature on their template.
+//!
+//! A valid signature only shows that the policy was signed by its `@author`,
+//! which anyone with a key can claim to be. [`verify_policy_set()`] therefore
+//! also requires the author to be one of a set of [`TrustedAuthors`].
+
+use std::collections::HashSet;
+
+use k256::ecdsa::{RecoveryId, Signature, SigningKey, VerifyingKey};
+use ref_cast::RefCast;
+use sha3::{Digest, Keccak256};
+use smol_str::SmolStr;
+use thiserror::Error;
+
+use cedar_policy_core::ast;
+
+use crate::audit::{normalize_address, to_hex, unhex};
+use crate::{Policy, PolicyId, PolicySet, Template};
+
+/// Annotation holding the author's address
+pub const AUTHOR_ANNOTATION: &str = "author";
+/// Annotation holding the id of the governance proposal
+pub const PROPOSAL_ANNOTATION: &str = "proposal";
+/// Annotation holding the author's signature
+pub const SIGNATURE_ANNOTATION: &str = "signature";
+
+/// Verified provenance of a policy or template
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Provenance {
+    /// Address of the author, lowercase with a `0x` prefix
+    pub author: String,
+    /// The governance proposal which approved the policy, if any
+    pub proposal_id: Option<String>,
+}
+
+/// The addresses whose signatures [`verify_policy_set()`] accepts
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrustedAuthors(HashSet<String>);
+
+impl TrustedAuthors {
+    /// Trust the `0x`-prefixed hex addresses `addresses`, or else return the
+    /// first which is malformed
+    pub fn new<S: AsRef<str>>(
+        addresses: impl IntoIterator<Item = S>,
+    ) -> Result<Self, ProvenanceError> {
+        addresses
+            .into_iter()
+            .map(|address| {
+                let address = address.as_ref();
+                normalize_address(address)
+                    .ok_or_else(|| ProvenanceError::MalformedTrustedAddress(address.to_string()))
+            })
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+
+    /// Whether `address`, lowercase with a `0x` prefix, is trusted
+    pub fn contains(&self, address: &str) -> bool {
+        self.0.contains(address)
+    }
+}
+
+/// Whether unsigned policies are accepted by [`verify_policy_set()`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SignatureMode {
+    /// Accept unsigned policies, but reject signed policies whose signature
+    /// doesn't verify
+    #[default]
+    VerifyIfSigned,
+    /// Reject unsigned policies too
+    RequireSigned,
+}
+
+/// Errors verifying or making signatures
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ProvenanceError {
+    /// A policy has no signature, and signatures are required
+    #[error("policy `{0}` is not signed")]
+    Unsigned(PolicyId),
+    /// A policy has a signature but no `@author`
+    #[error("policy `{0}` is signed but has no `@author`")]
+    MissingAuthor(PolicyId),
+    /// The `@author` annotation isn't an address
+    #[error("policy `{id}` has malformed author address `{address}`")]
+    MalformedAddress {
+        /// Id of the policy
+        id: PolicyId,
+        /// The annotation value
+        address: String,
+    },
+    /// The `@signature` annotation isn't a 65-byte hex signature
+    #[error("policy `{0}` has a malformed signature")]
+    MalformedSignature(PolicyId),
+    /// The signature wasn't made by the author over this policy, e.g. because
+    /// the policy was modified after signing
+    #[error("signature on policy `{id}` was made by `{signer}`, not its author `{author}`")]
+    InvalidSignature {
+        /// Id of the policy
+        id: PolicyId,
+        /// The claimed author
+        author: String,
+        /// The address which made the signature. When the policy has been
+        /// modified this is an unrelated address.
+        signer: String,
+    },
+    /// A policy is validly signed by its author, but the author isn't trusted
+    #[error("policy `{id}` is signed by `{author}`, who is not a trusted author")]
+    UntrustedAuthor {
+        /// Id of the policy
+        id: PolicyId,
+        /// The author who signed it
+        author: String,
+    },
+    /// An address given as a trusted author is malformed
+    #[error("malformed trusted author address `{0}`")]
+    MalformedTrustedAddress(String),
+    /// A template-linked policy can't be signed; sign its template instead
+    #[error("policy `{0}` is template-linked; sign its template instead")]
+    Linked(PolicyId),
+    /// Signing failed
+    #[error("signing failed: {0}")]
+    Signing(String),
+}
+
+impl ProvenanceError {
+    /// Id of the policy or template with the problem, if any
+    pub fn policy_id(&self) -> Option<&PolicyId> {
+        match self {
+            Self::Unsigned(id)
+            | Self::MissingAuthor(id)
+            | Self::MalformedAddress { id, .. }
+            | Self::MalformedSignature(id)
+            | Self::InvalidSignature { id, .. }
+            | Self::UntrustedAuthor { id, .. }
+            | Self::Linked(id) => Some(id),
+            Self::MalformedTrustedAddress(_) | Self::Signing(_) => None,
+        }
+    }
+}
+
+/// The canonical form of a static policy or template-linked policy, which is
+/// the canonical form of its template
+pub fn canonical_form(policy: &Policy) -> String {
+    canonical_template_form(policy.ast.template())
+}
+
+/// The canonical form of a template
+pub fn template_canonical_form(template: &Template) -> String {
+    canonical_template_form(&template.ast)
+}
+
+fn canonical_template_form(template: &ast::Template) -> String {
+    let annotations = template
+        .annotations()
+        .filter(|(key, _)| AsRef::<str>::as_ref(*key) != SIGNATURE_ANNOTATION)
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    ast::Template::new(
+        template.id().clone(),
+        annotations,
+        template.effect(),
+        template.principal_constraint().clone(),
+        template.action_constraint().clone(),
+        template.resource_constraint().clone(),
+        template.non_head_constraints().clone(),
+    )
+    .to_string()
+}
+
+/// Verify the provenance of a static or template-linked policy. Returns
+/// `None` if it is unsigned.
+pub fn verify_policy(policy: &Policy) -> Result<Option<Provenance>, ProvenanceError> {
+    verify(policy.ast.template())
+}
+
+/// Verify the provenance of a template. Returns `None` if it is unsigned.
+pub fn verify_template(template: &Template) -> Result<Option<Provenance>, ProvenanceError> {
+    verify(&template.ast)
+}
+
+fn verify(template: &ast::Template) -> Result<Option<Provenance>, ProvenanceError> {
+    let id = PolicyId::ref_cast(template.id()).clone();
+    let Some(signature) = annotation(template, SIGNATURE_ANNOTATION) else {
+        return Ok(None);
+    };
+    let author = annotation(template, AUTHOR_ANNOTATION)
+        .ok_or_else(|| ProvenanceError::MissingAuthor(id.clone()))?;
+    let author = normalize_address(author).ok_or_else(|| ProvenanceError::MalformedAddress {
+        id: id.clone(),
+        address: author.to_string(),
+    })?;
+    let signer = recover(&canonical_template_form(template), signature)
+        .ok_or_else(|| ProvenanceError::MalformedSignature(id.clone()))?;
+    if signer != author {
+        return Err(ProvenanceError::InvalidSignature { id, author, signer });
+    }
+    Ok(Some(Provenance {
+        author,
+        proposal_id: annotation(template, PROPOSAL_ANNOTATION).map(ToString::to_string),
+    }))
+}
+
+/// Verify every static policy and template in `policies`, returning all the
+/// problems found. Signed policies must be signed by one of `trusted`.
+pub fn verify_policy_set(
+    policies: &PolicySet,
+    mode: SignatureMode,
+    trusted: &TrustedAuthors,
+) -> Result<(), Vec<ProvenanceError>> {
+    let errors: Vec<_> = policies
+        .policies()
+        .filter(|policy| policy.is_static())
+        .map(|policy| policy.ast.template())
+        .chain(policies.templates().map(|template| &template.ast))
+        .filter_map(|template| match verify(template) {
+            Ok(Some(provenance)) => {
+                (!trusted.contains(&provenance.author)).then(|| ProvenanceError::UntrustedAuthor {
+                    id: PolicyId::ref_cast(template.id()).clone(),
+                    author: provenance.author,
+                })
+            }
+            Ok(None) => (mode == SignatureMode::RequireSigned)
+                .then(|| ProvenanceError::Unsigned(PolicyId::ref_cast(template.id()).clone())),
+            Err(e) => Some(e),
+        })
+        .collect();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Sign a static policy with `key`, replacing any existing signature. The
+/// policy's `@author` should be [`address()`] of the key, or the signature
+/// won't verify.
+pub fn sign_policy(policy: &Policy, key: &SigningKey) -> Result<Policy, ProvenanceError> {
+    if !policy.is_static() {
+        return Err(ProvenanceError::Linked(policy.id().clone()));
+    }
+    let text = signed_text(&canonical_form(policy), key)?;
+    Policy::parse(Some(policy.id().as_ref().to_string()), text)
+        .map_err(|e| ProvenanceError::Signing(e.to_string()))
+}
+
+/// Sign a template with `key`, replacing any existing signature. The
+/// template's `@author` should be [`address()`] of the key, or the signature
+/// won't verify.
+pub fn sign_template(template: &Template, key: &SigningKey) -> Result<Template, ProvenanceError> {
+    let text = signed_text(&template_canonical_form(template), key)?;
+    Template::parse(Some(template.id().as_ref().to_string()), text)
+        .map_err(|e| ProvenanceError::Signing(e.to_string()))
+}
+
+/// `canonical` with a `@signature` annotation prepended
+fn signed_text(canonical: &str, key: &SigningKey) -> Result<String, ProvenanceError> {
+    Ok(format!(
+        "@{SIGNATURE_ANNOTATION}(\"{}\")\n{canonical}",
+        sign(canonical, key)?
+    ))
+}
+
+/// Sign `message` as an Ethereum personal message, returning the hex
+/// signature
+pub fn sign(message: &str, key: &SigningKey) -> Result<String, ProvenanceError> {
+    sign_prehash(&personal_message_hash(message), key)
+        .map_err(|e| ProvenanceError::Signing(e.to_string()))
+}
+
+/// Sign the 32-byte `digest`, returning the 65-byte `r || s || v` signature,
+/// hex-encoded with a `0x` prefix
+pub(crate) fn sign_prehash(
+    digest: &[u8; 32],
+    key: &SigningKey,
+) -> Result<String, k256::ecdsa::Error> {
+    let (signature, recovery_id) = key.sign_prehash_recoverable(digest)?;
+    let mut bytes = signature.to_bytes().to_vec();
+    bytes.push(27 + recovery_id.to_byte());
+    Ok(format!("0x{}", to_hex(&bytes)))
+}
+
+/// The Ethereum address of `key`, lowercase with a `0x` prefix
+pub fn address(key: &VerifyingKey) -> String {
+    let point = key.to_encoded_point(false);
+    // the uncompressed encoding is a tag byte followed by the coordinates
+    let coordinates = point.as_bytes().split_first().map_or(&[][..], |(_, c)| c);
+    let hash: [u8; 32] = Keccak256::digest(coordinates).into();
+    let (_, address) = hash.split_at(12);
+    format!("0x{}", to_hex(address))
+}
+
+/// The address which signed `message`, or `None` if `signature` is malformed
+fn recover(message: &str, signature: &str) -> Option<String> {
+    recover_prehash(&personal_message_hash(message), signature)
+}
+
+/// The address which signed the 32-byte `digest`, or `None` if `signature`
+/// is malformed
+pub(crate) fn recover_prehash(digest: &[u8; 32], signature: &str) -> Option<String> {
+    let bytes = unhex(signature.strip_prefix("0x").unwrap_or(signature))?;
+    let (rs, v) = match bytes.as_slice() {
+        [rs @ .., v] if rs.len() == 64 => (rs, *v),
+        _ => return None,
+    };
+    let recovery_id = RecoveryId::from_byte(if v >= 27 { v - 27 } else { v })?;
+    let signature = Signature::from_slice(rs).ok()?;
+    let key = VerifyingKey::recover_from_prehash(digest, &signature, recovery_id).ok()?;
+    Some(address(&key))
+}
+
+/// Keccak-256 of `message` with the EIP-191 personal message prefix
+fn personal_message_hash(message: &str) -> [u8; 32] {
+    let mut hasher = Keccak256::new();
+    hasher.update(format!("\x19Ethereum Signed Message:\n{}", message.len()));
+    hasher.update(message);
+    hasher.finalize().into()
+}
+
+fn annotation<'a>(template: &'a ast::Template, key: &str) -> Option<&'a str> {
+    template.annotation(&key.parse().ok()?).map(SmolStr::as_str)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{EntityUid, SlotId};
+    use std::collections::HashMap;
+    use std::str::FromStr;
+
+    fn key() -> SigningKey {
+        SigningKey::from_slice(&[7; 32]).unwrap()
+    }
+
+    fn authored(src: &str) -> String {
+        format!(
+            "@{AUTHOR_ANNOTATION}(\"{}\") @{PROPOSAL_ANNOTATION}(\"prop-7\") {src}",
+            address(key().verifying_key())
+        )
+    }
+
+    #[test]
+    fn known_address() {
+        // the address of the private key 0x00..01
+        let mut secret = [0; 32];
+        secret[31] = 1;
+        let key = SigningKey::from_slice(&secret).unwrap();
+        assert_eq!(
+            address(key.verifying_key()),
+            "0x7e5f4552091a69125d5dfcb7b8c2659029395bdf"
+        );
+    }
+
+    #[test]
+    fn sign_and_verify() {
+        let policy = Policy::parse(
+            Some("p".into()),
+            authored("permit(principal, action, resource) when { context.amount < 100 };"),
+        )
+        .unwrap();
+        assert_eq!(verify_policy(&policy), Ok(None));
+        let signed = sign_policy(&policy, &key()).unwrap();
+        assert_eq!(signed.id(), policy.id());
+        assert_eq!(
+            verify_policy(&signed),
+            Ok(Some(Provenance {
+                author: address(key().verifying_key()),
+                proposal_id: Some("prop-7".into()),
+            }))
+        );
+
+        // the signature doesn't depend on formatting
+        let reformatted =
+            Policy::parse(Some("p".into()), signed.to_string().replace('\n', " ")).unwrap();
+        assert!(verify_policy(&reformatted).unwrap().is_some());
+
+        // but does cover the policy
+        let tampered = Policy::parse(
+            Some("p".into()),
+            signed.to_string().replace("permit", "forbid"),
+        )
+        .unwrap();
+        assert!(matches!(
+            verify_policy(&tampered),
+            Err(ProvenanceError::InvalidSignature { .. })
+        ));
+    }
+
+    #[test]
+    fn malformed() {
+        let unsigned_author = Policy::parse(
+            Some("p".into()),
+            r#"@signature("0x00") permit(principal, action, resource);"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            verify_policy(&unsigned_author),
+            Err(ProvenanceError::MissingAuthor(_))
+        ));
+        let bad_address = Policy::parse(
+            Some("p".into()),
+            r#"@author("alice") @signature("0x00") permit(principal, action, resource);"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            verify_policy(&bad_address),
+            Err(ProvenanceError::MalformedAddress { .. })
+        ));
+        let bad_signature = Policy::parse(
+            Some("p".into()),
+            authored(r#"@signature("0x00") permit(principal, action, resource);"#),
+        )
+        .unwrap();
+        assert!(matches!(
+            verify_policy(&bad_signature),
+            Err(ProvenanceError::MalformedSignature(_))
+        ));
+    }
+
+    #[test]
+    fn policy_sets() {
+        let template = Template::parse(
+            Some("t".into()),
+            authored("permit(principal == ?principal, action, resource);"),
+        )
+        .unwrap();
+        let mut pset = PolicySet::new();
+        pset.add_template(sign_template(&template, &key()).unwrap())
+            .unwrap();
+        pset.link(
+            PolicyId::from_str("t").unwrap(),
+            PolicyId::from_str("link").unwrap(),
+            HashMap::from([(SlotId::principal(), EntityUid::from_strs("User", "alice"))]),
+        )
+        .unwrap();
+        let signed = Policy::parse(
+            Some("signed".into()),
+            authored("forbid(principal, action, resource);"),
+        )
+        .unwrap();
+        pset.add(sign_policy(&signed, &key()).unwrap()).unwrap();
+        assert!(matches!(
+            sign_policy(
+                pset.policy(&PolicyId::from_str("link").unwrap()).unwrap(),
+                &key()
+            ),
+            Err(ProvenanceError::Linked(_))
+        ));
+        let trusted = TrustedAuthors::new([address(key().verifying_key())]).unwrap();
+        assert_eq!(
+            verify_policy_set(&pset, SignatureMode::RequireSigned, &trusted),
+            Ok(())
+        );
+
+        pset.add(
+            Policy::parse(
+                Some("unsigned".into()),
+                "permit(principal, action, resource);",
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            verify_policy_set(&pset, SignatureMode::VerifyIfSigned, &trusted),
+            Ok(())
+        );
+        assert_eq!(
+            verify_policy_set(&pset, SignatureMode::RequireSigned, &trusted),
+            Err(vec![ProvenanceError::Unsigned(
+                PolicyId::from_str("unsigned").unwrap()
+            )])
+        );
+    }
+
+    #[test]
+    fn self_signed_policies_are_untrusted() {
+        let trusted = TrustedAuthors::new([address(key().verifying_key())]).unwrap();
+        let attacker = SigningKey::from_slice(&[9; 32]).unwrap();
+        let policy = Policy::parse(
+            Some("backdoor".into()),
+            format!(
+                r#"@author("{}") permit(principal, action, resource);"#,
+                address(attacker.verifying_key())
+            ),
+        )
+        .unwrap();
+        let signed = sign_policy(&policy, &attacker).unwrap();
+        // the signature itself is valid
+        assert!(verify_policy(&signed).unwrap().is_some());
+
+        let pset = PolicySet::from_policies([signed]).unwrap();
+        for mode in [SignatureMode::RequireSigned, SignatureMode::VerifyIfSigned] {
+            assert_eq!(
+                verify_policy_set(&pset, mode, &trusted),
+                Err(vec![ProvenanceError::UntrustedAuthor {
+                    id: PolicyId::from_str("backdoor").unwrap(),
+                    author: address(attacker.verifying_key()),
+                }])
+            );
+        }
+        assert!(matches!(
+            TrustedAuthors::new(["0x1234"]),
+            Err(ProvenanceError::MalformedTrustedAddress(_))
+        ));
+    }
+}
@@ -0,0 +1,384 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Rate limiting of requests with token buckets.
+//!
+//! Brute-force request patterns can be throttled at the decision layer. An
+//! [`Authorizer`](crate::Authorizer) given a [`RateLimiter`] with
+//! [`with_rate_limiter()`](crate::Authorizer::with_rate_limiter) limits
+//! requests in two ways:
+//!
+//! - With [`RateLimiter::with_limit()`], every request takes a token from
+//!   the bucket of its principal and action, and the tokens there before it
+//!   did are exposed to policies as `context.rate.remaining`, with the
+//!   bucket's capacity as `context.rate.limit`, e.g.
+//!   ```text
+//!   forbid(principal, action == Action::"login", resource)
+//!   when { context.rate.remaining == 0 };
+//!   ```
+//! - A `permit` policy annotated with [`RATE_LIMIT_ANNOTATION`], e.g.
+//!   `@rateLimit("10/m")`, allows each principal at most that many requests
+//!   per period. An `Allow` which only such policies determine takes a token
+//!   from the bucket of one of them and the principal, and is turned into a
+//!   `Deny` if they are all empty.
+//!
+//! Buckets start full and refill continuously. They are kept in a
+//! [`RateLimitStore`], so that they can be shared by several authorizers;
+//! [`MemoryRateLimitStore`] keeps them in memory.
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use thiserror::Error;
+
+use crate::{Decision, Effect, PolicySet, Request, Response, RestrictedExpression};
+
+/// The annotation limiting how often a `permit` policy allows requests from
+/// each principal, e.g. `@rateLimit("10/m")`
+pub const RATE_LIMIT_ANNOTATION: &str = "rateLimit";
+/// The context attribute holding the rate limit of a request's principal
+/// and action, a record with `remaining` and `limit` attributes
+pub const RATE_ATTRIBUTE: &str = "rate";
+
+/// A token bucket holding up to `capacity` tokens, refilled at `capacity`
+/// tokens per `period`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    /// The most tokens in the bucket
+    pub capacity: u64,
+    /// How long it takes to refill an empty bucket
+    pub period: Duration,
+}
+
+impl RateLimit {
+    /// At most `capacity` requests per `period`
+    pub fn new(capacity: u64, period: Duration) -> Self {
+        Self { capacity, period }
+    }
+}
+
+/// The limit in the value of a [`RATE_LIMIT_ANNOTATION`]
+///
+/// The value is a whole number of requests, `/`, and a unit of time: `s`,
+/// `m`, `h`, or `d`, e.g. `10/m`. Returns `None` if it isn't.
+pub fn parse_rate_limit_annotation(value: &str) -> Option<RateLimit> {
+    let (count, unit) = value.trim().split_once('/')?;
+    let seconds = match unit.trim() {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return None,
+    };
+    let count = count.trim();
+    if count.is_empty() || !count.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some(RateLimit::new(
+        count.parse().ok()?,
+        Duration::from_secs(seconds),
+    ))
+}
+
+/// An error from a [`RateLimitStore`]
+#[derive(Debug, Error)]
+#[error("rate limit store error: {0}")]
+pub struct RateLimitStoreError(pub String);
+
+/// Where a [`RateLimiter`] keeps its buckets. Each method must check and
+/// update atomically, so that concurrent requests can't take the same
+/// token.
+pub trait RateLimitStore: Debug + Send + Sync {
+    /// Refill the bucket `key` with `limit` up to `now`, in milliseconds
+    /// since the Unix epoch, and take a token from it if it has one,
+    /// returning the tokens it had. A new bucket is full.
+    fn take(&self, key: &str, limit: &RateLimit, now: u64) -> Result<u64, RateLimitStoreError>;
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: u64,
+    refilled_at: u64,
+}
+
+impl Bucket {
+    /// Add the tokens accrued between `refilled_at` and `now`. Only the time
+    /// those tokens took is used up, so that no fraction of a token is lost.
+    fn refill(&mut self, limit: &RateLimit, now: u64) {
+        let period = limit.period.as_millis().max(1);
+        let capacity = u128::from(limit.capacity);
+        let elapsed = u128::from(now.saturating_sub(self.refilled_at));
+        let added = u64::try_from(elapsed * capacity / period).unwrap_or(u64::MAX);
+        if added == 0 {
+            return;
+        }
+        self.tokens = self.tokens.saturating_add(added).min(limit.capacity);
+        self.refilled_at = if self.tokens == limit.capacity {
+            now
+        } else {
+            let used = u128::from(added) * period / capacity;
+            self.refilled_at
+                .saturating_add(u64::try_from(used).unwrap_or(u64::MAX))
+        };
+    }
+}
+
+/// A [`RateLimitStore`] in memory
+#[derive(Debug, Default)]
+pub struct MemoryRateLimitStore {
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl MemoryRateLimitStore {
+    /// An empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl RateLimitStore for MemoryRateLimitStore {
+    fn take(&self, key: &str, limit: &RateLimit, now: u64) -> Result<u64, RateLimitStoreError> {
+        let mut buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: limit.capacity,
+            refilled_at: now,
+        });
+        bucket.refill(limit, now);
+        let tokens = bucket.tokens;
+        bucket.tokens = tokens.saturating_sub(1);
+        drop(buckets);
+        Ok(tokens)
+    }
+}
+
+/// Why a request was refused by a rate limit
+#[derive(Debug, Error)]
+pub enum RateLimitError {
+    /// The buckets of all the `@rateLimit` policies which allowed the
+    /// request are empty
+    #[error("`{0}` exceeded the rate limit of the policies which allow the request")]
+    Exhausted(String),
+    /// The store failed
+    #[error(transparent)]
+    Store(#[from] RateLimitStoreError),
+}
+
+/// Limits how often principals are allowed
+#[derive(Debug)]
+pub struct RateLimiter {
+    store: Arc<dyn RateLimitStore>,
+    limit: Option<RateLimit>,
+}
+
+impl RateLimiter {
+    /// A rate limiter keeping its buckets in `store`, which only enforces
+    /// `@rateLimit` annotations
+    pub fn new(store: Arc<dyn RateLimitStore>) -> Self {
+        Self { store, limit: None }
+    }
+
+    /// The store the buckets are kept in
+    pub(crate) fn store(&self) -> &Arc<dyn RateLimitStore> {
+        &self.store
+    }
+
+    /// Also count every request against `limit` per principal and action,
+    /// exposing the tokens remaining as `context.rate`
+    #[must_use]
+    pub fn with_limit(mut self, limit: RateLimit) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// `request` with the [`RATE_ATTRIBUTE`] of its principal and action
+    /// added to its context, after taking a token from their bucket. Returns
+    /// `None` if there's no limit per principal and action.
+    pub fn count(&self, request: &Request) -> Result<Option<Request>, RateLimitError> {
+        let Some(limit) = &self.limit else {
+            return Ok(None);
+        };
+        let key = format!(
+            "{}|{}",
+            display(request.principal()),
+            display(request.action())
+        );
+        let remaining = self.store.take(&key, limit, now())?;
+        let long = |n: u64| RestrictedExpression::new_long(i64::try_from(n).unwrap_or(i64::MAX));
+        let rate = RestrictedExpression::new_record([
+            ("remaining".to_string(), long(remaining)),
+            ("limit".to_string(), long(limit.capacity)),
+        ]);
+        Ok(Some(request.with_context_attribute(RATE_ATTRIBUTE, rate)))
+    }
+
+    /// Check the `@rateLimit` annotations of the policies in `policies`
+    /// which determined `response` to `request`, taking a token if it's an
+    /// `Allow` which only such policies determined. A malformed annotation
+    /// counts as an empty bucket.
+    pub fn check(
+        &self,
+        request: &Request,
+        policies: &PolicySet,
+        response: &Response,
+    ) -> Result<(), RateLimitError> {
+        if response.decision() != Decision::Allow {
+            return Ok(());
+        }
+        let mut limited = Vec::new();
+        for id in response.diagnostics().reason() {
+            let Some(policy) = policies.policy(id) else {
+                continue;
+            };
+            if policy.effect() != Effect::Permit {
+                continue;
+            }
+            let Some(value) = policy.annotation(RATE_LIMIT_ANNOTATION) else {
+                return Ok(());
+            };
+            limited.push((id.to_string(), parse_rate_limit_annotation(value)));
+        }
+        limited.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let principal = display(request.principal());
+        let now = now();
+        for (id, limit) in limited {
+            let Some(limit) = limit else {
+                continue;
+            };
+            if self.store.take(&format!("{id}|{principal}"), &limit, now)? > 0 {
+                return Ok(());
+            }
+        }
+        Err(RateLimitError::Exhausted(principal))
+    }
+}
+
+fn display(uid: Option<&crate::EntityUid>) -> String {
+    uid.map(ToString::to_string).unwrap_or_default()
+}
+
+/// Milliseconds since the Unix epoch
+fn now() -> u64 {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_millis();
+    u64::try_from(millis).unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{AuthorizationError, Authorizer, Context, Entities, EntityUid};
+    use std::str::FromStr;
+
+    fn request(principal: &str, action: &str) -> Request {
+        Request::new(
+            Some(EntityUid::from_strs("User", principal)),
+            Some(EntityUid::from_strs("Action", action)),
+            Some(EntityUid::from_strs("Vault", "v")),
+            Context::empty(),
+        )
+    }
+
+    #[test]
+    fn annotations() {
+        assert_eq!(
+            parse_rate_limit_annotation("10/m"),
+            Some(RateLimit::new(10, Duration::from_mins(1)))
+        );
+        assert_eq!(
+            parse_rate_limit_annotation(" 5 / s "),
+            Some(RateLimit::new(5, Duration::from_secs(1)))
+        );
+        for value in ["", "10", "/m", "10/w", "-1/m", "1.5/h"] {
+            assert_eq!(parse_rate_limit_annotation(value), None, "{value}");
+        }
+    }
+
+    #[test]
+    fn buckets_refill() {
+        let store = MemoryRateLimitStore::new();
+        let limit = RateLimit::new(2, Duration::from_secs(1));
+        assert_eq!(store.take("k", &limit, 0).unwrap(), 2);
+        assert_eq!(store.take("k", &limit, 0).unwrap(), 1);
+        assert_eq!(store.take("k", &limit, 100).unwrap(), 0);
+        // one token takes 500ms
+        assert_eq!(store.take("k", &limit, 499).unwrap(), 0);
+        assert_eq!(store.take("k", &limit, 500).unwrap(), 1);
+        assert_eq!(store.take("k", &limit, 10_000).unwrap(), 2);
+        // buckets are separate
+        assert_eq!(store.take("other", &limit, 0).unwrap(), 2);
+    }
+
+    #[test]
+    fn limits_per_principal_and_action() {
+        let limiter = RateLimiter::new(Arc::new(MemoryRateLimitStore::new()))
+            .with_limit(RateLimit::new(2, Duration::from_hours(1)));
+        let authorizer = Authorizer::new().with_rate_limiter(Arc::new(limiter));
+        let policies = PolicySet::from_str(
+            r#"permit(principal, action, resource);
+               forbid(principal, action == Action::"login", resource)
+               when { context.rate.remaining == 0 };"#,
+        )
+        .unwrap();
+        let decide = |principal, action| {
+            authorizer
+                .is_authorized(&request(principal, action), &policies, &Entities::empty())
+                .decision()
+        };
+        assert_eq!(decide("alice", "login"), Decision::Allow);
+        assert_eq!(decide("alice", "login"), Decision::Allow);
+        assert_eq!(decide("alice", "login"), Decision::Deny);
+        assert_eq!(decide("alice", "view"), Decision::Allow);
+        assert_eq!(decide("bob", "login"), Decision::Allow);
+    }
+
+    #[test]
+    fn limits_by_annotation() {
+        let limiter = RateLimiter::new(Arc::new(MemoryRateLimitStore::new()));
+        let authorizer = Authorizer::new().with_rate_limiter(Arc::new(limiter));
+        let policies = PolicySet::from_str(
+            r#"@rateLimit("1/h")
+               permit(principal, action == Action::"withdraw", resource);
+               @rateLimit("1/h")
+               permit(principal, action in [Action::"withdraw", Action::"view"], resource);
+               @rateLimit("often")
+               permit(principal, action == Action::"transfer", resource);
+               permit(principal, action == Action::"view", resource);"#,
+        )
+        .unwrap();
+        let authorize = |principal, action| {
+            authorizer.is_authorized(&request(principal, action), &policies, &Entities::empty())
+        };
+        // either policy's bucket will do
+        assert_eq!(authorize("alice", "withdraw").decision(), Decision::Allow);
+        assert_eq!(authorize("alice", "withdraw").decision(), Decision::Allow);
+        let response = authorize("alice", "withdraw");
+        assert_eq!(response.decision(), Decision::Deny);
+        assert!(matches!(
+            response.diagnostics().errors().next(),
+            Some(AuthorizationError::RateLimited(_))
+        ));
+        assert_eq!(authorize("bob", "withdraw").decision(), Decision::Allow);
+        // an unlimited policy also allows it
+        assert_eq!(authorize("alice", "view").decision(), Decision::Allow);
+        assert_eq!(authorize("alice", "view").decision(), Decision::Allow);
+        // a typo never allows more
+        assert_eq!(authorize("alice", "transfer").decision(), Decision::Deny);
+    }
+}
@@ -0,0 +1,355 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Signed receipts of authorization decisions.
+//!
+//! A [`DecisionReceipt`] states that a request with a given digest was
+//! decided under a policy set with a given digest, using the digests of
+//! [`crate::audit`]. Receipts are signed with ECDSA over secp256k1, so they
+//! can be checked by anyone holding the public key, including contracts which
+//! recover the signer on chain.
+//!
+//! The signed message is [`DecisionReceipt::canonical_bytes()`], a JSON
+//! object with no whitespace and fields in a fixed order. The signature is
+//! over its SHA-256 digest, and is the 64-byte `r || s` form with `s`
+//! normalized to the lower half of the curve order.
+
+use std::time::SystemTime;
+
+use k256::ecdsa::signature::{Signer, Verifier};
+use k256::ecdsa::{Signature, SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::audit::{policy_set_digest, request_digest, to_hex, unhex, unix_seconds, AuditRecord};
+use crate::{Decision, PolicySet, Request, Response};
+
+/// Version of the canonical serialization, included in every receipt
+pub const RECEIPT_VERSION: u32 = 1;
+
+/// The facts attested to by a receipt
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DecisionReceipt {
+    /// Version of the serialization, currently [`RECEIPT_VERSION`]
+    pub version: u32,
+    /// Hex-encoded digest of the request, as computed by
+    /// [`request_digest()`]
+    pub request_hash: String,
+    /// Hex-encoded digest of the policy set, as computed by
+    /// [`policy_set_digest()`]
+    pub policy_set_hash: String,
+    /// The decision
+    pub decision: Decision,
+    /// Ids of the policies which determined the decision, sorted
+    pub determining_policies: Vec<String>,
+    /// When the decision was made, in seconds since the Unix epoch
+    pub timestamp: u64,
+}
+
+impl DecisionReceipt {
+    /// The receipt for answering `request` against `policies` with
+    /// `response` at `time`
+    pub fn new(
+        request: &Request,
+        policies: &PolicySet,
+        response: &Response,
+        time: SystemTime,
+    ) -> Self {
+        let mut determining_policies: Vec<_> = response
+            .diagnostics()
+            .reason()
+            .map(ToString::to_string)
+            .collect();
+        determining_policies.sort();
+        Self {
+            version: RECEIPT_VERSION,
+            request_hash: request_digest(request),
+            policy_set_hash: policy_set_digest(policies),
+            decision: response.decision(),
+            determining_policies,
+            timestamp: unix_seconds(time),
+        }
+    }
+
+    /// The receipt for the decision described by an audit record, made at
+    /// `time`
+    pub fn from_audit_record(record: &AuditRecord, time: SystemTime) -> Self {
+        Self {
+            version: RECEIPT_VERSION,
+            request_hash: record.request_hash.clone(),
+            policy_set_hash: record.policy_set_hash.clone(),
+            decision: record.decision,
+            determining_policies: record.determining_policies.clone(),
+            timestamp: unix_seconds(time),
+        }
+    }
+
+    /// The message which is signed
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        // PANIC SAFETY: serializing a struct of strings and integers can't fail
+        #[allow(clippy::expect_used)]
+        serde_json::to_vec(self).expect("receipt should serialize")
+    }
+
+    /// Sign the receipt with `signer`
+    pub fn sign(self, signer: &impl ReceiptSigner) -> Result<SignedReceipt, ReceiptError> {
+        let signature = signer.sign(&self.canonical_bytes())?;
+        Ok(SignedReceipt::new(self, signer.key_id(), &signature))
+    }
+}
+
+/// A receipt and the signature over its canonical serialization
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignedReceipt {
+    /// The receipt
+    pub receipt: DecisionReceipt,
+    /// Identifies the key which signed the receipt, e.g. a KMS key ARN
+    pub key_id: String,
+    /// Hex-encoded 64-byte `r || s` signature
+    pub signature: String,
+}
+
+impl SignedReceipt {
+    fn new(receipt: DecisionReceipt, key_id: String, signature: &Signature) -> Self {
+        let signature = signature.normalize_s().unwrap_or(*signature);
+        Self {
+            receipt,
+            key_id,
+            signature: to_hex(&signature.to_bytes()),
+        }
+    }
+
+    /// Check that the receipt was signed by the holder of `key`
+    pub fn verify(&self, key: &VerifyingKey) -> Result<(), ReceiptError> {
+        let bytes = unhex(&self.signature).ok_or(ReceiptError::MalformedSignature)?;
+        let signature =
+            Signature::from_slice(&bytes).map_err(|_| ReceiptError::MalformedSignature)?;
+        key.verify(&self.receipt.canonical_bytes(), &signature)
+            .map_err(|_| ReceiptError::InvalidSignature)
+    }
+}
+
+/// Signs receipts
+pub trait ReceiptSigner {
+    /// Identifies the signing key, recorded in each receipt
+    fn key_id(&self) -> String;
+
+    /// Sign `message` with ECDSA over secp256k1 and SHA-256
+    fn sign(&self, message: &[u8]) -> Result<Signature, ReceiptError>;
+}
+
+/// Signs with a key held in memory
+#[derive(Debug, Clone)]
+pub struct LocalSigner {
+    key: SigningKey,
+    key_id: String,
+}
+
+impl LocalSigner {
+    /// Sign with `key`, identified in receipts by `key_id`
+    pub fn new(key: SigningKey, key_id: impl Into<String>) -> Self {
+        Self {
+            key,
+            key_id: key_id.into(),
+        }
+    }
+
+    /// The key which verifies this signer's receipts
+    pub fn verifying_key(&self) -> VerifyingKey {
+        *self.key.verifying_key()
+    }
+}
+
+impl ReceiptSigner for LocalSigner {
+    fn key_id(&self) -> String {
+        self.key_id.clone()
+    }
+
+    fn sign(&self, message: &[u8]) -> Result<Signature, ReceiptError> {
+        self.key
+            .try_sign(message)
+            .map_err(|e| ReceiptError::Signing(e.to_string()))
+    }
+}
+
+/// Signs with an AWS KMS key whose key spec is `ECC_SECG_P256K1`.
+///
+/// The KMS client is asynchronous, so this doesn't implement
+/// [`ReceiptSigner`]; use [`KmsSigner::sign()`] instead of
+/// [`DecisionReceipt::sign()`].
+#[cfg(feature = "aws-kms")]
+#[derive(Debug, Clone)]
+pub struct KmsSigner {
+    client: aws_sdk_kms::Client,
+    key_id: String,
+}
+
+#[cfg(feature = "aws-kms")]
+impl KmsSigner {
+    /// Sign with the KMS key `key_id`, which may be a key id, alias, or ARN
+    pub fn new(client: aws_sdk_kms::Client, key_id: impl Into<String>) -> Self {
+        Self {
+            client,
+            key_id: key_id.into(),
+        }
+    }
+
+    /// Sign `receipt`
+    pub async fn sign(&self, receipt: DecisionReceipt) -> Result<SignedReceipt, ReceiptError> {
+        use aws_sdk_kms::primitives::Blob;
+        use aws_sdk_kms::types::{MessageType, SigningAlgorithmSpec};
+        use sha2::{Digest, Sha256};
+
+        let digest = Sha256::digest(receipt.canonical_bytes());
+        let output = self
+            .client
+            .sign()
+            .key_id(&self.key_id)
+            .message(Blob::new(digest.to_vec()))
+            .message_type(MessageType::Digest)
+            .signing_algorithm(SigningAlgorithmSpec::EcdsaSha256)
+            .send()
+            .await
+            .map_err(|e| ReceiptError::Signing(e.to_string()))?;
+        let der = output
+            .signature()
+            .ok_or_else(|| ReceiptError::Signing("KMS returned no signature".into()))?;
+        // KMS returns a DER-encoded signature
+        let signature =
+            Signature::from_der(der.as_ref()).map_err(|_| ReceiptError::MalformedSignature)?;
+        let key_id = output.key_id().unwrap_or(&self.key_id).to_string();
+        Ok(SignedReceipt::new(receipt, key_id, &signature))
+    }
+}
+
+/// Errors signing or verifying receipts
+#[derive(Debug, Error)]
+pub enum ReceiptError {
+    /// The signer failed
+    #[error("failed to sign receipt: {0}")]
+    Signing(String),
+    /// The signature isn't a hex-encoded 64-byte ECDSA signature
+    #[error("malformed receipt signature")]
+    MalformedSignature,
+    /// The signature doesn't match the receipt and key
+    #[error("receipt signature is invalid")]
+    InvalidSignature,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Authorizer, Context, Entities, EntityUid};
+    use std::str::FromStr;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn signer() -> LocalSigner {
+        let key = SigningKey::from_slice(&[7; 32]).unwrap();
+        LocalSigner::new(key, "test-key")
+    }
+
+    fn receipt() -> DecisionReceipt {
+        let policies = PolicySet::from_str(
+            r#"permit(principal == User::"alice", action, resource);
+               permit(principal, action == Action::"read", resource);"#,
+        )
+        .unwrap();
+        let request = Request::new(
+            Some(EntityUid::from_strs("User", "alice")),
+            Some(EntityUid::from_strs("Action", "read")),
+            None,
+            Context::empty(),
+        );
+        let response = Authorizer::new().is_authorized(&request, &policies, &Entities::empty());
+        DecisionReceipt::new(
+            &request,
+            &policies,
+            &response,
+            UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+        )
+    }
+
+    #[test]
+    fn canonical_serialization() {
+        let receipt = receipt();
+        assert_eq!(
+            String::from_utf8(receipt.canonical_bytes()).unwrap(),
+            format!(
+                r#"{{"version":1,"requestHash":"{}","policySetHash":"{}","decision":"Allow","determiningPolicies":["policy0","policy1"],"timestamp":1700000000}}"#,
+                receipt.request_hash, receipt.policy_set_hash
+            )
+        );
+    }
+
+    #[test]
+    fn sign_and_verify() {
+        let signer = signer();
+        let signed = receipt().sign(&signer).unwrap();
+        assert_eq!(signed.key_id, "test-key");
+        assert_eq!(signed.signature.len(), 128);
+        signed.verify(&signer.verifying_key()).unwrap();
+
+        // survives a round trip through JSON
+        let json = serde_json::to_string(&signed).unwrap();
+        let parsed: SignedReceipt = serde_json::from_str(&json).unwrap();
+        parsed.verify(&signer.verifying_key()).unwrap();
+
+        // tampering is detected
+        let mut tampered = signed.clone();
+        tampered.receipt.decision = Decision::Deny;
+        assert!(matches!(
+            tampered.verify(&signer.verifying_key()),
+            Err(ReceiptError::InvalidSignature)
+        ));
+        let other = LocalSigner::new(SigningKey::from_slice(&[9; 32]).unwrap(), "other");
+        assert!(matches!(
+            signed.verify(&other.verifying_key()),
+            Err(ReceiptError::InvalidSignature)
+        ));
+        let mut garbled = signed;
+        garbled.signature.truncate(10);
+        assert!(matches!(
+            garbled.verify(&signer.verifying_key()),
+            Err(ReceiptError::MalformedSignature)
+        ));
+    }
+
+    #[test]
+    fn from_audit_record() {
+        let receipt = receipt();
+        let record = AuditRecord {
+            request_hash: receipt.request_hash.clone(),
+            decision: receipt.decision,
+            determining_policies: receipt.determining_policies.clone(),
+            policy_set_hash: receipt.policy_set_hash.clone(),
+            duration: Duration::from_micros(5),
+            extension_values: vec![],
+            request: None,
+            entities: None,
+            shadow: None,
+            tenant: None,
+        };
+        assert_eq!(
+            DecisionReceipt::from_audit_record(
+                &record,
+                UNIX_EPOCH + Duration::from_secs(1_700_000_000)
+            ),
+            receipt
+        );
+    }
+}
@@ -0,0 +1,571 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Suggested repairs for policies which break an invariant or don't validate.
+//!
+//! A [`Repair`] is a patch to one policy, or to the template of a linked
+//! policy: a list of small [`Edit`]s, and the text of the policy before and
+//! after them. For a [`Violation`] of an invariant, each conjunct of the
+//! invariant's condition which the policy doesn't already include is added
+//! with the smallest edit which includes it:
+//!
+//! - a looser bound on the same expression, e.g. `context.amount <= 1000`
+//!   for `context.amount <= 100`, is tightened;
+//! - an unconstrained `principal` or `resource` scope is constrained, e.g.
+//!   to `principal in Group::"signers"`;
+//! - otherwise the conjunct is added to the policy's conditions.
+//!
+//! For validation errors, unrecognized entity types and actions are renamed
+//! to the validator's suggestions, and `==` is replaced with `in` in scopes
+//! where that makes an action applicable.
+//!
+//! With the `analysis` feature, [`repair_violations_with()`] only suggests
+//! repairs after which a [`Verifier`](crate::analysis::Verifier) finds no
+//! counterexample, falling back to adding the whole invariant condition.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use cedar_policy_core::ast::{
+    self, BinaryOp, EntityReference, ExprKind, Literal, PrincipalConstraint,
+    PrincipalOrResourceConstraint, ResourceConstraint, Var,
+};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "analysis")]
+use crate::analysis::{AnalysisError, Verifier};
+use crate::invariants::{conjuncts_of, Invariant, Violation};
+use crate::{PolicySet, ValidationErrorKind, ValidationResult};
+
+/// A small change to a policy
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum Edit {
+    /// Replace a bound in the conditions with a tighter one
+    TightenBound {
+        /// The bound in the policy
+        from: String,
+        /// The bound replacing it
+        to: String,
+    },
+    /// Constrain the `principal` or `resource` scope
+    ConstrainScope {
+        /// `principal` or `resource`
+        variable: String,
+        /// The new scope constraint
+        constraint: String,
+    },
+    /// Add a condition
+    AddCondition {
+        /// The condition
+        condition: String,
+    },
+    /// Rename an entity type or action
+    Rename {
+        /// The name in the policy
+        from: String,
+        /// The name replacing it
+        to: String,
+    },
+}
+
+/// A patch to one policy, or to the template of a linked policy
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Repair {
+    /// Id of the policy or template to edit
+    pub policy: String,
+    /// What the repair fixes
+    pub reason: String,
+    /// The edits
+    pub edits: Vec<Edit>,
+    /// The text of the policy
+    pub original: String,
+    /// The text of the policy with the edits made
+    pub repaired: String,
+}
+
+/// The parts of a policy which repairs edit
+struct Draft {
+    principal: PrincipalConstraint,
+    resource: ResourceConstraint,
+    conditions: Vec<ast::Expr>,
+    edits: Vec<Edit>,
+}
+
+impl Draft {
+    fn new(template: &ast::Template) -> Self {
+        let mut conditions: Vec<ast::Expr> = conjuncts_of(template.non_head_constraints())
+            .into_iter()
+            .cloned()
+            .collect();
+        conditions.retain(|c| !matches!(c.expr_kind(), ExprKind::Lit(Literal::Bool(true))));
+        Self {
+            principal: template.principal_constraint().clone(),
+            resource: template.resource_constraint().clone(),
+            conditions,
+            edits: Vec::new(),
+        }
+    }
+
+    fn includes(&self, required: &ast::Expr) -> bool {
+        self.principal.as_expr().eq_shape(required)
+            || self.resource.as_expr().eq_shape(required)
+            || self.conditions.iter().any(|c| c.eq_shape(required))
+    }
+
+    /// Make the smallest edit which includes `required`
+    fn require(&mut self, required: &ast::Expr) {
+        if self.includes(required) {
+            return;
+        }
+        if let Some((bounded, bound)) = bound_of(required) {
+            let looser = self.conditions.iter_mut().find(|c| {
+                bound_of(c).is_some_and(|(other, other_bound)| {
+                    other.eq_shape(bounded) && bound.tighter_than(other_bound)
+                })
+            });
+            if let Some(looser) = looser {
+                self.edits.push(Edit::TightenBound {
+                    from: looser.to_string(),
+                    to: required.to_string(),
+                });
+                *looser = required.clone();
+                return;
+            }
+        }
+        if let Some((var, constraint)) = scope_of(required) {
+            let unconstrained = match var {
+                Var::Principal => {
+                    matches!(
+                        self.principal.as_inner(),
+                        PrincipalOrResourceConstraint::Any
+                    )
+                }
+                _ => matches!(self.resource.as_inner(), PrincipalOrResourceConstraint::Any),
+            };
+            if unconstrained {
+                self.edits.push(Edit::ConstrainScope {
+                    variable: var.to_string(),
+                    constraint: required.to_string(),
+                });
+                match var {
+                    Var::Principal => self.principal = PrincipalConstraint::new(constraint),
+                    _ => self.resource = ResourceConstraint::new(constraint),
+                }
+                return;
+            }
+        }
+        self.edits.push(Edit::AddCondition {
+            condition: required.to_string(),
+        });
+        self.conditions.push(required.clone());
+    }
+
+    fn repair(self, template: &ast::Template, reason: String) -> Repair {
+        let conditions = self
+            .conditions
+            .into_iter()
+            .reduce(ast::Expr::and)
+            .unwrap_or_else(|| ast::Expr::val(true));
+        let repaired = ast::Template::new(
+            template.id().clone(),
+            template
+                .annotations()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect::<BTreeMap<_, _>>(),
+            template.effect(),
+            self.principal,
+            template.action_constraint().clone(),
+            self.resource,
+            conditions,
+        );
+        let mut repaired = repaired.to_string();
+        for edit in &self.edits {
+            if let Edit::Rename { from, to } = edit {
+                repaired = rename(&repaired, from, to);
+            }
+        }
+        Repair {
+            policy: template.id().as_ref().to_string(),
+            reason,
+            edits: self.edits,
+            original: template.to_string(),
+            repaired,
+        }
+    }
+}
+
+/// An inclusive bound on a `Long` expression
+#[derive(Debug, Clone, Copy)]
+enum Bound {
+    Upper(i64),
+    Lower(i64),
+}
+
+impl Bound {
+    fn tighter_than(self, other: Self) -> bool {
+        match (self, other) {
+            (Self::Upper(a), Self::Upper(b)) => a < b,
+            (Self::Lower(a), Self::Lower(b)) => a > b,
+            _ => false,
+        }
+    }
+}
+
+/// The expression `e` bounds, and the bound, if `e` compares an expression
+/// with a literal
+fn bound_of(e: &ast::Expr) -> Option<(&ast::Expr, Bound)> {
+    let ExprKind::BinaryApp { op, arg1, arg2 } = e.expr_kind() else {
+        return None;
+    };
+    let strict = match op {
+        BinaryOp::Less => 1,
+        BinaryOp::LessEq => 0,
+        _ => return None,
+    };
+    match (arg1.expr_kind(), arg2.expr_kind()) {
+        (_, ExprKind::Lit(Literal::Long(n))) => Some((arg1, Bound::Upper(n.checked_sub(strict)?))),
+        (ExprKind::Lit(Literal::Long(n)), _) => Some((arg2, Bound::Lower(n.checked_add(strict)?))),
+        _ => None,
+    }
+}
+
+/// The scope constraint equivalent to `e`, if `e` is `principal` or
+/// `resource` `==` or `in` an entity literal
+fn scope_of(e: &ast::Expr) -> Option<(Var, PrincipalOrResourceConstraint)> {
+    let ExprKind::BinaryApp { op, arg1, arg2 } = e.expr_kind() else {
+        return None;
+    };
+    let (
+        ExprKind::Var(var @ (Var::Principal | Var::Resource)),
+        ExprKind::Lit(Literal::EntityUID(uid)),
+    ) = (arg1.expr_kind(), arg2.expr_kind())
+    else {
+        return None;
+    };
+    let reference = EntityReference::EUID(uid.clone());
+    match op {
+        BinaryOp::Eq => Some((*var, PrincipalOrResourceConstraint::Eq(reference))),
+        BinaryOp::In => Some((*var, PrincipalOrResourceConstraint::In(reference))),
+        _ => None,
+    }
+}
+
+/// A repair for `violation` of an invariant in `invariants`. If `whole`,
+/// the invariant's condition is added as one condition.
+fn repair_violation(
+    violation: &Violation,
+    invariants: &[Invariant],
+    policies: &PolicySet,
+    whole: bool,
+) -> Option<Repair> {
+    let invariant = invariants
+        .iter()
+        .find(|invariant| invariant.name() == violation.invariant())?;
+    let template = policies
+        .ast
+        .get(&ast::PolicyID::from_string(violation.policy().as_ref()))?
+        .template();
+    let mut draft = Draft::new(template);
+    if whole {
+        draft.edits.push(Edit::AddCondition {
+            condition: invariant.condition().0.to_string(),
+        });
+        draft.conditions.push(invariant.condition().0.clone());
+    } else {
+        for required in conjuncts_of(&invariant.condition().0) {
+            draft.require(required);
+        }
+    }
+    Some(draft.repair(template, violation.to_string()))
+}
+
+/// Repairs for the policies in `violations` of `invariants` in `policies`
+pub fn repair_violations(
+    violations: &[Violation],
+    invariants: &[Invariant],
+    policies: &PolicySet,
+) -> Vec<Repair> {
+    violations
+        .iter()
+        .filter_map(|violation| repair_violation(violation, invariants, policies, false))
+        .collect()
+}
+
+/// Repairs for the policies in `violations`, checked with `verifier`
+///
+/// A repair of a static policy after which the policy still breaks the
+/// invariant is replaced with one adding the whole invariant condition.
+#[cfg(feature = "analysis")]
+pub fn repair_violations_with(
+    violations: &[Violation],
+    invariants: &[Invariant],
+    policies: &PolicySet,
+    verifier: &Verifier<'_>,
+) -> Result<Vec<Repair>, AnalysisError> {
+    let mut repairs = Vec::new();
+    for violation in violations {
+        let Some(repair) = repair_violation(violation, invariants, policies, false) else {
+            continue;
+        };
+        let repaired = crate::Policy::parse(Some(repair.policy.clone()), &repair.repaired);
+        let mut patched = policies.clone();
+        let keeps = match repaired {
+            Ok(repaired) if patched.remove_static(violation.policy()).is_some() => {
+                patched.add(repaired).is_ok()
+                    && crate::invariants::check_with(invariants, &patched, verifier)?
+                        .iter()
+                        .all(|other| {
+                            other.policy() != violation.policy()
+                                || other.invariant() != violation.invariant()
+                        })
+            }
+            // template-linked policies are repaired through their template,
+            // which isn't checked
+            _ => true,
+        };
+        if keeps {
+            repairs.push(repair);
+        } else if let Some(repair) = repair_violation(violation, invariants, policies, true) {
+            repairs.push(repair);
+        }
+    }
+    Ok(repairs)
+}
+
+/// Repairs for the errors in `result`, from validating `policies`. Errors
+/// with no suggested repair are skipped.
+pub fn repair_validation(result: &ValidationResult<'_>, policies: &PolicySet) -> Vec<Repair> {
+    let mut drafts: BTreeMap<String, (Arc<ast::Template>, Draft, Vec<String>)> = BTreeMap::new();
+    for error in result.validation_errors() {
+        let Some(template) = policies.ast.get_template(&ast::PolicyID::from_string(
+            error.location().policy_id().as_ref(),
+        )) else {
+            continue;
+        };
+        let (_, draft, reasons) = drafts.entry(template.id().as_ref().to_string()).or_insert_with(|| {
+            let draft = Draft::new(&template);
+            (template, draft, Vec::new())
+        });
+        let edits = draft.edits.len();
+        match error.error_kind() {
+            ValidationErrorKind::UnrecognizedEntityType(err) => {
+                if let Some(suggested) = err.suggested_entity_type() {
+                    draft.edits.push(Edit::Rename {
+                        from: err.actual_entity_type().to_string(),
+                        to: suggested.to_string(),
+                    });
+                }
+            }
+            ValidationErrorKind::UnrecognizedActionId(err) => {
+                if let Some(suggested) = err.suggested_action_id() {
+                    draft.edits.push(Edit::Rename {
+                        from: err.actual_action_id().to_string(),
+                        to: suggested.to_string(),
+                    });
+                }
+            }
+            ValidationErrorKind::InvalidActionApplication(err) => {
+                if err.would_in_fix_principal() {
+                    if let PrincipalOrResourceConstraint::Eq(reference) =
+                        draft.principal.as_inner().clone()
+                    {
+                        draft.principal =
+                            PrincipalConstraint::new(PrincipalOrResourceConstraint::In(reference));
+                        draft.edits.push(Edit::ConstrainScope {
+                            variable: "principal".to_string(),
+                            constraint: draft.principal.to_string(),
+                        });
+                    }
+                }
+                if err.would_in_fix_resource() {
+                    if let PrincipalOrResourceConstraint::Eq(reference) =
+                        draft.resource.as_inner().clone()
+                    {
+                        draft.resource =
+                            ResourceConstraint::new(PrincipalOrResourceConstraint::In(reference));
+                        draft.edits.push(Edit::ConstrainScope {
+                            variable: "resource".to_string(),
+                            constraint: draft.resource.to_string(),
+                        });
+                    }
+                }
+            }
+            _ => (),
+        }
+        if draft.edits.len() > edits {
+            reasons.push(format!("validation error: {}", error.error_kind()));
+        }
+    }
+    drafts
+        .into_values()
+        .filter(|(_, draft, _)| !draft.edits.is_empty())
+        .map(|(template, draft, reasons)| draft.repair(&template, reasons.join("; ")))
+        .collect()
+}
+
+/// `text` with each occurrence of the name `from` replaced with `to`
+fn rename(text: &str, from: &str, to: &str) -> String {
+    let is_name = |c: char| c.is_alphanumeric() || c == '_' || c == ':';
+    let mut renamed = String::new();
+    let mut rest = text;
+    while let Some(i) = rest.find(from) {
+        let (before, after) = rest.split_at(i);
+        let after = after.get(from.len()..).unwrap_or_default();
+        let starts = !before.ends_with(is_name);
+        let ends = !after.starts_with(|c: char| c.is_alphanumeric() || c == '_');
+        renamed.push_str(before);
+        renamed.push_str(if starts && ends { to } else { from });
+        rest = after;
+    }
+    renamed.push_str(rest);
+    renamed
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::invariants::{check, parse_invariants};
+    use crate::{Schema, ValidationMode, Validator};
+    use std::str::FromStr;
+
+    fn schema() -> Schema {
+        Schema::from_str(
+            r#"{"": {
+                "entityTypes": {
+                    "User": {"memberOfTypes": ["Group"]},
+                    "Group": {},
+                    "Treasury": {}
+                },
+                "actions": {"transfer": {"appliesTo": {
+                    "principalTypes": ["User"],
+                    "resourceTypes": ["Treasury"],
+                    "context": {"type": "Record", "attributes": {
+                        "amount": {"type": "Long"}
+                    }}
+                }}}
+            }}"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn violations_are_repaired() {
+        let invariants = parse_invariants(
+            r#"invariant capped: permit Action::"transfer"
+               only if principal in Group::"signers" && context.amount <= 100
+                   && context.amount >= 0;"#,
+        )
+        .unwrap();
+        let policies = PolicySet::from_str(
+            r#"@note("transfers")
+               permit(principal, action == Action::"transfer", resource)
+               when { context.amount < 1000 };"#,
+        )
+        .unwrap();
+        let violations = check(&invariants, &policies, &schema());
+        let repairs = repair_violations(&violations, &invariants, &policies);
+        let [repair] = repairs.as_slice() else {
+            panic!("expected one repair: {repairs:?}");
+        };
+        assert_eq!(repair.policy, "policy0");
+        assert_eq!(
+            repair.edits,
+            [
+                Edit::ConstrainScope {
+                    variable: "principal".to_string(),
+                    constraint: r#"principal in Group::"signers""#.to_string(),
+                },
+                Edit::TightenBound {
+                    from: "(context[\"amount\"]) < 1000".to_string(),
+                    to: "(context[\"amount\"]) <= 100".to_string(),
+                },
+                Edit::AddCondition {
+                    condition: "0 <= (context[\"amount\"])".to_string(),
+                },
+            ]
+        );
+
+        // the repaired policy keeps the invariant
+        let repaired = PolicySet::from_str(&repair.repaired).unwrap();
+        assert!(check(&invariants, &repaired, &schema()).is_empty());
+        assert_eq!(
+            repaired
+                .policies()
+                .next()
+                .and_then(|p| p.annotation("note")),
+            Some("transfers")
+        );
+        let json = serde_json::to_value(repair).unwrap();
+        assert_eq!(
+            json.get("edits")
+                .and_then(|edits| edits.get(1))
+                .and_then(|edit| edit.get("kind")),
+            Some(&serde_json::json!("tightenBound"))
+        );
+    }
+
+    #[test]
+    fn validation_errors_are_repaired() {
+        let policies = PolicySet::from_str(
+            r#"permit(principal == Usr::"alice", action == Action::"transfr", resource);
+               permit(principal == Group::"signers", action == Action::"transfer", resource);
+               permit(principal, action, resource) when { context.amount > 5 };"#,
+        )
+        .unwrap();
+        let validator = Validator::new(schema());
+        let result = validator.validate(&policies, ValidationMode::default());
+        let repairs = repair_validation(&result, &policies);
+        let policy = |id: &str| repairs.iter().find(|r| r.policy == id);
+
+        let renamed = policy("policy0").unwrap();
+        assert_eq!(
+            renamed.edits,
+            [
+                Edit::Rename {
+                    from: "Usr".to_string(),
+                    to: "User".to_string(),
+                },
+                Edit::Rename {
+                    from: r#"Action::"transfr""#.to_string(),
+                    to: r#"Action::"transfer""#.to_string(),
+                },
+            ]
+        );
+        let scoped = policy("policy1").unwrap();
+        assert_eq!(
+            scoped.edits,
+            [Edit::ConstrainScope {
+                variable: "principal".to_string(),
+                constraint: r#"principal in Group::"signers""#.to_string(),
+            }]
+        );
+        // no repair is suggested for the type error
+        assert!(policy("policy2").is_none());
+
+        let repaired =
+            PolicySet::from_str(&format!("{}\n{}", renamed.repaired, scoped.repaired)).unwrap();
+        assert!(validator
+            .validate(&repaired, ValidationMode::default())
+            .validation_passed());
+
+        assert_eq!(
+            rename("Usr::\"a\" UsrX Usr", "Usr", "User"),
+            "User::\"a\" UsrX User"
+        );
+    }
+}
@@ -0,0 +1,370 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Replay of recorded authorization decisions.
+//!
+//! A [`Replayer`] reads audit records, as written by
+//! [`JsonLinesAuditSink`](crate::audit::JsonLinesAuditSink), rebuilds each
+//! request and its entities, and answers it again under a chosen policy set.
+//! When that policy set is the one a decision was recorded under, the
+//! replayed decision must match the recorded one; when it is a newer
+//! version, the replay shows how the decision would change.
+//!
+//! Records must have been made with [`AuditDetail::Requests`] or
+//! [`AuditDetail::RequestsAndEntities`]; records holding only digests can't
+//! be replayed.
+//!
+//! [`AuditDetail::Requests`]: crate::audit::AuditDetail::Requests
+//! [`AuditDetail::RequestsAndEntities`]: crate::audit::AuditDetail::RequestsAndEntities
+
+use std::io::BufRead;
+
+use thiserror::Error;
+
+use crate::audit::{policy_set_digest, request_digest, AuditRecord};
+use crate::{Authorizer, Decision, Entities, EntitiesError, PolicySet, Response};
+
+/// Errors reading or replaying a record
+#[derive(Debug, Error)]
+pub enum ReplayError {
+    /// Reading the audit log failed
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    /// A line of the audit log isn't an audit record
+    #[error("line {line} of the audit log is malformed: {source}")]
+    Malformed {
+        /// Line number, starting at 1
+        line: usize,
+        /// The parse error
+        source: serde_json::Error,
+    },
+    /// The record doesn't include the request
+    #[error("record of request {request_hash} doesn't include the request")]
+    NoRequest {
+        /// Digest of the request
+        request_hash: String,
+    },
+    /// The recorded request can't be rebuilt
+    #[error("recorded request {request_hash} is invalid: {reason}")]
+    InvalidRequest {
+        /// Digest of the request
+        request_hash: String,
+        /// What's wrong with it
+        reason: String,
+    },
+    /// The recorded request doesn't match its digest, so the record has been
+    /// modified
+    #[error("recorded request doesn't match its digest {request_hash}")]
+    DigestMismatch {
+        /// Digest of the request, as recorded
+        request_hash: String,
+    },
+    /// The record doesn't include entities, and the replayer has none to
+    /// fall back on
+    #[error("record of request {request_hash} doesn't include entities")]
+    NoEntities {
+        /// Digest of the request
+        request_hash: String,
+    },
+    /// The recorded entities can't be rebuilt
+    #[error("recorded entities are invalid: {0}")]
+    InvalidEntities(#[from] EntitiesError),
+}
+
+/// Read the records of an audit log written as JSON lines, skipping blank
+/// lines
+pub fn read_audit_log(
+    reader: impl BufRead,
+) -> impl Iterator<Item = Result<AuditRecord, ReplayError>> {
+    reader
+        .lines()
+        .enumerate()
+        .filter_map(|(index, line)| match line {
+            Ok(line) if line.trim().is_empty() => None,
+            Ok(line) => {
+                Some(
+                    serde_json::from_str(&line).map_err(|source| ReplayError::Malformed {
+                        line: index + 1,
+                        source,
+                    }),
+                )
+            }
+            Err(e) => Some(Err(e.into())),
+        })
+}
+
+/// The outcome of replaying one record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Replay {
+    /// The replayed record
+    pub record: AuditRecord,
+    /// The response under the replayer's policy set
+    pub response: Response,
+    /// Whether the record was made under the replayer's policy set, as
+    /// determined by its digest
+    pub same_policies: bool,
+}
+
+impl Replay {
+    /// Whether the replayed decision is the recorded one
+    pub fn decision_matches(&self) -> bool {
+        self.response.decision() == self.record.decision
+    }
+
+    /// Whether the record is inconsistent: it was made under the replayer's
+    /// policy set, but the decision differs. This indicates a tampered log,
+    /// entities which changed after they were recorded, or a bug.
+    pub fn is_inconsistent(&self) -> bool {
+        self.same_policies && !self.decision_matches()
+    }
+}
+
+/// Totals over a replayed audit log
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReplaySummary {
+    /// Records replayed
+    pub replayed: usize,
+    /// Records whose replayed decision differs from the recorded one
+    pub changed: usize,
+    /// Records which are inconsistent; see [`Replay::is_inconsistent()`]
+    pub inconsistent: usize,
+    /// Records which are now allowed but were denied
+    pub newly_allowed: usize,
+}
+
+/// Replays audit records under a fixed policy set
+#[derive(Debug)]
+pub struct Replayer {
+    authorizer: Authorizer,
+    policies: PolicySet,
+    policy_set_hash: String,
+    entities: Option<Entities>,
+}
+
+impl Replayer {
+    /// Replay records under `policies`
+    pub fn new(policies: PolicySet) -> Self {
+        Self {
+            authorizer: Authorizer::new(),
+            policy_set_hash: policy_set_digest(&policies),
+            policies,
+            entities: None,
+        }
+    }
+
+    /// Use `entities` for records which don't include entities
+    #[must_use]
+    pub fn with_entities(mut self, entities: Entities) -> Self {
+        self.entities = Some(entities);
+        self
+    }
+
+    /// Replay one record. The recorded request is checked against its
+    /// digest before it is answered.
+    pub fn replay(&self, record: AuditRecord) -> Result<Replay, ReplayError> {
+        let request_hash = &record.request_hash;
+        let recorded = record
+            .request
+            .as_ref()
+            .ok_or_else(|| ReplayError::NoRequest {
+                request_hash: request_hash.clone(),
+            })?;
+        let request = recorded
+            .to_request()
+            .map_err(|reason| ReplayError::InvalidRequest {
+                request_hash: request_hash.clone(),
+                reason,
+            })?;
+        if &request_digest(&request) != request_hash {
+            return Err(ReplayError::DigestMismatch {
+                request_hash: request_hash.clone(),
+            });
+        }
+        let recorded_entities = record
+            .entities
+            .clone()
+            .map(|json| Entities::from_json_value(json, None))
+            .transpose()?;
+        let entities = recorded_entities
+            .as_ref()
+            .or(self.entities.as_ref())
+            .ok_or_else(|| ReplayError::NoEntities {
+                request_hash: request_hash.clone(),
+            })?;
+        let response = self.authorizer.decide(&request, &self.policies, entities);
+        Ok(Replay {
+            same_policies: record.policy_set_hash == self.policy_set_hash,
+            record,
+            response,
+        })
+    }
+
+    /// Replay every record in an audit log, passing each replay to
+    /// `on_replay`, and total the results. Stops at the first record which
+    /// can't be replayed.
+    pub fn replay_log(
+        &self,
+        reader: impl BufRead,
+        mut on_replay: impl FnMut(&Replay),
+    ) -> Result<ReplaySummary, ReplayError> {
+        let mut summary = ReplaySummary::default();
+        for record in read_audit_log(reader) {
+            let replay = self.replay(record?)?;
+            summary.replayed += 1;
+            if !replay.decision_matches() {
+                summary.changed += 1;
+                if replay.response.decision() == Decision::Allow {
+                    summary.newly_allowed += 1;
+                }
+            }
+            if replay.is_inconsistent() {
+                summary.inconsistent += 1;
+            }
+            on_replay(&replay);
+        }
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::audit::{AuditDetail, JsonLinesAuditSink};
+    use crate::{Context, EntityUid, Request};
+    use std::str::FromStr;
+    use std::sync::Arc;
+
+    fn policies(limit: i64) -> PolicySet {
+        PolicySet::from_str(&format!(
+            r#"permit(principal in Group::"signers", action, resource)
+               when {{ context.amount < {limit} }};"#
+        ))
+        .unwrap()
+    }
+
+    fn request(amount: i64) -> Request {
+        Request::new(
+            Some(EntityUid::from_strs("User", "alice")),
+            Some(EntityUid::from_strs("Action", "transfer")),
+            Some(EntityUid::from_strs("Token", "usdc")),
+            Context::from_json_value(serde_json::json!({ "amount": amount }), None).unwrap(),
+        )
+    }
+
+    /// An audit log of answering transfers of `amounts` under a limit of 100
+    fn audit_log(detail: AuditDetail, amounts: &[i64]) -> String {
+        let entities = Entities::from_json_str(
+            r#"[
+                { "uid": { "type": "User", "id": "alice" }, "attrs": {},
+                  "parents": [{ "type": "Group", "id": "signers" }] },
+                { "uid": { "type": "Group", "id": "signers" }, "attrs": {}, "parents": [] }
+            ]"#,
+            None,
+        )
+        .unwrap();
+        let sink = Arc::new(JsonLinesAuditSink::new(Vec::new()));
+        let authorizer = Authorizer::new()
+            .with_audit_sink(sink.clone())
+            .with_audit_detail(detail);
+        for &amount in amounts {
+            authorizer.is_authorized(&request(amount), &policies(100), &entities);
+        }
+        drop(authorizer);
+        let sink = Arc::try_unwrap(sink).unwrap();
+        String::from_utf8(sink.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn replays_under_the_recorded_policies() {
+        let log = audit_log(AuditDetail::RequestsAndEntities, &[10, 500]);
+        let mut decisions = Vec::new();
+        let summary = Replayer::new(policies(100))
+            .replay_log(log.as_bytes(), |replay| {
+                assert!(replay.same_policies);
+                decisions.push(replay.response.decision());
+            })
+            .unwrap();
+        assert_eq!(decisions, [Decision::Allow, Decision::Deny]);
+        assert_eq!(
+            summary,
+            ReplaySummary {
+                replayed: 2,
+                ..ReplaySummary::default()
+            }
+        );
+    }
+
+    #[test]
+    fn replays_under_new_policies() {
+        let log = audit_log(AuditDetail::RequestsAndEntities, &[10, 500, 5000]);
+        let summary = Replayer::new(policies(1000))
+            .replay_log(log.as_bytes(), |replay| assert!(!replay.same_policies))
+            .unwrap();
+        assert_eq!(
+            summary,
+            ReplaySummary {
+                replayed: 3,
+                changed: 1,
+                inconsistent: 0,
+                newly_allowed: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn detects_tampering() {
+        let log = audit_log(AuditDetail::RequestsAndEntities, &[500]);
+        let flipped = log.replace(r#""decision":"Deny""#, r#""decision":"Allow""#);
+        assert_ne!(flipped, log);
+        let summary = Replayer::new(policies(100))
+            .replay_log(flipped.as_bytes(), |_| ())
+            .unwrap();
+        assert_eq!(summary.inconsistent, 1);
+
+        let altered = log.replace(r#""amount":500"#, r#""amount":5"#);
+        assert_ne!(altered, log);
+        assert!(matches!(
+            Replayer::new(policies(100)).replay_log(altered.as_bytes(), |_| ()),
+            Err(ReplayError::DigestMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn needs_requests_and_entities() {
+        let digests = audit_log(AuditDetail::Digests, &[10]);
+        assert!(matches!(
+            Replayer::new(policies(100)).replay_log(digests.as_bytes(), |_| ()),
+            Err(ReplayError::NoRequest { .. })
+        ));
+
+        let requests = audit_log(AuditDetail::Requests, &[10]);
+        assert!(matches!(
+            Replayer::new(policies(100)).replay_log(requests.as_bytes(), |_| ()),
+            Err(ReplayError::NoEntities { .. })
+        ));
+        let summary = Replayer::new(policies(100))
+            .with_entities(Entities::empty())
+            .replay_log(requests.as_bytes(), |_| ())
+            .unwrap();
+        // without the group membership the request is denied
+        assert_eq!(summary.inconsistent, 1);
+
+        assert!(matches!(
+            read_audit_log("\n{}\n".as_bytes()).next(),
+            Some(Err(ReplayError::Malformed { line: 2, .. }))
+        ));
+    }
+}
@@ -0,0 +1,224 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Versioned JSON form of authorization responses.
+//!
+//! A [`Response`] serializes as a [`ResponseJson`], e.g.
+//! ```json
+//! {
+//!   "version": 1,
+//!   "decision": "Deny",
+//!   "determiningPolicies": [],
+//!   "errors": [
+//!     { "code": "policyEvaluation", "policyId": "policy0", "message": "..." }
+//!   ]
+//! }
+//! ```
+//! The shape is described by the JSON Schema [`RESPONSE_SCHEMA`]. It only
+//! changes in a new [`RESPONSE_VERSION`], so services can rely on it across
+//! upgrades of this crate. Error messages are for people and may change;
+//! error codes are stable.
+
+use serde::{Deserialize, Serialize};
+
+use crate::shadow::{ShadowOutcome, ShadowSummary};
+use crate::{AuthorizationError, Decision, Response};
+
+/// Version of the JSON form, included in every [`ResponseJson`]
+pub const RESPONSE_VERSION: u32 = 1;
+
+/// JSON Schema of [`ResponseJson`]
+pub const RESPONSE_SCHEMA: &str = include_str!("../schemas/response.schema.json");
+
+/// The JSON form of a [`Response`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResponseJson {
+    /// Version of the JSON form, currently [`RESPONSE_VERSION`]
+    pub version: u32,
+    /// The decision
+    pub decision: Decision,
+    /// Ids of the policies which determined the decision, sorted
+    pub determining_policies: Vec<String>,
+    /// Errors which occurred while reaching the decision
+    pub errors: Vec<ErrorJson>,
+    /// What the shadow policies would have decided, if any were evaluated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shadow: Option<ShadowSummary>,
+}
+
+/// The JSON form of an [`AuthorizationError`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorJson {
+    /// What kind of error occurred
+    pub code: ErrorCode,
+    /// The policy whose evaluation failed, if the error is about a policy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy_id: Option<String>,
+    /// Description of the error
+    pub message: String,
+}
+
+/// Stable identifiers of the kinds of [`AuthorizationError`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ErrorCode {
+    /// Entity attributes failed to evaluate
+    AttributeEvaluation,
+    /// A policy failed to evaluate
+    PolicyEvaluation,
+    /// The request reused a nonce or request id
+    Replayed,
+    /// The request's chain couldn't be determined
+    InvalidChain,
+    /// The request matched a rule of an active kill switch
+    Halted,
+    /// The request exceeded a rate limit
+    RateLimited,
+    /// An address in the request couldn't be screened
+    ScreeningFailed,
+    /// Adding an organization chart made the entity hierarchy invalid
+    InvalidHierarchy,
+}
+
+impl From<&AuthorizationError> for ErrorJson {
+    fn from(err: &AuthorizationError) -> Self {
+        let (code, policy_id) = match err {
+            AuthorizationError::AttributeEvaluationError(_) => {
+                (ErrorCode::AttributeEvaluation, None)
+            }
+            AuthorizationError::PolicyEvaluationError { id, .. } => {
+                (ErrorCode::PolicyEvaluation, Some(id.to_string()))
+            }
+            AuthorizationError::Replayed(_) => (ErrorCode::Replayed, None),
+            AuthorizationError::InvalidChain(_) => (ErrorCode::InvalidChain, None),
+            AuthorizationError::Halted(_) => (ErrorCode::Halted, None),
+            AuthorizationError::RateLimited(_) => (ErrorCode::RateLimited, None),
+            AuthorizationError::ScreeningFailed(_) => (ErrorCode::ScreeningFailed, None),
+            AuthorizationError::InvalidHierarchy(_) => (ErrorCode::InvalidHierarchy, None),
+        };
+        Self {
+            code,
+            policy_id,
+            message: err.to_string(),
+        }
+    }
+}
+
+impl From<&Response> for ResponseJson {
+    fn from(response: &Response) -> Self {
+        let mut determining_policies: Vec<_> = response
+            .diagnostics()
+            .reason()
+            .map(ToString::to_string)
+            .collect();
+        determining_policies.sort();
+        Self {
+            version: RESPONSE_VERSION,
+            decision: response.decision(),
+            determining_policies,
+            errors: response
+                .diagnostics()
+                .errors()
+                .map(ErrorJson::from)
+                .collect(),
+            shadow: response.diagnostics().shadow().map(ShadowOutcome::summary),
+        }
+    }
+}
+
+impl Serialize for Response {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ResponseJson::from(self).serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Authorizer, Context, Entities, EntityUid, PolicySet, Request};
+    use serde_json::json;
+    use std::str::FromStr;
+
+    #[test]
+    fn response_json() {
+        let policies = PolicySet::from_str(
+            r#"permit(principal == User::"alice", action, resource);
+               permit(principal, action, resource) when { context.amount > 5 };
+               forbid(principal, action, resource) when { 1 + "one" == 2 };"#,
+        )
+        .unwrap();
+        let request = Request::new(
+            Some(EntityUid::from_strs("User", "alice")),
+            None,
+            None,
+            Context::from_json_value(json!({ "amount": 10 }), None).unwrap(),
+        );
+        let response = Authorizer::new().is_authorized(&request, &policies, &Entities::empty());
+        let json = serde_json::to_value(&response).unwrap();
+        assert_eq!(
+            json,
+            json!({
+                "version": 1,
+                "decision": "Allow",
+                "determiningPolicies": ["policy0", "policy1"],
+                "errors": [{
+                    "code": "policyEvaluation",
+                    "policyId": "policy2",
+                    "message": response.diagnostics().errors().next().unwrap().to_string(),
+                }],
+            })
+        );
+        assert_eq!(
+            serde_json::from_value::<ResponseJson>(json).unwrap(),
+            ResponseJson::from(&response)
+        );
+    }
+
+    #[test]
+    fn error_codes() {
+        let json = serde_json::to_value(ErrorJson::from(&AuthorizationError::Replayed(
+            "nonce 1 was already used".into(),
+        )))
+        .unwrap();
+        assert_eq!(
+            json,
+            json!({
+                "code": "replayed",
+                "message": "request was denied as a replay: nonce 1 was already used",
+            })
+        );
+    }
+
+    /// The published schema describes the current version
+    #[test]
+    fn schema() {
+        let schema: serde_json::Value = serde_json::from_str(RESPONSE_SCHEMA).unwrap();
+        assert_eq!(
+            schema.pointer("/properties/version/const"),
+            Some(&json!(RESPONSE_VERSION))
+        );
+        let codes: Vec<ErrorCode> = serde_json::from_value(
+            schema
+                .pointer("/$defs/error/properties/code/enum")
+                .unwrap()
+                .clone(),
+        )
+        .unwrap();
+        assert_eq!(codes.len(), 4);
+    }
+}
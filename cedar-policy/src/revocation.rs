@@ -0,0 +1,210 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Revocation of template-linked policies.
+//!
+//! A [`RevocationList`] names template-linked policies which must no longer
+//! grant or deny anything, e.g. the links granting permissions to a
+//! compromised wallet. An [`Authorizer`](crate::Authorizer) given a
+//! revocation list with
+//! [`with_revocations()`](crate::Authorizer::with_revocations) ignores the
+//! revoked links in every policy set it evaluates, so links can be revoked
+//! immediately without rebuilding the policy set.
+//!
+//! Only template-linked policies can be revoked. Revoking the id of a static
+//! policy or template has no effect, since ignoring a `forbid` policy could
+//! grant access.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+use arc_swap::ArcSwap;
+use serde::{Deserialize, Serialize};
+
+use crate::audit::unix_seconds;
+use crate::{PolicyId, PolicySet};
+
+/// The revocation of one template-linked policy
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Revocation {
+    /// Id of the revoked policy
+    pub id: PolicyId,
+    /// Why it was revoked
+    pub reason: String,
+    /// When it was revoked, in seconds since the Unix epoch
+    pub revoked_at: u64,
+}
+
+impl Revocation {
+    /// A revocation of `id`, made now
+    pub fn new(id: PolicyId, reason: impl Into<String>) -> Self {
+        Self {
+            id,
+            reason: reason.into(),
+            revoked_at: unix_seconds(SystemTime::now()),
+        }
+    }
+}
+
+/// A set of revoked template-linked policies, which can be read and updated
+/// concurrently. Readers never block.
+#[derive(Debug, Default)]
+pub struct RevocationList {
+    revoked: ArcSwap<HashMap<PolicyId, Revocation>>,
+}
+
+impl RevocationList {
+    /// An empty revocation list
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Revoke a policy, replacing any earlier revocation of it, which is
+    /// returned
+    pub fn revoke(&self, revocation: Revocation) -> Option<Revocation> {
+        let id = revocation.id.clone();
+        let previous = self.revoked.rcu(|revoked| {
+            let mut revoked = HashMap::clone(revoked);
+            revoked.insert(id.clone(), revocation.clone());
+            revoked
+        });
+        previous.get(&id).cloned()
+    }
+
+    /// Lift the revocation of `id`, returning it if there was one
+    pub fn reinstate(&self, id: &PolicyId) -> Option<Revocation> {
+        let previous = self.revoked.rcu(|revoked| {
+            let mut revoked = HashMap::clone(revoked);
+            revoked.remove(id);
+            revoked
+        });
+        previous.get(id).cloned()
+    }
+
+    /// The revocation of `id`, if it is revoked
+    pub fn get(&self, id: &PolicyId) -> Option<Revocation> {
+        self.revoked.load().get(id).cloned()
+    }
+
+    /// Whether `id` is revoked
+    pub fn is_revoked(&self, id: &PolicyId) -> bool {
+        self.revoked.load().contains_key(id)
+    }
+
+    /// All current revocations, in no particular order
+    pub fn revocations(&self) -> Vec<Revocation> {
+        self.revoked.load().values().cloned().collect()
+    }
+
+    /// Whether nothing is revoked
+    pub fn is_empty(&self) -> bool {
+        self.revoked.load().is_empty()
+    }
+
+    /// `policies` without its revoked template-linked policies. Borrows
+    /// `policies` if none of them are revoked.
+    pub fn apply<'a>(&self, policies: &'a PolicySet) -> Cow<'a, PolicySet> {
+        let revoked = self.revoked.load();
+        policies.retained(|policy| policy.is_static() || !revoked.contains_key(policy.id()))
+    }
+}
+
+impl FromIterator<Revocation> for RevocationList {
+    fn from_iter<T: IntoIterator<Item = Revocation>>(iter: T) -> Self {
+        Self {
+            revoked: ArcSwap::from_pointee(
+                iter.into_iter()
+                    .map(|revocation| (revocation.id.clone(), revocation))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Authorizer, Context, Decision, Entities, EntityUid, Request, SlotId};
+    use std::str::FromStr;
+    use std::sync::Arc;
+
+    fn id(id: &str) -> PolicyId {
+        PolicyId::from_str(id).unwrap()
+    }
+
+    #[test]
+    fn revoked_links_are_ignored() {
+        let mut policies = PolicySet::from_str(
+            r#"permit(principal == User::"bob", action, resource);
+               permit(principal == ?principal, action, resource);"#,
+        )
+        .unwrap();
+        policies
+            .link(
+                id("policy1"),
+                id("alice"),
+                HashMap::from([(SlotId::principal(), EntityUid::from_strs("User", "alice"))]),
+            )
+            .unwrap();
+        let revocations = Arc::new(RevocationList::new());
+        let authorizer = Authorizer::new().with_revocations(Arc::clone(&revocations));
+        let decide = |principal: &str| {
+            let request = Request::new(
+                Some(EntityUid::from_strs("User", principal)),
+                Some(EntityUid::from_strs("Action", "sign")),
+                Some(EntityUid::from_strs("Wallet", "w")),
+                Context::empty(),
+            );
+            authorizer
+                .is_authorized(&request, &policies, &Entities::empty())
+                .decision()
+        };
+        assert_eq!(decide("alice"), Decision::Allow);
+
+        assert_eq!(
+            revocations.revoke(Revocation::new(id("alice"), "key compromised")),
+            None
+        );
+        assert!(revocations.is_revoked(&id("alice")));
+        assert_eq!(decide("alice"), Decision::Deny);
+        // the policy set itself is unchanged
+        assert!(policies.policy(&id("alice")).is_some());
+
+        // static policies can't be revoked
+        revocations.revoke(Revocation::new(id("policy0"), "not a link"));
+        assert_eq!(decide("bob"), Decision::Allow);
+
+        let lifted = revocations.reinstate(&id("alice")).unwrap();
+        assert_eq!(lifted.reason, "key compromised");
+        assert_eq!(decide("alice"), Decision::Allow);
+    }
+
+    #[test]
+    fn apply_borrows_when_nothing_is_revoked() {
+        let policies = PolicySet::from_str("permit(principal, action, resource);").unwrap();
+        let revocations: RevocationList = [Revocation {
+            id: id("elsewhere"),
+            reason: "unrelated".into(),
+            revoked_at: 1_700_000_000,
+        }]
+        .into_iter()
+        .collect();
+        assert!(matches!(revocations.apply(&policies), Cow::Borrowed(_)));
+        assert_eq!(revocations.revocations().len(), 1);
+    }
+}
@@ -0,0 +1,257 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Risk scores of requests, as request context.
+//!
+//! Policies can combine rules with risk signals from a model or heuristic.
+//! An [`Authorizer`](crate::Authorizer) given a [`RiskScorer`] with
+//! [`with_risk_scorer()`](crate::Authorizer::with_risk_scorer) scores each
+//! request before evaluating it, and adds the score to its context as
+//! [`RISK_SCORE_ATTRIBUTE`], e.g.
+//! ```text
+//! forbid(principal, action == Action::"withdraw", resource)
+//! when { context.riskScore > 75 };
+//! ```
+//!
+//! [`NoopRiskScorer`] scores every request 0, and [`DeviationScorer`] is a
+//! simple statistical scorer, scoring how far a context attribute is from
+//! the principal's recent values.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Debug;
+use std::sync::{Mutex, PoisonError};
+
+use cedar_policy_core::ast::{ExprKind, Literal};
+
+use crate::{Entities, Request};
+
+/// The context attribute holding a request's risk score, a `Long` from 0 to
+/// 100
+pub const RISK_SCORE_ATTRIBUTE: &str = "riskScore";
+
+/// The highest risk score
+pub const MAX_RISK_SCORE: u8 = 100;
+
+/// Scores the risk of requests
+pub trait RiskScorer: Debug + Send + Sync {
+    /// The risk of `request` with `entities`, from 0 for none to
+    /// [`MAX_RISK_SCORE`]. Higher scores count as [`MAX_RISK_SCORE`].
+    fn score(&self, request: &Request, entities: &Entities) -> u8;
+}
+
+/// Scores every request 0
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopRiskScorer;
+
+impl RiskScorer for NoopRiskScorer {
+    fn score(&self, _request: &Request, _entities: &Entities) -> u8 {
+        0
+    }
+}
+
+/// The default number of recent values a [`DeviationScorer`] keeps per
+/// principal
+pub const DEFAULT_WINDOW: usize = 100;
+
+/// The default number of recent values a [`DeviationScorer`] needs before it
+/// scores a principal's requests
+pub const DEFAULT_MIN_SAMPLES: usize = 5;
+
+/// Scores how far a `Long` context attribute is from the principal's
+/// recent values
+///
+/// The attribute, e.g. an amount, scores 25 for each standard deviation it
+/// is from the mean of the recent values, up to [`MAX_RISK_SCORE`] at four
+/// or more. Requests
+/// without the attribute, and those of principals with too few recent
+/// values, score 0. Each request's value is recorded after it is scored.
+#[derive(Debug)]
+pub struct DeviationScorer {
+    attribute: String,
+    window: usize,
+    min_samples: usize,
+    history: Mutex<HashMap<String, VecDeque<i64>>>,
+}
+
+impl DeviationScorer {
+    /// A scorer of the context attribute `attribute`
+    pub fn new(attribute: impl Into<String>) -> Self {
+        Self {
+            attribute: attribute.into(),
+            window: DEFAULT_WINDOW,
+            min_samples: DEFAULT_MIN_SAMPLES,
+            history: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Keep the `window` most recent values per principal
+    #[must_use]
+    pub fn with_window(mut self, window: usize) -> Self {
+        self.window = window.max(1);
+        self
+    }
+
+    /// Only score principals with at least `min_samples` recent values
+    #[must_use]
+    pub fn with_min_samples(mut self, min_samples: usize) -> Self {
+        self.min_samples = min_samples;
+        self
+    }
+
+    /// The value of the scored attribute in `request`'s context
+    fn value(&self, request: &Request) -> Option<i64> {
+        request
+            .0
+            .context()?
+            .iter()
+            .find(|(key, _)| *key == self.attribute)
+            .and_then(|(_, value)| match value.expr_kind() {
+                ExprKind::Lit(Literal::Long(n)) => Some(*n),
+                _ => None,
+            })
+    }
+}
+
+impl RiskScorer for DeviationScorer {
+    fn score(&self, request: &Request, _entities: &Entities) -> u8 {
+        let Some(value) = self.value(request) else {
+            return 0;
+        };
+        let principal = request
+            .principal()
+            .map(ToString::to_string)
+            .unwrap_or_default();
+        let mut history = self.history.lock().unwrap_or_else(PoisonError::into_inner);
+        let values = history.entry(principal).or_default();
+        let score = if values.len() >= self.min_samples.max(1) {
+            deviations(values, value).map_or(MAX_RISK_SCORE, |k| k.saturating_mul(25))
+        } else {
+            0
+        };
+        if values.len() >= self.window {
+            values.pop_front();
+        }
+        values.push_back(value);
+        drop(history);
+        score
+    }
+}
+
+/// How many whole standard deviations `value` is from the mean of `values`,
+/// up to 4, or `None` if the arithmetic overflows
+///
+/// With `n` values summing to `sum` with squares summing to `squares`,
+/// `value` is at least `k` standard deviations from the mean exactly when
+/// `(n * value - sum)^2 >= k^2 * (n * squares - sum^2)`, so this needs no
+/// division or square roots.
+fn deviations(values: &VecDeque<i64>, value: i64) -> Option<u8> {
+    let n = i128::try_from(values.len()).ok()?;
+    let mut sum: i128 = 0;
+    let mut squares: i128 = 0;
+    for v in values {
+        let v = i128::from(*v);
+        sum = sum.checked_add(v)?;
+        squares = squares.checked_add(v.checked_mul(v)?)?;
+    }
+    let spread = n.checked_mul(squares)?.checked_sub(sum.checked_mul(sum)?)?;
+    let distance = n.checked_mul(i128::from(value))?.checked_sub(sum)?;
+    let distance = distance.checked_mul(distance)?;
+    if spread == 0 {
+        return Some(if distance == 0 { 0 } else { 4 });
+    }
+    let mut k: u8 = 0;
+    while k < 4 && distance >= i128::from((k + 1) * (k + 1)).checked_mul(spread)? {
+        k += 1;
+    }
+    Some(k)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Authorizer, Context, Decision, EntityUid, PolicySet, RestrictedExpression};
+    use std::str::FromStr;
+    use std::sync::Arc;
+
+    fn withdraw(principal: &str, amount: i64) -> Request {
+        Request::new(
+            Some(EntityUid::from_strs("User", principal)),
+            Some(EntityUid::from_strs("Action", "withdraw")),
+            Some(EntityUid::from_strs("Vault", "v")),
+            Context::from_pairs([("amount".to_string(), RestrictedExpression::new_long(amount))]),
+        )
+    }
+
+    #[test]
+    fn deviation_scores() {
+        let scorer = DeviationScorer::new("amount");
+        let entities = Entities::empty();
+        // too few samples
+        for amount in [90, 110, 90, 110, 100] {
+            assert_eq!(scorer.score(&withdraw("alice", amount), &entities), 0);
+        }
+        // the mean is 100 and the standard deviation is about 8.9
+        assert_eq!(scorer.score(&withdraw("alice", 105), &entities), 0);
+        assert_eq!(scorer.score(&withdraw("alice", 120), &entities), 50);
+        assert_eq!(scorer.score(&withdraw("alice", 10_000), &entities), 100);
+        // other principals have their own history
+        assert_eq!(scorer.score(&withdraw("bob", 10_000), &entities), 0);
+        let no_amount = Request::new(None, None, None, Context::empty());
+        assert_eq!(scorer.score(&no_amount, &entities), 0);
+    }
+
+    #[test]
+    fn windows() {
+        let values: VecDeque<i64> = [5, 5, 5].into();
+        assert_eq!(deviations(&values, 5), Some(0));
+        assert_eq!(deviations(&values, 6), Some(4));
+        let values: VecDeque<i64> = [i64::MAX, i64::MIN].into();
+        assert_eq!(deviations(&values, 0), None);
+
+        let scorer = DeviationScorer::new("amount")
+            .with_window(2)
+            .with_min_samples(2);
+        let entities = Entities::empty();
+        for amount in [1, 1_000, 1_000, 1_001] {
+            scorer.score(&withdraw("alice", amount), &entities);
+        }
+        // only the last two values are kept
+        assert_eq!(scorer.score(&withdraw("alice", 1_000), &entities), 25);
+    }
+
+    #[test]
+    fn scores_in_context() {
+        let policies = PolicySet::from_str(
+            "permit(principal, action, resource);
+             forbid(principal, action, resource) when { context.riskScore > 75 };",
+        )
+        .unwrap();
+        let authorizer = Authorizer::new()
+            .with_risk_scorer(Arc::new(DeviationScorer::new("amount").with_min_samples(3)));
+        let entities = Entities::empty();
+        for amount in [100, 100, 101] {
+            let response =
+                authorizer.is_authorized(&withdraw("alice", amount), &policies, &entities);
+            assert_eq!(response.decision(), Decision::Allow);
+        }
+        let response = authorizer.is_authorized(&withdraw("alice", 5_000), &policies, &entities);
+        assert_eq!(response.decision(), Decision::Deny);
+
+        let authorizer = Authorizer::new().with_risk_scorer(Arc::new(NoopRiskScorer));
+        let response = authorizer.is_authorized(&withdraw("alice", 5_000), &policies, &entities);
+        assert_eq!(response.decision(), Decision::Allow);
+    }
+}
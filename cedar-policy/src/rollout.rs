@@ -0,0 +1,225 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Gradual rollout of policies.
+//!
+//! A new policy, such as a new deny rule, can be canaried on a slice of
+//! traffic before it is enforced everywhere. A `@rollout` annotation either
+//! gives the share of principals the policy applies to, e.g.
+//! `@rollout("10%")`, or names a flag, e.g. `@rollout("strict-limits")`, so
+//! that the policy applies only where the flag is enabled. Principals are
+//! bucketed by a hash of their UID, so a principal is consistently in or out
+//! of a rollout, and a principal in a 10% rollout is also in every larger one.
+//!
+//! An [`Authorizer`](crate::Authorizer) given a [`Rollout`] with
+//! [`with_rollout()`](crate::Authorizer::with_rollout) considers only the
+//! policies rolled out to each request's principal. Without one, `@rollout`
+//! annotations are ignored and every policy applies.
+
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+use sha3::{Digest, Keccak256};
+
+use crate::{Effect, EntityUid, PolicySet, Request};
+
+/// The annotation restricting a policy to part of the traffic, e.g.
+/// `@rollout("10%")` or `@rollout("strict-limits")`
+pub const ROLLOUT_ANNOTATION: &str = "rollout";
+
+/// The number of buckets principals are divided into; a percentage with two
+/// decimal places selects a whole number of buckets
+pub const BUCKETS: u16 = 10_000;
+
+/// Which traffic a policy is rolled out to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RolloutStage {
+    /// The principals in the first `n` of the [`BUCKETS`], e.g. 1000 for
+    /// `10%`
+    Buckets(u16),
+    /// Everything, if the named flag is enabled
+    Flag(String),
+}
+
+/// The stage in the value of a [`ROLLOUT_ANNOTATION`]
+///
+/// The value is either a percentage from `0%` to `100%` with at most two
+/// decimal places, or a flag name of ASCII letters, digits, `-`, `_`, and
+/// `.`. Returns `None` if it is neither.
+pub fn parse_rollout_annotation(value: &str) -> Option<RolloutStage> {
+    let value = value.trim();
+    if let Some(percent) = value.strip_suffix('%') {
+        let (whole, fraction) = percent.split_once('.').unwrap_or((percent, ""));
+        let digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+        if whole.is_empty() || !digits(whole) || fraction.len() > 2 || !digits(fraction) {
+            return None;
+        }
+        let whole: u16 = whole.parse().ok()?;
+        let fraction: u16 = format!("{fraction:0<2}").parse().ok()?;
+        let buckets = whole.checked_mul(100)?.checked_add(fraction)?;
+        (buckets <= BUCKETS).then_some(RolloutStage::Buckets(buckets))
+    } else {
+        let valid = !value.is_empty()
+            && value
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b"-_.".contains(&b));
+        valid.then(|| RolloutStage::Flag(value.to_string()))
+    }
+}
+
+/// The bucket of `principal`, below [`BUCKETS`]: the first eight bytes of the
+/// Keccak-256 hash of its UID, e.g. `User::"alice"`, modulo [`BUCKETS`]
+pub fn bucket(principal: &EntityUid) -> u16 {
+    let hash = Keccak256::digest(principal.to_string().as_bytes());
+    let prefix = hash
+        .iter()
+        .take(8)
+        .fold(0_u64, |acc, b| (acc << 8) | u64::from(*b));
+    // the remainder is below `BUCKETS`, so it fits
+    u16::try_from(prefix % u64::from(BUCKETS)).unwrap_or(0)
+}
+
+/// The enabled flags, and so which policies apply to each request
+#[derive(Debug, Clone, Default)]
+pub struct Rollout {
+    flags: HashSet<String>,
+}
+
+impl Rollout {
+    /// A rollout with no flags enabled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Enable the flag `name`
+    #[must_use]
+    pub fn with_flag(mut self, name: impl Into<String>) -> Self {
+        self.flags.insert(name.into());
+        self
+    }
+
+    /// Whether a policy at `stage` applies to requests from `principal`. A
+    /// percentage applies to no request without a principal.
+    pub fn includes(&self, stage: &RolloutStage, principal: Option<&EntityUid>) -> bool {
+        match stage {
+            RolloutStage::Buckets(n) => principal.is_some_and(|p| bucket(p) < *n),
+            RolloutStage::Flag(name) => self.flags.contains(name),
+        }
+    }
+
+    /// The policies in `policies` rolled out to `request`: those without a
+    /// `@rollout` annotation, and those whose stage
+    /// [`includes()`](Self::includes) its principal. A malformed annotation
+    /// makes a `permit` policy apply to nothing and a `forbid` policy apply
+    /// to everything, so that a typo never allows more. Borrows `policies` if
+    /// they all apply.
+    pub fn apply<'a>(&self, request: &Request, policies: &'a PolicySet) -> Cow<'a, PolicySet> {
+        let principal = request.principal();
+        policies.retained(|policy| {
+            let stage = policy
+                .annotation(ROLLOUT_ANNOTATION)
+                .map(parse_rollout_annotation);
+            match stage {
+                None => true,
+                Some(Some(stage)) => self.includes(&stage, principal),
+                Some(None) => policy.effect() == Effect::Forbid,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Authorizer, Context, Decision, Entities};
+    use std::str::FromStr;
+
+    #[test]
+    fn annotations() {
+        assert_eq!(
+            parse_rollout_annotation("10%"),
+            Some(RolloutStage::Buckets(1000))
+        );
+        assert_eq!(
+            parse_rollout_annotation(" 0.5% "),
+            Some(RolloutStage::Buckets(50))
+        );
+        assert_eq!(
+            parse_rollout_annotation("100%"),
+            Some(RolloutStage::Buckets(BUCKETS))
+        );
+        assert_eq!(
+            parse_rollout_annotation("strict-limits"),
+            Some(RolloutStage::Flag("strict-limits".into()))
+        );
+        for malformed in ["", "%", "101%", "1.234%", ".5%", "-1%", "a b", "10 %"] {
+            assert_eq!(parse_rollout_annotation(malformed), None, "{malformed}");
+        }
+    }
+
+    #[test]
+    fn buckets_are_stable_and_spread() {
+        let alice = EntityUid::from_strs("User", "alice");
+        assert_eq!(bucket(&alice), bucket(&alice));
+        let in_ten_percent = (0..1000)
+            .filter(|i| bucket(&EntityUid::from_strs("User", &i.to_string())) < 1000)
+            .count();
+        assert!((50..150).contains(&in_ten_percent), "{in_ten_percent}");
+    }
+
+    #[test]
+    fn authorizer_applies_policies_rolled_out_to_the_principal() {
+        let policies = PolicySet::from_str(
+            r#"permit(principal, action, resource);
+               @rollout("25%") forbid(principal, action == Action::"withdraw", resource);
+               @rollout("strict-limits") forbid(principal, action == Action::"borrow", resource);
+               @rollout("25") permit(principal, action == Action::"admin", resource);"#,
+        )
+        .unwrap();
+        let decide = |authorizer: &Authorizer, user: &str, action: &str| {
+            let request = Request::new(
+                Some(EntityUid::from_strs("User", user)),
+                Some(EntityUid::from_strs("Action", action)),
+                Some(EntityUid::from_strs("Vault", "v")),
+                Context::empty(),
+            );
+            authorizer
+                .is_authorized(&request, &policies, &Entities::empty())
+                .decision()
+        };
+        let authorizer = Authorizer::new().with_rollout(Rollout::new());
+        let users: Vec<String> = (0..200).map(|i| format!("user{i}")).collect();
+        let denied: Vec<&String> = users
+            .iter()
+            .filter(|user| decide(&authorizer, user, "withdraw") == Decision::Deny)
+            .collect();
+        assert!((20..80).contains(&denied.len()), "{}", denied.len());
+        for user in &users {
+            let in_rollout = bucket(&EntityUid::from_strs("User", user)) < 2500;
+            assert_eq!(denied.contains(&user), in_rollout);
+        }
+
+        assert_eq!(decide(&authorizer, "alice", "borrow"), Decision::Allow);
+        let strict = Authorizer::new().with_rollout(Rollout::new().with_flag("strict-limits"));
+        assert_eq!(decide(&strict, "alice", "borrow"), Decision::Deny);
+
+        // without a rollout, every policy applies
+        assert_eq!(
+            decide(&Authorizer::new(), "alice", "borrow"),
+            Decision::Deny
+        );
+    }
+}
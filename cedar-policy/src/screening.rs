@@ -0,0 +1,506 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Sanctions screening of addresses, as entity attributes.
+//!
+//! An exported sanctions list goes stale as soon as it is written. A
+//! [`Screener`] asks a [`ScreeningProvider`] about the addresses in each
+//! request when it is evaluated, and gives their entities the attributes
+//! [`IS_SANCTIONED_ATTRIBUTE`] and [`RISK_CATEGORY_ATTRIBUTE`], e.g.
+//! ```text
+//! forbid(principal, action == Action::"transfer", resource)
+//! when { principal.isSanctioned || resource.isSanctioned };
+//! ```
+//!
+//! An [`Authorizer`](crate::Authorizer) given a screener with
+//! [`with_screener()`](crate::Authorizer::with_screener) screens the
+//! principal and resource of each request which are addresses, i.e.
+//! entities of type `Address` (see [`Screener::with_entity_type()`]).
+//! Results are cached for [`DEFAULT_TTL`] (see [`Screener::with_ttl()`]).
+//! If screening fails, the request is denied with an
+//! [`AuthorizationError::ScreeningFailed`](crate::AuthorizationError::ScreeningFailed)
+//! error.
+//!
+//! [`ScreeningList`] screens against a list kept in memory. With the
+//! `chainalysis` feature, `ChainalysisProvider` screens with the Chainalysis
+//! sanctions API.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use cedar_policy_core::ast;
+use cedar_policy_core::entities::TCComputation;
+use serde::{Deserialize, Serialize};
+use smol_str::SmolStr;
+use thiserror::Error;
+
+use crate::address_book::ADDRESS_TYPE;
+use crate::audit::normalize_address;
+use crate::{Entities, EntityTypeName, Request};
+
+/// The entity attribute holding whether an address is sanctioned, a `Bool`
+pub const IS_SANCTIONED_ATTRIBUTE: &str = "isSanctioned";
+
+/// The entity attribute holding an address's risk category, a `String`,
+/// which is `"none"` for addresses the provider knows nothing about
+pub const RISK_CATEGORY_ATTRIBUTE: &str = "riskCategory";
+
+/// The risk category of addresses the provider knows nothing about
+pub const NO_RISK_CATEGORY: &str = "none";
+
+/// How long a [`Screener`] caches results by default
+pub const DEFAULT_TTL: Duration = Duration::from_mins(10);
+
+/// An error screening an address
+#[derive(Debug, Error)]
+#[error("screening failed: {0}")]
+pub struct ScreeningError(pub String);
+
+/// What a provider knows about an address
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScreeningResult {
+    /// Whether the address is sanctioned
+    pub is_sanctioned: bool,
+    /// The kind of risk, e.g. `"sanctions"`, or [`NO_RISK_CATEGORY`]
+    pub risk_category: String,
+}
+
+impl ScreeningResult {
+    /// The result for an address the provider knows nothing about
+    pub fn clear() -> Self {
+        Self {
+            is_sanctioned: false,
+            risk_category: NO_RISK_CATEGORY.to_string(),
+        }
+    }
+}
+
+/// Screens addresses against sanctions and risk data
+pub trait ScreeningProvider: Debug + Send + Sync {
+    /// Screen `address`, a lowercase `0x`-prefixed hex address
+    fn screen(&self, address: &str) -> Result<ScreeningResult, ScreeningError>;
+}
+
+/// Screens addresses against a list kept in memory. Addresses which aren't
+/// in the list are clear.
+#[derive(Debug, Clone, Default)]
+pub struct ScreeningList {
+    results: HashMap<String, ScreeningResult>,
+}
+
+impl ScreeningList {
+    /// An empty list
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record `result` for `address`, returning `false` if it isn't an
+    /// address
+    pub fn insert(&mut self, address: &str, result: ScreeningResult) -> bool {
+        let Some(address) = normalize_address(address) else {
+            return false;
+        };
+        self.results.insert(address, result);
+        true
+    }
+
+    /// Record `address` as sanctioned, returning `false` if it isn't an
+    /// address
+    pub fn sanction(&mut self, address: &str) -> bool {
+        self.insert(
+            address,
+            ScreeningResult {
+                is_sanctioned: true,
+                risk_category: "sanctions".to_string(),
+            },
+        )
+    }
+}
+
+impl ScreeningProvider for ScreeningList {
+    fn screen(&self, address: &str) -> Result<ScreeningResult, ScreeningError> {
+        Ok(self
+            .results
+            .get(address)
+            .cloned()
+            .unwrap_or_else(ScreeningResult::clear))
+    }
+}
+
+/// Screens the addresses of requests with a [`ScreeningProvider`], caching
+/// the results
+#[derive(Debug)]
+pub struct Screener {
+    provider: Arc<dyn ScreeningProvider>,
+    entity_type: EntityTypeName,
+    ttl: Duration,
+    cache: Mutex<HashMap<String, (ScreeningResult, Instant)>>,
+}
+
+impl Screener {
+    /// A screener of `Address` entities with `provider`
+    pub fn new(provider: Arc<dyn ScreeningProvider>) -> Self {
+        Self {
+            provider,
+            entity_type: address_type(),
+            ttl: DEFAULT_TTL,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Screen entities of type `entity_type`, instead of `Address`
+    #[must_use]
+    pub fn with_entity_type(mut self, entity_type: EntityTypeName) -> Self {
+        self.entity_type = entity_type;
+        self
+    }
+
+    /// Cache results for `ttl`. A `ttl` of zero disables the cache.
+    #[must_use]
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Screen `address`, from the cache if possible
+    pub fn screen(&self, address: &str) -> Result<ScreeningResult, ScreeningError> {
+        let address = normalize_address(address)
+            .ok_or_else(|| ScreeningError(format!("`{address}` is not an address")))?;
+        let now = Instant::now();
+        let cached = self
+            .cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&address)
+            .filter(|(_, expires)| *expires > now)
+            .map(|(result, _)| result.clone());
+        if let Some(result) = cached {
+            return Ok(result);
+        }
+        let result = self.provider.screen(&address)?;
+        if !self.ttl.is_zero() {
+            self.cache
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(address, (result.clone(), now + self.ttl));
+        }
+        Ok(result)
+    }
+
+    /// Forget the cached results
+    pub fn clear_cache(&self) {
+        self.cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+
+    /// `entities` with the screening attributes added to the principal and
+    /// resource of `request` which are addresses, or `None` if neither is.
+    /// Entities which aren't in `entities` are added.
+    pub(crate) fn screened(
+        &self,
+        request: &Request,
+        entities: &Entities,
+    ) -> Result<Option<Entities>, ScreeningError> {
+        let mut screened: Vec<ast::Entity> = Vec::new();
+        for uid in [request.principal(), request.resource()]
+            .into_iter()
+            .flatten()
+            .filter(|uid| uid.type_name() == &self.entity_type)
+        {
+            if screened.iter().any(|entity| entity.uid() == uid.0) {
+                continue;
+            }
+            let result = self.screen(uid.id().as_ref())?;
+            let existing = entities.get(uid).map(|entity| &entity.0);
+            let mut attrs: HashMap<SmolStr, ast::RestrictedExpr> = existing
+                .into_iter()
+                .flat_map(ast::Entity::attrs)
+                .map(|(key, value)| (key.into(), restricted(&value)))
+                .collect();
+            attrs.insert(
+                IS_SANCTIONED_ATTRIBUTE.into(),
+                ast::RestrictedExpr::val(result.is_sanctioned),
+            );
+            attrs.insert(
+                RISK_CATEGORY_ATTRIBUTE.into(),
+                ast::RestrictedExpr::val(result.risk_category),
+            );
+            let entity = ast::Entity::new_with_tags(
+                uid.0.clone(),
+                attrs,
+                existing
+                    .into_iter()
+                    .flat_map(ast::Entity::ancestors)
+                    .cloned()
+                    .collect(),
+                existing
+                    .into_iter()
+                    .flat_map(ast::Entity::tags)
+                    .map(|(key, value)| (key.into(), restricted(&value)))
+                    .collect(),
+            );
+            screened.push(entity);
+        }
+        if screened.is_empty() {
+            return Ok(None);
+        }
+        let uids: HashSet<_> = screened.iter().map(ast::Entity::uid).collect();
+        let rest = entities
+            .0
+            .iter()
+            .filter(|entity| !uids.contains(&entity.uid()))
+            .cloned();
+        // Only attributes changed, and the added entities have no ancestors,
+        // so the transitive closure is unchanged
+        cedar_policy_core::entities::Entities::from_entities(
+            rest.chain(screened),
+            TCComputation::AssumeAlreadyComputed,
+        )
+        .map(|screened| Some(Entities(screened)))
+        .map_err(|err| ScreeningError(err.to_string()))
+    }
+}
+
+/// The `Address` entity type
+fn address_type() -> EntityTypeName {
+    // PANIC SAFETY: `Address` is a valid entity type name
+    #[allow(clippy::expect_used)]
+    EntityTypeName::from_str(ADDRESS_TYPE).expect("`Address` is a valid entity type name")
+}
+
+/// An owned copy of `expr`
+fn restricted(expr: &ast::Expr) -> ast::RestrictedExpr {
+    ast::RestrictedExpr::new_unchecked(expr.clone())
+}
+
+#[cfg(feature = "chainalysis")]
+pub use chainalysis::ChainalysisProvider;
+
+#[cfg(feature = "chainalysis")]
+mod chainalysis {
+    use super::{ScreeningError, ScreeningProvider, ScreeningResult, NO_RISK_CATEGORY};
+    use serde::Deserialize;
+
+    /// The base URL of the Chainalysis public sanctions API
+    pub const PUBLIC_API: &str = "https://public.chainalysis.com";
+
+    /// The response of the sanctions API for an address
+    #[derive(Debug, Deserialize)]
+    struct AddressResponse {
+        identifications: Vec<Identification>,
+    }
+
+    /// A sanctions identification of an address
+    #[derive(Debug, Deserialize)]
+    struct Identification {
+        category: String,
+    }
+
+    /// Screens addresses with the Chainalysis sanctions API. An address is
+    /// sanctioned if it has any identifications, and its risk category is
+    /// the category of the first one.
+    ///
+    /// Its requests are blocking, see the `domain` module's docs.
+    #[derive(Debug)]
+    pub struct ChainalysisProvider {
+        client: reqwest::blocking::Client,
+        base_url: String,
+        api_key: String,
+    }
+
+    impl ChainalysisProvider {
+        /// A provider using the public API with `api_key`
+        pub fn new(api_key: impl Into<String>) -> Self {
+            Self {
+                client: reqwest::blocking::Client::new(),
+                base_url: PUBLIC_API.to_string(),
+                api_key: api_key.into(),
+            }
+        }
+
+        /// Use the API at `base_url` instead of the public one
+        #[must_use]
+        pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+            self.base_url = base_url.into();
+            self
+        }
+    }
+
+    impl ScreeningProvider for ChainalysisProvider {
+        fn screen(&self, address: &str) -> Result<ScreeningResult, ScreeningError> {
+            let error = |message: String| ScreeningError(format!("{address}: {message}"));
+            let url = format!(
+                "{}/api/v1/address/{address}",
+                self.base_url.trim_end_matches('/')
+            );
+            let body = self
+                .client
+                .get(url)
+                .header("X-API-Key", &self.api_key)
+                .header("Accept", "application/json")
+                .send()
+                .and_then(reqwest::blocking::Response::error_for_status)
+                .and_then(reqwest::blocking::Response::text)
+                .map_err(|err| error(err.to_string()))?;
+            parse_response(&body).map_err(|err| error(err.to_string()))
+        }
+    }
+
+    /// The result in the API's response `body`
+    pub(super) fn parse_response(body: &str) -> Result<ScreeningResult, serde_json::Error> {
+        let response: AddressResponse = serde_json::from_str(body)?;
+        Ok(ScreeningResult {
+            is_sanctioned: !response.identifications.is_empty(),
+            risk_category: response
+                .identifications
+                .into_iter()
+                .next()
+                .map_or_else(|| NO_RISK_CATEGORY.to_string(), |id| id.category),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{AuthorizationError, Authorizer, Context, Decision, EntityUid, PolicySet};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    const SANCTIONED: &str = "0x8589427373D6D84E98730D7795D8f6f8731FDA16";
+    const CLEAN: &str = "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266";
+
+    #[derive(Debug)]
+    struct Counting {
+        list: ScreeningList,
+        calls: AtomicUsize,
+    }
+
+    impl ScreeningProvider for Counting {
+        fn screen(&self, address: &str) -> Result<ScreeningResult, ScreeningError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            self.list.screen(address)
+        }
+    }
+
+    #[derive(Debug)]
+    struct Unavailable;
+
+    impl ScreeningProvider for Unavailable {
+        fn screen(&self, address: &str) -> Result<ScreeningResult, ScreeningError> {
+            Err(ScreeningError(format!("{address}: service unavailable")))
+        }
+    }
+
+    fn transfer(to: &str) -> Request {
+        Request::new(
+            Some(EntityUid::from_strs("User", "alice")),
+            Some(EntityUid::from_strs("Action", "transfer")),
+            Some(EntityUid::from_strs("Address", &to.to_ascii_lowercase())),
+            Context::empty(),
+        )
+    }
+
+    #[test]
+    fn caches_results() {
+        let mut list = ScreeningList::new();
+        assert!(list.sanction(SANCTIONED));
+        assert!(!list.sanction("alice"));
+        let provider = Arc::new(Counting {
+            list,
+            calls: AtomicUsize::new(0),
+        });
+
+        let screener = Screener::new(Arc::clone(&provider) as Arc<dyn ScreeningProvider>);
+        let sanctioned = screener.screen(SANCTIONED).unwrap();
+        assert!(sanctioned.is_sanctioned);
+        assert_eq!(sanctioned.risk_category, "sanctions");
+        assert_eq!(
+            screener.screen(&SANCTIONED.to_ascii_lowercase()).unwrap(),
+            sanctioned
+        );
+        assert_eq!(screener.screen(CLEAN).unwrap(), ScreeningResult::clear());
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 2);
+        assert!(screener.screen("alice").is_err());
+
+        screener.clear_cache();
+        screener.screen(SANCTIONED).unwrap();
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 3);
+
+        let uncached = Screener::new(provider.clone()).with_ttl(Duration::ZERO);
+        uncached.screen(SANCTIONED).unwrap();
+        uncached.screen(SANCTIONED).unwrap();
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 5);
+    }
+
+    #[test]
+    fn screens_requests() {
+        let mut list = ScreeningList::new();
+        list.sanction(SANCTIONED);
+        let authorizer = Authorizer::new().with_screener(Arc::new(Screener::new(Arc::new(list))));
+        let policies = PolicySet::from_str(
+            r#"permit(principal, action, resource);
+               forbid(principal, action, resource) when { resource.isSanctioned };
+               forbid(principal, action, resource)
+               when { resource has label && resource.riskCategory != "none" };"#,
+        )
+        .unwrap();
+        let entities = Entities::empty();
+
+        let response = authorizer.is_authorized(&transfer(SANCTIONED), &policies, &entities);
+        assert_eq!(response.decision(), Decision::Deny);
+        let response = authorizer.is_authorized(&transfer(CLEAN), &policies, &entities);
+        assert_eq!(response.decision(), Decision::Allow);
+
+        // existing entities keep their attributes
+        let book = crate::address_book::AddressBook::new();
+        let entities = book.entities_for([SANCTIONED]).unwrap();
+        let response = authorizer.is_authorized(&transfer(SANCTIONED), &policies, &entities);
+        assert_eq!(response.decision(), Decision::Deny);
+        assert_eq!(response.diagnostics().reason().count(), 2);
+
+        let authorizer =
+            Authorizer::new().with_screener(Arc::new(Screener::new(Arc::new(Unavailable))));
+        let response = authorizer.is_authorized(&transfer(CLEAN), &policies, &Entities::empty());
+        assert_eq!(response.decision(), Decision::Deny);
+        assert!(matches!(
+            response.diagnostics().errors().next(),
+            Some(AuthorizationError::ScreeningFailed(_))
+        ));
+    }
+
+    #[cfg(feature = "chainalysis")]
+    #[test]
+    fn chainalysis_responses() {
+        let sanctioned = chainalysis::parse_response(
+            r#"{"identifications":[{"category":"sanctions","name":"SANCTIONS: OFAC SDN","description":"","url":""}]}"#,
+        )
+        .unwrap();
+        assert!(sanctioned.is_sanctioned);
+        assert_eq!(sanctioned.risk_category, "sanctions");
+        assert_eq!(
+            chainalysis::parse_response(r#"{"identifications":[]}"#).unwrap(),
+            ScreeningResult::clear()
+        );
+        assert!(chainalysis::parse_response("{}").is_err());
+    }
+}
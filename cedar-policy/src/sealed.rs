@@ -0,0 +1,314 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Sealed policy sets, validated and linked ahead of time.
+//!
+//! Parsing and validating a large policy set at startup is slow. A
+//! [`SealedPolicySet`] is produced once, by a build step, from a policy set
+//! which validates against a schema: it holds the parsed policies with their
+//! templates already linked, and names the schema version they were
+//! validated against. Loading one with
+//! [`into_policy_set()`](SealedPolicySet::into_policy_set) neither parses nor
+//! validates anything.
+//!
+//! The [`hash()`](SealedPolicySet::hash) of a sealed set covers the schema
+//! version and the [digest](crate::audit::policy_set_digest) of its policies,
+//! so it is the unit which is anchored on chain or signed. Loading a sealed
+//! set recomputes the digest and rejects the set if it doesn't match.
+//!
+//! With the `encryption` feature, a sealed set can be stored encrypted, in an
+//! [`Envelope`](crate::envelope::Envelope) of [`SEALED_CONTENT_TYPE`].
+
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
+use thiserror::Error;
+
+use cedar_policy_core::ast;
+
+use crate::audit::policy_set_digest;
+use crate::{PolicySet, ValidationMode, Validator};
+
+/// The version of the sealed policy set format
+pub const SEALED_VERSION: u32 = 1;
+
+/// The content type of [envelopes](crate::envelope::Envelope) holding sealed
+/// policy sets
+pub const SEALED_CONTENT_TYPE: &str = "banyan/sealed-policy-set";
+
+/// Errors in sealing or loading a policy set
+#[derive(Debug, Error)]
+pub enum SealError {
+    /// The policies don't validate against the schema
+    #[error("the policies don't validate: {}", .0.join("; "))]
+    Validation(Vec<String>),
+    /// The sealed set is in a format this version doesn't understand
+    #[error("unsupported sealed policy set version {0}")]
+    UnsupportedVersion(u32),
+    /// The policies don't match the digest they were sealed with
+    #[error("the policies don't match the digest they were sealed with")]
+    DigestMismatch,
+    /// The JSON is malformed
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    /// The sealed set couldn't be encrypted or decrypted
+    #[cfg(feature = "encryption")]
+    #[error(transparent)]
+    Envelope(#[from] crate::envelope::EnvelopeError),
+}
+
+/// A validated policy set with its templates linked, ready to load
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SealedPolicySet {
+    version: u32,
+    schema_version: String,
+    policy_set_digest: String,
+    policies: ast::PolicySet,
+}
+
+impl SealedPolicySet {
+    /// Seal `policies`, which must validate with `validator` in `mode`.
+    /// `schema_version` names the version of the validator's schema, e.g. a
+    /// release tag or the hash of the schema file.
+    pub fn seal(
+        policies: &PolicySet,
+        validator: &Validator,
+        mode: ValidationMode,
+        schema_version: impl Into<String>,
+    ) -> Result<Self, SealError> {
+        let result = validator.validate(policies, mode);
+        if !result.validation_passed() {
+            return Err(SealError::Validation(
+                result
+                    .validation_errors()
+                    .map(ToString::to_string)
+                    .collect(),
+            ));
+        }
+        Ok(Self {
+            version: SEALED_VERSION,
+            schema_version: schema_version.into(),
+            policy_set_digest: policy_set_digest(policies),
+            policies: policies.ast.clone(),
+        })
+    }
+
+    /// The version of the schema the policies were validated against
+    pub fn schema_version(&self) -> &str {
+        &self.schema_version
+    }
+
+    /// The [digest](crate::audit::policy_set_digest) of the policies
+    pub fn policy_set_digest(&self) -> &str {
+        &self.policy_set_digest
+    }
+
+    /// The hash to anchor or sign
+    ///
+    /// This is the Keccak-256 hash of the format version as four big-endian
+    /// bytes, the schema version, a NUL byte, and the hex policy set digest.
+    pub fn hash(&self) -> [u8; 32] {
+        let mut hasher = Keccak256::new();
+        hasher.update(self.version.to_be_bytes());
+        hasher.update(self.schema_version.as_bytes());
+        hasher.update([0]);
+        hasher.update(self.policy_set_digest.as_bytes());
+        hasher.finalize().into()
+    }
+
+    /// The JSON form of the sealed set
+    pub fn to_json(&self) -> Result<Vec<u8>, SealError> {
+        Ok(serde_json::to_vec(self)?)
+    }
+
+    /// Read a sealed set from its JSON form
+    pub fn from_json(json: &[u8]) -> Result<Self, SealError> {
+        let sealed: Self = serde_json::from_slice(json)?;
+        if sealed.version != SEALED_VERSION {
+            return Err(SealError::UnsupportedVersion(sealed.version));
+        }
+        Ok(sealed)
+    }
+
+    /// The JSON form of the sealed set, encrypted under a data key wrapped by
+    /// `wrapper`
+    #[cfg(feature = "encryption")]
+    pub fn encrypt(
+        &self,
+        wrapper: &dyn crate::envelope::KeyWrapper,
+    ) -> Result<crate::envelope::Envelope, SealError> {
+        Ok(crate::envelope::Envelope::seal(
+            SEALED_CONTENT_TYPE,
+            &self.to_json()?,
+            wrapper,
+        )?)
+    }
+
+    /// Read a sealed set from an envelope, decrypting it with the data key
+    /// unwrapped by `wrapper`
+    #[cfg(feature = "encryption")]
+    pub fn decrypt(
+        envelope: &crate::envelope::Envelope,
+        wrapper: &dyn crate::envelope::KeyWrapper,
+    ) -> Result<Self, SealError> {
+        Self::from_json(&envelope.open(SEALED_CONTENT_TYPE, wrapper)?)
+    }
+
+    /// The policy set, without validating it again. Fails if the policies
+    /// don't match the digest they were sealed with.
+    pub fn into_policy_set(self) -> Result<PolicySet, SealError> {
+        let policies = PolicySet::from_ast(self.policies);
+        if policy_set_digest(&policies) == self.policy_set_digest {
+            Ok(policies)
+        } else {
+            Err(SealError::DigestMismatch)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{PolicyId, Schema, SlotId};
+    use std::collections::HashMap;
+    use std::str::FromStr;
+
+    fn validator() -> Validator {
+        let schema = Schema::from_str(
+            r#"{"": {
+                "entityTypes": {"User": {}, "Vault": {}},
+                "actions": {"withdraw": {"appliesTo": {
+                    "principalTypes": ["User"], "resourceTypes": ["Vault"]
+                }}}
+            }}"#,
+        )
+        .unwrap();
+        Validator::new(schema)
+    }
+
+    fn policies() -> PolicySet {
+        let mut policies = PolicySet::from_str(
+            r#"permit(principal == User::"alice", action == Action::"withdraw", resource);
+               permit(principal == ?principal, action, resource == ?resource);"#,
+        )
+        .unwrap();
+        policies
+            .link(
+                PolicyId::from_str("policy1").unwrap(),
+                PolicyId::from_str("bob").unwrap(),
+                HashMap::from([
+                    (
+                        SlotId::principal(),
+                        crate::EntityUid::from_strs("User", "bob"),
+                    ),
+                    (
+                        SlotId::resource(),
+                        crate::EntityUid::from_strs("Vault", "v"),
+                    ),
+                ]),
+            )
+            .unwrap();
+        policies
+    }
+
+    #[test]
+    fn sealed_sets_round_trip() {
+        let policies = policies();
+        let sealed =
+            SealedPolicySet::seal(&policies, &validator(), ValidationMode::default(), "v3")
+                .unwrap();
+        assert_eq!(sealed.schema_version(), "v3");
+        assert_eq!(sealed.policy_set_digest(), policy_set_digest(&policies));
+
+        let loaded = SealedPolicySet::from_json(&sealed.to_json().unwrap()).unwrap();
+        assert_eq!(loaded.hash(), sealed.hash());
+        let loaded = loaded.into_policy_set().unwrap();
+        assert_eq!(loaded, policies);
+        assert!(loaded
+            .template(&PolicyId::from_str("policy1").unwrap())
+            .is_some());
+        assert_eq!(loaded.policies().count(), 2);
+
+        let other = SealedPolicySet::seal(&policies, &validator(), ValidationMode::default(), "v4")
+            .unwrap();
+        assert_ne!(other.hash(), sealed.hash());
+    }
+
+    #[test]
+    fn invalid_policies_are_not_sealed() {
+        let policies =
+            PolicySet::from_str(r#"permit(principal == Admin::"root", action, resource);"#)
+                .unwrap();
+        assert!(matches!(
+            SealedPolicySet::seal(&policies, &validator(), ValidationMode::default(), "v3"),
+            Err(SealError::Validation(errors)) if !errors.is_empty()
+        ));
+    }
+
+    #[test]
+    fn tampered_sets_are_rejected() {
+        let sealed =
+            SealedPolicySet::seal(&policies(), &validator(), ValidationMode::default(), "v3")
+                .unwrap();
+        let empty = SealedPolicySet::seal(
+            &PolicySet::new(),
+            &validator(),
+            ValidationMode::default(),
+            "v3",
+        )
+        .unwrap();
+        let tampered = SealedPolicySet {
+            policies: empty.policies,
+            ..sealed.clone()
+        };
+        let tampered = SealedPolicySet::from_json(&tampered.to_json().unwrap()).unwrap();
+        assert!(matches!(
+            tampered.into_policy_set(),
+            Err(SealError::DigestMismatch)
+        ));
+
+        let future = SealedPolicySet {
+            version: 2,
+            ..sealed
+        };
+        assert!(matches!(
+            SealedPolicySet::from_json(&future.to_json().unwrap()),
+            Err(SealError::UnsupportedVersion(2))
+        ));
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn encrypted_sets_round_trip() {
+        use crate::envelope::{EnvelopeError, LocalKeyWrapper};
+
+        let wrapper = LocalKeyWrapper::new([1; 32], "kek");
+        let sealed =
+            SealedPolicySet::seal(&policies(), &validator(), ValidationMode::default(), "v3")
+                .unwrap();
+        let envelope = sealed.encrypt(&wrapper).unwrap();
+        assert!(!String::from_utf8_lossy(&envelope.to_json().unwrap()).contains("alice"));
+        let loaded = SealedPolicySet::decrypt(&envelope, &wrapper).unwrap();
+        assert_eq!(loaded.hash(), sealed.hash());
+        assert_eq!(loaded.into_policy_set().unwrap(), policies());
+
+        let other = crate::envelope::Envelope::seal("other", b"{}", &wrapper).unwrap();
+        assert!(matches!(
+            SealedPolicySet::decrypt(&other, &wrapper),
+            Err(SealError::Envelope(EnvelopeError::ContentType { .. }))
+        ));
+    }
+}
@@ -0,0 +1,210 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Shadow-mode policies, which are evaluated but not enforced.
+//!
+//! A new restrictive policy can be observed against live traffic before it
+//! is enforced. A policy annotated with `@shadow`, e.g.
+//! `@shadow("new withdrawal cap")`, never affects the decision: the
+//! [`Authorizer`](crate::Authorizer) answers each request without it, and
+//! also answers with it, reporting the second decision as a
+//! [`ShadowOutcome`] in [`Diagnostics::shadow()`](crate::Diagnostics::shadow)
+//! and in audit records.
+//!
+//! A whole candidate policy set can be shadowed the same way with
+//! [`Authorizer::with_shadow_policies()`](crate::Authorizer::with_shadow_policies).
+
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{AuthorizationError, Decision, PolicyId, PolicySet, Response};
+
+/// The annotation marking a policy as evaluated but not enforced. Its value
+/// is free-form, e.g. a description of the change being observed.
+pub const SHADOW_ANNOTATION: &str = "shadow";
+
+/// Whether any policy in `policies` is in shadow mode
+pub fn has_shadow_policies(policies: &PolicySet) -> bool {
+    policies
+        .policies()
+        .any(|policy| policy.annotation(SHADOW_ANNOTATION).is_some())
+}
+
+/// The policies in `policies` which are enforced: those without a `@shadow`
+/// annotation. Borrows `policies` if there are none in shadow mode.
+pub fn enforced_policies(policies: &PolicySet) -> Cow<'_, PolicySet> {
+    policies.retained(|policy| policy.annotation(SHADOW_ANNOTATION).is_none())
+}
+
+/// What the decision would have been had the shadow policies been enforced
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShadowOutcome {
+    decision: Decision,
+    reason: HashSet<PolicyId>,
+    errors: Vec<AuthorizationError>,
+}
+
+impl ShadowOutcome {
+    /// The decision with the shadow policies enforced
+    pub fn decision(&self) -> Decision {
+        self.decision
+    }
+
+    /// The policies which would have determined the decision
+    pub fn reason(&self) -> impl Iterator<Item = &PolicyId> {
+        self.reason.iter()
+    }
+
+    /// The errors in evaluating the policies
+    pub fn errors(&self) -> impl Iterator<Item = &AuthorizationError> {
+        self.errors.iter()
+    }
+
+    /// A summary for logs
+    pub fn summary(&self) -> ShadowSummary {
+        let mut determining_policies: Vec<_> =
+            self.reason.iter().map(ToString::to_string).collect();
+        determining_policies.sort();
+        ShadowSummary {
+            decision: self.decision,
+            determining_policies,
+        }
+    }
+}
+
+impl From<Response> for ShadowOutcome {
+    fn from(response: Response) -> Self {
+        let (decision, reason, errors) = response.into_parts();
+        Self {
+            decision,
+            reason,
+            errors,
+        }
+    }
+}
+
+/// The JSON form of a [`ShadowOutcome`] in audit records and responses
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShadowSummary {
+    /// The decision with the shadow policies enforced
+    pub decision: Decision,
+    /// Ids of the policies which would have determined it, sorted
+    pub determining_policies: Vec<String>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::audit::{AuditRecord, AuditSink};
+    use crate::{Authorizer, Context, Entities, EntityUid, Request, RestrictedExpression};
+    use std::str::FromStr;
+    use std::sync::{Arc, Mutex};
+
+    fn withdraw(amount: i64) -> Request {
+        Request::new(
+            Some(EntityUid::from_strs("User", "alice")),
+            Some(EntityUid::from_strs("Action", "withdraw")),
+            Some(EntityUid::from_strs("Vault", "v")),
+            Context::from_pairs([("amount".to_string(), RestrictedExpression::new_long(amount))]),
+        )
+    }
+
+    fn policies() -> PolicySet {
+        PolicySet::from_str(
+            r#"permit(principal, action, resource);
+               @shadow("cap withdrawals at 100")
+               forbid(principal, action == Action::"withdraw", resource)
+               when { context.amount > 100 };"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn enforced() {
+        let policies = policies();
+        assert!(has_shadow_policies(&policies));
+        let enforced = enforced_policies(&policies);
+        assert_eq!(enforced.policies().count(), 1);
+        assert!(!has_shadow_policies(&enforced));
+        assert!(matches!(enforced_policies(&enforced), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn shadow_policies_are_reported_but_not_enforced() {
+        let policies = policies();
+        let authorizer = Authorizer::new();
+        let response = authorizer.is_authorized(&withdraw(500), &policies, &Entities::empty());
+        assert_eq!(response.decision(), Decision::Allow);
+        let shadow = response.diagnostics().shadow().unwrap();
+        assert_eq!(shadow.decision(), Decision::Deny);
+        assert_eq!(
+            shadow.summary().determining_policies,
+            vec!["policy1".to_string()]
+        );
+
+        let response = authorizer.is_authorized(&withdraw(50), &policies, &Entities::empty());
+        assert_eq!(response.decision(), Decision::Allow);
+        assert_eq!(
+            response.diagnostics().shadow().map(ShadowOutcome::decision),
+            Some(Decision::Allow)
+        );
+
+        // without shadow policies, nothing is reported
+        let plain = PolicySet::from_str("permit(principal, action, resource);").unwrap();
+        let response = authorizer.is_authorized(&withdraw(500), &plain, &Entities::empty());
+        assert_eq!(response.diagnostics().shadow(), None);
+    }
+
+    #[derive(Debug, Default)]
+    struct Records(Mutex<Vec<AuditRecord>>);
+
+    impl AuditSink for Records {
+        fn record(&self, record: &AuditRecord) {
+            self.0.lock().unwrap().push(record.clone());
+        }
+    }
+
+    #[test]
+    fn shadow_policy_sets() {
+        let enforced = PolicySet::from_str("permit(principal, action, resource);").unwrap();
+        let candidate = PolicySet::from_str(
+            "permit(principal, action, resource) when { context.amount <= 100 };",
+        )
+        .unwrap();
+        let records = Arc::new(Records::default());
+        let authorizer = Authorizer::new()
+            .with_shadow_policies(candidate)
+            .with_audit_sink(Arc::clone(&records) as Arc<dyn AuditSink>);
+        let response = authorizer.is_authorized(&withdraw(500), &enforced, &Entities::empty());
+        assert_eq!(response.decision(), Decision::Allow);
+        assert_eq!(
+            response.diagnostics().shadow().map(ShadowOutcome::decision),
+            Some(Decision::Deny)
+        );
+        let record = records.0.lock().unwrap().first().cloned().unwrap();
+        assert_eq!(record.decision, Decision::Allow);
+        assert_eq!(
+            record.shadow,
+            Some(ShadowSummary {
+                decision: Decision::Deny,
+                determining_policies: vec![],
+            })
+        );
+    }
+}
@@ -0,0 +1,232 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Permits which only depend on context supplied by the requester.
+//!
+//! Some context attributes are filled in by the requester and never checked
+//! by the provider, e.g. a balance the requester claims to hold. A schema
+//! lists them in the `requesterControlled` field of an action's `appliesTo`.
+//!
+//! A `permit` policy which leaves its principal and resource unconstrained,
+//! and whose conditions read only such attributes, is satisfied by anyone
+//! who claims the right values. [`check()`] reports these policies, for each
+//! action they apply to. Data is provider-verified if it is read from the
+//! principal, the resource, a template slot, an entity literal, or a context
+//! attribute which isn't requester controlled. An entity named by a requester
+//! controlled attribute is as untrusted as the attribute itself.
+
+use std::collections::BTreeSet;
+
+use cedar_policy_core::ast::{
+    self, BinaryOp, ExprKind, Literal, PrincipalOrResourceConstraint, Var,
+};
+use cedar_policy_validator::ValidatorActionId;
+use ref_cast::RefCast;
+use smol_str::SmolStr;
+
+use crate::invariants::applies_to_action;
+use crate::{Effect, EntityUid, PolicyId, PolicySet, Schema};
+
+/// A `permit` policy which only depends on requester controlled context for
+/// an action
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaintedPermit {
+    policy: PolicyId,
+    action: EntityUid,
+    attributes: Vec<SmolStr>,
+}
+
+impl TaintedPermit {
+    /// The policy
+    pub fn policy(&self) -> &PolicyId {
+        &self.policy
+    }
+
+    /// The action
+    pub fn action(&self) -> &EntityUid {
+        &self.action
+    }
+
+    /// The requester controlled context attributes the policy reads, sorted
+    pub fn attributes(&self) -> impl Iterator<Item = &str> {
+        self.attributes.iter().map(SmolStr::as_str)
+    }
+}
+
+impl std::fmt::Display for TaintedPermit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "policy `{}` permits `{}` on requester controlled context alone: {}",
+            self.policy,
+            self.action,
+            self.attributes.join(", ")
+        )
+    }
+}
+
+/// The data an expression reads
+#[derive(Debug, Default)]
+struct Reads {
+    /// Requester controlled context attributes
+    tainted: BTreeSet<SmolStr>,
+    /// Whether it reads any provider-verified data
+    verified: bool,
+}
+
+impl Reads {
+    /// The data read by `expr` in a request for `action`
+    fn of(expr: &ast::Expr, action: &ValidatorActionId) -> Self {
+        let mut reads = Self::default();
+        for expr in expr.subexpressions() {
+            match expr.expr_kind() {
+                ExprKind::GetAttr { expr: record, attr }
+                | ExprKind::HasAttr { expr: record, attr } => match record.expr_kind() {
+                    ExprKind::Var(Var::Context) if action.is_requester_controlled(attr) => {
+                        reads.tainted.insert(attr.clone());
+                    }
+                    ExprKind::Var(Var::Context) | ExprKind::Lit(Literal::EntityUID(_)) => {
+                        reads.verified = true;
+                    }
+                    _ => (),
+                },
+                ExprKind::BinaryApp {
+                    op: BinaryOp::GetTag | BinaryOp::HasTag,
+                    arg1,
+                    ..
+                } if matches!(arg1.expr_kind(), ExprKind::Lit(Literal::EntityUID(_))) => {
+                    reads.verified = true;
+                }
+                ExprKind::Var(Var::Principal | Var::Resource)
+                | ExprKind::Slot(_)
+                | ExprKind::Unknown { .. } => reads.verified = true,
+                _ => (),
+            }
+        }
+        reads
+    }
+}
+
+/// Whether a principal or resource scope constrains the entity beyond its
+/// type
+fn constrains(constraint: &PrincipalOrResourceConstraint) -> bool {
+    !matches!(
+        constraint,
+        PrincipalOrResourceConstraint::Any | PrincipalOrResourceConstraint::Is(_)
+    )
+}
+
+/// The `permit` policies in `policies` which, for some action in `schema`,
+/// only depend on context attributes the requester controls, sorted by id
+pub fn check(policies: &PolicySet, schema: &Schema) -> Vec<TaintedPermit> {
+    let actions = schema.0.action_entities().unwrap_or_default();
+    let mut action_ids: Vec<_> = schema.0.action_ids().collect();
+    action_ids.sort_by_key(|action| action.name().to_string());
+    let mut tainted = Vec::new();
+    for policy in policies.ast.policies() {
+        if policy.effect() != Effect::Permit
+            || constrains(policy.principal_constraint().as_inner())
+            || constrains(policy.resource_constraint().as_inner())
+        {
+            continue;
+        }
+        for action in &action_ids {
+            if !applies_to_action(policy, action.name(), &actions) {
+                continue;
+            }
+            let reads = Reads::of(policy.non_head_constraints(), action);
+            if !reads.verified && !reads.tainted.is_empty() {
+                tainted.push(TaintedPermit {
+                    policy: PolicyId::ref_cast(policy.id()).clone(),
+                    action: EntityUid(action.name().clone()),
+                    attributes: reads.tainted.into_iter().collect(),
+                });
+            }
+        }
+    }
+    tainted.sort_by(|a, b| a.policy.as_ref().cmp(b.policy.as_ref()));
+    tainted
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+
+    fn schema() -> Schema {
+        Schema::from_str(
+            r#"{"": {
+                "entityTypes": {"User": {}, "Vault": {}},
+                "actions": {
+                    "withdraw": {"appliesTo": {
+                        "principalTypes": ["User"],
+                        "resourceTypes": ["Vault"],
+                        "context": {"type": "Record", "attributes": {
+                            "claimedBalance": {"type": "Long"},
+                            "amount": {"type": "Long"},
+                            "verifiedBalance": {"type": "Long"}
+                        }},
+                        "requesterControlled": ["claimedBalance", "amount"]
+                    }},
+                    "view": {"appliesTo": {
+                        "principalTypes": ["User"],
+                        "resourceTypes": ["Vault"],
+                        "context": {"type": "Record", "attributes": {
+                            "claimedBalance": {"type": "Long"}
+                        }}
+                    }}
+                }
+            }}"#,
+        )
+        .unwrap()
+    }
+
+    fn found(policies: &str) -> Vec<String> {
+        check(&PolicySet::from_str(policies).unwrap(), &schema())
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn permits_on_claimed_data_are_flagged() {
+        assert_eq!(
+            found(
+                "permit(principal, action, resource is Vault)
+                   when { context.claimedBalance >= context.amount };"
+            ),
+            [
+                r#"policy `policy0` permits `Action::"withdraw"` on requester controlled context alone: amount, claimedBalance"#
+            ]
+        );
+    }
+
+    #[test]
+    fn verified_data_or_scope_is_enough() {
+        assert!(found(
+            r#"permit(principal, action == Action::"withdraw", resource)
+               when { context.verifiedBalance >= context.amount };
+               permit(principal == User::"alice", action == Action::"withdraw", resource)
+               when { context.claimedBalance > 0 };
+               permit(principal, action == Action::"withdraw", resource)
+               when { context.claimedBalance > 0 && principal.verified };
+               permit(principal, action == Action::"view", resource)
+               when { context.claimedBalance > 0 };
+               forbid(principal, action, resource) when { context.amount > 100 };"#
+        )
+        .is_empty());
+    }
+}
@@ -0,0 +1,606 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Isolated tenants in a single process.
+//!
+//! A hosted policy service answers requests for many customers, each with
+//! their own policies and entities. [`Tenants`] keeps them apart: each
+//! [`TenantId`] has its own policy set, its own entities, and its own
+//! [`Authorizer`], so that a request for one tenant is only ever answered
+//! with that tenant's policies, only resolves that tenant's entities, and
+//! only touches that tenant's decision cache, rate limits, and nonces.
+//!
+//! Each tenant's authorizer is built when the tenant is added, by the
+//! function given to [`Tenants::with_authorizers()`]. A tenant whose
+//! authorizer shares a decision cache, rate limiter, or nonce tracker, or
+//! the store of one, with another tenant's is refused. To keep audit records
+//! apart, the function can give each authorizer a [`TenantAuditSink`], which stamps
+//! each record with the tenant before passing it on to a shared sink.
+//! Replacing a tenant's policies or entities moves its decision cache, if
+//! it has one, to a new version once the requests being answered with the
+//! old ones are, so that no decision made with the old ones is reused.
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::str::FromStr;
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::audit::{AuditRecord, AuditSink};
+use crate::{Authorizer, Entities, PolicySet, Request, Response};
+
+/// The longest [`TenantId`]
+pub const MAX_TENANT_ID_LEN: usize = 64;
+
+/// Errors from [`Tenants`]
+#[derive(Debug, Error)]
+pub enum TenantError {
+    /// A tenant id is empty, too long, or has characters other than ASCII
+    /// letters, digits, `-`, and `_`
+    #[error("`{0}` isn't a valid tenant id")]
+    InvalidId(String),
+    /// There's no tenant with this id
+    #[error("unknown tenant `{0}`")]
+    UnknownTenant(TenantId),
+    /// The authorizer built for a new tenant shares state with the
+    /// authorizer of another tenant
+    #[error("the authorizer of tenant `{tenant}` shares its {state} with tenant `{other}`")]
+    SharedState {
+        /// The new tenant
+        tenant: TenantId,
+        /// The tenant it shares state with
+        other: TenantId,
+        /// What they share: `decision cache`, `rate limiter`, or `nonce tracker`
+        state: &'static str,
+    },
+}
+
+/// The id of a tenant: 1 to [`MAX_TENANT_ID_LEN`] ASCII letters, digits,
+/// `-`, and `_`, so that it can be used in paths and keys as is
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TenantId(String);
+
+impl TenantId {
+    /// The id, as a string
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for TenantId {
+    type Err = TenantError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let valid = !s.is_empty()
+            && s.len() <= MAX_TENANT_ID_LEN
+            && s.bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+        if valid {
+            Ok(Self(s.to_string()))
+        } else {
+            Err(TenantError::InvalidId(s.to_string()))
+        }
+    }
+}
+
+impl TryFrom<String> for TenantId {
+    type Error = TenantError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::from_str(&s)
+    }
+}
+
+impl From<TenantId> for String {
+    fn from(id: TenantId) -> Self {
+        id.0
+    }
+}
+
+impl AsRef<str> for TenantId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for TenantId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// An [`AuditSink`] which stamps each record with a tenant before passing
+/// it on, so that the records of several tenants can share a sink
+#[derive(Debug)]
+pub struct TenantAuditSink {
+    tenant: TenantId,
+    sink: Arc<dyn AuditSink>,
+}
+
+impl TenantAuditSink {
+    /// Record decisions for `tenant` in `sink`
+    pub fn new(tenant: TenantId, sink: Arc<dyn AuditSink>) -> Self {
+        Self { tenant, sink }
+    }
+}
+
+impl AuditSink for TenantAuditSink {
+    fn record(&self, record: &AuditRecord) {
+        let mut record = record.clone();
+        record.tenant = Some(self.tenant.to_string());
+        self.sink.record(&record);
+    }
+}
+
+/// The policies and entities of one tenant
+#[derive(Debug)]
+struct TenantData {
+    policies: Arc<PolicySet>,
+    entities: Arc<Entities>,
+}
+
+/// Everything one tenant's requests are answered with
+#[derive(Debug)]
+struct Tenant {
+    authorizer: Authorizer,
+    /// Read while a request is answered, so that it is answered with the
+    /// policies and entities of one update
+    data: RwLock<TenantData>,
+}
+
+impl Tenant {
+    fn data(&self) -> RwLockReadGuard<'_, TenantData> {
+        self.data.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Replace the policies and entities given, dropping the decisions
+    /// cached for the old ones, if there is a decision cache
+    fn set(&self, policies: Option<PolicySet>, entities: Option<Entities>) {
+        let mut data = self.data.write().unwrap_or_else(PoisonError::into_inner);
+        if let Some(cache) = self.authorizer.decision_cache() {
+            cache.clear();
+        }
+        if let Some(policies) = policies {
+            data.policies = Arc::new(policies);
+        }
+        if let Some(entities) = entities {
+            data.entities = Arc::new(entities);
+        }
+        drop(data);
+    }
+}
+
+type AuthorizerFactory = Box<dyn Fn(&TenantId) -> Authorizer + Send + Sync>;
+
+/// The policies, entities, and authorizers of many tenants
+pub struct Tenants {
+    new_authorizer: AuthorizerFactory,
+    tenants: RwLock<HashMap<TenantId, Arc<Tenant>>>,
+}
+
+impl Debug for Tenants {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Tenants")
+            .field("tenants", &self.tenants)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for Tenants {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Tenants {
+    /// No tenants, each of which will be answered by a default
+    /// [`Authorizer`]
+    pub fn new() -> Self {
+        Self {
+            new_authorizer: Box::new(|_| Authorizer::new()),
+            tenants: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Build the authorizer of each tenant added from now on with
+    /// `new_authorizer`, which must give each tenant its own decision cache,
+    /// rate limiter, and nonce tracker, if it gives it any, each with its own
+    /// store. [`Self::insert()`] refuses a tenant whose authorizer shares one
+    /// with another tenant's.
+    #[must_use]
+    pub fn with_authorizers(
+        mut self,
+        new_authorizer: impl Fn(&TenantId) -> Authorizer + Send + Sync + 'static,
+    ) -> Self {
+        self.new_authorizer = Box::new(new_authorizer);
+        self
+    }
+
+    /// Add `tenant` with `policies` and `entities`, or replace the policies
+    /// and entities of an existing tenant, keeping its authorizer
+    ///
+    /// Fails with [`TenantError::SharedState`], adding nothing, if the
+    /// authorizer built for a new tenant shares a decision cache, rate
+    /// limiter, or nonce tracker, or the store of one, with another tenant's.
+    pub fn insert(
+        &self,
+        tenant: TenantId,
+        policies: PolicySet,
+        entities: Entities,
+    ) -> Result<(), TenantError> {
+        let mut tenants = self.tenants.write().unwrap_or_else(PoisonError::into_inner);
+        if let Some(existing) = tenants.get(&tenant).cloned() {
+            drop(tenants);
+            existing.set(Some(policies), Some(entities));
+            return Ok(());
+        }
+        let authorizer = (self.new_authorizer)(&tenant);
+        if let Some((other, state)) = tenants.iter().find_map(|(other, existing)| {
+            shared_state(&authorizer, &existing.authorizer).map(|state| (other, state))
+        }) {
+            return Err(TenantError::SharedState {
+                tenant,
+                other: other.clone(),
+                state,
+            });
+        }
+        tenants.insert(
+            tenant,
+            Arc::new(Tenant {
+                authorizer,
+                data: RwLock::new(TenantData {
+                    policies: Arc::new(policies),
+                    entities: Arc::new(entities),
+                }),
+            }),
+        );
+        drop(tenants);
+        Ok(())
+    }
+
+    /// Replace the policies of `tenant`
+    pub fn set_policies(&self, tenant: &TenantId, policies: PolicySet) -> Result<(), TenantError> {
+        self.tenant(tenant)?.set(Some(policies), None);
+        Ok(())
+    }
+
+    /// Replace the entities of `tenant`
+    pub fn set_entities(&self, tenant: &TenantId, entities: Entities) -> Result<(), TenantError> {
+        self.tenant(tenant)?.set(None, Some(entities));
+        Ok(())
+    }
+
+    /// Remove `tenant`, with its policies, entities, and authorizer,
+    /// returning whether there was one
+    pub fn remove(&self, tenant: &TenantId) -> bool {
+        let mut tenants = self.tenants.write().unwrap_or_else(PoisonError::into_inner);
+        let removed = tenants.remove(tenant).is_some();
+        drop(tenants);
+        removed
+    }
+
+    /// Whether there's a tenant `tenant`
+    pub fn contains(&self, tenant: &TenantId) -> bool {
+        self.tenants
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .contains_key(tenant)
+    }
+
+    /// The ids of the tenants, sorted
+    pub fn ids(&self) -> Vec<TenantId> {
+        let mut ids: Vec<_> = self
+            .tenants
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .keys()
+            .cloned()
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    /// The policies of `tenant`
+    pub fn policies(&self, tenant: &TenantId) -> Result<Arc<PolicySet>, TenantError> {
+        Ok(self.tenant(tenant)?.data().policies.clone())
+    }
+
+    /// The entities of `tenant`
+    pub fn entities(&self, tenant: &TenantId) -> Result<Arc<Entities>, TenantError> {
+        Ok(self.tenant(tenant)?.data().entities.clone())
+    }
+
+    /// Answer `request` for `tenant` with its authorizer, policies, and
+    /// entities, and nothing of any other tenant's
+    pub fn is_authorized(
+        &self,
+        tenant: &TenantId,
+        request: &Request,
+    ) -> Result<Response, TenantError> {
+        let tenant = self.tenant(tenant)?;
+        let data = tenant.data();
+        let response = tenant
+            .authorizer
+            .is_authorized(request, &data.policies, &data.entities);
+        drop(data);
+        Ok(response)
+    }
+
+    fn tenant(&self, tenant: &TenantId) -> Result<Arc<Tenant>, TenantError> {
+        self.tenants
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(tenant)
+            .cloned()
+            .ok_or_else(|| TenantError::UnknownTenant(tenant.clone()))
+    }
+}
+
+/// The state `a` and `b` share which each tenant must have its own of, if
+/// they share any
+fn shared_state(a: &Authorizer, b: &Authorizer) -> Option<&'static str> {
+    /// Whether `a` and `b` are both set and point to the same value
+    fn same<T: ?Sized>(a: Option<&Arc<T>>, b: Option<&Arc<T>>) -> bool {
+        a.zip(b)
+            .is_some_and(|(a, b)| Arc::as_ptr(a).cast::<()>() == Arc::as_ptr(b).cast::<()>())
+    }
+    let (limiter_a, limiter_b) = (a.rate_limiter(), b.rate_limiter());
+    let (nonces_a, nonces_b) = (a.nonce_tracker(), b.nonce_tracker());
+    if same(a.decision_cache(), b.decision_cache()) {
+        Some("decision cache")
+    } else if same(limiter_a, limiter_b)
+        || same(
+            limiter_a.map(|limiter| limiter.store()),
+            limiter_b.map(|limiter| limiter.store()),
+        )
+    {
+        Some("rate limiter")
+    } else if same(nonces_a, nonces_b)
+        || same(
+            nonces_a.map(|nonces| nonces.store()),
+            nonces_b.map(|nonces| nonces.store()),
+        )
+    {
+        Some("nonce tracker")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cache::DecisionCache;
+    use crate::nonce::{MemoryNonceStore, NonceTracker};
+    use crate::rate_limit::{MemoryRateLimitStore, RateLimitStore, RateLimiter};
+    use crate::{Context, Decision, Entity, EntityUid};
+    use std::collections::HashSet;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    #[derive(Debug, Default)]
+    struct MemorySink(Mutex<Vec<AuditRecord>>);
+
+    impl AuditSink for MemorySink {
+        fn record(&self, record: &AuditRecord) {
+            self.0.lock().unwrap().push(record.clone());
+        }
+    }
+
+    fn tenant(id: &str) -> TenantId {
+        TenantId::from_str(id).unwrap()
+    }
+
+    fn request() -> Request {
+        Request::new(
+            Some(EntityUid::from_strs("User", "alice")),
+            Some(EntityUid::from_strs("Action", "transfer")),
+            Some(EntityUid::from_strs("Wallet", "w")),
+            Context::empty(),
+        )
+    }
+
+    /// Alice, as a member of `group`
+    fn entities(group: &str) -> Entities {
+        Entities::from_entities([Entity::new(
+            EntityUid::from_strs("User", "alice"),
+            std::collections::HashMap::new(),
+            HashSet::from([EntityUid::from_strs("Group", group)]),
+        )])
+        .unwrap()
+    }
+
+    #[test]
+    fn tenant_ids() {
+        assert_eq!(tenant("acme-1_eu").as_str(), "acme-1_eu");
+        let long = "a".repeat(MAX_TENANT_ID_LEN);
+        assert!(TenantId::from_str(&long).is_ok());
+        for id in [
+            "",
+            "../acme",
+            "acme/eu",
+            "acme eu",
+            "acmé",
+            &format!("{long}a"),
+        ] {
+            assert!(
+                matches!(TenantId::from_str(id), Err(TenantError::InvalidId(_))),
+                "{id}"
+            );
+        }
+        assert_eq!(
+            serde_json::from_str::<TenantId>(r#""acme""#).unwrap(),
+            tenant("acme")
+        );
+        assert!(serde_json::from_str::<TenantId>(r#""a/b""#).is_err());
+    }
+
+    #[test]
+    fn tenants_are_isolated() {
+        let tenants = Tenants::new();
+        let policies =
+            PolicySet::from_str(r#"permit(principal in Group::"admins", action, resource);"#)
+                .unwrap();
+        tenants
+            .insert(tenant("acme"), policies.clone(), entities("admins"))
+            .unwrap();
+        // the same principal, in another tenant, isn't an admin
+        tenants
+            .insert(tenant("globex"), policies, entities("staff"))
+            .unwrap();
+        let decide = |id: &str| {
+            tenants
+                .is_authorized(&tenant(id), &request())
+                .unwrap()
+                .decision()
+        };
+        assert_eq!(decide("acme"), Decision::Allow);
+        assert_eq!(decide("globex"), Decision::Deny);
+
+        // replacing one tenant's entities doesn't change the other's
+        tenants
+            .set_entities(&tenant("globex"), entities("admins"))
+            .unwrap();
+        tenants
+            .set_entities(&tenant("acme"), entities("staff"))
+            .unwrap();
+        assert_eq!(decide("acme"), Decision::Deny);
+        assert_eq!(decide("globex"), Decision::Allow);
+
+        assert!(matches!(
+            tenants.is_authorized(&tenant("initech"), &request()),
+            Err(TenantError::UnknownTenant(_))
+        ));
+        assert!(matches!(
+            tenants.set_policies(&tenant("initech"), PolicySet::new()),
+            Err(TenantError::UnknownTenant(_))
+        ));
+        assert_eq!(tenants.ids(), [tenant("acme"), tenant("globex")]);
+        assert!(tenants.remove(&tenant("acme")));
+        assert!(!tenants.contains(&tenant("acme")));
+        assert!(tenants.entities(&tenant("acme")).is_err());
+    }
+
+    #[test]
+    fn caches_and_audit_records_are_per_tenant() {
+        let sink = Arc::new(MemorySink::default());
+        let shared: Arc<dyn AuditSink> = sink.clone();
+        let caches = Arc::new(Mutex::new(HashMap::new()));
+        let tenants = {
+            let caches = caches.clone();
+            Tenants::new().with_authorizers(move |tenant| {
+                let cache = Arc::new(DecisionCache::new(Duration::from_mins(1)));
+                caches.lock().unwrap().insert(tenant.clone(), cache.clone());
+                Authorizer::new()
+                    .with_decision_cache(cache)
+                    .with_audit_sink(Arc::new(TenantAuditSink::new(
+                        tenant.clone(),
+                        shared.clone(),
+                    )))
+            })
+        };
+        let allow = PolicySet::from_str("permit(principal, action, resource);").unwrap();
+        tenants
+            .insert(tenant("acme"), allow.clone(), Entities::empty())
+            .unwrap();
+        tenants
+            .insert(tenant("globex"), PolicySet::new(), Entities::empty())
+            .unwrap();
+
+        let decide = |id: &str| {
+            tenants
+                .is_authorized(&tenant(id), &request())
+                .unwrap()
+                .decision()
+        };
+        assert_eq!(decide("acme"), Decision::Allow);
+        // the same request isn't answered from the other tenant's cache
+        assert_eq!(decide("globex"), Decision::Deny);
+        assert_eq!(decide("acme"), Decision::Allow);
+        let cache = |id: &str| caches.lock().unwrap()[&tenant(id)].clone();
+        assert_eq!(cache("acme").stats(), (1, 1));
+        assert_eq!(cache("globex").stats(), (0, 1));
+
+        // new policies aren't answered with decisions cached for the old ones
+        tenants.set_policies(&tenant("globex"), allow).unwrap();
+        assert!(cache("globex").is_empty());
+        assert_eq!(decide("globex"), Decision::Allow);
+
+        let tenants_recorded: Vec<_> = sink
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|record| record.tenant.clone().unwrap())
+            .collect();
+        assert_eq!(tenants_recorded, ["acme", "globex", "acme", "globex"]);
+        let json = serde_json::to_value(&sink.0.lock().unwrap()[0]).unwrap();
+        assert_eq!(json["tenant"], "acme");
+    }
+
+    #[test]
+    fn shared_state_is_refused() {
+        let cache = Arc::new(DecisionCache::new(Duration::from_mins(1)));
+        let tenants = Tenants::new()
+            .with_authorizers(move |_| Authorizer::new().with_decision_cache(cache.clone()));
+        tenants
+            .insert(tenant("acme"), PolicySet::new(), Entities::empty())
+            .unwrap();
+        assert!(matches!(
+            tenants.insert(tenant("globex"), PolicySet::new(), Entities::empty()),
+            Err(TenantError::SharedState {
+                state: "decision cache",
+                ..
+            })
+        ));
+        assert!(!tenants.contains(&tenant("globex")));
+        // replacing the policies of an existing tenant keeps its authorizer
+        tenants
+            .insert(tenant("acme"), PolicySet::new(), Entities::empty())
+            .unwrap();
+
+        // separate limiters which share a store share buckets
+        let store: Arc<dyn RateLimitStore> = Arc::new(MemoryRateLimitStore::new());
+        let tenants = Tenants::new().with_authorizers(move |_| {
+            Authorizer::new().with_rate_limiter(Arc::new(RateLimiter::new(store.clone())))
+        });
+        tenants
+            .insert(tenant("acme"), PolicySet::new(), Entities::empty())
+            .unwrap();
+        assert!(matches!(
+            tenants.insert(tenant("globex"), PolicySet::new(), Entities::empty()),
+            Err(TenantError::SharedState {
+                state: "rate limiter",
+                ..
+            })
+        ));
+
+        let tenants = Tenants::new().with_authorizers(|_| {
+            Authorizer::new().with_nonce_tracker(Arc::new(NonceTracker::new(Arc::new(
+                MemoryNonceStore::new(),
+            ))))
+        });
+        tenants
+            .insert(tenant("acme"), PolicySet::new(), Entities::empty())
+            .unwrap();
+        tenants
+            .insert(tenant("globex"), PolicySet::new(), Entities::empty())
+            .unwrap();
+    }
+}
@@ -0,0 +1,281 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! K-of-N agreement of independent evaluators' decisions.
+//!
+//! With no single trusted evaluator, each of N evaluator nodes decides a
+//! request itself and signs a [`DecisionReceipt`](crate::receipt::DecisionReceipt) with its own key. A
+//! combiner collects the [`SignedReceipt`]s and only releases an approval if
+//! K of the nodes allowed the request under the same policy set:
+//! [`Committee::combine()`] checks the receipts and aggregates them into an
+//! [`AggregatedReceipt`], which anyone holding the committee's keys can check
+//! again with [`Committee::verify()`].
+//!
+//! Receipts from keys outside the committee, with invalid signatures, or for
+//! other requests don't count, and neither does more than one receipt per
+//! key, so a faulty or malicious node can't block or forge an approval on
+//! its own.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use k256::ecdsa::VerifyingKey;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::receipt::SignedReceipt;
+use crate::Decision;
+
+/// The evaluators of a committee and the number which must agree
+#[derive(Debug, Clone)]
+pub struct Committee {
+    /// The key of each evaluator, by the key id in its receipts
+    keys: BTreeMap<String, VerifyingKey>,
+    threshold: usize,
+}
+
+/// Errors combining or verifying receipts
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ThresholdError {
+    /// The threshold is zero or larger than the committee
+    #[error("threshold {threshold} is not between 1 and the committee size {size}")]
+    InvalidThreshold {
+        /// The threshold
+        threshold: usize,
+        /// The number of evaluators
+        size: usize,
+    },
+    /// Fewer evaluators than the threshold allowed the request under any one
+    /// policy set
+    #[error("only {allowed} of the required {threshold} evaluators allowed the request")]
+    NoQuorum {
+        /// The most evaluators which allowed the request under the same
+        /// policy set
+        allowed: usize,
+        /// The number required
+        threshold: usize,
+    },
+}
+
+/// An approval: receipts from at least the threshold of a committee's
+/// evaluators, allowing the same request under the same policy set
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AggregatedReceipt {
+    /// Hex-encoded digest of the request
+    pub request_hash: String,
+    /// Hex-encoded digest of the policy set
+    pub policy_set_hash: String,
+    /// The agreeing receipts, one per evaluator, sorted by key id
+    pub receipts: Vec<SignedReceipt>,
+}
+
+impl Committee {
+    /// A committee of the evaluators with `keys`, by key id, of which
+    /// `threshold` must agree
+    pub fn new(
+        keys: impl IntoIterator<Item = (String, VerifyingKey)>,
+        threshold: usize,
+    ) -> Result<Self, ThresholdError> {
+        let keys: BTreeMap<_, _> = keys.into_iter().collect();
+        if threshold == 0 || threshold > keys.len() {
+            return Err(ThresholdError::InvalidThreshold {
+                threshold,
+                size: keys.len(),
+            });
+        }
+        Ok(Self { keys, threshold })
+    }
+
+    /// The number of evaluators which must agree
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    /// The number of evaluators
+    pub fn size(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Whether `receipt` is signed by a member of the committee
+    fn is_signed_by_member(&self, receipt: &SignedReceipt) -> bool {
+        self.keys
+            .get(&receipt.key_id)
+            .is_some_and(|key| receipt.verify(key).is_ok())
+    }
+
+    /// Combine the receipts for the request with digest `request_hash` into
+    /// an approval, if enough evaluators allowed it under the same policy
+    /// set. Receipts which don't count are ignored.
+    pub fn combine<'a>(
+        &self,
+        request_hash: &str,
+        receipts: impl IntoIterator<Item = &'a SignedReceipt>,
+    ) -> Result<AggregatedReceipt, ThresholdError> {
+        // the allowing receipts for each policy set, one per key
+        let mut allowed: BTreeMap<&str, BTreeMap<&str, &SignedReceipt>> = BTreeMap::new();
+        for receipt in receipts {
+            if receipt.receipt.request_hash == request_hash
+                && receipt.receipt.decision == Decision::Allow
+                && self.is_signed_by_member(receipt)
+            {
+                allowed
+                    .entry(&receipt.receipt.policy_set_hash)
+                    .or_default()
+                    .entry(&receipt.key_id)
+                    .or_insert(receipt);
+            }
+        }
+        let best = allowed
+            .into_iter()
+            .max_by_key(|(_, receipts)| receipts.len());
+        match best {
+            Some((policy_set_hash, receipts)) if receipts.len() >= self.threshold => {
+                Ok(AggregatedReceipt {
+                    request_hash: request_hash.to_string(),
+                    policy_set_hash: policy_set_hash.to_string(),
+                    receipts: receipts.into_values().cloned().collect(),
+                })
+            }
+            best => Err(ThresholdError::NoQuorum {
+                allowed: best.map_or(0, |(_, receipts)| receipts.len()),
+                threshold: self.threshold,
+            }),
+        }
+    }
+
+    /// Check that `aggregated` is an approval by this committee
+    pub fn verify(&self, aggregated: &AggregatedReceipt) -> Result<(), ThresholdError> {
+        let signers: BTreeSet<&str> = aggregated
+            .receipts
+            .iter()
+            .filter(|receipt| {
+                receipt.receipt.request_hash == aggregated.request_hash
+                    && receipt.receipt.policy_set_hash == aggregated.policy_set_hash
+                    && receipt.receipt.decision == Decision::Allow
+                    && self.is_signed_by_member(receipt)
+            })
+            .map(|receipt| receipt.key_id.as_str())
+            .collect();
+        if signers.len() >= self.threshold {
+            Ok(())
+        } else {
+            Err(ThresholdError::NoQuorum {
+                allowed: signers.len(),
+                threshold: self.threshold,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::receipt::{DecisionReceipt, LocalSigner, ReceiptSigner};
+    use k256::ecdsa::SigningKey;
+
+    fn signers() -> [LocalSigner; 3] {
+        [1, 2, 3].map(|i| {
+            LocalSigner::new(
+                SigningKey::from_slice(&[i; 32]).unwrap(),
+                format!("node-{i}"),
+            )
+        })
+    }
+
+    fn committee(signers: &[LocalSigner]) -> Committee {
+        Committee::new(
+            signers
+                .iter()
+                .map(|signer| (signer.key_id(), signer.verifying_key())),
+            2,
+        )
+        .unwrap()
+    }
+
+    fn receipt(signer: &LocalSigner, policy_set_hash: &str, decision: Decision) -> SignedReceipt {
+        DecisionReceipt {
+            version: 1,
+            request_hash: "aa".into(),
+            policy_set_hash: policy_set_hash.into(),
+            decision,
+            determining_policies: vec![],
+            timestamp: 1_700_000_000,
+        }
+        .sign(signer)
+        .unwrap()
+    }
+
+    #[test]
+    fn two_of_three_approve() {
+        let signers = signers();
+        let committee = committee(&signers);
+        let receipts: Vec<_> = signers
+            .iter()
+            .zip([Decision::Allow, Decision::Deny, Decision::Allow])
+            .map(|(signer, decision)| receipt(signer, "bb", decision))
+            .collect();
+        let aggregated = committee.combine("aa", &receipts).unwrap();
+        assert_eq!(aggregated.policy_set_hash, "bb");
+        assert_eq!(
+            aggregated
+                .receipts
+                .iter()
+                .map(|receipt| receipt.key_id.as_str())
+                .collect::<Vec<_>>(),
+            ["node-1", "node-3"]
+        );
+        committee.verify(&aggregated).unwrap();
+
+        // round trips through JSON
+        let json = serde_json::to_string(&aggregated).unwrap();
+        let parsed: AggregatedReceipt = serde_json::from_str(&json).unwrap();
+        committee.verify(&parsed).unwrap();
+    }
+
+    #[test]
+    fn receipts_which_dont_count() {
+        let signers = signers();
+        let committee = committee(&signers);
+        let [first, second, third] = &signers;
+        let outsider = LocalSigner::new(SigningKey::from_slice(&[9; 32]).unwrap(), "node-2");
+        let mut forged = receipt(second, "bb", Decision::Deny);
+        forged.receipt.decision = Decision::Allow;
+        let receipts = [
+            receipt(first, "bb", Decision::Allow),
+            // the same evaluator twice
+            receipt(first, "bb", Decision::Allow),
+            // another policy set
+            receipt(third, "cc", Decision::Allow),
+            // a key outside the committee, under a member's key id
+            receipt(&outsider, "bb", Decision::Allow),
+            forged,
+        ];
+        assert_eq!(
+            committee.combine("aa", &receipts),
+            Err(ThresholdError::NoQuorum {
+                allowed: 1,
+                threshold: 2
+            })
+        );
+        assert_eq!(
+            Committee::new([], 1).unwrap_err(),
+            ThresholdError::InvalidThreshold {
+                threshold: 1,
+                size: 0
+            }
+        );
+    }
+}
@@ -0,0 +1,530 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Time-locked `Allow` decisions.
+//!
+//! As with an on-chain timelock, some approvals should only take effect
+//! after a delay, leaving time to notice and cancel them. A `permit` policy
+//! annotated with `@timelock`, e.g.
+//!
+//! ```text
+//! @timelock("24h")
+//! permit(principal, action == Action::"upgrade", resource);
+//! ```
+//!
+//! doesn't allow a request immediately: [`Timelock::authorize()`] queues it
+//! and returns a [`TimelockDecision::Pending`] with the time after which it
+//! may be executed, and [`Timelock::confirm()`] releases it once that time
+//! has passed. Until then, [`Timelock::cancel()`] removes it from the queue.
+//!
+//! Confirming an action authorizes its request again, with the policies and
+//! entities current at that time, so that an action is never released after
+//! its policies were removed, a kill switch was activated or its principal
+//! was revoked while it was queued.
+//!
+//! The queue is kept in a [`TimelockStore`], so that it can be shared or
+//! persisted; [`MemoryTimelockStore`] keeps it in memory.
+
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::audit::request_digest;
+use crate::{Authorizer, Decision, Effect, Entities, PolicyId, PolicySet, Request, Response};
+
+/// The annotation delaying the `Allow` decisions of a `permit` policy, e.g.
+/// `@timelock("24h")`
+pub const TIMELOCK_ANNOTATION: &str = "timelock";
+
+/// The delay in seconds in the value of a [`TIMELOCK_ANNOTATION`]
+///
+/// The value is a whole number followed by a unit: `s`, `m`, `h`, or `d`.
+/// Returns `None` if it isn't, or if the delay doesn't fit.
+pub fn parse_timelock_annotation(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let unit = value.chars().last()?;
+    let seconds = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        _ => return None,
+    };
+    let count = value.strip_suffix(unit)?;
+    if count.is_empty() || !count.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    count.parse::<u64>().ok()?.checked_mul(seconds)
+}
+
+/// A queued request, allowed once its delay has passed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingAction {
+    /// The hex-encoded digest of the request, identifying it in the queue
+    pub id: String,
+    /// When the request was queued, in seconds since the Unix epoch
+    pub queued_at: u64,
+    /// When the request may be executed, in seconds since the Unix epoch
+    pub execute_after: u64,
+    /// Ids of the time-locked policies which allowed it, sorted
+    pub determining_policies: Vec<String>,
+}
+
+/// An error from a [`TimelockStore`]
+#[derive(Debug, Error)]
+#[error("timelock store error: {0}")]
+pub struct TimelockStoreError(pub String);
+
+/// Where a [`Timelock`] keeps its queue. Each method must check and update
+/// atomically, so that concurrent requests can't queue or release the same
+/// action twice.
+pub trait TimelockStore: Debug + Send + Sync {
+    /// Queue `action` unless an action with the same id is already queued,
+    /// returning the queued one
+    fn enqueue(&self, action: PendingAction) -> Result<PendingAction, TimelockStoreError>;
+
+    /// The queued action with `id`, if any
+    fn get(&self, id: &str) -> Result<Option<PendingAction>, TimelockStoreError>;
+
+    /// Remove the queued action with `id`, returning it
+    fn remove(&self, id: &str) -> Result<Option<PendingAction>, TimelockStoreError>;
+
+    /// All queued actions
+    fn pending(&self) -> Result<Vec<PendingAction>, TimelockStoreError>;
+}
+
+/// A [`TimelockStore`] in memory
+#[derive(Debug, Default)]
+pub struct MemoryTimelockStore {
+    queue: Mutex<BTreeMap<String, PendingAction>>,
+}
+
+impl MemoryTimelockStore {
+    /// An empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl TimelockStore for MemoryTimelockStore {
+    fn enqueue(&self, action: PendingAction) -> Result<PendingAction, TimelockStoreError> {
+        Ok(self
+            .queue
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(action.id.clone())
+            .or_insert(action)
+            .clone())
+    }
+
+    fn get(&self, id: &str) -> Result<Option<PendingAction>, TimelockStoreError> {
+        Ok(self
+            .queue
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(id)
+            .cloned())
+    }
+
+    fn remove(&self, id: &str) -> Result<Option<PendingAction>, TimelockStoreError> {
+        Ok(self
+            .queue
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(id))
+    }
+
+    fn pending(&self) -> Result<Vec<PendingAction>, TimelockStoreError> {
+        Ok(self
+            .queue
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .values()
+            .cloned()
+            .collect())
+    }
+}
+
+/// Errors authorizing or confirming time-locked requests
+#[derive(Debug, Error)]
+pub enum TimelockError {
+    /// A determining policy has a malformed `@timelock` annotation
+    #[error("policy `{policy}` has a malformed `@timelock` annotation `{value}`")]
+    InvalidAnnotation {
+        /// The policy's id
+        policy: String,
+        /// The annotation value
+        value: String,
+    },
+    /// A policy which determined the decision isn't in the policy set
+    #[error("determining policy `{0}` is not in the policy set")]
+    UnknownPolicy(String),
+    /// No action with the id is queued
+    #[error("no action `{0}` is queued")]
+    Unknown(String),
+    /// The action's request is no longer allowed
+    #[error("action `{id}` is no longer allowed")]
+    Denied {
+        /// The action's id
+        id: String,
+        /// The decision for the request when it was confirmed
+        response: Box<Response>,
+    },
+    /// The action's delay hasn't passed yet
+    #[error("action `{id}` can't be executed before {execute_after}")]
+    TooEarly {
+        /// The action's id
+        id: String,
+        /// When it may be executed, in seconds since the Unix epoch
+        execute_after: u64,
+    },
+    /// The store failed
+    #[error(transparent)]
+    Store(#[from] TimelockStoreError),
+}
+
+/// The decision for a request which may be time-locked
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TimelockDecision {
+    /// The request was decided immediately
+    Decided(Response),
+    /// The request was allowed, but only by time-locked policies, and is
+    /// queued until [`PendingAction::execute_after`]
+    Pending(PendingAction),
+}
+
+/// Queues requests allowed by time-locked policies until their delay passes
+#[derive(Debug, Clone)]
+pub struct Timelock {
+    store: Arc<dyn TimelockStore>,
+}
+
+impl Timelock {
+    /// A timelock keeping its queue in `store`
+    pub fn new(store: Arc<dyn TimelockStore>) -> Self {
+        Self { store }
+    }
+
+    /// Decide `request` with `authorizer`, at `now` in seconds since the
+    /// Unix epoch. An `Allow` is returned immediately if any determining
+    /// policy has no `@timelock` annotation; otherwise the request is queued
+    /// with the shortest delay of the determining policies. Authorizing a
+    /// request which is already queued returns the queued action, without
+    /// restarting its delay.
+    pub fn authorize(
+        &self,
+        authorizer: &Authorizer,
+        request: &Request,
+        policies: &PolicySet,
+        entities: &Entities,
+        now: u64,
+    ) -> Result<TimelockDecision, TimelockError> {
+        let response = authorizer.is_authorized(request, policies, entities);
+        if response.decision() != Decision::Allow {
+            return Ok(TimelockDecision::Decided(response));
+        }
+        let Some(delay) = delay(&response, policies)? else {
+            return Ok(TimelockDecision::Decided(response));
+        };
+        let mut determining_policies: Vec<_> = response
+            .diagnostics()
+            .reason()
+            .map(ToString::to_string)
+            .collect();
+        determining_policies.sort();
+        let action = self.store.enqueue(PendingAction {
+            id: request_digest(request),
+            queued_at: now,
+            execute_after: now.saturating_add(delay),
+            determining_policies,
+        })?;
+        Ok(TimelockDecision::Pending(action))
+    }
+
+    /// Release the queued action for `request` if its delay has passed at
+    /// `now`, removing it from the queue.
+    ///
+    /// The request is authorized again with `authorizer`, `policies` and
+    /// `entities`, which should be the current ones, and the action is only
+    /// released if it is still allowed, and the delay of the time-locked
+    /// policies which allow it now has passed since it was queued. An action
+    /// which is no longer allowed stays queued until it is cancelled.
+    pub fn confirm(
+        &self,
+        authorizer: &Authorizer,
+        request: &Request,
+        policies: &PolicySet,
+        entities: &Entities,
+        now: u64,
+    ) -> Result<PendingAction, TimelockError> {
+        let id = request_digest(request);
+        let action = self
+            .store
+            .get(&id)?
+            .ok_or_else(|| TimelockError::Unknown(id.clone()))?;
+        if now < action.execute_after {
+            return Err(TimelockError::TooEarly {
+                id: action.id,
+                execute_after: action.execute_after,
+            });
+        }
+        let response = authorizer.is_authorized(request, policies, entities);
+        if response.decision() != Decision::Allow {
+            return Err(TimelockError::Denied {
+                id,
+                response: Box::new(response),
+            });
+        }
+        if let Some(delay) = delay(&response, policies)? {
+            let execute_after = action.queued_at.saturating_add(delay);
+            if now < execute_after {
+                return Err(TimelockError::TooEarly { id, execute_after });
+            }
+        }
+        // another caller may have released or cancelled it in the meantime
+        self.store.remove(&id)?.ok_or(TimelockError::Unknown(id))
+    }
+
+    /// Remove the queued action with `id`, returning it
+    pub fn cancel(&self, id: &str) -> Result<PendingAction, TimelockError> {
+        self.store
+            .remove(id)?
+            .ok_or_else(|| TimelockError::Unknown(id.to_string()))
+    }
+
+    /// All queued actions
+    pub fn pending(&self) -> Result<Vec<PendingAction>, TimelockError> {
+        Ok(self.store.pending()?)
+    }
+}
+
+/// The delay of an `Allow` in `response`: `None` if a determining `permit`
+/// policy isn't time-locked, and otherwise the shortest of their delays
+fn delay(response: &Response, policies: &PolicySet) -> Result<Option<u64>, TimelockError> {
+    let mut shortest: Option<u64> = None;
+    for id in response.diagnostics().reason() {
+        let policy = policies
+            .policy(id)
+            .ok_or_else(|| TimelockError::UnknownPolicy(id.to_string()))?;
+        if policy.effect() != Effect::Permit {
+            continue;
+        }
+        let Some(value) = policy.annotation(TIMELOCK_ANNOTATION) else {
+            return Ok(None);
+        };
+        let seconds = parse_timelock_annotation(value).ok_or_else(|| invalid(id, value))?;
+        shortest = Some(shortest.map_or(seconds, |s| s.min(seconds)));
+    }
+    Ok(shortest)
+}
+
+fn invalid(id: &PolicyId, value: &str) -> TimelockError {
+    TimelockError::InvalidAnnotation {
+        policy: id.to_string(),
+        value: value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Context, EntityUid};
+    use std::str::FromStr;
+
+    fn request(action: &str) -> Request {
+        Request::new(
+            Some(EntityUid::from_strs("User", "alice")),
+            Some(EntityUid::from_strs("Action", action)),
+            Some(EntityUid::from_strs("Vault", "v")),
+            Context::empty(),
+        )
+    }
+
+    fn policies() -> PolicySet {
+        PolicySet::from_str(
+            r#"permit(principal, action == Action::"deposit", resource);
+               @timelock("24h")
+               permit(principal, action == Action::"upgrade", resource);
+               @timelock("1h")
+               permit(principal, action == Action::"upgrade", resource);
+               @timelock("soon")
+               permit(principal, action == Action::"pause", resource);"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn annotations() {
+        assert_eq!(parse_timelock_annotation("30s"), Some(30));
+        assert_eq!(parse_timelock_annotation(" 90m "), Some(5400));
+        assert_eq!(parse_timelock_annotation("24h"), Some(86_400));
+        assert_eq!(parse_timelock_annotation("2d"), Some(172_800));
+        for value in ["", "h", "24", "-1h", "1.5h", "24 h", "1w"] {
+            assert_eq!(parse_timelock_annotation(value), None, "{value}");
+        }
+    }
+
+    #[test]
+    fn queue_and_confirm() {
+        let timelock = Timelock::new(Arc::new(MemoryTimelockStore::new()));
+        let authorizer = Authorizer::new();
+        let policies = policies();
+        let entities = Entities::empty();
+
+        let decision = timelock
+            .authorize(&authorizer, &request("deposit"), &policies, &entities, 1000)
+            .unwrap();
+        assert!(
+            matches!(decision, TimelockDecision::Decided(response) if response.decision() == Decision::Allow)
+        );
+        let decision = timelock
+            .authorize(
+                &authorizer,
+                &request("withdraw"),
+                &policies,
+                &entities,
+                1000,
+            )
+            .unwrap();
+        assert!(
+            matches!(decision, TimelockDecision::Decided(response) if response.decision() == Decision::Deny)
+        );
+
+        // the shortest delay applies, and asking again doesn't restart it
+        let TimelockDecision::Pending(action) = timelock
+            .authorize(&authorizer, &request("upgrade"), &policies, &entities, 1000)
+            .unwrap()
+        else {
+            panic!("expected a pending action");
+        };
+        assert_eq!(action.execute_after, 4600);
+        assert_eq!(action.determining_policies, ["policy1", "policy2"]);
+        let TimelockDecision::Pending(again) = timelock
+            .authorize(&authorizer, &request("upgrade"), &policies, &entities, 2000)
+            .unwrap()
+        else {
+            panic!("expected a pending action");
+        };
+        assert_eq!(again, action);
+        assert_eq!(timelock.pending().unwrap(), std::slice::from_ref(&action));
+
+        let confirm = |policies: &PolicySet, now| {
+            timelock.confirm(&authorizer, &request("upgrade"), policies, &entities, now)
+        };
+        assert!(matches!(
+            confirm(&policies, 4599),
+            Err(TimelockError::TooEarly {
+                execute_after: 4600,
+                ..
+            })
+        ));
+        assert_eq!(confirm(&policies, 4600).unwrap(), action);
+        assert!(matches!(
+            confirm(&policies, 4600),
+            Err(TimelockError::Unknown(_))
+        ));
+    }
+
+    #[test]
+    fn confirm_authorizes_again() {
+        let timelock = Timelock::new(Arc::new(MemoryTimelockStore::new()));
+        let authorizer = Authorizer::new();
+        let entities = Entities::empty();
+        let TimelockDecision::Pending(action) = timelock
+            .authorize(
+                &authorizer,
+                &request("upgrade"),
+                &policies(),
+                &entities,
+                1000,
+            )
+            .unwrap()
+        else {
+            panic!("expected a pending action");
+        };
+        let confirm = |policies: &PolicySet| {
+            timelock.confirm(&authorizer, &request("upgrade"), policies, &entities, 4600)
+        };
+
+        // the policies were removed while it was queued
+        assert!(matches!(
+            confirm(&PolicySet::new()),
+            Err(TimelockError::Denied { id, response }) if id == action.id && response.decision() == Decision::Deny
+        ));
+        // only the policy with the longer delay is left
+        let longer = PolicySet::from_str(
+            r#"@timelock("24h")
+               permit(principal, action == Action::"upgrade", resource);"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            confirm(&longer),
+            Err(TimelockError::TooEarly {
+                execute_after: 87_400,
+                ..
+            })
+        ));
+        // refused actions stay queued
+        assert_eq!(timelock.pending().unwrap(), std::slice::from_ref(&action));
+        assert_eq!(confirm(&policies()).unwrap(), action);
+    }
+
+    #[test]
+    fn cancel_and_malformed() {
+        let timelock = Timelock::new(Arc::new(MemoryTimelockStore::new()));
+        let authorizer = Authorizer::new();
+        let policies = policies();
+        let entities = Entities::empty();
+
+        let TimelockDecision::Pending(action) = timelock
+            .authorize(&authorizer, &request("upgrade"), &policies, &entities, 0)
+            .unwrap()
+        else {
+            panic!("expected a pending action");
+        };
+        assert_eq!(timelock.cancel(&action.id).unwrap(), action);
+        assert!(timelock.pending().unwrap().is_empty());
+        assert!(matches!(
+            timelock.confirm(
+                &authorizer,
+                &request("upgrade"),
+                &policies,
+                &entities,
+                u64::MAX
+            ),
+            Err(TimelockError::Unknown(_))
+        ));
+
+        assert!(matches!(
+            timelock.authorize(&authorizer, &request("pause"), &policies, &entities, 0),
+            Err(TimelockError::InvalidAnnotation { policy, value }) if policy == "policy3" && value == "soon"
+        ));
+
+        // a determining policy missing from the policies is an error, rather
+        // than leaving out its delay
+        let response = authorizer.is_authorized(&request("upgrade"), &policies, &entities);
+        let mut without = policies;
+        without.remove_static(&PolicyId::from_str("policy2").unwrap());
+        assert!(matches!(
+            delay(&response, &without),
+            Err(TimelockError::UnknownPolicy(policy)) if policy == "policy2"
+        ));
+    }
+}
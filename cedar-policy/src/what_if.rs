@@ -0,0 +1,396 @@
+/*
+ * Copyright 2022-2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Impact analysis of policy changes.
+//!
+//! [`Authorizer::what_if()`] answers a corpus of requests, e.g. recent
+//! production traffic, under the current policy set and a candidate
+//! replacement, and reports which decisions would change and which policies
+//! are responsible.
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::{Authorizer, Decision, Entities, PolicyId, PolicySet, Request, Response};
+
+/// A request whose decision differs between the two policy sets
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecisionChange {
+    /// Position of the request in the corpus
+    pub index: usize,
+    /// The response under the current policy set
+    pub current: Response,
+    /// The response under the candidate policy set
+    pub candidate: Response,
+}
+
+impl DecisionChange {
+    /// Whether the candidate allows a request which is currently denied
+    pub fn is_newly_allowed(&self) -> bool {
+        self.candidate.decision() == Decision::Allow
+    }
+
+    /// Ids of the policies which determined either decision, sorted
+    pub fn responsible_policies(&self) -> BTreeSet<&PolicyId> {
+        self.current
+            .diagnostics()
+            .reason()
+            .chain(self.candidate.diagnostics().reason())
+            .collect()
+    }
+}
+
+/// How often one policy determined a decision
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PolicyImpact {
+    /// Requests whose decision this policy determined under the current
+    /// policy set
+    pub current: usize,
+    /// Requests whose decision this policy determined under the candidate
+    /// policy set
+    pub candidate: usize,
+    /// Requests whose decision changed, and which this policy determined
+    /// under either policy set
+    pub changed: usize,
+}
+
+/// The result of [`Authorizer::what_if()`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WhatIfReport {
+    /// Number of requests answered
+    pub requests: usize,
+    /// The requests whose decision changed, in corpus order
+    pub changes: Vec<DecisionChange>,
+    /// Impact of each policy which determined at least one decision under
+    /// either policy set
+    pub policies: HashMap<PolicyId, PolicyImpact>,
+}
+
+impl WhatIfReport {
+    /// Number of requests which are currently denied but would be allowed
+    pub fn newly_allowed(&self) -> usize {
+        self.changes.iter().filter(|c| c.is_newly_allowed()).count()
+    }
+
+    /// Number of requests which are currently allowed but would be denied
+    pub fn newly_denied(&self) -> usize {
+        self.changes.len() - self.newly_allowed()
+    }
+
+    /// Whether no decision changed
+    pub fn is_unchanged(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl Authorizer {
+    /// Answer each of `requests` under both `current` and `candidate`, with
+    /// `entities`, and report the differences.
+    ///
+    /// Revocations given to [`Authorizer::with_revocations()`] apply to both
+    /// policy sets. Nothing is recorded to the audit sink.
+    /// ```
+    /// # use cedar_policy::{Authorizer, Context, Entities, EntityUid, PolicySet, Request};
+    /// # use std::str::FromStr;
+    /// let current = PolicySet::from_str(r#"permit(principal == User::"alice", action, resource);"#).unwrap();
+    /// let candidate = PolicySet::from_str("permit(principal, action, resource);").unwrap();
+    /// let requests: Vec<_> = ["alice", "bob"]
+    ///     .into_iter()
+    ///     .map(|user| {
+    ///         let principal = EntityUid::from_str(&format!(r#"User::"{user}""#)).unwrap();
+    ///         Request::new(Some(principal), None, None, Context::empty())
+    ///     })
+    ///     .collect();
+    /// let report = Authorizer::new().what_if(&current, &candidate, &requests, &Entities::empty());
+    /// assert_eq!(report.newly_allowed(), 1);
+    /// assert_eq!(report.changes[0].index, 1);
+    /// ```
+    pub fn what_if<'a>(
+        &self,
+        current: &PolicySet,
+        candidate: &PolicySet,
+        requests: impl IntoIterator<Item = &'a Request>,
+        entities: &Entities,
+    ) -> WhatIfReport {
+        let mut report = WhatIfReport::default();
+        for (index, request) in requests.into_iter().enumerate() {
+            report.requests += 1;
+            let current = self.decide(request, current, entities);
+            let candidate = self.decide(request, candidate, entities);
+            for id in current.diagnostics().reason() {
+                report.policies.entry(id.clone()).or_default().current += 1;
+            }
+            for id in candidate.diagnostics().reason() {
+                report.policies.entry(id.clone()).or_default().candidate += 1;
+            }
+            if current.decision() != candidate.decision() {
+                let change = DecisionChange {
+                    index,
+                    current,
+                    candidate,
+                };
+                for id in change.responsible_policies() {
+                    report.policies.entry(id.clone()).or_default().changed += 1;
+                }
+                report.changes.push(change);
+            }
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Context, EntityUid, Policy};
+    use std::str::FromStr;
+
+    fn request(user: &str, amount: i64) -> Request {
+        Request::new(
+            Some(EntityUid::from_strs("User", user)),
+            Some(EntityUid::from_strs("Action", "transfer")),
+            Some(EntityUid::from_strs("Token", "usdc")),
+            Context::from_json_value(serde_json::json!({ "amount": amount }), None).unwrap(),
+        )
+    }
+
+    fn id(id: &str) -> PolicyId {
+        PolicyId::from_str(id).unwrap()
+    }
+
+    fn policies(policies: &[(&str, &str)]) -> PolicySet {
+        PolicySet::from_policies(
+            policies
+                .iter()
+                .map(|(id, src)| Policy::parse(Some((*id).to_string()), *src).unwrap()),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn reports_changes() {
+        let current = policies(&[
+            (
+                "limit",
+                "permit(principal, action, resource) when { context.amount < 100 };",
+            ),
+            (
+                "block-mallory",
+                r#"forbid(principal == User::"mallory", action, resource);"#,
+            ),
+        ]);
+        let candidate = policies(&[
+            (
+                "limit",
+                "permit(principal, action, resource) when { context.amount < 1000 };",
+            ),
+            (
+                "block-eve",
+                r#"forbid(principal == User::"eve", action, resource);"#,
+            ),
+        ]);
+        let requests = [
+            request("alice", 10),
+            request("alice", 500),
+            request("mallory", 10),
+            request("eve", 10),
+            request("bob", 5000),
+        ];
+        let report = Authorizer::new().what_if(&current, &candidate, &requests, &Entities::empty());
+
+        assert_eq!(report.requests, 5);
+        let changed: Vec<_> = report.changes.iter().map(|c| c.index).collect();
+        assert_eq!(changed, [1, 2, 3]);
+        assert_eq!((report.newly_allowed(), report.newly_denied()), (2, 1));
+        assert_eq!(
+            report.changes[1].responsible_policies(),
+            BTreeSet::from([&id("block-mallory"), &id("limit")])
+        );
+
+        assert_eq!(
+            report.policies[&id("limit")],
+            PolicyImpact {
+                current: 2,
+                candidate: 3,
+                changed: 3
+            }
+        );
+        assert_eq!(
+            report.policies[&id("block-eve")],
+            PolicyImpact {
+                current: 0,
+                candidate: 1,
+                changed: 1
+            }
+        );
+        assert!(!report.policies.contains_key(&id("unused")));
+
+        let same = Authorizer::new().what_if(&current, &current, &requests, &Entities::empty());
+        assert!(same.is_unchanged());
+    }
+
+    #[test]
+    fn removed_policies() {
+        let current = policies(&[
+            (
+                "small",
+                "permit(principal, action, resource) when { context.amount < 100 };",
+            ),
+            (
+                "block-mallory",
+                r#"forbid(principal == User::"mallory", action, resource);"#,
+            ),
+        ]);
+        let candidate = PolicySet::new();
+        let requests = [
+            request("alice", 10),
+            request("mallory", 10),
+            request("bob", 500),
+        ];
+        let report = Authorizer::new().what_if(&current, &candidate, &requests, &Entities::empty());
+
+        // without any policies, everything is denied, so only alice's request
+        // changes, and only the removed permit is responsible for it
+        let changed: Vec<_> = report.changes.iter().map(|c| c.index).collect();
+        assert_eq!(changed, [0]);
+        assert_eq!(report.newly_denied(), 1);
+        assert_eq!(
+            report.changes[0].responsible_policies(),
+            BTreeSet::from([&id("small")])
+        );
+        assert_eq!(
+            report.changes[0].candidate.diagnostics().reason().count(),
+            0
+        );
+        assert_eq!(
+            report.policies[&id("block-mallory")],
+            PolicyImpact {
+                current: 1,
+                candidate: 0,
+                changed: 0
+            }
+        );
+
+        // removing only the forbid allows mallory
+        let candidate = policies(&[(
+            "small",
+            "permit(principal, action, resource) when { context.amount < 100 };",
+        )]);
+        let report = Authorizer::new().what_if(&current, &candidate, &requests, &Entities::empty());
+        assert_eq!(report.changes.len(), 1);
+        assert!(report.changes[0].is_newly_allowed());
+        assert_eq!(
+            report.changes[0].responsible_policies(),
+            BTreeSet::from([&id("block-mallory"), &id("small")])
+        );
+    }
+
+    #[test]
+    fn forbids_change_decisions() {
+        let permit = ("any", "permit(principal, action, resource);");
+        let current = policies(&[permit]);
+        let candidate = policies(&[
+            permit,
+            (
+                "large",
+                "forbid(principal, action, resource) when { context.amount >= 1000 };",
+            ),
+        ]);
+        let requests = [
+            request("alice", 10),
+            request("alice", 5000),
+            request("bob", 1000),
+        ];
+        let report = Authorizer::new().what_if(&current, &candidate, &requests, &Entities::empty());
+
+        // the forbid overrides the permit, which still matches
+        let changed: Vec<_> = report.changes.iter().map(|c| c.index).collect();
+        assert_eq!(changed, [1, 2]);
+        assert_eq!((report.newly_allowed(), report.newly_denied()), (0, 2));
+        for change in &report.changes {
+            assert_eq!(change.current.decision(), Decision::Allow);
+            assert_eq!(change.candidate.decision(), Decision::Deny);
+            assert_eq!(
+                change.candidate.diagnostics().reason().collect::<Vec<_>>(),
+                [&id("large")]
+            );
+            assert_eq!(
+                change.responsible_policies(),
+                BTreeSet::from([&id("any"), &id("large")])
+            );
+        }
+        assert_eq!(
+            report.policies[&id("any")],
+            PolicyImpact {
+                current: 3,
+                candidate: 1,
+                changed: 2
+            }
+        );
+        assert_eq!(
+            report.policies[&id("large")],
+            PolicyImpact {
+                current: 0,
+                candidate: 2,
+                changed: 2
+            }
+        );
+    }
+
+    #[test]
+    fn policies_with_errors() {
+        let current = policies(&[(
+            "limit",
+            "permit(principal, action, resource) when { context.amount < context.limit };",
+        )]);
+        let candidate = policies(&[
+            (
+                "limit",
+                "permit(principal, action, resource) when { context.amount < 100 };",
+            ),
+            // erroring policies are skipped, so this forbid never applies
+            (
+                "risky",
+                "forbid(principal, action, resource) when { context.risk > 5 };",
+            ),
+        ]);
+        let requests = [request("alice", 10), request("alice", 500)];
+        let report = Authorizer::new().what_if(&current, &candidate, &requests, &Entities::empty());
+
+        // `context.limit` doesn't exist, so the current permit errors and
+        // everything is denied
+        let changed: Vec<_> = report.changes.iter().map(|c| c.index).collect();
+        assert_eq!(changed, [0]);
+        let change = &report.changes[0];
+        assert!(change.is_newly_allowed());
+        assert_eq!(change.current.diagnostics().errors().count(), 1);
+        assert_eq!(change.current.diagnostics().reason().count(), 0);
+        assert_eq!(change.candidate.diagnostics().errors().count(), 1);
+        // only policies which determined a decision are responsible
+        assert_eq!(
+            change.responsible_policies(),
+            BTreeSet::from([&id("limit")])
+        );
+        assert_eq!(
+            report.policies[&id("limit")],
+            PolicyImpact {
+                current: 0,
+                candidate: 1,
+                changed: 1
+            }
+        );
+        assert!(!report.policies.contains_key(&id("risky")));
+    }
+}